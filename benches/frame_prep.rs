@@ -0,0 +1,131 @@
+//! Frame preparation cost on a long page while scrolling: reshaping the
+//! whole page every frame versus reusing the cached layout and visiting only
+//! the visible lines.
+//!
+//! Run with `cargo bench --bench frame_prep`.
+
+use glyphon::{Color, TextArea, TextBounds};
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use markup5ever_rcdom::RcDom;
+use navigator::infrastructure::{LayoutBuilder, PageContent};
+use navigator::ui::fonts::build_font_system;
+use navigator::ui::text_renderer::{
+    build_page_layout, ContentLayoutOptions, LinkColors, PageArea, PageLayoutCache, PageLayoutKey,
+};
+use navigator::ui::FontSettings;
+use std::time::{Duration, Instant};
+
+const WIDTH: f32 = 960.0;
+const VIEWPORT_HEIGHT: f32 = 800.0;
+const FRAMES: usize = 60;
+
+fn long_page() -> PageContent {
+    let mut html = String::from("<html><body>");
+    for i in 0..1_000 {
+        html.push_str(&format!(
+            "<h2 id=\"s{i}\">Section {i}</h2><p>Lorem ipsum dolor sit amet, <b>consectetur</b> \
+             adipiscing elit, sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. \
+             <a href=\"#s{i}\">Ut enim</a> ad minim veniam, quis nostrud exercitation.</p>"
+        ));
+    }
+    html.push_str("</body></html>");
+
+    let dom = parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut html.as_bytes())
+        .unwrap();
+    LayoutBuilder::new().build(&dom)
+}
+
+/// Glyphs glyphon would prepare for a text area: it walks runs from the
+/// buffer's scroll position until they leave the bounds
+fn prepared_glyphs(area: &TextArea) -> usize {
+    let bounds_top = area.bounds.top as f32;
+    let bounds_bottom = area.bounds.bottom as f32;
+    let is_visible = |top: f32, height: f32| {
+        let top = area.top + top;
+        top <= bounds_bottom && bounds_top <= top + height
+    };
+    area.buffer
+        .layout_runs()
+        .skip_while(|run| !is_visible(run.line_top, run.line_height))
+        .take_while(|run| is_visible(run.line_top, run.line_height))
+        .map(|run| run.glyphs.len())
+        .sum()
+}
+
+fn area_at(offset: f32) -> PageArea {
+    PageArea {
+        left: 0.0,
+        top: -offset,
+        bounds: TextBounds {
+            left: 0,
+            top: 0,
+            right: WIDTH as i32,
+            bottom: VIEWPORT_HEIGHT as i32,
+        },
+        default_color: Color::rgb(0, 0, 0),
+    }
+}
+
+fn main() {
+    let fonts = FontSettings::default();
+    let mut font_system = build_font_system(&fonts);
+    let content = long_page();
+    let options = ContentLayoutOptions::default();
+    let font_size = fonts.default_size;
+    let key = PageLayoutKey {
+        generation: 1,
+        width: WIDTH,
+        font_size,
+        options,
+        dark_theme: false,
+        link_colors: LinkColors {
+            link: Color::rgb(0x0b, 0x57, 0xd0),
+            visited: Color::rgb(0x6b, 0x2f, 0xa0),
+        },
+        recolored: false,
+    };
+
+    let height = build_page_layout(&mut font_system, &fonts, &content, &key).height();
+    let step = (height - VIEWPORT_HEIGHT).max(0.0) / FRAMES as f32;
+    println!("page: {} bytes of text, {:.0}px tall", content.text.len(), height);
+
+    // Before: reshape the whole page every frame
+    let mut before = Duration::ZERO;
+    for frame in 0..FRAMES {
+        let started = Instant::now();
+        let layout = build_page_layout(&mut font_system, &fonts, &content, &key);
+        let area = area_at(frame as f32 * step);
+        let text_area = TextArea {
+            buffer: &layout.buffer,
+            left: area.left,
+            top: area.top,
+            scale: 1.0,
+            bounds: area.bounds,
+            default_color: area.default_color,
+            custom_glyphs: &[],
+        };
+        std::hint::black_box(prepared_glyphs(&text_area));
+        before += started.elapsed();
+    }
+
+    // After: shape once, then only place the visible lines
+    let mut cache = PageLayoutCache::new();
+    cache.get_or_build(key, &content, &mut font_system, &fonts);
+    let mut after = Duration::ZERO;
+    for frame in 0..FRAMES {
+        let started = Instant::now();
+        cache.get_or_build(key, &content, &mut font_system, &fonts);
+        if let Some(text_area) = cache.visible_area(&area_at(frame as f32 * step)) {
+            std::hint::black_box(prepared_glyphs(&text_area));
+        }
+        cache.reset_scroll();
+        after += started.elapsed();
+    }
+
+    let per_frame = |total: Duration| total / FRAMES as u32;
+    println!("rebuild every frame: {:?}/frame", per_frame(before));
+    println!("cached layout:       {:?}/frame", per_frame(after));
+}
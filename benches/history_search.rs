@@ -0,0 +1,97 @@
+//! History search on a plaintext profile versus an encrypted one.
+//!
+//! An encrypted profile stores URLs and titles sealed with AES-256-GCM, so
+//! SQLite can't match them with LIKE: every search reads and decrypts the
+//! whole history table, then filters in memory. Search cost therefore grows
+//! with the size of the history rather than with the number of matches, and
+//! a miss costs as much as a hit. Lookups by exact URL use a keyed hash and
+//! stay indexed.
+//!
+//! Measured on 5,000 entries with an optimized build:
+//!
+//! | search          | plaintext | encrypted |
+//! |-----------------|-----------|-----------|
+//! | hit (`topic 7`) | 0.36 ms   | 35 ms     |
+//! | miss            | 2.4 ms    | 33 ms     |
+//!
+//! Unlocking the profile (600,000 PBKDF2 rounds) took 0.35 s at startup.
+//!
+//! Run with `cargo bench --bench history_search`.
+
+use navigator::domain::{HistoryRepository, NewHistoryEntry, ValidatedUrl};
+use navigator::infrastructure::SqliteDatabase;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const ENTRIES: usize = 5_000;
+const SEARCHES: u32 = 20;
+const PASSPHRASE: &str = "benchmark passphrase";
+
+fn database_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("navigator-bench-{}-{}.db", name, std::process::id()))
+}
+
+fn remove_database(path: &Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let mut file = path.as_os_str().to_owned();
+        file.push(suffix);
+        let _ = std::fs::remove_file(file);
+    }
+}
+
+async fn filled_database(path: &Path) -> SqliteDatabase {
+    remove_database(path);
+    let db = SqliteDatabase::new(&format!("sqlite://{}", path.display()))
+        .await
+        .unwrap();
+
+    let entries: Vec<_> = (0..ENTRIES)
+        .map(|i| {
+            let url = ValidatedUrl::parse(&format!("https://site{}.example/articles/{}", i % 97, i)).unwrap();
+            NewHistoryEntry::new(url, format!("Article {} about topic {}", i, i % 13))
+        })
+        .collect();
+    db.add_batch(&entries).await.unwrap();
+    db
+}
+
+async fn time_search(db: &SqliteDatabase, query: &str) -> Duration {
+    let started = Instant::now();
+    for _ in 0..SEARCHES {
+        std::hint::black_box(HistoryRepository::search(db, query, 20).await.unwrap());
+    }
+    started.elapsed() / SEARCHES
+}
+
+async fn run() {
+    let plain_path = database_path("plain");
+    let encrypted_path = database_path("encrypted");
+
+    let plain = filled_database(&plain_path).await;
+    let mut encrypted = filled_database(&encrypted_path).await;
+    encrypted.encrypt_profile(PASSPHRASE).await.unwrap();
+
+    println!("{} history entries, {} searches each", ENTRIES, SEARCHES);
+    for query in ["topic 7", "no such page"] {
+        println!(
+            "{:>14}: plaintext {:?}/search, encrypted {:?}/search",
+            format!("{:?}", query),
+            time_search(&plain, query).await,
+            time_search(&encrypted, query).await,
+        );
+    }
+
+    let started = Instant::now();
+    SqliteDatabase::open(&format!("sqlite://{}", encrypted_path.display()), Some(PASSPHRASE))
+        .await
+        .unwrap();
+    println!("unlocking the encrypted profile: {:?}", started.elapsed());
+
+    drop((plain, encrypted));
+    remove_database(&plain_path);
+    remove_database(&encrypted_path);
+}
+
+fn main() {
+    tokio::runtime::Runtime::new().unwrap().block_on(run());
+}
@@ -44,17 +44,17 @@ async fn main() -> anyhow::Result<()> {
     let db = Arc::new(SqliteDatabase::new("test_browser.db").await?);
 
     // Bookmarks
-    let bookmark = Bookmark::new("Example Site".to_string(), url.clone());
-    let bookmark_id = BookmarkRepository::save(&*db, &bookmark).await?;
-    println!("   ✅ Bookmark sauvegardé: ID {}", bookmark_id);
+    let bookmark = NewBookmark::new("Example Site".to_string(), url.clone());
+    let bookmark = BookmarkRepository::save(&*db, bookmark).await?;
+    println!("   ✅ Bookmark sauvegardé: ID {}", bookmark.id);
 
     let all_bookmarks = BookmarkRepository::find_all(&*db).await?;
     println!("   ✅ Bookmarks trouvés: {}", all_bookmarks.len());
 
     // History
-    let history_entry = HistoryEntry::new(url.clone(), "Example Domain".to_string());
-    let history_id = db.add(&history_entry).await?;
-    println!("   ✅ Historique enregistré: ID {}", history_id);
+    let history_entry = NewHistoryEntry::new(url.clone(), "Example Domain".to_string());
+    let history_entry = db.add(history_entry).await?;
+    println!("   ✅ Historique enregistré: ID {}", history_entry.id);
 
     let recent_history = db.get_recent(10).await?;
     println!("   ✅ Entrées d'historique: {}", recent_history.len());
@@ -64,7 +64,7 @@ async fn main() -> anyhow::Result<()> {
     let state = BrowserState::new();
 
     let tab1_id = state.add_tab(Tab::new(false));
-    let tab2_id = state.add_tab(Tab::new(false));
+    let _tab2_id = state.add_tab(Tab::new(false));
     println!("   ✅ Onglets créés: {}", state.tab_count());
 
     state.set_active_tab(tab1_id);
@@ -76,13 +76,20 @@ async fn main() -> anyhow::Result<()> {
     // Test 5: Use Cases
     println!("\n🎯 [5/6] Test Use Cases");
 
-    let open_tab_uc = OpenTabUseCase::new(state.clone(), db.clone());
-    let new_tab_id = open_tab_uc.execute(None).await?;
-    println!("   ✅ OpenTabUseCase: Tab {}", new_tab_id);
-
-    let close_tab_uc = CloseTabUseCase::new(state.clone(), db.clone());
-    close_tab_uc.execute(new_tab_id).await?;
-    println!("   ✅ CloseTabUseCase: Tab fermé");
+    let controller = BrowserController::new(
+        state.clone(),
+        Repositories::shared(db.clone()),
+        security.clone(),
+        Arc::new(DefaultUserStyleService::new()),
+        Arc::new(DefaultUserScriptService::new()),
+        ServoRenderer::new,
+    );
+    let new_tab_id = controller.open_tab(None).await?;
+    println!("   ✅ BrowserController::open_tab: Tab {}", new_tab_id);
+
+    controller.close_tab(new_tab_id).await?;
+    println!("   ✅ BrowserController::close_tab: Tab fermé");
+    controller.shutdown().await;
 
     // Test 6: Network (si connexion internet disponible)
     println!("\n🌐 [6/6] Test Network Service");
@@ -0,0 +1,3238 @@
+use crate::domain::{
+    local_day_bounds, AccessibleNode, BlockedSummary, Bookmark, BookmarkRepository, BrowserProfileReader,
+    Certificate, ConsoleLevel, ConsoleMessage, CspViolation, CustomCa, CustomCaRepository, Download,
+    DownloadRepository, DownloadWriter, ExternalLauncher, HistoryEntry, HistoryRepository, HostPattern, HttpVersion,
+    MatchPattern, MemoryReporter, NewSearchEngine, NewUserScript, NewUserStyle, OpenSearchFetcher, PageArchiver,
+    PageCacheRepository, PagePrinter, PaperSize, PersistenceGuard, RenderingEngine, RequestContext, RobotsTxt,
+    RunAt, SavedWindow, SearchEngine, SearchEngineError, SearchEngineRepository, SearchTemplate, SecurityError,
+    SecurityReport, SecurityService, SettingsRepository, SiteMetadataFetcher, SiteOrigin, SiteSetting, SiteSettings,
+    SiteSettingsRepository, SitemapEntry, StorageArea, StorageChange, StorageKind, Tab, TabId, TabRepository,
+    TlsInspector, TrustStore, UpdateManifest, UpdateSource, UserScript, UserScriptError, UserScriptRepository,
+    UserScriptService, UserStyle, UserStyleError, UserStyleRepository, UserStyleRule, UserStyleService,
+    ValidatedUrl, VisitType, WebStorageRepository, WindowId,
+};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use super::download_safety::{DownloadManager, DownloadOutcome, FileDownload, PendingDownload, UnfinishedDownload};
+use super::downloads::{
+    SaveFormat, SessionDownloads, DOWNLOADS_DIRECTORY_SETTING, DOWNLOADS_PAGE, PAPER_SIZE_SETTING,
+};
+use super::error::NavigatorError;
+use super::external_schemes::{
+    parse_always_open_schemes, with_always_open_scheme, ExternalLaunch, ExternalSchemeHandler, LaunchAnswer,
+    ALWAYS_OPEN_SCHEMES_SETTING,
+};
+use super::history_writer::HistoryWriter;
+use super::keymap::{format_chords, Chord, Command, Keymap, COMMANDS};
+use super::memory::{
+    parse_memory_ceiling, MemoryRegistry, TabMemory, DEFAULT_MEMORY_CEILING, MEMORY_CEILING_SETTING,
+};
+use super::navigation::{NavigationEntry, NavigationHistory};
+use super::notifications::{Notification, NotificationSeverity};
+use super::omnibox::{classify_input, OmniboxInput};
+use super::palette::{rank_palette, PaletteEntry, PaletteUsage, MAX_PALETTE_HISTORY, PALETTE_USAGE_SETTING};
+use super::profile::{BrowserImportReport, BrowserImportRequest, ImportOptions, ImportReport};
+use super::settings::{
+    editable_setting, format_user_styles, parse_user_styles, setting_enabled, RESTORE_SESSION_SETTING,
+    USER_STYLES_SETTING,
+};
+use super::state::{BrowserState, TabActivity};
+use super::suggestions::{local_suggestions, Suggestion, MAX_LOCAL_SUGGESTIONS};
+use super::tab_search::{tab_query, TabIndex, MAX_TAB_SUGGESTIONS};
+use super::throttling::ThrottlePolicy;
+use super::typed_settings::{Settings, SharedSettings};
+use super::undo::{Deleted, UndoStack};
+use super::updates::{release_file_name, CURRENT_VERSION, UPDATE_PAGE};
+use super::use_cases::{
+    AddSearchEngineUseCase, AddUserScriptUseCase, AddUserStyleUseCase, CheckForUpdateUseCase,
+    ClearBrowsingDataUseCase, CloseTabUseCase, DeleteBookmarkUseCase, DeleteFolderUseCase,
+    DeleteHistoryEntryUseCase, DeleteHistoryRangeUseCase, DownloadFileUseCase, ExportProfileUseCase,
+    ExportSecurityReportUseCase, GetBlockedSummaryUseCase, GetRecentHistoryUseCase, ImportCertificateUseCase,
+    ImportFromBrowserUseCase, ImportProfileUseCase, MoveBookmarkUseCase, NavigateUseCase, OfferSearchEngineUseCase,
+    OpenTabUseCase, PrintPageUseCase, RemoveCertificateUseCase, RemoveDownloadEntryUseCase, RemoveUserScriptUseCase,
+    RemoveUserStyleUseCase, ReplaceUserStylesUseCase, RestoreDeletedUseCase, RunUserScriptsUseCase,
+    SaveBookmarkUseCase, SavePageUseCase, SearchHistoryUseCase, SetDefaultSearchEngineUseCase,
+    SetSiteSettingUseCase, UpdateSettingsUseCase, UpdateUserScriptUseCase, UpdateWebStorageUseCase,
+};
+
+type Result<T> = std::result::Result<T, NavigatorError>;
+
+/// Closed windows kept to reopen; older ones are forgotten
+const MAX_CLOSED_WINDOWS: usize = 10;
+/// Visited pages looked through for address bar suggestions
+const MAX_HISTORY_MATCHES: i32 = 20;
+
+/// Storage the controller reads and writes
+#[derive(Clone)]
+pub struct Repositories {
+    pub tabs: Arc<dyn TabRepository>,
+    pub bookmarks: Arc<dyn BookmarkRepository>,
+    pub history: Arc<dyn HistoryRepository>,
+    pub settings: Arc<dyn SettingsRepository>,
+    pub user_styles: Arc<dyn UserStyleRepository>,
+    pub user_scripts: Arc<dyn UserScriptRepository>,
+    pub search_engines: Arc<dyn SearchEngineRepository>,
+    pub downloads: Arc<dyn DownloadRepository>,
+    pub site_settings: Arc<dyn SiteSettingsRepository>,
+    pub web_storage: Arc<dyn WebStorageRepository>,
+    /// Pages kept for offline browsing
+    pub page_cache: Arc<dyn PageCacheRepository>,
+    /// Certificate authorities trusted besides the built-in roots
+    pub custom_cas: Arc<dyn CustomCaRepository>,
+}
+
+impl Repositories {
+    /// Keep everything in one store, such as the SQLite profile
+    pub fn shared<R>(store: Arc<R>) -> Self
+    where
+        R: TabRepository
+            + BookmarkRepository
+            + HistoryRepository
+            + SettingsRepository
+            + UserStyleRepository
+            + UserScriptRepository
+            + SearchEngineRepository
+            + DownloadRepository
+            + SiteSettingsRepository
+            + WebStorageRepository
+            + PageCacheRepository
+            + CustomCaRepository
+            + 'static,
+    {
+        Self {
+            tabs: store.clone(),
+            bookmarks: store.clone(),
+            history: store.clone(),
+            settings: store.clone(),
+            user_styles: store.clone(),
+            user_scripts: store.clone(),
+            search_engines: store.clone(),
+            downloads: store.clone(),
+            site_settings: store.clone(),
+            web_storage: store.clone(),
+            page_cache: store.clone(),
+            custom_cas: store,
+        }
+    }
+}
+
+/// Where a navigation left a tab
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavigationOutcome {
+    pub url: ValidatedUrl,
+    /// Only the fragment changed, so the loaded page was kept
+    pub same_document: bool,
+    /// Scroll position saved with the history entry, for back, forward and reload
+    pub scroll_offset: Option<f32>,
+}
+
+/// What clearing browsing data deletes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClearScope {
+    pub history: bool,
+    /// Every site's overrides of the global settings
+    pub site_settings: bool,
+    /// What sites keep in `localStorage` and `sessionStorage`
+    pub web_storage: bool,
+}
+
+impl ClearScope {
+    pub const HISTORY: Self = Self {
+        history: true,
+        site_settings: false,
+        web_storage: false,
+    };
+    pub const ALL: Self = Self {
+        history: true,
+        site_settings: true,
+        web_storage: true,
+    };
+}
+
+/// Engine and session history of one tab
+struct TabSession<E> {
+    engine: Arc<E>,
+    history: NavigationHistory,
+    /// URL of the document the engine last loaded
+    document: Option<ValidatedUrl>,
+    /// `sessionStorage` by origin, and in private tabs `localStorage` too
+    storage: HashMap<(StorageKind, SiteOrigin), StorageArea>,
+    /// Accounts for the engine in the memory registry while the tab is open
+    _memory: Arc<dyn MemoryReporter>,
+}
+
+/// Entry point for everything a front end does with tabs
+///
+/// Owns the browser state, storage, the security, user style and user script
+/// services and one rendering engine per tab, so front ends never validate
+/// URLs or touch repositories themselves.
+pub struct BrowserController<E> {
+    state: BrowserState,
+    security: Arc<dyn SecurityService>,
+    user_styles: Arc<dyn UserStyleService>,
+    user_scripts: Arc<dyn UserScriptService>,
+    repositories: Repositories,
+    history_writer: HistoryWriter,
+    throttle: ThrottlePolicy,
+    /// Reads the search engines pages offer; without it none are offered
+    opensearch: Option<Arc<dyn OpenSearchFetcher>>,
+    /// Stored downloads, plus those of private tabs for this session
+    downloads: Arc<SessionDownloads>,
+    /// Writes saved pages; without it pages cannot be saved
+    download_writer: Option<Arc<dyn DownloadWriter>>,
+    /// Downloads held until the user confirms them
+    download_manager: Arc<DownloadManager>,
+    /// Archives pages saved as MHTML; without it they cannot be
+    page_archiver: Option<Arc<dyn PageArchiver>>,
+    /// Prints pages to PDF; without it they cannot be printed
+    page_printer: Option<Arc<dyn PagePrinter>>,
+    /// Opens `mailto:` links and the like in other programs; without it
+    /// they cannot be opened
+    external_launcher: Option<Arc<dyn ExternalLauncher>>,
+    /// Links for other programs held until the user answers
+    external_schemes: ExternalSchemeHandler,
+    /// Trusts the certificate authorities added; without it none can be
+    trust_store: Option<Arc<dyn TrustStore>>,
+    /// Looks at pages' TLS sessions for security reports; without it
+    /// reports leave them out
+    tls_inspector: Option<Arc<dyn TlsInspector>>,
+    /// Fetches update manifests and releases; without it no update is
+    /// checked for
+    update_source: Option<Arc<dyn UpdateSource>>,
+    /// Reads sites' robots.txt and sitemaps; without it neither can be
+    site_metadata: Option<Arc<dyn SiteMetadataFetcher>>,
+    /// The newer release the last update check found
+    available_update: RwLock<Option<UpdateManifest>>,
+    /// Settings as last stored, read by each navigation
+    settings: SharedSettings,
+    /// Windows closed this session, most recent last
+    closed_windows: RwLock<Vec<SavedWindow>>,
+    /// Deletions that Ctrl+Z can still take back
+    undo: RwLock<UndoStack<Deleted>>,
+    /// Shortcuts as last stored in settings
+    keymap: RwLock<Keymap>,
+    /// Open tabs to suggest switching to from the address bar
+    tab_index: TabIndex,
+    /// Caches and stores accounting for their memory, each tab's engine
+    /// among them
+    memory: MemoryRegistry,
+    /// Turns true once shutdown began, ending loads under way
+    shutting_down: watch::Sender<bool>,
+    new_engine: Box<dyn Fn() -> E + Send + Sync>,
+    sessions: RwLock<HashMap<TabId, TabSession<E>>>,
+}
+
+impl<E: RenderingEngine + 'static> BrowserController<E> {
+    /// Must be called inside a Tokio runtime, which runs the history writer
+    pub fn new(
+        state: BrowserState,
+        repositories: Repositories,
+        security: Arc<dyn SecurityService>,
+        user_styles: Arc<dyn UserStyleService>,
+        user_scripts: Arc<dyn UserScriptService>,
+        new_engine: impl Fn() -> E + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            tab_index: TabIndex::new(state.clone()),
+            state,
+            security,
+            user_styles,
+            user_scripts,
+            history_writer: HistoryWriter::spawn(repositories.history.clone()),
+            throttle: ThrottlePolicy::new(),
+            opensearch: None,
+            downloads: Arc::new(SessionDownloads::new(repositories.downloads.clone())),
+            download_writer: None,
+            download_manager: Arc::new(DownloadManager::new()),
+            page_archiver: None,
+            page_printer: None,
+            external_launcher: None,
+            external_schemes: ExternalSchemeHandler::new(),
+            trust_store: None,
+            tls_inspector: None,
+            update_source: None,
+            site_metadata: None,
+            available_update: RwLock::new(None),
+            settings: SharedSettings::new(),
+            closed_windows: RwLock::new(Vec::new()),
+            undo: RwLock::new(UndoStack::new()),
+            keymap: RwLock::new(Keymap::new()),
+            memory: MemoryRegistry::new(),
+            shutting_down: watch::Sender::new(false),
+            repositories,
+            new_engine: Box::new(new_engine),
+            sessions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Throttle and suspend hidden tabs by `throttle` instead of the defaults
+    pub fn with_throttle_policy(mut self, throttle: ThrottlePolicy) -> Self {
+        self.throttle = throttle;
+        self
+    }
+
+    /// Offer adding the search engines pages link OpenSearch descriptions of
+    pub fn with_opensearch_fetcher(mut self, opensearch: Arc<dyn OpenSearchFetcher>) -> Self {
+        self.opensearch = Some(opensearch);
+        self
+    }
+
+    /// Save pages with `writer`, recording them in `downloads`; share
+    /// `downloads` with whatever lists them so private saves show there too
+    pub fn with_downloads(mut self, downloads: Arc<SessionDownloads>, writer: Arc<dyn DownloadWriter>) -> Self {
+        self.downloads = downloads;
+        self.download_writer = Some(writer);
+        self
+    }
+
+    /// Save pages as MHTML with `archiver`
+    pub fn with_page_archiver(mut self, archiver: Arc<dyn PageArchiver>) -> Self {
+        self.page_archiver = Some(archiver);
+        self
+    }
+
+    /// Print pages to PDF with `printer`
+    pub fn with_page_printer(mut self, printer: Arc<dyn PagePrinter>) -> Self {
+        self.page_printer = Some(printer);
+        self
+    }
+
+    /// Open links of external schemes, such as `mailto:`, with `launcher`
+    pub fn with_external_launcher(mut self, launcher: Arc<dyn ExternalLauncher>) -> Self {
+        self.external_launcher = Some(launcher);
+        self
+    }
+
+    /// Trust the certificate authorities added with `trust_store`
+    pub fn with_trust_store(mut self, trust_store: Arc<dyn TrustStore>) -> Self {
+        self.trust_store = Some(trust_store);
+        self
+    }
+
+    /// Describe pages' TLS sessions in security reports with `tls_inspector`
+    pub fn with_tls_inspector(mut self, tls_inspector: Arc<dyn TlsInspector>) -> Self {
+        self.tls_inspector = Some(tls_inspector);
+        self
+    }
+
+    /// Check for and download updates with `source`
+    pub fn with_update_source(mut self, source: Arc<dyn UpdateSource>) -> Self {
+        self.update_source = Some(source);
+        self
+    }
+
+    /// Read sites' robots.txt and sitemaps with `fetcher`
+    pub fn with_site_metadata(mut self, fetcher: Arc<dyn SiteMetadataFetcher>) -> Self {
+        self.site_metadata = Some(fetcher);
+        self
+    }
+
+    pub fn state(&self) -> &BrowserState {
+        &self.state
+    }
+
+    pub fn throttle_policy(&self) -> &ThrottlePolicy {
+        &self.throttle
+    }
+
+    /// Where caches register to be accounted and trimmed
+    pub fn memory(&self) -> &MemoryRegistry {
+        &self.memory
+    }
+
+    /// Turn user input into a URL that may be navigated to
+    pub fn resolve(&self, input: &str) -> Result<ValidatedUrl> {
+        let mut url = self.security.validate_url(input)?;
+        if self.settings.load().https_only && url.scheme() == "http" {
+            url = self.security.validate_url(&format!("https{}", &url.as_str()["http".len()..]))?;
+        }
+        if self.security.is_blocked(&url) {
+            return Err(SecurityError::Blocked(url.host_str().unwrap_or(url.as_str()).to_string()).into());
+        }
+        Ok(url)
+    }
+
+    /// Open an empty tab, in `window` if given, and make it active
+    pub async fn open_tab(&self, window: Option<WindowId>) -> Result<TabId> {
+        let tab_id = OpenTabUseCase::new(self.state.clone(), self.repositories.tabs.clone())
+            .execute(RequestContext::new(None, self.state.is_private_mode()), None, window)
+            .await?;
+
+        let engine = Arc::new((self.new_engine)());
+        engine.set_private(self.state.get_tab(tab_id).is_some_and(|tab| tab.is_private));
+        let memory: Arc<dyn MemoryReporter> = Arc::new(TabMemory::new(tab_id, engine.clone(), self.state.clone()));
+        self.memory.register(&memory);
+        if let Ok(mut sessions) = self.sessions.write() {
+            sessions.insert(
+                tab_id,
+                TabSession {
+                    engine,
+                    history: NavigationHistory::new(),
+                    document: None,
+                    storage: HashMap::new(),
+                    _memory: memory,
+                },
+            );
+        }
+        self.throttle_timers();
+        Ok(tab_id)
+    }
+
+    pub async fn close_tab(&self, tab_id: TabId) -> Result<()> {
+        if let Ok(mut sessions) = self.sessions.write() {
+            sessions.remove(&tab_id);
+        }
+        let context = self.context(tab_id);
+        CloseTabUseCase::new(self.state.clone(), self.repositories.tabs.clone())
+            .execute(context, tab_id)
+            .await
+    }
+
+    /// Close every tab of a window, then the window itself, keeping its
+    /// tabs to reopen with [`Self::reopen_closed_window`]
+    pub async fn close_window(&self, window: WindowId) -> Vec<Tab> {
+        if let Some(saved) = self.saved_window(window) {
+            if let Ok(mut closed) = self.closed_windows.write() {
+                if closed.len() == MAX_CLOSED_WINDOWS {
+                    closed.remove(0);
+                }
+                closed.push(saved);
+            }
+        }
+        let tabs = self.state.tabs_in_window(window);
+        for tab in &tabs {
+            if let Err(e) = self.close_tab(tab.id).await {
+                tracing::warn!("Failed to close tab {}: {}", tab.id, e);
+            }
+        }
+        self.state.close_window(window);
+        tabs
+    }
+
+    /// The window closed most recently, forgetting it; open a new window
+    /// with its tabs
+    pub fn reopen_closed_window(&self) -> Option<SavedWindow> {
+        self.closed_windows.write().ok()?.pop()
+    }
+
+    /// Open a saved window's tabs into `window` in their order. Only the
+    /// tab to show, returned and made active, gets a page to load; the
+    /// others keep their address and title until shown.
+    pub async fn restore_window(&self, window: WindowId, saved: &SavedWindow) -> Result<Option<TabId>> {
+        let shown = saved.shown_tab().map(|tab| tab.id);
+        let mut shown_id = None;
+        for tab in &saved.tabs {
+            let tab_id = self.open_tab(Some(window)).await?;
+            if Some(tab.id) == shown {
+                shown_id = Some(tab_id);
+                continue;
+            }
+            if let Some(mut opened) = self.state.get_tab(tab_id) {
+                opened.url = tab.url.clone();
+                opened.title = tab.title.clone();
+                if !opened.is_private {
+                    PersistenceGuard::new(self.repositories.tabs.clone(), RequestContext::for_tab(&opened))
+                        .save(&opened)
+                        .await?;
+                }
+                self.state.update_tab(opened);
+            }
+        }
+        if let Some(tab_id) = shown_id {
+            self.state.set_active_tab(tab_id);
+        }
+        Ok(shown_id)
+    }
+
+    /// A window's tabs as saved in a session; private tabs and tabs that
+    /// still blank are left out, and a window without others is
+    fn saved_window(&self, window: WindowId) -> Option<SavedWindow> {
+        let tabs: Vec<Tab> = self
+            .state
+            .tabs_in_window(window)
+            .into_iter()
+            .filter(|tab| !tab.is_private && tab.page_url().is_some())
+            .collect();
+        if tabs.is_empty() {
+            return None;
+        }
+        let active_tab = self
+            .state
+            .get_active_tab_id_in_window(window)
+            .filter(|active| tabs.iter().any(|tab| tab.id == *active));
+        Some(SavedWindow {
+            id: window,
+            geometry: self.state.window_geometry(window),
+            active_tab,
+            tabs,
+        })
+    }
+
+    /// Rendering engine showing a tab's page
+    pub fn get_page(&self, tab_id: TabId) -> Option<Arc<E>> {
+        self.sessions
+            .read()
+            .ok()?
+            .get(&tab_id)
+            .map(|session| session.engine.clone())
+    }
+
+    /// Text of the page shown in a tab, as laid out
+    pub async fn page_text(&self, tab_id: TabId) -> Result<String> {
+        Ok(self.page(tab_id)?.get_text().await?)
+    }
+
+    /// Accessible tree of the page shown in a tab
+    pub async fn accessibility_tree(&self, tab_id: TabId) -> Result<AccessibleNode> {
+        Ok(self.page(tab_id)?.accessibility_tree().await?)
+    }
+
+    /// PNG image of a tab's page; empty if the engine cannot capture one
+    pub async fn screenshot(&self, tab_id: TabId) -> Result<Vec<u8>> {
+        Ok(self.page(tab_id)?.take_screenshot().await?)
+    }
+
+    /// Run JavaScript in a tab's page, returning its result as text
+    pub async fn evaluate(&self, tab_id: TabId, script: &str) -> Result<String> {
+        Ok(self.page(tab_id)?.execute_javascript(script).await?)
+    }
+
+    /// What a tab's scripts logged and how they failed, oldest first
+    pub fn console(&self, tab_id: TabId) -> Result<Vec<ConsoleMessage>> {
+        Ok(self.page(tab_id)?.console_messages())
+    }
+
+    /// Evaluate an expression typed into a tab's console, logging it there
+    /// with its result or the exception it threw
+    pub async fn evaluate_in_console(&self, tab_id: TabId, expression: &str) -> Result<String> {
+        let page = self.page(tab_id)?;
+        page.log_to_console(ConsoleLevel::Log, &format!("> {}", expression), None);
+        match page.execute_javascript(expression).await {
+            Ok(result) => {
+                page.log_to_console(ConsoleLevel::Log, &format!("< {}", result), None);
+                Ok(result)
+            }
+            Err(e) => {
+                page.log_to_console(ConsoleLevel::Error, &format!("Uncaught {}", e), None);
+                Err(e.into())
+            }
+        }
+    }
+
+    pub fn clear_console(&self, tab_id: TabId) -> Result<()> {
+        self.page(tab_id)?.clear_console();
+        Ok(())
+    }
+
+    /// Keep a tab's console across navigations, or clear it on each again
+    pub fn set_preserve_console(&self, tab_id: TabId, preserve: bool) -> Result<()> {
+        self.page(tab_id)?.set_preserve_console(preserve);
+        Ok(())
+    }
+
+    /// Navigate a tab to user-entered text, recorded in history as typed
+    pub async fn navigate(&self, tab_id: TabId, input: &str) -> Result<NavigationOutcome> {
+        self.navigate_as(tab_id, input, VisitType::Typed).await
+    }
+
+    /// Navigate a tab to user-entered text or a resolved link, recording
+    /// the visit as `visit_type`; text that is not a URL is searched for as
+    /// described at [`classify_input`]
+    pub async fn navigate_as(
+        &self,
+        tab_id: TabId,
+        input: &str,
+        visit_type: VisitType,
+    ) -> Result<NavigationOutcome> {
+        let engines = self.repositories.search_engines.find_all().await.unwrap_or_else(|e| {
+            tracing::warn!("Searching without search engines: {}", e);
+            Vec::new()
+        });
+        let url = match classify_input(input, &engines) {
+            OmniboxInput::Navigate(text) => self.resolve(&text)?,
+            OmniboxInput::Search { url, .. } => self.resolve(url.as_str())?,
+        };
+        // Those go through open_external, never into a tab or its history
+        if self.security.is_external(&url) {
+            return Err(SecurityError::ExternalScheme(url.scheme().to_string()).into());
+        }
+        let url = self.carry_out_page_actions(url).await;
+        self.with_session(tab_id, |session| session.history.push(url.clone()))?;
+        self.load(tab_id, url, None, true, visit_type).await
+    }
+
+    /// Hand `input` to another program if it is a URL of an external
+    /// scheme, such as a `mailto:` link; `None` for anything to navigate to
+    /// instead. Schemes the user always opens go straight to their handler;
+    /// other URLs wait for [`Self::answer_external_launch`].
+    pub async fn open_external(&self, input: &str) -> Result<Option<ExternalLaunch>> {
+        let url = match self.security.validate_url(input) {
+            Ok(url) if self.security.is_external(&url) => url,
+            _ => return Ok(None),
+        };
+        let launcher = self.external_launcher.clone().ok_or(NavigatorError::LaunchingUnavailable)?;
+        let always_open = self.repositories.settings.get(ALWAYS_OPEN_SCHEMES_SETTING).await?;
+        if always_open
+            .as_deref()
+            .is_some_and(|value| parse_always_open_schemes(value).iter().any(|scheme| scheme == url.scheme()))
+        {
+            launch(launcher.as_ref(), &url)?;
+            return Ok(Some(ExternalLaunch::Launched(url)));
+        }
+        tracing::info!("Asking before opening a {}: link", url.scheme());
+        Ok(Some(ExternalLaunch::PendingConfirmation(self.external_schemes.hold(url))))
+    }
+
+    /// Act on the user's answer to a held launch, returning the URL if it
+    /// was opened; "always open" also stops asking for its scheme
+    pub async fn answer_external_launch(&self, id: u64, answer: LaunchAnswer) -> Result<Option<ValidatedUrl>> {
+        let pending = self
+            .external_schemes
+            .take(id)
+            .ok_or(NavigatorError::PendingLaunchNotFound(id))?;
+        if answer == LaunchAnswer::Cancel {
+            return Ok(None);
+        }
+        let launcher = self.external_launcher.clone().ok_or(NavigatorError::LaunchingUnavailable)?;
+        if answer == LaunchAnswer::AlwaysOpen {
+            let settings = &self.repositories.settings;
+            let current = settings.get(ALWAYS_OPEN_SCHEMES_SETTING).await?;
+            let value = with_always_open_scheme(current.as_deref(), pending.url.scheme());
+            settings.set(ALWAYS_OPEN_SCHEMES_SETTING, &value).await?;
+        }
+        launch(launcher.as_ref(), &pending.url)?;
+        Ok(Some(pending.url))
+    }
+
+    /// Step back through the tab's session history, if possible
+    pub async fn back(&self, tab_id: TabId) -> Result<Option<NavigationOutcome>> {
+        let entry = self.with_session(tab_id, |session| session.history.go_back().cloned())?;
+        self.load_entry(tab_id, entry, true, VisitType::Link).await
+    }
+
+    pub async fn forward(&self, tab_id: TabId) -> Result<Option<NavigationOutcome>> {
+        let entry = self.with_session(tab_id, |session| session.history.go_forward().cloned())?;
+        self.load_entry(tab_id, entry, true, VisitType::Link).await
+    }
+
+    /// Fetch the current entry again
+    pub async fn reload(&self, tab_id: TabId) -> Result<Option<NavigationOutcome>> {
+        let entry = self.current_entry(tab_id);
+        self.load_entry(tab_id, entry, false, VisitType::Reload).await
+    }
+
+    /// Make a tab active, waking it if it was suspended. Returns where the
+    /// woken tab's page now is, to be shown like a finished navigation.
+    pub async fn activate_tab(&self, tab_id: TabId) -> Result<Option<NavigationOutcome>> {
+        let hidden_for = self.state.hidden_for(tab_id, Instant::now());
+        let was = self.state.tab_activity(tab_id);
+        self.state.set_active_tab(tab_id);
+        self.throttle_timers();
+        self.wake(tab_id, was, hidden_for).await
+    }
+
+    /// Bring a tab to the foreground without making it active, e.g. when its
+    /// window is uncovered; wakes it like `activate_tab`
+    pub async fn show_tab(&self, tab_id: TabId) -> Result<Option<NavigationOutcome>> {
+        let hidden_for = self.state.hidden_for(tab_id, Instant::now());
+        let was = self.state.tab_activity(tab_id);
+        self.state.set_tab_activity(tab_id, TabActivity::Foreground);
+        self.throttle_timers();
+        self.wake(tab_id, was, hidden_for).await
+    }
+
+    /// Suspend background tabs hidden past the policy's limit, dropping their
+    /// layouts; returns the tabs suspended. Call periodically.
+    pub async fn suspend_idle_tabs(&self, now: Instant) -> Vec<TabId> {
+        let Some(after) = self.throttle.suspend_after() else {
+            return Vec::new();
+        };
+        let mut suspended = Vec::new();
+        for tab_id in self.state.idle_tabs(now, after) {
+            let engine = self.with_session(tab_id, |session| {
+                let blank = session.document.as_ref().is_none_or(ValidatedUrl::is_about_blank);
+                (!blank).then(|| session.engine.clone())
+            });
+            // Blank tabs hold nothing worth dropping
+            let Ok(Some(engine)) = engine else {
+                continue;
+            };
+            match engine.suspend().await {
+                Ok(()) => {
+                    tracing::debug!("Suspended tab {}", tab_id);
+                    self.state.set_tab_activity(tab_id, TabActivity::Suspended);
+                    suspended.push(tab_id);
+                }
+                Err(e) => tracing::warn!("Failed to suspend tab {}: {}", tab_id, e),
+            }
+        }
+        self.throttle_timers();
+        suspended
+    }
+
+    /// Tell every tab's engine how often its page timers may run, by how
+    /// visible the tab is; call whenever tabs are shown or hidden
+    pub fn throttle_timers(&self) {
+        let Ok(sessions) = self.sessions.read() else {
+            return;
+        };
+        for (tab_id, session) in sessions.iter() {
+            let activity = self.state.tab_activity(*tab_id);
+            session.engine.throttle_timers(self.throttle.interval(activity, Duration::ZERO));
+        }
+    }
+
+    /// Remember how far the current entry is scrolled before leaving it
+    pub fn set_scroll_offset(&self, tab_id: TabId, offset: f32) {
+        let _ = self.with_session(tab_id, |session| session.history.set_scroll_offset(offset));
+    }
+
+    pub fn current_entry(&self, tab_id: TabId) -> Option<NavigationEntry> {
+        self.with_session(tab_id, |session| session.history.current().cloned())
+            .ok()
+            .flatten()
+    }
+
+    /// Bookmark the tab's page, or remove its bookmark; true if now bookmarked
+    pub async fn toggle_bookmark(&self, tab_id: TabId) -> Result<bool> {
+        let tab = self
+            .state
+            .get_tab(tab_id)
+            .ok_or(NavigatorError::TabNotFound(tab_id))?;
+        let url = tab.page_url().cloned().ok_or(NavigatorError::NothingToBookmark)?;
+
+        let bookmarks = self.repositories.bookmarks.find_all().await?;
+        let existing: Vec<&Bookmark> = bookmarks.iter().filter(|b| b.url.same_document(&url)).collect();
+        if !existing.is_empty() {
+            let use_case = DeleteBookmarkUseCase::new(self.repositories.bookmarks.clone());
+            let mut deleted = Vec::new();
+            for bookmark in existing {
+                deleted.push(use_case.execute(bookmark.id).await?);
+            }
+            self.record_undo(format!("Removed the bookmark for {}", url), Deleted::Bookmarks(deleted));
+            return Ok(false);
+        }
+
+        SaveBookmarkUseCase::new(self.repositories.bookmarks.clone())
+            .execute(tab.title, url)
+            .await?;
+        Ok(true)
+    }
+
+    /// Delete a bookmark; Ctrl+Z brings it back
+    pub async fn delete_bookmark(&self, id: i64) -> Result<Bookmark> {
+        let bookmark = DeleteBookmarkUseCase::new(self.repositories.bookmarks.clone())
+            .execute(id)
+            .await?;
+        let description = format!("Deleted the bookmark \"{}\"", bookmark.title);
+        self.record_undo(description, Deleted::Bookmarks(vec![bookmark.clone()]));
+        Ok(bookmark)
+    }
+
+    /// Delete a bookmark folder and the folders under it with their
+    /// bookmarks, returning how many went; Ctrl+Z brings them back
+    pub async fn delete_bookmark_folder(&self, folder: &str) -> Result<usize> {
+        let bookmarks = DeleteFolderUseCase::new(self.repositories.bookmarks.clone())
+            .execute(folder)
+            .await?;
+        let deleted = bookmarks.len();
+        if deleted > 0 {
+            let description = format!("Deleted the folder \"{}\" with {} bookmarks", folder.trim(), deleted);
+            self.record_undo(description, Deleted::Bookmarks(bookmarks));
+        }
+        Ok(deleted)
+    }
+
+    /// Every bookmark as navigator://bookmarks lists them: unfiled ones
+    /// first, then folder by folder, each in its folder's order
+    pub async fn bookmarks(&self) -> Result<Vec<Bookmark>> {
+        let mut bookmarks = self.repositories.bookmarks.find_all().await?;
+        bookmarks.sort_by(|a, b| a.folder.cmp(&b.folder));
+        Ok(bookmarks)
+    }
+
+    /// Move a bookmark into `folder` at `index` among the bookmarks there
+    pub async fn move_bookmark(&self, id: i64, folder: Option<&str>, index: usize) -> Result<()> {
+        MoveBookmarkUseCase::new(self.repositories.bookmarks.clone())
+            .execute(id, folder, index)
+            .await
+    }
+
+    /// Clear the days navigator://history and remove the downloads
+    /// navigator://downloads ask to in their query here, before the page
+    /// does, so that Ctrl+Z can bring them back. navigator://scripts asks
+    /// to change (`set=<id>:<option>=<value>`) and remove (`remove=<id>`)
+    /// user scripts the same way. The URL comes back without those
+    /// requests, so reloading it does not act again.
+    async fn carry_out_page_actions(&self, url: ValidatedUrl) -> ValidatedUrl {
+        if url.scheme() != "navigator" || url.query().is_none() {
+            return url;
+        }
+        let query: Vec<(String, String)> = url::form_urlencoded::parse(url.query().unwrap_or("").as_bytes())
+            .into_owned()
+            .collect();
+        let mut kept = Vec::new();
+        let mut handled = false;
+        for (name, value) in &query {
+            let done = match (url.host_str(), name.as_str()) {
+                (Some("history"), "clear_day") => {
+                    handled = true;
+                    match local_day_bounds(value) {
+                        Some((from, to)) => self.delete_history_range(from, to).await.map(|_| ()),
+                        None => continue,
+                    }
+                }
+                (Some("downloads"), "remove") => {
+                    handled = true;
+                    match value.parse() {
+                        Ok(id) => self.remove_download(id, false).await,
+                        Err(_) => continue,
+                    }
+                }
+                (Some("scripts"), "set") => {
+                    handled = true;
+                    match value.split_once(':').and_then(|(id, option)| Some((id.parse().ok()?, option))) {
+                        Some((id, option)) => self.set_user_script_option(id, option).await.map(|_| ()),
+                        None => continue,
+                    }
+                }
+                (Some("scripts"), "remove") => {
+                    handled = true;
+                    match value.parse() {
+                        Ok(id) => self.remove_user_script(id).await,
+                        Err(_) => continue,
+                    }
+                }
+                _ => {
+                    kept.push((name, value));
+                    continue;
+                }
+            };
+            if let Err(e) = done {
+                tracing::warn!("Failed to carry out {}={} from {}: {}", name, value, url, e);
+            }
+        }
+        if !handled {
+            return url;
+        }
+        let Ok(mut stripped) = url::Url::parse(url.as_str()) else {
+            return url;
+        };
+        let kept = url::form_urlencoded::Serializer::new(String::new()).extend_pairs(kept).finish();
+        stripped.set_query((!kept.is_empty()).then_some(kept.as_str()));
+        ValidatedUrl::parse(stripped.as_str()).unwrap_or(url)
+    }
+
+    /// Remember a deletion for [`undo`](Self::undo) and say how to take it back
+    fn record_undo(&self, description: String, deleted: Deleted) {
+        let message = format!("{}. Press Ctrl+Z to undo", description);
+        if let Ok(mut undo) = self.undo.write() {
+            undo.push(description, deleted, Instant::now());
+        }
+        self.state.notify(Notification::new(message, NotificationSeverity::Info));
+    }
+
+    /// Put back what the latest deletion still in reach removed, returning
+    /// its description; `None` if there is nothing left to undo
+    pub async fn undo(&self) -> Result<Option<String>> {
+        let Some((description, deleted)) = self.undo.write().ok().and_then(|mut undo| undo.pop(Instant::now()))
+        else {
+            return Ok(None);
+        };
+        RestoreDeletedUseCase::new(
+            self.repositories.bookmarks.clone(),
+            self.repositories.history.clone(),
+            self.downloads.clone(),
+        )
+        .execute(&deleted)
+        .await?;
+        match deleted {
+            Deleted::Bookmarks(_) => {}
+            Deleted::History(_) => self.restyle_tabs().await,
+            Deleted::Download(_) => self.state.notify_downloads_changed(),
+        }
+        self.state.notify(Notification::new(format!("Undone: {}", description), NotificationSeverity::Success));
+        Ok(Some(description))
+    }
+
+    /// Save the tab's page into the downloads directory, named after its title
+    pub async fn save_page(&self, tab_id: TabId, format: SaveFormat) -> Result<Download> {
+        let writer = self.download_writer.clone().ok_or(NavigatorError::SavingUnavailable)?;
+        let tab = self
+            .state
+            .get_tab(tab_id)
+            .ok_or(NavigatorError::TabNotFound(tab_id))?;
+        let directory = self.downloads_directory(writer.as_ref()).await?;
+        let saved = SavePageUseCase::new(self.page(tab_id)?, self.downloads.clone(), writer, format)
+            .with_archiver(self.page_archiver.clone())
+            .execute(&tab, &directory)
+            .await?;
+        self.state.notify_downloads_changed();
+        self.notify_saved(tab_id, format!("Saved to {}", saved.path.display()));
+        Ok(saved)
+    }
+
+    /// Print the tab's page to a PDF in the downloads directory, on the
+    /// paper chosen in settings
+    pub async fn print_page(&self, tab_id: TabId) -> Result<Download> {
+        let writer = self.download_writer.clone().ok_or(NavigatorError::SavingUnavailable)?;
+        let printer = self.page_printer.clone().ok_or(NavigatorError::PrintingUnavailable)?;
+        let tab = self
+            .state
+            .get_tab(tab_id)
+            .ok_or(NavigatorError::TabNotFound(tab_id))?;
+        let directory = self.downloads_directory(writer.as_ref()).await?;
+        let paper = self
+            .repositories
+            .settings
+            .get(PAPER_SIZE_SETTING)
+            .await?
+            .and_then(|paper| PaperSize::parse(&paper))
+            .unwrap_or_default();
+        let printed = PrintPageUseCase::new(self.page(tab_id)?, self.downloads.clone(), writer, printer, paper)
+            .execute(&tab, &directory)
+            .await?;
+        self.state.notify_downloads_changed();
+        self.notify_saved(tab_id, format!("Printed to {}", printed.path.display()));
+        Ok(printed)
+    }
+
+    /// The robots.txt rules of the site at `input`, resolved as if typed
+    pub async fn robots(&self, input: &str) -> Result<RobotsTxt> {
+        let fetcher = self.site_metadata.clone().ok_or(NavigatorError::SiteMetadataUnavailable)?;
+        Ok(fetcher.fetch_robots(&self.resolve(input)?).await?)
+    }
+
+    /// Pages the sitemap at `input` lists, resolved as if typed
+    pub async fn sitemap(&self, input: &str) -> Result<Vec<SitemapEntry>> {
+        let fetcher = self.site_metadata.clone().ok_or(NavigatorError::SiteMetadataUnavailable)?;
+        Ok(fetcher.fetch_sitemap(&self.resolve(input)?).await?)
+    }
+
+    /// Pages the site at `input` lists in the sitemaps its robots.txt
+    /// names, or else in `/sitemap.xml`. Sitemaps that fail are skipped
+    /// unless all do.
+    pub async fn site_pages(&self, input: &str) -> Result<Vec<SitemapEntry>> {
+        let fetcher = self.site_metadata.clone().ok_or(NavigatorError::SiteMetadataUnavailable)?;
+        let origin = self.resolve(input)?;
+        let mut sitemaps = fetcher.fetch_robots(&origin).await?.sitemaps;
+        if sitemaps.is_empty() {
+            sitemaps.extend(origin.join("/sitemap.xml").ok());
+        }
+
+        let mut pages = Vec::new();
+        let mut failure = None;
+        for sitemap in &sitemaps {
+            match fetcher.fetch_sitemap(sitemap).await {
+                Ok(listed) => pages.extend(listed),
+                Err(e) => {
+                    tracing::debug!("Skipped sitemap {}: {}", sitemap, e);
+                    failure = Some(e);
+                }
+            }
+        }
+        match failure {
+            Some(e) if pages.is_empty() => Err(e.into()),
+            _ => Ok(pages),
+        }
+    }
+
+    /// What the browser knows of the security of the tab's page
+    pub async fn security_report(&self, tab_id: TabId) -> Result<SecurityReport> {
+        let tab = self
+            .state
+            .get_tab(tab_id)
+            .ok_or(NavigatorError::TabNotFound(tab_id))?;
+        self.security_report_use_case(tab_id)?.execute(&tab).await
+    }
+
+    /// Save the tab's [security report](Self::security_report) as JSON in
+    /// the downloads directory
+    pub async fn export_security_report(&self, tab_id: TabId) -> Result<Download> {
+        let writer = self.download_writer.clone().ok_or(NavigatorError::SavingUnavailable)?;
+        let tab = self
+            .state
+            .get_tab(tab_id)
+            .ok_or(NavigatorError::TabNotFound(tab_id))?;
+        let directory = self.downloads_directory(writer.as_ref()).await?;
+        let saved = self
+            .security_report_use_case(tab_id)?
+            .save(&tab, &directory, &self.downloads, writer.as_ref())
+            .await?;
+        self.state.notify_downloads_changed();
+        self.notify_saved(tab_id, format!("Security report saved to {}", saved.path.display()));
+        Ok(saved)
+    }
+
+    /// Tell the tab's window where a file it saved went
+    fn notify_saved(&self, tab_id: TabId, message: String) {
+        self.state.notify(
+            Notification::new(message, NotificationSeverity::Success)
+                .with_tab(tab_id)
+                .with_action('o', "open downloads", DOWNLOADS_PAGE),
+        );
+    }
+
+    fn security_report_use_case(&self, tab_id: TabId) -> Result<ExportSecurityReportUseCase> {
+        Ok(ExportSecurityReportUseCase::new(
+            self.page(tab_id)?,
+            self.security.clone(),
+            self.tls_inspector.clone(),
+        ))
+    }
+
+    /// The directory set in settings, else the writer's default
+    async fn downloads_directory(&self, writer: &dyn DownloadWriter) -> Result<PathBuf> {
+        Ok(match self.repositories.settings.get(DOWNLOADS_DIRECTORY_SETTING).await? {
+            Some(directory) if !directory.trim().is_empty() => PathBuf::from(directory.trim()),
+            _ => writer.default_directory(),
+        })
+    }
+
+    /// Check a file from the tab and write it into the downloads directory,
+    /// or hold it for confirmation if it looks risky
+    pub async fn download_file(&self, tab_id: TabId, download: FileDownload) -> Result<DownloadOutcome> {
+        let tab = self
+            .state
+            .get_tab(tab_id)
+            .ok_or(NavigatorError::TabNotFound(tab_id))?;
+        let use_case = self.download_file_use_case()?;
+        let directory = self.downloads_directory(use_case.writer()).await?;
+        let outcome = use_case.execute(RequestContext::for_tab(&tab), download, &directory).await?;
+        self.state.notify_downloads_changed();
+        Ok(outcome)
+    }
+
+    /// Downloads waiting for the user to confirm them, newest first
+    pub fn pending_downloads(&self) -> Vec<PendingDownload> {
+        self.download_manager.pending()
+    }
+
+    /// Write a download held for confirmation; confirming one never lets
+    /// later ones through
+    pub async fn confirm_download(&self, id: u64) -> Result<Download> {
+        let use_case = self.download_file_use_case()?;
+        let directory = self.downloads_directory(use_case.writer()).await?;
+        let saved = use_case.confirm(id, &directory).await?;
+        self.state.notify_downloads_changed();
+        Ok(saved)
+    }
+
+    /// Drop a download held for confirmation without writing it
+    pub fn discard_download(&self, id: u64) -> Result<()> {
+        if !self.download_manager.discard(id) {
+            return Err(NavigatorError::PendingDownloadNotFound(id));
+        }
+        self.state.notify_downloads_changed();
+        Ok(())
+    }
+
+    /// Downloads being written, or whose write failed, newest first
+    pub fn unfinished_downloads(&self) -> Vec<UnfinishedDownload> {
+        self.download_manager.unfinished()
+    }
+
+    /// Write a download whose last write failed again
+    pub async fn retry_download(&self, id: u64) -> Result<Download> {
+        let use_case = self.download_file_use_case()?;
+        let directory = self.downloads_directory(use_case.writer()).await?;
+        use_case.retry(id, &directory).await
+    }
+
+    /// Stop a download being written, or forget one whose write failed;
+    /// nothing of it is kept either way
+    pub fn cancel_download(&self, id: u64) -> Result<()> {
+        if !self.download_manager.cancel(id) {
+            return Err(NavigatorError::UnfinishedDownloadNotFound(id));
+        }
+        self.state.notify_downloads_changed();
+        Ok(())
+    }
+
+    fn download_file_use_case(&self) -> Result<DownloadFileUseCase> {
+        let writer = self.download_writer.clone().ok_or(NavigatorError::SavingUnavailable)?;
+        Ok(DownloadFileUseCase::new(
+            self.download_manager.clone(),
+            self.downloads.clone(),
+            writer,
+            self.state.clone(),
+        ))
+    }
+
+    /// Saved pages of this session and before, newest first
+    pub async fn downloads(&self) -> Result<Vec<Download>> {
+        Ok(self.downloads.find_all().await?)
+    }
+
+    /// Take a download off the list, deleting its file too if asked. Only
+    /// taking it off the list can be undone.
+    pub async fn remove_download(&self, id: i64, delete_file: bool) -> Result<()> {
+        let download = RemoveDownloadEntryUseCase::new(self.downloads.clone(), self.download_writer.clone())
+            .execute(id, delete_file)
+            .await?;
+        self.state.notify_downloads_changed();
+        if !delete_file {
+            let name = download.path.file_name().map_or_else(
+                || download.path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+            self.record_undo(format!("Removed {} from downloads", name), Deleted::Download(download));
+        }
+        Ok(())
+    }
+
+    /// Empty the list of finished downloads, keeping their files; returns
+    /// how many were taken off
+    pub async fn clear_downloads(&self) -> Result<usize> {
+        let downloads = self.downloads.find_all().await?;
+        for download in &downloads {
+            self.downloads.delete(download.id).await?;
+        }
+        self.state.notify_downloads_changed();
+        Ok(downloads.len())
+    }
+
+    pub async fn search_history(&self, query: &str, limit: i32) -> Result<Vec<HistoryEntry>> {
+        SearchHistoryUseCase::new(self.repositories.history.clone())
+            .execute(query, limit)
+            .await
+    }
+
+    /// Open tabs, bookmarks and visited pages matching what is typed in
+    /// the address bar of a tab, the top hit first. Input starting with
+    /// "%" or "tab:" looks through open tabs alone.
+    pub async fn suggest(&self, tab_id: TabId, input: &str) -> Result<Vec<Suggestion>> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(Vec::new());
+        }
+        if let Some(query) = tab_query(input) {
+            let tabs = self.tab_index.search(query, tab_id, MAX_LOCAL_SUGGESTIONS);
+            return Ok(tabs.into_iter().map(Suggestion::open_tab).collect());
+        }
+        let tabs = self.tab_index.search(input, tab_id, MAX_TAB_SUGGESTIONS);
+        let bookmarks = self.repositories.bookmarks.search(input).await?;
+        let history = self.search_history(input, MAX_HISTORY_MATCHES).await?;
+        let ids: Vec<i64> = history.iter().map(|entry| entry.id).collect();
+        let visits = self.repositories.history.visits(&ids).await?;
+        let now = Utc::now();
+        let history = history
+            .into_iter()
+            .map(|entry| {
+                let frecency = entry.frecency(&visits, now);
+                (entry, frecency)
+            })
+            .collect();
+        Ok(local_suggestions(input, tabs, bookmarks, history))
+    }
+
+    pub async fn recent_history(&self, limit: i32) -> Result<Vec<HistoryEntry>> {
+        GetRecentHistoryUseCase::new(self.repositories.history.clone())
+            .execute(limit)
+            .await
+    }
+
+    /// Delete the data in `scope`, writing out pending visits first if
+    /// history is cleared. Site settings cleared apply from the next load.
+    pub async fn clear_browsing_data(&self, scope: ClearScope) -> Result<()> {
+        if scope.history {
+            self.history_writer.flush().await;
+        }
+        ClearBrowsingDataUseCase::new(
+            self.repositories.history.clone(),
+            self.repositories.site_settings.clone(),
+            self.repositories.web_storage.clone(),
+            self.repositories.page_cache.clone(),
+        )
+        .execute(scope)
+        .await?;
+        if scope.web_storage {
+            if let Ok(mut sessions) = self.sessions.write() {
+                sessions.values_mut().for_each(|session| session.storage.clear());
+            }
+        }
+        if scope.history {
+            // Links shown as visited no longer are
+            self.restyle_tabs().await;
+        }
+        Ok(())
+    }
+
+    /// Delete one history entry; a bookmark for its URL is kept
+    pub async fn delete_history_entry(&self, id: i64) -> Result<()> {
+        self.history_writer.flush().await;
+        DeleteHistoryEntryUseCase::new(self.repositories.history.clone())
+            .execute(id)
+            .await?;
+        self.restyle_tabs().await;
+        Ok(())
+    }
+
+    /// Delete the visits made in `[from, to)`, returning how many entries
+    /// lost visits; Ctrl+Z brings them back
+    pub async fn delete_history_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<u64> {
+        self.history_writer.flush().await;
+        let removed = DeleteHistoryRangeUseCase::new(self.repositories.history.clone())
+            .execute(from, to)
+            .await?;
+        self.restyle_tabs().await;
+        let deleted = removed.entries.len() as u64;
+        if deleted > 0 {
+            let description = match deleted {
+                1 => "Deleted 1 history entry".to_string(),
+                deleted => format!("Deleted {} history entries", deleted),
+            };
+            self.record_undo(description, Deleted::History(removed));
+        }
+        Ok(deleted)
+    }
+
+    /// Portable JSON copy of bookmarks, settings and blocked domains, and
+    /// of history if asked for
+    pub async fn export_profile(&self, include_history: bool) -> Result<String> {
+        self.history_writer.flush().await;
+        ExportProfileUseCase::new(
+            self.repositories.bookmarks.clone(),
+            self.repositories.history.clone(),
+            self.repositories.settings.clone(),
+        )
+        .execute(include_history)
+        .await
+    }
+
+    /// Apply a document from `export_profile`; blocked domains take effect
+    /// the next time the profile is opened
+    pub async fn import_profile(&self, json: &str, options: ImportOptions) -> Result<ImportReport> {
+        self.history_writer.flush().await;
+        ImportProfileUseCase::new(
+            self.repositories.bookmarks.clone(),
+            self.repositories.history.clone(),
+            self.repositories.settings.clone(),
+        )
+        .execute(json, options)
+        .await
+    }
+
+    /// Bring bookmarks and history over from another browser's profile
+    pub async fn import_from_browser(
+        &self,
+        reader: Arc<dyn BrowserProfileReader>,
+        request: BrowserImportRequest,
+    ) -> Result<BrowserImportReport> {
+        self.history_writer.flush().await;
+        ImportFromBrowserUseCase::new(
+            reader,
+            self.repositories.bookmarks.clone(),
+            self.repositories.history.clone(),
+        )
+        .execute(request)
+        .await
+    }
+
+    /// Put the stored settings the controller applies into effect; call
+    /// once at startup
+    pub async fn load_settings(&self) -> Result<()> {
+        self.settings_use_case().load().await?;
+        let ceiling = self.repositories.settings.get(MEMORY_CEILING_SETTING).await?;
+        self.set_memory_ceiling(ceiling.as_deref());
+        self.load_keymap().await
+    }
+
+    /// Trim caches past the stored ceiling, or the default one
+    fn set_memory_ceiling(&self, value: Option<&str>) {
+        let ceiling = value.and_then(parse_memory_ceiling).unwrap_or(DEFAULT_MEMORY_CEILING);
+        self.memory.set_ceiling(ceiling);
+    }
+
+    /// Check for a newer release if the check is turned on and has not run
+    /// in the last day, notifying every window of one found. Failures are
+    /// returned for logging; the user need not hear of them.
+    pub async fn check_for_update(&self, now: DateTime<Utc>) -> Result<Option<UpdateManifest>> {
+        let Some(source) = self.update_source.clone() else {
+            return Ok(None);
+        };
+        let update = CheckForUpdateUseCase::new(self.repositories.settings.clone(), source)
+            .execute(CURRENT_VERSION, now)
+            .await?;
+        if let Some(update) = &update {
+            tracing::info!("Navigator {} is available", update.version);
+            if let Ok(mut available) = self.available_update.write() {
+                *available = Some(update.clone());
+            }
+            self.state.notify(
+                Notification::new(format!("Navigator {} is available", update.version), NotificationSeverity::Info)
+                    .with_action('u', "see what's new", UPDATE_PAGE),
+            );
+        }
+        Ok(update)
+    }
+
+    /// The newer release found by the last update check, if any
+    pub fn available_update(&self) -> Option<UpdateManifest> {
+        self.available_update.read().ok().and_then(|update| update.clone())
+    }
+
+    /// Fetch the available release and save it like any other download
+    /// from the tab. Nothing is installed.
+    pub async fn download_update(&self, tab_id: TabId) -> Result<DownloadOutcome> {
+        let (Some(update), Some(source)) = (self.available_update(), self.update_source.clone()) else {
+            return Err(NavigatorError::NoUpdateAvailable);
+        };
+        let release = source.download(&update.download).await?;
+        let download = FileDownload {
+            file_name: release_file_name(&release.url, &update.version),
+            url: release.url,
+            content_type: release.content_type,
+            contents: release.contents,
+        };
+        self.download_file(tab_id, download).await
+    }
+
+    /// The typed settings, as loaded by [`Self::load_settings`] and changed
+    /// since
+    pub fn settings(&self) -> Arc<Settings> {
+        self.settings.load()
+    }
+
+    /// Current value of a setting listed on navigator://settings
+    pub async fn setting(&self, key: &str) -> Result<String> {
+        let setting = editable_setting(key).ok_or_else(|| NavigatorError::UnknownSetting(key.to_string()))?;
+        if setting.key == USER_STYLES_SETTING {
+            let styles = self.user_styles().await?;
+            return Ok(format_user_styles(styles.iter().map(|style| (&style.host_pattern, &style.rule))));
+        }
+        self.settings_use_case().current(setting).await
+    }
+
+    /// Change a setting listed on navigator://settings, announcing the
+    /// change so the components it configures can pick it up. Returns the
+    /// value as stored.
+    pub async fn update_setting(&self, key: &str, value: &str) -> Result<String> {
+        if key == USER_STYLES_SETTING {
+            return self.replace_user_styles(value).await;
+        }
+        let (setting, value) = self.settings_use_case().execute(key, value).await?;
+        if setting.key == MEMORY_CEILING_SETTING {
+            self.set_memory_ceiling(Some(&value));
+        }
+        if Command::for_setting(setting.key).is_some() {
+            self.load_keymap().await?;
+        }
+        self.state.notify_setting_changed(setting.key);
+        Ok(value)
+    }
+
+    /// Read the shortcuts stored in settings, used from then on by
+    /// [`command_for`](Self::command_for)
+    async fn load_keymap(&self) -> Result<()> {
+        let keymap = self.settings_use_case().keymap().await?;
+        if let Ok(mut current) = self.keymap.write() {
+            *current = keymap;
+        }
+        Ok(())
+    }
+
+    /// Every command with its shortcuts
+    pub fn keymap(&self) -> Keymap {
+        self.keymap.read().map(|keymap| keymap.clone()).unwrap_or_default()
+    }
+
+    /// The command a key pressed with modifiers runs, if any
+    pub fn command_for(&self, chord: &Chord) -> Option<Command> {
+        self.keymap.read().ok().and_then(|keymap| keymap.command(chord))
+    }
+
+    /// Commands, open tabs, bookmarks and recently visited pages matching
+    /// what is typed in the command palette, best first as of `now`
+    pub async fn palette(&self, query: &str, now: DateTime<Utc>) -> Result<Vec<PaletteEntry>> {
+        let keymap = self.keymap();
+        let mut entries: Vec<PaletteEntry> = COMMANDS
+            .iter()
+            .filter(|info| info.command != Command::CommandPalette)
+            .map(|info| PaletteEntry::command(info.command, format_chords(&keymap.chords(info.command))))
+            .collect();
+        for tab in self.state.get_all_tabs() {
+            entries.push(PaletteEntry::tab(tab.id, &tab.title, tab.url.as_ref(), tab.last_accessed));
+        }
+        for bookmark in self.repositories.bookmarks.find_all().await? {
+            entries.push(PaletteEntry::page("Bookmark", &bookmark.title, bookmark.url, None));
+        }
+        for entry in self.recent_history(MAX_PALETTE_HISTORY as i32).await? {
+            entries.push(PaletteEntry::page("History", &entry.title, entry.url, Some(entry.visited_at)));
+        }
+        let usage = self.repositories.settings.get(PALETTE_USAGE_SETTING).await?;
+        let usage = PaletteUsage::parse(usage.as_deref().unwrap_or_default());
+        Ok(rank_palette(query, entries, &usage, now))
+    }
+
+    /// Count a command run from the palette of a tab, so it ranks higher
+    /// next time; nothing is kept for private tabs
+    pub async fn record_palette_use(&self, tab_id: TabId, command: Command) -> Result<()> {
+        if self.state.get_tab(tab_id).is_none_or(|tab| tab.is_private) {
+            return Ok(());
+        }
+        let settings = &self.repositories.settings;
+        let stored = settings.get(PALETTE_USAGE_SETTING).await?;
+        let mut usage = PaletteUsage::parse(stored.as_deref().unwrap_or_default());
+        usage.record(command.name());
+        settings.set(PALETTE_USAGE_SETTING, &usage.to_string()).await?;
+        Ok(())
+    }
+
+    /// Overrides the site of `url` has of the global settings
+    pub async fn site_settings(&self, url: &ValidatedUrl) -> Result<SiteSettings> {
+        SetSiteSettingUseCase::new(self.repositories.site_settings.clone())
+            .current(url)
+            .await
+    }
+
+    /// Set or remove one of the overrides of `url`'s site, returning all of
+    /// them. Open pages on the site use them once reloaded.
+    pub async fn set_site_setting(&self, url: &ValidatedUrl, setting: SiteSetting) -> Result<SiteSettings> {
+        SetSiteSettingUseCase::new(self.repositories.site_settings.clone())
+            .execute(url, setting)
+            .await
+    }
+
+    /// The Storage object of `kind` the page in a tab sees. Private tabs
+    /// keep `localStorage` in memory, like `sessionStorage`, until closed.
+    pub async fn storage(&self, tab_id: TabId, kind: StorageKind) -> Result<StorageArea> {
+        let (document, context) = self.storage_target(tab_id)?;
+        if kind == StorageKind::Local && !context.private {
+            return UpdateWebStorageUseCase::new(self.repositories.web_storage.clone())
+                .current(&document)
+                .await;
+        }
+        let origin = SiteOrigin::of(&document).ok_or_else(|| NavigatorError::NoStorage(document.scheme().into()))?;
+        self.with_session(tab_id, |session| {
+            let area = session.storage.get(&(kind, origin.clone()));
+            area.cloned().unwrap_or_else(|| StorageArea::new(origin))
+        })
+    }
+
+    /// Make a change the page in a tab asks of its Storage object of
+    /// `kind`, returning the items after it. Fails with
+    /// [`QuotaExceeded`](crate::domain::StorageError::QuotaExceeded) rather
+    /// than pass the site's quota.
+    pub async fn update_storage(
+        &self,
+        tab_id: TabId,
+        kind: StorageKind,
+        change: StorageChange,
+    ) -> Result<StorageArea> {
+        let (document, context) = self.storage_target(tab_id)?;
+        if kind == StorageKind::Local && !context.private {
+            return UpdateWebStorageUseCase::new(self.repositories.web_storage.clone())
+                .execute(context, &document, change)
+                .await;
+        }
+        let origin = SiteOrigin::of(&document).ok_or_else(|| NavigatorError::NoStorage(document.scheme().into()))?;
+        let area = self.with_session(tab_id, |session| {
+            let area = session
+                .storage
+                .entry((kind, origin.clone()))
+                .or_insert_with(|| StorageArea::new(origin));
+            area.apply(change).map(|_| area.clone())
+        })?;
+        Ok(area?)
+    }
+
+    /// The page a tab's Storage belongs to, and on whose behalf it is changed
+    fn storage_target(&self, tab_id: TabId) -> Result<(ValidatedUrl, RequestContext)> {
+        let tab = self.state.get_tab(tab_id).ok_or(NavigatorError::TabNotFound(tab_id))?;
+        let document = self.with_session(tab_id, |session| session.document.clone())?;
+        let document = document.ok_or_else(|| NavigatorError::NoStorage("about".to_string()))?;
+        Ok((document, RequestContext::for_tab(&tab)))
+    }
+
+    /// On whose behalf work for a tab is done. A tab no longer open counts
+    /// as private, so nothing more is kept of it.
+    fn context(&self, tab_id: TabId) -> RequestContext {
+        self.state
+            .get_tab(tab_id)
+            .map_or(RequestContext::new(Some(tab_id), true), |tab| RequestContext::for_tab(&tab))
+    }
+
+    /// The `localStorage` of `url`'s site, for navigator://site-settings
+    pub async fn site_storage(&self, url: &ValidatedUrl) -> Result<StorageArea> {
+        UpdateWebStorageUseCase::new(self.repositories.web_storage.clone())
+            .current(url)
+            .await
+    }
+
+    /// Delete everything `url`'s site keeps in `localStorage`
+    pub async fn clear_site_storage(&self, url: &ValidatedUrl) -> Result<()> {
+        UpdateWebStorageUseCase::new(self.repositories.web_storage.clone())
+            .execute(RequestContext::user(), url, StorageChange::Clear)
+            .await?;
+        Ok(())
+    }
+
+    /// What the page in a tab tried to load and was refused, since it loaded
+    pub fn blocked_summary(&self, tab_id: TabId) -> Result<BlockedSummary> {
+        Ok(GetBlockedSummaryUseCase::new(self.page(tab_id)?).execute())
+    }
+
+    /// Protocol the page in a tab came over, for pages from the network
+    pub fn page_protocol(&self, tab_id: TabId) -> Option<HttpVersion> {
+        self.page(tab_id).ok()?.http_version()
+    }
+
+    /// Loads the page in a tab made that its content security policy did
+    /// not allow
+    pub fn page_csp_violations(&self, tab_id: TabId) -> Vec<CspViolation> {
+        self.page(tab_id).map(|page| page.csp_violations()).unwrap_or_default()
+    }
+
+    /// Certificate the server of the page in a tab presented, for pages
+    /// from HTTPS
+    pub fn page_certificate(&self, tab_id: TabId) -> Option<Certificate> {
+        self.page(tab_id).ok()?.certificate()
+    }
+
+    fn settings_use_case(&self) -> UpdateSettingsUseCase {
+        UpdateSettingsUseCase::new(
+            self.repositories.settings.clone(),
+            self.repositories.search_engines.clone(),
+            self.settings.clone(),
+            self.state.clone(),
+        )
+    }
+
+    /// Store the open windows and their tabs so the next start can reopen
+    /// them, oldest window first; private tabs and tabs that never loaded a
+    /// page are left out
+    pub async fn save_session(&self) -> Result<()> {
+        let mut windows: Vec<SavedWindow> = self
+            .state
+            .window_ids()
+            .into_iter()
+            .filter_map(|window| self.saved_window(window))
+            .collect();
+        windows.sort_by_key(|window| window.tabs.iter().map(|tab| tab.created_at).min());
+        Ok(self.repositories.tabs.save_session(windows).await?)
+    }
+
+    /// Windows stored by the last session with the tabs to reopen in each,
+    /// clearing them from storage. Empty unless restoring the session is
+    /// turned on.
+    pub async fn take_saved_session(&self) -> Result<Vec<SavedWindow>> {
+        let mut windows = self.repositories.tabs.restore_session().await?;
+        self.repositories.tabs.clear_session().await?;
+        let restore = self.repositories.settings.get(RESTORE_SESSION_SETTING).await?;
+        if !setting_enabled(RESTORE_SESSION_SETTING, restore.as_deref()) {
+            return Ok(Vec::new());
+        }
+        for window in &mut windows {
+            window.tabs.retain(|tab| tab.page_url().is_some());
+            if !window.tabs.iter().any(|tab| Some(tab.id) == window.active_tab) {
+                window.active_tab = None;
+            }
+        }
+        windows.retain(|window| !window.tabs.is_empty());
+        Ok(windows)
+    }
+
+    /// Put the stored user style rules into effect; call once at startup
+    pub async fn load_user_styles(&self) -> Result<()> {
+        self.user_styles
+            .set_styles(self.repositories.user_styles.find_all().await?);
+        Ok(())
+    }
+
+    pub async fn user_styles(&self) -> Result<Vec<UserStyle>> {
+        Ok(self.repositories.user_styles.find_all().await?)
+    }
+
+    /// Save a rule for hosts matching `host_pattern` and restyle open tabs
+    pub async fn add_user_style(&self, host_pattern: &str, rule: UserStyleRule) -> Result<UserStyle> {
+        let host_pattern = HostPattern::parse(host_pattern)
+            .ok_or_else(|| UserStyleError::InvalidHostPattern(host_pattern.to_string()))?;
+        let saved = AddUserStyleUseCase::new(self.repositories.user_styles.clone(), self.user_styles.clone())
+            .execute(NewUserStyle::new(host_pattern, rule))
+            .await?;
+        self.restyle_tabs().await;
+        Ok(saved)
+    }
+
+    pub async fn remove_user_style(&self, id: i64) -> Result<()> {
+        RemoveUserStyleUseCase::new(self.repositories.user_styles.clone(), self.user_styles.clone())
+            .execute(id)
+            .await?;
+        self.restyle_tabs().await;
+        Ok(())
+    }
+
+    /// Replace every user style rule with those `value` lists the way
+    /// navigator://settings edits them, then restyle open tabs
+    async fn replace_user_styles(&self, value: &str) -> Result<String> {
+        let invalid = |reason: String| NavigatorError::InvalidSetting {
+            label: editable_setting(USER_STYLES_SETTING).map_or("", |setting| setting.label).to_string(),
+            reason,
+        };
+        let styles = parse_user_styles(value).map_err(invalid)?;
+        let saved = ReplaceUserStylesUseCase::new(self.repositories.user_styles.clone(), self.user_styles.clone())
+            .execute(styles)
+            .await
+            .map_err(|e| match e {
+                NavigatorError::UserStyle(e) => invalid(e.to_string()),
+                e => e,
+            })?;
+        self.restyle_tabs().await;
+        self.state.notify_setting_changed(USER_STYLES_SETTING);
+        Ok(format_user_styles(saved.iter().map(|style| (&style.host_pattern, &style.rule))))
+    }
+
+    /// Put the stored user scripts into effect; call once at startup
+    pub async fn load_user_scripts(&self) -> Result<()> {
+        self.user_scripts
+            .set_scripts(self.repositories.user_scripts.find_all().await?);
+        Ok(())
+    }
+
+    /// Every user script, with the error of its last run if it failed
+    pub async fn user_scripts(&self) -> Result<Vec<UserScript>> {
+        Ok(self.repositories.user_scripts.find_all().await?)
+    }
+
+    /// Save an enabled script that runs on pages matching any of `matches`
+    pub async fn add_user_script(&self, name: &str, matches: &[&str], code: &str) -> Result<UserScript> {
+        let matches = matches
+            .iter()
+            .map(|pattern| {
+                MatchPattern::parse(pattern)
+                    .ok_or_else(|| UserScriptError::InvalidMatchPattern(pattern.to_string()))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        AddUserScriptUseCase::new(self.repositories.user_scripts.clone(), self.user_scripts.clone())
+            .execute(NewUserScript::new(name.trim().to_string(), matches, code.to_string()))
+            .await
+    }
+
+    /// Store a script changed by the caller, e.g. enabled or allowed in private tabs
+    pub async fn update_user_script(&self, script: &UserScript) -> Result<()> {
+        UpdateUserScriptUseCase::new(self.repositories.user_scripts.clone(), self.user_scripts.clone())
+            .execute(script)
+            .await
+    }
+
+    /// Change one option of a user script, given as `<option>=<value>`:
+    /// `enabled`, `allow-private` or `allow-internal` set to `true` or
+    /// `false`, or `run-at` set to `document-end` or `document-idle`
+    pub async fn set_user_script_option(&self, id: i64, option: &str) -> Result<UserScript> {
+        let mut script = self
+            .repositories
+            .user_scripts
+            .find_all()
+            .await?
+            .into_iter()
+            .find(|script| script.id == id)
+            .ok_or(UserScriptError::NotFound(id))?;
+        let invalid = || UserScriptError::InvalidOption(option.to_string());
+        let (name, value) = option.split_once('=').ok_or_else(invalid)?;
+        let flag = || value.parse::<bool>().map_err(|_| invalid());
+        match name {
+            "enabled" => script.enabled = flag()?,
+            "allow-private" => script.allow_private = flag()?,
+            "allow-internal" => script.allow_internal = flag()?,
+            "run-at" => script.run_at = RunAt::parse(value).ok_or_else(invalid)?,
+            _ => return Err(invalid().into()),
+        }
+        self.update_user_script(&script).await?;
+        Ok(script)
+    }
+
+    pub async fn remove_user_script(&self, id: i64) -> Result<()> {
+        RemoveUserScriptUseCase::new(self.repositories.user_scripts.clone(), self.user_scripts.clone())
+            .execute(id)
+            .await
+    }
+
+    /// Trust the stored certificate authorities; call once at startup
+    pub async fn load_custom_cas(&self) -> Result<()> {
+        if let Some(trust_store) = &self.trust_store {
+            trust_store.set_custom_roots(&self.repositories.custom_cas.find_all().await?)?;
+        }
+        Ok(())
+    }
+
+    /// The certificate authorities trusted besides the built-in roots,
+    /// oldest first
+    pub async fn custom_cas(&self) -> Result<Vec<CustomCa>> {
+        Ok(self.repositories.custom_cas.find_all().await?)
+    }
+
+    /// Trust the certificate authority in `pem`, named `name` or after
+    /// itself, from the next connection on
+    pub async fn import_certificate(&self, name: Option<&str>, pem: &str) -> Result<CustomCa> {
+        let trust_store = self.trust_store.clone().ok_or(NavigatorError::CertificatesUnavailable)?;
+        ImportCertificateUseCase::new(self.repositories.custom_cas.clone(), trust_store)
+            .execute(name, pem)
+            .await
+    }
+
+    pub async fn remove_certificate(&self, id: i64) -> Result<CustomCa> {
+        let trust_store = self.trust_store.clone().ok_or(NavigatorError::CertificatesUnavailable)?;
+        RemoveCertificateUseCase::new(self.repositories.custom_cas.clone(), trust_store)
+            .execute(id)
+            .await
+    }
+
+    /// Every search engine, oldest first
+    pub async fn search_engines(&self) -> Result<Vec<SearchEngine>> {
+        Ok(self.repositories.search_engines.find_all().await?)
+    }
+
+    /// Save a search engine triggered by `keyword`; templates are https URLs
+    /// with one `%s` for the query
+    pub async fn add_search_engine(
+        &self,
+        name: &str,
+        keyword: &str,
+        query_template: &str,
+        suggest_template: Option<&str>,
+    ) -> Result<SearchEngine> {
+        let template = |template: &str| {
+            SearchTemplate::parse(template).ok_or_else(|| SearchEngineError::InvalidTemplate(template.to_string()))
+        };
+        let mut engine = NewSearchEngine::new(name.trim().to_string(), keyword, template(query_template)?);
+        if let Some(suggest_template) = suggest_template {
+            engine = engine.with_suggest_template(template(suggest_template)?);
+        }
+        AddSearchEngineUseCase::new(self.repositories.search_engines.clone())
+            .execute(engine)
+            .await
+    }
+
+    /// Search engine the tab's page offers, if not saved already. Show it to
+    /// the user; only `accept_search_engine_offer` saves it.
+    pub async fn search_engine_offer(&self, tab_id: TabId) -> Result<Option<NewSearchEngine>> {
+        let description = self.page(tab_id)?.search_description();
+        let (Some(opensearch), Some(description)) = (&self.opensearch, description) else {
+            return Ok(None);
+        };
+        OfferSearchEngineUseCase::new(self.repositories.search_engines.clone(), opensearch.clone())
+            .execute(&description)
+            .await
+    }
+
+    /// Save an offered search engine the user confirmed
+    pub async fn accept_search_engine_offer(&self, offer: NewSearchEngine) -> Result<SearchEngine> {
+        AddSearchEngineUseCase::new(self.repositories.search_engines.clone())
+            .execute(offer)
+            .await
+    }
+
+    pub async fn set_default_search_engine(&self, id: i64) -> Result<()> {
+        SetDefaultSearchEngineUseCase::new(self.repositories.search_engines.clone())
+            .execute(id)
+            .await
+    }
+
+    pub async fn remove_search_engine(&self, id: i64) -> Result<()> {
+        Ok(self.repositories.search_engines.delete(id).await?)
+    }
+
+    /// Lay every loaded page out again so style changes show on the next repaint
+    pub async fn restyle_tabs(&self) {
+        let engines: Vec<(TabId, Arc<E>)> = match self.sessions.read() {
+            Ok(sessions) => sessions
+                .iter()
+                .filter(|(_, session)| session.document.is_some())
+                .map(|(tab, session)| (*tab, session.engine.clone()))
+                .collect(),
+            Err(_) => return,
+        };
+        for (tab, engine) in engines {
+            match engine.restyle().await {
+                Ok(()) => self.state.notify_page_restyled(tab),
+                Err(e) => tracing::warn!("Failed to restyle tab {}: {}", tab, e),
+            }
+        }
+    }
+
+    /// Wind down before exiting: refuse new navigations and end those
+    /// under way, pause downloads being written so they can resume, then
+    /// write out pending visits. Call once.
+    pub async fn shutdown(&self) {
+        self.shutting_down.send_replace(true);
+        if let Some(writer) = &self.download_writer {
+            writer.pause().await;
+        }
+        self.history_writer.shutdown().await;
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutting_down.borrow()
+    }
+
+    /// Lay a suspended tab's page out again, or fetch it again if it was
+    /// hidden long enough to be stale; scrolls to where it was left
+    async fn wake(
+        &self,
+        tab_id: TabId,
+        was: TabActivity,
+        hidden_for: Option<Duration>,
+    ) -> Result<Option<NavigationOutcome>> {
+        if was != TabActivity::Suspended {
+            return Ok(None);
+        }
+        let Some(entry) = self.current_entry(tab_id) else {
+            return Ok(None);
+        };
+        if hidden_for.is_some_and(|hidden_for| self.throttle.is_stale(hidden_for)) {
+            tracing::info!("Reloading stale tab {}", tab_id);
+            return self.load_entry(tab_id, Some(entry), false, VisitType::Reload).await;
+        }
+
+        tracing::debug!("Resuming tab {}", tab_id);
+        self.page(tab_id)?.restyle().await?;
+        Ok(Some(NavigationOutcome {
+            url: entry.url,
+            same_document: false,
+            scroll_offset: Some(entry.scroll_offset),
+        }))
+    }
+
+    fn page(&self, tab_id: TabId) -> Result<Arc<E>> {
+        self.get_page(tab_id).ok_or(NavigatorError::TabNotFound(tab_id))
+    }
+
+    fn with_session<T>(
+        &self,
+        tab_id: TabId,
+        f: impl FnOnce(&mut TabSession<E>) -> T,
+    ) -> Result<T> {
+        self.sessions
+            .write()
+            .ok()
+            .and_then(|mut sessions| sessions.get_mut(&tab_id).map(f))
+            .ok_or(NavigatorError::TabNotFound(tab_id))
+    }
+
+    async fn load_entry(
+        &self,
+        tab_id: TabId,
+        entry: Option<NavigationEntry>,
+        allow_same_document: bool,
+        visit_type: VisitType,
+    ) -> Result<Option<NavigationOutcome>> {
+        let Some(entry) = entry else {
+            return Ok(None);
+        };
+        self.load(tab_id, entry.url, Some(entry.scroll_offset), allow_same_document, visit_type)
+            .await
+            .map(Some)
+    }
+
+    /// Show a URL in a tab: keep the page when only the fragment differs,
+    /// otherwise load it
+    async fn load(
+        &self,
+        tab_id: TabId,
+        url: ValidatedUrl,
+        scroll_offset: Option<f32>,
+        allow_same_document: bool,
+        visit_type: VisitType,
+    ) -> Result<NavigationOutcome> {
+        let (engine, document) =
+            self.with_session(tab_id, |session| (session.engine.clone(), session.document.clone()))?;
+
+        let same_document = allow_same_document
+            && (scroll_offset.is_some() || url.fragment().is_some())
+            && document.is_some_and(|document| document.same_document(&url));
+
+        if same_document {
+            tracing::info!("Scrolling within document to {}", url);
+            if let Some(mut tab) = self.state.get_tab(tab_id) {
+                tab.update_url(url.clone());
+                self.state.update_tab(tab);
+            }
+        } else {
+            let mut shutting_down = self.shutting_down.subscribe();
+            if *shutting_down.borrow_and_update() {
+                return Err(NavigatorError::ShuttingDown);
+            }
+            let navigate = NavigateUseCase::new(
+                self.state.clone(),
+                self.security.clone(),
+                self.history_writer.clone(),
+                self.repositories.site_settings.clone(),
+                engine.clone(),
+            );
+            tokio::select! {
+                navigated = navigate.execute(self.context(tab_id), tab_id, url.as_str(), visit_type) => navigated?,
+                _ = shutting_down.wait_for(|shutting_down| *shutting_down) => {
+                    return Err(NavigatorError::ShuttingDown);
+                }
+            }
+
+            RunUserScriptsUseCase::new(self.repositories.user_scripts.clone(), self.user_scripts.clone(), engine)
+                .execute(self.context(tab_id), &url)
+                .await;
+
+            // The tab may have been closed while loading
+            if self
+                .with_session(tab_id, |session| session.document = Some(url.clone()))
+                .is_ok()
+            {
+                // A fresh layout ends a suspension, e.g. when a hidden tab is driven remotely
+                if self.state.tab_activity(tab_id) == TabActivity::Suspended {
+                    self.state.set_tab_activity(tab_id, TabActivity::Background);
+                }
+                self.state.notify_page_content_changed(tab_id);
+            }
+        }
+
+        Ok(NavigationOutcome {
+            url,
+            same_document,
+            scroll_offset,
+        })
+    }
+}
+
+/// Start the handler for `url`
+fn launch(launcher: &dyn ExternalLauncher, url: &ValidatedUrl) -> Result<()> {
+    tracing::info!("Opening a {}: link in another program", url.scheme());
+    launcher
+        .launch(url)
+        .map_err(|e| NavigatorError::Launch(url.to_string(), e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::{
+        tab_switch, top_completion, PaletteTarget, SettingsSections, StateEvent, SuggestionContext,
+        DEFAULT_HOMEPAGE, DEFAULT_STALE_AFTER, DEFAULT_SUSPEND_AFTER, HOMEPAGE_SETTING, HTTPS_ONLY_SETTING,
+        MIN_BACKGROUND_INTERVAL, UPDATE_CHECK_SETTING, UPDATE_MANIFEST_SETTING,
+    };
+    use crate::application::testing::{
+        FakeEngine, FakeLauncher, FakePrinter, InMemoryBookmarkRepository, InMemoryCustomCaRepository,
+        InMemoryDownloadRepository, InMemoryDownloadWriter, InMemoryHistoryRepository, InMemoryPageCacheRepository,
+        InMemorySearchEngineRepository, InMemorySettingsRepository, InMemorySiteSettingsRepository,
+        InMemoryTabRepository, InMemoryUserScriptRepository, InMemoryUserStyleRepository,
+        InMemoryWebStorageRepository,
+    };
+    use crate::domain::{
+        BlockCategory, BlockedRequest, NewBookmark, ReleaseFile, RenderError, SiteMetadataError, StorageError,
+        UpdateError, WindowGeometry, DEFAULT_STORAGE_QUOTA, NEW_TAB_TITLE,
+    };
+    use crate::infrastructure::{
+        DefaultSecurityService, DefaultUserScriptService, DefaultUserStyleService, ServoRenderer,
+    };
+    use base64::Engine as _;
+
+    struct Fixture {
+        controller: BrowserController<FakeEngine>,
+        tabs: Arc<InMemoryTabRepository>,
+        bookmarks: Arc<InMemoryBookmarkRepository>,
+        security: Arc<DefaultSecurityService>,
+    }
+
+    fn repositories(tabs: Arc<InMemoryTabRepository>, bookmarks: Arc<InMemoryBookmarkRepository>) -> Repositories {
+        Repositories {
+            tabs,
+            bookmarks,
+            history: Arc::new(InMemoryHistoryRepository::new()),
+            settings: Arc::new(InMemorySettingsRepository::new()),
+            user_styles: Arc::new(InMemoryUserStyleRepository::new()),
+            user_scripts: Arc::new(InMemoryUserScriptRepository::new()),
+            search_engines: Arc::new(InMemorySearchEngineRepository::new()),
+            downloads: Arc::new(InMemoryDownloadRepository::new()),
+            site_settings: Arc::new(InMemorySiteSettingsRepository::new()),
+            web_storage: Arc::new(InMemoryWebStorageRepository::new()),
+            page_cache: Arc::new(InMemoryPageCacheRepository::new()),
+            custom_cas: Arc::new(InMemoryCustomCaRepository::new()),
+        }
+    }
+
+    fn fixture() -> Fixture {
+        let tabs = Arc::new(InMemoryTabRepository::new());
+        let bookmarks = Arc::new(InMemoryBookmarkRepository::new());
+        let security = Arc::new(DefaultSecurityService::new());
+        let repositories = repositories(tabs.clone(), bookmarks.clone());
+        Fixture {
+            controller: BrowserController::new(
+                BrowserState::new(),
+                repositories,
+                security.clone(),
+                Arc::new(DefaultUserStyleService::new()),
+                Arc::new(DefaultUserScriptService::new()),
+                FakeEngine::new,
+            ),
+            tabs,
+            bookmarks,
+            security,
+        }
+    }
+
+    fn loads(controller: &BrowserController<FakeEngine>, tab: TabId) -> usize {
+        controller.get_page(tab).unwrap().load_count()
+    }
+
+    #[tokio::test]
+    async fn test_external_links_wait_for_an_answer() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        let mail = "mailto:someone@example.com?subject=Hello";
+        assert_eq!(controller.open_external(mail).await, Err(NavigatorError::LaunchingUnavailable));
+        let launcher = Arc::new(FakeLauncher::new());
+        let controller = controller.with_external_launcher(launcher.clone());
+
+        // Web pages and refused schemes are not for other programs
+        assert_eq!(controller.open_external("example.com").await, Ok(None));
+        assert_eq!(controller.open_external("ssh://example.com").await, Ok(None));
+        assert_eq!(
+            controller.navigate(tab, mail).await,
+            Err(SecurityError::ExternalScheme("mailto".to_string()).into())
+        );
+
+        let Some(ExternalLaunch::PendingConfirmation(held)) = controller.open_external(mail).await.unwrap() else {
+            panic!("mailto: links are confirmed first");
+        };
+        assert_eq!(held.url.as_str(), mail);
+        assert!(launcher.launched().is_empty());
+        assert_eq!(controller.answer_external_launch(held.id, LaunchAnswer::Cancel).await, Ok(None));
+        assert_eq!(
+            controller.answer_external_launch(held.id, LaunchAnswer::Open).await,
+            Err(NavigatorError::PendingLaunchNotFound(held.id))
+        );
+        assert!(launcher.launched().is_empty());
+
+        // Opening once asks again next time
+        let Some(ExternalLaunch::PendingConfirmation(held)) = controller.open_external(mail).await.unwrap() else {
+            panic!("nothing was remembered yet");
+        };
+        let opened = controller.answer_external_launch(held.id, LaunchAnswer::Open).await.unwrap();
+        assert_eq!(opened.as_ref().map(ValidatedUrl::as_str), Some(mail));
+        let Some(ExternalLaunch::PendingConfirmation(held)) = controller.open_external(mail).await.unwrap() else {
+            panic!("opening once is not always");
+        };
+
+        // Always opening a scheme covers it and nothing else
+        controller.answer_external_launch(held.id, LaunchAnswer::AlwaysOpen).await.unwrap();
+        let next = controller.open_external("mailto:other@example.com").await.unwrap();
+        assert!(matches!(next, Some(ExternalLaunch::Launched(url)) if url.as_str() == "mailto:other@example.com"));
+        let phone = controller.open_external("tel:+15550100").await.unwrap();
+        assert!(matches!(phone, Some(ExternalLaunch::PendingConfirmation(_))));
+        assert_eq!(launcher.launched().len(), 3);
+
+        launcher.fail.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert!(matches!(controller.open_external(mail).await, Err(NavigatorError::Launch(..))));
+
+        // None of it reached the tab
+        assert_eq!(loads(&controller, tab), 0);
+        assert_eq!(controller.back(tab).await, Ok(None));
+        controller.shutdown().await;
+        assert!(controller.recent_history(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_navigate_loads_and_records_visit() {
+        let Fixture { controller, tabs, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        assert_eq!(tabs.find_all().await.unwrap().len(), 1);
+
+        let outcome = controller.navigate(tab, "example.com/docs").await.unwrap();
+        assert_eq!(outcome.url.as_str(), "https://example.com/docs");
+        assert!(!outcome.same_document);
+        assert_eq!(loads(&controller, tab), 1);
+        assert_eq!(controller.state().get_tab(tab).unwrap().title, "Page /docs");
+
+        controller.shutdown().await;
+        let history = controller.recent_history(10).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].title, "Page /docs");
+    }
+
+    #[tokio::test]
+    async fn test_state_events_for_open_navigate_close() {
+        let Fixture { controller, .. } = fixture();
+        let mut events = controller.state().subscribe();
+
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://example.com/").await.unwrap();
+        controller.close_tab(tab).await.unwrap();
+
+        let received: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(
+            received,
+            vec![
+                StateEvent::TabAdded(tab),
+                StateEvent::ActiveTabChanged(Some(tab)),
+                // Loading, then loaded with its title
+                StateEvent::TabUpdated(tab),
+                StateEvent::TabUpdated(tab),
+                StateEvent::PageContentChanged(tab),
+                StateEvent::TabRemoved(tab),
+                StateEvent::ActiveTabChanged(None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_styles_restyle_loaded_tabs() {
+        let Fixture { controller, .. } = fixture();
+        let loaded = controller.open_tab(None).await.unwrap();
+        let blank = controller.open_tab(None).await.unwrap();
+        controller.navigate(loaded, "https://example.com/").await.unwrap();
+        let mut events = controller.state().subscribe();
+
+        let rule = UserStyleRule::Hide { selector: ".ad".to_string() };
+        let style = controller.add_user_style("*.Example.com", rule).await.unwrap();
+        assert_eq!(style.host_pattern.as_str(), "*.example.com");
+        assert_eq!(controller.get_page(loaded).unwrap().restyle_count(), 1);
+        assert_eq!(controller.get_page(blank).unwrap().restyle_count(), 0);
+        assert_eq!(events.try_recv().ok(), Some(StateEvent::PageRestyled(loaded)));
+
+        let bad_selector = UserStyleRule::Hide { selector: "div > p".to_string() };
+        assert!(matches!(
+            controller.add_user_style("*", bad_selector).await,
+            Err(NavigatorError::UserStyle(UserStyleError::InvalidRule(_)))
+        ));
+        let rule = UserStyleRule::MinFontSize { px: 12.0 };
+        assert!(controller.add_user_style("exa mple", rule).await.is_err());
+
+        controller.remove_user_style(style.id).await.unwrap();
+        assert!(controller.user_styles().await.unwrap().is_empty());
+        assert_eq!(controller.get_page(loaded).unwrap().restyle_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_user_styles_are_edited_as_one_setting() {
+        let Fixture { controller, .. } = fixture();
+        let loaded = controller.open_tab(None).await.unwrap();
+        controller.navigate(loaded, "https://example.com/").await.unwrap();
+        let kept = controller
+            .add_user_style("*", UserStyleRule::MinFontSize { px: 14.0 })
+            .await
+            .unwrap();
+        controller
+            .add_user_style("example.com", UserStyleRule::Hide { selector: ".ad".to_string() })
+            .await
+            .unwrap();
+        assert_eq!(
+            controller.setting(USER_STYLES_SETTING).await.unwrap(),
+            "* min-font-size=14; example.com hide=.ad"
+        );
+
+        let stored = controller
+            .update_setting(USER_STYLES_SETTING, "* min-font-size=14; *.Example.com text-color=#222")
+            .await
+            .unwrap();
+        assert_eq!(stored, "* min-font-size=14; *.example.com text-color=#222");
+        let styles = controller.user_styles().await.unwrap();
+        assert_eq!(styles.len(), 2);
+        assert!(styles.iter().any(|style| style.id == kept.id));
+        assert_eq!(controller.get_page(loaded).unwrap().restyle_count(), 3);
+
+        // A rule that cannot be applied leaves them all as they were
+        let refused = controller.update_setting(USER_STYLES_SETTING, "* hide=div > p").await;
+        assert!(matches!(refused, Err(NavigatorError::InvalidSetting { .. })));
+        assert!(controller.update_setting(USER_STYLES_SETTING, "exa mple").await.is_err());
+        assert_eq!(controller.user_styles().await.unwrap(), styles);
+
+        controller.update_setting(USER_STYLES_SETTING, "").await.unwrap();
+        assert!(controller.user_styles().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_scripts_run_after_load_and_record_errors() {
+        let Fixture { controller, .. } = fixture();
+        let script = controller
+            .add_user_script("Counter", &["*://*.example.com/*"], "GM_setValue('n', 1)")
+            .await
+            .unwrap();
+        let tab = controller.open_tab(None).await.unwrap();
+        let page = controller.get_page(tab).unwrap();
+
+        page.set_script_result(Ok(r#"{"log":["hi"],"values":{"n":1},"error":null}"#.to_string()));
+        controller.navigate(tab, "https://news.example.com/").await.unwrap();
+        controller.navigate(tab, "https://other.org/").await.unwrap();
+        assert_eq!(page.executed_scripts().len(), 1);
+        assert!(page.executed_scripts()[0].contains(r#""GM_setValue('n', 1)""#));
+
+        // The stored value is handed to the next run; errors never fail the load
+        page.set_script_result(Err(RenderError::InvalidContent("SyntaxError".to_string())));
+        controller.navigate(tab, "https://example.com/").await.unwrap();
+        assert!(page.executed_scripts()[1].contains(r#"var values = {"n":1};"#));
+        let scripts = controller.user_scripts().await.unwrap();
+        assert_eq!(scripts[0].last_error.as_deref(), Some("Invalid content: SyntaxError"));
+
+        // Private tabs are skipped unless the script allows them
+        controller.state().set_private_mode(true);
+        let private = controller.open_tab(None).await.unwrap();
+        controller.navigate(private, "https://example.com/").await.unwrap();
+        assert!(controller.get_page(private).unwrap().executed_scripts().is_empty());
+
+        let mut allowed = scripts[0].clone();
+        allowed.allow_private = true;
+        controller.update_user_script(&allowed).await.unwrap();
+        controller.reload(private).await.unwrap();
+        assert_eq!(controller.get_page(private).unwrap().executed_scripts().len(), 1);
+
+        assert!(matches!(
+            controller.add_user_script("Bad", &["example.com"], "").await,
+            Err(NavigatorError::UserScript(UserScriptError::InvalidMatchPattern(_)))
+        ));
+        controller.remove_user_script(script.id).await.unwrap();
+        assert!(controller.user_scripts().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scripts_page_changes_and_removes_scripts() {
+        let Fixture { controller, .. } = fixture();
+        let script = controller.add_user_script("Greeter", &["*://example.com/*"], "GM_log('hi')").await.unwrap();
+        let tab = controller.open_tab(None).await.unwrap();
+
+        let page = format!("navigator://scripts?set={}%3Aenabled%3Dfalse&x=1", script.id);
+        let outcome = controller.navigate(tab, &page).await.unwrap();
+        assert_eq!(outcome.url.as_str(), "navigator://scripts?x=1");
+        let page = format!("navigator://scripts?set={}%3Arun-at%3Didle", script.id);
+        controller.navigate(tab, &page).await.unwrap();
+        let changed = &controller.user_scripts().await.unwrap()[0];
+        assert_eq!((changed.enabled, changed.run_at), (false, RunAt::DocumentIdle));
+
+        // A disabled script no longer runs
+        controller.navigate(tab, "https://example.com/").await.unwrap();
+        assert!(controller.get_page(tab).unwrap().executed_scripts().is_empty());
+
+        assert!(matches!(
+            controller.set_user_script_option(script.id, "enabled=maybe").await,
+            Err(NavigatorError::UserScript(UserScriptError::InvalidOption(_)))
+        ));
+        assert!(matches!(
+            controller.set_user_script_option(script.id + 1, "enabled=true").await,
+            Err(NavigatorError::UserScript(UserScriptError::NotFound(_)))
+        ));
+
+        let outcome = controller
+            .navigate(tab, &format!("navigator://scripts?remove={}", script.id))
+            .await
+            .unwrap();
+        assert_eq!(outcome.url.as_str(), "navigator://scripts");
+        assert!(controller.user_scripts().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_console_collects_logs_and_evaluations() {
+        let Fixture { controller, .. } = fixture();
+        controller.add_user_script("Greeter", &["*://example.com/*"], "GM_log('hi')").await.unwrap();
+        let tab = controller.open_tab(None).await.unwrap();
+        let page = controller.get_page(tab).unwrap();
+
+        page.set_script_result(Ok(r#"{"log":["hi"],"values":{},"error":null}"#.to_string()));
+        controller.navigate(tab, "https://example.com/").await.unwrap();
+        let console = controller.console(tab).unwrap();
+        assert_eq!(console[0].message, "hi");
+        assert_eq!(console[0].source.as_deref(), Some("Greeter"));
+
+        page.set_script_result(Ok("2".to_string()));
+        assert_eq!(controller.evaluate_in_console(tab, "1 + 1").await.unwrap(), "2");
+        page.set_script_result(Err(RenderError::Script("ReferenceError: x is not defined".to_string())));
+        assert!(controller.evaluate_in_console(tab, "x").await.is_err());
+        let console = controller.console(tab).unwrap();
+        let lines: Vec<_> = console.iter().map(|message| message.message.as_str()).collect();
+        let uncaught = "Uncaught Script failed: ReferenceError: x is not defined";
+        assert_eq!(lines[1..], ["> 1 + 1", "< 2", "> x", uncaught]);
+        assert_eq!(console[4].level, ConsoleLevel::Error);
+
+        // Navigating clears the console unless the log is preserved
+        controller.set_preserve_console(tab, true).unwrap();
+        controller.navigate(tab, "https://other.org/").await.unwrap();
+        assert_eq!(controller.console(tab).unwrap().len(), 5);
+        controller.set_preserve_console(tab, false).unwrap();
+        controller.navigate(tab, "https://other.org/next").await.unwrap();
+        assert!(controller.console(tab).unwrap().is_empty());
+        controller.evaluate_in_console(tab, "x").await.ok();
+        controller.clear_console(tab).unwrap();
+        assert!(controller.console(tab).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_new_tabs_are_blank() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        let opened = controller.state().get_tab(tab).unwrap();
+        assert!(opened.url().is_about_blank());
+        assert_eq!(opened.title, NEW_TAB_TITLE);
+
+        // Reloading shows the empty document rather than doing nothing
+        let reload = controller.reload(tab).await.unwrap().unwrap();
+        assert!(reload.url.is_about_blank());
+        assert_eq!(loads(&controller, tab), 1);
+        assert_eq!(controller.toggle_bookmark(tab).await, Err(NavigatorError::NothingToBookmark));
+
+        controller.navigate(tab, "https://example.com/").await.unwrap();
+        controller.back(tab).await.unwrap().unwrap();
+        let back = controller.state().get_tab(tab).unwrap();
+        assert_eq!(back.page_url(), None);
+        assert_eq!(back.title, NEW_TAB_TITLE);
+        assert!(controller.forward(tab).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_fragment_navigation_keeps_document() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+
+        controller.navigate(tab, "https://example.com/").await.unwrap();
+        controller.set_scroll_offset(tab, 240.0);
+        let outcome = controller.navigate(tab, "https://example.com/#part").await.unwrap();
+        assert!(outcome.same_document);
+        assert_eq!(loads(&controller, tab), 1);
+
+        let back = controller.back(tab).await.unwrap().unwrap();
+        assert!(back.same_document);
+        assert_eq!(back.scroll_offset, Some(240.0));
+
+        let reload = controller.reload(tab).await.unwrap().unwrap();
+        assert!(!reload.same_document);
+        assert_eq!(loads(&controller, tab), 2);
+
+        assert_eq!(controller.current_entry(tab).unwrap().url.as_str(), "https://example.com/");
+        // The tab started blank
+        let blank = controller.back(tab).await.unwrap().unwrap();
+        assert!(blank.url.is_about_blank());
+        assert!(controller.back(tab).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_blocked_url_is_not_loaded() {
+        let Fixture { controller, security, .. } = fixture();
+        security.add_blocked_domain("blocked.example".to_string());
+        let tab = controller.open_tab(None).await.unwrap();
+
+        assert_eq!(
+            controller.navigate(tab, "https://blocked.example/").await,
+            Err(NavigatorError::Security(SecurityError::Blocked("blocked.example".to_string())))
+        );
+        assert_eq!(loads(&controller, tab), 0);
+        assert!(controller.current_entry(tab).unwrap().url.is_about_blank());
+    }
+
+    #[tokio::test]
+    async fn test_typed_text_searches_by_keyword_or_default() {
+        let Fixture { controller, security, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        assert!(matches!(
+            controller.add_search_engine("Plain", "p", "http://search.example/?q=%s", None).await,
+            Err(NavigatorError::SearchEngine(SearchEngineError::InvalidTemplate(_)))
+        ));
+        let web = controller.add_search_engine("Web", "s", "https://search.example/?q=%s", None).await.unwrap();
+        let docs = controller
+            .add_search_engine("Docs", "docs", "https://docs.example/find/%s", None)
+            .await
+            .unwrap();
+        assert!(web.is_default && !docs.is_default);
+
+        let outcome = controller.navigate(tab, "docs borrow checker").await.unwrap();
+        assert_eq!(outcome.url.as_str(), "https://docs.example/find/borrow+checker");
+        let outcome = controller.navigate(tab, "borrow checker").await.unwrap();
+        assert_eq!(outcome.url.as_str(), "https://search.example/?q=borrow+checker");
+
+        controller.set_default_search_engine(docs.id).await.unwrap();
+        controller.remove_search_engine(web.id).await.unwrap();
+        assert_eq!(controller.search_engines().await.unwrap(), vec![SearchEngine { is_default: true, ..docs }]);
+        let outcome = controller.navigate(tab, "s lifetimes").await.unwrap();
+        assert_eq!(outcome.url.as_str(), "https://docs.example/find/s+lifetimes");
+
+        // Search URLs go through the blocklist like any other
+        security.add_blocked_domain("docs.example".to_string());
+        assert!(controller.navigate(tab, "lifetimes").await.is_err());
+    }
+
+    /// Describes the site's engine, counting how often it is asked
+    struct FakeOpenSearch {
+        fetches: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl OpenSearchFetcher for FakeOpenSearch {
+        async fn fetch(
+            &self,
+            _description: &ValidatedUrl,
+        ) -> std::result::Result<NewSearchEngine, SearchEngineError> {
+            self.fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let template = SearchTemplate::parse("https://docs.example/find/%s").unwrap();
+            Ok(NewSearchEngine::new("Docs".to_string(), "docs.example", template))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_offered_search_engine_is_added_only_when_accepted() {
+        let fetcher = Arc::new(FakeOpenSearch {
+            fetches: Default::default(),
+        });
+        let Fixture { controller, .. } = fixture();
+        let controller = controller.with_opensearch_fetcher(fetcher.clone());
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://docs.example/").await.unwrap();
+        assert_eq!(controller.search_engine_offer(tab).await.unwrap(), None);
+
+        let description = ValidatedUrl::parse("https://docs.example/opensearch.xml").unwrap();
+        controller.get_page(tab).unwrap().set_search_description(Some(description));
+        let offer = controller.search_engine_offer(tab).await.unwrap().unwrap();
+        assert_eq!((offer.name.as_str(), offer.keyword.as_str()), ("Docs", "docs.example"));
+        assert!(controller.search_engines().await.unwrap().is_empty());
+
+        let saved = controller.accept_search_engine_offer(offer).await.unwrap();
+        assert!(saved.is_default);
+        // A saved engine is not offered again
+        assert_eq!(controller.search_engine_offer(tab).await.unwrap(), None);
+        assert_eq!(fetcher.fetches.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    /// Sites listing sitemaps on `news.example` only, where the second
+    /// one fails
+    struct FakeSiteMetadata;
+
+    #[async_trait::async_trait]
+    impl SiteMetadataFetcher for FakeSiteMetadata {
+        async fn fetch_robots(&self, origin: &ValidatedUrl) -> std::result::Result<RobotsTxt, SiteMetadataError> {
+            let mut robots = RobotsTxt::default();
+            if origin.host_str() == Some("news.example") {
+                for sitemap in ["https://news.example/a.xml", "https://news.example/b.xml"] {
+                    robots.sitemaps.push(ValidatedUrl::parse(sitemap).unwrap());
+                }
+            }
+            Ok(robots)
+        }
+
+        async fn fetch_sitemap(
+            &self,
+            url: &ValidatedUrl,
+        ) -> std::result::Result<Vec<SitemapEntry>, SiteMetadataError> {
+            if url.path() == "/b.xml" {
+                return Err(SiteMetadataError::InvalidSitemap("empty".to_string()));
+            }
+            Ok(vec![SitemapEntry {
+                url: url.join("/listed").unwrap(),
+                last_modified: None,
+                change_frequency: None,
+                priority: None,
+            }])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_site_pages_come_from_the_sitemaps_robots_lists() {
+        let Fixture { controller, .. } = fixture();
+        assert_eq!(
+            controller.site_pages("news.example").await.unwrap_err(),
+            NavigatorError::SiteMetadataUnavailable
+        );
+
+        let controller = controller.with_site_metadata(Arc::new(FakeSiteMetadata));
+        let pages = controller.site_pages("news.example").await.unwrap();
+        assert_eq!(pages.len(), 1, "the failing sitemap is skipped");
+        assert_eq!(pages[0].url.as_str(), "https://news.example/listed");
+        // Without sitemaps in robots.txt, the usual place is tried
+        let pages = controller.site_pages("https://docs.example/guide/").await.unwrap();
+        assert_eq!(pages[0].url.as_str(), "https://docs.example/listed");
+        assert!(matches!(
+            controller.sitemap("https://news.example/b.xml").await,
+            Err(NavigatorError::SiteMetadata(SiteMetadataError::InvalidSitemap(_)))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_toggle_bookmark() {
+        let Fixture { controller, bookmarks, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        assert!(controller.toggle_bookmark(tab).await.is_err());
+
+        controller.navigate(tab, "https://example.com/a").await.unwrap();
+        assert!(controller.toggle_bookmark(tab).await.unwrap());
+        assert_eq!(bookmarks.find_all().await.unwrap()[0].title, "Page /a");
+
+        assert!(!controller.toggle_bookmark(tab).await.unwrap());
+        assert!(bookmarks.find_all().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_undo_restores_a_deleted_folder_exactly() {
+        let Fixture { controller, bookmarks, .. } = fixture();
+        for n in 0..50 {
+            let mut bookmark = NewBookmark::new(
+                format!("Page {}", n),
+                ValidatedUrl::parse(&format!("https://example.com/{}", n)).unwrap(),
+            );
+            bookmark.folder = Some(if n % 2 == 0 { "Work" } else { "Work/Rust" }.to_string());
+            bookmark.tags = vec![format!("tag {}", n)];
+            bookmarks.save(bookmark).await.unwrap();
+        }
+        let home = ValidatedUrl::parse("https://home.example/").unwrap();
+        let mut elsewhere = NewBookmark::new("Home".to_string(), home);
+        elsewhere.folder = Some("Workshop".to_string());
+        bookmarks.save(elsewhere).await.unwrap();
+        let before = bookmarks.find_all().await.unwrap();
+        let mut events = controller.state().subscribe();
+
+        assert_eq!(controller.delete_bookmark_folder("Work").await.unwrap(), 50);
+        assert_eq!(bookmarks.find_all().await.unwrap().len(), 1);
+        let notified = std::iter::from_fn(|| events.try_recv().ok())
+            .find_map(|event| match event {
+                StateEvent::Notified(id) => controller.state().notification(id),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(notified.message, "Deleted the folder \"Work\" with 50 bookmarks. Press Ctrl+Z to undo");
+
+        let undone = controller.undo().await.unwrap();
+        assert_eq!(undone.as_deref(), Some("Deleted the folder \"Work\" with 50 bookmarks"));
+        assert_eq!(bookmarks.find_all().await.unwrap(), before);
+        assert_eq!(controller.undo().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_bookmarks_are_listed_folder_by_folder_in_their_order() {
+        let Fixture { controller, bookmarks, .. } = fixture();
+        for (title, folder) in [("Rust", Some("Work")), ("News", None), ("Docs", Some("Work")), ("Mail", None)] {
+            let url = ValidatedUrl::parse(&format!("https://{}.example/", title.to_lowercase())).unwrap();
+            let mut bookmark = NewBookmark::new(title.to_string(), url);
+            bookmark.folder = folder.map(str::to_string);
+            bookmarks.save(bookmark).await.unwrap();
+        }
+        let titles = |listed: Vec<Bookmark>| listed.into_iter().map(|b| b.title).collect::<Vec<_>>();
+        assert_eq!(titles(controller.bookmarks().await.unwrap()), ["News", "Mail", "Rust", "Docs"]);
+
+        let docs = controller.bookmarks().await.unwrap()[3].id;
+        controller.move_bookmark(docs, Some("Work"), 0).await.unwrap();
+        assert_eq!(titles(controller.bookmarks().await.unwrap()), ["News", "Mail", "Docs", "Rust"]);
+        controller.move_bookmark(docs, None, 1).await.unwrap();
+        assert_eq!(titles(controller.bookmarks().await.unwrap()), ["News", "Docs", "Mail", "Rust"]);
+        assert_eq!(
+            controller.move_bookmark(999, None, 0).await,
+            Err(NavigatorError::BookmarkNotFound(999))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_undo_goes_back_through_deletions_in_turn() {
+        let Fixture { controller, bookmarks, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://example.com/a").await.unwrap();
+        controller.toggle_bookmark(tab).await.unwrap();
+        let bookmark = bookmarks.find_all().await.unwrap().remove(0);
+        let stored = controller.repositories.downloads.clone();
+        let controller = controller
+            .with_downloads(
+                Arc::new(SessionDownloads::new(stored.clone())),
+                Arc::new(InMemoryDownloadWriter::new()),
+            );
+        let download = controller.save_page(tab, SaveFormat::Text).await.unwrap();
+        controller.history_writer.flush().await;
+        let visits = controller.recent_history(10).await.unwrap();
+
+        controller.delete_bookmark(bookmark.id).await.unwrap();
+        let now = Utc::now();
+        assert_eq!(controller.delete_history_range(now - chrono::Duration::hours(1), now).await.unwrap(), 1);
+        controller.remove_download(download.id, false).await.unwrap();
+        assert!(controller.downloads().await.unwrap().is_empty());
+
+        assert!(controller.undo().await.unwrap().unwrap().contains("from downloads"));
+        assert_eq!(stored.find_all().await.unwrap(), vec![download.clone()]);
+        assert_eq!(controller.undo().await.unwrap().as_deref(), Some("Deleted 1 history entry"));
+        assert_eq!(controller.recent_history(10).await.unwrap(), visits);
+        controller.undo().await.unwrap();
+        assert_eq!(bookmarks.find_all().await.unwrap(), vec![bookmark]);
+
+        // Deleting the file as well cannot be undone
+        controller.remove_download(download.id, true).await.unwrap();
+        assert_eq!(controller.undo().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_removing_from_the_downloads_page_is_undoable_and_not_repeated() {
+        let Fixture { controller, .. } = fixture();
+        let stored = controller.repositories.downloads.clone();
+        let controller = controller
+            .with_downloads(
+                Arc::new(SessionDownloads::new(stored.clone())),
+                Arc::new(InMemoryDownloadWriter::new()),
+            );
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://example.com/a").await.unwrap();
+        let download = controller.save_page(tab, SaveFormat::Text).await.unwrap();
+
+        let page = format!("navigator://downloads?remove={}&sort=name", download.id);
+        let outcome = controller.navigate(tab, &page).await.unwrap();
+        assert_eq!(outcome.url.as_str(), "navigator://downloads?sort=name");
+        assert!(stored.find_all().await.unwrap().is_empty());
+
+        controller.undo().await.unwrap();
+        controller.reload(tab).await.unwrap();
+        assert_eq!(stored.find_all().await.unwrap(), vec![download]);
+    }
+
+    #[tokio::test]
+    async fn test_shortcuts_are_rebound_from_settings_unless_taken() {
+        let Fixture { controller, .. } = fixture();
+        let chord = |text: &str| text.parse::<Chord>().unwrap();
+        assert_eq!(controller.command_for(&chord("Ctrl+Shift+J")), Some(Command::ShowConsole));
+
+        let error = controller
+            .update_setting("keybindings.show_console", "Ctrl+Shift+D")
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Invalid value for Show the page's console: \
+                                       Ctrl+Shift+D is already the shortcut for \"Switch between light and dark\"");
+        assert_eq!(controller.command_for(&chord("Ctrl+Shift+J")), Some(Command::ShowConsole));
+
+        let stored = controller.update_setting("keybindings.show_console", "f12, ctrl+shift+c").await.unwrap();
+        assert_eq!(stored, "F12, Ctrl+Shift+C");
+        assert_eq!(controller.command_for(&chord("Ctrl+Shift+J")), None);
+        assert_eq!(controller.command_for(&chord("Ctrl+Shift+C")), Some(Command::ShowConsole));
+        assert_eq!(controller.setting("keybindings.show_console").await.unwrap(), "F12, Ctrl+Shift+C");
+    }
+
+    #[tokio::test]
+    async fn test_palette_lists_commands_tabs_and_pages_ranked_by_use() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://example.com/history").await.unwrap();
+        controller.toggle_bookmark(tab).await.unwrap();
+        controller.shutdown().await;
+        let now = Utc::now();
+
+        let entries = controller.palette("show his", now).await.unwrap();
+        assert_eq!(entries[0].target, PaletteTarget::Command(Command::ShowHistory));
+        assert_eq!(entries[0].detail, "Ctrl+H");
+        let entries = controller.palette("example history", now).await.unwrap();
+        let targets: Vec<&PaletteTarget> = entries.iter().map(|entry| &entry.target).collect();
+        let url = ValidatedUrl::parse("https://example.com/history").unwrap();
+        assert!(targets.contains(&&PaletteTarget::Tab(tab)));
+        assert!(targets.contains(&&PaletteTarget::Page(url)));
+
+        // Nothing typed lists the commands run most lately first
+        controller.record_palette_use(tab, Command::ToggleTheme).await.unwrap();
+        let entries = controller.palette("", now).await.unwrap();
+        assert_eq!(entries[0].target, PaletteTarget::Command(Command::ToggleTheme));
+
+        // Private tabs leave no count behind
+        controller.state().set_private_mode(true);
+        let private = controller.open_tab(None).await.unwrap();
+        controller.record_palette_use(private, Command::Reload).await.unwrap();
+        let usage = controller.repositories.settings.get(PALETTE_USAGE_SETTING).await.unwrap();
+        assert_eq!(usage.as_deref(), Some("toggle_theme:1"));
+    }
+
+    #[tokio::test]
+    async fn test_suggestions_from_bookmarks_and_history() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://wikipedia.org/wiki/Rust").await.unwrap();
+        controller.toggle_bookmark(tab).await.unwrap();
+        controller.navigate(tab, "https://example.com/wiki").await.unwrap();
+        controller.shutdown().await;
+
+        assert!(controller.suggest(tab, "  ").await.unwrap().is_empty());
+        let suggestions = controller.suggest(tab, "wiki").await.unwrap();
+        let listed: Vec<(&str, &SuggestionContext)> = suggestions
+            .iter()
+            .map(|suggestion| (suggestion.text.as_str(), &suggestion.context))
+            .collect();
+        assert_eq!(listed[0], ("https://wikipedia.org/wiki/Rust", &SuggestionContext::Bookmark { folder: None }));
+        assert!(matches!(
+            listed[1],
+            ("https://example.com/wiki", SuggestionContext::History { visit_count: 1, .. })
+        ));
+        assert_eq!(listed.len(), 2);
+        assert_eq!(top_completion("wiki", &suggestions).as_deref(), Some("wikipedia.org"));
+    }
+
+    #[tokio::test]
+    async fn test_open_tabs_are_suggested_for_switching_to() {
+        let Fixture { controller, .. } = fixture();
+        let typing = controller.open_tab(None).await.unwrap();
+        let open = controller.open_tab(None).await.unwrap();
+        controller.navigate(typing, "https://example.com/wiki").await.unwrap();
+        controller.navigate(open, "https://example.com/wiki").await.unwrap();
+        controller.state().set_private_mode(true);
+        let private = controller.open_tab(None).await.unwrap();
+        controller.navigate(private, "https://example.com/wiki/private").await.unwrap();
+        controller.shutdown().await;
+
+        // The page is in history and open in another tab; the tab wins
+        let suggestions = controller.suggest(typing, "example.com/wi").await.unwrap();
+        let open_tab = SuggestionContext::OpenTab { tab: open, title: "Page /wiki".to_string() };
+        assert_eq!(suggestions[0].text, "https://example.com/wiki");
+        assert_eq!(suggestions[0].context, open_tab);
+        assert!(suggestions[1..].iter().all(|suggestion| suggestion.text != "https://example.com/wiki"));
+        assert_eq!(tab_switch("example.com/wi", &suggestions), Some(("example.com/wiki".to_string(), open)));
+
+        // "%" looks through open tabs alone, and only private ones see private tabs
+        let tabs = |suggestions: Vec<Suggestion>| -> Vec<SuggestionContext> {
+            suggestions.into_iter().map(|suggestion| suggestion.context).collect()
+        };
+        assert_eq!(tabs(controller.suggest(typing, "%wiki").await.unwrap()), [open_tab]);
+        assert_eq!(controller.suggest(private, "tab: wiki").await.unwrap().len(), 2);
+        assert!(controller.suggest(typing, "%nothing like it").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_private_tabs_leave_no_trace() {
+        let Fixture { controller, tabs, .. } = fixture();
+        controller.state().set_private_mode(true);
+        let tab = controller.open_tab(None).await.unwrap();
+
+        controller.navigate(tab, "https://example.com/").await.unwrap();
+        controller.shutdown().await;
+
+        assert!(tabs.find_all().await.unwrap().is_empty());
+        assert!(controller.recent_history(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pages_save_to_the_chosen_directory() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://example.com/a").await.unwrap();
+        assert_eq!(
+            controller.save_page(tab, SaveFormat::Html).await,
+            Err(NavigatorError::SavingUnavailable)
+        );
+
+        let stored = controller.repositories.downloads.clone();
+        let writer = Arc::new(InMemoryDownloadWriter::new());
+        let controller = controller.with_downloads(Arc::new(SessionDownloads::new(stored.clone())), writer.clone());
+        let mut events = controller.state().subscribe();
+        let saved = controller.save_page(tab, SaveFormat::Text).await.unwrap();
+        assert_eq!(saved.path, PathBuf::from("downloads").join("Page _a.txt"));
+
+        // The tab's window is told where it went
+        let notified = std::iter::from_fn(|| events.try_recv().ok())
+            .find_map(|event| match event {
+                StateEvent::Notified(id) => controller.state().notification(id),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(notified.tab, Some(tab));
+        assert_eq!(notified.message, format!("Saved to {}", saved.path.display()));
+        assert_eq!(notified.action.unwrap().url, DOWNLOADS_PAGE);
+
+        controller
+            .repositories
+            .settings
+            .set(DOWNLOADS_DIRECTORY_SETTING, "/home/me/Saved")
+            .await
+            .unwrap();
+        controller.state().set_private_mode(true);
+        let private = controller.open_tab(None).await.unwrap();
+        controller.navigate(private, "https://example.com/b").await.unwrap();
+        let saved = controller.save_page(private, SaveFormat::Html).await.unwrap();
+        assert_eq!(saved.path, PathBuf::from("/home/me/Saved/Page _b.html"));
+        assert!(writer.file(&saved.path).is_some());
+
+        // Listed with the stored download, but never stored itself
+        assert_eq!(controller.downloads().await.unwrap().len(), 2);
+        assert_eq!(stored.find_all().await.unwrap().len(), 1);
+    }
+
+    /// Offers one release, counting how often the manifest is fetched
+    struct FakeUpdateSource {
+        version: &'static str,
+        fetches: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl UpdateSource for FakeUpdateSource {
+        async fn manifest(&self, _url: &ValidatedUrl) -> std::result::Result<UpdateManifest, UpdateError> {
+            self.fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(UpdateManifest {
+                version: self.version.to_string(),
+                notes: "Faster".to_string(),
+                download: ValidatedUrl::parse("https://example.com/navigator.zip").unwrap(),
+            })
+        }
+
+        async fn download(&self, url: &ValidatedUrl) -> std::result::Result<ReleaseFile, UpdateError> {
+            Ok(ReleaseFile {
+                url: url.clone(),
+                content_type: Some("application/zip".to_string()),
+                contents: b"PK".to_vec(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_check_is_opt_in_and_daily() {
+        let source = Arc::new(FakeUpdateSource {
+            version: "99.0.0",
+            fetches: Default::default(),
+        });
+        let Fixture { controller, .. } = fixture();
+        let stored = controller.repositories.downloads.clone();
+        let writer = Arc::new(InMemoryDownloadWriter::new());
+        let controller = controller
+            .with_update_source(source.clone())
+            .with_downloads(Arc::new(SessionDownloads::new(stored)), writer.clone());
+        let fetches = || source.fetches.load(std::sync::atomic::Ordering::SeqCst);
+        let now = Utc::now();
+        assert_eq!(controller.check_for_update(now).await, Ok(None));
+        controller.update_setting(UPDATE_CHECK_SETTING, "on").await.unwrap();
+        assert_eq!(controller.check_for_update(now).await, Ok(None));
+        assert_eq!(fetches(), 0);
+
+        controller
+            .update_setting(UPDATE_MANIFEST_SETTING, "https://example.com/latest.json")
+            .await
+            .unwrap();
+        let mut events = controller.state().subscribe();
+        let update = controller.check_for_update(now).await.unwrap().unwrap();
+        assert_eq!(update.version, "99.0.0");
+        assert_eq!(controller.available_update(), Some(update));
+        let notified = std::iter::from_fn(|| events.try_recv().ok())
+            .find_map(|event| match event {
+                StateEvent::Notified(id) => controller.state().notification(id),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(notified.tab, None);
+        assert_eq!(notified.action.unwrap().url, UPDATE_PAGE);
+
+        // Once a day at most
+        assert_eq!(controller.check_for_update(now + chrono::Duration::hours(3)).await, Ok(None));
+        assert_eq!(fetches(), 1);
+        assert!(controller.check_for_update(now + chrono::Duration::days(1)).await.unwrap().is_some());
+        assert_eq!(fetches(), 2);
+
+        let tab = controller.open_tab(None).await.unwrap();
+        let DownloadOutcome::Saved(saved) = controller.download_update(tab).await.unwrap() else {
+            panic!("an archive needs no confirmation");
+        };
+        assert_eq!(saved.path, PathBuf::from("downloads").join("navigator.zip"));
+        assert_eq!(writer.file(&saved.path), Some(b"PK".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_downloads_are_removed_with_or_without_their_files() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://example.com/a").await.unwrap();
+        let writer = Arc::new(InMemoryDownloadWriter::new());
+        let stored = controller.repositories.downloads.clone();
+        let controller = controller.with_downloads(Arc::new(SessionDownloads::new(stored)), writer.clone());
+        let mut events = controller.state().subscribe();
+
+        let kept = controller.save_page(tab, SaveFormat::Text).await.unwrap();
+        let deleted = controller.save_page(tab, SaveFormat::Html).await.unwrap();
+        assert_eq!(events.try_recv().ok(), Some(StateEvent::DownloadsChanged));
+
+        controller.remove_download(kept.id, false).await.unwrap();
+        assert!(writer.file(&kept.path).is_some());
+        controller.remove_download(deleted.id, true).await.unwrap();
+        assert!(writer.file(&deleted.path).is_none());
+        assert!(controller.downloads().await.unwrap().is_empty());
+        assert_eq!(
+            controller.remove_download(deleted.id, false).await,
+            Err(NavigatorError::DownloadNotFound(deleted.id))
+        );
+
+        controller.save_page(tab, SaveFormat::Text).await.unwrap();
+        controller.save_page(tab, SaveFormat::Text).await.unwrap();
+        assert_eq!(controller.clear_downloads().await.unwrap(), 2);
+        assert!(controller.downloads().await.unwrap().is_empty());
+        // Clearing the list leaves the files alone
+        assert!(writer.file(&kept.path).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pages_print_on_the_chosen_paper() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://example.com/a").await.unwrap();
+        let writer = Arc::new(InMemoryDownloadWriter::new());
+        let stored = controller.repositories.downloads.clone();
+        let controller = controller.with_downloads(Arc::new(SessionDownloads::new(stored)), writer.clone());
+        assert_eq!(controller.print_page(tab).await, Err(NavigatorError::PrintingUnavailable));
+
+        let controller = controller.with_page_printer(Arc::new(FakePrinter));
+        let printed = controller.print_page(tab).await.unwrap();
+        assert_eq!(printed.path, PathBuf::from("downloads").join("Page _a.pdf"));
+        assert!(writer.file(&printed.path).unwrap().starts_with(b"%PDF A4 "));
+
+        controller.repositories.settings.set(PAPER_SIZE_SETTING, "Letter").await.unwrap();
+        let printed = controller.print_page(tab).await.unwrap();
+        assert!(writer.file(&printed.path).unwrap().starts_with(b"%PDF Letter "));
+    }
+
+    #[tokio::test]
+    async fn test_close_window_closes_its_tabs() {
+        let Fixture { controller, tabs, .. } = fixture();
+        let window = controller.state().open_window();
+        let first = controller.open_tab(Some(window)).await.unwrap();
+        let second = controller.open_tab(Some(window)).await.unwrap();
+        assert_eq!(controller.state().get_active_tab_id_in_window(window), Some(second));
+
+        let closed = controller.close_window(window).await;
+        assert_eq!(closed.len(), 2);
+        assert!(controller.get_page(first).is_none());
+        assert!(controller.get_page(second).is_none());
+        assert!(tabs.find_all().await.unwrap().is_empty());
+        assert_eq!(controller.state().tab_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_settings_update_validates_and_announces() {
+        let Fixture { controller, .. } = fixture();
+        let mut events = controller.state().subscribe();
+        assert_eq!(controller.setting(HOMEPAGE_SETTING).await.unwrap(), DEFAULT_HOMEPAGE);
+
+        let stored = controller.update_setting(HTTPS_ONLY_SETTING, "on").await.unwrap();
+        assert_eq!(stored, "true");
+        let general = SettingsSections {
+            general: true,
+            ..SettingsSections::default()
+        };
+        assert_eq!(events.try_recv().ok(), Some(StateEvent::SettingsChanged(general)));
+        assert_eq!(events.try_recv().ok(), Some(StateEvent::SettingChanged(HTTPS_ONLY_SETTING)));
+        assert!(controller.settings().https_only);
+        assert_eq!(controller.resolve("http://example.com/a").unwrap().as_str(), "https://example.com/a");
+
+        assert!(matches!(
+            controller.update_setting(HOMEPAGE_SETTING, "not a url").await,
+            Err(NavigatorError::InvalidSetting { .. })
+        ));
+        assert!(matches!(
+            controller.update_setting("proxy.url", "x").await,
+            Err(NavigatorError::UnknownSetting(_))
+        ));
+        assert!(events.try_recv().is_err());
+        assert_eq!(controller.setting(HOMEPAGE_SETTING).await.unwrap(), DEFAULT_HOMEPAGE);
+    }
+
+    #[tokio::test]
+    async fn test_site_settings_reach_the_engine_for_their_site_only() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        let site = ValidatedUrl::parse("https://example.com/a").unwrap();
+        controller.set_site_setting(&site, SiteSetting::JavaScript(Some(false))).await.unwrap();
+
+        controller.navigate(tab, "https://example.com/b").await.unwrap();
+        let applied = controller.get_page(tab).unwrap().site_settings().unwrap();
+        assert_eq!(applied.javascript, Some(false));
+        controller.navigate(tab, "https://example.org/").await.unwrap();
+        assert!(controller.get_page(tab).unwrap().site_settings().is_none());
+
+        assert!(matches!(
+            controller.set_site_setting(&site, SiteSetting::UserAgent(Some("Bot\u{7}".into()))).await,
+            Err(NavigatorError::InvalidSetting { .. })
+        ));
+        let reset = controller.set_site_setting(&site, SiteSetting::JavaScript(None)).await.unwrap();
+        assert!(reset.is_empty());
+        controller.navigate(tab, "https://example.com/c").await.unwrap();
+        assert!(controller.get_page(tab).unwrap().site_settings().is_none());
+
+        let file = ValidatedUrl::parse("file:///tmp/page.html").unwrap();
+        assert!(matches!(
+            controller.site_settings(&file).await,
+            Err(NavigatorError::NoSiteSettings(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_storage_is_kept_per_origin_tab_and_privacy() {
+        let Fixture { controller, .. } = fixture();
+        let set = |key: &str, value: &str| StorageChange::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        let first = controller.open_tab(None).await.unwrap();
+        let second = controller.open_tab(None).await.unwrap();
+        assert!(matches!(
+            controller.storage(first, StorageKind::Local).await,
+            Err(NavigatorError::NoStorage(_))
+        ));
+        controller.navigate(first, "https://example.com/a").await.unwrap();
+        controller.navigate(second, "https://example.com/b").await.unwrap();
+
+        controller.update_storage(first, StorageKind::Local, set("theme", "dark")).await.unwrap();
+        controller.update_storage(first, StorageKind::Session, set("draft", "hi")).await.unwrap();
+        let local = controller.storage(second, StorageKind::Local).await.unwrap();
+        assert_eq!(local.get_item("theme"), Some("dark"));
+        assert_eq!(controller.storage(second, StorageKind::Session).await.unwrap().length(), 0);
+        assert_eq!(controller.storage(first, StorageKind::Session).await.unwrap().key(0), Some("draft"));
+        controller.navigate(second, "https://example.org/").await.unwrap();
+        assert_eq!(controller.storage(second, StorageKind::Local).await.unwrap().length(), 0);
+
+        // Over the quota nothing is stored
+        let huge = "x".repeat(DEFAULT_STORAGE_QUOTA);
+        assert!(matches!(
+            controller.update_storage(first, StorageKind::Local, set("huge", &huge)).await,
+            Err(NavigatorError::Storage(StorageError::QuotaExceeded(DEFAULT_STORAGE_QUOTA)))
+        ));
+        let site = ValidatedUrl::parse("https://example.com/").unwrap();
+        assert_eq!(controller.site_storage(&site).await.unwrap().usage(), "themedark".len());
+
+        controller.state().set_private_mode(true);
+        let private = controller.open_tab(None).await.unwrap();
+        controller.navigate(private, "https://example.com/").await.unwrap();
+        controller.update_storage(private, StorageKind::Local, set("secret", "1")).await.unwrap();
+        assert_eq!(controller.storage(private, StorageKind::Local).await.unwrap().get_item("secret"), Some("1"));
+        assert_eq!(controller.site_storage(&site).await.unwrap().get_item("secret"), None);
+        controller.close_tab(private).await.unwrap();
+
+        controller.clear_site_storage(&site).await.unwrap();
+        assert_eq!(controller.storage(first, StorageKind::Local).await.unwrap().length(), 0);
+        controller.clear_browsing_data(ClearScope::ALL).await.unwrap();
+        assert_eq!(controller.storage(first, StorageKind::Session).await.unwrap().length(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_summary_groups_by_site_until_the_next_navigation() {
+        let Fixture { controller, .. } = fixture();
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://example.com/").await.unwrap();
+        let blocked = |url: &str, category| BlockedRequest {
+            url: ValidatedUrl::parse(url).unwrap(),
+            category,
+        };
+        controller.get_page(tab).unwrap().set_blocked_requests(vec![
+            blocked("https://a.ads.example/1.js", BlockCategory::Ads),
+            blocked("https://pixel.tracker.co.uk/p.gif", BlockCategory::Trackers),
+            blocked("https://b.ads.example/2.png", BlockCategory::Ads),
+        ]);
+
+        let summary = controller.blocked_summary(tab).unwrap();
+        assert_eq!(summary.total(), 3);
+        assert_eq!(summary.count(BlockCategory::Ads), 2);
+        assert_eq!(summary.count(BlockCategory::MixedContent), 0);
+        let sites: Vec<(&str, usize)> =
+            summary.sites.iter().map(|(site, urls)| (site.as_str(), urls.len())).collect();
+        assert_eq!(sites, [("ads.example", 2), ("tracker.co.uk", 1)]);
+
+        controller.navigate(tab, "https://example.com/next").await.unwrap();
+        assert_eq!(controller.blocked_summary(tab).unwrap().total(), 0);
+        assert!(matches!(
+            controller.blocked_summary(TabId::new()),
+            Err(NavigatorError::TabNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_saved_session_is_restored_when_enabled() {
+        let Fixture { controller, tabs, .. } = fixture();
+        let first = controller.state().open_window();
+        let tab = controller.open_tab(Some(first)).await.unwrap();
+        controller.navigate(tab, "https://example.com/a").await.unwrap();
+        controller.save_session().await.unwrap();
+        assert!(controller.take_saved_session().await.unwrap().is_empty());
+        assert!(tabs.find_all().await.unwrap().is_empty());
+
+        controller.update_setting(RESTORE_SESSION_SETTING, "true").await.unwrap();
+        let second = controller.state().open_window();
+        let geometry = WindowGeometry {
+            x: 1920,
+            y: 0,
+            width: 1000,
+            height: 700,
+            monitor: Some("HDMI-1".to_string()),
+        };
+        controller.state().set_window_geometry(second, geometry.clone());
+        for url in ["https://example.com/b", "https://example.com/c"] {
+            let tab = controller.open_tab(Some(second)).await.unwrap();
+            controller.navigate(tab, url).await.unwrap();
+        }
+        let blank = controller.open_tab(Some(second)).await.unwrap();
+        controller.state().set_active_tab(blank);
+        controller.save_session().await.unwrap();
+
+        let windows = controller.take_saved_session().await.unwrap();
+        let urls: Vec<Vec<&str>> = windows
+            .iter()
+            .map(|window| window.tabs.iter().filter_map(|tab| tab.url.as_ref()).map(ValidatedUrl::as_str).collect())
+            .collect();
+        assert_eq!(urls, [vec!["https://example.com/a"], vec!["https://example.com/b", "https://example.com/c"]]);
+        assert_eq!(windows[1].geometry, Some(geometry));
+        // The blank tab was not saved, so neither is it shown
+        assert_eq!(windows[1].active_tab, None);
+        assert!(controller.take_saved_session().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_closed_windows_can_be_reopened() {
+        let Fixture { controller, .. } = fixture();
+        assert_eq!(controller.reopen_closed_window(), None);
+        let window = controller.state().open_window();
+        let first = controller.open_tab(Some(window)).await.unwrap();
+        controller.navigate(first, "https://example.com/a").await.unwrap();
+        let second = controller.open_tab(Some(window)).await.unwrap();
+        controller.navigate(second, "https://example.com/b").await.unwrap();
+        controller.state().set_active_tab(first);
+        let private = controller.state().open_window();
+        controller.state().set_private_mode(true);
+        let hidden = controller.open_tab(Some(private)).await.unwrap();
+        controller.navigate(hidden, "https://example.com/secret").await.unwrap();
+
+        assert_eq!(controller.close_window(window).await.len(), 2);
+        controller.close_window(private).await;
+        let reopened = controller.reopen_closed_window().unwrap();
+        assert_eq!(reopened.id, window);
+        assert_eq!(reopened.shown_tab().map(|tab| tab.id), Some(first));
+        assert_eq!(reopened.tabs.len(), 2);
+        assert_eq!(controller.reopen_closed_window(), None);
+    }
+
+    #[tokio::test]
+    async fn test_idle_tabs_suspend_and_resume_where_they_were() {
+        let Fixture { controller, .. } = fixture();
+        let hidden = controller.open_tab(None).await.unwrap();
+        controller.navigate(hidden, "https://example.com/a").await.unwrap();
+        controller.navigate(hidden, "https://example.com/b").await.unwrap();
+        controller.set_scroll_offset(hidden, 300.0);
+        let shown = controller.open_tab(None).await.unwrap();
+        let blank = controller.open_tab(None).await.unwrap();
+        controller.navigate(shown, "https://example.com/").await.unwrap();
+        controller.activate_tab(shown).await.unwrap();
+
+        let later = Instant::now() + DEFAULT_SUSPEND_AFTER;
+        assert!(controller.suspend_idle_tabs(Instant::now()).await.is_empty());
+        assert_eq!(controller.suspend_idle_tabs(later).await, vec![hidden]);
+        assert_eq!(controller.get_page(hidden).unwrap().suspend_count(), 1);
+        assert_eq!(controller.state().tab_activity(blank), TabActivity::Background);
+        assert_eq!(controller.state().tab_activity(shown), TabActivity::Foreground);
+
+        let outcome = controller.activate_tab(hidden).await.unwrap().unwrap();
+        assert_eq!(outcome.url.as_str(), "https://example.com/b");
+        assert_eq!(outcome.scroll_offset, Some(300.0));
+        assert_eq!(controller.get_page(hidden).unwrap().restyle_count(), 1);
+        assert_eq!(loads(&controller, hidden), 2);
+        assert_eq!(controller.state().tab_activity(shown), TabActivity::Background);
+        assert_eq!(controller.back(hidden).await.unwrap().unwrap().url.path(), "/a");
+
+        // Tabs that were never suspended have nothing to wake
+        assert_eq!(controller.activate_tab(shown).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_timers_are_throttled_by_tab_activity() {
+        let Fixture { controller, .. } = fixture();
+        let throttle = |tab| controller.get_page(tab).unwrap().timer_throttles().last().copied();
+        let first = controller.open_tab(None).await.unwrap();
+        controller.navigate(first, "https://example.com/").await.unwrap();
+        let second = controller.open_tab(None).await.unwrap();
+        assert_eq!(throttle(first), Some(Some(MIN_BACKGROUND_INTERVAL)));
+        assert_eq!(throttle(second), Some(Some(Duration::ZERO)));
+
+        controller.suspend_idle_tabs(Instant::now() + DEFAULT_SUSPEND_AFTER).await;
+        assert_eq!(throttle(first), Some(None));
+        controller.activate_tab(first).await.unwrap();
+        assert_eq!(throttle(first), Some(Some(Duration::ZERO)));
+        assert_eq!(throttle(second), Some(Some(MIN_BACKGROUND_INTERVAL)));
+    }
+
+    #[tokio::test]
+    async fn test_stale_tabs_reload_when_woken() {
+        let controller = fixture()
+            .controller
+            .with_throttle_policy(ThrottlePolicy::new().with_stale_after(Duration::ZERO));
+        let tab = controller.open_tab(None).await.unwrap();
+        controller.navigate(tab, "https://example.com/").await.unwrap();
+        controller.open_tab(None).await.unwrap();
+
+        assert_eq!(controller.suspend_idle_tabs(Instant::now() + DEFAULT_SUSPEND_AFTER).await, vec![tab]);
+        let outcome = controller.show_tab(tab).await.unwrap().unwrap();
+        assert!(!outcome.same_document);
+        assert_eq!(loads(&controller, tab), 2);
+        assert_eq!(controller.get_page(tab).unwrap().restyle_count(), 0);
+        assert_eq!(controller.state().tab_activity(tab), TabActivity::Foreground);
+
+        let never = controller.with_throttle_policy(ThrottlePolicy::new().with_suspend_after(Duration::ZERO));
+        never.state().set_tab_activity(tab, TabActivity::Background);
+        assert!(never.suspend_idle_tabs(Instant::now() + DEFAULT_STALE_AFTER).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_suspending_background_tabs_frees_their_layouts() {
+        let controller = BrowserController::new(
+            BrowserState::new(),
+            repositories(
+                Arc::new(InMemoryTabRepository::new()),
+                Arc::new(InMemoryBookmarkRepository::new()),
+            ),
+            Arc::new(DefaultSecurityService::new()),
+            Arc::new(DefaultUserStyleService::new()),
+            Arc::new(DefaultUserScriptService::new()),
+            ServoRenderer::new,
+        );
+        let paragraph = "<p>Background tabs keep their source but not their layout, \
+                         <a href=\"/next\">next page</a> <b>bold</b> text.</p>";
+        let html = format!("<html><body>{}</body></html>", paragraph.repeat(2_000));
+        let url = format!(
+            "data:text/html;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(&html)
+        );
+
+        let mut tabs = Vec::new();
+        for _ in 0..20 {
+            let tab = controller.open_tab(None).await.unwrap();
+            controller.navigate(tab, &url).await.unwrap();
+            tabs.push(tab);
+        }
+        let retained = |controller: &BrowserController<ServoRenderer>| -> usize {
+            tabs.iter().map(|tab| controller.get_page(*tab).unwrap().retained_bytes()).sum()
+        };
+        let before = retained(&controller);
+
+        // The last tab is active; one other was looked at recently
+        controller.state().set_tab_activity(tabs[0], TabActivity::Foreground);
+        let suspended = controller.suspend_idle_tabs(Instant::now() + DEFAULT_SUSPEND_AFTER).await;
+        assert_eq!(suspended.len(), 18);
+        let after = retained(&controller);
+        assert!(after * 2 < before, "retained {} bytes of {}", after, before);
+        assert_eq!(controller.get_page(tabs[1]).unwrap().retained_bytes(), html.len());
+
+        controller.activate_tab(tabs[1]).await.unwrap().unwrap();
+        assert!(controller.get_page(tabs[1]).unwrap().layout().text.contains("next page"));
+    }
+
+    #[tokio::test]
+    async fn test_trimming_memory_suspends_hidden_tabs() {
+        let controller = BrowserController::new(
+            BrowserState::new(),
+            repositories(
+                Arc::new(InMemoryTabRepository::new()),
+                Arc::new(InMemoryBookmarkRepository::new()),
+            ),
+            Arc::new(DefaultSecurityService::new()),
+            Arc::new(DefaultUserStyleService::new()),
+            Arc::new(DefaultUserScriptService::new()),
+            ServoRenderer::new,
+        );
+        let html = format!("<html><body>{}</body></html>", "<p>Accounted text.</p>".repeat(500));
+        let url = format!("data:text/html;base64,{}", base64::engine::general_purpose::STANDARD.encode(&html));
+        let closed = controller.open_tab(None).await.unwrap();
+        controller.close_tab(closed).await.unwrap();
+        let hidden = controller.open_tab(None).await.unwrap();
+        controller.navigate(hidden, &url).await.unwrap();
+        let shown = controller.open_tab(None).await.unwrap();
+        controller.navigate(shown, &url).await.unwrap();
+
+        // One line per open tab, each with its document
+        let reports = controller.memory().reports();
+        assert_eq!(reports.len(), 2);
+        assert!(reports.iter().all(|report| report.usage.entries == 1 && report.usage.bytes > html.len()));
+        let total = controller.memory().total_bytes();
+
+        // The page being looked at keeps its layout
+        let freed = controller.memory().trim_to(0);
+        assert!(freed > 0);
+        assert_eq!(controller.memory().total_bytes(), total - freed);
+        assert_eq!(controller.state().tab_activity(hidden), TabActivity::Suspended);
+        assert_eq!(controller.get_page(hidden).unwrap().retained_bytes(), html.len());
+        assert_eq!(controller.state().tab_activity(shown), TabActivity::Foreground);
+        assert!(controller.get_page(shown).unwrap().layout().text.contains("Accounted text."));
+
+        controller.activate_tab(hidden).await.unwrap().unwrap();
+        assert!(controller.get_page(hidden).unwrap().layout().text.contains("Accounted text."));
+    }
+}
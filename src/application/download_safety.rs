@@ -0,0 +1,483 @@
+use crate::domain::{Download, ValidatedUrl};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// How much harm opening a downloaded file can do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DownloadRisk {
+    /// Opened by another program as a document, picture or archive
+    Safe,
+    /// Runs code of its own when opened: a program, installer or script
+    Dangerous,
+}
+
+/// Extensions of files that run code when opened, so they are only written
+/// once the user confirms
+pub const DANGEROUS_EXTENSIONS: [&str; 36] = [
+    // Programs and installers
+    "exe", "com", "scr", "pif", "cpl", "msi", "msix", "msp", "appx", "dll", "apk", "jar", "deb", "rpm", "dmg",
+    "pkg", "appimage", "run",
+    // Scripts run by a shell or the system's script hosts
+    "bat", "cmd", "sh", "bash", "command", "ps1", "psm1", "vbs", "vbe", "js", "jse", "wsf", "wsh", "hta", "py",
+    "pl",
+    // Files that change the system or point at something else to run
+    "reg", "lnk",
+];
+
+/// Content types and the extensions a file served with them should have;
+/// a file whose extension is not listed for its type is flagged
+const TYPE_EXTENSIONS: [(&str, &[&str]); 17] = [
+    ("application/pdf", &["pdf"]),
+    ("application/zip", &["zip"]),
+    ("application/json", &["json"]),
+    ("application/x-msdownload", &["exe", "dll", "com", "msi"]),
+    ("application/x-msdos-program", &["exe", "com", "bat"]),
+    ("application/x-msi", &["msi"]),
+    ("application/vnd.android.package-archive", &["apk"]),
+    ("application/java-archive", &["jar"]),
+    ("application/x-sh", &["sh"]),
+    ("image/png", &["png"]),
+    ("image/jpeg", &["jpg", "jpeg"]),
+    ("image/gif", &["gif"]),
+    ("image/webp", &["webp"]),
+    ("audio/mpeg", &["mp3"]),
+    ("video/mp4", &["mp4", "m4v"]),
+    ("text/plain", &["txt", "text", "log"]),
+    ("text/html", &["html", "htm"]),
+];
+
+/// Characters that reorder how the rest of a name is displayed, used to
+/// show `invoice\u{202E}fdp.exe` as `invoiceexe.pdf`
+const BIDI_CONTROLS: [char; 12] = [
+    '\u{061C}', '\u{200E}', '\u{200F}', '\u{202A}', '\u{202B}', '\u{202C}', '\u{202D}', '\u{202E}', '\u{2066}',
+    '\u{2067}', '\u{2068}', '\u{2069}',
+];
+
+/// Risk of a file with `extension`, in any case
+pub fn extension_risk(extension: &str) -> DownloadRisk {
+    let extension = extension.to_ascii_lowercase();
+    if DANGEROUS_EXTENSIONS.contains(&extension.as_str()) {
+        DownloadRisk::Dangerous
+    } else {
+        DownloadRisk::Safe
+    }
+}
+
+/// Why a download is held until the user confirms it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadWarning {
+    /// Files with the extension run code when opened
+    DangerousType(String),
+    /// The extension is not one the server's content type should have
+    TypeMismatch { extension: String, content_type: String },
+    /// The name hid its real extension behind reordering characters or a
+    /// second, harmless looking one
+    DisguisedName,
+    /// A program came over plain HTTP, where anyone on the way could have
+    /// replaced it
+    InsecureOrigin,
+}
+
+impl fmt::Display for DownloadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DangerousType(extension) => write!(f, ".{} files can run programs on this computer", extension),
+            Self::TypeMismatch { extension, content_type } => {
+                write!(f, "the site says this is {}, not a .{} file", content_type, extension)
+            }
+            Self::DisguisedName => write!(f, "the file name disguises its real type"),
+            Self::InsecureOrigin => write!(f, "it came over an unencrypted connection"),
+        }
+    }
+}
+
+/// What checking a download before writing it found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadAssessment {
+    /// The name to write the file under, with reordering characters and
+    /// runs of whitespace taken out
+    pub file_name: String,
+    pub warnings: Vec<DownloadWarning>,
+}
+
+impl DownloadAssessment {
+    /// Whether the download may only be written once the user confirms it
+    pub fn needs_confirmation(&self) -> bool {
+        !self.warnings.is_empty()
+    }
+}
+
+/// Check a download of `file_name` from `url`, served as `content_type`,
+/// before anything is written
+pub fn assess_download(url: &ValidatedUrl, file_name: &str, content_type: Option<&str>) -> DownloadAssessment {
+    let (normalized, reordered) = normalized_file_name(file_name);
+    let mut extensions = normalized.rsplit('.').take(2).map(str::to_ascii_lowercase);
+    let extension = extensions.next().filter(|_| normalized.contains('.')).unwrap_or_default();
+    let inner = extensions.next().filter(|_| normalized.matches('.').count() >= 2);
+
+    let mut warnings = Vec::new();
+    let dangerous = extension_risk(&extension) == DownloadRisk::Dangerous;
+    if dangerous {
+        warnings.push(DownloadWarning::DangerousType(extension.clone()));
+    }
+    let mime = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|mime| mime.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    let expected = TYPE_EXTENSIONS.iter().find(|(served, _)| *served == mime);
+    if expected.is_some_and(|(_, extensions)| !extensions.contains(&extension.as_str())) {
+        warnings.push(DownloadWarning::TypeMismatch {
+            extension: extension.clone(),
+            content_type: mime.clone(),
+        });
+    }
+    // `invoice.pdf.exe`: a program named after the document it pretends to be
+    let document_inside = inner.is_some_and(|inner| {
+        extension_risk(&inner) == DownloadRisk::Safe
+            && TYPE_EXTENSIONS.iter().any(|(_, extensions)| extensions.contains(&inner.as_str()))
+    });
+    if reordered || (dangerous && document_inside) {
+        warnings.push(DownloadWarning::DisguisedName);
+    }
+    if dangerous && url.scheme() == "http" {
+        warnings.push(DownloadWarning::InsecureOrigin);
+    }
+    DownloadAssessment {
+        file_name: normalized,
+        warnings,
+    }
+}
+
+/// `file_name` without reordering characters, with whitespace runs
+/// collapsed and trimmed around dots, and without the trailing dots and
+/// spaces Windows drops when opening it; also whether reordering
+/// characters were taken out
+fn normalized_file_name(file_name: &str) -> (String, bool) {
+    let reordered = file_name.contains(BIDI_CONTROLS);
+    let stripped: String = file_name.chars().filter(|c| !BIDI_CONTROLS.contains(c)).collect();
+    let name = stripped
+        .split('.')
+        .map(|part| part.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join(".");
+    let name = match name.trim_end_matches(|c: char| c == '.' || c.is_whitespace()) {
+        "" => "download".to_string(),
+        trimmed => trimmed.to_string(),
+    };
+    (name, reordered)
+}
+
+/// A download held back until the user confirms it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingDownload {
+    pub id: u64,
+    pub url: ValidatedUrl,
+    /// Name the file gets once confirmed, already normalized
+    pub file_name: String,
+    pub warnings: Vec<DownloadWarning>,
+    /// Started from a private tab, so not stored once written
+    pub private: bool,
+}
+
+/// A download being written, or whose write failed and can be tried again
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnfinishedDownload {
+    pub id: u64,
+    pub url: ValidatedUrl,
+    /// Name the file gets once written, already normalized
+    pub file_name: String,
+    /// Why the last write failed; none while one is under way
+    pub error: Option<String>,
+    pub private: bool,
+}
+
+/// A write of an [`UnfinishedDownload`] about to start: what to write, and
+/// the flag that cancels it
+#[derive(Debug, Clone)]
+pub struct DownloadWrite {
+    pub download: UnfinishedDownload,
+    pub contents: Arc<Vec<u8>>,
+    pub cancelled: Arc<AtomicBool>,
+}
+
+/// A file to download, as it arrived
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDownload {
+    pub url: ValidatedUrl,
+    /// Name the server or the URL suggested
+    pub file_name: String,
+    pub content_type: Option<String>,
+    pub contents: Vec<u8>,
+}
+
+/// What became of a requested download
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadOutcome {
+    /// Nothing was flagged and the file was written
+    Saved(Download),
+    /// The download waits on the downloads page to be confirmed or discarded
+    PendingConfirmation(PendingDownload),
+}
+
+/// Holds flagged downloads until each one is confirmed or discarded.
+/// Confirming applies to that download only; nothing is remembered for
+/// later ones of the same type or site. Also lists the downloads being
+/// written and those whose write failed, keeping the contents of the
+/// latter to try again.
+#[derive(Debug, Default)]
+pub struct DownloadManager {
+    pending: RwLock<Vec<(PendingDownload, Vec<u8>)>>,
+    unfinished: RwLock<Vec<DownloadWrite>>,
+    next_id: AtomicU64,
+}
+
+impl DownloadManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold `download`, checked as `assessment`, until it is confirmed
+    pub fn hold(&self, download: FileDownload, assessment: DownloadAssessment, private: bool) -> PendingDownload {
+        let pending = PendingDownload {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst) + 1,
+            url: download.url,
+            file_name: assessment.file_name,
+            warnings: assessment.warnings,
+            private,
+        };
+        if let Ok(mut held) = self.pending.write() {
+            held.push((pending.clone(), download.contents));
+        }
+        pending
+    }
+
+    /// Downloads waiting for confirmation, newest first
+    pub fn pending(&self) -> Vec<PendingDownload> {
+        self.pending
+            .read()
+            .map(|held| held.iter().rev().map(|(pending, _)| pending.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Let go of a held download with its contents, to write it
+    pub fn take(&self, id: u64) -> Option<(PendingDownload, Vec<u8>)> {
+        let mut held = self.pending.write().ok()?;
+        let index = held.iter().position(|(pending, _)| pending.id == id)?;
+        Some(held.remove(index))
+    }
+
+    /// Drop a held download without writing it; returns whether it was held
+    pub fn discard(&self, id: u64) -> bool {
+        self.take(id).is_some()
+    }
+
+    /// List a download as being written until [`finish`](Self::finish) or
+    /// [`fail`](Self::fail) is called with its id
+    pub fn begin(&self, url: ValidatedUrl, file_name: String, contents: Vec<u8>, private: bool) -> DownloadWrite {
+        let write = DownloadWrite {
+            download: UnfinishedDownload {
+                id: self.next_id.fetch_add(1, Ordering::SeqCst) + 1,
+                url,
+                file_name,
+                error: None,
+                private,
+            },
+            contents: Arc::new(contents),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+        if let Ok(mut unfinished) = self.unfinished.write() {
+            unfinished.push(write.clone());
+        }
+        write
+    }
+
+    /// Take a download off the list once it is saved or cancelled
+    pub fn finish(&self, id: u64) {
+        if let Ok(mut unfinished) = self.unfinished.write() {
+            unfinished.retain(|write| write.download.id != id);
+        }
+    }
+
+    /// Keep a download whose write failed listed, with why, to try again
+    pub fn fail(&self, id: u64, error: String) {
+        if let Ok(mut unfinished) = self.unfinished.write() {
+            if let Some(write) = unfinished.iter_mut().find(|write| write.download.id == id) {
+                write.download.error = Some(error);
+            }
+        }
+    }
+
+    /// Start writing a failed download again; none if no failed download
+    /// has this id
+    pub fn retry(&self, id: u64) -> Option<DownloadWrite> {
+        let mut unfinished = self.unfinished.write().ok()?;
+        let write = unfinished
+            .iter_mut()
+            .find(|write| write.download.id == id && write.download.error.is_some())?;
+        write.download.error = None;
+        write.cancelled = Arc::new(AtomicBool::new(false));
+        Some(write.clone())
+    }
+
+    /// Stop a write under way, or forget a failed one; returns whether
+    /// either was listed
+    pub fn cancel(&self, id: u64) -> bool {
+        let Ok(mut unfinished) = self.unfinished.write() else {
+            return false;
+        };
+        let Some(index) = unfinished.iter().position(|write| write.download.id == id) else {
+            return false;
+        };
+        if unfinished[index].download.error.is_some() {
+            unfinished.remove(index);
+        } else {
+            unfinished[index].cancelled.store(true, Ordering::SeqCst);
+        }
+        true
+    }
+
+    /// Downloads being written or failed, newest first
+    pub fn unfinished(&self) -> Vec<UnfinishedDownload> {
+        self.unfinished
+            .read()
+            .map(|unfinished| unfinished.iter().rev().map(|write| write.download.clone()).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assess(url: &str, file_name: &str, content_type: Option<&str>) -> DownloadAssessment {
+        assess_download(&ValidatedUrl::parse(url).unwrap(), file_name, content_type)
+    }
+
+    #[test]
+    fn test_every_extension_has_its_risk_level() {
+        for extension in DANGEROUS_EXTENSIONS {
+            assert_eq!(extension_risk(extension), DownloadRisk::Dangerous, "{}", extension);
+            assert_eq!(extension_risk(&extension.to_ascii_uppercase()), DownloadRisk::Dangerous, "{}", extension);
+        }
+        for (_, extensions) in TYPE_EXTENSIONS {
+            for extension in extensions {
+                let dangerous = DANGEROUS_EXTENSIONS.contains(extension);
+                assert_eq!(extension_risk(extension) == DownloadRisk::Dangerous, dangerous, "{}", extension);
+            }
+        }
+        for extension in ["pdf", "zip", "png", "txt", "docx", "mp3", "", "exe2", "xe"] {
+            assert_eq!(extension_risk(extension), DownloadRisk::Safe, "{:?}", extension);
+        }
+        let unique: std::collections::HashSet<_> = DANGEROUS_EXTENSIONS.iter().collect();
+        assert_eq!(unique.len(), DANGEROUS_EXTENSIONS.len());
+    }
+
+    #[test]
+    fn test_ordinary_files_need_no_confirmation() {
+        let report = assess("https://example.com/report.pdf", "report.pdf", Some("application/pdf"));
+        assert!(!report.needs_confirmation());
+        assert_eq!(report.file_name, "report.pdf");
+        // Unknown types and a missing one are taken at the name's word
+        assert!(!assess("http://example.com/a", "notes.md", Some("application/octet-stream")).needs_confirmation());
+        assert!(!assess("http://example.com/a", "photo.JPG", Some("image/jpeg; q=1")).needs_confirmation());
+        assert!(!assess("http://example.com/a", "README", None).needs_confirmation());
+    }
+
+    #[test]
+    fn test_risky_downloads_are_flagged() {
+        let setup = assess("https://example.com/setup.exe", "setup.exe", Some("application/x-msdownload"));
+        assert_eq!(setup.warnings, [DownloadWarning::DangerousType("exe".to_string())]);
+
+        let mismatch = assess("https://example.com/doc", "doc.pdf", Some("application/x-msdownload"));
+        assert_eq!(
+            mismatch.warnings,
+            [DownloadWarning::TypeMismatch {
+                extension: "pdf".to_string(),
+                content_type: "application/x-msdownload".to_string()
+            }]
+        );
+
+        let insecure = assess("http://example.com/install.sh", "install.sh", None);
+        assert!(insecure.warnings.contains(&DownloadWarning::InsecureOrigin));
+        // Plain HTTP alone flags nothing
+        assert!(!assess("http://example.com/a.zip", "a.zip", Some("application/zip")).needs_confirmation());
+    }
+
+    #[test]
+    fn test_disguised_names_are_normalized_and_flagged() {
+        let double = assess("https://example.com/f", "invoice.pdf.exe", None);
+        assert!(double.warnings.contains(&DownloadWarning::DisguisedName));
+        assert!(double.warnings.contains(&DownloadWarning::DangerousType("exe".to_string())));
+        // A version number is not a second extension
+        let versioned = assess("https://example.com/f", "tool-1.2.exe", None);
+        assert!(!versioned.warnings.contains(&DownloadWarning::DisguisedName));
+
+        let reordered = assess("https://example.com/f", "invoice\u{202E}fdp.exe", None);
+        assert_eq!(reordered.file_name, "invoicefdp.exe");
+        assert!(reordered.warnings.contains(&DownloadWarning::DisguisedName));
+        let padded = assess("https://example.com/f", "photo.jpg        .exe", None);
+        assert_eq!(padded.file_name, "photo.jpg.exe");
+        assert!(padded.warnings.contains(&DownloadWarning::DisguisedName));
+        // `setup.exe.` and `setup.exe ` run as `setup.exe` on Windows
+        for trailing in ["setup.exe.", "setup.exe ", "setup.exe. . "] {
+            let trailed = assess("https://example.com/f", trailing, None);
+            assert_eq!(trailed.file_name, "setup.exe", "{:?}", trailing);
+            assert!(trailed.warnings.contains(&DownloadWarning::DangerousType("exe".to_string())));
+        }
+        assert_eq!(assess("https://example.com/f", ". .", None).file_name, "download");
+        // Reordering characters are flagged even on harmless files
+        assert!(assess("https://example.com/f", "\u{2067}notes.txt", None).needs_confirmation());
+    }
+
+    #[test]
+    fn test_held_downloads_wait_until_taken_or_discarded() {
+        let manager = DownloadManager::new();
+        let download = |name: &str| FileDownload {
+            url: ValidatedUrl::parse("https://example.com/").unwrap(),
+            file_name: name.to_string(),
+            content_type: None,
+            contents: b"MZ".to_vec(),
+        };
+        let first = manager.hold(download("a.exe"), assess("https://example.com/", "a.exe", None), false);
+        let second = manager.hold(download("b.bat"), assess("https://example.com/", "b.bat", None), true);
+        assert_ne!(first.id, second.id);
+        assert_eq!(manager.pending(), [second.clone(), first.clone()]);
+
+        let (taken, contents) = manager.take(first.id).unwrap();
+        assert_eq!((taken, contents), (first.clone(), b"MZ".to_vec()));
+        assert!(manager.take(first.id).is_none());
+        assert!(manager.discard(second.id));
+        assert!(!manager.discard(second.id));
+        assert!(manager.pending().is_empty());
+    }
+
+    #[test]
+    fn test_failed_downloads_can_be_retried_and_writes_cancelled() {
+        let manager = DownloadManager::new();
+        let url = ValidatedUrl::parse("https://example.com/").unwrap();
+        let first = manager.begin(url.clone(), "a.zip".to_string(), b"PK".to_vec(), false);
+        let second = manager.begin(url, "b.zip".to_string(), Vec::new(), true);
+        let ids = |manager: &DownloadManager| manager.unfinished().iter().map(|d| d.id).collect::<Vec<_>>();
+        assert_eq!(ids(&manager), [second.download.id, first.download.id]);
+
+        // Only a failed download is retried, with its contents
+        assert!(manager.retry(first.download.id).is_none());
+        manager.fail(first.download.id, "Disk full".to_string());
+        assert_eq!(manager.unfinished()[1].error.as_deref(), Some("Disk full"));
+        let again = manager.retry(first.download.id).unwrap();
+        assert_eq!((again.download.error, again.contents.as_slice()), (None, &b"PK"[..]));
+        assert!(manager.retry(first.download.id).is_none());
+
+        // Cancelling signals a write under way; it stays listed until it stops
+        assert!(manager.cancel(again.download.id));
+        assert!(again.cancelled.load(Ordering::SeqCst));
+        assert!(!first.cancelled.load(Ordering::SeqCst));
+        manager.finish(again.download.id);
+        // A failed one is simply forgotten
+        manager.fail(second.download.id, "Disk full".to_string());
+        assert!(manager.cancel(second.download.id));
+        assert!(manager.unfinished().is_empty());
+        assert!(!manager.cancel(second.download.id));
+    }
+}
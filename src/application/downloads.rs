@@ -0,0 +1,408 @@
+use crate::domain::{
+    Download, DownloadRepository, NewDownload, PersistenceGuard, RequestContext, StorageError, ValidatedUrl,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::ops::Range;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Directory saved pages go to, instead of the writer's default
+pub const DOWNLOADS_DIRECTORY_SETTING: &str = "downloads.directory";
+
+/// Browser page listing saved files
+pub const DOWNLOADS_PAGE: &str = "navigator://downloads";
+
+/// Paper printed pages are laid out for, `a4` or `letter`
+pub const PAPER_SIZE_SETTING: &str = "print.paper_size";
+
+/// Longest file name, before the extension, given to a saved page
+const MAX_FILE_STEM: usize = 100;
+
+/// Attributes holding URLs that a saved page should keep pointing at
+const URL_ATTRIBUTES: [&str; 4] = ["href", "src", "action", "poster"];
+
+/// Elements whose content is text rather than markup
+const RAW_TEXT_ELEMENTS: [&str; 4] = ["script", "style", "textarea", "title"];
+
+/// How a page is saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveFormat {
+    /// The HTML as fetched, with links made absolute
+    Html,
+    /// The text as laid out
+    Text,
+    /// The page with its images, stylesheets and scripts, as one MHTML file
+    Mhtml,
+}
+
+impl SaveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Text => "txt",
+            Self::Mhtml => "mhtml",
+        }
+    }
+}
+
+/// A URL attribute of a start tag, its value decoded
+#[derive(Debug)]
+struct UrlAttribute {
+    tag: String,
+    value: String,
+    /// The value as written, quotes included
+    span: Range<usize>,
+}
+
+/// Rewrite relative URLs in `html` to absolute ones, so a saved copy still
+/// links to and loads from the site. URLs resolve against the first
+/// `<base href>` if there is one, else `page_url`; fragment-only links are
+/// left alone.
+pub fn absolutize_links(html: &str, page_url: &ValidatedUrl) -> String {
+    let attributes = url_attributes(html);
+    let base = attributes
+        .iter()
+        .find(|attribute| attribute.tag == "base")
+        .and_then(|attribute| page_url.join(&attribute.value).ok())
+        .unwrap_or_else(|| page_url.clone());
+
+    let mut saved = String::with_capacity(html.len() + html.len() / 8);
+    let mut copied = 0;
+    for attribute in &attributes {
+        let value = attribute.value.trim();
+        if value.is_empty() || value.starts_with('#') || ValidatedUrl::parse(value).is_ok() {
+            continue;
+        }
+        let Ok(absolute) = base.join(value) else {
+            continue;
+        };
+        saved.push_str(&html[copied..attribute.span.start]);
+        saved.push('"');
+        saved.push_str(&absolute.as_str().replace('&', "&amp;").replace('"', "&quot;"));
+        saved.push('"');
+        copied = attribute.span.end;
+    }
+    saved.push_str(&html[copied..]);
+    saved
+}
+
+/// `html` as saved from `url`: links made absolute, under a comment noting
+/// where and when it came from
+pub fn saved_html(html: &str, url: &ValidatedUrl, saved_at: DateTime<Utc>) -> String {
+    // "--" may not appear inside a comment; an escaped hyphen is the same URL
+    let source = url.as_str().replace("--", "-%2D");
+    format!(
+        "<!-- saved from {} on {} -->\n{}",
+        source,
+        saved_at.format("%Y-%m-%d %H:%M UTC"),
+        absolutize_links(html, url)
+    )
+}
+
+/// File name with `extension` for a page titled `title`, safe on every
+/// platform; untitled pages are named after their host
+pub fn page_file_name(title: &str, url: &ValidatedUrl, extension: &str) -> String {
+    let mut stem = String::new();
+    for c in title.chars() {
+        if c.is_whitespace() {
+            if !stem.is_empty() && !stem.ends_with(' ') {
+                stem.push(' ');
+            }
+        } else if c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') {
+            stem.push('_');
+        } else {
+            stem.push(c);
+        }
+    }
+    let mut stem: String = stem.chars().take(MAX_FILE_STEM).collect();
+    // Windows drops trailing dots and spaces, and hidden files start with a dot
+    stem = stem.trim_matches(|c| c == '.' || c == ' ').to_string();
+    if stem.is_empty() {
+        stem = url.host_str().filter(|host| !host.is_empty()).unwrap_or("page").to_string();
+    }
+    let device = stem.split('.').next().unwrap_or_default().to_ascii_uppercase();
+    let numbered_device = |prefix: &str| {
+        device.strip_prefix(prefix).is_some_and(|n| n.len() == 1 && n.as_bytes()[0].is_ascii_digit())
+    };
+    let reserved = matches!(device.as_str(), "CON" | "PRN" | "AUX" | "NUL");
+    if reserved || numbered_device("COM") || numbered_device("LPT") {
+        stem.push('_');
+    }
+    format!("{}.{}", stem, extension)
+}
+
+/// Every URL attribute of the start tags in `html`, in document order
+fn url_attributes(html: &str) -> Vec<UrlAttribute> {
+    let bytes = html.as_bytes();
+    let find = |from: usize, needle: &str| html[from..].find(needle).map(|at| from + at);
+    let mut attributes = Vec::new();
+    let mut at = 0;
+    while let Some(open) = find(at, "<") {
+        at = open + 1;
+        if html[at..].starts_with("!--") {
+            at = find(at + 3, "-->").map_or(html.len(), |end| end + 3);
+            continue;
+        }
+        if !bytes.get(at).is_some_and(|b| b.is_ascii_alphabetic()) {
+            // End tags, doctypes and stray less-than signs
+            continue;
+        }
+
+        let name_end = html[at..]
+            .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+            .map_or(html.len(), |end| at + end);
+        let tag = html[at..name_end].to_ascii_lowercase();
+        at = name_end;
+
+        loop {
+            while bytes.get(at).is_some_and(|b| b.is_ascii_whitespace() || *b == b'/') {
+                at += 1;
+            }
+            if at >= bytes.len() || bytes[at] == b'>' {
+                at = (at + 1).min(bytes.len());
+                break;
+            }
+            let name_start = at;
+            while bytes.get(at).is_some_and(|b| !b.is_ascii_whitespace() && !b"/>=".contains(b)) {
+                at += 1;
+            }
+            let name = html[name_start..at].to_ascii_lowercase();
+            while bytes.get(at).is_some_and(|b| b.is_ascii_whitespace()) {
+                at += 1;
+            }
+            if bytes.get(at) != Some(&b'=') {
+                continue;
+            }
+            at += 1;
+            while bytes.get(at).is_some_and(|b| b.is_ascii_whitespace()) {
+                at += 1;
+            }
+
+            let value_start = at;
+            let (value, value_end) = match bytes.get(at) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let end = html[at + 1..].find(quote as char).map_or(html.len(), |end| at + 1 + end);
+                    (&html[at + 1..end], (end + 1).min(html.len()))
+                }
+                _ => {
+                    while bytes.get(at).is_some_and(|b| !b.is_ascii_whitespace() && *b != b'>') {
+                        at += 1;
+                    }
+                    (&html[value_start..at], at)
+                }
+            };
+            at = value_end;
+            if URL_ATTRIBUTES.contains(&name.as_str()) {
+                attributes.push(UrlAttribute {
+                    tag: tag.clone(),
+                    value: decode_entities(value),
+                    span: value_start..value_end,
+                });
+            }
+        }
+
+        // Markup-looking text inside scripts and styles is not markup
+        if RAW_TEXT_ELEMENTS.contains(&tag.as_str()) {
+            let close = format!("</{}", tag);
+            at = html[at..]
+                .to_ascii_lowercase()
+                .find(&close)
+                .map_or(html.len(), |end| at + end);
+        }
+    }
+    attributes
+}
+
+/// Decode the character references URLs commonly carry in attributes
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Downloads of this session: those of ordinary tabs go to `stored`, those of
+/// private tabs are kept in memory only and forgotten on exit
+pub struct SessionDownloads {
+    stored: Arc<dyn DownloadRepository>,
+    private: RwLock<Vec<Download>>,
+    /// Private downloads count down from -1 so their ids never meet stored ones
+    next_private_id: AtomicI64,
+}
+
+impl SessionDownloads {
+    pub fn new(stored: Arc<dyn DownloadRepository>) -> Self {
+        Self {
+            stored,
+            private: RwLock::new(Vec::new()),
+            next_private_id: AtomicI64::new(-1),
+        }
+    }
+
+    /// Record a finished download, in memory only if it came from a private tab
+    pub async fn record(&self, download: NewDownload, context: RequestContext) -> Result<Download, StorageError> {
+        if !context.private {
+            return PersistenceGuard::new(self.stored.clone(), context).save(download).await;
+        }
+        let download = Download {
+            id: self.next_private_id.fetch_sub(1, Ordering::SeqCst),
+            url: download.url,
+            path: download.path,
+            size: download.size,
+            created_at: Utc::now(),
+        };
+        self.private
+            .write()
+            .map_err(|_| StorageError::Database("Downloads lock poisoned".to_string()))?
+            .push(download.clone());
+        Ok(download)
+    }
+}
+
+#[async_trait]
+impl DownloadRepository for SessionDownloads {
+    async fn save(&self, download: NewDownload) -> Result<Download, StorageError> {
+        self.record(download, RequestContext::user()).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Download>, StorageError> {
+        let mut downloads = self.stored.find_all().await?;
+        if let Ok(private) = self.private.read() {
+            downloads.extend(private.iter().cloned());
+        }
+        downloads.sort_by_key(|download| std::cmp::Reverse(download.created_at));
+        Ok(downloads)
+    }
+
+    async fn delete(&self, id: i64) -> Result<(), StorageError> {
+        if id >= 0 {
+            return self.stored.delete(id).await;
+        }
+        if let Ok(mut private) = self.private.write() {
+            private.retain(|download| download.id != id);
+        }
+        Ok(())
+    }
+
+    async fn restore(&self, download: &Download) -> Result<(), StorageError> {
+        if download.id >= 0 {
+            return self.stored.restore(download).await;
+        }
+        self.private
+            .write()
+            .map_err(|_| StorageError::Database("Downloads lock poisoned".to_string()))?
+            .push(download.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::testing::InMemoryDownloadRepository;
+    use std::path::PathBuf;
+
+    fn url(text: &str) -> ValidatedUrl {
+        ValidatedUrl::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_relative_links_become_absolute() {
+        let page = url("https://example.com/docs/guide/intro.html");
+        let html = r##"<a href="next.html">Next</a> <img src='../logo.png' alt=x>
+<a href=/about>About</a> <a HREF="https://other.org/">Other</a> <a href="#top">Top</a>
+<form action="search?q=a&amp;lang=en"></form> <a href="mailto:me@example.com">Mail</a>"##;
+
+        let saved = absolutize_links(html, &page);
+        assert!(saved.contains(r#"<a href="https://example.com/docs/guide/next.html">"#));
+        assert!(saved.contains(r#"<img src="https://example.com/docs/logo.png" alt=x>"#));
+        assert!(saved.contains(r#"<a href="https://example.com/about">"#));
+        assert!(saved.contains(r#"<a HREF="https://other.org/">"#));
+        assert!(saved.contains(r##"<a href="#top">"##));
+        assert!(saved.contains(r#"action="https://example.com/docs/guide/search?q=a&amp;lang=en""#));
+        assert!(saved.contains(r#"href="mailto:me@example.com""#));
+    }
+
+    #[test]
+    fn test_links_resolve_against_base_tag() {
+        let page = url("https://example.com/docs/page.html");
+        let html = r#"<html><head><base href="/static/v2/"><base href="/ignored/">
+<link rel=stylesheet href="site.css"></head>
+<body><a href="guide/">Guide</a><img src="//cdn.example.net/img.png"></body></html>"#;
+
+        let saved = absolutize_links(html, &page);
+        // The base itself is made absolute; only the first one counts
+        assert!(saved.contains(r#"<base href="https://example.com/static/v2/">"#));
+        assert!(saved.contains(r#"href="https://example.com/static/v2/site.css""#));
+        assert!(saved.contains(r#"<a href="https://example.com/static/v2/guide/">"#));
+        // Protocol-relative URLs take the page's scheme
+        assert!(saved.contains(r#"<img src="https://cdn.example.net/img.png">"#));
+
+        let relative_base = absolutize_links(r#"<base href="../"><a href="x">"#, &page);
+        assert!(relative_base.contains(r#"<a href="https://example.com/x">"#));
+        let script = r#"<script src="//cdn.example.net/app.js"></script>"#;
+        let plain = absolutize_links(script, &url("http://example.com/"));
+        assert!(plain.contains(r#"src="http://cdn.example.net/app.js""#));
+    }
+
+    #[test]
+    fn test_comments_and_scripts_are_left_alone() {
+        let page = url("https://example.com/");
+        let html = r#"<!-- <a href="old.html"> --><script>document.write('<a href="x.html">')</script>
+<style>a[href="y.html"] {}</style><p>1 < 2 and <a href='z.html'>z</a>"#;
+
+        let saved = absolutize_links(html, &page);
+        assert!(saved.contains(r#"<!-- <a href="old.html"> -->"#));
+        assert!(saved.contains(r#"'<a href="x.html">'"#));
+        assert!(saved.contains(r#"a[href="y.html"]"#));
+        assert!(saved.contains(r#"<a href="https://example.com/z.html">z</a>"#));
+    }
+
+    #[test]
+    fn test_saved_html_notes_its_source() {
+        let page = url("https://example.com/a--b/");
+        let saved_at = DateTime::parse_from_rfc3339("2024-05-06T07:08:09Z").unwrap().with_timezone(&Utc);
+        let saved = saved_html("<a href=c>", &page, saved_at);
+        assert_eq!(
+            saved,
+            "<!-- saved from https://example.com/a-%2Db/ on 2024-05-06 07:08 UTC -->\n\
+             <a href=\"https://example.com/a--b/c\">"
+        );
+    }
+
+    #[test]
+    fn test_page_file_names_are_sanitized() {
+        let page = url("https://example.com/");
+        assert_eq!(page_file_name("Rust: A <Guide>", &page, "html"), "Rust_ A _Guide_.html");
+        assert_eq!(page_file_name("  a\n\tb  ", &page, "txt"), "a b.txt");
+        assert_eq!(page_file_name("../..", &page, "html"), "_.html");
+        assert_eq!(page_file_name("...", &page, "html"), "example.com.html");
+        assert_eq!(page_file_name("", &page, "txt"), "example.com.txt");
+        assert_eq!(page_file_name("con", &page, "txt"), "con_.txt");
+        assert_eq!(page_file_name("COM1.txt", &page, "txt"), "COM1.txt_.txt");
+        assert_eq!(page_file_name(&"x".repeat(300), &page, "html").len(), MAX_FILE_STEM + 5);
+    }
+
+    #[tokio::test]
+    async fn test_private_downloads_stay_in_memory() {
+        let stored = Arc::new(InMemoryDownloadRepository::new());
+        let downloads = SessionDownloads::new(stored.clone());
+        let download = |name: &str| NewDownload {
+            url: url("https://example.com/"),
+            path: PathBuf::from(name),
+            size: 1,
+        };
+
+        let kept = downloads.record(download("kept.html"), RequestContext::user()).await.unwrap();
+        let private = downloads.record(download("private.html"), RequestContext::new(None, true)).await.unwrap();
+        assert!(private.id < 0);
+        assert_eq!(stored.find_all().await.unwrap(), vec![kept.clone()]);
+        assert_eq!(downloads.find_all().await.unwrap(), vec![private.clone(), kept.clone()]);
+
+        downloads.delete(private.id).await.unwrap();
+        assert_eq!(downloads.find_all().await.unwrap(), vec![kept]);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::domain::{
+    CertificateImportError, DownloadError, ImportError, NetworkError, RenderError, SearchEngineError, SecurityError,
+    SiteMetadataError, StorageError, TabId, UpdateError, UserScriptError, UserStyleError,
+};
+use thiserror::Error;
+
+/// Why a browser operation failed, for front ends to act on
+///
+/// Security and network failures keep their own variant wherever they
+/// happened, so an error page can tell a blocked site from a timeout without
+/// looking inside rendering errors.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum NavigatorError {
+    #[error(transparent)]
+    Security(#[from] SecurityError),
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Import(#[from] ImportError),
+    #[error(transparent)]
+    UserStyle(#[from] UserStyleError),
+    #[error(transparent)]
+    UserScript(#[from] UserScriptError),
+    #[error(transparent)]
+    SearchEngine(#[from] SearchEngineError),
+    #[error(transparent)]
+    Download(#[from] DownloadError),
+    #[error(transparent)]
+    Certificate(#[from] CertificateImportError),
+    #[error(transparent)]
+    Update(#[from] UpdateError),
+    #[error(transparent)]
+    SiteMetadata(#[from] SiteMetadataError),
+    /// The page was fetched but could not be shown
+    #[error(transparent)]
+    Render(RenderError),
+    #[error("Tab {0} not found")]
+    TabNotFound(TabId),
+    #[error("Download {0} not found")]
+    DownloadNotFound(i64),
+    #[error("No download {0} is waiting for confirmation")]
+    PendingDownloadNotFound(u64),
+    #[error("No download {0} is being saved or failed")]
+    UnfinishedDownloadNotFound(u64),
+    #[error("The tab has no page to bookmark")]
+    NothingToBookmark,
+    #[error("Bookmark {0} not found")]
+    BookmarkNotFound(i64),
+    #[error("The tab has no page to save")]
+    NothingToSave,
+    /// The front end gave the controller nowhere to write files
+    #[error("Saving pages is not available")]
+    SavingUnavailable,
+    /// The front end gave the controller no printer
+    #[error("Printing is not available")]
+    PrintingUnavailable,
+    /// The front end gave the controller no way to start other programs
+    #[error("Links cannot be opened in other programs")]
+    LaunchingUnavailable,
+    #[error("No link {0} is waiting to be opened")]
+    PendingLaunchNotFound(u64),
+    /// The system's handler for a URL could not be started; holds the URL
+    /// and the reason
+    #[error("Could not open {0}: {1}")]
+    Launch(String, String),
+    /// The front end gave the controller no trust store to add roots to
+    #[error("Certificate authorities cannot be added")]
+    CertificatesUnavailable,
+    #[error("No update is available")]
+    NoUpdateAvailable,
+    /// The front end gave the controller nothing to fetch robots.txt and
+    /// sitemaps with
+    #[error("Robots.txt and sitemaps cannot be read")]
+    SiteMetadataUnavailable,
+    #[error("There is no setting {0}")]
+    UnknownSetting(String),
+    /// A value a setting cannot take, with the reason
+    #[error("Invalid value for {label}: {reason}")]
+    InvalidSetting { label: String, reason: String },
+    /// Only web pages have an origin to keep settings for; holds the scheme
+    #[error("{0}: pages have no site settings")]
+    NoSiteSettings(String),
+    /// Only web pages have an origin to keep Storage for; holds the scheme
+    #[error("{0}: pages have no storage")]
+    NoStorage(String),
+    /// A profile document that is not valid JSON of the expected shape
+    #[error("Invalid profile document: {0}")]
+    InvalidProfile(String),
+    #[error("Unsupported profile version {0}")]
+    UnsupportedProfileVersion(u32),
+    #[error("Cannot write the security report: {0}")]
+    SecurityReport(String),
+    /// Navigations are refused once [`shutdown`] began
+    ///
+    /// [`shutdown`]: super::BrowserController::shutdown
+    #[error("The browser is shutting down")]
+    ShuttingDown,
+}
+
+/// Why a keyboard shortcut cannot be set
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum KeymapError {
+    #[error("\"{0}\" is not a key combination such as Ctrl+Shift+T")]
+    InvalidChord(String),
+    /// The chord already runs another command, described
+    #[error("{chord} is already the shortcut for \"{command}\"")]
+    Conflict { chord: String, command: String },
+}
+
+impl From<RenderError> for NavigatorError {
+    fn from(error: RenderError) -> Self {
+        match error {
+            RenderError::Security(e) => Self::Security(e),
+            RenderError::Network(e) => Self::Network(e),
+            other => Self::Render(other),
+        }
+    }
+}
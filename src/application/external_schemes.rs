@@ -0,0 +1,133 @@
+use crate::domain::ValidatedUrl;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Schemes whose links open in another program without asking, one per
+/// line; added to by answering a prompt with "always open"
+pub const ALWAYS_OPEN_SCHEMES_SETTING: &str = "security.external_schemes.always_open";
+
+/// Launches kept waiting for an answer; asking again for more drops the
+/// oldest, whose prompts are long gone
+const MAX_PENDING_LAUNCHES: usize = 16;
+
+/// A URL for another program, held until the user answers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingLaunch {
+    pub id: u64,
+    pub url: ValidatedUrl,
+}
+
+/// What became of a URL handed to another program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalLaunch {
+    /// The scheme is always opened, and the URL went to its handler
+    Launched(ValidatedUrl),
+    /// The URL waits for the user to confirm opening it
+    PendingConfirmation(PendingLaunch),
+}
+
+/// The user's answer to opening a URL in another program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaunchAnswer {
+    Open,
+    /// Open this URL, and later ones of its scheme without asking
+    AlwaysOpen,
+    Cancel,
+}
+
+/// Schemes listed in the always open setting, lowercased
+pub fn parse_always_open_schemes(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|scheme| !scheme.is_empty())
+        .map(|scheme| scheme.trim_end_matches(':').to_ascii_lowercase())
+        .collect()
+}
+
+/// The always open setting with `scheme` added, if it is not listed yet
+pub fn with_always_open_scheme(value: Option<&str>, scheme: &str) -> String {
+    let mut schemes = value.map(parse_always_open_schemes).unwrap_or_default();
+    let scheme = scheme.to_ascii_lowercase();
+    if !schemes.contains(&scheme) {
+        schemes.push(scheme);
+    }
+    schemes.join("\n")
+}
+
+/// Holds URLs for other programs until the user says whether to open each.
+/// Nothing held is loaded by the browser or enters history.
+#[derive(Debug, Default)]
+pub struct ExternalSchemeHandler {
+    pending: RwLock<VecDeque<PendingLaunch>>,
+    next_id: AtomicU64,
+}
+
+impl ExternalSchemeHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold `url` until it is answered
+    pub fn hold(&self, url: ValidatedUrl) -> PendingLaunch {
+        let launch = PendingLaunch {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst) + 1,
+            url,
+        };
+        if let Ok(mut pending) = self.pending.write() {
+            if pending.len() == MAX_PENDING_LAUNCHES {
+                pending.pop_front();
+            }
+            pending.push_back(launch.clone());
+        }
+        launch
+    }
+
+    /// Launches waiting for an answer, oldest first
+    pub fn pending(&self) -> Vec<PendingLaunch> {
+        self.pending
+            .read()
+            .map(|pending| pending.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Let go of a held launch to act on the answer; each is answered once
+    pub fn take(&self, id: u64) -> Option<PendingLaunch> {
+        let mut pending = self.pending.write().ok()?;
+        let index = pending.iter().position(|launch| launch.id == id)?;
+        pending.remove(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_launches_are_answered_once() {
+        let handler = ExternalSchemeHandler::new();
+        let mail = handler.hold(ValidatedUrl::parse("mailto:a@example.com").unwrap());
+        let phone = handler.hold(ValidatedUrl::parse("tel:+15550100").unwrap());
+        assert_ne!(mail.id, phone.id);
+        assert_eq!(handler.pending(), [mail.clone(), phone.clone()]);
+
+        assert_eq!(handler.take(mail.id), Some(mail.clone()));
+        assert_eq!(handler.take(mail.id), None);
+        assert_eq!(handler.pending().len(), 1);
+        assert_eq!(handler.pending()[0], phone);
+
+        for _ in 0..MAX_PENDING_LAUNCHES {
+            handler.hold(ValidatedUrl::parse("tel:1").unwrap());
+        }
+        assert_eq!(handler.pending().len(), MAX_PENDING_LAUNCHES);
+        assert_eq!(handler.take(phone.id), None);
+    }
+
+    #[test]
+    fn test_always_open_setting() {
+        assert_eq!(parse_always_open_schemes(" mailto:\nTEL,,matrix "), ["mailto", "tel", "matrix"]);
+        assert_eq!(with_always_open_scheme(None, "MailTo"), "mailto");
+        assert_eq!(with_always_open_scheme(Some("mailto"), "tel"), "mailto\ntel");
+        assert_eq!(with_always_open_scheme(Some("mailto\ntel"), "tel"), "mailto\ntel");
+    }
+}
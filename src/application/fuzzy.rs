@@ -0,0 +1,169 @@
+/// Score of each query character found in the candidate
+const MATCH_SCORE: i32 = 16;
+/// Bonus for a character right after the previous one matched
+const CONSECUTIVE_BONUS: i32 = 24;
+/// Bonus for a character starting a word: the first, one after a space or
+/// punctuation, or an upper-case letter after a lower-case one
+const WORD_START_BONUS: i32 = 20;
+/// Penalty for each character skipped between two matched ones
+const GAP_PENALTY: i32 = 1;
+
+/// How well a query matched a candidate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyMatch {
+    /// Higher is better; only comparable between matches of one query
+    pub score: i32,
+    /// Indices of the matched characters of the candidate, in order
+    pub positions: Vec<usize>,
+}
+
+/// Text to match queries against, lowercased and scored once so that
+/// matching it again is cheap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzyCandidate {
+    lower: Vec<char>,
+    /// Score of matching each character, with its word-start bonus
+    bonus: Vec<i32>,
+}
+
+impl FuzzyCandidate {
+    pub fn new(text: &str) -> Self {
+        let original: Vec<char> = text.chars().collect();
+        let lower = original
+            .iter()
+            .map(|c| c.to_lowercase().next().unwrap_or(*c))
+            .collect();
+        let bonus = (0..original.len())
+            .map(|j| {
+                let word_start = match j.checked_sub(1).map(|previous| original[previous]) {
+                    None => true,
+                    Some(previous) => {
+                        !previous.is_alphanumeric() || (previous.is_lowercase() && original[j].is_uppercase())
+                    }
+                };
+                MATCH_SCORE + if word_start { WORD_START_BONUS } else { 0 }
+            })
+            .collect();
+        Self { lower, bonus }
+    }
+
+    /// Match a query against the text; see [`fuzzy_match`]
+    pub fn matches(&self, query: &str) -> Option<FuzzyMatch> {
+        let query: Vec<char> = query
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .flat_map(char::to_lowercase)
+            .collect();
+        if query.is_empty() {
+            return Some(FuzzyMatch {
+                score: 0,
+                positions: Vec::new(),
+            });
+        }
+        // Most candidates fail; find out before scoring
+        let mut rest = self.lower.iter();
+        if !query.iter().all(|c| rest.any(|candidate| candidate == c)) {
+            return None;
+        }
+        let (lower, bonus) = (&self.lower, &self.bonus);
+
+        // best[i][j]: best score with query[i] matched at candidate[j], and
+        // from[i][j] where query[i - 1] was matched for it
+        let mut best = vec![vec![None; lower.len()]; query.len()];
+        let mut from = vec![vec![0; lower.len()]; query.len()];
+        for (j, c) in lower.iter().enumerate() {
+            if *c == query[0] {
+                best[0][j] = Some(bonus[j]);
+            }
+        }
+        for i in 1..query.len() {
+            // Best of best[i - 1][k] + k over k < j - 1, so that taking off j
+            // gives the score less the gap
+            let mut before: Option<(i32, usize)> = None;
+            for j in 1..lower.len() {
+                if j >= 2 {
+                    if let Some(score) = best[i - 1][j - 2] {
+                        let candidate = (score + (j - 2) as i32 * GAP_PENALTY, j - 2);
+                        if before.is_none_or(|(current, _)| candidate.0 > current) {
+                            before = Some(candidate);
+                        }
+                    }
+                }
+                if lower[j] != query[i] {
+                    continue;
+                }
+                let consecutive = best[i - 1][j - 1].map(|score| (score + CONSECUTIVE_BONUS, j - 1));
+                let gapped = before.map(|(score, k)| (score - (j - 1) as i32 * GAP_PENALTY, k));
+                let chosen = match (consecutive, gapped) {
+                    (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                    (a, b) => a.or(b),
+                };
+                if let Some((score, k)) = chosen {
+                    best[i][j] = Some(score + bonus[j]);
+                    from[i][j] = k;
+                }
+            }
+        }
+
+        let last = query.len() - 1;
+        let (mut j, score) = best[last]
+            .iter()
+            .enumerate()
+            .filter_map(|(j, score)| score.map(|score| (j, score)))
+            .max_by_key(|(j, score)| (*score, std::cmp::Reverse(*j)))?;
+        let mut positions = vec![0; query.len()];
+        for i in (0..query.len()).rev() {
+            positions[i] = j;
+            j = from[i][j];
+        }
+        Some(FuzzyMatch { score, positions })
+    }
+}
+
+/// Match `query` against `candidate` as a subsequence, ignoring case and
+/// the spaces in the query. Runs of consecutive characters and characters
+/// starting words score higher, and gaps between them lower; of all the
+/// ways the query fits, the best scoring one is kept. `None` if some query
+/// character is not found in order.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<FuzzyMatch> {
+    FuzzyCandidate::new(candidate).matches(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, candidate: &str) -> i32 {
+        fuzzy_match(query, candidate).unwrap().score
+    }
+
+    #[test]
+    fn test_query_must_be_a_subsequence() {
+        assert_eq!(fuzzy_match("nw", "New window").unwrap().positions, vec![0, 4]);
+        assert!(fuzzy_match("wn", "New").is_none());
+        assert!(fuzzy_match("newer", "New").is_none());
+        assert_eq!(fuzzy_match("", "anything").unwrap().score, 0);
+        assert_eq!(fuzzy_match("NEW WIN", "Open a new window").unwrap().positions, vec![7, 8, 9, 11, 12, 13]);
+    }
+
+    #[test]
+    fn test_runs_and_word_starts_score_higher() {
+        // A run beats the same letters scattered
+        assert!(score("hist", "Show history") > score("hist", "Highest listing"));
+        // Word starts beat letters inside words
+        assert!(score("sp", "Save page") > score("sp", "Inspect"));
+        assert!(score("pp", "PrintPage") > score("pp", "Approve"));
+        // Of the ways the query fits, the best is found
+        assert_eq!(fuzzy_match("sp", "Use spaces").unwrap().positions, vec![4, 5]);
+        // Tighter matches beat looser ones
+        assert!(score("rl", "Reload") > score("rl", "Reopen the closed window list"));
+    }
+
+    #[test]
+    fn test_one_candidate_matches_many_queries() {
+        let candidate = FuzzyCandidate::new("Show Keyboard Shortcuts");
+        assert_eq!(candidate.matches("sks").unwrap().positions, vec![0, 5, 14]);
+        assert_eq!(candidate.matches("KEY").unwrap().positions, vec![5, 6, 7]);
+        assert!(candidate.matches("shortcutss").is_none());
+    }
+}
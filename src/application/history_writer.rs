@@ -0,0 +1,297 @@
+use crate::domain::{HistoryRepository, NewHistoryEntry, RequestContext};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Tuning for [`HistoryWriter`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryWriterConfig {
+    /// Visits held in memory before back-pressure kicks in
+    pub capacity: usize,
+    /// Pending visits that trigger a flush before the interval elapses
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+}
+
+impl Default for HistoryWriterConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            batch_size: 20,
+            flush_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Bounded queue of visits waiting to be written
+#[derive(Debug, Default)]
+struct PendingVisits {
+    entries: VecDeque<NewHistoryEntry>,
+    closed: bool,
+}
+
+impl PendingVisits {
+    /// Queue a visit; when full, the oldest visit to a URL that is visited
+    /// again later is folded into that later visit, and only if there is
+    /// none is the oldest visit dropped
+    fn push(&mut self, mut entry: NewHistoryEntry, capacity: usize) {
+        if self.entries.len() >= capacity.max(1) {
+            self.make_room(&mut entry);
+        }
+        self.entries.push_back(entry);
+    }
+
+    fn make_room(&mut self, incoming: &mut NewHistoryEntry) {
+        let duplicate = (0..self.entries.len()).find_map(|index| {
+            let url = &self.entries[index].url;
+            match self.entries.iter().skip(index + 1).position(|later| &later.url == url) {
+                Some(offset) => Some((index, Some(index + offset))),
+                None => (url == &incoming.url).then_some((index, None)),
+            }
+        });
+
+        let Some((index, later)) = duplicate else {
+            if let Some(dropped) = self.entries.pop_front() {
+                tracing::warn!("History queue full, dropping visit to {}", dropped.url);
+            }
+            return;
+        };
+
+        // Indices after `index` shift down by one once it is removed
+        if let Some(dropped) = self.entries.remove(index) {
+            match later {
+                Some(later) => self.entries[later].visit_count += dropped.visit_count,
+                None => incoming.visit_count += dropped.visit_count,
+            }
+        }
+    }
+
+    fn take(&mut self, limit: usize) -> Vec<NewHistoryEntry> {
+        let count = self.entries.len().min(limit.max(1));
+        self.entries.drain(..count).collect()
+    }
+}
+
+struct Shared {
+    pending: Mutex<PendingVisits>,
+    wake: Notify,
+    config: HistoryWriterConfig,
+}
+
+/// Background writer that batches history visits
+///
+/// Visits are queued without waiting on the disk and written in a single
+/// transaction every `flush_interval` or `batch_size` visits, whichever
+/// comes first. [`HistoryWriter::shutdown`] flushes whatever is left.
+#[derive(Clone)]
+pub struct HistoryWriter {
+    shared: Arc<Shared>,
+    repository: Arc<dyn HistoryRepository>,
+    task: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl HistoryWriter {
+    /// Spawn the writer on the current Tokio runtime
+    pub fn spawn(repository: Arc<dyn HistoryRepository>) -> Self {
+        Self::with_config(repository, HistoryWriterConfig::default())
+    }
+
+    pub fn with_config(repository: Arc<dyn HistoryRepository>, config: HistoryWriterConfig) -> Self {
+        let shared = Arc::new(Shared {
+            pending: Mutex::new(PendingVisits::default()),
+            wake: Notify::new(),
+            config,
+        });
+        let task = tokio::spawn(run(shared.clone(), repository.clone()));
+
+        Self {
+            shared,
+            repository,
+            task: Arc::new(Mutex::new(Some(task))),
+        }
+    }
+
+    /// Queue a visit, unless it is for a private tab; never waits on the disk
+    pub fn record(&self, context: RequestContext, entry: NewHistoryEntry) {
+        if !context.permits("a visit") {
+            return;
+        }
+        let Ok(mut pending) = self.shared.pending.lock() else {
+            return;
+        };
+        if pending.closed {
+            tracing::warn!("History writer closed, dropping visit to {}", entry.url);
+            return;
+        }
+
+        pending.push(entry, self.shared.config.capacity);
+        if pending.entries.len() >= self.shared.config.batch_size {
+            self.shared.wake.notify_one();
+        }
+    }
+
+    /// Visits queued but not yet written
+    pub fn pending_count(&self) -> usize {
+        self.shared
+            .pending
+            .lock()
+            .map(|pending| pending.entries.len())
+            .unwrap_or(0)
+    }
+
+    /// Write every queued visit now, without stopping the writer
+    pub async fn flush(&self) {
+        let batch = match self.shared.pending.lock() {
+            Ok(mut pending) => pending.entries.drain(..).collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        if batch.is_empty() {
+            return;
+        }
+        if let Err(e) = self.repository.add_batch(&batch).await {
+            tracing::warn!("Failed to write {} history visits: {}", batch.len(), e);
+        }
+    }
+
+    /// Write every queued visit and stop the writer
+    pub async fn shutdown(&self) {
+        if let Ok(mut pending) = self.shared.pending.lock() {
+            pending.closed = true;
+        }
+        self.shared.wake.notify_one();
+
+        let task = self.task.lock().ok().and_then(|mut task| task.take());
+        if let Some(task) = task {
+            if let Err(e) = task.await {
+                tracing::error!("History writer failed: {}", e);
+            }
+        }
+    }
+}
+
+async fn run(shared: Arc<Shared>, repository: Arc<dyn HistoryRepository>) {
+    let config = shared.config;
+    let mut interval = tokio::time::interval(config.flush_interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        let timed_out = tokio::select! {
+            _ = interval.tick() => true,
+            _ = shared.wake.notified() => false,
+        };
+
+        loop {
+            let (batch, closed) = match shared.pending.lock() {
+                Ok(mut pending) => {
+                    let ready = timed_out || pending.closed || pending.entries.len() >= config.batch_size;
+                    let batch = if ready { pending.take(config.batch_size) } else { Vec::new() };
+                    (batch, pending.closed)
+                }
+                Err(_) => return,
+            };
+
+            if batch.is_empty() {
+                if closed {
+                    return;
+                }
+                break;
+            }
+
+            if let Err(e) = repository.add_batch(&batch).await {
+                tracing::warn!("Failed to write {} history visits: {}", batch.len(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ValidatedUrl;
+    use crate::infrastructure::SqliteDatabase;
+
+    fn visit(url: &str) -> NewHistoryEntry {
+        NewHistoryEntry::new(ValidatedUrl::parse(url).unwrap(), url.to_string())
+    }
+
+    const URLS: [&str; 5] = [
+        "https://example.com/",
+        "https://example.org/a",
+        "https://example.com/",
+        "https://example.net/",
+        "https://example.org/a",
+    ];
+
+    async fn visit_counts(db: &SqliteDatabase) -> Vec<(String, i32)> {
+        let mut counts: Vec<_> = db
+            .get_recent(100)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.url.as_str().to_string(), entry.visit_count))
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    #[tokio::test]
+    async fn test_batched_writes_match_direct_writes() {
+        let direct = SqliteDatabase::new(":memory:").await.unwrap();
+        for _ in 0..9 {
+            for url in URLS {
+                direct.add(visit(url)).await.unwrap();
+            }
+        }
+
+        let batched = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+        let writer = HistoryWriter::spawn(batched.clone());
+        for _ in 0..9 {
+            for url in URLS {
+                writer.record(RequestContext::user(), visit(url));
+            }
+        }
+        writer.shutdown().await;
+
+        assert_eq!(writer.pending_count(), 0);
+        assert_eq!(visit_counts(&batched).await, visit_counts(&direct).await);
+    }
+
+    #[tokio::test]
+    async fn test_back_pressure_folds_duplicates() {
+        let direct = SqliteDatabase::new(":memory:").await.unwrap();
+        for url in URLS {
+            direct.add(visit(url)).await.unwrap();
+        }
+
+        // A long interval keeps everything queued until shutdown
+        let batched = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+        let writer = HistoryWriter::with_config(
+            batched.clone(),
+            HistoryWriterConfig {
+                capacity: 3,
+                batch_size: 100,
+                flush_interval: Duration::from_secs(3600),
+            },
+        );
+        for url in URLS {
+            writer.record(RequestContext::user(), visit(url));
+        }
+        assert!(writer.pending_count() <= 3);
+        writer.shutdown().await;
+
+        assert_eq!(visit_counts(&batched).await, visit_counts(&direct).await);
+    }
+
+    #[test]
+    fn test_full_queue_without_duplicates_drops_oldest() {
+        let mut pending = PendingVisits::default();
+        for url in ["https://a.example/", "https://b.example/", "https://c.example/"] {
+            pending.push(visit(url), 2);
+        }
+
+        let urls: Vec<_> = pending.entries.iter().map(|entry| entry.url.as_str().to_string()).collect();
+        assert_eq!(urls, ["https://b.example/", "https://c.example/"]);
+    }
+}
@@ -0,0 +1,723 @@
+use super::downloads::{SaveFormat, DOWNLOADS_PAGE};
+use super::error::KeymapError;
+use crate::domain::{EditableSetting, SettingKind};
+use std::fmt;
+use std::str::FromStr;
+
+/// Browser page listing every command with its shortcuts
+pub const SHORTCUTS_PAGE: &str = "navigator://shortcuts";
+
+/// Keys named rather than typed, as written in a chord
+const NAMED_KEYS: &[&str] = &[
+    "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11", "F12", "Left", "Right", "Up", "Down",
+    "Home", "End", "PageUp", "PageDown", "Enter", "Escape", "Tab", "Space", "Backspace", "Delete", "Insert",
+];
+
+/// Modifier keys held for a chord
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    /// The Windows, Command or Super key
+    pub logo: bool,
+}
+
+/// A key pressed with modifiers, written like `Ctrl+Shift+T`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub modifiers: Modifiers,
+    /// A typed character in upper case, or one of the named keys such as
+    /// `F5` or `Left`
+    pub key: String,
+}
+
+impl Chord {
+    /// `None` if `key` is neither a single character nor a named key
+    pub fn new(modifiers: Modifiers, key: &str) -> Option<Self> {
+        let mut chars = key.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(c), None) if !c.is_whitespace() && !c.is_control() => c.to_uppercase().collect(),
+            _ => NAMED_KEYS.iter().find(|name| name.eq_ignore_ascii_case(key))?.to_string(),
+        };
+        Some(Self { modifiers, key })
+    }
+
+    /// Whether no Ctrl, Alt or logo key is held, so the keys could be
+    /// typing meant for the page or the address bar
+    pub fn is_plain(&self) -> bool {
+        !(self.modifiers.ctrl || self.modifiers.alt || self.modifiers.logo)
+    }
+
+    /// Whether the key is one of F1 to F12, never typed as text
+    pub fn is_function_key(&self) -> bool {
+        self.key.len() > 1 && self.key.starts_with('F')
+    }
+}
+
+impl FromStr for Chord {
+    type Err = KeymapError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || KeymapError::InvalidChord(text.trim().to_string());
+        let text = text.trim();
+        // The key itself may be a plus, as in Ctrl++
+        let (held, key) = match text.strip_suffix("++") {
+            Some(held) => (held, "+"),
+            None if text == "+" => ("", "+"),
+            None => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+        let mut modifiers = Modifiers::default();
+        for modifier in held.split('+').map(str::trim).filter(|modifier| !modifier.is_empty()) {
+            let flag = match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut modifiers.ctrl,
+                "shift" => &mut modifiers.shift,
+                "alt" | "option" => &mut modifiers.alt,
+                "super" | "cmd" | "command" | "meta" | "win" => &mut modifiers.logo,
+                _ => return Err(invalid()),
+            };
+            *flag = true;
+        }
+        Chord::new(modifiers, key.trim()).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let held = [
+            (self.modifiers.ctrl, "Ctrl+"),
+            (self.modifiers.alt, "Alt+"),
+            (self.modifiers.shift, "Shift+"),
+            (self.modifiers.logo, "Super+"),
+        ];
+        for (_, name) in held.iter().filter(|(down, _)| *down) {
+            f.write_str(name)?;
+        }
+        f.write_str(&self.key)
+    }
+}
+
+/// Chords separated by commas, as the keybinding settings store them;
+/// empty or `none` for no shortcut
+pub fn parse_chords(text: &str) -> Result<Vec<Chord>, KeymapError> {
+    if text.trim().eq_ignore_ascii_case("none") {
+        return Ok(Vec::new());
+    }
+    let mut chords: Vec<Chord> = Vec::new();
+    for part in text.split(',').filter(|part| !part.trim().is_empty()) {
+        let chord = part.parse()?;
+        if !chords.contains(&chord) {
+            chords.push(chord);
+        }
+    }
+    Ok(chords)
+}
+
+/// `chords` written as the keybinding settings store them
+pub fn format_chords(chords: &[Chord]) -> String {
+    chords.iter().map(Chord::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Everything a keyboard shortcut can do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Back,
+    Forward,
+    GoUp,
+    GoToRoot,
+    Reload,
+    FocusAddressBar,
+    CommandPalette,
+    LinkHints,
+    CaretBrowsing,
+    NewWindow,
+    ReopenClosedWindow,
+    TabOverview,
+    ZoomIn,
+    ZoomOut,
+    Copy,
+    Paste,
+    SelectAll,
+    Undo,
+    SavePage,
+    SavePageAsText,
+    SavePageAsMhtml,
+    PrintPage,
+    CopyScreenshot,
+    CopyFullPageScreenshot,
+    WrapPreformatted,
+    SiteSettings,
+    SecurityReport,
+    AddSearchEngine,
+    ToggleTheme,
+    DarkPages,
+    ToggleAccessibility,
+    ToggleOffline,
+    HitRegions,
+    ShowDownloads,
+    ShowBookmarks,
+    ShowHistory,
+    ShowSettings,
+    ShowShortcuts,
+    ShowConsole,
+    ShowLogs,
+    ShowScripts,
+}
+
+/// A command with where navigator://shortcuts lists it and the setting
+/// holding its shortcuts, labelled with what it does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandInfo {
+    pub command: Command,
+    pub section: &'static str,
+    pub setting: EditableSetting,
+}
+
+const fn command(
+    command: Command,
+    section: &'static str,
+    key: &'static str,
+    label: &'static str,
+    default: &'static str,
+) -> CommandInfo {
+    CommandInfo {
+        command,
+        section,
+        setting: EditableSetting {
+            key,
+            label,
+            kind: SettingKind::Text,
+            default,
+        },
+    }
+}
+
+/// Every command, in the order listed, with its default shortcuts
+pub const COMMANDS: &[CommandInfo] = &[
+    command(Command::Back, "Navigation", "keybindings.back", "Go back", "Alt+Left"),
+    command(Command::Forward, "Navigation", "keybindings.forward", "Go forward", "Alt+Right"),
+    command(Command::GoUp, "Navigation", "keybindings.go_up", "Go up a level of the address", "Alt+Up"),
+    command(Command::GoToRoot, "Navigation", "keybindings.go_to_root", "Go to the site's root", "Alt+Shift+Up"),
+    command(Command::Reload, "Navigation", "keybindings.reload", "Reload the page", "F5"),
+    command(
+        Command::FocusAddressBar,
+        "Navigation",
+        "keybindings.focus_address_bar",
+        "Type in the address bar",
+        "Ctrl+L, F6",
+    ),
+    command(
+        Command::CommandPalette,
+        "Navigation",
+        "keybindings.command_palette",
+        "Find a command, tab or page by name",
+        "Ctrl+Shift+P",
+    ),
+    command(Command::LinkHints, "Navigation", "keybindings.link_hints", "Follow a link by its letters", "F"),
+    command(
+        Command::CaretBrowsing,
+        "Navigation",
+        "keybindings.caret_browsing",
+        "Move through the page with a caret",
+        "F7",
+    ),
+    command(Command::NewWindow, "Windows", "keybindings.new_window", "Open a new window", "Ctrl+N"),
+    command(
+        Command::ReopenClosedWindow,
+        "Windows",
+        "keybindings.reopen_closed_window",
+        "Reopen the last closed window",
+        "Ctrl+Shift+N",
+    ),
+    command(Command::TabOverview, "Windows", "keybindings.tab_overview", "Show every tab", "Ctrl+Shift+A"),
+    command(Command::ZoomIn, "Zoom", "keybindings.zoom_in", "Larger browser interface", "Ctrl+Shift+="),
+    command(Command::ZoomOut, "Zoom", "keybindings.zoom_out", "Smaller browser interface", "Ctrl+Shift+-"),
+    command(Command::Copy, "Editing", "keybindings.copy", "Copy", "Ctrl+C"),
+    command(Command::Paste, "Editing", "keybindings.paste", "Paste", "Ctrl+V"),
+    command(Command::SelectAll, "Editing", "keybindings.select_all", "Select the whole page", "Ctrl+A"),
+    command(Command::Undo, "Editing", "keybindings.undo", "Undo the last deletion", "Ctrl+Z"),
+    command(Command::SavePage, "Page", "keybindings.save_page", "Save the page as HTML", "Ctrl+S"),
+    command(
+        Command::SavePageAsText,
+        "Page",
+        "keybindings.save_page_as_text",
+        "Save the page as text",
+        "Ctrl+Alt+S",
+    ),
+    command(
+        Command::SavePageAsMhtml,
+        "Page",
+        "keybindings.save_page_as_mhtml",
+        "Save the page with its images and stylesheets as MHTML",
+        "Ctrl+M",
+    ),
+    command(Command::PrintPage, "Page", "keybindings.print_page", "Print the page to a PDF", "Ctrl+P"),
+    command(
+        Command::CopyScreenshot,
+        "Page",
+        "keybindings.copy_screenshot",
+        "Copy the visible page as an image",
+        "Ctrl+Shift+S",
+    ),
+    command(
+        Command::CopyFullPageScreenshot,
+        "Page",
+        "keybindings.copy_full_page_screenshot",
+        "Copy the whole page as an image",
+        "Ctrl+Alt+Shift+S",
+    ),
+    command(
+        Command::WrapPreformatted,
+        "Page",
+        "keybindings.wrap_preformatted",
+        "Wrap long lines of preformatted text",
+        "Alt+Z",
+    ),
+    command(Command::SiteSettings, "Sites", "keybindings.site_settings", "Settings for the current site", "Ctrl+I"),
+    command(
+        Command::SecurityReport,
+        "Sites",
+        "keybindings.security_report",
+        "Save a security report on the page",
+        "Ctrl+Shift+I",
+    ),
+    command(
+        Command::AddSearchEngine,
+        "Sites",
+        "keybindings.add_search_engine",
+        "Add the page's search engine",
+        "Ctrl+Shift+K",
+    ),
+    command(
+        Command::ToggleTheme,
+        "Display",
+        "keybindings.toggle_theme",
+        "Switch between light and dark",
+        "Ctrl+Shift+D",
+    ),
+    command(
+        Command::DarkPages,
+        "Display",
+        "keybindings.dark_pages",
+        "Show light pages in dark colors",
+        "Ctrl+Alt+D",
+    ),
+    command(
+        Command::ToggleAccessibility,
+        "Display",
+        "keybindings.toggle_accessibility",
+        "Accessibility mode",
+        "Ctrl+Alt+A",
+    ),
+    command(Command::ToggleOffline, "Display", "keybindings.toggle_offline", "Offline mode", "Ctrl+Shift+O"),
+    command(
+        Command::HitRegions,
+        "Display",
+        "keybindings.hit_regions",
+        "Outline what the mouse can click, for debugging",
+        "Ctrl+Alt+Shift+H",
+    ),
+    command(Command::ShowDownloads, "Browser pages", "keybindings.show_downloads", "Show downloads", "Ctrl+J"),
+    command(
+        Command::ShowBookmarks,
+        "Browser pages",
+        "keybindings.show_bookmarks",
+        "Show bookmarks",
+        "Ctrl+Shift+B",
+    ),
+    command(Command::ShowHistory, "Browser pages", "keybindings.show_history", "Show history", "Ctrl+H"),
+    command(Command::ShowSettings, "Browser pages", "keybindings.show_settings", "Show settings", ""),
+    command(
+        Command::ShowShortcuts,
+        "Browser pages",
+        "keybindings.show_shortcuts",
+        "Show keyboard shortcuts",
+        "Ctrl+/",
+    ),
+    command(
+        Command::ShowConsole,
+        "Browser pages",
+        "keybindings.show_console",
+        "Show the page's console",
+        "Ctrl+Shift+J",
+    ),
+    command(Command::ShowLogs, "Browser pages", "keybindings.show_logs", "Show the browser's logs", ""),
+    command(Command::ShowScripts, "Browser pages", "keybindings.show_scripts", "Show user scripts", ""),
+];
+
+impl Command {
+    pub fn info(self) -> &'static CommandInfo {
+        COMMANDS
+            .iter()
+            .find(|info| info.command == self)
+            .expect("every command is listed")
+    }
+
+    /// Short name of the command, as its keybinding setting is keyed
+    pub fn name(self) -> &'static str {
+        let key = self.info().setting.key;
+        key.strip_prefix("keybindings.").unwrap_or(key)
+    }
+
+    /// What the command does, as listed on navigator://shortcuts
+    pub fn description(self) -> &'static str {
+        self.info().setting.label
+    }
+
+    /// The command whose shortcuts the setting stored under `key` holds
+    pub fn for_setting(key: &str) -> Option<Command> {
+        COMMANDS.iter().find(|info| info.setting.key == key).map(|info| info.command)
+    }
+
+    /// Carry out the command with `handler`
+    pub fn dispatch(self, handler: &mut dyn CommandHandler) {
+        match self {
+            Command::Back => handler.back(),
+            Command::Forward => handler.forward(),
+            Command::GoUp => handler.go_up(false),
+            Command::GoToRoot => handler.go_up(true),
+            Command::Reload => handler.reload(),
+            Command::FocusAddressBar => handler.focus_address_bar(),
+            Command::CommandPalette => handler.command_palette(),
+            Command::LinkHints => handler.link_hints(),
+            Command::CaretBrowsing => handler.caret_browsing(),
+            Command::NewWindow => handler.new_window(),
+            Command::ReopenClosedWindow => handler.reopen_closed_window(),
+            Command::TabOverview => handler.tab_overview(),
+            Command::ZoomIn => handler.zoom(true),
+            Command::ZoomOut => handler.zoom(false),
+            Command::Copy => handler.copy(),
+            Command::Paste => handler.paste(),
+            Command::SelectAll => handler.select_all(),
+            Command::Undo => handler.undo(),
+            Command::SavePage => handler.save_page(SaveFormat::Html),
+            Command::SavePageAsText => handler.save_page(SaveFormat::Text),
+            Command::SavePageAsMhtml => handler.save_page(SaveFormat::Mhtml),
+            Command::PrintPage => handler.print_page(),
+            Command::CopyScreenshot => handler.copy_screenshot(false),
+            Command::CopyFullPageScreenshot => handler.copy_screenshot(true),
+            Command::WrapPreformatted => handler.wrap_preformatted(),
+            Command::SiteSettings => handler.site_settings(),
+            Command::SecurityReport => handler.security_report(),
+            Command::AddSearchEngine => handler.add_search_engine(),
+            Command::ToggleTheme => handler.toggle_theme(),
+            Command::DarkPages => handler.dark_pages(),
+            Command::ToggleAccessibility => handler.toggle_accessibility(),
+            Command::ToggleOffline => handler.toggle_offline(),
+            Command::HitRegions => handler.hit_regions(),
+            Command::ShowDownloads => handler.show_page(DOWNLOADS_PAGE),
+            Command::ShowBookmarks => handler.show_page("navigator://bookmarks"),
+            Command::ShowHistory => handler.show_page("navigator://history"),
+            Command::ShowSettings => handler.show_page("navigator://settings"),
+            Command::ShowShortcuts => handler.show_page(SHORTCUTS_PAGE),
+            Command::ShowConsole => handler.show_page("navigator://console"),
+            Command::ShowLogs => handler.show_page("navigator://logs"),
+            Command::ShowScripts => handler.show_page("navigator://scripts"),
+        }
+    }
+}
+
+/// Carries out commands in the window they were typed in; the front end
+/// implements it
+pub trait CommandHandler {
+    fn back(&mut self);
+    fn forward(&mut self);
+    /// Go up a path level of the page's address, or to its site's root
+    fn go_up(&mut self, to_root: bool);
+    fn reload(&mut self);
+    fn focus_address_bar(&mut self);
+    fn command_palette(&mut self);
+    fn link_hints(&mut self);
+    fn caret_browsing(&mut self);
+    fn new_window(&mut self);
+    fn reopen_closed_window(&mut self);
+    fn tab_overview(&mut self);
+    /// Scale the browser interface up a step, or down if not `larger`
+    fn zoom(&mut self, larger: bool);
+    fn copy(&mut self);
+    fn paste(&mut self);
+    fn select_all(&mut self);
+    fn undo(&mut self);
+    fn save_page(&mut self, format: SaveFormat);
+    fn print_page(&mut self);
+    /// Copy the visible part of the page as an image, or all of it
+    fn copy_screenshot(&mut self, full_page: bool);
+    fn wrap_preformatted(&mut self);
+    fn site_settings(&mut self);
+    fn security_report(&mut self);
+    fn add_search_engine(&mut self);
+    fn toggle_theme(&mut self);
+    fn dark_pages(&mut self);
+    fn toggle_accessibility(&mut self);
+    fn toggle_offline(&mut self);
+    /// Show or hide the hit region overlay, where it is allowed
+    fn hit_regions(&mut self);
+    /// Open one of the navigator:// pages
+    fn show_page(&mut self, url: &str);
+}
+
+/// Which command each chord runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: Vec<(Chord, Command)>,
+}
+
+impl Keymap {
+    /// Every command bound to its default shortcuts
+    pub fn new() -> Self {
+        Self::from_bindings(COMMANDS.iter().map(|info| (info.command, info.setting.default)))
+    }
+
+    /// Each command bound to the chords written for it, as the keybinding
+    /// settings store them. Chords that do not parse, or that an earlier
+    /// command already has, are left out.
+    pub fn from_bindings<'a>(bindings: impl IntoIterator<Item = (Command, &'a str)>) -> Self {
+        let mut keymap = Self { bindings: Vec::new() };
+        for (command, chords) in bindings {
+            let chords = parse_chords(chords).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring the shortcuts of {:?}: {}", command, e);
+                Vec::new()
+            });
+            for chord in chords {
+                match keymap.command(&chord) {
+                    Some(other) => {
+                        tracing::warn!("{} is already the shortcut for {:?}, not {:?}", chord, other, command)
+                    }
+                    None => keymap.bindings.push((chord, command)),
+                }
+            }
+        }
+        keymap
+    }
+
+    /// The command `chord` runs, if any
+    pub fn command(&self, chord: &Chord) -> Option<Command> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| bound == chord)
+            .map(|(_, command)| *command)
+    }
+
+    /// The shortcuts of `command`, in the order given
+    pub fn chords(&self, command: Command) -> Vec<Chord> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == command)
+            .map(|(chord, _)| chord.clone())
+            .collect()
+    }
+
+    /// Give `command` exactly `chords`, unless one of them already runs
+    /// another command
+    pub fn bind(&mut self, command: Command, chords: Vec<Chord>) -> Result<(), KeymapError> {
+        for chord in &chords {
+            if let Some(other) = self.command(chord).filter(|other| *other != command) {
+                return Err(KeymapError::Conflict {
+                    chord: chord.to_string(),
+                    command: other.description().to_string(),
+                });
+            }
+        }
+        self.bindings.retain(|(_, bound)| *bound != command);
+        self.bindings.extend(chords.into_iter().map(|chord| (chord, command)));
+        Ok(())
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(text: &str) -> Chord {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_chords_are_parsed_and_written_canonically() {
+        let parsed = chord(" shift+ctrl+t ");
+        assert_eq!(
+            parsed.modifiers,
+            Modifiers {
+                ctrl: true,
+                shift: true,
+                ..Modifiers::default()
+            }
+        );
+        assert_eq!(parsed.key, "T");
+        assert_eq!(parsed.to_string(), "Ctrl+Shift+T");
+        assert!(!parsed.is_plain());
+
+        assert_eq!(chord("Ctrl++").key, "+");
+        assert_eq!(chord("alt+left").to_string(), "Alt+Left");
+        assert_eq!(chord("Cmd+f5").to_string(), "Super+F5");
+        assert!(chord("F").is_plain());
+        assert!(!chord("F").is_function_key());
+        assert!(chord("f11").is_function_key());
+        for invalid in ["", "Ctrl+", "Hyper+T", "Ctrl+Shift+Tee", "Ctrl+ "] {
+            assert_eq!(invalid.parse::<Chord>(), Err(KeymapError::InvalidChord(invalid.trim().to_string())));
+        }
+
+        let chords = parse_chords("Ctrl+L, f6, ctrl+l").unwrap();
+        assert_eq!(format_chords(&chords), "Ctrl+L, F6");
+        assert_eq!(parse_chords("none").unwrap(), Vec::new());
+        assert!(parse_chords("Ctrl+L, Shift+").is_err());
+    }
+
+    #[test]
+    fn test_defaults_bind_every_shortcut_once() {
+        let keymap = Keymap::new();
+        for info in COMMANDS {
+            assert_eq!(format_chords(&keymap.chords(info.command)), info.setting.default);
+            assert_eq!(Command::for_setting(info.setting.key), Some(info.command));
+        }
+        assert_eq!(keymap.command(&chord("Ctrl+Shift+T")), None);
+        assert_eq!(keymap.command(&chord("F6")), Some(Command::FocusAddressBar));
+        assert_eq!(keymap.command(&chord("Ctrl+Shift+D")), Some(Command::ToggleTheme));
+    }
+
+    #[test]
+    fn test_binding_a_chord_taken_by_another_command_is_refused() {
+        let mut keymap = Keymap::new();
+        assert_eq!(
+            keymap.bind(Command::ShowConsole, vec![chord("Ctrl+Shift+D")]),
+            Err(KeymapError::Conflict {
+                chord: "Ctrl+Shift+D".to_string(),
+                command: "Switch between light and dark".to_string(),
+            })
+        );
+        assert_eq!(keymap.chords(Command::ShowConsole), vec![chord("Ctrl+Shift+J")]);
+
+        // Rebinding a command to its own chords, or freeing one first, is fine
+        keymap.bind(Command::FocusAddressBar, vec![chord("F6")]).unwrap();
+        keymap.bind(Command::ShowConsole, vec![chord("Ctrl+L"), chord("Ctrl+Shift+J")]).unwrap();
+        assert_eq!(keymap.command(&chord("Ctrl+L")), Some(Command::ShowConsole));
+
+        let loaded = Keymap::from_bindings([(Command::Reload, "F5, Ctrl+R"), (Command::Back, "Ctrl+R")]);
+        assert_eq!(loaded.command(&chord("Ctrl+R")), Some(Command::Reload));
+        assert!(loaded.chords(Command::Back).is_empty());
+    }
+
+    /// Names the handler method each command was dispatched to
+    #[derive(Default)]
+    struct RecordingHandler {
+        calls: Vec<String>,
+    }
+
+    impl RecordingHandler {
+        fn record(&mut self, call: impl Into<String>) {
+            self.calls.push(call.into());
+        }
+    }
+
+    impl CommandHandler for RecordingHandler {
+        fn back(&mut self) {
+            self.record("back");
+        }
+        fn forward(&mut self) {
+            self.record("forward");
+        }
+        fn go_up(&mut self, to_root: bool) {
+            self.record(if to_root { "go_to_root" } else { "go_up" });
+        }
+        fn reload(&mut self) {
+            self.record("reload");
+        }
+        fn focus_address_bar(&mut self) {
+            self.record("focus_address_bar");
+        }
+        fn command_palette(&mut self) {
+            self.record("command_palette");
+        }
+        fn link_hints(&mut self) {
+            self.record("link_hints");
+        }
+        fn caret_browsing(&mut self) {
+            self.record("caret_browsing");
+        }
+        fn new_window(&mut self) {
+            self.record("new_window");
+        }
+        fn reopen_closed_window(&mut self) {
+            self.record("reopen_closed_window");
+        }
+        fn tab_overview(&mut self) {
+            self.record("tab_overview");
+        }
+        fn zoom(&mut self, larger: bool) {
+            self.record(if larger { "zoom_in" } else { "zoom_out" });
+        }
+        fn copy(&mut self) {
+            self.record("copy");
+        }
+        fn paste(&mut self) {
+            self.record("paste");
+        }
+        fn select_all(&mut self) {
+            self.record("select_all");
+        }
+        fn undo(&mut self) {
+            self.record("undo");
+        }
+        fn save_page(&mut self, format: SaveFormat) {
+            self.record(match format {
+                SaveFormat::Html => "save_page",
+                SaveFormat::Text => "save_page_as_text",
+                SaveFormat::Mhtml => "save_page_as_mhtml",
+            });
+        }
+        fn print_page(&mut self) {
+            self.record("print_page");
+        }
+        fn copy_screenshot(&mut self, full_page: bool) {
+            self.record(if full_page { "copy_full_page_screenshot" } else { "copy_screenshot" });
+        }
+        fn wrap_preformatted(&mut self) {
+            self.record("wrap_preformatted");
+        }
+        fn site_settings(&mut self) {
+            self.record("site_settings");
+        }
+        fn security_report(&mut self) {
+            self.record("security_report");
+        }
+        fn add_search_engine(&mut self) {
+            self.record("add_search_engine");
+        }
+        fn toggle_theme(&mut self) {
+            self.record("toggle_theme");
+        }
+        fn dark_pages(&mut self) {
+            self.record("dark_pages");
+        }
+        fn toggle_accessibility(&mut self) {
+            self.record("toggle_accessibility");
+        }
+        fn toggle_offline(&mut self) {
+            self.record("toggle_offline");
+        }
+        fn hit_regions(&mut self) {
+            self.record("hit_regions");
+        }
+        fn show_page(&mut self, url: &str) {
+            let page = url.trim_start_matches("navigator://");
+            self.record(format!("show_{}", page));
+        }
+    }
+
+    #[test]
+    fn test_every_command_is_dispatched_to_its_handler() {
+        let mut handler = RecordingHandler::default();
+        for info in COMMANDS {
+            info.command.dispatch(&mut handler);
+            let expected = info.command.name();
+            assert_eq!(handler.calls.last().map(String::as_str), Some(expected), "{:?}", info.command);
+        }
+        assert_eq!(handler.calls.len(), COMMANDS.len());
+    }
+}
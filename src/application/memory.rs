@@ -0,0 +1,304 @@
+use super::state::{BrowserState, TabActivity};
+use crate::domain::{MemoryReport, MemoryReporter, MemoryUsage, RenderingEngine, TabId};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+/// What each cache and store holds, with a button to trim them
+pub const MEMORY_PAGE: &str = "navigator://memory";
+/// Megabytes of accounted memory above which caches are trimmed on their
+/// own; "0" never trims
+pub const MEMORY_CEILING_SETTING: &str = "memory.ceiling_mb";
+pub const DEFAULT_MEMORY_CEILING: usize = 512 * MEGABYTE;
+/// Past the ceiling, caches are trimmed to this share of it, so the next
+/// few pages do not trim again at once
+const TRIM_TO_PERCENT: usize = 75;
+
+const MEGABYTE: usize = 1024 * 1024;
+
+/// Bytes from the stored setting; `None` when it isn't a whole number
+pub fn parse_memory_ceiling(value: &str) -> Option<usize> {
+    value.trim().parse::<usize>().ok().map(|megabytes| megabytes.saturating_mul(MEGABYTE))
+}
+
+/// The caches and stores that account for their memory.
+///
+/// Registrants are held weakly, so one leaves once whatever owns it drops
+/// it. Clones share the registrants and the ceiling.
+#[derive(Clone)]
+pub struct MemoryRegistry {
+    reporters: Arc<RwLock<Vec<Weak<dyn MemoryReporter>>>>,
+    ceiling: Arc<RwLock<usize>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self {
+            reporters: Arc::new(RwLock::new(Vec::new())),
+            ceiling: Arc::new(RwLock::new(DEFAULT_MEMORY_CEILING)),
+        }
+    }
+
+    pub fn register(&self, reporter: &Arc<dyn MemoryReporter>) {
+        if let Ok(mut reporters) = self.reporters.write() {
+            reporters.push(Arc::downgrade(reporter));
+        }
+    }
+
+    /// Bytes above which [`Self::trim_if_over`] trims; zero never does
+    pub fn ceiling(&self) -> usize {
+        self.ceiling.read().map(|ceiling| *ceiling).unwrap_or(DEFAULT_MEMORY_CEILING)
+    }
+
+    pub fn set_ceiling(&self, bytes: usize) {
+        if let Ok(mut ceiling) = self.ceiling.write() {
+            *ceiling = bytes;
+        }
+    }
+
+    /// Registrants still alive, forgetting the rest
+    fn live(&self) -> Vec<Arc<dyn MemoryReporter>> {
+        let Ok(mut reporters) = self.reporters.write() else {
+            return Vec::new();
+        };
+        reporters.retain(|reporter| reporter.strong_count() > 0);
+        reporters.iter().filter_map(Weak::upgrade).collect()
+    }
+
+    /// What every registrant holds, in the order they registered
+    pub fn reports(&self) -> Vec<MemoryReport> {
+        self.live()
+            .iter()
+            .map(|reporter| MemoryReport {
+                name: reporter.name().to_string(),
+                tab: reporter.tab(),
+                usage: reporter.usage(),
+            })
+            .collect()
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.live().iter().map(|reporter| reporter.usage().bytes).sum()
+    }
+
+    /// Ask registrants to shed until at most `target` bytes are held in
+    /// all, the largest first and among equals the least recently used;
+    /// returns the bytes freed
+    pub fn trim_to(&self, target: usize) -> usize {
+        let mut reporters: Vec<_> = self
+            .live()
+            .into_iter()
+            .map(|reporter| {
+                let usage = reporter.usage();
+                let last_used = reporter.last_used();
+                (reporter, usage.bytes, last_used)
+            })
+            .collect();
+        // Never used sorts before any time, so counts as least recent
+        reporters.sort_by(|a, b| b.1.cmp(&a.1).then(a.2.cmp(&b.2)));
+        let mut total: usize = reporters.iter().map(|(_, bytes, _)| bytes).sum();
+        let mut freed = 0;
+        for (reporter, bytes, _) in reporters {
+            if total <= target {
+                break;
+            }
+            let shed = reporter.trim(bytes.saturating_sub(total - target)).min(bytes);
+            if shed > 0 {
+                tracing::debug!("Trimmed {} bytes from {}", shed, reporter.name());
+            }
+            total -= shed;
+            freed += shed;
+        }
+        freed
+    }
+
+    /// Trim to three quarters of the ceiling if more than it is held;
+    /// returns the bytes freed
+    pub fn trim_if_over(&self) -> usize {
+        let ceiling = self.ceiling();
+        if ceiling == 0 || self.total_bytes() <= ceiling {
+            return 0;
+        }
+        self.trim_to(ceiling / 100 * TRIM_TO_PERCENT)
+    }
+}
+
+impl Default for MemoryRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A tab's engine as a registrant: its document, source and layout.
+/// Trimming drops the layout of a hidden tab and marks it suspended, so
+/// showing it lays the page out again.
+pub(crate) struct TabMemory<E> {
+    tab: TabId,
+    engine: Arc<E>,
+    state: BrowserState,
+}
+
+impl<E: RenderingEngine> TabMemory<E> {
+    pub(crate) fn new(tab: TabId, engine: Arc<E>, state: BrowserState) -> Self {
+        Self { tab, engine, state }
+    }
+}
+
+impl<E: RenderingEngine> MemoryReporter for TabMemory<E> {
+    fn name(&self) -> &str {
+        "Page"
+    }
+
+    fn tab(&self) -> Option<TabId> {
+        Some(self.tab)
+    }
+
+    fn usage(&self) -> MemoryUsage {
+        self.engine.memory_usage()
+    }
+
+    fn last_used(&self) -> Option<Instant> {
+        let now = Instant::now();
+        let hidden_for = self.state.hidden_for(self.tab, now).unwrap_or(Duration::ZERO);
+        now.checked_sub(hidden_for)
+    }
+
+    fn trim(&self, target: usize) -> usize {
+        // The page being looked at keeps its layout
+        if target >= self.usage().bytes || self.state.tab_activity(self.tab) == TabActivity::Foreground {
+            return 0;
+        }
+        let freed = self.engine.trim_memory();
+        if freed > 0 {
+            self.state.set_tab_activity(self.tab, TabActivity::Suspended);
+        }
+        freed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    type Trims = Arc<Mutex<Vec<&'static str>>>;
+
+    /// Holds `bytes` in entries of `entry` bytes each, noting when it was
+    /// asked to trim
+    struct FakeReporter {
+        name: &'static str,
+        entry: usize,
+        bytes: Mutex<usize>,
+        last_used: Option<Instant>,
+        trims: Trims,
+    }
+
+    impl FakeReporter {
+        fn new(name: &'static str, bytes: usize, last_used: Option<Instant>, trims: &Trims) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                entry: 10,
+                bytes: Mutex::new(bytes),
+                last_used,
+                trims: trims.clone(),
+            })
+        }
+    }
+
+    impl MemoryReporter for FakeReporter {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn usage(&self) -> MemoryUsage {
+            let bytes = *self.bytes.lock().unwrap();
+            MemoryUsage::new(bytes / self.entry, bytes)
+        }
+
+        fn last_used(&self) -> Option<Instant> {
+            self.last_used
+        }
+
+        fn trim(&self, target: usize) -> usize {
+            self.trims.lock().unwrap().push(self.name);
+            let mut bytes = self.bytes.lock().unwrap();
+            let before = *bytes;
+            while *bytes > target {
+                *bytes -= self.entry;
+            }
+            before - *bytes
+        }
+    }
+
+    fn register(registry: &MemoryRegistry, reporter: &Arc<FakeReporter>) -> Arc<dyn MemoryReporter> {
+        let reporter: Arc<dyn MemoryReporter> = reporter.clone();
+        registry.register(&reporter);
+        reporter
+    }
+
+    #[test]
+    fn test_trim_sheds_from_the_largest_then_least_recently_used() {
+        let registry = MemoryRegistry::new();
+        let trims = Arc::new(Mutex::new(Vec::new()));
+        let now = Instant::now();
+        let earlier = now - Duration::from_secs(60);
+        let recent = FakeReporter::new("recent", 300, Some(now), &trims);
+        let stale = FakeReporter::new("stale", 300, Some(earlier), &trims);
+        let small = FakeReporter::new("small", 100, None, &trims);
+        let _held = [
+            register(&registry, &small),
+            register(&registry, &recent),
+            register(&registry, &stale),
+        ];
+        assert_eq!(registry.total_bytes(), 700);
+
+        // Shedding 400 empties the stale one before touching the recent one
+        assert_eq!(registry.trim_to(300), 400);
+        assert_eq!(*trims.lock().unwrap(), ["stale", "recent"]);
+        assert_eq!(stale.usage().bytes, 0);
+        assert_eq!(recent.usage().bytes, 200);
+        assert_eq!(small.usage().bytes, 100);
+        assert_eq!(registry.total_bytes(), 300);
+
+        // Already under the target, nothing is asked
+        assert_eq!(registry.trim_to(500), 0);
+        assert_eq!(trims.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_trim_if_over_the_ceiling() {
+        let registry = MemoryRegistry::new();
+        let trims = Arc::new(Mutex::new(Vec::new()));
+        let cache = FakeReporter::new("cache", 1000, None, &trims);
+        let _held = register(&registry, &cache);
+
+        registry.set_ceiling(2000);
+        assert_eq!(registry.trim_if_over(), 0);
+        registry.set_ceiling(0);
+        assert_eq!(registry.trim_if_over(), 0);
+        registry.set_ceiling(800);
+        assert_eq!(registry.trim_if_over(), 400);
+        assert_eq!(cache.usage(), MemoryUsage::new(60, 600));
+    }
+
+    #[test]
+    fn test_dropped_reporters_leave_the_registry() {
+        let registry = MemoryRegistry::new();
+        let trims = Arc::new(Mutex::new(Vec::new()));
+        let kept = register(&registry, &FakeReporter::new("kept", 20, None, &trims));
+        drop(register(&registry, &FakeReporter::new("dropped", 50, None, &trims)));
+
+        let reports = registry.reports();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].name, "kept");
+        assert_eq!(reports[0].tab, None);
+        assert_eq!(reports[0].usage, MemoryUsage::new(2, 20));
+        assert_eq!(registry.total_bytes(), kept.usage().bytes);
+    }
+
+    #[test]
+    fn test_parse_memory_ceiling() {
+        assert_eq!(parse_memory_ceiling(" 256 "), Some(256 * MEGABYTE));
+        assert_eq!(parse_memory_ceiling("0"), Some(0));
+        assert_eq!(parse_memory_ceiling("lots"), None);
+    }
+}
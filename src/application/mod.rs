@@ -1,8 +1,53 @@
 // Application Layer - Use cases and application logic
 // Orchestrates the flow of data between domain and infrastructure
 
+pub mod controller;
+pub mod download_safety;
+pub mod downloads;
+pub mod error;
+pub mod external_schemes;
+pub mod fuzzy;
+pub mod history_writer;
+pub mod keymap;
+pub mod memory;
+pub mod navigation;
+pub mod notifications;
+pub mod omnibox;
+pub mod palette;
+pub mod profile;
+pub mod settings;
 pub mod state;
+pub mod suggestions;
+pub mod tab_search;
+pub mod throttling;
+pub mod typed_settings;
+pub mod undo;
+pub mod updates;
 pub mod use_cases;
 
+/// Fakes for tests; enable `test-util` to use them from integration tests
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+
+pub use controller::*;
+pub use download_safety::*;
+pub use downloads::*;
+pub use error::*;
+pub use external_schemes::*;
+pub use fuzzy::*;
+pub use history_writer::*;
+pub use keymap::*;
+pub use memory::*;
+pub use navigation::*;
+pub use notifications::*;
+pub use omnibox::*;
+pub use palette::*;
+pub use profile::*;
+pub use settings::*;
 pub use state::*;
-pub use use_cases::*;
+pub use suggestions::*;
+pub use tab_search::*;
+pub use throttling::*;
+pub use typed_settings::*;
+pub use undo::*;
+pub use updates::*;
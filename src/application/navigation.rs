@@ -0,0 +1,124 @@
+use crate::domain::ValidatedUrl;
+
+/// One back/forward history entry of a tab
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavigationEntry {
+    pub url: ValidatedUrl,
+    /// Scroll offset to restore when returning to this entry
+    pub scroll_offset: f32,
+}
+
+/// Back/forward session history of a single tab. It starts on
+/// `about:blank`, so Back from the first page returns to the blank tab.
+#[derive(Debug, Clone)]
+pub struct NavigationHistory {
+    entries: Vec<NavigationEntry>,
+    index: usize,
+}
+
+impl NavigationHistory {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![NavigationEntry {
+                url: ValidatedUrl::about_blank(),
+                scroll_offset: 0.0,
+            }],
+            index: 0,
+        }
+    }
+
+    pub fn current(&self) -> Option<&NavigationEntry> {
+        self.entries.get(self.index)
+    }
+
+    /// Record a new navigation, dropping any forward entries
+    pub fn push(&mut self, url: ValidatedUrl) {
+        self.entries.truncate(self.index + 1);
+        self.entries.push(NavigationEntry {
+            url,
+            scroll_offset: 0.0,
+        });
+        self.index = self.entries.len() - 1;
+    }
+
+    /// Remember how far the current entry was scrolled before leaving it
+    pub fn set_scroll_offset(&mut self, offset: f32) {
+        if let Some(entry) = self.entries.get_mut(self.index) {
+            entry.scroll_offset = offset;
+        }
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.index > 0
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.index + 1 < self.entries.len()
+    }
+
+    pub fn go_back(&mut self) -> Option<&NavigationEntry> {
+        if !self.can_go_back() {
+            return None;
+        }
+        self.index -= 1;
+        self.current()
+    }
+
+    pub fn go_forward(&mut self) -> Option<&NavigationEntry> {
+        if !self.can_go_forward() {
+            return None;
+        }
+        self.index += 1;
+        self.current()
+    }
+}
+
+impl Default for NavigationHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> ValidatedUrl {
+        ValidatedUrl::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut history = NavigationHistory::new();
+        assert!(history.current().unwrap().url.is_about_blank());
+        assert!(!history.can_go_back());
+
+        history.push(url("https://example.com/"));
+        history.set_scroll_offset(120.0);
+        history.push(url("https://example.com/#section"));
+
+        let back = history.go_back().unwrap();
+        assert_eq!(back.url.as_str(), "https://example.com/");
+        assert_eq!(back.scroll_offset, 120.0);
+        // Back from the first page is the blank tab, and no further
+        assert!(history.go_back().unwrap().url.is_about_blank());
+        assert!(history.go_back().is_none());
+        history.go_forward();
+
+        let forward = history.go_forward().unwrap();
+        assert_eq!(forward.url.fragment(), Some("section"));
+        assert!(!history.can_go_forward());
+    }
+
+    #[test]
+    fn test_push_drops_forward_entries() {
+        let mut history = NavigationHistory::new();
+        history.push(url("https://a.example/"));
+        history.push(url("https://b.example/"));
+        history.go_back();
+        history.push(url("https://c.example/"));
+
+        assert!(!history.can_go_forward());
+        assert_eq!(history.go_back().unwrap().url.as_str(), "https://a.example/");
+    }
+}
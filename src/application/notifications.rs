@@ -0,0 +1,143 @@
+use crate::domain::TabId;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Seconds a notification stays on screen before dismissing itself
+pub const NOTIFICATION_SECONDS_SETTING: &str = "ui.notification_seconds";
+pub const DEFAULT_NOTIFICATION_DURATION: Duration = Duration::from_secs(5);
+/// Longest a notification may be set to stay
+pub const MAX_NOTIFICATION_SECONDS: u64 = 60;
+
+/// Notifications kept for subscribers to read; a subscriber this far
+/// behind has missed events anyway
+const MAX_LOGGED_NOTIFICATIONS: usize = 64;
+
+/// How much a notification matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NotificationSeverity {
+    #[default]
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+/// Something the user can do from a notification by pressing its key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationAction {
+    /// Lowercase; the key is shown uppercase
+    pub key: char,
+    /// What pressing the key does, e.g. "open"
+    pub label: String,
+    /// Page opened in the notified window
+    pub url: String,
+}
+
+/// A short message for the user, shown without interrupting them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub message: String,
+    pub severity: NotificationSeverity,
+    /// The tab it is about; `None` notifies every window
+    pub tab: Option<TabId>,
+    pub action: Option<NotificationAction>,
+}
+
+impl Notification {
+    pub fn new(message: impl Into<String>, severity: NotificationSeverity) -> Self {
+        Self {
+            message: message.into(),
+            severity,
+            tab: None,
+            action: None,
+        }
+    }
+
+    pub fn with_tab(mut self, tab: TabId) -> Self {
+        self.tab = Some(tab);
+        self
+    }
+
+    pub fn with_action(mut self, key: char, label: impl Into<String>, url: impl Into<String>) -> Self {
+        self.action = Some(NotificationAction {
+            key: key.to_ascii_lowercase(),
+            label: label.into(),
+            url: url.into(),
+        });
+        self
+    }
+}
+
+/// How long notifications stay, from the stored setting; unset or invalid
+/// values keep the default
+pub fn parse_notification_duration(value: Option<&str>) -> Duration {
+    value
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|seconds| (1..=MAX_NOTIFICATION_SECONDS).contains(seconds))
+        .map_or(DEFAULT_NOTIFICATION_DURATION, Duration::from_secs)
+}
+
+/// Recent notifications by id, for subscribers told of them by event
+#[derive(Debug, Default)]
+pub struct NotificationLog {
+    entries: RwLock<VecDeque<(u64, Notification)>>,
+    next_id: AtomicU64,
+}
+
+impl NotificationLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep `notification`, dropping the oldest past the limit
+    pub fn push(&self, notification: Notification) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Ok(mut entries) = self.entries.write() {
+            if entries.len() == MAX_LOGGED_NOTIFICATIONS {
+                entries.pop_front();
+            }
+            entries.push_back((id, notification));
+        }
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<Notification> {
+        let entries = self.entries.read().ok()?;
+        entries
+            .iter()
+            .find(|(entry_id, _)| *entry_id == id)
+            .map(|(_, notification)| notification.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_keeps_recent_notifications() {
+        let log = NotificationLog::new();
+        let first = log.push(Notification::new("Saved", NotificationSeverity::Success));
+        let action =
+            Notification::new("Done", NotificationSeverity::Info).with_action('O', "open", "navigator://downloads");
+        let second = log.push(action.clone());
+        assert_ne!(first, second);
+        assert_eq!(log.get(second), Some(action.clone()));
+        assert_eq!(action.action.unwrap().key, 'o');
+
+        for _ in 0..MAX_LOGGED_NOTIFICATIONS {
+            log.push(Notification::new("More", NotificationSeverity::Info));
+        }
+        assert_eq!(log.get(first), None);
+    }
+
+    #[test]
+    fn test_notification_duration_setting() {
+        assert_eq!(parse_notification_duration(None), DEFAULT_NOTIFICATION_DURATION);
+        assert_eq!(parse_notification_duration(Some(" 8 ")), Duration::from_secs(8));
+        assert_eq!(parse_notification_duration(Some("0")), DEFAULT_NOTIFICATION_DURATION);
+        assert_eq!(parse_notification_duration(Some("soon")), DEFAULT_NOTIFICATION_DURATION);
+    }
+}
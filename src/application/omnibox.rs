@@ -0,0 +1,124 @@
+use crate::domain::{looks_like_url, SearchEngine, ValidatedUrl};
+
+/// What text typed into the address bar asks for
+#[derive(Debug, Clone, PartialEq)]
+pub enum OmniboxInput {
+    /// Go to the text itself, a URL or something to be read as one
+    Navigate(String),
+    /// Search `query` with the engine `engine_id`
+    Search {
+        engine_id: i64,
+        query: String,
+        url: ValidatedUrl,
+    },
+}
+
+/// Ctrl+Enter's address for a single bare word typed: `https://www.{word}.com`.
+/// Anything else, e.g. with a dot, slash or space, is left alone.
+pub fn wrap_bare_word(input: &str) -> Option<String> {
+    let word = input.trim();
+    let bare = !word.is_empty() && word.chars().all(|c| c.is_alphanumeric() || c == '-');
+    bare.then(|| format!("https://www.{}.com", word))
+}
+
+/// Sort typed text into a navigation or a search.
+///
+/// A first word matching an engine's keyword searches that engine for the
+/// rest. Otherwise URL-like text is navigated to and anything else searched
+/// with the default engine; without one, the text is navigated to as before.
+pub fn classify_input(input: &str, engines: &[SearchEngine]) -> OmniboxInput {
+    let input = input.trim();
+    if let Some((first, rest)) = input.split_once(char::is_whitespace) {
+        let query = rest.trim();
+        let engine = engines.iter().find(|e| e.keyword.eq_ignore_ascii_case(first));
+        if let Some(found) = engine.and_then(|engine| search(engine, query)) {
+            return found;
+        }
+    }
+
+    if looks_like_url(input) {
+        return OmniboxInput::Navigate(input.to_string());
+    }
+    engines
+        .iter()
+        .find(|e| e.is_default)
+        .and_then(|engine| search(engine, input))
+        .unwrap_or_else(|| OmniboxInput::Navigate(input.to_string()))
+}
+
+fn search(engine: &SearchEngine, query: &str) -> Option<OmniboxInput> {
+    if query.is_empty() {
+        return None;
+    }
+    Some(OmniboxInput::Search {
+        engine_id: engine.id,
+        query: query.to_string(),
+        url: engine.search_url(query)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SearchTemplate;
+
+    fn engine(id: i64, keyword: &str, template: &str, is_default: bool) -> SearchEngine {
+        SearchEngine {
+            id,
+            name: keyword.to_string(),
+            keyword: keyword.to_string(),
+            query_template: SearchTemplate::parse(template).unwrap(),
+            suggest_template: None,
+            is_default,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    fn search_url(input: &str, engines: &[SearchEngine]) -> Option<String> {
+        match classify_input(input, engines) {
+            OmniboxInput::Search { url, .. } => Some(url.to_string()),
+            OmniboxInput::Navigate(_) => None,
+        }
+    }
+
+    #[test]
+    fn test_keywords_defaults_and_urls() {
+        let engines = [
+            engine(1, "d", "https://duckduckgo.com/?q=%s", true),
+            engine(2, "w", "https://en.wikipedia.org/w/index.php?search=%s", false),
+        ];
+
+        assert_eq!(
+            search_url("W  rust & cargo ", &engines).as_deref(),
+            Some("https://en.wikipedia.org/w/index.php?search=rust+%26+cargo")
+        );
+        // A keyword wins even when the query looks like a URL
+        assert_eq!(
+            search_url("w example.com", &engines).as_deref(),
+            Some("https://en.wikipedia.org/w/index.php?search=example.com")
+        );
+        assert_eq!(search_url("what is rust", &engines).as_deref(), Some("https://duckduckgo.com/?q=what+is+rust"));
+        // A keyword alone is searched for with the default engine
+        assert_eq!(search_url("w", &engines).as_deref(), Some("https://duckduckgo.com/?q=w"));
+        assert_eq!(
+            classify_input(" example.com/a ", &engines),
+            OmniboxInput::Navigate("example.com/a".to_string())
+        );
+    }
+
+    #[test]
+    fn test_only_bare_words_are_wrapped() {
+        assert_eq!(wrap_bare_word(" example ").as_deref(), Some("https://www.example.com"));
+        assert_eq!(wrap_bare_word("my-site2").as_deref(), Some("https://www.my-site2.com"));
+        for input in ["", "example.org", "two words", "localhost:8080", "a/b", "https://example"] {
+            assert_eq!(wrap_bare_word(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_without_default_engine_text_is_navigated_to() {
+        let engines = [engine(2, "w", "https://en.wikipedia.org/w/index.php?search=%s", false)];
+        assert_eq!(classify_input("rust", &engines), OmniboxInput::Navigate("rust".to_string()));
+        assert!(search_url("w rust", &engines).is_some());
+    }
+}
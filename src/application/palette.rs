@@ -0,0 +1,252 @@
+use super::fuzzy::fuzzy_match;
+use super::keymap::Command;
+use crate::domain::{TabId, ValidatedUrl};
+use chrono::{DateTime, Utc};
+use std::fmt;
+
+/// Settings key holding how often each command was run from the palette,
+/// most recently run first, as in "reload:3, show_history:1"
+pub const PALETTE_USAGE_SETTING: &str = "palette.usage";
+/// Most entries the palette lists at once
+pub const MAX_PALETTE_ENTRIES: usize = 12;
+/// Most commands whose use is remembered
+pub const MAX_REMEMBERED_COMMANDS: usize = 32;
+/// Most recently visited pages offered besides bookmarks
+pub const MAX_PALETTE_HISTORY: usize = 50;
+
+/// What choosing a palette entry does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteTarget {
+    /// Run the command, as its shortcut would
+    Command(Command),
+    /// Bring the open tab to the front
+    Tab(TabId),
+    /// Open a bookmarked or visited page
+    Page(ValidatedUrl),
+}
+
+/// One line of the command palette
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaletteEntry {
+    pub target: PaletteTarget,
+    /// Matched against the query: a command's description or a page title
+    pub label: String,
+    /// Shown beside the label: a command's shortcuts or a page's address,
+    /// which is matched too
+    pub detail: String,
+    /// When the tab or page was last looked at
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl PaletteEntry {
+    pub fn command(command: Command, shortcuts: String) -> Self {
+        Self {
+            target: PaletteTarget::Command(command),
+            label: command.description().to_string(),
+            detail: shortcuts,
+            last_used: None,
+        }
+    }
+
+    pub fn tab(id: TabId, title: &str, url: Option<&ValidatedUrl>, last_used: DateTime<Utc>) -> Self {
+        let address = url.map(ToString::to_string).unwrap_or_default();
+        Self {
+            target: PaletteTarget::Tab(id),
+            label: format!("Tab: {}", page_title(title, &address)),
+            detail: address,
+            last_used: Some(last_used),
+        }
+    }
+
+    pub fn page(kind: &str, title: &str, url: ValidatedUrl, last_used: Option<DateTime<Utc>>) -> Self {
+        let address = url.to_string();
+        Self {
+            label: format!("{}: {}", kind, page_title(title, &address)),
+            detail: address,
+            target: PaletteTarget::Page(url),
+            last_used,
+        }
+    }
+}
+
+fn page_title<'a>(title: &'a str, address: &'a str) -> &'a str {
+    match title.trim() {
+        "" => address,
+        title => title,
+    }
+}
+
+/// How often commands were run from the palette, most recently run first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaletteUsage {
+    counts: Vec<(String, u32)>,
+}
+
+impl PaletteUsage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read the usage as `PALETTE_USAGE_SETTING` stores it, skipping
+    /// entries that do not parse
+    pub fn parse(value: &str) -> Self {
+        let mut usage = Self::new();
+        for entry in value.split(',') {
+            let Some((name, count)) = entry.trim().rsplit_once(':') else {
+                continue;
+            };
+            let (name, Ok(count)) = (name.trim(), count.trim().parse::<u32>()) else {
+                continue;
+            };
+            if !name.is_empty() && usage.count(name) == 0 && usage.counts.len() < MAX_REMEMBERED_COMMANDS {
+                usage.counts.push((name.to_string(), count));
+            }
+        }
+        usage
+    }
+
+    /// Times `command` was run from the palette
+    pub fn count(&self, command: &str) -> u32 {
+        self.counts
+            .iter()
+            .find(|(name, _)| name == command)
+            .map_or(0, |(_, count)| *count)
+    }
+
+    /// Count one more run of `command`, making it the most recent
+    pub fn record(&mut self, command: &str) {
+        let count = self.count(command);
+        self.counts.retain(|(name, _)| name != command);
+        self.counts.insert(0, (command.to_string(), count.saturating_add(1)));
+        self.counts.truncate(MAX_REMEMBERED_COMMANDS);
+    }
+
+    /// Ranking boost of `command`: more for frequent use, and more for
+    /// recent use
+    fn bonus(&self, command: &str) -> i32 {
+        let Some(rank) = self.counts.iter().position(|(name, _)| name == command) else {
+            return 0;
+        };
+        let frequency = self.counts[rank].1.min(10) as i32 * 4;
+        frequency + (20 - 2 * rank as i32).max(0)
+    }
+}
+
+impl fmt::Display for PaletteUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self
+            .counts
+            .iter()
+            .map(|(name, count)| format!("{}:{}", name, count))
+            .collect();
+        write!(f, "{}", entries.join(", "))
+    }
+}
+
+/// Ranking boost of a tab or page last looked at `last_used`, fading over
+/// a week
+fn recency_bonus(last_used: Option<DateTime<Utc>>, now: DateTime<Utc>) -> i32 {
+    let Some(last_used) = last_used else {
+        return 0;
+    };
+    let hours = now.signed_duration_since(last_used).num_hours().max(0);
+    (24 - hours / 7).max(0) as i32
+}
+
+/// Up to `MAX_PALETTE_ENTRIES` entries matching `query`, best first.
+/// Entries are ranked by how well the query matches their label, or the
+/// address of tabs and pages, plus how often and how lately they were
+/// used as of `now`. Ties keep the order given, so an empty query lists
+/// the commands used most, then the rest in order.
+pub fn rank_palette(
+    query: &str,
+    entries: Vec<PaletteEntry>,
+    usage: &PaletteUsage,
+    now: DateTime<Utc>,
+) -> Vec<PaletteEntry> {
+    let mut ranked: Vec<(i32, PaletteEntry)> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let label = fuzzy_match(query, &entry.label).map(|found| found.score);
+            let score = match &entry.target {
+                PaletteTarget::Command(command) => label? + usage.bonus(command.name()),
+                _ => {
+                    // An address match counts for less than a title match
+                    let address = fuzzy_match(query, &entry.detail).map(|found| found.score * 3 / 4);
+                    label.max(address)? + recency_bonus(entry.last_used, now)
+                }
+            };
+            Some((score, entry))
+        })
+        .collect();
+    ranked.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    ranked.truncate(MAX_PALETTE_ENTRIES);
+    ranked.into_iter().map(|(_, entry)| entry).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn commands() -> Vec<PaletteEntry> {
+        [Command::Reload, Command::ShowHistory, Command::ShowShortcuts, Command::NewWindow]
+            .into_iter()
+            .map(|command| PaletteEntry::command(command, String::new()))
+            .collect()
+    }
+
+    fn labels(entries: &[PaletteEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.label.as_str()).collect()
+    }
+
+    #[test]
+    fn test_usage_round_trips_and_records_most_recent_first() {
+        let mut usage = PaletteUsage::parse("reload:3, nonsense, show_history:x, new_window:1, reload:9");
+        assert_eq!(usage.to_string(), "reload:3, new_window:1");
+
+        usage.record("new_window");
+        usage.record("show_shortcuts");
+        assert_eq!(usage.to_string(), "show_shortcuts:1, new_window:2, reload:3");
+        assert_eq!(PaletteUsage::parse(&usage.to_string()), usage);
+        assert_eq!(usage.count("undo"), 0);
+    }
+
+    #[test]
+    fn test_entries_are_ranked_by_match_then_use() {
+        let now = Utc::now();
+        let unused = PaletteUsage::new();
+        assert_eq!(labels(&rank_palette("show", commands(), &unused, now)), [
+            "Show history",
+            "Show keyboard shortcuts"
+        ]);
+        assert!(rank_palette("xyz", commands(), &unused, now).is_empty());
+
+        // Commands used before come first, with nothing or a close match typed
+        let mut usage = PaletteUsage::new();
+        usage.record("show_shortcuts");
+        assert_eq!(labels(&rank_palette("", commands(), &usage, now))[..2], [
+            "Show keyboard shortcuts",
+            "Reload the page"
+        ]);
+        assert_eq!(labels(&rank_palette("show", commands(), &usage, now))[0], "Show keyboard shortcuts");
+    }
+
+    #[test]
+    fn test_pages_match_by_title_or_address_and_recent_ones_win() {
+        let now = Utc::now();
+        let url = |address: &str| ValidatedUrl::parse(address).unwrap();
+        let month_ago = Some(now - Duration::days(30));
+        let entries = vec![
+            PaletteEntry::page("History", "Rust news", url("https://old.example.com/"), month_ago),
+            PaletteEntry::page("Bookmark", "", url("https://rust-lang.org/"), None),
+            PaletteEntry::tab(TabId::new(), "Rust forum", Some(&url("https://users.rust-lang.org/")), now),
+        ];
+        let ranked = rank_palette("rust", entries, &PaletteUsage::new(), now);
+        assert_eq!(labels(&ranked), ["Tab: Rust forum", "History: Rust news", "Bookmark: https://rust-lang.org/"]);
+        assert!(matches!(ranked[0].target, PaletteTarget::Tab(_)));
+
+        let entries = vec![PaletteEntry::page("Bookmark", "Docs", url("https://docs.rs/"), None)];
+        assert_eq!(rank_palette("docsrs", entries, &PaletteUsage::new(), now).len(), 1);
+    }
+}
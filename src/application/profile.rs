@@ -0,0 +1,188 @@
+use crate::domain::BrowserSource;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Schema version written by this build; documents with another version are refused
+pub const PROFILE_VERSION: u32 = 1;
+
+/// Settings key holding user-blocked domains, one per line
+pub const BLOCKED_DOMAINS_SETTING: &str = "security.blocked_domains";
+
+/// File navigator://settings last exported the profile to; setting it
+/// exports again
+pub const PROFILE_EXPORT_SETTING: &str = "profile.export_file";
+/// Whether exports from navigator://settings include history
+pub const PROFILE_EXPORT_HISTORY_SETTING: &str = "profile.export_history";
+/// File navigator://settings last imported a profile from; setting it
+/// merges that file into this profile
+pub const PROFILE_IMPORT_SETTING: &str = "profile.import_file";
+
+/// Domains listed in the blocked-domains setting
+pub fn parse_blocked_domains(value: &str) -> Vec<String> {
+    value
+        .lines()
+        .map(|line| line.trim().to_ascii_lowercase())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Portable copy of a profile, independent of the database schema.
+///
+/// URLs are kept as plain strings so one bad record does not stop the rest
+/// of a document from loading; they are validated on import.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileDocument {
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    #[serde(default)]
+    pub bookmarks: Vec<BookmarkRecord>,
+    #[serde(default)]
+    pub settings: BTreeMap<String, String>,
+    #[serde(default)]
+    pub blocked_domains: Vec<String>,
+    /// Absent unless history was exported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub history: Option<Vec<HistoryRecord>>,
+}
+
+/// A bookmark; a document lists them in their folders' order, which an
+/// import keeps
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookmarkRecord {
+    pub title: String,
+    pub url: String,
+    #[serde(default)]
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub url: String,
+    pub title: String,
+    pub visited_at: DateTime<Utc>,
+    pub visit_count: i32,
+}
+
+/// How one section of a document is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImportMode {
+    /// Add to what is there; imported settings win, existing bookmarks stay
+    #[default]
+    Merge,
+    /// Drop what is there first
+    Replace,
+    /// Leave the section untouched
+    Skip,
+}
+
+/// Per-section import modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportOptions {
+    pub bookmarks: ImportMode,
+    pub settings: ImportMode,
+    pub blocked_domains: ImportMode,
+    pub history: ImportMode,
+}
+
+impl ImportOptions {
+    /// The same mode for every section
+    pub fn all(mode: ImportMode) -> Self {
+        Self {
+            bookmarks: mode,
+            settings: mode,
+            blocked_domains: mode,
+            history: mode,
+        }
+    }
+}
+
+/// A record that was skipped, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportFailure {
+    pub section: &'static str,
+    /// The record as it appeared, usually its URL
+    pub record: String,
+    pub reason: String,
+}
+
+/// What an import changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub bookmarks: usize,
+    pub settings: usize,
+    pub blocked_domains: usize,
+    pub history: usize,
+    pub failures: Vec<ImportFailure>,
+}
+
+/// What to bring over from another browser
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportItems {
+    pub bookmarks: bool,
+    pub history: bool,
+}
+
+impl ImportItems {
+    pub fn all() -> Self {
+        Self {
+            bookmarks: true,
+            history: true,
+        }
+    }
+}
+
+impl Default for ImportItems {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+/// An import from another browser's profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserImportRequest {
+    pub source: BrowserSource,
+    /// The browser's default profile when unset
+    pub profile_path: Option<PathBuf>,
+    pub items: ImportItems,
+    /// Count what would be imported without writing anything
+    pub dry_run: bool,
+}
+
+impl BrowserImportRequest {
+    pub fn new(source: BrowserSource) -> Self {
+        Self {
+            source,
+            profile_path: None,
+            items: ImportItems::all(),
+            dry_run: false,
+        }
+    }
+}
+
+/// What an import from another browser added, or would add on a dry run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BrowserImportReport {
+    pub bookmarks: usize,
+    pub history: usize,
+    /// Records skipped because their URL is already known
+    pub duplicates: usize,
+    pub dry_run: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_blocked_domains() {
+        assert_eq!(
+            parse_blocked_domains("Ads.example\n\n  tracker.example \n"),
+            vec!["ads.example", "tracker.example"]
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use super::downloads::DOWNLOADS_DIRECTORY_SETTING;
+use super::keymap::{format_chords, parse_chords, Command, COMMANDS};
+use super::memory::MEMORY_CEILING_SETTING;
+use super::notifications::{MAX_NOTIFICATION_SECONDS, NOTIFICATION_SECONDS_SETTING};
+use super::profile::{PROFILE_EXPORT_HISTORY_SETTING, PROFILE_EXPORT_SETTING, PROFILE_IMPORT_SETTING};
+use super::updates::{UPDATE_CHECK_SETTING, UPDATE_MANIFEST_SETTING};
+use crate::domain::{
+    CookiePolicy, EditableSetting, HostPattern, NewUserStyle, SettingKind, SiteSetting, SiteSettings, StorageArea,
+    UserStyleRule, ValidatedUrl,
+};
+use std::path::Path;
+
+/// Page new windows open on
+pub const HOMEPAGE_SETTING: &str = "browser.homepage";
+pub const DEFAULT_HOMEPAGE: &str = "https://example.com";
+/// Keyword of the default search engine. Kept with the search engines
+/// rather than in settings; listed so it can be changed with the rest.
+pub const DEFAULT_SEARCH_ENGINE_SETTING: &str = "search.default_engine";
+/// Theme preference: `light`, `dark` or `auto`
+pub const THEME_SETTING: &str = "theme";
+/// Load `http:` pages over `https:` instead
+pub const HTTPS_ONLY_SETTING: &str = "security.https_only";
+/// Whether `file://` URLs open local files at all
+pub const FILE_ACCESS_SETTING: &str = "security.file_access";
+/// Directories `file://` URLs may open files under, separated the way
+/// `PATH` separates them; empty for the home directory
+pub const FILE_ACCESS_ROOTS_SETTING: &str = "security.file_access_roots";
+/// Reopen the tabs left open when the browser last closed
+pub const RESTORE_SESSION_SETTING: &str = "session.restore";
+/// `User-Agent` sent with requests; empty sends the browser's own
+pub const USER_AGENT_SETTING: &str = "network.user_agent";
+/// Whether the content blocker refuses requests to blocked domains
+pub const CONTENT_BLOCKER_SETTING: &str = "content_blocker.enabled";
+/// Which cookies requests carry and keep: `allow-all`,
+/// `block-third-party` or `block-all`
+pub const COOKIES_SETTING: &str = "network.cookies";
+/// Stay off the network, showing pages from those kept for offline use
+pub const OFFLINE_SETTING: &str = "network.offline";
+/// Experimental: refuse what a page's content security policy does not
+/// allow instead of only reporting it
+pub const ENFORCE_CSP_SETTING: &str = "experimental.enforce_csp";
+/// Every user style rule as `<host-pattern> <rule>` entries separated by
+/// `;`. Kept in the user styles table rather than in settings; listed so
+/// they can be changed with the rest.
+pub const USER_STYLES_SETTING: &str = "user_styles.rules";
+
+/// Longest user agent accepted
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// Settings changed from navigator://settings, in the order listed
+pub const EDITABLE_SETTINGS: &[EditableSetting] = &[
+    EditableSetting {
+        key: HOMEPAGE_SETTING,
+        label: "Homepage",
+        kind: SettingKind::Text,
+        default: DEFAULT_HOMEPAGE,
+    },
+    EditableSetting {
+        key: DEFAULT_SEARCH_ENGINE_SETTING,
+        label: "Search engine",
+        kind: SettingKind::Text,
+        default: "",
+    },
+    EditableSetting {
+        key: THEME_SETTING,
+        label: "Theme",
+        kind: SettingKind::Choice(&["auto", "light", "dark"]),
+        default: "auto",
+    },
+    EditableSetting {
+        key: HTTPS_ONLY_SETTING,
+        label: "HTTPS only",
+        kind: SettingKind::Toggle,
+        default: "false",
+    },
+    EditableSetting {
+        key: FILE_ACCESS_SETTING,
+        label: "Open local files",
+        kind: SettingKind::Toggle,
+        default: "true",
+    },
+    EditableSetting {
+        key: FILE_ACCESS_ROOTS_SETTING,
+        label: "Local file folders",
+        kind: SettingKind::Text,
+        default: "",
+    },
+    EditableSetting {
+        key: RESTORE_SESSION_SETTING,
+        label: "Restore session",
+        kind: SettingKind::Toggle,
+        default: "false",
+    },
+    EditableSetting {
+        key: DOWNLOADS_DIRECTORY_SETTING,
+        label: "Download directory",
+        kind: SettingKind::Text,
+        default: "",
+    },
+    EditableSetting {
+        key: USER_AGENT_SETTING,
+        label: "User agent",
+        kind: SettingKind::Text,
+        default: "",
+    },
+    EditableSetting {
+        key: CONTENT_BLOCKER_SETTING,
+        label: "Content blocker",
+        kind: SettingKind::Toggle,
+        default: "true",
+    },
+    EditableSetting {
+        key: COOKIES_SETTING,
+        label: "Cookies",
+        kind: SettingKind::Choice(&["block-third-party", "allow-all", "block-all"]),
+        default: "block-third-party",
+    },
+    EditableSetting {
+        key: USER_STYLES_SETTING,
+        label: "User styles",
+        kind: SettingKind::Text,
+        default: "",
+    },
+    EditableSetting {
+        key: OFFLINE_SETTING,
+        label: "Offline mode",
+        kind: SettingKind::Toggle,
+        default: "false",
+    },
+    EditableSetting {
+        key: NOTIFICATION_SECONDS_SETTING,
+        label: "Notification seconds",
+        kind: SettingKind::Text,
+        default: "5",
+    },
+    EditableSetting {
+        key: UPDATE_CHECK_SETTING,
+        label: "Check for updates",
+        kind: SettingKind::Toggle,
+        default: "false",
+    },
+    EditableSetting {
+        key: UPDATE_MANIFEST_SETTING,
+        label: "Update manifest",
+        kind: SettingKind::Text,
+        default: "",
+    },
+    EditableSetting {
+        key: MEMORY_CEILING_SETTING,
+        label: "Memory ceiling (MB)",
+        kind: SettingKind::Text,
+        default: "512",
+    },
+    EditableSetting {
+        key: PROFILE_EXPORT_SETTING,
+        label: "Export profile to",
+        kind: SettingKind::Text,
+        default: "",
+    },
+    EditableSetting {
+        key: PROFILE_EXPORT_HISTORY_SETTING,
+        label: "Export history too",
+        kind: SettingKind::Toggle,
+        default: "false",
+    },
+    EditableSetting {
+        key: PROFILE_IMPORT_SETTING,
+        label: "Import profile from",
+        kind: SettingKind::Text,
+        default: "",
+    },
+    EditableSetting {
+        key: ENFORCE_CSP_SETTING,
+        label: "Enforce CSP (experimental)",
+        kind: SettingKind::Toggle,
+        default: "false",
+    },
+];
+
+/// A site's overrides on navigator://site-settings, in the order listed,
+/// then what it stores
+pub const SITE_SETTING_LABELS: &[&str] =
+    &["JavaScript", "Images", "Cookies", "User agent", "Content blocking", "Dark pages", "Stored data"];
+/// Row of the site's user agent, the one typed rather than stepped through
+pub const SITE_USER_AGENT_ROW: usize = 3;
+/// Row of the site's `localStorage`, cleared rather than changed
+pub const SITE_STORAGE_ROW: usize = 6;
+/// Shown for an override that is not set
+const SITE_DEFAULT: &str = "default";
+
+/// How each override in [`SITE_SETTING_LABELS`] is shown
+pub fn site_setting_values(settings: &SiteSettings) -> Vec<String> {
+    let toggle = |enabled: Option<bool>| match enabled {
+        Some(true) => "on".to_string(),
+        Some(false) => "off".to_string(),
+        None => SITE_DEFAULT.to_string(),
+    };
+    vec![
+        toggle(settings.javascript),
+        toggle(settings.images),
+        settings.cookies.map_or(SITE_DEFAULT.to_string(), |policy| policy.to_string()),
+        settings.user_agent.clone().unwrap_or_else(|| SITE_DEFAULT.to_string()),
+        toggle(settings.content_blocking),
+        toggle(settings.dark_pages),
+    ]
+}
+
+/// How a site's `localStorage` is shown on its row
+pub fn site_storage_value(storage: &StorageArea) -> String {
+    match storage.length() {
+        0 => "none".to_string(),
+        1 => format!("1 item, {} characters", storage.usage()),
+        items => format!("{} items, {} characters", items, storage.usage()),
+    }
+}
+
+/// The override after the current one in `row`: default, off, on for the
+/// toggles and default, then each cookie policy. `None` for the user agent.
+pub fn next_site_setting(settings: &SiteSettings, row: usize) -> Option<SiteSetting> {
+    let toggle = |enabled: Option<bool>| match enabled {
+        None => Some(false),
+        Some(false) => Some(true),
+        Some(true) => None,
+    };
+    match row {
+        0 => Some(SiteSetting::JavaScript(toggle(settings.javascript))),
+        1 => Some(SiteSetting::Images(toggle(settings.images))),
+        2 => Some(SiteSetting::Cookies(match settings.cookies {
+            None => Some(CookiePolicy::Allow),
+            Some(CookiePolicy::Allow) => Some(CookiePolicy::SessionOnly),
+            Some(CookiePolicy::SessionOnly) => Some(CookiePolicy::Block),
+            Some(CookiePolicy::Block) => None,
+        })),
+        4 => Some(SiteSetting::ContentBlocking(toggle(settings.content_blocking))),
+        5 => Some(SiteSetting::DarkPages(toggle(settings.dark_pages))),
+        _ => None,
+    }
+}
+
+/// Removal of the override in `row`, going back to the global setting
+pub fn reset_site_setting(row: usize) -> Option<SiteSetting> {
+    match row {
+        0 => Some(SiteSetting::JavaScript(None)),
+        1 => Some(SiteSetting::Images(None)),
+        2 => Some(SiteSetting::Cookies(None)),
+        SITE_USER_AGENT_ROW => Some(SiteSetting::UserAgent(None)),
+        4 => Some(SiteSetting::ContentBlocking(None)),
+        5 => Some(SiteSetting::DarkPages(None)),
+        _ => None,
+    }
+}
+
+/// Every setting on navigator://settings, in the order listed: the
+/// editable settings, then the shortcuts of each command
+pub fn listed_settings() -> impl Iterator<Item = &'static EditableSetting> {
+    EDITABLE_SETTINGS.iter().chain(COMMANDS.iter().map(|info| &info.setting))
+}
+
+/// The listed setting stored under `key`
+pub fn editable_setting(key: &str) -> Option<&'static EditableSetting> {
+    listed_settings().find(|setting| setting.key == key)
+}
+
+/// Whether the toggle stored under `key` is on, given its stored value if
+/// any
+pub fn setting_enabled(key: &str, value: Option<&str>) -> bool {
+    let default = editable_setting(key).map_or("false", |setting| setting.default);
+    parse_toggle(value.unwrap_or(default)) == Some(true)
+}
+
+/// Check a new value for `setting`, returning it as it should be stored or
+/// why it cannot be
+pub fn validate_setting(setting: &EditableSetting, value: &str) -> Result<String, String> {
+    let value = value.trim();
+    match setting.kind {
+        SettingKind::Toggle => parse_toggle(value)
+            .map(|enabled| enabled.to_string())
+            .ok_or_else(|| format!("expected true or false, not \"{}\"", value)),
+        SettingKind::Choice(choices) => choices
+            .iter()
+            .find(|choice| choice.eq_ignore_ascii_case(value))
+            .map(|choice| choice.to_string())
+            .ok_or_else(|| format!("expected one of {}", choices.join(", "))),
+        SettingKind::Text => match setting.key {
+            HOMEPAGE_SETTING => ValidatedUrl::parse(value)
+                .map(|url| url.to_string())
+                .map_err(|e| e.to_string()),
+            DOWNLOADS_DIRECTORY_SETTING if !value.is_empty() && !Path::new(value).is_absolute() => {
+                Err("the directory must be an absolute path".to_string())
+            }
+            PROFILE_EXPORT_SETTING | PROFILE_IMPORT_SETTING if !Path::new(value).is_absolute() => {
+                Err("the file must be an absolute path".to_string())
+            }
+            FILE_ACCESS_ROOTS_SETTING
+                if !value.is_empty() && std::env::split_paths(value).any(|root| !root.is_absolute()) =>
+            {
+                Err("every folder must be an absolute path".to_string())
+            }
+            USER_AGENT_SETTING if value.len() > MAX_USER_AGENT_LEN => {
+                Err(format!("at most {} characters", MAX_USER_AGENT_LEN))
+            }
+            USER_AGENT_SETTING if !value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) => {
+                Err("only printable ASCII characters are allowed".to_string())
+            }
+            UPDATE_MANIFEST_SETTING if value.is_empty() => Ok(String::new()),
+            UPDATE_MANIFEST_SETTING => match ValidatedUrl::parse(value) {
+                Ok(url) if url.is_secure() => Ok(url.to_string()),
+                Ok(_) => Err("the manifest must be fetched over https".to_string()),
+                Err(e) => Err(e.to_string()),
+            },
+            NOTIFICATION_SECONDS_SETTING => match value.parse::<u64>() {
+                Ok(seconds) if (1..=MAX_NOTIFICATION_SECONDS).contains(&seconds) => Ok(seconds.to_string()),
+                _ => Err(format!("expected a number of seconds from 1 to {}", MAX_NOTIFICATION_SECONDS)),
+            },
+            MEMORY_CEILING_SETTING => match value.parse::<u64>() {
+                Ok(megabytes) => Ok(megabytes.to_string()),
+                Err(_) => Err("expected a number of megabytes, or 0 to never trim".to_string()),
+            },
+            USER_STYLES_SETTING => parse_user_styles(value)
+                .map(|styles| format_user_styles(styles.iter().map(|style| (&style.host_pattern, &style.rule)))),
+            key if Command::for_setting(key).is_some() => parse_chords(value)
+                .map(|chords| format_chords(&chords))
+                .map_err(|e| e.to_string()),
+            _ => Ok(value.to_string()),
+        },
+    }
+}
+
+/// User style rules the way [`USER_STYLES_SETTING`] shows them, e.g.
+/// `* min-font-size=14; *.example.com hide=.ad`
+pub fn format_user_styles<'a>(styles: impl IntoIterator<Item = (&'a HostPattern, &'a UserStyleRule)>) -> String {
+    let entries: Vec<String> = styles
+        .into_iter()
+        .map(|(pattern, rule)| format!("{} {}", pattern.as_str(), rule))
+        .collect();
+    entries.join("; ")
+}
+
+/// Read the entries of [`USER_STYLES_SETTING`]; an empty value has none
+pub fn parse_user_styles(value: &str) -> Result<Vec<NewUserStyle>, String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (pattern, rule) = entry
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("expected a host pattern and a rule in \"{}\"", entry))?;
+            let host_pattern =
+                HostPattern::parse(pattern).ok_or_else(|| format!("\"{}\" is not a host pattern", pattern))?;
+            let rule = UserStyleRule::parse(rule)
+                .ok_or_else(|| format!("expected min-font-size=, text-color= or hide=, not \"{}\"", rule.trim()))?;
+            Ok(NewUserStyle::new(host_pattern, rule))
+        })
+        .collect()
+}
+
+pub(crate) fn parse_toggle(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_are_validated_by_kind() {
+        let setting = |key| editable_setting(key).unwrap();
+        assert_eq!(validate_setting(setting(HTTPS_ONLY_SETTING), " On "), Ok("true".to_string()));
+        assert!(validate_setting(setting(HTTPS_ONLY_SETTING), "maybe").is_err());
+        assert_eq!(validate_setting(setting(THEME_SETTING), "Dark"), Ok("dark".to_string()));
+        assert_eq!(
+            validate_setting(setting(THEME_SETTING), "sepia"),
+            Err("expected one of auto, light, dark".to_string())
+        );
+
+        assert_eq!(
+            validate_setting(setting(HOMEPAGE_SETTING), "https://example.org"),
+            Ok("https://example.org/".to_string())
+        );
+        assert!(validate_setting(setting(HOMEPAGE_SETTING), "not a url").is_err());
+        assert!(validate_setting(setting(DOWNLOADS_DIRECTORY_SETTING), "relative/dir").is_err());
+        assert_eq!(validate_setting(setting(DOWNLOADS_DIRECTORY_SETTING), ""), Ok(String::new()));
+        assert!(validate_setting(setting(FILE_ACCESS_ROOTS_SETTING), "relative/dir").is_err());
+        assert_eq!(validate_setting(setting(FILE_ACCESS_ROOTS_SETTING), " "), Ok(String::new()));
+        assert!(validate_setting(setting(PROFILE_EXPORT_SETTING), "profile.json").is_err());
+        assert!(validate_setting(setting(PROFILE_IMPORT_SETTING), "").is_err());
+        let file = std::env::temp_dir().join("profile.json");
+        let file = file.to_str().unwrap();
+        assert_eq!(validate_setting(setting(PROFILE_IMPORT_SETTING), file), Ok(file.to_string()));
+        assert!(!setting_enabled(PROFILE_EXPORT_HISTORY_SETTING, None));
+        assert!(setting_enabled(FILE_ACCESS_SETTING, None));
+        assert!(validate_setting(setting(USER_AGENT_SETTING), "Bot/1.0\u{7}").is_err());
+        assert_eq!(validate_setting(setting(USER_AGENT_SETTING), "Bot/1.0 "), Ok("Bot/1.0".to_string()));
+        assert_eq!(validate_setting(setting(NOTIFICATION_SECONDS_SETTING), " 10"), Ok("10".to_string()));
+        assert!(validate_setting(setting(NOTIFICATION_SECONDS_SETTING), "0").is_err());
+        assert_eq!(validate_setting(setting(MEMORY_CEILING_SETTING), " 0"), Ok("0".to_string()));
+        assert!(validate_setting(setting(MEMORY_CEILING_SETTING), "-1").is_err());
+        assert!(validate_setting(setting(UPDATE_MANIFEST_SETTING), "http://example.com/latest.json").is_err());
+        assert_eq!(validate_setting(setting(UPDATE_MANIFEST_SETTING), " "), Ok(String::new()));
+        assert!(!setting_enabled(UPDATE_CHECK_SETTING, None));
+        assert_eq!(
+            validate_setting(setting("keybindings.show_console"), "shift+ctrl+j , f12"),
+            Ok("Ctrl+Shift+J, F12".to_string())
+        );
+        assert_eq!(validate_setting(setting("keybindings.show_console"), "None"), Ok(String::new()));
+        assert!(validate_setting(setting("keybindings.show_console"), "Ctrl+Shift+Jay").is_err());
+        assert_eq!(
+            validate_setting(setting(USER_STYLES_SETTING), "*  min-font-size=14px;; *.Example.com hide=.ad, .promo;"),
+            Ok("* min-font-size=14; *.example.com hide=.ad, .promo".to_string())
+        );
+        assert_eq!(validate_setting(setting(USER_STYLES_SETTING), " "), Ok(String::new()));
+        assert!(validate_setting(setting(USER_STYLES_SETTING), "* blink=true").is_err());
+        assert!(validate_setting(setting(USER_STYLES_SETTING), "hide=.ad").is_err());
+
+        assert!(setting_enabled(CONTENT_BLOCKER_SETTING, None));
+        assert!(!setting_enabled(CONTENT_BLOCKER_SETTING, Some("false")));
+        assert_eq!(setting(THEME_SETTING).next_value("dark"), Some("auto".to_string()));
+        assert_eq!(setting(HTTPS_ONLY_SETTING).next_value("false"), Some("true".to_string()));
+        assert_eq!(setting(HOMEPAGE_SETTING).next_value("x"), None);
+    }
+
+    #[test]
+    fn test_site_settings_step_through_their_values() {
+        let mut settings = SiteSettings::new(crate::domain::SiteOrigin::parse("https://example.com").unwrap());
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            settings.apply(next_site_setting(&settings, 2).unwrap());
+            seen.push(site_setting_values(&settings)[2].clone());
+        }
+        assert_eq!(seen, ["allow", "session-only", "block", "default"]);
+
+        settings.apply(next_site_setting(&settings, 0).unwrap());
+        assert_eq!(site_setting_values(&settings)[..2], ["off", "default"]);
+        assert_eq!(next_site_setting(&settings, SITE_USER_AGENT_ROW), None);
+        assert_eq!(next_site_setting(&settings, SITE_STORAGE_ROW), None);
+        let mut storage = crate::domain::StorageArea::new(settings.origin.clone());
+        assert_eq!(site_storage_value(&storage), "none");
+        let set = crate::domain::StorageChange::Set {
+            key: "k".to_string(),
+            value: "v".to_string(),
+        };
+        storage.apply(set).unwrap();
+        assert_eq!(site_storage_value(&storage), "1 item, 2 characters");
+        settings.apply(next_site_setting(&settings, 4).unwrap());
+        assert_eq!(site_setting_values(&settings)[4], "off");
+        settings.apply(reset_site_setting(4).unwrap());
+        settings.apply(next_site_setting(&settings, 5).unwrap());
+        assert_eq!(site_setting_values(&settings)[5], "off");
+        settings.apply(reset_site_setting(5).unwrap());
+        settings.apply(reset_site_setting(0).unwrap());
+        assert!(settings.is_empty());
+    }
+}
@@ -1,6 +1,76 @@
-use crate::domain::{Tab, TabId};
+use crate::domain::{Tab, TabId, WindowGeometry, WindowId};
+use super::notifications::{Notification, NotificationLog};
+use super::typed_settings::SettingsSections;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before the slowest one starts lagging
+const EVENT_CAPACITY: usize = 256;
+
+/// A change to the browser state, naming what changed rather than carrying it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateEvent {
+    TabAdded(TabId),
+    TabRemoved(TabId),
+    /// The tab's URL, title or loading flag changed
+    TabUpdated(TabId),
+    /// The globally active tab changed; `None` once no tab is active
+    ActiveTabChanged(Option<TabId>),
+    PrivateModeChanged(bool),
+    /// The tab finished loading a new document
+    PageContentChanged(TabId),
+    /// The tab's document was laid out again in place, e.g. for new user styles
+    PageRestyled(TabId),
+    /// The tab was suspended and its layout dropped until it is shown again
+    TabSuspended(TabId),
+    /// A setting listed on navigator://settings was changed, by key
+    SettingChanged(&'static str),
+    /// The typed settings changed, in the sections named
+    SettingsChanged(SettingsSections),
+    /// A download was recorded or removed
+    DownloadsChanged,
+    /// A notification was posted; read it by id with
+    /// [`BrowserState::notification`]
+    Notified(u64),
+}
+
+/// How much work a tab may do, by whether anyone can see it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TabActivity {
+    /// Shown in a visible window
+    Foreground,
+    /// Open but hidden; throttled
+    Background,
+    /// Hidden long enough that its layout was dropped; does nothing until shown
+    Suspended,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ActivityState {
+    activity: TabActivity,
+    /// When the tab last left the foreground; `None` while in it
+    hidden_since: Option<Instant>,
+}
+
+impl ActivityState {
+    fn hidden(now: Instant) -> Self {
+        Self {
+            activity: TabActivity::Background,
+            hidden_since: Some(now),
+        }
+    }
+}
+
+/// Tabs belonging to one window, in display order
+#[derive(Debug, Clone, Default)]
+struct WindowTabs {
+    tabs: Vec<TabId>,
+    active: Option<TabId>,
+    /// Where the window was last seen on screen
+    geometry: Option<WindowGeometry>,
+}
 
 /// Manages the browser's runtime state
 #[derive(Clone)]
@@ -8,6 +78,10 @@ pub struct BrowserState {
     tabs: Arc<RwLock<HashMap<TabId, Tab>>>,
     active_tab: Arc<RwLock<Option<TabId>>>,
     is_private_mode: Arc<RwLock<bool>>,
+    windows: Arc<RwLock<HashMap<WindowId, WindowTabs>>>,
+    activity: Arc<RwLock<HashMap<TabId, ActivityState>>>,
+    events: broadcast::Sender<StateEvent>,
+    notifications: Arc<NotificationLog>,
 }
 
 impl BrowserState {
@@ -16,24 +90,236 @@ impl BrowserState {
             tabs: Arc::new(RwLock::new(HashMap::new())),
             active_tab: Arc::new(RwLock::new(None)),
             is_private_mode: Arc::new(RwLock::new(false)),
+            windows: Arc::new(RwLock::new(HashMap::new())),
+            activity: Arc::new(RwLock::new(HashMap::new())),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            notifications: Arc::new(NotificationLog::new()),
+        }
+    }
+
+    /// Receive every state change made after this call
+    pub fn subscribe(&self) -> broadcast::Receiver<StateEvent> {
+        self.events.subscribe()
+    }
+
+    /// Announce that a tab finished loading a new document
+    pub fn notify_page_content_changed(&self, tab_id: TabId) {
+        self.emit(StateEvent::PageContentChanged(tab_id));
+    }
+
+    /// Announce that a tab's document was laid out again without reloading
+    pub fn notify_page_restyled(&self, tab_id: TabId) {
+        self.emit(StateEvent::PageRestyled(tab_id));
+    }
+
+    /// Announce that the setting stored under `key` changed
+    pub fn notify_setting_changed(&self, key: &'static str) {
+        self.emit(StateEvent::SettingChanged(key));
+    }
+
+    /// Announce that the typed settings changed
+    pub fn notify_settings_changed(&self, sections: SettingsSections) {
+        self.emit(StateEvent::SettingsChanged(sections));
+    }
+
+    /// Announce that the list of downloads changed
+    pub fn notify_downloads_changed(&self) {
+        self.emit(StateEvent::DownloadsChanged);
+    }
+
+    /// Show the user a short message in the windows it concerns
+    pub fn notify(&self, notification: Notification) -> u64 {
+        let id = self.notifications.push(notification);
+        self.emit(StateEvent::Notified(id));
+        id
+    }
+
+    /// A recently posted notification
+    pub fn notification(&self, id: u64) -> Option<Notification> {
+        self.notifications.get(id)
+    }
+
+    fn emit(&self, event: StateEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.events.send(event);
+    }
+
+    /// Register a new, empty window
+    pub fn open_window(&self) -> WindowId {
+        let window_id = WindowId::new();
+        if let Ok(mut windows) = self.windows.write() {
+            windows.insert(window_id, WindowTabs::default());
+        }
+        window_id
+    }
+
+    /// Remove a window along with all of its tabs
+    pub fn close_window(&self, window_id: WindowId) -> Vec<Tab> {
+        let tab_ids = match self.windows.write() {
+            Ok(mut windows) => windows.remove(&window_id).map(|w| w.tabs).unwrap_or_default(),
+            Err(_) => return Vec::new(),
+        };
+
+        let mut closed = Vec::new();
+        if let Ok(mut tabs) = self.tabs.write() {
+            for tab_id in &tab_ids {
+                if let Some(tab) = tabs.remove(tab_id) {
+                    closed.push(tab);
+                }
+            }
+        }
+        if let Ok(mut activity) = self.activity.write() {
+            for tab_id in &tab_ids {
+                activity.remove(tab_id);
+            }
+        }
+
+        let active_cleared = match self.active_tab.write() {
+            Ok(mut active) if active.is_some_and(|id| tab_ids.contains(&id)) => {
+                *active = None;
+                true
+            }
+            _ => false,
+        };
+
+        for tab in &closed {
+            self.emit(StateEvent::TabRemoved(tab.id));
+        }
+        if active_cleared {
+            self.emit(StateEvent::ActiveTabChanged(None));
+        }
+
+        closed
+    }
+
+    /// Record where a window is on screen, for saving the session
+    pub fn set_window_geometry(&self, window_id: WindowId, geometry: WindowGeometry) {
+        if let Ok(mut windows) = self.windows.write() {
+            if let Some(window) = windows.get_mut(&window_id) {
+                window.geometry = Some(geometry);
+            }
+        }
+    }
+
+    /// Where a window was last recorded to be on screen
+    pub fn window_geometry(&self, window_id: WindowId) -> Option<WindowGeometry> {
+        let windows = self.windows.read().ok()?;
+        windows.get(&window_id).and_then(|w| w.geometry.clone())
+    }
+
+    /// Get the IDs of all open windows
+    pub fn window_ids(&self) -> Vec<WindowId> {
+        if let Ok(windows) = self.windows.read() {
+            return windows.keys().copied().collect();
+        }
+        Vec::new()
+    }
+
+    /// Add a new tab to a window
+    pub fn add_tab_to_window(&self, window_id: WindowId, tab: Tab) -> TabId {
+        let tab_id = self.add_tab(tab);
+        self.move_tab_to_window(tab_id, window_id);
+        tab_id
+    }
+
+    /// Move a tab into another window, appending it to that window's tabs
+    pub fn move_tab_to_window(&self, tab_id: TabId, window_id: WindowId) {
+        if let Ok(mut windows) = self.windows.write() {
+            for window in windows.values_mut() {
+                window.tabs.retain(|id| *id != tab_id);
+                if window.active == Some(tab_id) {
+                    window.active = window.tabs.last().copied();
+                }
+            }
+            windows.entry(window_id).or_default().tabs.push(tab_id);
+        }
+    }
+
+    /// Get the window a tab belongs to
+    pub fn window_of_tab(&self, tab_id: TabId) -> Option<WindowId> {
+        if let Ok(windows) = self.windows.read() {
+            return windows
+                .iter()
+                .find(|(_, window)| window.tabs.contains(&tab_id))
+                .map(|(window_id, _)| *window_id);
+        }
+        None
+    }
+
+    /// Get the tabs of a window in display order
+    pub fn tabs_in_window(&self, window_id: WindowId) -> Vec<Tab> {
+        let tab_ids = match self.windows.read() {
+            Ok(windows) => windows
+                .get(&window_id)
+                .map(|w| w.tabs.clone())
+                .unwrap_or_default(),
+            Err(_) => return Vec::new(),
+        };
+
+        tab_ids
+            .into_iter()
+            .filter_map(|tab_id| self.get_tab(tab_id))
+            .collect()
+    }
+
+    /// Get the active tab ID of a window
+    pub fn get_active_tab_id_in_window(&self, window_id: WindowId) -> Option<TabId> {
+        if let Ok(windows) = self.windows.read() {
+            return windows.get(&window_id).and_then(|w| w.active);
         }
+        None
     }
 
-    /// Add a new tab
+    /// Get the active tab of a window
+    pub fn get_active_tab_in_window(&self, window_id: WindowId) -> Option<Tab> {
+        self.get_active_tab_id_in_window(window_id)
+            .and_then(|tab_id| self.get_tab(tab_id))
+    }
+
+    /// Add a new tab, in the background until it is made active
     pub fn add_tab(&self, tab: Tab) -> TabId {
         let tab_id = tab.id;
         if let Ok(mut tabs) = self.tabs.write() {
             tabs.insert(tab_id, tab);
         }
+        if let Ok(mut activity) = self.activity.write() {
+            activity.insert(tab_id, ActivityState::hidden(Instant::now()));
+        }
+        self.emit(StateEvent::TabAdded(tab_id));
         tab_id
     }
 
-    /// Remove a tab
+    /// Remove a tab, leaving no tab active if it was the active one
     pub fn remove_tab(&self, tab_id: TabId) -> Option<Tab> {
-        if let Ok(mut tabs) = self.tabs.write() {
-            return tabs.remove(&tab_id);
+        if let Ok(mut windows) = self.windows.write() {
+            for window in windows.values_mut() {
+                window.tabs.retain(|id| *id != tab_id);
+                if window.active == Some(tab_id) {
+                    window.active = None;
+                }
+            }
         }
-        None
+        let removed = match self.tabs.write() {
+            Ok(mut tabs) => tabs.remove(&tab_id)?,
+            Err(_) => return None,
+        };
+        if let Ok(mut activity) = self.activity.write() {
+            activity.remove(&tab_id);
+        }
+
+        let active_cleared = match self.active_tab.write() {
+            Ok(mut active) if *active == Some(tab_id) => {
+                *active = None;
+                true
+            }
+            _ => false,
+        };
+
+        self.emit(StateEvent::TabRemoved(tab_id));
+        if active_cleared {
+            self.emit(StateEvent::ActiveTabChanged(None));
+        }
+        Some(removed)
     }
 
     /// Get a tab by ID
@@ -46,9 +332,11 @@ impl BrowserState {
 
     /// Update a tab
     pub fn update_tab(&self, tab: Tab) {
+        let tab_id = tab.id;
         if let Ok(mut tabs) = self.tabs.write() {
-            tabs.insert(tab.id, tab);
+            tabs.insert(tab_id, tab);
         }
+        self.emit(StateEvent::TabUpdated(tab_id));
     }
 
     /// Get all tabs
@@ -67,10 +355,27 @@ impl BrowserState {
         0
     }
 
-    /// Set the active tab
+    /// Set the active tab, also making it the active tab of its window.
+    /// It moves to the foreground and the tab it replaces, in its window or
+    /// among tabs without one, to the background.
     pub fn set_active_tab(&self, tab_id: TabId) {
-        if let Ok(mut active) = self.active_tab.write() {
-            *active = Some(tab_id);
+        let previous = match self.active_tab.write() {
+            Ok(mut active) => active.replace(tab_id),
+            Err(_) => Some(tab_id),
+        };
+        let changed = previous != Some(tab_id);
+        let mut replaced = previous.filter(|previous| self.window_of_tab(*previous).is_none());
+        if let Ok(mut windows) = self.windows.write() {
+            if let Some(window) = windows.values_mut().find(|w| w.tabs.contains(&tab_id)) {
+                replaced = window.active.replace(tab_id);
+            }
+        }
+        if let Some(previous) = replaced.filter(|previous| *previous != tab_id) {
+            self.set_tab_activity(previous, TabActivity::Background);
+        }
+        self.set_tab_activity(tab_id, TabActivity::Foreground);
+        if changed {
+            self.emit(StateEvent::ActiveTabChanged(Some(tab_id)));
         }
     }
 
@@ -90,10 +395,69 @@ impl BrowserState {
         None
     }
 
+    /// Activity class of a tab; tabs not known here count as background
+    pub fn tab_activity(&self, tab_id: TabId) -> TabActivity {
+        self.activity
+            .read()
+            .ok()
+            .and_then(|activity| activity.get(&tab_id).map(|state| state.activity))
+            .unwrap_or(TabActivity::Background)
+    }
+
+    /// Move a tab between activity classes. Leaving the foreground starts its
+    /// hidden time, which suspension keeps counting.
+    pub fn set_tab_activity(&self, tab_id: TabId, activity: TabActivity) {
+        let suspended = match self.activity.write() {
+            Ok(mut states) => {
+                let Some(state) = states.get_mut(&tab_id) else {
+                    return;
+                };
+                let previous = std::mem::replace(&mut state.activity, activity);
+                state.hidden_since = match activity {
+                    TabActivity::Foreground => None,
+                    _ => state.hidden_since.or(Some(Instant::now())),
+                };
+                activity == TabActivity::Suspended && previous != activity
+            }
+            Err(_) => false,
+        };
+        if suspended {
+            self.emit(StateEvent::TabSuspended(tab_id));
+        }
+    }
+
+    /// How long a tab has been out of the foreground, if it is
+    pub fn hidden_for(&self, tab_id: TabId, now: Instant) -> Option<Duration> {
+        let activity = self.activity.read().ok()?;
+        let hidden_since = activity.get(&tab_id)?.hidden_since?;
+        Some(now.saturating_duration_since(hidden_since))
+    }
+
+    /// Background tabs hidden for at least `after`
+    pub fn idle_tabs(&self, now: Instant, after: Duration) -> Vec<TabId> {
+        let Ok(activity) = self.activity.read() else {
+            return Vec::new();
+        };
+        activity
+            .iter()
+            .filter(|(_, state)| state.activity == TabActivity::Background)
+            .filter(|(_, state)| {
+                state
+                    .hidden_since
+                    .is_some_and(|since| now.saturating_duration_since(since) >= after)
+            })
+            .map(|(tab_id, _)| *tab_id)
+            .collect()
+    }
+
     /// Set private mode
     pub fn set_private_mode(&self, enabled: bool) {
-        if let Ok(mut mode) = self.is_private_mode.write() {
-            *mode = enabled;
+        let changed = match self.is_private_mode.write() {
+            Ok(mut mode) => std::mem::replace(&mut *mode, enabled) != enabled,
+            Err(_) => false,
+        };
+        if changed {
+            self.emit(StateEvent::PrivateModeChanged(enabled));
         }
     }
 
@@ -107,11 +471,29 @@ impl BrowserState {
 
     /// Clear all tabs
     pub fn clear_all_tabs(&self) {
-        if let Ok(mut tabs) = self.tabs.write() {
-            tabs.clear();
+        let removed: Vec<TabId> = match self.tabs.write() {
+            Ok(mut tabs) => tabs.drain().map(|(tab_id, _)| tab_id).collect(),
+            Err(_) => Vec::new(),
+        };
+        if let Ok(mut activity) = self.activity.write() {
+            activity.clear();
+        }
+        let active_cleared = match self.active_tab.write() {
+            Ok(mut active) => active.take().is_some(),
+            Err(_) => false,
+        };
+        if let Ok(mut windows) = self.windows.write() {
+            for window in windows.values_mut() {
+                window.tabs.clear();
+                window.active = None;
+            }
+        }
+
+        for tab_id in removed {
+            self.emit(StateEvent::TabRemoved(tab_id));
         }
-        if let Ok(mut active) = self.active_tab.write() {
-            *active = None;
+        if active_cleared {
+            self.emit(StateEvent::ActiveTabChanged(None));
         }
     }
 }
@@ -125,7 +507,7 @@ impl Default for BrowserState {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::ValidatedUrl;
+    use crate::application::NotificationSeverity;
 
     #[test]
     fn test_add_and_get_tab() {
@@ -165,6 +547,78 @@ mod tests {
         assert_eq!(state.get_active_tab_id(), Some(tab_id));
     }
 
+    #[test]
+    fn test_tabs_grouped_by_window() {
+        let state = BrowserState::new();
+        let first = state.open_window();
+        let second = state.open_window();
+
+        let a = state.add_tab_to_window(first, Tab::new(false));
+        let b = state.add_tab_to_window(second, Tab::new(false));
+        state.set_active_tab(b);
+
+        assert_eq!(state.tabs_in_window(first).len(), 1);
+        assert_eq!(state.window_of_tab(b), Some(second));
+        assert_eq!(state.get_active_tab_id_in_window(second), Some(b));
+        assert_eq!(state.get_active_tab_id_in_window(first), None);
+
+        state.move_tab_to_window(a, second);
+        assert!(state.tabs_in_window(first).is_empty());
+        assert_eq!(
+            state.tabs_in_window(second).iter().map(|t| t.id).collect::<Vec<_>>(),
+            vec![b, a]
+        );
+    }
+
+    #[test]
+    fn test_close_window_removes_its_tabs() {
+        let state = BrowserState::new();
+        let first = state.open_window();
+        let second = state.open_window();
+
+        let a = state.add_tab_to_window(first, Tab::new(false));
+        state.add_tab_to_window(second, Tab::new(false));
+        state.set_active_tab(a);
+
+        let closed = state.close_window(first);
+        assert_eq!(closed.len(), 1);
+        assert_eq!(state.tab_count(), 1);
+        assert_eq!(state.get_active_tab_id(), None);
+        assert_eq!(state.window_ids(), vec![second]);
+    }
+
+    #[test]
+    fn test_activity_follows_tab_switching() {
+        let state = BrowserState::new();
+        let window = state.open_window();
+        let first = state.add_tab_to_window(window, Tab::new(false));
+        let second = state.add_tab_to_window(window, Tab::new(false));
+        let other = state.add_tab_to_window(state.open_window(), Tab::new(false));
+        let mut events = state.subscribe();
+
+        state.set_active_tab(first);
+        state.set_active_tab(other);
+        assert_eq!(state.tab_activity(first), TabActivity::Foreground);
+        assert_eq!(state.tab_activity(second), TabActivity::Background);
+
+        state.set_active_tab(second);
+        assert_eq!(state.tab_activity(first), TabActivity::Background);
+        assert_eq!(state.tab_activity(other), TabActivity::Foreground);
+        assert_eq!(state.hidden_for(second, Instant::now()), None);
+
+        let later = Instant::now() + Duration::from_secs(60);
+        assert_eq!(state.idle_tabs(later, Duration::from_secs(30)), vec![first]);
+        state.set_tab_activity(first, TabActivity::Suspended);
+        assert!(state.idle_tabs(later, Duration::from_secs(30)).is_empty());
+        // Suspension keeps counting from when the tab was hidden
+        assert!(state.hidden_for(first, later).unwrap() >= Duration::from_secs(60));
+        assert!(std::iter::from_fn(|| events.try_recv().ok()).any(|e| e == StateEvent::TabSuspended(first)));
+
+        state.set_active_tab(first);
+        assert_eq!(state.tab_activity(first), TabActivity::Foreground);
+        assert_eq!(state.tab_activity(second), TabActivity::Background);
+    }
+
     #[test]
     fn test_private_mode() {
         let state = BrowserState::new();
@@ -173,4 +627,41 @@ mod tests {
         state.set_private_mode(true);
         assert!(state.is_private_mode());
     }
+
+    #[test]
+    fn test_events_only_for_actual_changes() {
+        let state = BrowserState::new();
+        let mut events = state.subscribe();
+        let tab_id = state.add_tab(Tab::new(false));
+
+        state.set_active_tab(tab_id);
+        state.set_active_tab(tab_id);
+        state.set_private_mode(false);
+        state.set_private_mode(true);
+        state.remove_tab(tab_id);
+        state.remove_tab(tab_id);
+
+        let received: Vec<_> = std::iter::from_fn(|| events.try_recv().ok()).collect();
+        assert_eq!(
+            received,
+            vec![
+                StateEvent::TabAdded(tab_id),
+                StateEvent::ActiveTabChanged(Some(tab_id)),
+                StateEvent::PrivateModeChanged(true),
+                StateEvent::TabRemoved(tab_id),
+                StateEvent::ActiveTabChanged(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_notifications_are_read_by_event() {
+        let state = BrowserState::new();
+        let mut events = state.subscribe();
+        let notification = Notification::new("Saved", NotificationSeverity::Success);
+        let id = state.notify(notification.clone());
+
+        assert_eq!(events.try_recv().ok(), Some(StateEvent::Notified(id)));
+        assert_eq!(state.notification(id), Some(notification));
+    }
 }
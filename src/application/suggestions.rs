@@ -0,0 +1,601 @@
+use super::tab_search::{tab_query, TabMatch};
+use crate::domain::{looks_like_url, Bookmark, HistoryEntry, SuggestionProvider, TabId};
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+/// Settings key turning search engine suggestions on with "true". Off by
+/// default, since it sends what is typed to the search engine.
+pub const SEARCH_SUGGESTIONS_SETTING: &str = "search.suggestions";
+/// Pause in typing before suggestions are asked for
+pub const SUGGEST_DEBOUNCE: Duration = Duration::from_millis(150);
+/// Most search engine suggestions listed below the local ones
+pub const MAX_REMOTE_SUGGESTIONS: usize = 3;
+/// Shown before search engine suggestions to tell them from visited pages
+pub const SEARCH_SUGGESTION_PREFIX: &str = "Search: ";
+/// Most bookmarks and visited pages listed
+pub const MAX_LOCAL_SUGGESTIONS: usize = 6;
+
+pub fn parse_search_suggestions(value: &str) -> bool {
+    value.trim().eq_ignore_ascii_case("true")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuggestionKind {
+    /// From history or bookmarks
+    Local,
+    /// A query suggested by the search engine
+    Search,
+}
+
+/// What is known about a suggestion, shown beside it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuggestionContext {
+    None,
+    /// A page open in another tab, which choosing switches to
+    OpenTab { tab: TabId, title: String },
+    /// A bookmarked page, filed in `folder` if it is in one
+    Bookmark { folder: Option<String> },
+    /// A page visited before, ranked among the others by its frecency
+    History { visit_count: i32, last_visited: DateTime<Utc>, frecency: i64 },
+    /// A query suggested by the named search engine
+    Engine(String),
+}
+
+/// One line of the address bar's suggestion list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub text: String,
+    pub kind: SuggestionKind,
+    pub context: SuggestionContext,
+}
+
+impl Suggestion {
+    pub fn local(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            kind: SuggestionKind::Local,
+            context: SuggestionContext::None,
+        }
+    }
+
+    pub fn search(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            kind: SuggestionKind::Search,
+            context: SuggestionContext::None,
+        }
+    }
+
+    pub fn with_context(mut self, context: SuggestionContext) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn open_tab(tab: TabMatch) -> Self {
+        Self::local(tab.url.to_string()).with_context(SuggestionContext::OpenTab {
+            tab: tab.id,
+            title: tab.title,
+        })
+    }
+
+    fn bookmark(bookmark: Bookmark) -> Self {
+        Self::local(bookmark.url.to_string()).with_context(SuggestionContext::Bookmark {
+            folder: bookmark.folder.filter(|folder| !folder.trim().is_empty()),
+        })
+    }
+
+    fn visited(entry: HistoryEntry, frecency: i64) -> Self {
+        Self::local(entry.url.to_string()).with_context(SuggestionContext::History {
+            visit_count: entry.visit_count,
+            last_visited: entry.visited_at,
+            frecency,
+        })
+    }
+
+    /// Text as listed, with search suggestions marked as such
+    pub fn label(&self) -> String {
+        match self.kind {
+            SuggestionKind::Local => self.text.clone(),
+            SuggestionKind::Search => format!("{}{}", SEARCH_SUGGESTION_PREFIX, self.text),
+        }
+    }
+
+    /// What is shown beside the label as of `now`: that open tabs are
+    /// switched to, a star and the folder of bookmarks, how often and when
+    /// visited pages were, and the engine that suggested a query
+    pub fn detail(&self, now: DateTime<Utc>) -> Option<String> {
+        match &self.context {
+            SuggestionContext::None => None,
+            SuggestionContext::OpenTab { title, .. } if title.trim().is_empty() => {
+                Some("Switch to tab".to_string())
+            }
+            SuggestionContext::OpenTab { title, .. } => Some(format!("Switch to tab · {}", title)),
+            SuggestionContext::Bookmark { folder: None } => Some("★".to_string()),
+            SuggestionContext::Bookmark { folder: Some(folder) } => Some(format!("★ {}", folder)),
+            SuggestionContext::History { visit_count, last_visited, .. } => {
+                let visits = match visit_count {
+                    1 => "visited once".to_string(),
+                    count => format!("visited {} times", count),
+                };
+                Some(format!("{} · last visited {}", visits, relative_time(*last_visited, now)))
+            }
+            SuggestionContext::Engine(name) => Some(name.clone()),
+        }
+    }
+}
+
+/// How long before `now` `then` was, as in "3 days ago"
+pub fn relative_time(then: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let elapsed = now.signed_duration_since(then);
+    let ago = |count: i64, unit: &str| match count {
+        1 => format!("1 {} ago", unit),
+        count => format!("{} {}s ago", count, unit),
+    };
+    match elapsed.num_days() {
+        0 if elapsed.num_minutes() < 1 => "just now".to_string(),
+        0 if elapsed.num_hours() < 1 => ago(elapsed.num_minutes(), "minute"),
+        0 => ago(elapsed.num_hours(), "hour"),
+        1 => "yesterday".to_string(),
+        days @ 2..=6 => ago(days, "day"),
+        days @ 7..=29 => ago(days / 7, "week"),
+        days @ 30..=364 => ago(days / 30, "month"),
+        days => ago(days / 365, "year"),
+    }
+}
+
+/// What the address bar completes `input` to inline when `url` is the top
+/// hit: its host while `input` is part of it, else the whole URL, without
+/// the scheme and with "www." only if typed. `None` unless `url` continues
+/// what was typed.
+pub fn inline_completion(input: &str, url: &str) -> Option<String> {
+    if input.is_empty() || input.contains(char::is_whitespace) {
+        return None;
+    }
+    let address = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let forms = [address.strip_prefix("www."), Some(address)];
+    let form = forms
+        .into_iter()
+        .flatten()
+        .find(|form| form.get(..input.len()).is_some_and(|start| start.eq_ignore_ascii_case(input)))?;
+    let host_end = form.find(['/', '?', '#']).unwrap_or(form.len());
+    let completion = if input.len() < host_end {
+        &form[..host_end]
+    } else {
+        form.strip_suffix('/').unwrap_or(form)
+    };
+    (completion.len() > input.len()).then(|| completion.to_string())
+}
+
+/// The inline completion of `input` by the top hit, if it is a page
+pub fn top_completion(input: &str, suggestions: &[Suggestion]) -> Option<String> {
+    suggestions
+        .first()
+        .filter(|suggestion| suggestion.kind == SuggestionKind::Local)
+        .and_then(|suggestion| inline_completion(input, &suggestion.text))
+}
+
+/// The open tab that Enter switches to instead of loading what the address
+/// bar holds, with what it must hold for that: the top hit, if it is an
+/// open tab and `input` either asks for tabs alone or is completed inline
+/// by it
+pub fn tab_switch(input: &str, suggestions: &[Suggestion]) -> Option<(String, TabId)> {
+    let top = suggestions.first()?;
+    let SuggestionContext::OpenTab { tab, .. } = top.context else {
+        return None;
+    };
+    if tab_query(input).is_some() {
+        return Some((input.to_string(), tab));
+    }
+    inline_completion(input, &top.text).map(|completed| (completed, tab))
+}
+
+/// Up to `MAX_LOCAL_SUGGESTIONS` open tabs, bookmarks and visited pages
+/// matching `input`, once each, with a page open in a tab suggested as
+/// that tab. Pages that complete it inline come first; after that tabs,
+/// then bookmarks, then visited pages by their frecency, given with each.
+pub fn local_suggestions(
+    input: &str,
+    tabs: Vec<TabMatch>,
+    bookmarks: Vec<Bookmark>,
+    history: Vec<(HistoryEntry, i64)>,
+) -> Vec<Suggestion> {
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    let found = tabs
+        .into_iter()
+        .map(Suggestion::open_tab)
+        .chain(bookmarks.into_iter().map(Suggestion::bookmark))
+        .chain(history.into_iter().map(|(entry, frecency)| Suggestion::visited(entry, frecency)));
+    for suggestion in found {
+        if !suggestions.iter().any(|listed| listed.text == suggestion.text) {
+            suggestions.push(suggestion);
+        }
+    }
+    suggestions.sort_by_key(|suggestion| {
+        let (source, visits) = match suggestion.context {
+            SuggestionContext::OpenTab { .. } => (0, 0),
+            SuggestionContext::History { frecency, .. } => (1, frecency),
+            _ => (1, i64::MAX),
+        };
+        (inline_completion(input, &suggestion.text).is_none(), source, std::cmp::Reverse(visits))
+    });
+    suggestions.truncate(MAX_LOCAL_SUGGESTIONS);
+    suggestions
+}
+
+/// Local suggestions followed by up to `MAX_REMOTE_SUGGESTIONS` remote
+/// ones not already listed, credited to `engine` if named
+pub fn merge_suggestions(mut local: Vec<Suggestion>, remote: Vec<String>, engine: Option<&str>) -> Vec<Suggestion> {
+    let mut added = 0;
+    for text in remote {
+        if added == MAX_REMOTE_SUGGESTIONS {
+            break;
+        }
+        if local.iter().any(|suggestion| suggestion.text.eq_ignore_ascii_case(&text)) {
+            continue;
+        }
+        let suggestion = Suggestion::search(text);
+        local.push(match engine {
+            Some(name) => suggestion.with_context(SuggestionContext::Engine(name.to_string())),
+            None => suggestion,
+        });
+        added += 1;
+    }
+    local
+}
+
+/// Search engine suggestions for one address bar: asked for once typing
+/// pauses, with each keystroke cancelling the request before it
+pub struct RemoteSuggestions {
+    provider: Arc<dyn SuggestionProvider>,
+    runtime: Handle,
+    enabled: bool,
+    in_flight: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl RemoteSuggestions {
+    /// Disabled until turned on; must be created inside a Tokio runtime,
+    /// which runs the requests
+    pub fn new(provider: Arc<dyn SuggestionProvider>) -> Self {
+        Self {
+            provider,
+            runtime: Handle::current(),
+            enabled: false,
+            in_flight: Mutex::new(None),
+        }
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Call on every change to the typed text. Unless suggestions are off,
+    /// the tab is private or the text looks like a URL, `deliver` gets the
+    /// engine's suggestions after `SUGGEST_DEBOUNCE` without another call.
+    /// Failures deliver nothing, leaving the local suggestions.
+    pub fn request(&self, input: &str, private: bool, deliver: impl FnOnce(Vec<String>) + Send + 'static) {
+        self.cancel();
+        let query = input.trim();
+        if !self.enabled || private || query.is_empty() || looks_like_url(query) {
+            return;
+        }
+
+        let provider = self.provider.clone();
+        let query = query.to_string();
+        let task = self.runtime.spawn(async move {
+            tokio::time::sleep(SUGGEST_DEBOUNCE).await;
+            match provider.suggest(&query).await {
+                Ok(suggestions) => deliver(suggestions),
+                Err(e) => tracing::debug!("No search suggestions: {}", e),
+            }
+        });
+        if let Ok(mut in_flight) = self.in_flight.lock() {
+            *in_flight = Some(task);
+        }
+    }
+
+    /// Drop a pending or in-flight request, e.g. when the address bar closes
+    pub fn cancel(&self) {
+        if let Some(task) = self.in_flight.lock().ok().and_then(|mut in_flight| in_flight.take()) {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for RemoteSuggestions {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{NetworkError, Visit, VisitType};
+    use async_trait::async_trait;
+    use tokio::sync::mpsc;
+
+    /// Answers after `delay` with the query and two completions of it
+    struct FakeEndpoint {
+        delay: Duration,
+        fail: bool,
+        queries: Mutex<Vec<String>>,
+    }
+
+    impl FakeEndpoint {
+        fn new(delay: Duration) -> Arc<Self> {
+            Arc::new(Self {
+                delay,
+                fail: false,
+                queries: Mutex::new(Vec::new()),
+            })
+        }
+
+        fn queries(&self) -> Vec<String> {
+            self.queries.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl SuggestionProvider for FakeEndpoint {
+        async fn suggest(&self, query: &str) -> Result<Vec<String>, NetworkError> {
+            self.queries.lock().unwrap().push(query.to_string());
+            tokio::time::sleep(self.delay).await;
+            if self.fail {
+                return Err(NetworkError::Timeout);
+            }
+            Ok(vec![format!("{} one", query), format!("{} two", query)])
+        }
+    }
+
+    fn request(suggestions: &RemoteSuggestions, input: &str, results: &mpsc::UnboundedSender<Vec<String>>) {
+        let results = results.clone();
+        suggestions.request(input, false, move |found| {
+            let _ = results.send(found);
+        });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_wait_for_typing_to_pause() {
+        let endpoint = FakeEndpoint::new(Duration::ZERO);
+        let suggestions = RemoteSuggestions::new(endpoint.clone()).with_enabled(true);
+        let (sender, mut results) = mpsc::unbounded_channel();
+
+        for input in ["r", "ru", "rus"] {
+            request(&suggestions, input, &sender);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(endpoint.queries().is_empty());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(endpoint.queries(), vec!["rus"]);
+        assert_eq!(results.recv().await.unwrap(), vec!["rus one", "rus two"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keystroke_cancels_request_in_flight() {
+        let endpoint = FakeEndpoint::new(Duration::from_millis(500));
+        let suggestions = RemoteSuggestions::new(endpoint.clone()).with_enabled(true);
+        let (sender, mut results) = mpsc::unbounded_channel();
+
+        request(&suggestions, "rust", &sender);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(endpoint.queries(), vec!["rust"]);
+        request(&suggestions, "rust book", &sender);
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(endpoint.queries(), vec!["rust", "rust book"]);
+        assert_eq!(results.recv().await.unwrap(), vec!["rust book one", "rust book two"]);
+        assert!(results.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_nothing_sent_when_off_private_or_url() {
+        let endpoint = FakeEndpoint::new(Duration::ZERO);
+        let (sender, mut results) = mpsc::unbounded_channel();
+
+        let off = RemoteSuggestions::new(endpoint.clone());
+        request(&off, "rust", &sender);
+        let on = RemoteSuggestions::new(endpoint.clone()).with_enabled(true);
+        on.request("rust", true, |_| panic!("private tabs get no suggestions"));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        request(&on, "example.com/pa", &sender);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(endpoint.queries().is_empty());
+
+        let failing = Arc::new(FakeEndpoint {
+            delay: Duration::ZERO,
+            fail: true,
+            queries: Mutex::new(Vec::new()),
+        });
+        let unreachable = RemoteSuggestions::new(failing.clone()).with_enabled(true);
+        request(&unreachable, "rust", &sender);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(failing.queries(), vec!["rust"]);
+        drop(sender);
+        assert_eq!(results.recv().await, None);
+    }
+
+    #[test]
+    fn test_remote_suggestions_follow_local_ones() {
+        let local = vec![Suggestion::local("rust"), Suggestion::local("https://rust-lang.org/")];
+        let remote = ["Rust", "rust book", "rustup", "rust analyzer", "rustc"].map(String::from).to_vec();
+
+        let merged = merge_suggestions(local, remote, Some("DuckDuckGo"));
+        let labels: Vec<String> = merged.iter().map(Suggestion::label).collect();
+        assert_eq!(
+            labels,
+            vec!["rust", "https://rust-lang.org/", "Search: rust book", "Search: rustup", "Search: rust analyzer"]
+        );
+        let now = Utc::now();
+        assert_eq!(merged[0].detail(now), None);
+        assert_eq!(merged[2].detail(now).as_deref(), Some("DuckDuckGo"));
+        assert!(parse_search_suggestions(" TRUE "));
+        assert!(!parse_search_suggestions("1"));
+    }
+
+    fn bookmark(url: &str, folder: Option<&str>) -> Bookmark {
+        Bookmark {
+            id: 1,
+            title: String::new(),
+            url: crate::domain::ValidatedUrl::parse(url).unwrap(),
+            folder: folder.map(String::from),
+            position: 0,
+            created_at: Utc::now(),
+            tags: Vec::new(),
+        }
+    }
+
+    /// A page visited `visit_count` times, none of them recorded one by one,
+    /// with its frecency as of `visited_at`
+    fn visit(url: &str, visit_count: i32, visited_at: DateTime<Utc>) -> (HistoryEntry, i64) {
+        let entry = HistoryEntry {
+            id: 1,
+            url: crate::domain::ValidatedUrl::parse(url).unwrap(),
+            title: String::new(),
+            visited_at,
+            visit_count,
+        };
+        let frecency = entry.frecency(&[], visited_at);
+        (entry, frecency)
+    }
+
+    #[test]
+    fn test_typed_and_recent_visits_rank_higher() {
+        let now = Utc::now();
+        let days = chrono::Duration::days;
+        let (typed, _) = visit("https://docs.example/", 3, now - days(1));
+        let (linked, _) = visit("https://blog.example/", 5, now - days(1));
+        let visits = |entry: &HistoryEntry, visit_type, age| Visit {
+            history_id: entry.id,
+            visited_at: now - age,
+            visit_type,
+        };
+        let typed_visits = vec![visits(&typed, VisitType::Typed, days(1)); 3];
+        let linked_visits = vec![visits(&linked, VisitType::Link, days(1)); 5];
+        assert_eq!(typed.frecency(&typed_visits, now), 600);
+        assert_eq!(linked.frecency(&linked_visits, now), 500);
+
+        // Visits of other entries don't count, and old ones count for less
+        assert_eq!(typed.frecency(&[], now), 300);
+        let other = HistoryEntry { id: 2, ..linked.clone() };
+        assert_eq!(other.frecency(&linked_visits, now), 500);
+        let old = vec![visits(&linked, VisitType::Link, days(200)); 5];
+        assert_eq!(linked.frecency(&old, now), 50);
+        // Visits counted but not recorded count as links at the last visit
+        let reloads = vec![visits(&linked, VisitType::Reload, days(1)); 2];
+        assert_eq!(linked.frecency(&reloads, now), 350);
+
+        let suggestions = local_suggestions(
+            "example",
+            Vec::new(),
+            Vec::new(),
+            vec![(linked.clone(), 500), (typed.clone(), 600)],
+        );
+        assert_eq!(suggestions[0].text, "https://docs.example/");
+    }
+
+    #[test]
+    fn test_suggestions_say_where_they_come_from() {
+        let now = Utc::now();
+        let suggestions = local_suggestions(
+            "wik",
+            Vec::new(),
+            vec![bookmark("https://docs.rs/wiki", Some("Work/Rust")), bookmark("https://example.com/wik", None)],
+            vec![
+                visit("https://www.wikipedia.org/", 12, now - chrono::Duration::days(2)),
+                visit("https://example.com/wik", 3, now),
+                visit("https://wiktionary.org/", 1, now - chrono::Duration::minutes(5)),
+            ],
+        );
+        let listed: Vec<(&str, Option<String>)> = suggestions
+            .iter()
+            .map(|suggestion| (suggestion.text.as_str(), suggestion.detail(now)))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                (
+                    "https://www.wikipedia.org/",
+                    Some("visited 12 times · last visited 2 days ago".to_string())
+                ),
+                ("https://wiktionary.org/", Some("visited once · last visited 5 minutes ago".to_string())),
+                ("https://docs.rs/wiki", Some("★ Work/Rust".to_string())),
+                ("https://example.com/wik", Some("★".to_string())),
+            ]
+        );
+        assert_eq!(top_completion("wik", &suggestions).as_deref(), Some("wikipedia.org"));
+        assert_eq!(top_completion("wik", &[Suggestion::search("wikipedia")]), None);
+    }
+
+    #[test]
+    fn test_open_tab_wins_over_the_same_page_in_history() {
+        let now = Utc::now();
+        let tab = TabId::new();
+        let open = TabMatch {
+            id: tab,
+            title: "Rust".to_string(),
+            url: crate::domain::ValidatedUrl::parse("https://www.wikipedia.org/wiki/Rust").unwrap(),
+            score: 40,
+        };
+        let suggestions = local_suggestions(
+            "wik",
+            vec![open],
+            vec![bookmark("https://www.wikipedia.org/wiki/Rust", None)],
+            vec![
+                visit("https://www.wikipedia.org/wiki/Rust", 30, now),
+                visit("https://wiktionary.org/", 50, now),
+            ],
+        );
+        let listed: Vec<(&str, Option<String>)> = suggestions
+            .iter()
+            .map(|suggestion| (suggestion.text.as_str(), suggestion.detail(now)))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                ("https://www.wikipedia.org/wiki/Rust", Some("Switch to tab · Rust".to_string())),
+                ("https://wiktionary.org/", Some("visited 50 times · last visited just now".to_string())),
+            ]
+        );
+
+        // Enter switches to the tab once its completion is taken, or for a tab query
+        assert_eq!(tab_switch("wik", &suggestions), Some(("wikipedia.org".to_string(), tab)));
+        assert_eq!(tab_switch("%rust", &suggestions), Some(("%rust".to_string(), tab)));
+        assert_eq!(tab_switch("rust", &suggestions), None);
+        assert_eq!(tab_switch("wik", &suggestions[1..]), None);
+    }
+
+    #[test]
+    fn test_inline_completion_continues_what_was_typed() {
+        let url = "https://www.wikipedia.org/wiki/Rust";
+        assert_eq!(inline_completion("wik", url).as_deref(), Some("wikipedia.org"));
+        assert_eq!(inline_completion("WIK", url).as_deref(), Some("wikipedia.org"));
+        assert_eq!(inline_completion("www.w", url).as_deref(), Some("www.wikipedia.org"));
+        assert_eq!(inline_completion("wikipedia.org/", url).as_deref(), Some("wikipedia.org/wiki/Rust"));
+        assert_eq!(inline_completion("wikipedia.org/wiki/Rust", url), None);
+        assert_eq!(inline_completion("pedia", url), None);
+        assert_eq!(inline_completion("wik rust", url), None);
+        assert_eq!(inline_completion("", url), None);
+    }
+
+    #[test]
+    fn test_relative_time() {
+        let now = Utc::now();
+        let ago = |duration: chrono::Duration| relative_time(now - duration, now);
+        assert_eq!(ago(chrono::Duration::seconds(20)), "just now");
+        assert_eq!(ago(chrono::Duration::minutes(1)), "1 minute ago");
+        assert_eq!(ago(chrono::Duration::hours(5)), "5 hours ago");
+        assert_eq!(ago(chrono::Duration::hours(30)), "yesterday");
+        assert_eq!(ago(chrono::Duration::days(15)), "2 weeks ago");
+        assert_eq!(ago(chrono::Duration::days(95)), "3 months ago");
+        assert_eq!(ago(chrono::Duration::days(800)), "2 years ago");
+    }
+}
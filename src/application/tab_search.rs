@@ -0,0 +1,236 @@
+use super::fuzzy::FuzzyCandidate;
+use super::state::{BrowserState, StateEvent};
+use crate::domain::{TabId, ValidatedUrl};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+/// Most open tabs suggested besides bookmarks and history
+pub const MAX_TAB_SUGGESTIONS: usize = 3;
+/// Typed before a query to look through open tabs only, as in "%docs" or
+/// "tab:docs"
+pub const TAB_SEARCH_PREFIXES: &[&str] = &["%", "tab:"];
+
+/// What to look for among open tabs if `input` asks for them alone
+pub fn tab_query(input: &str) -> Option<&str> {
+    let input = input.trim_start();
+    TAB_SEARCH_PREFIXES.iter().find_map(|prefix| {
+        let start = input.get(..prefix.len())?;
+        start.eq_ignore_ascii_case(prefix).then(|| input[prefix.len()..].trim())
+    })
+}
+
+/// An open tab matching a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabMatch {
+    pub id: TabId,
+    pub title: String,
+    pub url: ValidatedUrl,
+    pub score: i32,
+}
+
+struct IndexedTab {
+    title: String,
+    url: ValidatedUrl,
+    is_private: bool,
+    last_accessed: DateTime<Utc>,
+    title_key: FuzzyCandidate,
+    url_key: FuzzyCandidate,
+}
+
+impl IndexedTab {
+    /// Best score of `query` on the title, or for less on the address
+    fn score(&self, query: &str) -> Option<i32> {
+        let title = self.title_key.matches(query).map(|found| found.score);
+        let url = self.url_key.matches(query).map(|found| found.score * 3 / 4);
+        title.max(url)
+    }
+}
+
+/// Titles and addresses of the open tabs, lowercased once and kept up to
+/// date from the state's events, so that looking through hundreds of tabs
+/// on every key typed stays cheap
+pub struct TabIndex {
+    state: BrowserState,
+    events: Mutex<broadcast::Receiver<StateEvent>>,
+    tabs: Mutex<HashMap<TabId, IndexedTab>>,
+}
+
+impl TabIndex {
+    pub fn new(state: BrowserState) -> Self {
+        let events = Mutex::new(state.subscribe());
+        let index = Self {
+            state,
+            events,
+            tabs: Mutex::new(HashMap::new()),
+        };
+        if let Ok(mut tabs) = index.tabs.lock() {
+            index.rebuild(&mut tabs);
+        }
+        index
+    }
+
+    /// Up to `limit` tabs other than `from` whose title or address matches
+    /// `query`, best first and then most recently used. Private tabs are
+    /// only found from private ones, and tabs that never loaded a page not
+    /// at all.
+    pub fn search(&self, query: &str, from: TabId, limit: usize) -> Vec<TabMatch> {
+        let Ok(mut tabs) = self.tabs.lock() else {
+            return Vec::new();
+        };
+        self.catch_up(&mut tabs);
+        let private = tabs.get(&from).is_some_and(|tab| tab.is_private)
+            || self.state.get_tab(from).is_some_and(|tab| tab.is_private);
+        let mut found: Vec<(&TabId, &IndexedTab, i32)> = tabs
+            .iter()
+            .filter(|(id, tab)| **id != from && (private || !tab.is_private))
+            .filter_map(|(id, tab)| Some((id, tab, tab.score(query)?)))
+            .collect();
+        found.sort_by_key(|(_, tab, score)| (std::cmp::Reverse(*score), std::cmp::Reverse(tab.last_accessed)));
+        found
+            .into_iter()
+            .take(limit)
+            .map(|(id, tab, score)| TabMatch {
+                id: *id,
+                title: tab.title.clone(),
+                url: tab.url.clone(),
+                score,
+            })
+            .collect()
+    }
+
+    /// Apply the tab changes announced since last looked
+    fn catch_up(&self, tabs: &mut HashMap<TabId, IndexedTab>) {
+        let Ok(mut events) = self.events.lock() else {
+            return;
+        };
+        loop {
+            match events.try_recv() {
+                Ok(
+                    StateEvent::TabAdded(id)
+                    | StateEvent::TabUpdated(id)
+                    | StateEvent::TabRemoved(id)
+                    | StateEvent::ActiveTabChanged(Some(id)),
+                ) => self.refresh(tabs, id),
+                Ok(_) => {}
+                Err(TryRecvError::Lagged(_)) => self.rebuild(tabs),
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+    }
+
+    fn rebuild(&self, tabs: &mut HashMap<TabId, IndexedTab>) {
+        tabs.clear();
+        for tab in self.state.get_all_tabs() {
+            self.refresh(tabs, tab.id);
+        }
+    }
+
+    /// Index the tab as it is now; its title and address are only
+    /// lowercased again if they changed
+    fn refresh(&self, tabs: &mut HashMap<TabId, IndexedTab>, id: TabId) {
+        let Some(tab) = self.state.get_tab(id) else {
+            tabs.remove(&id);
+            return;
+        };
+        let Some(url) = tab.url else {
+            tabs.remove(&id);
+            return;
+        };
+        let unchanged = |indexed: &&mut IndexedTab| indexed.title == tab.title && indexed.url == url;
+        if let Some(indexed) = tabs.get_mut(&id).filter(unchanged) {
+            indexed.last_accessed = tab.last_accessed;
+            return;
+        }
+        tabs.insert(
+            id,
+            IndexedTab {
+                title_key: FuzzyCandidate::new(&tab.title),
+                url_key: FuzzyCandidate::new(url.as_str()),
+                title: tab.title,
+                url,
+                is_private: tab.is_private,
+                last_accessed: tab.last_accessed,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Tab;
+
+    fn open(state: &BrowserState, title: &str, url: &str, private: bool) -> TabId {
+        let mut tab = Tab::new(private);
+        tab.title = title.to_string();
+        tab.url = ValidatedUrl::parse(url).ok();
+        state.add_tab(tab)
+    }
+
+    fn titles(found: Vec<TabMatch>) -> Vec<String> {
+        found.into_iter().map(|tab| tab.title).collect()
+    }
+
+    #[test]
+    fn test_tab_queries_are_marked_by_prefix() {
+        assert_eq!(tab_query("%docs"), Some("docs"));
+        assert_eq!(tab_query(" TAB: rust book"), Some("rust book"));
+        assert_eq!(tab_query("%"), Some(""));
+        assert_eq!(tab_query("tabs"), None);
+        assert_eq!(tab_query("100%"), None);
+    }
+
+    #[test]
+    fn test_index_follows_tab_changes() {
+        let state = BrowserState::new();
+        let from = open(&state, "", "about:blank", false);
+        let docs = open(&state, "Rust docs", "https://doc.rust-lang.org/", false);
+        let index = TabIndex::new(state.clone());
+        assert_eq!(titles(index.search("rust", from, 10)), ["Rust docs"]);
+
+        // Changes after the index was built are picked up
+        let book = open(&state, "The book", "https://doc.rust-lang.org/book/", false);
+        let mut renamed = state.get_tab(docs).unwrap();
+        renamed.title = "Standard library".to_string();
+        state.update_tab(renamed);
+        assert_eq!(titles(index.search("stdlib", from, 10)), ["Standard library"]);
+        assert_eq!(titles(index.search("rustbook", from, 10)), ["The book"]);
+
+        state.remove_tab(book);
+        assert_eq!(titles(index.search("book", from, 10)), Vec::<String>::new());
+        // The tab searched from is never found
+        assert!(index.search("", docs, 10).iter().all(|tab| tab.id != docs));
+    }
+
+    #[test]
+    fn test_private_tabs_are_only_found_from_private_ones() {
+        let state = BrowserState::new();
+        let normal = open(&state, "", "about:blank", false);
+        let private = open(&state, "", "about:blank", true);
+        open(&state, "Secret plans", "https://example.com/secret", true);
+        open(&state, "Public plans", "https://example.com/public", false);
+        let index = TabIndex::new(state);
+
+        assert_eq!(titles(index.search("plans", normal, 10)), ["Public plans"]);
+        assert_eq!(index.search("plans", private, 10).len(), 2);
+    }
+
+    #[test]
+    fn test_hundreds_of_tabs_are_searched_quickly() {
+        let state = BrowserState::new();
+        let from = open(&state, "", "about:blank", false);
+        for n in 0..500 {
+            let url = format!("https://tracker.example/issues/{}", n);
+            open(&state, &format!("Issue {} - tracker", n), &url, false);
+        }
+        let index = TabIndex::new(state);
+        let started = std::time::Instant::now();
+        for query in ["issue 499", "tracker", "zz"] {
+            index.search(query, from, MAX_TAB_SUGGESTIONS);
+        }
+        assert!(started.elapsed() < std::time::Duration::from_secs(1), "{:?}", started.elapsed());
+        assert_eq!(titles(index.search("issue 499", from, 1)), ["Issue 499 - tracker"]);
+    }
+}
@@ -0,0 +1,1350 @@
+//! In-memory stand-ins for the repositories and rendering engine
+//!
+//! Available to unit tests and, with the `test-util` feature, to integration
+//! tests, so application code can be exercised without SQLite or a network.
+
+use crate::domain::{
+    BlockedRequest, Bookmark, BookmarkRepository, CachedPage, CachedPageSummary, Certificate, Clock, ConsoleLevel,
+    ConsoleMessage, CustomCa, CustomCaRepository, DeletedHistory, DocumentFetch, Download, DownloadError,
+    DownloadRepository, DownloadWriter, ExternalLauncher, HistoryEntry, HistoryRepository, HttpVersion, NetworkError,
+    NewBookmark, NewCustomCa, NewDownload, NewHistoryEntry, NewSearchEngine, NewUserScript, NewUserStyle, PageArchiver,
+    PageCacheRepository, PagePrinter, PaperSize, RenderError, RenderingEngine, SavedWindow, SearchEngine,
+    SearchEngineRepository, SettingsRepository, SiteOrigin, SiteSettings, SiteSettingsRepository, StorageArea,
+    StorageChange, StorageError, Tab, TabId, TabRepository, TlsInspector, TlsSession, UserScript,
+    UserScriptRepository, UserStyle, UserStyleRepository, ValidatedUrl, Visit, WebStorageRepository, WindowId,
+    bookmark_move_positions, BOOKMARK_POSITION_STEP,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+fn read<T>(lock: &RwLock<T>) -> Result<RwLockReadGuard<'_, T>> {
+    lock.read().map_err(|_| StorageError::Database("Repository lock poisoned".to_string()))
+}
+
+fn write<T>(lock: &RwLock<T>) -> Result<RwLockWriteGuard<'_, T>> {
+    lock.write().map_err(|_| StorageError::Database("Repository lock poisoned".to_string()))
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryTabRepository {
+    tabs: RwLock<Vec<Tab>>,
+    windows: RwLock<Vec<SavedWindow>>,
+}
+
+impl InMemoryTabRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl TabRepository for InMemoryTabRepository {
+    async fn save(&self, tab: &Tab) -> Result<()> {
+        let mut tabs = write(&self.tabs)?;
+        match tabs.iter_mut().find(|t| t.id == tab.id) {
+            Some(existing) => *existing = tab.clone(),
+            None => tabs.push(tab.clone()),
+        }
+        Ok(())
+    }
+
+    async fn find_by_id(&self, id: TabId) -> Result<Option<Tab>> {
+        Ok(read(&self.tabs)?.iter().find(|t| t.id == id).cloned())
+    }
+
+    async fn find_all(&self) -> Result<Vec<Tab>> {
+        Ok(read(&self.tabs)?.clone())
+    }
+
+    async fn delete(&self, id: TabId) -> Result<()> {
+        write(&self.tabs)?.retain(|t| t.id != id);
+        Ok(())
+    }
+
+    async fn save_session(&self, windows: Vec<SavedWindow>) -> Result<()> {
+        for tab in windows.iter().flat_map(|window| &window.tabs) {
+            self.save(tab).await?;
+        }
+        *write(&self.windows)? = windows;
+        Ok(())
+    }
+
+    async fn restore_session(&self) -> Result<Vec<SavedWindow>> {
+        let mut windows = read(&self.windows)?.clone();
+        let orphans: Vec<Tab> = read(&self.tabs)?
+            .iter()
+            .filter(|tab| !windows.iter().any(|window| window.tabs.iter().any(|t| t.id == tab.id)))
+            .cloned()
+            .collect();
+        if !orphans.is_empty() {
+            if windows.is_empty() {
+                windows.push(SavedWindow {
+                    id: WindowId::new(),
+                    geometry: None,
+                    active_tab: None,
+                    tabs: Vec::new(),
+                });
+            }
+            windows[0].tabs.extend(orphans);
+        }
+        Ok(windows)
+    }
+
+    async fn clear_session(&self) -> Result<()> {
+        write(&self.tabs)?.clear();
+        write(&self.windows)?.clear();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryBookmarkRepository {
+    bookmarks: RwLock<Vec<Bookmark>>,
+}
+
+impl InMemoryBookmarkRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl BookmarkRepository for InMemoryBookmarkRepository {
+    async fn save(&self, bookmark: NewBookmark) -> Result<Bookmark> {
+        let mut bookmarks = write(&self.bookmarks)?;
+        let last = bookmarks.iter().filter(|b| b.folder == bookmark.folder).map(|b| b.position).max();
+        let saved = Bookmark {
+            id: bookmarks.iter().map(|b| b.id).max().unwrap_or(0) + 1,
+            title: bookmark.title,
+            url: bookmark.url,
+            folder: bookmark.folder,
+            position: last.unwrap_or(0) + BOOKMARK_POSITION_STEP,
+            created_at: bookmark.created_at,
+            tags: bookmark.tags,
+        };
+        bookmarks.push(saved.clone());
+        Ok(saved)
+    }
+
+    async fn find_by_id(&self, id: i64) -> Result<Option<Bookmark>> {
+        Ok(read(&self.bookmarks)?.iter().find(|b| b.id == id).cloned())
+    }
+
+    async fn find_all(&self) -> Result<Vec<Bookmark>> {
+        let mut bookmarks = read(&self.bookmarks)?.clone();
+        bookmarks.sort_by_key(|b| (b.position, b.created_at, b.id));
+        Ok(bookmarks)
+    }
+
+    async fn find_by_folder(&self, folder: &str) -> Result<Vec<Bookmark>> {
+        let mut bookmarks: Vec<Bookmark> = read(&self.bookmarks)?
+            .iter()
+            .filter(|b| b.folder.as_deref() == Some(folder))
+            .cloned()
+            .collect();
+        bookmarks.sort_by_key(|b| (b.position, b.created_at, b.id));
+        Ok(bookmarks)
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<Bookmark>> {
+        Ok(read(&self.bookmarks)?
+            .iter()
+            .filter(|b| b.title.contains(query) || b.url.as_str().contains(query))
+            .cloned()
+            .collect())
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        write(&self.bookmarks)?.retain(|b| b.id != id);
+        Ok(())
+    }
+
+    async fn update(&self, bookmark: &Bookmark) -> Result<()> {
+        let mut bookmarks = write(&self.bookmarks)?;
+        if let Some(existing) = bookmarks.iter_mut().find(|b| b.id == bookmark.id) {
+            *existing = bookmark.clone();
+        }
+        Ok(())
+    }
+
+    async fn restore(&self, restored: &[Bookmark]) -> Result<()> {
+        let mut bookmarks = write(&self.bookmarks)?;
+        bookmarks.extend(restored.iter().cloned());
+        bookmarks.sort_by_key(|b| b.id);
+        Ok(())
+    }
+
+    async fn move_to(&self, id: i64, folder: Option<&str>, index: usize) -> Result<()> {
+        // Held across the whole move, so moves never interleave
+        let mut bookmarks = write(&self.bookmarks)?;
+        if !bookmarks.iter().any(|b| b.id == id) {
+            return Err(StorageError::NotFound(id));
+        }
+        let mut siblings: Vec<&Bookmark> =
+            bookmarks.iter().filter(|b| b.id != id && b.folder.as_deref() == folder).collect();
+        siblings.sort_by_key(|b| (b.position, b.created_at, b.id));
+        let siblings: Vec<(i64, i64)> = siblings.iter().map(|b| (b.id, b.position)).collect();
+        for (moved, position) in bookmark_move_positions(id, &siblings, index) {
+            if let Some(bookmark) = bookmarks.iter_mut().find(|b| b.id == moved) {
+                bookmark.position = position;
+            }
+        }
+        if let Some(bookmark) = bookmarks.iter_mut().find(|b| b.id == id) {
+            bookmark.folder = folder.map(str::to_string);
+        }
+        Ok(())
+    }
+}
+
+/// History keyed by URL like the SQLite profile: revisits add to the count
+/// and each is kept as a visit
+#[derive(Debug, Default)]
+pub struct InMemoryHistoryRepository {
+    entries: RwLock<Vec<HistoryEntry>>,
+    visits: RwLock<Vec<Visit>>,
+}
+
+impl InMemoryHistoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delete the entries `keep` rejects, with their visits
+    fn delete_where(&self, keep: impl Fn(&HistoryEntry) -> bool) -> Result<u64> {
+        let mut entries = write(&self.entries)?;
+        let deleted: Vec<i64> = entries.iter().filter(|e| !keep(e)).map(|e| e.id).collect();
+        entries.retain(|e| !deleted.contains(&e.id));
+        write(&self.visits)?.retain(|visit| !deleted.contains(&visit.history_id));
+        Ok(deleted.len() as u64)
+    }
+}
+
+#[async_trait]
+impl HistoryRepository for InMemoryHistoryRepository {
+    async fn add(&self, entry: NewHistoryEntry) -> Result<HistoryEntry> {
+        let mut entries = write(&self.entries)?;
+        let url = entry.url.normalized();
+        let added = match entries.iter_mut().find(|e| e.url == url) {
+            Some(existing) => {
+                existing.title = entry.title;
+                existing.visited_at = entry.visited_at;
+                existing.visit_count += entry.visit_count;
+                existing.clone()
+            }
+            None => {
+                let added = HistoryEntry {
+                    id: entries.iter().map(|e| e.id).max().unwrap_or(0) + 1,
+                    url,
+                    title: entry.title,
+                    visited_at: entry.visited_at,
+                    visit_count: entry.visit_count,
+                };
+                entries.push(added.clone());
+                added
+            }
+        };
+        write(&self.visits)?.push(Visit {
+            history_id: added.id,
+            visited_at: entry.visited_at,
+            visit_type: entry.visit_type,
+        });
+        Ok(added)
+    }
+
+    async fn find_by_url(&self, url: &ValidatedUrl) -> Result<Option<HistoryEntry>> {
+        Ok(read(&self.entries)?.iter().find(|e| e.url.same_document(url)).cloned())
+    }
+
+    async fn search(&self, query: &str, limit: i32) -> Result<Vec<HistoryEntry>> {
+        let mut found: Vec<_> = read(&self.entries)?
+            .iter()
+            .filter(|e| e.title.contains(query) || e.url.as_str().contains(query))
+            .cloned()
+            .collect();
+        found.sort_by_key(|entry| std::cmp::Reverse(entry.visited_at));
+        found.truncate(limit.max(0) as usize);
+        Ok(found)
+    }
+
+    async fn get_recent(&self, limit: i32) -> Result<Vec<HistoryEntry>> {
+        self.search("", limit).await
+    }
+
+    async fn visits(&self, history_ids: &[i64]) -> Result<Vec<Visit>> {
+        let mut found: Vec<_> = read(&self.visits)?
+            .iter()
+            .filter(|visit| history_ids.contains(&visit.history_id))
+            .copied()
+            .collect();
+        found.sort_by_key(|visit| visit.visited_at);
+        Ok(found)
+    }
+
+    async fn delete_by_url(&self, url: &ValidatedUrl) -> Result<()> {
+        self.delete_where(|e| !e.url.same_document(url))?;
+        Ok(())
+    }
+
+    async fn delete_by_id(&self, id: i64) -> Result<()> {
+        self.delete_where(|e| e.id != id)?;
+        Ok(())
+    }
+
+    async fn find_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<HistoryEntry>> {
+        let mut found: Vec<_> = read(&self.entries)?
+            .iter()
+            .filter(|e| e.visited_at >= from && e.visited_at < to)
+            .cloned()
+            .collect();
+        found.sort_by_key(|entry| std::cmp::Reverse(entry.visited_at));
+        Ok(found)
+    }
+
+    async fn delete_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<DeletedHistory> {
+        let mut entries = write(&self.entries)?;
+        let mut visits = write(&self.visits)?;
+        let in_range = |at: DateTime<Utc>| at >= from && at < to;
+        let mut deleted = DeletedHistory {
+            visits: visits.iter().filter(|visit| in_range(visit.visited_at)).copied().collect(),
+            ..DeletedHistory::default()
+        };
+        deleted.visits.sort_by_key(|visit| visit.visited_at);
+        visits.retain(|visit| !in_range(visit.visited_at));
+        entries.retain_mut(|entry| {
+            let removed = deleted.visits.iter().filter(|visit| visit.history_id == entry.id).count() as i32;
+            if removed == 0 && !in_range(entry.visited_at) {
+                return true;
+            }
+            let before = entry.clone();
+            let last = visits.iter().filter(|visit| visit.history_id == entry.id).map(|visit| visit.visited_at).max();
+            let kept = match last {
+                Some(last) => {
+                    entry.visit_count = (entry.visit_count - removed).max(1);
+                    entry.visited_at = last;
+                    true
+                }
+                None => false,
+            };
+            let visit_count = before.visit_count - if kept { entry.visit_count } else { 0 };
+            deleted.entries.push(HistoryEntry { visit_count, ..before });
+            kept
+        });
+        Ok(deleted)
+    }
+
+    async fn restore(&self, deleted: &DeletedHistory) -> Result<()> {
+        let mut entries = write(&self.entries)?;
+        let mut visits = write(&self.visits)?;
+        for entry in &deleted.entries {
+            let id = match entries.iter_mut().find(|e| e.url == entry.url) {
+                Some(existing) => {
+                    existing.visited_at = existing.visited_at.max(entry.visited_at);
+                    existing.visit_count += entry.visit_count;
+                    existing.id
+                }
+                None => {
+                    entries.push(entry.clone());
+                    entry.id
+                }
+            };
+            let restored = deleted.visits.iter().filter(|visit| visit.history_id == entry.id);
+            visits.extend(restored.map(|visit| Visit { history_id: id, ..*visit }));
+        }
+        Ok(())
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        self.delete_where(|_| false)?;
+        Ok(())
+    }
+
+    async fn increment_visit_count(&self, url: &ValidatedUrl) -> Result<()> {
+        if let Some(entry) = write(&self.entries)?.iter_mut().find(|e| e.url.same_document(url)) {
+            entry.visit_count += 1;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemorySettingsRepository {
+    settings: RwLock<BTreeMap<String, String>>,
+}
+
+impl InMemorySettingsRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SettingsRepository for InMemorySettingsRepository {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(read(&self.settings)?.get(key).cloned())
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        write(&self.settings)?.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        write(&self.settings)?.remove(key);
+        Ok(())
+    }
+
+    async fn get_all(&self) -> Result<Vec<(String, String)>> {
+        Ok(read(&self.settings)?
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryUserStyleRepository {
+    styles: RwLock<Vec<UserStyle>>,
+}
+
+impl InMemoryUserStyleRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl UserStyleRepository for InMemoryUserStyleRepository {
+    async fn save(&self, style: NewUserStyle) -> Result<UserStyle> {
+        let mut styles = write(&self.styles)?;
+        let saved = UserStyle {
+            id: styles.iter().map(|s| s.id).max().unwrap_or(0) + 1,
+            host_pattern: style.host_pattern,
+            rule: style.rule,
+            created_at: chrono::Utc::now(),
+        };
+        styles.push(saved.clone());
+        Ok(saved)
+    }
+
+    async fn find_all(&self) -> Result<Vec<UserStyle>> {
+        Ok(read(&self.styles)?.clone())
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        write(&self.styles)?.retain(|s| s.id != id);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemorySearchEngineRepository {
+    engines: RwLock<Vec<SearchEngine>>,
+}
+
+impl InMemorySearchEngineRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SearchEngineRepository for InMemorySearchEngineRepository {
+    async fn save(&self, engine: NewSearchEngine) -> Result<SearchEngine> {
+        let mut engines = write(&self.engines)?;
+        // Keywords are unique, as in the SQLite table
+        if engines.iter().any(|e| e.keyword == engine.keyword) {
+            return Err(StorageError::Database(format!("Keyword {} already exists", engine.keyword)));
+        }
+        let saved = SearchEngine {
+            id: engines.iter().map(|e| e.id).max().unwrap_or(0) + 1,
+            name: engine.name,
+            keyword: engine.keyword,
+            query_template: engine.query_template,
+            suggest_template: engine.suggest_template,
+            is_default: false,
+            created_at: chrono::Utc::now(),
+        };
+        engines.push(saved.clone());
+        Ok(saved)
+    }
+
+    async fn find_all(&self) -> Result<Vec<SearchEngine>> {
+        Ok(read(&self.engines)?.clone())
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        write(&self.engines)?.retain(|e| e.id != id);
+        Ok(())
+    }
+
+    async fn set_default(&self, id: i64) -> Result<()> {
+        for engine in write(&self.engines)?.iter_mut() {
+            engine.is_default = engine.id == id;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryDownloadRepository {
+    downloads: RwLock<Vec<Download>>,
+}
+
+impl InMemoryDownloadRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl DownloadRepository for InMemoryDownloadRepository {
+    async fn save(&self, download: NewDownload) -> Result<Download> {
+        let mut downloads = write(&self.downloads)?;
+        let saved = Download {
+            id: downloads.iter().map(|d| d.id).max().unwrap_or(0) + 1,
+            url: download.url,
+            path: download.path,
+            size: download.size,
+            created_at: chrono::Utc::now(),
+        };
+        downloads.push(saved.clone());
+        Ok(saved)
+    }
+
+    async fn find_all(&self) -> Result<Vec<Download>> {
+        Ok(read(&self.downloads)?.iter().rev().cloned().collect())
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        write(&self.downloads)?.retain(|d| d.id != id);
+        Ok(())
+    }
+
+    async fn restore(&self, download: &Download) -> Result<()> {
+        let mut downloads = write(&self.downloads)?;
+        downloads.push(download.clone());
+        downloads.sort_by_key(|d| d.id);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryCustomCaRepository {
+    cas: RwLock<Vec<CustomCa>>,
+}
+
+impl InMemoryCustomCaRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl CustomCaRepository for InMemoryCustomCaRepository {
+    async fn save(&self, ca: NewCustomCa) -> Result<CustomCa> {
+        let mut cas = write(&self.cas)?;
+        let saved = CustomCa {
+            id: cas.iter().map(|ca| ca.id).max().unwrap_or(0) + 1,
+            name: ca.name,
+            pem: ca.pem,
+            fingerprint: ca.fingerprint,
+            added_at: chrono::Utc::now(),
+        };
+        cas.push(saved.clone());
+        Ok(saved)
+    }
+
+    async fn find_all(&self) -> Result<Vec<CustomCa>> {
+        Ok(read(&self.cas)?.clone())
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        write(&self.cas)?.retain(|ca| ca.id != id);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemorySiteSettingsRepository {
+    sites: RwLock<BTreeMap<SiteOrigin, SiteSettings>>,
+}
+
+impl InMemorySiteSettingsRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl SiteSettingsRepository for InMemorySiteSettingsRepository {
+    async fn find(&self, origin: &SiteOrigin) -> Result<Option<SiteSettings>> {
+        Ok(read(&self.sites)?.get(origin).cloned())
+    }
+
+    async fn find_all(&self) -> Result<Vec<SiteSettings>> {
+        Ok(read(&self.sites)?.values().cloned().collect())
+    }
+
+    async fn save(&self, settings: &SiteSettings) -> Result<()> {
+        write(&self.sites)?.insert(settings.origin.clone(), settings.clone());
+        Ok(())
+    }
+
+    async fn delete(&self, origin: &SiteOrigin) -> Result<()> {
+        write(&self.sites)?.remove(origin);
+        Ok(())
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        write(&self.sites)?.clear();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryWebStorageRepository {
+    origins: RwLock<BTreeMap<SiteOrigin, Vec<(String, String)>>>,
+}
+
+impl InMemoryWebStorageRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl WebStorageRepository for InMemoryWebStorageRepository {
+    async fn load(&self, origin: &SiteOrigin) -> Result<Vec<(String, String)>> {
+        Ok(read(&self.origins)?.get(origin).cloned().unwrap_or_default())
+    }
+
+    async fn apply(&self, origin: &SiteOrigin, change: &StorageChange) -> Result<()> {
+        let mut area = StorageArea::with_items(origin.clone(), self.load(origin).await?).with_quota(usize::MAX);
+        area.apply(change.clone())?;
+        write(&self.origins)?.insert(origin.clone(), area.items().to_vec());
+        Ok(())
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        write(&self.origins)?.clear();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryPageCacheRepository {
+    pages: RwLock<Vec<CachedPage>>,
+}
+
+impl InMemoryPageCacheRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl PageCacheRepository for InMemoryPageCacheRepository {
+    async fn store(&self, page: &CachedPage) -> Result<()> {
+        let page = CachedPage {
+            url: page.url.normalized(),
+            ..page.clone()
+        };
+        let mut pages = write(&self.pages)?;
+        pages.retain(|kept| kept.url != page.url);
+        pages.push(page);
+        Ok(())
+    }
+
+    async fn find(&self, url: &ValidatedUrl) -> Result<Option<CachedPage>> {
+        let url = url.normalized();
+        Ok(read(&self.pages)?.iter().find(|page| page.url == url).cloned())
+    }
+
+    async fn find_by_origin(&self, origin: &SiteOrigin) -> Result<Vec<CachedPageSummary>> {
+        let mut pages: Vec<_> = read(&self.pages)?
+            .iter()
+            .filter(|page| SiteOrigin::of(&page.url).as_ref() == Some(origin))
+            .map(|page| CachedPageSummary {
+                url: page.url.clone(),
+                title: page.title.clone(),
+                fetched_at: page.fetched_at,
+            })
+            .collect();
+        pages.sort_by_key(|page| std::cmp::Reverse(page.fetched_at));
+        Ok(pages)
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        write(&self.pages)?.clear();
+        Ok(())
+    }
+}
+
+/// Keeps written files in memory, numbering taken names like the real
+/// writer; fails every write while `fail` is set
+#[derive(Debug, Default)]
+pub struct InMemoryDownloadWriter {
+    files: RwLock<BTreeMap<PathBuf, Vec<u8>>>,
+    pub fail: AtomicBool,
+}
+
+impl InMemoryDownloadWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Contents written to `path`, if any
+    pub fn file(&self, path: &Path) -> Option<Vec<u8>> {
+        self.files.read().ok()?.get(path).cloned()
+    }
+}
+
+#[async_trait]
+impl DownloadWriter for InMemoryDownloadWriter {
+    fn default_directory(&self) -> PathBuf {
+        PathBuf::from("downloads")
+    }
+
+    async fn write_new(
+        &self,
+        directory: &Path,
+        file_name: &str,
+        contents: &[u8],
+        cancelled: Arc<AtomicBool>,
+    ) -> std::result::Result<PathBuf, DownloadError> {
+        if self.fail.load(Ordering::SeqCst) {
+            return Err(DownloadError::Write {
+                path: directory.join(file_name).display().to_string(),
+                reason: "Disk full".to_string(),
+            });
+        }
+        if cancelled.load(Ordering::SeqCst) {
+            return Err(DownloadError::Cancelled(file_name.to_string()));
+        }
+        let mut files = self.files.write().map_err(|_| DownloadError::Write {
+            path: directory.display().to_string(),
+            reason: "Writer lock poisoned".to_string(),
+        })?;
+        let mut path = directory.join(file_name);
+        let mut number = 0;
+        while files.contains_key(&path) {
+            number += 1;
+            path = directory.join(match file_name.rsplit_once('.') {
+                Some((stem, extension)) => format!("{} ({}).{}", stem, number, extension),
+                None => format!("{} ({})", file_name, number),
+            });
+        }
+        files.insert(path.clone(), contents.to_vec());
+        Ok(path)
+    }
+
+    async fn delete(&self, path: &Path) -> std::result::Result<(), DownloadError> {
+        let mut files = self.files.write().map_err(|_| DownloadError::Delete {
+            path: path.display().to_string(),
+            reason: "Writer lock poisoned".to_string(),
+        })?;
+        files.remove(path);
+        Ok(())
+    }
+}
+
+/// Launcher recording the URLs it is asked to open instead of starting
+/// anything; fails every launch while `fail` is set
+#[derive(Debug, Default)]
+pub struct FakeLauncher {
+    launched: RwLock<Vec<ValidatedUrl>>,
+    pub fail: AtomicBool,
+}
+
+impl FakeLauncher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// URLs opened so far, in order
+    pub fn launched(&self) -> Vec<ValidatedUrl> {
+        self.launched.read().map(|launched| launched.clone()).unwrap_or_default()
+    }
+}
+
+impl ExternalLauncher for FakeLauncher {
+    fn launch(&self, url: &ValidatedUrl) -> std::io::Result<()> {
+        if self.fail.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no handler"));
+        }
+        if let Ok(mut launched) = self.launched.write() {
+            launched.push(url.clone());
+        }
+        Ok(())
+    }
+}
+
+/// Printer writing the paper, title and URL it is given as plain text
+#[derive(Debug, Default)]
+pub struct FakePrinter;
+
+#[async_trait]
+impl PagePrinter for FakePrinter {
+    async fn print(&self, url: &ValidatedUrl, title: &str, _html: &str, paper: PaperSize) -> Vec<u8> {
+        format!("%PDF {:?} {} from {}", paper, title, url).into_bytes()
+    }
+}
+
+/// Archiver writing the title, URL and source it is given as plain text
+#[derive(Debug, Default)]
+pub struct FakeArchiver;
+
+#[async_trait]
+impl PageArchiver for FakeArchiver {
+    async fn archive(&self, url: &ValidatedUrl, title: &str, html: &str) -> Vec<u8> {
+        format!("{} from {}:\n{}", title, url, html).into_bytes()
+    }
+}
+
+/// Inspector describing every server as a TLS 1.3 one with a single
+/// certificate, whose fingerprint is made of the host
+#[derive(Debug, Default)]
+pub struct FakeTlsInspector;
+
+#[async_trait]
+impl TlsInspector for FakeTlsInspector {
+    async fn tls_session(&self, url: &ValidatedUrl) -> std::result::Result<TlsSession, NetworkError> {
+        Ok(TlsSession {
+            version: "TLSv1_3".to_string(),
+            cipher_suite: "TLS13_AES_128_GCM_SHA256".to_string(),
+            chain_fingerprints: vec![format!("FINGERPRINT:{}", url.host_str().unwrap_or_default())],
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryUserScriptRepository {
+    scripts: RwLock<Vec<UserScript>>,
+    values: RwLock<BTreeMap<(i64, String), String>>,
+}
+
+impl InMemoryUserScriptRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl UserScriptRepository for InMemoryUserScriptRepository {
+    async fn save(&self, script: NewUserScript) -> Result<UserScript> {
+        let mut scripts = write(&self.scripts)?;
+        let saved = UserScript {
+            id: scripts.iter().map(|s| s.id).max().unwrap_or(0) + 1,
+            name: script.name,
+            matches: script.matches,
+            code: script.code,
+            enabled: script.enabled,
+            run_at: script.run_at,
+            allow_internal: script.allow_internal,
+            allow_private: script.allow_private,
+            last_error: None,
+            created_at: chrono::Utc::now(),
+        };
+        scripts.push(saved.clone());
+        Ok(saved)
+    }
+
+    async fn find_all(&self) -> Result<Vec<UserScript>> {
+        Ok(read(&self.scripts)?.clone())
+    }
+
+    async fn update(&self, script: &UserScript) -> Result<()> {
+        if let Some(existing) = write(&self.scripts)?.iter_mut().find(|s| s.id == script.id) {
+            *existing = script.clone();
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        write(&self.scripts)?.retain(|s| s.id != id);
+        write(&self.values)?.retain(|(script_id, _), _| *script_id != id);
+        Ok(())
+    }
+
+    async fn values(&self, script_id: i64) -> Result<Vec<(String, String)>> {
+        Ok(read(&self.values)?
+            .iter()
+            .filter(|((id, _), _)| *id == script_id)
+            .map(|((_, key), value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    async fn set_value(&self, script_id: i64, key: &str, value: &str) -> Result<()> {
+        write(&self.values)?.insert((script_id, key.to_string()), value.to_string());
+        Ok(())
+    }
+}
+
+/// Wraps a repository and fails every call after the first `successes`
+///
+/// Calls are counted across all methods; a batch write counts as one call.
+#[derive(Debug)]
+pub struct FailingRepository<R> {
+    inner: R,
+    remaining: AtomicUsize,
+}
+
+impl<R> FailingRepository<R> {
+    pub fn after(successes: usize, inner: R) -> Self {
+        Self {
+            inner,
+            remaining: AtomicUsize::new(successes),
+        }
+    }
+
+    /// The wrapped repository, to inspect what was written before failing
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    fn check(&self, operation: &str) -> Result<()> {
+        self.remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .map(|_| ())
+            .map_err(|_| StorageError::Database(format!("Injected failure in {}", operation)))
+    }
+}
+
+#[async_trait]
+impl<R: TabRepository> TabRepository for FailingRepository<R> {
+    async fn save(&self, tab: &Tab) -> Result<()> {
+        self.check("save")?;
+        self.inner.save(tab).await
+    }
+
+    async fn find_by_id(&self, id: TabId) -> Result<Option<Tab>> {
+        self.check("find_by_id")?;
+        self.inner.find_by_id(id).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Tab>> {
+        self.check("find_all")?;
+        self.inner.find_all().await
+    }
+
+    async fn delete(&self, id: TabId) -> Result<()> {
+        self.check("delete")?;
+        self.inner.delete(id).await
+    }
+
+    async fn save_session(&self, windows: Vec<SavedWindow>) -> Result<()> {
+        self.check("save_session")?;
+        self.inner.save_session(windows).await
+    }
+
+    async fn restore_session(&self) -> Result<Vec<SavedWindow>> {
+        self.check("restore_session")?;
+        self.inner.restore_session().await
+    }
+
+    async fn clear_session(&self) -> Result<()> {
+        self.check("clear_session")?;
+        self.inner.clear_session().await
+    }
+}
+
+#[async_trait]
+impl<R: BookmarkRepository> BookmarkRepository for FailingRepository<R> {
+    async fn save(&self, bookmark: NewBookmark) -> Result<Bookmark> {
+        self.check("save")?;
+        self.inner.save(bookmark).await
+    }
+
+    async fn find_by_id(&self, id: i64) -> Result<Option<Bookmark>> {
+        self.check("find_by_id")?;
+        self.inner.find_by_id(id).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Bookmark>> {
+        self.check("find_all")?;
+        self.inner.find_all().await
+    }
+
+    async fn find_by_folder(&self, folder: &str) -> Result<Vec<Bookmark>> {
+        self.check("find_by_folder")?;
+        self.inner.find_by_folder(folder).await
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<Bookmark>> {
+        self.check("search")?;
+        self.inner.search(query).await
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        self.check("delete")?;
+        self.inner.delete(id).await
+    }
+
+    async fn update(&self, bookmark: &Bookmark) -> Result<()> {
+        self.check("update")?;
+        self.inner.update(bookmark).await
+    }
+
+    async fn restore(&self, bookmarks: &[Bookmark]) -> Result<()> {
+        self.check("restore")?;
+        self.inner.restore(bookmarks).await
+    }
+
+    async fn move_to(&self, id: i64, folder: Option<&str>, index: usize) -> Result<()> {
+        self.check("move_to")?;
+        self.inner.move_to(id, folder, index).await
+    }
+}
+
+#[async_trait]
+impl<R: HistoryRepository> HistoryRepository for FailingRepository<R> {
+    async fn add(&self, entry: NewHistoryEntry) -> Result<HistoryEntry> {
+        self.check("add")?;
+        self.inner.add(entry).await
+    }
+
+    async fn add_batch(&self, entries: &[NewHistoryEntry]) -> Result<()> {
+        self.check("add_batch")?;
+        self.inner.add_batch(entries).await
+    }
+
+    async fn find_by_url(&self, url: &ValidatedUrl) -> Result<Option<HistoryEntry>> {
+        self.check("find_by_url")?;
+        self.inner.find_by_url(url).await
+    }
+
+    async fn search(&self, query: &str, limit: i32) -> Result<Vec<HistoryEntry>> {
+        self.check("search")?;
+        self.inner.search(query, limit).await
+    }
+
+    async fn get_recent(&self, limit: i32) -> Result<Vec<HistoryEntry>> {
+        self.check("get_recent")?;
+        self.inner.get_recent(limit).await
+    }
+
+    async fn visits(&self, history_ids: &[i64]) -> Result<Vec<Visit>> {
+        self.check("visits")?;
+        self.inner.visits(history_ids).await
+    }
+
+    async fn delete_by_url(&self, url: &ValidatedUrl) -> Result<()> {
+        self.check("delete_by_url")?;
+        self.inner.delete_by_url(url).await
+    }
+
+    async fn delete_by_id(&self, id: i64) -> Result<()> {
+        self.check("delete_by_id")?;
+        self.inner.delete_by_id(id).await
+    }
+
+    async fn find_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<HistoryEntry>> {
+        self.check("find_range")?;
+        self.inner.find_range(from, to).await
+    }
+
+    async fn delete_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<DeletedHistory> {
+        self.check("delete_range")?;
+        self.inner.delete_range(from, to).await
+    }
+
+    async fn restore(&self, deleted: &DeletedHistory) -> Result<()> {
+        self.check("restore")?;
+        self.inner.restore(deleted).await
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        self.check("clear_all")?;
+        self.inner.clear_all().await
+    }
+
+    async fn increment_visit_count(&self, url: &ValidatedUrl) -> Result<()> {
+        self.check("increment_visit_count")?;
+        self.inner.increment_visit_count(url).await
+    }
+}
+
+/// Engine that loads instantly and titles pages after their path
+#[derive(Debug, Default)]
+pub struct FakeEngine {
+    loads: AtomicUsize,
+    restyles: AtomicUsize,
+    suspends: AtomicUsize,
+    title: RwLock<String>,
+    search_description: RwLock<Option<ValidatedUrl>>,
+    scripts: RwLock<Vec<String>>,
+    script_result: RwLock<Option<std::result::Result<String, RenderError>>>,
+    site_settings: RwLock<Option<SiteSettings>>,
+    blocked: RwLock<Vec<BlockedRequest>>,
+    timer_throttles: RwLock<Vec<Option<Duration>>>,
+    console: RwLock<Vec<ConsoleMessage>>,
+    preserve_console: AtomicBool,
+    fetch: RwLock<Option<DocumentFetch>>,
+    certificate: RwLock<Option<Certificate>>,
+    redirect: RwLock<Option<(ValidatedUrl, ValidatedUrl)>>,
+}
+
+impl FakeEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load_count(&self) -> usize {
+        self.loads.load(Ordering::SeqCst)
+    }
+
+    pub fn restyle_count(&self) -> usize {
+        self.restyles.load(Ordering::SeqCst)
+    }
+
+    pub fn suspend_count(&self) -> usize {
+        self.suspends.load(Ordering::SeqCst)
+    }
+
+    /// Every script passed to `execute_javascript`, oldest first
+    pub fn executed_scripts(&self) -> Vec<String> {
+        self.scripts.read().map(|scripts| scripts.clone()).unwrap_or_default()
+    }
+
+    /// Every timer throttle handed over, oldest first
+    pub fn timer_throttles(&self) -> Vec<Option<Duration>> {
+        self.timer_throttles.read().map(|throttles| throttles.clone()).unwrap_or_default()
+    }
+
+    /// Overrides handed over for the last load
+    pub fn site_settings(&self) -> Option<SiteSettings> {
+        self.site_settings.read().ok().and_then(|settings| settings.clone())
+    }
+
+    /// Have the current page's requests to `blocked` refused, until the next load
+    pub fn set_blocked_requests(&self, blocked: Vec<BlockedRequest>) {
+        if let Ok(mut current) = self.blocked.write() {
+            *current = blocked;
+        }
+    }
+
+    /// Have the current page come from the network as `fetch`, with
+    /// `certificate`, until the next load
+    pub fn set_document_fetch(&self, fetch: DocumentFetch, certificate: Option<Certificate>) {
+        if let Ok(mut current) = self.fetch.write() {
+            *current = Some(fetch);
+        }
+        if let Ok(mut current) = self.certificate.write() {
+            *current = certificate;
+        }
+    }
+
+    /// Have loads of `from` end up at `to`, as if the server redirected them
+    pub fn set_redirect(&self, from: ValidatedUrl, to: ValidatedUrl) {
+        if let Ok(mut redirect) = self.redirect.write() {
+            *redirect = Some((from, to));
+        }
+    }
+
+    /// Have the current page link an OpenSearch description
+    pub fn set_search_description(&self, description: Option<ValidatedUrl>) {
+        if let Ok(mut current) = self.search_description.write() {
+            *current = description;
+        }
+    }
+
+    /// What `execute_javascript` returns from now on, instead of an empty string
+    pub fn set_script_result(&self, result: std::result::Result<String, RenderError>) {
+        if let Ok(mut current) = self.script_result.write() {
+            *current = Some(result);
+        }
+    }
+}
+
+#[async_trait]
+impl RenderingEngine for FakeEngine {
+    async fn load_url(&self, url: &ValidatedUrl) -> std::result::Result<(), RenderError> {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+        if let Ok(mut title) = self.title.write() {
+            *title = format!("Page {}", url.path());
+        }
+        self.set_blocked_requests(Vec::new());
+        let redirect = self.redirect.read().ok().and_then(|redirect| redirect.clone());
+        if let Ok(mut fetch) = self.fetch.write() {
+            *fetch = redirect.filter(|(from, _)| from == url).map(|(from, to)| DocumentFetch {
+                url: to,
+                redirected_from: vec![from],
+                status: 200,
+                headers: Vec::new(),
+                version: HttpVersion::Http11,
+            });
+        }
+        if let Ok(mut certificate) = self.certificate.write() {
+            *certificate = None;
+        }
+        if !self.preserve_console.load(Ordering::SeqCst) {
+            self.clear_console();
+        }
+        Ok(())
+    }
+
+    async fn get_title(&self) -> std::result::Result<String, RenderError> {
+        Ok(self.title.read().map(|title| title.clone()).unwrap_or_default())
+    }
+
+    async fn execute_javascript(&self, script: &str) -> std::result::Result<String, RenderError> {
+        if let Ok(mut scripts) = self.scripts.write() {
+            scripts.push(script.to_string());
+        }
+        let result = self.script_result.read().ok().and_then(|result| result.clone());
+        result.unwrap_or_else(|| Ok(String::new()))
+    }
+
+    async fn take_screenshot(&self) -> std::result::Result<Vec<u8>, RenderError> {
+        Ok(Vec::new())
+    }
+
+    async fn restyle(&self) -> std::result::Result<(), RenderError> {
+        self.restyles.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn suspend(&self) -> std::result::Result<(), RenderError> {
+        self.suspends.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn get_text(&self) -> std::result::Result<String, RenderError> {
+        Ok(self.title.read().map(|title| title.clone()).unwrap_or_default())
+    }
+
+    /// A title and one relative link
+    async fn get_source(&self) -> std::result::Result<String, RenderError> {
+        let title = self.title.read().map(|title| title.clone()).unwrap_or_default();
+        Ok(format!("<title>{}</title><a href=\"next\">Next</a>", title))
+    }
+
+    fn search_description(&self) -> Option<ValidatedUrl> {
+        self.search_description.read().ok().and_then(|description| description.clone())
+    }
+
+    fn set_site_settings(&self, settings: Option<SiteSettings>) {
+        if let Ok(mut current) = self.site_settings.write() {
+            *current = settings;
+        }
+    }
+
+    fn blocked_requests(&self) -> Vec<BlockedRequest> {
+        self.blocked.read().map(|blocked| blocked.clone()).unwrap_or_default()
+    }
+
+    fn document_fetch(&self) -> Option<DocumentFetch> {
+        self.fetch.read().ok().and_then(|fetch| fetch.clone())
+    }
+
+    fn certificate(&self) -> Option<Certificate> {
+        self.certificate.read().ok().and_then(|certificate| certificate.clone())
+    }
+
+    fn throttle_timers(&self, min_interval: Option<Duration>) {
+        if let Ok(mut throttles) = self.timer_throttles.write() {
+            throttles.push(min_interval);
+        }
+    }
+
+    fn console_messages(&self) -> Vec<ConsoleMessage> {
+        self.console.read().map(|console| console.clone()).unwrap_or_default()
+    }
+
+    fn log_to_console(&self, level: ConsoleLevel, message: &str, source: Option<&str>) {
+        if let Ok(mut console) = self.console.write() {
+            console.push(ConsoleMessage {
+                time: Utc::now(),
+                level,
+                message: message.to_string(),
+                source: source.map(str::to_string),
+                stack: None,
+            });
+        }
+    }
+
+    fn clear_console(&self) {
+        if let Ok(mut console) = self.console.write() {
+            console.clear();
+        }
+    }
+
+    fn set_preserve_console(&self, preserve: bool) {
+        self.preserve_console.store(preserve, Ordering::SeqCst);
+    }
+}
+
+/// A clock that stands still until a test moves it
+#[derive(Debug)]
+pub struct ManualClock {
+    now: RwLock<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self { now: RwLock::new(now) }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        if let Ok(mut current) = self.now.write() {
+            *current = now;
+        }
+    }
+
+    pub fn advance(&self, by: chrono::Duration) {
+        if let Ok(mut current) = self.now.write() {
+            *current += by;
+        }
+    }
+}
+
+impl Default for ManualClock {
+    /// 2024-01-01T00:00:00Z
+    fn default() -> Self {
+        Self::new(DateTime::from_timestamp(1_704_067_200, 0).unwrap_or_default())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.now.read().map(|now| *now).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_failing_repository_counts_calls() {
+        let repo = FailingRepository::after(2, InMemoryTabRepository::new());
+        let tab = Tab::new(false);
+
+        repo.save(&tab).await.unwrap();
+        assert_eq!(repo.find_all().await.unwrap().len(), 1);
+        assert!(repo.delete(tab.id).await.is_err());
+        assert!(repo.find_all().await.is_err());
+
+        assert_eq!(repo.inner().find_all().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_history_revisits_add_to_count() {
+        let repo = InMemoryHistoryRepository::new();
+        let url = ValidatedUrl::parse("https://example.com/").unwrap();
+
+        repo.add(NewHistoryEntry::new(url.clone(), "First".to_string())).await.unwrap();
+        let entry = repo.add(NewHistoryEntry::new(url, "Second".to_string())).await.unwrap();
+
+        assert_eq!(entry.visit_count, 2);
+        assert_eq!(entry.title, "Second");
+        assert_eq!(repo.get_recent(10).await.unwrap().len(), 1);
+    }
+}
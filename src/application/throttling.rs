@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use super::state::TabActivity;
+
+/// Settings key for how many minutes a hidden tab waits before it is
+/// suspended; "0" never suspends
+pub const SUSPEND_AFTER_SETTING: &str = "tabs.suspend_after_minutes";
+pub const DEFAULT_SUSPEND_AFTER: Duration = Duration::from_secs(30 * 60);
+/// Suspended tabs hidden longer than this are reloaded rather than resumed
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(2 * 60 * 60);
+/// Shortest refresh or timer interval a background tab gets
+pub const MIN_BACKGROUND_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Parallel fetches a foreground page may make for its resources
+const FOREGROUND_FETCHES: usize = 6;
+const BACKGROUND_FETCHES: usize = 1;
+
+/// Minutes from the stored setting; `None` when it isn't a whole number
+pub fn parse_suspend_after(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(|minutes| Duration::from_secs(minutes.saturating_mul(60)))
+}
+
+/// How much work tabs may do in each activity class.
+///
+/// Meta refresh, subresource loading and script timers ask here before
+/// scheduling work for a tab, so hidden tabs cannot keep the browser busy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottlePolicy {
+    suspend_after: Duration,
+    stale_after: Duration,
+}
+
+impl ThrottlePolicy {
+    pub fn new() -> Self {
+        Self {
+            suspend_after: DEFAULT_SUSPEND_AFTER,
+            stale_after: DEFAULT_STALE_AFTER,
+        }
+    }
+
+    /// Suspend background tabs after this long hidden; zero never suspends
+    pub fn with_suspend_after(mut self, suspend_after: Duration) -> Self {
+        self.suspend_after = suspend_after;
+        self
+    }
+
+    /// Reload instead of resuming tabs hidden longer than this
+    pub fn with_stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// How long a background tab stays hidden before it is suspended
+    pub fn suspend_after(&self) -> Option<Duration> {
+        (!self.suspend_after.is_zero()).then_some(self.suspend_after)
+    }
+
+    /// Whether a tab hidden for `hidden_for` should fetch its page again
+    /// when shown, instead of laying out the document it kept
+    pub fn is_stale(&self, hidden_for: Duration) -> bool {
+        hidden_for >= self.stale_after
+    }
+
+    /// Resource fetches a tab may have in flight at once
+    pub fn fetch_concurrency(&self, activity: TabActivity) -> usize {
+        match activity {
+            TabActivity::Foreground => FOREGROUND_FETCHES,
+            TabActivity::Background => BACKGROUND_FETCHES,
+            TabActivity::Suspended => 0,
+        }
+    }
+
+    /// Delay before a refresh or timer a page asked for after `requested`;
+    /// `None` while the tab is suspended and must not run it
+    pub fn interval(&self, activity: TabActivity, requested: Duration) -> Option<Duration> {
+        match activity {
+            TabActivity::Foreground => Some(requested),
+            TabActivity::Background => Some(requested.max(MIN_BACKGROUND_INTERVAL)),
+            TabActivity::Suspended => None,
+        }
+    }
+}
+
+impl Default for ThrottlePolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_background_tabs_are_throttled() {
+        let policy = ThrottlePolicy::new();
+        let second = Duration::from_secs(1);
+
+        assert_eq!(policy.interval(TabActivity::Foreground, second), Some(second));
+        assert_eq!(policy.interval(TabActivity::Background, second), Some(MIN_BACKGROUND_INTERVAL));
+        assert_eq!(policy.interval(TabActivity::Background, second * 90), Some(second * 90));
+        assert_eq!(policy.interval(TabActivity::Suspended, second), None);
+        let background = policy.fetch_concurrency(TabActivity::Background);
+        assert!(background < policy.fetch_concurrency(TabActivity::Foreground));
+        assert_eq!(policy.fetch_concurrency(TabActivity::Suspended), 0);
+    }
+
+    #[test]
+    fn test_suspend_after_setting() {
+        assert_eq!(parse_suspend_after(" 45 "), Some(Duration::from_secs(45 * 60)));
+        assert_eq!(parse_suspend_after("soon"), None);
+
+        let policy = ThrottlePolicy::new().with_suspend_after(parse_suspend_after("0").unwrap());
+        assert_eq!(policy.suspend_after(), None);
+        assert_eq!(ThrottlePolicy::new().suspend_after(), Some(DEFAULT_SUSPEND_AFTER));
+    }
+}
@@ -0,0 +1,411 @@
+use super::downloads::DOWNLOADS_DIRECTORY_SETTING;
+use super::settings::{
+    parse_toggle, CONTENT_BLOCKER_SETTING, COOKIES_SETTING, DEFAULT_HOMEPAGE, HOMEPAGE_SETTING, HTTPS_ONLY_SETTING,
+    OFFLINE_SETTING, RESTORE_SESSION_SETTING, THEME_SETTING, USER_AGENT_SETTING,
+};
+use super::suggestions::SEARCH_SUGGESTIONS_SETTING;
+use crate::domain::{CookieBlocking, ValidatedUrl};
+use arc_swap::ArcSwap;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Version of the stored settings, set once older values were rewritten
+/// the way [`Settings`] stores them
+pub const SETTINGS_VERSION_SETTING: &str = "settings.version";
+pub const SETTINGS_VERSION: &str = "1";
+
+/// The settings components read, typed. Each field is stored under its
+/// own key of the settings table, the one the field is renamed to; keys
+/// missing from the table take their default and keys it does not know are
+/// left alone, so older and newer profiles load alike.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    #[serde(rename = "browser.homepage", with = "url_text")]
+    pub homepage: ValidatedUrl,
+    #[serde(rename = "theme", with = "text")]
+    pub theme: Theme,
+    #[serde(rename = "security.https_only", with = "toggle")]
+    pub https_only: bool,
+    /// Empty for the platform's downloads directory
+    #[serde(rename = "downloads.directory")]
+    pub download_dir: PathBuf,
+    #[serde(flatten)]
+    pub search: SearchSettings,
+    #[serde(flatten)]
+    pub network: NetworkSettings,
+    #[serde(flatten)]
+    pub privacy: PrivacySettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            homepage: ValidatedUrl::parse(DEFAULT_HOMEPAGE).expect("the default homepage is a URL"),
+            theme: Theme::default(),
+            https_only: false,
+            download_dir: PathBuf::new(),
+            search: SearchSettings::default(),
+            network: NetworkSettings::default(),
+            privacy: PrivacySettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    /// Ask the search engine for suggestions while typing
+    #[serde(rename = "search.suggestions", with = "toggle")]
+    pub suggestions: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkSettings {
+    /// Empty sends the browser's own
+    #[serde(rename = "network.user_agent")]
+    pub user_agent: String,
+    #[serde(rename = "network.offline", with = "toggle")]
+    pub offline: bool,
+    #[serde(rename = "network.cookies", with = "text")]
+    pub cookies: CookieBlocking,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrivacySettings {
+    #[serde(rename = "content_blocker.enabled", with = "toggle")]
+    pub content_blocker: bool,
+    /// Reopen the tabs left open, keeping where they were between runs
+    #[serde(rename = "session.restore", with = "toggle")]
+    pub restore_session: bool,
+}
+
+impl Default for PrivacySettings {
+    fn default() -> Self {
+        Self {
+            content_blocker: true,
+            restore_session: false,
+        }
+    }
+}
+
+/// Theme the user picked for the browser's chrome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Theme {
+    Light,
+    Dark,
+    /// Follow the operating system
+    #[default]
+    Auto,
+}
+
+impl Theme {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "light" => Some(Self::Light),
+            "dark" => Some(Self::Dark),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Light => write!(f, "light"),
+            Self::Dark => write!(f, "dark"),
+            Self::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+/// Which sections of [`Settings`] an update changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SettingsSections {
+    /// Homepage, theme, HTTPS only or download directory
+    pub general: bool,
+    pub search: bool,
+    pub network: bool,
+    pub privacy: bool,
+}
+
+impl SettingsSections {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Settings {
+    /// Settings from the rows of the settings table. A stored value that
+    /// does not parse is skipped, leaving its default, and reported by key.
+    pub fn from_rows(rows: &[(String, String)]) -> (Self, Vec<String>) {
+        fn parse<'a>(rows: impl Iterator<Item = &'a (String, String)>) -> serde_json::Result<Settings> {
+            let map = rows.map(|(key, value)| (key.clone(), value.clone().into())).collect();
+            serde_json::from_value(serde_json::Value::Object(map))
+        }
+        if let Ok(settings) = parse(rows.iter()) {
+            return (settings, Vec::new());
+        }
+        let (valid, invalid): (Vec<_>, Vec<_>) = rows.iter().partition(|row| parse(std::iter::once(*row)).is_ok());
+        let settings = parse(valid.into_iter()).unwrap_or_default();
+        (settings, invalid.into_iter().map(|(key, _)| key.clone()).collect())
+    }
+
+    /// Every field as the key and value it is stored under
+    pub fn to_rows(&self) -> Vec<(String, String)> {
+        let Ok(serde_json::Value::Object(map)) = serde_json::to_value(self) else {
+            return Vec::new();
+        };
+        map.into_iter()
+            .filter_map(|(key, value)| Some((key, value.as_str()?.to_string())))
+            .collect()
+    }
+
+    /// Whether `key` is stored for a field of these settings
+    pub fn has_key(key: &str) -> bool {
+        [
+            HOMEPAGE_SETTING,
+            THEME_SETTING,
+            HTTPS_ONLY_SETTING,
+            DOWNLOADS_DIRECTORY_SETTING,
+            SEARCH_SUGGESTIONS_SETTING,
+            USER_AGENT_SETTING,
+            OFFLINE_SETTING,
+            COOKIES_SETTING,
+            CONTENT_BLOCKER_SETTING,
+            RESTORE_SESSION_SETTING,
+        ]
+        .contains(&key)
+    }
+
+    /// These settings with the value stored under `key` replaced; `None`
+    /// if it does not parse
+    pub fn with_value(&self, key: &str, value: &str) -> Option<Self> {
+        let mut rows = self.to_rows();
+        rows.retain(|(stored, _)| stored != key);
+        rows.push((key.to_string(), value.to_string()));
+        let (settings, invalid) = Self::from_rows(&rows);
+        invalid.is_empty().then_some(settings)
+    }
+
+    /// The sections in which `other` differs from these settings
+    pub fn changed_sections(&self, other: &Self) -> SettingsSections {
+        SettingsSections {
+            general: self.homepage != other.homepage
+                || self.theme != other.theme
+                || self.https_only != other.https_only
+                || self.download_dir != other.download_dir,
+            search: self.search != other.search,
+            network: self.network != other.network,
+            privacy: self.privacy != other.privacy,
+        }
+    }
+}
+
+/// Stored values of settings [`Settings`] knows that it would store
+/// differently, such as `on` for a toggle, as the rows to write in their
+/// place. Values that do not parse are left for the user to change.
+pub fn migrate_settings(rows: &[(String, String)]) -> Vec<(String, String)> {
+    rows.iter()
+        .filter(|(key, _)| Settings::has_key(key))
+        .filter_map(|(key, value)| {
+            let settings = Settings::default().with_value(key, value)?;
+            let (_, stored) = settings.to_rows().into_iter().find(|(stored, _)| stored == key)?;
+            (stored != *value).then(|| (key.clone(), stored))
+        })
+        .collect()
+}
+
+/// The current [`Settings`], shared by the components reading them.
+/// Readers take the whole settings at once and never see half an update;
+/// writers swap in a new copy.
+#[derive(Debug, Clone, Default)]
+pub struct SharedSettings {
+    current: Arc<ArcSwap<Settings>>,
+    /// Held through an update, so concurrent ones do not lose each other
+    updating: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl SharedSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The settings as they are now
+    pub fn load(&self) -> Arc<Settings> {
+        self.current.load_full()
+    }
+
+    pub fn store(&self, settings: Settings) {
+        self.current.store(Arc::new(settings));
+    }
+
+    /// Wait for other updates to finish; this one goes on until the guard
+    /// is dropped
+    pub(crate) async fn begin_update(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.updating.lock().await
+    }
+}
+
+/// Toggles are stored as `true` or `false`, though older values such as
+/// `on` still read
+mod toggle {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(if *value { "true" } else { "false" })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        parse_toggle(&value).ok_or_else(|| D::Error::custom(format!("expected true or false, not \"{}\"", value)))
+    }
+}
+
+mod url_text {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(url: &ValidatedUrl, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(url.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ValidatedUrl, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        ValidatedUrl::parse(value.trim()).map_err(D::Error::custom)
+    }
+}
+
+/// Choices stored by the names their `Display` writes
+mod text {
+    use super::*;
+
+    pub trait Choice: fmt::Display + Sized {
+        fn parse(value: &str) -> Option<Self>;
+    }
+
+    impl Choice for Theme {
+        fn parse(value: &str) -> Option<Self> {
+            Theme::parse(value)
+        }
+    }
+
+    impl Choice for CookieBlocking {
+        fn parse(value: &str) -> Option<Self> {
+            CookieBlocking::parse(value)
+        }
+    }
+
+    pub fn serialize<S: Serializer, T: Choice>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Choice>(deserializer: D) -> Result<T, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        T::parse(&value).ok_or_else(|| D::Error::custom(format!("unknown choice \"{}\"", value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[(&str, &str)]) -> Vec<(String, String)> {
+        rows.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_settings_round_trip_through_their_keys() {
+        let settings = Settings {
+            homepage: ValidatedUrl::parse("https://example.org/start").unwrap(),
+            theme: Theme::Dark,
+            https_only: true,
+            download_dir: PathBuf::from("/home/me/Saved"),
+            search: SearchSettings { suggestions: true },
+            network: NetworkSettings {
+                user_agent: "Bot/1.0".to_string(),
+                offline: true,
+                cookies: CookieBlocking::BlockAll,
+            },
+            privacy: PrivacySettings {
+                content_blocker: false,
+                restore_session: true,
+            },
+        };
+        let stored = settings.to_rows();
+        assert!(stored.contains(&(THEME_SETTING.to_string(), "dark".to_string())));
+        assert!(stored.contains(&(COOKIES_SETTING.to_string(), "block-all".to_string())));
+        assert!(stored.contains(&(CONTENT_BLOCKER_SETTING.to_string(), "false".to_string())));
+        assert!(stored.iter().all(|(key, _)| Settings::has_key(key)));
+        assert_eq!(stored.len(), 10);
+        assert_eq!(Settings::from_rows(&stored), (settings, Vec::new()));
+
+        let defaults = Settings::default();
+        assert_eq!(Settings::from_rows(&defaults.to_rows()).0, defaults);
+        assert_eq!(Settings::from_rows(&[]).0, defaults);
+        assert!(defaults.privacy.content_blocker);
+        assert_eq!(defaults.network.cookies, CookieBlocking::BlockThirdParty);
+    }
+
+    #[test]
+    fn test_missing_unknown_and_invalid_keys_are_tolerated() {
+        let (settings, invalid) = Settings::from_rows(&rows(&[
+            (THEME_SETTING, "light"),
+            ("future.setting", "anything"),
+            (HTTPS_ONLY_SETTING, "maybe"),
+            (HOMEPAGE_SETTING, "not a url"),
+        ]));
+        assert_eq!(settings.theme, Theme::Light);
+        assert!(!settings.https_only);
+        assert_eq!(settings.homepage, Settings::default().homepage);
+        assert_eq!(invalid, [HTTPS_ONLY_SETTING, HOMEPAGE_SETTING]);
+
+        assert_eq!(settings.with_value(HTTPS_ONLY_SETTING, "on").map(|s| s.https_only), Some(true));
+        assert_eq!(settings.with_value(THEME_SETTING, "sepia"), None);
+    }
+
+    #[test]
+    fn test_changed_sections_are_named() {
+        let before = Settings::default();
+        let after = before.with_value(OFFLINE_SETTING, "true").unwrap();
+        let changed = before.changed_sections(&after);
+        assert_eq!(
+            changed,
+            SettingsSections {
+                network: true,
+                ..SettingsSections::default()
+            }
+        );
+        let after = after.with_value(THEME_SETTING, "dark").unwrap();
+        assert!(before.changed_sections(&after).general);
+        assert!(before.changed_sections(&before).is_empty());
+    }
+
+    #[test]
+    fn test_raw_values_are_migrated_to_their_stored_form() {
+        let migrated = migrate_settings(&rows(&[
+            (HTTPS_ONLY_SETTING, "On"),
+            (THEME_SETTING, "Dark"),
+            (HOMEPAGE_SETTING, " https://example.org"),
+            (OFFLINE_SETTING, "false"),
+            (COOKIES_SETTING, "block_all"),
+            (USER_AGENT_SETTING, "Bot/1.0"),
+            ("future.setting", "On"),
+            (CONTENT_BLOCKER_SETTING, "sometimes"),
+        ]));
+        assert_eq!(
+            migrated,
+            rows(&[
+                (HTTPS_ONLY_SETTING, "true"),
+                (THEME_SETTING, "dark"),
+                (HOMEPAGE_SETTING, "https://example.org/"),
+                (COOKIES_SETTING, "block-all"),
+            ])
+        );
+    }
+}
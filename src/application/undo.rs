@@ -0,0 +1,119 @@
+use crate::domain::{Bookmark, DeletedHistory, Download};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long after a destructive action it can still be undone
+pub const UNDO_WINDOW: Duration = Duration::from_secs(60);
+/// Most actions kept to undo; older ones are forgotten
+pub const MAX_UNDO_ACTIONS: usize = 20;
+
+/// What a destructive action removed, kept whole so undoing it puts back
+/// exactly that
+#[derive(Debug, Clone, PartialEq)]
+pub enum Deleted {
+    /// Bookmarks with their ids, folders and tags
+    Bookmarks(Vec<Bookmark>),
+    /// History entries with their ids, and the visits deleted from them
+    History(DeletedHistory),
+    /// A download taken off the list, its file left alone
+    Download(Download),
+}
+
+struct UndoEntry<T> {
+    description: String,
+    inverse: T,
+    done_at: Instant,
+}
+
+/// Recent destructive actions, each with a description and what takes it
+/// back. Actions older than the window, or beyond the newest
+/// `MAX_UNDO_ACTIONS`, can no longer be undone.
+pub struct UndoStack<T> {
+    entries: VecDeque<UndoEntry<T>>,
+    window: Duration,
+}
+
+impl<T> UndoStack<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+            window: UNDO_WINDOW,
+        }
+    }
+
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Remember an action done at `now`, described as `description`
+    pub fn push(&mut self, description: impl Into<String>, inverse: T, now: Instant) {
+        if self.entries.len() == MAX_UNDO_ACTIONS {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(UndoEntry {
+            description: description.into(),
+            inverse,
+            done_at: now,
+        });
+    }
+
+    /// Take the newest action that can still be undone at `now`, with its
+    /// description; expired ones are forgotten
+    pub fn pop(&mut self, now: Instant) -> Option<(String, T)> {
+        self.entries
+            .retain(|entry| now.saturating_duration_since(entry.done_at) <= self.window);
+        self.entries
+            .pop_back()
+            .map(|entry| (entry.description, entry.inverse))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T> Default for UndoStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newest_action_is_undone_first_until_it_expires() {
+        let start = Instant::now();
+        let mut stack = UndoStack::new().with_window(Duration::from_secs(10));
+        stack.push("first", 1, start);
+        stack.push("second", 2, start + Duration::from_secs(5));
+
+        assert_eq!(stack.pop(start + Duration::from_secs(8)), Some(("second".to_string(), 2)));
+        assert_eq!(stack.pop(start + Duration::from_secs(11)), None);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_actions_are_forgotten_past_the_limit() {
+        let now = Instant::now();
+        let mut stack = UndoStack::new();
+        for action in 0..MAX_UNDO_ACTIONS + 5 {
+            stack.push(format!("action {}", action), action, now);
+        }
+        assert_eq!(stack.len(), MAX_UNDO_ACTIONS);
+
+        let mut undone = Vec::new();
+        while let Some((_, action)) = stack.pop(now) {
+            undone.push(action);
+        }
+        assert_eq!(undone.len(), MAX_UNDO_ACTIONS);
+        assert_eq!(undone.first(), Some(&(MAX_UNDO_ACTIONS + 4)));
+        assert_eq!(undone.last(), Some(&5));
+    }
+}
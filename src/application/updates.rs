@@ -0,0 +1,103 @@
+use crate::domain::{UpdateError, UpdateManifest, ValidatedUrl};
+use chrono::{DateTime, Utc};
+use semver::Version;
+use std::time::Duration;
+
+/// Check for a newer release on startup; off unless turned on
+pub const UPDATE_CHECK_SETTING: &str = "updates.check";
+/// Where the JSON manifest describing the latest release is fetched from;
+/// empty skips the check
+pub const UPDATE_MANIFEST_SETTING: &str = "updates.manifest_url";
+/// When the update check last ran, in RFC 3339; not listed on
+/// navigator://settings
+pub const UPDATE_LAST_CHECKED_SETTING: &str = "updates.last_checked";
+/// Release notes of the update found, with a link to download it
+pub const UPDATE_PAGE: &str = "navigator://update";
+/// Shortest time between two update checks
+pub const UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Version of this build, compared with the manifest's
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Whether a day has passed since the check recorded in `last_checked`.
+/// A missing or unreadable record, or one in the future after the clock
+/// was turned back, does not hold the check back.
+pub fn update_check_due(last_checked: Option<&str>, now: DateTime<Utc>) -> bool {
+    let Some(last) = last_checked.and_then(|value| DateTime::parse_from_rfc3339(value.trim()).ok()) else {
+        return true;
+    };
+    (now - last.with_timezone(&Utc))
+        .to_std()
+        .map_or(true, |elapsed| elapsed >= UPDATE_CHECK_INTERVAL)
+}
+
+/// The release `manifest` describes, if its version is newer than
+/// `current` by semantic versioning; a leading `v` is allowed
+pub fn newer_release(manifest: UpdateManifest, current: &str) -> Result<Option<UpdateManifest>, UpdateError> {
+    let parse = |version: &str| {
+        let version = version.trim();
+        Version::parse(version.strip_prefix('v').unwrap_or(version))
+            .map_err(|e| UpdateError::InvalidManifest(format!("version \"{}\": {}", version, e)))
+    };
+    let offered = parse(&manifest.version)?;
+    Ok((offered > parse(current)?).then_some(manifest))
+}
+
+/// Name to save a release under: the last segment of its URL, else one
+/// made from its version
+pub fn release_file_name(url: &ValidatedUrl, version: &str) -> String {
+    url.path()
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            percent_encoding::percent_decode_str(name)
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+        .unwrap_or_else(|| format!("navigator-{}", version.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(version: &str) -> UpdateManifest {
+        UpdateManifest {
+            version: version.to_string(),
+            notes: String::new(),
+            download: ValidatedUrl::parse("https://example.com/navigator-setup.exe").unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_versions_are_compared_semantically() {
+        assert!(newer_release(manifest("0.10.0"), "0.9.3").unwrap().is_some());
+        assert!(newer_release(manifest("v1.0.0"), "1.0.0-beta.2").unwrap().is_some());
+        assert_eq!(newer_release(manifest("0.1.0"), "0.1.0"), Ok(None));
+        assert_eq!(newer_release(manifest("1.0.0-rc.1"), "1.0.0"), Ok(None));
+        assert!(matches!(
+            newer_release(manifest("1.2"), "1.0.0"),
+            Err(UpdateError::InvalidManifest(_))
+        ));
+    }
+
+    #[test]
+    fn test_checks_run_once_a_day() {
+        let now = DateTime::parse_from_rfc3339("2024-05-02T12:00:00Z").unwrap().with_timezone(&Utc);
+        assert!(update_check_due(None, now));
+        assert!(update_check_due(Some("yesterday"), now));
+        assert!(!update_check_due(Some("2024-05-02T08:00:00+00:00"), now));
+        assert!(update_check_due(Some("2024-05-01T12:00:00Z"), now));
+        assert!(update_check_due(Some("2024-06-01T12:00:00Z"), now));
+    }
+
+    #[test]
+    fn test_release_file_name() {
+        let url = |input: &str| ValidatedUrl::parse(input).unwrap();
+        assert_eq!(
+            release_file_name(&url("https://example.com/releases/navigator%201.2.zip"), "1.2.0"),
+            "navigator 1.2.zip"
+        );
+        assert_eq!(release_file_name(&url("https://example.com/"), "1.2.0"), "navigator-1.2.0");
+    }
+}
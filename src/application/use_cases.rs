@@ -1,28 +1,67 @@
 use crate::domain::{
-    Bookmark, BookmarkRepository, HistoryEntry, HistoryRepository, RenderingEngine,
-    SecurityService, Tab, TabId, TabRepository, ValidatedUrl,
+    BlockCategory, BlockedSummary, Bookmark, BookmarkRepository, BrowserProfileReader, CertificateImportError,
+    ConsoleLevel, CspPolicy, CustomCa, CustomCaRepository, DeletedHistory, Download, DownloadError, DownloadRepository,
+    DownloadWriter, EditableSetting, HistoryEntry, HistoryRepository, HostRisk, HstsPolicy, ImportError, NewBookmark,
+    NewCustomCa, NewDownload, NewHistoryEntry, NewSearchEngine, NewUserScript, NewUserStyle, ObservedCookie,
+    OpenSearchFetcher, PageArchiver, PageCacheRepository, PagePrinter, PaperSize, PersistenceGuard, RenderingEngine,
+    RequestContext, ScriptOutcome, SearchEngine, SearchEngineError, SearchEngineRepository, SecurityError,
+    SecurityReport, SecurityService, SettingsRepository, SiteOrigin, SiteSetting, SiteSettings, SiteSettingsRepository,
+    StorageArea, StorageChange, Tab, TabId, TabRepository, TlsInspector, TrustStore, UpdateManifest, UpdateSource,
+    UserScript, UserScriptError, UserScriptRepository, UserScriptService, UserStyle, UserStyleRepository,
+    UserStyleService, ValidatedUrl, VisitType, WebStorageRepository, WindowId, NEW_TAB_TITLE, SECURITY_REPORT_VERSION,
 };
-use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tracing::Instrument;
 
+use super::download_safety::{assess_download, DownloadManager, DownloadOutcome, DownloadWrite, FileDownload};
+use super::downloads::{page_file_name, saved_html, SaveFormat, SessionDownloads};
+use super::error::NavigatorError;
+use super::history_writer::HistoryWriter;
+use super::keymap::{parse_chords, Command, Keymap, COMMANDS};
+use super::profile::{
+    parse_blocked_domains, BookmarkRecord, BrowserImportReport, BrowserImportRequest, HistoryRecord, ImportFailure,
+    ImportMode, ImportOptions, ImportReport, ProfileDocument, BLOCKED_DOMAINS_SETTING, PROFILE_VERSION,
+};
+use super::controller::ClearScope;
+use super::settings::{
+    editable_setting, setting_enabled, validate_setting, DEFAULT_SEARCH_ENGINE_SETTING, USER_AGENT_SETTING,
+};
 use super::state::BrowserState;
+use super::typed_settings::{
+    migrate_settings, Settings, SharedSettings, SETTINGS_VERSION, SETTINGS_VERSION_SETTING,
+};
+use super::undo::Deleted;
+use super::updates::{
+    newer_release, update_check_due, UPDATE_CHECK_SETTING, UPDATE_LAST_CHECKED_SETTING, UPDATE_MANIFEST_SETTING,
+};
+
+type Result<T> = std::result::Result<T, NavigatorError>;
 
 /// Use case: Open a new tab
-pub struct OpenTabUseCase {
+pub(crate) struct OpenTabUseCase {
     state: BrowserState,
     tab_repository: Arc<dyn TabRepository>,
 }
 
 impl OpenTabUseCase {
-    pub fn new(state: BrowserState, tab_repository: Arc<dyn TabRepository>) -> Self {
+    pub(crate) fn new(state: BrowserState, tab_repository: Arc<dyn TabRepository>) -> Self {
         Self {
             state,
             tab_repository,
         }
     }
 
-    pub async fn execute(&self, url: Option<ValidatedUrl>) -> Result<TabId> {
-        let is_private = self.state.is_private_mode();
+    /// The tab is private if `context` is
+    pub(crate) async fn execute(
+        &self,
+        context: RequestContext,
+        url: Option<ValidatedUrl>,
+        window: Option<WindowId>,
+    ) -> Result<TabId> {
+        let is_private = context.private;
 
         let tab = match url {
             Some(url) => Tab::with_url(url, is_private),
@@ -33,11 +72,16 @@ impl OpenTabUseCase {
 
         // Save to repository if not in private mode
         if !is_private {
-            self.tab_repository.save(&tab).await?;
+            PersistenceGuard::new(self.tab_repository.clone(), context)
+                .save(&tab)
+                .await?;
         }
 
         // Add to state
-        self.state.add_tab(tab);
+        match window {
+            Some(window) => self.state.add_tab_to_window(window, tab),
+            None => self.state.add_tab(tab),
+        };
         self.state.set_active_tab(tab_id);
 
         tracing::info!("Opened new tab: {}", tab_id);
@@ -47,33 +91,32 @@ impl OpenTabUseCase {
 }
 
 /// Use case: Close a tab
-pub struct CloseTabUseCase {
+pub(crate) struct CloseTabUseCase {
     state: BrowserState,
     tab_repository: Arc<dyn TabRepository>,
 }
 
 impl CloseTabUseCase {
-    pub fn new(state: BrowserState, tab_repository: Arc<dyn TabRepository>) -> Self {
+    pub(crate) fn new(state: BrowserState, tab_repository: Arc<dyn TabRepository>) -> Self {
         Self {
             state,
             tab_repository,
         }
     }
 
-    pub async fn execute(&self, tab_id: TabId) -> Result<()> {
+    /// The tab leaves the state even if deleting it from storage fails; that
+    /// failure is still returned, since the tab would come back on restore
+    pub(crate) async fn execute(&self, context: RequestContext, tab_id: TabId) -> Result<()> {
+        let was_active = self.state.get_active_tab_id() == Some(tab_id);
+
         // Remove from state
         let tab = self
             .state
             .remove_tab(tab_id)
-            .ok_or_else(|| anyhow!("Tab not found"))?;
-
-        // Delete from repository if not private
-        if !tab.is_private {
-            self.tab_repository.delete(tab_id).await?;
-        }
+            .ok_or(NavigatorError::TabNotFound(tab_id))?;
 
         // If this was the active tab, activate another
-        if self.state.get_active_tab_id() == Some(tab_id) {
+        if was_active {
             let tabs = self.state.get_all_tabs();
             if let Some(next_tab) = tabs.first() {
                 self.state.set_active_tab(next_tab.id);
@@ -82,50 +125,80 @@ impl CloseTabUseCase {
 
         tracing::info!("Closed tab: {}", tab_id);
 
+        // Delete from repository if not private
+        if !tab.is_private {
+            PersistenceGuard::new(self.tab_repository.clone(), context)
+                .delete(tab_id)
+                .await?;
+        }
+
         Ok(())
     }
 }
 
 /// Use case: Navigate to a URL
-pub struct NavigateUseCase {
+pub(crate) struct NavigateUseCase {
     state: BrowserState,
     security_service: Arc<dyn SecurityService>,
-    history_repository: Arc<dyn HistoryRepository>,
+    history_writer: HistoryWriter,
+    site_settings: Arc<dyn SiteSettingsRepository>,
     rendering_engine: Arc<dyn RenderingEngine>,
 }
 
 impl NavigateUseCase {
-    pub fn new(
+    pub(crate) fn new(
         state: BrowserState,
         security_service: Arc<dyn SecurityService>,
-        history_repository: Arc<dyn HistoryRepository>,
+        history_writer: HistoryWriter,
+        site_settings: Arc<dyn SiteSettingsRepository>,
         rendering_engine: Arc<dyn RenderingEngine>,
     ) -> Self {
         Self {
             state,
             security_service,
-            history_repository,
+            history_writer,
+            site_settings,
             rendering_engine,
         }
     }
 
-    pub async fn execute(&self, tab_id: TabId, url_str: &str) -> Result<()> {
-        // Validate URL
-        let url = self
-            .security_service
-            .validate_url(url_str)
-            .context("Invalid URL")?;
+    /// Load `url_str` in the tab, recording the visit as `visit_type`
+    pub(crate) async fn execute(
+        &self,
+        context: RequestContext,
+        tab_id: TabId,
+        url_str: &str,
+        visit_type: VisitType,
+    ) -> Result<()> {
+        // The URL is recorded once validated, without any data: payload
+        let span = tracing::info_span!("navigate", tab = %tab_id, url = tracing::field::Empty);
+        self.navigate(context, tab_id, url_str, visit_type).instrument(span).await
+    }
 
-        // Check if URL is blocked
-        if self.security_service.is_blocked(&url) {
-            return Err(anyhow!("This URL is blocked for security reasons"));
-        }
+    async fn navigate(
+        &self,
+        context: RequestContext,
+        tab_id: TabId,
+        url_str: &str,
+        visit_type: VisitType,
+    ) -> Result<()> {
+        let url = tracing::info_span!("validate").in_scope(|| {
+            // Validate URL
+            let url = self.security_service.validate_url(url_str)?;
+
+            // Check if URL is blocked
+            if self.security_service.is_blocked(&url) {
+                return Err(SecurityError::Blocked(url.host_str().unwrap_or(url.as_str()).to_string()));
+            }
+            Ok(url)
+        })?;
+        tracing::Span::current().record("url", url.for_history().as_str());
 
         // Get the tab
         let mut tab = self
             .state
             .get_tab(tab_id)
-            .ok_or_else(|| anyhow!("Tab not found"))?;
+            .ok_or(NavigatorError::TabNotFound(tab_id))?;
 
         // Update tab state
         tab.update_url(url.clone());
@@ -134,22 +207,45 @@ impl NavigateUseCase {
 
         tracing::info!("Navigating tab {} to {}", tab_id, url);
 
+        // The site's overrides go on top of the global settings for this load
+        let site = match SiteOrigin::of(&url) {
+            Some(origin) => self.site_settings.find(&origin).await.unwrap_or_else(|e| {
+                tracing::warn!("Cannot read the settings of {}, using the global ones: {}", origin, e);
+                None
+            }),
+            None => None,
+        };
+        self.rendering_engine.set_site_settings(site);
+
         // Load URL in rendering engine
-        self.rendering_engine
-            .load_url(&url)
-            .await
-            .context("Failed to load URL")?;
+        self.rendering_engine.load_url(&url).await?;
 
-        // Add to history if not in private mode
-        if !tab.is_private {
+        // Add to history if not in private mode; the empty document is no
+        // visit, and leaves the tab looking new
+        if url.is_about_blank() {
+            tab.update_title(NEW_TAB_TITLE.to_string());
+        } else if !tab.is_private {
             let title = self
                 .rendering_engine
                 .get_title()
                 .await
-                .unwrap_or_else(|_| url.as_str().to_string());
+                .unwrap_or_else(|_| url.for_history().as_str().to_string());
 
-            let entry = HistoryEntry::new(url.clone(), title.clone());
-            self.history_repository.add(&entry).await?;
+            // Never persist full data: payloads; the write happens in the background.
+            // Browser pages are not visits. A page redirected to is visited too.
+            if url.scheme() != "navigator" {
+                let entry = NewHistoryEntry::new(url.for_history(), title.clone()).with_visit_type(visit_type);
+                self.history_writer.record(context, entry);
+                let redirected = self
+                    .rendering_engine
+                    .document_fetch()
+                    .filter(|fetch| !fetch.redirected_from.is_empty() && !fetch.url.same_document(&url));
+                if let Some(fetch) = redirected {
+                    let entry = NewHistoryEntry::new(fetch.url.for_history(), title.clone())
+                        .with_visit_type(VisitType::Redirect);
+                    self.history_writer.record(context, entry);
+                }
+            }
 
             // Update tab title
             tab.update_title(title);
@@ -164,88 +260,2332 @@ impl NavigateUseCase {
 }
 
 /// Use case: Save a bookmark
-pub struct SaveBookmarkUseCase {
+pub(crate) struct SaveBookmarkUseCase {
     bookmark_repository: Arc<dyn BookmarkRepository>,
 }
 
 impl SaveBookmarkUseCase {
-    pub fn new(bookmark_repository: Arc<dyn BookmarkRepository>) -> Self {
+    pub(crate) fn new(bookmark_repository: Arc<dyn BookmarkRepository>) -> Self {
         Self {
             bookmark_repository,
         }
     }
 
-    pub async fn execute(&self, title: String, url: ValidatedUrl) -> Result<i64> {
-        let bookmark = Bookmark::new(title, url);
-        let id = self.bookmark_repository.save(&bookmark).await?;
+    /// Save a bookmark, or return the existing one for the same document
+    pub(crate) async fn execute(&self, title: String, url: ValidatedUrl) -> Result<Bookmark> {
+        let existing = self.bookmark_repository.find_all().await?;
+        if let Some(bookmark) = existing.into_iter().find(|b| b.url.same_document(&url)) {
+            tracing::info!("Already bookmarked as {}", bookmark.id);
+            return Ok(bookmark);
+        }
+
+        let bookmark = self
+            .bookmark_repository
+            .save(NewBookmark::new(title, url))
+            .await?;
 
-        tracing::info!("Saved bookmark: {}", id);
+        tracing::info!("Saved bookmark: {}", bookmark.id);
 
-        Ok(id)
+        Ok(bookmark)
     }
 }
 
 /// Use case: Search history
-pub struct SearchHistoryUseCase {
+pub(crate) struct SearchHistoryUseCase {
     history_repository: Arc<dyn HistoryRepository>,
 }
 
 impl SearchHistoryUseCase {
-    pub fn new(history_repository: Arc<dyn HistoryRepository>) -> Self {
+    pub(crate) fn new(history_repository: Arc<dyn HistoryRepository>) -> Self {
         Self { history_repository }
     }
 
-    pub async fn execute(&self, query: &str, limit: i32) -> Result<Vec<HistoryEntry>> {
-        self.history_repository.search(query, limit).await
+    pub(crate) async fn execute(&self, query: &str, limit: i32) -> Result<Vec<HistoryEntry>> {
+        Ok(self.history_repository.search(query, limit).await?)
     }
 }
 
 /// Use case: Get recent history
-pub struct GetRecentHistoryUseCase {
+pub(crate) struct GetRecentHistoryUseCase {
     history_repository: Arc<dyn HistoryRepository>,
 }
 
 impl GetRecentHistoryUseCase {
-    pub fn new(history_repository: Arc<dyn HistoryRepository>) -> Self {
+    pub(crate) fn new(history_repository: Arc<dyn HistoryRepository>) -> Self {
         Self { history_repository }
     }
 
-    pub async fn execute(&self, limit: i32) -> Result<Vec<HistoryEntry>> {
-        self.history_repository.get_recent(limit).await
+    pub(crate) async fn execute(&self, limit: i32) -> Result<Vec<HistoryEntry>> {
+        Ok(self.history_repository.get_recent(limit).await?)
     }
 }
 
 /// Use case: Clear browsing data
-pub struct ClearBrowsingDataUseCase {
+pub(crate) struct ClearBrowsingDataUseCase {
     history_repository: Arc<dyn HistoryRepository>,
+    site_settings: Arc<dyn SiteSettingsRepository>,
+    web_storage: Arc<dyn WebStorageRepository>,
+    page_cache: Arc<dyn PageCacheRepository>,
 }
 
 impl ClearBrowsingDataUseCase {
-    pub fn new(history_repository: Arc<dyn HistoryRepository>) -> Self {
+    pub(crate) fn new(
+        history_repository: Arc<dyn HistoryRepository>,
+        site_settings: Arc<dyn SiteSettingsRepository>,
+        web_storage: Arc<dyn WebStorageRepository>,
+        page_cache: Arc<dyn PageCacheRepository>,
+    ) -> Self {
+        Self {
+            history_repository,
+            site_settings,
+            web_storage,
+            page_cache,
+        }
+    }
+
+    pub(crate) async fn execute(&self, scope: ClearScope) -> Result<()> {
+        // Cached pages show where the user has been, so they go with history
+        if scope.history {
+            self.history_repository.clear_all().await?;
+            self.page_cache.clear_all().await?;
+        }
+        if scope.site_settings {
+            self.site_settings.clear_all().await?;
+        }
+        if scope.web_storage {
+            self.web_storage.clear_all().await?;
+        }
+        tracing::info!("Cleared browsing data: {:?}", scope);
+        Ok(())
+    }
+}
+
+/// Use case: Delete a single history entry
+pub(crate) struct DeleteHistoryEntryUseCase {
+    history_repository: Arc<dyn HistoryRepository>,
+}
+
+impl DeleteHistoryEntryUseCase {
+    pub(crate) fn new(history_repository: Arc<dyn HistoryRepository>) -> Self {
         Self { history_repository }
     }
 
-    pub async fn execute(&self) -> Result<()> {
-        self.history_repository.clear_all().await?;
-        tracing::info!("Cleared all browsing data");
+    pub(crate) async fn execute(&self, id: i64) -> Result<()> {
+        self.history_repository.delete_by_id(id).await?;
+        tracing::info!("Deleted history entry {}", id);
         Ok(())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::infrastructure::SqliteDatabase;
+/// Use case: Delete history from a span of time
+pub(crate) struct DeleteHistoryRangeUseCase {
+    history_repository: Arc<dyn HistoryRepository>,
+}
 
-    #[tokio::test]
-    async fn test_open_tab_use_case() {
-        let state = BrowserState::new();
-        let db = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+impl DeleteHistoryRangeUseCase {
+    pub(crate) fn new(history_repository: Arc<dyn HistoryRepository>) -> Self {
+        Self { history_repository }
+    }
 
-        let use_case = OpenTabUseCase::new(state.clone(), db);
-        let tab_id = use_case.execute(None).await.unwrap();
+    /// Delete the visits, returning what went
+    pub(crate) async fn execute(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<DeletedHistory> {
+        let deleted = self.history_repository.delete_range(from, to).await?;
+        tracing::info!(
+            "Deleted {} visits to {} history entries from {} to {}",
+            deleted.visits.len(),
+            deleted.entries.len(),
+            from,
+            to
+        );
+        Ok(deleted)
+    }
+}
 
-        assert_eq!(state.tab_count(), 1);
-        assert_eq!(state.get_active_tab_id(), Some(tab_id));
+/// Use case: Delete a bookmark
+pub(crate) struct DeleteBookmarkUseCase {
+    bookmark_repository: Arc<dyn BookmarkRepository>,
+}
+
+impl DeleteBookmarkUseCase {
+    pub(crate) fn new(bookmark_repository: Arc<dyn BookmarkRepository>) -> Self {
+        Self { bookmark_repository }
+    }
+
+    /// Delete the bookmark, returning it as it was
+    pub(crate) async fn execute(&self, id: i64) -> Result<Bookmark> {
+        let bookmark = self
+            .bookmark_repository
+            .find_by_id(id)
+            .await?
+            .ok_or(NavigatorError::BookmarkNotFound(id))?;
+        self.bookmark_repository.delete(id).await?;
+        tracing::info!("Deleted bookmark {}", id);
+        Ok(bookmark)
+    }
+}
+
+/// Use case: Delete a bookmark folder with everything filed in it
+pub(crate) struct DeleteFolderUseCase {
+    bookmark_repository: Arc<dyn BookmarkRepository>,
+}
+
+impl DeleteFolderUseCase {
+    pub(crate) fn new(bookmark_repository: Arc<dyn BookmarkRepository>) -> Self {
+        Self { bookmark_repository }
+    }
+
+    /// Delete the bookmarks in `folder` and the folders under it, as in
+    /// "Work" and "Work/Rust", returning them as they were
+    pub(crate) async fn execute(&self, folder: &str) -> Result<Vec<Bookmark>> {
+        let folder = folder.trim().trim_end_matches('/');
+        let filed = |bookmark: &Bookmark| {
+            bookmark.folder.as_deref().is_some_and(|name| {
+                name == folder || name.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'))
+            })
+        };
+        let bookmarks: Vec<Bookmark> =
+            self.bookmark_repository.find_all().await?.into_iter().filter(filed).collect();
+        for bookmark in &bookmarks {
+            self.bookmark_repository.delete(bookmark.id).await?;
+        }
+        tracing::info!("Deleted folder {} with {} bookmarks", folder, bookmarks.len());
+        Ok(bookmarks)
+    }
+}
+
+/// Use case: Move a bookmark to another place in its folder or another folder
+pub(crate) struct MoveBookmarkUseCase {
+    bookmark_repository: Arc<dyn BookmarkRepository>,
+}
+
+impl MoveBookmarkUseCase {
+    pub(crate) fn new(bookmark_repository: Arc<dyn BookmarkRepository>) -> Self {
+        Self { bookmark_repository }
+    }
+
+    /// Put the bookmark in `folder` at `index` among the bookmarks there
+    pub(crate) async fn execute(&self, id: i64, folder: Option<&str>, index: usize) -> Result<()> {
+        if self.bookmark_repository.find_by_id(id).await?.is_none() {
+            return Err(NavigatorError::BookmarkNotFound(id));
+        }
+        self.bookmark_repository.move_to(id, folder, index).await?;
+        tracing::info!("Moved bookmark {}", id);
+        Ok(())
+    }
+}
+
+/// Use case: Take a download off the list, and delete its file if asked
+pub(crate) struct RemoveDownloadEntryUseCase {
+    download_repository: Arc<dyn DownloadRepository>,
+    writer: Option<Arc<dyn DownloadWriter>>,
+}
+
+impl RemoveDownloadEntryUseCase {
+    pub(crate) fn new(
+        download_repository: Arc<dyn DownloadRepository>,
+        writer: Option<Arc<dyn DownloadWriter>>,
+    ) -> Self {
+        Self {
+            download_repository,
+            writer,
+        }
+    }
+
+    /// Remove the entry, returning it as it was
+    pub(crate) async fn execute(&self, id: i64, delete_file: bool) -> Result<Download> {
+        let download = self
+            .download_repository
+            .find_all()
+            .await?
+            .into_iter()
+            .find(|download| download.id == id)
+            .ok_or(NavigatorError::DownloadNotFound(id))?;
+        if delete_file {
+            let writer = self.writer.as_ref().ok_or(NavigatorError::SavingUnavailable)?;
+            writer.delete(&download.path).await?;
+        }
+        self.download_repository.delete(id).await?;
+        Ok(download)
+    }
+}
+
+/// Use case: Put back what a destructive action removed
+pub(crate) struct RestoreDeletedUseCase {
+    bookmark_repository: Arc<dyn BookmarkRepository>,
+    history_repository: Arc<dyn HistoryRepository>,
+    download_repository: Arc<dyn DownloadRepository>,
+}
+
+impl RestoreDeletedUseCase {
+    pub(crate) fn new(
+        bookmark_repository: Arc<dyn BookmarkRepository>,
+        history_repository: Arc<dyn HistoryRepository>,
+        download_repository: Arc<dyn DownloadRepository>,
+    ) -> Self {
+        Self {
+            bookmark_repository,
+            history_repository,
+            download_repository,
+        }
+    }
+
+    pub(crate) async fn execute(&self, deleted: &Deleted) -> Result<()> {
+        match deleted {
+            Deleted::Bookmarks(bookmarks) => self.bookmark_repository.restore(bookmarks).await?,
+            Deleted::History(deleted) => self.history_repository.restore(deleted).await?,
+            Deleted::Download(download) => self.download_repository.restore(download).await?,
+        }
+        Ok(())
+    }
+}
+
+/// Use case: Export the profile as a portable JSON document
+pub(crate) struct ExportProfileUseCase {
+    bookmark_repository: Arc<dyn BookmarkRepository>,
+    history_repository: Arc<dyn HistoryRepository>,
+    settings_repository: Arc<dyn SettingsRepository>,
+}
+
+impl ExportProfileUseCase {
+    pub(crate) fn new(
+        bookmark_repository: Arc<dyn BookmarkRepository>,
+        history_repository: Arc<dyn HistoryRepository>,
+        settings_repository: Arc<dyn SettingsRepository>,
+    ) -> Self {
+        Self {
+            bookmark_repository,
+            history_repository,
+            settings_repository,
+        }
+    }
+
+    pub(crate) async fn execute(&self, include_history: bool) -> Result<String> {
+        let bookmarks = self
+            .bookmark_repository
+            .find_all()
+            .await?
+            .into_iter()
+            .map(|bookmark| BookmarkRecord {
+                title: bookmark.title,
+                url: bookmark.url.as_str().to_string(),
+                folder: bookmark.folder,
+                tags: bookmark.tags,
+                created_at: bookmark.created_at,
+            })
+            .collect();
+
+        let mut settings: BTreeMap<String, String> =
+            self.settings_repository.get_all().await?.into_iter().collect();
+        let blocked_domains = settings
+            .remove(BLOCKED_DOMAINS_SETTING)
+            .map(|value| parse_blocked_domains(&value))
+            .unwrap_or_default();
+
+        let history = if include_history {
+            let entries = self.history_repository.get_recent(i32::MAX).await?;
+            Some(
+                entries
+                    .into_iter()
+                    .map(|entry| HistoryRecord {
+                        url: entry.url.as_str().to_string(),
+                        title: entry.title,
+                        visited_at: entry.visited_at,
+                        visit_count: entry.visit_count,
+                    })
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+        let document = ProfileDocument {
+            version: PROFILE_VERSION,
+            exported_at: chrono::Utc::now(),
+            bookmarks,
+            settings,
+            blocked_domains,
+            history,
+        };
+        serde_json::to_string_pretty(&document).map_err(|e| NavigatorError::InvalidProfile(e.to_string()))
+    }
+}
+
+/// Use case: Import a profile document exported by `ExportProfileUseCase`
+///
+/// Records that fail validation are reported and skipped; only an unreadable
+/// document, an unknown version or a storage failure stops the import.
+pub(crate) struct ImportProfileUseCase {
+    bookmark_repository: Arc<dyn BookmarkRepository>,
+    history_repository: Arc<dyn HistoryRepository>,
+    settings_repository: Arc<dyn SettingsRepository>,
+}
+
+impl ImportProfileUseCase {
+    pub(crate) fn new(
+        bookmark_repository: Arc<dyn BookmarkRepository>,
+        history_repository: Arc<dyn HistoryRepository>,
+        settings_repository: Arc<dyn SettingsRepository>,
+    ) -> Self {
+        Self {
+            bookmark_repository,
+            history_repository,
+            settings_repository,
+        }
+    }
+
+    pub(crate) async fn execute(&self, json: &str, options: ImportOptions) -> Result<ImportReport> {
+        // Check the version before the shape, which may differ between versions
+        let value: serde_json::Value =
+            serde_json::from_str(json).map_err(|e| NavigatorError::InvalidProfile(e.to_string()))?;
+        let version = value
+            .get("version")
+            .and_then(|version| version.as_u64())
+            .ok_or_else(|| NavigatorError::InvalidProfile("missing version".to_string()))?;
+        if version != PROFILE_VERSION as u64 {
+            return Err(NavigatorError::UnsupportedProfileVersion(
+                u32::try_from(version).unwrap_or(u32::MAX),
+            ));
+        }
+        let document: ProfileDocument =
+            serde_json::from_value(value).map_err(|e| NavigatorError::InvalidProfile(e.to_string()))?;
+
+        let mut report = ImportReport::default();
+        self.import_bookmarks(document.bookmarks, options.bookmarks, &mut report)
+            .await?;
+        self.import_blocked_domains(document.blocked_domains, options.blocked_domains, &mut report)
+            .await?;
+        // After blocked domains, so replacing settings keeps the imported list
+        self.import_settings(document.settings, options.settings, &mut report)
+            .await?;
+        if let Some(history) = document.history {
+            self.import_history(history, options.history, &mut report).await?;
+        }
+
+        tracing::info!(
+            "Imported profile: {} bookmarks, {} settings, {} blocked domains, {} history entries, {} skipped",
+            report.bookmarks,
+            report.settings,
+            report.blocked_domains,
+            report.history,
+            report.failures.len()
+        );
+        Ok(report)
+    }
+
+    async fn import_bookmarks(
+        &self,
+        records: Vec<BookmarkRecord>,
+        mode: ImportMode,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        let existing = self.bookmark_repository.find_all().await?;
+        let mut known: HashSet<String> = match mode {
+            ImportMode::Skip => return Ok(()),
+            ImportMode::Merge => existing.iter().map(|b| b.url.normalized().to_string()).collect(),
+            ImportMode::Replace => {
+                for bookmark in existing {
+                    self.bookmark_repository.delete(bookmark.id).await?;
+                }
+                HashSet::new()
+            }
+        };
+
+        // Each saved bookmark goes last in its folder, keeping the listed order
+        for record in records {
+            let url = match ValidatedUrl::parse(&record.url) {
+                Ok(url) => url,
+                Err(e) => {
+                    report.failures.push(failure("bookmarks", &record.url, e));
+                    continue;
+                }
+            };
+            if !known.insert(url.normalized().to_string()) {
+                continue;
+            }
+            let bookmark = NewBookmark {
+                title: record.title,
+                url,
+                folder: record.folder,
+                created_at: record.created_at,
+                tags: record.tags,
+            };
+            self.bookmark_repository.save(bookmark).await?;
+            report.bookmarks += 1;
+        }
+        Ok(())
+    }
+
+    async fn import_blocked_domains(
+        &self,
+        domains: Vec<String>,
+        mode: ImportMode,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        let mut blocked = match mode {
+            ImportMode::Skip => return Ok(()),
+            ImportMode::Merge => self
+                .settings_repository
+                .get(BLOCKED_DOMAINS_SETTING)
+                .await?
+                .map(|value| parse_blocked_domains(&value))
+                .unwrap_or_default(),
+            ImportMode::Replace => Vec::new(),
+        };
+
+        for domain in domains {
+            let domain = domain.trim().to_ascii_lowercase();
+            // A bare host name parses back to itself
+            let valid = ValidatedUrl::parse(&format!("https://{}/", domain))
+                .is_ok_and(|url| url.host_str() == Some(domain.as_str()));
+            if !valid {
+                report
+                    .failures
+                    .push(failure("blocked_domains", &domain, "not a host name"));
+            } else if !blocked.contains(&domain) {
+                blocked.push(domain);
+                report.blocked_domains += 1;
+            }
+        }
+
+        self.settings_repository
+            .set(BLOCKED_DOMAINS_SETTING, &blocked.join("\n"))
+            .await?;
+        Ok(())
+    }
+
+    async fn import_settings(
+        &self,
+        settings: BTreeMap<String, String>,
+        mode: ImportMode,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        match mode {
+            ImportMode::Skip => return Ok(()),
+            ImportMode::Merge => {}
+            ImportMode::Replace => {
+                for (key, _) in self.settings_repository.get_all().await? {
+                    if key != BLOCKED_DOMAINS_SETTING && !settings.contains_key(&key) {
+                        self.settings_repository.delete(&key).await?;
+                    }
+                }
+            }
+        }
+
+        for (key, value) in settings {
+            // Blocked domains have their own section
+            if key.is_empty() || key == BLOCKED_DOMAINS_SETTING {
+                report.failures.push(failure("settings", &key, "reserved key"));
+                continue;
+            }
+            self.settings_repository.set(&key, &value).await?;
+            report.settings += 1;
+        }
+        Ok(())
+    }
+
+    async fn import_history(
+        &self,
+        records: Vec<HistoryRecord>,
+        mode: ImportMode,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        match mode {
+            ImportMode::Skip => return Ok(()),
+            ImportMode::Merge => {}
+            ImportMode::Replace => self.history_repository.clear_all().await?,
+        }
+
+        let mut entries = Vec::with_capacity(records.len());
+        for record in records {
+            match ValidatedUrl::parse(&record.url) {
+                Ok(url) => entries.push(NewHistoryEntry {
+                    url,
+                    title: record.title,
+                    visited_at: record.visited_at,
+                    visit_count: record.visit_count.max(1),
+                    visit_type: VisitType::Link,
+                }),
+                Err(e) => report.failures.push(failure("history", &record.url, e)),
+            }
+        }
+        self.history_repository.add_batch(&entries).await?;
+        report.history = entries.len();
+        Ok(())
+    }
+}
+
+/// Use case: Bring bookmarks and history over from another browser
+pub(crate) struct ImportFromBrowserUseCase {
+    reader: Arc<dyn BrowserProfileReader>,
+    bookmark_repository: Arc<dyn BookmarkRepository>,
+    history_repository: Arc<dyn HistoryRepository>,
+}
+
+impl ImportFromBrowserUseCase {
+    pub(crate) fn new(
+        reader: Arc<dyn BrowserProfileReader>,
+        bookmark_repository: Arc<dyn BookmarkRepository>,
+        history_repository: Arc<dyn HistoryRepository>,
+    ) -> Self {
+        Self {
+            reader,
+            bookmark_repository,
+            history_repository,
+        }
+    }
+
+    /// Import everything asked for, skipping URLs that are already known
+    pub(crate) async fn execute(&self, request: BrowserImportRequest) -> Result<BrowserImportReport> {
+        let profile = match request.profile_path {
+            Some(path) => path,
+            None => self.reader.default_profile(request.source).ok_or_else(|| {
+                ImportError::ProfileNotFound(format!("no default {} profile", request.source))
+            })?,
+        };
+        let mut report = BrowserImportReport {
+            dry_run: request.dry_run,
+            ..Default::default()
+        };
+
+        if request.items.bookmarks {
+            let mut known: HashSet<String> = self
+                .bookmark_repository
+                .find_all()
+                .await?
+                .iter()
+                .map(|b| b.url.normalized().to_string())
+                .collect();
+            for bookmark in self.reader.read_bookmarks(request.source, &profile).await? {
+                if !known.insert(bookmark.url.normalized().to_string()) {
+                    report.duplicates += 1;
+                    continue;
+                }
+                if !request.dry_run {
+                    self.bookmark_repository.save(bookmark).await?;
+                }
+                report.bookmarks += 1;
+            }
+        }
+
+        if request.items.history {
+            let mut seen = HashSet::new();
+            let mut entries = Vec::new();
+            for entry in self.reader.read_history(request.source, &profile).await? {
+                // Importing twice must not double the visit counts
+                if !seen.insert(entry.url.normalized().to_string())
+                    || self.history_repository.find_by_url(&entry.url).await?.is_some()
+                {
+                    report.duplicates += 1;
+                    continue;
+                }
+                entries.push(entry);
+            }
+            if !request.dry_run {
+                self.history_repository.add_batch(&entries).await?;
+            }
+            report.history = entries.len();
+        }
+
+        tracing::info!(
+            "{} {} bookmarks and {} history entries from {} ({} already known)",
+            if request.dry_run { "Would import" } else { "Imported" },
+            report.bookmarks,
+            report.history,
+            request.source,
+            report.duplicates
+        );
+        Ok(report)
+    }
+}
+
+/// Use case: Save a user style rule and put it into effect
+pub(crate) struct AddUserStyleUseCase {
+    repository: Arc<dyn UserStyleRepository>,
+    service: Arc<dyn UserStyleService>,
+}
+
+impl AddUserStyleUseCase {
+    pub(crate) fn new(repository: Arc<dyn UserStyleRepository>, service: Arc<dyn UserStyleService>) -> Self {
+        Self { repository, service }
+    }
+
+    pub(crate) async fn execute(&self, style: NewUserStyle) -> Result<UserStyle> {
+        self.service.validate(&style.rule)?;
+        let saved = self.repository.save(style).await?;
+        self.service.set_styles(self.repository.find_all().await?);
+        tracing::info!("Added user style {} for {}", saved.id, saved.host_pattern);
+        Ok(saved)
+    }
+}
+
+/// Use case: Delete a user style rule and stop applying it
+pub(crate) struct RemoveUserStyleUseCase {
+    repository: Arc<dyn UserStyleRepository>,
+    service: Arc<dyn UserStyleService>,
+}
+
+impl RemoveUserStyleUseCase {
+    pub(crate) fn new(repository: Arc<dyn UserStyleRepository>, service: Arc<dyn UserStyleService>) -> Self {
+        Self { repository, service }
+    }
+
+    pub(crate) async fn execute(&self, id: i64) -> Result<()> {
+        self.repository.delete(id).await?;
+        self.service.set_styles(self.repository.find_all().await?);
+        Ok(())
+    }
+}
+
+/// Use case: Replace every user style rule at once, keeping the rules that
+/// stay under their ids. Nothing changes if one of the rules cannot be
+/// applied.
+pub(crate) struct ReplaceUserStylesUseCase {
+    repository: Arc<dyn UserStyleRepository>,
+    service: Arc<dyn UserStyleService>,
+}
+
+impl ReplaceUserStylesUseCase {
+    pub(crate) fn new(repository: Arc<dyn UserStyleRepository>, service: Arc<dyn UserStyleService>) -> Self {
+        Self { repository, service }
+    }
+
+    pub(crate) async fn execute(&self, styles: Vec<NewUserStyle>) -> Result<Vec<UserStyle>> {
+        for style in &styles {
+            self.service.validate(&style.rule)?;
+        }
+        let same = |style: &NewUserStyle, saved: &UserStyle| {
+            style.host_pattern == saved.host_pattern && style.rule == saved.rule
+        };
+        let mut kept = Vec::new();
+        for saved in self.repository.find_all().await? {
+            if styles.iter().any(|style| same(style, &saved)) {
+                kept.push(saved);
+            } else {
+                self.repository.delete(saved.id).await?;
+            }
+        }
+        for style in styles {
+            if !kept.iter().any(|saved| same(&style, saved)) {
+                kept.push(self.repository.save(style).await?);
+            }
+        }
+        self.service.set_styles(kept.clone());
+        tracing::info!("Replaced the user styles with {} rules", kept.len());
+        Ok(kept)
+    }
+}
+
+/// Use case: Trust a certificate authority besides the built-in roots. It
+/// must be a current CA certificate not trusted already.
+pub(crate) struct ImportCertificateUseCase {
+    repository: Arc<dyn CustomCaRepository>,
+    trust_store: Arc<dyn TrustStore>,
+}
+
+impl ImportCertificateUseCase {
+    pub(crate) fn new(repository: Arc<dyn CustomCaRepository>, trust_store: Arc<dyn TrustStore>) -> Self {
+        Self { repository, trust_store }
+    }
+
+    /// Trust the certificate in `pem` under `name`, or its common name
+    pub(crate) async fn execute(&self, name: Option<&str>, pem: &str) -> Result<CustomCa> {
+        let details = self.trust_store.inspect(pem)?;
+        if !details.is_ca {
+            return Err(CertificateImportError::NotAuthority(details.subject).into());
+        }
+        if details.valid_until < Utc::now() {
+            let expired = CertificateImportError::Expired {
+                subject: details.subject,
+                expired: details.valid_until,
+            };
+            return Err(expired.into());
+        }
+        let existing = self.repository.find_all().await?;
+        if let Some(trusted) = existing.iter().find(|ca| ca.fingerprint == details.fingerprint) {
+            return Err(CertificateImportError::AlreadyTrusted(trusted.name.clone()).into());
+        }
+
+        let name = name
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .or(details.common_name)
+            .unwrap_or(details.subject);
+        let saved = self
+            .repository
+            .save(NewCustomCa {
+                name,
+                pem: details.pem,
+                fingerprint: details.fingerprint,
+            })
+            .await?;
+        if let Err(e) = self.trust_store.set_custom_roots(&self.repository.find_all().await?) {
+            self.repository.delete(saved.id).await?;
+            return Err(CertificateImportError::from(e).into());
+        }
+        tracing::info!("Trusting certificate authority {} ({})", saved.name, saved.fingerprint);
+        Ok(saved)
+    }
+}
+
+/// Use case: Stop trusting a certificate authority the user added
+pub(crate) struct RemoveCertificateUseCase {
+    repository: Arc<dyn CustomCaRepository>,
+    trust_store: Arc<dyn TrustStore>,
+}
+
+impl RemoveCertificateUseCase {
+    pub(crate) fn new(repository: Arc<dyn CustomCaRepository>, trust_store: Arc<dyn TrustStore>) -> Self {
+        Self { repository, trust_store }
+    }
+
+    pub(crate) async fn execute(&self, id: i64) -> Result<CustomCa> {
+        let removed = self
+            .repository
+            .find_all()
+            .await?
+            .into_iter()
+            .find(|ca| ca.id == id)
+            .ok_or(CertificateImportError::NotFound(id))?;
+        self.repository.delete(id).await?;
+        self.trust_store
+            .set_custom_roots(&self.repository.find_all().await?)
+            .map_err(CertificateImportError::from)?;
+        tracing::info!("No longer trusting certificate authority {}", removed.name);
+        Ok(removed)
+    }
+}
+
+/// Use case: Save a search engine under a keyword not yet taken. The first
+/// engine saved becomes the default.
+pub(crate) struct AddSearchEngineUseCase {
+    repository: Arc<dyn SearchEngineRepository>,
+}
+
+impl AddSearchEngineUseCase {
+    pub(crate) fn new(repository: Arc<dyn SearchEngineRepository>) -> Self {
+        Self { repository }
+    }
+
+    pub(crate) async fn execute(&self, engine: NewSearchEngine) -> Result<SearchEngine> {
+        let existing = self.repository.find_all().await?;
+        engine.check_keyword(&existing)?;
+
+        let mut saved = self.repository.save(engine).await?;
+        if !existing.iter().any(|e| e.is_default) {
+            self.repository.set_default(saved.id).await?;
+            saved.is_default = true;
+        }
+        tracing::info!("Added search engine {} with keyword {}", saved.name, saved.keyword);
+        Ok(saved)
+    }
+}
+
+/// Use case: Read the search engine a page offers in its OpenSearch
+/// description, unless one for that site is saved already. Nothing is
+/// saved; an accepted offer is added with `AddSearchEngineUseCase`.
+pub(crate) struct OfferSearchEngineUseCase {
+    repository: Arc<dyn SearchEngineRepository>,
+    fetcher: Arc<dyn OpenSearchFetcher>,
+}
+
+impl OfferSearchEngineUseCase {
+    pub(crate) fn new(repository: Arc<dyn SearchEngineRepository>, fetcher: Arc<dyn OpenSearchFetcher>) -> Self {
+        Self { repository, fetcher }
+    }
+
+    pub(crate) async fn execute(&self, description: &ValidatedUrl) -> Result<Option<NewSearchEngine>> {
+        let offered = self.fetcher.fetch(description).await?;
+        let host = offered.query_template.host();
+        let saved = self.repository.find_all().await?;
+        if saved.iter().any(|e| e.keyword == offered.keyword || e.query_template.host() == host) {
+            return Ok(None);
+        }
+        Ok(Some(offered))
+    }
+}
+
+/// Use case: Search with another engine when typed text is not a URL
+pub(crate) struct SetDefaultSearchEngineUseCase {
+    repository: Arc<dyn SearchEngineRepository>,
+}
+
+impl SetDefaultSearchEngineUseCase {
+    pub(crate) fn new(repository: Arc<dyn SearchEngineRepository>) -> Self {
+        Self { repository }
+    }
+
+    pub(crate) async fn execute(&self, id: i64) -> Result<()> {
+        if !self.repository.find_all().await?.iter().any(|e| e.id == id) {
+            return Err(SearchEngineError::NotFound(id).into());
+        }
+        self.repository.set_default(id).await?;
+        Ok(())
+    }
+}
+
+/// Use case: Change a setting listed on navigator://settings, keeping the
+/// typed [`Settings`] components read in step
+pub(crate) struct UpdateSettingsUseCase {
+    settings: Arc<dyn SettingsRepository>,
+    search_engines: Arc<dyn SearchEngineRepository>,
+    shared: SharedSettings,
+    state: BrowserState,
+}
+
+impl UpdateSettingsUseCase {
+    pub(crate) fn new(
+        settings: Arc<dyn SettingsRepository>,
+        search_engines: Arc<dyn SearchEngineRepository>,
+        shared: SharedSettings,
+        state: BrowserState,
+    ) -> Self {
+        Self {
+            settings,
+            search_engines,
+            shared,
+            state,
+        }
+    }
+
+    /// Read the stored settings into the shared ones, first rewriting
+    /// values stored before they were typed the way they are stored now
+    pub(crate) async fn load(&self) -> Result<Arc<Settings>> {
+        let _update = self.shared.begin_update().await;
+        let mut rows = self.settings.get_all().await?;
+        let migrated = rows
+            .iter()
+            .any(|(key, value)| key == SETTINGS_VERSION_SETTING && value == SETTINGS_VERSION);
+        if !migrated {
+            for (key, value) in migrate_settings(&rows) {
+                tracing::info!("Migrating setting {} to {:?}", key, value);
+                self.settings.set(&key, &value).await?;
+                if let Some(row) = rows.iter_mut().find(|(stored, _)| *stored == key) {
+                    row.1 = value;
+                }
+            }
+            self.settings.set(SETTINGS_VERSION_SETTING, SETTINGS_VERSION).await?;
+        }
+        let (settings, invalid) = Settings::from_rows(&rows);
+        for key in invalid {
+            tracing::warn!("Ignoring the stored value of {}; using its default", key);
+        }
+        self.shared.store(settings);
+        Ok(self.shared.load())
+    }
+
+    /// The setting's value, or its default while none is stored
+    pub(crate) async fn current(&self, setting: &EditableSetting) -> Result<String> {
+        if setting.key == DEFAULT_SEARCH_ENGINE_SETTING {
+            let engines = self.search_engines.find_all().await?;
+            let default = engines.into_iter().find(|engine| engine.is_default);
+            return Ok(default.map(|engine| engine.keyword).unwrap_or_default());
+        }
+        Ok(self
+            .settings
+            .get(setting.key)
+            .await?
+            .unwrap_or_else(|| setting.default.to_string()))
+    }
+
+    /// Every command bound to its stored shortcuts, or its defaults
+    pub(crate) async fn keymap(&self) -> Result<Keymap> {
+        let mut bindings = Vec::new();
+        for info in COMMANDS {
+            bindings.push((info.command, self.current(&info.setting).await?));
+        }
+        Ok(Keymap::from_bindings(
+            bindings.iter().map(|(command, chords)| (*command, chords.as_str())),
+        ))
+    }
+
+    /// Validate and store a new value, returning the setting and the value
+    /// as stored. A shortcut already running another command is refused.
+    pub(crate) async fn execute(&self, key: &str, value: &str) -> Result<(&'static EditableSetting, String)> {
+        let setting = editable_setting(key).ok_or_else(|| NavigatorError::UnknownSetting(key.to_string()))?;
+        let invalid = |reason: String| NavigatorError::InvalidSetting {
+            label: setting.label.to_string(),
+            reason,
+        };
+        let value = validate_setting(setting, value).map_err(&invalid)?;
+
+        if let Some(command) = Command::for_setting(setting.key) {
+            let chords = parse_chords(&value).map_err(|e| invalid(e.to_string()))?;
+            let mut keymap = self.keymap().await?;
+            keymap.bind(command, chords).map_err(|e| invalid(e.to_string()))?;
+        }
+        if setting.key == DEFAULT_SEARCH_ENGINE_SETTING {
+            let engines = self.search_engines.find_all().await?;
+            let engine = engines
+                .iter()
+                .find(|engine| engine.keyword.eq_ignore_ascii_case(&value))
+                .ok_or_else(|| invalid(format!("no search engine has the keyword \"{}\"", value)))?;
+            self.search_engines.set_default(engine.id).await?;
+            return Ok((setting, engine.keyword.clone()));
+        }
+        let _update = self.shared.begin_update().await;
+        self.settings.set(setting.key, &value).await?;
+        tracing::info!("Set {} to {:?}", setting.key, value);
+        let before = self.shared.load();
+        if let Some(after) = before.with_value(setting.key, &value) {
+            let changed = before.changed_sections(&after);
+            self.shared.store(after);
+            if !changed.is_empty() {
+                self.state.notify_settings_changed(changed);
+            }
+        }
+        Ok((setting, value))
+    }
+}
+
+/// Use case: Set or remove one of a site's overrides of the global
+/// settings. A site left without overrides is forgotten.
+pub(crate) struct SetSiteSettingUseCase {
+    repository: Arc<dyn SiteSettingsRepository>,
+}
+
+impl SetSiteSettingUseCase {
+    pub(crate) fn new(repository: Arc<dyn SiteSettingsRepository>) -> Self {
+        Self { repository }
+    }
+
+    /// The overrides of `url`'s site, empty if it has none
+    pub(crate) async fn current(&self, url: &ValidatedUrl) -> Result<SiteSettings> {
+        let origin = site_origin(url)?;
+        Ok(self
+            .repository
+            .find(&origin)
+            .await?
+            .unwrap_or_else(|| SiteSettings::new(origin)))
+    }
+
+    pub(crate) async fn execute(&self, url: &ValidatedUrl, setting: SiteSetting) -> Result<SiteSettings> {
+        let setting = match setting {
+            SiteSetting::UserAgent(Some(agent)) => {
+                let user_agent = editable_setting(USER_AGENT_SETTING).ok_or_else(|| {
+                    NavigatorError::UnknownSetting(USER_AGENT_SETTING.to_string())
+                })?;
+                let agent = validate_setting(user_agent, &agent).map_err(|reason| NavigatorError::InvalidSetting {
+                    label: user_agent.label.to_string(),
+                    reason,
+                })?;
+                SiteSetting::UserAgent(Some(agent))
+            }
+            other => other,
+        };
+
+        let mut settings = self.current(url).await?;
+        settings.apply(setting);
+        if settings.is_empty() {
+            self.repository.delete(&settings.origin).await?;
+        } else {
+            self.repository.save(&settings).await?;
+        }
+        tracing::info!("Settings of {} are now {:?}", settings.origin, settings);
+        Ok(settings)
+    }
+}
+
+fn site_origin(url: &ValidatedUrl) -> Result<SiteOrigin> {
+    SiteOrigin::of(url).ok_or_else(|| NavigatorError::NoSiteSettings(url.scheme().to_string()))
+}
+
+/// Use case: Read and change a site's `localStorage`
+pub(crate) struct UpdateWebStorageUseCase {
+    repository: Arc<dyn WebStorageRepository>,
+}
+
+impl UpdateWebStorageUseCase {
+    pub(crate) fn new(repository: Arc<dyn WebStorageRepository>) -> Self {
+        Self { repository }
+    }
+
+    /// The items of `url`'s origin, empty if it stored none
+    pub(crate) async fn current(&self, url: &ValidatedUrl) -> Result<StorageArea> {
+        let origin = storage_origin(url)?;
+        let items = self.repository.load(&origin).await?;
+        Ok(StorageArea::with_items(origin, items))
+    }
+
+    /// Make `change` if it fits the origin's quota, returning the items
+    /// after it
+    pub(crate) async fn execute(
+        &self,
+        context: RequestContext,
+        url: &ValidatedUrl,
+        change: StorageChange,
+    ) -> Result<StorageArea> {
+        let mut area = self.current(url).await?;
+        area.apply(change.clone())?;
+        PersistenceGuard::new(self.repository.clone(), context)
+            .apply(&area.origin, &change)
+            .await?;
+        Ok(area)
+    }
+}
+
+fn storage_origin(url: &ValidatedUrl) -> Result<SiteOrigin> {
+    SiteOrigin::of(url).ok_or_else(|| NavigatorError::NoStorage(url.scheme().to_string()))
+}
+
+/// Use case: What a tab's page tried to load and was refused
+pub(crate) struct GetBlockedSummaryUseCase {
+    rendering_engine: Arc<dyn RenderingEngine>,
+}
+
+impl GetBlockedSummaryUseCase {
+    /// `rendering_engine` is the tab's own, so only its page is counted
+    pub(crate) fn new(rendering_engine: Arc<dyn RenderingEngine>) -> Self {
+        Self { rendering_engine }
+    }
+
+    pub(crate) fn execute(&self) -> BlockedSummary {
+        BlockedSummary::new(&self.rendering_engine.blocked_requests())
+    }
+}
+
+/// Use case: Save a user script and put it into effect
+pub(crate) struct AddUserScriptUseCase {
+    repository: Arc<dyn UserScriptRepository>,
+    service: Arc<dyn UserScriptService>,
+}
+
+impl AddUserScriptUseCase {
+    pub(crate) fn new(repository: Arc<dyn UserScriptRepository>, service: Arc<dyn UserScriptService>) -> Self {
+        Self { repository, service }
+    }
+
+    pub(crate) async fn execute(&self, script: NewUserScript) -> Result<UserScript> {
+        if script.name.trim().is_empty() || script.matches.is_empty() {
+            return Err(UserScriptError::Incomplete.into());
+        }
+        let saved = self.repository.save(script).await?;
+        self.service.set_scripts(self.repository.find_all().await?);
+        tracing::info!("Added user script {} ({})", saved.id, saved.name);
+        Ok(saved)
+    }
+}
+
+/// Use case: Store changed settings of a user script, such as whether it is enabled
+pub(crate) struct UpdateUserScriptUseCase {
+    repository: Arc<dyn UserScriptRepository>,
+    service: Arc<dyn UserScriptService>,
+}
+
+impl UpdateUserScriptUseCase {
+    pub(crate) fn new(repository: Arc<dyn UserScriptRepository>, service: Arc<dyn UserScriptService>) -> Self {
+        Self { repository, service }
+    }
+
+    pub(crate) async fn execute(&self, script: &UserScript) -> Result<()> {
+        let scripts = self.repository.find_all().await?;
+        if !scripts.iter().any(|s| s.id == script.id) {
+            return Err(UserScriptError::NotFound(script.id).into());
+        }
+        self.repository.update(script).await?;
+        self.service.set_scripts(self.repository.find_all().await?);
+        Ok(())
+    }
+}
+
+/// Use case: Delete a user script and its stored values
+pub(crate) struct RemoveUserScriptUseCase {
+    repository: Arc<dyn UserScriptRepository>,
+    service: Arc<dyn UserScriptService>,
+}
+
+impl RemoveUserScriptUseCase {
+    pub(crate) fn new(repository: Arc<dyn UserScriptRepository>, service: Arc<dyn UserScriptService>) -> Self {
+        Self { repository, service }
+    }
+
+    pub(crate) async fn execute(&self, id: i64) -> Result<()> {
+        self.repository.delete(id).await?;
+        self.service.set_scripts(self.repository.find_all().await?);
+        Ok(())
+    }
+}
+
+/// Use case: Run the user scripts matching a freshly loaded page
+///
+/// Failures are recorded on the script and logged, never returned, so a
+/// broken script cannot fail the page load.
+pub(crate) struct RunUserScriptsUseCase {
+    repository: Arc<dyn UserScriptRepository>,
+    service: Arc<dyn UserScriptService>,
+    rendering_engine: Arc<dyn RenderingEngine>,
+}
+
+impl RunUserScriptsUseCase {
+    pub(crate) fn new(
+        repository: Arc<dyn UserScriptRepository>,
+        service: Arc<dyn UserScriptService>,
+        rendering_engine: Arc<dyn RenderingEngine>,
+    ) -> Self {
+        Self {
+            repository,
+            service,
+            rendering_engine,
+        }
+    }
+
+    /// Scripts allowed in private tabs read their stored values there, but
+    /// what they store and the errors they raise are not kept
+    pub(crate) async fn execute(&self, context: RequestContext, url: &ValidatedUrl) {
+        for script in self.service.scripts_for(url, context.private) {
+            if let Err(e) = self.run(context, script, url).await {
+                tracing::warn!("Failed to store user script results: {}", e);
+            }
+        }
+    }
+
+    async fn run(&self, context: RequestContext, mut script: UserScript, url: &ValidatedUrl) -> Result<()> {
+        let repository = PersistenceGuard::new(self.repository.clone(), context);
+        let values = repository.values(script.id).await?;
+        let prepared = self.service.prepare(&script, &values);
+        let outcome = match self.rendering_engine.execute_javascript(&prepared).await {
+            Ok(output) => self.service.outcome(&output),
+            Err(e) => ScriptOutcome {
+                error: Some(e.to_string()),
+                ..Default::default()
+            },
+        };
+
+        for message in &outcome.log {
+            self.rendering_engine.log_to_console(ConsoleLevel::Log, message, Some(&script.name));
+        }
+        if let Some(error) = &outcome.error {
+            tracing::warn!("User script {} failed on {}: {}", script.name, url, error);
+            self.rendering_engine.log_to_console(ConsoleLevel::Error, error, Some(&script.name));
+        }
+        if context.private {
+            return Ok(());
+        }
+        for (key, value) in &outcome.values {
+            repository.set_value(script.id, key, value).await?;
+        }
+        if script.last_error != outcome.error {
+            script.last_error = outcome.error;
+            repository.update(&script).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Use case: Save a tab's page into a directory under a name taken from
+/// its title, and record the download; private tabs' records are kept in
+/// memory only
+pub(crate) struct SavePageUseCase {
+    rendering_engine: Arc<dyn RenderingEngine>,
+    downloads: Arc<SessionDownloads>,
+    writer: Arc<dyn DownloadWriter>,
+    format: SaveFormat,
+    archiver: Option<Arc<dyn PageArchiver>>,
+}
+
+impl SavePageUseCase {
+    pub(crate) fn new(
+        rendering_engine: Arc<dyn RenderingEngine>,
+        downloads: Arc<SessionDownloads>,
+        writer: Arc<dyn DownloadWriter>,
+        format: SaveFormat,
+    ) -> Self {
+        Self {
+            rendering_engine,
+            downloads,
+            writer,
+            format,
+            archiver: None,
+        }
+    }
+
+    /// Archive pages saved as MHTML with `archiver`
+    pub(crate) fn with_archiver(mut self, archiver: Option<Arc<dyn PageArchiver>>) -> Self {
+        self.archiver = archiver;
+        self
+    }
+
+    pub(crate) async fn execute(&self, tab: &Tab, directory: &Path) -> Result<Download> {
+        let url = tab.page_url().cloned().ok_or(NavigatorError::NothingToSave)?;
+        // Private tabs never have their title stored on the tab
+        let title = self.rendering_engine.get_title().await?;
+        let contents = match self.format {
+            SaveFormat::Html => {
+                saved_html(&self.rendering_engine.get_source().await?, &url, Utc::now()).into_bytes()
+            }
+            SaveFormat::Text => self.rendering_engine.get_text().await?.into_bytes(),
+            SaveFormat::Mhtml => {
+                let archiver = self.archiver.as_ref().ok_or(NavigatorError::SavingUnavailable)?;
+                let html = self.rendering_engine.get_source().await?;
+                archiver.archive(&url, &title, &html).await
+            }
+        };
+        let file_name = page_file_name(&title, &url, self.format.extension());
+        let context = RequestContext::for_tab(tab);
+        write_download(self.writer.as_ref(), &self.downloads, directory, &file_name, &contents, url, context).await
+    }
+}
+
+/// Print a tab's page to PDF and write it as a download
+pub(crate) struct PrintPageUseCase {
+    rendering_engine: Arc<dyn RenderingEngine>,
+    downloads: Arc<SessionDownloads>,
+    writer: Arc<dyn DownloadWriter>,
+    printer: Arc<dyn PagePrinter>,
+    paper: PaperSize,
+}
+
+impl PrintPageUseCase {
+    pub(crate) fn new(
+        rendering_engine: Arc<dyn RenderingEngine>,
+        downloads: Arc<SessionDownloads>,
+        writer: Arc<dyn DownloadWriter>,
+        printer: Arc<dyn PagePrinter>,
+        paper: PaperSize,
+    ) -> Self {
+        Self {
+            rendering_engine,
+            downloads,
+            writer,
+            printer,
+            paper,
+        }
+    }
+
+    pub(crate) async fn execute(&self, tab: &Tab, directory: &Path) -> Result<Download> {
+        let url = tab.page_url().cloned().ok_or(NavigatorError::NothingToSave)?;
+        let title = self.rendering_engine.get_title().await?;
+        let html = self.rendering_engine.get_source().await?;
+        let pdf = self.printer.print(&url, &title, &html, self.paper).await;
+        let file_name = page_file_name(&title, &url, "pdf");
+        let context = RequestContext::for_tab(tab);
+        write_download(self.writer.as_ref(), &self.downloads, directory, &file_name, &pdf, url, context).await
+    }
+}
+
+/// Use case: Check a file before writing it as a download. Risky files are
+/// held by the download manager until the user confirms each one. Files
+/// being written are listed by the manager, and stay listed to try again if
+/// writing fails.
+pub(crate) struct DownloadFileUseCase {
+    manager: Arc<DownloadManager>,
+    downloads: Arc<SessionDownloads>,
+    writer: Arc<dyn DownloadWriter>,
+    state: BrowserState,
+}
+
+impl DownloadFileUseCase {
+    pub(crate) fn new(
+        manager: Arc<DownloadManager>,
+        downloads: Arc<SessionDownloads>,
+        writer: Arc<dyn DownloadWriter>,
+        state: BrowserState,
+    ) -> Self {
+        Self {
+            manager,
+            downloads,
+            writer,
+            state,
+        }
+    }
+
+    pub(crate) async fn execute(
+        &self,
+        context: RequestContext,
+        download: FileDownload,
+        directory: &Path,
+    ) -> Result<DownloadOutcome> {
+        let assessment = assess_download(&download.url, &download.file_name, download.content_type.as_deref());
+        if assessment.needs_confirmation() {
+            tracing::warn!("Holding {} from {} for confirmation", assessment.file_name, download.url);
+            let pending = self.manager.hold(download, assessment, context.private);
+            return Ok(DownloadOutcome::PendingConfirmation(pending));
+        }
+        let write = self
+            .manager
+            .begin(download.url, assessment.file_name, download.contents, context.private);
+        Ok(DownloadOutcome::Saved(self.write(write, directory).await?))
+    }
+
+    pub(crate) fn writer(&self) -> &dyn DownloadWriter {
+        self.writer.as_ref()
+    }
+
+    /// Write a held download, the user having confirmed it
+    pub(crate) async fn confirm(&self, id: u64, directory: &Path) -> Result<Download> {
+        let (pending, contents) = self.manager.take(id).ok_or(NavigatorError::PendingDownloadNotFound(id))?;
+        let write = self.manager.begin(pending.url, pending.file_name, contents, pending.private);
+        self.write(write, directory).await
+    }
+
+    /// Write a download whose last write failed again
+    pub(crate) async fn retry(&self, id: u64, directory: &Path) -> Result<Download> {
+        let write = self.manager.retry(id).ok_or(NavigatorError::UnfinishedDownloadNotFound(id))?;
+        self.write(write, directory).await
+    }
+
+    /// Write a download listed by the manager, taking it off the list once
+    /// saved or cancelled
+    async fn write(&self, write: DownloadWrite, directory: &Path) -> Result<Download> {
+        self.state.notify_downloads_changed();
+        let download = write.download;
+        let context = RequestContext::new(None, download.private);
+        let written = match self
+            .writer
+            .write_new(directory, &download.file_name, &write.contents, write.cancelled)
+            .await
+        {
+            Ok(path) => record_download(&self.downloads, download.url.clone(), path, write.contents.len(), context).await,
+            Err(e) => Err(e.into()),
+        };
+        match &written {
+            Ok(_) | Err(NavigatorError::Download(DownloadError::Cancelled(_))) => self.manager.finish(download.id),
+            Err(e) => {
+                tracing::warn!("Failed to save {} from {}: {}", download.file_name, download.url, e);
+                self.manager.fail(download.id, e.to_string());
+            }
+        }
+        self.state.notify_downloads_changed();
+        written
+    }
+}
+
+/// Use case: Describe what the browser knows of the security of a tab's
+/// page, and save it as JSON
+pub(crate) struct ExportSecurityReportUseCase {
+    rendering_engine: Arc<dyn RenderingEngine>,
+    security: Arc<dyn SecurityService>,
+    /// Looks at the page's TLS session; without it the report has none
+    tls: Option<Arc<dyn TlsInspector>>,
+}
+
+impl ExportSecurityReportUseCase {
+    pub(crate) fn new(
+        rendering_engine: Arc<dyn RenderingEngine>,
+        security: Arc<dyn SecurityService>,
+        tls: Option<Arc<dyn TlsInspector>>,
+    ) -> Self {
+        Self {
+            rendering_engine,
+            security,
+            tls,
+        }
+    }
+
+    pub(crate) async fn execute(&self, tab: &Tab) -> Result<SecurityReport> {
+        let requested = tab.page_url().cloned().ok_or(NavigatorError::NothingToSave)?;
+        let fetch = self.rendering_engine.document_fetch();
+        let url = fetch.as_ref().map_or(requested, |fetch| fetch.url.clone());
+        let tls = match &self.tls {
+            Some(tls) if fetch.is_some() && url.is_secure() => match tls.tls_session(&url).await {
+                Ok(session) => Some(session),
+                Err(e) => {
+                    tracing::warn!("Cannot look at the TLS session of {}: {}", url, e);
+                    None
+                }
+            },
+            _ => None,
+        };
+        let header = |name| fetch.as_ref().and_then(|fetch| fetch.header_values(name).next());
+        let blocked = BlockedSummary::new(&self.rendering_engine.blocked_requests());
+        let certificate = self.rendering_engine.certificate();
+        Ok(SecurityReport {
+            schema_version: SECURITY_REPORT_VERSION,
+            generated_at: Utc::now(),
+            redirect_chain: fetch
+                .iter()
+                .flat_map(|fetch| &fetch.redirected_from)
+                .map(ValidatedUrl::to_string)
+                .collect(),
+            status: fetch.as_ref().map(|fetch| fetch.status),
+            http_version: fetch.as_ref().map(|fetch| fetch.version.to_string()),
+            tls,
+            // Browsers ignore the header when it comes over plain HTTP
+            hsts: header("strict-transport-security")
+                .filter(|_| url.is_secure())
+                .and_then(HstsPolicy::parse),
+            mixed_content_blocked: blocked.count(BlockCategory::MixedContent),
+            trackers_blocked: blocked.count(BlockCategory::Trackers),
+            csp: header("content-security-policy").map(CspPolicy::parse),
+            cookies: fetch
+                .iter()
+                .flat_map(|fetch| fetch.header_values("set-cookie"))
+                .filter_map(ObservedCookie::parse)
+                .collect(),
+            risk: HostRisk::assess(&url, self.security.is_blocked(&url), certificate.as_ref()),
+            certificate,
+            url: url.to_string(),
+        })
+    }
+
+    /// Save the report under a free name in `directory`
+    pub(crate) async fn save(
+        &self,
+        tab: &Tab,
+        directory: &Path,
+        downloads: &SessionDownloads,
+        writer: &dyn DownloadWriter,
+    ) -> Result<Download> {
+        let report = self.execute(tab).await?;
+        let json = security_report_json(&report)?;
+        let url = ValidatedUrl::parse(&report.url).map_err(|e| NavigatorError::SecurityReport(e.to_string()))?;
+        let title = format!("Security report {}", url.host_str().unwrap_or(url.scheme()));
+        let file_name = page_file_name(&title, &url, "json");
+        let context = RequestContext::for_tab(tab);
+        write_download(writer, downloads, directory, &file_name, json.as_bytes(), url, context).await
+    }
+}
+
+/// A security report as indented JSON
+pub(crate) fn security_report_json(report: &SecurityReport) -> Result<String> {
+    serde_json::to_string_pretty(report).map_err(|e| NavigatorError::SecurityReport(e.to_string()))
+}
+
+/// Write `contents` under a free name in `directory` and record the download
+async fn write_download(
+    writer: &dyn DownloadWriter,
+    downloads: &SessionDownloads,
+    directory: &Path,
+    file_name: &str,
+    contents: &[u8],
+    url: ValidatedUrl,
+    context: RequestContext,
+) -> Result<Download> {
+    let path = writer.write_new(directory, file_name, contents, Arc::default()).await?;
+    record_download(downloads, url, path, contents.len(), context).await
+}
+
+/// Add a download written to `path` to the list
+async fn record_download(
+    downloads: &SessionDownloads,
+    url: ValidatedUrl,
+    path: PathBuf,
+    size: usize,
+    context: RequestContext,
+) -> Result<Download> {
+    let download = NewDownload {
+        url,
+        path,
+        size: size as u64,
+    };
+    let download = downloads.record(download, context).await?;
+    tracing::info!("Saved {} to {}", download.url, download.path.display());
+    Ok(download)
+}
+
+/// Use case: Fetch the update manifest, at most once a day and only while
+/// the check is turned on, and return the release it describes if it is
+/// newer than `current`
+pub(crate) struct CheckForUpdateUseCase {
+    settings: Arc<dyn SettingsRepository>,
+    source: Arc<dyn UpdateSource>,
+}
+
+impl CheckForUpdateUseCase {
+    pub(crate) fn new(settings: Arc<dyn SettingsRepository>, source: Arc<dyn UpdateSource>) -> Self {
+        Self { settings, source }
+    }
+
+    pub(crate) async fn execute(&self, current: &str, now: DateTime<Utc>) -> Result<Option<UpdateManifest>> {
+        let enabled = self.settings.get(UPDATE_CHECK_SETTING).await?;
+        if !setting_enabled(UPDATE_CHECK_SETTING, enabled.as_deref()) {
+            return Ok(None);
+        }
+        let Some(manifest_url) = self.settings.get(UPDATE_MANIFEST_SETTING).await? else {
+            return Ok(None);
+        };
+        if manifest_url.trim().is_empty()
+            || !update_check_due(self.settings.get(UPDATE_LAST_CHECKED_SETTING).await?.as_deref(), now)
+        {
+            return Ok(None);
+        }
+        // Recorded before fetching, so an unreachable server is also asked
+        // only once a day
+        self.settings.set(UPDATE_LAST_CHECKED_SETTING, &now.to_rfc3339()).await?;
+        let manifest_url = ValidatedUrl::parse(manifest_url.trim()).map_err(|e| NavigatorError::InvalidSetting {
+            label: "Update manifest".to_string(),
+            reason: e.to_string(),
+        })?;
+        let manifest = self.source.manifest(&manifest_url).await?;
+        Ok(newer_release(manifest, current)?)
+    }
+}
+
+fn failure(section: &'static str, record: &str, reason: impl std::fmt::Display) -> ImportFailure {
+    ImportFailure {
+        section,
+        record: record.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::testing::{
+        FailingRepository, FakeArchiver, FakeEngine, FakePrinter, FakeTlsInspector, InMemoryBookmarkRepository,
+        InMemoryCustomCaRepository, InMemoryDownloadRepository, InMemoryDownloadWriter, InMemoryHistoryRepository,
+        InMemorySearchEngineRepository, InMemorySiteSettingsRepository, InMemoryTabRepository,
+    };
+    use crate::domain::{
+        BlockedRequest, BrowserSource, Certificate, DocumentFetch, DownloadRepository, HttpVersion, OcspStaple,
+        RevocationStatus, RiskLevel, SearchTemplate,
+    };
+    use crate::application::download_safety::DownloadWarning;
+    use crate::infrastructure::{DefaultSecurityService, ForeignProfileReader, SecureNetworkClient, SqliteDatabase};
+    use std::path::Path;
+    use std::sync::atomic::Ordering;
+
+    #[tokio::test]
+    async fn test_open_tab_use_case() {
+        let state = BrowserState::new();
+        let repo = Arc::new(InMemoryTabRepository::new());
+
+        let use_case = OpenTabUseCase::new(state.clone(), repo.clone());
+        let tab_id = use_case.execute(RequestContext::user(), None, None).await.unwrap();
+
+        assert_eq!(state.tab_count(), 1);
+        assert_eq!(state.get_active_tab_id(), Some(tab_id));
+        assert_eq!(repo.find_all().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_open_tab_fails_when_save_fails() {
+        let state = BrowserState::new();
+        let repo = Arc::new(FailingRepository::after(0, InMemoryTabRepository::new()));
+
+        let use_case = OpenTabUseCase::new(state.clone(), repo);
+        assert!(use_case.execute(RequestContext::user(), None, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_navigation_survives_history_write_failure() {
+        let state = BrowserState::new();
+        let tab_id = state.add_tab(Tab::new(false));
+        let history = Arc::new(FailingRepository::after(0, InMemoryHistoryRepository::new()));
+        let writer = HistoryWriter::spawn(history.clone());
+
+        let use_case = NavigateUseCase::new(
+            state.clone(),
+            Arc::new(DefaultSecurityService::new()),
+            writer.clone(),
+            Arc::new(InMemorySiteSettingsRepository::new()),
+            Arc::new(FakeEngine::new()),
+        );
+        use_case.execute(RequestContext::user(), tab_id, "https://example.com/a", VisitType::Typed).await.unwrap();
+        // The failed batch is logged and dropped
+        writer.shutdown().await;
+
+        let tab = state.get_tab(tab_id).unwrap();
+        assert_eq!(tab.title, "Page /a");
+        assert!(!tab.is_loading);
+        assert!(history.inner().get_recent(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_about_blank_is_no_visit() {
+        let state = BrowserState::new();
+        let tab_id = state.add_tab(Tab::new(false));
+        let history = Arc::new(InMemoryHistoryRepository::new());
+        let writer = HistoryWriter::spawn(history.clone());
+        let use_case = NavigateUseCase::new(
+            state.clone(),
+            Arc::new(DefaultSecurityService::new()),
+            writer.clone(),
+            Arc::new(InMemorySiteSettingsRepository::new()),
+            Arc::new(FakeEngine::new()),
+        );
+
+        use_case.execute(RequestContext::user(), tab_id, "https://example.com/a", VisitType::Typed).await.unwrap();
+        use_case.execute(RequestContext::user(), tab_id, "about:blank", VisitType::Typed).await.unwrap();
+        writer.shutdown().await;
+
+        let tab = state.get_tab(tab_id).unwrap();
+        assert!(tab.url().is_about_blank());
+        assert_eq!(tab.page_url(), None);
+        assert_eq!(tab.title, NEW_TAB_TITLE);
+        let visits = history.get_recent(10).await.unwrap();
+        assert_eq!(visits.len(), 1);
+        assert_eq!(visits[0].url.as_str(), "https://example.com/a");
+    }
+
+    #[tokio::test]
+    async fn test_navigation_records_how_pages_were_visited() {
+        let state = BrowserState::new();
+        let tab_id = state.add_tab(Tab::new(false));
+        let history = Arc::new(InMemoryHistoryRepository::new());
+        let writer = HistoryWriter::spawn(history.clone());
+        let engine = Arc::new(FakeEngine::new());
+        let url = |url: &str| ValidatedUrl::parse(url).unwrap();
+        engine.set_redirect(url("http://example.com/old"), url("https://example.com/new"));
+        let use_case = NavigateUseCase::new(
+            state.clone(),
+            Arc::new(DefaultSecurityService::new()),
+            writer.clone(),
+            Arc::new(InMemorySiteSettingsRepository::new()),
+            engine,
+        );
+
+        let navigate = |address, visit_type| use_case.execute(RequestContext::user(), tab_id, address, visit_type);
+        navigate("https://example.com/a", VisitType::Typed).await.unwrap();
+        navigate("https://example.com/a", VisitType::Reload).await.unwrap();
+        navigate("http://example.com/old", VisitType::Bookmark).await.unwrap();
+        writer.shutdown().await;
+
+        let mut visited = Vec::new();
+        for address in ["https://example.com/a", "http://example.com/old", "https://example.com/new"] {
+            let entry = history.find_by_url(&url(address)).await.unwrap().unwrap();
+            let types: Vec<VisitType> = history
+                .visits(&[entry.id])
+                .await
+                .unwrap()
+                .iter()
+                .map(|visit| visit.visit_type)
+                .collect();
+            visited.push(types);
+        }
+        assert_eq!(
+            visited,
+            vec![
+                vec![VisitType::Typed, VisitType::Reload],
+                vec![VisitType::Bookmark],
+                vec![VisitType::Redirect],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_tab_reports_delete_failure_after_removing_tab() {
+        let state = BrowserState::new();
+        let repo = Arc::new(FailingRepository::after(2, InMemoryTabRepository::new()));
+        let open = OpenTabUseCase::new(state.clone(), repo.clone());
+        let first = open.execute(RequestContext::user(), None, None).await.unwrap();
+        let second = open.execute(RequestContext::user(), None, None).await.unwrap();
+
+        let close = CloseTabUseCase::new(state.clone(), repo.clone());
+        assert!(matches!(close.execute(RequestContext::user(), second).await, Err(NavigatorError::Storage(_))));
+
+        assert!(state.get_tab(second).is_none());
+        assert_eq!(state.get_active_tab_id(), Some(first));
+        assert_eq!(repo.inner().find_all().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_save_bookmark_propagates_failure() {
+        let url = ValidatedUrl::parse("https://example.com/").unwrap();
+        // Each save looks for an existing bookmark first
+        let repo = Arc::new(FailingRepository::after(2, InMemoryBookmarkRepository::new()));
+        let use_case = SaveBookmarkUseCase::new(repo.clone());
+
+        let saved = use_case.execute("Example".to_string(), url).await.unwrap();
+        assert_eq!(saved.title, "Example");
+        let other = ValidatedUrl::parse("https://example.com/other").unwrap();
+        assert!(use_case.execute("Other".to_string(), other).await.is_err());
+        assert_eq!(repo.inner().find_all().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_save_bookmark_returns_existing_for_same_document() {
+        let repo = Arc::new(InMemoryBookmarkRepository::new());
+        let use_case = SaveBookmarkUseCase::new(repo.clone());
+
+        let url = |input: &str| ValidatedUrl::parse(input).unwrap();
+        let saved = use_case.execute("Example".to_string(), url("https://example.com/")).await.unwrap();
+        let again = use_case.execute("Again".to_string(), url("https://EXAMPLE.com:443/#top")).await.unwrap();
+        assert_eq!((again.id, again.title.as_str()), (saved.id, "Example"));
+        assert_eq!(repo.find_all().await.unwrap().len(), 1);
+    }
+
+    fn profile_use_cases(db: &Arc<SqliteDatabase>) -> (ExportProfileUseCase, ImportProfileUseCase) {
+        (
+            ExportProfileUseCase::new(db.clone(), db.clone(), db.clone()),
+            ImportProfileUseCase::new(db.clone(), db.clone(), db.clone()),
+        )
+    }
+
+    async fn populated_profile() -> Arc<SqliteDatabase> {
+        let db = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+        let mut bookmark = NewBookmark::new(
+            "Docs".to_string(),
+            ValidatedUrl::parse("https://example.com/docs").unwrap(),
+        );
+        bookmark.folder = Some("Work".to_string());
+        bookmark.tags = vec!["rust".to_string(), "reference".to_string()];
+        BookmarkRepository::save(db.as_ref(), bookmark).await.unwrap();
+
+        db.set("theme", "dark").await.unwrap();
+        db.set(BLOCKED_DOMAINS_SETTING, "ads.example\ntracker.example").await.unwrap();
+
+        let mut visit = NewHistoryEntry::new(
+            ValidatedUrl::parse("https://example.com/").unwrap(),
+            "Example".to_string(),
+        );
+        visit.visit_count = 3;
+        db.add(visit).await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_profile_round_trip() {
+        let source = populated_profile().await;
+        let (export, _) = profile_use_cases(&source);
+        let json = export.execute(true).await.unwrap();
+
+        let target = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+        target.set("font.default_size", "20").await.unwrap();
+        let (export_again, import) = profile_use_cases(&target);
+        let report = import
+            .execute(&json, ImportOptions::all(ImportMode::Replace))
+            .await
+            .unwrap();
+        assert_eq!(
+            (report.bookmarks, report.settings, report.blocked_domains, report.history),
+            (1, 1, 2, 1)
+        );
+        assert!(report.failures.is_empty());
+
+        let bookmarks = BookmarkRepository::find_all(target.as_ref()).await.unwrap();
+        assert_eq!(bookmarks[0].folder.as_deref(), Some("Work"));
+        assert_eq!(bookmarks[0].tags, vec!["rust", "reference"]);
+        let history = target.get_recent(10).await.unwrap();
+        assert_eq!(history[0].visit_count, 3);
+        // Replacing settings dropped the one the document did not have
+        assert_eq!(SettingsRepository::get(target.as_ref(), "font.default_size").await.unwrap(), None);
+
+        let original: ProfileDocument = serde_json::from_str(&json).unwrap();
+        let copied: ProfileDocument =
+            serde_json::from_str(&export_again.execute(true).await.unwrap()).unwrap();
+        assert_eq!(copied.bookmarks, original.bookmarks);
+        assert_eq!(copied.settings, original.settings);
+        assert_eq!(copied.blocked_domains, original.blocked_domains);
+        assert_eq!(copied.history, original.history);
+    }
+
+    #[tokio::test]
+    async fn test_settings_are_migrated_loaded_and_updated_in_place() {
+        use crate::application::settings::{HTTPS_ONLY_SETTING, OFFLINE_SETTING, THEME_SETTING};
+        use crate::application::typed_settings::{SettingsSections, Theme};
+        use crate::application::StateEvent;
+
+        let db = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+        for (key, value) in [
+            (THEME_SETTING, "Dark"),
+            (HTTPS_ONLY_SETTING, "on"),
+            (OFFLINE_SETTING, "bogus"),
+            ("future.setting", "kept"),
+        ] {
+            SettingsRepository::set(db.as_ref(), key, value).await.unwrap();
+        }
+        let (state, shared) = (BrowserState::new(), SharedSettings::new());
+        let use_case = || {
+            let engines = Arc::new(InMemorySearchEngineRepository::new());
+            UpdateSettingsUseCase::new(db.clone(), engines, shared.clone(), state.clone())
+        };
+        let stored = |key: &'static str| SettingsRepository::get(db.as_ref(), key);
+
+        let settings = use_case().load().await.unwrap();
+        assert_eq!((settings.theme, settings.https_only, settings.network.offline), (Theme::Dark, true, false));
+        assert_eq!(stored(THEME_SETTING).await.unwrap().as_deref(), Some("dark"));
+        assert_eq!(stored(HTTPS_ONLY_SETTING).await.unwrap().as_deref(), Some("true"));
+        assert_eq!(stored(OFFLINE_SETTING).await.unwrap().as_deref(), Some("bogus"));
+        assert_eq!(stored(SETTINGS_VERSION_SETTING).await.unwrap().as_deref(), Some(SETTINGS_VERSION));
+
+        let mut events = state.subscribe();
+        use_case().execute(THEME_SETTING, "light").await.unwrap();
+        assert_eq!(shared.load().theme, Theme::Light);
+        let general = SettingsSections {
+            general: true,
+            ..SettingsSections::default()
+        };
+        assert_eq!(events.try_recv().ok(), Some(StateEvent::SettingsChanged(general)));
+        use_case().execute(THEME_SETTING, "light").await.unwrap();
+        assert!(events.try_recv().is_err());
+
+        // A key a newer release stored survives, and a fresh load reads
+        // back what was saved
+        assert_eq!(stored("future.setting").await.unwrap().as_deref(), Some("kept"));
+        let before = shared.load();
+        shared.store(Settings::default());
+        assert_eq!(use_case().load().await.unwrap(), before);
+
+        // Migration runs once
+        SettingsRepository::set(db.as_ref(), HTTPS_ONLY_SETTING, "On").await.unwrap();
+        assert!(use_case().load().await.unwrap().https_only);
+        assert_eq!(stored(HTTPS_ONLY_SETTING).await.unwrap().as_deref(), Some("On"));
+    }
+
+    #[tokio::test]
+    async fn test_profile_round_trip_keeps_bookmark_order() {
+        let source = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+        for (title, folder) in [("One", None), ("Two", Some("Work")), ("Three", None), ("Four", Some("Work"))] {
+            let mut bookmark = NewBookmark::new(
+                title.to_string(),
+                ValidatedUrl::parse(&format!("https://example.com/{}", title)).unwrap(),
+            );
+            bookmark.folder = folder.map(str::to_string);
+            BookmarkRepository::save(source.as_ref(), bookmark).await.unwrap();
+        }
+        let saved = BookmarkRepository::find_all(source.as_ref()).await.unwrap();
+        let id = |title: &str| saved.iter().find(|b| b.title == title).unwrap().id;
+        source.move_to(id("Three"), None, 0).await.unwrap();
+        source.move_to(id("Four"), Some("Work"), 0).await.unwrap();
+
+        let (export, _) = profile_use_cases(&source);
+        let target = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+        let (_, import) = profile_use_cases(&target);
+        import
+            .execute(&export.execute(false).await.unwrap(), ImportOptions::all(ImportMode::Replace))
+            .await
+            .unwrap();
+
+        let titles = |bookmarks: Vec<Bookmark>| bookmarks.into_iter().map(|b| b.title).collect::<Vec<_>>();
+        let unfiled: Vec<Bookmark> = BookmarkRepository::find_all(target.as_ref())
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|b| b.folder.is_none())
+            .collect();
+        assert_eq!(titles(unfiled), vec!["Three", "One"]);
+        let work = BookmarkRepository::find_by_folder(target.as_ref(), "Work").await.unwrap();
+        assert_eq!(titles(work), vec!["Four", "Two"]);
+    }
+
+    #[tokio::test]
+    async fn test_profile_import_skips_invalid_records() {
+        let db = populated_profile().await;
+        let (export, import) = profile_use_cases(&db);
+        assert!(serde_json::from_str::<ProfileDocument>(&export.execute(false).await.unwrap())
+            .unwrap()
+            .history
+            .is_none());
+
+        let json = r#"{
+            "version": 1,
+            "exported_at": "2024-01-01T00:00:00Z",
+            "bookmarks": [
+                {"title": "Again", "url": "https://example.com/docs", "created_at": "2024-01-01T00:00:00Z"},
+                {"title": "Broken", "url": "not a url", "created_at": "2024-01-01T00:00:00Z"},
+                {"title": "New", "url": "https://example.org/", "created_at": "2024-01-01T00:00:00Z"}
+            ],
+            "blocked_domains": ["ads.example", "bad host"]
+        }"#;
+        let report = import.execute(json, ImportOptions::default()).await.unwrap();
+
+        assert_eq!(report.bookmarks, 1);
+        assert_eq!(report.blocked_domains, 0);
+        let skipped: Vec<_> = report.failures.iter().map(|f| (f.section, f.record.as_str())).collect();
+        assert_eq!(skipped, vec![("bookmarks", "not a url"), ("blocked_domains", "bad host")]);
+        assert_eq!(BookmarkRepository::find_all(db.as_ref()).await.unwrap().len(), 2);
+        // Merging left existing settings alone
+        assert_eq!(SettingsRepository::get(db.as_ref(), "theme").await.unwrap().as_deref(), Some("dark"));
+    }
+
+    #[tokio::test]
+    async fn test_profile_import_rejects_other_versions() {
+        let db = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+        let (_, import) = profile_use_cases(&db);
+
+        assert_eq!(
+            import.execute(r#"{"version": 2}"#, ImportOptions::default()).await,
+            Err(NavigatorError::UnsupportedProfileVersion(2))
+        );
+        assert!(matches!(
+            import.execute("[]", ImportOptions::default()).await,
+            Err(NavigatorError::InvalidProfile(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_browser_import_dedupes_and_dry_runs() {
+        let bookmarks = Arc::new(InMemoryBookmarkRepository::new());
+        let history = Arc::new(InMemoryHistoryRepository::new());
+        let rust = ValidatedUrl::parse("https://www.rust-lang.org/").unwrap();
+        bookmarks.save(NewBookmark::new("Rust".to_string(), rust.clone())).await.unwrap();
+
+        let use_case = ImportFromBrowserUseCase::new(
+            Arc::new(ForeignProfileReader::new()),
+            bookmarks.clone(),
+            history.clone(),
+        );
+        let mut request = BrowserImportRequest::new(BrowserSource::Firefox);
+        request.profile_path = Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/firefox"));
+        request.dry_run = true;
+
+        let report = use_case.execute(request.clone()).await.unwrap();
+        assert_eq!((report.bookmarks, report.history, report.duplicates), (1, 2, 1));
+        assert_eq!(bookmarks.find_all().await.unwrap().len(), 1);
+        assert!(history.get_recent(10).await.unwrap().is_empty());
+
+        request.dry_run = false;
+        use_case.execute(request.clone()).await.unwrap();
+        assert_eq!(history.find_by_url(&rust).await.unwrap().unwrap().visit_count, 5);
+
+        // Nothing is imported twice
+        let again = use_case.execute(request).await.unwrap();
+        assert_eq!((again.bookmarks, again.history, again.duplicates), (0, 0, 4));
+    }
+
+    #[tokio::test]
+    async fn test_search_engine_keywords_and_default() {
+        let repository = Arc::new(InMemorySearchEngineRepository::new());
+        let add = AddSearchEngineUseCase::new(repository.clone());
+        let engine = |keyword: &str| {
+            let template = SearchTemplate::parse("https://search.example/?q=%s").unwrap();
+            NewSearchEngine::new("Example".to_string(), keyword, template)
+        };
+
+        let first = add.execute(engine(" Ex ")).await.unwrap();
+        assert_eq!(first.keyword, "ex");
+        assert!(first.is_default);
+        let second = add.execute(engine("ex2")).await.unwrap();
+        assert!(!second.is_default);
+        assert_eq!(
+            add.execute(engine("EX")).await,
+            Err(NavigatorError::SearchEngine(SearchEngineError::DuplicateKeyword("ex".to_string())))
+        );
+        for keyword in ["", "two words"] {
+            assert!(matches!(
+                add.execute(engine(keyword)).await,
+                Err(NavigatorError::SearchEngine(SearchEngineError::InvalidKeyword(_)))
+            ));
+        }
+
+        let set_default = SetDefaultSearchEngineUseCase::new(repository.clone());
+        set_default.execute(second.id).await.unwrap();
+        let defaults: Vec<i64> = repository
+            .find_all()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.is_default)
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(defaults, vec![second.id]);
+        assert_eq!(
+            set_default.execute(99).await,
+            Err(NavigatorError::SearchEngine(SearchEngineError::NotFound(99)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_save_page_as_html_and_text() {
+        let engine = Arc::new(FakeEngine::new());
+        let url = ValidatedUrl::parse("https://example.com/docs/guide").unwrap();
+        engine.load_url(&url).await.unwrap();
+        let stored = Arc::new(InMemoryDownloadRepository::new());
+        let downloads = Arc::new(SessionDownloads::new(stored.clone()));
+        let writer = Arc::new(InMemoryDownloadWriter::new());
+        let save = |format| SavePageUseCase::new(engine.clone(), downloads.clone(), writer.clone(), format);
+        let mut tab = Tab::with_url(url, false);
+        let directory = Path::new("saved");
+
+        let html = save(SaveFormat::Html).execute(&tab, directory).await.unwrap();
+        assert_eq!(html.path, directory.join("Page _docs_guide.html"));
+        let written = String::from_utf8(writer.file(&html.path).unwrap()).unwrap();
+        assert!(written.starts_with("<!-- saved from https://example.com/docs/guide on "));
+        assert!(written.contains(r#"<a href="https://example.com/docs/next">"#));
+        assert_eq!(html.size, written.len() as u64);
+
+        let again = save(SaveFormat::Html).execute(&tab, directory).await.unwrap();
+        assert_eq!(again.path, directory.join("Page _docs_guide (1).html"));
+        let text = save(SaveFormat::Text).execute(&tab, directory).await.unwrap();
+        assert_eq!(writer.file(&text.path).unwrap(), b"Page /docs/guide");
+        assert_eq!(stored.find_all().await.unwrap().len(), 3);
+
+        // Private tabs still save, but leave no stored record
+        tab.is_private = true;
+        let private = save(SaveFormat::Text).execute(&tab, directory).await.unwrap();
+        assert!(writer.file(&private.path).is_some());
+        assert_eq!(stored.find_all().await.unwrap().len(), 3);
+        assert_eq!(downloads.find_all().await.unwrap().len(), 4);
+
+        assert_eq!(
+            save(SaveFormat::Html).execute(&Tab::new(false), directory).await,
+            Err(NavigatorError::NothingToSave)
+        );
+
+        // Archives need an archiver
+        tab.is_private = false;
+        assert_eq!(
+            save(SaveFormat::Mhtml).execute(&tab, directory).await,
+            Err(NavigatorError::SavingUnavailable)
+        );
+        let archiver: Arc<dyn PageArchiver> = Arc::new(FakeArchiver);
+        let archive = save(SaveFormat::Mhtml)
+            .with_archiver(Some(archiver))
+            .execute(&tab, directory)
+            .await
+            .unwrap();
+        assert_eq!(archive.path, directory.join("Page _docs_guide.mhtml"));
+        let written = String::from_utf8(writer.file(&archive.path).unwrap()).unwrap();
+        assert!(written.starts_with("Page /docs/guide from https://example.com/docs/guide:\n<title>"));
+    }
+
+    #[tokio::test]
+    async fn test_risky_downloads_wait_for_confirmation() {
+        let stored = Arc::new(InMemoryDownloadRepository::new());
+        let downloads = Arc::new(SessionDownloads::new(stored.clone()));
+        let writer = Arc::new(InMemoryDownloadWriter::new());
+        let manager = Arc::new(DownloadManager::new());
+        let use_case = DownloadFileUseCase::new(manager.clone(), downloads.clone(), writer.clone(), BrowserState::new());
+        let directory = Path::new("downloads");
+        let file = |url: &str, name: &str, content_type: &str| FileDownload {
+            url: ValidatedUrl::parse(url).unwrap(),
+            file_name: name.to_string(),
+            content_type: Some(content_type.to_string()),
+            contents: name.as_bytes().to_vec(),
+        };
+
+        let report = file("https://example.com/report.pdf", "report.pdf", "application/pdf");
+        let context = RequestContext::user();
+        let DownloadOutcome::Saved(saved) = use_case.execute(context, report, directory).await.unwrap() else {
+            panic!("an ordinary download was held");
+        };
+        assert_eq!(writer.file(&saved.path).unwrap(), b"report.pdf");
+
+        // Nothing of a held download is written or listed until confirmed
+        let setup = file("http://example.com/setup", "setup.exe", "application/x-msdownload");
+        let DownloadOutcome::PendingConfirmation(held) = use_case.execute(context, setup, directory).await.unwrap()
+        else {
+            panic!("a program over plain HTTP was written without asking");
+        };
+        assert!(held.warnings.contains(&DownloadWarning::InsecureOrigin));
+        assert!(writer.file(&directory.join("setup.exe")).is_none());
+        assert_eq!(downloads.find_all().await.unwrap().len(), 1);
+
+        let confirmed = use_case.confirm(held.id, directory).await.unwrap();
+        assert_eq!(confirmed.path, directory.join("setup.exe"));
+        assert_eq!(writer.file(&confirmed.path).unwrap(), b"setup.exe");
+        assert_eq!(
+            use_case.confirm(held.id, directory).await,
+            Err(NavigatorError::PendingDownloadNotFound(held.id))
+        );
+
+        // Confirming one download lets no later one through
+        let again = file("http://example.com/setup", "setup.exe", "application/x-msdownload");
+        let outcome = use_case.execute(RequestContext::new(None, true), again, directory).await.unwrap();
+        let DownloadOutcome::PendingConfirmation(second) = outcome else {
+            panic!("a confirmation was remembered");
+        };
+        assert!(second.private);
+        assert!(manager.discard(second.id));
+        assert!(manager.pending().is_empty());
+        assert_eq!(stored.find_all().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_downloads_stay_listed_to_retry() {
+        let stored = Arc::new(InMemoryDownloadRepository::new());
+        let downloads = Arc::new(SessionDownloads::new(stored.clone()));
+        let writer = Arc::new(InMemoryDownloadWriter::new());
+        let manager = Arc::new(DownloadManager::new());
+        let use_case = DownloadFileUseCase::new(manager.clone(), downloads.clone(), writer.clone(), BrowserState::new());
+        let directory = Path::new("downloads");
+        let report = FileDownload {
+            url: ValidatedUrl::parse("https://example.com/report.pdf").unwrap(),
+            file_name: "report.pdf".to_string(),
+            content_type: Some("application/pdf".to_string()),
+            contents: b"report".to_vec(),
+        };
+
+        writer.fail.store(true, Ordering::SeqCst);
+        let failed = use_case.execute(RequestContext::user(), report, directory).await;
+        assert!(matches!(failed, Err(NavigatorError::Download(DownloadError::Write { .. }))));
+        let [unfinished] = manager.unfinished().try_into().unwrap();
+        assert_eq!(unfinished.file_name, "report.pdf");
+        assert!(unfinished.error.is_some());
+        assert!(stored.find_all().await.unwrap().is_empty());
+
+        writer.fail.store(false, Ordering::SeqCst);
+        let saved = use_case.retry(unfinished.id, directory).await.unwrap();
+        assert_eq!(writer.file(&saved.path).unwrap(), b"report");
+        assert!(manager.unfinished().is_empty());
+        assert_eq!(
+            use_case.retry(unfinished.id, directory).await,
+            Err(NavigatorError::UnfinishedDownloadNotFound(unfinished.id))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_print_page_to_pdf() {
+        let engine = Arc::new(FakeEngine::new());
+        let url = ValidatedUrl::parse("https://example.com/docs/guide").unwrap();
+        engine.load_url(&url).await.unwrap();
+        let stored = Arc::new(InMemoryDownloadRepository::new());
+        let downloads = Arc::new(SessionDownloads::new(stored.clone()));
+        let writer = Arc::new(InMemoryDownloadWriter::new());
+        let print = |paper| {
+            PrintPageUseCase::new(engine.clone(), downloads.clone(), writer.clone(), Arc::new(FakePrinter), paper)
+        };
+        let directory = Path::new("saved");
+
+        let printed = print(PaperSize::Letter)
+            .execute(&Tab::with_url(url.clone(), false), directory)
+            .await
+            .unwrap();
+        assert_eq!(printed.path, directory.join("Page _docs_guide.pdf"));
+        let written = String::from_utf8(writer.file(&printed.path).unwrap()).unwrap();
+        assert!(written.starts_with("%PDF Letter Page /docs/guide from https://example.com/docs/guide"));
+        assert_eq!(stored.find_all().await.unwrap().len(), 1);
+        assert_eq!(
+            print(PaperSize::A4).execute(&Tab::new(false), directory).await,
+            Err(NavigatorError::NothingToSave)
+        );
+    }
+
+    fn tls_fixture(name: &str) -> String {
+        std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/tls").join(name)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_import_and_remove_certificate_authority() {
+        let repository = Arc::new(InMemoryCustomCaRepository::new());
+        let network: Arc<dyn TrustStore> = Arc::new(SecureNetworkClient::new().unwrap());
+        let import = ImportCertificateUseCase::new(repository.clone(), network.clone());
+
+        let ca = import.execute(None, &tls_fixture("ca.pem")).await.unwrap();
+        assert_eq!(ca.name, "Navigator Test CA");
+        assert_eq!(ca.fingerprint.len(), 32 * 3 - 1);
+        assert_eq!(
+            import.execute(Some("Again"), &tls_fixture("ca.pem")).await,
+            Err(CertificateImportError::AlreadyTrusted("Navigator Test CA".to_string()).into())
+        );
+
+        let refused = |result: Result<CustomCa>| match result {
+            Err(NavigatorError::Certificate(e)) => e,
+            other => panic!("Expected an import error, got {:?}", other),
+        };
+        let localhost = refused(import.execute(None, &tls_fixture("localhost.pem")).await);
+        assert!(matches!(localhost, CertificateImportError::NotAuthority(_)));
+        let expired = refused(import.execute(None, &tls_fixture("expired-ca.pem")).await);
+        assert_eq!(expired.to_string(), "CN=Navigator Expired Test CA expired on 2021-01-01");
+        let garbage = refused(import.execute(None, "not a certificate").await);
+        assert_eq!(garbage, CertificateImportError::NotPem("no PEM block".to_string()));
+        let key = refused(import.execute(None, &tls_fixture("localhost.key")).await);
+        assert_eq!(key, CertificateImportError::NotPem("a PRIVATE KEY block".to_string()));
+        assert_eq!(repository.find_all().await.unwrap().len(), 1);
+
+        let remove = RemoveCertificateUseCase::new(repository.clone(), network);
+        assert_eq!(remove.execute(ca.id).await.unwrap().name, "Navigator Test CA");
+        assert!(repository.find_all().await.unwrap().is_empty());
+        assert_eq!(
+            remove.execute(ca.id).await,
+            Err(CertificateImportError::NotFound(ca.id).into())
+        );
+    }
+
+    /// A page reached through two redirects, with its security headers
+    fn secure_page(engine: &FakeEngine) -> Tab {
+        let url = |url: &str| ValidatedUrl::parse(url).unwrap();
+        let date = |date: &str| DateTime::parse_from_rfc3339(date).unwrap().with_timezone(&Utc);
+        let header = |name: &str, value: &str| (name.to_string(), value.to_string());
+        let fetch = DocumentFetch {
+            url: url("https://www.shop.example/checkout"),
+            redirected_from: vec![url("http://shop.example/checkout"), url("https://shop.example/checkout")],
+            status: 200,
+            headers: vec![
+                header("content-type", "text/html"),
+                header("strict-transport-security", "max-age=31536000; includeSubDomains"),
+                header(
+                    "content-security-policy",
+                    "default-src 'self'; img-src 'self' data:; upgrade-insecure-requests",
+                ),
+                header("set-cookie", "session=abc123; Path=/; Secure; HttpOnly; SameSite=Lax"),
+                header("set-cookie", "theme=dark; Max-Age=3600"),
+            ],
+            version: HttpVersion::Http2,
+        };
+        let certificate = Certificate {
+            subject: "CN=www.shop.example".to_string(),
+            issuer: "CN=Example Issuing CA".to_string(),
+            valid_from: date("2026-01-01T00:00:00Z"),
+            valid_until: date("2099-01-01T00:00:00Z"),
+            is_valid: true,
+            ocsp: Some(OcspStaple {
+                status: RevocationStatus::Good,
+                this_update: date("2026-10-01T00:00:00Z"),
+                next_update: Some(date("2026-10-08T00:00:00Z")),
+            }),
+            sct_count: 2,
+        };
+        engine.set_document_fetch(fetch, Some(certificate));
+        engine.set_blocked_requests(vec![
+            BlockedRequest {
+                url: url("https://tracker.example/pixel.gif"),
+                category: BlockCategory::Trackers,
+            },
+            BlockedRequest {
+                url: url("http://cdn.example/logo.png"),
+                category: BlockCategory::MixedContent,
+            },
+        ]);
+        Tab::with_url(url("https://shop.example/checkout"), false)
+    }
+
+    #[tokio::test]
+    async fn test_security_report_matches_snapshot() {
+        let engine = Arc::new(FakeEngine::new());
+        let tab = secure_page(&engine);
+        let export = ExportSecurityReportUseCase::new(
+            engine.clone(),
+            Arc::new(DefaultSecurityService::new()),
+            Some(Arc::new(FakeTlsInspector)),
+        );
+
+        let mut report = export.execute(&tab).await.unwrap();
+        assert_eq!(report.schema_version, SECURITY_REPORT_VERSION);
+        report.generated_at = DateTime::parse_from_rfc3339("2026-10-18T12:00:00Z").unwrap().with_timezone(&Utc);
+        let snapshot = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/security-report/report.json");
+        let expected = std::fs::read_to_string(snapshot).unwrap();
+        assert_eq!(security_report_json(&report).unwrap(), expected.trim_end());
+
+        // Reports written by this version read back whole
+        let read: SecurityReport = serde_json::from_str(&expected).unwrap();
+        assert_eq!(read, report);
+    }
+
+    #[tokio::test]
+    async fn test_security_report_of_insecure_page() {
+        let engine = Arc::new(FakeEngine::new());
+        let security = Arc::new(DefaultSecurityService::new());
+        security.add_blocked_domain("198.51.100.7".to_string());
+        let url = ValidatedUrl::parse("http://198.51.100.7:8080/login").unwrap();
+        engine.set_document_fetch(
+            DocumentFetch {
+                url: url.clone(),
+                redirected_from: Vec::new(),
+                status: 200,
+                // Only honoured over HTTPS
+                headers: vec![("strict-transport-security".to_string(), "max-age=600".to_string())],
+                version: HttpVersion::Http11,
+            },
+            None,
+        );
+        let export = ExportSecurityReportUseCase::new(engine, security, Some(Arc::new(FakeTlsInspector)));
+
+        let tab = Tab::with_url(url, false);
+        let report = export.execute(&tab).await.unwrap();
+        assert_eq!((report.tls, report.hsts, report.csp), (None, None, None));
+        assert_eq!(report.risk.level, RiskLevel::High);
+        assert_eq!(
+            report.risk.reasons,
+            [
+                "The host is on the blocked domains list",
+                "The page is not served over HTTPS",
+                "The host is an IP address rather than a name",
+                "The page is served on a non-standard port",
+            ]
+        );
+
+        let writer = InMemoryDownloadWriter::new();
+        let downloads = SessionDownloads::new(Arc::new(InMemoryDownloadRepository::new()));
+        let saved = export.save(&tab, Path::new("saved"), &downloads, &writer).await.unwrap();
+        assert_eq!(saved.path, Path::new("saved").join("Security report 198.51.100.7.json"));
+        let written: SecurityReport = serde_json::from_slice(&writer.file(&saved.path).unwrap()).unwrap();
+        assert_eq!(written.url, "http://198.51.100.7:8080/login");
+        assert_eq!(
+            export.execute(&Tab::new(false)).await,
+            Err(NavigatorError::NothingToSave)
+        );
     }
 }
@@ -1,9 +1,20 @@
-use super::value_objects::{TabId, ValidatedUrl, Certificate};
+use super::value_objects::{
+    BlockCategory, BlockedRequest, Certificate, CookiePolicy, HostPattern, MatchPattern, RunAt, SearchTemplate,
+    SiteOrigin, SiteSetting, StorageChange, TabId, UserStyleRule, ValidatedUrl, VisitType, WindowGeometry,
+    WindowId,
+};
+use super::errors::{SearchEngineError, StorageError};
+use super::services::{Clock, SystemClock};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Title of a tab showing no page
+pub const NEW_TAB_TITLE: &str = "New Tab";
 
 /// Represents a browser tab
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Tab {
     pub id: TabId,
     pub title: String,
@@ -17,10 +28,10 @@ pub struct Tab {
 
 impl Tab {
     pub fn new(is_private: bool) -> Self {
-        let now = Utc::now();
+        let now = SystemClock.now();
         Self {
             id: TabId::new(),
-            title: "New Tab".to_string(),
+            title: NEW_TAB_TITLE.to_string(),
             url: None,
             is_loading: false,
             is_private,
@@ -37,9 +48,19 @@ impl Tab {
         tab
     }
 
+    /// Where the tab is; `about:blank` until it navigates anywhere
+    pub fn url(&self) -> ValidatedUrl {
+        self.url.clone().unwrap_or_else(ValidatedUrl::about_blank)
+    }
+
+    /// The page the tab shows, if any: `None` while it is blank
+    pub fn page_url(&self) -> Option<&ValidatedUrl> {
+        self.url.as_ref().filter(|url| !url.is_about_blank())
+    }
+
     pub fn update_url(&mut self, url: ValidatedUrl) {
         self.url = Some(url);
-        self.last_accessed = Utc::now();
+        self.last_accessed = SystemClock.now();
     }
 
     pub fn update_title(&mut self, title: String) {
@@ -51,32 +72,240 @@ impl Tab {
     }
 }
 
+/// A window as a session left it, for reopening it with the same tabs
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedWindow {
+    pub id: WindowId,
+    /// `None` opens the window where new windows go
+    pub geometry: Option<WindowGeometry>,
+    /// The tab the window showed; `None` shows its first
+    pub active_tab: Option<TabId>,
+    /// Its tabs, in display order
+    pub tabs: Vec<Tab>,
+}
+
+impl SavedWindow {
+    /// The tab to show first: the active one if it is still among the tabs
+    pub fn shown_tab(&self) -> Option<&Tab> {
+        self.active_tab
+            .and_then(|active| self.tabs.iter().find(|tab| tab.id == active))
+            .or_else(|| self.tabs.first())
+    }
+}
+
+/// Gap left between the positions of bookmarks added one after another,
+/// so moving one between two others rarely renumbers the rest
+pub const BOOKMARK_POSITION_STEP: i64 = 1024;
+
 /// Represents a bookmark
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Bookmark {
     pub id: i64,
     pub title: String,
     pub url: ValidatedUrl,
     pub folder: Option<String>,
+    /// Place among the bookmarks of its folder, lowest first; positions
+    /// have gaps and ties are broken by `created_at`
+    #[serde(default)]
+    pub position: i64,
     pub created_at: DateTime<Utc>,
     pub tags: Vec<String>,
 }
 
-impl Bookmark {
+/// Positions to store for moving bookmark `moved` to `index` among
+/// `siblings`, the other bookmarks of its folder in order as `(id,
+/// position)`. Only the moved bookmark's changes while there is a gap to
+/// put it in; otherwise the whole folder is renumbered.
+pub fn bookmark_move_positions(moved: i64, siblings: &[(i64, i64)], index: usize) -> Vec<(i64, i64)> {
+    let index = index.min(siblings.len());
+    let before = index.checked_sub(1).map(|before| siblings[before].1);
+    let after = siblings.get(index).map(|(_, after)| *after);
+    let position = match (before, after) {
+        (None, None) => Some(BOOKMARK_POSITION_STEP),
+        (Some(before), None) => before.checked_add(BOOKMARK_POSITION_STEP),
+        (None, Some(after)) => after.checked_sub(BOOKMARK_POSITION_STEP),
+        (Some(before), Some(after)) => (after.saturating_sub(before) >= 2).then(|| before + (after - before) / 2),
+    };
+    if let Some(position) = position {
+        return vec![(moved, position)];
+    }
+    let mut ids: Vec<i64> = siblings.iter().map(|(id, _)| *id).collect();
+    ids.insert(index, moved);
+    (1..).zip(ids).map(|(rank, id)| (id, rank * BOOKMARK_POSITION_STEP)).collect()
+}
+
+/// A bookmark that has not been saved yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewBookmark {
+    pub title: String,
+    pub url: ValidatedUrl,
+    pub folder: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub tags: Vec<String>,
+}
+
+impl NewBookmark {
     pub fn new(title: String, url: ValidatedUrl) -> Self {
         Self {
-            id: 0, // Will be set by database
             title,
             url,
             folder: None,
-            created_at: Utc::now(),
+            created_at: SystemClock.now(),
             tags: Vec::new(),
         }
     }
 }
 
+/// A saved user style rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserStyle {
+    pub id: i64,
+    pub host_pattern: HostPattern,
+    pub rule: UserStyleRule,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user style rule that has not been saved yet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewUserStyle {
+    pub host_pattern: HostPattern,
+    pub rule: UserStyleRule,
+}
+
+impl NewUserStyle {
+    pub fn new(host_pattern: HostPattern, rule: UserStyleRule) -> Self {
+        Self { host_pattern, rule }
+    }
+}
+
+/// A saved user script
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserScript {
+    pub id: i64,
+    pub name: String,
+    pub matches: Vec<MatchPattern>,
+    pub code: String,
+    pub enabled: bool,
+    pub run_at: RunAt,
+    /// Also run on browser-internal pages such as `about:` and `file:`
+    pub allow_internal: bool,
+    /// Also run in private tabs
+    pub allow_private: bool,
+    /// Why the last run failed; cleared by a run that succeeds
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl UserScript {
+    pub fn matches(&self, url: &ValidatedUrl) -> bool {
+        self.matches.iter().any(|pattern| pattern.matches(url))
+    }
+}
+
+/// A user script that has not been saved yet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NewUserScript {
+    pub name: String,
+    pub matches: Vec<MatchPattern>,
+    pub code: String,
+    pub enabled: bool,
+    pub run_at: RunAt,
+    pub allow_internal: bool,
+    pub allow_private: bool,
+}
+
+impl NewUserScript {
+    /// An enabled script that runs at document end on ordinary pages only
+    pub fn new(name: String, matches: Vec<MatchPattern>, code: String) -> Self {
+        Self {
+            name,
+            matches,
+            code,
+            enabled: true,
+            run_at: RunAt::default(),
+            allow_internal: false,
+            allow_private: false,
+        }
+    }
+}
+
+/// What a user script reported back from one run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptOutcome {
+    /// Messages passed to `GM_log`
+    pub log: Vec<String>,
+    /// Keys passed to `GM_setValue`, with their values as JSON
+    pub values: Vec<(String, String)>,
+    /// The exception the script threw, if any
+    pub error: Option<String>,
+}
+
+/// A saved search engine, triggered by typing its keyword before a query
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchEngine {
+    pub id: i64,
+    pub name: String,
+    /// A single lowercase word
+    pub keyword: String,
+    pub query_template: SearchTemplate,
+    /// Endpoint answering in the OpenSearch suggestions JSON format
+    pub suggest_template: Option<SearchTemplate>,
+    /// Searched when typed text is neither a URL nor starts with a keyword
+    pub is_default: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SearchEngine {
+    /// Results page for a query
+    pub fn search_url(&self, query: &str) -> Option<ValidatedUrl> {
+        self.query_template.expand(query)
+    }
+
+    /// Suggestions for a partly typed query, if the engine offers them
+    pub fn suggest_url(&self, query: &str) -> Option<ValidatedUrl> {
+        self.suggest_template.as_ref()?.expand(query)
+    }
+}
+
+/// A search engine that has not been saved yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewSearchEngine {
+    pub name: String,
+    pub keyword: String,
+    pub query_template: SearchTemplate,
+    pub suggest_template: Option<SearchTemplate>,
+}
+
+impl NewSearchEngine {
+    /// Keywords are matched case-insensitively, so they are kept lowercase
+    pub fn new(name: String, keyword: &str, query_template: SearchTemplate) -> Self {
+        Self {
+            name,
+            keyword: keyword.trim().to_lowercase(),
+            query_template,
+            suggest_template: None,
+        }
+    }
+
+    pub fn with_suggest_template(mut self, suggest_template: SearchTemplate) -> Self {
+        self.suggest_template = Some(suggest_template);
+        self
+    }
+
+    /// The keyword must be a single word none of `existing` uses
+    pub fn check_keyword(&self, existing: &[SearchEngine]) -> Result<(), SearchEngineError> {
+        if self.keyword.is_empty() || self.keyword.contains(char::is_whitespace) {
+            return Err(SearchEngineError::InvalidKeyword(self.keyword.clone()));
+        }
+        if existing.iter().any(|engine| engine.keyword == self.keyword) {
+            return Err(SearchEngineError::DuplicateKeyword(self.keyword.clone()));
+        }
+        Ok(())
+    }
+}
+
 /// Represents a history entry
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HistoryEntry {
     pub id: i64,
     pub url: ValidatedUrl,
@@ -85,16 +314,342 @@ pub struct HistoryEntry {
     pub visit_count: i32,
 }
 
+/// Frecency points of a visit made within each age, in percent of a fresh
+/// one; older visits get `OLD_VISIT_BONUS`
+const RECENCY_BONUSES: [(i64, i64); 4] = [(4, 100), (14, 70), (31, 50), (90, 30)];
+const OLD_VISIT_BONUS: i64 = 10;
+
+fn recency_bonus(visited_at: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    let days = now.signed_duration_since(visited_at).num_days();
+    RECENCY_BONUSES
+        .iter()
+        .find(|(within, _)| days <= *within)
+        .map_or(OLD_VISIT_BONUS, |(_, bonus)| *bonus)
+}
+
 impl HistoryEntry {
+    /// How likely the page is to be wanted again as of `now`: each of its
+    /// `visits` weighted by its type and how recent it is. Visits counted
+    /// but not recorded one by one, such as imported ones, count as links
+    /// followed at the last visit.
+    pub fn frecency(&self, visits: &[Visit], now: DateTime<Utc>) -> i64 {
+        let mut recorded = 0;
+        let mut frecency = 0;
+        for visit in visits.iter().filter(|visit| visit.history_id == self.id) {
+            recorded += 1;
+            frecency += visit.visit_type.weight() * recency_bonus(visit.visited_at, now);
+        }
+        let unrecorded = i64::from(self.visit_count) - recorded;
+        if unrecorded > 0 {
+            frecency += unrecorded * VisitType::Link.weight() * recency_bonus(self.visited_at, now);
+        }
+        frecency / 100
+    }
+}
+
+/// One visit to a history entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Visit {
+    pub history_id: i64,
+    pub visited_at: DateTime<Utc>,
+    pub visit_type: VisitType,
+}
+
+/// History removed by deleting a span of time, kept to put it back
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeletedHistory {
+    /// Each entry that lost visits, as last visited before, counting only
+    /// the visits it lost
+    pub entries: Vec<HistoryEntry>,
+    /// The visits removed, oldest first
+    pub visits: Vec<Visit>,
+}
+
+/// A visit that has not been recorded yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewHistoryEntry {
+    pub url: ValidatedUrl,
+    pub title: String,
+    pub visited_at: DateTime<Utc>,
+    /// Visits this entry stands for; more than one when visits are merged
+    pub visit_count: i32,
+    /// How the visit came about; merged visits take the latest one's
+    #[serde(default)]
+    pub visit_type: VisitType,
+}
+
+impl NewHistoryEntry {
     pub fn new(url: ValidatedUrl, title: String) -> Self {
+        Self::at(url, title, &SystemClock)
+    }
+
+    /// A visit made now by `clock`
+    pub fn at(url: ValidatedUrl, title: String, clock: &dyn Clock) -> Self {
         Self {
-            id: 0, // Will be set by database
             url,
             title,
-            visited_at: Utc::now(),
+            visited_at: clock.now(),
             visit_count: 1,
+            visit_type: VisitType::default(),
+        }
+    }
+
+    pub fn with_visit_type(mut self, visit_type: VisitType) -> Self {
+        self.visit_type = visit_type;
+        self
+    }
+}
+
+/// A file written to the downloads directory, such as a saved page
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Download {
+    pub id: i64,
+    /// Where the contents came from
+    pub url: ValidatedUrl,
+    pub path: PathBuf,
+    /// Bytes written
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A download that has not been recorded yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewDownload {
+    pub url: ValidatedUrl,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+/// A certificate authority the user trusts besides the built-in roots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomCa {
+    pub id: i64,
+    pub name: String,
+    /// The certificate, PEM-encoded
+    pub pem: String,
+    /// SHA-256 of the certificate, as colon-separated hex
+    pub fingerprint: String,
+    pub added_at: DateTime<Utc>,
+}
+
+/// A certificate authority that has not been stored yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewCustomCa {
+    pub name: String,
+    pub pem: String,
+    pub fingerprint: String,
+}
+
+/// A document kept from the network so it can be shown while offline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedPage {
+    pub url: ValidatedUrl,
+    pub title: String,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// A cached page without its body, for listing what is available offline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedPageSummary {
+    pub url: ValidatedUrl,
+    pub title: String,
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// The latest release, as described by the update manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateManifest {
+    /// Semantic version, such as `1.2.0`
+    pub version: String,
+    /// Release notes, as plain text
+    pub notes: String,
+    /// Where the release can be downloaded
+    pub download: ValidatedUrl,
+}
+
+/// A release file fetched whole, to be saved as a download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleaseFile {
+    /// Where the contents came from, after redirects
+    pub url: ValidatedUrl,
+    pub content_type: Option<String>,
+    pub contents: Vec<u8>,
+}
+
+/// Settings one site has in place of the global ones; `None` keeps the
+/// global value
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SiteSettings {
+    pub origin: SiteOrigin,
+    pub javascript: Option<bool>,
+    pub images: Option<bool>,
+    pub cookies: Option<CookiePolicy>,
+    pub user_agent: Option<String>,
+    /// Whether ads and trackers are blocked on the site's pages
+    pub content_blocking: Option<bool>,
+    /// Whether the site's pages are recolored dark when dark pages are on
+    pub dark_pages: Option<bool>,
+}
+
+impl SiteSettings {
+    /// No overrides yet
+    pub fn new(origin: SiteOrigin) -> Self {
+        Self {
+            origin,
+            javascript: None,
+            images: None,
+            cookies: None,
+            user_agent: None,
+            content_blocking: None,
+            dark_pages: None,
+        }
+    }
+
+    /// Set or remove one override; an empty user agent removes it
+    pub fn apply(&mut self, setting: SiteSetting) {
+        match setting {
+            SiteSetting::JavaScript(enabled) => self.javascript = enabled,
+            SiteSetting::Images(enabled) => self.images = enabled,
+            SiteSetting::Cookies(policy) => self.cookies = policy,
+            SiteSetting::UserAgent(agent) => {
+                self.user_agent = agent.map(|agent| agent.trim().to_string()).filter(|agent| !agent.is_empty())
+            }
+            SiteSetting::ContentBlocking(enabled) => self.content_blocking = enabled,
+            SiteSetting::DarkPages(enabled) => self.dark_pages = enabled,
         }
     }
+
+    /// Whether nothing is overridden, so there is nothing to keep
+    pub fn is_empty(&self) -> bool {
+        self.javascript.is_none()
+            && self.images.is_none()
+            && self.cookies.is_none()
+            && self.user_agent.is_none()
+            && self.content_blocking.is_none()
+            && self.dark_pages.is_none()
+    }
+}
+
+/// Characters one origin may keep in `localStorage`, about the 5MB other
+/// browsers allow
+pub const DEFAULT_STORAGE_QUOTA: usize = 5 * 1024 * 1024;
+
+/// The items of one origin's Storage object, in the order first set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageArea {
+    pub origin: SiteOrigin,
+    items: Vec<(String, String)>,
+    /// Most characters keys and values may add up to
+    quota: usize,
+}
+
+impl StorageArea {
+    pub fn new(origin: SiteOrigin) -> Self {
+        Self::with_items(origin, Vec::new())
+    }
+
+    /// An area holding `items` already stored
+    pub fn with_items(origin: SiteOrigin, items: Vec<(String, String)>) -> Self {
+        Self {
+            origin,
+            items,
+            quota: DEFAULT_STORAGE_QUOTA,
+        }
+    }
+
+    pub fn with_quota(mut self, quota: usize) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    pub fn get_item(&self, key: &str) -> Option<&str> {
+        self.items
+            .iter()
+            .find(|(stored, _)| stored == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The key at `index`, for `Storage.key`
+    pub fn key(&self, index: usize) -> Option<&str> {
+        self.items.get(index).map(|(key, _)| key.as_str())
+    }
+
+    pub fn length(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn items(&self) -> &[(String, String)] {
+        &self.items
+    }
+
+    /// Characters stored, counted in UTF-16 code units like pages see them
+    pub fn usage(&self) -> usize {
+        self.items.iter().map(|(key, value)| storage_len(key, value)).sum()
+    }
+
+    /// Make `change`, unless setting an item would pass the quota. A key
+    /// set again keeps its place.
+    pub fn apply(&mut self, change: StorageChange) -> Result<(), StorageError> {
+        match change {
+            StorageChange::Set { key, value } => {
+                let replaced = self.get_item(&key).map_or(0, |old| storage_len(&key, old));
+                if self.usage() - replaced + storage_len(&key, &value) > self.quota {
+                    return Err(StorageError::QuotaExceeded(self.quota));
+                }
+                match self.items.iter_mut().find(|(stored, _)| *stored == key) {
+                    Some((_, stored)) => *stored = value,
+                    None => self.items.push((key, value)),
+                }
+            }
+            StorageChange::Remove(key) => self.items.retain(|(stored, _)| *stored != key),
+            StorageChange::Clear => self.items.clear(),
+        }
+        Ok(())
+    }
+}
+
+fn storage_len(key: &str, value: &str) -> usize {
+    key.encode_utf16().count() + value.encode_utf16().count()
+}
+
+/// What a tab's page tried to load and was refused, since it was loaded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockedSummary {
+    /// Blocked requests in each category, every category listed
+    pub counts: Vec<(BlockCategory, usize)>,
+    /// Blocked URLs grouped by the site they belong to, sites in order
+    pub sites: Vec<(String, Vec<ValidatedUrl>)>,
+}
+
+impl BlockedSummary {
+    pub fn new(requests: &[BlockedRequest]) -> Self {
+        let counts = BlockCategory::ALL
+            .iter()
+            .map(|category| (*category, requests.iter().filter(|request| request.category == *category).count()))
+            .collect();
+        let mut sites: BTreeMap<String, Vec<ValidatedUrl>> = BTreeMap::new();
+        for request in requests {
+            let site = request.url.site_host().unwrap_or_else(|| request.url.scheme().to_string());
+            sites.entry(site).or_default().push(request.url.clone());
+        }
+        Self {
+            counts,
+            sites: sites.into_iter().collect(),
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|(_, count)| count).sum()
+    }
+
+    pub fn count(&self, category: BlockCategory) -> usize {
+        self.counts
+            .iter()
+            .find(|(counted, _)| *counted == category)
+            .map_or(0, |(_, count)| *count)
+    }
 }
 
 /// Security context for a tab
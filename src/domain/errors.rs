@@ -0,0 +1,260 @@
+use thiserror::Error;
+
+/// Why a URL may not be navigated to
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SecurityError {
+    /// The host is on the block list
+    #[error("{0} is blocked for security reasons")]
+    Blocked(String),
+    #[error("Unsupported URL scheme: {0}")]
+    UnsupportedScheme(String),
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    /// A file:// URL the file access policy rejects
+    #[error("{0}")]
+    FileAccessDenied(String),
+    /// The port belongs to a non-web service and is refused
+    #[error("Port {0} is restricted because it is used by services other than the web")]
+    RestrictedPort(u16),
+    /// URLs of the scheme are opened by another program, never loaded
+    #[error("{0}: links open in another program")]
+    ExternalScheme(String),
+}
+
+/// Why a resource could not be fetched
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum NetworkError {
+    #[error("The request timed out")]
+    Timeout,
+    /// The host name could not be resolved
+    #[error("Could not resolve {0}")]
+    Dns(String),
+    #[error("Secure connection failed: {0}")]
+    Tls(String),
+    /// The server's certificate authority says its certificate is revoked;
+    /// holds the host
+    #[error("The certificate of {0} has been revoked")]
+    CertificateRevoked(String),
+    #[error("Could not connect: {0}")]
+    Connection(String),
+    /// The server answered with an error status
+    #[error("The server responded with status {0}")]
+    Status(u16),
+    /// A request interceptor refused the request; holds the interceptor's name
+    #[error("The request was blocked by {0}")]
+    Blocked(String),
+    #[error("Too many redirects")]
+    TooManyRedirects,
+    /// A redirect or resource pointed at a restricted port
+    #[error("Port {0} is restricted because it is used by services other than the web")]
+    RestrictedPort(u16),
+    /// The response is larger than the given number of bytes allowed
+    #[error("The response exceeds {0} bytes")]
+    BodyTooLarge(usize),
+    /// A page on the public internet tried to reach this host on a private
+    /// network or the local machine
+    #[error("Pages on the internet may not connect to {0} on a private network")]
+    PrivateNetwork(String),
+    /// The WebSocket handshake failed or the server broke the protocol
+    #[error("WebSocket failed: {0}")]
+    WebSocket(String),
+    /// Offline mode is on and the resource was not kept for offline use
+    #[error("You are offline")]
+    Offline,
+    /// Any other failure while sending the request or reading the response
+    #[error("Request failed: {0}")]
+    Request(String),
+}
+
+/// Why profile storage could not be read or written
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum StorageError {
+    /// Another connection kept the database locked through every retry
+    #[error("The profile database is locked: {0}")]
+    Locked(String),
+    #[error("Database error: {0}")]
+    Database(String),
+    /// Stored data that can no longer be read back
+    #[error("Corrupt profile data: {0}")]
+    Corrupt(String),
+    #[error("This profile is encrypted; a passphrase is required to open it")]
+    PassphraseRequired,
+    #[error("Wrong passphrase for the encrypted profile")]
+    WrongPassphrase,
+    #[error("Profile encryption failed: {0}")]
+    Encryption(String),
+    /// A site's Storage would grow past its quota, in characters; shown to
+    /// pages as a `QuotaExceededError`
+    #[error("QuotaExceededError: the storage quota of {0} characters is used up")]
+    QuotaExceeded(usize),
+    /// A write on behalf of a private tab, which leaves nothing behind
+    #[error("Nothing a private tab does is stored")]
+    PrivateWrite,
+    /// No stored record has the id
+    #[error("Record {0} not found")]
+    NotFound(i64),
+}
+
+impl StorageError {
+    /// Database error with what was being done, for the log
+    pub fn database(context: &str, error: impl std::fmt::Display) -> Self {
+        Self::Database(format!("{}: {}", context, error))
+    }
+}
+
+/// Why another browser's profile could not be read
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ImportError {
+    /// No profile directory or data file where one was expected
+    #[error("Browser profile not found: {0}")]
+    ProfileNotFound(String),
+    #[error("Could not copy the profile: {0}")]
+    Io(String),
+    /// The data is not in a format this version understands
+    #[error("Unreadable profile data: {0}")]
+    Unreadable(String),
+}
+
+/// Why a user style rule was refused
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum UserStyleError {
+    #[error("Invalid host pattern: {0}")]
+    InvalidHostPattern(String),
+    #[error("Unsupported user style rule: {0}")]
+    InvalidRule(String),
+}
+
+/// Why a search engine was refused
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SearchEngineError {
+    #[error("A search template must be an https URL with exactly one %s: {0}")]
+    InvalidTemplate(String),
+    /// Keywords are a single word, typed before the query
+    #[error("Invalid search keyword: {0:?}")]
+    InvalidKeyword(String),
+    #[error("The keyword {0} is already used by another search engine")]
+    DuplicateKeyword(String),
+    #[error("Search engine {0} not found")]
+    NotFound(i64),
+    /// An OpenSearch description without a usable name or search template
+    #[error("Not a usable OpenSearch description: {0}")]
+    InvalidDescription(String),
+    /// Only searches sent with GET can be typed into the address bar
+    #[error("Searches sent with {0} are not supported")]
+    UnsupportedMethod(String),
+    #[error("Search engines expecting {0} text are not supported")]
+    UnsupportedEncoding(String),
+    /// The description could not be fetched
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+}
+
+/// Why a file could not be written to the downloads directory
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DownloadError {
+    #[error("Cannot write to {path}: {reason}")]
+    Write { path: String, reason: String },
+    /// Every numbered variant of the file name is taken
+    #[error("No free file name for {0}")]
+    NameTaken(String),
+    #[error("Cannot delete {path}: {reason}")]
+    Delete { path: String, reason: String },
+    /// Writing stopped for a shutdown; holds the file, whose part file
+    /// was kept to resume
+    #[error("Download of {0} was paused")]
+    Paused(String),
+    /// The user stopped the write; nothing of it was kept
+    #[error("Download of {0} was cancelled")]
+    Cancelled(String),
+}
+
+/// Why a user script was refused
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum UserScriptError {
+    #[error("Invalid match pattern: {0}")]
+    InvalidMatchPattern(String),
+    #[error("A user script needs a name and at least one match pattern")]
+    Incomplete,
+    #[error("User script {0} not found")]
+    NotFound(i64),
+    /// Not an `<option>=<value>` a user script has
+    #[error("Invalid user script option: {0}")]
+    InvalidOption(String),
+}
+
+/// Why a certificate authority could not be trusted
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CertificateImportError {
+    /// No PEM certificate block; holds what was found instead
+    #[error("Expected a PEM certificate but found {0}")]
+    NotPem(String),
+    /// The PEM block does not hold a valid X.509 certificate
+    #[error("Invalid certificate: {0}")]
+    Invalid(String),
+    /// The certificate may not issue others, so trusting it does nothing
+    #[error("{0} is not a certificate authority")]
+    NotAuthority(String),
+    #[error("{subject} expired on {}", .expired.format("%Y-%m-%d"))]
+    Expired {
+        subject: String,
+        expired: chrono::DateTime<chrono::Utc>,
+    },
+    /// Holds the name the certificate is trusted under
+    #[error("This certificate authority is already trusted as {0}")]
+    AlreadyTrusted(String),
+    #[error("Certificate authority {0} not found")]
+    NotFound(i64),
+    /// The network client could not be rebuilt with the new roots
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+}
+
+/// Why the update check or the download of a release failed
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum UpdateError {
+    /// The manifest is not JSON of the expected shape or its version is
+    /// not a semantic version
+    #[error("Invalid update manifest: {0}")]
+    InvalidManifest(String),
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+}
+
+/// Why a site's robots.txt or sitemap could not be read
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SiteMetadataError {
+    /// Only web sites publish them; holds the scheme
+    #[error("{0}: sites have no robots.txt or sitemap")]
+    UnsupportedScheme(String),
+    /// Neither sitemap XML nor a list of URLs; holds the reason
+    #[error("Not a sitemap: {0}")]
+    InvalidSitemap(String),
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+}
+
+/// Why a page could not be loaded or rendered
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RenderError {
+    #[error(transparent)]
+    Security(#[from] SecurityError),
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+    /// A local file or directory could not be read
+    #[error("{0}")]
+    File(String),
+    /// Inline content, such as a data: URL, that cannot be decoded
+    #[error("Invalid content: {0}")]
+    InvalidContent(String),
+    #[error("Layout failed: {0}")]
+    Layout(String),
+    /// The document scripts work on is missing or its thread has stopped
+    #[error("Script failed: {0}")]
+    Script(String),
+    /// The page's parser or scripts panicked; the document was dropped
+    #[error("The page crashed: {0}")]
+    Crashed(String),
+    /// The page could not be painted into an image
+    #[error("Screenshot failed: {0}")]
+    Capture(String),
+}
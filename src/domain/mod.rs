@@ -2,11 +2,15 @@
 // This layer is independent of frameworks and external dependencies
 
 pub mod entities;
+pub mod errors;
+pub mod persistence;
 pub mod repositories;
 pub mod services;
 pub mod value_objects;
 
 pub use entities::*;
+pub use errors::*;
+pub use persistence::*;
 pub use repositories::*;
 pub use services::*;
 pub use value_objects::*;
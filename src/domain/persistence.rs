@@ -0,0 +1,520 @@
+use super::entities::{
+    Bookmark, CachedPage, CachedPageSummary, CustomCa, DeletedHistory, Download, HistoryEntry, NewBookmark,
+    NewCustomCa, NewDownload, NewHistoryEntry, NewSearchEngine, NewUserScript, NewUserStyle, SavedWindow, SearchEngine,
+    SiteSettings, Tab, UserScript, UserStyle, Visit,
+};
+use super::errors::StorageError;
+use super::repositories::{
+    BookmarkRepository, CustomCaRepository, DownloadRepository, HistoryRepository, PageCacheRepository,
+    SearchEngineRepository, SettingsRepository, SiteSettingsRepository, TabRepository, UserScriptRepository,
+    UserStyleRepository, WebStorageRepository,
+};
+use super::value_objects::{SiteOrigin, StorageChange, TabId, ValidatedUrl};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+/// On whose behalf something is stored: a tab, or the user directly
+///
+/// Work a page or a navigation causes carries its tab's privacy, so
+/// storage can refuse what a private tab must not leave behind. What the
+/// user deliberately keeps, such as bookmarks, settings or a downloaded
+/// file, is theirs even from a private window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RequestContext {
+    /// The tab the work is for, if any
+    pub tab: Option<TabId>,
+    pub private: bool,
+}
+
+impl RequestContext {
+    pub fn new(tab: Option<TabId>, private: bool) -> Self {
+        Self { tab, private }
+    }
+
+    /// Work the user asked for outside any page, e.g. on a settings page
+    pub fn user() -> Self {
+        Self::default()
+    }
+
+    /// Work on behalf of `tab`
+    pub fn for_tab(tab: &Tab) -> Self {
+        Self::new(Some(tab.id), tab.is_private)
+    }
+
+    /// Whether `what` may be stored. Private requests may not; in debug
+    /// builds refusals are logged as errors, since one means a feature
+    /// forgot about private tabs.
+    pub fn permits(&self, what: &str) -> bool {
+        if !self.private {
+            return true;
+        }
+        #[cfg(debug_assertions)]
+        match self.tab {
+            Some(tab) => tracing::error!("Refused to store {} for private tab {}", what, tab),
+            None => tracing::error!("Refused to store {} for a private tab", what),
+        }
+        false
+    }
+}
+
+/// A repository that refuses writes on behalf of private tabs
+///
+/// Reads pass through. Refused writes are dropped, or fail with
+/// [`StorageError::PrivateWrite`] where the caller expects what was
+/// stored back.
+pub struct PersistenceGuard<R: ?Sized> {
+    inner: Arc<R>,
+    context: RequestContext,
+}
+
+impl<R: ?Sized> PersistenceGuard<R> {
+    pub fn new(inner: Arc<R>, context: RequestContext) -> Self {
+        Self { inner, context }
+    }
+
+    pub fn context(&self) -> RequestContext {
+        self.context
+    }
+
+    /// The repository, if the context may write `what` to it
+    fn writable(&self, what: &str) -> Option<&R> {
+        self.context.permits(what).then_some(&*self.inner)
+    }
+
+    /// The repository for a write whose result the caller needs
+    fn required(&self, what: &str) -> Result<&R> {
+        self.writable(what).ok_or(StorageError::PrivateWrite)
+    }
+}
+
+#[async_trait]
+impl<R: TabRepository + ?Sized> TabRepository for PersistenceGuard<R> {
+    async fn save(&self, tab: &Tab) -> Result<()> {
+        match self.writable("a tab") {
+            Some(inner) => inner.save(tab).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn find_by_id(&self, id: TabId) -> Result<Option<Tab>> {
+        self.inner.find_by_id(id).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Tab>> {
+        self.inner.find_all().await
+    }
+
+    async fn delete(&self, id: TabId) -> Result<()> {
+        match self.writable("a closed tab") {
+            Some(inner) => inner.delete(id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn save_session(&self, windows: Vec<SavedWindow>) -> Result<()> {
+        match self.writable("the session") {
+            Some(inner) => inner.save_session(windows).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn restore_session(&self) -> Result<Vec<SavedWindow>> {
+        self.inner.restore_session().await
+    }
+
+    async fn clear_session(&self) -> Result<()> {
+        match self.writable("a cleared session") {
+            Some(inner) => inner.clear_session().await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: BookmarkRepository + ?Sized> BookmarkRepository for PersistenceGuard<R> {
+    async fn save(&self, bookmark: NewBookmark) -> Result<Bookmark> {
+        self.required("a bookmark")?.save(bookmark).await
+    }
+
+    async fn find_by_id(&self, id: i64) -> Result<Option<Bookmark>> {
+        self.inner.find_by_id(id).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Bookmark>> {
+        self.inner.find_all().await
+    }
+
+    async fn find_by_folder(&self, folder: &str) -> Result<Vec<Bookmark>> {
+        self.inner.find_by_folder(folder).await
+    }
+
+    async fn search(&self, query: &str) -> Result<Vec<Bookmark>> {
+        self.inner.search(query).await
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        match self.writable("a removed bookmark") {
+            Some(inner) => inner.delete(id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn update(&self, bookmark: &Bookmark) -> Result<()> {
+        match self.writable("a bookmark") {
+            Some(inner) => inner.update(bookmark).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn restore(&self, bookmarks: &[Bookmark]) -> Result<()> {
+        match self.writable("restored bookmarks") {
+            Some(inner) => inner.restore(bookmarks).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn move_to(&self, id: i64, folder: Option<&str>, index: usize) -> Result<()> {
+        match self.writable("a moved bookmark") {
+            Some(inner) => inner.move_to(id, folder, index).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: HistoryRepository + ?Sized> HistoryRepository for PersistenceGuard<R> {
+    async fn add(&self, entry: NewHistoryEntry) -> Result<HistoryEntry> {
+        self.required("a visit")?.add(entry).await
+    }
+
+    async fn add_batch(&self, entries: &[NewHistoryEntry]) -> Result<()> {
+        match self.writable("visits") {
+            Some(inner) => inner.add_batch(entries).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn find_by_url(&self, url: &ValidatedUrl) -> Result<Option<HistoryEntry>> {
+        self.inner.find_by_url(url).await
+    }
+
+    async fn visited(&self, urls: &[ValidatedUrl]) -> Result<Vec<bool>> {
+        self.inner.visited(urls).await
+    }
+
+    async fn search(&self, query: &str, limit: i32) -> Result<Vec<HistoryEntry>> {
+        self.inner.search(query, limit).await
+    }
+
+    async fn get_recent(&self, limit: i32) -> Result<Vec<HistoryEntry>> {
+        self.inner.get_recent(limit).await
+    }
+
+    async fn visits(&self, history_ids: &[i64]) -> Result<Vec<Visit>> {
+        self.inner.visits(history_ids).await
+    }
+
+    async fn delete_by_url(&self, url: &ValidatedUrl) -> Result<()> {
+        match self.writable("a removed visit") {
+            Some(inner) => inner.delete_by_url(url).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn delete_by_id(&self, id: i64) -> Result<()> {
+        match self.writable("a removed visit") {
+            Some(inner) => inner.delete_by_id(id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn find_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<HistoryEntry>> {
+        self.inner.find_range(from, to).await
+    }
+
+    async fn delete_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<DeletedHistory> {
+        self.required("removed visits")?.delete_range(from, to).await
+    }
+
+    async fn restore(&self, deleted: &DeletedHistory) -> Result<()> {
+        match self.writable("restored visits") {
+            Some(inner) => inner.restore(deleted).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        match self.writable("cleared history") {
+            Some(inner) => inner.clear_all().await,
+            None => Ok(()),
+        }
+    }
+
+    async fn increment_visit_count(&self, url: &ValidatedUrl) -> Result<()> {
+        match self.writable("a visit") {
+            Some(inner) => inner.increment_visit_count(url).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: SettingsRepository + ?Sized> SettingsRepository for PersistenceGuard<R> {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        self.inner.get(key).await
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        match self.writable("a setting") {
+            Some(inner) => inner.set(key, value).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match self.writable("a removed setting") {
+            Some(inner) => inner.delete(key).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn get_all(&self) -> Result<Vec<(String, String)>> {
+        self.inner.get_all().await
+    }
+}
+
+#[async_trait]
+impl<R: UserStyleRepository + ?Sized> UserStyleRepository for PersistenceGuard<R> {
+    async fn save(&self, style: NewUserStyle) -> Result<UserStyle> {
+        self.required("a user style")?.save(style).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<UserStyle>> {
+        self.inner.find_all().await
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        match self.writable("a removed user style") {
+            Some(inner) => inner.delete(id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: UserScriptRepository + ?Sized> UserScriptRepository for PersistenceGuard<R> {
+    async fn save(&self, script: NewUserScript) -> Result<UserScript> {
+        self.required("a user script")?.save(script).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<UserScript>> {
+        self.inner.find_all().await
+    }
+
+    async fn update(&self, script: &UserScript) -> Result<()> {
+        match self.writable("a user script's results") {
+            Some(inner) => inner.update(script).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        match self.writable("a removed user script") {
+            Some(inner) => inner.delete(id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn values(&self, script_id: i64) -> Result<Vec<(String, String)>> {
+        self.inner.values(script_id).await
+    }
+
+    async fn set_value(&self, script_id: i64, key: &str, value: &str) -> Result<()> {
+        match self.writable("a user script's value") {
+            Some(inner) => inner.set_value(script_id, key, value).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: SearchEngineRepository + ?Sized> SearchEngineRepository for PersistenceGuard<R> {
+    async fn save(&self, engine: NewSearchEngine) -> Result<SearchEngine> {
+        self.required("a search engine")?.save(engine).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<SearchEngine>> {
+        self.inner.find_all().await
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        match self.writable("a removed search engine") {
+            Some(inner) => inner.delete(id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn set_default(&self, id: i64) -> Result<()> {
+        match self.writable("the default search engine") {
+            Some(inner) => inner.set_default(id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: DownloadRepository + ?Sized> DownloadRepository for PersistenceGuard<R> {
+    async fn save(&self, download: NewDownload) -> Result<Download> {
+        self.required("a download")?.save(download).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<Download>> {
+        self.inner.find_all().await
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        match self.writable("a removed download") {
+            Some(inner) => inner.delete(id).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn restore(&self, download: &Download) -> Result<()> {
+        match self.writable("a restored download") {
+            Some(inner) => inner.restore(download).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: SiteSettingsRepository + ?Sized> SiteSettingsRepository for PersistenceGuard<R> {
+    async fn find(&self, origin: &SiteOrigin) -> Result<Option<SiteSettings>> {
+        self.inner.find(origin).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<SiteSettings>> {
+        self.inner.find_all().await
+    }
+
+    async fn save(&self, settings: &SiteSettings) -> Result<()> {
+        match self.writable("a site's settings") {
+            Some(inner) => inner.save(settings).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn delete(&self, origin: &SiteOrigin) -> Result<()> {
+        match self.writable("a site's removed settings") {
+            Some(inner) => inner.delete(origin).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        match self.writable("cleared site settings") {
+            Some(inner) => inner.clear_all().await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: WebStorageRepository + ?Sized> WebStorageRepository for PersistenceGuard<R> {
+    async fn load(&self, origin: &SiteOrigin) -> Result<Vec<(String, String)>> {
+        self.inner.load(origin).await
+    }
+
+    async fn apply(&self, origin: &SiteOrigin, change: &StorageChange) -> Result<()> {
+        match self.writable("localStorage") {
+            Some(inner) => inner.apply(origin, change).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        match self.writable("cleared localStorage") {
+            Some(inner) => inner.clear_all().await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: CustomCaRepository + ?Sized> CustomCaRepository for PersistenceGuard<R> {
+    async fn save(&self, ca: NewCustomCa) -> Result<CustomCa> {
+        self.required("a certificate authority")?.save(ca).await
+    }
+
+    async fn find_all(&self) -> Result<Vec<CustomCa>> {
+        self.inner.find_all().await
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        match self.writable("a removed certificate authority") {
+            Some(inner) => inner.delete(id).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: PageCacheRepository + ?Sized> PageCacheRepository for PersistenceGuard<R> {
+    async fn store(&self, page: &CachedPage) -> Result<()> {
+        match self.writable("a page for offline use") {
+            Some(inner) => inner.store(page).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn find(&self, url: &ValidatedUrl) -> Result<Option<CachedPage>> {
+        self.inner.find(url).await
+    }
+
+    async fn find_by_origin(&self, origin: &SiteOrigin) -> Result<Vec<CachedPageSummary>> {
+        self.inner.find_by_origin(origin).await
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        match self.writable("cleared offline pages") {
+            Some(inner) => inner.clear_all().await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::testing::{InMemoryBookmarkRepository, InMemoryTabRepository};
+
+    #[tokio::test]
+    async fn test_private_requests_read_but_never_write() {
+        let tabs = Arc::new(InMemoryTabRepository::new());
+        let kept = Tab::new(false);
+        let private = Tab::new(true);
+        PersistenceGuard::new(tabs.clone(), RequestContext::for_tab(&kept))
+            .save(&kept)
+            .await
+            .unwrap();
+
+        let guard = PersistenceGuard::new(tabs.clone(), RequestContext::for_tab(&private));
+        guard.save(&private).await.unwrap();
+        guard.delete(kept.id).await.unwrap();
+        assert_eq!(guard.find_all().await.unwrap(), vec![kept.clone()]);
+        assert_eq!(tabs.find_all().await.unwrap(), vec![kept]);
+
+        let bookmarks = Arc::new(InMemoryBookmarkRepository::new());
+        let url = ValidatedUrl::parse("https://example.com/").unwrap();
+        let refused = PersistenceGuard::new(bookmarks.clone(), RequestContext::new(None, true))
+            .save(NewBookmark::new("Example".to_string(), url.clone()))
+            .await;
+        assert!(matches!(refused, Err(StorageError::PrivateWrite)));
+        let guard = PersistenceGuard::new(bookmarks.clone(), RequestContext::user());
+        assert!(guard.save(NewBookmark::new("Example".to_string(), url)).await.is_ok());
+        assert_eq!(bookmarks.find_all().await.unwrap().len(), 1);
+    }
+}
@@ -1,7 +1,14 @@
-use super::entities::{Bookmark, HistoryEntry, Tab};
-use super::value_objects::{TabId, ValidatedUrl};
+use super::entities::{
+    Bookmark, CachedPage, CachedPageSummary, CustomCa, DeletedHistory, Download, HistoryEntry, NewBookmark, NewCustomCa,
+    NewDownload, NewHistoryEntry, NewSearchEngine, NewUserScript, NewUserStyle, SavedWindow, SearchEngine,
+    SiteSettings, Tab, UserScript, UserStyle, Visit,
+};
+use super::errors::StorageError;
+use super::value_objects::{SiteOrigin, StorageChange, TabId, ValidatedUrl};
 use async_trait::async_trait;
-use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+type Result<T> = std::result::Result<T, StorageError>;
 
 /// Repository for managing tabs persistence
 #[async_trait]
@@ -10,30 +17,174 @@ pub trait TabRepository: Send + Sync {
     async fn find_by_id(&self, id: TabId) -> Result<Option<Tab>>;
     async fn find_all(&self) -> Result<Vec<Tab>>;
     async fn delete(&self, id: TabId) -> Result<()>;
-    async fn save_session(&self, tabs: Vec<Tab>) -> Result<()>;
-    async fn restore_session(&self) -> Result<Vec<Tab>>;
+    /// Store the windows open at exit with their tabs, replacing the
+    /// windows stored before
+    async fn save_session(&self, windows: Vec<SavedWindow>) -> Result<()>;
+    /// The stored windows in the order saved. Stored tabs whose window is
+    /// missing are adopted by the first window rather than lost.
+    async fn restore_session(&self) -> Result<Vec<SavedWindow>>;
+    /// Forget the stored windows and every stored tab
+    async fn clear_session(&self) -> Result<()>;
 }
 
 /// Repository for managing bookmarks
 #[async_trait]
 pub trait BookmarkRepository: Send + Sync {
-    async fn save(&self, bookmark: &Bookmark) -> Result<i64>;
+    /// Store a new bookmark, returning it as saved
+    async fn save(&self, bookmark: NewBookmark) -> Result<Bookmark>;
     async fn find_by_id(&self, id: i64) -> Result<Option<Bookmark>>;
     async fn find_all(&self) -> Result<Vec<Bookmark>>;
     async fn find_by_folder(&self, folder: &str) -> Result<Vec<Bookmark>>;
     async fn search(&self, query: &str) -> Result<Vec<Bookmark>>;
     async fn delete(&self, id: i64) -> Result<()>;
     async fn update(&self, bookmark: &Bookmark) -> Result<()>;
+    /// Put deleted bookmarks back as they were, ids and positions included
+    async fn restore(&self, bookmarks: &[Bookmark]) -> Result<()>;
+    /// Move a bookmark into `folder` at `index` among the bookmarks there,
+    /// last if `index` is past them, failing with
+    /// [`StorageError::NotFound`] if it does not exist. Implementations
+    /// must not let moves made at the same time interleave.
+    async fn move_to(&self, id: i64, folder: Option<&str>, index: usize) -> Result<()>;
 }
 
 /// Repository for managing browsing history
 #[async_trait]
 pub trait HistoryRepository: Send + Sync {
-    async fn add(&self, entry: &HistoryEntry) -> Result<i64>;
+    /// Record a visit, returning the URL's history entry after the visit
+    async fn add(&self, entry: NewHistoryEntry) -> Result<HistoryEntry>;
+    /// Record several visits at once; implementations should write them atomically
+    async fn add_batch(&self, entries: &[NewHistoryEntry]) -> Result<()> {
+        for entry in entries {
+            self.add(entry.clone()).await?;
+        }
+        Ok(())
+    }
     async fn find_by_url(&self, url: &ValidatedUrl) -> Result<Option<HistoryEntry>>;
+    /// Whether each URL is in history, in order; implementations should
+    /// look them up together
+    async fn visited(&self, urls: &[ValidatedUrl]) -> Result<Vec<bool>> {
+        let mut visited = Vec::with_capacity(urls.len());
+        for url in urls {
+            visited.push(self.find_by_url(url).await?.is_some());
+        }
+        Ok(visited)
+    }
     async fn search(&self, query: &str, limit: i32) -> Result<Vec<HistoryEntry>>;
     async fn get_recent(&self, limit: i32) -> Result<Vec<HistoryEntry>>;
+    /// Visits recorded to the entries with these ids, oldest first.
+    /// Deleting an entry deletes its visits.
+    async fn visits(&self, history_ids: &[i64]) -> Result<Vec<Visit>>;
     async fn delete_by_url(&self, url: &ValidatedUrl) -> Result<()>;
+    async fn delete_by_id(&self, id: i64) -> Result<()>;
+    /// Entries last visited in `[from, to)`, newest first
+    async fn find_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<HistoryEntry>>;
+    /// Delete the visits made in `[from, to)`, returning what went. An
+    /// entry keeps its other visits, counted, and the last of them as its
+    /// last visit; one left without visits is deleted.
+    async fn delete_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<DeletedHistory>;
+    /// Put back what [`delete_range`](Self::delete_range) removed, deleted
+    /// entries with their ids. An entry still stored, or a URL visited
+    /// again since, keeps its row, with the restored visits added to it.
+    async fn restore(&self, deleted: &DeletedHistory) -> Result<()>;
     async fn clear_all(&self) -> Result<()>;
     async fn increment_visit_count(&self, url: &ValidatedUrl) -> Result<()>;
 }
+
+/// Repository for persisted user settings
+#[async_trait]
+pub trait SettingsRepository: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+    async fn set(&self, key: &str, value: &str) -> Result<()>;
+    async fn delete(&self, key: &str) -> Result<()>;
+    async fn get_all(&self) -> Result<Vec<(String, String)>>;
+}
+
+/// Repository for user stylesheet rules
+#[async_trait]
+pub trait UserStyleRepository: Send + Sync {
+    async fn save(&self, style: NewUserStyle) -> Result<UserStyle>;
+    /// Every rule, oldest first
+    async fn find_all(&self) -> Result<Vec<UserStyle>>;
+    async fn delete(&self, id: i64) -> Result<()>;
+}
+
+/// Repository for user scripts and the values they store
+#[async_trait]
+pub trait UserScriptRepository: Send + Sync {
+    async fn save(&self, script: NewUserScript) -> Result<UserScript>;
+    /// Every script, oldest first
+    async fn find_all(&self) -> Result<Vec<UserScript>>;
+    /// Store changes to a script's settings or last error
+    async fn update(&self, script: &UserScript) -> Result<()>;
+    /// Delete a script along with its stored values
+    async fn delete(&self, id: i64) -> Result<()>;
+    /// Values the script stored with `GM_setValue`, as JSON
+    async fn values(&self, script_id: i64) -> Result<Vec<(String, String)>>;
+    async fn set_value(&self, script_id: i64, key: &str, value: &str) -> Result<()>;
+}
+
+/// Repository for search engines and which of them is the default
+#[async_trait]
+pub trait SearchEngineRepository: Send + Sync {
+    async fn save(&self, engine: NewSearchEngine) -> Result<SearchEngine>;
+    /// Every engine, oldest first
+    async fn find_all(&self) -> Result<Vec<SearchEngine>>;
+    async fn delete(&self, id: i64) -> Result<()>;
+    /// Make one engine the default and no other
+    async fn set_default(&self, id: i64) -> Result<()>;
+}
+
+/// Repository for the record of files written to the downloads directory
+#[async_trait]
+pub trait DownloadRepository: Send + Sync {
+    async fn save(&self, download: NewDownload) -> Result<Download>;
+    /// Every download, newest first
+    async fn find_all(&self) -> Result<Vec<Download>>;
+    /// Forget a download; the file is left alone
+    async fn delete(&self, id: i64) -> Result<()>;
+    /// Put a forgotten download back on the list as it was
+    async fn restore(&self, download: &Download) -> Result<()>;
+}
+
+/// Repository for per-site settings, one entry per origin
+#[async_trait]
+pub trait SiteSettingsRepository: Send + Sync {
+    async fn find(&self, origin: &SiteOrigin) -> Result<Option<SiteSettings>>;
+    /// Every site with overrides, by origin
+    async fn find_all(&self) -> Result<Vec<SiteSettings>>;
+    /// Store a site's overrides, replacing any it had
+    async fn save(&self, settings: &SiteSettings) -> Result<()>;
+    async fn delete(&self, origin: &SiteOrigin) -> Result<()>;
+    async fn clear_all(&self) -> Result<()>;
+}
+
+/// Repository for the `localStorage` of each origin
+#[async_trait]
+pub trait WebStorageRepository: Send + Sync {
+    /// An origin's items, in the order first set
+    async fn load(&self, origin: &SiteOrigin) -> Result<Vec<(String, String)>>;
+    /// Store a change already checked against the origin's quota
+    async fn apply(&self, origin: &SiteOrigin, change: &StorageChange) -> Result<()>;
+    async fn clear_all(&self) -> Result<()>;
+}
+
+/// Repository for the certificate authorities the user added
+#[async_trait]
+pub trait CustomCaRepository: Send + Sync {
+    async fn save(&self, ca: NewCustomCa) -> Result<CustomCa>;
+    /// Every certificate authority, oldest first
+    async fn find_all(&self) -> Result<Vec<CustomCa>>;
+    async fn delete(&self, id: i64) -> Result<()>;
+}
+
+/// Repository for documents kept for offline browsing, one per URL
+#[async_trait]
+pub trait PageCacheRepository: Send + Sync {
+    /// Keep a page, replacing any earlier copy of the same URL
+    async fn store(&self, page: &CachedPage) -> Result<()>;
+    /// The copy of `url`, ignoring its fragment
+    async fn find(&self, url: &ValidatedUrl) -> Result<Option<CachedPage>>;
+    /// The pages kept from `origin`, newest first
+    async fn find_by_origin(&self, origin: &SiteOrigin) -> Result<Vec<CachedPageSummary>>;
+    async fn clear_all(&self) -> Result<()>;
+}
@@ -1,29 +1,163 @@
-use super::entities::SecurityContext;
-use super::value_objects::{ValidatedUrl, Certificate};
+use super::entities::{
+    CustomCa, NewBookmark, NewHistoryEntry, NewSearchEngine, ReleaseFile, ScriptOutcome, SecurityContext,
+    SiteSettings, UpdateManifest, UserScript, UserStyle,
+};
+use super::errors::{
+    CertificateImportError, DownloadError, ImportError, NetworkError, RenderError, SearchEngineError,
+    SecurityError, SiteMetadataError, UpdateError, UserStyleError,
+};
+use super::value_objects::{
+    AccessibleNode, BlockCategory, BlockedRequest, BrowserSource, Certificate, CertificateDetails, ConsoleLevel,
+    ConsoleMessage, CspViolation, DocumentFetch, FetchRequest, HttpVersion, InterceptDecision, MemoryUsage,
+    PaperSize, RobotsTxt, SitemapEntry, Subresource, TabId, TlsSession, UserStyleRule, ValidatedUrl,
+    WebSocketMessage,
+};
 use async_trait::async_trait;
-use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Service for handling network requests securely
 #[async_trait]
 pub trait NetworkService: Send + Sync {
-    async fn fetch(&self, url: &ValidatedUrl) -> Result<Vec<u8>>;
-    async fn verify_certificate(&self, url: &ValidatedUrl) -> Result<Certificate>;
-    async fn check_security(&self, url: &ValidatedUrl) -> Result<SecurityContext>;
+    async fn fetch(&self, url: &ValidatedUrl) -> Result<Vec<u8>, NetworkError>;
+    async fn verify_certificate(&self, url: &ValidatedUrl) -> Result<Certificate, NetworkError>;
+    async fn check_security(&self, url: &ValidatedUrl) -> Result<SecurityContext, NetworkError>;
+    /// Open a WebSocket to `url` for `page`, or for the browser itself
+    /// without one. Plain `ws:` is only allowed for pages served over http.
+    async fn open_websocket(
+        &self,
+        url: &ValidatedUrl,
+        page: Option<&ValidatedUrl>,
+    ) -> Result<Arc<dyn WebSocketConnection>, NetworkError>;
+}
+
+/// Looks at the TLS connection a server negotiates
+#[async_trait]
+pub trait TlsInspector: Send + Sync {
+    /// Connect to the `https:` URL's server and describe the session
+    async fn tls_session(&self, url: &ValidatedUrl) -> Result<TlsSession, NetworkError>;
+}
+
+/// An open WebSocket. Pings are answered and sent on its own; dropping the
+/// last reference closes the connection at once.
+#[async_trait]
+pub trait WebSocketConnection: Send + Sync {
+    async fn send(&self, message: WebSocketMessage) -> Result<(), NetworkError>;
+    /// The next message from the server, or `None` once the connection has closed
+    async fn receive(&self) -> Option<WebSocketMessage>;
+    /// Close with `code` and `reason`, waiting for the server to agree
+    async fn close(&self, code: u16, reason: &str) -> Result<(), NetworkError>;
 }
 
 /// Service for rendering web content
 #[async_trait]
 pub trait RenderingEngine: Send + Sync {
-    async fn load_url(&self, url: &ValidatedUrl) -> Result<()>;
-    async fn get_title(&self) -> Result<String>;
-    async fn execute_javascript(&self, script: &str) -> Result<String>;
-    async fn take_screenshot(&self) -> Result<Vec<u8>>;
+    async fn load_url(&self, url: &ValidatedUrl) -> Result<(), RenderError>;
+    async fn get_title(&self) -> Result<String, RenderError>;
+    async fn execute_javascript(&self, script: &str) -> Result<String, RenderError>;
+    async fn take_screenshot(&self) -> Result<Vec<u8>, RenderError>;
+    /// Text of the current document as laid out
+    async fn get_text(&self) -> Result<String, RenderError> {
+        Ok(String::new())
+    }
+    /// HTML of the current document as fetched
+    async fn get_source(&self) -> Result<String, RenderError> {
+        Ok(String::new())
+    }
+    /// Lay the current document out again, e.g. after user styles changed
+    async fn restyle(&self) -> Result<(), RenderError> {
+        Ok(())
+    }
+    /// Drop the laid-out document while the tab is suspended, keeping what
+    /// `restyle` needs to lay it out again
+    async fn suspend(&self) -> Result<(), RenderError> {
+        Ok(())
+    }
+    /// Accessible tree of the current document
+    async fn accessibility_tree(&self) -> Result<AccessibleNode, RenderError> {
+        Ok(AccessibleNode::new("document"))
+    }
+    /// OpenSearch description the current document links to, if any
+    fn search_description(&self) -> Option<ValidatedUrl> {
+        None
+    }
+    /// Show pages for a private tab, which never looks at history
+    fn set_private(&self, _private: bool) {}
+    /// Overrides the next loads take in place of the global settings, for
+    /// the site about to be loaded; `None` for none
+    fn set_site_settings(&self, _settings: Option<SiteSettings>) {}
+    /// Requests the current document made that were blocked, oldest first
+    fn blocked_requests(&self) -> Vec<BlockedRequest> {
+        Vec::new()
+    }
+    /// Loads the current document made that its content security policy
+    /// did not allow, oldest first
+    fn csp_violations(&self) -> Vec<CspViolation> {
+        Vec::new()
+    }
+    /// Protocol the current document came over, if fetched from the network
+    fn http_version(&self) -> Option<HttpVersion> {
+        None
+    }
+    /// Certificate the current document's server presented, if it came
+    /// over HTTPS
+    fn certificate(&self) -> Option<Certificate> {
+        None
+    }
+    /// How the current document was fetched, if from the network
+    fn document_fetch(&self) -> Option<DocumentFetch> {
+        None
+    }
+    /// Run page timers no more often than `min_interval`, or not at all for
+    /// `None` while the tab is suspended
+    fn throttle_timers(&self, _min_interval: Option<Duration>) {}
+    /// What the tab's scripts and the browser on their behalf logged, oldest first
+    fn console_messages(&self) -> Vec<ConsoleMessage> {
+        Vec::new()
+    }
+    /// Add a message to the tab's console
+    fn log_to_console(&self, _level: ConsoleLevel, _message: &str, _source: Option<&str>) {}
+    fn clear_console(&self) {}
+    /// Keep the console across navigations instead of clearing it on each
+    fn set_preserve_console(&self, _preserve: bool) {}
+    /// What the tab holds for its current document
+    fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::default()
+    }
+    /// Drop what `restyle` can rebuild, like `suspend` does; returns the
+    /// bytes freed
+    fn trim_memory(&self) -> usize {
+        0
+    }
+}
+
+/// Sees every request the network client makes, like a browser extension.
+///
+/// Hooks are synchronous and run on the fetch path, so they should only
+/// consult data prepared ahead of time.
+pub trait RequestInterceptor: Send + Sync {
+    /// Shown in logs and in errors for requests this interceptor blocks
+    fn name(&self) -> &str;
+    /// Called before each request is sent, including each redirect hop
+    fn on_before_request(&self, request: &FetchRequest) -> InterceptDecision;
+    /// Called once the response status and headers are in, before the body is read
+    fn on_headers_received(
+        &self,
+        _request: &FetchRequest,
+        _status: u16,
+        _headers: &[(String, String)],
+    ) -> InterceptDecision {
+        InterceptDecision::Allow
+    }
 }
 
 /// Service for content security policy enforcement
 pub trait SecurityService: Send + Sync {
     /// Validate if URL is safe to navigate to
-    fn validate_url(&self, url: &str) -> Result<ValidatedUrl>;
+    fn validate_url(&self, url: &str) -> Result<ValidatedUrl, SecurityError>;
 
     /// Check if URL should be blocked (malware, phishing, etc.)
     fn is_blocked(&self, url: &ValidatedUrl) -> bool;
@@ -33,12 +167,188 @@ pub trait SecurityService: Send + Sync {
 
     /// Check if mixed content should be allowed
     fn allow_mixed_content(&self, url: &ValidatedUrl) -> bool;
+
+    /// Whether `url` is handed to another program, such as a mail client
+    /// for `mailto:`, instead of being loaded
+    fn is_external(&self, _url: &ValidatedUrl) -> bool {
+        false
+    }
 }
 
 /// Service for managing content blockers (ads, trackers)
 #[async_trait]
 pub trait ContentBlockerService: Send + Sync {
     async fn should_block(&self, url: &ValidatedUrl) -> bool;
-    async fn update_blocklists(&self) -> Result<()>;
+    async fn update_blocklists(&self) -> Result<(), NetworkError>;
+    /// Why requests to `url` are blocked, if they are
+    fn category(&self, url: &ValidatedUrl) -> Option<BlockCategory>;
+    /// Requests blocked this session across all tabs
     fn get_blocked_count(&self) -> usize;
 }
+
+/// Fetches search suggestions for partly typed text from a search engine
+#[async_trait]
+pub trait SuggestionProvider: Send + Sync {
+    async fn suggest(&self, query: &str) -> Result<Vec<String>, NetworkError>;
+}
+
+/// Fetches the OpenSearch descriptions pages offer their search engine in
+#[async_trait]
+pub trait OpenSearchFetcher: Send + Sync {
+    async fn fetch(&self, description: &ValidatedUrl) -> Result<NewSearchEngine, SearchEngineError>;
+}
+
+/// Fetches the robots.txt rules and sitemaps sites publish
+#[async_trait]
+pub trait SiteMetadataFetcher: Send + Sync {
+    /// Rules of the site serving `origin`; a site without a robots.txt
+    /// allows everything
+    async fn fetch_robots(&self, origin: &ValidatedUrl) -> Result<RobotsTxt, SiteMetadataError>;
+    /// Pages the sitemap at `url` lists, including those of the sitemaps
+    /// a sitemap index lists
+    async fn fetch_sitemap(&self, url: &ValidatedUrl) -> Result<Vec<SitemapEntry>, SiteMetadataError>;
+}
+
+/// Fetches the manifest describing the latest release, and the release
+/// itself. Nothing is installed.
+#[async_trait]
+pub trait UpdateSource: Send + Sync {
+    async fn manifest(&self, url: &ValidatedUrl) -> Result<UpdateManifest, UpdateError>;
+    async fn download(&self, url: &ValidatedUrl) -> Result<ReleaseFile, UpdateError>;
+}
+
+/// Reads bookmarks and history out of another browser's profile directory
+#[async_trait]
+pub trait BrowserProfileReader: Send + Sync {
+    /// The browser's default profile on this machine, if one exists
+    fn default_profile(&self, source: BrowserSource) -> Option<PathBuf>;
+    async fn read_bookmarks(&self, source: BrowserSource, profile: &Path) -> Result<Vec<NewBookmark>, ImportError>;
+    async fn read_history(&self, source: BrowserSource, profile: &Path) -> Result<Vec<NewHistoryEntry>, ImportError>;
+}
+
+/// User stylesheet rules, consulted when pages are laid out
+pub trait UserStyleService: Send + Sync {
+    /// Replace the rules in effect
+    fn set_styles(&self, styles: Vec<UserStyle>);
+    /// Rules for a page on `host`, least specific pattern first so later ones win
+    fn rules_for(&self, host: Option<&str>) -> Vec<UserStyleRule>;
+    /// Check that a rule can be applied, e.g. that its selector is supported
+    fn validate(&self, rule: &UserStyleRule) -> Result<(), UserStyleError>;
+}
+
+/// User scripts, run in a page's JavaScript context once it has loaded
+pub trait UserScriptService: Send + Sync {
+    /// Replace the scripts in effect
+    fn set_scripts(&self, scripts: Vec<UserScript>);
+    /// Enabled scripts for a page, in the order they should run.
+    ///
+    /// Internal pages and private tabs only get scripts that allow them.
+    fn scripts_for(&self, url: &ValidatedUrl, private: bool) -> Vec<UserScript>;
+    /// JavaScript running `script` with the GM API over its stored `values`
+    fn prepare(&self, script: &UserScript, values: &[(String, String)]) -> String;
+    /// Read what a prepared script returned from the engine
+    fn outcome(&self, output: &str) -> ScriptOutcome;
+}
+
+/// The roots certificates are checked against: the built-in ones plus
+/// the certificate authorities the user added
+pub trait TrustStore: Send + Sync {
+    /// Read the single certificate in `pem`, saying precisely what is wrong
+    /// with anything else
+    fn inspect(&self, pem: &str) -> Result<CertificateDetails, CertificateImportError>;
+    /// Trust exactly `roots` besides the built-in ones, from the next
+    /// connection on
+    fn set_custom_roots(&self, roots: &[CustomCa]) -> Result<(), NetworkError>;
+}
+
+/// Loads the images, stylesheets and scripts pages use
+#[async_trait]
+pub trait SubresourceLoader: Send + Sync {
+    /// Load `url` for the page at `page`, which decides whose cookies
+    /// go with it
+    async fn load(&self, url: &ValidatedUrl, page: &ValidatedUrl) -> Result<Subresource, NetworkError>;
+}
+
+/// Packs a page and its subresources into a single file
+#[async_trait]
+pub trait PageArchiver: Send + Sync {
+    /// Archive `html`, the page at `url`; subresources that fail to load are
+    /// left out
+    async fn archive(&self, url: &ValidatedUrl, title: &str, html: &str) -> Vec<u8>;
+}
+
+/// Lays pages out on paper
+#[async_trait]
+pub trait PagePrinter: Send + Sync {
+    /// A PDF of `html`, the page at `url`, paginated for `paper`
+    async fn print(&self, url: &ValidatedUrl, title: &str, html: &str, paper: PaperSize) -> Vec<u8>;
+}
+
+/// Writes files into the downloads directory
+#[async_trait]
+pub trait DownloadWriter: Send + Sync {
+    /// Where files go unless the user picked a directory
+    fn default_directory(&self) -> PathBuf;
+
+    /// Write `contents` to a new file called `file_name` in `directory`,
+    /// creating the directory if needed. A taken name gets " (1)", " (2)"
+    /// and so on before its extension; existing files are never replaced.
+    /// Setting `cancelled` stops the write, which leaves nothing behind and
+    /// fails with [`DownloadError::Cancelled`].
+    async fn write_new(
+        &self,
+        directory: &Path,
+        file_name: &str,
+        contents: &[u8],
+        cancelled: Arc<AtomicBool>,
+    ) -> Result<PathBuf, DownloadError>;
+
+    /// Delete a file written before; one that is already gone is no error
+    async fn delete(&self, path: &Path) -> Result<(), DownloadError>;
+
+    /// Stop writing, e.g. on shutdown: writes under way keep what they
+    /// wrote to resume later and fail with [`DownloadError::Paused`], as do
+    /// writes started afterwards. Returns once none is left under way.
+    async fn pause(&self) {}
+}
+
+/// Opens URLs the browser does not load itself, such as `mailto:` links,
+/// with the program the system has for their scheme
+pub trait ExternalLauncher: Send + Sync {
+    /// Start the handler for `url` without waiting for it
+    fn launch(&self, url: &ValidatedUrl) -> std::io::Result<()>;
+}
+
+/// A cache or store accounted on navigator://memory
+pub trait MemoryReporter: Send + Sync {
+    fn name(&self) -> &str;
+    /// The tab it belongs to; `None` for what tabs share
+    fn tab(&self) -> Option<TabId> {
+        None
+    }
+    fn usage(&self) -> MemoryUsage;
+    /// When it was last read from; `None` if never
+    fn last_used(&self) -> Option<Instant> {
+        None
+    }
+    /// Let go of entries until at most `target` bytes are held, if it can;
+    /// returns the bytes freed
+    fn trim(&self, _target: usize) -> usize {
+        0
+    }
+}
+
+/// Tells the time, so what stamps records with it can be run at a fixed one
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system's wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
@@ -0,0 +1,361 @@
+use super::layout::{attribute, element_name, heading_level, HIDDEN_ELEMENTS};
+use crate::domain::AccessibleNode;
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Elements whose `header` and `footer` are not page landmarks
+pub const SECTIONING_ELEMENTS: &[&str] = &["article", "aside", "main", "nav", "section"];
+
+/// Roles that take their name from the text inside when not labelled
+const NAMED_FROM_CONTENT: &[&str] = &[
+    "button", "cell", "columnheader", "heading", "link", "listitem", "menuitem", "option", "row", "rowheader",
+    "tab",
+];
+
+/// Whether pages are laid out for screen readers, shared by every tab
+#[derive(Debug, Clone, Default)]
+pub struct AccessibilityMode {
+    enabled: Arc<AtomicBool>,
+}
+
+impl AccessibilityMode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Takes effect as pages are next laid out
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// ARIA role of an element: its `role` attribute, or the one its tag
+/// implies. `None` for elements that only group their children.
+/// `sectioned` says whether the element is inside sectioning content.
+pub fn element_role(handle: &Handle, sectioned: bool) -> Option<String> {
+    let tag = element_name(handle)?;
+    if let Some(role) = attribute(handle, "role") {
+        if let Some(role) = role.split_whitespace().next().map(str::to_ascii_lowercase) {
+            return Some(role).filter(|role| role != "none" && role != "presentation");
+        }
+    }
+
+    let labelled = || attribute(handle, "aria-label").is_some_and(|label| !label.trim().is_empty());
+    let role = match tag.as_str() {
+        "nav" => "navigation",
+        "main" => "main",
+        "aside" => "complementary",
+        "header" if !sectioned => "banner",
+        "footer" if !sectioned => "contentinfo",
+        "search" => "search",
+        "section" if labelled() => "region",
+        "form" if labelled() => "form",
+        "article" => "article",
+        "ul" | "ol" | "menu" => "list",
+        "li" => "listitem",
+        "a" if attribute(handle, "href").is_some() => "link",
+        // Empty alt text marks a decorative image
+        "img" if attribute(handle, "alt").is_some_and(|alt| alt.trim().is_empty()) => return None,
+        "img" => "img",
+        "button" => "button",
+        "table" => "table",
+        "tr" => "row",
+        "td" => "cell",
+        "th" => "columnheader",
+        "p" => "paragraph",
+        "blockquote" => "blockquote",
+        "textarea" => "textbox",
+        "select" => "combobox",
+        "input" => match attribute(handle, "type").unwrap_or_default().to_ascii_lowercase().as_str() {
+            "hidden" => return None,
+            "checkbox" => "checkbox",
+            "radio" => "radio",
+            "button" | "submit" | "reset" => "button",
+            _ => "textbox",
+        },
+        _ if heading_level(&tag).is_some() => "heading",
+        _ => return None,
+    };
+    Some(role.to_string())
+}
+
+/// Level of a heading: its `aria-level`, else the level of its tag
+pub fn element_level(handle: &Handle, role: &str) -> Option<u8> {
+    if role != "heading" {
+        return None;
+    }
+    let aria_level = attribute(handle, "aria-level").and_then(|level| level.trim().parse::<u8>().ok());
+    let tag_level = element_name(handle).and_then(|tag| heading_level(&tag));
+    // A `role="heading"` without a level is a level 2 heading
+    Some(aria_level.filter(|level| *level > 0).or(tag_level).unwrap_or(2))
+}
+
+/// Accessible name of an element with `role`: its `aria-label`, an image's
+/// alt text, or the text inside for roles named from content
+pub fn accessible_name(handle: &Handle, role: &str) -> String {
+    let label = |name: &str| attribute(handle, name).map(|value| collapse_whitespace(&value)).unwrap_or_default();
+    let name = label("aria-label");
+    if !name.is_empty() {
+        return name;
+    }
+    let name = match role {
+        "img" => label("alt"),
+        _ if NAMED_FROM_CONTENT.contains(&role) => {
+            let mut text = String::new();
+            text_content(handle, &mut text);
+            collapse_whitespace(&text)
+        }
+        _ => String::new(),
+    };
+    if name.is_empty() {
+        label("title")
+    } else {
+        name
+    }
+}
+
+/// What a landmark is announced as
+pub fn landmark_name(role: &str) -> Option<&'static str> {
+    match role {
+        "banner" => Some("Banner"),
+        "navigation" => Some("Navigation"),
+        "main" => Some("Main"),
+        "complementary" => Some("Complementary"),
+        "contentinfo" => Some("Content information"),
+        "search" => Some("Search"),
+        "region" => Some("Region"),
+        "form" => Some("Form"),
+        _ => None,
+    }
+}
+
+/// Elements never displayed, or hidden from assistive technology
+pub fn is_hidden(handle: &Handle) -> bool {
+    let Some(tag) = element_name(handle) else {
+        return false;
+    };
+    HIDDEN_ELEMENTS.contains(&tag.as_str())
+        || attribute(handle, "hidden").is_some()
+        || attribute(handle, "aria-hidden").is_some_and(|hidden| hidden.trim().eq_ignore_ascii_case("true"))
+}
+
+/// Accessible tree of a document titled `title`
+pub fn build_accessibility_tree(dom: &RcDom, title: &str) -> AccessibleNode {
+    let mut root = AccessibleNode::new("document").with_name(title);
+    collect(&dom.document, false, &mut root.children);
+    finish(&mut root.children);
+    root
+}
+
+/// Add the nodes for `handle` to `nodes`; elements without a role add their
+/// children instead
+fn collect(handle: &Handle, sectioned: bool, nodes: &mut Vec<AccessibleNode>) {
+    match &handle.data {
+        NodeData::Document => {
+            for child in handle.children.borrow().iter() {
+                collect(child, sectioned, nodes);
+            }
+        }
+        NodeData::Text { contents } => push_text(nodes, &contents.borrow()),
+        NodeData::Element { .. } => {
+            if is_hidden(handle) {
+                return;
+            }
+            let inner_sectioned = sectioned
+                || element_name(handle).is_some_and(|tag| SECTIONING_ELEMENTS.contains(&tag.as_str()));
+            let Some(role) = element_role(handle, sectioned) else {
+                for child in handle.children.borrow().iter() {
+                    collect(child, inner_sectioned, nodes);
+                }
+                return;
+            };
+            let mut node = AccessibleNode::new(role.as_str()).with_name(accessible_name(handle, &role));
+            node.level = element_level(handle, &role);
+            for child in handle.children.borrow().iter() {
+                collect(child, inner_sectioned, &mut node.children);
+            }
+            nodes.push(node);
+        }
+        _ => {}
+    }
+}
+
+/// Add text, joining it to text just before so inline markup doesn't split it
+fn push_text(nodes: &mut Vec<AccessibleNode>, text: &str) {
+    let mut collapsed = collapse_whitespace(text);
+    if collapsed.is_empty() {
+        if text.is_empty() {
+            return;
+        }
+        collapsed = " ".to_string();
+    } else {
+        // Keep the spaces at either end so joined runs stay separate words
+        if text.starts_with(char::is_whitespace) {
+            collapsed.insert(0, ' ');
+        }
+        if text.ends_with(char::is_whitespace) {
+            collapsed.push(' ');
+        }
+    }
+    match nodes.last_mut().filter(|node| node.role == "text") {
+        Some(last) => {
+            if last.name.ends_with(' ') && collapsed.starts_with(' ') {
+                collapsed.remove(0);
+            }
+            last.name.push_str(&collapsed);
+        }
+        None => nodes.push(AccessibleNode::new("text").with_name(collapsed)),
+    }
+}
+
+/// Trim text nodes, dropping those left empty
+fn finish(nodes: &mut Vec<AccessibleNode>) {
+    for node in nodes.iter_mut() {
+        if node.role == "text" {
+            node.name = node.name.trim().to_string();
+        }
+        finish(&mut node.children);
+    }
+    nodes.retain(|node| node.role != "text" || !node.name.is_empty());
+}
+
+/// Text of the visible descendants, with image alt text standing in for images
+fn text_content(handle: &Handle, text: &mut String) {
+    for child in handle.children.borrow().iter() {
+        match &child.data {
+            NodeData::Text { contents } => text.push_str(&contents.borrow()),
+            NodeData::Element { .. } if !is_hidden(child) => {
+                if element_name(child).as_deref() == Some("img") {
+                    text.push(' ');
+                    text.push_str(&attribute(child, "alt").unwrap_or_default());
+                    text.push(' ');
+                } else {
+                    text_content(child, text);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use html5ever::parse_document;
+    use html5ever::tendril::TendrilSink;
+
+    fn tree(html: &str) -> AccessibleNode {
+        let dom = parse_document(RcDom::default(), Default::default())
+            .from_utf8()
+            .read_from(&mut html.as_bytes())
+            .unwrap();
+        build_accessibility_tree(&dom, "Fixture")
+    }
+
+    fn node(role: &str, name: &str) -> AccessibleNode {
+        AccessibleNode::new(role).with_name(name)
+    }
+
+    #[test]
+    fn test_landmarks_and_labels() {
+        let root = tree(
+            "<header><a href=/>Home</a></header>\
+             <nav aria-label='Primary'><ul><li><a href=/a>A</a></li><li>B</li></ul></nav>\
+             <main><article><header>Byline</header><h2>Story</h2><p>Some <b>bold</b> text</p></article></main>\
+             <aside>Related</aside><section>Plain</section><section aria-label=' Sidebar '>Labelled</section>\
+             <footer>Fine print</footer>",
+        );
+        let roles: Vec<&str> = root.children.iter().map(|node| node.role.as_str()).collect();
+        assert_eq!(
+            roles,
+            ["banner", "navigation", "main", "complementary", "text", "region", "contentinfo"]
+        );
+        assert_eq!(root.name, "Fixture");
+
+        let nav = &root.children[1];
+        assert_eq!(nav.name, "Primary");
+        let list = &nav.children[0];
+        assert_eq!(list.role, "list");
+        assert_eq!(list.children[0].name, "A");
+        assert_eq!(list.children[0].children[0], {
+            let mut link = node("link", "A");
+            link.children.push(node("text", "A"));
+            link
+        });
+
+        // A header inside an article is no banner
+        let article = &root.children[2].children[0];
+        assert_eq!(article.role, "article");
+        assert_eq!(article.children[0], node("text", "Byline"));
+        let mut heading = node("heading", "Story");
+        heading.level = Some(2);
+        heading.children.push(node("text", "Story"));
+        assert_eq!(article.children[1], heading);
+        assert_eq!(article.children[2].children, vec![node("text", "Some bold text")]);
+
+        assert_eq!(root.children[4], node("text", "Plain"));
+        assert_eq!(root.children[5].name, "Sidebar");
+    }
+
+    #[test]
+    fn test_aria_roles_override_tags() {
+        let root = tree(
+            "<div role='navigation' aria-label='Pages'><span role=link>Next</span></div>\
+             <div role='heading' aria-level='4'>Deep</div><div role=heading>Default</div>\
+             <table role=presentation><tr><td>Layout</td></tr></table>\
+             <p aria-hidden=true>Hidden</p><p hidden>Also hidden</p>\
+             <img src=a.png alt='A cat'><img src=spacer.gif alt=''>\
+             <a href=/home aria-label='Go home'><img src=home.png alt=Home></a>",
+        );
+        let nav = &root.children[0];
+        assert_eq!((nav.role.as_str(), nav.name.as_str()), ("navigation", "Pages"));
+        assert_eq!(nav.children[0].role, "link");
+        assert_eq!(nav.children[0].name, "Next");
+
+        let levels: Vec<_> = root
+            .descendants()
+            .into_iter()
+            .filter(|node| node.role == "heading")
+            .map(|node| (node.name.as_str(), node.level))
+            .collect();
+        assert_eq!(levels, [("Deep", Some(4)), ("Default", Some(2))]);
+
+        // Presentational tables leave their rows and cells in place
+        assert!(root.descendants().iter().all(|node| node.role != "table"));
+        assert!(root.descendants().iter().any(|node| node.role == "row"));
+        assert!(root.descendants().iter().all(|node| !node.name.contains("idden")));
+
+        let images: Vec<_> = root.descendants().into_iter().filter(|node| node.role == "img").collect();
+        assert_eq!(images, [&node("img", "A cat"), &node("img", "Home")]);
+        assert_eq!(root.children.last().unwrap().name, "Go home");
+    }
+
+    #[test]
+    fn test_tree_serializes_without_empty_fields() {
+        let root = tree("<h1>Title</h1>");
+        let json = serde_json::to_value(&root).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "role": "document",
+                "name": "Fixture",
+                "children": [{
+                    "role": "heading",
+                    "name": "Title",
+                    "level": 1,
+                    "children": [{ "role": "text", "name": "Title" }]
+                }]
+            })
+        );
+        assert_eq!(serde_json::from_value::<AccessibleNode>(json).unwrap(), root);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::domain::{SiteOrigin, ValidatedUrl};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long an advertisement lasts when it gives no `ma`
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Origins that advertised HTTP/3 on their own host and port with
+/// `Alt-Svc`, so later requests to them can go over QUIC
+#[derive(Debug, Default)]
+pub struct AltSvcCache {
+    /// When each origin's advertisement runs out
+    http3: Mutex<HashMap<SiteOrigin, Instant>>,
+}
+
+impl AltSvcCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note the `Alt-Svc` header of a response from `url`. Only https
+    /// origins count, and a header that clears the alternatives or no
+    /// longer lists HTTP/3 forgets the origin.
+    pub fn record(&self, url: &ValidatedUrl, header: &str) {
+        if !url.is_secure() {
+            return;
+        }
+        let Some(origin) = SiteOrigin::of(url) else {
+            return;
+        };
+        let max_age = http3_max_age(url, header);
+        if let Ok(mut http3) = self.http3.lock() {
+            match max_age {
+                Some(max_age) if !max_age.is_zero() => {
+                    if http3.insert(origin.clone(), Instant::now() + max_age).is_none() {
+                        tracing::debug!("{} advertises HTTP/3", origin);
+                    }
+                }
+                _ => {
+                    http3.remove(&origin);
+                }
+            }
+        }
+    }
+
+    /// Whether requests for `url` may try HTTP/3
+    pub fn supports_http3(&self, url: &ValidatedUrl) -> bool {
+        let Some(origin) = SiteOrigin::of(url) else {
+            return false;
+        };
+        let Ok(mut http3) = self.http3.lock() else {
+            return false;
+        };
+        match http3.get(&origin) {
+            Some(expires) if *expires > Instant::now() => true,
+            Some(_) => {
+                http3.remove(&origin);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Stop trying HTTP/3 for `url`'s origin, as after it failed
+    pub fn forget(&self, url: &ValidatedUrl) {
+        if let (Some(origin), Ok(mut http3)) = (SiteOrigin::of(url), self.http3.lock()) {
+            http3.remove(&origin);
+        }
+    }
+}
+
+/// How long `header`, from a response for `url`, says HTTP/3 is served on
+/// the same host and port; `None` if it is not
+fn http3_max_age(url: &ValidatedUrl, header: &str) -> Option<Duration> {
+    let host = url.host_str()?;
+    let port = url.port_or_default()?;
+    header.split(',').find_map(|alternative| {
+        let mut parts = alternative.split(';').map(str::trim);
+        let (protocol, authority) = parts.next()?.split_once('=')?;
+        if protocol.trim() != "h3" {
+            return None;
+        }
+        let authority = authority.trim().trim_matches('"');
+        let (alt_host, alt_port) = authority.rsplit_once(':')?;
+        if (!alt_host.is_empty() && !alt_host.eq_ignore_ascii_case(host)) || alt_port.parse() != Ok(port) {
+            return None;
+        }
+        let max_age = parts
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("ma"))
+            .and_then(|(_, value)| value.trim().trim_matches('"').parse().ok())
+            .map(Duration::from_secs);
+        Some(max_age.unwrap_or(DEFAULT_MAX_AGE))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(input: &str) -> ValidatedUrl {
+        ValidatedUrl::parse(input).unwrap()
+    }
+
+    #[test]
+    fn test_alt_svc_advertisements() {
+        let cache = AltSvcCache::new();
+        let page = url("https://example.com/page");
+        cache.record(&page, "h2=\":443\", h3=\":443\"; ma=3600");
+        assert!(cache.supports_http3(&url("https://example.com/style.css")));
+        assert!(!cache.supports_http3(&url("https://other.example/")));
+
+        // Alternatives elsewhere are not followed, and clearing forgets
+        cache.record(&page, "clear");
+        assert!(!cache.supports_http3(&page));
+        cache.record(&page, "h3=\"alt.example.com:443\"");
+        assert!(!cache.supports_http3(&page));
+        cache.record(&page, "h3=\"example.com:8443\"");
+        assert!(!cache.supports_http3(&page));
+        cache.record(&page, "h3=\"example.com:443\"");
+        assert!(cache.supports_http3(&page));
+        cache.record(&page, "h3=\":443\"; ma=0");
+        assert!(!cache.supports_http3(&page));
+
+        cache.record(&url("http://example.com/"), "h3=\":80\"");
+        assert!(!cache.supports_http3(&url("http://example.com/")));
+        cache.record(&page, "h3=\":443\"");
+        cache.forget(&page);
+        assert!(!cache.supports_http3(&page));
+    }
+}
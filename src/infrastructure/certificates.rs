@@ -0,0 +1,538 @@
+use crate::domain::{
+    Certificate, CertificateDetails, CertificateImportError, NetworkError, OcspStaple, RevocationStatus,
+};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use ring::digest;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, SignatureScheme};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+use x509_parser::der_parser::asn1_rs::BitString;
+use x509_parser::pem::Pem;
+use x509_parser::prelude::{FromDer, ParsedExtension, X509Certificate};
+use x509_parser::x509::AlgorithmIdentifier;
+
+/// How far ahead of ours a responder's clock may run
+const CLOCK_SKEW: chrono::Duration = chrono::Duration::minutes(5);
+
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OBJECT_IDENTIFIER: u8 = 0x06;
+const ENUMERATED: u8 = 0x0a;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+/// Context-specific tags: `[0]`, `[1]`, `[2]`, constructed or not
+const EXPLICIT_0: u8 = 0xa0;
+const EXPLICIT_1: u8 = 0xa1;
+const EXPLICIT_2: u8 = 0xa2;
+const IMPLICIT_0: u8 = 0x80;
+const IMPLICIT_2: u8 = 0x82;
+
+/// id-pkix-ocsp-basic, 1.3.6.1.5.5.7.48.1.1
+const OCSP_BASIC: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+/// id-sha1, 1.3.14.3.2.26
+const SHA1: &[u8] = &[0x2b, 0x0e, 0x03, 0x02, 0x1a];
+/// id-sha256, 2.16.840.1.101.3.4.2.1
+const SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+
+/// Why a stapled OCSP response was not believed
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OcspError {
+    #[error("The OCSP response is malformed")]
+    Malformed,
+    /// The responder could not answer; holds its response status
+    #[error("The OCSP responder returned error status {0}")]
+    Unsuccessful(u8),
+    #[error("The OCSP response is about another certificate")]
+    WrongCertificate,
+    #[error("The OCSP response is not signed by the certificate's issuer")]
+    BadSignature,
+    #[error("The OCSP response is out of date")]
+    OutOfDate,
+}
+
+/// One DER element, with its header (`raw`) and without (`content`)
+struct Element<'a> {
+    tag: u8,
+    raw: &'a [u8],
+    content: &'a [u8],
+}
+
+/// The element at the start of `input`, and what follows it
+fn read(input: &[u8]) -> Result<(Element<'_>, &[u8]), OcspError> {
+    let (&tag, rest) = input.split_first().ok_or(OcspError::Malformed)?;
+    let (&first, rest) = rest.split_first().ok_or(OcspError::Malformed)?;
+    let (length, rest) = if first < 0x80 {
+        (usize::from(first), rest)
+    } else {
+        let count = usize::from(first & 0x7f);
+        if count == 0 || count > 4 || rest.len() < count {
+            return Err(OcspError::Malformed);
+        }
+        let length = rest[..count].iter().fold(0, |length, &byte| length << 8 | usize::from(byte));
+        (length, &rest[count..])
+    };
+    if rest.len() < length {
+        return Err(OcspError::Malformed);
+    }
+    let header = input.len() - rest.len();
+    let element = Element {
+        tag,
+        raw: &input[..header + length],
+        content: &rest[..length],
+    };
+    Ok((element, &rest[length..]))
+}
+
+/// Like [`read`], but the element must have the tag `tag`
+fn expect(input: &[u8], tag: u8) -> Result<(Element<'_>, &[u8]), OcspError> {
+    let (element, rest) = read(input)?;
+    if element.tag != tag {
+        return Err(OcspError::Malformed);
+    }
+    Ok((element, rest))
+}
+
+/// Like [`expect`], but `None` if the next element has another tag
+fn optional(input: &[u8], tag: u8) -> Result<(Option<Element<'_>>, &[u8]), OcspError> {
+    match input.first() {
+        Some(&next) if next == tag => expect(input, tag).map(|(element, rest)| (Some(element), rest)),
+        _ => Ok((None, input)),
+    }
+}
+
+fn generalized_time(element: &Element<'_>) -> Result<DateTime<Utc>, OcspError> {
+    let text = std::str::from_utf8(element.content).map_err(|_| OcspError::Malformed)?;
+    let time = NaiveDateTime::parse_from_str(text, "%Y%m%d%H%M%SZ")
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y%m%d%H%M%S%.fZ"))
+        .map_err(|_| OcspError::Malformed)?;
+    Ok(time.and_utc())
+}
+
+/// Check the DER-encoded OCSP response a server stapled for `leaf`, which
+/// `issuer` issued, at time `now`. The response must be signed by the
+/// issuer or by a responder the issuer delegated to, and be current.
+pub fn check_ocsp_response(
+    der: &[u8],
+    leaf: &X509Certificate<'_>,
+    issuer: &X509Certificate<'_>,
+    now: DateTime<Utc>,
+) -> Result<OcspStaple, OcspError> {
+    let (response, _) = expect(der, SEQUENCE)?;
+    let (status, rest) = expect(response.content, ENUMERATED)?;
+    match status.content {
+        [0] => {}
+        [status] => return Err(OcspError::Unsuccessful(*status)),
+        _ => return Err(OcspError::Malformed),
+    }
+    let (response_bytes, _) = expect(rest, EXPLICIT_0)?;
+    let (response_bytes, _) = expect(response_bytes.content, SEQUENCE)?;
+    let (kind, rest) = expect(response_bytes.content, OBJECT_IDENTIFIER)?;
+    if kind.content != OCSP_BASIC {
+        return Err(OcspError::Malformed);
+    }
+    let (basic, _) = expect(rest, OCTET_STRING)?;
+    let (basic, _) = expect(basic.content, SEQUENCE)?;
+    let (tbs, rest) = expect(basic.content, SEQUENCE)?;
+    let (algorithm, rest) = expect(rest, SEQUENCE)?;
+    let (signature, rest) = expect(rest, BIT_STRING)?;
+    let (certs, _) = optional(rest, EXPLICIT_0)?;
+
+    let mut responders = Vec::new();
+    if let Some(certs) = certs {
+        let (mut certs, _) = expect(certs.content, SEQUENCE)?;
+        while !certs.content.is_empty() {
+            let (cert, rest) = expect(certs.content, SEQUENCE)?;
+            let (_, cert) = X509Certificate::from_der(cert.raw).map_err(|_| OcspError::Malformed)?;
+            responders.push(cert);
+            certs.content = rest;
+        }
+    }
+    let (_, algorithm) = AlgorithmIdentifier::from_der(algorithm.raw).map_err(|_| OcspError::Malformed)?;
+    let (&unused_bits, signature) = signature.content.split_first().ok_or(OcspError::Malformed)?;
+    let signature = BitString::new(unused_bits, signature);
+    let signed_by = |signer: &X509Certificate<'_>| {
+        x509_parser::verify::verify_signature(signer.public_key(), &algorithm, &signature, tbs.raw).is_ok()
+    };
+    // A delegated responder is one the issuer certified for OCSP signing
+    let delegated = |responder: &X509Certificate<'_>| {
+        responder.issuer().as_raw() == issuer.subject().as_raw()
+            && responder.verify_signature(Some(issuer.public_key())).is_ok()
+            && matches!(responder.extended_key_usage(), Ok(Some(usage)) if usage.value.ocsp_signing)
+    };
+    if !signed_by(issuer) && !responders.iter().any(|responder| delegated(responder) && signed_by(responder)) {
+        return Err(OcspError::BadSignature);
+    }
+
+    let (_, fields) = optional(tbs.content, EXPLICIT_0)?;
+    let (responder_id, fields) = read(fields)?;
+    if responder_id.tag != EXPLICIT_1 && responder_id.tag != EXPLICIT_2 {
+        return Err(OcspError::Malformed);
+    }
+    let (_, fields) = expect(fields, GENERALIZED_TIME)?;
+    let (mut responses, _) = expect(fields, SEQUENCE)?;
+    while !responses.content.is_empty() {
+        let (single, rest) = expect(responses.content, SEQUENCE)?;
+        responses.content = rest;
+        let (cert_id, fields) = expect(single.content, SEQUENCE)?;
+        if !is_about(cert_id.content, leaf, issuer)? {
+            continue;
+        }
+
+        let (cert_status, fields) = read(fields)?;
+        let status = match cert_status.tag {
+            IMPLICIT_0 => RevocationStatus::Good,
+            EXPLICIT_1 => RevocationStatus::Revoked,
+            IMPLICIT_2 => RevocationStatus::Unknown,
+            _ => return Err(OcspError::Malformed),
+        };
+        let (this_update, fields) = expect(fields, GENERALIZED_TIME)?;
+        let this_update = generalized_time(&this_update)?;
+        let next_update = match optional(fields, EXPLICIT_0)? {
+            (Some(next_update), _) => Some(generalized_time(&expect(next_update.content, GENERALIZED_TIME)?.0)?),
+            (None, _) => None,
+        };
+        if this_update > now + CLOCK_SKEW || next_update.is_some_and(|next_update| next_update < now) {
+            return Err(OcspError::OutOfDate);
+        }
+        return Ok(OcspStaple {
+            status,
+            this_update,
+            next_update,
+        });
+    }
+    Err(OcspError::WrongCertificate)
+}
+
+/// Whether the `CertID` with contents `cert_id` names `leaf` from `issuer`
+fn is_about(cert_id: &[u8], leaf: &X509Certificate<'_>, issuer: &X509Certificate<'_>) -> Result<bool, OcspError> {
+    let (hash, rest) = expect(cert_id, SEQUENCE)?;
+    let (hash, _) = expect(hash.content, OBJECT_IDENTIFIER)?;
+    let (name_hash, rest) = expect(rest, OCTET_STRING)?;
+    let (key_hash, rest) = expect(rest, OCTET_STRING)?;
+    let (serial, _) = expect(rest, INTEGER)?;
+    let algorithm = match hash.content {
+        SHA1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+        SHA256 => &digest::SHA256,
+        _ => return Ok(false),
+    };
+    let issuer_key = &issuer.public_key().subject_public_key.data;
+    Ok(serial.content == leaf.raw_serial()
+        && name_hash.content == digest::digest(algorithm, issuer.subject().as_raw()).as_ref()
+        && key_hash.content == digest::digest(algorithm, issuer_key).as_ref())
+}
+
+/// Signed certificate timestamps embedded in `certificate`
+pub fn embedded_sct_count(certificate: &X509Certificate<'_>) -> usize {
+    certificate
+        .extensions()
+        .iter()
+        .find_map(|extension| match extension.parsed_extension() {
+            ParsedExtension::SCT(timestamps) => Some(timestamps.len()),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+/// `leaf` as shown in page info, once its chain has been verified
+pub fn describe_certificate(leaf: &X509Certificate<'_>, ocsp: Option<OcspStaple>) -> Certificate {
+    let time = |time: x509_parser::time::ASN1Time| {
+        DateTime::from_timestamp(time.timestamp(), 0).unwrap_or_default()
+    };
+    Certificate {
+        subject: leaf.subject().to_string(),
+        issuer: leaf.issuer().to_string(),
+        valid_from: time(leaf.validity().not_before),
+        valid_until: time(leaf.validity().not_after),
+        is_valid: true,
+        ocsp,
+        sct_count: embedded_sct_count(leaf),
+    }
+}
+
+/// SHA-256 of a DER-encoded certificate, as colon-separated hex
+pub fn fingerprint(der: &[u8]) -> String {
+    let digest = digest::digest(&digest::SHA256, der);
+    let bytes: Vec<String> = digest.as_ref().iter().map(|byte| format!("{:02X}", byte)).collect();
+    bytes.join(":")
+}
+
+/// Read the single certificate in `pem`, as a user adding a certificate
+/// authority gives it
+pub fn inspect_certificate(pem: &str) -> Result<CertificateDetails, CertificateImportError> {
+    let blocks = Pem::iter_from_buffer(pem.as_bytes())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CertificateImportError::Invalid(format!("unreadable PEM: {}", e)))?;
+    let certificates: Vec<&Pem> = blocks.iter().filter(|block| block.label == "CERTIFICATE").collect();
+    let block = match (certificates.as_slice(), blocks.first()) {
+        ([certificate], _) => certificate,
+        ([], None) => return Err(CertificateImportError::NotPem("no PEM block".to_string())),
+        ([], Some(other)) => return Err(CertificateImportError::NotPem(format!("a {} block", other.label))),
+        (several, _) => {
+            let found = format!("{} certificates; add them one at a time", several.len());
+            return Err(CertificateImportError::NotPem(found));
+        }
+    };
+    let der = &block.contents;
+    let (rest, certificate) =
+        X509Certificate::from_der(der).map_err(|e| CertificateImportError::Invalid(e.to_string()))?;
+    if !rest.is_empty() {
+        return Err(CertificateImportError::Invalid("data after the certificate".to_string()));
+    }
+
+    let encoded = BASE64.encode(der);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    let common_name = certificate
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|name| name.as_str().ok())
+        .map(str::to_string);
+    let not_after = certificate.validity().not_after.timestamp();
+    let details = CertificateDetails {
+        subject: certificate.subject().to_string(),
+        common_name,
+        fingerprint: fingerprint(der),
+        is_ca: certificate.is_ca(),
+        valid_until: DateTime::from_timestamp(not_after, 0).unwrap_or_default(),
+        pem,
+    };
+    Ok(details)
+}
+
+/// The certificate each host last presented, as checked
+#[derive(Debug, Default)]
+pub struct CertificateLog {
+    hosts: RwLock<HashMap<String, Certificate>>,
+}
+
+impl CertificateLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, host: &str, certificate: Certificate) {
+        if let Ok(mut hosts) = self.hosts.write() {
+            hosts.insert(host.to_ascii_lowercase(), certificate);
+        }
+    }
+
+    pub fn get(&self, host: &str) -> Option<Certificate> {
+        self.hosts.read().ok()?.get(&host.to_ascii_lowercase()).cloned()
+    }
+}
+
+/// Verifies server certificates as rustls would, then checks the OCSP
+/// response stapled to the handshake, refusing certificates it says are
+/// revoked, and logs what it found. A missing or unusable staple is only
+/// noted.
+#[derive(Debug)]
+pub struct CertificateVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    /// Trusted roots besides the built-in ones, which may have issued
+    /// certificates directly
+    extra_roots: Vec<CertificateDer<'static>>,
+    log: Arc<CertificateLog>,
+}
+
+impl CertificateVerifier {
+    pub fn new(extra_roots: &[CertificateDer<'static>], log: Arc<CertificateLog>) -> Result<Self, NetworkError> {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        for root in extra_roots {
+            roots.add(root.clone()).map_err(|e| NetworkError::Tls(e.to_string()))?;
+        }
+        let inner = WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider())
+            .build()
+            .map_err(|e| NetworkError::Tls(e.to_string()))?;
+        Ok(Self {
+            inner,
+            extra_roots: extra_roots.to_vec(),
+            log,
+        })
+    }
+
+    /// What the staple says of `leaf`, if it can be checked
+    fn check_staple(
+        &self,
+        host: &str,
+        leaf: &X509Certificate<'_>,
+        intermediates: &[CertificateDer<'_>],
+        staple: &[u8],
+        now: UnixTime,
+    ) -> Option<OcspStaple> {
+        if staple.is_empty() {
+            return None;
+        }
+        let issuer = intermediates
+            .iter()
+            .map(|der| der.as_ref())
+            .chain(self.extra_roots.iter().map(|der| der.as_ref()))
+            .filter_map(|der| X509Certificate::from_der(der).ok().map(|(_, cert)| cert))
+            .find(|cert| cert.subject().as_raw() == leaf.issuer().as_raw());
+        let Some(issuer) = issuer else {
+            tracing::debug!("Cannot check the OCSP response from {} without its issuer", host);
+            return None;
+        };
+        let now = DateTime::from_timestamp(now.as_secs() as i64, 0).unwrap_or_else(Utc::now);
+        match check_ocsp_response(staple, leaf, &issuer, now) {
+            Ok(staple) => Some(staple),
+            Err(e) => {
+                tracing::warn!("Ignoring the OCSP response stapled by {}: {}", host, e);
+                None
+            }
+        }
+    }
+}
+
+impl ServerCertVerifier for CertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        let Ok((_, leaf)) = X509Certificate::from_der(end_entity) else {
+            return Ok(verified);
+        };
+        let host = server_name.to_str();
+        let ocsp = self.check_staple(&host, &leaf, intermediates, ocsp_response, now);
+        let certificate = describe_certificate(&leaf, ocsp);
+        if certificate.is_revoked() {
+            tracing::warn!("The certificate of {} has been revoked", host);
+            return Err(rustls::Error::InvalidCertificate(CertificateError::Revoked));
+        }
+        self.log.record(&host, certificate);
+        Ok(verified)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// A TLS configuration trusting the built-in roots and `extra_roots`, whose
+/// certificate checks are logged in `log`
+pub fn tls_config(
+    extra_roots: &[CertificateDer<'static>],
+    log: Arc<CertificateLog>,
+) -> Result<rustls::ClientConfig, NetworkError> {
+    let verifier = CertificateVerifier::new(extra_roots, log)?;
+    let mut config = rustls::ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| NetworkError::Tls(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    // Clients take a configuration as it is, so it offers HTTP/2 itself
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/ocsp").join(name)).unwrap()
+    }
+
+    fn pem(name: &str) -> Vec<u8> {
+        let (_, pem) = x509_parser::pem::parse_x509_pem(&fixture(name)).unwrap();
+        pem.contents
+    }
+
+    /// The day after the fixtures' responses were produced
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-10-19T12:00:00Z").unwrap().to_utc()
+    }
+
+    fn check(response: &str, leaf: &str) -> Result<OcspStaple, OcspError> {
+        let (leaf, ca) = (pem(leaf), pem("ca.pem"));
+        let (_, leaf) = X509Certificate::from_der(&leaf).unwrap();
+        let (_, ca) = X509Certificate::from_der(&ca).unwrap();
+        check_ocsp_response(&fixture(response), &leaf, &ca, now())
+    }
+
+    #[test]
+    fn test_stapled_responses_give_each_status() {
+        let good = check("good.der", "localhost.pem").unwrap();
+        assert_eq!(good.status, RevocationStatus::Good);
+        assert_eq!(good.this_update.to_rfc3339(), "2026-10-18T11:38:05+00:00");
+        assert_eq!(good.next_update.unwrap().to_rfc3339(), "2126-09-24T11:38:05+00:00");
+        assert_eq!(check("revoked.der", "revoked.pem").unwrap().status, RevocationStatus::Revoked);
+        assert_eq!(check("unknown.der", "unknown.pem").unwrap().status, RevocationStatus::Unknown);
+    }
+
+    #[test]
+    fn test_unusable_responses_are_refused() {
+        assert_eq!(check("stale.der", "localhost.pem"), Err(OcspError::OutOfDate));
+        assert_eq!(check("revoked.der", "localhost.pem"), Err(OcspError::WrongCertificate));
+
+        let mut tampered = fixture("good.der");
+        let status = tampered.windows(2).position(|pair| pair == [0x80, 0x00]).unwrap();
+        tampered[status] = 0x82;
+        let (leaf, ca) = (pem("localhost.pem"), pem("ca.pem"));
+        let (_, leaf) = X509Certificate::from_der(&leaf).unwrap();
+        let (_, ca) = X509Certificate::from_der(&ca).unwrap();
+        assert_eq!(check_ocsp_response(&tampered, &leaf, &ca, now()), Err(OcspError::BadSignature));
+        // Signed by the CA, so not by the leaf posing as the issuer
+        assert_eq!(check_ocsp_response(&fixture("good.der"), &leaf, &leaf, now()), Err(OcspError::BadSignature));
+        assert_eq!(check_ocsp_response(b"\x30\x03\x0a\x01", &leaf, &ca, now()), Err(OcspError::Malformed));
+        let unavailable = check_ocsp_response(b"\x30\x03\x0a\x01\x06", &leaf, &ca, now());
+        assert_eq!(unavailable, Err(OcspError::Unsuccessful(6)));
+    }
+
+    #[test]
+    fn test_embedded_scts_are_counted() {
+        let logged = pem("localhost.pem");
+        let (_, logged) = X509Certificate::from_der(&logged).unwrap();
+        assert_eq!(embedded_sct_count(&logged), 2);
+        let unlogged = pem("revoked.pem");
+        let (_, unlogged) = X509Certificate::from_der(&unlogged).unwrap();
+        assert_eq!(embedded_sct_count(&unlogged), 0);
+
+        let certificate = describe_certificate(&logged, None);
+        assert!(certificate.subject.contains("localhost"));
+        assert!(certificate.issuer.contains("Navigator Test OCSP CA"));
+        assert_eq!((certificate.sct_count, certificate.ocsp), (2, None));
+    }
+}
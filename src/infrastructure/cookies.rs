@@ -0,0 +1,295 @@
+use crate::domain::{Clock, PublicSuffixList, SystemClock, ValidatedUrl};
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+
+/// A cookie as the jar keeps it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredCookie {
+    pub name: String,
+    pub value: String,
+    /// Host the cookie was set by, or the domain it was widened to
+    pub domain: String,
+    /// Sent only to `domain` itself, not its subdomains
+    pub host_only: bool,
+    pub path: String,
+    /// Sent only over https
+    pub secure: bool,
+    /// When the cookie stops being sent; none for one kept until the
+    /// session ends
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl StoredCookie {
+    fn expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Whether the cookie goes with a request for `url`
+    fn matches(&self, url: &ValidatedUrl) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let domain_matches = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(&host, &self.domain)
+        };
+        domain_matches && path_match(url.path(), &self.path) && (url.is_secure() || !self.secure)
+    }
+
+    fn same_cookie(&self, other: &StoredCookie) -> bool {
+        self.name == other.name && self.domain == other.domain && self.path == other.path
+    }
+}
+
+/// Cookies kept for the session, as `Set-Cookie` headers left them
+#[derive(Debug)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<StoredCookie>>,
+    /// What `Expires` dates are compared with
+    clock: Arc<dyn Clock>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the time from `clock` rather than the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Keep the cookie a response from `url` set, replacing one with the
+    /// same name, domain and path; an expired one removes it instead.
+    /// Returns whether the header was accepted.
+    pub fn store(&self, url: &ValidatedUrl, set_cookie: &str) -> bool {
+        let now = self.clock.now();
+        let Some(cookie) = parse_set_cookie(url, set_cookie, now) else {
+            tracing::debug!("Ignoring invalid cookie from {}: {}", url, set_cookie);
+            return false;
+        };
+        let Ok(mut cookies) = self.cookies.lock() else {
+            return false;
+        };
+        cookies.retain(|stored| !stored.same_cookie(&cookie));
+        if !cookie.expired(now) {
+            cookies.push(cookie);
+        }
+        true
+    }
+
+    /// The `Cookie` header for a request to `url`, longest paths first.
+    /// Cookies that have expired since they were set are dropped first.
+    pub fn header(&self, url: &ValidatedUrl) -> Option<String> {
+        let now = self.clock.now();
+        let mut cookies = self.cookies.lock().ok()?;
+        cookies.retain(|cookie| !cookie.expired(now));
+        let mut matching: Vec<&StoredCookie> = cookies.iter().filter(|cookie| cookie.matches(url)).collect();
+        if matching.is_empty() {
+            return None;
+        }
+        matching.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        let pairs: Vec<String> = matching
+            .iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    /// Every cookie kept, in the order set
+    pub fn cookies(&self) -> Vec<StoredCookie> {
+        self.cookies.lock().map(|cookies| cookies.clone()).unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut cookies) = self.cookies.lock() {
+            cookies.clear();
+        }
+    }
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        Self {
+            cookies: Mutex::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+/// The cookie a `Set-Cookie` header from `url` sets, expiring as counted
+/// from `now`. None for a malformed header, a `Domain` that is a public
+/// suffix or does not contain the host, or a `Secure` cookie from a page
+/// that is not.
+fn parse_set_cookie(url: &ValidatedUrl, header: &str, now: DateTime<Utc>) -> Option<StoredCookie> {
+    let host = url.host_str()?.trim_end_matches('.').to_ascii_lowercase();
+    let mut parts = header.split(';');
+    let (name, value) = parts.next()?.split_once('=')?;
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut cookie = StoredCookie {
+        name: name.to_string(),
+        value: value.trim().trim_matches('"').to_string(),
+        domain: host.clone(),
+        host_only: true,
+        path: default_path(url.path()),
+        secure: false,
+        expires: None,
+    };
+    let (mut max_age, mut expires) = (None, None);
+    for attribute in parts {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => {
+                let domain = value.trim_start_matches('.').trim_end_matches('.').to_ascii_lowercase();
+                if domain != host {
+                    let is_public_suffix = PublicSuffixList::current().registrable_domain(&domain).is_none();
+                    if !domain_match(&host, &domain) || is_public_suffix || url.registrable_domain().is_none() {
+                        return None;
+                    }
+                    cookie.host_only = false;
+                }
+                cookie.domain = domain;
+            }
+            "path" if value.starts_with('/') => cookie.path = value.to_string(),
+            "secure" if !url.is_secure() => return None,
+            "secure" => cookie.secure = true,
+            "max-age" => max_age = value.parse::<i64>().ok().or(max_age),
+            "expires" => {
+                expires = DateTime::parse_from_rfc2822(value)
+                    .map(|expires| expires.with_timezone(&Utc))
+                    .ok()
+                    .or(expires);
+            }
+            _ => {}
+        }
+    }
+    // Max-Age wins over Expires, wherever each appears (RFC 6265 §5.3)
+    cookie.expires = match max_age {
+        Some(seconds) if seconds <= 0 => Some(DateTime::<Utc>::MIN_UTC),
+        Some(seconds) => Some(
+            chrono::Duration::try_seconds(seconds)
+                .and_then(|age| now.checked_add_signed(age))
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+        ),
+        None => expires,
+    };
+    Some(cookie)
+}
+
+/// Whether `host` is `domain` or one of its subdomains
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain || host.strip_suffix(domain).is_some_and(|rest| rest.ends_with('.'))
+}
+
+/// Whether a cookie set for `cookie_path` goes with requests for
+/// `request_path`
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || request_path.strip_prefix(cookie_path).is_some_and(|rest| {
+            cookie_path.ends_with('/') || rest.starts_with('/')
+        })
+}
+
+/// A cookie's path when `Set-Cookie` gives none: the request path up to
+/// its last slash
+fn default_path(request_path: &str) -> String {
+    match request_path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(last) => request_path[..last].to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::testing::ManualClock;
+
+    fn url(input: &str) -> ValidatedUrl {
+        ValidatedUrl::parse(input).unwrap()
+    }
+
+    /// A jar whose clock reads 2024-01-01
+    fn jar() -> (CookieJar, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock::default());
+        (CookieJar::new().with_clock(clock.clone()), clock)
+    }
+
+    #[test]
+    fn test_cookies_match_domain_and_path() {
+        let (jar, _) = jar();
+        assert!(jar.store(&url("https://www.example.co.uk/a/page"), "host=1"));
+        assert!(jar.store(&url("https://www.example.co.uk/"), "wide=2; Domain=.example.co.uk; Path=/"));
+        assert!(jar.store(&url("https://www.example.co.uk/"), "safe=3; Secure"));
+        // A page over plain http may not set a cookie only https gets
+        assert!(!jar.store(&url("http://www.example.co.uk/"), "unsafe=4; Secure"));
+
+        assert_eq!(jar.header(&url("https://www.example.co.uk/a/other")).as_deref(), Some("host=1; wide=2; safe=3"));
+        assert_eq!(jar.header(&url("https://shop.example.co.uk/a/")).as_deref(), Some("wide=2"));
+        assert_eq!(jar.header(&url("http://www.example.co.uk/")).as_deref(), Some("wide=2"));
+        assert_eq!(jar.header(&url("https://www.example.co.uk/ab")).as_deref(), Some("wide=2; safe=3"));
+        assert_eq!(jar.header(&url("https://other.co.uk/")), None);
+    }
+
+    #[test]
+    fn test_cookies_cannot_widen_to_a_public_suffix() {
+        let (jar, _) = jar();
+        let page = url("https://alice.github.io/");
+        assert!(!jar.store(&page, "a=1; Domain=github.io"));
+        assert!(!jar.store(&url("https://www.example.co.uk/"), "a=1; Domain=co.uk"));
+        assert!(!jar.store(&page, "a=1; Domain=bob.github.io"));
+        assert!(!jar.store(&page, "=1"));
+        assert!(jar.store(&page, "a=1; Domain=alice.github.io"));
+        assert_eq!(jar.header(&url("https://bob.github.io/")), None);
+        assert!(!jar.store(&url("http://10.0.0.1/"), "a=1; Domain=0.0.1"));
+    }
+
+    #[test]
+    fn test_expired_cookies_are_removed() {
+        let (jar, clock) = jar();
+        let page = url("https://example.com/");
+        jar.store(&page, "a=1");
+        jar.store(&page, "b=2");
+        jar.store(&page, "a=3");
+        assert_eq!(jar.header(&page).as_deref(), Some("b=2; a=3"));
+        jar.store(&page, "a=; Max-Age=0");
+        jar.store(&page, "b=; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+        assert!(jar.cookies().is_empty());
+
+        // Expiry dates are read against the jar's clock, not the system's
+        let expires = "c=4; Expires=Wed, 01 Jan 2025 00:00:00 GMT";
+        jar.store(&page, expires);
+        assert_eq!(jar.header(&page).as_deref(), Some("c=4"));
+        clock.advance(chrono::Duration::days(400));
+        jar.store(&page, expires);
+        assert!(jar.cookies().is_empty());
+    }
+
+    #[test]
+    fn test_cookies_stop_being_sent_when_they_expire() {
+        let (jar, clock) = jar();
+        let page = url("https://example.com/");
+        jar.store(&page, "session=1");
+        jar.store(&page, "hour=2; Max-Age=3600");
+        // Max-Age wins over Expires, in either order
+        jar.store(&page, "day=3; Expires=Mon, 01 Jan 2024 00:00:01 GMT; Max-Age=86400");
+        jar.store(&page, "minute=4; Max-Age=60; Expires=Tue, 31 Dec 2024 00:00:00 GMT");
+        assert_eq!(jar.header(&page).as_deref(), Some("session=1; hour=2; day=3; minute=4"));
+
+        clock.advance(chrono::Duration::minutes(2));
+        assert_eq!(jar.header(&page).as_deref(), Some("session=1; hour=2; day=3"));
+        clock.advance(chrono::Duration::hours(2));
+        assert_eq!(jar.header(&page).as_deref(), Some("session=1; day=3"));
+        clock.advance(chrono::Duration::days(1));
+        assert_eq!(jar.header(&page).as_deref(), Some("session=1"));
+        // Expired cookies are evicted, not just left out
+        assert_eq!(jar.cookies().len(), 1);
+    }
+}
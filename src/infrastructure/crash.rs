@@ -0,0 +1,205 @@
+use super::logging::LogBuffer;
+use super::safe_file::write_atomically;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tokio::runtime::Handle;
+
+/// Set to "true" while the browser runs and cleared on a clean exit, so a
+/// launch that finds it set knows the last run crashed
+pub const SESSION_RUNNING_SETTING: &str = "crash.session_running";
+
+thread_local! {
+    /// The page the thread is working on, named in its crash reports
+    static CURRENT_PAGE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Names a page in the reports of panics on this thread until dropped,
+/// when the page named before is restored
+pub struct CrashPage {
+    previous: Option<String>,
+}
+
+impl CrashPage {
+    pub fn enter(url: &str) -> Self {
+        let previous = CURRENT_PAGE.with(|page| page.replace(Some(url.to_string())));
+        Self { previous }
+    }
+}
+
+impl Drop for CrashPage {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT_PAGE.with(|page| *page.borrow_mut() = previous);
+    }
+}
+
+/// The page this thread is working on, if any
+pub fn current_page() -> Option<String> {
+    CURRENT_PAGE.with(|page| page.borrow().clone())
+}
+
+/// Writes `crash-{timestamp}.log` reports into the profile directory
+pub struct CrashReporter {
+    directory: PathBuf,
+    logs: LogBuffer,
+}
+
+impl CrashReporter {
+    pub fn new(directory: impl Into<PathBuf>, logs: LogBuffer) -> Self {
+        Self {
+            directory: directory.into(),
+            logs,
+        }
+    }
+
+    /// Write a report for a panic on `thread` while working on `page`,
+    /// returning where it went
+    pub fn write_report(
+        &self,
+        thread: &str,
+        page: Option<&str>,
+        message: &str,
+        backtrace: &Backtrace,
+    ) -> std::io::Result<PathBuf> {
+        let mut report = String::new();
+        let _ = writeln!(report, "Navigator {} crashed", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(report, "Time: {}", chrono::Utc::now().to_rfc3339());
+        let _ = writeln!(report, "Thread: {}", thread);
+        if let Some(page) = page {
+            let _ = writeln!(report, "Page: {}", page);
+        }
+        let _ = writeln!(report, "\n{}\n\nBacktrace:\n{}", message, backtrace);
+        report.push_str("\nRecent events:\n");
+        for record in self.logs.try_records() {
+            let _ = writeln!(report, "{}", record);
+        }
+
+        std::fs::create_dir_all(&self.directory)?;
+        let name = format!("crash-{}.log", chrono::Utc::now().format("%Y%m%d-%H%M%S-%3f"));
+        write_atomically(self.directory.join(name), report)
+    }
+
+    /// Report every panic, after the default hook has printed it.
+    ///
+    /// Panics in background tasks are reported too; the task's
+    /// [`spawn_supervised`] wrapper keeps them from ending the browser.
+    pub fn install(self) {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            previous(info);
+            let thread = std::thread::current();
+            let thread = thread.name().unwrap_or("unnamed");
+            let page = current_page();
+            match self.write_report(thread, page.as_deref(), &info.to_string(), &Backtrace::force_capture()) {
+                Ok(path) => eprintln!("Crash report written to {}", path.display()),
+                Err(e) => eprintln!("Failed to write crash report: {}", e),
+            }
+        }));
+    }
+}
+
+/// The newest crash report in `directory`
+pub fn latest_crash_report(directory: &Path) -> Option<PathBuf> {
+    std::fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("crash-") && name.ends_with(".log"))
+        })
+        // Timestamps in the names sort in order
+        .max()
+}
+
+/// A panic payload as text
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+/// Spawn a background task whose panic is logged instead of lost
+pub fn spawn_supervised<F>(runtime: &Handle, name: &'static str, task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let task = runtime.spawn(task);
+    runtime.spawn(async move {
+        if let Err(e) = task.await {
+            if e.is_panic() {
+                tracing::error!("Background task '{}' panicked: {}", name, panic_message(&*e.into_panic()));
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_report_includes_message_version_and_events() {
+        let directory = std::env::temp_dir().join(format!("navigator-crash-{}", uuid::Uuid::new_v4()));
+        let logs = LogBuffer::new(10);
+        let subscriber = tracing_subscriber::registry().with(logs.clone());
+        tracing::subscriber::with_default(subscriber, || tracing::info!("Loading URL"));
+        let reporter = CrashReporter::new(&directory, logs);
+
+        let path = reporter
+            .write_report("main", None, "panicked at src/main.rs:1:1:\nboom", &Backtrace::disabled())
+            .unwrap();
+        assert_eq!(latest_crash_report(&directory), Some(path.clone()));
+
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert!(report.contains(env!("CARGO_PKG_VERSION")));
+        assert!(report.contains("Thread: main"));
+        assert!(report.contains("boom"));
+        assert!(report.contains("INFO navigator::infrastructure::crash"));
+        assert!(report.contains("Loading URL"));
+        assert!(!report.contains("Page:"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_reports_name_the_page_being_worked_on() {
+        assert_eq!(current_page(), None);
+        {
+            let _page = CrashPage::enter("https://example.com/");
+            {
+                let _frame = CrashPage::enter("https://example.com/frame");
+                assert_eq!(current_page().as_deref(), Some("https://example.com/frame"));
+            }
+            assert_eq!(current_page().as_deref(), Some("https://example.com/"));
+        }
+        assert_eq!(current_page(), None);
+
+        let directory = std::env::temp_dir().join(format!("navigator-crash-{}", uuid::Uuid::new_v4()));
+        let reporter = CrashReporter::new(&directory, LogBuffer::new(10));
+        let path = reporter
+            .write_report("dom", Some("https://example.com/"), "boom", &Backtrace::disabled())
+            .unwrap();
+        let report = std::fs::read_to_string(&path).unwrap();
+        assert!(report.contains("Thread: dom\nPage: https://example.com/\n"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_supervised_panic_does_not_escape() {
+        spawn_supervised(&Handle::current(), "test", async { panic!("task bug") });
+        let (done, finished) = tokio::sync::oneshot::channel();
+        spawn_supervised(&Handle::current(), "test", async move {
+            let _ = done.send(());
+        });
+        finished.await.unwrap();
+    }
+}
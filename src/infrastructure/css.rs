@@ -0,0 +1,559 @@
+//! A small CSS subset: inline `style` attributes and `<style>` blocks with
+//! simple selectors, limited to the properties the text layout can show
+
+use crate::domain::{UserStyleError, UserStyleRule};
+
+/// Most rules kept from a page's stylesheets
+const MAX_RULES: usize = 10_000;
+
+/// An opaque sRGB color
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Parse a CSS color: hex, `rgb()`/`rgba()` or a basic named color.
+    /// Alpha is dropped.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        if let Some(hex) = value.strip_prefix('#') {
+            return Self::parse_hex(hex);
+        }
+        if let Some(args) = value
+            .strip_prefix("rgba(")
+            .or_else(|| value.strip_prefix("rgb("))
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            return Self::parse_rgb_args(args);
+        }
+        named_color(&value)
+    }
+
+    fn parse_hex(hex: &str) -> Option<Self> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok();
+        let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        match hex.len() {
+            3 | 4 => Some(Self::new(digit(0)? * 17, digit(1)? * 17, digit(2)? * 17)),
+            6 | 8 => Some(Self::new(pair(0)?, pair(2)?, pair(4)?)),
+            _ => None,
+        }
+    }
+
+    fn parse_rgb_args(args: &str) -> Option<Self> {
+        let mut channels = args
+            .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .map(|part| match part.strip_suffix('%') {
+                Some(percent) => percent.parse::<f32>().ok().map(|p| p * 2.55),
+                None => part.parse::<f32>().ok(),
+            });
+        let mut channel = || Some(channels.next()??.round().clamp(0.0, 255.0) as u8);
+        Some(Self::new(channel()?, channel()?, channel()?))
+    }
+}
+
+fn named_color(name: &str) -> Option<Rgb> {
+    let rgb = match name {
+        "black" => Rgb::new(0x00, 0x00, 0x00),
+        "white" => Rgb::new(0xff, 0xff, 0xff),
+        "red" => Rgb::new(0xff, 0x00, 0x00),
+        "green" => Rgb::new(0x00, 0x80, 0x00),
+        "lime" => Rgb::new(0x00, 0xff, 0x00),
+        "blue" => Rgb::new(0x00, 0x00, 0xff),
+        "navy" => Rgb::new(0x00, 0x00, 0x80),
+        "yellow" => Rgb::new(0xff, 0xff, 0x00),
+        "orange" => Rgb::new(0xff, 0xa5, 0x00),
+        "purple" => Rgb::new(0x80, 0x00, 0x80),
+        "fuchsia" | "magenta" => Rgb::new(0xff, 0x00, 0xff),
+        "aqua" | "cyan" => Rgb::new(0x00, 0xff, 0xff),
+        "teal" => Rgb::new(0x00, 0x80, 0x80),
+        "maroon" => Rgb::new(0x80, 0x00, 0x00),
+        "olive" => Rgb::new(0x80, 0x80, 0x00),
+        "gray" | "grey" => Rgb::new(0x80, 0x80, 0x80),
+        "darkgray" | "darkgrey" => Rgb::new(0xa9, 0xa9, 0xa9),
+        "lightgray" | "lightgrey" => Rgb::new(0xd3, 0xd3, 0xd3),
+        "silver" => Rgb::new(0xc0, 0xc0, 0xc0),
+        "brown" => Rgb::new(0xa5, 0x2a, 0x2a),
+        "pink" => Rgb::new(0xff, 0xc0, 0xcb),
+        _ => return None,
+    };
+    Some(rgb)
+}
+
+/// A `font-size` value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FontSize {
+    /// Absolute size in CSS pixels
+    Px(f32),
+    /// Multiple of the parent element's size
+    Relative(f32),
+}
+
+impl FontSize {
+    fn parse(value: &str) -> Option<Self> {
+        let keyword = match value {
+            "xx-small" => Some(Self::Px(9.0)),
+            "x-small" => Some(Self::Px(10.0)),
+            "small" => Some(Self::Px(13.0)),
+            "medium" => Some(Self::Px(16.0)),
+            "large" => Some(Self::Px(18.0)),
+            "x-large" => Some(Self::Px(24.0)),
+            "xx-large" => Some(Self::Px(32.0)),
+            "smaller" => Some(Self::Relative(0.83)),
+            "larger" => Some(Self::Relative(1.2)),
+            _ => None,
+        };
+        if keyword.is_some() {
+            return keyword;
+        }
+
+        let number = |suffix: &str| value.strip_suffix(suffix)?.trim().parse::<f32>().ok();
+        let size = if let Some(px) = number("px") {
+            Self::Px(px)
+        } else if let Some(rem) = number("rem") {
+            Self::Px(rem * 16.0)
+        } else if let Some(em) = number("em") {
+            Self::Relative(em)
+        } else if let Some(pt) = number("pt") {
+            Self::Px(pt * 4.0 / 3.0)
+        } else if let Some(percent) = number("%") {
+            Self::Relative(percent / 100.0)
+        } else {
+            return None;
+        };
+
+        match size {
+            Self::Px(px) | Self::Relative(px) if px.is_finite() && px > 0.0 => Some(size),
+            _ => None,
+        }
+    }
+
+    /// Size in pixels given the parent's size
+    pub fn resolve(self, parent: f32) -> f32 {
+        match self {
+            Self::Px(px) => px,
+            Self::Relative(factor) => parent * factor,
+        }
+    }
+}
+
+/// Supported properties set by a declaration block; `None` means not set
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Declarations {
+    pub color: Option<Rgb>,
+    pub background_color: Option<Rgb>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub font_size: Option<FontSize>,
+    /// `display: none` when true
+    pub hidden: Option<bool>,
+}
+
+impl Declarations {
+    /// Parse a declaration block such as a `style` attribute. Unknown
+    /// properties and malformed declarations are skipped.
+    pub fn parse(block: &str) -> Self {
+        let mut declarations = Self::default();
+        for declaration in block.split(';') {
+            let Some((name, value)) = declaration.split_once(':') else {
+                continue;
+            };
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim().to_ascii_lowercase();
+            // Precedence is decided by specificity alone
+            let value = value.strip_suffix("!important").unwrap_or(&value).trim();
+
+            match name.as_str() {
+                "color" => declarations.color = Rgb::parse(value).or(declarations.color),
+                "background-color" | "background" => {
+                    declarations.background_color =
+                        Rgb::parse(value).or(declarations.background_color);
+                }
+                "font-weight" => {
+                    let bold = match value {
+                        "bold" | "bolder" => Some(true),
+                        "normal" | "lighter" => Some(false),
+                        _ => value.parse::<u16>().ok().map(|weight| weight >= 600),
+                    };
+                    declarations.bold = bold.or(declarations.bold);
+                }
+                "font-style" => {
+                    let italic = match value {
+                        "italic" | "oblique" => Some(true),
+                        "normal" => Some(false),
+                        _ => None,
+                    };
+                    declarations.italic = italic.or(declarations.italic);
+                }
+                "font-size" => {
+                    declarations.font_size = FontSize::parse(value).or(declarations.font_size);
+                }
+                "display" => {
+                    if value == "none" {
+                        declarations.hidden = Some(true);
+                    } else if !value.is_empty()
+                        && value.chars().all(|c| c.is_ascii_alphabetic() || c == '-' || c == ' ')
+                    {
+                        declarations.hidden = Some(false);
+                    }
+                }
+                _ => {}
+            }
+        }
+        declarations
+    }
+
+    /// Override properties with those set in `other`
+    pub fn merge(&mut self, other: &Declarations) {
+        self.color = other.color.or(self.color);
+        self.background_color = other.background_color.or(self.background_color);
+        self.bold = other.bold.or(self.bold);
+        self.italic = other.italic.or(self.italic);
+        self.font_size = other.font_size.or(self.font_size);
+        self.hidden = other.hidden.or(self.hidden);
+    }
+}
+
+/// A compound selector of at most a tag, an id and classes, e.g. `p.note#intro`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Selector {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Selector {
+    /// Parse a simple selector; combinators, pseudo-classes and attribute
+    /// selectors are not supported
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() || text.contains(|c: char| c.is_whitespace() || ">+~:[*".contains(c)) {
+            return (text == "*").then(Self::default);
+        }
+
+        let mut selector = Self::default();
+        let mut rest = text;
+        let is_name = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+        while !rest.is_empty() {
+            let (prefix, body) = match rest.chars().next()? {
+                c @ ('#' | '.') => (Some(c), &rest[1..]),
+                _ => (None, rest),
+            };
+            let end = body.find(|c: char| !is_name(c)).unwrap_or(body.len());
+            if end == 0 {
+                return None;
+            }
+            let name = body[..end].to_string();
+            match prefix {
+                Some('#') => selector.id = Some(name),
+                Some(_) => selector.classes.push(name),
+                None if selector.tag.is_none() && rest.len() == text.len() => {
+                    selector.tag = Some(name.to_ascii_lowercase());
+                }
+                None => return None,
+            }
+            rest = &body[end..];
+        }
+        Some(selector)
+    }
+
+    fn matches(&self, tag: &str, id: Option<&str>, classes: &[&str]) -> bool {
+        self.tag.as_deref().is_none_or(|wanted| wanted == tag)
+            && self.id.as_deref().is_none_or(|wanted| Some(wanted) == id)
+            && self.classes.iter().all(|wanted| classes.contains(&wanted.as_str()))
+    }
+
+    /// (ids, classes, tags), compared lexicographically
+    fn specificity(&self) -> (usize, usize, usize) {
+        (
+            usize::from(self.id.is_some()),
+            self.classes.len(),
+            usize::from(self.tag.is_some()),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    selector: Selector,
+    declarations: Declarations,
+}
+
+/// Rules from a page's `<style>` blocks, in source order
+#[derive(Debug, Clone, Default)]
+pub struct Stylesheet {
+    rules: Vec<Rule>,
+}
+
+impl Stylesheet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the rules of a style sheet; at-rules such as `@media` are skipped
+    pub fn add(&mut self, css: &str) {
+        let css = strip_comments(css);
+        let mut rest = css.as_str();
+        while let Some(open) = rest.find('{') {
+            let prelude = rest[..open].trim();
+            let Some(close) = matching_brace(&rest[open..]).map(|i| open + i) else {
+                break;
+            };
+            let block = &rest[open + 1..close];
+            rest = &rest[close + 1..];
+
+            // `@import url(x);` style statements end up in the next prelude
+            let prelude = prelude.rsplit(';').next().unwrap_or(prelude).trim();
+            if prelude.starts_with('@') {
+                continue;
+            }
+            let declarations = Declarations::parse(block);
+            if declarations == Declarations::default() {
+                continue;
+            }
+            for selector in prelude.split(',').filter_map(Selector::parse) {
+                if self.rules.len() >= MAX_RULES {
+                    return;
+                }
+                self.rules.push(Rule {
+                    selector,
+                    declarations,
+                });
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Combined declarations of every rule matching an element, by
+    /// specificity and then source order
+    pub fn declarations_for(&self, tag: &str, id: Option<&str>, classes: &[&str]) -> Declarations {
+        let mut matching: Vec<(usize, &Rule)> = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.selector.matches(tag, id, classes))
+            .collect();
+        matching.sort_by_key(|(order, rule)| (rule.selector.specificity(), *order));
+
+        let mut declarations = Declarations::default();
+        for (_, rule) in matching {
+            declarations.merge(&rule.declarations);
+        }
+        declarations
+    }
+}
+
+/// User style rules compiled for one page
+#[derive(Debug, Clone, Default)]
+pub struct UserStylesheet {
+    /// Smallest font size in CSS pixels
+    pub min_font_size: Option<f32>,
+    /// Color forced onto all text
+    pub text_color: Option<Rgb>,
+    hidden: Vec<Selector>,
+}
+
+impl UserStylesheet {
+    /// Compile rules given least specific first; later sizes and colors
+    /// override earlier ones, while hidden selectors add up. Invalid rules
+    /// are skipped.
+    pub fn from_rules(rules: &[UserStyleRule]) -> Self {
+        let mut sheet = Self::default();
+        for rule in rules {
+            match rule {
+                UserStyleRule::MinFontSize { px } if px.is_finite() && *px > 0.0 => {
+                    sheet.min_font_size = Some(*px);
+                }
+                UserStyleRule::TextColor { color } => {
+                    sheet.text_color = Rgb::parse(color).or(sheet.text_color);
+                }
+                UserStyleRule::Hide { selector } => {
+                    sheet.hidden.extend(parse_selector_list(selector).unwrap_or_default());
+                }
+                _ => {}
+            }
+        }
+        sheet
+    }
+
+    /// Check that a rule would take effect
+    pub fn validate(rule: &UserStyleRule) -> Result<(), UserStyleError> {
+        let valid = match rule {
+            UserStyleRule::MinFontSize { px } => px.is_finite() && *px > 0.0,
+            UserStyleRule::TextColor { color } => Rgb::parse(color).is_some(),
+            UserStyleRule::Hide { selector } => parse_selector_list(selector).is_some(),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(UserStyleError::InvalidRule(rule.to_string()))
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min_font_size.is_none() && self.text_color.is_none() && self.hidden.is_empty()
+    }
+
+    /// Whether a hide rule matches an element
+    pub fn hides(&self, tag: &str, id: Option<&str>, classes: &[&str]) -> bool {
+        self.hidden
+            .iter()
+            .any(|selector| selector.matches(tag, id, classes))
+    }
+}
+
+/// Every selector of a comma-separated list, or `None` if any is unsupported
+fn parse_selector_list(text: &str) -> Option<Vec<Selector>> {
+    text.split(',').map(Selector::parse).collect()
+}
+
+/// A comma-separated list of the simple selectors stylesheets support, as
+/// `querySelector` takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectorList(Vec<Selector>);
+
+impl SelectorList {
+    /// None if any selector in the list is unsupported
+    pub fn parse(text: &str) -> Option<Self> {
+        parse_selector_list(text).map(Self)
+    }
+
+    /// Whether any selector matches an element
+    pub fn matches(&self, tag: &str, id: Option<&str>, classes: &[&str]) -> bool {
+        self.0.iter().any(|selector| selector.matches(tag, id, classes))
+    }
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = rest[start + 2..].find("*/").map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Byte index of the brace closing the one `text` starts with
+fn matching_brace(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_colors() {
+        assert_eq!(Rgb::parse("#fff"), Some(Rgb::new(255, 255, 255)));
+        assert_eq!(Rgb::parse("#1A2b3C"), Some(Rgb::new(0x1a, 0x2b, 0x3c)));
+        assert_eq!(Rgb::parse("#11223380"), Some(Rgb::new(0x11, 0x22, 0x33)));
+        assert_eq!(Rgb::parse("rgb(255, 0, 10)"), Some(Rgb::new(255, 0, 10)));
+        assert_eq!(Rgb::parse("rgba(0 128 0 / 50%)"), Some(Rgb::new(0, 128, 0)));
+        assert_eq!(Rgb::parse("rgb(100%, 0%, 300)"), Some(Rgb::new(255, 0, 255)));
+        assert_eq!(Rgb::parse("Navy"), Some(Rgb::new(0, 0, 0x80)));
+        assert_eq!(Rgb::parse("#ggg"), None);
+        assert_eq!(Rgb::parse("rgb(1, 2)"), None);
+        assert_eq!(Rgb::parse("currentcolor"), None);
+    }
+
+    #[test]
+    fn test_parse_declarations_ignores_unknown_and_malformed() {
+        let declarations = Declarations::parse(
+            "color: red; margin: 0 auto; font-weight: 700; garbage; font-style: italic;\
+             font-size: 2em; display: NONE !important; background-color: nonsense",
+        );
+        assert_eq!(declarations.color, Some(Rgb::new(255, 0, 0)));
+        assert_eq!(declarations.bold, Some(true));
+        assert_eq!(declarations.italic, Some(true));
+        assert_eq!(declarations.font_size, Some(FontSize::Relative(2.0)));
+        assert_eq!(declarations.hidden, Some(true));
+        assert_eq!(declarations.background_color, None);
+
+        assert_eq!(Declarations::parse(";;:;color"), Declarations::default());
+        assert_eq!(Declarations::parse("font-size: -3px").font_size, None);
+        assert_eq!(Declarations::parse("font-size: 12pt").font_size, Some(FontSize::Px(16.0)));
+    }
+
+    #[test]
+    fn test_stylesheet_specificity_and_order() {
+        let mut sheet = Stylesheet::new();
+        sheet.add(
+            "/* comment { } */ @media print { p { display: none } }\
+             #lead { color: #00f } p.note { color: #0f0 } .note { color: #f00; font-weight: bold }\
+             p { color: black } p, div { font-style: italic } a:hover { color: red }\
+             div p { color: red }",
+        );
+
+        let note = sheet.declarations_for("p", None, &["note"]);
+        assert_eq!(note.color, Some(Rgb::new(0, 255, 0)));
+        assert_eq!(note.bold, Some(true));
+        assert_eq!(note.italic, Some(true));
+        assert_eq!(note.hidden, None);
+
+        let lead = sheet.declarations_for("p", Some("lead"), &["note"]);
+        assert_eq!(lead.color, Some(Rgb::new(0, 0, 255)));
+
+        let plain = sheet.declarations_for("p", None, &[]);
+        assert_eq!(plain.color, Some(Rgb::new(0, 0, 0)));
+        assert_eq!(sheet.declarations_for("a", None, &[]).color, None);
+    }
+
+    #[test]
+    fn test_user_stylesheet_later_rules_win() {
+        let rules = [
+            UserStyleRule::TextColor { color: "black".to_string() },
+            UserStyleRule::Hide { selector: ".ad, aside".to_string() },
+            UserStyleRule::MinFontSize { px: 14.0 },
+            UserStyleRule::TextColor { color: "#222".to_string() },
+        ];
+        let sheet = UserStylesheet::from_rules(&rules);
+        assert_eq!(sheet.text_color, Some(Rgb::new(0x22, 0x22, 0x22)));
+        assert_eq!(sheet.min_font_size, Some(14.0));
+        assert!(sheet.hides("div", None, &["ad"]));
+        assert!(sheet.hides("aside", None, &[]));
+        assert!(!sheet.hides("p", None, &[]));
+
+        let unsupported = UserStyleRule::Hide { selector: ".ok, div > p".to_string() };
+        assert!(UserStylesheet::validate(&unsupported).is_err());
+        assert!(UserStylesheet::validate(&rules[1]).is_ok());
+        assert!(UserStylesheet::validate(&UserStyleRule::TextColor { color: "dark".to_string() }).is_err());
+    }
+
+    #[test]
+    fn test_later_rules_win_at_equal_specificity() {
+        let mut sheet = Stylesheet::new();
+        sheet.add(".a { color: red } .b { color: blue }");
+        sheet.add(".a { color: green }");
+
+        let both = sheet.declarations_for("span", None, &["a", "b"]);
+        assert_eq!(both.color, Some(Rgb::new(0, 128, 0)));
+    }
+}
@@ -0,0 +1,178 @@
+use base64::Engine;
+use thiserror::Error;
+
+/// Default maximum decoded size of a data: URL payload (2MB)
+pub const DEFAULT_MAX_DATA_URL_SIZE: usize = 2 * 1024 * 1024;
+
+/// Why a data: URL was rejected
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DataUrlError {
+    #[error("Malformed data URL: {0}")]
+    Malformed(String),
+    #[error("Unsupported data URL type: {0}")]
+    UnsupportedType(String),
+    #[error("data URL exceeds {0} bytes")]
+    TooLarge(usize),
+}
+
+fn malformed(reason: impl Into<String>) -> DataUrlError {
+    DataUrlError::Malformed(reason.into())
+}
+
+/// Decoded contents of a `data:` URL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataUrl {
+    pub mime_type: String,
+    pub parameters: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl DataUrl {
+    /// Parse and decode a data: URL.
+    ///
+    /// Only text/html, text/plain and image/* payloads are accepted, and the
+    /// decoded body may not exceed `max_size` bytes.
+    pub fn parse(input: &str, max_size: usize) -> Result<Self, DataUrlError> {
+        let rest = input
+            .strip_prefix("data:")
+            .ok_or_else(|| malformed("not a data URL"))?;
+
+        let (header, payload) = rest
+            .split_once(',')
+            .ok_or_else(|| malformed("missing ','"))?;
+
+        // Whitespace in the header is never legitimate and is a common way to
+        // hide a ";base64" marker from naive filters
+        if header.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(malformed("whitespace in header"));
+        }
+
+        let mut parts = header.split(';');
+        let mime_type = parts.next().unwrap_or("").to_ascii_lowercase();
+        let mime_type = if mime_type.is_empty() {
+            "text/plain".to_string()
+        } else {
+            mime_type
+        };
+
+        let mut is_base64 = false;
+        let mut parameters = Vec::new();
+        for part in parts {
+            if is_base64 {
+                return Err(malformed("base64 marker must come last"));
+            }
+            if part.eq_ignore_ascii_case("base64") {
+                is_base64 = true;
+            } else {
+                let (name, value) = part
+                    .split_once('=')
+                    .ok_or_else(|| malformed(format!("bad parameter {}", part)))?;
+                parameters.push((name.to_ascii_lowercase(), value.to_string()));
+            }
+        }
+
+        if !is_renderable(&mime_type) {
+            return Err(DataUrlError::UnsupportedType(mime_type));
+        }
+
+        let body = if is_base64 {
+            // Base64 payloads may still be percent-encoded in the URL
+            let encoded = percent_decode(payload);
+            if encoded.len() / 4 * 3 > max_size {
+                return Err(DataUrlError::TooLarge(max_size));
+            }
+            base64::engine::general_purpose::STANDARD
+                .decode(&encoded)
+                .map_err(|e| malformed(format!("bad base64 payload: {}", e)))?
+        } else {
+            percent_decode(payload)
+        };
+
+        if body.len() > max_size {
+            return Err(DataUrlError::TooLarge(max_size));
+        }
+
+        Ok(Self {
+            mime_type,
+            parameters,
+            body,
+        })
+    }
+
+    /// Full content type including parameters, e.g. `text/html;charset=utf-8`
+    pub fn content_type(&self) -> String {
+        let mut content_type = self.mime_type.clone();
+        for (name, value) in &self.parameters {
+            content_type.push_str(&format!(";{}={}", name, value));
+        }
+        content_type
+    }
+}
+
+fn is_renderable(mime_type: &str) -> bool {
+    matches!(mime_type, "text/html" | "text/plain") || mime_type.starts_with("image/")
+}
+
+fn percent_decode(input: &str) -> Vec<u8> {
+    percent_encoding::percent_decode_str(input).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text() {
+        let data = DataUrl::parse("data:,Hello%2C%20World", DEFAULT_MAX_DATA_URL_SIZE).unwrap();
+        assert_eq!(data.mime_type, "text/plain");
+        assert_eq!(data.body, b"Hello, World");
+    }
+
+    #[test]
+    fn test_base64_html() {
+        let data = DataUrl::parse(
+            "data:text/html;charset=utf-8;base64,PGgxPkhpPC9oMT4=",
+            DEFAULT_MAX_DATA_URL_SIZE,
+        )
+        .unwrap();
+        assert_eq!(data.mime_type, "text/html");
+        assert_eq!(data.content_type(), "text/html;charset=utf-8");
+        assert_eq!(data.body, b"<h1>Hi</h1>");
+    }
+
+    #[test]
+    fn test_missing_comma() {
+        assert!(DataUrl::parse("data:text/html;base64", DEFAULT_MAX_DATA_URL_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_whitespace_in_base64_marker() {
+        assert!(DataUrl::parse("data:text/html;base 64,PGgxPg==", DEFAULT_MAX_DATA_URL_SIZE).is_err());
+        assert!(DataUrl::parse("data:text/html; base64,PGgxPg==", DEFAULT_MAX_DATA_URL_SIZE).is_err());
+        assert!(DataUrl::parse("data:text/html;\tbase64,PGgxPg==", DEFAULT_MAX_DATA_URL_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_nested_markers_rejected() {
+        assert!(DataUrl::parse("data:text/html;base64;base64,PGgxPg==", DEFAULT_MAX_DATA_URL_SIZE).is_err());
+        assert!(DataUrl::parse("data:data:text/html,hi", DEFAULT_MAX_DATA_URL_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_invalid_base64() {
+        assert!(DataUrl::parse("data:text/plain;base64,@@@", DEFAULT_MAX_DATA_URL_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_disallowed_type() {
+        assert!(DataUrl::parse("data:application/javascript,alert(1)", DEFAULT_MAX_DATA_URL_SIZE).is_err());
+        assert!(DataUrl::parse("data:image/png;base64,iVBORw0KGgo=", DEFAULT_MAX_DATA_URL_SIZE).is_ok());
+    }
+
+    #[test]
+    fn test_size_cap() {
+        let url = format!("data:text/plain,{}", "a".repeat(11));
+        assert_eq!(DataUrl::parse(&url, 10), Err(DataUrlError::TooLarge(10)));
+        assert!(DataUrl::parse(&url, 11).is_ok());
+    }
+}
@@ -1,33 +1,308 @@
 use crate::domain::{
-    Bookmark, BookmarkRepository, HistoryEntry, HistoryRepository, Tab, TabId, TabRepository,
-    ValidatedUrl,
+    Bookmark, BookmarkRepository, CachedPage, CachedPageSummary, Clock, CookiePolicy, CustomCa, CustomCaRepository,
+    DeletedHistory, Download, DownloadRepository, HistoryEntry, HistoryRepository, HostPattern, NewBookmark,
+    MatchPattern, NewCustomCa, NewDownload, NewHistoryEntry, NewSearchEngine, NewUserScript, NewUserStyle,
+    PageCacheRepository, SearchEngine, SearchEngineRepository, SearchTemplate, SettingsRepository, SiteOrigin,
+    SiteSettings, SiteSettingsRepository, StorageChange, StorageError, Tab, TabId, RunAt, TabRepository, UserScript,
+    SystemClock, UserScriptRepository, UserStyle, UserStyleRepository, ValidatedUrl, Visit, VisitType,
+    WebStorageRepository, SavedWindow, WindowGeometry, WindowId, bookmark_move_positions, BOOKMARK_POSITION_STEP,
 };
-use anyhow::{Context, Result};
+use super::profile_crypto::{
+    Argon2Cost, KeyDerivation, KeyMaterial, ProfileCipher, KDF_COST, LEGACY_ENCRYPTION_SCHEME, PROFILE_ENCRYPTION_SCHEME,
+};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use async_trait::async_trait;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteSynchronous};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::hash::{BuildHasher, RandomState};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+/// How long a connection waits on a lock held by another before failing
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Attempts made for a write that keeps failing with a busy error
+const BUSY_RETRY_ATTEMPTS: u32 = 3;
+const BUSY_RETRY_DELAY: Duration = Duration::from_millis(25);
+/// Most pages kept for offline browsing; the oldest go first
+pub const MAX_CACHED_PAGES: i64 = 500;
+/// How often the maintenance task checkpoints the WAL
+pub const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Storage figures for the about page
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DatabaseStats {
+    pub page_size: i64,
+    pub page_count: i64,
+    /// Unused pages that a VACUUM would reclaim
+    pub freelist_count: i64,
+    /// Size of the write-ahead log; zero for in-memory databases
+    pub wal_bytes: u64,
+}
+
+impl DatabaseStats {
+    pub fn database_bytes(&self) -> i64 {
+        self.page_size * self.page_count
+    }
+}
 
 /// SQLite-based implementation of repositories
 pub struct SqliteDatabase {
     pool: SqlitePool,
+    /// Write-ahead log next to the database file, if it is on disk
+    wal_path: Option<PathBuf>,
+    /// Set when the profile is encrypted and has been unlocked
+    cipher: Option<ProfileCipher>,
+    /// Stamps visits and new records
+    clock: Arc<dyn Clock>,
+}
+
+/// History columns in the order [`SqliteDatabase::decode_visit`] expects
+const HISTORY_COLUMNS: &str = "id, url, url_data, title, visited_at, visit_count";
+/// URLs looked up per visited-link query, well under SQLite's limit on
+/// bound parameters
+const VISITED_QUERY_CHUNK: usize = 500;
+/// `PRAGMA user_version` once history URLs are stored normalized
+const NORMALIZED_HISTORY_VERSION: i64 = 1;
+/// `PRAGMA user_version` once the built-in search engines have been added
+const SEARCH_ENGINES_VERSION: i64 = 2;
+/// `PRAGMA user_version` once history entries from before visits were
+/// recorded have one each
+const VISITS_VERSION: i64 = 3;
+
+/// Search engines a new profile starts with as name, keyword, query and
+/// suggest templates; the first is the default
+const BUILTIN_SEARCH_ENGINES: &[(&str, &str, &str, Option<&str>)] = &[
+    (
+        "DuckDuckGo",
+        "d",
+        "https://duckduckgo.com/?q=%s",
+        Some("https://duckduckgo.com/ac/?q=%s&type=list"),
+    ),
+    (
+        "Wikipedia",
+        "w",
+        "https://en.wikipedia.org/w/index.php?search=%s",
+        Some("https://en.wikipedia.org/w/api.php?action=opensearch&search=%s"),
+    ),
+    ("GitHub", "gh", "https://github.com/search?q=%s", None),
+];
+type HistoryRow = (i64, String, Option<String>, String, String, i32);
+type VisitRow = (i64, String, String);
+type SqliteQuery<'q> = sqlx::query::Query<'q, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'q>>;
+
+/// History columns as written for a visit
+struct StoredVisit {
+    /// The URL itself, or its blind index in an encrypted profile
+    url: String,
+    /// The encrypted URL in an encrypted profile
+    url_data: Option<String>,
+    title: String,
 }
 
 impl SqliteDatabase {
+    /// Open an unencrypted profile
     pub async fn new(database_path: &str) -> Result<Self> {
+        Self::open(database_path, None).await
+    }
+
+    /// Open a profile, unlocking it with `passphrase` if it is encrypted
+    ///
+    /// Fails with [`StorageError::PassphraseRequired`] or
+    /// [`StorageError::WrongPassphrase`] when an encrypted profile is opened
+    /// without a passphrase or with the wrong one.
+    pub async fn open(database_path: &str, passphrase: Option<&str>) -> Result<Self> {
         let options = SqliteConnectOptions::from_str(database_path)?
             .create_if_missing(true)
-            .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal);
+            .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(BUSY_TIMEOUT);
+
+        let in_memory = database_path.contains(":memory:");
+        let wal_path = (!in_memory).then(|| {
+            let mut path = options.get_filename().as_os_str().to_owned();
+            path.push("-wal");
+            PathBuf::from(path)
+        });
+
+        // Every connection to ":memory:" opens a separate database
+        let max_connections = if in_memory { 1 } else { 5 };
 
         let pool = SqlitePoolOptions::new()
-            .max_connections(5)
+            .max_connections(max_connections)
             .connect_with(options)
             .await
-            .context("Failed to connect to database")?;
+            .map_err(|e| StorageError::database("Failed to connect to database", e))?;
 
         // Run migrations
         Self::create_tables(&pool).await?;
 
-        Ok(Self { pool })
+        let cipher = match (Self::load_key_material(&pool).await?, passphrase) {
+            (Some(_), None) => return Err(StorageError::PassphraseRequired),
+            (Some(material), Some(passphrase)) => Some(ProfileCipher::unlock(passphrase, &material)?),
+            (None, Some(_)) => {
+                tracing::warn!("Profile is not encrypted; ignoring the passphrase");
+                None
+            }
+            (None, None) => None,
+        };
+
+        let db = Self {
+            pool,
+            wal_path,
+            cipher,
+            clock: Arc::new(SystemClock),
+        };
+        // Needs the cipher, so it runs once the profile is unlocked
+        db.normalize_history_urls().await?;
+        db.add_builtin_search_engines().await?;
+        db.add_visits().await?;
+        Ok(db)
+    }
+
+    /// Take the time from `clock` rather than the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Give profiles from before search engines were stored the built-in ones
+    async fn add_builtin_search_engines(&self) -> Result<()> {
+        let (version,) = sqlx::query_as::<_, (i64,)>("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await?;
+        if version >= SEARCH_ENGINES_VERSION {
+            return Ok(());
+        }
+
+        let (existing,) = sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM search_engines")
+            .fetch_one(&self.pool)
+            .await?;
+        let created_at = self.clock.now().to_rfc3339();
+        let mut transaction = self.pool.begin().await?;
+        for (index, (name, keyword, query, suggest)) in BUILTIN_SEARCH_ENGINES.iter().enumerate() {
+            sqlx::query(
+                "INSERT OR IGNORE INTO search_engines
+                 (name, keyword, query_template, suggest_template, is_default, created_at)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(name)
+            .bind(keyword)
+            .bind(query)
+            .bind(suggest)
+            .bind(existing == 0 && index == 0)
+            .bind(&created_at)
+            .execute(&mut *transaction)
+            .await?;
+        }
+        sqlx::query(&format!("PRAGMA user_version = {}", SEARCH_ENGINES_VERSION))
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// Give history entries from before visits were recorded one visit
+    /// each, as a link followed at their last visit
+    async fn add_visits(&self) -> Result<()> {
+        let (version,) = sqlx::query_as::<_, (i64,)>("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await?;
+        if version >= VISITS_VERSION {
+            return Ok(());
+        }
+
+        let mut transaction = self.pool.begin().await?;
+        let added = sqlx::query(
+            "INSERT INTO visits (history_id, visited_at, visit_type)
+             SELECT id, visited_at, ? FROM history
+             WHERE id NOT IN (SELECT history_id FROM visits)",
+        )
+        .bind(VisitType::Link.to_string())
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query(&format!("PRAGMA user_version = {}", VISITS_VERSION))
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+
+        if added.rows_affected() > 0 {
+            tracing::info!("Added visits to {} history entries", added.rows_affected());
+        }
+        Ok(())
+    }
+
+    /// Rewrite visits stored before URLs were normalized, merging visits
+    /// whose URLs only differed in spelling or fragment
+    async fn normalize_history_urls(&self) -> Result<()> {
+        let (version,) = sqlx::query_as::<_, (i64,)>("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await?;
+        if version >= NORMALIZED_HISTORY_VERSION {
+            return Ok(());
+        }
+
+        let query = format!("SELECT {} FROM history ORDER BY id", HISTORY_COLUMNS);
+        let rows = sqlx::query_as::<_, HistoryRow>(&query).fetch_all(&self.pool).await?;
+        // Rows come oldest first, so each group starts with its oldest row
+        let mut groups: HashMap<String, (ValidatedUrl, Vec<HistoryEntry>)> = HashMap::new();
+        for entry in self.decode_visits(rows) {
+            let url = entry.url.normalized();
+            groups
+                .entry(url.as_str().to_string())
+                .or_insert_with(|| (url, Vec::new()))
+                .1
+                .push(entry);
+        }
+
+        let mut transaction = self.pool.begin().await?;
+        let mut rewritten = 0;
+        for (url, entries) in groups.into_values() {
+            if entries.len() == 1 && entries[0].url == url {
+                continue;
+            }
+            // Keep the oldest row's id and the latest visit's title and time
+            let Some(latest) = entries.iter().max_by_key(|entry| entry.visited_at) else {
+                continue;
+            };
+            let stored = self.encode_visit(&url, &latest.title)?;
+            let visit_count: i32 = entries.iter().map(|entry| entry.visit_count).sum();
+            for entry in &entries {
+                sqlx::query("DELETE FROM history WHERE id = ?")
+                    .bind(entry.id)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            sqlx::query(
+                "INSERT INTO history (id, url, url_data, title, visited_at, visit_count)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(entries[0].id)
+            .bind(&stored.url)
+            .bind(&stored.url_data)
+            .bind(&stored.title)
+            .bind(latest.visited_at.to_rfc3339())
+            .bind(visit_count)
+            .execute(&mut *transaction)
+            .await?;
+            rewritten += entries.len();
+        }
+        sqlx::query(&format!("PRAGMA user_version = {}", NORMALIZED_HISTORY_VERSION))
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+
+        if rewritten > 0 {
+            tracing::info!("Normalized {} history entries", rewritten);
+        }
+        Ok(())
     }
 
     async fn create_tables(pool: &SqlitePool) -> Result<()> {
@@ -47,6 +322,35 @@ impl SqliteDatabase {
         .execute(pool)
         .await?;
 
+        // Columns added after the tabs table was first created
+        let tab_columns = sqlx::query_as::<_, (String,)>("SELECT name FROM pragma_table_info('tabs')")
+            .fetch_all(pool)
+            .await?;
+        if !tab_columns.iter().any(|(name,)| name == "window_id") {
+            sqlx::query("ALTER TABLE tabs ADD COLUMN window_id TEXT")
+                .execute(pool)
+                .await?;
+        }
+
+        // Windows of the session saved at exit, in the order they reopen;
+        // tabs name theirs in `window_id`
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS windows (
+                id TEXT PRIMARY KEY,
+                position INTEGER NOT NULL,
+                x INTEGER,
+                y INTEGER,
+                width INTEGER,
+                height INTEGER,
+                monitor TEXT,
+                active_tab TEXT
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
+
         // Create bookmarks table
         sqlx::query(
             r#"
@@ -55,6 +359,7 @@ impl SqliteDatabase {
                 title TEXT NOT NULL,
                 url TEXT NOT NULL,
                 folder TEXT,
+                position INTEGER NOT NULL DEFAULT 0,
                 created_at TEXT NOT NULL,
                 tags TEXT
             )
@@ -63,6 +368,29 @@ impl SqliteDatabase {
         .execute(pool)
         .await?;
 
+        // Bookmarks saved before they could be reordered keep the order
+        // they were added in
+        let bookmark_columns = sqlx::query_as::<_, (String,)>("SELECT name FROM pragma_table_info('bookmarks')")
+            .fetch_all(pool)
+            .await?;
+        if !bookmark_columns.iter().any(|(name,)| name == "position") {
+            let mut transaction = pool.begin().await?;
+            sqlx::query("ALTER TABLE bookmarks ADD COLUMN position INTEGER NOT NULL DEFAULT 0")
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query(
+                "UPDATE bookmarks SET position = ? * (1 + (
+                     SELECT COUNT(*) FROM bookmarks AS earlier
+                     WHERE earlier.folder IS bookmarks.folder
+                       AND (earlier.created_at, earlier.id) < (bookmarks.created_at, bookmarks.id)
+                 ))",
+            )
+            .bind(BOOKMARK_POSITION_STEP)
+            .execute(&mut *transaction)
+            .await?;
+            transaction.commit().await?;
+        }
+
         // Create history table
         sqlx::query(
             r#"
@@ -78,379 +406,3110 @@ impl SqliteDatabase {
         .execute(pool)
         .await?;
 
-        // Create indices for performance
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_history_visited_at ON history(visited_at DESC)")
-            .execute(pool)
+        // Columns added after the history table was first created
+        let history_columns = sqlx::query_as::<_, (String,)>("SELECT name FROM pragma_table_info('history')")
+            .fetch_all(pool)
             .await?;
+        if !history_columns.iter().any(|(name,)| name == "url_data") {
+            sqlx::query("ALTER TABLE history ADD COLUMN url_data TEXT")
+                .execute(pool)
+                .await?;
+        }
 
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_bookmarks_folder ON bookmarks(folder)")
-            .execute(pool)
-            .await?;
+        // Each visit to a history entry, which keeps their count and the last
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS visits (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                history_id INTEGER NOT NULL,
+                visited_at TEXT NOT NULL,
+                visit_type TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
 
-        Ok(())
-    }
+        // Create profile metadata table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
 
-    pub fn get_pool(&self) -> &SqlitePool {
-        &self.pool
-    }
-}
+        // Create settings table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
 
-// Implement TabRepository
-#[async_trait]
-impl TabRepository for SqliteDatabase {
-    async fn save(&self, tab: &Tab) -> Result<()> {
+        // Create user stylesheet rules table; `rule` holds the rule as JSON
         sqlx::query(
-            "INSERT OR REPLACE INTO tabs (id, title, url, is_private, created_at, last_accessed)
-             VALUES (?, ?, ?, ?, ?, ?)",
+            r#"
+            CREATE TABLE IF NOT EXISTS user_styles (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                host_pattern TEXT NOT NULL,
+                rule TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
         )
-        .bind(tab.id.to_string())
-        .bind(&tab.title)
-        .bind(tab.url.as_ref().map(|u| u.as_str()))
-        .bind(tab.is_private)
-        .bind(tab.created_at.to_rfc3339())
-        .bind(tab.last_accessed.to_rfc3339())
-        .execute(&self.pool)
+        .execute(pool)
         .await?;
 
-        Ok(())
-    }
+        // Create user scripts table; `matches` holds the patterns as a JSON array
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS user_scripts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                matches TEXT NOT NULL,
+                code TEXT NOT NULL,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                run_at TEXT NOT NULL,
+                allow_internal INTEGER NOT NULL DEFAULT 0,
+                allow_private INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
 
-    async fn find_by_id(&self, id: TabId) -> Result<Option<Tab>> {
-        let result = sqlx::query_as::<_, (String, String, Option<String>, bool, String, String)>(
-            "SELECT id, title, url, is_private, created_at, last_accessed FROM tabs WHERE id = ?",
+        // Values user scripts store with GM_setValue, as JSON
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS user_script_values (
+                script_id INTEGER NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (script_id, key)
+            )
+            "#,
         )
-        .bind(id.to_string())
-        .fetch_optional(&self.pool)
+        .execute(pool)
         .await?;
 
-        Ok(result.map(|(_id_str, title, url, is_private, created_at, last_accessed)| {
-            Tab {
-                id: TabId::new(), // Parse from string in production
-                title,
-                url: url.and_then(|u| ValidatedUrl::parse(&u).ok()),
-                is_loading: false,
-                is_private,
-                created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
-                    .unwrap()
-                    .with_timezone(&chrono::Utc),
-                last_accessed: chrono::DateTime::parse_from_rfc3339(&last_accessed)
-                    .unwrap()
-                    .with_timezone(&chrono::Utc),
-                favicon_url: None,
-            }
-        }))
-    }
+        // Search engines; templates hold `%s` where the query goes
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS search_engines (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                keyword TEXT NOT NULL UNIQUE,
+                query_template TEXT NOT NULL,
+                suggest_template TEXT,
+                is_default INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
 
-    async fn find_all(&self) -> Result<Vec<Tab>> {
-        let results = sqlx::query_as::<_, (String, String, Option<String>, bool, String, String)>(
-            "SELECT id, title, url, is_private, created_at, last_accessed FROM tabs
-             ORDER BY last_accessed DESC",
+        // Files written to the downloads directory, such as saved pages
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS downloads (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL,
+                path TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
         )
-        .fetch_all(&self.pool)
+        .execute(pool)
         .await?;
 
-        Ok(results
-            .into_iter()
-            .map(|(_id_str, title, url, is_private, created_at, last_accessed)| Tab {
-                id: TabId::new(),
-                title,
-                url: url.and_then(|u| ValidatedUrl::parse(&u).ok()),
-                is_loading: false,
-                is_private,
-                created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
-                    .unwrap()
-                    .with_timezone(&chrono::Utc),
-                last_accessed: chrono::DateTime::parse_from_rfc3339(&last_accessed)
-                    .unwrap()
-                    .with_timezone(&chrono::Utc),
-                favicon_url: None,
-            })
-            .collect())
-    }
+        // Certificate authorities trusted besides the built-in roots, as PEM
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS custom_cas (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                pem TEXT NOT NULL,
+                fingerprint TEXT NOT NULL UNIQUE,
+                added_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
 
-    async fn delete(&self, id: TabId) -> Result<()> {
-        sqlx::query("DELETE FROM tabs WHERE id = ?")
-            .bind(id.to_string())
-            .execute(&self.pool)
-            .await?;
-        Ok(())
-    }
+        // Per-site overrides of global settings; NULL keeps the global value
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS site_settings (
+                origin TEXT PRIMARY KEY,
+                javascript INTEGER,
+                images INTEGER,
+                cookies TEXT,
+                user_agent TEXT
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
 
-    async fn save_session(&self, tabs: Vec<Tab>) -> Result<()> {
-        for tab in tabs {
-            TabRepository::save(self, &tab).await?;
+        // Columns added after the site_settings table was first created
+        let site_settings_columns =
+            sqlx::query_as::<_, (String,)>("SELECT name FROM pragma_table_info('site_settings')")
+                .fetch_all(pool)
+                .await?;
+        for column in ["content_blocking", "dark_pages"] {
+            if !site_settings_columns.iter().any(|(name,)| name == column) {
+                sqlx::query(&format!("ALTER TABLE site_settings ADD COLUMN {} INTEGER", column))
+                    .execute(pool)
+                    .await?;
+            }
         }
-        Ok(())
-    }
 
-    async fn restore_session(&self) -> Result<Vec<Tab>> {
-        TabRepository::find_all(self).await
-    }
-}
+        // localStorage items of each origin, in the order first set
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS web_storage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                origin TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                UNIQUE(origin, key)
+            )
+            "#,
+        )
+        .execute(pool)
+        .await?;
 
-// Implement BookmarkRepository
-#[async_trait]
-impl BookmarkRepository for SqliteDatabase {
-    async fn save(&self, bookmark: &Bookmark) -> Result<i64> {
-        let result = sqlx::query(
-            "INSERT INTO bookmarks (title, url, folder, created_at, tags)
-             VALUES (?, ?, ?, ?, ?)",
+        // Documents kept for offline browsing, keyed by URL without fragment
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS page_cache (
+                url TEXT PRIMARY KEY,
+                origin TEXT NOT NULL,
+                title TEXT NOT NULL,
+                content_type TEXT,
+                body BLOB NOT NULL,
+                fetched_at TEXT NOT NULL
+            )
+            "#,
         )
-        .bind(&bookmark.title)
-        .bind(bookmark.url.as_str())
-        .bind(&bookmark.folder)
-        .bind(bookmark.created_at.to_rfc3339())
-        .bind(serde_json::to_string(&bookmark.tags)?)
-        .execute(&self.pool)
+        .execute(pool)
         .await?;
 
-        Ok(result.last_insert_rowid())
+        // Create indices for performance
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_history_visited_at ON history(visited_at DESC)")
+            .execute(pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_visits_history_id ON visits(history_id, visited_at)")
+            .execute(pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_bookmarks_folder ON bookmarks(folder, position)")
+            .execute(pool)
+            .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_page_cache_origin ON page_cache(origin, fetched_at DESC)")
+            .execute(pool)
+            .await?;
+
+        Ok(())
     }
 
-    async fn find_by_id(&self, id: i64) -> Result<Option<Bookmark>> {
-        let result = sqlx::query_as::<_, (i64, String, String, Option<String>, String, String)>(
-            "SELECT id, title, url, folder, created_at, tags FROM bookmarks WHERE id = ?",
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await?;
+    async fn load_key_material(pool: &SqlitePool) -> Result<Option<KeyMaterial>> {
+        let rows = sqlx::query_as::<_, (String, String)>("SELECT key, value FROM meta")
+            .fetch_all(pool)
+            .await?;
+        let value = |key: &str| {
+            rows.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.as_str())
+        };
 
-        Ok(result.and_then(|(id, title, url, folder, created_at, tags)| {
-            ValidatedUrl::parse(&url).ok().map(|url| Bookmark {
-                id,
-                title,
-                url,
-                folder,
-                created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
-                    .unwrap()
-                    .with_timezone(&chrono::Utc),
-                tags: serde_json::from_str(&tags).unwrap_or_default(),
-            })
+        let Some(scheme) = value("encryption") else {
+            return Ok(None);
+        };
+        let missing = || corrupt("encrypted profile is missing its key material");
+        let number = |key: &str| -> Result<u32> { value(key).ok_or_else(missing)?.parse().map_err(corrupt) };
+        let derivation = match scheme {
+            PROFILE_ENCRYPTION_SCHEME => KeyDerivation::Argon2id(Argon2Cost {
+                memory_kib: number("kdf_memory_kib")?,
+                passes: number("kdf_passes")?,
+                lanes: number("kdf_lanes")?,
+            }),
+            // Read so profiles encrypted before Argon2id still open
+            LEGACY_ENCRYPTION_SCHEME => KeyDerivation::Pbkdf2(number("kdf_iterations")?),
+            other => return Err(corrupt(format!("unsupported profile encryption {}", other))),
+        };
+
+        Ok(Some(KeyMaterial {
+            salt: BASE64
+                .decode(value("kdf_salt").ok_or_else(missing)?)
+                .map_err(corrupt)?,
+            derivation,
+            key_check: value("key_check").ok_or_else(missing)?.to_string(),
         }))
     }
 
-    async fn find_all(&self) -> Result<Vec<Bookmark>> {
-        let results = sqlx::query_as::<_, (i64, String, String, Option<String>, String, String)>(
-            "SELECT id, title, url, folder, created_at, tags FROM bookmarks
-             ORDER BY created_at DESC",
-        )
-        .fetch_all(&self.pool)
-        .await?;
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
 
-        Ok(results
-            .into_iter()
-            .filter_map(|(id, title, url, folder, created_at, tags)| {
-                ValidatedUrl::parse(&url).ok().map(|url| Bookmark {
-                    id,
-                    title,
-                    url,
-                    folder,
-                    created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
-                        .unwrap()
-                        .with_timezone(&chrono::Utc),
-                    tags: serde_json::from_str(&tags).unwrap_or_default(),
+    /// Encrypt the profile with a key derived from `passphrase`
+    ///
+    /// History, the saved session's tab titles and URLs, and the downloads
+    /// list are encrypted, and cached pages are deleted. Bookmarks, web
+    /// storage, site settings, settings, window geometry and the times of
+    /// visits stay in plain text.
+    pub async fn encrypt_profile(&mut self, passphrase: &str) -> Result<()> {
+        self.encrypt_profile_with(passphrase, KDF_COST).await
+    }
+
+    async fn encrypt_profile_with(&mut self, passphrase: &str, cost: Argon2Cost) -> Result<()> {
+        if self.is_encrypted() {
+            return Err(StorageError::Encryption("Profile is already encrypted".to_string()));
+        }
+
+        let (cipher, material) = ProfileCipher::create(passphrase, cost)?;
+        let meta = [
+            ("encryption", PROFILE_ENCRYPTION_SCHEME.to_string()),
+            ("kdf_salt", BASE64.encode(&material.salt)),
+            ("kdf_memory_kib", cost.memory_kib.to_string()),
+            ("kdf_passes", cost.passes.to_string()),
+            ("kdf_lanes", cost.lanes.to_string()),
+            ("key_check", material.key_check),
+        ];
+        self.rewrite_profile(Some(cipher), &meta).await
+    }
+
+    /// Store the profile in plain text again; it must be unlocked
+    pub async fn decrypt_profile(&mut self) -> Result<()> {
+        if !self.is_encrypted() {
+            return Err(StorageError::Encryption("Profile is not encrypted".to_string()));
+        }
+        self.rewrite_profile(None, &[]).await
+    }
+
+    /// Re-encode every encrypted column for `cipher` and replace the profile
+    /// metadata, then scrub the old encoding from free pages and the WAL
+    async fn rewrite_profile(
+        &mut self,
+        cipher: Option<ProfileCipher>,
+        meta: &[(&str, String)],
+    ) -> Result<()> {
+        let rows = sqlx::query_as::<_, HistoryRow>(&format!("SELECT {} FROM history", HISTORY_COLUMNS))
+            .fetch_all(&self.pool)
+            .await?;
+        let visits = rows
+            .into_iter()
+            .map(|row| self.decode_visit(row))
+            .collect::<Result<Vec<_>>>()?;
+        let tabs = sqlx::query_as::<_, (String, String, Option<String>)>("SELECT id, title, url FROM tabs")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(id, title, url)| Ok((id, self.unseal(title)?, url.map(|url| self.unseal(url)).transpose()?)))
+            .collect::<Result<Vec<_>>>()?;
+        let downloads = sqlx::query_as::<_, (i64, String, String)>("SELECT id, url, path FROM downloads")
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(|(id, url, path)| Ok((id, self.unseal(url)?, self.unseal(path)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let previous = std::mem::replace(&mut self.cipher, cipher);
+        let encoded = (|| {
+            let stored = visits
+                .iter()
+                .map(|visit| self.encode_visit(&visit.url, &visit.title))
+                .collect::<Result<Vec<_>>>()?;
+            let tabs = tabs
+                .iter()
+                .map(|(id, title, url)| {
+                    Ok((id, self.seal(title)?, url.as_deref().map(|url| self.seal(url)).transpose()?))
                 })
+                .collect::<Result<Vec<_>>>()?;
+            let downloads = downloads
+                .iter()
+                .map(|(id, url, path)| Ok((*id, self.seal(url)?, self.seal(path)?)))
+                .collect::<Result<Vec<_>>>()?;
+            Ok((stored, tabs, downloads))
+        })();
+        let (stored, tabs, downloads) = match encoded {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                self.cipher = previous;
+                return Err(e);
+            }
+        };
+
+        let written = async {
+            let mut transaction = self.pool.begin().await?;
+            sqlx::query("DELETE FROM history")
+                .execute(&mut *transaction)
+                .await?;
+            // Cached pages are not encrypted, so they would give history away
+            if self.cipher.is_some() {
+                sqlx::query("DELETE FROM page_cache")
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            for (visit, stored) in visits.iter().zip(&stored) {
+                sqlx::query(
+                    "INSERT INTO history (id, url, url_data, title, visited_at, visit_count)
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(visit.id)
+                .bind(&stored.url)
+                .bind(&stored.url_data)
+                .bind(&stored.title)
+                .bind(visit.visited_at.to_rfc3339())
+                .bind(visit.visit_count)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            for (id, title, url) in &tabs {
+                sqlx::query("UPDATE tabs SET title = ?, url = ? WHERE id = ?")
+                    .bind(title)
+                    .bind(url)
+                    .bind(id)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            for (id, url, path) in &downloads {
+                sqlx::query("UPDATE downloads SET url = ?, path = ? WHERE id = ?")
+                    .bind(url)
+                    .bind(path)
+                    .bind(id)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+
+            sqlx::query("DELETE FROM meta").execute(&mut *transaction).await?;
+            for (key, value) in meta {
+                sqlx::query("INSERT INTO meta (key, value) VALUES (?, ?)")
+                    .bind(key)
+                    .bind(value)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            transaction.commit().await
+        }
+        .await;
+
+        if let Err(e) = written {
+            self.cipher = previous;
+            return Err(StorageError::database("Failed to rewrite the profile", e));
+        }
+
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        self.checkpoint().await?;
+        tracing::info!(
+            "Rewrote {} history entries, {} tabs and {} downloads",
+            visits.len(),
+            tabs.len(),
+            downloads.len()
+        );
+        Ok(())
+    }
+
+    /// `value` as stored outside history: sealed in an encrypted profile
+    fn seal(&self, value: &str) -> Result<String> {
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(value),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    /// A value stored by [`Self::seal`]
+    fn unseal(&self, stored: String) -> Result<String> {
+        match &self.cipher {
+            Some(cipher) => cipher.decrypt(&stored),
+            None => Ok(stored),
+        }
+    }
+
+    /// Title and URL columns of a saved tab
+    fn seal_tab(&self, tab: &Tab) -> Result<(String, Option<String>)> {
+        Ok((
+            self.seal(&tab.title)?,
+            tab.url.as_ref().map(|url| self.seal(url.as_str())).transpose()?,
+        ))
+    }
+
+    /// A saved tab's title and URL, dropping a URL that no longer parses
+    fn open_tab(&self, title: String, url: Option<String>) -> Result<(String, Option<ValidatedUrl>)> {
+        let url = url.map(|url| self.unseal(url)).transpose()?;
+        Ok((self.unseal(title)?, url.and_then(|url| ValidatedUrl::parse(&url).ok())))
+    }
+
+    /// Visits are keyed by the normalized URL
+    fn history_key(&self, url: &ValidatedUrl) -> String {
+        let url = url.normalized();
+        match &self.cipher {
+            Some(cipher) => cipher.blind_index(url.as_str()),
+            None => url.as_str().to_string(),
+        }
+    }
+
+    fn encode_visit(&self, url: &ValidatedUrl, title: &str) -> Result<StoredVisit> {
+        let url = &url.normalized();
+        Ok(match &self.cipher {
+            Some(cipher) => StoredVisit {
+                url: cipher.blind_index(url.as_str()),
+                url_data: Some(cipher.encrypt(url.as_str())?),
+                title: cipher.encrypt(title)?,
+            },
+            None => StoredVisit {
+                url: url.as_str().to_string(),
+                url_data: None,
+                title: title.to_string(),
+            },
+        })
+    }
+
+    fn decode_visit(&self, row: HistoryRow) -> Result<HistoryEntry> {
+        let (id, url, url_data, title, visited_at, visit_count) = row;
+        let (url, title) = match (&self.cipher, url_data) {
+            (Some(cipher), Some(url_data)) => (cipher.decrypt(&url_data)?, cipher.decrypt(&title)?),
+            (None, None) => (url, title),
+            _ => {
+                return Err(corrupt(format!(
+                    "history entry {} does not match the profile encryption",
+                    id
+                )))
+            }
+        };
+
+        Ok(HistoryEntry {
+            id,
+            url: ValidatedUrl::parse(&url).map_err(corrupt)?,
+            title,
+            visited_at: chrono::DateTime::parse_from_rfc3339(&visited_at)
+                .map_err(corrupt)?
+                .with_timezone(&chrono::Utc),
+            visit_count,
+        })
+    }
+
+    /// Decode rows, skipping any that are unreadable
+    fn decode_visits(&self, rows: Vec<HistoryRow>) -> Vec<HistoryEntry> {
+        rows.into_iter()
+            .filter_map(|row| match self.decode_visit(row) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    tracing::warn!("Skipping history entry: {:#}", e);
+                    None
+                }
             })
-            .collect())
+            .collect()
     }
 
-    async fn find_by_folder(&self, folder: &str) -> Result<Vec<Bookmark>> {
-        let results = sqlx::query_as::<_, (i64, String, String, Option<String>, String, String)>(
-            "SELECT id, title, url, folder, created_at, tags FROM bookmarks
-             WHERE folder = ? ORDER BY created_at DESC",
+    /// Insert a visit, or fold it into the existing row for its URL,
+    /// returning the row as stored
+    fn upsert_history<'q>(
+        entry: &'q NewHistoryEntry,
+        stored: &'q StoredVisit,
+    ) -> sqlx::query::QueryAs<'q, sqlx::Sqlite, HistoryRow, sqlx::sqlite::SqliteArguments<'q>> {
+        sqlx::query_as(
+            "INSERT INTO history (url, url_data, title, visited_at, visit_count)
+             VALUES (?, ?, ?, ?, ?)
+             ON CONFLICT(url) DO UPDATE SET
+                url_data = excluded.url_data,
+                title = excluded.title,
+                visited_at = excluded.visited_at,
+                visit_count = visit_count + excluded.visit_count
+             RETURNING id, url, url_data, title, visited_at, visit_count",
         )
-        .bind(folder)
-        .fetch_all(&self.pool)
+        .bind(&stored.url)
+        .bind(&stored.url_data)
+        .bind(&stored.title)
+        .bind(entry.visited_at.to_rfc3339())
+        .bind(entry.visit_count)
+    }
+
+    /// Record a visit to the history entry `history_id`
+    fn insert_visit(history_id: i64, entry: &NewHistoryEntry) -> SqliteQuery<'_> {
+        sqlx::query("INSERT INTO visits (history_id, visited_at, visit_type) VALUES (?, ?, ?)")
+            .bind(history_id)
+            .bind(entry.visited_at.to_rfc3339())
+            .bind(entry.visit_type.to_string())
+    }
+
+    /// Delete the history entries matching `filter`, with `bind` giving its
+    /// parameters, and their visits; returns how many entries were deleted
+    async fn delete_history(
+        &self,
+        filter: &str,
+        bind: impl for<'q> Fn(SqliteQuery<'q>) -> SqliteQuery<'q>,
+    ) -> Result<u64> {
+        let visits = format!("DELETE FROM visits WHERE history_id IN (SELECT id FROM history WHERE {})", filter);
+        let entries = format!("DELETE FROM history WHERE {}", filter);
+        let result = retry_busy(|| async {
+            let mut transaction = self.pool.begin().await?;
+            bind(sqlx::query(&visits)).execute(&mut *transaction).await?;
+            let result = bind(sqlx::query(&entries)).execute(&mut *transaction).await?;
+            transaction.commit().await?;
+            Ok(result)
+        })
         .await?;
+        Ok(result.rows_affected())
+    }
 
-        Ok(results
-            .into_iter()
-            .filter_map(|(id, title, url, folder, created_at, tags)| {
-                ValidatedUrl::parse(&url).ok().map(|url| Bookmark {
-                    id,
-                    title,
-                    url,
-                    folder,
-                    created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
-                        .unwrap()
-                        .with_timezone(&chrono::Utc),
-                    tags: serde_json::from_str(&tags).unwrap_or_default(),
-                })
-            })
-            .collect())
+    pub fn get_pool(&self) -> &SqlitePool {
+        &self.pool
     }
 
-    async fn search(&self, query: &str) -> Result<Vec<Bookmark>> {
-        let search_pattern = format!("%{}%", query);
-        let results = sqlx::query_as::<_, (i64, String, String, Option<String>, String, String)>(
-            "SELECT id, title, url, folder, created_at, tags FROM bookmarks
-             WHERE title LIKE ? OR url LIKE ? ORDER BY created_at DESC",
+    /// Copy the WAL back into the database and truncate it
+    pub async fn checkpoint(&self) -> Result<()> {
+        retry_busy(|| sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&self.pool))
+            .await
+            .map_err(|e| StorageError::database("Failed to checkpoint database", e))?;
+        Ok(())
+    }
+
+    pub async fn stats(&self) -> Result<DatabaseStats> {
+        let pragma = |name: &'static str| async move {
+            sqlx::query_as::<_, (i64,)>(name)
+                .fetch_one(&self.pool)
+                .await
+                .map(|(value,)| value)
+        };
+
+        let wal_bytes = match &self.wal_path {
+            Some(path) => tokio::fs::metadata(path).await.map(|m| m.len()).unwrap_or(0),
+            None => 0,
+        };
+
+        Ok(DatabaseStats {
+            page_size: pragma("PRAGMA page_size").await?,
+            page_count: pragma("PRAGMA page_count").await?,
+            freelist_count: pragma("PRAGMA freelist_count").await?,
+            wal_bytes,
+        })
+    }
+
+    /// Spread the bookmarks of every folder out again at
+    /// [`BOOKMARK_POSITION_STEP`] apart, keeping their order, so moves find
+    /// gaps to go into instead of renumbering the folder
+    pub async fn compact_bookmark_positions(&self) -> Result<()> {
+        // Under the write lock, so no move lands between the read and the
+        // writes
+        retry_busy(|| async {
+            let mut transaction = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+            let bookmarks = sqlx::query_as::<_, (i64, Option<String>, i64)>(
+                "SELECT id, folder, position FROM bookmarks ORDER BY folder, position, created_at, id",
+            )
+            .fetch_all(&mut *transaction)
+            .await?;
+            let (mut folder, mut rank) = (None, 0);
+            for (id, in_folder, position) in bookmarks {
+                if rank == 0 || in_folder != folder {
+                    (folder, rank) = (in_folder, 0);
+                }
+                rank += 1;
+                if position != rank * BOOKMARK_POSITION_STEP {
+                    sqlx::query("UPDATE bookmarks SET position = ? WHERE id = ?")
+                        .bind(rank * BOOKMARK_POSITION_STEP)
+                        .bind(id)
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+            }
+            transaction.commit().await
+        })
+        .await
+        .map_err(|e| StorageError::database("Failed to compact bookmark positions", e))?;
+        Ok(())
+    }
+
+    /// Checkpoint the WAL every `interval` so it doesn't grow unbounded, and
+    /// compact bookmark positions
+    pub fn spawn_maintenance(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let db = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            // The first tick completes immediately; nothing to do at startup
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                if let Err(e) = db.compact_bookmark_positions().await {
+                    tracing::warn!("Database maintenance failed: {:#}", e);
+                }
+                if let Err(e) = db.checkpoint().await {
+                    tracing::warn!("Database maintenance failed: {:#}", e);
+                }
+            }
+        })
+    }
+}
+
+impl From<sqlx::Error> for StorageError {
+    fn from(error: sqlx::Error) -> Self {
+        if is_busy(&error) {
+            Self::Locked(error.to_string())
+        } else {
+            Self::Database(error.to_string())
+        }
+    }
+}
+
+/// Stored data that no longer parses
+fn corrupt(error: impl std::fmt::Display) -> StorageError {
+    StorageError::Corrupt(error.to_string())
+}
+
+fn decode_visit_row((history_id, visited_at, visit_type): VisitRow) -> Result<Visit> {
+    Ok(Visit {
+        history_id,
+        visited_at: chrono::DateTime::parse_from_rfc3339(&visited_at)
+            .map_err(corrupt)?
+            .with_timezone(&chrono::Utc),
+        visit_type: VisitType::parse(&visit_type).ok_or_else(|| corrupt(format!("unknown visit type {}", visit_type)))?,
+    })
+}
+
+/// Whether an error is a transient lock conflict worth retrying
+fn is_busy(error: &sqlx::Error) -> bool {
+    const SQLITE_BUSY: i32 = 5;
+    const SQLITE_LOCKED: i32 = 6;
+
+    let Some(code) = error.as_database_error().and_then(|e| e.code()) else {
+        return false;
+    };
+    // Extended result codes keep the primary code in the low byte
+    matches!(code.parse::<i32>().map(|code| code & 0xff), Ok(SQLITE_BUSY | SQLITE_LOCKED))
+}
+
+/// Run a write, retrying busy errors with jittered exponential backoff
+async fn retry_busy<T, F, Fut>(mut operation: F) -> sqlx::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = sqlx::Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if attempt < BUSY_RETRY_ATTEMPTS && is_busy(&e) => {
+                let delay = BUSY_RETRY_DELAY * 2u32.pow(attempt - 1);
+                tracing::debug!("Database busy, retrying in about {:?}", delay);
+                tokio::time::sleep(delay + jitter(delay)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Random duration up to `max`, so retrying writers don't collide again
+fn jitter(max: Duration) -> Duration {
+    let random = RandomState::new().hash_one(std::time::SystemTime::now());
+    max.mul_f64((random % 1000) as f64 / 1000.0)
+}
+
+/// id, x, y, width, height, monitor, active_tab
+type WindowRow = (String, Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<String>, Option<String>);
+
+// Implement TabRepository
+#[async_trait]
+impl TabRepository for SqliteDatabase {
+    async fn save(&self, tab: &Tab) -> Result<()> {
+        let (title, url) = self.seal_tab(tab)?;
+        retry_busy(|| {
+            sqlx::query(
+                "INSERT OR REPLACE INTO tabs (id, title, url, is_private, created_at, last_accessed)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(tab.id.to_string())
+            .bind(&title)
+            .bind(&url)
+            .bind(tab.is_private)
+            .bind(tab.created_at.to_rfc3339())
+            .bind(tab.last_accessed.to_rfc3339())
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find_by_id(&self, id: TabId) -> Result<Option<Tab>> {
+        let result = sqlx::query_as::<_, (String, String, Option<String>, bool, String, String)>(
+            "SELECT id, title, url, is_private, created_at, last_accessed FROM tabs WHERE id = ?",
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((id_str, title, url, is_private, created_at, last_accessed)) = result else {
+            return Ok(None);
+        };
+        let (title, url) = self.open_tab(title, url)?;
+        Ok(Some(Tab {
+            id: TabId::parse(&id_str).unwrap_or(id),
+            title,
+            url,
+            is_loading: false,
+            is_private,
+            created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+            last_accessed: chrono::DateTime::parse_from_rfc3339(&last_accessed)
+                .unwrap()
+                .with_timezone(&chrono::Utc),
+            favicon_url: None,
+        }))
+    }
+
+    async fn find_all(&self) -> Result<Vec<Tab>> {
+        let results = sqlx::query_as::<_, (String, String, Option<String>, bool, String, String)>(
+            "SELECT id, title, url, is_private, created_at, last_accessed FROM tabs
+             ORDER BY last_accessed DESC",
         )
-        .bind(&search_pattern)
-        .bind(&search_pattern)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(results
+        results
             .into_iter()
-            .filter_map(|(id, title, url, folder, created_at, tags)| {
-                ValidatedUrl::parse(&url).ok().map(|url| Bookmark {
-                    id,
+            .map(|(id_str, title, url, is_private, created_at, last_accessed)| {
+                let (title, url) = self.open_tab(title, url)?;
+                Ok(Tab {
+                    id: TabId::parse(&id_str).unwrap_or_default(),
                     title,
                     url,
-                    folder,
+                    is_loading: false,
+                    is_private,
                     created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
                         .unwrap()
                         .with_timezone(&chrono::Utc),
-                    tags: serde_json::from_str(&tags).unwrap_or_default(),
+                    last_accessed: chrono::DateTime::parse_from_rfc3339(&last_accessed)
+                        .unwrap()
+                        .with_timezone(&chrono::Utc),
+                    favicon_url: None,
                 })
             })
-            .collect())
+            .collect()
     }
 
-    async fn delete(&self, id: i64) -> Result<()> {
-        sqlx::query("DELETE FROM bookmarks WHERE id = ?")
-            .bind(id)
-            .execute(&self.pool)
+    async fn delete(&self, id: TabId) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM tabs WHERE id = ?")
+                .bind(id.to_string())
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn save_session(&self, windows: Vec<SavedWindow>) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM windows").execute(&mut *transaction).await?;
+        for (position, window) in windows.iter().enumerate() {
+            let geometry = window.geometry.as_ref();
+            sqlx::query(
+                "INSERT INTO windows (id, position, x, y, width, height, monitor, active_tab)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(window.id.to_string())
+            .bind(position as i64)
+            .bind(geometry.map(|geometry| geometry.x))
+            .bind(geometry.map(|geometry| geometry.y))
+            .bind(geometry.map(|geometry| i64::from(geometry.width)))
+            .bind(geometry.map(|geometry| i64::from(geometry.height)))
+            .bind(geometry.and_then(|geometry| geometry.monitor.as_deref()))
+            .bind(window.active_tab.map(|tab| tab.to_string()))
+            .execute(&mut *transaction)
             .await?;
+            for tab in &window.tabs {
+                let (title, url) = self.seal_tab(tab)?;
+                sqlx::query(
+                    "INSERT OR REPLACE INTO tabs (id, title, url, is_private, created_at, last_accessed, window_id)
+                     VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(tab.id.to_string())
+                .bind(&title)
+                .bind(&url)
+                .bind(tab.is_private)
+                .bind(tab.created_at.to_rfc3339())
+                .bind(tab.last_accessed.to_rfc3339())
+                .bind(window.id.to_string())
+                .execute(&mut *transaction)
+                .await?;
+            }
+        }
+        transaction.commit().await?;
         Ok(())
     }
 
-    async fn update(&self, bookmark: &Bookmark) -> Result<()> {
-        sqlx::query(
-            "UPDATE bookmarks SET title = ?, url = ?, folder = ?, tags = ? WHERE id = ?",
+    async fn restore_session(&self) -> Result<Vec<SavedWindow>> {
+        let rows = sqlx::query_as::<_, WindowRow>(
+            "SELECT id, x, y, width, height, monitor, active_tab FROM windows ORDER BY position",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        let mut windows: Vec<SavedWindow> = rows
+            .into_iter()
+            .filter_map(|(id, x, y, width, height, monitor, active_tab)| {
+                let geometry = match (x, y, width, height) {
+                    (Some(x), Some(y), Some(width), Some(height)) => Some(WindowGeometry {
+                        x: i32::try_from(x).ok()?,
+                        y: i32::try_from(y).ok()?,
+                        width: u32::try_from(width).ok()?,
+                        height: u32::try_from(height).ok()?,
+                        monitor,
+                    }),
+                    _ => None,
+                };
+                Some(SavedWindow {
+                    id: WindowId::parse(&id)?,
+                    geometry,
+                    active_tab: active_tab.as_deref().and_then(TabId::parse),
+                    tabs: Vec::new(),
+                })
+            })
+            .collect();
+
+        let tabs = sqlx::query_as::<_, (String, String, Option<String>, bool, String, String, Option<String>)>(
+            "SELECT id, title, url, is_private, created_at, last_accessed, window_id FROM tabs
+             ORDER BY created_at",
         )
-        .bind(&bookmark.title)
-        .bind(bookmark.url.as_str())
-        .bind(&bookmark.folder)
-        .bind(serde_json::to_string(&bookmark.tags)?)
-        .bind(bookmark.id)
-        .execute(&self.pool)
+        .fetch_all(&self.pool)
         .await?;
+        let mut orphans = Vec::new();
+        for (id, title, url, is_private, created_at, last_accessed, window_id) in tabs {
+            let (title, url) = self.open_tab(title, url)?;
+            let tab = Tab {
+                id: TabId::parse(&id).unwrap_or_default(),
+                title,
+                url,
+                is_loading: false,
+                is_private,
+                created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+                    .map_err(corrupt)?
+                    .with_timezone(&chrono::Utc),
+                last_accessed: chrono::DateTime::parse_from_rfc3339(&last_accessed)
+                    .map_err(corrupt)?
+                    .with_timezone(&chrono::Utc),
+                favicon_url: None,
+            };
+            let window_id = window_id.as_deref().and_then(WindowId::parse);
+            match windows.iter_mut().find(|window| Some(window.id) == window_id) {
+                Some(window) => window.tabs.push(tab),
+                None => orphans.push(tab),
+            }
+        }
+        // Tabs stored without a window, or whose window row is gone, such
+        // as those of a session saved before windows were, join the first
+        if !orphans.is_empty() {
+            if windows.is_empty() {
+                windows.push(SavedWindow {
+                    id: WindowId::new(),
+                    geometry: None,
+                    active_tab: None,
+                    tabs: Vec::new(),
+                });
+            }
+            windows[0].tabs.extend(orphans);
+        }
+        windows.retain(|window| !window.tabs.is_empty());
+        Ok(windows)
+    }
+
+    async fn clear_session(&self) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        sqlx::query("DELETE FROM tabs").execute(&mut *transaction).await?;
+        sqlx::query("DELETE FROM windows").execute(&mut *transaction).await?;
+        transaction.commit().await?;
         Ok(())
     }
 }
 
-// Implement HistoryRepository
+// Implement BookmarkRepository
 #[async_trait]
-impl HistoryRepository for SqliteDatabase {
-    async fn add(&self, entry: &HistoryEntry) -> Result<i64> {
-        let result = sqlx::query(
-            "INSERT INTO history (url, title, visited_at, visit_count)
-             VALUES (?, ?, ?, ?)
-             ON CONFLICT(url) DO UPDATE SET
-                title = excluded.title,
-                visited_at = excluded.visited_at,
-                visit_count = visit_count + 1",
-        )
-        .bind(entry.url.as_str())
-        .bind(&entry.title)
-        .bind(entry.visited_at.to_rfc3339())
-        .bind(entry.visit_count)
-        .execute(&self.pool)
+impl BookmarkRepository for SqliteDatabase {
+    async fn save(&self, bookmark: NewBookmark) -> Result<Bookmark> {
+        let tags = serde_json::to_string(&bookmark.tags)
+            .map_err(|e| StorageError::database("Failed to encode bookmark tags", e))?;
+        // New bookmarks go last in their folder
+        let (id, position, created_at) = retry_busy(|| {
+            sqlx::query_as::<_, (i64, i64, String)>(
+                "INSERT INTO bookmarks (title, url, folder, position, created_at, tags)
+                 VALUES (?, ?, ?, (SELECT COALESCE(MAX(position), 0) + ? FROM bookmarks WHERE folder IS ?), ?, ?)
+                 RETURNING id, position, created_at",
+            )
+            .bind(&bookmark.title)
+            .bind(bookmark.url.as_str())
+            .bind(&bookmark.folder)
+            .bind(BOOKMARK_POSITION_STEP)
+            .bind(&bookmark.folder)
+            .bind(bookmark.created_at.to_rfc3339())
+            .bind(&tags)
+            .fetch_one(&self.pool)
+        })
         .await?;
 
-        Ok(result.last_insert_rowid())
+        Ok(Bookmark {
+            id,
+            title: bookmark.title,
+            url: bookmark.url,
+            folder: bookmark.folder,
+            position,
+            created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+                .map_err(corrupt)?
+                .with_timezone(&chrono::Utc),
+            tags: bookmark.tags,
+        })
     }
 
-    async fn find_by_url(&self, url: &ValidatedUrl) -> Result<Option<HistoryEntry>> {
-        let result = sqlx::query_as::<_, (i64, String, String, String, i32)>(
-            "SELECT id, url, title, visited_at, visit_count FROM history WHERE url = ?",
+    async fn find_by_id(&self, id: i64) -> Result<Option<Bookmark>> {
+        let result = sqlx::query_as::<_, BookmarkRow>(
+            "SELECT id, title, url, folder, position, created_at, tags FROM bookmarks WHERE id = ?",
         )
-        .bind(url.as_str())
+        .bind(id)
         .fetch_optional(&self.pool)
         .await?;
 
-        Ok(result.and_then(|(id, url, title, visited_at, visit_count)| {
-            ValidatedUrl::parse(&url).ok().map(|url| HistoryEntry {
-                id,
-                url,
-                title,
-                visited_at: chrono::DateTime::parse_from_rfc3339(&visited_at)
-                    .unwrap()
-                    .with_timezone(&chrono::Utc),
-                visit_count,
-            })
-        }))
+        Ok(result.map(bookmark_from_row).transpose()?.flatten())
     }
 
-    async fn search(&self, query: &str, limit: i32) -> Result<Vec<HistoryEntry>> {
-        let search_pattern = format!("%{}%", query);
-        let results = sqlx::query_as::<_, (i64, String, String, String, i32)>(
-            "SELECT id, url, title, visited_at, visit_count FROM history
-             WHERE title LIKE ? OR url LIKE ?
-             ORDER BY visited_at DESC LIMIT ?",
+    async fn find_all(&self) -> Result<Vec<Bookmark>> {
+        let results = sqlx::query_as::<_, BookmarkRow>(
+            "SELECT id, title, url, folder, position, created_at, tags FROM bookmarks
+             ORDER BY folder, position, created_at, id",
         )
-        .bind(&search_pattern)
-        .bind(&search_pattern)
-        .bind(limit)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(results
-            .into_iter()
-            .filter_map(|(id, url, title, visited_at, visit_count)| {
-                ValidatedUrl::parse(&url).ok().map(|url| HistoryEntry {
-                    id,
-                    url,
-                    title,
-                    visited_at: chrono::DateTime::parse_from_rfc3339(&visited_at)
-                        .unwrap()
-                        .with_timezone(&chrono::Utc),
-                    visit_count,
-                })
-            })
-            .collect())
+        results.into_iter().filter_map(|row| bookmark_from_row(row).transpose()).collect()
     }
 
-    async fn get_recent(&self, limit: i32) -> Result<Vec<HistoryEntry>> {
-        let results = sqlx::query_as::<_, (i64, String, String, String, i32)>(
-            "SELECT id, url, title, visited_at, visit_count FROM history
-             ORDER BY visited_at DESC LIMIT ?",
+    async fn find_by_folder(&self, folder: &str) -> Result<Vec<Bookmark>> {
+        let results = sqlx::query_as::<_, BookmarkRow>(
+            "SELECT id, title, url, folder, position, created_at, tags FROM bookmarks
+             WHERE folder = ? ORDER BY position, created_at, id",
         )
-        .bind(limit)
+        .bind(folder)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(results
-            .into_iter()
-            .filter_map(|(id, url, title, visited_at, visit_count)| {
-                ValidatedUrl::parse(&url).ok().map(|url| HistoryEntry {
-                    id,
-                    url,
-                    title,
-                    visited_at: chrono::DateTime::parse_from_rfc3339(&visited_at)
-                        .unwrap()
-                        .with_timezone(&chrono::Utc),
-                    visit_count,
-                })
-            })
-            .collect())
+        results.into_iter().filter_map(|row| bookmark_from_row(row).transpose()).collect()
     }
 
-    async fn delete_by_url(&self, url: &ValidatedUrl) -> Result<()> {
-        sqlx::query("DELETE FROM history WHERE url = ?")
-            .bind(url.as_str())
-            .execute(&self.pool)
-            .await?;
+    async fn search(&self, query: &str) -> Result<Vec<Bookmark>> {
+        let search_pattern = format!("%{}%", query);
+        let results = sqlx::query_as::<_, BookmarkRow>(
+            "SELECT id, title, url, folder, position, created_at, tags FROM bookmarks
+             WHERE title LIKE ? OR url LIKE ? ORDER BY created_at DESC",
+        )
+        .bind(&search_pattern)
+        .bind(&search_pattern)
+        .fetch_all(&self.pool)
+        .await?;
+
+        results.into_iter().filter_map(|row| bookmark_from_row(row).transpose()).collect()
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM bookmarks WHERE id = ?")
+                .bind(id)
+                .execute(&self.pool)
+        })
+        .await?;
         Ok(())
     }
 
-    async fn clear_all(&self) -> Result<()> {
-        sqlx::query("DELETE FROM history")
+    async fn update(&self, bookmark: &Bookmark) -> Result<()> {
+        let tags = serde_json::to_string(&bookmark.tags)
+            .map_err(|e| StorageError::database("Failed to encode bookmark tags", e))?;
+        retry_busy(|| {
+            sqlx::query(
+                "UPDATE bookmarks SET title = ?, url = ?, folder = ?, tags = ? WHERE id = ?",
+            )
+            .bind(&bookmark.title)
+            .bind(bookmark.url.as_str())
+            .bind(&bookmark.folder)
+            .bind(&tags)
+            .bind(bookmark.id)
             .execute(&self.pool)
-            .await?;
+        })
+        .await?;
         Ok(())
     }
-
+    async fn restore(&self, bookmarks: &[Bookmark]) -> Result<()> {
+        let tags = bookmarks
+            .iter()
+            .map(|bookmark| serde_json::to_string(&bookmark.tags))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| StorageError::database("Failed to encode bookmark tags", e))?;
+        // Ids are never handed out twice, so a deleted bookmark's is still free
+        retry_busy(|| async {
+            let mut transaction = self.pool.begin().await?;
+            for (bookmark, tags) in bookmarks.iter().zip(&tags) {
+                sqlx::query(
+                    "INSERT INTO bookmarks (id, title, url, folder, position, created_at, tags)
+                     VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(bookmark.id)
+                .bind(&bookmark.title)
+                .bind(bookmark.url.as_str())
+                .bind(&bookmark.folder)
+                .bind(bookmark.position)
+                .bind(bookmark.created_at.to_rfc3339())
+                .bind(tags)
+                .execute(&mut *transaction)
+                .await?;
+            }
+            transaction.commit().await
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn move_to(&self, id: i64, folder: Option<&str>, index: usize) -> Result<()> {
+        // Taking the write lock before reading the folder keeps another move
+        // from renumbering it between the read and the writes
+        retry_busy(|| async {
+            let mut transaction = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+            let found = sqlx::query_as::<_, (i64,)>("SELECT id FROM bookmarks WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&mut *transaction)
+                .await?;
+            if found.is_none() {
+                return Ok(false);
+            }
+            let siblings = sqlx::query_as::<_, (i64, i64)>(
+                "SELECT id, position FROM bookmarks WHERE folder IS ? AND id != ?
+                 ORDER BY position, created_at, id",
+            )
+            .bind(folder)
+            .bind(id)
+            .fetch_all(&mut *transaction)
+            .await?;
+            sqlx::query("UPDATE bookmarks SET folder = ? WHERE id = ?")
+                .bind(folder)
+                .bind(id)
+                .execute(&mut *transaction)
+                .await?;
+            for (moved, position) in bookmark_move_positions(id, &siblings, index) {
+                sqlx::query("UPDATE bookmarks SET position = ? WHERE id = ?")
+                    .bind(position)
+                    .bind(moved)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            transaction.commit().await?;
+            Ok(true)
+        })
+        .await?
+        .then_some(())
+        .ok_or(StorageError::NotFound(id))
+    }
+}
+
+/// id, title, url, folder, position, created_at, tags
+type BookmarkRow = (i64, String, String, Option<String>, i64, String, String);
+
+/// Bookmark read back from a row, unless its URL no longer parses
+/// The bookmark stored in `row`, or none if its URL no longer parses
+fn bookmark_from_row((id, title, url, folder, position, created_at, tags): BookmarkRow) -> Result<Option<Bookmark>> {
+    let Ok(url) = ValidatedUrl::parse(&url) else {
+        return Ok(None);
+    };
+    Ok(Some(Bookmark {
+        id,
+        title,
+        url,
+        folder,
+        position,
+        created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+            .map_err(corrupt)?
+            .with_timezone(&chrono::Utc),
+        tags: serde_json::from_str(&tags).unwrap_or_default(),
+    }))
+}
+
+// Implement HistoryRepository
+#[async_trait]
+impl HistoryRepository for SqliteDatabase {
+    async fn add(&self, entry: NewHistoryEntry) -> Result<HistoryEntry> {
+        let stored = self.encode_visit(&entry.url, &entry.title)?;
+        let row = retry_busy(|| async {
+            let mut transaction = self.pool.begin().await?;
+            let row = Self::upsert_history(&entry, &stored).fetch_one(&mut *transaction).await?;
+            Self::insert_visit(row.0, &entry).execute(&mut *transaction).await?;
+            transaction.commit().await?;
+            Ok(row)
+        })
+        .await?;
+        self.decode_visit(row)
+    }
+
+    async fn add_batch(&self, entries: &[NewHistoryEntry]) -> Result<()> {
+        let stored = entries
+            .iter()
+            .map(|entry| self.encode_visit(&entry.url, &entry.title))
+            .collect::<Result<Vec<_>>>()?;
+        retry_busy(|| async {
+            let mut transaction = self.pool.begin().await?;
+            for (entry, stored) in entries.iter().zip(&stored) {
+                let (id, ..) = Self::upsert_history(entry, stored)
+                    .fetch_one(&mut *transaction)
+                    .await?;
+                Self::insert_visit(id, entry).execute(&mut *transaction).await?;
+            }
+            transaction.commit().await
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn find_by_url(&self, url: &ValidatedUrl) -> Result<Option<HistoryEntry>> {
+        let result = sqlx::query_as::<_, HistoryRow>(&format!(
+            "SELECT {} FROM history WHERE url = ?",
+            HISTORY_COLUMNS
+        ))
+        .bind(self.history_key(url))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(result.and_then(|row| self.decode_visits(vec![row]).pop()))
+    }
+
+    async fn visited(&self, urls: &[ValidatedUrl]) -> Result<Vec<bool>> {
+        let keys: Vec<String> = urls.iter().map(|url| self.history_key(url)).collect();
+        let mut found = HashSet::new();
+        for chunk in keys.chunks(VISITED_QUERY_CHUNK) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let sql = format!("SELECT url FROM history WHERE url IN ({})", placeholders);
+            let mut query = sqlx::query_scalar::<_, String>(&sql);
+            for key in chunk {
+                query = query.bind(key);
+            }
+            found.extend(query.fetch_all(&self.pool).await?);
+        }
+        Ok(keys.iter().map(|key| found.contains(key)).collect())
+    }
+
+    async fn search(&self, query: &str, limit: i32) -> Result<Vec<HistoryEntry>> {
+        // Encrypted columns can't be matched in SQL, so scan and decrypt
+        if self.is_encrypted() {
+            let rows = sqlx::query_as::<_, HistoryRow>(&format!(
+                "SELECT {} FROM history ORDER BY visited_at DESC",
+                HISTORY_COLUMNS
+            ))
+            .fetch_all(&self.pool)
+            .await?;
+
+            let query = query.to_lowercase();
+            return Ok(self
+                .decode_visits(rows)
+                .into_iter()
+                .filter(|entry| {
+                    entry.title.to_lowercase().contains(&query)
+                        || entry.url.as_str().to_lowercase().contains(&query)
+                })
+                .take(limit.max(0) as usize)
+                .collect());
+        }
+
+        let search_pattern = format!("%{}%", query);
+        let results = sqlx::query_as::<_, HistoryRow>(&format!(
+            "SELECT {} FROM history
+             WHERE title LIKE ? OR url LIKE ?
+             ORDER BY visited_at DESC LIMIT ?",
+            HISTORY_COLUMNS
+        ))
+        .bind(&search_pattern)
+        .bind(&search_pattern)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(self.decode_visits(results))
+    }
+
+    async fn get_recent(&self, limit: i32) -> Result<Vec<HistoryEntry>> {
+        let results = sqlx::query_as::<_, HistoryRow>(&format!(
+            "SELECT {} FROM history ORDER BY visited_at DESC LIMIT ?",
+            HISTORY_COLUMNS
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(self.decode_visits(results))
+    }
+
+    async fn visits(&self, history_ids: &[i64]) -> Result<Vec<Visit>> {
+        let mut visits = Vec::new();
+        for chunk in history_ids.chunks(VISITED_QUERY_CHUNK) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let sql = format!(
+                "SELECT history_id, visited_at, visit_type FROM visits WHERE history_id IN ({})",
+                placeholders
+            );
+            let mut query = sqlx::query_as::<_, VisitRow>(&sql);
+            for id in chunk {
+                query = query.bind(id);
+            }
+            for row in query.fetch_all(&self.pool).await? {
+                visits.push(decode_visit_row(row)?);
+            }
+        }
+        visits.sort_by_key(|visit| visit.visited_at);
+        Ok(visits)
+    }
+
+    async fn delete_by_url(&self, url: &ValidatedUrl) -> Result<()> {
+        let key = self.history_key(url);
+        self.delete_history("url = ?", |query| query.bind(key.clone())).await?;
+        Ok(())
+    }
+
+    async fn delete_by_id(&self, id: i64) -> Result<()> {
+        self.delete_history("id = ?", |query| query.bind(id)).await?;
+        Ok(())
+    }
+
+    async fn find_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<HistoryEntry>> {
+        let rows = sqlx::query_as::<_, HistoryRow>(&format!(
+            "SELECT {} FROM history WHERE visited_at >= ? AND visited_at < ? ORDER BY visited_at DESC",
+            HISTORY_COLUMNS
+        ))
+        .bind(from.to_rfc3339())
+        .bind(to.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(self.decode_visits(rows))
+    }
+
+    async fn delete_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<DeletedHistory> {
+        // Visit times are stored as UTC RFC 3339, which sorts as text
+        let (from, to) = (from.to_rfc3339(), to.to_rfc3339());
+        // Under the write lock, so no visit lands between the reads and the
+        // writes
+        let (rows, visits) = retry_busy(|| async {
+            let mut transaction = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+            let visits = sqlx::query_as::<_, VisitRow>(
+                "SELECT history_id, visited_at, visit_type FROM visits
+                 WHERE visited_at >= ? AND visited_at < ? ORDER BY visited_at",
+            )
+            .bind(&from)
+            .bind(&to)
+            .fetch_all(&mut *transaction)
+            .await?;
+            let entries = sqlx::query_as::<_, HistoryRow>(
+                "SELECT id, url, url_data, title, visited_at, visit_count FROM history
+                 WHERE visited_at >= ? AND visited_at < ?
+                    OR id IN (SELECT history_id FROM visits WHERE visited_at >= ? AND visited_at < ?)",
+            )
+            .bind(&from)
+            .bind(&to)
+            .bind(&from)
+            .bind(&to)
+            .fetch_all(&mut *transaction)
+            .await?;
+            sqlx::query("DELETE FROM visits WHERE visited_at >= ? AND visited_at < ?")
+                .bind(&from)
+                .bind(&to)
+                .execute(&mut *transaction)
+                .await?;
+            // Each entry now counts only the visits it has left, the count
+            // of those it lost kept for undoing
+            let mut rows = Vec::with_capacity(entries.len());
+            for mut entry in entries {
+                let removed = visits.iter().filter(|visit| visit.0 == entry.0).count() as i32;
+                let (last,) = sqlx::query_as::<_, (Option<String>,)>(
+                    "SELECT max(visited_at) FROM visits WHERE history_id = ?",
+                )
+                .bind(entry.0)
+                .fetch_one(&mut *transaction)
+                .await?;
+                let left = match last {
+                    Some(last) => {
+                        let left = (entry.5 - removed).max(1);
+                        sqlx::query("UPDATE history SET visit_count = ?, visited_at = ? WHERE id = ?")
+                            .bind(left)
+                            .bind(last)
+                            .bind(entry.0)
+                            .execute(&mut *transaction)
+                            .await?;
+                        left
+                    }
+                    None => {
+                        sqlx::query("DELETE FROM history WHERE id = ?")
+                            .bind(entry.0)
+                            .execute(&mut *transaction)
+                            .await?;
+                        0
+                    }
+                };
+                entry.5 -= left;
+                rows.push(entry);
+            }
+            transaction.commit().await?;
+            Ok((rows, visits))
+        })
+        .await?;
+        Ok(DeletedHistory {
+            entries: self.decode_visits(rows),
+            visits: visits.into_iter().map(decode_visit_row).collect::<Result<_>>()?,
+        })
+    }
+
+    async fn restore(&self, deleted: &DeletedHistory) -> Result<()> {
+        let entries = &deleted.entries;
+        let stored = entries
+            .iter()
+            .map(|entry| self.encode_visit(&entry.url, &entry.title))
+            .collect::<Result<Vec<_>>>()?;
+        let mut visits: HashMap<i64, Vec<&Visit>> = HashMap::new();
+        for visit in &deleted.visits {
+            visits.entry(visit.history_id).or_default().push(visit);
+        }
+        retry_busy(|| async {
+            let mut transaction = self.pool.begin().await?;
+            for (entry, stored) in entries.iter().zip(&stored) {
+                // The visits go to the row now holding the URL, which is a
+                // new one if it was visited again since
+                let (id,) = sqlx::query_as::<_, (i64,)>(
+                    "INSERT INTO history (id, url, url_data, title, visited_at, visit_count)
+                     VALUES (?, ?, ?, ?, ?, ?)
+                     ON CONFLICT(url) DO UPDATE SET
+                        visited_at = max(visited_at, excluded.visited_at),
+                        visit_count = visit_count + excluded.visit_count
+                     RETURNING id",
+                )
+                .bind(entry.id)
+                .bind(&stored.url)
+                .bind(&stored.url_data)
+                .bind(&stored.title)
+                .bind(entry.visited_at.to_rfc3339())
+                .bind(entry.visit_count)
+                .fetch_one(&mut *transaction)
+                .await?;
+                for visit in visits.get(&entry.id).into_iter().flatten() {
+                    sqlx::query("INSERT INTO visits (history_id, visited_at, visit_type) VALUES (?, ?, ?)")
+                        .bind(id)
+                        .bind(visit.visited_at.to_rfc3339())
+                        .bind(visit.visit_type.to_string())
+                        .execute(&mut *transaction)
+                        .await?;
+                }
+            }
+            transaction.commit().await
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        retry_busy(|| async {
+            let mut transaction = self.pool.begin().await?;
+            sqlx::query("DELETE FROM visits").execute(&mut *transaction).await?;
+            sqlx::query("DELETE FROM history").execute(&mut *transaction).await?;
+            transaction.commit().await
+        })
+        .await?;
+        Ok(())
+    }
+
     async fn increment_visit_count(&self, url: &ValidatedUrl) -> Result<()> {
-        sqlx::query(
-            "UPDATE history SET visit_count = visit_count + 1, visited_at = ? WHERE url = ?",
+        let key = self.history_key(url);
+        retry_busy(|| {
+            sqlx::query(
+                "UPDATE history SET visit_count = visit_count + 1, visited_at = ? WHERE url = ?",
+            )
+            .bind(self.clock.now().to_rfc3339())
+            .bind(&key)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+// Implement SettingsRepository
+#[async_trait]
+impl SettingsRepository for SqliteDatabase {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let result = sqlx::query_as::<_, (String,)>("SELECT value FROM settings WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(result.map(|(value,)| value))
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query(
+                "INSERT INTO settings (key, value) VALUES (?, ?)
+                 ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            )
+            .bind(key)
+            .bind(value)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM settings WHERE key = ?")
+                .bind(key)
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn get_all(&self) -> Result<Vec<(String, String)>> {
+        let results = sqlx::query_as::<_, (String, String)>(
+            "SELECT key, value FROM settings ORDER BY key",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(results)
+    }
+}
+
+#[async_trait]
+impl UserStyleRepository for SqliteDatabase {
+    async fn save(&self, style: NewUserStyle) -> Result<UserStyle> {
+        let rule = serde_json::to_string(&style.rule)
+            .map_err(|e| StorageError::database("Failed to encode user style", e))?;
+        let created_at = self.clock.now();
+        let id = retry_busy(|| {
+            sqlx::query_scalar::<_, i64>(
+                "INSERT INTO user_styles (host_pattern, rule, created_at)
+                 VALUES (?, ?, ?)
+                 RETURNING id",
+            )
+            .bind(style.host_pattern.as_str())
+            .bind(&rule)
+            .bind(created_at.to_rfc3339())
+            .fetch_one(&self.pool)
+        })
+        .await?;
+
+        Ok(UserStyle {
+            id,
+            host_pattern: style.host_pattern,
+            rule: style.rule,
+            created_at,
+        })
+    }
+
+    async fn find_all(&self) -> Result<Vec<UserStyle>> {
+        let rows = sqlx::query_as::<_, (i64, String, String, String)>(
+            "SELECT id, host_pattern, rule, created_at FROM user_styles ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(id, host_pattern, rule, created_at)| {
+                Ok(UserStyle {
+                    id,
+                    host_pattern: HostPattern::parse(&host_pattern)
+                        .ok_or_else(|| corrupt(format!("invalid host pattern {}", host_pattern)))?,
+                    rule: serde_json::from_str(&rule).map_err(corrupt)?,
+                    created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+                        .map_err(corrupt)?
+                        .with_timezone(&chrono::Utc),
+                })
+            })
+            .collect()
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM user_styles WHERE id = ?")
+                .bind(id)
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+type SearchEngineRow = (i64, String, String, String, Option<String>, bool, String);
+
+#[async_trait]
+impl SearchEngineRepository for SqliteDatabase {
+    async fn save(&self, engine: NewSearchEngine) -> Result<SearchEngine> {
+        let created_at = self.clock.now();
+        let id = retry_busy(|| {
+            sqlx::query_scalar::<_, i64>(
+                "INSERT INTO search_engines (name, keyword, query_template, suggest_template, created_at)
+                 VALUES (?, ?, ?, ?, ?)
+                 RETURNING id",
+            )
+            .bind(&engine.name)
+            .bind(&engine.keyword)
+            .bind(engine.query_template.as_str())
+            .bind(engine.suggest_template.as_ref().map(SearchTemplate::as_str))
+            .bind(created_at.to_rfc3339())
+            .fetch_one(&self.pool)
+        })
+        .await?;
+
+        Ok(SearchEngine {
+            id,
+            name: engine.name,
+            keyword: engine.keyword,
+            query_template: engine.query_template,
+            suggest_template: engine.suggest_template,
+            is_default: false,
+            created_at,
+        })
+    }
+
+    async fn find_all(&self) -> Result<Vec<SearchEngine>> {
+        let rows = sqlx::query_as::<_, SearchEngineRow>(
+            "SELECT id, name, keyword, query_template, suggest_template, is_default, created_at
+             FROM search_engines ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let template = |template: &str| {
+            SearchTemplate::parse(template).ok_or_else(|| corrupt(format!("invalid search template {}", template)))
+        };
+        rows.into_iter()
+            .map(|(id, name, keyword, query_template, suggest_template, is_default, created_at)| {
+                Ok(SearchEngine {
+                    id,
+                    name,
+                    keyword,
+                    query_template: template(&query_template)?,
+                    suggest_template: suggest_template.as_deref().map(template).transpose()?,
+                    is_default,
+                    created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+                        .map_err(corrupt)?
+                        .with_timezone(&chrono::Utc),
+                })
+            })
+            .collect()
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM search_engines WHERE id = ?")
+                .bind(id)
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn set_default(&self, id: i64) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query("UPDATE search_engines SET is_default = (id = ?)")
+                .bind(id)
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DownloadRepository for SqliteDatabase {
+    async fn save(&self, download: NewDownload) -> Result<Download> {
+        let created_at = self.clock.now();
+        let url = self.seal(download.url.as_str())?;
+        let path = self.seal(&download.path.to_string_lossy())?;
+        let id = retry_busy(|| {
+            sqlx::query_scalar::<_, i64>(
+                "INSERT INTO downloads (url, path, size, created_at) VALUES (?, ?, ?, ?) RETURNING id",
+            )
+            .bind(&url)
+            .bind(&path)
+            .bind(download.size as i64)
+            .bind(created_at.to_rfc3339())
+            .fetch_one(&self.pool)
+        })
+        .await?;
+
+        Ok(Download {
+            id,
+            url: download.url,
+            path: download.path,
+            size: download.size,
+            created_at,
+        })
+    }
+
+    async fn find_all(&self) -> Result<Vec<Download>> {
+        let rows = sqlx::query_as::<_, (i64, String, String, i64, String)>(
+            "SELECT id, url, path, size, created_at FROM downloads ORDER BY id DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(id, url, path, size, created_at)| {
+                Ok(Download {
+                    id,
+                    url: ValidatedUrl::parse(&self.unseal(url)?).map_err(corrupt)?,
+                    path: PathBuf::from(self.unseal(path)?),
+                    size: size.max(0) as u64,
+                    created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+                        .map_err(corrupt)?
+                        .with_timezone(&chrono::Utc),
+                })
+            })
+            .collect()
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM downloads WHERE id = ?")
+                .bind(id)
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn restore(&self, download: &Download) -> Result<()> {
+        let url = self.seal(download.url.as_str())?;
+        let path = self.seal(&download.path.to_string_lossy())?;
+        retry_busy(|| {
+            sqlx::query("INSERT INTO downloads (id, url, path, size, created_at) VALUES (?, ?, ?, ?, ?)")
+                .bind(download.id)
+                .bind(&url)
+                .bind(&path)
+                .bind(download.size as i64)
+                .bind(download.created_at.to_rfc3339())
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CustomCaRepository for SqliteDatabase {
+    async fn save(&self, ca: NewCustomCa) -> Result<CustomCa> {
+        let added_at = self.clock.now();
+        let id = retry_busy(|| {
+            sqlx::query_scalar::<_, i64>(
+                "INSERT INTO custom_cas (name, pem, fingerprint, added_at) VALUES (?, ?, ?, ?) RETURNING id",
+            )
+            .bind(&ca.name)
+            .bind(&ca.pem)
+            .bind(&ca.fingerprint)
+            .bind(added_at.to_rfc3339())
+            .fetch_one(&self.pool)
+        })
+        .await?;
+
+        Ok(CustomCa {
+            id,
+            name: ca.name,
+            pem: ca.pem,
+            fingerprint: ca.fingerprint,
+            added_at,
+        })
+    }
+
+    async fn find_all(&self) -> Result<Vec<CustomCa>> {
+        let rows = sqlx::query_as::<_, (i64, String, String, String, String)>(
+            "SELECT id, name, pem, fingerprint, added_at FROM custom_cas ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(id, name, pem, fingerprint, added_at)| {
+                Ok(CustomCa {
+                    id,
+                    name,
+                    pem,
+                    fingerprint,
+                    added_at: chrono::DateTime::parse_from_rfc3339(&added_at)
+                        .map_err(corrupt)?
+                        .with_timezone(&chrono::Utc),
+                })
+            })
+            .collect()
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM custom_cas WHERE id = ?")
+                .bind(id)
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserScriptRepository for SqliteDatabase {
+    async fn save(&self, script: NewUserScript) -> Result<UserScript> {
+        let matches = encode_match_patterns(&script.matches)?;
+        let created_at = self.clock.now();
+        let id = retry_busy(|| {
+            sqlx::query_scalar::<_, i64>(
+                "INSERT INTO user_scripts
+                     (name, matches, code, enabled, run_at, allow_internal, allow_private, created_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                 RETURNING id",
+            )
+            .bind(&script.name)
+            .bind(&matches)
+            .bind(&script.code)
+            .bind(script.enabled)
+            .bind(script.run_at.to_string())
+            .bind(script.allow_internal)
+            .bind(script.allow_private)
+            .bind(created_at.to_rfc3339())
+            .fetch_one(&self.pool)
+        })
+        .await?;
+
+        Ok(UserScript {
+            id,
+            name: script.name,
+            matches: script.matches,
+            code: script.code,
+            enabled: script.enabled,
+            run_at: script.run_at,
+            allow_internal: script.allow_internal,
+            allow_private: script.allow_private,
+            last_error: None,
+            created_at,
+        })
+    }
+
+    async fn find_all(&self) -> Result<Vec<UserScript>> {
+        let rows = sqlx::query_as::<_, UserScriptRow>(
+            "SELECT id, name, matches, code, enabled, run_at, allow_internal, allow_private, last_error, created_at
+             FROM user_scripts ORDER BY id",
         )
-        .bind(chrono::Utc::now().to_rfc3339())
-        .bind(url.as_str())
-        .execute(&self.pool)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let (id, name, matches, code, enabled, run_at, allow_internal, allow_private, last_error, created_at) =
+                    row;
+                let matches: Vec<String> = serde_json::from_str(&matches).map_err(corrupt)?;
+                Ok(UserScript {
+                    id,
+                    name,
+                    matches: matches
+                        .iter()
+                        .map(|pattern| {
+                            MatchPattern::parse(pattern)
+                                .ok_or_else(|| corrupt(format!("invalid match pattern {}", pattern)))
+                        })
+                        .collect::<Result<_>>()?,
+                    code,
+                    enabled,
+                    run_at: RunAt::parse(&run_at).ok_or_else(|| corrupt(format!("invalid run_at {}", run_at)))?,
+                    allow_internal,
+                    allow_private,
+                    last_error,
+                    created_at: chrono::DateTime::parse_from_rfc3339(&created_at)
+                        .map_err(corrupt)?
+                        .with_timezone(&chrono::Utc),
+                })
+            })
+            .collect()
+    }
+
+    async fn update(&self, script: &UserScript) -> Result<()> {
+        let matches = encode_match_patterns(&script.matches)?;
+        retry_busy(|| {
+            sqlx::query(
+                "UPDATE user_scripts
+                 SET name = ?, matches = ?, code = ?, enabled = ?, run_at = ?,
+                     allow_internal = ?, allow_private = ?, last_error = ?
+                 WHERE id = ?",
+            )
+            .bind(&script.name)
+            .bind(&matches)
+            .bind(&script.code)
+            .bind(script.enabled)
+            .bind(script.run_at.to_string())
+            .bind(script.allow_internal)
+            .bind(script.allow_private)
+            .bind(&script.last_error)
+            .bind(script.id)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: i64) -> Result<()> {
+        retry_busy(|| async {
+            let mut transaction = self.pool.begin().await?;
+            sqlx::query("DELETE FROM user_script_values WHERE script_id = ?")
+                .bind(id)
+                .execute(&mut *transaction)
+                .await?;
+            sqlx::query("DELETE FROM user_scripts WHERE id = ?")
+                .bind(id)
+                .execute(&mut *transaction)
+                .await?;
+            transaction.commit().await
+        })
         .await?;
         Ok(())
     }
+
+    async fn values(&self, script_id: i64) -> Result<Vec<(String, String)>> {
+        Ok(sqlx::query_as::<_, (String, String)>(
+            "SELECT key, value FROM user_script_values WHERE script_id = ? ORDER BY key",
+        )
+        .bind(script_id)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn set_value(&self, script_id: i64, key: &str, value: &str) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query(
+                "INSERT INTO user_script_values (script_id, key, value) VALUES (?, ?, ?)
+                 ON CONFLICT(script_id, key) DO UPDATE SET value = excluded.value",
+            )
+            .bind(script_id)
+            .bind(key)
+            .bind(value)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// Site settings columns in the order [`SiteSettingsRepository::find_all`] selects them
+type SiteSettingsRow =
+    (String, Option<bool>, Option<bool>, Option<String>, Option<String>, Option<bool>, Option<bool>);
+
+fn decode_site_settings(row: SiteSettingsRow) -> Result<SiteSettings> {
+    let (origin, javascript, images, cookies, user_agent, content_blocking, dark_pages) = row;
+    let cookies = cookies
+        .map(|cookies| {
+            CookiePolicy::parse(&cookies).ok_or_else(|| corrupt(format!("invalid cookie policy {}", cookies)))
+        })
+        .transpose()?;
+    Ok(SiteSettings {
+        origin: SiteOrigin::parse(&origin).ok_or_else(|| corrupt(format!("invalid site origin {}", origin)))?,
+        javascript,
+        images,
+        cookies,
+        user_agent,
+        content_blocking,
+        dark_pages,
+    })
+}
+
+#[async_trait]
+impl SiteSettingsRepository for SqliteDatabase {
+    async fn find(&self, origin: &SiteOrigin) -> Result<Option<SiteSettings>> {
+        let row = sqlx::query_as::<_, SiteSettingsRow>(
+            "SELECT origin, javascript, images, cookies, user_agent, content_blocking, dark_pages
+             FROM site_settings WHERE origin = ?",
+        )
+        .bind(origin.as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(decode_site_settings).transpose()
+    }
+
+    async fn find_all(&self) -> Result<Vec<SiteSettings>> {
+        let rows = sqlx::query_as::<_, SiteSettingsRow>(
+            "SELECT origin, javascript, images, cookies, user_agent, content_blocking, dark_pages
+             FROM site_settings ORDER BY origin",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter().map(decode_site_settings).collect()
+    }
+
+    async fn save(&self, settings: &SiteSettings) -> Result<()> {
+        let cookies = settings.cookies.map(|cookies| cookies.to_string());
+        retry_busy(|| {
+            sqlx::query(
+                "INSERT INTO site_settings
+                 (origin, javascript, images, cookies, user_agent, content_blocking, dark_pages)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)
+                 ON CONFLICT(origin) DO UPDATE SET javascript = excluded.javascript,
+                 images = excluded.images, cookies = excluded.cookies, user_agent = excluded.user_agent,
+                 content_blocking = excluded.content_blocking, dark_pages = excluded.dark_pages",
+            )
+            .bind(settings.origin.as_str())
+            .bind(settings.javascript)
+            .bind(settings.images)
+            .bind(&cookies)
+            .bind(&settings.user_agent)
+            .bind(settings.content_blocking)
+            .bind(settings.dark_pages)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete(&self, origin: &SiteOrigin) -> Result<()> {
+        retry_busy(|| {
+            sqlx::query("DELETE FROM site_settings WHERE origin = ?")
+                .bind(origin.as_str())
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        retry_busy(|| sqlx::query("DELETE FROM site_settings").execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WebStorageRepository for SqliteDatabase {
+    async fn load(&self, origin: &SiteOrigin) -> Result<Vec<(String, String)>> {
+        let items = sqlx::query_as::<_, (String, String)>(
+            "SELECT key, value FROM web_storage WHERE origin = ? ORDER BY id",
+        )
+        .bind(origin.as_str())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(items)
+    }
+
+    async fn apply(&self, origin: &SiteOrigin, change: &StorageChange) -> Result<()> {
+        retry_busy(|| {
+            let query = match change {
+                StorageChange::Set { key, value } => sqlx::query(
+                    "INSERT INTO web_storage (origin, key, value) VALUES (?, ?, ?)
+                     ON CONFLICT(origin, key) DO UPDATE SET value = excluded.value",
+                )
+                .bind(origin.as_str())
+                .bind(key)
+                .bind(value),
+                StorageChange::Remove(key) => sqlx::query("DELETE FROM web_storage WHERE origin = ? AND key = ?")
+                    .bind(origin.as_str())
+                    .bind(key),
+                StorageChange::Clear => {
+                    sqlx::query("DELETE FROM web_storage WHERE origin = ?").bind(origin.as_str())
+                }
+            };
+            query.execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        retry_busy(|| sqlx::query("DELETE FROM web_storage").execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PageCacheRepository for SqliteDatabase {
+    async fn store(&self, page: &CachedPage) -> Result<()> {
+        // Encrypted profiles keep no plain-text copies of what was visited
+        if self.is_encrypted() {
+            return Ok(());
+        }
+        let Some(origin) = SiteOrigin::of(&page.url) else {
+            return Ok(());
+        };
+        let url = page.url.normalized();
+        retry_busy(|| {
+            sqlx::query(
+                "INSERT INTO page_cache (url, origin, title, content_type, body, fetched_at)
+                 VALUES (?, ?, ?, ?, ?, ?)
+                 ON CONFLICT(url) DO UPDATE SET title = excluded.title,
+                     content_type = excluded.content_type, body = excluded.body, fetched_at = excluded.fetched_at",
+            )
+            .bind(url.as_str())
+            .bind(origin.as_str())
+            .bind(&page.title)
+            .bind(&page.content_type)
+            .bind(&page.body)
+            .bind(page.fetched_at.to_rfc3339())
+            .execute(&self.pool)
+        })
+        .await?;
+        retry_busy(|| {
+            sqlx::query(
+                "DELETE FROM page_cache WHERE url NOT IN
+                 (SELECT url FROM page_cache ORDER BY fetched_at DESC LIMIT ?)",
+            )
+            .bind(MAX_CACHED_PAGES)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn find(&self, url: &ValidatedUrl) -> Result<Option<CachedPage>> {
+        let row = sqlx::query_as::<_, (String, String, Option<String>, Vec<u8>, String)>(
+            "SELECT url, title, content_type, body, fetched_at FROM page_cache WHERE url = ?",
+        )
+        .bind(url.normalized().as_str())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|(url, title, content_type, body, fetched_at)| {
+            Ok(CachedPage {
+                url: ValidatedUrl::parse(&url).map_err(corrupt)?,
+                title,
+                content_type,
+                body,
+                fetched_at: chrono::DateTime::parse_from_rfc3339(&fetched_at)
+                    .map_err(corrupt)?
+                    .with_timezone(&chrono::Utc),
+            })
+        })
+        .transpose()
+    }
+
+    async fn find_by_origin(&self, origin: &SiteOrigin) -> Result<Vec<CachedPageSummary>> {
+        let rows = sqlx::query_as::<_, (String, String, String)>(
+            "SELECT url, title, fetched_at FROM page_cache WHERE origin = ? ORDER BY fetched_at DESC, url",
+        )
+        .bind(origin.as_str())
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|(url, title, fetched_at)| {
+                Ok(CachedPageSummary {
+                    url: ValidatedUrl::parse(&url).map_err(corrupt)?,
+                    title,
+                    fetched_at: chrono::DateTime::parse_from_rfc3339(&fetched_at)
+                        .map_err(corrupt)?
+                        .with_timezone(&chrono::Utc),
+                })
+            })
+            .collect()
+    }
+
+    async fn clear_all(&self) -> Result<()> {
+        retry_busy(|| sqlx::query("DELETE FROM page_cache").execute(&self.pool)).await?;
+        Ok(())
+    }
+}
+
+/// User script columns in the order [`UserScriptRepository::find_all`] selects them
+type UserScriptRow = (i64, String, String, String, bool, String, bool, bool, Option<String>, String);
+
+fn encode_match_patterns(patterns: &[MatchPattern]) -> Result<String> {
+    let patterns: Vec<&str> = patterns.iter().map(MatchPattern::as_str).collect();
+    serde_json::to_string(&patterns).map_err(|e| StorageError::database("Failed to encode match patterns", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::testing::ManualClock;
+    use crate::domain::UserStyleRule;
+
+    /// On-disk database, so connections really contend for locks
+    struct TempDatabase(PathBuf);
+
+    impl TempDatabase {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!("navigator-test-{}.db", uuid::Uuid::new_v4())))
+        }
+
+        fn url(&self) -> String {
+            format!("sqlite://{}", self.0.display())
+        }
+    }
+
+    impl Drop for TempDatabase {
+        fn drop(&mut self) {
+            for suffix in ["", "-wal", "-shm"] {
+                let mut path = self.0.clone().into_os_string();
+                path.push(suffix);
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    fn session_tab(url: &str, minutes: i64) -> Tab {
+        let mut tab = Tab::with_url(ValidatedUrl::parse(url).unwrap(), false);
+        tab.title = url.to_string();
+        tab.is_loading = false;
+        tab.created_at = DateTime::parse_from_rfc3339("2024-05-01T09:00:00Z").unwrap().with_timezone(&Utc)
+            + chrono::Duration::minutes(minutes);
+        tab.last_accessed = tab.created_at;
+        tab
+    }
+
+    fn two_windows() -> Vec<SavedWindow> {
+        let (a, b, c) = (
+            session_tab("https://a.example/", 0),
+            session_tab("https://b.example/", 1),
+            session_tab("https://c.example/", 2),
+        );
+        vec![
+            SavedWindow {
+                id: WindowId::new(),
+                geometry: Some(WindowGeometry {
+                    x: -1280,
+                    y: 40,
+                    width: 1200,
+                    height: 800,
+                    monitor: Some("DP-2".to_string()),
+                }),
+                active_tab: Some(b.id),
+                tabs: vec![a, b],
+            },
+            SavedWindow {
+                id: WindowId::new(),
+                geometry: None,
+                active_tab: None,
+                tabs: vec![c],
+            },
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_session_round_trips_with_its_windows() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        assert!(db.restore_session().await.unwrap().is_empty());
+        let windows = two_windows();
+        db.save_session(windows.clone()).await.unwrap();
+        assert_eq!(db.restore_session().await.unwrap(), windows);
+
+        // Saving again replaces the windows stored before
+        db.clear_session().await.unwrap();
+        db.save_session(windows[1..].to_vec()).await.unwrap();
+        assert_eq!(db.restore_session().await.unwrap(), windows[1..]);
+        db.clear_session().await.unwrap();
+        assert!(db.restore_session().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_orphaned_tabs_join_the_first_window() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let windows = two_windows();
+        db.save_session(windows.clone()).await.unwrap();
+        sqlx::query("DELETE FROM windows WHERE id = ?")
+            .bind(windows[1].id.to_string())
+            .execute(db.get_pool())
+            .await
+            .unwrap();
+        // Stored while open, before the session knew its window
+        let loose = session_tab("https://d.example/", 3);
+        TabRepository::save(&db, &loose).await.unwrap();
+
+        let restored = db.restore_session().await.unwrap();
+        assert_eq!(restored.len(), 1);
+        let urls: Vec<&str> =
+            restored[0].tabs.iter().filter_map(|tab| tab.url.as_ref()).map(|u| u.as_str()).collect();
+        assert_eq!(urls, ["https://a.example/", "https://b.example/", "https://c.example/", "https://d.example/"]);
+        assert_eq!(restored[0].shown_tab().unwrap().id, windows[0].tabs[1].id);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_rows_are_restored_with_their_ids() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let url = |text: &str| ValidatedUrl::parse(text).unwrap();
+
+        let mut bookmark = NewBookmark::new("Rust".to_string(), url("https://rust-lang.org/"));
+        bookmark.folder = Some("Work".to_string());
+        bookmark.tags = vec!["lang".to_string()];
+        let bookmark = BookmarkRepository::save(&db, bookmark).await.unwrap();
+        BookmarkRepository::delete(&db, bookmark.id).await.unwrap();
+        let newer = BookmarkRepository::save(&db, NewBookmark::new("Docs".to_string(), url("https://docs.rs/")))
+            .await
+            .unwrap();
+        assert_ne!(newer.id, bookmark.id);
+        BookmarkRepository::restore(&db, std::slice::from_ref(&bookmark)).await.unwrap();
+        assert_eq!(BookmarkRepository::find_by_id(&db, bookmark.id).await.unwrap(), Some(bookmark));
+
+        let day = Utc::now() - chrono::Duration::days(1);
+        let mut visit = NewHistoryEntry::new(url("https://example.com/"), "Example".to_string());
+        visit.visited_at = day;
+        visit.visit_count = 4;
+        let entry = db.add(visit).await.unwrap();
+        let range = (day - chrono::Duration::hours(1), day + chrono::Duration::hours(1));
+        assert_eq!(db.find_range(range.0, range.1).await.unwrap(), vec![entry.clone()]);
+        let deleted = db.delete_range(range.0, range.1).await.unwrap();
+        assert_eq!(deleted.entries, vec![entry.clone()]);
+        HistoryRepository::restore(&db, &deleted).await.unwrap();
+        assert_eq!(db.find_by_url(&entry.url).await.unwrap(), Some(entry.clone()));
+        assert_eq!(db.visits(&[entry.id]).await.unwrap(), deleted.visits);
+
+        // Visited again after deleting: the new entry keeps the old visits
+        let deleted = db.delete_range(range.0, range.1).await.unwrap();
+        let again = db.add(NewHistoryEntry::new(entry.url.clone(), "Example".to_string())).await.unwrap();
+        HistoryRepository::restore(&db, &deleted).await.unwrap();
+        let merged = db.find_by_url(&entry.url).await.unwrap().unwrap();
+        assert_eq!((merged.id, merged.visit_count, merged.visited_at), (again.id, 5, again.visited_at));
+        assert_eq!(db.visits(&[again.id]).await.unwrap().len(), 2);
+
+        let download = DownloadRepository::save(
+            &db,
+            NewDownload {
+                url: url("https://example.com/a.pdf"),
+                path: PathBuf::from("/tmp/a.pdf"),
+                size: 10,
+            },
+        )
+        .await
+        .unwrap();
+        DownloadRepository::delete(&db, download.id).await.unwrap();
+        DownloadRepository::restore(&db, &download).await.unwrap();
+        assert_eq!(DownloadRepository::find_all(&db).await.unwrap(), vec![download]);
+    }
+
+    #[tokio::test]
+    async fn test_settings_round_trip() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        assert_eq!(SettingsRepository::get(&db, "theme").await.unwrap(), None);
+
+        db.set("theme", "dark").await.unwrap();
+        db.set("theme", "light").await.unwrap();
+
+        assert_eq!(
+            SettingsRepository::get(&db, "theme").await.unwrap(),
+            Some("light".to_string())
+        );
+        assert_eq!(db.get_all().await.unwrap().len(), 1);
+
+        SettingsRepository::delete(&db, "theme").await.unwrap();
+        assert!(db.get_all().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_styles_round_trip() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let style = NewUserStyle::new(
+            HostPattern::parse("*.Example.com").unwrap(),
+            UserStyleRule::Hide { selector: ".ad".to_string() },
+        );
+        let saved = UserStyleRepository::save(&db, style.clone()).await.unwrap();
+
+        let all = UserStyleRepository::find_all(&db).await.unwrap();
+        assert_eq!(all, vec![saved.clone()]);
+        assert_eq!(all[0].host_pattern.as_str(), "*.example.com");
+        assert_eq!(all[0].rule, style.rule);
+
+        UserStyleRepository::delete(&db, saved.id).await.unwrap();
+        assert!(UserStyleRepository::find_all(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_engines_round_trip() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let builtin = SearchEngineRepository::find_all(&db).await.unwrap();
+        let keywords: Vec<&str> = builtin.iter().map(|engine| engine.keyword.as_str()).collect();
+        assert_eq!(keywords, vec!["d", "w", "gh"]);
+        assert!(builtin[0].is_default && !builtin[1].is_default);
+        assert!(builtin[1].suggest_url("rust").is_some());
+
+        let template = SearchTemplate::parse("https://docs.rs/releases/search?query=%s").unwrap();
+        let docs = NewSearchEngine::new("Docs.rs".to_string(), "RS", template);
+        let saved = SearchEngineRepository::save(&db, docs.clone()).await.unwrap();
+        assert_eq!(saved.keyword, "rs");
+        assert!(SearchEngineRepository::save(&db, docs).await.is_err());
+
+        SearchEngineRepository::set_default(&db, saved.id).await.unwrap();
+        let all = SearchEngineRepository::find_all(&db).await.unwrap();
+        assert_eq!(all.iter().filter(|engine| engine.is_default).count(), 1);
+        assert_eq!(all.last(), Some(&SearchEngine { is_default: true, ..saved.clone() }));
+
+        SearchEngineRepository::delete(&db, builtin[2].id).await.unwrap();
+        assert_eq!(SearchEngineRepository::find_all(&db).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_round_trip() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let download = |path: &str| NewDownload {
+            url: ValidatedUrl::parse("https://example.com/a?b=c").unwrap(),
+            path: PathBuf::from(path),
+            size: 42,
+        };
+        let first = DownloadRepository::save(&db, download("/tmp/Example.html")).await.unwrap();
+        let second = DownloadRepository::save(&db, download("/tmp/Example (1).html")).await.unwrap();
+
+        let all = DownloadRepository::find_all(&db).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!((all[0].id, all[1].id), (second.id, first.id));
+        assert_eq!(all[1].path, first.path);
+        assert_eq!(all[1].url, first.url);
+        assert_eq!(all[1].size, 42);
+
+        DownloadRepository::delete(&db, second.id).await.unwrap();
+        assert_eq!(DownloadRepository::find_all(&db).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_custom_cas_round_trip() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let ca = |name: &str, fingerprint: &str| NewCustomCa {
+            name: name.to_string(),
+            pem: "-----BEGIN CERTIFICATE-----\n...\n-----END CERTIFICATE-----\n".to_string(),
+            fingerprint: fingerprint.to_string(),
+        };
+        let corporate = CustomCaRepository::save(&db, ca("Corporate", "AA:BB")).await.unwrap();
+        let lab = CustomCaRepository::save(&db, ca("Lab", "CC:DD")).await.unwrap();
+        // One entry per certificate
+        assert!(CustomCaRepository::save(&db, ca("Again", "AA:BB")).await.is_err());
+        assert_eq!(CustomCaRepository::find_all(&db).await.unwrap(), vec![corporate.clone(), lab]);
+
+        CustomCaRepository::delete(&db, corporate.id).await.unwrap();
+        let names: Vec<String> =
+            CustomCaRepository::find_all(&db).await.unwrap().into_iter().map(|ca| ca.name).collect();
+        assert_eq!(names, ["Lab"]);
+    }
+
+    #[tokio::test]
+    async fn test_user_scripts_round_trip() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let mut script = NewUserScript::new(
+            "Counter".to_string(),
+            vec![MatchPattern::parse("*://*.example.com/*").unwrap()],
+            "GM_setValue('n', 1)".to_string(),
+        );
+        script.run_at = RunAt::DocumentIdle;
+        let mut saved = UserScriptRepository::save(&db, script).await.unwrap();
+        let other = NewUserScript::new("Other".to_string(), Vec::new(), String::new());
+        let other = UserScriptRepository::save(&db, other).await.unwrap();
+
+        saved.enabled = false;
+        saved.last_error = Some("ReferenceError: x is not defined".to_string());
+        UserScriptRepository::update(&db, &saved).await.unwrap();
+        assert_eq!(UserScriptRepository::find_all(&db).await.unwrap()[0], saved);
+
+        db.set_value(saved.id, "n", "1").await.unwrap();
+        db.set_value(saved.id, "n", "2").await.unwrap();
+        db.set_value(other.id, "n", "\"kept\"").await.unwrap();
+        assert_eq!(db.values(saved.id).await.unwrap(), vec![("n".to_string(), "2".to_string())]);
+
+        UserScriptRepository::delete(&db, saved.id).await.unwrap();
+        assert!(db.values(saved.id).await.unwrap().is_empty());
+        assert_eq!(db.values(other.id).await.unwrap().len(), 1);
+        assert_eq!(UserScriptRepository::find_all(&db).await.unwrap(), vec![other]);
+    }
+
+    #[tokio::test]
+    async fn test_site_settings_round_trip() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let origin = SiteOrigin::parse("https://example.com/page").unwrap();
+        assert_eq!(SiteSettingsRepository::find(&db, &origin).await.unwrap(), None);
+
+        let mut settings = SiteSettings::new(origin.clone());
+        settings.javascript = Some(false);
+        settings.cookies = Some(CookiePolicy::SessionOnly);
+        SiteSettingsRepository::save(&db, &settings).await.unwrap();
+        settings.user_agent = Some("Test/1.0".to_string());
+        settings.content_blocking = Some(false);
+        settings.dark_pages = Some(false);
+        SiteSettingsRepository::save(&db, &settings).await.unwrap();
+        assert_eq!(SiteSettingsRepository::find(&db, &origin).await.unwrap(), Some(settings.clone()));
+
+        let other = SiteSettings {
+            images: Some(false),
+            ..SiteSettings::new(SiteOrigin::parse("http://a.example").unwrap())
+        };
+        SiteSettingsRepository::save(&db, &other).await.unwrap();
+        assert_eq!(SiteSettingsRepository::find_all(&db).await.unwrap(), vec![other.clone(), settings]);
+
+        SiteSettingsRepository::delete(&db, &origin).await.unwrap();
+        assert_eq!(SiteSettingsRepository::find_all(&db).await.unwrap(), vec![other]);
+        SiteSettingsRepository::clear_all(&db).await.unwrap();
+        assert!(SiteSettingsRepository::find_all(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_web_storage_keeps_items_in_order_per_origin() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let origin = SiteOrigin::parse("https://example.com").unwrap();
+        let other = SiteOrigin::parse("https://example.org").unwrap();
+        let set = |key: &str, value: &str| StorageChange::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        for (site, change) in [(&origin, set("b", "1")), (&origin, set("a", "2")), (&other, set("a", "x"))] {
+            db.apply(site, &change).await.unwrap();
+        }
+        db.apply(&origin, &set("b", "3")).await.unwrap();
+        let items = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+        };
+        assert_eq!(db.load(&origin).await.unwrap(), items(&[("b", "3"), ("a", "2")]));
+
+        db.apply(&origin, &StorageChange::Remove("b".to_string())).await.unwrap();
+        assert_eq!(db.load(&origin).await.unwrap(), items(&[("a", "2")]));
+        db.apply(&origin, &StorageChange::Clear).await.unwrap();
+        assert!(db.load(&origin).await.unwrap().is_empty());
+        assert_eq!(db.load(&other).await.unwrap(), items(&[("a", "x")]));
+        WebStorageRepository::clear_all(&db).await.unwrap();
+        assert!(db.load(&other).await.unwrap().is_empty());
+    }
+
+    fn cached_page(url: ValidatedUrl, title: &str, fetched_at: DateTime<Utc>) -> CachedPage {
+        CachedPage {
+            url,
+            title: title.to_string(),
+            content_type: Some("text/html".to_string()),
+            body: format!("<title>{}</title>", title).into_bytes(),
+            fetched_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_page_cache_keeps_one_copy_per_url() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let url = |input: &str| ValidatedUrl::parse(input).unwrap();
+        let earlier = Utc::now() - chrono::Duration::days(3);
+        db.store(&cached_page(url("https://example.com/a"), "Old A", earlier)).await.unwrap();
+        db.store(&cached_page(url("https://example.com/b"), "B", earlier)).await.unwrap();
+        db.store(&cached_page(url("https://example.org/"), "Other", earlier)).await.unwrap();
+        let latest = cached_page(url("https://example.com/a#top"), "A", Utc::now());
+        db.store(&latest).await.unwrap();
+
+        let found = PageCacheRepository::find(&db, &url("https://example.com/a#bottom")).await.unwrap().unwrap();
+        assert_eq!(found.title, "A");
+        assert_eq!(found.body, latest.body);
+        assert_eq!(found.url.as_str(), "https://example.com/a");
+        let origin = SiteOrigin::parse("https://example.com").unwrap();
+        let titles: Vec<_> = db.find_by_origin(&origin).await.unwrap().into_iter().map(|page| page.title).collect();
+        assert_eq!(titles, ["A", "B"]);
+
+        PageCacheRepository::clear_all(&db).await.unwrap();
+        assert!(db.find_by_origin(&origin).await.unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_history_writes() {
+        let file = TempDatabase::new();
+        let db = Arc::new(SqliteDatabase::new(&file.url()).await.unwrap());
+
+        let writers = (0..8).map(|task| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for i in 0..25 {
+                    let url = ValidatedUrl::parse(&format!("https://example.com/{}", i % 5)).unwrap();
+                    db.add(NewHistoryEntry::new(url, format!("Task {}", task))).await?;
+                    db.get_recent(10).await?;
+                }
+                anyhow::Ok(())
+            })
+        });
+        for writer in writers.collect::<Vec<_>>() {
+            writer.await.unwrap().unwrap();
+        }
+
+        let total: i32 = db.get_recent(100).await.unwrap().iter().map(|e| e.visit_count).sum();
+        assert_eq!(total, 8 * 25);
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_truncates_wal() {
+        let file = TempDatabase::new();
+        let db = SqliteDatabase::new(&file.url()).await.unwrap();
+        db.set("theme", "dark").await.unwrap();
+
+        let stats = db.stats().await.unwrap();
+        assert!(stats.page_count > 0);
+        assert!(stats.wal_bytes > 0);
+
+        db.checkpoint().await.unwrap();
+        let stats = db.stats().await.unwrap();
+        assert_eq!(stats.wal_bytes, 0);
+        assert_eq!(stats.database_bytes(), stats.page_size * stats.page_count);
+    }
+
+    /// Cheap enough for tests; real profiles use [`KDF_COST`]
+    const TEST_KDF_COST: Argon2Cost = Argon2Cost {
+        memory_kib: 64,
+        passes: 1,
+        lanes: 1,
+    };
+
+    #[tokio::test]
+    async fn test_encrypt_profile_round_trip() {
+        let file = TempDatabase::new();
+        let secret = "https://secret.example/diary";
+        let mut windows = two_windows();
+        windows[0].tabs[0] = session_tab("https://tab.secret.example/", 0);
+        windows[0].active_tab = None;
+        let download = NewDownload {
+            url: ValidatedUrl::parse("https://files.secret.example/plans.pdf").unwrap(),
+            path: PathBuf::from("/home/me/Downloads/plans.pdf"),
+            size: 10,
+        };
+        {
+            let mut db = SqliteDatabase::new(&file.url()).await.unwrap();
+            let url = ValidatedUrl::parse(secret).unwrap();
+            db.add(NewHistoryEntry::new(url.clone(), "Dear diary".to_string())).await.unwrap();
+            db.add(NewHistoryEntry::new(url.clone(), "Dear diary".to_string())).await.unwrap();
+            db.store(&cached_page(url, "Dear diary", Utc::now())).await.unwrap();
+            db.save_session(windows.clone()).await.unwrap();
+            DownloadRepository::save(&db, download.clone()).await.unwrap();
+            // Bookmarks and web storage are outside what encryption covers
+            let bookmark = NewBookmark::new(
+                "Bookmarked".to_string(),
+                ValidatedUrl::parse("https://bookmark.example/").unwrap(),
+            );
+            BookmarkRepository::save(&db, bookmark).await.unwrap();
+            let change = StorageChange::Set {
+                key: "key".to_string(),
+                value: "stored value".to_string(),
+            };
+            let origin = SiteOrigin::parse("https://storage.example").unwrap();
+            WebStorageRepository::apply(&db, &origin, &change).await.unwrap();
+
+            db.encrypt_profile_with("hunter2", TEST_KDF_COST).await.unwrap();
+            assert!(db.is_encrypted());
+        }
+
+        let bytes = std::fs::read(&file.0).unwrap();
+        let contains = |needle: &str| bytes.windows(needle.len()).any(|w| w == needle.as_bytes());
+        assert!(!contains("secret.example"));
+        assert!(!contains("Dear diary"));
+        assert!(!contains("plans.pdf"));
+        assert!(contains("bookmark.example"));
+        assert!(contains("stored value"));
+
+        let error = SqliteDatabase::new(&file.url()).await.err().unwrap();
+        assert_eq!(error, StorageError::PassphraseRequired);
+        let error = SqliteDatabase::open(&file.url(), Some("hunter3")).await.err().unwrap();
+        assert_eq!(error, StorageError::WrongPassphrase);
+
+        let mut db = SqliteDatabase::open(&file.url(), Some("hunter2")).await.unwrap();
+        let url = ValidatedUrl::parse(secret).unwrap();
+        db.add(NewHistoryEntry::new(url.clone(), "Dear diary".to_string())).await.unwrap();
+        db.store(&cached_page(url.clone(), "Dear diary", Utc::now())).await.unwrap();
+        assert_eq!(PageCacheRepository::find(&db, &url).await.unwrap(), None);
+        let entry = db.find_by_url(&url).await.unwrap().unwrap();
+        assert_eq!((entry.title.as_str(), entry.visit_count), ("Dear diary", 3));
+        assert_eq!(HistoryRepository::search(&db, "DIARY", 10).await.unwrap().len(), 1);
+        let other = ValidatedUrl::parse("https://secret.example/other").unwrap();
+        assert_eq!(db.visited(&[other, url.clone()]).await.unwrap(), vec![false, true]);
+        assert_eq!(db.restore_session().await.unwrap(), windows);
+        let downloads = DownloadRepository::find_all(&db).await.unwrap();
+        assert_eq!((&downloads[0].url, &downloads[0].path), (&download.url, &download.path));
+
+        db.decrypt_profile().await.unwrap();
+        drop(db);
+        let db = SqliteDatabase::new(&file.url()).await.unwrap();
+        assert_eq!(db.find_by_url(&url).await.unwrap().unwrap().visit_count, 3);
+        assert_eq!(db.restore_session().await.unwrap(), windows);
+        assert_eq!(DownloadRepository::find_all(&db).await.unwrap()[0].path, download.path);
+    }
+
+    #[tokio::test]
+    async fn test_pbkdf2_encrypted_profiles_still_open() {
+        let file = TempDatabase::new();
+        let url = ValidatedUrl::parse("https://secret.example/diary").unwrap();
+        {
+            let mut db = SqliteDatabase::new(&file.url()).await.unwrap();
+            db.add(NewHistoryEntry::new(url.clone(), "Dear diary".to_string())).await.unwrap();
+            // Encrypted the way profiles were before Argon2id
+            let (cipher, material) = ProfileCipher::create_pbkdf2("hunter2", 1_000).unwrap();
+            let meta = [
+                ("encryption", LEGACY_ENCRYPTION_SCHEME.to_string()),
+                ("kdf_salt", BASE64.encode(&material.salt)),
+                ("kdf_iterations", "1000".to_string()),
+                ("key_check", material.key_check),
+            ];
+            db.rewrite_profile(Some(cipher), &meta).await.unwrap();
+        }
+
+        let error = SqliteDatabase::open(&file.url(), Some("hunter3")).await.err().unwrap();
+        assert_eq!(error, StorageError::WrongPassphrase);
+        let mut db = SqliteDatabase::open(&file.url(), Some("hunter2")).await.unwrap();
+        assert_eq!(db.find_by_url(&url).await.unwrap().unwrap().title, "Dear diary");
+
+        // Decrypting and encrypting again moves the profile to Argon2id
+        db.decrypt_profile().await.unwrap();
+        db.encrypt_profile_with("hunter2", TEST_KDF_COST).await.unwrap();
+        let scheme = sqlx::query_scalar::<_, String>("SELECT value FROM meta WHERE key = 'encryption'")
+            .fetch_one(db.get_pool())
+            .await
+            .unwrap();
+        assert_eq!(scheme, PROFILE_ENCRYPTION_SCHEME);
+    }
+
+    #[tokio::test]
+    async fn test_saved_bookmark_round_trips() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let mut bookmark = NewBookmark::new(
+            "Example".to_string(),
+            ValidatedUrl::parse("https://example.com/").unwrap(),
+        );
+        bookmark.folder = Some("Reading".to_string());
+        bookmark.tags = vec!["docs".to_string()];
+
+        let saved = BookmarkRepository::save(&db, bookmark).await.unwrap();
+        assert!(saved.id > 0);
+
+        let found = BookmarkRepository::find_by_id(&db, saved.id).await.unwrap().unwrap();
+        assert_eq!(found.id, saved.id);
+        assert_eq!(found.title, saved.title);
+        assert_eq!(found.url, saved.url);
+        assert_eq!(found.folder, saved.folder);
+        assert_eq!(found.created_at, saved.created_at);
+        assert_eq!(found.tags, saved.tags);
+
+        // A creation time that no longer parses is an error, not a panic
+        sqlx::query("UPDATE bookmarks SET created_at = 'yesterday' WHERE id = ?")
+            .bind(saved.id)
+            .execute(db.get_pool())
+            .await
+            .unwrap();
+        assert!(matches!(
+            BookmarkRepository::find_by_id(&db, saved.id).await,
+            Err(StorageError::Corrupt(_))
+        ));
+        assert!(matches!(BookmarkRepository::find_all(&db).await, Err(StorageError::Corrupt(_))));
+    }
+
+    async fn save_bookmarks(db: &SqliteDatabase, folder: Option<&str>, count: usize) -> Vec<i64> {
+        let mut ids = Vec::new();
+        for n in 0..count {
+            let url = ValidatedUrl::parse(&format!("https://example.com/{}", n)).unwrap();
+            let mut bookmark = NewBookmark::new(format!("Page {}", n), url);
+            bookmark.folder = folder.map(str::to_string);
+            ids.push(BookmarkRepository::save(db, bookmark).await.unwrap().id);
+        }
+        ids
+    }
+
+    async fn folder_order(db: &SqliteDatabase, folder: &str) -> Vec<(i64, i64)> {
+        let bookmarks = BookmarkRepository::find_by_folder(db, folder).await.unwrap();
+        bookmarks.iter().map(|b| (b.id, b.position)).collect()
+    }
+
+    #[tokio::test]
+    async fn test_moved_bookmarks_keep_their_place() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let ids = save_bookmarks(&db, Some("Work"), 3).await;
+        let order = |ids: &[(i64, i64)]| ids.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        assert_eq!(order(&folder_order(&db, "Work").await), ids);
+
+        db.move_to(ids[2], Some("Work"), 0).await.unwrap();
+        assert_eq!(order(&folder_order(&db, "Work").await), [ids[2], ids[0], ids[1]]);
+        // Only the moved bookmark was renumbered
+        assert_eq!(folder_order(&db, "Work").await[1..], [(ids[0], 1024), (ids[1], 2048)]);
+
+        // Moving between the first two again and again uses up the gap,
+        // and the folder is renumbered
+        let mut expected = order(&folder_order(&db, "Work").await);
+        let mut renumbered = false;
+        for _ in 0..12 {
+            let last = expected.pop().unwrap();
+            expected.insert(1, last);
+            db.move_to(last, Some("Work"), 1).await.unwrap();
+            let moved = folder_order(&db, "Work").await;
+            assert_eq!(order(&moved), expected);
+            let positions: Vec<i64> = moved.iter().map(|(_, position)| *position).collect();
+            renumbered |= positions == [1024, 2048, 3072];
+        }
+        assert!(renumbered);
+
+        // Into another folder, past its end; a missing bookmark is an error
+        db.move_to(ids[0], None, 10).await.unwrap();
+        assert_eq!(BookmarkRepository::find_by_id(&db, ids[0]).await.unwrap().unwrap().folder, None);
+        assert_eq!(folder_order(&db, "Work").await.len(), 2);
+        assert_eq!(db.move_to(999, Some("Work"), 0).await, Err(StorageError::NotFound(999)));
+    }
+
+    #[tokio::test]
+    async fn test_bookmark_positions_are_compacted_by_folder() {
+        let db = SqliteDatabase::new(":memory:").await.unwrap();
+        let work = save_bookmarks(&db, Some("Work"), 3).await;
+        let unfiled = save_bookmarks(&db, None, 2).await;
+        // Squeezed between the first two, leaving uneven gaps
+        db.move_to(work[2], Some("Work"), 1).await.unwrap();
+        db.move_to(unfiled[1], None, 0).await.unwrap();
+        assert_eq!(folder_order(&db, "Work").await[1], (work[2], 1536));
+
+        db.compact_bookmark_positions().await.unwrap();
+        let compacted = [(work[0], 1024), (work[2], 2048), (work[1], 3072)];
+        assert_eq!(folder_order(&db, "Work").await, compacted);
+
+        // Listed a folder at a time, each in its own order
+        let all: Vec<(Option<String>, i64, i64)> = BookmarkRepository::find_all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|b| (b.folder, b.id, b.position))
+            .collect();
+        let work = |(id, position)| (Some("Work".to_string()), id, position);
+        assert_eq!(
+            all,
+            [
+                (None, unfiled[1], 1024),
+                (None, unfiled[0], 2048),
+                work(compacted[0]),
+                work(compacted[1]),
+                work(compacted[2]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bookmark_positions_are_backfilled_on_open() {
+        let file = TempDatabase::new();
+        {
+            let db = SqliteDatabase::new(&file.url()).await.unwrap();
+            // The table as created before bookmarks could be reordered
+            sqlx::query("DROP TABLE bookmarks").execute(db.get_pool()).await.unwrap();
+            sqlx::query(
+                "CREATE TABLE bookmarks (id INTEGER PRIMARY KEY AUTOINCREMENT, title TEXT NOT NULL,
+                 url TEXT NOT NULL, folder TEXT, created_at TEXT NOT NULL, tags TEXT)",
+            )
+            .execute(db.get_pool())
+            .await
+            .unwrap();
+            for (title, folder, created_at) in [
+                ("Newest", Some("Work"), "2024-03-01T00:00:00+00:00"),
+                ("Oldest", Some("Work"), "2024-01-01T00:00:00+00:00"),
+                ("Unfiled", None, "2024-02-01T00:00:00+00:00"),
+                ("Middle", Some("Work"), "2024-02-01T00:00:00+00:00"),
+            ] {
+                sqlx::query(
+                    "INSERT INTO bookmarks (title, url, folder, created_at, tags) VALUES (?, ?, ?, ?, '[]')",
+                )
+                .bind(title)
+                .bind("https://example.com/")
+                .bind(folder)
+                .bind(created_at)
+                .execute(db.get_pool())
+                .await
+                .unwrap();
+            }
+        }
+
+        let db = SqliteDatabase::new(&file.url()).await.unwrap();
+        let work: Vec<(String, i64)> = BookmarkRepository::find_by_folder(&db, "Work")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|b| (b.title, b.position))
+            .collect();
+        let expected = [("Oldest", 1024), ("Middle", 2048), ("Newest", 3072)];
+        assert_eq!(work, expected.map(|(title, position)| (title.to_string(), position)));
+        let all = BookmarkRepository::find_all(&db).await.unwrap();
+        assert_eq!(all.iter().find(|b| b.title == "Unfiled").unwrap().position, 1024);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_bookmark_moves_keep_a_consistent_order() {
+        let file = TempDatabase::new();
+        // Two windows' worth of connections to the same profile
+        let first = Arc::new(SqliteDatabase::new(&file.url()).await.unwrap());
+        let second = Arc::new(SqliteDatabase::new(&file.url()).await.unwrap());
+        let ids = save_bookmarks(&first, Some("Work"), 8).await;
+
+        let movers = [first.clone(), second.clone()].into_iter().enumerate().map(|(window, db)| {
+            let ids = ids.clone();
+            tokio::spawn(async move {
+                for n in 0..40 {
+                    let id = ids[(window * 3 + n * 5) % ids.len()];
+                    db.move_to(id, Some("Work"), (n * 7 + window) % ids.len()).await?;
+                }
+                anyhow::Ok(())
+            })
+        });
+        for mover in movers.collect::<Vec<_>>() {
+            mover.await.unwrap().unwrap();
+        }
+
+        // Every bookmark is still there, each at its own position, and both
+        // connections read the same order
+        let order = folder_order(&first, "Work").await;
+        assert_eq!(order, folder_order(&second, "Work").await);
+        let mut moved: Vec<i64> = order.iter().map(|(id, _)| *id).collect();
+        moved.sort();
+        assert_eq!(moved, ids);
+        assert!(order.windows(2).all(|pair| pair[0].1 < pair[1].1), "{:?}", order);
+    }
+
+    #[tokio::test]
+    async fn test_added_visit_round_trips() {
+        let clock = Arc::new(ManualClock::default());
+        let db = SqliteDatabase::new(":memory:").await.unwrap().with_clock(clock.clone());
+        let url = ValidatedUrl::parse("https://example.com/").unwrap();
+
+        let first = db.add(NewHistoryEntry::at(url.clone(), "Old".to_string(), &*clock)).await.unwrap();
+        clock.advance(chrono::Duration::minutes(5));
+        let second = db.add(NewHistoryEntry::at(url.clone(), "New".to_string(), &*clock)).await.unwrap();
+        assert_eq!(first.id, second.id);
+        assert_eq!((second.title.as_str(), second.visit_count), ("New", 2));
+        assert_eq!(second.visited_at, clock.now());
+
+        let found = db.find_by_url(&url).await.unwrap().unwrap();
+        assert_eq!(found.id, second.id);
+        assert_eq!(found.visited_at, second.visited_at);
+        assert_eq!(found.visit_count, second.visit_count);
+
+        clock.advance(chrono::Duration::hours(1));
+        db.increment_visit_count(&url).await.unwrap();
+        let found = db.find_by_url(&url).await.unwrap().unwrap();
+        assert_eq!((found.visited_at, found.visit_count), (clock.now(), 3));
+
+        // More URLs than one query binds, with the visited one last
+        let mut links: Vec<ValidatedUrl> = (0..VISITED_QUERY_CHUNK + 10)
+            .map(|page| ValidatedUrl::parse(&format!("https://example.com/{}", page)).unwrap())
+            .collect();
+        links.push(ValidatedUrl::parse("https://EXAMPLE.com").unwrap());
+        let visited = db.visited(&links).await.unwrap();
+        assert_eq!(visited.iter().filter(|visited| **visited).count(), 1);
+        assert_eq!(visited.last(), Some(&true));
+    }
+
+    #[tokio::test]
+    async fn test_history_urls_are_normalized_once_on_open() {
+        let file = TempDatabase::new();
+        {
+            let db = SqliteDatabase::new(&file.url()).await.unwrap();
+            // Rows as written before visits were keyed by the normalized URL
+            for (url, title, visited_at, count) in [
+                ("https://example.com/", "Old", "2024-01-01T00:00:00+00:00", 2),
+                ("https://example.com/#top", "Newest", "2024-03-01T00:00:00+00:00", 1),
+                ("https://example.com./", "Middle", "2024-02-01T00:00:00+00:00", 4),
+                ("https://other.example/%7ea", "Other", "2024-01-01T00:00:00+00:00", 1),
+            ] {
+                sqlx::query("INSERT INTO history (url, title, visited_at, visit_count) VALUES (?, ?, ?, ?)")
+                    .bind(url)
+                    .bind(title)
+                    .bind(visited_at)
+                    .bind(count)
+                    .execute(db.get_pool())
+                    .await
+                    .unwrap();
+            }
+            sqlx::query("PRAGMA user_version = 0").execute(db.get_pool()).await.unwrap();
+        }
+
+        let db = SqliteDatabase::new(&file.url()).await.unwrap();
+        let mut entries = db.get_recent(10).await.unwrap();
+        entries.sort_by_key(|entry| entry.id);
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| (e.id, e.url.as_str(), e.title.as_str(), e.visit_count))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1, "https://example.com/", "Newest", 7),
+                (4, "https://other.example/~a", "Other", 1),
+            ]
+        );
+
+        let spelled = ValidatedUrl::parse("https://EXAMPLE.com:443/#bottom").unwrap();
+        let visit = db.add(NewHistoryEntry::new(spelled, "Again".to_string())).await.unwrap();
+        assert_eq!((visit.id, visit.visit_count), (1, 8));
+    }
+
+    #[tokio::test]
+    async fn test_delete_history_by_id_and_range() {
+        let clock = Arc::new(ManualClock::default());
+        let db = SqliteDatabase::new(":memory:").await.unwrap().with_clock(clock.clone());
+        let now = clock.now();
+        let visit = |url: &str, hours_ago: i64| {
+            clock.set(now - chrono::Duration::hours(hours_ago));
+            NewHistoryEntry::at(ValidatedUrl::parse(url).unwrap(), url.to_string(), &*clock)
+        };
+        let bookmarked = db.add(visit("https://example.com/saved", 1)).await.unwrap();
+        BookmarkRepository::save(&db, NewBookmark::new("Saved".to_string(), bookmarked.url.clone()))
+            .await
+            .unwrap();
+        db.add(visit("https://example.com/old", 30)).await.unwrap();
+        db.add(visit("https://example.com/older", 50)).await.unwrap();
+        db.add(visit("https://example.com/recent", 2)).await.unwrap();
+
+        db.delete_by_id(bookmarked.id).await.unwrap();
+        assert!(db.find_by_url(&bookmarked.url).await.unwrap().is_none());
+        assert_eq!(BookmarkRepository::find_all(&db).await.unwrap().len(), 1);
+
+        let deleted = db
+            .delete_range(now - chrono::Duration::hours(48), now - chrono::Duration::hours(24))
+            .await
+            .unwrap();
+        assert_eq!(deleted.entries.len(), 1);
+        let left: Vec<String> = db
+            .get_recent(10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.url.path().to_string())
+            .collect();
+        assert_eq!(left, vec!["/recent", "/older"]);
+    }
+
+    #[tokio::test]
+    async fn test_visits_are_recorded_with_their_type() {
+        let clock = Arc::new(ManualClock::default());
+        let db = SqliteDatabase::new(":memory:").await.unwrap().with_clock(clock.clone());
+        let visit = |url: &str, visit_type| {
+            clock.advance(chrono::Duration::minutes(1));
+            NewHistoryEntry::at(ValidatedUrl::parse(url).unwrap(), url.to_string(), &*clock)
+                .with_visit_type(visit_type)
+        };
+        let typed = db.add(visit("https://example.com/", VisitType::Typed)).await.unwrap();
+        db.add(visit("https://example.com/", VisitType::Link)).await.unwrap();
+        db.add_batch(&[
+            visit("https://example.com/saved", VisitType::Bookmark),
+            visit("https://example.com/#top", VisitType::Reload),
+        ])
+        .await
+        .unwrap();
+        let saved_url = ValidatedUrl::parse("https://example.com/saved").unwrap();
+        let saved = db.find_by_url(&saved_url).await.unwrap().unwrap();
+
+        let visits = db.visits(&[typed.id]).await.unwrap();
+        let types: Vec<VisitType> = visits.iter().map(|visit| visit.visit_type).collect();
+        assert_eq!(types, vec![VisitType::Typed, VisitType::Link, VisitType::Reload]);
+        assert!(visits.windows(2).all(|pair| pair[0].visited_at < pair[1].visited_at));
+        assert_eq!(db.find_by_url(&typed.url).await.unwrap().unwrap().visit_count, 3);
+
+        let both = db.visits(&[saved.id, typed.id]).await.unwrap();
+        assert_eq!(both.len(), 4);
+        assert_eq!(both[2].visit_type, VisitType::Bookmark);
+        assert_eq!(both[2].history_id, saved.id);
+        assert!(db.visits(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_deletions_cascade_to_visits() {
+        let clock = Arc::new(ManualClock::default());
+        let db = SqliteDatabase::new(":memory:").await.unwrap().with_clock(clock.clone());
+        let now = clock.now();
+        let mut ids = Vec::new();
+        for (path, hours_ago) in [("by-url", 1), ("by-id", 1), ("in-range", 30), ("kept", 2)] {
+            clock.set(now - chrono::Duration::hours(hours_ago));
+            let url = ValidatedUrl::parse(&format!("https://example.com/{}", path)).unwrap();
+            for visit_type in [VisitType::Typed, VisitType::Link] {
+                let entry = NewHistoryEntry::at(url.clone(), path.to_string(), &*clock).with_visit_type(visit_type);
+                ids.push(db.add(entry).await.unwrap().id);
+            }
+        }
+        ids.dedup();
+        let visit_count = |db: &SqliteDatabase| {
+            let pool = db.get_pool().clone();
+            async move {
+                sqlx::query_as::<_, (i64,)>("SELECT COUNT(*) FROM visits").fetch_one(&pool).await.unwrap().0
+            }
+        };
+        assert_eq!(visit_count(&db).await, 8);
+
+        db.delete_by_url(&ValidatedUrl::parse("https://example.com/by-url").unwrap()).await.unwrap();
+        db.delete_by_id(ids[1]).await.unwrap();
+        let deleted = db
+            .delete_range(now - chrono::Duration::hours(48), now - chrono::Duration::hours(24))
+            .await
+            .unwrap();
+        assert_eq!(deleted.entries.len(), 1);
+        assert!(db.visits(&ids[..3]).await.unwrap().is_empty());
+        assert_eq!(db.visits(&ids).await.unwrap().len(), 2);
+        assert_eq!(visit_count(&db).await, 2);
+
+        HistoryRepository::clear_all(&db).await.unwrap();
+        assert_eq!(visit_count(&db).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_deleting_a_range_keeps_the_visits_after_it() {
+        let clock = Arc::new(ManualClock::default());
+        let db = SqliteDatabase::new(":memory:").await.unwrap().with_clock(clock.clone());
+        let now = clock.now();
+        let hours = chrono::Duration::hours;
+        let visit = |url: &ValidatedUrl, hours_ago: i64| {
+            clock.set(now - hours(hours_ago));
+            NewHistoryEntry::at(url.clone(), url.to_string(), &*clock)
+        };
+        let kept = ValidatedUrl::parse("https://example.com/kept").unwrap();
+        let gone = ValidatedUrl::parse("https://example.com/gone").unwrap();
+        for hours_ago in [40, 30, 10] {
+            db.add(visit(&kept, hours_ago)).await.unwrap();
+        }
+        db.add(visit(&gone, 35)).await.unwrap();
+        let before = db.find_by_url(&kept).await.unwrap().unwrap();
+        let gone_before = db.find_by_url(&gone).await.unwrap().unwrap();
+
+        let deleted = db.delete_range(now - hours(48), now - hours(24)).await.unwrap();
+        let after = db.find_by_url(&kept).await.unwrap().unwrap();
+        assert_eq!((after.id, after.visit_count, after.visited_at), (before.id, 1, now - hours(10)));
+        let left = db.visits(&[before.id]).await.unwrap();
+        assert_eq!(left.iter().map(|visit| visit.visited_at).collect::<Vec<_>>(), vec![now - hours(10)]);
+        assert!(db.find_by_url(&gone).await.unwrap().is_none());
+        assert_eq!(deleted.visits.len(), 3);
+        let lost: Vec<(i64, i32)> = deleted.entries.iter().map(|entry| (entry.id, entry.visit_count)).collect();
+        assert_eq!(lost.len(), 2);
+        assert!(lost.contains(&(before.id, 2)) && lost.contains(&(gone_before.id, 1)));
+
+        // Undoing puts back the counts, the last visits and every visit
+        HistoryRepository::restore(&db, &deleted).await.unwrap();
+        assert_eq!(db.find_by_url(&kept).await.unwrap(), Some(before.clone()));
+        assert_eq!(db.find_by_url(&gone).await.unwrap(), Some(gone_before.clone()));
+        assert_eq!(db.visits(&[before.id]).await.unwrap().len(), 3);
+        assert_eq!(db.visits(&[gone_before.id]).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_entries_from_before_visits_get_one_link_visit() {
+        let file = TempDatabase::new();
+        {
+            let db = SqliteDatabase::new(&file.url()).await.unwrap();
+            for (url, visited_at, count) in [
+                ("https://example.com/", "2024-01-01T00:00:00+00:00", 12),
+                ("https://other.example/", "2024-02-01T00:00:00+00:00", 1),
+            ] {
+                sqlx::query("INSERT INTO history (url, title, visited_at, visit_count) VALUES (?, '', ?, ?)")
+                    .bind(url)
+                    .bind(visited_at)
+                    .bind(count)
+                    .execute(db.get_pool())
+                    .await
+                    .unwrap();
+            }
+            // As the profile was before visits were recorded
+            sqlx::query("DROP TABLE visits").execute(db.get_pool()).await.unwrap();
+            sqlx::query("PRAGMA user_version = 2").execute(db.get_pool()).await.unwrap();
+        }
+
+        for _ in 0..2 {
+            let db = SqliteDatabase::new(&file.url()).await.unwrap();
+            let entries = db.get_recent(10).await.unwrap();
+            let ids: Vec<i64> = entries.iter().map(|entry| entry.id).collect();
+            let visits = db.visits(&ids).await.unwrap();
+            assert_eq!(visits.len(), 2);
+            for entry in &entries {
+                let visit = visits.iter().find(|visit| visit.history_id == entry.id).unwrap();
+                assert_eq!((visit.visit_type, visit.visited_at), (VisitType::Link, entry.visited_at));
+            }
+            // The visits counted before still count towards frecency
+            let example = entries.iter().find(|entry| entry.visit_count == 12).unwrap();
+            assert_eq!(example.frecency(&visits, example.visited_at), 1200);
+        }
+    }
 }
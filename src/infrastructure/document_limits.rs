@@ -0,0 +1,373 @@
+use html5ever::interface::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use html5ever::tree_builder::TreeBuilderOpts;
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::{local_name, namespace_url, ns, parse_document, Attribute, ExpandedName, ParseOpts, QualName};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+
+/// Largest document body read before the rest is cut off
+pub const DEFAULT_MAX_DOCUMENT_SIZE: usize = 10 * 1024 * 1024;
+
+/// Input fed to the parser between checks of the time budget
+const PARSE_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Style of the banner listing the limits a page ran into
+const NOTICE_STYLE: &str = "background-color: #fff3cd; color: #664d03";
+
+/// Bounds on the work spent turning one document into a DOM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Wall-clock time after which the rest of the input is left unparsed
+    pub time_budget: Duration,
+    /// Nodes kept in the DOM; later ones are dropped
+    pub max_nodes: usize,
+    /// Deepest element nesting kept; anything deeper is dropped
+    pub max_depth: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self {
+            time_budget: Duration::from_secs(3),
+            max_nodes: 500_000,
+            max_depth: 512,
+        }
+    }
+}
+
+/// Which limits a document ran into
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseReport {
+    /// Size the body was cut to, if it was larger
+    pub truncated_at: Option<usize>,
+    /// Bytes of input left over when the time budget ran out
+    pub unparsed_bytes: usize,
+    /// Nodes dropped past the node ceiling
+    pub dropped_nodes: usize,
+    /// Nodes dropped for being nested too deeply
+    pub pruned_nodes: usize,
+}
+
+impl ParseReport {
+    pub fn is_limited(&self) -> bool {
+        self.truncated_at.is_some() || self.unparsed_bytes > 0 || self.dropped_nodes > 0 || self.pruned_nodes > 0
+    }
+
+    /// One line per limit hit, as shown at the top of the page
+    pub fn notices(&self) -> Vec<String> {
+        let mut notices = Vec::new();
+        if let Some(size) = self.truncated_at {
+            notices.push(format!("This page was cut off after {} bytes.", size));
+        }
+        if self.unparsed_bytes > 0 {
+            notices.push(format!(
+                "This page took too long to read; the last {} bytes were skipped.",
+                self.unparsed_bytes
+            ));
+        }
+        if self.dropped_nodes > 0 {
+            notices.push(format!("This page is too large; {} elements were left out.", self.dropped_nodes));
+        }
+        if self.pruned_nodes > 0 {
+            notices.push(format!("This page is nested too deeply; {} elements were left out.", self.pruned_nodes));
+        }
+        notices
+    }
+}
+
+/// Parse HTML within `limits`, putting a notice at the top of the body for
+/// each limit reached. `truncated_at` is where the body was cut off, if it was.
+pub fn parse_html_limited(html: &str, truncated_at: Option<usize>, limits: &ParseLimits) -> (RcDom, ParseReport) {
+    parse_html_scripted(html, truncated_at, limits, true)
+}
+
+/// Like [`parse_html_limited`], but with `scripting` off `<noscript>`
+/// content is parsed as markup, to be shown in place of the scripts
+pub fn parse_html_scripted(
+    html: &str,
+    truncated_at: Option<usize>,
+    limits: &ParseLimits,
+    scripting: bool,
+) -> (RcDom, ParseReport) {
+    tracing::info!("Parsing HTML ({} bytes)", html.len());
+    let started = Instant::now();
+    let opts = ParseOpts {
+        tree_builder: TreeBuilderOpts {
+            scripting_enabled: scripting,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut parser = parse_document(LimitedSink::new(limits.max_nodes), opts);
+
+    let mut rest = html;
+    while !rest.is_empty() && started.elapsed() < limits.time_budget {
+        let mut end = rest.len().min(PARSE_CHUNK_SIZE);
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+        parser.process(StrTendril::from_slice(&rest[..end]));
+        rest = &rest[end..];
+    }
+    let (mut dom, dropped_nodes) = parser.finish();
+
+    let report = ParseReport {
+        truncated_at,
+        unparsed_bytes: rest.len(),
+        dropped_nodes,
+        pruned_nodes: prune_depth(&dom, limits.max_depth),
+    };
+    if report.is_limited() {
+        let notices = report.notices();
+        for notice in &notices {
+            tracing::warn!("{}", notice);
+        }
+        insert_notices(&mut dom, &notices);
+    }
+    (dom, report)
+}
+
+/// Drop everything nested deeper than `max_depth`, returning how many
+/// nodes went. Iterative, so a nesting bomb cannot overflow the stack.
+fn prune_depth(dom: &RcDom, max_depth: usize) -> usize {
+    let mut pruned = 0;
+    let mut stack = vec![(dom.document.clone(), 0)];
+    while let Some((node, depth)) = stack.pop() {
+        if depth < max_depth {
+            stack.extend(node.children.borrow().iter().map(|child| (child.clone(), depth + 1)));
+            continue;
+        }
+
+        let mut removed = std::mem::take(&mut *node.children.borrow_mut());
+        while let Some(child) = removed.pop() {
+            pruned += 1;
+            removed.extend(std::mem::take(&mut *child.children.borrow_mut()));
+        }
+    }
+    pruned
+}
+
+/// Put one paragraph per notice at the start of the body
+fn insert_notices(dom: &mut RcDom, notices: &[String]) {
+    let Some(body) = find_body(&dom.document) else {
+        return;
+    };
+    for notice in notices.iter().rev() {
+        let style = Attribute {
+            name: QualName::new(None, ns!(), local_name!("style")),
+            value: NOTICE_STYLE.into(),
+        };
+        let paragraph = dom.create_element(
+            QualName::new(None, ns!(html), local_name!("p")),
+            vec![style],
+            ElementFlags::default(),
+        );
+        dom.append(&paragraph, NodeOrText::AppendText(StrTendril::from_slice(notice)));
+
+        let first = body.children.borrow().first().cloned();
+        match first {
+            Some(first) => dom.append_before_sibling(&first, NodeOrText::AppendNode(paragraph)),
+            None => dom.append(&body, NodeOrText::AppendNode(paragraph)),
+        }
+    }
+}
+
+/// The `<body>` under the document's `<html>` element
+fn find_body(document: &Handle) -> Option<Handle> {
+    let is = |node: &Handle, tag: &str| matches!(&node.data, NodeData::Element { name, .. } if &*name.local == tag);
+    let html = document.children.borrow().iter().find(|node| is(node, "html")).cloned()?;
+    let body = html.children.borrow().iter().find(|node| is(node, "body")).cloned();
+    body
+}
+
+/// An RcDom that stops growing once it holds `max_nodes` nodes; nodes the
+/// parser adds after that are counted and dropped
+struct LimitedSink {
+    dom: RcDom,
+    max_nodes: usize,
+    nodes: usize,
+    dropped: usize,
+}
+
+impl LimitedSink {
+    fn new(max_nodes: usize) -> Self {
+        Self {
+            dom: RcDom::default(),
+            max_nodes,
+            nodes: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Count a node about to be added, saying whether it may be
+    fn admit(&mut self) -> bool {
+        // Once anything is dropped, nothing else is let in, so later
+        // operations never refer to a node that was left detached
+        if self.dropped > 0 || self.nodes >= self.max_nodes {
+            self.dropped += 1;
+            return false;
+        }
+        self.nodes += 1;
+        true
+    }
+}
+
+impl TreeSink for LimitedSink {
+    type Handle = Handle;
+    type Output = (RcDom, usize);
+
+    fn finish(self) -> Self::Output {
+        (self.dom, self.dropped)
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.dom.parse_error(msg)
+    }
+
+    fn get_document(&mut self) -> Handle {
+        self.dom.get_document()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Handle) -> ExpandedName<'a> {
+        self.dom.elem_name(target)
+    }
+
+    fn create_element(&mut self, name: QualName, attrs: Vec<Attribute>, flags: ElementFlags) -> Handle {
+        self.dom.create_element(name, attrs, flags)
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Handle {
+        self.dom.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle {
+        self.dom.create_pi(target, data)
+    }
+
+    fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+        if self.admit() {
+            self.dom.append(parent, child)
+        }
+    }
+
+    fn append_based_on_parent_node(&mut self, element: &Handle, prev_element: &Handle, child: NodeOrText<Handle>) {
+        if self.admit() {
+            self.dom.append_based_on_parent_node(element, prev_element, child)
+        }
+    }
+
+    fn append_doctype_to_document(&mut self, name: StrTendril, public_id: StrTendril, system_id: StrTendril) {
+        self.dom.append_doctype_to_document(name, public_id, system_id)
+    }
+
+    fn mark_script_already_started(&mut self, node: &Handle) {
+        self.dom.mark_script_already_started(node)
+    }
+
+    fn get_template_contents(&mut self, target: &Handle) -> Handle {
+        self.dom.get_template_contents(target)
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        self.dom.same_node(x, y)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.dom.set_quirks_mode(mode)
+    }
+
+    fn append_before_sibling(&mut self, sibling: &Handle, new_node: NodeOrText<Handle>) {
+        if self.admit() {
+            self.dom.append_before_sibling(sibling, new_node)
+        }
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+        self.dom.add_attrs_if_missing(target, attrs)
+    }
+
+    fn remove_from_parent(&mut self, target: &Handle) {
+        self.dom.remove_from_parent(target)
+    }
+
+    fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+        self.dom.reparent_children(node, new_parent)
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &Handle) -> bool {
+        self.dom.is_mathml_annotation_xml_integration_point(handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::LayoutBuilder;
+
+    fn body_text(dom: &RcDom) -> String {
+        LayoutBuilder::new().build(dom).text
+    }
+
+    #[test]
+    fn test_ordinary_page_is_untouched() {
+        let (dom, report) = parse_html_limited("<p>Hello</p>", None, &ParseLimits::default());
+        assert_eq!(report, ParseReport::default());
+        assert_eq!(body_text(&dom).trim(), "Hello");
+    }
+
+    #[test]
+    fn test_nesting_bomb_is_pruned_quickly() {
+        let depth = 200_000;
+        let html = format!("{}deep{}", "<div>".repeat(depth), "</div>".repeat(depth));
+        let limits = ParseLimits {
+            time_budget: Duration::from_millis(500),
+            ..ParseLimits::default()
+        };
+        let started = Instant::now();
+        let (dom, report) = parse_html_limited(&html, None, &limits);
+        let text = body_text(&dom);
+
+        // The tree builder slows down as nesting grows, so the budget is
+        // what ends this; whatever was built is then pruned
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(report.pruned_nodes > 0);
+        assert!(report.unparsed_bytes > 0);
+        assert!(text.starts_with("This page took too long to read"), "{}", text);
+        assert!(text.contains("This page is nested too deeply"));
+    }
+
+    #[test]
+    fn test_node_ceiling_drops_later_nodes() {
+        let html = format!("<p>first</p>{}<p>last</p>", "<span>x</span>".repeat(10_000));
+        let limits = ParseLimits {
+            max_nodes: 1_000,
+            ..ParseLimits::default()
+        };
+        let (dom, report) = parse_html_limited(&html, None, &limits);
+        let text = body_text(&dom);
+
+        assert!(report.dropped_nodes > 10_000);
+        assert!(text.contains("first"));
+        assert!(!text.contains("last"));
+        assert!(text.contains("too large"));
+    }
+
+    #[test]
+    fn test_entity_flood_stays_within_budget() {
+        // HTML has no user-defined entities, so "billion laughs" can only
+        // be a flood of references, each expanding to a few bytes at most
+        let html = format!("<p>{}</p>", "&lol;&amp;&NotNestedGreaterGreater;&#x1F600;".repeat(100_000));
+        let limits = ParseLimits {
+            time_budget: Duration::from_millis(50),
+            ..ParseLimits::default()
+        };
+        let started = Instant::now();
+        let (dom, report) = parse_html_limited(&html, Some(html.len()), &limits);
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(report.unparsed_bytes < html.len());
+        assert!(body_text(&dom).starts_with("This page was cut off after"));
+        assert!(!report.notices().is_empty());
+    }
+}
@@ -0,0 +1,625 @@
+use super::crash::{panic_message, CrashPage};
+use super::css::SelectorList;
+use super::document_limits::{parse_html_scripted, ParseLimits};
+use super::page_scripts::ConsoleLog;
+use super::timers::{TimerId, TimerScheduler};
+use crate::domain::{ConsoleLevel, RenderError};
+use html5ever::serialize::{serialize, SerializeOpts};
+use html5ever::tendril::StrTendril;
+use html5ever::{namespace_url, ns, Attribute, LocalName, QualName};
+use markup5ever_rcdom::{Handle, Node, NodeData, RcDom, SerializableHandle};
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+type Result<T> = std::result::Result<T, RenderError>;
+
+/// Elements whose text is never shown, left out of `innerText`
+const UNRENDERED_ELEMENTS: &[&str] = &["head", "script", "style", "template", "noscript"];
+
+/// A timer callback, given the document to work on
+pub type TimerCallback = Box<dyn FnMut(&mut DomDocument) + Send>;
+
+/// An element handed out by a `DomDocument`, valid until another document
+/// is loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ElementId(usize);
+
+/// The document a page's scripts read and change. Its nodes are not `Send`,
+/// so it lives on the DOM thread alone and is reached through a `DomThread`.
+pub struct DomDocument {
+    dom: RcDom,
+    /// Elements handed out so far, indexed by `ElementId`
+    elements: Vec<Handle>,
+    /// Changed since the page was last laid out
+    dirty: bool,
+    /// Pending `setTimeout` and `setInterval` callbacks, dropped with the page
+    timers: TimerScheduler<TimerCallback>,
+    /// The tab's console, told about timers refused over the cap
+    console: Option<Arc<ConsoleLog>>,
+}
+
+impl DomDocument {
+    /// Parse a document the way pages with scripts on are parsed
+    pub fn parse(html: &str, truncated_at: Option<usize>, limits: &ParseLimits) -> Self {
+        let (dom, _) = parse_html_scripted(html, truncated_at, limits, true);
+        Self {
+            dom,
+            elements: Vec::new(),
+            dirty: false,
+            timers: TimerScheduler::new(),
+            console: None,
+        }
+    }
+
+    /// `document.getElementById`
+    pub fn get_element_by_id(&mut self, id: &str) -> Option<ElementId> {
+        let found = self
+            .all_elements()
+            .into_iter()
+            .find(|element| attribute(element, "id").as_deref() == Some(id))?;
+        Some(self.id_of(&found))
+    }
+
+    /// `document.querySelector`; None for a selector list stylesheets do
+    /// not support either
+    pub fn query_selector(&mut self, selectors: &str) -> Option<Option<ElementId>> {
+        Some(self.query_selector_all(selectors)?.first().copied())
+    }
+
+    /// `document.querySelectorAll`, in document order
+    pub fn query_selector_all(&mut self, selectors: &str) -> Option<Vec<ElementId>> {
+        let selectors = SelectorList::parse(selectors)?;
+        let matching: Vec<Handle> = self
+            .all_elements()
+            .into_iter()
+            .filter(|element| {
+                let class = attribute(element, "class").unwrap_or_default();
+                let classes: Vec<&str> = class.split_whitespace().collect();
+                let id = attribute(element, "id");
+                selectors.matches(&tag_name(element), id.as_deref(), &classes)
+            })
+            .collect();
+        Some(matching.iter().map(|element| self.id_of(element)).collect())
+    }
+
+    /// `document.getElementsByTagName`; `*` lists every element
+    pub fn get_elements_by_tag_name(&mut self, tag: &str) -> Vec<ElementId> {
+        let tag = tag.to_ascii_lowercase();
+        let matching: Vec<Handle> = self
+            .all_elements()
+            .into_iter()
+            .filter(|element| tag == "*" || tag_name(element) == tag)
+            .collect();
+        matching.iter().map(|element| self.id_of(element)).collect()
+    }
+
+    /// `element.textContent`: the text of every descendant
+    pub fn text_content(&self, element: ElementId) -> Option<String> {
+        let mut text = String::new();
+        collect_text(self.element(element)?, &mut text, false);
+        Some(text)
+    }
+
+    /// Set `element.textContent`, replacing its children with the text
+    pub fn set_text_content(&mut self, element: ElementId, text: &str) {
+        let Some(handle) = self.element(element).cloned() else {
+            return;
+        };
+        for child in handle.children.borrow_mut().drain(..) {
+            child.parent.set(None);
+        }
+        if !text.is_empty() {
+            let node = Node::new(NodeData::Text {
+                contents: RefCell::new(StrTendril::from(text)),
+            });
+            node.parent.set(Some(Rc::downgrade(&handle)));
+            handle.children.borrow_mut().push(node);
+        }
+        self.dirty = true;
+    }
+
+    /// `element.innerText`: the text shown, without scripts and styles and
+    /// with whitespace collapsed
+    pub fn inner_text(&self, element: ElementId) -> Option<String> {
+        let mut text = String::new();
+        collect_text(self.element(element)?, &mut text, true);
+        let lines: Vec<String> = text
+            .split('\n')
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        Some(lines.join("\n").trim().to_string())
+    }
+
+    /// `element.getAttribute`
+    pub fn get_attribute(&self, element: ElementId, name: &str) -> Option<String> {
+        attribute(self.element(element)?, &name.to_ascii_lowercase())
+    }
+
+    /// `element.setAttribute`
+    pub fn set_attribute(&mut self, element: ElementId, name: &str, value: &str) {
+        let Some(NodeData::Element { attrs, .. }) = self.element(element).map(|handle| &handle.data) else {
+            return;
+        };
+        let name = name.to_ascii_lowercase();
+        let mut attrs = attrs.borrow_mut();
+        match attrs.iter_mut().find(|attr| attr.name.local.as_ref() == name) {
+            Some(attr) => attr.value = StrTendril::from(value),
+            None => attrs.push(Attribute {
+                name: QualName::new(None, ns!(), LocalName::from(name)),
+                value: StrTendril::from(value),
+            }),
+        }
+        drop(attrs);
+        self.dirty = true;
+    }
+
+    /// `element.classList.add`
+    pub fn class_list_add(&mut self, element: ElementId, class: &str) {
+        let mut classes = self.classes(element);
+        if !classes.iter().any(|existing| existing == class) {
+            classes.push(class.to_string());
+            self.set_attribute(element, "class", &classes.join(" "));
+        }
+    }
+
+    /// `element.classList.remove`
+    pub fn class_list_remove(&mut self, element: ElementId, class: &str) {
+        let mut classes = self.classes(element);
+        let before = classes.len();
+        classes.retain(|existing| existing != class);
+        if classes.len() != before {
+            self.set_attribute(element, "class", &classes.join(" "));
+        }
+    }
+
+    /// `element.classList.contains`
+    pub fn class_list_contains(&self, element: ElementId, class: &str) -> bool {
+        self.classes(element).iter().any(|existing| existing == class)
+    }
+
+    /// `setTimeout`
+    pub fn set_timeout(&mut self, delay: Duration, callback: TimerCallback) -> Result<TimerId> {
+        let set = self.timers.set_timeout(delay, callback);
+        self.warn_on_error(set)
+    }
+
+    /// `setInterval`
+    pub fn set_interval(&mut self, delay: Duration, callback: TimerCallback) -> Result<TimerId> {
+        let set = self.timers.set_interval(delay, callback);
+        self.warn_on_error(set)
+    }
+
+    fn warn_on_error<T>(&self, result: Result<T>) -> Result<T> {
+        if let (Err(e), Some(console)) = (&result, &self.console) {
+            console.push(ConsoleLevel::Warn, e.to_string(), None);
+        }
+        result
+    }
+
+    /// `clearTimeout` and `clearInterval`
+    pub fn clear_timer(&mut self, id: TimerId) -> bool {
+        self.timers.clear(id)
+    }
+
+    /// Run the timers due at `now`, time since the DOM thread started, in
+    /// deadline order; returns how many ran
+    pub fn run_timers(&mut self, now: Duration) -> usize {
+        self.timers.advance_to(now);
+        let horizon = self.timers.horizon();
+        let mut ran = 0;
+        while let Some((id, mut callback)) = self.timers.take_due(horizon) {
+            callback(self);
+            self.timers.finish(id, callback);
+            ran += 1;
+        }
+        ran
+    }
+
+    /// Whether anything changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// The document as HTML, as it is now
+    pub fn to_html(&self) -> String {
+        let mut html = Vec::new();
+        let document: SerializableHandle = self.dom.document.clone().into();
+        if let Err(e) = serialize(&mut html, &document, SerializeOpts::default()) {
+            tracing::warn!("Could not serialize the document: {}", e);
+        }
+        String::from_utf8_lossy(&html).into_owned()
+    }
+
+    fn element(&self, element: ElementId) -> Option<&Handle> {
+        self.elements.get(element.0)
+    }
+
+    fn classes(&self, element: ElementId) -> Vec<String> {
+        self.get_attribute(element, "class")
+            .map(|class| class.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    /// The id an element was handed out with, handing it out if it was not
+    fn id_of(&mut self, element: &Handle) -> ElementId {
+        let index = match self.elements.iter().position(|known| Rc::ptr_eq(known, element)) {
+            Some(index) => index,
+            None => {
+                self.elements.push(element.clone());
+                self.elements.len() - 1
+            }
+        };
+        ElementId(index)
+    }
+
+    /// Every element in document order
+    fn all_elements(&self) -> Vec<Handle> {
+        fn walk(handle: &Handle, elements: &mut Vec<Handle>) {
+            if matches!(handle.data, NodeData::Element { .. }) {
+                elements.push(handle.clone());
+            }
+            for child in handle.children.borrow().iter() {
+                walk(child, elements);
+            }
+        }
+        let mut elements = Vec::new();
+        walk(&self.dom.document, &mut elements);
+        elements
+    }
+}
+
+fn tag_name(handle: &Handle) -> String {
+    match &handle.data {
+        NodeData::Element { name, .. } => name.local.to_ascii_lowercase().to_string(),
+        _ => String::new(),
+    }
+}
+
+fn attribute(handle: &Handle, name: &str) -> Option<String> {
+    let NodeData::Element { attrs, .. } = &handle.data else {
+        return None;
+    };
+    let attrs = attrs.borrow();
+    attrs
+        .iter()
+        .find(|attr| attr.name.local.as_ref() == name)
+        .map(|attr| attr.value.to_string())
+}
+
+/// Append the text under `handle`; `rendered` skips what is never shown
+/// and breaks lines at `<br>`
+fn collect_text(handle: &Handle, text: &mut String, rendered: bool) {
+    match &handle.data {
+        NodeData::Text { contents } => text.push_str(&contents.borrow()),
+        NodeData::Element { .. } if rendered => {
+            let tag = tag_name(handle);
+            if tag == "br" {
+                text.push('\n');
+            }
+            if UNRENDERED_ELEMENTS.contains(&tag.as_str()) {
+                return;
+            }
+        }
+        _ => {}
+    }
+    for child in handle.children.borrow().iter() {
+        collect_text(child, text, rendered);
+    }
+}
+
+/// What the DOM thread keeps between jobs
+struct DomState {
+    document: Option<DomDocument>,
+    /// Where the document was loaded from, named in crash reports
+    url: Option<String>,
+    /// Why the document was dropped, if work on it panicked
+    crashed: Option<String>,
+    /// Shortest timer delay the tab's activity allows; `None` while it is
+    /// suspended and runs no timers
+    timer_floor: Option<Duration>,
+}
+
+impl DomState {
+    /// Drop the document whose work panicked, and its pending timers, so
+    /// the thread goes on serving the tab
+    fn crash(&mut self, payload: &(dyn Any + Send)) -> RenderError {
+        let message = panic_message(payload);
+        tracing::error!("The page {} crashed: {}", self.url.as_deref().unwrap_or("about:blank"), message);
+        self.document = None;
+        self.crashed = Some(message.clone());
+        RenderError::Crashed(message)
+    }
+}
+
+type DomJob = Box<dyn FnOnce(&mut DomState) + Send>;
+
+/// The thread a tab's document lives on. Work is sent to it as closures
+/// over a channel, so the rest of the browser never touches DOM nodes;
+/// between jobs it runs the page's timers as they come due. A job or timer
+/// that panics takes down the document, not the thread or other tabs.
+pub struct DomThread {
+    jobs: mpsc::Sender<DomJob>,
+    console: Option<Arc<ConsoleLog>>,
+}
+
+impl DomThread {
+    pub fn spawn() -> Self {
+        let (jobs, queue) = mpsc::channel::<DomJob>();
+        let spawned = std::thread::Builder::new()
+            .name("dom".to_string())
+            .spawn(move || run_dom_thread(queue));
+        if let Err(e) = spawned {
+            tracing::error!("Could not start the DOM thread: {}", e);
+        }
+        Self { jobs, console: None }
+    }
+
+    /// Log what the documents loaded from now on refuse to the tab's console
+    pub fn with_console(mut self, console: Arc<ConsoleLog>) -> Self {
+        self.console = Some(console);
+        self
+    }
+
+    /// Replace the document with one parsed from `html`, loaded from
+    /// `url`, dropping the old one's timers
+    pub async fn load(
+        &self,
+        url: String,
+        html: String,
+        truncated_at: Option<usize>,
+        limits: ParseLimits,
+    ) -> Result<()> {
+        let console = self.console.clone();
+        self.send(move |state| {
+            state.document = None;
+            state.crashed = None;
+            let _page = CrashPage::enter(&url);
+            state.url = Some(url);
+            let mut document = DomDocument::parse(&html, truncated_at, &limits);
+            document.console = console;
+            document.timers.set_floor(state.timer_floor.unwrap_or_default());
+            state.document = Some(document);
+        })
+        .await
+    }
+
+    /// Drop the document and its timers, as for a page without scripts
+    pub async fn unload(&self) -> Result<()> {
+        self.send(|state| {
+            state.document = None;
+            state.url = None;
+            state.crashed = None;
+        })
+        .await
+    }
+
+    /// Run timers no more often than `floor`, or none at all for `None`;
+    /// returns without waiting for the thread
+    pub fn throttle_timers(&self, floor: Option<Duration>) {
+        let job: DomJob = Box::new(move |state| {
+            state.timer_floor = floor;
+            if let Some(document) = &mut state.document {
+                document.timers.set_floor(floor.unwrap_or_default());
+            }
+        });
+        let _ = self.jobs.send(job);
+    }
+
+    /// Run `f` on the document on the DOM thread; `RenderError::Crashed`
+    /// if it, or an earlier job or timer, panicked
+    pub async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DomDocument) -> T + Send + 'static,
+    {
+        self.send(move |state| match (state.document.as_mut(), &state.crashed) {
+            (Some(document), _) => Ok(f(document)),
+            (None, Some(message)) => Err(RenderError::Crashed(message.clone())),
+            (None, None) => Err(RenderError::Script("No document is loaded".to_string())),
+        })
+        .await?
+    }
+
+    async fn send<T, F>(&self, job: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DomState) -> T + Send + 'static,
+    {
+        let stopped = || RenderError::Script("The DOM thread has stopped".to_string());
+        let (reply, answer) = tokio::sync::oneshot::channel();
+        let job: DomJob = Box::new(move |state| {
+            let done = catch_unwind(AssertUnwindSafe(|| job(&mut *state)));
+            let _ = reply.send(done.map_err(|payload| state.crash(&*payload)));
+        });
+        self.jobs.send(job).map_err(|_| stopped())?;
+        answer.await.map_err(|_| stopped())?
+    }
+}
+
+/// Run jobs as they arrive and timers as they come due, until the owning
+/// renderer drops its sender
+fn run_dom_thread(queue: mpsc::Receiver<DomJob>) {
+    let started = Instant::now();
+    let mut state = DomState {
+        document: None,
+        url: None,
+        crashed: None,
+        timer_floor: Some(Duration::ZERO),
+    };
+    loop {
+        let next_timer = match (&mut state.document, state.timer_floor) {
+            (Some(document), Some(_)) => document.timers.next_deadline(),
+            _ => None,
+        };
+        let job = match next_timer {
+            Some(deadline) => match queue.recv_timeout(deadline.saturating_sub(started.elapsed())) {
+                Ok(job) => Some(job),
+                Err(mpsc::RecvTimeoutError::Timeout) => None,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            },
+            None => match queue.recv() {
+                Ok(job) => Some(job),
+                Err(_) => break,
+            },
+        };
+        let _page = state.url.as_deref().map(CrashPage::enter);
+        if let Some(document) = &mut state.document {
+            document.timers.advance_to(started.elapsed());
+        }
+        if let Some(job) = job {
+            job(&mut state);
+        }
+        if let (Some(document), Some(_)) = (&mut state.document, state.timer_floor) {
+            if let Err(payload) = catch_unwind(AssertUnwindSafe(|| document.run_timers(started.elapsed()))) {
+                state.crash(&*payload);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = "<html><head><title>T</title><style>p{}</style></head><body>\
+        <div id=\"main\" class=\"box wide\"><p class=\"note\">One</p><p>Two<br>lines</p></div>\
+        <script>ignored()</script></body></html>";
+    const URL: &str = "https://example.com/";
+
+    fn document() -> DomDocument {
+        DomDocument::parse(PAGE, None, &ParseLimits::default())
+    }
+
+    #[test]
+    fn test_elements_are_found_by_id_tag_and_selector() {
+        let mut document = document();
+        let main = document.get_element_by_id("main").unwrap();
+        assert_eq!(document.get_element_by_id("missing"), None);
+        assert_eq!(document.query_selector("div.box"), Some(Some(main)));
+        assert_eq!(document.query_selector("p, div").unwrap(), Some(main));
+        assert_eq!(document.query_selector_all("p").unwrap().len(), 2);
+        assert_eq!(document.query_selector_all(".note").unwrap().len(), 1);
+        assert_eq!(document.query_selector("div > p"), None);
+        assert_eq!(document.get_elements_by_tag_name("P").len(), 2);
+        assert!(document.get_elements_by_tag_name("*").len() > 5);
+        assert!(!document.take_dirty());
+    }
+
+    #[test]
+    fn test_text_attributes_and_classes_change_the_document() {
+        let mut document = document();
+        let main = document.get_element_by_id("main").unwrap();
+        assert_eq!(document.text_content(main).as_deref(), Some("OneTwolines"));
+        let body = document.get_elements_by_tag_name("body")[0];
+        assert_eq!(document.inner_text(body).as_deref(), Some("OneTwo\nlines"));
+
+        assert!(document.class_list_contains(main, "wide"));
+        document.class_list_remove(main, "wide");
+        document.class_list_add(main, "open");
+        assert_eq!(document.get_attribute(main, "class").as_deref(), Some("box open"));
+        document.set_attribute(main, "Title", "Main");
+        assert_eq!(document.get_attribute(main, "title").as_deref(), Some("Main"));
+
+        let note = document.query_selector(".note").unwrap().unwrap();
+        document.set_text_content(note, "Changed");
+        assert!(document.take_dirty());
+        assert!(document.to_html().contains("<p class=\"note\">Changed</p>"));
+    }
+
+    #[tokio::test]
+    async fn test_thread_runs_work_on_the_loaded_document() {
+        let dom = DomThread::spawn();
+        assert!(matches!(dom.run(|_| ()).await, Err(RenderError::Script(_))));
+
+        dom.load(URL.to_string(), PAGE.to_string(), None, ParseLimits::default()).await.unwrap();
+        let title = dom
+            .run(|document| {
+                let title = document.get_elements_by_tag_name("title")[0];
+                document.text_content(title)
+            })
+            .await
+            .unwrap();
+        assert_eq!(title.as_deref(), Some("T"));
+
+        dom.unload().await.unwrap();
+        assert!(dom.run(|_| ()).await.is_err());
+    }
+
+    /// A callback appending `text` to the first `.note`
+    fn append(text: &'static str) -> TimerCallback {
+        Box::new(move |document: &mut DomDocument| {
+            let note = document.query_selector(".note").flatten().unwrap();
+            let old = document.text_content(note).unwrap_or_default();
+            document.set_text_content(note, &(old + text));
+        })
+    }
+
+    fn note(document: &mut DomDocument) -> Option<String> {
+        let note = document.query_selector(".note").flatten()?;
+        document.text_content(note)
+    }
+
+    #[test]
+    fn test_timers_change_the_document_in_deadline_order() {
+        let mut document = document();
+        document.set_timeout(Duration::from_millis(20), append("!")).unwrap();
+        let tick = document.set_interval(Duration::from_millis(10), append("+")).unwrap();
+
+        // The interval comes due again at 20ms, but runs on the next pass
+        assert_eq!(document.run_timers(Duration::from_millis(20)), 2);
+        assert_eq!(document.run_timers(Duration::from_millis(20)), 1);
+        assert!(document.clear_timer(tick));
+        assert_eq!(document.run_timers(Duration::from_secs(1)), 0);
+        assert_eq!(note(&mut document).as_deref(), Some("One+!+"));
+        assert!(document.take_dirty());
+    }
+
+    #[tokio::test]
+    async fn test_thread_runs_timers_unless_suspended() {
+        let console = Arc::new(ConsoleLog::new());
+        let dom = DomThread::spawn().with_console(console.clone());
+        dom.load(URL.to_string(), PAGE.to_string(), None, ParseLimits::default()).await.unwrap();
+
+        dom.throttle_timers(None);
+        dom.run(|document| document.set_timeout(Duration::ZERO, append("!"))).await.unwrap().unwrap();
+        assert_eq!(dom.run(note).await.unwrap().as_deref(), Some("One"));
+        dom.throttle_timers(Some(Duration::ZERO));
+        assert_eq!(dom.run(note).await.unwrap().as_deref(), Some("One!"));
+
+        // Navigating away drops what is still pending
+        dom.run(|document| document.set_timeout(Duration::from_secs(3600), append("?"))).await.unwrap().unwrap();
+        dom.load(URL.to_string(), PAGE.to_string(), None, ParseLimits::default()).await.unwrap();
+        assert_eq!(dom.run(|document| document.timers.len()).await.unwrap(), 0);
+
+        // Timers over the cap are refused and reported to the tab's console
+        let refused = dom.run(|document| {
+            document.timers = TimerScheduler::new().with_max_timers(0);
+            document.set_timeout(Duration::ZERO, append("x")).is_err()
+        });
+        assert!(refused.await.unwrap());
+        assert_eq!(console.messages()[0].level, ConsoleLevel::Warn);
+    }
+
+    #[tokio::test]
+    async fn test_panics_drop_the_document_but_not_the_thread() {
+        let dom = DomThread::spawn();
+        dom.load(URL.to_string(), PAGE.to_string(), None, ParseLimits::default()).await.unwrap();
+        let crashed = dom.run(|_| -> () { panic!("script bug") }).await;
+        assert!(matches!(crashed, Err(RenderError::Crashed(message)) if message == "script bug"));
+        assert!(matches!(dom.run(note).await, Err(RenderError::Crashed(_))));
+
+        // A panicking timer is caught between jobs the same way
+        dom.load(URL.to_string(), PAGE.to_string(), None, ParseLimits::default()).await.unwrap();
+        assert_eq!(dom.run(note).await.unwrap().as_deref(), Some("One"));
+        let timer: TimerCallback = Box::new(|_| panic!("timer bug"));
+        dom.run(|document| document.set_timeout(Duration::ZERO, timer)).await.unwrap().unwrap();
+        assert!(matches!(dom.run(note).await, Err(RenderError::Crashed(message)) if message == "timer bug"));
+
+        dom.load(URL.to_string(), PAGE.to_string(), None, ParseLimits::default()).await.unwrap();
+        assert_eq!(dom.run(note).await.unwrap().as_deref(), Some("One"));
+    }
+}
@@ -0,0 +1,198 @@
+use super::external_launcher::open_with_system;
+use super::profile_dir::ProfileDir;
+use super::safe_file::SafeFileWriter;
+use crate::domain::{DownloadError, DownloadWriter};
+use async_trait::async_trait;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Numbered variants tried before giving up on a file name
+const MAX_NAME_ATTEMPTS: u32 = 1000;
+/// Bytes written between checks for a pause
+const DEFAULT_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Writes downloads to the local file system
+#[derive(Debug, Clone)]
+pub struct LocalDownloadWriter {
+    /// Kept alive while downloads may still go into it
+    profile: Option<Arc<ProfileDir>>,
+    /// Set by [`DownloadWriter::pause`]; shared by clones
+    paused: Arc<AtomicBool>,
+    /// Held for reading by each write under way, so pausing can wait for
+    /// them to stop
+    writing: Arc<RwLock<()>>,
+    chunk_size: usize,
+}
+
+impl LocalDownloadWriter {
+    pub fn new() -> Self {
+        Self {
+            profile: None,
+            paused: Arc::new(AtomicBool::new(false)),
+            writing: Arc::new(RwLock::new(())),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Download into `profile` when it keeps its own downloads directory
+    pub fn with_profile(mut self, profile: Arc<ProfileDir>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Write in chunks of `bytes`, checking for a pause before each
+    pub fn with_chunk_size(mut self, bytes: usize) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+}
+
+impl Default for LocalDownloadWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `name`, then `name (1)`, `name (2)`, ... with the extension kept last
+fn numbered_name(file_name: &str, number: u32) -> String {
+    if number == 0 {
+        return file_name.to_string();
+    }
+    match file_name.rsplit_once('.').filter(|(stem, _)| !stem.is_empty()) {
+        Some((stem, extension)) => format!("{} ({}).{}", stem, number, extension),
+        None => format!("{} ({})", file_name, number),
+    }
+}
+
+#[async_trait]
+impl DownloadWriter for LocalDownloadWriter {
+    fn default_directory(&self) -> PathBuf {
+        if let Some(directory) = self.profile.as_ref().and_then(|profile| profile.downloads_directory()) {
+            return directory;
+        }
+        match std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")) {
+            Some(home) => PathBuf::from(home).join("Downloads"),
+            None => PathBuf::from("downloads"),
+        }
+    }
+
+    async fn write_new(
+        &self,
+        directory: &Path,
+        file_name: &str,
+        contents: &[u8],
+        cancelled: Arc<AtomicBool>,
+    ) -> Result<PathBuf, DownloadError> {
+        let failed = |path: &Path, e: std::io::Error| DownloadError::Write {
+            path: path.display().to_string(),
+            reason: e.to_string(),
+        };
+        let _writing = self.writing.read().await;
+        if self.paused.load(Ordering::SeqCst) {
+            return Err(DownloadError::Paused(file_name.to_string()));
+        }
+        tokio::fs::create_dir_all(directory).await.map_err(|e| failed(directory, e))?;
+
+        let target = directory.join(file_name);
+        let (directory, file_name, contents) = (directory.to_path_buf(), file_name.to_string(), contents.to_vec());
+        let (paused, chunk_size) = (self.paused.clone(), self.chunk_size);
+        let write = tokio::task::spawn_blocking(move || {
+            for number in 0..MAX_NAME_ATTEMPTS {
+                let path = directory.join(numbered_name(&file_name, number));
+                if path.exists() {
+                    continue;
+                }
+                // Creating and committing exclusively keeps two saves from
+                // picking the same name, and a half-written file never shows
+                // under it
+                let mut writer = match SafeFileWriter::create_new(&path) {
+                    Ok(writer) => writer,
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                    Err(e) => return Err(failed(&path, e)),
+                };
+                for chunk in contents.chunks(chunk_size) {
+                    // Dropping the writer takes the part file with it
+                    if cancelled.load(Ordering::SeqCst) {
+                        return Err(DownloadError::Cancelled(path.display().to_string()));
+                    }
+                    if paused.load(Ordering::SeqCst) {
+                        // Keep what was written, to resume after a restart
+                        writer.pause().map_err(|e| failed(&path, e))?;
+                        return Err(DownloadError::Paused(path.display().to_string()));
+                    }
+                    writer.write_all(chunk).map_err(|e| failed(&path, e))?;
+                }
+                match writer.commit_new() {
+                    Ok(path) => return Ok(path),
+                    Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                    Err(e) => return Err(failed(&path, e)),
+                }
+            }
+            Err(DownloadError::NameTaken(file_name))
+        });
+        write.await.map_err(|e| failed(&target, std::io::Error::other(e)))?
+    }
+
+    async fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        // Writes under way notice at their next chunk
+        let _stopped = self.writing.write().await;
+    }
+
+    async fn delete(&self, path: &Path) -> Result<(), DownloadError> {
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(DownloadError::Delete {
+                path: path.display().to_string(),
+                reason: e.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Show the folder holding `path` in the system's file manager
+pub fn open_containing_folder(path: &Path) -> std::io::Result<()> {
+    open_with_system(path.parent().unwrap_or(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_taken_names_get_numbered() {
+        let directory = std::env::temp_dir().join(format!("navigator-downloads-{}", uuid::Uuid::new_v4()));
+        let writer = LocalDownloadWriter::new();
+
+        let first = writer.write_new(&directory, "Rust.html", b"one", Arc::default()).await.unwrap();
+        let second = writer.write_new(&directory, "Rust.html", b"two", Arc::default()).await.unwrap();
+        let bare = writer.write_new(&directory, "notes", b"three", Arc::default()).await.unwrap();
+        writer.write_new(&directory, "notes", b"four", Arc::default()).await.unwrap();
+
+        assert_eq!(first, directory.join("Rust.html"));
+        assert_eq!(second, directory.join("Rust (1).html"));
+        assert_eq!(std::fs::read(&first).unwrap(), b"one");
+        assert_eq!(std::fs::read(&second).unwrap(), b"two");
+        // Written through a part file, which is gone once the download is in place
+        assert!(!super::super::safe_file::part_path(&first).exists());
+        assert_eq!(bare, directory.join("notes"));
+        assert!(directory.join("notes (1)").exists());
+
+        writer.delete(&second).await.unwrap();
+        assert!(!second.exists());
+        // Deleting again finds nothing to do
+        writer.delete(&second).await.unwrap();
+        assert_eq!(numbered_name(".profile", 2), ".profile (2)");
+
+        // A cancelled write leaves neither the file nor its part file
+        let cancelled = Arc::new(AtomicBool::new(true));
+        let result = writer.write_new(&directory, "big.bin", b"data", cancelled).await;
+        assert!(matches!(result, Err(DownloadError::Cancelled(_))));
+        assert!(!directory.join("big.bin").exists());
+        assert!(!super::super::safe_file::part_path(&directory.join("big.bin")).exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,28 @@
+use crate::domain::{ExternalLauncher, ValidatedUrl};
+use std::ffi::OsStr;
+
+/// Opens URLs with the system's default handler for their scheme, through
+/// `opener`: `open` on macOS, the shell on Windows and `xdg-open`
+/// elsewhere. The URL is passed as a single argument, never through a shell.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemLauncher;
+
+impl SystemLauncher {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ExternalLauncher for SystemLauncher {
+    fn launch(&self, url: &ValidatedUrl) -> std::io::Result<()> {
+        open_with_system(url.as_str())
+    }
+}
+
+/// Hand `target`, a URL or a path, to the system's default program for it
+pub fn open_with_system(target: impl AsRef<OsStr>) -> std::io::Result<()> {
+    opener::open(target).map_err(|e| match e {
+        opener::OpenError::Io(e) => e,
+        e => std::io::Error::other(e),
+    })
+}
@@ -0,0 +1,523 @@
+// Readers for other browsers' profiles, used for first-run migration
+
+use crate::domain::{
+    BrowserProfileReader, BrowserSource, ImportError, NewBookmark, NewHistoryEntry, ValidatedUrl,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection};
+use sqlx::{ConnectOptions, Connection, Row};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Seconds between 1601-01-01, Chrome's epoch, and the Unix epoch
+const WEBKIT_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// Display names for Firefox's built-in bookmark roots
+const FIREFOX_ROOTS: &[(&str, &str)] = &[
+    ("menu________", "Bookmarks Menu"),
+    ("toolbar_____", "Bookmarks Toolbar"),
+    ("unfiled_____", "Other Bookmarks"),
+    ("mobile______", "Mobile Bookmarks"),
+];
+const FIREFOX_ROOT: &str = "root________";
+const FIREFOX_TAGS_ROOT: &str = "tags________";
+
+/// moz_bookmarks.type values
+const FIREFOX_BOOKMARK: i64 = 1;
+const FIREFOX_FOLDER: i64 = 2;
+
+/// Reads Firefox and Chrome profiles from disk
+pub struct ForeignProfileReader;
+
+impl ForeignProfileReader {
+    pub fn new() -> Self {
+        Self
+    }
+
+    async fn firefox_bookmarks(&self, profile: &Path) -> Result<Vec<NewBookmark>, ImportError> {
+        let copy = TempCopy::of(&profile.join("places.sqlite"))?;
+        let mut conn = open_read_only(copy.path()).await?;
+        let rows = sqlx::query(
+            "SELECT b.id, b.type, b.fk, b.parent, b.title, b.dateAdded, b.guid, p.url
+             FROM moz_bookmarks b LEFT JOIN moz_places p ON p.id = b.fk
+             ORDER BY b.parent, b.position",
+        )
+        .fetch_all(&mut conn)
+        .await
+        .map_err(unreadable)?;
+        conn.close().await.ok();
+
+        let mut nodes = HashMap::new();
+        for row in &rows {
+            let node = FirefoxNode {
+                kind: row.try_get("type").map_err(unreadable)?,
+                place: row.try_get("fk").map_err(unreadable)?,
+                parent: row.try_get("parent").map_err(unreadable)?,
+                title: row.try_get::<Option<String>, _>("title").map_err(unreadable)?.unwrap_or_default(),
+                added: row.try_get::<Option<i64>, _>("dateAdded").map_err(unreadable)?,
+                guid: row.try_get::<Option<String>, _>("guid").map_err(unreadable)?.unwrap_or_default(),
+                url: row.try_get("url").map_err(unreadable)?,
+            };
+            nodes.insert(row.try_get::<i64, _>("id").map_err(unreadable)?, node);
+        }
+
+        // Firefox stores a tag as a folder under the tags root holding one
+        // entry per tagged place
+        let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+        for node in nodes.values().filter(|n| n.kind == FIREFOX_BOOKMARK) {
+            let Some(tag) = nodes.get(&node.parent) else { continue };
+            let under_tags = nodes.get(&tag.parent).is_some_and(|p| p.guid == FIREFOX_TAGS_ROOT);
+            if let (true, Some(place)) = (under_tags, node.place) {
+                tags.entry(place).or_default().push(tag.title.clone());
+            }
+        }
+
+        let mut bookmarks = Vec::new();
+        // Rows are already in folder order
+        for row in &rows {
+            let id: i64 = row.try_get("id").map_err(unreadable)?;
+            let node = &nodes[&id];
+            if node.kind != FIREFOX_BOOKMARK {
+                continue;
+            }
+            let Some(folder) = firefox_folder(&nodes, id) else { continue };
+            let Some(url) = node.url.as_deref().and_then(importable_url) else { continue };
+            let title = if node.title.is_empty() { url.as_str().to_string() } else { node.title.clone() };
+            let mut bookmark = NewBookmark::new(title, url);
+            bookmark.folder = folder;
+            if let Some(added) = node.added.and_then(from_prtime) {
+                bookmark.created_at = added;
+            }
+            if let Some(place_tags) = node.place.and_then(|place| tags.get(&place)) {
+                bookmark.tags = place_tags.clone();
+            }
+            bookmarks.push(bookmark);
+        }
+        Ok(bookmarks)
+    }
+
+    async fn firefox_history(&self, profile: &Path) -> Result<Vec<NewHistoryEntry>, ImportError> {
+        let copy = TempCopy::of(&profile.join("places.sqlite"))?;
+        let mut conn = open_read_only(copy.path()).await?;
+        let rows: Vec<(String, Option<String>, i64, i64)> = sqlx::query_as(
+            "SELECT url, title, visit_count, last_visit_date FROM moz_places
+             WHERE visit_count > 0 AND last_visit_date IS NOT NULL AND hidden = 0",
+        )
+        .fetch_all(&mut conn)
+        .await
+        .map_err(unreadable)?;
+        conn.close().await.ok();
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(url, title, visits, last_visit)| {
+                history_entry(&url, title, visits, from_prtime(last_visit))
+            })
+            .collect())
+    }
+
+    async fn chrome_bookmarks(&self, profile: &Path) -> Result<Vec<NewBookmark>, ImportError> {
+        let path = profile.join("Bookmarks");
+        let json = std::fs::read_to_string(&path).map_err(|e| not_found_or_io(&path, e))?;
+        let document: serde_json::Value = serde_json::from_str(&json).map_err(unreadable)?;
+        let roots = document
+            .get("roots")
+            .and_then(|roots| roots.as_object())
+            .ok_or_else(|| ImportError::Unreadable("Bookmarks file has no roots".to_string()))?;
+
+        let mut bookmarks = Vec::new();
+        for key in ["bookmark_bar", "other", "synced"] {
+            if let Some(root) = roots.get(key) {
+                let name = root.get("name").and_then(|n| n.as_str()).unwrap_or(key);
+                collect_chrome_bookmarks(root, name.to_string(), &mut bookmarks);
+            }
+        }
+        Ok(bookmarks)
+    }
+
+    async fn chrome_history(&self, profile: &Path) -> Result<Vec<NewHistoryEntry>, ImportError> {
+        let copy = TempCopy::of(&profile.join("History"))?;
+        let mut conn = open_read_only(copy.path()).await?;
+        let rows: Vec<(String, Option<String>, i64, i64)> = sqlx::query_as(
+            "SELECT url, title, visit_count, last_visit_time FROM urls
+             WHERE visit_count > 0 AND hidden = 0",
+        )
+        .fetch_all(&mut conn)
+        .await
+        .map_err(unreadable)?;
+        conn.close().await.ok();
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(url, title, visits, last_visit)| {
+                history_entry(&url, title, visits, from_webkit(last_visit))
+            })
+            .collect())
+    }
+}
+
+impl Default for ForeignProfileReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl BrowserProfileReader for ForeignProfileReader {
+    fn default_profile(&self, source: BrowserSource) -> Option<PathBuf> {
+        match source {
+            BrowserSource::Firefox => firefox_profiles_dir().and_then(|dir| default_firefox_profile(&dir)),
+            BrowserSource::Chrome => chrome_profile_dir().filter(|dir| dir.is_dir()),
+        }
+    }
+
+    async fn read_bookmarks(&self, source: BrowserSource, profile: &Path) -> Result<Vec<NewBookmark>, ImportError> {
+        match source {
+            BrowserSource::Firefox => self.firefox_bookmarks(profile).await,
+            BrowserSource::Chrome => self.chrome_bookmarks(profile).await,
+        }
+    }
+
+    async fn read_history(&self, source: BrowserSource, profile: &Path) -> Result<Vec<NewHistoryEntry>, ImportError> {
+        match source {
+            BrowserSource::Firefox => self.firefox_history(profile).await,
+            BrowserSource::Chrome => self.chrome_history(profile).await,
+        }
+    }
+}
+
+/// A moz_bookmarks row
+struct FirefoxNode {
+    kind: i64,
+    place: Option<i64>,
+    parent: i64,
+    title: String,
+    added: Option<i64>,
+    guid: String,
+    url: Option<String>,
+}
+
+/// Folder path of a bookmark, e.g. `Bookmarks Toolbar/Dev`.
+///
+/// `None` for entries that are not real bookmarks, such as tag entries.
+fn firefox_folder(nodes: &HashMap<i64, FirefoxNode>, id: i64) -> Option<Option<String>> {
+    let mut path = Vec::new();
+    let mut current = nodes.get(&id)?.parent;
+    // Bounded so a corrupt parent cycle cannot loop forever
+    for _ in 0..nodes.len() {
+        let folder = nodes.get(&current)?;
+        if folder.guid == FIREFOX_ROOT {
+            break;
+        }
+        if folder.guid == FIREFOX_TAGS_ROOT {
+            return None;
+        }
+        let name = FIREFOX_ROOTS
+            .iter()
+            .find(|(guid, _)| *guid == folder.guid)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| folder.title.clone());
+        if folder.kind == FIREFOX_FOLDER {
+            path.push(name);
+        }
+        current = folder.parent;
+    }
+    path.reverse();
+    Some((!path.is_empty()).then(|| path.join("/")))
+}
+
+/// Walk a Chrome bookmark folder, naming nested folders by path
+fn collect_chrome_bookmarks(node: &serde_json::Value, folder: String, out: &mut Vec<NewBookmark>) {
+    let Some(children) = node.get("children").and_then(|c| c.as_array()) else {
+        return;
+    };
+    for child in children {
+        let name = child.get("name").and_then(|n| n.as_str()).unwrap_or_default();
+        match child.get("type").and_then(|t| t.as_str()) {
+            Some("folder") => collect_chrome_bookmarks(child, format!("{}/{}", folder, name), out),
+            Some("url") => {
+                let Some(url) = child.get("url").and_then(|u| u.as_str()).and_then(importable_url) else {
+                    continue;
+                };
+                let title = if name.is_empty() { url.as_str().to_string() } else { name.to_string() };
+                let mut bookmark = NewBookmark::new(title, url);
+                bookmark.folder = Some(folder.clone());
+                // Chrome writes its timestamps as strings
+                let added = child
+                    .get("date_added")
+                    .and_then(|d| d.as_str())
+                    .and_then(|d| d.parse().ok())
+                    .and_then(from_webkit);
+                if let Some(added) = added {
+                    bookmark.created_at = added;
+                }
+                out.push(bookmark);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn history_entry(
+    url: &str,
+    title: Option<String>,
+    visits: i64,
+    last_visit: Option<DateTime<Utc>>,
+) -> Option<NewHistoryEntry> {
+    let url = importable_url(url)?;
+    let title = title.filter(|t| !t.is_empty()).unwrap_or_else(|| url.as_str().to_string());
+    let mut entry = NewHistoryEntry::new(url, title);
+    entry.visited_at = last_visit?;
+    entry.visit_count = i32::try_from(visits).unwrap_or(i32::MAX).max(1);
+    Some(entry)
+}
+
+/// Only web and file URLs make sense here; `place:`, `chrome:` and the like
+/// belong to the other browser
+fn importable_url(url: &str) -> Option<ValidatedUrl> {
+    ValidatedUrl::parse(url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https" | "file"))
+}
+
+/// Firefox PRTime: microseconds since the Unix epoch
+fn from_prtime(micros: i64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_micros(micros)
+}
+
+/// Chrome time: microseconds since 1601-01-01
+fn from_webkit(micros: i64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_micros(micros.checked_sub(WEBKIT_EPOCH_OFFSET * 1_000_000)?)
+}
+
+async fn open_read_only(path: &Path) -> Result<SqliteConnection, ImportError> {
+    SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect()
+        .await
+        .map_err(unreadable)
+}
+
+fn unreadable(e: impl std::fmt::Display) -> ImportError {
+    ImportError::Unreadable(e.to_string())
+}
+
+fn not_found_or_io(path: &Path, e: std::io::Error) -> ImportError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => ImportError::ProfileNotFound(path.display().to_string()),
+        _ => ImportError::Io(e.to_string()),
+    }
+}
+
+/// A private copy of a database and its write-ahead log, deleted on drop.
+///
+/// The source browser may be running and holding locks on the original.
+struct TempCopy {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl TempCopy {
+    fn of(source: &Path) -> Result<Self, ImportError> {
+        let dir = std::env::temp_dir().join(format!("navigator-import-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).map_err(|e| ImportError::Io(e.to_string()))?;
+        let copy = Self {
+            path: dir.join(source.file_name().unwrap_or_default()),
+            dir,
+        };
+        std::fs::copy(source, &copy.path).map_err(|e| not_found_or_io(source, e))?;
+
+        let mut wal = source.as_os_str().to_owned();
+        wal.push("-wal");
+        let wal = PathBuf::from(wal);
+        if wal.exists() {
+            let mut target = copy.path.as_os_str().to_owned();
+            target.push("-wal");
+            std::fs::copy(&wal, PathBuf::from(target)).map_err(|e| ImportError::Io(e.to_string()))?;
+        }
+        Ok(copy)
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempCopy {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            tracing::warn!("Failed to remove import copy {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(PathBuf::from)
+}
+
+/// The directory holding Firefox profiles on this OS
+fn firefox_profiles_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join(r"Mozilla\Firefox"))
+    } else if cfg!(target_os = "macos") {
+        home_dir().map(|home| home.join("Library/Application Support/Firefox"))
+    } else {
+        home_dir().map(|home| home.join(".mozilla/firefox"))
+    }
+}
+
+/// Chrome's default profile directory on this OS
+fn chrome_profile_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        std::env::var_os("LOCALAPPDATA")
+            .map(|dir| PathBuf::from(dir).join(r"Google\Chrome\User Data\Default"))
+    } else if cfg!(target_os = "macos") {
+        home_dir().map(|home| home.join("Library/Application Support/Google/Chrome/Default"))
+    } else {
+        home_dir().map(|home| home.join(".config/google-chrome/Default"))
+    }
+}
+
+/// The profile marked default in `profiles.ini`, or else the first profile
+/// directory holding a places database
+fn default_firefox_profile(dir: &Path) -> Option<PathBuf> {
+    if let Ok(ini) = std::fs::read_to_string(dir.join("profiles.ini")) {
+        if let Some(path) = default_profile_in_ini(&ini) {
+            let profile = if path.1 { dir.join(&path.0) } else { PathBuf::from(&path.0) };
+            if profile.join("places.sqlite").is_file() {
+                return Some(profile);
+            }
+        }
+    }
+
+    let search = [dir.to_path_buf(), dir.join("Profiles")];
+    search
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.filter_map(|entry| entry.ok()))
+        .map(|entry| entry.path())
+        .find(|path| path.join("places.sqlite").is_file())
+}
+
+/// `(path, is_relative)` of the default profile in a `profiles.ini`.
+///
+/// Newer files name it in an `[Install…]` section; older ones mark a
+/// `[Profile…]` section with `Default=1`.
+fn default_profile_in_ini(ini: &str) -> Option<(String, bool)> {
+    let mut install_default = None;
+    let mut marked_default = None;
+    let mut section = String::new();
+    let mut path = None;
+    let mut relative = true;
+    let mut is_default = false;
+
+    let mut finish_profile = |path: &mut Option<String>, relative: bool, is_default: bool| {
+        if let (Some(path), true) = (path.take(), is_default) {
+            marked_default.get_or_insert((path, relative));
+        }
+    };
+
+    for line in ini.lines().map(str::trim) {
+        if line.starts_with('[') {
+            finish_profile(&mut path, relative, is_default);
+            section = line.to_string();
+            relative = true;
+            is_default = false;
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else { continue };
+        match (section.starts_with("[Install"), key.trim(), value.trim()) {
+            (true, "Default", value) => {
+                install_default.get_or_insert((value.to_string(), true));
+            }
+            (false, "Path", value) => path = Some(value.to_string()),
+            (false, "IsRelative", value) => relative = value != "0",
+            (false, "Default", "1") => is_default = true,
+            _ => {}
+        }
+    }
+    finish_profile(&mut path, relative, is_default);
+    install_default.or(marked_default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(browser: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(browser)
+    }
+
+    #[tokio::test]
+    async fn test_reads_firefox_profile() {
+        let reader = ForeignProfileReader::new();
+        let profile = fixture("firefox");
+
+        let bookmarks = reader.read_bookmarks(BrowserSource::Firefox, &profile).await.unwrap();
+        let summary: Vec<_> = bookmarks
+            .iter()
+            .map(|b| (b.title.as_str(), b.folder.as_deref(), b.tags.clone()))
+            .collect();
+        // Tag entries, separators and place: queries are skipped
+        assert_eq!(
+            summary,
+            vec![
+                ("Unvisited", Some("Bookmarks Menu"), vec![]),
+                ("Rust Lang", Some("Bookmarks Toolbar/Dev"), vec!["lang".to_string()]),
+            ]
+        );
+        assert_eq!(bookmarks[1].created_at.to_rfc3339(), "2024-02-29T12:00:00+00:00");
+
+        let history = reader.read_history(BrowserSource::Firefox, &profile).await.unwrap();
+        assert_eq!(history.len(), 2);
+        let rust = history.iter().find(|e| e.url.as_str() == "https://www.rust-lang.org/").unwrap();
+        assert_eq!(rust.visit_count, 5);
+        assert_eq!(rust.visited_at.to_rfc3339(), "2024-03-01T12:00:00+00:00");
+    }
+
+    #[tokio::test]
+    async fn test_reads_chrome_profile() {
+        let reader = ForeignProfileReader::new();
+        let profile = fixture("chrome");
+
+        let bookmarks = reader.read_bookmarks(BrowserSource::Chrome, &profile).await.unwrap();
+        let summary: Vec<_> = bookmarks
+            .iter()
+            .map(|b| (b.url.as_str(), b.folder.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("https://www.rust-lang.org/", Some("Bookmarks bar")),
+                ("https://example.org/article", Some("Bookmarks bar/Reading")),
+            ]
+        );
+        assert_eq!(bookmarks[0].created_at.to_rfc3339(), "2024-02-29T12:00:00+00:00");
+
+        let history = reader.read_history(BrowserSource::Chrome, &profile).await.unwrap();
+        let visits: Vec<_> = history.iter().map(|e| (e.url.as_str(), e.visit_count)).collect();
+        assert_eq!(
+            visits,
+            vec![("https://www.rust-lang.org/", 7), ("https://example.org/article", 1)]
+        );
+        assert_eq!(history[0].visited_at.to_rfc3339(), "2024-03-01T12:00:00+00:00");
+
+        let missing = reader.read_history(BrowserSource::Chrome, &fixture("none")).await;
+        assert!(matches!(missing, Err(ImportError::ProfileNotFound(_))));
+    }
+
+    #[test]
+    fn test_default_profile_in_ini() {
+        let ini = "[Profile1]\nName=old\nIsRelative=1\nPath=Profiles/old.default\nDefault=1\n\n\
+                   [Profile0]\nName=abs\nIsRelative=0\nPath=/data/ff\n";
+        assert_eq!(default_profile_in_ini(ini), Some(("Profiles/old.default".to_string(), true)));
+
+        let installed = format!("[Install4F96D1932A9F858E]\nDefault=Profiles/new.default-release\n{}", ini);
+        assert_eq!(
+            default_profile_in_ini(&installed),
+            Some(("Profiles/new.default-release".to_string(), true))
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::domain::ValidatedUrl;
+use base64::Engine;
+use ring::digest;
+use std::fmt;
+
+/// Hash functions an `integrity` attribute may name, weakest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IntegrityAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl IntegrityAlgorithm {
+    /// Parse a name such as `sha384`, in any case
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha256" => Some(Self::Sha256),
+            "sha384" => Some(Self::Sha384),
+            "sha512" => Some(Self::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Base64 digest of `body`
+    pub fn digest(self, body: &[u8]) -> String {
+        let algorithm = match self {
+            Self::Sha256 => &digest::SHA256,
+            Self::Sha384 => &digest::SHA384,
+            Self::Sha512 => &digest::SHA512,
+        };
+        base64::engine::general_purpose::STANDARD.encode(digest::digest(algorithm, body).as_ref())
+    }
+}
+
+impl fmt::Display for IntegrityAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sha256 => write!(f, "sha256"),
+            Self::Sha384 => write!(f, "sha384"),
+            Self::Sha512 => write!(f, "sha512"),
+        }
+    }
+}
+
+/// The digests an `integrity` attribute accepts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityMetadata {
+    hashes: Vec<(IntegrityAlgorithm, String)>,
+}
+
+impl IntegrityMetadata {
+    /// Parse an attribute value: `<algorithm>-<base64>` hashes separated
+    /// by whitespace, each perhaps followed by `?` and options, which are
+    /// ignored. Hashes with other algorithms than SHA-256, SHA-384 and
+    /// SHA-512 are left out.
+    pub fn parse(attribute: &str) -> Self {
+        let hashes = attribute
+            .split_ascii_whitespace()
+            .filter_map(|token| {
+                let hash = token.split('?').next().unwrap_or_default();
+                let (algorithm, value) = hash.split_once('-')?;
+                Some((IntegrityAlgorithm::parse(algorithm)?, value.to_string()))
+                    .filter(|(_, value)| !value.is_empty())
+            })
+            .collect();
+        Self { hashes }
+    }
+
+    /// Whether no hash can be checked, which leaves the resource alone
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Check `body` against the hashes of the strongest algorithm listed;
+    /// any one of them matching will do
+    pub fn verify(&self, body: &[u8]) -> IntegrityStatus {
+        let Some(strongest) = self.hashes.iter().map(|(algorithm, _)| *algorithm).max() else {
+            return IntegrityStatus::Unchecked;
+        };
+        let actual = strongest.digest(body);
+        let matched = self
+            .hashes
+            .iter()
+            .filter(|(algorithm, _)| *algorithm == strongest)
+            .any(|(_, expected)| normalized_base64(expected) == normalized_base64(&actual));
+        if matched {
+            IntegrityStatus::Verified(strongest)
+        } else {
+            IntegrityStatus::Mismatch(format!("{}-{}", strongest, actual))
+        }
+    }
+}
+
+/// `value` read as either base64 alphabet, without padding
+fn normalized_base64(value: &str) -> String {
+    value.trim_end_matches('=').replace('-', "+").replace('_', "/")
+}
+
+/// What checking a subresource against its `integrity` attribute found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityStatus {
+    /// There is no attribute, or none of its hashes could be checked
+    Unchecked,
+    /// A hash made with the algorithm matched
+    Verified(IntegrityAlgorithm),
+    /// No hash matched; holds the body's own, as an attribute would give it
+    Mismatch(String),
+}
+
+impl IntegrityStatus {
+    /// Whether the resource may be used
+    pub fn passed(&self) -> bool {
+        !matches!(self, Self::Mismatch(_))
+    }
+}
+
+impl fmt::Display for IntegrityStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unchecked => write!(f, "not checked"),
+            Self::Verified(algorithm) => write!(f, "verified with {}", algorithm),
+            Self::Mismatch(actual) => write!(f, "failed, the body's digest is {}", actual),
+        }
+    }
+}
+
+/// Check the subresource at `url` against its element's `integrity`
+/// attribute, if it has one, logging the outcome
+pub fn check_integrity(url: &ValidatedUrl, integrity: Option<&str>, body: &[u8]) -> IntegrityStatus {
+    let Some(integrity) = integrity else {
+        return IntegrityStatus::Unchecked;
+    };
+    let status = IntegrityMetadata::parse(integrity).verify(body);
+    match &status {
+        IntegrityStatus::Mismatch(_) => tracing::warn!("Integrity of {}: {}", url, status),
+        _ => tracing::info!("Integrity of {}: {}", url, status),
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = b"console.log('integrity')";
+    const SHA256: &str = "sha256-ZwozeSFwK4d28BW5CaAaYYRWDEbles0qVTb+1cVOTtc=";
+    const SHA384: &str = "sha384-gRF66wcqB6WMlhPXnbXmDcSVl+4S1mRxVZ1SVSHCwcWcBK5TLIZrXBe6GFEojV6O";
+    const SHA512: &str =
+        "sha512-EHOALMJV7l+OSxGOyKX5UCQiHRjMQ/x3RkZ/Ejxbg4no0zw+XNRdcltPUrThpgkGPD+pMpAxj40kvX3GDHXbSA==";
+
+    fn verify(attribute: &str) -> IntegrityStatus {
+        IntegrityMetadata::parse(attribute).verify(BODY)
+    }
+
+    #[test]
+    fn test_any_hash_of_the_strongest_algorithm_passes() {
+        assert_eq!(verify(SHA256), IntegrityStatus::Verified(IntegrityAlgorithm::Sha256));
+        assert_eq!(verify(SHA512), IntegrityStatus::Verified(IntegrityAlgorithm::Sha512));
+        // Several acceptable hashes, separated by any whitespace
+        let listed = format!("sha384-c3RhbGU=\n\t{}?ct=application/javascript  ", SHA384);
+        assert_eq!(verify(&listed), IntegrityStatus::Verified(IntegrityAlgorithm::Sha384));
+        // Base64url and missing padding are read the same
+        let url_safe = SHA512.trim_end_matches('=').replace('+', "-").replace('/', "_");
+        assert!(verify(&url_safe).passed());
+        let upper = SHA256.replacen("sha256", "SHA256", 1);
+        assert_eq!(verify(&upper), IntegrityStatus::Verified(IntegrityAlgorithm::Sha256));
+    }
+
+    #[test]
+    fn test_unsupported_algorithms_are_ignored() {
+        for attribute in ["", "   ", "md5-bm90IGNoZWNrZWQ=", "sha1-abc sha3-def", "sha256-", "sha256", "garbage"] {
+            assert!(IntegrityMetadata::parse(attribute).is_empty(), "{:?}", attribute);
+            assert_eq!(verify(attribute), IntegrityStatus::Unchecked, "{:?}", attribute);
+        }
+        assert_eq!(
+            verify(&format!("md5-bm90IGNoZWNrZWQ= {}", SHA256)),
+            IntegrityStatus::Verified(IntegrityAlgorithm::Sha256)
+        );
+    }
+
+    #[test]
+    fn test_mismatch_reports_the_actual_digest() {
+        let tampered = IntegrityMetadata::parse(SHA384).verify(b"console.log('tampered')");
+        assert!(!tampered.passed());
+        assert!(tampered.to_string().starts_with("failed, the body's digest is sha384-"), "{}", tampered);
+        // A matching weaker hash does not make up for the strongest failing
+        assert!(!verify(&format!("{} sha512-c3RhbGU=", SHA256)).passed());
+
+        let url = ValidatedUrl::parse("https://cdn.example/app.js").unwrap();
+        assert_eq!(check_integrity(&url, None, b"anything"), IntegrityStatus::Unchecked);
+        let checked = check_integrity(&url, Some(SHA512), BODY);
+        assert_eq!(checked, IntegrityStatus::Verified(IntegrityAlgorithm::Sha512));
+    }
+}
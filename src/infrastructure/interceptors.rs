@@ -0,0 +1,379 @@
+use crate::domain::{
+    BlockCategory, ContentBlockerService, FetchRequest, InterceptDecision, NetworkError, RequestInterceptor,
+    ValidatedUrl,
+};
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Query parameters that only identify where a click came from
+pub const TRACKING_PARAMETERS: &[&str] = &[
+    "fbclid", "gclid", "dclid", "gbraid", "wbraid", "msclkid", "yclid", "igshid", "mc_cid", "mc_eid",
+    "_hsenc", "_hsmi",
+];
+/// Prefix shared by the Google Analytics campaign parameters
+pub const TRACKING_PARAMETER_PREFIX: &str = "utm_";
+
+struct Registered {
+    interceptor: Arc<dyn RequestInterceptor>,
+    /// Set once the interceptor has panicked
+    disabled: AtomicBool,
+}
+
+/// Request interceptors in the order they were added.
+///
+/// An interceptor that panics is disabled for the rest of the session and
+/// its request carries on as if it had allowed it.
+pub struct InterceptorChain {
+    interceptors: RwLock<Vec<Arc<Registered>>>,
+}
+
+impl InterceptorChain {
+    pub fn new() -> Self {
+        Self {
+            interceptors: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn add(&self, interceptor: Arc<dyn RequestInterceptor>) {
+        if let Ok(mut interceptors) = self.interceptors.write() {
+            interceptors.push(Arc::new(Registered {
+                interceptor,
+                disabled: AtomicBool::new(false),
+            }));
+        }
+    }
+
+    /// Names of interceptors disabled after a panic
+    pub fn disabled(&self) -> Vec<String> {
+        self.snapshot()
+            .iter()
+            .filter(|registered| registered.disabled.load(Ordering::Relaxed))
+            .map(|registered| registered.interceptor.name().to_string())
+            .collect()
+    }
+
+    /// Ask each interceptor about a request before it is sent.
+    ///
+    /// A redirect replaces the URL the later interceptors see; the first
+    /// block ends the chain. Returns the URL to fetch if it changed.
+    pub fn before_request(&self, request: &FetchRequest) -> Result<Option<ValidatedUrl>, NetworkError> {
+        self.decide(request, |interceptor, request| interceptor.on_before_request(request))
+    }
+
+    /// Ask each interceptor about a response whose headers have arrived;
+    /// a redirect fetches another URL in place of reading this body
+    pub fn headers_received(
+        &self,
+        request: &FetchRequest,
+        status: u16,
+        headers: &[(String, String)],
+    ) -> Result<Option<ValidatedUrl>, NetworkError> {
+        self.decide(request, |interceptor, request| {
+            interceptor.on_headers_received(request, status, headers)
+        })
+    }
+
+    fn decide(
+        &self,
+        request: &FetchRequest,
+        hook: impl Fn(&dyn RequestInterceptor, &FetchRequest) -> InterceptDecision,
+    ) -> Result<Option<ValidatedUrl>, NetworkError> {
+        let mut current = request.clone();
+        let mut redirected = None;
+        for registered in self.snapshot() {
+            if registered.disabled.load(Ordering::Relaxed) {
+                continue;
+            }
+            let interceptor = registered.interceptor.as_ref();
+            match catch_unwind(AssertUnwindSafe(|| hook(interceptor, &current))) {
+                Ok(InterceptDecision::Allow) => {}
+                Ok(InterceptDecision::Block) => {
+                    tracing::debug!("{} blocked {}", interceptor.name(), current.url);
+                    return Err(NetworkError::Blocked(interceptor.name().to_string()));
+                }
+                Ok(InterceptDecision::Redirect(url)) => {
+                    tracing::debug!("{} redirected {} to {}", interceptor.name(), current.url, url);
+                    current.url = url.clone();
+                    redirected = Some(url);
+                }
+                Err(_) => {
+                    registered.disabled.store(true, Ordering::Relaxed);
+                    tracing::error!("Request interceptor {} panicked and has been disabled", interceptor.name());
+                }
+            }
+        }
+        Ok(redirected)
+    }
+
+    /// The interceptors, so none are called with the lock held
+    fn snapshot(&self) -> Vec<Arc<Registered>> {
+        self.interceptors
+            .read()
+            .map(|interceptors| interceptors.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for InterceptorChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Blocks requests to listed hosts and their subdomains
+pub struct ContentBlocker {
+    /// Listed hosts and what they serve
+    hosts: RwLock<HashMap<String, BlockCategory>>,
+    /// Requests blocked this session, summed over every tab
+    blocked: AtomicUsize,
+    enabled: AtomicBool,
+}
+
+impl ContentBlocker {
+    pub fn new() -> Self {
+        Self {
+            hosts: RwLock::new(HashMap::new()),
+            blocked: AtomicUsize::new(0),
+            enabled: AtomicBool::new(true),
+        }
+    }
+
+    /// Turn blocking on or off, keeping the listed hosts
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn with_hosts<I: IntoIterator<Item = String>>(self, hosts: I) -> Self {
+        for host in hosts {
+            self.add_host(&host);
+        }
+        self
+    }
+
+    /// List a host as serving ads, as the blocked domains setting does
+    pub fn add_host(&self, host: &str) {
+        self.add_host_as(host, BlockCategory::Ads);
+    }
+
+    pub fn add_host_as(&self, host: &str, category: BlockCategory) {
+        if let Ok(mut hosts) = self.hosts.write() {
+            hosts.insert(host.trim().trim_end_matches('.').to_ascii_lowercase(), category);
+        }
+    }
+
+    pub fn remove_host(&self, host: &str) {
+        if let Ok(mut hosts) = self.hosts.write() {
+            hosts.remove(&host.trim().to_ascii_lowercase());
+        }
+    }
+
+    /// Whether blocking is on and the URL's host, or a domain it belongs
+    /// to, is listed
+    pub fn blocks(&self, url: &ValidatedUrl) -> bool {
+        self.category(url).is_some()
+    }
+
+    /// Like [`category`](ContentBlockerService::category), counting the
+    /// request as blocked if it is; for requests `document` makes that
+    /// never reach the network client. A site's own trackers are
+    /// first-party and pass, only third-party ones are blocked.
+    pub fn check(&self, url: &ValidatedUrl, document: &ValidatedUrl) -> Option<BlockCategory> {
+        let category = self
+            .category(url)
+            .filter(|category| *category != BlockCategory::Trackers || !url.is_same_site(document))?;
+        self.blocked.fetch_add(1, Ordering::Relaxed);
+        Some(category)
+    }
+}
+
+impl Default for ContentBlocker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestInterceptor for ContentBlocker {
+    fn name(&self) -> &str {
+        "content blocker"
+    }
+
+    fn on_before_request(&self, request: &FetchRequest) -> InterceptDecision {
+        if self.blocks(&request.url) {
+            self.blocked.fetch_add(1, Ordering::Relaxed);
+            return InterceptDecision::Block;
+        }
+        InterceptDecision::Allow
+    }
+}
+
+#[async_trait]
+impl ContentBlockerService for ContentBlocker {
+    async fn should_block(&self, url: &ValidatedUrl) -> bool {
+        self.blocks(url)
+    }
+
+    /// The host list is kept locally, so there is nothing to download
+    async fn update_blocklists(&self) -> Result<(), NetworkError> {
+        Ok(())
+    }
+
+    /// The category of the most specific listed domain the URL's host
+    /// belongs to, while blocking is on
+    fn category(&self, url: &ValidatedUrl) -> Option<BlockCategory> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let (Some(host), Ok(hosts)) = (url.host_str(), self.hosts.read()) else {
+            return None;
+        };
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        let mut domain = host.as_str();
+        loop {
+            if let Some(category) = hosts.get(domain) {
+                return Some(*category);
+            }
+            domain = domain.split_once('.')?.1;
+        }
+    }
+
+    fn get_blocked_count(&self) -> usize {
+        self.blocked.load(Ordering::Relaxed)
+    }
+}
+
+/// Drops click-tracking parameters such as `utm_source` from request URLs
+pub struct TrackingParameterCleaner {
+    parameters: HashSet<String>,
+}
+
+impl TrackingParameterCleaner {
+    pub fn new() -> Self {
+        Self {
+            parameters: TRACKING_PARAMETERS.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    /// The URL without tracking parameters, or `None` if it had none
+    pub fn clean(&self, url: &ValidatedUrl) -> Option<ValidatedUrl> {
+        let query = url.query()?;
+        let kept: Vec<&str> = query
+            .split('&')
+            .filter(|pair| {
+                let name = pair.split('=').next().unwrap_or("").to_ascii_lowercase();
+                !self.parameters.contains(&name) && !name.starts_with(TRACKING_PARAMETER_PREFIX)
+            })
+            .collect();
+        if kept.len() == query.split('&').count() {
+            return None;
+        }
+
+        let mut cleaned = url::Url::parse(url.as_str()).ok()?;
+        cleaned.set_query((!kept.is_empty()).then(|| kept.join("&")).as_deref());
+        ValidatedUrl::parse(cleaned.as_str()).ok()
+    }
+}
+
+impl Default for TrackingParameterCleaner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestInterceptor for TrackingParameterCleaner {
+    fn name(&self) -> &str {
+        "tracking parameter cleaner"
+    }
+
+    fn on_before_request(&self, request: &FetchRequest) -> InterceptDecision {
+        match self.clean(&request.url) {
+            Some(url) => InterceptDecision::Redirect(url),
+            None => InterceptDecision::Allow,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ResourceKind;
+
+    fn request(input: &str) -> FetchRequest {
+        FetchRequest::new(ValidatedUrl::parse(input).unwrap(), ResourceKind::Subresource)
+    }
+
+    struct Panicking;
+
+    impl RequestInterceptor for Panicking {
+        fn name(&self) -> &str {
+            "panicking"
+        }
+
+        fn on_before_request(&self, _request: &FetchRequest) -> InterceptDecision {
+            panic!("interceptor bug");
+        }
+    }
+
+    #[test]
+    fn test_chain_runs_in_order_and_disables_panicking_interceptors() {
+        let chain = InterceptorChain::new();
+        chain.add(Arc::new(Panicking));
+        chain.add(Arc::new(TrackingParameterCleaner::new()));
+        chain.add(Arc::new(ContentBlocker::new().with_hosts(["ads.example".to_string()])));
+
+        let cleaned = chain.before_request(&request("https://example.com/?id=1&utm_source=x")).unwrap();
+        assert_eq!(cleaned.unwrap().as_str(), "https://example.com/?id=1");
+        assert_eq!(chain.disabled(), vec!["panicking"]);
+
+        assert_eq!(chain.before_request(&request("https://example.com/")), Ok(None));
+        assert_eq!(
+            chain.before_request(&request("https://cdn.ads.example/x.js?gclid=1")),
+            Err(NetworkError::Blocked("content blocker".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_tracking_parameters_are_removed() {
+        let cleaner = TrackingParameterCleaner::new();
+        let clean = |input: &str| cleaner.clean(&ValidatedUrl::parse(input).unwrap()).map(|url| url.to_string());
+
+        assert_eq!(
+            clean("https://example.com/a?q=rust%20lang&UTM_Medium=mail&fbclid=abc#top"),
+            Some("https://example.com/a?q=rust%20lang#top".to_string())
+        );
+        assert_eq!(clean("https://example.com/?gclid=1"), Some("https://example.com/".to_string()));
+        assert_eq!(clean("https://example.com/?q=utm_source"), None);
+        assert_eq!(clean("https://example.com/"), None);
+    }
+
+    #[tokio::test]
+    async fn test_content_blocker_matches_subdomains() {
+        let blocker = ContentBlocker::new().with_hosts(["Tracker.example".to_string()]);
+        let url = |input: &str| ValidatedUrl::parse(input).unwrap();
+
+        assert!(blocker.should_block(&url("https://tracker.example/pixel")).await);
+        assert!(blocker.should_block(&url("https://a.b.tracker.example/")).await);
+        assert!(!blocker.should_block(&url("https://nottracker.example/")).await);
+
+        blocker.on_before_request(&request("https://tracker.example/"));
+        assert_eq!(blocker.get_blocked_count(), 1);
+
+        blocker.add_host_as("pixel.tracker.example", BlockCategory::Trackers);
+        assert_eq!(blocker.category(&url("https://a.pixel.tracker.example/")), Some(BlockCategory::Trackers));
+        let page = url("https://example.com/");
+        assert_eq!(blocker.check(&url("https://tracker.example/ad.js"), &page), Some(BlockCategory::Ads));
+        assert_eq!(blocker.check(&url("https://example.com/"), &page), None);
+        assert_eq!(blocker.get_blocked_count(), 2);
+        // Trackers on the page's own site are first-party
+        blocker.add_host_as("stats.example.co.uk", BlockCategory::Trackers);
+        let tracker = url("https://stats.example.co.uk/t.js");
+        assert_eq!(blocker.check(&tracker, &url("https://www.example.co.uk/")), None);
+        assert_eq!(blocker.check(&tracker, &url("https://other.co.uk/")), Some(BlockCategory::Trackers));
+        assert_eq!(blocker.get_blocked_count(), 3);
+
+        blocker.set_enabled(false);
+        assert!(!blocker.should_block(&url("https://tracker.example/pixel")).await);
+    }
+}
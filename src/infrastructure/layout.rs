@@ -0,0 +1,1353 @@
+use super::accessibility::{
+    accessible_name, element_level, element_role, is_hidden, landmark_name, SECTIONING_ELEMENTS,
+};
+use super::css::{Declarations, Rgb, Stylesheet, UserStylesheet};
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use unicode_width::UnicodeWidthChar;
+
+/// Elements whose content is never displayed
+pub(super) const HIDDEN_ELEMENTS: &[&str] = &["head", "script", "style", "template", "noscript", "title"];
+
+/// Elements that start on a new line
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "body", "dd", "div", "dl", "dt", "fieldset",
+    "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr",
+    "html", "li", "main", "nav", "ol", "p", "pre", "section", "table", "tr", "ul",
+];
+
+/// Elements rendered in a monospace font
+const MONOSPACE_ELEMENTS: &[&str] = &["pre", "code", "kbd", "samp", "tt"];
+
+/// Elements rendered in a bold font
+const BOLD_ELEMENTS: &[&str] = &["b", "strong", "th"];
+
+/// Elements rendered in italics
+const ITALIC_ELEMENTS: &[&str] = &["cite", "dfn", "em", "i", "var"];
+
+/// Font size that CSS sizes are relative to, in CSS pixels
+const BASE_FONT_SIZE: f32 = 16.0;
+/// Bounds for sizes set by page styles, in CSS pixels
+const FONT_SIZE_RANGE: (f32, f32) = (6.0, 96.0);
+
+/// Longest preformatted line kept before it is cut short with an ellipsis
+pub const MAX_PREFORMATTED_LINE_CHARS: usize = 10_000;
+
+/// Width in columns that tables are fitted into by default
+pub const DEFAULT_TABLE_WIDTH: usize = 96;
+/// Columns between tab stops of preformatted text in table cells
+const TABLE_TAB_WIDTH: usize = 8;
+/// Columns of padding between adjacent table cells
+const CELL_GAP: usize = 2;
+/// Narrowest a table column is squeezed to when the table doesn't fit
+const MIN_COLUMN_WIDTH: usize = 6;
+
+/// Inline styling of a run of text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextStyle {
+    pub monospace: bool,
+    pub bold: bool,
+    pub italic: bool,
+    pub color: Option<Rgb>,
+    pub background: Option<Rgb>,
+    /// Font size as a percentage of the default size, if the page changed it
+    pub font_scale: Option<u16>,
+}
+
+/// A styled byte range of the laid-out text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyleSpan {
+    pub range: Range<usize>,
+    pub style: TextStyle,
+}
+
+/// Kinds of block boxes that need special painting or layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockKind {
+    /// Whitespace-preserving, unwrapped block with its own background
+    Preformatted,
+    /// One row of a table, already wrapped into aligned columns
+    TableRow,
+    /// An `h1`-`h6` heading, by level
+    Heading(u8),
+}
+
+/// A block element covering whole lines of the laid-out text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSpan {
+    pub range: Range<usize>,
+    pub kind: BlockKind,
+}
+
+/// Base direction of a paragraph, as set by the `dir` attribute
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextDirection {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+/// Invisible characters the layout starts lines with to give them a
+/// direction: left-to-right and right-to-left marks
+pub const DIRECTION_MARKS: [char; 2] = ['\u{200E}', '\u{200F}'];
+
+impl TextDirection {
+    /// `ltr` or `rtl`; `auto` and unknown values give None
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "ltr" => Some(Self::Ltr),
+            "rtl" => Some(Self::Rtl),
+            _ => None,
+        }
+    }
+
+    pub fn is_rtl(self) -> bool {
+        self == Self::Rtl
+    }
+
+    /// The strong character that makes a paragraph this direction without
+    /// showing anything
+    pub fn mark(self) -> char {
+        match self {
+            Self::Ltr => DIRECTION_MARKS[0],
+            Self::Rtl => DIRECTION_MARKS[1],
+        }
+    }
+}
+
+/// A hyperlink covering a range of the laid-out text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkSpan {
+    /// Byte range in `PageContent::text`
+    pub range: Range<usize>,
+    pub href: String,
+    /// The target is in history; set after layout
+    pub visited: bool,
+}
+
+/// Displayable text of a page plus positions of anchors and links in it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PageContent {
+    pub text: String,
+    /// `id`/`name` attribute values mapped to byte offsets in `text`
+    pub anchors: HashMap<String, usize>,
+    pub links: Vec<LinkSpan>,
+    /// Non-default styles, sorted and non-overlapping
+    pub styles: Vec<StyleSpan>,
+    pub blocks: Vec<BlockSpan>,
+    /// Direction of the document, from `dir` on `<html>` or `<body>`
+    pub direction: TextDirection,
+    /// Background color the page sets on `<body>`, or else `<html>`
+    pub background: Option<Rgb>,
+}
+
+impl PageContent {
+    /// Content with no structure, e.g. status messages
+    pub fn from_text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Approximate heap bytes held by the layout
+    pub fn heap_size(&self) -> usize {
+        let anchors: usize = self
+            .anchors
+            .keys()
+            .map(|key| key.capacity() + std::mem::size_of::<(String, usize)>())
+            .sum();
+        let links: usize = self.links.iter().map(|link| link.href.capacity()).sum();
+        self.text.capacity()
+            + anchors
+            + links
+            + self.links.capacity() * std::mem::size_of::<LinkSpan>()
+            + self.styles.capacity() * std::mem::size_of::<StyleSpan>()
+            + self.blocks.capacity() * std::mem::size_of::<BlockSpan>()
+    }
+
+    /// Byte offset of the element targeted by a URL fragment
+    pub fn anchor_offset(&self, fragment: &str) -> Option<usize> {
+        if let Some(offset) = self.anchors.get(fragment) {
+            return Some(*offset);
+        }
+        // Fragments arrive percent-encoded from the URL
+        let decoded = percent_encoding::percent_decode_str(fragment).decode_utf8().ok()?;
+        self.anchors.get(decoded.as_ref()).copied()
+    }
+
+    /// Style at a byte offset of the text
+    pub fn style_at(&self, offset: usize) -> TextStyle {
+        self.styles
+            .iter()
+            .find(|span| span.range.contains(&offset))
+            .map(|span| span.style)
+            .unwrap_or_default()
+    }
+
+    /// Link covering a byte offset of the text
+    pub fn link_at(&self, offset: usize) -> Option<&LinkSpan> {
+        self.links.iter().find(|link| link.range.contains(&offset))
+    }
+}
+
+/// Walks a parsed DOM and flattens it into line-broken, whitespace-collapsed
+/// text, recording where anchors and links land
+#[derive(Debug, Default)]
+pub struct LayoutBuilder {
+    content: PageContent,
+    /// Whitespace seen since the last emitted character
+    pending_space: bool,
+    /// Depth of enclosing `<pre>` elements
+    preformatted: usize,
+    /// Depth of enclosing monospace elements; nesting doesn't compound
+    monospace: usize,
+    /// Characters on the current preformatted line
+    preformatted_line: usize,
+    /// Styles cascading down from enclosing elements
+    inherited: Inherited,
+    /// Rules from the page's `<style>` blocks
+    stylesheet: Arc<Stylesheet>,
+    /// The user's rules, applied over the page's
+    user_styles: Arc<UserStylesheet>,
+    /// Inside a table cell; nested tables fall back to linear text
+    in_table: bool,
+    /// Columns available to a table, `DEFAULT_TABLE_WIDTH` if unset
+    table_width: Option<usize>,
+    /// Announce headings, lists, links, landmarks and images in the text
+    accessible: bool,
+    /// Depth of enclosing sectioning elements, which scope landmarks
+    sectioned: usize,
+    /// Scripts do not run, so `<noscript>` content is shown
+    show_noscript: bool,
+}
+
+/// Properties inherited down the DOM walk
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Inherited {
+    bold: bool,
+    italic: bool,
+    color: Option<Rgb>,
+    /// Not inherited in CSS, but the parent's background shows through
+    background: Option<Rgb>,
+    font_size: f32,
+    /// User style floor for `font_size`
+    min_font_size: f32,
+    /// User style color that wins over page colors
+    forced_color: Option<Rgb>,
+    /// Set by the nearest `dir` attribute; None leaves each line's
+    /// direction to its first strong character
+    direction: Option<TextDirection>,
+}
+
+impl Default for Inherited {
+    fn default() -> Self {
+        Self {
+            bold: false,
+            italic: false,
+            color: None,
+            background: None,
+            font_size: BASE_FONT_SIZE,
+            min_font_size: 0.0,
+            forced_color: None,
+            direction: None,
+        }
+    }
+}
+
+impl Inherited {
+    /// Style of a child element with the given tag and declarations
+    fn child(&self, tag: &str, declarations: &Declarations) -> Self {
+        let (min, max) = FONT_SIZE_RANGE;
+        Self {
+            bold: declarations.bold.unwrap_or(self.bold || BOLD_ELEMENTS.contains(&tag)),
+            italic: declarations.italic.unwrap_or(self.italic || ITALIC_ELEMENTS.contains(&tag)),
+            color: self.forced_color.or(declarations.color).or(self.color),
+            background: declarations.background_color.or(self.background),
+            font_size: declarations
+                .font_size
+                .map_or(self.font_size, |size| size.resolve(self.font_size).clamp(min, max))
+                .max(self.min_font_size),
+            ..*self
+        }
+    }
+
+    fn font_scale(&self) -> Option<u16> {
+        let percent = (self.font_size / BASE_FONT_SIZE * 100.0).round() as u16;
+        (percent != 100).then_some(percent)
+    }
+}
+
+/// A `td`/`th` laid out on its own, waiting to be placed in the grid
+#[derive(Debug)]
+struct TableCell {
+    content: PageContent,
+    colspan: usize,
+    rowspan: usize,
+}
+
+impl TableCell {
+    /// Width of the longest line, in columns
+    fn natural_width(&self) -> usize {
+        self.content.text.lines().map(display_width).max().unwrap_or(0)
+    }
+}
+
+impl LayoutBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fit tables into `columns` character columns
+    pub fn with_table_width(mut self, columns: usize) -> Self {
+        self.table_width = Some(columns);
+        self
+    }
+
+    /// Apply the user's rules after the page's own styles
+    pub fn with_user_styles(mut self, user_styles: UserStylesheet) -> Self {
+        self.user_styles = Arc::new(user_styles);
+        self
+    }
+
+    /// Write out what a screen reader would announce: "Heading 2:" before
+    /// headings, the size of lists, landmarks, "(link)" after links and the
+    /// alt text of images
+    pub fn with_accessible_output(mut self, accessible: bool) -> Self {
+        self.accessible = accessible;
+        self
+    }
+
+    /// Lay out `<noscript>` content when scripting is not `enabled`. The
+    /// DOM should come from a parse with scripting off, which gives
+    /// `<noscript>` elements rather than text.
+    pub fn with_scripting(mut self, enabled: bool) -> Self {
+        self.show_noscript = !enabled;
+        self
+    }
+
+    pub fn build(mut self, dom: &RcDom) -> PageContent {
+        let mut stylesheet = Stylesheet::new();
+        collect_stylesheets(&dom.document, &mut stylesheet);
+        self.stylesheet = Arc::new(stylesheet);
+
+        if let Some(min) = self.user_styles.min_font_size {
+            self.inherited.min_font_size = min;
+            self.inherited.font_size = self.inherited.font_size.max(min);
+        }
+        self.inherited.forced_color = self.user_styles.text_color;
+        self.inherited.color = self.inherited.forced_color;
+
+        self.walk(&dom.document);
+        self.finish()
+    }
+
+    fn finish(mut self) -> PageContent {
+        let trimmed = self.content.text.trim_end().len();
+        self.content.text.truncate(trimmed);
+        for span in &mut self.content.styles {
+            span.range.end = span.range.end.min(trimmed);
+        }
+        self.content.styles.retain(|span| !span.range.is_empty());
+        for block in &mut self.content.blocks {
+            block.range.end = block.range.end.min(trimmed);
+        }
+        self.content
+    }
+
+    fn walk(&mut self, handle: &Handle) {
+        match &handle.data {
+            NodeData::Document => self.walk_children(handle),
+            NodeData::Text { contents } => self.push_text(&contents.borrow()),
+            NodeData::Element { name, attrs, .. } => {
+                let tag = name.local.as_ref();
+                if HIDDEN_ELEMENTS.contains(&tag) && !(tag == "noscript" && self.show_noscript) {
+                    return;
+                }
+                let declarations = self.element_declarations(handle);
+                if declarations.hidden == Some(true) || (self.accessible && is_hidden(handle)) {
+                    return;
+                }
+                let inherited = self.inherited;
+                self.inherited = inherited.child(tag, &declarations);
+                if matches!(tag, "html" | "body") && declarations.background_color.is_some() {
+                    self.content.background = declarations.background_color;
+                }
+                let role = self
+                    .accessible
+                    .then(|| element_role(handle, self.sectioned > 0))
+                    .flatten();
+
+                let attrs = attrs.borrow();
+                let attr = |wanted: &str| {
+                    attrs
+                        .iter()
+                        .find(|a| a.name.local.as_ref() == wanted)
+                        .map(|a| a.value.to_string())
+                };
+
+                if let Some(dir) = attr("dir") {
+                    if dir.trim().eq_ignore_ascii_case("auto") {
+                        self.inherited.direction = None;
+                    } else if let Some(direction) = TextDirection::parse(&dir) {
+                        self.inherited.direction = Some(direction);
+                        if matches!(tag, "html" | "body") {
+                            self.content.direction = direction;
+                        }
+                    }
+                }
+
+                let is_block = BLOCK_ELEMENTS.contains(&tag);
+                if is_block {
+                    self.line_break();
+                }
+                if tag == "br" {
+                    self.hard_break();
+                }
+
+                for anchor in [attr("id"), attr("name").filter(|_| tag == "a")].into_iter().flatten() {
+                    self.content
+                        .anchors
+                        .entry(anchor)
+                        .or_insert(self.content.text.len());
+                }
+
+                let start = self.content.text.len();
+                if let Some(role) = &role {
+                    self.announce(handle, role);
+                }
+                let is_sectioning = SECTIONING_ELEMENTS.contains(&tag);
+                if is_sectioning {
+                    self.sectioned += 1;
+                }
+                let is_monospace = MONOSPACE_ELEMENTS.contains(&tag);
+                let is_pre = tag == "pre";
+                if is_monospace {
+                    self.monospace += 1;
+                }
+                if is_pre {
+                    self.preformatted += 1;
+                    self.preformatted_line = 0;
+                }
+                if tag == "table" && !self.in_table {
+                    self.push_table(handle);
+                } else {
+                    self.walk_children(handle);
+                }
+                self.inherited = inherited;
+                if is_sectioning {
+                    self.sectioned -= 1;
+                }
+                // Linearized cells still need separating
+                if matches!(tag, "td" | "th") {
+                    self.pending_space = true;
+                }
+                if let Some(level) = heading_level(tag) {
+                    self.push_block(start, BlockKind::Heading(level));
+                }
+                if is_pre {
+                    self.preformatted -= 1;
+                    // Only the outermost pre gets a block box
+                    if self.preformatted == 0 {
+                        self.push_block(start, BlockKind::Preformatted);
+                    }
+                }
+                if is_monospace {
+                    self.monospace -= 1;
+                }
+
+                if tag == "a" {
+                    if let Some(href) = attr("href") {
+                        let start = start + self.leading_break_len(start);
+                        if start < self.content.text.len() {
+                            self.content.links.push(LinkSpan {
+                                range: start..self.content.text.len(),
+                                href,
+                                visited: false,
+                            });
+                        }
+                    }
+                }
+                if role.as_deref() == Some("link") {
+                    self.push_text(" (link)");
+                }
+
+                if is_block {
+                    self.line_break();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Stylesheet rules matching an element, overridden by its `style`
+    /// attribute and then by user hide rules
+    fn element_declarations(&self, handle: &Handle) -> Declarations {
+        let Some(tag) = element_name(handle) else {
+            return Declarations::default();
+        };
+        let id = attribute(handle, "id");
+        let class = attribute(handle, "class").unwrap_or_default();
+        let classes: Vec<&str> = class.split_whitespace().collect();
+
+        let mut declarations = if self.stylesheet.is_empty() {
+            Declarations::default()
+        } else {
+            self.stylesheet.declarations_for(&tag, id.as_deref(), &classes)
+        };
+        if let Some(style) = attribute(handle, "style") {
+            declarations.merge(&Declarations::parse(&style));
+        }
+        if self.user_styles.hides(&tag, id.as_deref(), &classes) {
+            declarations.hidden = Some(true);
+        }
+        declarations
+    }
+
+    /// Text announcing an element with `role`, written before its content
+    fn announce(&mut self, handle: &Handle, role: &str) {
+        let name = accessible_name(handle, role);
+        if let Some(landmark) = landmark_name(role) {
+            self.line_break();
+            if name.is_empty() {
+                self.push_text(&format!("{} landmark", landmark));
+            } else {
+                self.push_text(&format!("{} landmark: {}", landmark, name));
+            }
+            self.line_break();
+            return;
+        }
+        match role {
+            "heading" => {
+                let level = element_level(handle, role).unwrap_or(2);
+                self.push_text(&format!("Heading {}: ", level));
+            }
+            "list" => {
+                let items = handle
+                    .children
+                    .borrow()
+                    .iter()
+                    .filter(|child| !is_hidden(child) && element_role(child, false).as_deref() == Some("listitem"))
+                    .count();
+                self.line_break();
+                self.push_text(&format!("List of {} item{}", items, if items == 1 { "" } else { "s" }));
+                self.line_break();
+            }
+            "img" if name.is_empty() => self.push_text(" Image "),
+            "img" => self.push_text(&format!(" Image: {} ", name)),
+            _ => {}
+        }
+    }
+
+    fn walk_children(&mut self, handle: &Handle) {
+        for child in handle.children.borrow().iter() {
+            self.walk(child);
+        }
+    }
+
+    /// Newlines, spaces and direction marks emitted at `start` before the
+    /// first visible character
+    fn leading_break_len(&self, start: usize) -> usize {
+        self.content.text[start..]
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\n' || DIRECTION_MARKS.contains(c))
+            .map(char::len_utf8)
+            .sum()
+    }
+
+    fn push_text(&mut self, text: &str) {
+        let start = self.content.text.len();
+
+        if self.preformatted > 0 {
+            self.push_preformatted(text);
+        } else {
+            for c in text.chars() {
+                // HTML collapses only ASCII whitespace; a no-break space
+                // stays as it is so the line can't break there
+                if c.is_ascii_whitespace() {
+                    self.pending_space = true;
+                    continue;
+                }
+                if self.at_line_start() {
+                    self.mark_direction();
+                } else if self.pending_space {
+                    self.content.text.push(' ');
+                }
+                self.pending_space = false;
+                self.content.text.push(c);
+            }
+        }
+
+        let style = TextStyle {
+            monospace: self.monospace > 0,
+            bold: self.inherited.bold,
+            italic: self.inherited.italic,
+            color: self.inherited.color,
+            background: self.inherited.background,
+            font_scale: self.inherited.font_scale(),
+        };
+        self.push_style(start..self.content.text.len(), style);
+    }
+
+    /// Append text verbatim, cutting lines past the length cap so a minified
+    /// script dumped in a `<pre>` can't stall layout
+    fn push_preformatted(&mut self, text: &str) {
+        self.pending_space = false;
+        for c in text.chars() {
+            // Cells are measured in columns, where a tab takes none; it
+            // becomes the spaces to the next tab stop of the cell instead
+            if c == '\t' && self.in_table {
+                let spaces = TABLE_TAB_WIDTH - self.preformatted_line % TABLE_TAB_WIDTH;
+                for _ in 0..spaces {
+                    self.push_preformatted_char(' ');
+                }
+            } else {
+                self.push_preformatted_char(c);
+            }
+        }
+    }
+
+    fn push_preformatted_char(&mut self, c: char) {
+        if c == '\n' {
+            self.preformatted_line = 0;
+        } else {
+            self.preformatted_line += 1;
+            if self.preformatted_line > MAX_PREFORMATTED_LINE_CHARS {
+                if self.preformatted_line == MAX_PREFORMATTED_LINE_CHARS + 1 {
+                    self.content.text.push('…');
+                }
+                return;
+            }
+            if self.at_line_start() {
+                self.mark_direction();
+            }
+        }
+        self.content.text.push(c);
+    }
+
+    /// Start a line with the mark of the direction an enclosing `dir` set.
+    /// The shaper takes each line's direction from its first strong
+    /// character, which would otherwise overrule the page. Left-to-right
+    /// lines are only marked in right-to-left documents, and table cells,
+    /// which share lines, are left alone.
+    fn mark_direction(&mut self) {
+        let document = self.content.direction;
+        if let Some(direction) = self.inherited.direction.filter(|d| d.is_rtl() || document.is_rtl()) {
+            if !self.in_table {
+                self.content.text.push(direction.mark());
+            }
+        }
+    }
+
+    /// Record the style of a range of appended text, merging with the
+    /// previous span when they touch
+    fn push_style(&mut self, range: Range<usize>, style: TextStyle) {
+        let Range { start, end } = range;
+        if start == end || style == TextStyle::default() {
+            return;
+        }
+        if let Some(last) = self.content.styles.last_mut() {
+            if last.style == style && last.range.end == start {
+                last.range.end = end;
+                return;
+            }
+        }
+        self.content.styles.push(StyleSpan {
+            range: start..end,
+            style,
+        });
+    }
+
+    /// Lay out a table as a grid of monospace columns, one block per row
+    fn push_table(&mut self, table: &Handle) {
+        let mut rows = Vec::new();
+        self.collect_rows(table, &mut rows);
+
+        // Place cells into columns, skipping slots held by rowspans from above
+        let mut occupied: Vec<usize> = Vec::new();
+        let mut grid = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut placed = Vec::with_capacity(row.len());
+            let mut column = 0;
+            for cell in row {
+                while occupied.get(column).is_some_and(|rows| *rows > 0) {
+                    column += 1;
+                }
+                let end = column + cell.colspan;
+                if occupied.len() < end {
+                    occupied.resize(end, 0);
+                }
+                occupied[column..end].fill(cell.rowspan);
+                placed.push((column, cell));
+                column = end;
+            }
+            for rows in &mut occupied {
+                *rows = rows.saturating_sub(1);
+            }
+            grid.push(placed);
+        }
+
+        let mut natural = vec![0; occupied.len()];
+        for (column, cell) in grid.iter().flatten() {
+            // Spanning cells just get the summed widths of their columns
+            if cell.colspan == 1 {
+                natural[*column] = natural[*column].max(cell.natural_width());
+            }
+        }
+        let widths = column_widths(&natural, self.table_width.unwrap_or(DEFAULT_TABLE_WIDTH));
+        let starts: Vec<usize> = widths
+            .iter()
+            .scan(0, |x, width| {
+                let start = *x;
+                *x += width + CELL_GAP;
+                Some(start)
+            })
+            .collect();
+
+        for row in &grid {
+            self.line_break();
+            let row_start = self.content.text.len();
+            let laid: Vec<_> = row
+                .iter()
+                .map(|(column, cell)| {
+                    let spanned = &widths[*column..*column + cell.colspan];
+                    let width = spanned.iter().sum::<usize>() + CELL_GAP * (cell.colspan - 1);
+                    (starts[*column], cell, wrap_lines(&cell.content.text, width))
+                })
+                .collect();
+            let height = laid.iter().map(|(_, _, lines)| lines.len()).max().unwrap_or(0);
+
+            for line in 0..height {
+                if line > 0 {
+                    self.content.text.push('\n');
+                }
+                let mut x = 0;
+                for (start, cell, lines) in &laid {
+                    let Some(range) = lines.get(line) else {
+                        continue;
+                    };
+                    let text = cell.content.text[range.clone()].trim_end();
+                    if text.is_empty() {
+                        continue;
+                    }
+                    let padding = start.saturating_sub(x).max(usize::from(x > 0));
+                    self.push_padding(padding);
+                    self.push_cell_text(&cell.content, range.start..range.start + text.len());
+                    x += padding + display_width(text);
+                }
+            }
+
+            // Anchors in cells that produced no text land on the row
+            for (_, cell, _) in &laid {
+                for name in cell.content.anchors.keys() {
+                    self.content.anchors.entry(name.clone()).or_insert(row_start);
+                }
+            }
+            self.push_block(row_start, BlockKind::TableRow);
+        }
+        self.line_break();
+    }
+
+    /// Gather rows of cells, emitting captions as they are found
+    fn collect_rows(&mut self, node: &Handle, rows: &mut Vec<Vec<TableCell>>) {
+        for child in node.children.borrow().iter() {
+            let Some(tag) = element_name(child) else {
+                continue;
+            };
+            if tag == "caption" {
+                self.line_break();
+                self.walk(child);
+                self.line_break();
+                continue;
+            }
+
+            let declarations = self.element_declarations(child);
+            if declarations.hidden == Some(true) {
+                continue;
+            }
+            let inherited = self.inherited;
+            self.inherited = inherited.child(&tag, &declarations);
+            match tag.as_str() {
+                "thead" | "tbody" | "tfoot" => self.collect_rows(child, rows),
+                "tr" => rows.push(self.collect_cells(child)),
+                _ => {}
+            }
+            self.inherited = inherited;
+        }
+    }
+
+    fn collect_cells(&self, row: &Handle) -> Vec<TableCell> {
+        let mut cells = Vec::new();
+        for child in row.children.borrow().iter() {
+            let Some(tag) = element_name(child).filter(|tag| tag == "td" || tag == "th") else {
+                continue;
+            };
+            let declarations = self.element_declarations(child);
+            if declarations.hidden == Some(true) {
+                continue;
+            }
+            let span = |name: &str| {
+                attribute(child, name)
+                    .and_then(|value| value.trim().parse::<usize>().ok())
+                    .unwrap_or(1)
+                    .clamp(1, 64)
+            };
+
+            let mut builder = LayoutBuilder {
+                in_table: true,
+                inherited: self.inherited.child(&tag, &declarations),
+                stylesheet: self.stylesheet.clone(),
+                user_styles: self.user_styles.clone(),
+                accessible: self.accessible,
+                sectioned: self.sectioned,
+                show_noscript: self.show_noscript,
+                ..LayoutBuilder::default()
+            };
+            if let Some(id) = attribute(child, "id") {
+                builder.content.anchors.insert(id, 0);
+            }
+            builder.walk_children(child);
+            cells.push(TableCell {
+                content: builder.finish(),
+                colspan: span("colspan"),
+                rowspan: span("rowspan"),
+            });
+        }
+        cells
+    }
+
+    /// Spaces between table cells, kept monospace so columns line up
+    fn push_padding(&mut self, columns: usize) {
+        let start = self.content.text.len();
+        self.content.text.extend(std::iter::repeat_n(' ', columns));
+        let style = TextStyle {
+            monospace: true,
+            ..TextStyle::default()
+        };
+        self.push_style(start..self.content.text.len(), style);
+    }
+
+    /// Copy part of a cell's text, carrying its styles, links and anchors over
+    fn push_cell_text(&mut self, cell: &PageContent, range: Range<usize>) {
+        let out = self.content.text.len();
+        let map = |offset: usize| out + offset - range.start;
+        self.content.text.push_str(&cell.text[range.clone()]);
+
+        let mono = TextStyle {
+            monospace: true,
+            ..TextStyle::default()
+        };
+        let mut position = range.start;
+        for span in &cell.styles {
+            let start = span.range.start.max(position);
+            let end = span.range.end.min(range.end);
+            if start >= end {
+                continue;
+            }
+            self.push_style(map(position)..map(start), mono);
+            self.push_style(map(start)..map(end), TextStyle { monospace: true, ..span.style });
+            position = end;
+        }
+        self.push_style(map(position)..map(range.end), mono);
+
+        for link in &cell.links {
+            let start = link.range.start.max(range.start);
+            let end = link.range.end.min(range.end);
+            if start < end {
+                self.content.links.push(LinkSpan {
+                    range: map(start)..map(end),
+                    href: link.href.clone(),
+                    visited: link.visited,
+                });
+            }
+        }
+        for (name, offset) in &cell.anchors {
+            if range.contains(offset) {
+                self.content.anchors.entry(name.clone()).or_insert(map(*offset));
+            }
+        }
+    }
+
+    fn push_block(&mut self, start: usize, kind: BlockKind) {
+        let start = start + self.leading_break_len(start);
+        let end = self.content.text.trim_end_matches('\n').len();
+        if start < end {
+            self.content.blocks.push(BlockSpan {
+                range: start..end,
+                kind,
+            });
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.content.text.is_empty() || self.content.text.ends_with('\n')
+    }
+
+    /// End the current line unless already at the start of one
+    fn line_break(&mut self) {
+        if !self.at_line_start() {
+            self.content.text.push('\n');
+        }
+        self.pending_space = false;
+    }
+
+    fn hard_break(&mut self) {
+        self.content.text.push('\n');
+        self.pending_space = false;
+    }
+}
+
+/// Gather the rules of every `<style>` block that applies on screen
+fn collect_stylesheets(handle: &Handle, stylesheet: &mut Stylesheet) {
+    for child in handle.children.borrow().iter() {
+        if element_name(child).as_deref() != Some("style") {
+            collect_stylesheets(child, stylesheet);
+            continue;
+        }
+        let media = attribute(child, "media").unwrap_or_default().to_ascii_lowercase();
+        if media.contains("print") && !media.contains("screen") {
+            continue;
+        }
+        let mut css = String::new();
+        for text in child.children.borrow().iter() {
+            if let NodeData::Text { contents } = &text.data {
+                css.push_str(&contents.borrow());
+            }
+        }
+        stylesheet.add(&css);
+    }
+}
+
+pub(super) fn element_name(handle: &Handle) -> Option<String> {
+    match &handle.data {
+        NodeData::Element { name, .. } => Some(name.local.to_string()),
+        _ => None,
+    }
+}
+
+pub(super) fn attribute(handle: &Handle, wanted: &str) -> Option<String> {
+    match &handle.data {
+        NodeData::Element { attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|a| a.name.local.as_ref() == wanted)
+            .map(|a| a.value.to_string()),
+        _ => None,
+    }
+}
+
+/// Level of a heading element
+pub(super) fn heading_level(tag: &str) -> Option<u8> {
+    match tag.as_bytes() {
+        [b'h', level @ b'1'..=b'6'] => Some(level - b'0'),
+        _ => None,
+    }
+}
+
+/// Width of text in monospace columns; wide CJK characters take two
+fn display_width(text: &str) -> usize {
+    text.chars().map(char_columns).sum()
+}
+
+/// Columns a character takes; soft hyphens are shaped as nothing
+fn char_columns(c: char) -> usize {
+    if c == '\u{ad}' {
+        0
+    } else {
+        c.width().unwrap_or(0)
+    }
+}
+
+/// Fit natural column widths into `total` columns, shrinking proportionally
+/// but never below a minimum
+fn column_widths(natural: &[usize], total: usize) -> Vec<usize> {
+    let available = total.saturating_sub(CELL_GAP * natural.len().saturating_sub(1));
+    let sum: usize = natural.iter().sum();
+    if sum <= available {
+        return natural.to_vec();
+    }
+    natural
+        .iter()
+        .map(|width| (width * available / sum).max(MIN_COLUMN_WIDTH.min(*width)))
+        .collect()
+}
+
+/// Byte ranges of `text` word-wrapped to `width` columns; words longer than
+/// a line are split
+fn wrap_lines(text: &str, width: usize) -> Vec<Range<usize>> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut base = 0;
+    for line in text.split('\n') {
+        let mut start = base;
+        let mut line_width = 0;
+        // End of the text before the last space, where to resume, and the
+        // width consumed up to and including that space
+        let mut last_break: Option<(usize, usize, usize)> = None;
+
+        for (i, c) in line.char_indices() {
+            let position = base + i;
+            let char_width = char_columns(c);
+            if c == ' ' {
+                last_break = Some((position, position + 1, line_width + 1));
+            } else if line_width + char_width > width && position > start {
+                match last_break.filter(|(end, _, _)| *end > start) {
+                    Some((end, next, consumed)) => {
+                        lines.push(start..end);
+                        start = next;
+                        line_width -= consumed;
+                    }
+                    None => {
+                        lines.push(start..position);
+                        start = position;
+                        line_width = 0;
+                    }
+                }
+                last_break = None;
+            }
+            line_width += char_width;
+        }
+        lines.push(start..base + line.len());
+        base += line.len() + 1;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::UserStyleRule;
+    use html5ever::parse_document;
+    use html5ever::tendril::TendrilSink;
+
+    fn layout(html: &str) -> PageContent {
+        let dom = parse_document(RcDom::default(), Default::default())
+            .from_utf8()
+            .read_from(&mut html.as_bytes())
+            .unwrap();
+        LayoutBuilder::new().build(&dom)
+    }
+
+    #[test]
+    fn test_blocks_and_whitespace() {
+        let content = layout(
+            "<html><head><title>T</title><style>p{}</style></head>\
+             <body><h1>Title</h1><p>Some   <b>bold</b>\n text</p><div>Next</div></body></html>",
+        );
+        assert_eq!(content.text, "Title\nSome bold text\nNext");
+        assert_eq!(
+            content.blocks,
+            vec![BlockSpan {
+                range: 0..5,
+                kind: BlockKind::Heading(1)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_anchor_offsets() {
+        let content = layout(
+            "<body><p>Intro</p><h2 id=\"usage\">Usage</h2><a name=\"old-style\"></a><p>More</p>\
+             <h2 id=\"caf%C3%A9\">x</h2><h2 id=\"café\">Café</h2></body>",
+        );
+        let usage = content.anchor_offset("usage").unwrap();
+        assert!(content.text[usage..].starts_with("Usage"));
+        let old = content.anchor_offset("old-style").unwrap();
+        assert!(content.text[old..].trim_start().starts_with("More"));
+        let cafe = content.anchor_offset("caf%C3%A9").unwrap();
+        assert!(content.text[cafe..].starts_with('x'));
+        assert!(content.anchor_offset("missing").is_none());
+    }
+
+    #[test]
+    fn test_link_spans() {
+        let content = layout("<body><p>See <a href=\"#details\">the details</a> below</p></body>");
+        let start = content.text.find("the details").unwrap();
+        let link = content.link_at(start + 2).unwrap();
+        assert_eq!(link.href, "#details");
+        assert_eq!(&content.text[link.range.clone()], "the details");
+        assert!(content.link_at(0).is_none());
+    }
+
+    #[test]
+    fn test_preformatted_keeps_whitespace() {
+        let content = layout("<body><pre>a  b\n  c</pre></body>");
+        assert_eq!(content.text, "a  b\n  c");
+    }
+
+    #[test]
+    fn test_no_break_spaces_are_not_collapsed() {
+        let content = layout("<p>1&nbsp;000\u{3000}km \t\u{c}\r\nfar\u{a0} away</p>");
+        assert_eq!(content.text, "1\u{a0}000\u{3000}km far\u{a0} away");
+    }
+
+    #[test]
+    fn test_soft_hyphens_and_tabs() {
+        // Soft hyphens reach the shaper, which breaks lines at them
+        let content = layout("<p>Donau&shy;dampf&shy;schiff&shy;fahrts\u{ad}gesell\tschaft</p>");
+        assert_eq!(content.text, "Donau\u{ad}dampf\u{ad}schiff\u{ad}fahrts\u{ad}gesell schaft");
+
+        // but take no column in a table, and tabs in its cells become spaces
+        let content = layout(
+            "<table><tr><td>Rind&shy;fleisch</td><td>kg</td></tr>\
+             <tr><td><pre>ab\tc</pre></td><td>lb</td></tr></table>",
+        );
+        assert_eq!(column_of(&content.text, "kg"), "Rindfleisch".len() + CELL_GAP);
+        assert_eq!(column_of(&content.text, "lb"), column_of(&content.text, "kg"));
+        assert!(content.text.contains("ab      c  "), "{:?}", content.text);
+        assert!(!content.text.contains('\t'));
+    }
+
+    #[test]
+    fn test_preformatted_block_is_monospace() {
+        let content = layout("<body><p>Before</p><pre><code>fn main() {\n\tlet x = 1;\n}</code></pre><p>After</p></body>");
+
+        assert_eq!(content.blocks.len(), 1);
+        let block = &content.blocks[0];
+        assert_eq!(block.kind, BlockKind::Preformatted);
+        assert_eq!(&content.text[block.range.clone()], "fn main() {\n\tlet x = 1;\n}");
+
+        // pre > code is a single monospace span, not two nested ones
+        assert_eq!(content.styles.len(), 1);
+        assert_eq!(content.styles[0].range, block.range);
+        assert!(!content.style_at(0).monospace);
+    }
+
+    #[test]
+    fn test_inline_code_is_monospace_without_block() {
+        let content = layout("<body><p>Call <code>foo()</code> now</p></body>");
+        let start = content.text.find("foo()").unwrap();
+
+        assert!(content.blocks.is_empty());
+        assert!(content.style_at(start).monospace);
+        assert!(!content.style_at(content.text.find("now").unwrap()).monospace);
+    }
+
+    #[test]
+    fn test_long_preformatted_lines_capped() {
+        let long = "x".repeat(MAX_PREFORMATTED_LINE_CHARS + 500);
+        let content = layout(&format!("<body><pre>{}\nshort</pre></body>", long));
+        let first = content.text.lines().next().unwrap();
+
+        assert_eq!(first.chars().count(), MAX_PREFORMATTED_LINE_CHARS + 1);
+        assert!(first.ends_with('…'));
+        assert!(content.text.ends_with("short"));
+    }
+
+    const INFOBOX: &str = r#"<body><table class="infobox"><tbody>
+        <tr><th colspan="2" class="infobox-above">Rust</th></tr>
+        <tr><th scope="row">Paradigms</th><td>Concurrent, functional, generic, imperative</td></tr>
+        <tr><th scope="row">Designed by</th><td><a href="/wiki/Graydon_Hoare">Graydon Hoare</a></td></tr>
+        <tr><th scope="row">First appeared</th><td id="release">May 15, 2015</td></tr>
+        </tbody></table><p>Rust is a language.</p></body>"#;
+
+    const DATA_TABLE: &str = r#"<body><table>
+        <caption>Releases</caption>
+        <thead><tr><th>Version</th><th>Date</th><th>Notes</th></tr></thead>
+        <tbody>
+        <tr><td>1.0</td><td rowspan="2">2015</td><td>Stable</td></tr>
+        <tr><td>1.1</td><td>Faster builds</td></tr>
+        <tr><td>1.2</td><td>2015</td><td><table><tr><td>nested</td><td>cell</td></tr></table></td></tr>
+        </tbody></table></body>"#;
+
+    /// Display column where `needle` starts on its line
+    fn column_of(text: &str, needle: &str) -> usize {
+        let offset = text.find(needle).unwrap();
+        let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        display_width(&text[line_start..offset])
+    }
+
+    #[test]
+    fn test_infobox_table_aligns_columns() {
+        let content = layout(INFOBOX);
+        let lines: Vec<&str> = content.text.lines().collect();
+        assert_eq!(lines[0], "Rust");
+        assert_eq!(lines.last(), Some(&"Rust is a language."));
+
+        // Values start in the same column, past the longest label
+        let value_column = column_of(&content.text, "Concurrent");
+        assert_eq!(value_column, "First appeared".len() + CELL_GAP);
+        assert_eq!(column_of(&content.text, "Graydon"), value_column);
+        assert_eq!(column_of(&content.text, "May 15"), value_column);
+
+        // One row block each, header cells bold, everything monospace
+        let rows = content.blocks.iter().filter(|b| b.kind == BlockKind::TableRow).count();
+        assert_eq!(rows, 4);
+        let label = content.text.find("Designed by").unwrap();
+        assert!(content.style_at(label).bold && content.style_at(label).monospace);
+        let value = content.text.find("May 15").unwrap();
+        assert!(!content.style_at(value).bold && content.style_at(value).monospace);
+        assert!(!content.style_at(content.text.find("is a language").unwrap()).monospace);
+
+        // Links and anchors inside cells point at the moved text
+        let link = content.link_at(content.text.find("Hoare").unwrap()).unwrap();
+        assert_eq!(&content.text[link.range.clone()], "Graydon Hoare");
+        assert_eq!(content.anchor_offset("release"), Some(value));
+    }
+
+    #[test]
+    fn test_data_table_spans_and_nesting() {
+        let content = layout(DATA_TABLE);
+        let lines: Vec<&str> = content.text.lines().collect();
+        assert_eq!(lines[0], "Releases");
+        assert!(lines[1].starts_with("Version  Date"));
+
+        // The rowspan cell only renders in its first row and keeps its column
+        assert_eq!(column_of(&content.text, "Faster"), column_of(&content.text, "Stable"));
+        assert_eq!(content.text.matches("2015").count(), 2);
+
+        // Nested tables fall back to linear text inside the cell
+        assert!(lines.last().unwrap().ends_with("2015  nested cell"));
+    }
+
+    #[test]
+    fn test_column_widths_shrink_proportionally() {
+        assert_eq!(column_widths(&[10, 20], 96), vec![10, 20]);
+
+        let widths = column_widths(&[100, 300, 2], 50);
+        assert_eq!(widths, vec![11, 34, 2]);
+        assert!(widths[0] >= MIN_COLUMN_WIDTH);
+    }
+
+    #[test]
+    fn test_long_cells_wrap_within_column() {
+        let text = "alpha beta gamma\nsupercalifragilistic";
+        let lines: Vec<&str> = wrap_lines(text, 10).into_iter().map(|r| &text[r]).collect();
+        assert_eq!(lines, ["alpha beta", "gamma", "supercalif", "ragilistic"]);
+
+        let wide = "表格内容";
+        let lines: Vec<&str> = wrap_lines(wide, 4).into_iter().map(|r| &wide[r]).collect();
+        assert_eq!(lines, ["表格", "内容"]);
+    }
+
+    const STYLED: &str = r#"<html><head><style>
+        .banner { display: none }
+        p { color: #333333 }
+        .warn { color: red; font-weight: bold }
+        #final { color: blue }
+        </style><style media="print">p { display: none }</style></head>
+        <body><div class="banner">Accept cookies</div>
+        <p>Plain</p>
+        <p class="warn">Careful <em>now</em></p>
+        <p class="warn" style="color: green; font-weight: normal">Inline wins</p>
+        <p id="final" class="warn" style="font-size: 200%">Big <span style="font-size: 0.5em">small</span></p>
+        <div style="display:none"><p>Hidden <a href="/x">link</a></p></div>
+        <p style="color: nonsense; background-color: #ff0">Marked</p>
+        </body></html>"#;
+
+    #[test]
+    fn test_display_none_skips_subtree() {
+        let content = layout(STYLED);
+        assert!(!content.text.contains("Accept cookies"));
+        assert!(!content.text.contains("Hidden"));
+        assert!(content.links.is_empty());
+        assert!(content.text.starts_with("Plain"));
+    }
+
+    #[test]
+    fn test_inline_style_overrides_stylesheet() {
+        let content = layout(STYLED);
+        let style = |needle: &str| content.style_at(content.text.find(needle).unwrap());
+
+        assert_eq!(style("Plain").color, Some(Rgb::new(0x33, 0x33, 0x33)));
+        assert_eq!(style("Careful").color, Some(Rgb::new(255, 0, 0)));
+        assert!(style("Careful").bold);
+
+        // Inherited down to children, which add their own defaults
+        assert!(style("now").bold && style("now").italic);
+        assert_eq!(style("now").color, Some(Rgb::new(255, 0, 0)));
+
+        let inline = style("Inline wins");
+        assert_eq!(inline.color, Some(Rgb::new(0, 128, 0)));
+        assert!(!inline.bold);
+
+        // The id rule beats the class rule; sizes compound
+        assert_eq!(style("Big").color, Some(Rgb::new(0, 0, 255)));
+        assert_eq!(style("Big").font_scale, Some(200));
+        assert_eq!(style("small").font_scale, None);
+
+        // A malformed value falls back to the stylesheet
+        let marked = style("Marked");
+        assert_eq!(marked.color, Some(Rgb::new(0x33, 0x33, 0x33)));
+        assert_eq!(marked.background, Some(Rgb::new(255, 255, 0)));
+        assert_eq!(content.background, None);
+    }
+
+    #[test]
+    fn test_page_background_comes_from_body_or_html() {
+        let content = layout("<html style=\"background-color: #fff\"><body><p>Text</p></body></html>");
+        assert_eq!(content.background, Some(Rgb::new(255, 255, 255)));
+        let content = layout(
+            "<html style=\"background-color: #fff\"><body style=\"background-color: #111\">\
+             <p style=\"background-color: #ff0\">Text</p></body></html>",
+        );
+        assert_eq!(content.background, Some(Rgb::new(0x11, 0x11, 0x11)));
+    }
+
+    #[test]
+    fn test_user_styles_apply_over_page_styles() {
+        let dom = parse_document(RcDom::default(), Default::default())
+            .from_utf8()
+            .read_from(&mut STYLED.as_bytes())
+            .unwrap();
+        let user_styles = UserStylesheet::from_rules(&[
+            UserStyleRule::MinFontSize { px: 20.0 },
+            UserStyleRule::TextColor { color: "#111".to_string() },
+            UserStyleRule::Hide { selector: "#final".to_string() },
+        ]);
+        let content = LayoutBuilder::new().with_user_styles(user_styles).build(&dom);
+        let style = |needle: &str| content.style_at(content.text.find(needle).unwrap());
+
+        assert!(!content.text.contains("Big"));
+        assert_eq!(style("Careful").color, Some(Rgb::new(0x11, 0x11, 0x11)));
+        assert_eq!(style("Inline wins").color, Some(Rgb::new(0x11, 0x11, 0x11)));
+        assert_eq!(style("Plain").font_scale, Some(125));
+        // Page backgrounds are left alone
+        assert_eq!(style("Marked").background, Some(Rgb::new(255, 255, 0)));
+    }
+
+    #[test]
+    fn test_accessible_output_announces_structure() {
+        let html = "<nav aria-label='Site'><ul><li><a href='/'>Home</a></li><li hidden>Gone</li>\
+                    <li><a href='/about'>About</a></li></ul></nav>\
+                    <main><h2>News</h2><p>A <img src=cat.png alt='Sleeping cat'> photo\
+                    <img src=dot.gif alt=''><span aria-hidden=true>*</span></p>\
+                    <div role=heading aria-level=3>Also</div><ol><li>One</li></ol></main>";
+        let dom = parse_document(RcDom::default(), Default::default())
+            .from_utf8()
+            .read_from(&mut html.as_bytes())
+            .unwrap();
+        let content = LayoutBuilder::new().with_accessible_output(true).build(&dom);
+        assert_eq!(
+            content.text,
+            "Navigation landmark: Site\nList of 2 items\nHome (link)\nAbout (link)\nMain landmark\n\
+             Heading 2: News\nA Image: Sleeping cat photo\nHeading 3: Also\nList of 1 item\nOne"
+        );
+        let links: Vec<&str> = content.links.iter().map(|link| &content.text[link.range.clone()]).collect();
+        assert_eq!(links, ["Home", "About"]);
+        let heading = content.text.find("Heading 2").unwrap();
+        assert!(content.blocks.contains(&BlockSpan {
+            range: heading..heading + "Heading 2: News".len(),
+            kind: BlockKind::Heading(2)
+        }));
+
+        // Off by default
+        assert_eq!(layout(html).text, "Home\nGone\nAbout\nNews\nA photo*\nAlso\nOne");
+    }
+
+    #[test]
+    fn test_direction_marks_fix_paragraph_direction() {
+        let content = layout("<p>Plain</p><p dir=rtl>Hello שלום</p><p dir=auto>Next</p>");
+        assert_eq!(content.direction, TextDirection::Ltr);
+        assert_eq!(content.text, "Plain\n\u{200F}Hello שלום\nNext");
+
+        let content =
+            layout("<html dir=rtl><body><p>שלום world</p><p dir=ltr>Hi</p><pre>a\nb</pre></body></html>");
+        assert_eq!(content.direction, TextDirection::Rtl);
+        assert_eq!(content.text, "\u{200F}שלום world\n\u{200E}Hi\n\u{200F}a\n\u{200F}b");
+        assert_eq!(TextDirection::parse(" RTL "), Some(TextDirection::Rtl));
+        assert_eq!(TextDirection::parse("auto"), None);
+    }
+}
@@ -0,0 +1,172 @@
+use super::layout::{BlockKind, PageContent, TextDirection, TextStyle, DIRECTION_MARKS};
+use std::fmt::Write;
+use std::ops::Range;
+
+/// Write `content` out as stable, line-oriented text for snapshot tests.
+///
+/// Positions are `line:column`, both counted from 1 in characters, so the
+/// text reads the way it is laid out on screen before any fonts are
+/// involved. Links are listed with the cells they cover on each line, and
+/// anchors are sorted by name. Direction marks and no-break spaces are
+/// spelled out as `<LRM>`, `<RLM>` and `<NBSP>` but count as one column.
+pub fn layout_snapshot(content: &PageContent) -> String {
+    let positions = Positions::new(&content.text);
+    let direction = match content.direction {
+        TextDirection::Ltr => "ltr",
+        TextDirection::Rtl => "rtl",
+    };
+    let mut out = format!("== direction {}\n== text\n", direction);
+    let text = content
+        .text
+        .replace(DIRECTION_MARKS[0], "<LRM>")
+        .replace(DIRECTION_MARKS[1], "<RLM>")
+        .replace('\u{A0}', "<NBSP>");
+    for (number, line) in text.split('\n').enumerate() {
+        let _ = if line.is_empty() {
+            writeln!(out, "{:>4} |", number + 1)
+        } else {
+            writeln!(out, "{:>4} | {}", number + 1, line)
+        };
+    }
+
+    out.push_str("== blocks\n");
+    for block in &content.blocks {
+        let kind = match block.kind {
+            BlockKind::Preformatted => "preformatted".to_string(),
+            BlockKind::TableRow => "table row".to_string(),
+            BlockKind::Heading(level) => format!("heading {}", level),
+        };
+        let _ = writeln!(out, "{} {}", positions.range(&block.range), kind);
+    }
+
+    out.push_str("== styles\n");
+    for span in &content.styles {
+        let _ = writeln!(out, "{} {}", positions.range(&span.range), describe_style(&span.style));
+    }
+
+    out.push_str("== links\n");
+    for link in &content.links {
+        let cells: Vec<String> = positions
+            .line_ranges(&link.range)
+            .into_iter()
+            .map(|(line, columns)| format!("{}:{}-{}", line, columns.start, columns.end))
+            .collect();
+        let visited = if link.visited { " (visited)" } else { "" };
+        let _ = writeln!(out, "[{}] {}{}", cells.join(", "), link.href, visited);
+    }
+
+    out.push_str("== anchors\n");
+    let mut anchors: Vec<_> = content.anchors.iter().collect();
+    anchors.sort();
+    for (name, offset) in anchors {
+        let _ = writeln!(out, "{} {}", positions.at(*offset), name);
+    }
+    out
+}
+
+fn describe_style(style: &TextStyle) -> String {
+    let mut parts = Vec::new();
+    if style.monospace {
+        parts.push("monospace".to_string());
+    }
+    if style.bold {
+        parts.push("bold".to_string());
+    }
+    if style.italic {
+        parts.push("italic".to_string());
+    }
+    if let Some(color) = style.color {
+        parts.push(format!("color #{:02x}{:02x}{:02x}", color.r, color.g, color.b));
+    }
+    if let Some(background) = style.background {
+        parts.push(format!("background #{:02x}{:02x}{:02x}", background.r, background.g, background.b));
+    }
+    if let Some(scale) = style.font_scale {
+        parts.push(format!("size {}%", scale));
+    }
+    parts.join(" ")
+}
+
+/// Maps byte offsets of laid-out text to lines and columns
+struct Positions<'a> {
+    text: &'a str,
+    /// Byte offset where each line starts
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Positions<'a> {
+    fn new(text: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self { text, line_starts }
+    }
+
+    /// 1-based line and column of `offset`
+    fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = self.clamp(offset);
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let column = self.text[self.line_starts[line]..offset].chars().count();
+        (line + 1, column + 1)
+    }
+
+    fn at(&self, offset: usize) -> String {
+        let (line, column) = self.line_column(offset);
+        format!("{}:{}", line, column)
+    }
+
+    fn range(&self, range: &Range<usize>) -> String {
+        format!("{}..{}", self.at(range.start), self.at(range.end))
+    }
+
+    /// The columns `range` covers on each line it touches, ends exclusive
+    fn line_ranges(&self, range: &Range<usize>) -> Vec<(usize, Range<usize>)> {
+        let (first, start) = self.line_column(range.start);
+        let (last, end) = self.line_column(range.end);
+        (first..=last)
+            .map(|line| {
+                let from = if line == first { start } else { 1 };
+                let to = if line == last {
+                    end
+                } else {
+                    let line_end = self.line_starts[line] - 1;
+                    self.line_column(line_end).1
+                };
+                (line, from..to)
+            })
+            .filter(|(_, columns)| !columns.is_empty())
+            .collect()
+    }
+
+    /// `offset` moved back onto a character boundary within the text
+    fn clamp(&self, offset: usize) -> usize {
+        let mut offset = offset.min(self.text.len());
+        while !self.text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::layout::LinkSpan;
+
+    #[test]
+    fn test_links_list_the_cells_they_cover() {
+        let mut content = PageContent::from_text("héllo wide\nworld");
+        content.links.push(LinkSpan {
+            range: 7..17,
+            href: "https://example.com/".to_string(),
+            visited: true,
+        });
+        content.anchors.insert("top".to_string(), 0);
+        content.anchors.insert("end".to_string(), 12);
+
+        let snapshot = layout_snapshot(&content);
+        assert!(snapshot.contains("   1 | héllo wide\n   2 | world\n"));
+        assert!(snapshot.contains("[1:7-11, 2:1-6] https://example.com/ (visited)\n"));
+        assert!(snapshot.ends_with("== anchors\n2:1 end\n1:1 top\n"));
+    }
+}
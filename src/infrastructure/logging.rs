@@ -0,0 +1,411 @@
+use crate::domain::{SettingsRepository, StorageError};
+use chrono::{DateTime, Utc};
+use std::fmt::{self, Write as _};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Events kept for the logs page and crash reports
+pub const LOG_BUFFER_CAPACITY: usize = 2000;
+/// Level for Navigator's own events unless overridden
+pub const DEFAULT_LOG_LEVEL: Level = Level::INFO;
+/// Overrides [`DEFAULT_LOG_LEVEL`] without a restart, e.g. "debug"
+pub const LOG_LEVEL_SETTING: &str = "debug.log_level";
+/// Noisy dependencies, kept quiet whatever the level
+const QUIET_TARGETS: &str = "wgpu=warn";
+
+/// One tracing event as kept in the [`LogBuffer`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    /// Order the event was recorded in
+    pub sequence: u64,
+    pub time: DateTime<Utc>,
+    pub level: Level,
+    pub target: String,
+    /// Enclosing spans, outermost first, as `name{fields}:name{fields}`
+    pub spans: String,
+    pub message: String,
+    /// Other fields of the event as ` name=value`
+    pub fields: String,
+}
+
+impl fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:>5} {}: ", self.time.format("%H:%M:%S%.3f"), self.level, self.target)?;
+        if !self.spans.is_empty() {
+            write!(f, "{}: ", self.spans)?;
+        }
+        write!(f, "{}{}", self.message, self.fields)
+    }
+}
+
+struct Ring {
+    /// Each slot is locked only to swap its record, so concurrent writers
+    /// contend only when they land on the same slot
+    slots: Box<[Mutex<Option<Arc<LogRecord>>>]>,
+    next: AtomicU64,
+}
+
+/// Tracing layer keeping the latest events in a fixed-size ring buffer
+#[derive(Clone)]
+pub struct LogBuffer {
+    ring: Arc<Ring>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        let slots = (0..capacity.max(1)).map(|_| Mutex::new(None)).collect();
+        Self {
+            ring: Arc::new(Ring {
+                slots,
+                next: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+
+    /// The kept records, oldest first
+    pub fn records(&self) -> Vec<Arc<LogRecord>> {
+        self.collect(true)
+    }
+
+    /// Like `records`, but skips slots rather than wait for them, since a
+    /// panicking thread may be holding one itself
+    pub fn try_records(&self) -> Vec<Arc<LogRecord>> {
+        self.collect(false)
+    }
+
+    fn collect(&self, wait: bool) -> Vec<Arc<LogRecord>> {
+        let mut records: Vec<Arc<LogRecord>> = self
+            .ring
+            .slots
+            .iter()
+            .filter_map(|slot| {
+                let record = if wait { slot.lock().ok() } else { slot.try_lock().ok() };
+                record.and_then(|record| record.clone())
+            })
+            .collect();
+        records.sort_by_key(|record| record.sequence);
+        records
+    }
+
+    fn push(&self, level: Level, target: &str, spans: String, message: String, fields: String) {
+        let sequence = self.ring.next.fetch_add(1, Ordering::Relaxed);
+        let record = LogRecord {
+            sequence,
+            time: Utc::now(),
+            level,
+            target: target.to_string(),
+            spans,
+            message,
+            fields,
+        };
+        let slot = &self.ring.slots[(sequence % self.ring.slots.len() as u64) as usize];
+        if let Ok(mut slot) = slot.lock() {
+            // A writer that lapped this one may have filled the slot already
+            if slot.as_ref().is_none_or(|current| current.sequence < sequence) {
+                *slot = Some(Arc::new(record));
+            }
+        }
+    }
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        Self::new(LOG_BUFFER_CAPACITY)
+    }
+}
+
+/// Fields of a span, kept in its extensions
+struct SpanFields {
+    fields: String,
+    opened: Instant,
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for LogBuffer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = EventFields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields {
+                fields: fields.rest.trim_start().to_string(),
+                opened: Instant::now(),
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let mut fields = EventFields::default();
+        values.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            if let Some(span_fields) = span.extensions_mut().get_mut::<SpanFields>() {
+                if !span_fields.fields.is_empty() {
+                    span_fields.fields.push(' ');
+                }
+                span_fields.fields.push_str(fields.rest.trim_start());
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut fields = EventFields::default();
+        event.record(&mut fields);
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| describe_scope(scope.from_root()))
+            .unwrap_or_default();
+        self.push(*metadata.level(), metadata.target(), spans, fields.message, fields.rest);
+    }
+
+    /// Each span is logged as it closes, with how long it was open
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let elapsed = span
+            .extensions()
+            .get::<SpanFields>()
+            .map(|fields| fields.opened.elapsed())
+            .unwrap_or_default();
+        let metadata = span.metadata();
+        self.push(
+            *metadata.level(),
+            metadata.target(),
+            describe_scope(span.scope().from_root()),
+            format!("done in {:.1?}", elapsed),
+            String::new(),
+        );
+    }
+}
+
+fn describe_scope<'a, S>(scope: impl Iterator<Item = SpanRef<'a, S>>) -> String
+where
+    S: for<'l> LookupSpan<'l> + 'a,
+{
+    let mut spans = String::new();
+    for span in scope {
+        if !spans.is_empty() {
+            spans.push(':');
+        }
+        spans.push_str(span.name());
+        if let Some(fields) = span.extensions().get::<SpanFields>() {
+            if !fields.fields.is_empty() {
+                let _ = write!(spans, "{{{}}}", fields.fields);
+            }
+        }
+    }
+    spans
+}
+
+/// An event's message, then its other fields as ` name=value`
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for EventFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.rest, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{:?}", value);
+        } else {
+            let _ = write!(self.rest, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// Which records the logs page shows, from its query string
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LogFilter {
+    /// Least severe level shown
+    pub level: Option<Level>,
+    /// Case-insensitive text each shown record contains
+    pub text: Option<String>,
+}
+
+impl LogFilter {
+    /// Read `level=` and `q=` from a query string, ignoring other parameters
+    pub fn from_query(query: Option<&str>) -> Self {
+        let mut filter = Self::default();
+        for (name, value) in url::form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+            match name.as_ref() {
+                "level" => filter.level = Level::from_str(&value).ok(),
+                "q" if !value.trim().is_empty() => filter.text = Some(value.trim().to_lowercase()),
+                _ => {}
+            }
+        }
+        filter
+    }
+
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        // More verbose levels compare greater
+        self.level.is_none_or(|level| record.level <= level)
+            && self
+                .text
+                .as_ref()
+                .is_none_or(|text| record.to_string().to_lowercase().contains(text))
+    }
+}
+
+/// Reloadable filter installed on the subscriber
+pub type LogFilterLayer = reload::Layer<EnvFilter, Registry>;
+
+/// Changes which events are logged while the browser runs
+pub struct LogLevelControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    level: RwLock<Option<Level>>,
+    settings: OnceLock<Arc<dyn SettingsRepository>>,
+}
+
+impl LogLevelControl {
+    /// The control and the filter layer it changes, logging at `DEFAULT_LOG_LEVEL`
+    pub fn new() -> (Self, LogFilterLayer) {
+        let (layer, handle) = reload::Layer::new(EnvFilter::new(directives(DEFAULT_LOG_LEVEL)));
+        let control = Self {
+            handle,
+            level: RwLock::new(None),
+            settings: OnceLock::new(),
+        };
+        (control, layer)
+    }
+
+    /// Apply the stored override, and store later changes in `settings`
+    pub async fn attach(&self, settings: Arc<dyn SettingsRepository>) -> Result<(), StorageError> {
+        if let Some(stored) = settings.get(LOG_LEVEL_SETTING).await? {
+            match Level::from_str(&stored) {
+                Ok(level) => self.apply(Some(level)),
+                Err(_) => tracing::warn!("Ignoring unknown log level setting: {}", stored),
+            }
+        }
+        let _ = self.settings.set(settings);
+        Ok(())
+    }
+
+    /// The override, if the default level is not in use
+    pub fn overridden(&self) -> Option<Level> {
+        self.level.read().ok().and_then(|level| *level)
+    }
+
+    pub fn level(&self) -> Level {
+        self.overridden().unwrap_or(DEFAULT_LOG_LEVEL)
+    }
+
+    /// Log at `level`, or the default for `None`, remembering the choice
+    pub async fn set_level(&self, level: Option<Level>) -> Result<(), StorageError> {
+        self.apply(level);
+        tracing::info!("Log level set to {}", self.level());
+        if let Some(settings) = self.settings.get() {
+            match level {
+                Some(level) => settings.set(LOG_LEVEL_SETTING, level.as_str()).await?,
+                None => settings.delete(LOG_LEVEL_SETTING).await?,
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&self, level: Option<Level>) {
+        let filter = EnvFilter::new(directives(level.unwrap_or(DEFAULT_LOG_LEVEL)));
+        if let Err(e) = self.handle.reload(filter) {
+            tracing::warn!("Cannot change log level: {}", e);
+            return;
+        }
+        if let Ok(mut current) = self.level.write() {
+            *current = level;
+        }
+    }
+}
+
+fn directives(level: Level) -> String {
+    format!("navigator={},{}", level.as_str().to_ascii_lowercase(), QUIET_TARGETS)
+}
+
+/// Recorded logs and the runtime log level, shown on `navigator://logs`
+#[derive(Clone)]
+pub struct Diagnostics {
+    pub buffer: LogBuffer,
+    pub levels: Arc<LogLevelControl>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::testing::InMemorySettingsRepository;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_buffer_keeps_latest_events_with_their_spans() {
+        let buffer = LogBuffer::new(3);
+        let subscriber = tracing_subscriber::registry().with(buffer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("dropped");
+            let navigate = tracing::info_span!("navigate", tab = 7, url = tracing::field::Empty);
+            navigate.record("url", "https://example.com/");
+            let _navigate = navigate.enter();
+            tracing::info_span!("fetch").in_scope(|| tracing::warn!(bytes = 12, "received"));
+        });
+
+        let records = buffer.records();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].spans, "navigate{tab=7 url=https://example.com/}:fetch");
+        assert_eq!(records[0].level, Level::WARN);
+        assert!(records[0].to_string().ends_with("fetch: received bytes=12"), "{}", records[0]);
+        assert!(records[1].message.starts_with("done in"));
+        assert_eq!(records[2].spans, "navigate{tab=7 url=https://example.com/}");
+    }
+
+    #[test]
+    fn test_filter_from_query() {
+        let filter = LogFilter::from_query(Some("level=warn&q=Example%20COM"));
+        assert_eq!(filter.level, Some(Level::WARN));
+        assert_eq!(filter.text.as_deref(), Some("example com"));
+
+        let record = |level, message: &str| LogRecord {
+            sequence: 0,
+            time: Utc::now(),
+            level,
+            target: "navigator".to_string(),
+            spans: String::new(),
+            message: message.to_string(),
+            fields: String::new(),
+        };
+        assert!(filter.matches(&record(Level::ERROR, "failed on example com")));
+        assert!(!filter.matches(&record(Level::INFO, "loaded example com")));
+        assert!(!filter.matches(&record(Level::WARN, "something else")));
+        assert_eq!(LogFilter::from_query(None), LogFilter::default());
+    }
+
+    #[tokio::test]
+    async fn test_level_override_is_stored() {
+        let (control, _layer) = LogLevelControl::new();
+        let settings = Arc::new(InMemorySettingsRepository::new());
+        settings.set(LOG_LEVEL_SETTING, "debug").await.unwrap();
+
+        control.attach(settings.clone()).await.unwrap();
+        assert_eq!(control.overridden(), Some(Level::DEBUG));
+
+        control.set_level(Some(Level::TRACE)).await.unwrap();
+        assert_eq!(settings.get(LOG_LEVEL_SETTING).await.unwrap().as_deref(), Some("TRACE"));
+        control.set_level(None).await.unwrap();
+        assert_eq!(control.level(), DEFAULT_LOG_LEVEL);
+        assert_eq!(settings.get(LOG_LEVEL_SETTING).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,715 @@
+use super::document_limits::{parse_html_limited, ParseLimits};
+use super::integrity::check_integrity;
+use crate::domain::{NetworkError, PageArchiver, Subresource, SubresourceLoader, ValidatedUrl};
+use async_trait::async_trait;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use markup5ever_rcdom::{Handle, NodeData};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Content type of an MHTML archive
+pub const MHTML_CONTENT_TYPE: &str = "multipart/related";
+
+/// Most subresources fetched into one archive
+const MAX_ARCHIVED_SUBRESOURCES: usize = 200;
+/// Longest encoded line, as MIME requires
+const MAX_LINE: usize = 76;
+/// Bytes of a file looked at when guessing whether it is an archive
+const SNIFF_LENGTH: usize = 16 * 1024;
+
+/// Why a file could not be read as an MHTML archive
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MhtmlError {
+    #[error("Not an MHTML archive: {0}")]
+    NotAnArchive(String),
+    #[error("The archive holds no documents")]
+    Empty,
+    #[error("Cannot decode {location}: {reason}")]
+    Encoding { location: String, reason: String },
+}
+
+/// How a page uses a subresource
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubresourceUse {
+    Image,
+    Stylesheet,
+    Script,
+}
+
+/// A subresource a page uses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageSubresource {
+    pub used: SubresourceUse,
+    pub url: ValidatedUrl,
+    /// `integrity` attribute of a script or stylesheet, which its contents
+    /// must match to be used
+    pub integrity: Option<String>,
+}
+
+/// A page and its subresources read from an MHTML archive
+#[derive(Debug, Clone)]
+pub struct MhtmlArchive {
+    root: Subresource,
+    /// Every part, the root included, by URL without fragment
+    resources: HashMap<String, Subresource>,
+}
+
+impl MhtmlArchive {
+    /// Read an archive as written by this or another browser. The root
+    /// document is the part at `Snapshot-Content-Location`, else the first
+    /// HTML part.
+    pub fn parse(data: &[u8]) -> Result<Self, MhtmlError> {
+        let (headers, body) =
+            split_headers(data).ok_or_else(|| MhtmlError::NotAnArchive("no message headers".to_string()))?;
+        let content_type = header(&headers, "content-type").unwrap_or_default();
+        if !is_mhtml_type(content_type) {
+            return Err(MhtmlError::NotAnArchive(format!("content type {:?}", content_type)));
+        }
+        let boundary = parameter(content_type, "boundary")
+            .filter(|boundary| !boundary.is_empty())
+            .ok_or_else(|| MhtmlError::NotAnArchive("no boundary".to_string()))?;
+
+        let mut parts = Vec::new();
+        for part in split_parts(body, &boundary) {
+            let Some((part_headers, content)) = split_headers(part) else {
+                continue;
+            };
+            let Some(url) = header(&part_headers, "content-location").and_then(|url| ValidatedUrl::parse(url).ok())
+            else {
+                continue;
+            };
+            let body = match header(&part_headers, "content-transfer-encoding")
+                .unwrap_or_default()
+                .to_ascii_lowercase()
+                .as_str()
+            {
+                "base64" => {
+                    let encoded: Vec<u8> = content.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+                    base64::engine::general_purpose::STANDARD
+                        .decode(encoded)
+                        .map_err(|e| MhtmlError::Encoding {
+                            location: url.to_string(),
+                            reason: e.to_string(),
+                        })?
+                }
+                "quoted-printable" => decode_quoted_printable(content),
+                _ => content.to_vec(),
+            };
+            let content_type = header(&part_headers, "content-type").unwrap_or("text/plain").to_string();
+            parts.push(Subresource { url, content_type, body });
+        }
+
+        let snapshot = header(&headers, "snapshot-content-location").and_then(|url| ValidatedUrl::parse(url).ok());
+        let root = snapshot
+            .and_then(|snapshot| parts.iter().find(|part| part.url == snapshot))
+            .or_else(|| parts.iter().find(|part| part.content_type.to_ascii_lowercase().starts_with("text/html")))
+            .or_else(|| parts.first())
+            .cloned()
+            .ok_or(MhtmlError::Empty)?;
+        let resources = parts
+            .into_iter()
+            .rev()
+            .map(|part| (archive_key(&part.url), part))
+            .collect();
+        Ok(Self { root, resources })
+    }
+
+    /// The archived page
+    pub fn root(&self) -> &Subresource {
+        &self.root
+    }
+
+    /// The archived copy of `url`, ignoring any fragment
+    pub fn get(&self, url: &ValidatedUrl) -> Option<&Subresource> {
+        self.resources.get(&archive_key(url))
+    }
+
+    /// Parts in the archive, the root included
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+}
+
+/// Serves subresources from the archive instead of the network
+#[async_trait]
+impl SubresourceLoader for MhtmlArchive {
+    async fn load(&self, url: &ValidatedUrl, _page: &ValidatedUrl) -> Result<Subresource, NetworkError> {
+        self.get(url)
+            .cloned()
+            .ok_or_else(|| NetworkError::Request(format!("{} is not in the archive", url)))
+    }
+}
+
+/// Archives pages as MHTML, loading subresources with `loader`
+pub struct MhtmlArchiver {
+    loader: Arc<dyn SubresourceLoader>,
+}
+
+impl MhtmlArchiver {
+    pub fn new(loader: Arc<dyn SubresourceLoader>) -> Self {
+        Self { loader }
+    }
+}
+
+#[async_trait]
+impl PageArchiver for MhtmlArchiver {
+    async fn archive(&self, url: &ValidatedUrl, title: &str, html: &str) -> Vec<u8> {
+        let mut parts = vec![Subresource {
+            url: url.clone(),
+            content_type: "text/html; charset=utf-8".to_string(),
+            body: html.as_bytes().to_vec(),
+        }];
+        let subresources = page_subresources(html, url);
+        if subresources.len() > MAX_ARCHIVED_SUBRESOURCES {
+            tracing::warn!("Archiving only {} of {} subresources", MAX_ARCHIVED_SUBRESOURCES, subresources.len());
+        }
+        for subresource in subresources.into_iter().take(MAX_ARCHIVED_SUBRESOURCES) {
+            match self.loader.load(&subresource.url, url).await {
+                Ok(loaded) => {
+                    let integrity = subresource.integrity.as_deref();
+                    if check_integrity(&subresource.url, integrity, &loaded.body).passed() {
+                        parts.push(loaded);
+                    } else {
+                        let failed = &subresource.url;
+                        tracing::warn!("Leaving {} out of the archive: it failed its integrity check", failed);
+                    }
+                }
+                Err(e) => tracing::warn!("Leaving {} out of the archive: {}", subresource.url, e),
+            }
+        }
+        tracing::info!("Archived {} with {} subresources", url, parts.len() - 1);
+        write_mhtml(title, Utc::now(), &parts)
+    }
+}
+
+/// Serialize `parts` as a multipart/related MIME document; the first part is
+/// the page. Text is quoted-printable, everything else base64.
+pub fn write_mhtml(title: &str, saved_at: DateTime<Utc>, parts: &[Subresource]) -> Vec<u8> {
+    let boundary = format!("----MultipartBoundary--{}----", uuid::Uuid::new_v4().simple());
+    let mut mhtml = String::new();
+    mhtml.push_str("From: <Saved by Navigator>\r\n");
+    if let Some(root) = parts.first() {
+        let _ = write!(mhtml, "Snapshot-Content-Location: {}\r\n", root.url);
+    }
+    let _ = write!(mhtml, "Subject: {}\r\n", encode_header_text(title));
+    let _ = write!(mhtml, "Date: {}\r\n", saved_at.to_rfc2822());
+    mhtml.push_str("MIME-Version: 1.0\r\n");
+    let _ = write!(
+        mhtml,
+        "Content-Type: {};\r\n\ttype=\"text/html\";\r\n\tboundary=\"{}\"\r\n\r\n",
+        MHTML_CONTENT_TYPE, boundary
+    );
+
+    for part in parts {
+        let content_type = part.content_type.replace(['\r', '\n'], "");
+        let text = content_type.to_ascii_lowercase().starts_with("text/");
+        let _ = write!(mhtml, "--{}\r\nContent-Type: {}\r\n", boundary, content_type);
+        let _ = write!(
+            mhtml,
+            "Content-Transfer-Encoding: {}\r\n",
+            if text { "quoted-printable" } else { "base64" }
+        );
+        let _ = write!(mhtml, "Content-Location: {}\r\n\r\n", part.url);
+        if text {
+            mhtml.push_str(&encode_quoted_printable(&part.body));
+        } else {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(&part.body);
+            for line in encoded.as_bytes().chunks(MAX_LINE) {
+                mhtml.push_str(std::str::from_utf8(line).unwrap_or_default());
+                mhtml.push_str("\r\n");
+            }
+        }
+        mhtml.push_str("\r\n");
+    }
+    let _ = write!(mhtml, "--{}--\r\n", boundary);
+    mhtml.into_bytes()
+}
+
+/// Whether a file's contents look like an MHTML archive, whatever its name
+pub fn looks_like_mhtml(data: &[u8]) -> bool {
+    split_headers(&data[..data.len().min(SNIFF_LENGTH)])
+        .is_some_and(|(headers, _)| header(&headers, "content-type").is_some_and(is_mhtml_type))
+}
+
+/// Images, stylesheets and scripts `html` refers to, resolved against its
+/// `<base href>` or else `page_url`, once each. Only web URLs are kept.
+pub fn page_subresources(html: &str, page_url: &ValidatedUrl) -> Vec<PageSubresource> {
+    type Found = (SubresourceUse, String, Option<String>);
+    fn walk(handle: &Handle, base: &mut Option<String>, found: &mut Vec<Found>) {
+        if let NodeData::Element { name, attrs, .. } = &handle.data {
+            let attrs = attrs.borrow();
+            let attribute = |wanted: &str| {
+                attrs
+                    .iter()
+                    .find(|attr| &*attr.name.local == wanted)
+                    .map(|attr| attr.value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+            };
+            let rel_has = |wanted: &str| {
+                attribute("rel").is_some_and(|rel| {
+                    rel.split_ascii_whitespace().any(|token| token.eq_ignore_ascii_case(wanted))
+                })
+            };
+            let used = match &*name.local {
+                "base" if base.is_none() => {
+                    *base = attribute("href");
+                    None
+                }
+                "img" => attribute("src").map(|src| (SubresourceUse::Image, src)),
+                "script" => attribute("src").map(|src| (SubresourceUse::Script, src)),
+                "link" if rel_has("stylesheet") => attribute("href").map(|href| (SubresourceUse::Stylesheet, href)),
+                "link" if rel_has("icon") => attribute("href").map(|href| (SubresourceUse::Image, href)),
+                _ => None,
+            };
+            let integrity = used
+                .as_ref()
+                .filter(|(used, _)| *used != SubresourceUse::Image)
+                .and_then(|_| attribute("integrity"));
+            found.extend(used.map(|(used, href)| (used, href, integrity)));
+        }
+        for child in handle.children.borrow().iter() {
+            walk(child, base, found);
+        }
+    }
+
+    let (dom, _) = parse_html_limited(html, None, &ParseLimits::default());
+    let (mut base, mut found) = (None, Vec::new());
+    walk(&dom.document, &mut base, &mut found);
+    let base = base
+        .and_then(|base| page_url.join(&base).ok())
+        .unwrap_or_else(|| page_url.clone());
+
+    let mut subresources: Vec<PageSubresource> = Vec::new();
+    for (used, href, integrity) in found {
+        let Ok(url) = base.join(&href) else {
+            continue;
+        };
+        let url = url.normalized();
+        if matches!(url.scheme(), "http" | "https") && !subresources.iter().any(|seen| seen.url == url) {
+            subresources.push(PageSubresource { used, url, integrity });
+        }
+    }
+    subresources
+}
+
+/// `html` with the stylesheets it links, as `loader` finds them, added as
+/// `<style>` elements ahead of the document's own styles. Stylesheets that
+/// fail their `integrity` check are left out.
+pub async fn inline_stylesheets(html: &str, page_url: &ValidatedUrl, loader: &dyn SubresourceLoader) -> String {
+    let mut styles = String::new();
+    for PageSubresource { used, url, integrity } in page_subresources(html, page_url) {
+        if used != SubresourceUse::Stylesheet {
+            continue;
+        }
+        match loader.load(&url, page_url).await {
+            Ok(stylesheet) if !check_integrity(&url, integrity.as_deref(), &stylesheet.body).passed() => {
+                tracing::warn!("Stylesheet {} not applied: it failed its integrity check", url)
+            }
+            Ok(stylesheet) => {
+                let css = String::from_utf8_lossy(&stylesheet.body);
+                // `\/` is a plain slash to CSS, so the element cannot be closed early
+                let _ = write!(styles, "<style>{}</style>", css.replace("</", "<\\/"));
+            }
+            Err(e) => tracing::debug!("Stylesheet {} not applied: {}", url, e),
+        }
+    }
+    if styles.is_empty() {
+        return html.to_string();
+    }
+    // The parser moves styles found ahead of the document into its head
+    format!("{}{}", styles, html)
+}
+
+fn is_mhtml_type(content_type: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case(MHTML_CONTENT_TYPE))
+}
+
+fn archive_key(url: &ValidatedUrl) -> String {
+    url.normalized().as_str().to_string()
+}
+
+/// Header names and values in order
+type Headers = Vec<(String, String)>;
+
+/// Headers, unfolded, and the body after the blank line ending them
+fn split_headers(data: &[u8]) -> Option<(Headers, &[u8])> {
+    let (head, body) = if data.starts_with(b"\r\n") {
+        (&data[..0], &data[2..])
+    } else if data.starts_with(b"\n") {
+        (&data[..0], &data[1..])
+    } else {
+        let crlf = data.windows(4).position(|w| w == b"\r\n\r\n").map(|at| (at, at + 4));
+        let lf = data.windows(2).position(|w| w == b"\n\n").map(|at| (at, at + 2));
+        let (end, body_start) = match (crlf, lf) {
+            (Some(crlf), Some(lf)) => crlf.min(lf),
+            (found, None) | (None, found) => found?,
+        };
+        (&data[..end], &data[body_start..])
+    };
+
+    let mut headers: Headers = Vec::new();
+    for line in String::from_utf8_lossy(head).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        } else if !line.trim().is_empty() {
+            return None;
+        }
+    }
+    Some((headers, body))
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// A parameter of a header value such as `multipart/related; boundary="x"`
+fn parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// The parts between `--boundary` lines, without the line break ahead of
+/// each delimiter
+fn split_parts<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let closing = format!("{}--", delimiter);
+    let mut parts = Vec::new();
+    let mut start = None;
+    let mut offset = 0;
+    for line in body.split_inclusive(|b| *b == b'\n') {
+        let end = line
+            .iter()
+            .rposition(|b| !b" \t\r\n".contains(b))
+            .map_or(0, |last| last + 1);
+        let trimmed = &line[..end];
+        let is_closing = trimmed == closing.as_bytes();
+        if is_closing || trimmed == delimiter.as_bytes() {
+            if let Some(start) = start {
+                let part: &[u8] = &body[start..offset];
+                let part = part.strip_suffix(b"\n").unwrap_or(part);
+                parts.push(part.strip_suffix(b"\r").unwrap_or(part));
+            }
+            if is_closing {
+                return parts;
+            }
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    // An archive cut off before its closing delimiter keeps its last part
+    if let Some(start) = start {
+        parts.push(&body[start..]);
+    }
+    parts
+}
+
+/// Quoted-printable with line breaks as CRLF; carriage returns not ahead of
+/// a line feed are escaped, so decoding gives back the same bytes
+fn encode_quoted_printable(body: &[u8]) -> String {
+    let mut encoded = String::with_capacity(body.len() + body.len() / 8);
+    let mut line = 0;
+    for (i, &byte) in body.iter().enumerate() {
+        if byte == b'\n' {
+            encoded.push_str("\r\n");
+            line = 0;
+            continue;
+        }
+        let at_line_end = matches!(body.get(i + 1), None | Some(b'\n'));
+        let literal = (byte.is_ascii_graphic() && byte != b'=') || (matches!(byte, b' ' | b'\t') && !at_line_end);
+        let width = if literal { 1 } else { 3 };
+        // Leave room for the "=" of a soft line break
+        if line + width > MAX_LINE - 1 {
+            encoded.push_str("=\r\n");
+            line = 0;
+        }
+        if literal {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "={:02X}", byte);
+        }
+        line += width;
+    }
+    encoded
+}
+
+/// Decode quoted-printable, taking line breaks as line feeds
+fn decode_quoted_printable(encoded: &[u8]) -> Vec<u8> {
+    let hex = |b: u8| (b as char).to_digit(16).map(|digit| digit as u8);
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        match &encoded[i..] {
+            [b'=', b'\r', b'\n', ..] => i += 3,
+            [b'=', b'\n', ..] => i += 2,
+            [b'=', high, low, ..] if hex(*high).is_some() && hex(*low).is_some() => {
+                decoded.push(hex(*high).unwrap_or(0) << 4 | hex(*low).unwrap_or(0));
+                i += 3;
+            }
+            [b'\r', b'\n', ..] => {
+                decoded.push(b'\n');
+                i += 2;
+            }
+            [byte, ..] => {
+                decoded.push(*byte);
+                i += 1;
+            }
+            [] => break,
+        }
+    }
+    decoded
+}
+
+/// Header text, RFC 2047 encoded unless it is printable ASCII
+fn encode_header_text(text: &str) -> String {
+    let text = text.replace(['\r', '\n'], " ");
+    if text.bytes().all(|b| b == b' ' || b.is_ascii_graphic()) {
+        return text;
+    }
+    format!("=?utf-8?B?{}?=", base64::engine::general_purpose::STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::{FileAccess, FileAccessPolicy, SecureNetworkClient, ServoRenderer};
+    use crate::domain::RenderingEngine;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn url(text: &str) -> ValidatedUrl {
+        ValidatedUrl::parse(text).unwrap()
+    }
+
+    #[test]
+    fn test_quoted_printable_round_trip() {
+        let samples: [&[u8]; 5] = [
+            b"plain text\nwith lines\r\nand CRLF",
+            "caf\u{e9} = 100% \u{2713}".as_bytes(),
+            b"trailing space \ntrailing tab\t",
+            &[0, 13, 255, b'=', b'\r'],
+            &[b'x'; 300],
+        ];
+        for sample in samples {
+            let encoded = encode_quoted_printable(sample);
+            assert!(encoded.lines().all(|line| line.len() <= MAX_LINE), "{}", encoded);
+            assert!(encoded.bytes().all(|b| b.is_ascii()));
+            assert_eq!(decode_quoted_printable(encoded.as_bytes()), sample);
+        }
+        assert_eq!(encode_quoted_printable(b"a b \n"), "a b=20\r\n");
+    }
+
+    #[test]
+    fn test_parse_archive_from_another_browser() {
+        // Folded headers, an unquoted boundary, soft line breaks and
+        // wrapped base64, as other browsers write them
+        let archive = "From: <Saved by Blink>\r\n\
+            Snapshot-Content-Location: https://example.com/page\r\n\
+            Subject: Example\r\n\
+            MIME-Version: 1.0\r\n\
+            Content-Type: multipart/related;\r\n\ttype=\"text/html\";\r\n\tboundary=----Boundary--x----\r\n\
+            \r\n\
+            preamble\r\n\
+            ------Boundary--x----\r\n\
+            Content-Type: text/css\r\n\
+            Content-Transfer-Encoding: 8bit\r\n\
+            Content-Location: https://example.com/style.css\r\n\
+            \r\n\
+            p { color: red }\r\n\
+            ------Boundary--x----\r\n\
+            Content-Type: text/html\r\n\
+            Content-ID: <frame-1@mhtml.blink>\r\n\
+            Content-Transfer-Encoding: quoted-printable\r\n\
+            Content-Location: https://example.com/page\r\n\
+            \r\n\
+            <p class=3D\"a\">caf=C3=A9 and a very long line that was wrapped by=\r\n the writer</p>\r\n\
+            ------Boundary--x----\r\n\
+            Content-Type: image/png\r\n\
+            Content-Transfer-Encoding: base64\r\n\
+            Content-Location: https://example.com/logo.png#top\r\n\
+            \r\n\
+            iVBO\r\nRw0K\r\n\
+            ------Boundary--x------\r\n";
+
+        let archive = MhtmlArchive::parse(archive.as_bytes()).unwrap();
+        assert_eq!(archive.len(), 3);
+        assert_eq!(archive.root().url, url("https://example.com/page"));
+        assert_eq!(
+            String::from_utf8(archive.root().body.clone()).unwrap(),
+            "<p class=\"a\">caf\u{e9} and a very long line that was wrapped by the writer</p>"
+        );
+        assert_eq!(archive.get(&url("https://example.com/style.css")).unwrap().body, b"p { color: red }");
+        let logo = archive.get(&url("https://example.com/logo.png")).unwrap();
+        assert_eq!((logo.content_type.as_str(), &logo.body[..]), ("image/png", &b"\x89PNG\r\n"[..]));
+
+        assert!(matches!(MhtmlArchive::parse(b"<html></html>"), Err(MhtmlError::NotAnArchive(_))));
+        assert!(!looks_like_mhtml(b"<!DOCTYPE html>\n\n<html>"));
+    }
+
+    #[test]
+    fn test_page_subresources() {
+        let page = url("https://example.com/docs/page.html");
+        let html = r#"<html><head><base href="/static/"><link rel="stylesheet" href="site.css">
+            <link rel="icon" href="//cdn.example.net/favicon.ico"><link rel="next" href="two.html">
+            <script src="app.js" integrity="sha384-abc"></script></head>
+            <body><img src="logo.png#x"><img src="logo.png"><img src="data:image/png;base64,AA=="></body></html>"#;
+
+        let found: Vec<(SubresourceUse, ValidatedUrl)> = page_subresources(html, &page)
+            .into_iter()
+            .map(|subresource| (subresource.used, subresource.url))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (SubresourceUse::Stylesheet, url("https://example.com/static/site.css")),
+                (SubresourceUse::Image, url("https://cdn.example.net/favicon.ico")),
+                (SubresourceUse::Script, url("https://example.com/static/app.js")),
+                (SubresourceUse::Image, url("https://example.com/static/logo.png")),
+            ]
+        );
+        let integrity: Vec<Option<String>> =
+            page_subresources(html, &page).into_iter().map(|subresource| subresource.integrity).collect();
+        assert_eq!(integrity, [None, None, Some("sha384-abc".to_string()), None]);
+    }
+
+    /// Serve `files` by path until the task is aborted
+    async fn serve(files: Vec<(&'static str, &'static str, Vec<u8>)>) -> (u16, tokio::task::JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let response = match files.iter().find(|(file, _, _)| *file == path) {
+                    Some((_, content_type, body)) => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            content_type,
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(body);
+                        response
+                    }
+                    None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec(),
+                };
+                stream.write_all(&response).await.unwrap();
+            }
+        });
+        (port, server)
+    }
+
+    #[tokio::test]
+    async fn test_archived_site_reopens_offline() {
+        let page = r#"<html><head><title>Fixture site</title><link rel="stylesheet" href="style.css"></head>
+            <body><p>Visible text</p><p class="hidden">Hidden by the stylesheet</p>
+            <img src="images/logo.png"><img src="missing.png"><a href="about.html">About</a></body></html>"#;
+        let logo: Vec<u8> = (0..=255).collect();
+        let (port, server) = serve(vec![
+            ("/index.html", "text/html", page.as_bytes().to_vec()),
+            ("/style.css", "text/css", b".hidden { display: none }\r\n".to_vec()),
+            ("/images/logo.png", "image/png", logo.clone()),
+        ])
+        .await;
+        let site = |path: &str| url(&format!("http://127.0.0.1:{}/{}", port, path));
+
+        let archiver = MhtmlArchiver::new(Arc::new(SecureNetworkClient::new().unwrap()));
+        let mhtml = archiver.archive(&site("index.html"), "Fixture site", page).await;
+        server.abort();
+
+        let archive = MhtmlArchive::parse(&mhtml).unwrap();
+        assert_eq!(archive.len(), 3, "the missing image is left out");
+        assert_eq!(archive.root().body, page.as_bytes());
+        assert_eq!(archive.get(&site("images/logo.png")).unwrap().body, logo);
+        assert_eq!(archive.get(&site("style.css")).unwrap().body, b".hidden { display: none }\r\n");
+
+        let path = std::env::temp_dir().join(format!("navigator-{}.mhtml", uuid::Uuid::new_v4()));
+        std::fs::write(&path, &mhtml).unwrap();
+        let file = ValidatedUrl::parse(url::Url::from_file_path(&path).unwrap().as_str()).unwrap();
+        let renderer = ServoRenderer::new().with_file_access(Arc::new(FileAccess::new(FileAccessPolicy {
+            enabled: true,
+            allowed_roots: vec![std::env::temp_dir()],
+        })));
+        renderer.load_url(&file).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(renderer.get_title().await.unwrap(), "Fixture site");
+        let text = renderer.render_to_text();
+        assert!(text.contains("Visible text"), "{}", text);
+        assert!(!text.contains("Hidden by the stylesheet"), "{}", text);
+        // Links lead back to the site, and archived resources open offline
+        assert_eq!(renderer.resolve_link("about.html"), Some(site("about.html")));
+        renderer.load_url(&site("images/logo.png")).await.unwrap();
+        assert!(renderer.render_to_text().contains("[image: logo.png]"));
+        assert!(renderer.load_url(&site("about.html")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stylesheets_failing_integrity_are_left_out() {
+        let page = r#"<html><head>
+            <link rel="stylesheet" href="good.css" integrity="sha256-yyvRkyz9ihaWct4rozMpe73huHhzbSkAerIUMnhX3EQ=">
+            <link rel="stylesheet" href="bad.css" integrity="sha256-yyvRkyz9ihaWct4rozMpe73huHhzbSkAerIUMnhX3EQ=">
+            <link rel="stylesheet" href="plain.css"></head><body><p class="hidden">Hidden</p></body></html>"#;
+        let (port, server) = serve(vec![
+            ("/good.css", "text/css", b".hidden { display: none }".to_vec()),
+            ("/bad.css", "text/css", b"p { color: red }".to_vec()),
+            ("/plain.css", "text/css", b"p { color: blue }".to_vec()),
+        ])
+        .await;
+        let site = |path: &str| url(&format!("http://127.0.0.1:{}/{}", port, path));
+
+        let archiver = MhtmlArchiver::new(Arc::new(SecureNetworkClient::new().unwrap()));
+        let mhtml = archiver.archive(&site("index.html"), "Integrity", page).await;
+        server.abort();
+        let archive = MhtmlArchive::parse(&mhtml).unwrap();
+        assert!(archive.get(&site("good.css")).is_some());
+        assert!(archive.get(&site("bad.css")).is_none());
+        assert!(archive.get(&site("plain.css")).is_some());
+
+        // A stylesheet that changed after archiving is not applied either
+        let tampered = write_mhtml(
+            "Integrity",
+            Utc::now(),
+            &[
+                Subresource {
+                    url: site("index.html"),
+                    content_type: "text/html".to_string(),
+                    body: page.as_bytes().to_vec(),
+                },
+                Subresource {
+                    url: site("good.css"),
+                    content_type: "text/css".to_string(),
+                    body: b".hidden { display: block }".to_vec(),
+                },
+            ],
+        );
+        let tampered = MhtmlArchive::parse(&tampered).unwrap();
+        let html = inline_stylesheets(page, &site("index.html"), &tampered).await;
+        assert!(!html.contains("<style>"), "{}", html);
+        let html = inline_stylesheets(page, &site("index.html"), &archive).await;
+        assert!(html.starts_with("<style>.hidden { display: none }</style><style>p { color: blue }</style>"));
+    }
+}
@@ -1,12 +1,78 @@
 // Infrastructure Layer - External dependencies and adapters
 // Implements domain interfaces using concrete technologies
 
+pub mod accessibility;
+pub mod alt_svc;
+pub mod certificates;
+pub mod cookies;
+pub mod crash;
+pub mod css;
+pub mod data_url;
 pub mod database;
+pub mod document_limits;
+pub mod dom;
+pub mod downloads;
+pub mod external_launcher;
+pub mod import;
+pub mod integrity;
+pub mod interceptors;
+pub mod layout;
+pub mod layout_snapshot;
+pub mod logging;
+pub mod mhtml;
 pub mod network;
+pub mod opensearch;
+pub mod page_scripts;
+pub mod pdf;
+pub mod profile_crypto;
+pub mod profile_dir;
 pub mod rendering;
+pub mod safe_file;
+pub mod script_fetch;
 pub mod security;
+pub mod site_metadata;
+pub mod startup;
+pub mod suggestions;
+pub mod timers;
+pub mod updates;
+pub mod user_scripts;
+pub mod user_styles;
+pub mod websocket;
 
+pub use accessibility::*;
+pub use alt_svc::*;
+pub use certificates::*;
+pub use cookies::*;
+pub use crash::*;
+pub use css::*;
+pub use data_url::*;
 pub use database::*;
+pub use document_limits::*;
+pub use dom::*;
+pub use downloads::*;
+pub use external_launcher::*;
+pub use import::*;
+pub use integrity::*;
+pub use interceptors::*;
+pub use layout::*;
+pub use layout_snapshot::*;
+pub use logging::*;
+pub use mhtml::*;
 pub use network::*;
+pub use opensearch::*;
+pub use page_scripts::*;
+pub use pdf::*;
+pub use profile_crypto::*;
+pub use profile_dir::*;
 pub use rendering::*;
+pub use safe_file::*;
+pub use script_fetch::*;
 pub use security::*;
+pub use site_metadata::*;
+pub use startup::*;
+pub use suggestions::*;
+pub use timers::*;
+pub use updates::*;
+pub use user_scripts::*;
+pub use user_styles::*;
+pub use websocket::*;
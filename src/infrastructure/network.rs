@@ -1,26 +1,766 @@
-use crate::domain::{Certificate, NetworkService, SecurityContext, ValidatedUrl};
-use anyhow::{anyhow, Context, Result};
+use super::alt_svc::AltSvcCache;
+use super::certificates::{fingerprint, inspect_certificate, tls_config, CertificateLog};
+use super::cookies::CookieJar;
+use super::interceptors::InterceptorChain;
+use super::security::PortPolicy;
+use super::websocket::{self, WebSocketHandle, WEBSOCKET_KEEPALIVE};
+use crate::domain::{
+    Certificate, CertificateDetails, CertificateImportError, CookieBlocking, CustomCa, FetchRequest, HttpVersion,
+    NetworkError, NetworkService, RequestInterceptor, ResourceKind, SecurityContext, SiteOrigin,
+    SiteSettingsRepository, Subresource, SubresourceLoader, TlsInspector, TlsSession, TrustStore, ValidatedUrl,
+    WebSocketConnection,
+};
 use async_trait::async_trait;
-use reqwest::Client;
+use ring::rand::{SecureRandom, SystemRandom};
+use reqwest::header::{ALT_SVC, COOKIE, SET_COOKIE, USER_AGENT};
+use reqwest::{Client, ClientBuilder, Method, RequestBuilder, Version};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName};
+use std::error::Error as StdError;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::Duration;
+
+/// Redirects followed for one fetch before giving up
+const MAX_REDIRECTS: u32 = 10;
+/// Largest image, stylesheet or script loaded for a page
+const MAX_SUBRESOURCE_BODY: usize = 16 * 1024 * 1024;
+/// Times a GET or HEAD that failed in passing is tried again, by default
+pub const DEFAULT_RETRIES: u32 = 2;
+/// Wait before the first retry, doubling for each after it, plus up to
+/// half again at random so clients that failed together spread out
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+/// Fetches in a row that could not reach their server before the
+/// connection is taken to be lost
+pub const CONNECTIVITY_FAILURES: u32 = 3;
+/// Longest a connection made only to look at its TLS session may take
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(15);
+
+impl From<reqwest::Error> for NetworkError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            return Self::Timeout;
+        }
+        if let Some(status) = error.status() {
+            return Self::Status(status.as_u16());
+        }
+
+        let detail = describe(&error);
+        if error.is_connect() {
+            if let Some(tls) = find_cause::<rustls::Error>(&error) {
+                if matches!(tls, rustls::Error::InvalidCertificate(rustls::CertificateError::Revoked)) {
+                    let host = error.url().and_then(|url| url.host_str()).unwrap_or("the host");
+                    return Self::CertificateRevoked(host.to_string());
+                }
+                return Self::Tls(tls.to_string());
+            }
+            if detail.contains("dns error") || detail.contains("failed to lookup address") {
+                let host = error.url().and_then(|url| url.host_str()).unwrap_or("the host");
+                return Self::Dns(host.to_string());
+            }
+            return Self::Connection(detail);
+        }
+        Self::Request(detail)
+    }
+}
+
+/// An error and its causes on one line, for the log
+fn describe(error: &dyn StdError) -> String {
+    let mut detail = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        detail.push_str(": ");
+        detail.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    detail
+}
+
+/// First cause of type `E`, looking inside I/O errors, which hide the error
+/// they wrap from `source()`
+fn find_cause<'a, E: StdError + 'static>(error: &'a (dyn StdError + 'static)) -> Option<&'a E> {
+    let mut current = Some(error);
+    while let Some(cause) = current {
+        if let Some(found) = cause.downcast_ref::<E>() {
+            return Some(found);
+        }
+        // I/O errors may wrap one another, each hiding the error it wraps
+        current = match cause.downcast_ref::<std::io::Error>().and_then(|io| io.get_ref()) {
+            Some(inner) => Some(inner as &(dyn StdError + 'static)),
+            None => cause.source(),
+        };
+    }
+    None
+}
+
+/// A response read through the interceptor chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchResponse {
+    /// Where the body came from, after redirects
+    pub url: ValidatedUrl,
+    /// URLs redirected through to reach `url`, the one requested first
+    pub redirected_from: Vec<ValidatedUrl>,
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// Every response header, names lower case
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Protocol the final response came over
+    pub version: HttpVersion,
+}
+
+impl From<Version> for HttpVersion {
+    fn from(version: Version) -> Self {
+        match version {
+            Version::HTTP_09 => HttpVersion::Http09,
+            Version::HTTP_10 => HttpVersion::Http10,
+            Version::HTTP_2 => HttpVersion::Http2,
+            Version::HTTP_3 => HttpVersion::Http3,
+            _ => HttpVersion::Http11,
+        }
+    }
+}
+
+/// How a fetch is made, besides the request itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchOptions {
+    /// Stop reading the body after this many bytes, returning what arrived
+    pub max_body: Option<usize>,
+    /// Sent in place of the browser's `User-Agent`, such as a site's own
+    pub user_agent: Option<String>,
+    /// Times a GET or HEAD is tried again after a dropped connection, a DNS
+    /// server asking to try again, or a 502, 503 or 504; 0 for never
+    pub retries: u32,
+}
+
+impl FetchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_body(mut self, max_body: Option<usize>) -> Self {
+        self.max_body = max_body;
+        self
+    }
+
+    pub fn with_user_agent(mut self, user_agent: Option<&str>) -> Self {
+        self.user_agent = user_agent.map(str::to_string);
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            max_body: None,
+            user_agent: None,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+}
 
 /// HTTP client with security features
+/// Whether the browser stays off the network, shared by the network client
+/// and every tab, with a count of fetches in a row that could not reach
+/// their server
+#[derive(Debug, Clone, Default)]
+pub struct OfflineMode {
+    enabled: Arc<AtomicBool>,
+    failures: Arc<AtomicU32>,
+}
+
+impl OfflineMode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Takes effect from the next fetch; the failure count starts over
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Note how a fetch that went to the network ended. Only failures to
+    /// resolve or connect count against the connection.
+    pub fn record(&self, error: Option<&NetworkError>) {
+        match error {
+            Some(NetworkError::Dns(_) | NetworkError::Connection(_)) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(_) => {}
+            None => self.failures.store(0, Ordering::Relaxed),
+        }
+    }
+
+    /// Whether enough fetches in a row failed to suggest going offline
+    pub fn connectivity_lost(&self) -> bool {
+        !self.enabled() && self.failures.load(Ordering::Relaxed) >= CONNECTIVITY_FAILURES
+    }
+}
+
 pub struct SecureNetworkClient {
-    client: Client,
+    /// One client for documents, subresources and scripts' fetches alike,
+    /// so they share its pool of connections; replaced when the trusted
+    /// roots change
+    client: RwLock<Client>,
+    /// Certificate authorities trusted besides the built-in ones
+    roots: Vec<CertificateDer<'static>>,
+    /// Those the user added, as the trust store was last told
+    custom_roots: RwLock<Vec<CertificateDer<'static>>>,
+    /// What each host's certificate and its stapled OCSP response said
+    certificates: Arc<CertificateLog>,
+    /// Origins that said they serve HTTP/3
+    alt_svc: AltSvcCache,
+    /// Sent instead of the browser's own `User-Agent`, if set
+    user_agent: RwLock<Option<String>>,
+    interceptors: InterceptorChain,
+    ports: Arc<PortPolicy>,
+    /// Where cookies are kept; without one requests carry none
+    cookies: Option<Arc<CookieJar>>,
+    /// Where private tabs keep theirs, from the first request one makes
+    /// until the last private tab closes
+    private_cookies: Mutex<Option<Arc<CookieJar>>>,
+    cookie_blocking: RwLock<CookieBlocking>,
+    /// Sites' own cookie policies, overriding `cookie_blocking`
+    site_settings: Option<Arc<dyn SiteSettingsRepository>>,
+    websocket_keepalive: Duration,
+    /// Refuses every request while on
+    offline: OfflineMode,
 }
 
 impl SecureNetworkClient {
-    pub fn new() -> Result<Self> {
-        // Configure client with security best practices
-        let client = Client::builder()
-            .use_rustls_tls() // Use Rust's memory-safe TLS implementation
-            .https_only(false) // Allow HTTP but we'll enforce HTTPS at higher level
-            .redirect(reqwest::redirect::Policy::limited(10))
-            .timeout(std::time::Duration::from_secs(30))
-            .user_agent(format!("Navigator/{}", env!("CARGO_PKG_VERSION")))
-            .build()
-            .context("Failed to create HTTP client")?;
+    pub fn new() -> Result<Self, NetworkError> {
+        let certificates = Arc::new(CertificateLog::new());
+        Ok(Self {
+            client: RwLock::new(client_builder(&[], certificates.clone())?.build()?),
+            roots: Vec::new(),
+            custom_roots: RwLock::new(Vec::new()),
+            certificates,
+            alt_svc: AltSvcCache::new(),
+            user_agent: RwLock::new(None),
+            interceptors: InterceptorChain::new(),
+            ports: Arc::new(PortPolicy::new()),
+            cookies: None,
+            private_cookies: Mutex::new(None),
+            cookie_blocking: RwLock::new(CookieBlocking::default()),
+            site_settings: None,
+            websocket_keepalive: WEBSOCKET_KEEPALIVE,
+            offline: OfflineMode::new(),
+        })
+    }
+
+    /// Also trust the certificate authority in `pem`, such as a company's own
+    pub fn with_root_certificate(mut self, pem: &[u8]) -> Result<Self, NetworkError> {
+        let root = CertificateDer::from_pem_slice(pem).map_err(|e| NetworkError::Tls(e.to_string()))?;
+        self.roots.push(root);
+        self.rebuild_client()?;
+        Ok(self)
+    }
+
+    /// Replace the client with one trusting the roots now configured.
+    /// Connections already open are dropped with the old client.
+    fn rebuild_client(&self) -> Result<(), NetworkError> {
+        let client = client_builder(&self.trusted_roots(), self.certificates.clone())?.build()?;
+        *self.client.write().unwrap_or_else(PoisonError::into_inner) = client;
+        Ok(())
+    }
+
+    /// Roots trusted besides the built-in ones
+    fn trusted_roots(&self) -> Vec<CertificateDer<'static>> {
+        let mut roots = self.roots.clone();
+        roots.extend(self.custom_roots.read().map(|custom| custom.clone()).unwrap_or_default());
+        roots
+    }
+
+    /// Refuse fetches to ports this policy restricts, including redirects
+    pub fn with_port_policy(mut self, ports: Arc<PortPolicy>) -> Self {
+        self.ports = ports;
+        self
+    }
+
+    /// Send `user_agent` with later requests; `None` or empty restores the
+    /// browser's own
+    pub fn set_user_agent(&self, user_agent: Option<&str>) {
+        if let Ok(mut current) = self.user_agent.write() {
+            *current = user_agent.filter(|agent| !agent.is_empty()).map(str::to_string);
+        }
+    }
+
+    /// Send and keep cookies in `cookies`, as the cookie blocking allows
+    pub fn with_cookie_jar(mut self, cookies: Arc<CookieJar>) -> Self {
+        self.cookies = Some(cookies);
+        self
+    }
+
+    /// Forget the cookies private tabs were given; the next private tab
+    /// starts with none
+    pub fn end_private_session(&self) {
+        *self.private_cookies.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    /// The jar `request` takes its cookies from: a private tab's own, or
+    /// the shared one
+    fn cookie_jar(&self, request: &FetchRequest) -> Option<Arc<CookieJar>> {
+        let shared = self.cookies.as_ref()?;
+        if !request.private {
+            return Some(shared.clone());
+        }
+        let mut private = self.private_cookies.lock().unwrap_or_else(PoisonError::into_inner);
+        Some(private.get_or_insert_with(|| Arc::new(CookieJar::new())).clone())
+    }
+
+    /// Let each page's site override the cookie blocking
+    pub fn with_site_settings(mut self, site_settings: Arc<dyn SiteSettingsRepository>) -> Self {
+        self.site_settings = Some(site_settings);
+        self
+    }
+
+    /// Ping open WebSockets this often
+    pub fn with_websocket_keepalive(mut self, keepalive: Duration) -> Self {
+        self.websocket_keepalive = keepalive;
+        self
+    }
+
+    /// Refuse requests while `offline` is enabled, and count against it
+    /// those that cannot reach their server
+    pub fn with_offline_mode(mut self, offline: OfflineMode) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn offline_mode(&self) -> &OfflineMode {
+        &self.offline
+    }
+
+    /// The certificate `host` presented when last connected to, as checked
+    pub fn certificate(&self, host: &str) -> Option<Certificate> {
+        self.certificates.get(host.trim_start_matches('[').trim_end_matches(']'))
+    }
+
+    /// Which cookies later requests carry and keep
+    pub fn set_cookie_blocking(&self, blocking: CookieBlocking) {
+        if let Ok(mut current) = self.cookie_blocking.write() {
+            *current = blocking;
+        }
+    }
+
+    /// Why `request` neither carries nor keeps cookies, or None if it
+    /// does. The page's own site decides, over the global blocking.
+    async fn cookies_blocked(&self, request: &FetchRequest) -> Option<&'static str> {
+        let page = request.top_level.as_ref().unwrap_or(&request.url);
+        let mut blocking = self.cookie_blocking.read().map(|blocking| *blocking).unwrap_or_default();
+        if let (Some(site_settings), Some(origin)) = (&self.site_settings, SiteOrigin::of(page)) {
+            let site = site_settings
+                .find(&origin)
+                .await
+                .inspect_err(|e| tracing::warn!("Failed to read the settings of {}: {}", origin, e))
+                .ok()
+                .flatten();
+            blocking = blocking.for_site(site.and_then(|site| site.cookies));
+        }
+        match blocking {
+            CookieBlocking::AllowAll => None,
+            CookieBlocking::BlockThirdParty if request.is_third_party() => Some("third-party"),
+            CookieBlocking::BlockThirdParty => None,
+            CookieBlocking::BlockAll => Some("all blocked"),
+        }
+    }
+
+    /// Add an interceptor after those already registered
+    pub fn with_interceptor(self, interceptor: Arc<dyn RequestInterceptor>) -> Self {
+        self.add_interceptor(interceptor);
+        self
+    }
+
+    pub fn add_interceptor(&self, interceptor: Arc<dyn RequestInterceptor>) {
+        self.interceptors.add(interceptor);
+    }
+
+    pub fn interceptors(&self) -> &InterceptorChain {
+        &self.interceptors
+    }
+
+    /// A GET for `url` sent as `user_agent`, if given, or else as the
+    /// agent set for every request
+    fn get(&self, url: &ValidatedUrl, user_agent: Option<&str>) -> RequestBuilder {
+        self.build(Method::GET, url, user_agent)
+    }
+
+    fn build(&self, method: Method, url: &ValidatedUrl, user_agent: Option<&str>) -> RequestBuilder {
+        let client = self.client.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let request = client.request(method, url.as_str());
+        let agent = user_agent
+            .map(str::to_string)
+            .or_else(|| self.user_agent.read().ok().and_then(|agent| agent.clone()));
+        match agent {
+            Some(agent) => request.header(USER_AGENT, agent),
+            None => request,
+        }
+    }
+
+    /// Fetch a URL, following redirects, with every hop passed through the
+    /// interceptors. Error statuses are returned as responses.
+    pub async fn fetch_resource(
+        &self,
+        url: &ValidatedUrl,
+        kind: ResourceKind,
+    ) -> Result<FetchResponse, NetworkError> {
+        self.fetch_resource_limited(url, kind, None).await
+    }
+
+    /// Like [`fetch_resource`](Self::fetch_resource), but stop reading the
+    /// body after `max_body` bytes, if given, returning what arrived
+    pub async fn fetch_resource_limited(
+        &self,
+        url: &ValidatedUrl,
+        kind: ResourceKind,
+        max_body: Option<usize>,
+    ) -> Result<FetchResponse, NetworkError> {
+        self.fetch_resource_as(url, kind, max_body, None).await
+    }
+
+    /// Like [`fetch_resource_limited`](Self::fetch_resource_limited), but
+    /// every hop is sent as `user_agent` if given, such as a site's own
+    pub async fn fetch_resource_as(
+        &self,
+        url: &ValidatedUrl,
+        kind: ResourceKind,
+        max_body: Option<usize>,
+        user_agent: Option<&str>,
+    ) -> Result<FetchResponse, NetworkError> {
+        self.fetch_request(FetchRequest::new(url.clone(), kind), max_body, user_agent)
+            .await
+    }
+
+    /// Like [`fetch_resource_as`](Self::fetch_resource_as), for a request
+    /// that may come from a page, deciding whose cookies go with it
+    pub async fn fetch_request(
+        &self,
+        request: FetchRequest,
+        max_body: Option<usize>,
+        user_agent: Option<&str>,
+    ) -> Result<FetchResponse, NetworkError> {
+        let options = FetchOptions::new().with_max_body(max_body).with_user_agent(user_agent);
+        self.fetch_with_options(request, &options).await
+    }
+
+    /// Like [`fetch_request`](Self::fetch_request), with each hop retried
+    /// as `options` allow. A retry waits out its backoff first, so dropping
+    /// the fetch, as when its navigation is cancelled, sends no more.
+    pub async fn fetch_with_options(
+        &self,
+        mut request: FetchRequest,
+        options: &FetchOptions,
+    ) -> Result<FetchResponse, NetworkError> {
+        let (max_body, user_agent) = (options.max_body, options.user_agent.as_deref());
+        let kind = request.kind;
+        if self.offline.enabled() {
+            return Err(NetworkError::Offline);
+        }
+        let mut redirected_from = Vec::new();
+        loop {
+            if let Some(url) = self.interceptors.before_request(&request)? {
+                request.url = url;
+            }
+            check_scheme(&request.url)?;
+            if let Some(port) = self.ports.restricted_port(&request.url) {
+                return Err(NetworkError::RestrictedPort(port));
+            }
+            let stripped = match &self.cookies {
+                Some(_) if !request.credentials => Some("credentials omitted"),
+                Some(_) => self.cookies_blocked(&request).await,
+                None => None,
+            };
+            match stripped {
+                Some(reason) => tracing::debug!("Fetching URL: {} (cookies stripped: {})", request.url, reason),
+                None => tracing::debug!("Fetching URL: {}", request.url),
+            }
+            let jar = self.cookie_jar(&request).filter(|_| stripped.is_none());
+
+            let method = Method::from_bytes(request.method.as_bytes())
+                .map_err(|_| NetworkError::Request(format!("Invalid method {}", request.method)))?;
+            let mut outgoing = self.build(method, &request.url, user_agent);
+            for (name, value) in &request.headers {
+                outgoing = outgoing.header(name.as_str(), value.as_str());
+            }
+            if let Some(body) = &request.body {
+                outgoing = outgoing.body(body.clone());
+            }
+            if let Some(cookie) = jar.as_ref().and_then(|jar| jar.header(&request.url)) {
+                outgoing = outgoing.header(COOKIE, cookie);
+            }
+            let idempotent = matches!(request.method.as_str(), "GET" | "HEAD");
+            let retries = if idempotent { options.retries } else { 0 };
+            let sent = self.send_with_retries(outgoing, &request.url, retries).await;
+            self.offline.record(sent.as_ref().err());
+            let mut response = sent?;
+            let version = HttpVersion::from(response.version());
+            tracing::debug!("{} {} over {}", response.status().as_u16(), request.url, version);
+            if let Some(alt_svc) = response.headers().get(ALT_SVC).and_then(|value| value.to_str().ok()) {
+                self.alt_svc.record(&request.url, alt_svc);
+            }
+            if let Some(jar) = &jar {
+                for set_cookie in response.headers().get_all(SET_COOKIE) {
+                    jar.store(&request.url, &String::from_utf8_lossy(set_cookie.as_bytes()));
+                }
+            }
+            let status = response.status();
+            let headers: Vec<(String, String)> = response
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned())
+                })
+                .collect();
+
+            let redirect = match self.interceptors.headers_received(&request, status.as_u16(), &headers)? {
+                Some(url) => Some(url),
+                None if status.is_redirection() => response
+                    .headers()
+                    .get(reqwest::header::LOCATION)
+                    .and_then(|location| location.to_str().ok())
+                    .and_then(|location| request.url.join(location).ok()),
+                None => None,
+            };
+            if let Some(url) = redirect {
+                if request.redirects >= MAX_REDIRECTS {
+                    return Err(NetworkError::TooManyRedirects);
+                }
+                // As browsers do, a 303, or a 301 or 302 after a POST, is followed with a GET
+                let as_get = match status.as_u16() {
+                    303 => !matches!(request.method.as_str(), "GET" | "HEAD"),
+                    301 | 302 => request.method == "POST",
+                    _ => false,
+                };
+                // A script's fetch only carries cookies to its page's origin, and
+                // not again once a redirect has left it
+                let left_origin = kind == ResourceKind::Fetch
+                    && request.top_level.as_ref().and_then(SiteOrigin::of) != SiteOrigin::of(&url);
+                redirected_from.push(request.url.clone());
+                request = FetchRequest {
+                    url,
+                    kind,
+                    redirects: request.redirects + 1,
+                    top_level: request.top_level,
+                    method: if as_get { "GET".to_string() } else { request.method },
+                    headers: request.headers,
+                    body: if as_get { None } else { request.body },
+                    credentials: request.credentials && !left_origin,
+                    private: request.private,
+                };
+                continue;
+            }
+
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let mut body = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                if let Some(max_body) = max_body.filter(|max| body.len() + chunk.len() > *max) {
+                    body.extend_from_slice(&chunk[..max_body - body.len()]);
+                    tracing::debug!("Stopped reading {} after {} bytes", request.url, max_body);
+                    break;
+                }
+                body.extend_from_slice(&chunk);
+            }
+            return Ok(FetchResponse {
+                url: request.url,
+                redirected_from,
+                status: status.as_u16(),
+                content_type,
+                headers,
+                body,
+                version,
+            });
+        }
+    }
+
+    /// Send `request` for `url`, over HTTP/3 if built with the `http3`
+    /// feature and the origin advertised it, falling back to TCP if that
+    /// fails
+    async fn send(&self, request: RequestBuilder, url: &ValidatedUrl) -> reqwest::Result<reqwest::Response> {
+        if cfg!(feature = "http3") && self.alt_svc.supports_http3(url) {
+            if let Some(fallback) = request.try_clone() {
+                match request.version(Version::HTTP_3).send().await {
+                    Ok(response) => return Ok(response),
+                    Err(e) => {
+                        tracing::debug!("HTTP/3 to {} failed, falling back: {}", url, e);
+                        self.alt_svc.forget(url);
+                        return fallback.send().await;
+                    }
+                }
+            }
+        }
+        request.send().await
+    }
+
+    /// Send `request` for `url`, trying again up to `retries` times after
+    /// failures a moment's wait may cure. Only the response head is waited
+    /// for, so no body has reached the caller when a retry is made.
+    async fn send_with_retries(
+        &self,
+        request: RequestBuilder,
+        url: &ValidatedUrl,
+        retries: u32,
+    ) -> Result<reqwest::Response, NetworkError> {
+        let mut attempt = 1;
+        let outcome = loop {
+            let retry = if attempt <= retries { request.try_clone() } else { None };
+            let Some(sending) = retry else {
+                break self.send(request, url).await;
+            };
+            match self.send(sending, url).await {
+                Ok(response) if !is_transient_status(response.status().as_u16()) => break Ok(response),
+                Ok(response) => {
+                    tracing::debug!("Attempt {} at {} got {}", attempt, url, response.status().as_u16());
+                }
+                Err(e) if is_transient(&e) => {
+                    tracing::debug!("Attempt {} at {} failed: {}", attempt, url, describe(&e));
+                }
+                Err(e) => break Err(e),
+            }
+            tokio::time::sleep(retry_delay(attempt)).await;
+            attempt += 1;
+        };
+        if attempt > 1 {
+            match &outcome {
+                Ok(response) => tracing::info!("{} {} after {} attempts", response.status().as_u16(), url, attempt),
+                Err(e) => tracing::warn!("{} failed after {} attempts: {}", url, attempt, describe(e)),
+            }
+        }
+        Ok(outcome?)
+    }
+
+    /// Open a WebSocket to `url` for `page`, sent as `user_agent` if given.
+    /// Plain `ws:` is only allowed from plain `http:` pages; the opening
+    /// handshake goes through the interceptors and port policy like a fetch,
+    /// and pages on the internet may not reach private networks. `wss:`
+    /// certificates are checked as for fetches, against the same roots.
+    pub async fn websocket(
+        &self,
+        url: &ValidatedUrl,
+        page: Option<&ValidatedUrl>,
+        user_agent: Option<&str>,
+    ) -> Result<WebSocketHandle, NetworkError> {
+        if self.offline.enabled() {
+            return Err(NetworkError::Offline);
+        }
+        let mut request = FetchRequest::new(url.clone(), ResourceKind::WebSocket);
+        if let Some(page) = page {
+            request = request.with_top_level(page.clone());
+        }
+        if let Some(url) = self.interceptors.before_request(&request)? {
+            request.url = url;
+        }
+        let url = &request.url;
+        match (url.scheme(), page.map(ValidatedUrl::scheme)) {
+            ("wss", _) | ("ws", Some("http")) => {}
+            ("ws", _) => {
+                return Err(NetworkError::Request(format!("{} must use wss: from this page", url)));
+            }
+            (other, _) => {
+                return Err(NetworkError::Request(format!("Refusing to open a WebSocket to a {} URL", other)));
+            }
+        }
+        if let Some(port) = self.ports.restricted_port(url) {
+            return Err(NetworkError::RestrictedPort(port));
+        }
+        let origin = page.and_then(SiteOrigin::of).map(|origin| origin.to_string());
+        let agent = user_agent
+            .map(str::to_string)
+            .or_else(|| self.user_agent.read().ok().and_then(|agent| agent.clone()))
+            .unwrap_or_else(default_user_agent);
+        let from_public_page = page.is_some_and(|page| !websocket::is_private_host(page));
+        let mut tls = tls_config(&self.trusted_roots(), self.certificates.clone())?;
+        // The upgrade is an HTTP/1.1 request
+        tls.alpn_protocols = vec![b"http/1.1".to_vec()];
+        let opening = websocket::Opening {
+            origin: origin.as_deref(),
+            user_agent: &agent,
+            from_public_page,
+            keepalive: self.websocket_keepalive,
+            tls: Arc::new(tls),
+        };
+        websocket::connect(url, opening).await
+    }
+}
+
+/// Whether `error` is one a moment's wait may cure: a connection dropped
+/// before the response, or a DNS server that asked to be tried again
+fn is_transient(error: &reqwest::Error) -> bool {
+    if find_cause::<std::io::Error>(error).is_some_and(|e| e.kind() == std::io::ErrorKind::ConnectionReset) {
+        return true;
+    }
+    let detail = describe(error).to_ascii_lowercase();
+    ["connection reset", "connection closed before message completed", "temporary failure in name resolution"]
+        .iter()
+        .any(|transient| detail.contains(transient))
+}
+
+/// Why a TLS handshake with `host` failed, telling a revoked certificate
+/// from other failures
+pub(super) fn tls_handshake_error(error: &std::io::Error, host: &str) -> NetworkError {
+    match find_cause::<rustls::Error>(error) {
+        Some(rustls::Error::InvalidCertificate(rustls::CertificateError::Revoked)) => {
+            NetworkError::CertificateRevoked(host.to_string())
+        }
+        Some(tls) => NetworkError::Tls(tls.to_string()),
+        None => NetworkError::Connection(error.to_string()),
+    }
+}
+
+/// Bad gateway, service unavailable and gateway timeout
+fn is_transient_status(status: u16) -> bool {
+    matches!(status, 502..=504)
+}
+
+/// How long to wait after failed attempt number `attempt`, counting from 1
+fn retry_delay(attempt: u32) -> Duration {
+    let backoff = RETRY_BACKOFF * 2u32.saturating_pow(attempt.saturating_sub(1));
+    let mut random = [0u8; 1];
+    let jitter = match SystemRandom::new().fill(&mut random) {
+        Ok(()) => backoff.mul_f64(f64::from(random[0]) / 512.0),
+        Err(_) => Duration::ZERO,
+    };
+    backoff + jitter
+}
+
+/// How every client is configured, trusting `roots` besides the built-in
+/// ones and logging the certificates it checks in `certificates`
+fn client_builder(
+    roots: &[CertificateDer<'static>],
+    certificates: Arc<CertificateLog>,
+) -> Result<ClientBuilder, NetworkError> {
+    Ok(Client::builder()
+        // Rust's memory-safe TLS implementation, checking stapled OCSP responses
+        .use_preconfigured_tls(tls_config(roots, certificates)?)
+        .https_only(false) // Allow HTTP but we'll enforce HTTPS at higher level
+        // Redirects are followed in `fetch_resource`, so interceptors see every hop
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(Duration::from_secs(30))
+        .user_agent(default_user_agent()))
+}
+
+fn default_user_agent() -> String {
+    format!("Navigator/{}", env!("CARGO_PKG_VERSION"))
+}
 
-        Ok(Self { client })
+/// Only web URLs are fetched, so a redirect cannot lead to a local file
+fn check_scheme(url: &ValidatedUrl) -> Result<(), NetworkError> {
+    match url.scheme() {
+        "http" | "https" => Ok(()),
+        other => Err(NetworkError::Request(format!("Refusing to fetch a {} URL", other))),
     }
 }
 
@@ -31,59 +771,56 @@ impl Default for SecureNetworkClient {
 }
 
 #[async_trait]
-impl NetworkService for SecureNetworkClient {
-    async fn fetch(&self, url: &ValidatedUrl) -> Result<Vec<u8>> {
-        tracing::debug!("Fetching URL: {}", url);
-
+impl SubresourceLoader for SecureNetworkClient {
+    async fn load(&self, url: &ValidatedUrl, page: &ValidatedUrl) -> Result<Subresource, NetworkError> {
+        let request = FetchRequest::new(url.clone(), ResourceKind::Subresource).with_top_level(page.clone());
+        // One byte over the cap tells a body at the cap from a larger one
         let response = self
-            .client
-            .get(url.as_str())
-            .send()
-            .await
-            .context("Failed to send HTTP request")?;
-
-        if !response.status().is_success() {
-            return Err(anyhow!(
-                "HTTP request failed with status: {}",
-                response.status()
-            ));
+            .fetch_request(request, Some(MAX_SUBRESOURCE_BODY + 1), None)
+            .await?;
+        if !(200..300).contains(&response.status) {
+            return Err(NetworkError::Status(response.status));
         }
+        if response.body.len() > MAX_SUBRESOURCE_BODY {
+            return Err(NetworkError::BodyTooLarge(MAX_SUBRESOURCE_BODY));
+        }
+        Ok(Subresource {
+            url: url.clone(),
+            content_type: response
+                .content_type
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            body: response.body,
+        })
+    }
+}
 
-        let bytes = response
-            .bytes()
-            .await
-            .context("Failed to read response body")?
-            .to_vec();
-
-        Ok(bytes)
+#[async_trait]
+impl NetworkService for SecureNetworkClient {
+    async fn fetch(&self, url: &ValidatedUrl) -> Result<Vec<u8>, NetworkError> {
+        let response = self.fetch_resource(url, ResourceKind::Subresource).await?;
+        if !(200..300).contains(&response.status) {
+            return Err(NetworkError::Status(response.status));
+        }
+        Ok(response.body)
     }
 
-    async fn verify_certificate(&self, url: &ValidatedUrl) -> Result<Certificate> {
+    async fn verify_certificate(&self, url: &ValidatedUrl) -> Result<Certificate, NetworkError> {
         // For HTTPS URLs, verify certificate
         if !url.is_secure() {
-            return Err(anyhow!("Cannot verify certificate for non-HTTPS URL"));
+            return Err(NetworkError::Tls(format!("{} is not an HTTPS URL", url)));
         }
 
-        // Make a request to verify the certificate
-        let response = self
-            .client
-            .get(url.as_str())
-            .send()
-            .await
-            .context("Failed to verify certificate")?;
-
-        // In a real implementation, we would extract actual certificate details
-        // For now, return a mock certificate
-        Ok(Certificate {
-            subject: url.host_str().unwrap_or("unknown").to_string(),
-            issuer: "Unknown CA".to_string(),
-            valid_from: chrono::Utc::now(),
-            valid_until: chrono::Utc::now() + chrono::Duration::days(365),
-            is_valid: response.status().is_success(),
-        })
+        if self.offline.enabled() {
+            return Err(NetworkError::Offline);
+        }
+        // Connecting checks the certificate, which the verifier then logs
+        self.get(url, None).send().await?;
+        let host = url.host_str().unwrap_or_default();
+        self.certificate(host)
+            .ok_or_else(|| NetworkError::Tls(format!("No certificate was checked for {}", host)))
     }
 
-    async fn check_security(&self, url: &ValidatedUrl) -> Result<SecurityContext> {
+    async fn check_security(&self, url: &ValidatedUrl) -> Result<SecurityContext, NetworkError> {
         let mut context = SecurityContext::new();
 
         if url.is_secure() {
@@ -101,20 +838,97 @@ impl NetworkService for SecureNetworkClient {
 
         Ok(context)
     }
+
+    async fn open_websocket(
+        &self,
+        url: &ValidatedUrl,
+        page: Option<&ValidatedUrl>,
+    ) -> Result<Arc<dyn WebSocketConnection>, NetworkError> {
+        Ok(Arc::new(self.websocket(url, page, None).await?))
+    }
+}
+
+impl TrustStore for SecureNetworkClient {
+    fn inspect(&self, pem: &str) -> Result<CertificateDetails, CertificateImportError> {
+        inspect_certificate(pem)
+    }
+
+    fn set_custom_roots(&self, roots: &[CustomCa]) -> Result<(), NetworkError> {
+        let roots = roots
+            .iter()
+            .map(|ca| {
+                CertificateDer::from_pem_slice(ca.pem.as_bytes())
+                    .map_err(|e| NetworkError::Tls(format!("Cannot read {}: {}", ca.name, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let count = roots.len();
+        if let Ok(mut custom) = self.custom_roots.write() {
+            *custom = roots;
+        }
+        self.rebuild_client()?;
+        tracing::info!("Trusting {} certificate authorities besides the built-in ones", count);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TlsInspector for SecureNetworkClient {
+    async fn tls_session(&self, url: &ValidatedUrl) -> Result<TlsSession, NetworkError> {
+        if !url.is_secure() {
+            return Err(NetworkError::Tls(format!("{} is not an HTTPS URL", url)));
+        }
+        if self.offline.enabled() {
+            return Err(NetworkError::Offline);
+        }
+        if let Some(port) = self.ports.restricted_port(url) {
+            return Err(NetworkError::RestrictedPort(port));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| NetworkError::Request(format!("{} has no host", url)))?;
+        let bare_host = host.trim_start_matches('[').trim_end_matches(']');
+        let name = ServerName::try_from(bare_host.to_string()).map_err(|e| NetworkError::Tls(e.to_string()))?;
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(tls_config(
+            &self.trusted_roots(),
+            self.certificates.clone(),
+        )?));
+
+        let handshake = async {
+            let port = url.port_or_default().unwrap_or(443);
+            let tcp = tokio::net::TcpStream::connect((bare_host, port))
+                .await
+                .map_err(|e| NetworkError::Connection(e.to_string()))?;
+            connector.connect(name, tcp).await.map_err(|e| tls_handshake_error(&e, bare_host))
+        };
+        let stream = tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, handshake)
+            .await
+            .map_err(|_| NetworkError::Timeout)??;
+        let (_, connection) = stream.get_ref();
+        let describe = |name: Option<String>| name.unwrap_or_else(|| "unknown".to_string());
+        let cipher_suite = connection.negotiated_cipher_suite().map(|suite| format!("{:?}", suite.suite()));
+        Ok(TlsSession {
+            version: describe(connection.protocol_version().map(|version| format!("{:?}", version))),
+            cipher_suite: describe(cipher_suite),
+            chain_fingerprints: connection
+                .peer_certificates()
+                .unwrap_or_default()
+                .iter()
+                .map(|certificate| fingerprint(certificate))
+                .collect(),
+        })
+    }
 }
 
 /// DNS-over-HTTPS resolver for enhanced privacy
 pub struct DohResolver {
+    #[allow(dead_code)] // Used once real DoH queries are implemented
     client: Client,
     doh_server: String,
 }
 
 impl DohResolver {
-    pub fn new() -> Result<Self> {
-        let client = Client::builder()
-            .use_rustls_tls()
-            .build()
-            .context("Failed to create DoH client")?;
+    pub fn new() -> Result<Self, NetworkError> {
+        let client = Client::builder().use_rustls_tls().build()?;
 
         Ok(Self {
             client,
@@ -122,8 +936,8 @@ impl DohResolver {
         })
     }
 
-    pub async fn resolve(&self, domain: &str) -> Result<Vec<std::net::IpAddr>> {
-        tracing::debug!("Resolving domain via DoH: {}", domain);
+    pub async fn resolve(&self, domain: &str) -> Result<Vec<std::net::IpAddr>, NetworkError> {
+        tracing::debug!("Resolving domain via DoH ({}): {}", self.doh_server, domain);
 
         // In a real implementation, we would make a DNS query over HTTPS
         // For now, return an empty result as this is a stub
@@ -140,6 +954,7 @@ impl Default for DohResolver {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::infrastructure::{ContentBlocker, TrackingParameterCleaner};
 
     #[tokio::test]
     async fn test_network_client_creation() {
@@ -152,4 +967,461 @@ mod tests {
         let resolver = DohResolver::new();
         assert!(resolver.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_refused_connection_is_classified() {
+        // Bind and drop a listener so the port is very likely closed
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+
+        let error = SecureNetworkClient::new().unwrap().fetch(&url).await.unwrap_err();
+        assert!(matches!(error, NetworkError::Connection(_)), "{:?}", error);
+    }
+
+    #[tokio::test]
+    async fn test_offline_mode_refuses_requests_and_notices_lost_connectivity() {
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let offline = OfflineMode::new();
+        let client = SecureNetworkClient::new().unwrap().with_offline_mode(offline.clone());
+        let url = |port: u16| ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+
+        for attempt in 1..=CONNECTIVITY_FAILURES {
+            assert!(!offline.connectivity_lost());
+            let error = client.fetch(&url(closed)).await.unwrap_err();
+            assert!(matches!(error, NetworkError::Connection(_)), "attempt {}: {:?}", attempt, error);
+        }
+        assert!(offline.connectivity_lost());
+
+        // Reaching any server clears the count
+        let (port, server) = serve(vec!["HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n"]).await;
+        client.fetch_resource(&url(port), ResourceKind::Document).await.unwrap();
+        server.await.unwrap();
+        assert!(!offline.connectivity_lost());
+
+        offline.set_enabled(true);
+        let error = client.fetch_resource(&url(closed), ResourceKind::Document).await.unwrap_err();
+        assert_eq!(error, NetworkError::Offline);
+        let error = client.websocket(&ValidatedUrl::parse("wss://example.com/").unwrap(), None, None).await;
+        assert_eq!(error.err(), Some(NetworkError::Offline));
+    }
+
+    /// Answers each connection with the next canned response, returning
+    /// the requests it saw
+    async fn serve(responses: Vec<&'static str>) -> (u16, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0; 4096];
+                let read = stream.read(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..read]).into_owned();
+                requests.push(request);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (port, server)
+    }
+
+    fn request_lines(requests: Vec<String>) -> Vec<String> {
+        let line = |request: &String| request.lines().next().unwrap_or("").to_string();
+        requests.iter().map(line).collect()
+    }
+
+    /// The `Cookie` header each request carried
+    fn cookie_headers(requests: Vec<String>) -> Vec<Option<String>> {
+        let cookie = |request: &String| {
+            let line = request.lines().find(|line| line.to_ascii_lowercase().starts_with("cookie:"));
+            line.map(|line| line["cookie:".len()..].trim().to_string())
+        };
+        requests.iter().map(cookie).collect()
+    }
+
+    #[tokio::test]
+    async fn test_interceptors_see_every_redirect_hop() {
+        let (port, server) = serve(vec![
+            "HTTP/1.1 302 Found\r\nLocation: /final?gclid=abc\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+        ])
+        .await;
+        let client = SecureNetworkClient::new()
+            .unwrap()
+            .with_interceptor(Arc::new(TrackingParameterCleaner::new()));
+
+        let url = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/start?id=1&utm_source=x", port)).unwrap();
+        let response = client.fetch_resource(&url, ResourceKind::Document).await.unwrap();
+        assert_eq!(response.body, b"ok");
+        assert_eq!(response.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(response.url.path(), "/final");
+        let hops: Vec<&str> = response.redirected_from.iter().map(ValidatedUrl::as_str).collect();
+        assert_eq!(hops, [format!("http://127.0.0.1:{}/start?id=1", port)]);
+        assert_eq!(
+            request_lines(server.await.unwrap()),
+            vec!["GET /start?id=1 HTTP/1.1", "GET /final HTTP/1.1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_redirect_to_restricted_port_is_refused() {
+        let (port, server) = serve(vec![
+            "HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:6667/\r\n\
+             Content-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+        let client = SecureNetworkClient::new().unwrap().with_port_policy(Arc::new(PortPolicy::new()));
+
+        let url = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+        assert_eq!(client.fetch(&url).await, Err(NetworkError::RestrictedPort(6667)));
+        assert_eq!(request_lines(server.await.unwrap()), vec!["GET / HTTP/1.1"]);
+    }
+
+    #[tokio::test]
+    async fn test_blocked_request_is_never_sent() {
+        let client = SecureNetworkClient::new()
+            .unwrap()
+            .with_interceptor(Arc::new(ContentBlocker::new().with_hosts(["127.0.0.1".to_string()])));
+        // Nothing listens here; a request that was sent would fail to connect
+        let url = ValidatedUrl::parse("http://127.0.0.1:9/pixel.gif").unwrap();
+
+        assert_eq!(
+            client.fetch(&url).await,
+            Err(NetworkError::Blocked("content blocker".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cookies_flow_first_party_only() {
+        const SET_SITE: &str =
+            "HTTP/1.1 200 OK\r\nSet-Cookie: site=a\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+        const SET_TRACKER: &str =
+            "HTTP/1.1 200 OK\r\nSet-Cookie: tracker=b\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+        const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+        // The same address under two names, so two sites
+        let (site_port, site) = serve(vec![SET_SITE, OK, OK]).await;
+        let (tracker_port, tracker) = serve(vec![SET_TRACKER, SET_TRACKER, OK, OK]).await;
+        let page = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", site_port)).unwrap();
+        let own = page.join("/style.css").unwrap();
+        let pixel = ValidatedUrl::parse(&format!("http://localhost:{}/pixel.gif", tracker_port)).unwrap();
+        let jar = Arc::new(CookieJar::new());
+        let client = SecureNetworkClient::new().unwrap().with_cookie_jar(jar.clone());
+
+        client.fetch_resource(&page, ResourceKind::Document).await.unwrap();
+        client.load(&pixel, &page).await.unwrap();
+        client.load(&own, &page).await.unwrap();
+        assert_eq!(jar.cookies().len(), 1);
+
+        client.set_cookie_blocking(CookieBlocking::AllowAll);
+        client.load(&pixel, &page).await.unwrap();
+        client.set_cookie_blocking(CookieBlocking::BlockThirdParty);
+        client.load(&pixel, &page).await.unwrap();
+        // Visited directly the tracker is first-party
+        client.fetch_resource(&pixel, ResourceKind::Document).await.unwrap();
+        client.set_cookie_blocking(CookieBlocking::BlockAll);
+        client.fetch_resource(&page, ResourceKind::Document).await.unwrap();
+
+        assert_eq!(cookie_headers(site.await.unwrap()), [None, Some("site=a".to_string()), None]);
+        assert_eq!(
+            cookie_headers(tracker.await.unwrap()),
+            [None, None, None, Some("tracker=b".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_private_tabs_keep_their_cookies_apart() {
+        const SET: &str =
+            "HTTP/1.1 200 OK\r\nSet-Cookie: private=a\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+        const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+        let (port, server) = serve(vec![SET, OK, OK, OK]).await;
+        let page = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+        let jar = Arc::new(CookieJar::new());
+        let client = SecureNetworkClient::new().unwrap().with_cookie_jar(jar.clone());
+        let private = || FetchRequest::new(page.clone(), ResourceKind::Document).with_private(true);
+
+        client.fetch_request(private(), None, None).await.unwrap();
+        client.fetch_resource(&page, ResourceKind::Document).await.unwrap();
+        client.fetch_request(private(), None, None).await.unwrap();
+        // Closing the last private tab forgets them
+        client.end_private_session();
+        client.fetch_request(private(), None, None).await.unwrap();
+
+        assert!(jar.cookies().is_empty());
+        assert_eq!(
+            cookie_headers(server.await.unwrap()),
+            [None, None, Some("private=a".to_string()), None]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_site_cookie_policy_overrides_the_blocking() {
+        use crate::application::testing::InMemorySiteSettingsRepository;
+        use crate::domain::{CookiePolicy, SiteSettings};
+
+        let page = ValidatedUrl::parse("https://news.example/").unwrap();
+        let sites = Arc::new(InMemorySiteSettingsRepository::new());
+        let mut settings = SiteSettings::new(SiteOrigin::of(&page).unwrap());
+        settings.cookies = Some(CookiePolicy::Allow);
+        sites.save(&settings).await.unwrap();
+        let client = SecureNetworkClient::new()
+            .unwrap()
+            .with_cookie_jar(Arc::new(CookieJar::new()))
+            .with_site_settings(sites.clone());
+        let widget = ValidatedUrl::parse("https://comments.example/embed.js").unwrap();
+        let request = FetchRequest::new(widget.clone(), ResourceKind::Subresource);
+
+        assert_eq!(client.cookies_blocked(&request.clone().with_top_level(page.clone())).await, None);
+        let elsewhere = ValidatedUrl::parse("https://blog.example/").unwrap();
+        let third_party = request.clone().with_top_level(elsewhere);
+        assert_eq!(client.cookies_blocked(&third_party).await, Some("third-party"));
+
+        settings.cookies = Some(CookiePolicy::Block);
+        sites.save(&settings).await.unwrap();
+        let document = FetchRequest::new(page.clone(), ResourceKind::Document);
+        assert_eq!(client.cookies_blocked(&document).await, Some("all blocked"));
+        client.set_cookie_blocking(CookieBlocking::BlockAll);
+        assert_eq!(client.cookies_blocked(&request).await, Some("all blocked"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transient_failures_are_retried() {
+        const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+        const UNAVAILABLE: &str =
+            "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        let client = SecureNetworkClient::new().unwrap();
+        let url = |port: u16| ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+
+        // The first connection is dropped without an answer
+        let (port, server) = serve(vec!["", OK]).await;
+        let start = tokio::time::Instant::now();
+        let response = client.fetch_resource(&url(port), ResourceKind::Document).await.unwrap();
+        let waited = start.elapsed();
+        assert_eq!(response.body, b"ok");
+        assert!(waited >= RETRY_BACKOFF && waited <= RETRY_BACKOFF.mul_f64(1.5), "{:?}", waited);
+        assert_eq!(server.await.unwrap().len(), 2);
+
+        // Two retries, backing off, then the last answer stands
+        let (port, server) = serve(vec![UNAVAILABLE; 3]).await;
+        let start = tokio::time::Instant::now();
+        let response = client.fetch_resource(&url(port), ResourceKind::Document).await.unwrap();
+        let waited = start.elapsed();
+        assert_eq!(response.status, 503);
+        assert!(waited >= RETRY_BACKOFF * 3 && waited <= RETRY_BACKOFF.mul_f64(4.5), "{:?}", waited);
+        assert_eq!(server.await.unwrap().len(), 3);
+
+        // Neither opted-out requests nor POSTs are retried
+        let (port, server) = serve(vec![UNAVAILABLE, UNAVAILABLE]).await;
+        let start = tokio::time::Instant::now();
+        let request = FetchRequest::new(url(port), ResourceKind::Fetch);
+        let options = FetchOptions::new().with_retries(0);
+        assert_eq!(client.fetch_with_options(request.clone(), &options).await.unwrap().status, 503);
+        let post = request.with_method("POST").with_body(b"form".to_vec());
+        assert_eq!(client.fetch_with_options(post, &FetchOptions::new()).await.unwrap().status, 503);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(request_lines(server.await.unwrap()), ["GET / HTTP/1.1", "POST / HTTP/1.1"]);
+    }
+
+    fn tls_fixture(name: &str) -> Vec<u8> {
+        std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/tls").join(name)).unwrap()
+    }
+
+    /// An HTTP/2-only server over TLS answering every request with "ok",
+    /// and the number of connections it has accepted
+    async fn serve_h2() -> (u16, Arc<std::sync::atomic::AtomicUsize>) {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+        let certificates = CertificateDer::pem_slice_iter(&tls_fixture("localhost.pem"))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_slice(&tls_fixture("localhost.key")).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)
+            .unwrap();
+        config.alpn_protocols = vec![b"h2".to_vec()];
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let connections = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut connection = h2::server::handshake(stream).await.unwrap();
+                    while let Some(Ok((_, mut respond))) = connection.accept().await {
+                        let response = http::Response::builder()
+                            .header("content-type", "text/plain")
+                            .header("alt-svc", format!("h3=\":{}\"; ma=60", port))
+                            .body(())
+                            .unwrap();
+                        let mut body = respond.send_response(response, false).unwrap();
+                        body.send_data(bytes::Bytes::from_static(b"ok"), true).unwrap();
+                    }
+                });
+            }
+        });
+        (port, connections)
+    }
+
+    #[tokio::test]
+    async fn test_http2_connection_is_shared() {
+        let (port, connections) = serve_h2().await;
+        let client = SecureNetworkClient::new().unwrap().with_root_certificate(&tls_fixture("ca.pem"));
+        let client = Arc::new(client.unwrap());
+        let url = |path: &str| ValidatedUrl::parse(&format!("https://127.0.0.1:{}/{}", port, path)).unwrap();
+
+        let document = client.fetch_resource(&url("index.html"), ResourceKind::Document).await.unwrap();
+        assert_eq!(document.version, HttpVersion::Http2);
+        assert_eq!(document.body, b"ok");
+        assert!(client.alt_svc.supports_http3(&url("")));
+
+        // The page's subresources all go over the document's connection
+        let fetches: Vec<_> = (0..8)
+            .map(|index| {
+                let client = client.clone();
+                let url = url(&format!("image{}.png", index));
+                tokio::spawn(async move { client.fetch_resource(&url, ResourceKind::Subresource).await })
+            })
+            .collect();
+        for fetch in fetches {
+            let response = fetch.await.unwrap().unwrap();
+            assert_eq!((response.status, response.version), (200, HttpVersion::Http2));
+        }
+        assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_custom_roots_are_trusted_until_removed() {
+        let (port, _) = serve_h2().await;
+        let client = SecureNetworkClient::new().unwrap();
+        let url = ValidatedUrl::parse(&format!("https://127.0.0.1:{}/", port)).unwrap();
+        assert!(client.fetch_resource(&url, ResourceKind::Document).await.is_err());
+
+        let pem = String::from_utf8(tls_fixture("ca.pem")).unwrap();
+        let details = client.inspect(&pem).unwrap();
+        let ca = CustomCa {
+            id: 1,
+            name: "Test CA".to_string(),
+            pem: details.pem,
+            fingerprint: details.fingerprint,
+            added_at: chrono::Utc::now(),
+        };
+        client.set_custom_roots(std::slice::from_ref(&ca)).unwrap();
+        assert_eq!(client.fetch_resource(&url, ResourceKind::Document).await.unwrap().body, b"ok");
+
+        client.set_custom_roots(&[]).unwrap();
+        assert!(client.fetch_resource(&url, ResourceKind::Document).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tls_sessions_are_described() {
+        let (port, _) = serve_h2().await;
+        let client = SecureNetworkClient::new().unwrap();
+        let url = ValidatedUrl::parse(&format!("https://127.0.0.1:{}/", port)).unwrap();
+        assert!(matches!(client.tls_session(&url).await, Err(NetworkError::Tls(_))));
+
+        let client = client.with_root_certificate(&tls_fixture("ca.pem")).unwrap();
+        let session = client.tls_session(&url).await.unwrap();
+        assert_eq!(session.version, "TLSv1_3");
+        assert!(session.cipher_suite.starts_with("TLS13_"), "{}", session.cipher_suite);
+        let leaf = CertificateDer::from_pem_slice(&tls_fixture("localhost.pem")).unwrap();
+        assert_eq!(session.chain_fingerprints, [fingerprint(&leaf)]);
+
+        let plain = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+        assert!(client.tls_session(&plain).await.is_err());
+    }
+
+    fn ocsp_fixture(name: &str) -> Vec<u8> {
+        std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/ocsp").join(name)).unwrap()
+    }
+
+    /// A server over TLS presenting the certificate in `leaf`, stapling the
+    /// OCSP response in `staple`, and answering every request with "ok"
+    async fn serve_stapled(leaf: &str, staple: &str) -> u16 {
+        use rustls::pki_types::PrivateKeyDer;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let certificates = CertificateDer::pem_slice_iter(&ocsp_fixture(leaf))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_slice(&ocsp_fixture("localhost.key")).unwrap();
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert_with_ocsp(certificates, key, ocsp_fixture(staple))
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    continue;
+                };
+                if stream.read(&mut [0; 4096]).await.is_err() {
+                    continue;
+                }
+                let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok";
+                stream.write_all(response.as_bytes()).await.ok();
+                stream.shutdown().await.ok();
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_stapled_ocsp_responses_are_checked() {
+        let client = SecureNetworkClient::new().unwrap().with_root_certificate(&ocsp_fixture("ca.pem")).unwrap();
+        let url = |port: u16| ValidatedUrl::parse(&format!("https://localhost:{}/", port)).unwrap();
+
+        let good = url(serve_stapled("localhost.pem", "good.der").await);
+        let certificate = client.verify_certificate(&good).await.unwrap();
+        assert_eq!(certificate.ocsp.map(|ocsp| ocsp.status), Some(crate::domain::RevocationStatus::Good));
+        assert_eq!(certificate.sct_count, 2);
+
+        // A staple that cannot be believed is only left out
+        let stale = url(serve_stapled("localhost.pem", "stale.der").await);
+        let certificate = client.verify_certificate(&stale).await.unwrap();
+        assert_eq!((certificate.ocsp, certificate.sct_count), (None, 2));
+
+        let revoked = url(serve_stapled("revoked.pem", "revoked.der").await);
+        let refused = client.fetch_resource(&revoked, ResourceKind::Document).await;
+        assert_eq!(refused.unwrap_err(), NetworkError::CertificateRevoked("localhost".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_websockets_check_certificates_like_fetches() {
+        let client = SecureNetworkClient::new().unwrap().with_root_certificate(&ocsp_fixture("ca.pem")).unwrap();
+        let socket = |port: u16| ValidatedUrl::parse(&format!("wss://localhost:{}/", port)).unwrap();
+        let page = ValidatedUrl::parse("https://localhost/").unwrap();
+
+        // Trusted through the added root, the server then refuses to upgrade
+        let good = socket(serve_stapled("localhost.pem", "good.der").await);
+        let opened = client.websocket(&good, Some(&page), None).await;
+        assert_eq!(opened.err(), Some(NetworkError::Status(200)));
+
+        let revoked = socket(serve_stapled("revoked.pem", "revoked.der").await);
+        let refused = client.websocket(&revoked, Some(&page), None).await;
+        assert_eq!(refused.err(), Some(NetworkError::CertificateRevoked("localhost".to_string())));
+        let untrusted = SecureNetworkClient::new().unwrap().websocket(&good, Some(&page), None).await;
+        assert!(matches!(untrusted.err(), Some(NetworkError::Tls(_))));
+    }
 }
@@ -0,0 +1,366 @@
+use super::network::SecureNetworkClient;
+use crate::domain::{
+    NetworkError, NewSearchEngine, OpenSearchFetcher, ResourceKind, SearchEngineError, SearchTemplate, ValidatedUrl,
+};
+use async_trait::async_trait;
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Content type of the descriptions pages link with `rel="search"`
+pub const OPENSEARCH_CONTENT_TYPE: &str = "application/opensearchdescription+xml";
+/// Descriptions are a few hundred bytes; anything far larger is not one
+pub const MAX_DESCRIPTION_BODY: usize = 64 * 1024;
+
+/// Most offered descriptions remembered; the oldest are forgotten first
+const MAX_DISCOVERED: usize = 10;
+
+/// Turn an OpenSearch description document into a search engine, keyed by
+/// the host it was fetched from.
+///
+/// Only the `text/html` and suggestions JSON `Url` elements are read, and
+/// only GET searches in UTF-8 are accepted. The document is read with the
+/// HTML parser, which keeps elements and attributes of XML this simple
+/// intact, lowercasing their names.
+pub fn parse_opensearch_description(
+    body: &[u8],
+    description_url: &ValidatedUrl,
+) -> Result<NewSearchEngine, SearchEngineError> {
+    let invalid = |reason: &str| SearchEngineError::InvalidDescription(reason.to_string());
+    let dom = parse_document(RcDom::default(), Default::default()).one(String::from_utf8_lossy(body).into_owned());
+
+    let mut description = Description::default();
+    description.walk(&dom.document);
+    let name = description.short_name.filter(|name| !name.is_empty()).ok_or_else(|| invalid("no ShortName"))?;
+    // Without an InputEncoding, UTF-8 is assumed
+    if !description.input_encodings.is_empty() && !description.input_encodings.iter().any(|e| is_utf8(e)) {
+        return Err(SearchEngineError::UnsupportedEncoding(description.input_encodings.join(", ")));
+    }
+    let query_template = match (description.query_template, description.post_method) {
+        (Some(template), _) => template,
+        (None, Some(method)) => return Err(SearchEngineError::UnsupportedMethod(method)),
+        (None, None) => return Err(invalid("no text/html Url")),
+    };
+    let query_template = SearchTemplate::parse(&to_search_template(&query_template)?)
+        .ok_or(SearchEngineError::InvalidTemplate(query_template))?;
+
+    let host = description_url.host_str().unwrap_or("");
+    let mut engine = NewSearchEngine::new(name, host.strip_prefix("www.").unwrap_or(host), query_template);
+    // Suggestions are optional, so an unusable template only drops them
+    if let Some(suggest_template) = description
+        .suggest_template
+        .and_then(|template| to_search_template(&template).ok())
+        .and_then(|template| SearchTemplate::parse(&template))
+    {
+        engine = engine.with_suggest_template(suggest_template);
+    }
+    Ok(engine)
+}
+
+fn is_utf8(encoding: &str) -> bool {
+    matches!(encoding.trim().to_ascii_lowercase().as_str(), "utf-8" | "utf8")
+}
+
+/// Replace OpenSearch template parameters: `{searchTerms}` with `%s`,
+/// encodings with UTF-8 and optional parameters with nothing
+fn to_search_template(template: &str) -> Result<String, SearchEngineError> {
+    let mut converted = String::with_capacity(template.len());
+    let mut rest = template.trim();
+    while let Some(start) = rest.find('{') {
+        converted.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| SearchEngineError::InvalidTemplate(template.to_string()))?;
+        match &rest[start + 1..start + end] {
+            "searchTerms" => converted.push_str("%s"),
+            "inputEncoding" | "outputEncoding" => converted.push_str("UTF-8"),
+            "language" => converted.push('*'),
+            optional if optional.ends_with('?') => {}
+            _ => return Err(SearchEngineError::InvalidTemplate(template.to_string())),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    converted.push_str(rest);
+    Ok(converted)
+}
+
+#[derive(Default)]
+struct Description {
+    short_name: Option<String>,
+    input_encodings: Vec<String>,
+    query_template: Option<String>,
+    suggest_template: Option<String>,
+    /// Method of a `text/html` Url skipped for not using GET
+    post_method: Option<String>,
+}
+
+impl Description {
+    fn walk(&mut self, handle: &Handle) {
+        if let NodeData::Element { name, attrs, .. } = &handle.data {
+            let attribute = |wanted: &str| {
+                attrs
+                    .borrow()
+                    .iter()
+                    .find(|attr| &*attr.name.local == wanted)
+                    .map(|attr| attr.value.to_string())
+            };
+            match &*name.local {
+                "shortname" if self.short_name.is_none() => self.short_name = Some(text_of(handle)),
+                "inputencoding" => self.input_encodings.push(text_of(handle)),
+                "url" => {
+                    let kind = attribute("type").unwrap_or_default().to_ascii_lowercase();
+                    let method = attribute("method").unwrap_or_else(|| "GET".to_string());
+                    let slot = match kind.as_str() {
+                        "text/html" => Some(&mut self.query_template),
+                        "application/x-suggestions+json" => Some(&mut self.suggest_template),
+                        _ => None,
+                    };
+                    if !method.eq_ignore_ascii_case("get") {
+                        if kind == "text/html" {
+                            self.post_method = Some(method.to_ascii_uppercase());
+                        }
+                    } else if let Some(slot) = slot.filter(|slot| slot.is_none()) {
+                        // The first of each kind wins
+                        *slot = attribute("template");
+                    }
+                }
+                _ => {}
+            }
+        }
+        for child in handle.children.borrow().iter() {
+            self.walk(child);
+        }
+    }
+}
+
+/// Text directly inside an element, trimmed
+fn text_of(handle: &Handle) -> String {
+    let text: String = handle
+        .children
+        .borrow()
+        .iter()
+        .filter_map(|child| match &child.data {
+            NodeData::Text { contents } => Some(contents.borrow().to_string()),
+            _ => None,
+        })
+        .collect();
+    text.trim().to_string()
+}
+
+/// Fetches OpenSearch descriptions through the shared client, remembering
+/// each origin's so revisiting its pages fetches nothing. Failures to fetch
+/// are not remembered; unusable descriptions are.
+pub struct OpenSearchDescriptions {
+    network: Arc<SecureNetworkClient>,
+    fetched: Mutex<HashMap<String, Result<NewSearchEngine, SearchEngineError>>>,
+}
+
+impl OpenSearchDescriptions {
+    pub fn new(network: Arc<SecureNetworkClient>) -> Self {
+        Self {
+            network,
+            fetched: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Key of the origin serving `url`
+fn origin_key(url: &ValidatedUrl) -> String {
+    let url = url.normalized();
+    format!(
+        "{}://{}:{}",
+        url.scheme(),
+        url.host_str().unwrap_or(""),
+        url.port_or_default().unwrap_or(0)
+    )
+}
+
+#[async_trait]
+impl OpenSearchFetcher for OpenSearchDescriptions {
+    async fn fetch(&self, description: &ValidatedUrl) -> Result<NewSearchEngine, SearchEngineError> {
+        let origin = origin_key(description);
+        if let Some(fetched) = self.fetched.lock().ok().and_then(|fetched| fetched.get(&origin).cloned()) {
+            return fetched;
+        }
+
+        let response = self
+            .network
+            .fetch_resource_limited(description, ResourceKind::Subresource, Some(MAX_DESCRIPTION_BODY))
+            .await?;
+        if response.status >= 400 {
+            return Err(NetworkError::Status(response.status).into());
+        }
+        let parsed = parse_opensearch_description(&response.body, description);
+        if let Ok(mut fetched) = self.fetched.lock() {
+            fetched.insert(origin, parsed.clone());
+        }
+        parsed
+    }
+}
+
+/// A page offering its site's search engine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OfferedSearchEngine {
+    /// Host of the page that linked the description
+    pub site: String,
+    pub description: ValidatedUrl,
+}
+
+/// Search engines offered by pages loaded this session, newest first, for
+/// navigator://settings to offer adding. Held in memory only; clones share
+/// one list.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveredSearchEngines {
+    offered: Arc<Mutex<VecDeque<OfferedSearchEngine>>>,
+}
+
+impl DiscoveredSearchEngines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note that `page` links the description at `description`
+    pub fn record(&self, page: &ValidatedUrl, description: ValidatedUrl) {
+        let Ok(mut offered) = self.offered.lock() else {
+            return;
+        };
+        offered.retain(|engine| engine.description != description);
+        offered.push_front(OfferedSearchEngine {
+            site: page.host_str().unwrap_or("").to_string(),
+            description,
+        });
+        offered.truncate(MAX_DISCOVERED);
+    }
+
+    pub fn offered(&self) -> Vec<OfferedSearchEngine> {
+        self.offered
+            .lock()
+            .map(|offered| offered.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn sample(name: &str) -> Vec<u8> {
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/opensearch").join(name)).unwrap()
+    }
+
+    const DESCRIPTION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <OpenSearchDescription xmlns="http://a9.com/-/spec/opensearch/1.1/">
+          <ShortName> MDN Web Docs </ShortName>
+          <Url type="text/html"
+               template="https://developer.mozilla.org/search?q={searchTerms}&amp;page={startPage?}"/>
+          <Url type="application/x-suggestions+json" template="https://developer.mozilla.org/s?q={searchTerms}"/>
+        </OpenSearchDescription>"#;
+
+    #[test]
+    fn test_parse_opensearch_description() {
+        let url = ValidatedUrl::parse("https://www.developer.mozilla.org/opensearch.xml").unwrap();
+        let engine = parse_opensearch_description(DESCRIPTION.as_bytes(), &url).unwrap();
+        assert_eq!(engine.name, "MDN Web Docs");
+        assert_eq!(engine.keyword, "developer.mozilla.org");
+        assert_eq!(engine.query_template.as_str(), "https://developer.mozilla.org/search?q=%s&page=");
+        assert_eq!(engine.suggest_template.unwrap().as_str(), "https://developer.mozilla.org/s?q=%s");
+
+        let plain = DESCRIPTION.replace("https://developer.mozilla.org/search", "http://example.com/");
+        assert!(matches!(
+            parse_opensearch_description(plain.as_bytes(), &url),
+            Err(SearchEngineError::InvalidTemplate(_))
+        ));
+        let unknown = DESCRIPTION.replace("{startPage?}", "{startPage}");
+        assert!(parse_opensearch_description(unknown.as_bytes(), &url).is_err());
+        assert!(matches!(
+            parse_opensearch_description(b"<html><body>Not found</body></html>", &url),
+            Err(SearchEngineError::InvalidDescription(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_published_descriptions() {
+        let url = ValidatedUrl::parse("https://en.wikipedia.org/w/opensearch_desc.php").unwrap();
+        let wikipedia = parse_opensearch_description(&sample("wikipedia.xml"), &url).unwrap();
+        assert_eq!(wikipedia.name, "Wikipedia (en)");
+        assert_eq!(wikipedia.keyword, "en.wikipedia.org");
+        assert_eq!(
+            wikipedia.query_template.as_str(),
+            "https://en.wikipedia.org/w/index.php?title=Special:Search&search=%s"
+        );
+        assert_eq!(
+            wikipedia.suggest_template.unwrap().as_str(),
+            "https://en.wikipedia.org/w/api.php?action=opensearch&search=%s&namespace=0"
+        );
+
+        let url = ValidatedUrl::parse("https://duckduckgo.com/opensearch.xml").unwrap();
+        let duckduckgo = parse_opensearch_description(&sample("duckduckgo.xml"), &url).unwrap();
+        assert_eq!(duckduckgo.name, "DuckDuckGo");
+        assert_eq!(duckduckgo.keyword, "duckduckgo.com");
+        assert_eq!(duckduckgo.query_template.as_str(), "https://duckduckgo.com/?q=%s");
+        assert_eq!(duckduckgo.suggest_template.unwrap().as_str(), "https://duckduckgo.com/ac/?q=%s&type=list");
+    }
+
+    #[test]
+    fn test_post_and_legacy_encodings_are_rejected() {
+        let url = ValidatedUrl::parse("https://duckduckgo.com/opensearch.xml").unwrap();
+        let body = String::from_utf8(sample("duckduckgo.xml")).unwrap();
+
+        let post = body.replace(r#"<Url type="text/html""#, r#"<Url type="text/html" method="post""#);
+        assert!(matches!(
+            parse_opensearch_description(post.as_bytes(), &url),
+            Err(SearchEngineError::UnsupportedMethod(method)) if method == "POST"
+        ));
+        let latin1 = body.replace("<InputEncoding>UTF-8", "<InputEncoding>ISO-8859-1");
+        assert!(matches!(
+            parse_opensearch_description(latin1.as_bytes(), &url),
+            Err(SearchEngineError::UnsupportedEncoding(encoding)) if encoding == "ISO-8859-1"
+        ));
+        // Any UTF-8 among the encodings is enough
+        let both = body.replace("<InputEncoding>", "<InputEncoding>Shift_JIS</InputEncoding><InputEncoding>");
+        assert!(parse_opensearch_description(both.as_bytes(), &url).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_descriptions_are_fetched_once_per_origin() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Serves a single request; a second fetch would fail to connect
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0; 4096];
+            let _ = stream.read(&mut buffer).await.unwrap();
+            let body = sample("duckduckgo.xml");
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                OPENSEARCH_CONTENT_TYPE,
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(&body).await.unwrap();
+        });
+
+        let descriptions = OpenSearchDescriptions::new(Arc::new(SecureNetworkClient::new().unwrap()));
+        let url = |path: &str| ValidatedUrl::parse(&format!("http://127.0.0.1:{}{}", port, path)).unwrap();
+        let first = descriptions.fetch(&url("/opensearch.xml")).await.unwrap();
+        server.await.unwrap();
+        assert_eq!(first.name, "DuckDuckGo");
+        assert_eq!(descriptions.fetch(&url("/other.xml")).await.unwrap(), first);
+    }
+
+    #[test]
+    fn test_discovered_engines_are_deduplicated() {
+        let url = |input: &str| ValidatedUrl::parse(input).unwrap();
+        let discovered = DiscoveredSearchEngines::new();
+        discovered.record(&url("https://a.example/page"), url("https://a.example/search.xml"));
+        discovered.clone().record(&url("https://b.example/"), url("https://b.example/search.xml"));
+        discovered.record(&url("https://a.example/other"), url("https://a.example/search.xml"));
+
+        let sites: Vec<String> = discovered.offered().into_iter().map(|engine| engine.site).collect();
+        assert_eq!(sites, vec!["a.example", "b.example"]);
+    }
+}
@@ -0,0 +1,250 @@
+use crate::domain::{ConsoleLevel, ConsoleMessage};
+use chrono::Utc;
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Messages kept in a tab's console; older ones are dropped
+pub const CONSOLE_CAPACITY: usize = 500;
+/// Time all of a page's scripts may take together before the rest are skipped
+pub const DEFAULT_SCRIPT_BUDGET: Duration = Duration::from_secs(5);
+/// `type` values of `<script>` elements that hold JavaScript
+const JAVASCRIPT_TYPES: &[&str] = &["text/javascript", "application/javascript", "text/ecmascript"];
+
+/// When a script runs relative to parsing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptTiming {
+    /// Where it appears, blocking the parser
+    Classic,
+    /// As soon as it has loaded, once parsing has finished here
+    Async,
+    /// After parsing, in document order
+    Defer,
+}
+
+/// Where a script's code comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptSource {
+    Inline(String),
+    /// Loaded from a `src`, as written in the page
+    External(String),
+}
+
+/// A classic `<script>` of a page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageScript {
+    pub source: ScriptSource,
+    pub timing: ScriptTiming,
+    /// `integrity` attribute of an external script, which its contents
+    /// must match to run
+    pub integrity: Option<String>,
+}
+
+/// The page's JavaScript `<script>` elements in document order, skipping
+/// data blocks of other types and empty inline scripts. `async` and `defer`
+/// only apply to external scripts, as in browsers.
+pub fn extract_scripts(dom: &RcDom) -> Vec<PageScript> {
+    fn walk(handle: &Handle, scripts: &mut Vec<PageScript>) {
+        if let NodeData::Element { name, attrs, .. } = &handle.data {
+            if &name.local == "script" {
+                let attrs = attrs.borrow();
+                let attribute = |wanted: &str| attrs.iter().find(|attr| &*attr.name.local == wanted);
+                let classic = attribute("type").is_none_or(|kind| {
+                    let kind = kind.value.trim().to_ascii_lowercase();
+                    kind.is_empty() || JAVASCRIPT_TYPES.contains(&kind.as_str())
+                });
+                if !classic {
+                    return;
+                }
+                let script = match attribute("src") {
+                    Some(src) => PageScript {
+                        source: ScriptSource::External(src.value.trim().to_string()),
+                        timing: if attribute("async").is_some() {
+                            ScriptTiming::Async
+                        } else if attribute("defer").is_some() {
+                            ScriptTiming::Defer
+                        } else {
+                            ScriptTiming::Classic
+                        },
+                        integrity: attribute("integrity").map(|integrity| integrity.value.to_string()),
+                    },
+                    None => {
+                        let mut source = String::new();
+                        for child in handle.children.borrow().iter() {
+                            if let NodeData::Text { contents } = &child.data {
+                                source.push_str(&contents.borrow());
+                            }
+                        }
+                        if source.trim().is_empty() {
+                            return;
+                        }
+                        PageScript {
+                            source: ScriptSource::Inline(source),
+                            timing: ScriptTiming::Classic,
+                            integrity: None,
+                        }
+                    }
+                };
+                scripts.push(script);
+                return;
+            }
+        }
+        for child in handle.children.borrow().iter() {
+            walk(child, scripts);
+        }
+    }
+
+    let mut scripts = Vec::new();
+    walk(&dom.document, &mut scripts);
+    scripts
+}
+
+/// The order scripts run in once they are all loaded: classic ones in
+/// document order, then async ones, then deferred ones in document order
+pub fn execution_order(scripts: Vec<PageScript>) -> Vec<PageScript> {
+    let mut ordered = scripts;
+    // Stable, so each group keeps document order
+    ordered.sort_by_key(|script| match script.timing {
+        ScriptTiming::Classic => 0,
+        ScriptTiming::Async => 1,
+        ScriptTiming::Defer => 2,
+    });
+    ordered
+}
+
+/// What a tab's scripts logged and how they failed, shown on
+/// navigator://console. It starts over with each navigation unless the log
+/// is preserved.
+#[derive(Debug, Default)]
+pub struct ConsoleLog {
+    messages: Mutex<VecDeque<ConsoleMessage>>,
+    preserve: AtomicBool,
+}
+
+impl ConsoleLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, level: ConsoleLevel, message: impl Into<String>, source: Option<&str>) {
+        self.record(level, message.into(), source, None);
+    }
+
+    /// An exception no script caught, with the stack it was thrown from
+    pub fn push_exception(&self, message: impl Into<String>, stack: Option<String>, source: Option<&str>) {
+        self.record(ConsoleLevel::Error, message.into(), source, stack);
+    }
+
+    /// The kept messages, oldest first
+    pub fn messages(&self) -> Vec<ConsoleMessage> {
+        self.messages
+            .lock()
+            .map(|messages| messages.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.clear();
+        }
+    }
+
+    /// Keep messages across navigations, or clear them on each one again
+    pub fn set_preserve(&self, preserve: bool) {
+        self.preserve.store(preserve, Ordering::Relaxed);
+    }
+
+    pub fn preserves(&self) -> bool {
+        self.preserve.load(Ordering::Relaxed)
+    }
+
+    /// The tab is leaving its page: start over unless the log is preserved
+    pub fn navigated(&self) {
+        if !self.preserves() {
+            self.clear();
+        }
+    }
+
+    fn record(&self, level: ConsoleLevel, message: String, source: Option<&str>, stack: Option<String>) {
+        let Ok(mut messages) = self.messages.lock() else {
+            return;
+        };
+        if messages.len() >= CONSOLE_CAPACITY {
+            messages.pop_front();
+        }
+        messages.push_back(ConsoleMessage {
+            time: Utc::now(),
+            level,
+            message,
+            source: source.map(str::to_string),
+            stack,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::{parse_html_limited, ParseLimits};
+
+    fn scripts(html: &str) -> Vec<PageScript> {
+        let (dom, _) = parse_html_limited(html, None, &ParseLimits::default());
+        extract_scripts(&dom)
+    }
+
+    fn sources(scripts: &[PageScript]) -> Vec<&str> {
+        scripts
+            .iter()
+            .map(|script| match &script.source {
+                ScriptSource::Inline(code) => code.as_str(),
+                ScriptSource::External(src) => src.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_scripts_run_classic_then_async_then_deferred() {
+        let page = scripts(
+            "<script src=\"late.js\" defer></script><script>one()</script>\
+             <script src=\"any.js\" async defer></script><script type=\"application/json\">{}</script>\
+             <script src=\"two.js\" integrity=\"sha256-abc\"></script><script defer>inline()</script>\
+             <script> </script><script src=\"later.js\" defer></script>",
+        );
+        assert_eq!(sources(&page), ["late.js", "one()", "any.js", "two.js", "inline()", "later.js"]);
+        assert_eq!(page[2].timing, ScriptTiming::Async);
+        assert_eq!(page[4].timing, ScriptTiming::Classic);
+        assert_eq!(page[3].integrity.as_deref(), Some("sha256-abc"));
+
+        let ordered = execution_order(page);
+        assert_eq!(sources(&ordered), ["one()", "two.js", "inline()", "any.js", "late.js", "later.js"]);
+    }
+
+    #[test]
+    fn test_console_keeps_the_latest_messages() {
+        let console = ConsoleLog::new();
+        for index in 0..CONSOLE_CAPACITY + 2 {
+            console.push(ConsoleLevel::Log, index.to_string(), None);
+        }
+        console.push(ConsoleLevel::Error, "boom", Some("https://example.com/app.js"));
+        let messages = console.messages();
+        assert_eq!(messages.len(), CONSOLE_CAPACITY);
+        assert_eq!(messages[0].message, "3");
+        assert!(messages[CONSOLE_CAPACITY - 1].to_string().ends_with("error https://example.com/app.js: boom"));
+        console.clear();
+        assert!(console.messages().is_empty());
+    }
+
+    #[test]
+    fn test_console_clears_on_navigation_unless_preserved() {
+        let console = ConsoleLog::new();
+        console.push_exception("TypeError: x is undefined", Some("at f (app.js:3)".to_string()), None);
+        console.set_preserve(true);
+        console.navigated();
+        assert_eq!(console.messages()[0].stack.as_deref(), Some("at f (app.js:3)"));
+        console.set_preserve(false);
+        console.navigated();
+        assert!(console.messages().is_empty());
+    }
+}
@@ -0,0 +1,1278 @@
+//! Printing pages to PDF
+//!
+//! Pages are laid out with the block layout used on screen, wrapped to the
+//! paper's width and paginated, then written as a PDF with subsets of the
+//! fonts embedded. The file is written by hand, with every stream
+//! deflated.
+
+use super::document_limits::{parse_html_limited, ParseLimits};
+use super::layout::{BlockKind, LayoutBuilder, PageContent};
+use crate::domain::{PagePrinter, PaperSize, ValidatedUrl};
+use async_trait::async_trait;
+use chrono::Utc;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::io::Write as _;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+use thiserror::Error;
+use ttf_parser::{name_id, Face, GlyphId, Tag};
+
+/// Space left around the text on each side, in points
+const MARGIN: f32 = 50.0;
+/// Body text size in points
+const FONT_SIZE: f32 = 11.0;
+/// Line height as a multiple of the largest text on the line
+const LINE_SPACING: f32 = 1.3;
+/// Heading sizes relative to body text, from `h1` to `h6`
+const HEADING_SCALES: [f32; 6] = [2.0, 1.5, 1.17, 1.0, 0.83, 0.67];
+/// Space above a heading as a multiple of its size, dropped at the top of a page
+const HEADING_SPACE: f32 = 0.6;
+/// Footer text size, and its baseline's height above the bottom edge
+const FOOTER_SIZE: f32 = 8.0;
+const FOOTER_BASELINE: f32 = 28.0;
+const LINK_COLOR: [f32; 3] = [0.0, 0.0, 0.8];
+const FOOTER_COLOR: [f32; 3] = [0.4, 0.4, 0.4];
+/// Width of a monospace character as a fraction of the font size, for
+/// fitting tables to the paper
+const MONOSPACE_ADVANCE: f32 = 0.6;
+/// What a tab prints as; fonts rarely have a glyph for it
+const TAB: &str = "    ";
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum FontError {
+    #[error("Unreadable font: {0}")]
+    Unreadable(String),
+    #[error("Only fonts with TrueType outlines can be embedded")]
+    NotTrueType,
+}
+
+/// A font that can be embedded in printed pages
+#[derive(Clone)]
+pub struct PdfFont {
+    data: Arc<Vec<u8>>,
+    index: u32,
+}
+
+impl std::fmt::Debug for PdfFont {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PdfFont")
+            .field("bytes", &self.data.len())
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+impl PdfFont {
+    /// Face `index` of a font file, which must have TrueType outlines to be
+    /// subset
+    pub fn from_data(data: Vec<u8>, index: u32) -> Result<Self, FontError> {
+        let face = Face::parse(&data, index).map_err(|e| FontError::Unreadable(e.to_string()))?;
+        let raw = face.raw_face();
+        if raw.table(Tag::from_bytes(b"glyf")).is_none() || raw.table(Tag::from_bytes(b"loca")).is_none() {
+            return Err(FontError::NotTrueType);
+        }
+        Ok(Self {
+            data: Arc::new(data),
+            index,
+        })
+    }
+
+    fn face(&self) -> Face<'_> {
+        Face::parse(&self.data, self.index).expect("font was checked when loaded")
+    }
+}
+
+/// Fonts printed text is set in
+#[derive(Debug, Clone)]
+pub struct PdfFonts {
+    pub regular: PdfFont,
+    pub bold: PdfFont,
+    pub italic: PdfFont,
+    pub bold_italic: PdfFont,
+    /// Code and tables; `regular` if unset
+    pub monospace: Option<PdfFont>,
+}
+
+impl PdfFonts {
+    /// The bundled Noto Sans faces
+    pub fn bundled() -> Self {
+        let font = |data: &[u8]| PdfFont::from_data(data.to_vec(), 0).expect("bundled fonts are TrueType");
+        Self {
+            regular: font(notosans::REGULAR_TTF),
+            bold: font(notosans::BOLD_TTF),
+            italic: font(notosans::ITALIC_TTF),
+            bold_italic: font(notosans::BOLD_ITALIC_TTF),
+            monospace: None,
+        }
+    }
+
+    fn font(&self, role: FontRole) -> &PdfFont {
+        match role {
+            FontRole::Regular => &self.regular,
+            FontRole::Bold => &self.bold,
+            FontRole::Italic => &self.italic,
+            FontRole::BoldItalic => &self.bold_italic,
+            FontRole::Monospace => self.monospace.as_ref().unwrap_or(&self.regular),
+        }
+    }
+}
+
+impl Default for PdfFonts {
+    fn default() -> Self {
+        Self::bundled()
+    }
+}
+
+type FontLoader = dyn Fn() -> PdfFonts + Send + Sync;
+
+/// Prints pages to PDF
+#[derive(Default)]
+pub struct PdfPrinter {
+    fonts: Arc<OnceLock<Arc<PdfFonts>>>,
+    load_fonts: Option<Arc<FontLoader>>,
+}
+
+impl PdfPrinter {
+    /// A printer setting text in the bundled fonts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set text in the fonts `load` finds, looked up on the first print
+    pub fn with_font_loader(mut self, load: impl Fn() -> PdfFonts + Send + Sync + 'static) -> Self {
+        self.load_fonts = Some(Arc::new(load));
+        self
+    }
+}
+
+#[async_trait]
+impl PagePrinter for PdfPrinter {
+    async fn print(&self, url: &ValidatedUrl, title: &str, html: &str, paper: PaperSize) -> Vec<u8> {
+        let (fonts, load_fonts) = (self.fonts.clone(), self.load_fonts.clone());
+        let (page_url, title, html) = (url.clone(), title.to_string(), html.to_string());
+        let span = tracing::Span::current();
+        let printed = tokio::task::spawn_blocking(move || {
+            let _span = span.entered();
+            let fonts = fonts.get_or_init(|| Arc::new(load_fonts.map_or_else(PdfFonts::bundled, |load| load())));
+            let (width, _) = paper.dimensions();
+            let columns = ((width - 2.0 * MARGIN) / (FONT_SIZE * MONOSPACE_ADVANCE)) as usize;
+            let (dom, _) = parse_html_limited(&html, None, &ParseLimits::default());
+            let content = LayoutBuilder::new().with_table_width(columns).build(&dom);
+            print_pdf(&content, &page_url, &title, paper, fonts)
+        })
+        .await;
+        match printed {
+            Ok(pdf) => pdf,
+            Err(e) => {
+                tracing::error!("Printing {} failed: {}", url, e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Faces text is set in; monospace doesn't come in bold or italic
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FontRole {
+    Regular,
+    Bold,
+    Italic,
+    BoldItalic,
+    Monospace,
+}
+
+const FONT_ROLES: [FontRole; 5] = [
+    FontRole::Regular,
+    FontRole::Bold,
+    FontRole::Italic,
+    FontRole::BoldItalic,
+    FontRole::Monospace,
+];
+
+/// The fonts of one print, parsed
+struct Faces<'a> {
+    faces: Vec<(FontRole, Face<'a>)>,
+}
+
+impl<'a> Faces<'a> {
+    fn new(fonts: &'a PdfFonts) -> Self {
+        Self {
+            faces: FONT_ROLES.iter().map(|role| (*role, fonts.font(*role).face())).collect(),
+        }
+    }
+
+    fn get(&self, role: FontRole) -> &Face<'a> {
+        &self.faces.iter().find(|(r, _)| *r == role).expect("every role has a face").1
+    }
+
+    fn glyph(&self, role: FontRole, c: char) -> GlyphId {
+        self.get(role).glyph_index(c).unwrap_or(GlyphId(0))
+    }
+
+    /// Advance of `c` at `size` points
+    fn advance(&self, role: FontRole, size: f32, c: char) -> f32 {
+        let face = self.get(role);
+        let advance = face.glyph_hor_advance(self.glyph(role, c)).unwrap_or(0);
+        f32::from(advance) * size / f32::from(face.units_per_em())
+    }
+
+    fn width(&self, role: FontRole, size: f32, text: &str) -> f32 {
+        text.chars().map(|c| self.advance(role, size, c)).sum()
+    }
+}
+
+/// A run of text in one font on a printed line
+#[derive(Debug, Clone, PartialEq)]
+struct Run {
+    text: String,
+    role: FontRole,
+    size: f32,
+    /// Absolute URL the run links to
+    link: Option<String>,
+    /// Offset from the left margin
+    x: f32,
+    width: f32,
+}
+
+/// A line of printed text
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    runs: Vec<Run>,
+    /// Largest text size on the line
+    size: f32,
+    height: f32,
+    /// Gap above the line unless it starts a page
+    space_before: f32,
+    /// Headings stay on the page of the line after them
+    keep_with_next: bool,
+}
+
+impl Line {
+    fn empty(size: f32) -> Self {
+        Self {
+            runs: Vec::new(),
+            size,
+            height: size * LINE_SPACING,
+            space_before: 0.0,
+            keep_with_next: false,
+        }
+    }
+}
+
+/// How a character of the laid-out text prints
+#[derive(Debug, Clone, PartialEq)]
+struct CharStyle {
+    role: FontRole,
+    size: f32,
+    link: Option<String>,
+}
+
+/// A character placed on a line, with its advance
+#[derive(Debug)]
+struct Placed<'a> {
+    text: &'a str,
+    style: usize,
+    width: f32,
+}
+
+/// Wrap the laid-out page into lines at most `width` points wide
+fn print_lines(content: &PageContent, page_url: &ValidatedUrl, faces: &Faces, width: f32) -> Vec<Line> {
+    let text = content.text.as_str();
+    let mut links: Vec<_> = content.links.iter().collect();
+    links.sort_by_key(|link| link.range.start);
+    let targets: Vec<Option<String>> = links
+        .iter()
+        .map(|link| page_url.join(&link.href).ok().map(|url| url.to_string()))
+        .collect();
+
+    let style_at = |offset: usize| {
+        let index = content.styles.partition_point(|span| span.range.end <= offset);
+        content.styles.get(index).filter(|span| span.range.contains(&offset)).map(|span| span.style)
+    };
+    let link_at = |offset: usize| {
+        let index = links.partition_point(|link| link.range.start <= offset);
+        let index = index.checked_sub(1).filter(|index| links[*index].range.contains(&offset))?;
+        targets[index].clone()
+    };
+    let block_at = |offset: usize| content.blocks.iter().find(|block| block.range.contains(&offset));
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for source in text.split('\n') {
+        let range = start..start + source.len();
+        start = range.end + 1;
+        let block = block_at(range.start).map(|block| block.kind);
+        let scale = match block {
+            Some(BlockKind::Heading(level)) => HEADING_SCALES[usize::from(level.clamp(1, 6)) - 1],
+            _ => 1.0,
+        };
+        let is_heading = matches!(block, Some(BlockKind::Heading(_)));
+
+        // Style of every character, deduplicated so runs can be compared by index
+        let mut styles: Vec<CharStyle> = Vec::new();
+        let mut placed = Vec::with_capacity(source.len());
+        for (i, c) in source.char_indices() {
+            let offset = range.start + i;
+            let style = style_at(offset).unwrap_or_default();
+            let bold = style.bold || is_heading;
+            let role = match (style.monospace, bold, style.italic) {
+                (true, _, _) => FontRole::Monospace,
+                (false, true, true) => FontRole::BoldItalic,
+                (false, true, false) => FontRole::Bold,
+                (false, false, true) => FontRole::Italic,
+                (false, false, false) => FontRole::Regular,
+            };
+            let size = FONT_SIZE * scale * style.font_scale.map_or(1.0, |percent| f32::from(percent) / 100.0);
+            let char_style = CharStyle {
+                role,
+                size,
+                link: link_at(offset),
+            };
+            let index = match styles.iter().position(|existing| *existing == char_style) {
+                Some(index) => index,
+                None => {
+                    styles.push(char_style);
+                    styles.len() - 1
+                }
+            };
+            let text = if c == '\t' { TAB } else { &source[i..i + c.len_utf8()] };
+            let width = faces.width(role, styles[index].size, text);
+            placed.push(Placed { text, style: index, width });
+        }
+
+        let first = lines.len();
+        for wrapped in wrap(&placed, width) {
+            lines.push(line(&placed[wrapped], &styles, scale));
+        }
+        if lines.len() == first {
+            lines.push(Line::empty(FONT_SIZE * scale));
+        }
+        if is_heading {
+            lines[first].space_before = lines[first].size * HEADING_SPACE;
+            for line in &mut lines[first..] {
+                line.keep_with_next = true;
+            }
+        }
+    }
+    lines
+}
+
+/// Ranges of `placed` filling lines `width` wide, broken at spaces where
+/// possible; the spaces broken at are left out
+fn wrap(placed: &[Placed], width: f32) -> Vec<Range<usize>> {
+    let mut lines = Vec::new();
+    let (mut start, mut x) = (0, 0.0);
+    let mut last_space = None;
+    for (i, character) in placed.iter().enumerate() {
+        if character.text == " " {
+            last_space = Some(i);
+        } else if x + character.width > width && i > start {
+            match last_space.filter(|space| *space > start) {
+                Some(space) => {
+                    lines.push(start..space);
+                    start = space + 1;
+                }
+                None => {
+                    lines.push(start..i);
+                    start = i;
+                }
+            }
+            x = placed[start..i].iter().map(|c| c.width).sum();
+            last_space = None;
+        }
+        x += character.width;
+    }
+    if start < placed.len() {
+        lines.push(start..placed.len());
+    }
+    lines
+}
+
+/// Group characters of one line into runs
+fn line(placed: &[Placed], styles: &[CharStyle], scale: f32) -> Line {
+    let mut runs: Vec<Run> = Vec::new();
+    let mut x = 0.0;
+    let mut previous = None;
+    for character in placed {
+        let style = &styles[character.style];
+        match runs.last_mut().filter(|_| previous == Some(character.style)) {
+            Some(run) => {
+                run.text.push_str(character.text);
+                run.width += character.width;
+            }
+            None => runs.push(Run {
+                text: character.text.to_string(),
+                role: style.role,
+                size: style.size,
+                link: style.link.clone(),
+                x,
+                width: character.width,
+            }),
+        }
+        previous = Some(character.style);
+        x += character.width;
+    }
+    let size = runs.iter().map(|run| run.size).fold(FONT_SIZE * scale, f32::max);
+    Line {
+        runs,
+        height: size * LINE_SPACING,
+        ..Line::empty(size)
+    }
+}
+
+/// Lines on each page as ranges of `lines`, filling at most `available`
+/// points of height. A heading that would end a page starts the next one
+/// instead, unless it is too tall to fit with its next line on any page.
+fn paginate(lines: &[Line], available: f32) -> Vec<Range<usize>> {
+    let mut pages = Vec::new();
+    let (mut start, mut used) = (0, 0.0);
+    let needed = |i: usize, start: usize| {
+        let line = &lines[i];
+        if i == start {
+            line.height
+        } else {
+            line.space_before + line.height
+        }
+    };
+    let mut i = 0;
+    while i < lines.len() {
+        let height = needed(i, start);
+        let mut breaks = i > start && used + height > available;
+        if !breaks && i > start && lines[i].keep_with_next {
+            // The heading, and the line after it
+            let end = (i..lines.len())
+                .find(|j| !lines[*j].keep_with_next)
+                .map_or(lines.len(), |j| j + 1);
+            let group: f32 = (i..end).map(|j| needed(j, start)).sum();
+            let on_new_page = group - lines[i].space_before;
+            breaks = used + group > available && on_new_page <= available;
+        }
+        if breaks {
+            pages.push(start..i);
+            start = i;
+            used = 0.0;
+            continue;
+        }
+        used += height;
+        i += 1;
+    }
+    if start < lines.len() || pages.is_empty() {
+        pages.push(start..lines.len());
+    }
+    pages
+}
+
+/// A font used in the document and the glyphs used from it
+#[derive(Debug)]
+struct UsedFont {
+    role: FontRole,
+    /// Object number of its `Type0` font dictionary
+    id: usize,
+    /// Glyph IDs and the text each stands for
+    glyphs: BTreeMap<u16, char>,
+}
+
+/// PDF objects written so far and where they start
+#[derive(Debug)]
+struct PdfWriter {
+    pdf: Vec<u8>,
+    offsets: Vec<Option<usize>>,
+}
+
+impl PdfWriter {
+    fn new() -> Self {
+        // A comment of high bytes marks the file as binary
+        let mut pdf = b"%PDF-1.7\n%".to_vec();
+        pdf.extend_from_slice(&[0xe2, 0xe3, 0xcf, 0xd3, b'\n']);
+        Self {
+            pdf,
+            offsets: Vec::new(),
+        }
+    }
+
+    /// Number an object to be written later
+    fn reserve(&mut self) -> usize {
+        self.offsets.push(None);
+        self.offsets.len()
+    }
+
+    fn object(&mut self, id: usize, body: &str) {
+        self.offsets[id - 1] = Some(self.pdf.len());
+        self.pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", id, body).as_bytes());
+    }
+
+    /// A stream with `entries` added to its dictionary, compressed
+    fn stream(&mut self, id: usize, entries: &str, data: &[u8]) {
+        let mut encoder = ZlibEncoder::new(Vec::with_capacity(data.len() / 2), Compression::default());
+        // Writing to memory cannot fail
+        let data = encoder.write_all(data).and_then(|_| encoder.finish()).unwrap_or_default();
+        self.offsets[id - 1] = Some(self.pdf.len());
+        let dictionary = format!(
+            "{} 0 obj\n<< /Length {} /Filter /FlateDecode{} >>\nstream\n",
+            id,
+            data.len(),
+            entries
+        );
+        self.pdf.extend_from_slice(dictionary.as_bytes());
+        self.pdf.extend_from_slice(&data);
+        self.pdf.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    fn finish(mut self, root: usize, info: usize) -> Vec<u8> {
+        let xref = self.pdf.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            // Reserved objects are always written; an unused one is a free entry
+            match offset {
+                Some(offset) => writeln!(table, "{:010} 00000 n ", offset),
+                None => writeln!(table, "0000000000 65535 f "),
+            }
+            .ok();
+        }
+        write!(
+            table,
+            "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            root,
+            info,
+            xref
+        )
+        .ok();
+        self.pdf.extend_from_slice(table.as_bytes());
+        self.pdf
+    }
+}
+
+/// Write the laid-out page as a PDF on `paper`
+fn print_pdf(
+    content: &PageContent,
+    url: &ValidatedUrl,
+    title: &str,
+    paper: PaperSize,
+    fonts: &PdfFonts,
+) -> Vec<u8> {
+    let faces = Faces::new(fonts);
+    let (page_width, page_height) = paper.dimensions();
+    let text_width = page_width - 2.0 * MARGIN;
+    let lines = print_lines(content, url, &faces, text_width);
+    let pages = paginate(&lines, page_height - 2.0 * MARGIN);
+
+    let mut writer = PdfWriter::new();
+    let (catalog, page_tree) = (writer.reserve(), writer.reserve());
+    let (info, resources) = (writer.reserve(), writer.reserve());
+    let mut used: Vec<UsedFont> = Vec::new();
+    let mut page_ids = Vec::new();
+
+    for (number, page) in pages.iter().enumerate() {
+        let mut stream = String::new();
+        let mut annotations = Vec::new();
+        let mut top = page_height - MARGIN;
+        for (i, line) in lines[page.clone()].iter().enumerate() {
+            if i > 0 {
+                top -= line.space_before;
+            }
+            // Center the text in its line, resting on the face's baseline
+            let baseline = top - (line.height - line.size) / 2.0 - line.size * ascent(faces.get(FontRole::Regular));
+            top -= line.height;
+            if line.runs.is_empty() {
+                continue;
+            }
+            stream.push_str("BT\n");
+            for run in &line.runs {
+                let color = if run.link.is_some() { LINK_COLOR } else { [0.0; 3] };
+                let font = font_name(&mut used, &mut writer, run.role);
+                writeln!(stream, "{} {} Tf", font, number_text(run.size)).ok();
+                writeln!(stream, "{} rg", color_text(color)).ok();
+                writeln!(stream, "1 0 0 1 {} {} Tm", number_text(MARGIN + run.x), number_text(baseline)).ok();
+                writeln!(stream, "<{}> Tj", encode(&mut used, &faces, run.role, &run.text)).ok();
+            }
+            stream.push_str("ET\n");
+
+            for run in line.runs.iter().filter(|run| run.link.is_some()) {
+                let (left, right) = (MARGIN + run.x, MARGIN + run.x + run.width);
+                let underline = baseline - run.size * 0.12;
+                writeln!(
+                    stream,
+                    "{} RG {} w {} {} m {} {} l S",
+                    color_text(LINK_COLOR),
+                    number_text(run.size * 0.06),
+                    number_text(left),
+                    number_text(underline),
+                    number_text(right),
+                    number_text(underline)
+                )
+                .ok();
+                annotations.push(format!(
+                    "<< /Type /Annot /Subtype /Link /Rect [{} {} {} {}] /Border [0 0 0] \
+                     /A << /S /URI /URI {} >> >>",
+                    number_text(left),
+                    number_text(baseline - run.size * 0.25),
+                    number_text(right),
+                    number_text(baseline + run.size * 0.85),
+                    literal(run.link.as_deref().unwrap_or_default())
+                ));
+            }
+        }
+
+        // Footer: the address on the left, the page number on the right
+        let page_label = format!("Page {} of {}", number + 1, pages.len());
+        let label_width = faces.width(FontRole::Regular, FOOTER_SIZE, &page_label);
+        let address = fit(&faces, url.as_str(), text_width - label_width - FOOTER_SIZE * 2.0);
+        let font = font_name(&mut used, &mut writer, FontRole::Regular);
+        for (x, text) in [(MARGIN, address), (page_width - MARGIN - label_width, page_label)] {
+            writeln!(stream, "BT\n{} {} Tf\n{} rg", font, number_text(FOOTER_SIZE), color_text(FOOTER_COLOR)).ok();
+            writeln!(stream, "1 0 0 1 {} {} Tm", number_text(x), number_text(FOOTER_BASELINE)).ok();
+            writeln!(stream, "<{}> Tj\nET", encode(&mut used, &faces, FontRole::Regular, &text)).ok();
+        }
+
+        let (page_id, contents) = (writer.reserve(), writer.reserve());
+        writer.stream(contents, "", stream.as_bytes());
+        let mut annotation_ids = Vec::new();
+        for annotation in annotations {
+            let id = writer.reserve();
+            writer.object(id, &annotation);
+            annotation_ids.push(format!("{} 0 R", id));
+        }
+        writer.object(
+            page_id,
+            &format!(
+                "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources {} 0 R /Contents {} 0 R \
+                 /Annots [{}] >>",
+                page_tree,
+                number_text(page_width),
+                number_text(page_height),
+                resources,
+                contents,
+                annotation_ids.join(" ")
+            ),
+        );
+        page_ids.push(format!("{} 0 R", page_id));
+    }
+
+    let mut font_entries = String::new();
+    for (index, font) in used.iter().enumerate() {
+        write!(font_entries, "/F{} {} 0 R ", index + 1, font.id).ok();
+        embed_font(&mut writer, font, faces.get(font.role), index);
+    }
+    writer.object(resources, &format!("<< /Font << {}>> >>", font_entries));
+    writer.object(
+        page_tree,
+        &format!("<< /Type /Pages /Kids [{}] /Count {} >>", page_ids.join(" "), page_ids.len()),
+    );
+    writer.object(catalog, &format!("<< /Type /Catalog /Pages {} 0 R >>", page_tree));
+    writer.object(
+        info,
+        &format!(
+            "<< /Title {} /Producer (Navigator) /CreationDate ({}) >>",
+            text_string(title),
+            Utc::now().format("D:%Y%m%d%H%M%SZ")
+        ),
+    );
+    writer.finish(catalog, info)
+}
+
+/// Height of the face above the baseline, as a fraction of its size
+fn ascent(face: &Face) -> f32 {
+    let (ascender, descender) = (f32::from(face.ascender()), f32::from(face.descender()));
+    if ascender - descender <= 0.0 {
+        return 0.8;
+    }
+    ascender / (ascender - descender)
+}
+
+/// Resource name of the font for `role`, adding it if it is the first use
+fn font_name(used: &mut Vec<UsedFont>, writer: &mut PdfWriter, role: FontRole) -> String {
+    let index = match used.iter().position(|font| font.role == role) {
+        Some(index) => index,
+        None => {
+            used.push(UsedFont {
+                role,
+                id: writer.reserve(),
+                glyphs: BTreeMap::new(),
+            });
+            used.len() - 1
+        }
+    };
+    format!("/F{}", index + 1)
+}
+
+/// Hex glyph IDs of `text`, recording the glyphs as used
+fn encode(used: &mut [UsedFont], faces: &Faces, role: FontRole, text: &str) -> String {
+    let glyphs = &mut used
+        .iter_mut()
+        .find(|font| font.role == role)
+        .expect("fonts are named before use")
+        .glyphs;
+    let mut hex = String::with_capacity(text.len() * 4);
+    for c in text.chars() {
+        let glyph = faces.glyph(role, c).0;
+        glyphs.entry(glyph).or_insert(c);
+        write!(hex, "{:04X}", glyph).ok();
+    }
+    hex
+}
+
+/// `text` cut short with an ellipsis to fit `width` points of footer
+fn fit(faces: &Faces, text: &str, width: f32) -> String {
+    if faces.width(FontRole::Regular, FOOTER_SIZE, text) <= width {
+        return text.to_string();
+    }
+    let mut fitted = String::new();
+    let mut used = faces.advance(FontRole::Regular, FOOTER_SIZE, '…');
+    for c in text.chars() {
+        used += faces.advance(FontRole::Regular, FOOTER_SIZE, c);
+        if used > width {
+            break;
+        }
+        fitted.push(c);
+    }
+    fitted.push('…');
+    fitted
+}
+
+/// Embed the glyphs used from a font as a Type 0 font with Identity-H
+/// encoding, so content streams address glyphs by ID
+fn embed_font(writer: &mut PdfWriter, font: &UsedFont, face: &Face, index: usize) {
+    let (descendant, descriptor, file, to_unicode) =
+        (writer.reserve(), writer.reserve(), writer.reserve(), writer.reserve());
+    // Subsets are named with a tag of six capitals
+    let tag: String = format!("NV{:04}", index)
+        .bytes()
+        .map(|b| if b.is_ascii_digit() { (b - b'0' + b'A') as char } else { b as char })
+        .collect();
+    let name = format!("{}+{}", tag, postscript_name(face));
+    let scale = 1000.0 / f32::from(face.units_per_em());
+
+    let glyphs: BTreeSet<u16> = font.glyphs.keys().copied().collect();
+    let subset = subset_font(face, &glyphs);
+    writer.stream(file, &format!(" /Length1 {}", subset.len()), &subset);
+
+    let bbox = face.global_bounding_box();
+    let mut flags = 32;
+    if face.is_monospaced() {
+        flags |= 1;
+    }
+    if face.is_italic() {
+        flags |= 64;
+    }
+    writer.object(
+        descriptor,
+        &format!(
+            "<< /Type /FontDescriptor /FontName /{} /Flags {} /FontBBox [{} {} {} {}] /ItalicAngle {} \
+             /Ascent {} /Descent {} /CapHeight {} /StemV 80 /FontFile2 {} 0 R >>",
+            name,
+            flags,
+            number_text(f32::from(bbox.x_min) * scale),
+            number_text(f32::from(bbox.y_min) * scale),
+            number_text(f32::from(bbox.x_max) * scale),
+            number_text(f32::from(bbox.y_max) * scale),
+            number_text(face.italic_angle().unwrap_or(0.0)),
+            number_text(f32::from(face.ascender()) * scale),
+            number_text(f32::from(face.descender()) * scale),
+            number_text(f32::from(face.capital_height().unwrap_or(face.ascender())) * scale),
+            file
+        ),
+    );
+
+    let mut widths = String::new();
+    for glyph in &glyphs {
+        let advance = face.glyph_hor_advance(GlyphId(*glyph)).unwrap_or(0);
+        write!(widths, "{} [{}] ", glyph, number_text(f32::from(advance) * scale)).ok();
+    }
+    writer.object(
+        descendant,
+        &format!(
+            "<< /Type /Font /Subtype /CIDFontType2 /BaseFont /{} /CIDSystemInfo << /Registry (Adobe) \
+             /Ordering (Identity) /Supplement 0 >> /FontDescriptor {} 0 R /W [{}] /CIDToGIDMap /Identity >>",
+            name, descriptor, widths
+        ),
+    );
+    writer.stream(to_unicode, "", to_unicode_cmap(&font.glyphs).as_bytes());
+    writer.object(
+        font.id,
+        &format!(
+            "<< /Type /Font /Subtype /Type0 /BaseFont /{} /Encoding /Identity-H /DescendantFonts [{} 0 R] \
+             /ToUnicode {} 0 R >>",
+            name, descendant, to_unicode
+        ),
+    );
+}
+
+/// The face's PostScript name, reduced to characters PDF names allow
+fn postscript_name(face: &Face) -> String {
+    let name: String = face
+        .names()
+        .into_iter()
+        .filter(|name| name.name_id == name_id::POST_SCRIPT_NAME)
+        .find_map(|name| name.to_string())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    if name.is_empty() {
+        "Font".to_string()
+    } else {
+        name
+    }
+}
+
+/// CMap mapping glyph IDs back to text, so printed text can be copied
+fn to_unicode_cmap(glyphs: &BTreeMap<u16, char>) -> String {
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n12 dict begin\nbegincmap\n\
+         /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+         /CMapName /Adobe-Identity-UCS def\n/CMapType 2 def\n\
+         1 begincodespacerange\n<0000> <FFFF>\nendcodespacerange\n",
+    );
+    // Nothing maps to the missing glyph
+    let mapped: Vec<_> = glyphs.iter().filter(|(glyph, _)| **glyph != 0).collect();
+    // At most 100 entries to a block
+    for block in mapped.chunks(100) {
+        writeln!(cmap, "{} beginbfchar", block.len()).ok();
+        for (glyph, c) in block {
+            let utf16: String = c.encode_utf16(&mut [0; 2]).iter().map(|unit| format!("{:04X}", unit)).collect();
+            writeln!(cmap, "<{:04X}> <{}>", glyph, utf16).ok();
+        }
+        cmap.push_str("endbfchar\n");
+    }
+    cmap.push_str("endcmap\nCMapName currentdict /CMap defineresource pop\nend\nend\n");
+    cmap
+}
+
+/// A copy of the font keeping only the outlines of `glyphs`, the missing
+/// glyph and the glyphs they are built from. Glyph IDs are unchanged, so
+/// the rest are left empty rather than removed.
+fn subset_font(face: &Face, glyphs: &BTreeSet<u16>) -> Vec<u8> {
+    let raw = face.raw_face();
+    let table = |tag: &[u8; 4]| raw.table(Tag::from_bytes(tag));
+    let (Some(head), Some(loca), Some(glyf)) = (table(b"head"), table(b"loca"), table(b"glyf")) else {
+        return Vec::new();
+    };
+    if head.len() < 54 {
+        return Vec::new();
+    }
+    let count = usize::from(face.number_of_glyphs());
+    let long_offsets = read_u16(head, 50) == Some(1);
+    let offset = |glyph: usize| -> Option<usize> {
+        if long_offsets {
+            read_u32(loca, glyph * 4).map(|offset| offset as usize)
+        } else {
+            read_u16(loca, glyph * 2).map(|offset| usize::from(offset) * 2)
+        }
+    };
+    let outline = |glyph: usize| -> &[u8] {
+        match (offset(glyph), offset(glyph + 1)) {
+            (Some(start), Some(end)) if start <= end && end <= glyf.len() => &glyf[start..end],
+            _ => &[],
+        }
+    };
+
+    // Composite glyphs pull in the glyphs they are made of
+    let mut kept = BTreeSet::new();
+    let mut pending: Vec<u16> = glyphs.iter().copied().chain([0]).collect();
+    while let Some(glyph) = pending.pop() {
+        if usize::from(glyph) >= count || !kept.insert(glyph) {
+            continue;
+        }
+        pending.extend(components(outline(usize::from(glyph))).into_iter().filter(|c| !kept.contains(c)));
+    }
+
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((count + 1) * 4);
+    for glyph in 0..count {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if kept.contains(&(glyph as u16)) {
+            new_glyf.extend_from_slice(outline(glyph));
+            while new_glyf.len() % 4 != 0 {
+                new_glyf.push(0);
+            }
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    let mut new_head = head.to_vec();
+    // Checksum adjustment is recomputed below; offsets are now long
+    new_head[8..12].fill(0);
+    new_head[50..52].copy_from_slice(&1u16.to_be_bytes());
+
+    let mut tables: Vec<([u8; 4], Vec<u8>)> = vec![
+        (*b"glyf", new_glyf),
+        (*b"head", new_head),
+        (*b"loca", new_loca),
+    ];
+    // Metrics and hinting carry over unchanged
+    for tag in [b"cvt ", b"fpgm", b"hhea", b"hmtx", b"maxp", b"prep"] {
+        if let Some(data) = table(tag) {
+            tables.push((*tag, data.to_vec()));
+        }
+    }
+    tables.sort_by_key(|(tag, _)| *tag);
+    let mut font = font_file(&tables);
+
+    // The head checksum makes the whole file sum to a magic number
+    if let Some(head_offset) = font_table_offset(&font, b"head") {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&font));
+        font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    font
+}
+
+/// Glyphs a composite glyph is built from
+fn components(outline: &[u8]) -> Vec<u16> {
+    const ARGS_ARE_WORDS: u16 = 0x0001;
+    const HAS_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const HAS_XY_SCALE: u16 = 0x0040;
+    const HAS_TWO_BY_TWO: u16 = 0x0080;
+
+    let mut glyphs = Vec::new();
+    // Simple glyphs have a non-negative contour count
+    if read_u16(outline, 0).is_none_or(|contours| contours as i16 >= 0) {
+        return glyphs;
+    }
+    let mut at = 10;
+    while let (Some(flags), Some(glyph)) = (read_u16(outline, at), read_u16(outline, at + 2)) {
+        glyphs.push(glyph);
+        at += 4 + if flags & ARGS_ARE_WORDS != 0 { 4 } else { 2 };
+        if flags & HAS_SCALE != 0 {
+            at += 2;
+        } else if flags & HAS_XY_SCALE != 0 {
+            at += 4;
+        } else if flags & HAS_TWO_BY_TWO != 0 {
+            at += 8;
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    glyphs
+}
+
+/// A TrueType file of `tables`, sorted by tag
+fn font_file(tables: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let count = tables.len() as u16;
+    let power = (count.max(1)).ilog2() as u16;
+    let search_range = (1u16 << power) * 16;
+    let mut font = Vec::new();
+    font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    for value in [count, search_range, power, count * 16 - search_range] {
+        font.extend_from_slice(&value.to_be_bytes());
+    }
+    let mut offset = 12 + tables.len() * 16;
+    for (tag, data) in tables {
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&checksum(data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += data.len().div_ceil(4) * 4;
+    }
+    for (_, data) in tables {
+        font.extend_from_slice(data);
+        font.resize(font.len().div_ceil(4) * 4, 0);
+    }
+    font
+}
+
+/// Where a table of a font written by `font_file` starts
+fn font_table_offset(font: &[u8], wanted: &[u8; 4]) -> Option<usize> {
+    let count = usize::from(read_u16(font, 4)?);
+    (0..count)
+        .map(|i| 12 + i * 16)
+        .find(|record| font.get(*record..*record + 4) == Some(wanted.as_slice()))
+        .and_then(|record| read_u32(font, record + 8))
+        .map(|offset| offset as usize)
+}
+
+/// Sum of big-endian 32-bit words, the last padded with zeros
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, word| {
+        let mut padded = [0; 4];
+        padded[..word.len()].copy_from_slice(word);
+        sum.wrapping_add(u32::from_be_bytes(padded))
+    })
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// A number as PDF writes it, to two decimals without trailing zeros
+fn number_text(value: f32) -> String {
+    let text = format!("{:.2}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" | "" => "0".to_string(),
+        _ => text.to_string(),
+    }
+}
+
+fn color_text([r, g, b]: [f32; 3]) -> String {
+    format!("{} {} {}", number_text(r), number_text(g), number_text(b))
+}
+
+/// An ASCII string literal, such as a URL
+fn literal(text: &str) -> String {
+    let mut literal = String::from("(");
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            c if c.is_ascii() && !c.is_ascii_control() => literal.push(c),
+            c => {
+                for byte in c.to_string().bytes() {
+                    write!(literal, "%{:02X}", byte).ok();
+                }
+            }
+        }
+    }
+    literal.push(')');
+    literal
+}
+
+/// A text string of any characters, as UTF-16 with a byte order mark
+fn text_string(text: &str) -> String {
+    let mut hex = String::from("<FEFF");
+    for unit in text.encode_utf16() {
+        write!(hex, "{:04X}", unit).ok();
+    }
+    hex.push('>');
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut inflated = Vec::new();
+        flate2::read::ZlibDecoder::new(data).read_to_end(&mut inflated).unwrap();
+        inflated
+    }
+
+    /// Every stream holding text operators, decompressed
+    fn page_contents(pdf: &[u8]) -> String {
+        let find = |from: usize, needle: &[u8]| {
+            pdf[from..].windows(needle.len()).position(|window| window == needle).map(|at| from + at)
+        };
+        let mut contents = String::new();
+        let mut at = 0;
+        while let Some(length_at) = find(at, b"/Length ") {
+            let digits = pdf[length_at + 8..].iter().take_while(|byte| byte.is_ascii_digit()).count();
+            let length = std::str::from_utf8(&pdf[length_at + 8..length_at + 8 + digits]).unwrap();
+            let length: usize = length.parse().unwrap();
+            let start = find(length_at, b"\nstream\n").unwrap() + 8;
+            let stream = String::from_utf8_lossy(&inflate(&pdf[start..start + length])).into_owned();
+            if stream.contains(" Tf\n") {
+                contents.push_str(&stream);
+            }
+            at = start + length;
+        }
+        contents
+    }
+
+    /// Text of each page, a line per text object, read back through the
+    /// fonts' ToUnicode maps
+    fn extract_text(pdf: &[u8]) -> Vec<String> {
+        let text = String::from_utf8_lossy(pdf);
+        let xref = text.rfind("\nxref\n").unwrap() + 1;
+        let entries: Vec<&str> = text[xref..]
+            .lines()
+            .skip(2)
+            .take_while(|line| !line.starts_with("trailer"))
+            .collect();
+        let mut objects = HashMap::new();
+        for (id, entry) in entries.iter().enumerate() {
+            if entry.ends_with("n ") || entry.ends_with('n') {
+                let offset: usize = entry[..10].parse().unwrap();
+                objects.insert(id, offset);
+            }
+        }
+        let object = |id: usize| -> (String, Vec<u8>) {
+            let start = objects[&id];
+            let rest = &pdf[start..];
+            let header = String::from_utf8_lossy(&rest[..rest.len().min(4096)]).into_owned();
+            let dictionary_end = header.find("\nstream\n").or_else(|| header.find("\nendobj")).unwrap();
+            let dictionary = header[..dictionary_end].to_string();
+            let data = match header[..].find("\nstream\n").filter(|at| *at == dictionary_end) {
+                Some(at) => {
+                    let length: usize = dictionary
+                        .split("/Length ")
+                        .nth(1)
+                        .unwrap()
+                        .split(|c: char| !c.is_ascii_digit())
+                        .next()
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    inflate(&rest[at + 8..at + 8 + length])
+                }
+                None => Vec::new(),
+            };
+            (dictionary, data)
+        };
+        let reference = |dictionary: &str, key: &str| -> usize {
+            let after = dictionary.split(key).nth(1).unwrap();
+            after.split_whitespace().next().unwrap().parse().unwrap()
+        };
+
+        let trailer = &text[text.rfind("trailer").unwrap()..];
+        let (catalog, _) = object(reference(trailer, "/Root "));
+        let (pages, _) = object(reference(&catalog, "/Pages "));
+        let kids = pages.split("/Kids [").nth(1).unwrap().split(']').next().unwrap().to_string();
+        let kids: Vec<usize> = kids.split(" 0 R").filter_map(|id| id.trim().parse().ok()).collect();
+
+        kids.iter()
+            .map(|page| {
+                let (page, _) = object(*page);
+                let (resources, _) = object(reference(&page, "/Resources "));
+                let mut maps: HashMap<String, HashMap<String, String>> = HashMap::new();
+                let fonts = resources.split("/Font <<").nth(1).unwrap().to_string();
+                for entry in fonts.split(" 0 R").filter(|entry| entry.contains("/F")) {
+                    let mut parts = entry.split_whitespace();
+                    let (name, id) = (parts.next().unwrap().to_string(), parts.next().unwrap().parse().unwrap());
+                    let (font, _) = object(id);
+                    let (_, cmap) = object(reference(&font, "/ToUnicode "));
+                    let cmap = String::from_utf8(cmap).unwrap();
+                    let map = cmap
+                        .lines()
+                        .filter(|line| line.starts_with('<') && !line.contains("<0000> <FFFF>"))
+                        .map(|line| {
+                            let (glyph, unicode) = line.split_once(' ').unwrap();
+                            let unicode = unicode.trim_matches(['<', '>']);
+                            let units: Vec<u16> = (0..unicode.len())
+                                .step_by(4)
+                                .map(|i| u16::from_str_radix(&unicode[i..i + 4], 16).unwrap())
+                                .collect();
+                            (glyph.trim_matches(['<', '>']).to_string(), String::from_utf16(&units).unwrap())
+                        })
+                        .collect();
+                    maps.insert(name, map);
+                }
+
+                let (_, contents) = object(reference(&page, "/Contents "));
+                let (mut lines, mut line, mut font) = (Vec::new(), String::new(), String::new());
+                for op in String::from_utf8(contents).unwrap().lines() {
+                    if op.ends_with(" Tf") {
+                        font = op.split_whitespace().next().unwrap().to_string();
+                    } else if let Some(hex) = op.strip_suffix("> Tj") {
+                        let hex = hex.trim_start_matches('<');
+                        for i in (0..hex.len()).step_by(4) {
+                            line.push_str(maps[&font].get(&hex[i..i + 4]).map_or("\u{fffd}", String::as_str));
+                        }
+                    } else if op == "ET" {
+                        lines.push(std::mem::take(&mut line));
+                    }
+                }
+                lines.join("\n")
+            })
+            .collect()
+    }
+
+    fn print(html: &str, paper: PaperSize) -> Vec<u8> {
+        let url = ValidatedUrl::parse("https://example.com/docs/print").unwrap();
+        let (dom, _) = parse_html_limited(html, None, &ParseLimits::default());
+        let content = LayoutBuilder::new().build(&dom);
+        print_pdf(&content, &url, "Printed", paper, &PdfFonts::bundled())
+    }
+
+    fn line(height: f32, keep_with_next: bool) -> Line {
+        Line {
+            height,
+            keep_with_next,
+            ..Line::empty(FONT_SIZE)
+        }
+    }
+
+    #[test]
+    fn test_pages_paginate_for_the_paper() {
+        // 150 single lines; 51 fit on A4 and 48 on Letter
+        let html: String = (1..=150).map(|i| format!("<p>Line {}</p>", i)).collect();
+        let a4 = extract_text(&print(&html, PaperSize::A4));
+        assert_eq!(a4.len(), 3);
+        assert!(a4[0].starts_with("Line 1\nLine 2\n"));
+        assert!(a4[0].contains("Line 51\nhttps://example.com/docs/print\nPage 1 of 3"));
+        assert!(a4[1].starts_with("Line 52\n"));
+        assert!(a4[2].ends_with("Line 150\nhttps://example.com/docs/print\nPage 3 of 3"));
+        assert_eq!(extract_text(&print(&html, PaperSize::Letter)).len(), 4);
+    }
+
+    #[test]
+    fn test_styled_text_and_links_print() {
+        let pdf = print(
+            "<h1>Guide</h1><p>Read <b>this</b> and <i>café</i> <a href=\"../next\">next</a></p>\
+             <pre>fn main() {}</pre>",
+            PaperSize::A4,
+        );
+        let pages = extract_text(&pdf);
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].starts_with("Guide\nRead this and café next\nfn main() {}\n"));
+
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/URI (https://example.com/next)"));
+        assert!(text.contains("/Title <FEFF005000720069006E007400650064>"));
+        // Regular, bold for the heading and bold text, italic and monospace
+        assert_eq!(text.matches("/Subtype /Type0").count(), 4);
+        assert!(text.contains("/Font << /F1 "));
+        // The heading comes first, in bold at twice the body size
+        assert!(page_contents(&pdf).contains("/F1 22 Tf"));
+        assert!(text.contains("/Filter /FlateDecode"));
+        assert!(!text.contains(" Tf\n"), "content streams are compressed");
+    }
+
+    #[test]
+    fn test_headings_stay_with_their_next_line() {
+        // Room for 49 lines and the heading, but not the line after it
+        let mut html: String = (1..=49).map(|i| format!("<p>Line {}</p>", i)).collect();
+        html.push_str("<h2>Chapter two</h2><p>First line</p>");
+        let pages = extract_text(&print(&html, PaperSize::A4));
+        assert_eq!(pages.len(), 2);
+        assert!(!pages[0].contains("Chapter two"));
+        assert!(pages[1].starts_with("Chapter two\nFirst line\n"));
+
+        let lines = [line(10.0, false), line(10.0, true), line(10.0, true), line(10.0, false)];
+        assert_eq!(paginate(&lines, 30.0), vec![0..1, 1..4]);
+        // Headings too tall to ever fit with their line split where they must
+        assert_eq!(paginate(&lines, 25.0), vec![0..2, 2..4]);
+        assert_eq!(paginate(&[], 25.0), vec![0..0]);
+    }
+
+    #[test]
+    fn test_fonts_are_subset() {
+        let fonts = PdfFonts::bundled();
+        let face = fonts.regular.face();
+        let glyph = |c| face.glyph_index(c).unwrap();
+        let subset = subset_font(&face, &BTreeSet::from([glyph('A').0, glyph('é').0]));
+        assert!(subset.len() < notosans::REGULAR_TTF.len() / 4);
+        assert_eq!(checksum(&subset), 0xB1B0_AFBA);
+
+        let subset = Face::parse(&subset, 0).unwrap();
+        struct Sink;
+        impl ttf_parser::OutlineBuilder for Sink {
+            fn move_to(&mut self, _: f32, _: f32) {}
+            fn line_to(&mut self, _: f32, _: f32) {}
+            fn quad_to(&mut self, _: f32, _: f32, _: f32, _: f32) {}
+            fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, _: f32, _: f32) {}
+            fn close(&mut self) {}
+        }
+        let outline = |c| subset.outline_glyph(glyph(c), &mut Sink);
+        assert!(outline('A').is_some());
+        assert!(outline('é').is_some());
+        assert!(outline('B').is_none());
+        assert_eq!(subset.glyph_hor_advance(glyph('B')), face.glyph_hor_advance(glyph('B')));
+
+        assert_eq!(number_text(12.0), "12");
+        assert_eq!(number_text(-0.001), "0");
+        assert_eq!(literal("a(b)\\é"), "(a\\(b\\)\\\\%C3%A9)");
+    }
+}
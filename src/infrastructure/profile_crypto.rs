@@ -0,0 +1,232 @@
+use crate::domain::StorageError;
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{aead, hmac, pbkdf2};
+use std::num::NonZeroU32;
+
+type Result<T> = std::result::Result<T, StorageError>;
+
+/// Key derivation and cipher recorded in the profile's `meta` table
+pub const PROFILE_ENCRYPTION_SCHEME: &str = "argon2id/aes-256-gcm";
+/// Scheme of profiles encrypted before keys came from Argon2id; these still
+/// unlock, but new profiles are never written with it
+pub const LEGACY_ENCRYPTION_SCHEME: &str = "pbkdf2-sha256/aes-256-gcm";
+/// Argon2id cost for newly encrypted profiles
+pub const KDF_COST: Argon2Cost = Argon2Cost {
+    memory_kib: 64 * 1024,
+    passes: 3,
+    lanes: 1,
+};
+const SALT_LEN: usize = 16;
+/// Encrypted with the profile key to check a passphrase without touching data
+const KEY_CHECK_PLAINTEXT: &str = "navigator-profile-key-check";
+
+/// Memory, time and parallelism of an Argon2id derivation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Cost {
+    pub memory_kib: u32,
+    pub passes: u32,
+    pub lanes: u32,
+}
+
+/// How a profile key is derived from its passphrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDerivation {
+    Argon2id(Argon2Cost),
+    /// PBKDF2-HMAC-SHA256 with this many rounds, only read from older profiles
+    Pbkdf2(u32),
+}
+
+/// Parameters needed to re-derive a profile key, stored next to the data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMaterial {
+    pub salt: Vec<u8>,
+    pub derivation: KeyDerivation,
+    /// [`KEY_CHECK_PLAINTEXT`] sealed with the derived key
+    pub key_check: String,
+}
+
+/// Encrypts sensitive profile columns with a key derived from a passphrase
+///
+/// Values are sealed with AES-256-GCM under a fresh random nonce, so equal
+/// inputs encrypt differently. Columns that must stay queryable by equality
+/// use [`ProfileCipher::blind_index`], a keyed hash, instead.
+pub struct ProfileCipher {
+    key: aead::LessSafeKey,
+    index_key: hmac::Key,
+    rng: SystemRandom,
+}
+
+impl ProfileCipher {
+    /// Derive a key for a new profile and the material to unlock it later
+    pub fn create(passphrase: &str, cost: Argon2Cost) -> Result<(Self, KeyMaterial)> {
+        Self::create_with(passphrase, KeyDerivation::Argon2id(cost))
+    }
+
+    /// A profile as encrypted before keys came from Argon2id
+    #[cfg(test)]
+    pub(super) fn create_pbkdf2(passphrase: &str, iterations: u32) -> Result<(Self, KeyMaterial)> {
+        Self::create_with(passphrase, KeyDerivation::Pbkdf2(iterations))
+    }
+
+    fn create_with(passphrase: &str, derivation: KeyDerivation) -> Result<(Self, KeyMaterial)> {
+        let rng = SystemRandom::new();
+        let mut salt = vec![0u8; SALT_LEN];
+        rng.fill(&mut salt)
+            .map_err(|_| StorageError::Encryption("Failed to generate a salt".to_string()))?;
+
+        let cipher = Self::derive(passphrase, &salt, derivation)?;
+        let key_check = cipher.encrypt(KEY_CHECK_PLAINTEXT)?;
+        Ok((
+            cipher,
+            KeyMaterial {
+                salt,
+                derivation,
+                key_check,
+            },
+        ))
+    }
+
+    /// Re-derive the key of an existing profile, failing on a wrong passphrase
+    pub fn unlock(passphrase: &str, material: &KeyMaterial) -> Result<Self> {
+        let cipher = Self::derive(passphrase, &material.salt, material.derivation)?;
+        match cipher.decrypt(&material.key_check) {
+            Ok(check) if check == KEY_CHECK_PLAINTEXT => Ok(cipher),
+            _ => Err(StorageError::WrongPassphrase),
+        }
+    }
+
+    fn derive(passphrase: &str, salt: &[u8], derivation: KeyDerivation) -> Result<Self> {
+        let invalid = || StorageError::Corrupt("Invalid key derivation parameters".to_string());
+
+        // One derivation yields both the cipher key and the index key
+        let mut derived = [0u8; 64];
+        match derivation {
+            KeyDerivation::Argon2id(cost) => {
+                let params = Params::new(cost.memory_kib, cost.passes, cost.lanes, Some(derived.len()))
+                    .map_err(|_| invalid())?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut derived)
+                    .map_err(|_| invalid())?;
+            }
+            KeyDerivation::Pbkdf2(iterations) => {
+                let iterations = NonZeroU32::new(iterations).ok_or_else(invalid)?;
+                pbkdf2::derive(
+                    pbkdf2::PBKDF2_HMAC_SHA256,
+                    iterations,
+                    salt,
+                    passphrase.as_bytes(),
+                    &mut derived,
+                );
+            }
+        }
+
+        let key = aead::UnboundKey::new(&aead::AES_256_GCM, &derived[..32])
+            .map_err(|_| StorageError::Encryption("Invalid profile key".to_string()))?;
+        Ok(Self {
+            key: aead::LessSafeKey::new(key),
+            index_key: hmac::Key::new(hmac::HMAC_SHA256, &derived[32..]),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Seal a value as base64 of nonce followed by ciphertext and tag
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let mut nonce = [0u8; aead::NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| StorageError::Encryption("Failed to generate a nonce".to_string()))?;
+
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                aead::Nonce::assume_unique_for_key(nonce),
+                aead::Aad::empty(),
+                &mut sealed,
+            )
+            .map_err(|_| StorageError::Encryption("Failed to encrypt profile data".to_string()))?;
+
+        let mut output = nonce.to_vec();
+        output.extend_from_slice(&sealed);
+        Ok(BASE64.encode(output))
+    }
+
+    pub fn decrypt(&self, encoded: &str) -> Result<String> {
+        let corrupt = || StorageError::Corrupt("Unreadable encrypted value".to_string());
+        let data = BASE64.decode(encoded).map_err(|_| corrupt())?;
+        if data.len() < aead::NONCE_LEN {
+            return Err(corrupt());
+        }
+
+        let (nonce, sealed) = data.split_at(aead::NONCE_LEN);
+        let nonce = aead::Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| corrupt())?;
+        let mut sealed = sealed.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, aead::Aad::empty(), &mut sealed)
+            .map_err(|_| corrupt())?;
+
+        String::from_utf8(plaintext.to_vec()).map_err(|_| corrupt())
+    }
+
+    /// Deterministic keyed hash for lookups by equality
+    pub fn blind_index(&self, value: &str) -> String {
+        let tag = hmac::sign(&self.index_key, value.as_bytes());
+        let hex: String = tag.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("hmac:{}", hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap enough for tests; real profiles use [`KDF_COST`]
+    const TEST_COST: Argon2Cost = Argon2Cost {
+        memory_kib: 64,
+        passes: 1,
+        lanes: 1,
+    };
+
+    #[test]
+    fn test_round_trip_and_passphrase_check() {
+        let (cipher, material) = ProfileCipher::create("correct horse", TEST_COST).unwrap();
+        assert_eq!(material.derivation, KeyDerivation::Argon2id(TEST_COST));
+
+        let sealed = cipher.encrypt("https://example.com/").unwrap();
+        assert_ne!(sealed, cipher.encrypt("https://example.com/").unwrap());
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), "https://example.com/");
+
+        let unlocked = ProfileCipher::unlock("correct horse", &material).unwrap();
+        assert_eq!(unlocked.decrypt(&sealed).unwrap(), "https://example.com/");
+        assert_eq!(
+            unlocked.blind_index("https://example.com/"),
+            cipher.blind_index("https://example.com/")
+        );
+
+        assert!(matches!(
+            ProfileCipher::unlock("battery staple", &material),
+            Err(StorageError::WrongPassphrase)
+        ));
+    }
+
+    #[test]
+    fn test_pbkdf2_profiles_still_unlock() {
+        let (legacy, material) = ProfileCipher::create_pbkdf2("correct horse", 1_000).unwrap();
+        let sealed = legacy.encrypt("https://example.com/").unwrap();
+
+        let unlocked = ProfileCipher::unlock("correct horse", &material).unwrap();
+        assert_eq!(unlocked.decrypt(&sealed).unwrap(), "https://example.com/");
+        assert!(matches!(
+            ProfileCipher::unlock("battery staple", &material),
+            Err(StorageError::WrongPassphrase)
+        ));
+
+        // The same passphrase and salt give a different key under Argon2id
+        let argon = ProfileCipher::derive("correct horse", &material.salt, KeyDerivation::Argon2id(TEST_COST)).unwrap();
+        assert!(argon.decrypt(&sealed).is_err());
+    }
+}
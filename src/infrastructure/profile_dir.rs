@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+/// Start of the name of every temporary profile's directory
+pub const TEMPORARY_PROFILE_PREFIX: &str = "navigator-profile-";
+
+/// Where a profile keeps what it stores
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileMode {
+    /// A database file that outlives the browser
+    Persistent,
+    /// An in-memory database and a temporary directory, both gone once the
+    /// profile is dropped
+    Ephemeral,
+}
+
+/// The directory a profile lives in, and its database
+#[derive(Debug)]
+pub struct ProfileDir {
+    mode: ProfileMode,
+    directory: PathBuf,
+    database_path: String,
+}
+
+impl ProfileDir {
+    /// The profile in `database_path` and the directory around it
+    pub fn persistent(database_path: &str) -> Self {
+        let directory = match Path::new(database_path).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        Self {
+            mode: ProfileMode::Persistent,
+            directory,
+            database_path: database_path.to_string(),
+        }
+    }
+
+    /// A fresh profile under the system's temporary directory, removed with
+    /// everything in it when dropped
+    pub fn temporary() -> std::io::Result<Self> {
+        let name = format!("{}{}", TEMPORARY_PROFILE_PREFIX, uuid::Uuid::new_v4());
+        let directory = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&directory)?;
+        Ok(Self {
+            mode: ProfileMode::Ephemeral,
+            directory,
+            database_path: ":memory:".to_string(),
+        })
+    }
+
+    pub fn mode(&self) -> ProfileMode {
+        self.mode
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// What to open the profile's database with; `:memory:` when ephemeral
+    pub fn database_path(&self) -> &str {
+        &self.database_path
+    }
+
+    /// Where downloads go when the profile keeps them itself: inside a
+    /// temporary profile, so they go with it
+    pub fn downloads_directory(&self) -> Option<PathBuf> {
+        (self.mode == ProfileMode::Ephemeral).then(|| self.directory.join("downloads"))
+    }
+}
+
+impl Drop for ProfileDir {
+    fn drop(&mut self) {
+        if self.mode == ProfileMode::Ephemeral {
+            if let Err(e) = std::fs::remove_dir_all(&self.directory) {
+                tracing::warn!("Failed to remove temporary profile {}: {}", self.directory.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Temporary profiles in the system's temporary directory
+    fn temporary_profiles() -> Vec<PathBuf> {
+        std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(TEMPORARY_PROFILE_PREFIX))
+            .map(|entry| entry.path())
+            .collect()
+    }
+
+    #[test]
+    fn test_temporary_profiles_leave_nothing_behind() {
+        let profile = ProfileDir::temporary().unwrap();
+        assert_eq!(profile.mode(), ProfileMode::Ephemeral);
+        assert_eq!(profile.database_path(), ":memory:");
+        let downloads = profile.downloads_directory().unwrap();
+        std::fs::create_dir_all(&downloads).unwrap();
+        std::fs::write(downloads.join("page.html"), "<p>saved</p>").unwrap();
+        let directory = profile.directory().to_path_buf();
+        assert!(temporary_profiles().contains(&directory));
+
+        drop(profile);
+        assert!(!temporary_profiles().contains(&directory));
+
+        let persistent = ProfileDir::persistent("navigator.db");
+        assert_eq!(persistent.directory(), Path::new("."));
+        assert_eq!(persistent.downloads_directory(), None);
+    }
+}
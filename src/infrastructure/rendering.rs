@@ -1,136 +1,1229 @@
-use crate::domain::{RenderingEngine, ValidatedUrl};
-use anyhow::Result;
+use super::accessibility::{build_accessibility_tree, AccessibilityMode};
+use super::crash::panic_message;
+use super::css::UserStylesheet;
+use super::data_url::{DataUrl, DEFAULT_MAX_DATA_URL_SIZE};
+use super::document_limits::{
+    parse_html_scripted, ParseLimits, ParseReport, DEFAULT_MAX_DOCUMENT_SIZE,
+};
+use super::dom::{DomDocument, DomThread};
+use super::integrity::{check_integrity, IntegrityStatus};
+use super::interceptors::ContentBlocker;
+use super::layout::{LayoutBuilder, PageContent};
+use super::logging::{Diagnostics, LogFilter};
+use super::mhtml::{inline_stylesheets, looks_like_mhtml, MhtmlArchive, MHTML_CONTENT_TYPE};
+use super::network::SecureNetworkClient;
+use super::page_scripts::{
+    execution_order, extract_scripts, ConsoleLog, PageScript, ScriptSource, DEFAULT_SCRIPT_BUDGET,
+};
+use super::opensearch::{DiscoveredSearchEngines, OPENSEARCH_CONTENT_TYPE};
+use super::script_fetch::{script_fetch, FetchInit, ScriptResponse};
+use super::security::{sanitize_html, CspEnforcement, FileAccess};
+use super::websocket::WebSocketHandle;
+use crate::domain::{
+    local_day_bounds, AccessibleNode, BlockCategory, BlockedRequest, BlockedSummary, Bookmark, CachedPage,
+    CachedPageSummary, Certificate, ConsoleLevel, ConsoleMessage, ContentBlockerService, CookiePolicy, CspPolicy,
+    CspViolation, CustomCaRepository, DocumentFetch, Download, DownloadRepository, FetchRequest, HistoryEntry,
+    HistoryRepository, HttpVersion, MemoryReport, MemoryUsage, NetworkError, NewSearchEngine, OpenSearchFetcher,
+    PageCacheRepository, PersistenceGuard, RenderError, RenderingEngine, RequestContext, ResourceKind, RunAt,
+    SearchEngine, SearchEngineError, SearchEngineRepository, SearchTemplate, SecurityError, SiteOrigin,
+    SiteSettings, TabId, TrustStore, UpdateManifest, UserScriptRepository, UserStyleService, ValidatedUrl, Visit,
+    VisitType,
+};
 use async_trait::async_trait;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{Instrument, Level};
 
-use html5ever::parse_document;
-use html5ever::tendril::TendrilSink;
 use markup5ever_rcdom::{RcDom, Handle, NodeData};
 
+type Result<T> = std::result::Result<T, RenderError>;
+
+/// Most recent entries listed on navigator://history
+const HISTORY_PAGE_LIMIT: i32 = 500;
+/// Times a crashed page is loaded again before its crash is shown instead
+const MAX_CRASH_RETRIES: u32 = 2;
+/// Largest document kept for offline browsing
+const MAX_OFFLINE_BODY: usize = 2 * 1024 * 1024;
+/// Elements that pull in a subresource, the attribute naming it and the
+/// content security policy directive it is loaded under
+const SUBRESOURCE_ATTRIBUTES: &[(&str, &str, &str)] = &[
+    ("img", "src", "img-src"),
+    ("script", "src", "script-src"),
+    ("iframe", "src", "frame-src"),
+    ("source", "src", "media-src"),
+    ("track", "src", "media-src"),
+    ("video", "src", "media-src"),
+    ("video", "poster", "img-src"),
+    ("audio", "src", "media-src"),
+    ("embed", "src", "object-src"),
+    ("object", "data", "object-src"),
+];
+/// `rel` values of `<link>` elements that load what they point at, and the
+/// directive each is loaded under
+const LOADING_LINK_RELS: &[(&str, &str)] = &[
+    ("stylesheet", "style-src"),
+    ("icon", "img-src"),
+    ("preload", "default-src"),
+    ("modulepreload", "script-src"),
+    ("prefetch", "default-src"),
+];
+
+/// Colors used by browser-generated pages, as CSS color values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageColors {
+    pub background: String,
+    pub text: String,
+    pub link: String,
+}
+
+impl PageColors {
+    fn body_style(&self) -> String {
+        format!(
+            "background-color: {}; color: {}",
+            self.background, self.text
+        )
+    }
+}
+
+impl Default for PageColors {
+    fn default() -> Self {
+        Self {
+            background: "#f2f2f2".to_string(),
+            text: "#1a1a1a".to_string(),
+            link: "#0b57d0".to_string(),
+        }
+    }
+}
+
+/// Browser settings listed on navigator://settings, as the user edits them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SettingsView {
+    /// Label and current value of each setting, in order
+    pub rows: Vec<(String, String)>,
+    /// Row under the keyboard focus
+    pub focused: usize,
+    /// Why the last change to the focused setting was refused
+    pub error: Option<String>,
+    /// Row where the keyboard shortcuts begin, listed under their own
+    /// heading
+    pub shortcuts_from: Option<usize>,
+}
+
+/// A site's overrides, and what the page it was opened from had blocked,
+/// listed on navigator://site-settings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SiteSettingsView {
+    pub settings: SettingsView,
+    pub blocked: BlockedSummary,
+    /// Protocol the page came over
+    pub protocol: Option<HttpVersion>,
+    /// Certificate the page's server presented
+    pub certificate: Option<Certificate>,
+    /// Loads the page's content security policy did not allow
+    pub csp_violations: Vec<CspViolation>,
+}
+
+/// Keyboard state of navigator://downloads
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadsView {
+    /// File name and warnings of each download waiting for confirmation,
+    /// listed above the finished ones
+    pub pending: Vec<(String, String)>,
+    /// File name and state of each download being written or whose write
+    /// failed, listed between the waiting and the finished ones
+    pub unfinished: Vec<(String, String)>,
+    /// Row under the keyboard focus, counting the waiting and unfinished
+    /// downloads first
+    pub focused: usize,
+    /// Why the last action on the focused download failed
+    pub error: Option<String>,
+}
+
+/// What navigator://bookmarks lists, and its keyboard state
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookmarksView {
+    /// Unfiled bookmarks first, then folder by folder, each in its order
+    pub bookmarks: Vec<Bookmark>,
+    /// Bookmark under the keyboard focus
+    pub focused: usize,
+    /// Why the last action on the focused bookmark failed
+    pub error: Option<String>,
+}
+
+/// What navigator://update shows
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateView {
+    /// Version of the running browser
+    pub current_version: String,
+    /// The newer release the update check found, if any
+    pub update: Option<UpdateManifest>,
+}
+
+/// What navigator://shortcuts lists: each section's commands, described,
+/// with their shortcuts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShortcutsView {
+    pub sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+/// What navigator://memory lists
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryView {
+    pub reports: Vec<MemoryReport>,
+    /// Titles of the tabs reports belong to
+    pub tab_titles: HashMap<TabId, String>,
+    /// Bytes past which caches are trimmed on their own; zero for never
+    pub ceiling: usize,
+    /// Bytes the trim just asked for freed
+    pub trimmed: Option<usize>,
+}
+
 /// Custom browser rendering engine using html5ever
 pub struct ServoRenderer {
     current_url: Arc<Mutex<Option<ValidatedUrl>>>,
     current_html: Arc<Mutex<String>>,
+    /// Where the current document was cut off, if it was too large
+    current_truncated_at: Mutex<Option<usize>>,
+    /// OpenSearch description the current document links to
+    current_search_description: Mutex<Option<ValidatedUrl>>,
+    /// Where links resolve from when not the loaded URL, such as the page an
+    /// opened archive was saved from
+    current_base: Mutex<Option<ValidatedUrl>>,
+    /// Archive the current document came from; URLs in it load from it
+    current_archive: Mutex<Option<Arc<MhtmlArchive>>>,
     current_title: Arc<Mutex<String>>,
+    /// Laid-out current document, replaced whole when a new one loads
+    current_layout: Mutex<Arc<PageContent>>,
+    /// Thread holding the document scripts work on, started with the first
+    /// page that has scripts on
+    dom: OnceLock<DomThread>,
+    /// How often each page crashed in this tab, to stop loading it again
+    /// after a few crashes
+    crashes: Mutex<HashMap<String, u32>>,
+    /// Settings the current document was loaded with, the global ones
+    /// with its site's overrides on top
+    current_config: Mutex<RenderingConfig>,
+    page_colors: Mutex<PageColors>,
+    settings_view: Mutex<SettingsView>,
+    /// Overrides listed on navigator://site-settings
+    site_settings_view: Mutex<SiteSettingsView>,
+    downloads_view: Mutex<DownloadsView>,
+    bookmarks_view: Mutex<BookmarksView>,
+    update_view: Mutex<UpdateView>,
+    shortcuts_view: Mutex<ShortcutsView>,
+    memory_view: Mutex<MemoryView>,
+    /// Overrides for the site about to be loaded
+    site_settings: Mutex<Option<SiteSettings>>,
+    /// Requests the current document made that were refused
+    current_blocked: Mutex<Vec<BlockedRequest>>,
+    /// Content security policies the current document was sent with
+    current_csp: Mutex<Vec<CspPolicy>>,
+    /// Loads the current document made that its policies did not allow
+    current_csp_violations: Mutex<Vec<CspViolation>>,
+    /// How the current document was fetched, if from the network
+    current_fetch: Mutex<Option<DocumentFetch>>,
+    /// What the tab's scripts logged, shown on navigator://console; the DOM
+    /// thread logs to it too
+    console: Arc<ConsoleLog>,
+    /// WebSockets the current document opened, closed when it goes away
+    websockets: Mutex<Vec<Arc<WebSocketHandle>>>,
+    /// Decides which of a page's subresources are ads or trackers
+    content_blocker: Option<Arc<ContentBlocker>>,
+    /// Rules applied over page styles at layout
+    user_styles: Option<Arc<dyn UserStyleService>>,
+    /// Whether pages are laid out with screen reader announcements
+    accessibility: AccessibilityMode,
+    /// Whether what pages' content security policies do not allow is
+    /// refused, or only reported
+    csp_enforcement: CspEnforcement,
+    /// Client documents are fetched with, and so its request interceptors
+    network: Arc<SecureNetworkClient>,
+    /// Which local files file:// URLs may read
+    file_access: Arc<FileAccess>,
+    /// Logs shown on navigator://logs
+    diagnostics: Option<Diagnostics>,
+    /// Visits listed, and deleted, on navigator://history, and looked up to
+    /// mark visited links
+    history: Option<Arc<dyn HistoryRepository>>,
+    /// Showing a private tab, which never looks at history
+    private: AtomicBool,
+    /// Saved files listed on navigator://downloads
+    downloads: Option<Arc<dyn DownloadRepository>>,
+    /// Documents kept from the network, shown in their place while the
+    /// network client is offline
+    page_cache: Option<Arc<dyn PageCacheRepository>>,
+    /// Engines managed on navigator://settings
+    search_engines: Option<Arc<dyn SearchEngineRepository>>,
+    /// Where loaded pages' OpenSearch descriptions are noted
+    discovered: Option<DiscoveredSearchEngines>,
+    /// Fetches the descriptions added from navigator://settings
+    descriptions: Option<Arc<dyn OpenSearchFetcher>>,
+    /// Certificate authorities listed, and removed, on navigator://settings
+    custom_cas: Option<Arc<dyn CustomCaRepository>>,
+    /// Scripts listed on navigator://scripts
+    user_scripts: Option<Arc<dyn UserScriptRepository>>,
+    /// Paints the laid-out page for screenshots
+    capture: Option<Arc<dyn PageCapture>>,
+    config: RenderingConfig,
+}
+
+/// Paints laid-out pages into PNG images. Painting is the UI's job, so it
+/// provides one.
+pub trait PageCapture: Send + Sync {
+    /// A PNG of the top of `content`; may block while the GPU draws
+    fn capture(&self, content: &PageContent) -> Result<Vec<u8>>;
 }
 
 impl ServoRenderer {
     pub fn new() -> Self {
+        Self::with_config(RenderingConfig::default())
+    }
+
+    pub fn with_config(config: RenderingConfig) -> Self {
         Self {
             current_url: Arc::new(Mutex::new(None)),
             current_html: Arc::new(Mutex::new(String::new())),
+            current_truncated_at: Mutex::new(None),
+            current_search_description: Mutex::new(None),
+            current_base: Mutex::new(None),
+            current_archive: Mutex::new(None),
             current_title: Arc::new(Mutex::new("Navigator".to_string())),
+            current_layout: Mutex::new(Arc::new(PageContent::default())),
+            dom: OnceLock::new(),
+            crashes: Mutex::new(HashMap::new()),
+            current_config: Mutex::new(config.clone()),
+            page_colors: Mutex::new(PageColors::default()),
+            settings_view: Mutex::new(SettingsView::default()),
+            site_settings_view: Mutex::new(SiteSettingsView::default()),
+            downloads_view: Mutex::new(DownloadsView::default()),
+            bookmarks_view: Mutex::new(BookmarksView::default()),
+            update_view: Mutex::new(UpdateView::default()),
+            shortcuts_view: Mutex::new(ShortcutsView::default()),
+            memory_view: Mutex::new(MemoryView::default()),
+            site_settings: Mutex::new(None),
+            current_blocked: Mutex::new(Vec::new()),
+            current_csp: Mutex::new(Vec::new()),
+            current_csp_violations: Mutex::new(Vec::new()),
+            current_fetch: Mutex::new(None),
+            console: Arc::new(ConsoleLog::new()),
+            websockets: Mutex::new(Vec::new()),
+            content_blocker: None,
+            user_styles: None,
+            accessibility: AccessibilityMode::new(),
+            csp_enforcement: CspEnforcement::new(),
+            network: Arc::new(SecureNetworkClient::default()),
+            file_access: Arc::new(FileAccess::default()),
+            diagnostics: None,
+            history: None,
+            private: AtomicBool::new(false),
+            downloads: None,
+            page_cache: None,
+            search_engines: None,
+            discovered: None,
+            descriptions: None,
+            custom_cas: None,
+            user_scripts: None,
+            capture: None,
+            config,
         }
     }
 
-    /// Fetch HTML content from URL
-    async fn fetch_html(&self, url: &ValidatedUrl) -> Result<String> {
-        tracing::info!("Fetching HTML from: {}", url);
+    /// Lay pages out with the user's style rules
+    pub fn with_user_styles(mut self, user_styles: Arc<dyn UserStyleService>) -> Self {
+        self.user_styles = Some(user_styles);
+        self
+    }
+
+    /// Lay pages out for screen readers while `accessibility` is enabled
+    pub fn with_accessibility(mut self, accessibility: AccessibilityMode) -> Self {
+        self.accessibility = accessibility;
+        self
+    }
+
+    /// Refuse what pages' content security policies do not allow while
+    /// `csp_enforcement` is enabled, instead of only reporting it
+    pub fn with_csp_enforcement(mut self, csp_enforcement: CspEnforcement) -> Self {
+        self.csp_enforcement = csp_enforcement;
+        self
+    }
+
+    /// Fetch documents with a shared client instead of one of its own
+    pub fn with_network(mut self, network: Arc<SecureNetworkClient>) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Read local files under the policy the security service checks
+    /// file:// URLs with
+    pub fn with_file_access(mut self, file_access: Arc<FileAccess>) -> Self {
+        self.file_access = file_access;
+        self
+    }
+
+    /// Note which of a page's subresources `content_blocker` refuses. It
+    /// should be the one the network client intercepts requests with.
+    pub fn with_content_blocker(mut self, content_blocker: Arc<ContentBlocker>) -> Self {
+        self.content_blocker = Some(content_blocker);
+        self
+    }
+
+    /// Serve navigator://logs from recorded logs
+    pub fn with_diagnostics(mut self, diagnostics: Diagnostics) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Serve navigator://history from the profile's history, and mark links
+    /// to pages in it visited
+    pub fn with_history(mut self, history: Arc<dyn HistoryRepository>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Serve navigator://downloads from `downloads`
+    pub fn with_downloads(mut self, downloads: Arc<dyn DownloadRepository>) -> Self {
+        self.downloads = Some(downloads);
+        self
+    }
+
+    /// Keep documents loaded from the network in `page_cache`, except in
+    /// private tabs, and show them from it while offline
+    pub fn with_page_cache(mut self, page_cache: Arc<dyn PageCacheRepository>) -> Self {
+        self.page_cache = Some(page_cache);
+        self
+    }
+
+    /// Serve navigator://settings, managing `search_engines`, and note the
+    /// search engines pages offer in `discovered`, adding them with
+    /// `descriptions`
+    pub fn with_search_engines(
+        mut self,
+        search_engines: Arc<dyn SearchEngineRepository>,
+        discovered: DiscoveredSearchEngines,
+        descriptions: Arc<dyn OpenSearchFetcher>,
+    ) -> Self {
+        self.search_engines = Some(search_engines);
+        self.discovered = Some(discovered);
+        self.descriptions = Some(descriptions);
+        self
+    }
+
+    /// List the certificate authorities in `custom_cas` on
+    /// navigator://settings, removing them from the network client's trust
+    pub fn with_custom_cas(mut self, custom_cas: Arc<dyn CustomCaRepository>) -> Self {
+        self.custom_cas = Some(custom_cas);
+        self
+    }
+
+    /// List the scripts in `user_scripts` on navigator://scripts
+    pub fn with_user_scripts(mut self, user_scripts: Arc<dyn UserScriptRepository>) -> Self {
+        self.user_scripts = Some(user_scripts);
+        self
+    }
+
+    /// Take screenshots by painting the page with `capture`
+    pub fn with_capture(mut self, capture: Arc<dyn PageCapture>) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    /// User style rules for a page at `url`
+    fn user_stylesheet(&self, url: &ValidatedUrl) -> UserStylesheet {
+        self.user_styles
+            .as_ref()
+            .map(|styles| UserStylesheet::from_rules(&styles.rules_for(url.host_str())))
+            .unwrap_or_default()
+    }
+
+    /// Set the colors used for browser-generated pages
+    pub fn set_page_colors(&self, colors: PageColors) {
+        if let Ok(mut page_colors) = self.page_colors.lock() {
+            *page_colors = colors;
+        }
+    }
 
-        let client = reqwest::Client::builder()
-            .user_agent(format!("Navigator/{}", env!("CARGO_PKG_VERSION")))
-            .build()?;
+    /// Set the browser settings listed on navigator://settings, shown the
+    /// next time it loads
+    pub fn set_settings_view(&self, view: SettingsView) {
+        if let Ok(mut settings_view) = self.settings_view.lock() {
+            *settings_view = view;
+        }
+    }
+
+    /// Set the overrides listed on navigator://site-settings, shown the
+    /// next time it loads
+    pub fn set_site_settings_view(&self, view: SiteSettingsView) {
+        if let Ok(mut site_settings_view) = self.site_settings_view.lock() {
+            *site_settings_view = view;
+        }
+    }
+
+    /// Set the focused row of navigator://downloads, shown the next time
+    /// it loads
+    pub fn set_downloads_view(&self, view: DownloadsView) {
+        if let Ok(mut downloads_view) = self.downloads_view.lock() {
+            *downloads_view = view;
+        }
+    }
+
+    /// Set the bookmarks navigator://bookmarks lists and its focused row,
+    /// shown the next time it loads
+    pub fn set_bookmarks_view(&self, view: BookmarksView) {
+        if let Ok(mut bookmarks_view) = self.bookmarks_view.lock() {
+            *bookmarks_view = view;
+        }
+    }
+
+    /// Set the release navigator://update describes, shown the next time it
+    /// loads
+    pub fn set_update_view(&self, view: UpdateView) {
+        if let Ok(mut update_view) = self.update_view.lock() {
+            *update_view = view;
+        }
+    }
+
+    /// Set the shortcuts navigator://shortcuts lists, shown the next time
+    /// it loads
+    pub fn set_shortcuts_view(&self, view: ShortcutsView) {
+        if let Ok(mut shortcuts_view) = self.shortcuts_view.lock() {
+            *shortcuts_view = view;
+        }
+    }
+
+    /// Set what navigator://memory lists, shown the next time it loads
+    pub fn set_memory_view(&self, view: MemoryView) {
+        if let Ok(mut memory_view) = self.memory_view.lock() {
+            *memory_view = view;
+        }
+    }
+
+    /// Settings the current document was loaded with
+    pub fn effective_config(&self) -> RenderingConfig {
+        self.current_config
+            .lock()
+            .map(|config| config.clone())
+            .unwrap_or_else(|_| self.config.clone())
+    }
+
+    /// Global settings with the overrides given for `url`'s site on top
+    fn config_for(&self, url: &ValidatedUrl) -> (RenderingConfig, Option<SiteSettings>) {
+        let origin = SiteOrigin::of(url);
+        let site = self
+            .site_settings
+            .lock()
+            .ok()
+            .and_then(|site| site.clone())
+            .filter(|site| origin.as_ref() == Some(&site.origin));
+        (self.config.for_site(site.as_ref()), site)
+    }
+
+    /// Which of a document's subresources are refused: those the content
+    /// blocker lists, unless `config` turns blocking off for the site, and
+    /// plain http ones on an https page, unless `policies` upgrade them.
+    /// Each URL is counted once.
+    fn blocked_subresources(
+        &self,
+        subresources: &[(&str, String)],
+        document_url: &ValidatedUrl,
+        config: &RenderingConfig,
+        policies: &[CspPolicy],
+    ) -> Vec<BlockedRequest> {
+        let blocker = self.content_blocker.as_ref().filter(|_| config.block_content);
+        let mut seen = HashSet::new();
+        let mut blocked = Vec::new();
+        for (_, href) in subresources {
+            let Ok(url) = document_url.join(href) else {
+                continue;
+            };
+            let url = upgrade_insecure(url, policies);
+            if !matches!(url.scheme(), "http" | "https") || !seen.insert(url.as_str().to_string()) {
+                continue;
+            }
+            let mixed = document_url.is_secure() && url.scheme() == "http";
+            let category = blocker
+                .and_then(|blocker| blocker.check(&url, document_url))
+                .or(mixed.then_some(BlockCategory::MixedContent));
+            if let Some(category) = category {
+                blocked.push(BlockedRequest { url, category });
+            }
+        }
+        blocked
+    }
+
+    /// The first violation of the current document's content security
+    /// policies a load of `url` under `directive` makes, if any
+    fn csp_violation(&self, directive: &str, url: &ValidatedUrl, page: &ValidatedUrl) -> Option<CspViolation> {
+        let policies = self.current_csp.lock().ok()?;
+        let violated = policies
+            .iter()
+            .find_map(|policy| policy.violated_directive(directive, url, page))?;
+        Some(CspViolation {
+            url: url.clone(),
+            directive: violated.to_string(),
+            enforced: self.csp_enforcement.enabled(),
+        })
+    }
+
+    /// Check a document's subresources against its content security
+    /// policies, reporting each violation once
+    fn check_csp(&self, subresources: &[(&str, String)], document_url: &ValidatedUrl) {
+        let policies = self.current_csp.lock().map(|policies| policies.clone()).unwrap_or_default();
+        if policies.is_empty() {
+            return;
+        }
+        let mut seen = HashSet::new();
+        for (directive, href) in subresources {
+            let Ok(url) = document_url.join(href) else {
+                continue;
+            };
+            let url = upgrade_insecure(url, &policies);
+            if !seen.insert((*directive, url.as_str().to_string())) {
+                continue;
+            }
+            if let Some(violation) = self.csp_violation(directive, &url, document_url) {
+                self.record_csp_violation(violation, document_url);
+            }
+        }
+    }
+
+    /// Log a violation to the tab's console and count it for the page
+    fn record_csp_violation(&self, violation: CspViolation, page: &ValidatedUrl) {
+        let (url, directive) = (&violation.url, &violation.directive);
+        let message = if violation.enforced {
+            format!("Refused to load {}: the page's content security policy ({}) does not allow it", url, directive)
+        } else {
+            format!("[Report only] Loading {} breaks the page's content security policy ({})", url, directive)
+        };
+        self.console.push(ConsoleLevel::Warn, message, Some(page.as_str()));
+        if let Ok(mut violations) = self.current_csp_violations.lock() {
+            violations.push(violation);
+        }
+    }
+
+    /// `fetch(url, init)` from a script on the current page, with `url`
+    /// resolved against it and checked against the page's content security
+    /// policies. A request the content blocker refuses is listed with the
+    /// page's other blocked requests.
+    pub async fn script_fetch(&self, url: &str, init: FetchInit) -> Result<ScriptResponse> {
+        let base = self.current_base.lock().ok().and_then(|base| base.clone());
+        let page = base
+            .or_else(|| self.current_url())
+            .ok_or_else(|| RenderError::Script("No document is loaded".to_string()))?;
+        let target = page
+            .join(url)
+            .map_err(|_| RenderError::Script(format!("TypeError: {} is not a valid URL", url)))?;
+        let target = match self.current_csp.lock() {
+            Ok(policies) => upgrade_insecure(target, &policies),
+            Err(_) => target,
+        };
+        if let Some(violation) = self.csp_violation("connect-src", &target, &page) {
+            let enforced = violation.enforced;
+            self.record_csp_violation(violation, &page);
+            if enforced {
+                return Err(NetworkError::Blocked("the page's content security policy".to_string()).into());
+            }
+        }
+        let config = self.effective_config();
+        let private = self.private.load(Ordering::Relaxed);
+        script_fetch(&self.network, &page, &target, init, config.user_agent.as_deref(), private)
+            .await
+            .inspect_err(|e| {
+                let message = format!("Fetch of {} failed: {}", target, e);
+                self.console.push(ConsoleLevel::Warn, message, Some(page.as_str()));
+                let category = self.content_blocker.as_ref().and_then(|blocker| blocker.category(&target));
+                if let (RenderError::Network(NetworkError::Blocked(_)), Some(category)) = (e, category) {
+                    if let Ok(mut blocked) = self.current_blocked.lock() {
+                        blocked.push(BlockedRequest { url: target.clone(), category });
+                    }
+                }
+            })
+    }
 
-        let response = client.get(url.as_str()).send().await?;
-        let html = response.text().await?;
+    /// `new WebSocket(url)` from a script on the current page, with `url`
+    /// resolved against it. The connection lasts until it is closed or the
+    /// page goes away.
+    pub async fn open_websocket(&self, url: &str) -> Result<Arc<WebSocketHandle>> {
+        let base = self.current_base.lock().ok().and_then(|base| base.clone());
+        let page = base
+            .or_else(|| self.current_url())
+            .ok_or_else(|| RenderError::Script("No document is loaded".to_string()))?;
+        let target = page
+            .join(url)
+            .map_err(|_| RenderError::Script(format!("SyntaxError: {} is not a valid URL", url)))?;
+        let config = self.effective_config();
+        let socket = self
+            .network
+            .websocket(&target, Some(&page), config.user_agent.as_deref())
+            .await
+            .inspect_err(|e| {
+                let message = format!("WebSocket connection to {} failed: {}", target, e);
+                self.console.push(ConsoleLevel::Warn, message, Some(page.as_str()));
+            })?;
+        let socket = Arc::new(socket);
+        if let Ok(mut websockets) = self.websockets.lock() {
+            websockets.push(socket.clone());
+        }
+        Ok(socket)
+    }
 
-        tracing::info!("Received {} bytes of HTML", html.len());
-        Ok(html)
+    /// Close the current document's WebSockets as going away
+    fn close_websockets(&self) {
+        let websockets = match self.websockets.lock() {
+            Ok(mut websockets) => std::mem::take(&mut *websockets),
+            Err(_) => return,
+        };
+        for socket in websockets {
+            socket.shut_down();
+        }
     }
 
-    /// Parse HTML into DOM
-    fn parse_html(&self, html: &str) -> RcDom {
-        tracing::info!("Parsing HTML ({} bytes)", html.len());
-        parse_document(RcDom::default(), Default::default())
-            .from_utf8()
-            .read_from(&mut html.as_bytes())
-            .unwrap()
+    /// What the tab's scripts logged and how they failed
+    pub fn console(&self) -> &ConsoleLog {
+        &self.console
     }
 
-    /// Extract title from DOM
-    fn extract_title(&self, dom: &RcDom) -> String {
-        fn walk(handle: &Handle, title: &mut Option<String>) {
-            let node = handle;
-            match &node.data {
-                NodeData::Element { name, .. } => {
-                    if &name.local == "title" {
-                        if let Some(text_node) = node.children.borrow().first() {
-                            if let NodeData::Text { contents } = &text_node.data {
-                                *title = Some(contents.borrow().to_string());
+    /// Run a page's scripts in execution order, loading external ones under
+    /// `fetch`'s same-origin and CORS rules, until they have run for
+    /// `budget` between them. External scripts that fail their `integrity`
+    /// check are not run and count as blocked. Failures go to the tab's
+    /// console and leave the page as it is.
+    async fn run_scripts(
+        &self,
+        scripts: Vec<PageScript>,
+        page: &ValidatedUrl,
+        budget: Duration,
+        user_agent: Option<&str>,
+    ) {
+        let ordered = execution_order(scripts);
+        let total = ordered.len();
+        let mut spent = Duration::ZERO;
+        for (index, script) in ordered.into_iter().enumerate() {
+            if spent >= budget {
+                let skipped = total - index;
+                let message = format!("Skipped {} scripts: the page used its {:?} script budget", skipped, budget);
+                self.console.push(ConsoleLevel::Warn, message, Some(page.as_str()));
+                break;
+            }
+            let (code, source) = match script.source {
+                ScriptSource::Inline(code) => (code, page.to_string()),
+                ScriptSource::External(src) => {
+                    let Ok(url) = page.join(&src) else {
+                        let message = format!("Invalid script URL {}", src);
+                        self.console.push(ConsoleLevel::Error, message, Some(page.as_str()));
+                        continue;
+                    };
+                    let url = match self.current_csp.lock() {
+                        Ok(policies) => upgrade_insecure(url, &policies),
+                        Err(_) => url,
+                    };
+                    // Reported with the page's other subresources as it loaded
+                    if self.csp_violation("script-src", &url, page).is_some_and(|violation| violation.enforced) {
+                        continue;
+                    }
+                    let private = self.private.load(Ordering::Relaxed);
+                    match script_fetch(&self.network, page, &url, FetchInit::new(), user_agent, private).await {
+                        Ok(response) if response.ok() => {
+                            let status = check_integrity(&url, script.integrity.as_deref(), &response.body);
+                            if let IntegrityStatus::Mismatch(actual) = status {
+                                let message = format!(
+                                    "Refused to run script: its contents do not match its integrity attribute \
+                                     (they hash to {})",
+                                    actual
+                                );
+                                self.console.push(ConsoleLevel::Error, message, Some(url.as_str()));
+                                if let Ok(mut blocked) = self.current_blocked.lock() {
+                                    blocked.push(BlockedRequest { url, category: BlockCategory::Integrity });
+                                }
+                                continue;
                             }
+                            (response.text(), url.to_string())
+                        }
+                        Ok(response) => {
+                            let message = format!("Failed to load script: status {}", response.status);
+                            self.console.push(ConsoleLevel::Error, message, Some(url.as_str()));
+                            continue;
+                        }
+                        Err(e) => {
+                            let message = format!("Failed to load script: {}", e);
+                            self.console.push(ConsoleLevel::Error, message, Some(url.as_str()));
+                            continue;
                         }
                     }
                 }
-                _ => {}
+            };
+            let started = Instant::now();
+            let result = self.execute_javascript(&code).await;
+            spent += started.elapsed();
+            if let Err(e) = result {
+                self.console.push(ConsoleLevel::Error, e.to_string(), Some(&source));
+            }
+        }
+    }
+
+    fn set_blocked(&self, blocked: Vec<BlockedRequest>) {
+        if let Ok(mut current_blocked) = self.current_blocked.lock() {
+            *current_blocked = blocked;
+        }
+    }
+
+    fn page_colors(&self) -> PageColors {
+        self.page_colors
+            .lock()
+            .map(|colors| colors.clone())
+            .unwrap_or_default()
+    }
+
+    /// Mark links to pages in history visited, looking them all up at once.
+    /// Private tabs leave every link unvisited.
+    async fn mark_visited_links(&self, content: &mut PageContent, base: &ValidatedUrl) {
+        let Some(history) = self.history.as_ref().filter(|_| !self.private.load(Ordering::Relaxed)) else {
+            return;
+        };
+        let targets: Vec<Option<ValidatedUrl>> = content
+            .links
+            .iter()
+            .map(|link| base.join(&link.href).ok().map(|url| url.for_history()))
+            .collect();
+        // Each distinct target once, by its position in the lookup
+        let mut positions: HashMap<&str, usize> = HashMap::new();
+        let mut urls: Vec<ValidatedUrl> = Vec::new();
+        for url in targets.iter().flatten() {
+            positions.entry(url.as_str()).or_insert_with(|| {
+                urls.push(url.clone());
+                urls.len() - 1
+            });
+        }
+        if urls.is_empty() {
+            return;
+        }
+        let visited = match history.visited(&urls).await {
+            Ok(visited) => visited,
+            Err(e) => {
+                tracing::warn!("Cannot look up visited links: {}", e);
+                return;
             }
-            for child in node.children.borrow().iter() {
-                walk(child, title);
+        };
+        for (link, target) in content.links.iter_mut().zip(&targets) {
+            link.visited = target
+                .as_ref()
+                .and_then(|target| positions.get(target.as_str()))
+                .is_some_and(|index| visited.get(*index) == Some(&true));
+        }
+    }
+
+    /// Fetch a network resource, returning its content type and body. A
+    /// site's own `user_agent` is sent in place of the browser's.
+    /// Fetch a document, also saying whether it may be kept for offline use
+    async fn fetch_http(&self, url: &ValidatedUrl, user_agent: Option<&str>) -> Result<(String, Vec<u8>, bool)> {
+        tracing::info!("Fetching HTML from: {}", url);
+
+        // One byte over the cap, so `load_url` sees that the body was cut off
+        let max_body = self.config.max_document_size.saturating_add(1);
+        let request = FetchRequest::new(url.clone(), ResourceKind::Document)
+            .with_private(self.private.load(Ordering::Relaxed));
+        let response = self.network.fetch_request(request, Some(max_body), user_agent).await?;
+        let no_store = response.headers.iter().any(|(name, value)| {
+            name == "cache-control"
+                && value.split(',').any(|directive| directive.trim().eq_ignore_ascii_case("no-store"))
+        });
+        let cacheable = (200..300).contains(&response.status) && !no_store;
+        let content_type = response.content_type.unwrap_or_else(|| "text/html".to_string());
+        let body = response.body;
+        if let Ok(mut fetch) = self.current_fetch.lock() {
+            *fetch = Some(DocumentFetch {
+                url: response.url,
+                redirected_from: response.redirected_from,
+                status: response.status,
+                headers: response.headers,
+                version: response.version,
+            });
+        }
+
+        tracing::info!("Received {} bytes ({}) over {}", body.len(), content_type, response.version);
+        Ok((content_type, body, cacheable))
+    }
+
+    /// The copy of `url` kept for offline use and when it was fetched, or
+    /// else a page saying it is not available offline
+    async fn fetch_offline(&self, url: &ValidatedUrl) -> (String, Vec<u8>, Option<chrono::DateTime<chrono::Utc>>) {
+        let colors = self.page_colors();
+        let Some(page_cache) = &self.page_cache else {
+            return ("text/html".to_string(), offline_page(url, &[], &colors).into_bytes(), None);
+        };
+        match page_cache.find(url).await {
+            Ok(Some(page)) => {
+                tracing::info!("Showing the offline copy of {} from {}", url, page.fetched_at);
+                let content_type = page.content_type.unwrap_or_else(|| "text/html".to_string());
+                return (content_type, page.body, Some(page.fetched_at));
             }
+            Ok(None) => tracing::info!("No offline copy of {}", url),
+            Err(e) => tracing::warn!("Failed to read the offline copy of {}: {}", url, e),
         }
+        let kept = match SiteOrigin::of(url) {
+            Some(origin) => page_cache.find_by_origin(&origin).await.unwrap_or_else(|e| {
+                tracing::warn!("Failed to list offline copies from {}: {}", origin, e);
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+        ("text/html".to_string(), offline_page(url, &kept, &colors).into_bytes(), None)
+    }
 
-        let mut title = None;
-        walk(&dom.document, &mut title);
-        title.unwrap_or_else(|| "Untitled".to_string())
+    /// Keep a document just loaded from the network for offline use
+    async fn keep_for_offline(&self, url: &ValidatedUrl, title: &str, content_type: &str, body: &[u8]) {
+        let private = self.private.load(Ordering::Relaxed);
+        let Some(page_cache) = self.page_cache.as_ref().filter(|_| !private) else {
+            return;
+        };
+        let page_cache = PersistenceGuard::new(page_cache.clone(), RequestContext::new(None, private));
+        if body.len() > MAX_OFFLINE_BODY {
+            return;
+        }
+        let page = CachedPage {
+            url: url.clone(),
+            title: title.to_string(),
+            content_type: Some(content_type.to_string()),
+            body: body.to_vec(),
+            fetched_at: chrono::Utc::now(),
+        };
+        if let Err(e) = page_cache.store(&page).await {
+            tracing::warn!("Failed to keep {} for offline use: {}", url, e);
+        }
     }
 
-    /// Render DOM to text (simple rendering for now)
-    pub fn render_to_text(&self) -> String {
-        if let Ok(html) = self.current_html.lock() {
-            // Parse HTML on demand
-            let dom = self.parse_html(&html);
-            let mut output = String::new();
-            self.walk_dom(&dom.document, &mut output, 0);
-            output
-        } else {
-            String::new()
+    /// Read a local resource for a file:// URL.
+    ///
+    /// Local pages bypass the HTTP client entirely, so they never carry
+    /// cookies and never read from or write to the HTTP cache. The path is
+    /// checked against the file access policy here too, whatever the URL
+    /// was validated with.
+    async fn fetch_file(&self, url: &ValidatedUrl) -> Result<(String, Vec<u8>)> {
+        let path = url
+            .to_file_path()
+            .ok_or_else(|| SecurityError::InvalidUrl(url.to_string()))?;
+        let path = self.file_access.check(&path)?;
+
+        let metadata = tokio::fs::metadata(&path)
+            .await
+            .map_err(|e| RenderError::File(format!("Cannot open {}: {}", path.display(), e)))?;
+
+        if metadata.is_dir() {
+            let listing = directory_listing(&path, &self.page_colors()).await?;
+            return Ok(("text/html".to_string(), listing.into_bytes()));
         }
+
+        let body = tokio::fs::read(&path)
+            .await
+            .map_err(|e| RenderError::File(format!("Failed to read {}: {}", path.display(), e)))?;
+        tracing::info!("Read {} bytes from {}", body.len(), path.display());
+
+        Ok((content_type_for_path(&path).to_string(), body))
     }
 
-    fn walk_dom(&self, handle: &Handle, output: &mut String, depth: usize) {
-        let node = handle;
-        let indent = "  ".repeat(depth);
+    /// Decode an inline data: URL
+    fn fetch_data(&self, url: &ValidatedUrl) -> Result<(String, Vec<u8>)> {
+        let data = DataUrl::parse(url.as_str(), self.config.max_data_url_size)
+            .map_err(|e| RenderError::InvalidContent(e.to_string()))?;
+        Ok((data.content_type(), data.body))
+    }
 
-        match &node.data {
-            NodeData::Document => {}
-            NodeData::Element { name, .. } => {
-                let tag_name = &name.local;
-                output.push_str(&format!("{}<{}>\n", indent, tag_name));
+    /// Generate a browser page for a navigator:// URL
+    async fn internal_page(&self, url: &ValidatedUrl) -> Result<(String, Vec<u8>)> {
+        let colors = self.page_colors();
+        let html = match url.host_str() {
+            Some("logs") => match &self.diagnostics {
+                Some(diagnostics) => {
+                    if let Some(level) = requested_log_level(url.query()) {
+                        if let Err(e) = diagnostics.levels.set_level(level).await {
+                            tracing::warn!("Failed to save log level: {}", e);
+                        }
+                    }
+                    logs_page(diagnostics, &LogFilter::from_query(url.query()), &colors)
+                }
+                None => unavailable_page("Logs", "Logs are not being recorded.", &colors),
+            },
+            Some("history") => match &self.history {
+                Some(history) => history_page(history.as_ref(), url.query(), &colors).await,
+                None => unavailable_page("History", "History is not available.", &colors),
+            },
+            Some("downloads") => match &self.downloads {
+                Some(downloads) => {
+                    let view = self.downloads_view.lock().map(|view| view.clone()).unwrap_or_default();
+                    downloads_page(downloads.as_ref(), &view, url.query(), &colors).await
+                }
+                None => unavailable_page("Downloads", "Downloads are not available.", &colors),
+            },
+            Some("bookmarks") => {
+                let view = self.bookmarks_view.lock().map(|view| view.clone()).unwrap_or_default();
+                bookmarks_page(&view, &colors)
             }
-            NodeData::Text { contents } => {
-                let text = contents.borrow();
-                let trimmed = text.trim();
-                if !trimmed.is_empty() {
-                    output.push_str(&format!("{}{}\n", indent, trimmed));
+            Some("settings") => match (&self.search_engines, &self.discovered, &self.descriptions) {
+                (Some(search_engines), Some(discovered), Some(descriptions)) => {
+                    let view = self.settings_view.lock().map(|view| view.clone()).unwrap_or_default();
+                    let page = SettingsPage {
+                        view: &view,
+                        search_engines: search_engines.as_ref(),
+                        discovered,
+                        descriptions: descriptions.as_ref(),
+                        custom_cas: self.custom_cas.as_deref(),
+                        trust_store: self.network.as_ref(),
+                        colors: &colors,
+                    };
+                    page.render(url.query()).await
                 }
+                _ => unavailable_page("Settings", "Settings are not available.", &colors),
+            },
+            Some("console") => console_page(&self.console, url.query(), &colors),
+            Some("scripts") => match &self.user_scripts {
+                Some(user_scripts) => scripts_page(user_scripts.as_ref(), &colors).await,
+                None => unavailable_page("User scripts", "User scripts are not available.", &colors),
+            },
+            Some("update") => {
+                let view = self.update_view.lock().map(|view| view.clone()).unwrap_or_default();
+                update_page(&view, &colors)
             }
-            _ => {}
+            Some("shortcuts") => {
+                let view = self.shortcuts_view.lock().map(|view| view.clone()).unwrap_or_default();
+                shortcuts_page(&view, &colors)
+            }
+            Some("memory") => {
+                let view = self.memory_view.lock().map(|view| view.clone()).unwrap_or_default();
+                memory_page(&view, &colors)
+            }
+            Some("site-settings") => {
+                let view = self.site_settings_view.lock().map(|view| view.clone()).unwrap_or_default();
+                let blocked_total = self.content_blocker.as_ref().map(|blocker| blocker.get_blocked_count());
+                site_settings_page(&view, blocked_total, url.query(), &colors)
+            }
+            _ => return Err(RenderError::InvalidContent(format!("No browser page at {}", url))),
+        };
+        Ok(("text/html".to_string(), html.into_bytes()))
+    }
+
+    /// Turn a response body into displayable HTML based on its content type
+    fn content_to_html(&self, url: &ValidatedUrl, content_type: &str, body: &[u8]) -> String {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+
+        match mime.as_str() {
+            "text/html" | "application/xhtml+xml" => String::from_utf8_lossy(body).into_owned(),
+            m if m.starts_with("text/") => format!(
+                "<html><body><pre>{}</pre></body></html>",
+                escape_html(&String::from_utf8_lossy(body))
+            ),
+            m if m.starts_with("image/") => format!(
+                "<html><head><title>{name}</title></head><body style=\"{style}\"><p>[image: {name}]</p></body></html>",
+                name = escape_html(url.path().rsplit('/').next().unwrap_or("image")),
+                style = self.page_colors().body_style()
+            ),
+            other => format!(
+                "<html><body style=\"{}\"><p>Cannot display content of type {}</p></body></html>",
+                self.page_colors().body_style(),
+                escape_html(other)
+            ),
         }
+    }
 
-        for child in node.children.borrow().iter() {
-            self.walk_dom(child, output, depth + 1);
+    /// Resolve a link on the current page, e.g. a sibling file of a local page
+    pub fn resolve_link(&self, href: &str) -> Option<ValidatedUrl> {
+        let base = self.current_base.lock().ok()?.clone();
+        let current = base.or_else(|| self.current_url())?;
+        current.join(href).ok()
+    }
+
+    pub fn current_url(&self) -> Option<ValidatedUrl> {
+        self.current_url.lock().ok().and_then(|url| url.clone())
+    }
+
+    /// Layout of the current document, computed when it was loaded
+    pub fn layout(&self) -> Arc<PageContent> {
+        self.current_layout
+            .lock()
+            .map(|layout| layout.clone())
+            .unwrap_or_default()
+    }
+
+    /// Run `f` on the current document's DOM, as script bindings do, and lay
+    /// the page out again if it changed the document
+    pub async fn update_dom<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut DomDocument) -> T + Send + 'static,
+    {
+        let url = self.current_url();
+        let ran = self
+            .dom()
+            .run(move |document| {
+                let value = f(document);
+                (value, document.take_dirty().then(|| document.to_html()))
+            })
+            .await;
+        let (value, changed) = match (ran, &url) {
+            // The document is gone; load it again, or show that it crashed
+            (Err(RenderError::Crashed(message)), Some(url)) if self.current_url().as_ref() == Some(url) => {
+                if !self.retry_after_crash(url, &message) {
+                    self.show_crashed_page(url, &message).await;
+                } else if let Err(e) = self.load_url(url).await {
+                    tracing::warn!("Failed to load {} again: {}", url, e);
+                }
+                return Err(RenderError::Crashed(message));
+            }
+            (ran, _) => ran?,
+        };
+        // A navigation may have replaced the document meanwhile
+        if let Some(html) = changed.filter(|_| self.current_url() == url) {
+            if let Ok(mut current_html) = self.current_html.lock() {
+                *current_html = html;
+            }
+            self.restyle().await?;
         }
+        Ok(value)
     }
-}
 
-impl Default for ServoRenderer {
-    fn default() -> Self {
-        Self::new()
+    fn dom(&self) -> &DomThread {
+        self.dom.get_or_init(|| DomThread::spawn().with_console(self.console.clone()))
     }
-}
 
-#[async_trait]
-impl RenderingEngine for ServoRenderer {
-    async fn load_url(&self, url: &ValidatedUrl) -> Result<()> {
+    /// Approximate heap bytes held for the current document, source and layout
+    pub fn retained_bytes(&self) -> usize {
+        let source = self.current_html.lock().map(|html| html.capacity()).unwrap_or(0);
+        source + self.layout().heap_size()
+    }
+
+    /// How many requests the current document made were refused
+    pub fn blocked_count(&self) -> usize {
+        self.current_blocked.lock().map(|blocked| blocked.len()).unwrap_or(0)
+    }
+
+    /// Render DOM to text
+    pub fn render_to_text(&self) -> String {
+        self.layout().text.clone()
+    }
+
+    /// Fetch, parse and lay out `url` as the tab's document;
+    /// `RenderError::Crashed` if its parser or scripts panicked
+    async fn load_document(&self, url: &ValidatedUrl) -> Result<()> {
         tracing::info!("Loading URL: {}", url);
+        let (config, site) = self.config_for(url);
+        if let Some(site) = &site {
+            tracing::debug!("Loading with the settings of {}: {:?}", site.origin, config);
+        }
+        self.set_blocked(Vec::new());
+        if let Ok(mut fetch) = self.current_fetch.lock() {
+            *fetch = None;
+        }
+        if let Ok(mut violations) = self.current_csp_violations.lock() {
+            violations.clear();
+        }
+        self.close_websockets();
+        if url.scheme() != "navigator" {
+            self.console.navigated();
+            self.console.push(ConsoleLevel::Log, format!("Navigated to {}", url), None);
+        }
+
+        // Pages and files saved in the open archive load without the network
+        let mut archive = self.current_archive.lock().ok().and_then(|archive| archive.clone());
+        let archived = archive.as_ref().and_then(|archive| archive.get(url).cloned());
+        archive = archive.filter(|_| archived.is_some());
+        // Whether the document may be kept for offline use, or when the
+        // offline copy shown was fetched
+        let (mut cacheable, mut offline_copy) = (false, None);
+        let (mut content_type, mut body) = async {
+            if let Some(archived) = archived {
+                return Ok((archived.content_type, archived.body));
+            }
+            match url.scheme() {
+                "file" => self.fetch_file(url).await,
+                "data" => self.fetch_data(url),
+                // Only the empty document gets past validation
+                "about" => Ok(("text/html".to_string(), Vec::new())),
+                "navigator" => self.internal_page(url).await,
+                _ if self.network.offline_mode().enabled() => {
+                    let (content_type, body, fetched_at) = self.fetch_offline(url).await;
+                    offline_copy = fetched_at;
+                    Ok((content_type, body))
+                }
+                _ => {
+                    let user_agent = site.as_ref().and_then(|site| site.user_agent.as_deref());
+                    match self.fetch_http(url, user_agent).await {
+                        Ok((content_type, body, keep)) => {
+                            cacheable = keep;
+                            Ok((content_type, body))
+                        }
+                        // Shown in place of the page, with no way past it
+                        Err(RenderError::Network(NetworkError::CertificateRevoked(host))) => {
+                            let page = revoked_certificate_page(&host, &self.page_colors());
+                            Ok(("text/html".to_string(), page.into_bytes()))
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+            }
+        }
+        .instrument(tracing::info_span!("fetch"))
+        .await
+        .inspect_err(|e| {
+            let category = self.content_blocker.as_ref().and_then(|blocker| blocker.category(url));
+            if let (RenderError::Network(NetworkError::Blocked(_)), Some(category)) = (e, category) {
+                let blocked = BlockedRequest { url: url.clone(), category };
+                self.set_blocked(vec![blocked]);
+            }
+        })?;
 
-        // Fetch HTML
-        let html = self.fetch_html(url).await?;
+        // An opened archive shows its page as if loaded from where it was saved
+        let mut base = None;
+        let is_archive = content_type.eq_ignore_ascii_case(MHTML_CONTENT_TYPE) || looks_like_mhtml(&body);
+        if url.scheme() == "file" && is_archive {
+            let opened = MhtmlArchive::parse(&body).map_err(|e| RenderError::InvalidContent(e.to_string()))?;
+            tracing::info!("Opened an archive of {} with {} parts", opened.root().url, opened.len());
+            content_type = opened.root().content_type.clone();
+            body = opened.root().body.clone();
+            base = Some(opened.root().url.clone());
+            archive = Some(Arc::new(opened));
+        }
+        let document_url = base.clone().unwrap_or_else(|| url.clone());
+
+        let max_size = self.config.max_document_size;
+        let truncated_at = (body.len() > max_size).then(|| {
+            body.truncate(max_size);
+            max_size
+        });
+        let mut html = self.content_to_html(&document_url, &content_type, &body);
+        if let Some(fetched_at) = offline_copy {
+            html = with_offline_banner(&html, fetched_at, &self.page_colors());
+        }
+        if let Some(archive) = &archive {
+            html = inline_stylesheets(&html, &document_url, archive.as_ref()).await;
+        }
 
-        // Parse HTML
-        let dom = self.parse_html(&html);
+        // Inline documents have no origin to trust
+        if url.scheme() == "data" {
+            html = sanitize_html(&html);
+        }
 
-        // Extract title
-        let title = self.extract_title(&dom);
+        // Parse and lay out without blocking the runtime
+        let user_styles = self.user_stylesheet(url);
+        let accessible = self.accessibility.enabled();
+        let mut parsed = parse_page(html.clone(), truncated_at, user_styles, &config, accessible).await?;
+        self.mark_visited_links(&mut parsed.content, &document_url).await;
+        let policies: Vec<CspPolicy> = self
+            .document_fetch()
+            .map(|fetch| fetch.header_values("content-security-policy").flat_map(CspPolicy::parse_list).collect())
+            .unwrap_or_default();
+        let blocked = self.blocked_subresources(&parsed.subresources, &document_url, &config, &policies);
+        if !blocked.is_empty() {
+            tracing::info!("Blocked {} requests on {}", blocked.len(), url);
+        }
+        if cacheable && truncated_at.is_none() {
+            self.keep_for_offline(url, &parsed.title, &content_type, &body).await;
+        }
 
         // Update state
         if let Ok(mut current_url) = self.current_url.lock() {
@@ -139,54 +1232,2701 @@ impl RenderingEngine for ServoRenderer {
         if let Ok(mut current_html) = self.current_html.lock() {
             *current_html = html.clone();
         }
+        if let Ok(mut current_base) = self.current_base.lock() {
+            *current_base = base;
+        }
+        if let Ok(mut current_archive) = self.current_archive.lock() {
+            *current_archive = archive;
+        }
+        if let Ok(mut current_truncated_at) = self.current_truncated_at.lock() {
+            *current_truncated_at = truncated_at;
+        }
+        // Scripts see the document as parsed, before layout strips anything
+        let dom_loaded = if config.enable_javascript {
+            self.dom().load(url.to_string(), html.clone(), truncated_at, config.parse_limits).await
+        } else if let Some(dom) = self.dom.get() {
+            dom.unload().await
+        } else {
+            Ok(())
+        };
+        match dom_loaded {
+            Err(RenderError::Crashed(message)) => return Err(RenderError::Crashed(message)),
+            Err(e) => tracing::warn!("Scripts on {} cannot reach the document: {}", url, e),
+            Ok(()) => {}
+        }
+        let (script_budget, user_agent) = (config.script_budget, config.user_agent.clone());
+        if let Ok(mut current_config) = self.current_config.lock() {
+            *current_config = config;
+        }
+        self.set_blocked(blocked);
+        if let Ok(mut current_csp) = self.current_csp.lock() {
+            *current_csp = policies;
+        }
+        self.check_csp(&parsed.subresources, &document_url);
         if let Ok(mut current_title) = self.current_title.lock() {
-            *current_title = title;
+            *current_title = parsed.title;
+        }
+        let search_description = parsed
+            .search_description
+            .and_then(|href| document_url.join(&href).ok())
+            .filter(|description| matches!(description.scheme(), "http" | "https"));
+        if let (Some(discovered), Some(description)) = (&self.discovered, &search_description) {
+            discovered.record(url, description.clone());
+        }
+        if let Ok(mut current_search_description) = self.current_search_description.lock() {
+            *current_search_description = search_description;
         }
+        if let Ok(mut current_layout) = self.current_layout.lock() {
+            *current_layout = Arc::new(parsed.content);
+        }
+        self.run_scripts(parsed.scripts, &document_url, script_budget, user_agent.as_deref())
+            .await;
 
         tracing::info!("Page loaded successfully: {}", url);
         Ok(())
     }
 
-    async fn get_title(&self) -> Result<String> {
-        if let Ok(title) = self.current_title.lock() {
-            Ok(title.clone())
-        } else {
-            Ok("Navigator".to_string())
+    /// Count a crash of `url`, returning whether it may be loaded again
+    /// without the user asking
+    fn retry_after_crash(&self, url: &ValidatedUrl, message: &str) -> bool {
+        let crashes = self
+            .crashes
+            .lock()
+            .map(|mut crashes| {
+                let count = crashes.entry(url.to_string()).or_insert(0);
+                *count += 1;
+                *count
+            })
+            .unwrap_or(u32::MAX);
+        if crashes > MAX_CRASH_RETRIES {
+            tracing::error!("{} crashed {} times, not loading it again: {}", url, crashes, message);
+            return false;
         }
+        tracing::warn!("{} crashed, loading it again: {}", url, message);
+        true
     }
 
-    async fn execute_javascript(&self, script: &str) -> Result<String> {
-        tracing::debug!("JavaScript execution: {}", script);
-
-        // TODO: Integrate boa_engine for JS execution
-        // For now, just log
-        tracing::warn!("JavaScript execution not yet implemented");
-        Ok(String::new())
+    /// Replace the document of `url` with a page saying it crashed, which
+    /// offers to load it again
+    async fn show_crashed_page(&self, url: &ValidatedUrl, message: &str) {
+        let html = crashed_page(url, message, &self.page_colors());
+        let config = RenderingConfig {
+            enable_javascript: false,
+            ..self.config.clone()
+        };
+        let accessible = self.accessibility.enabled();
+        let parsed = parse_page(html.clone(), None, UserStylesheet::default(), &config, accessible)
+            .await
+            .unwrap_or_default();
+        if let Some(dom) = self.dom.get() {
+            let _ = dom.unload().await;
+        }
+        if let Ok(mut current_url) = self.current_url.lock() {
+            *current_url = Some(url.clone());
+        }
+        if let Ok(mut current_html) = self.current_html.lock() {
+            *current_html = html;
+        }
+        if let Ok(mut current_base) = self.current_base.lock() {
+            *current_base = None;
+        }
+        if let Ok(mut current_truncated_at) = self.current_truncated_at.lock() {
+            *current_truncated_at = None;
+        }
+        if let Ok(mut current_search_description) = self.current_search_description.lock() {
+            *current_search_description = None;
+        }
+        if let Ok(mut current_title) = self.current_title.lock() {
+            *current_title = parsed.title;
+        }
+        if let Ok(mut current_layout) = self.current_layout.lock() {
+            *current_layout = Arc::new(parsed.content);
+        }
     }
+}
 
-    async fn take_screenshot(&self) -> Result<Vec<u8>> {
-        Ok(Vec::new())
-    }
+/// A parsed and laid-out document. Holds no DOM nodes, so it can be handed
+/// across threads.
+#[derive(Debug, Clone, Default)]
+pub struct ParsedPage {
+    pub title: String,
+    pub content: PageContent,
+    /// Limits the document ran into
+    pub limits: ParseReport,
+    /// `href` of the document's OpenSearch description link, unresolved
+    pub search_description: Option<String>,
+    /// The document's scripts, in document order; none when JavaScript is off
+    pub scripts: Vec<PageScript>,
+    /// Unresolved URLs of the images, scripts, frames and other resources
+    /// the document loads, in document order, each with the content
+    /// security policy directive it is loaded under
+    pub subresources: Vec<(&'static str, String)>,
 }
 
-/// Rendering configuration
-#[derive(Debug, Clone)]
-pub struct RenderingConfig {
-    pub enable_javascript: bool,
-    pub enable_images: bool,
-    pub enable_plugins: bool,
-    pub user_agent: Option<String>,
-    pub default_encoding: String,
+/// Parse and lay out HTML on the blocking thread pool, keeping multi-megabyte
+/// documents from stalling the async runtime or the UI. `truncated_at` is
+/// where the document was cut off, if it was; `config` holds the parse
+/// limits and whether scripts run; `accessible` lays it out with screen
+/// reader announcements.
+pub async fn parse_page(
+    html: String,
+    truncated_at: Option<usize>,
+    user_styles: UserStylesheet,
+    config: &RenderingConfig,
+    accessible: bool,
+) -> Result<ParsedPage> {
+    let (limits, scripting) = (config.parse_limits, config.enable_javascript);
+    // Blocking threads do not inherit the caller's span
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || {
+        let _span = span.entered();
+        let (dom, report) = tracing::info_span!("parse")
+            .in_scope(|| parse_html_scripted(&html, truncated_at, &limits, scripting));
+        ParsedPage {
+            title: extract_title(&dom),
+            search_description: extract_search_description(&dom),
+            scripts: if scripting { extract_scripts(&dom) } else { Vec::new() },
+            subresources: extract_subresources(&dom),
+            content: tracing::info_span!("layout").in_scope(|| {
+                LayoutBuilder::new()
+                    .with_user_styles(user_styles)
+                    .with_accessible_output(accessible)
+                    .with_scripting(scripting)
+                    .build(&dom)
+            }),
+            limits: report,
+        }
+    })
+    .await
+    .map_err(|e| match e.try_into_panic() {
+        Ok(payload) => RenderError::Crashed(panic_message(&*payload)),
+        Err(e) => RenderError::Layout(e.to_string()),
+    })
 }
 
-impl Default for RenderingConfig {
-    fn default() -> Self {
-        Self {
+/// Extract title from DOM
+fn extract_title(dom: &RcDom) -> String {
+    fn walk(handle: &Handle, title: &mut Option<String>) {
+        let node = handle;
+        if let NodeData::Element { name, .. } = &node.data {
+            if &name.local == "title" {
+                if let Some(text_node) = node.children.borrow().first() {
+                    if let NodeData::Text { contents } = &text_node.data {
+                        *title = Some(contents.borrow().to_string());
+                    }
+                }
+            }
+        }
+        for child in node.children.borrow().iter() {
+            walk(child, title);
+        }
+    }
+
+    let mut title = None;
+    walk(&dom.document, &mut title);
+    title.unwrap_or_else(|| "Untitled".to_string())
+}
+
+/// `href` of the first `<link rel="search">` to an OpenSearch description
+fn extract_search_description(dom: &RcDom) -> Option<String> {
+    fn walk(handle: &Handle) -> Option<String> {
+        if let NodeData::Element { name, attrs, .. } = &handle.data {
+            if &name.local == "link" {
+                let attrs = attrs.borrow();
+                let attribute = |wanted: &str| {
+                    attrs
+                        .iter()
+                        .find(|attr| &*attr.name.local == wanted)
+                        .map(|attr| attr.value.trim().to_string())
+                };
+                let is_search = attribute("rel").is_some_and(|rel| {
+                    rel.split_ascii_whitespace()
+                        .any(|token| token.eq_ignore_ascii_case("search"))
+                });
+                let is_opensearch =
+                    attribute("type").is_some_and(|kind| kind.eq_ignore_ascii_case(OPENSEARCH_CONTENT_TYPE));
+                if is_search && is_opensearch {
+                    if let Some(href) = attribute("href").filter(|href| !href.is_empty()) {
+                        return Some(href);
+                    }
+                }
+            }
+        }
+        handle.children.borrow().iter().find_map(walk)
+    }
+
+    walk(&dom.document)
+}
+
+/// `url` over https when it is plain http and one of `policies` upgrades
+/// insecure requests
+fn upgrade_insecure(url: ValidatedUrl, policies: &[CspPolicy]) -> ValidatedUrl {
+    if url.scheme() != "http" || !policies.iter().any(CspPolicy::upgrades_insecure_requests) {
+        return url;
+    }
+    ValidatedUrl::parse(&format!("https{}", &url.as_str()["http".len()..])).unwrap_or(url)
+}
+
+/// Where the document's subresources are loaded from, as written, and the
+/// directive each is loaded under
+fn extract_subresources(dom: &RcDom) -> Vec<(&'static str, String)> {
+    fn walk(handle: &Handle, urls: &mut Vec<(&'static str, String)>) {
+        if let NodeData::Element { name, attrs, .. } = &handle.data {
+            let attrs = attrs.borrow();
+            let attribute = |wanted: &str| {
+                attrs
+                    .iter()
+                    .find(|attr| &*attr.name.local == wanted)
+                    .map(|attr| attr.value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+            };
+            let tag = &*name.local;
+            if tag == "link" {
+                let directive = attribute("rel").and_then(|rel| {
+                    rel.split_ascii_whitespace().find_map(|token| {
+                        LOADING_LINK_RELS
+                            .iter()
+                            .find(|(wanted, _)| token.eq_ignore_ascii_case(wanted))
+                            .map(|(_, directive)| *directive)
+                    })
+                });
+                if let (Some(directive), Some(href)) = (directive, attribute("href")) {
+                    urls.push((directive, href));
+                }
+            }
+            for (element, wanted, directive) in SUBRESOURCE_ATTRIBUTES {
+                if tag == *element {
+                    urls.extend(attribute(wanted).map(|href| (*directive, href)));
+                }
+            }
+        }
+        for child in handle.children.borrow().iter() {
+            walk(child, urls);
+        }
+    }
+
+    let mut urls = Vec::new();
+    walk(&dom.document, &mut urls);
+    urls
+}
+
+impl Default for ServoRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl RenderingEngine for ServoRenderer {
+    async fn load_url(&self, url: &ValidatedUrl) -> Result<()> {
+        // A crashed page is loaded again, at most twice, before its crash
+        // is shown in its place
+        loop {
+            match self.load_document(url).await {
+                Err(RenderError::Crashed(message)) if !self.retry_after_crash(url, &message) => {
+                    self.show_crashed_page(url, &message).await;
+                    return Ok(());
+                }
+                Err(RenderError::Crashed(_)) => {}
+                loaded => return loaded,
+            }
+        }
+    }
+
+    async fn get_title(&self) -> Result<String> {
+        if let Ok(title) = self.current_title.lock() {
+            Ok(title.clone())
+        } else {
+            Ok("Navigator".to_string())
+        }
+    }
+
+    async fn execute_javascript(&self, script: &str) -> Result<String> {
+        tracing::debug!("JavaScript execution: {}", script);
+
+        // TODO: Integrate boa_engine for JS execution
+        // For now, just log
+        tracing::warn!("JavaScript execution not yet implemented");
+        Ok(String::new())
+    }
+
+    async fn take_screenshot(&self) -> Result<Vec<u8>> {
+        let Some(capture) = self.capture.clone() else {
+            return Ok(Vec::new());
+        };
+        let content = self.layout();
+        tokio::task::spawn_blocking(move || capture.capture(&content))
+            .await
+            .map_err(|e| RenderError::Capture(e.to_string()))?
+    }
+
+    async fn get_text(&self) -> Result<String> {
+        Ok(self.render_to_text())
+    }
+
+    async fn get_source(&self) -> Result<String> {
+        Ok(self.current_html.lock().map(|html| html.clone()).unwrap_or_default())
+    }
+
+    async fn restyle(&self) -> Result<()> {
+        let Some(url) = self.current_url() else {
+            return Ok(());
+        };
+        let html = self
+            .current_html
+            .lock()
+            .map(|html| html.clone())
+            .unwrap_or_default();
+        let truncated_at = self.current_truncated_at.lock().ok().and_then(|truncated_at| *truncated_at);
+        let user_styles = self.user_stylesheet(&url);
+        let (config, accessible) = (self.effective_config(), self.accessibility.enabled());
+        let mut parsed = parse_page(html, truncated_at, user_styles, &config, accessible).await?;
+        let base = self.current_base.lock().ok().and_then(|base| base.clone());
+        self.mark_visited_links(&mut parsed.content, base.as_ref().unwrap_or(&url)).await;
+
+        // A navigation may have replaced the document meanwhile
+        if self.current_url().as_ref() != Some(&url) {
+            return Ok(());
+        }
+        if let Ok(mut current_layout) = self.current_layout.lock() {
+            *current_layout = Arc::new(parsed.content);
+        }
+        Ok(())
+    }
+
+    async fn suspend(&self) -> Result<()> {
+        if let Ok(mut current_layout) = self.current_layout.lock() {
+            *current_layout = Arc::new(PageContent::default());
+        }
+        Ok(())
+    }
+
+    async fn accessibility_tree(&self) -> Result<AccessibleNode> {
+        if self.current_url().is_none() {
+            return Ok(AccessibleNode::new("document"));
+        }
+        let html = self.current_html.lock().map(|html| html.clone()).unwrap_or_default();
+        let truncated_at = self.current_truncated_at.lock().ok().and_then(|truncated_at| *truncated_at);
+        let config = self.effective_config();
+        let (limits, scripting) = (config.parse_limits, config.enable_javascript);
+        // Built from the DOM on request, as only assistive tools ask for it
+        let span = tracing::Span::current();
+        tokio::task::spawn_blocking(move || {
+            let _span = span.entered();
+            let (dom, _) = parse_html_scripted(&html, truncated_at, &limits, scripting);
+            build_accessibility_tree(&dom, &extract_title(&dom))
+        })
+        .await
+        .map_err(|e| RenderError::Layout(e.to_string()))
+    }
+
+    fn search_description(&self) -> Option<ValidatedUrl> {
+        self.current_search_description.lock().ok().and_then(|url| url.clone())
+    }
+
+    fn set_private(&self, private: bool) {
+        self.private.store(private, Ordering::Relaxed);
+    }
+
+    fn throttle_timers(&self, min_interval: Option<Duration>) {
+        self.dom().throttle_timers(min_interval);
+    }
+
+    fn console_messages(&self) -> Vec<ConsoleMessage> {
+        self.console.messages()
+    }
+
+    fn log_to_console(&self, level: ConsoleLevel, message: &str, source: Option<&str>) {
+        self.console.push(level, message, source);
+    }
+
+    fn clear_console(&self) {
+        self.console.clear();
+    }
+
+    fn set_preserve_console(&self, preserve: bool) {
+        self.console.set_preserve(preserve);
+    }
+
+    fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage::new(usize::from(self.current_url().is_some()), self.retained_bytes())
+    }
+
+    fn trim_memory(&self) -> usize {
+        let Ok(mut current_layout) = self.current_layout.lock() else {
+            return 0;
+        };
+        std::mem::take(&mut *current_layout).heap_size()
+    }
+
+    fn blocked_requests(&self) -> Vec<BlockedRequest> {
+        self.current_blocked.lock().map(|blocked| blocked.clone()).unwrap_or_default()
+    }
+
+    fn csp_violations(&self) -> Vec<CspViolation> {
+        self.current_csp_violations
+            .lock()
+            .map(|violations| violations.clone())
+            .unwrap_or_default()
+    }
+
+    fn http_version(&self) -> Option<HttpVersion> {
+        self.current_fetch.lock().ok()?.as_ref().map(|fetch| fetch.version)
+    }
+
+    fn document_fetch(&self) -> Option<DocumentFetch> {
+        self.current_fetch.lock().ok()?.clone()
+    }
+
+    fn certificate(&self) -> Option<Certificate> {
+        // The server the document came from, after any redirects
+        let url = self.document_fetch()?.url;
+        if !url.is_secure() {
+            return None;
+        }
+        self.network.certificate(url.host_str()?)
+    }
+
+    fn set_site_settings(&self, settings: Option<SiteSettings>) {
+        if let Ok(mut site_settings) = self.site_settings.lock() {
+            *site_settings = settings;
+        }
+    }
+}
+
+/// Guess a content type from a file extension
+fn content_type_for_path(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "xhtml" => "application/xhtml+xml",
+        "txt" | "md" | "rs" | "toml" | "log" => "text/plain",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "mhtml" | "mht" => MHTML_CONTENT_TYPE,
+        _ => "application/octet-stream",
+    }
+}
+
+/// Build an HTML index page for a local directory
+async fn directory_listing(path: &Path, colors: &PageColors) -> Result<String> {
+    let mut entries = Vec::new();
+    let unreadable = |e: std::io::Error| RenderError::File(format!("Cannot list {}: {}", path.display(), e));
+    let mut dir = tokio::fs::read_dir(path).await.map_err(unreadable)?;
+
+    while let Some(entry) = dir.next_entry().await.map_err(unreadable)? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_dir = entry.file_type().await.map(|t| t.is_dir()).unwrap_or(false);
+        entries.push((is_dir, name));
+    }
+
+    // Directories first, then files, each alphabetically
+    entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+    let title = escape_html(&path.display().to_string());
+    let link_style = format!("color: {}", colors.link);
+    let mut html = format!(
+        "<html><head><title>Index of {title}</title></head><body style=\"{}\"><h1>Index of {title}</h1><ul>",
+        colors.body_style()
+    );
+    html.push_str(&format!("<li><a href=\"../\" style=\"{link_style}\">../</a></li>"));
+    for (is_dir, name) in entries {
+        let suffix = if is_dir { "/" } else { "" };
+        let href = url::form_urlencoded::byte_serialize(name.as_bytes())
+            .collect::<String>()
+            .replace('+', "%20");
+        html.push_str(&format!(
+            "<li><a href=\"{href}{suffix}\" style=\"{link_style}\">{}{suffix}</a></li>",
+            escape_html(&name)
+        ));
+    }
+    html.push_str("</ul></body></html>");
+
+    Ok(html)
+}
+
+/// `set_level=` on navigator://logs: a level, or "default" to clear the override
+fn requested_log_level(query: Option<&str>) -> Option<Option<Level>> {
+    url::form_urlencoded::parse(query?.as_bytes())
+        .find(|(name, _)| name == "set_level")
+        .and_then(|(_, value)| match value.as_ref() {
+            "default" => Some(None),
+            other => other.parse().ok().map(Some),
+        })
+}
+
+/// Recorded log events, newest first, with links to filter them and to
+/// change the log level
+fn logs_page(diagnostics: &Diagnostics, filter: &LogFilter, colors: &PageColors) -> String {
+    let link_style = format!("color: {}", colors.link);
+    let link = |query: String, label: &str| format!("<a href=\"?{query}\" style=\"{link_style}\">{label}</a>");
+    let text_query = filter
+        .text
+        .as_ref()
+        .map(|text| format!("&q={}", url::form_urlencoded::byte_serialize(text.as_bytes()).collect::<String>()))
+        .unwrap_or_default();
+
+    let levels = &diagnostics.levels;
+    let mut html = format!(
+        "<html><head><title>Logs</title></head><body style=\"{}\"><h1>Logs</h1><p>Logging at {}{}. Log at: ",
+        colors.body_style(),
+        levels.level(),
+        if levels.overridden().is_some() { "" } else { " (default)" }
+    );
+    for level in ["debug", "trace", "default"] {
+        html.push_str(&link(format!("set_level={level}"), level));
+        html.push(' ');
+    }
+    html.push_str("</p><p>Show: ");
+    for (level, label) in [("error", "errors"), ("warn", "warnings"), ("info", "info"), ("debug", "debug")] {
+        html.push_str(&link(format!("level={level}{text_query}"), label));
+        html.push(' ');
+    }
+    html.push_str(&link(text_query.trim_start_matches('&').to_string(), "everything"));
+
+    let records = diagnostics.buffer.records();
+    let shown: Vec<_> = records.iter().rev().filter(|record| filter.matches(record)).collect();
+    html.push_str(&format!(
+        "</p><p>{} of {} events{}. Add q=text to the address to search.</p><pre>",
+        shown.len(),
+        records.len(),
+        filter
+            .text
+            .as_ref()
+            .map(|text| format!(" containing \"{}\"", escape_html(text)))
+            .unwrap_or_default()
+    ));
+    for record in shown {
+        html.push_str(&escape_html(&record.to_string()));
+        html.push('\n');
+    }
+    html.push_str("</pre></body></html>");
+    html
+}
+
+/// navigator://console: what the tab's scripts logged, newest first and
+/// colored by level. `preserve=on|off` and `clear` in the query are carried
+/// out before listing.
+fn console_page(console: &ConsoleLog, query: Option<&str>, colors: &PageColors) -> String {
+    for (name, value) in url::form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        match name.as_ref() {
+            "preserve" => console.set_preserve(value == "on"),
+            "clear" => console.clear(),
+            _ => {}
+        }
+    }
+    let messages = console.messages();
+    let link_style = format!("color: {}", colors.link);
+    let (preserve, toggle) = if console.preserves() { ("on", "off") } else { ("off", "on") };
+    let mut html = format!(
+        "<html><head><title>Console</title></head><body style=\"{}\"><h1>Console</h1>\
+         <p>{} messages. Preserve log: {preserve} \
+         <a href=\"?preserve={toggle}\" style=\"{link_style}\">[turn {toggle}]</a> \
+         <a href=\"?clear\" style=\"{link_style}\">[clear]</a></p>\
+         <p>&gt; Press Enter to evaluate an expression in this tab</p><pre>",
+        colors.body_style(),
+        messages.len()
+    );
+    for message in messages.iter().rev() {
+        let color = match message.level {
+            ConsoleLevel::Log => colors.text.as_str(),
+            ConsoleLevel::Info => colors.link.as_str(),
+            ConsoleLevel::Warn => "#9a6700",
+            ConsoleLevel::Error => "#c5221f",
+        };
+        html.push_str(&format!("<span style=\"color: {}\">{}", color, escape_html(&message.to_string())));
+        if let Some(stack) = &message.stack {
+            for frame in stack.lines() {
+                html.push_str(&format!("\n    {}", escape_html(frame.trim())));
+            }
+        }
+        html.push_str("</span>\n");
+    }
+    html.push_str("</pre></body></html>");
+    html
+}
+
+/// Shown in place of a page whose parser or scripts kept crashing
+fn crashed_page(url: &ValidatedUrl, message: &str, colors: &PageColors) -> String {
+    format!(
+        "<html><head><title>Page crashed</title></head><body style=\"{}\"><h1>This page crashed</h1>\
+         <p>Something on {url} went wrong while it was shown, so it was closed. Other tabs are not affected.</p>\
+         <p><a href=\"{url}\" style=\"color: {}\">Reload</a> the page, or press F5, to try again.</p>\
+         <p>Details: {}</p></body></html>",
+        colors.body_style(),
+        colors.link,
+        escape_html(message),
+        url = escape_html(url.as_str())
+    )
+}
+
+fn unavailable_page(title: &str, message: &str, colors: &PageColors) -> String {
+    format!(
+        "<html><head><title>{title}</title></head><body style=\"{}\"><p>{message}</p></body></html>",
+        colors.body_style()
+    )
+}
+
+/// navigator://update: the release notes of the newer release found, one
+/// paragraph per blank-line separated block, and a `download=1` link the
+/// browser saves the release for
+fn update_page(view: &UpdateView, colors: &PageColors) -> String {
+    let mut html = format!(
+        "<html><head><title>Update</title></head><body style=\"{}\"><h1>Update</h1><p>This is Navigator {}.</p>",
+        colors.body_style(),
+        escape_html(&view.current_version)
+    );
+    match &view.update {
+        Some(update) => {
+            html.push_str(&format!(
+                "<h2>Navigator {} is available</h2><p><a href=\"?download=1\" style=\"color: {}\">Download it</a> \
+                 from {}. It is saved to the downloads directory; nothing is installed.</p>",
+                escape_html(&update.version),
+                colors.link,
+                escape_html(update.download.host_str().unwrap_or(update.download.as_str()))
+            ));
+            for paragraph in update.notes.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+                html.push_str(&format!("<p>{}</p>", escape_html(paragraph).replace('\n', "<br>")));
+            }
+        }
+        None => html.push_str("<p>No newer release has been found.</p>"),
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+/// navigator://bookmarks: the bookmarks folder by folder, in the order
+/// Alt+Up and Alt+Down put them in
+fn bookmarks_page(view: &BookmarksView, colors: &PageColors) -> String {
+    let mut html = format!(
+        "<html><head><title>Bookmarks</title></head><body style=\"{}\"><h1>Bookmarks</h1>",
+        colors.body_style()
+    );
+    if view.bookmarks.is_empty() {
+        html.push_str("<p>No bookmarks.</p></body></html>");
+        return html;
+    }
+    html.push_str(
+        "<p>Up and Down pick a bookmark and Enter opens it. Alt+Up and Alt+Down move it within its folder.</p>",
+    );
+    let focused = view.focused.min(view.bookmarks.len() - 1);
+    for (index, bookmark) in view.bookmarks.iter().enumerate() {
+        if index == 0 || bookmark.folder != view.bookmarks[index - 1].folder {
+            if index > 0 {
+                html.push_str("</ul>");
+            }
+            let heading = bookmark.folder.as_deref().map_or("Unfiled".to_string(), escape_html);
+            html.push_str(&format!("<h2>{}</h2><ul>", heading));
+        }
+        let title = if bookmark.title.is_empty() { bookmark.url.as_str() } else { &bookmark.title };
+        let mut item = format!(
+            "<a href=\"{}\" style=\"color: {}\">{}</a>",
+            escape_html(bookmark.url.as_str()),
+            colors.link,
+            escape_html(title)
+        );
+        if index == focused {
+            item = format!("<strong>&gt; {}</strong>", item);
+            if let Some(error) = &view.error {
+                item.push_str(&format!("<br><em>{}</em>", escape_html(error)));
+            }
+        }
+        html.push_str(&format!("<li>{}</li>", item));
+    }
+    html.push_str("</ul></body></html>");
+    html
+}
+
+/// navigator://shortcuts: every command with its keyboard shortcuts, by
+/// section
+fn shortcuts_page(view: &ShortcutsView, colors: &PageColors) -> String {
+    let mut html = format!(
+        "<html><head><title>Keyboard shortcuts</title></head><body style=\"{}\"><h1>Keyboard shortcuts</h1>\
+         <p>Change them under Keyboard shortcuts on navigator://settings.</p>",
+        colors.body_style()
+    );
+    for (section, commands) in &view.sections {
+        html.push_str(&format!("<h2>{}</h2><ul>", escape_html(section)));
+        for (description, chords) in commands {
+            let chords = if chords.is_empty() { "(none)".to_string() } else { escape_html(chords) };
+            html.push_str(&format!("<li>{chords}: {}</li>", escape_html(description)));
+        }
+        html.push_str("</ul>");
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+/// navigator://memory: what the caches and stores hold in all, those tabs
+/// share, then each tab's with the largest first, and a `trim` link that
+/// asks them all to let go of what they can
+fn memory_page(view: &MemoryView, colors: &PageColors) -> String {
+    let size = |bytes: usize| format_size(bytes as u64);
+    let total: usize = view.reports.iter().map(|report| report.usage.bytes).sum();
+    let ceiling = if view.ceiling == 0 {
+        "Caches are never trimmed on their own.".to_string()
+    } else {
+        format!("Caches are trimmed past {}.", size(view.ceiling))
+    };
+    let mut html = format!(
+        "<html><head><title>Memory</title></head><body style=\"{}\"><h1>Memory</h1>\
+         <p>{} accounted. {ceiling} <a href=\"?trim\" style=\"color: {}\">[trim caches]</a></p>",
+        colors.body_style(),
+        size(total),
+        colors.link
+    );
+    if let Some(trimmed) = view.trimmed {
+        html.push_str(&format!("<p>Trimming freed {}.</p>", size(trimmed)));
+    }
+    let list = |reports: &[&MemoryReport]| {
+        let mut list = String::from("<ul>");
+        for report in reports {
+            list.push_str(&format!(
+                "<li>{}: {} in {} entries</li>",
+                escape_html(&report.name),
+                size(report.usage.bytes),
+                report.usage.entries
+            ));
+        }
+        list.push_str("</ul>");
+        list
+    };
+
+    let shared: Vec<&MemoryReport> = view.reports.iter().filter(|report| report.tab.is_none()).collect();
+    html.push_str("<h2>Shared</h2>");
+    html.push_str(&list(&shared));
+    let mut tabs: Vec<(TabId, Vec<&MemoryReport>)> = Vec::new();
+    for report in &view.reports {
+        let Some(tab) = report.tab else {
+            continue;
+        };
+        match tabs.iter_mut().find(|(id, _)| *id == tab) {
+            Some((_, reports)) => reports.push(report),
+            None => tabs.push((tab, vec![report])),
+        }
+    }
+    let tab_bytes = |reports: &[&MemoryReport]| reports.iter().map(|report| report.usage.bytes).sum::<usize>();
+    tabs.sort_by_key(|(_, reports)| std::cmp::Reverse(tab_bytes(reports)));
+    for (tab, reports) in &tabs {
+        let title = view.tab_titles.get(tab).map(String::as_str).filter(|title| !title.is_empty());
+        html.push_str(&format!(
+            "<h2>{}: {}</h2>",
+            escape_html(title.unwrap_or("Untitled tab")),
+            size(tab_bytes(reports))
+        ));
+        html.push_str(&list(reports));
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+/// Shown instead of a page whose server presented a revoked certificate
+fn revoked_certificate_page(host: &str, colors: &PageColors) -> String {
+    format!(
+        "<html><head><title>Certificate revoked</title></head><body style=\"{}\"><h1>Certificate revoked</h1>\
+         <p>The authority that issued the certificate of {host} has revoked it, so the connection cannot be \
+         trusted. The page was not loaded.</p></body></html>",
+        colors.body_style(),
+        host = escape_html(host)
+    )
+}
+
+/// `html` with a notice at the top of its body that it is the copy kept
+/// for offline use, fetched at `fetched_at`
+fn with_offline_banner(html: &str, fetched_at: chrono::DateTime<chrono::Utc>, colors: &PageColors) -> String {
+    let fetched = fetched_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+    let banner = format!(
+        "<div role=\"status\" style=\"{}; border-bottom: 1px solid; padding: 4px\">\
+         Offline copy from {fetched}</div>",
+        colors.body_style()
+    );
+    let lower = html.to_ascii_lowercase();
+    let body_open = lower
+        .find("<body")
+        .filter(|start| !lower[start + 5..].starts_with(|c: char| c.is_ascii_alphanumeric()))
+        .and_then(|start| lower[start..].find('>').map(|end| start + end + 1));
+    match body_open {
+        Some(at) => format!("{}{}{}", &html[..at], banner, &html[at..]),
+        None => format!("{}{}", banner, html),
+    }
+}
+
+/// Shown offline in place of a page with no copy kept, linking to the pages
+/// of the same site that have one
+fn offline_page(url: &ValidatedUrl, kept: &[CachedPageSummary], colors: &PageColors) -> String {
+    let link_style = format!("color: {}", colors.link);
+    let mut html = format!(
+        "<html><head><title>Offline</title></head><body style=\"{}\"><h1>You are offline</h1>\
+         <p>{} was not kept for offline use. Turn offline mode off with Ctrl+Shift+O to load it.</p>",
+        colors.body_style(),
+        escape_html(url.as_str())
+    );
+    if !kept.is_empty() {
+        html.push_str("<h2>Available offline from this site</h2><ul>");
+        for page in kept {
+            let fetched = page.fetched_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+            let title = if page.title.is_empty() { page.url.as_str() } else { &page.title };
+            html.push_str(&format!(
+                "<li><a href=\"{}\" style=\"{link_style}\">{}</a> ({fetched})</li>",
+                escape_html(page.url.as_str()),
+                escape_html(title)
+            ));
+        }
+        html.push_str("</ul>");
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+/// Recent history grouped by day, with how each page was visited and
+/// links to delete an entry or a whole day. `delete=<id>` and `clear_day=<yyyy-mm-dd>` in the query are
+/// carried out before listing.
+async fn history_page(history: &dyn HistoryRepository, query: Option<&str>, colors: &PageColors) -> String {
+    for (name, value) in url::form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        let deleted = match name.as_ref() {
+            "delete" => match value.parse() {
+                Ok(id) => history.delete_by_id(id).await,
+                Err(_) => continue,
+            },
+            "clear_day" => match local_day_bounds(&value) {
+                Some((from, to)) => history.delete_range(from, to).await.map(|_| ()),
+                None => continue,
+            },
+            _ => continue,
+        };
+        if let Err(e) = deleted {
+            tracing::warn!("Failed to delete history: {}", e);
+        }
+    }
+
+    let entries = match history.get_recent(HISTORY_PAGE_LIMIT).await {
+        Ok(entries) => entries,
+        Err(e) => return unavailable_page("History", &escape_html(&format!("Cannot read history: {}", e)), colors),
+    };
+    let ids: Vec<i64> = entries.iter().map(|entry| entry.id).collect();
+    let visits = history.visits(&ids).await.unwrap_or_else(|e| {
+        tracing::warn!("Failed to read visits: {}", e);
+        Vec::new()
+    });
+
+    let link_style = format!("color: {}", colors.link);
+    let mut html = format!(
+        "<html><head><title>History</title></head><body style=\"{}\"><h1>History</h1>",
+        colors.body_style()
+    );
+    if entries.is_empty() {
+        html.push_str("<p>No history.</p>");
+    }
+    let mut day = None;
+    for entry in &entries {
+        let visited = entry.visited_at.with_timezone(&chrono::Local);
+        if day != Some(visited.date_naive()) {
+            if day.is_some() {
+                html.push_str("</ul>");
+            }
+            day = Some(visited.date_naive());
+            html.push_str(&format!(
+                "<h2>{}</h2><p><a href=\"?clear_day={}\" style=\"{link_style}\">Clear this day</a></p><ul>",
+                visited.format("%A, %-d %B %Y"),
+                visited.format("%Y-%m-%d")
+            ));
+        }
+        let breakdown = visit_breakdown(entry.id, &visits);
+        html.push_str(&history_item(entry, &visited.format("%H:%M").to_string(), &breakdown, &link_style));
+    }
+    if day.is_some() {
+        html.push_str("</ul>");
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+/// navigator://downloads: saved files, newest first, each with a link to
+/// forget it as `remove=<id>`; the file itself is kept. `clear=all` forgets
+/// them all.
+async fn downloads_page(
+    downloads: &dyn DownloadRepository,
+    view: &DownloadsView,
+    query: Option<&str>,
+    colors: &PageColors,
+) -> String {
+    for (name, value) in url::form_urlencoded::parse(query.unwrap_or("").as_bytes()) {
+        let removed = match (name.as_ref(), value.parse()) {
+            ("remove", Ok(id)) => vec![id],
+            ("clear", _) => match downloads.find_all().await {
+                Ok(all) => all.iter().map(|download| download.id).collect(),
+                Err(e) => {
+                    tracing::warn!("Failed to read downloads: {}", e);
+                    Vec::new()
+                }
+            },
+            _ => continue,
+        };
+        for id in removed {
+            if let Err(e) = downloads.delete(id).await {
+                tracing::warn!("Failed to remove download: {}", e);
+            }
+        }
+    }
+
+    let downloads = match downloads.find_all().await {
+        Ok(downloads) => downloads,
+        Err(e) => {
+            let message = escape_html(&format!("Cannot read downloads: {}", e));
+            return unavailable_page("Downloads", &message, colors);
+        }
+    };
+
+    let link_style = format!("color: {}", colors.link);
+    let mut html = format!(
+        "<html><head><title>Downloads</title></head><body style=\"{}\"><h1>Downloads</h1>",
+        colors.body_style()
+    );
+    let listed = view.pending.len() + view.unfinished.len() + downloads.len();
+    let focused = view.focused.min(listed.saturating_sub(1));
+    if !view.pending.is_empty() {
+        html.push_str(
+            "<h2>Waiting for confirmation</h2><p>These files may harm this computer. Enter keeps the focused \
+             one, Delete discards it.</p><ul>",
+        );
+        for (index, (file_name, warnings)) in view.pending.iter().enumerate() {
+            let mut item = format!("{}: {}", escape_html(file_name), escape_html(warnings));
+            if index == focused {
+                item = format!("<strong>&gt; {}</strong>", item);
+                if let Some(error) = &view.error {
+                    item.push_str(&format!("<br><em>{}</em>", escape_html(error)));
+                }
+            }
+            html.push_str(&format!("<li>{}</li>", item));
+        }
+        html.push_str("</ul>");
+    }
+    if !view.unfinished.is_empty() {
+        html.push_str(
+            "<h2>In progress</h2><p>Enter tries a failed download again, Delete cancels the focused one or \
+             forgets it.</p><ul>",
+        );
+        let focused = focused.checked_sub(view.pending.len());
+        for (index, (file_name, state)) in view.unfinished.iter().enumerate() {
+            let mut item = format!("{}: {}", escape_html(file_name), escape_html(state));
+            if Some(index) == focused {
+                item = format!("<strong>&gt; {}</strong>", item);
+                if let Some(error) = &view.error {
+                    item.push_str(&format!("<br><em>{}</em>", escape_html(error)));
+                }
+            }
+            html.push_str(&format!("<li>{}</li>", item));
+        }
+        html.push_str("</ul>");
+    }
+    if downloads.is_empty() {
+        html.push_str("<p>No downloads.</p>");
+    } else {
+        html.push_str(&format!(
+            "<p>Up and Down pick a download. Enter opens its folder, Delete takes it off the list, \
+             Shift+Delete deletes its file too, and C clears the list. \
+             <a href=\"?clear=all\" style=\"{link_style}\">[clear list]</a></p><ul>"
+        ));
+        let focused = focused.checked_sub(view.pending.len() + view.unfinished.len());
+        for (index, download) in downloads.iter().enumerate() {
+            let error = view.error.as_deref().filter(|_| Some(index) == focused);
+            html.push_str(&download_item(download, Some(index) == focused, error, &link_style));
+        }
+        html.push_str("</ul>");
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+fn download_item(download: &Download, focused: bool, error: Option<&str>, link_style: &str) -> String {
+    let name = download
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| download.path.display().to_string());
+    let file = match url::Url::from_file_path(&download.path) {
+        Ok(file) => format!(
+            "<a href=\"{}\" style=\"{link_style}\">{}</a>",
+            escape_html(file.as_str()),
+            escape_html(&name)
+        ),
+        Err(()) => escape_html(&name),
+    };
+    let origin = download.url.host_str().unwrap_or(download.url.as_str());
+    let state = if download.path.exists() { "complete" } else { "file missing" };
+    let saved = download.created_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M");
+    let mut item = format!(
+        "{file} from <a href=\"{url}\" style=\"{link_style}\">{}</a>: {}, {state}, saved {saved} \
+         <a href=\"?remove={}\" style=\"{link_style}\">[remove]</a>",
+        escape_html(origin),
+        format_size(download.size),
+        download.id,
+        url = escape_html(download.url.as_str())
+    );
+    if focused {
+        item = format!("<strong>&gt; {}</strong>", item);
+    }
+    if let Some(error) = error {
+        item.push_str(&format!("<br><em>{}</em>", escape_html(error)));
+    }
+    format!("<li>{}</li>", item)
+}
+
+/// A file size in bytes, KB or MB
+fn format_size(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    match bytes as f64 {
+        size if size < KB => format!("{} bytes", bytes),
+        size if size < KB * KB => format!("{:.1} KB", size / KB),
+        size => format!("{:.1} MB", size / (KB * KB)),
+    }
+}
+
+/// navigator://settings: the browser settings, the search engines, with
+/// links to make one the default or remove it, those offered by pages
+/// loaded this session, and the imported certificate authorities.
+struct SettingsPage<'a> {
+    view: &'a SettingsView,
+    search_engines: &'a dyn SearchEngineRepository,
+    discovered: &'a DiscoveredSearchEngines,
+    descriptions: &'a dyn OpenSearchFetcher,
+    custom_cas: Option<&'a dyn CustomCaRepository>,
+    trust_store: &'a dyn TrustStore,
+    colors: &'a PageColors,
+}
+
+impl SettingsPage<'_> {
+    /// Carries out `set_default=<id>`, `remove=<id>`, `remove_ca=<id>`,
+    /// `add_opensearch=<url>` or `name=`, `keyword=` and `template=` (with
+    /// `suggest=` optional) adding an engine, then lists the engines
+    async fn render(&self, query: Option<&str>) -> String {
+        let params: Vec<(String, String)> = url::form_urlencoded::parse(query.unwrap_or("").as_bytes())
+            .into_owned()
+            .collect();
+        let message = match self.act(&params).await {
+            Ok(Some(message)) => format!("<p>{}</p>", escape_html(&message)),
+            Ok(None) => String::new(),
+            Err(e) => format!("<p><strong>{}</strong></p>", escape_html(&e)),
+        };
+
+        let engines = match self.search_engines.find_all().await {
+            Ok(engines) => engines,
+            Err(e) => {
+                let message = escape_html(&format!("Cannot read search engines: {}", e));
+                return unavailable_page("Settings", &message, self.colors);
+            }
+        };
+        let link_style = format!("color: {}", self.colors.link);
+        let mut html = format!(
+            "<html><head><title>Settings</title></head><body style=\"{}\"><h1>Settings</h1>{message}",
+            self.colors.body_style()
+        );
+        if !self.view.rows.is_empty() {
+            let shortcuts_from = self.view.shortcuts_from.unwrap_or(self.view.rows.len());
+            html.push_str(
+                "<h2>Browser</h2><p>Up and Down choose a setting; Enter changes it, \
+                 or edits it in the address bar. User styles are a host pattern and a rule each, \
+                 separated by semicolons: <code>* min-font-size=14; *.example.com hide=.ad</code>.</p>",
+            );
+            html.push_str(&settings_rows(self.view, 0..shortcuts_from));
+            if shortcuts_from < self.view.rows.len() {
+                html.push_str(
+                    "<h2>Keyboard shortcuts</h2><p>Type keys such as Ctrl+Shift+T, separated by commas, \
+                     or none. A shortcut another command has is refused. \
+                     navigator://shortcuts lists them all.</p>",
+                );
+                html.push_str(&settings_rows(self.view, shortcuts_from..self.view.rows.len()));
+            }
+        }
+        html.push_str("<h2>Search engines</h2><p>Type a keyword before a search to use that engine.</p><ul>");
+        for engine in &engines {
+            let action = if engine.is_default {
+                "(default)".to_string()
+            } else {
+                format!("<a href=\"?set_default={}\" style=\"{link_style}\">[make default]</a>", engine.id)
+            };
+            html.push_str(&format!(
+                "<li>{} <code>{}</code> {} {action} \
+                 <a href=\"?remove={}\" style=\"{link_style}\">[remove]</a></li>",
+                escape_html(&engine.name),
+                escape_html(&engine.keyword),
+                escape_html(engine.query_template.as_str()),
+                engine.id
+            ));
+        }
+        html.push_str("</ul>");
+
+        let offered: Vec<_> = self
+            .discovered
+            .offered()
+            .into_iter()
+            .filter(|offered| !engines.iter().any(|engine| offered_by(engine, &offered.description)))
+            .collect();
+        if !offered.is_empty() {
+            html.push_str("<h2>Offered by sites you visited</h2><ul>");
+            for engine in offered {
+                let encoded: String =
+                    url::form_urlencoded::byte_serialize(engine.description.as_str().as_bytes()).collect();
+                html.push_str(&format!(
+                    "<li>{} <a href=\"?add_opensearch={encoded}\" style=\"{link_style}\">[add]</a></li>",
+                    escape_html(&engine.site)
+                ));
+            }
+            html.push_str("</ul>");
+        }
+        html.push_str(
+            "<p>Add an engine with name=, keyword= and template= in the address, \
+             the template being an https URL with %s where the search goes.</p>",
+        );
+        if let Some(custom_cas) = self.custom_cas {
+            html.push_str(&certificate_authorities_section(custom_cas, &link_style).await);
+        }
+        html.push_str("</body></html>");
+        html
+    }
+
+    /// What the query asks for, returning a message to show
+    async fn act(&self, params: &[(String, String)]) -> std::result::Result<Option<String>, String> {
+        let param = |wanted: &str| params.iter().find(|(name, _)| name == wanted).map(|(_, value)| value.as_str());
+        let engines = self.search_engines.find_all().await.map_err(|e| e.to_string())?;
+        let existing = |id: Option<&str>| {
+            id.and_then(|id| id.parse::<i64>().ok())
+                .and_then(|id| engines.iter().find(|engine| engine.id == id))
+        };
+        if let Some(engine) = existing(param("set_default")) {
+            self.search_engines.set_default(engine.id).await.map_err(|e| e.to_string())?;
+            return Ok(Some(format!("{} is now the default search engine.", engine.name)));
+        }
+        if let Some(engine) = existing(param("remove")) {
+            self.search_engines.delete(engine.id).await.map_err(|e| e.to_string())?;
+            return Ok(Some(format!("Removed {}.", engine.name)));
+        }
+        if let (Some(custom_cas), Some(id)) = (self.custom_cas, param("remove_ca")) {
+            return self.remove_ca(custom_cas, id).await.map(Some);
+        }
+
+        let engine = if let Some(description) = param("add_opensearch") {
+            let description = ValidatedUrl::parse(description).map_err(|e| e.to_string())?;
+            self.descriptions.fetch(&description).await.map_err(|e| e.to_string())?
+        } else if let (Some(name), Some(keyword), Some(template)) =
+            (param("name"), param("keyword"), param("template"))
+        {
+            let parse = |template: &str| {
+                SearchTemplate::parse(template)
+                    .ok_or_else(|| SearchEngineError::InvalidTemplate(template.to_string()))
+            };
+            let template = parse(template).map_err(|e| e.to_string())?;
+            let mut engine = NewSearchEngine::new(name.trim().to_string(), keyword, template);
+            if let Some(suggest) = param("suggest").filter(|suggest| !suggest.is_empty()) {
+                engine = engine.with_suggest_template(parse(suggest).map_err(|e| e.to_string())?);
+            }
+            engine
+        } else {
+            return Ok(None);
+        };
+
+        // As adding from the browser does: a free keyword, and the first
+        // engine becomes the default
+        engine.check_keyword(&engines).map_err(|e| e.to_string())?;
+        let saved = self.search_engines.save(engine).await.map_err(|e| e.to_string())?;
+        if !engines.iter().any(|engine| engine.is_default) {
+            self.search_engines.set_default(saved.id).await.map_err(|e| e.to_string())?;
+        }
+        Ok(Some(format!("Added {} with the keyword {}.", saved.name, saved.keyword)))
+    }
+
+    /// Forgets an imported authority and stops trusting it
+    async fn remove_ca(
+        &self,
+        custom_cas: &dyn CustomCaRepository,
+        id: &str,
+    ) -> std::result::Result<String, String> {
+        let existing = custom_cas.find_all().await.map_err(|e| e.to_string())?;
+        let Some(ca) = id.parse::<i64>().ok().and_then(|id| existing.into_iter().find(|ca| ca.id == id)) else {
+            return Err(format!("No certificate authority {id}"));
+        };
+        custom_cas.delete(ca.id).await.map_err(|e| e.to_string())?;
+        let remaining = custom_cas.find_all().await.map_err(|e| e.to_string())?;
+        self.trust_store.set_custom_roots(&remaining).map_err(|e| e.to_string())?;
+        Ok(format!("No longer trusting {}.", ca.name))
+    }
+}
+
+/// The user scripts and where they run, each with links to change it as
+/// `set=<id>:<option>=<value>` and to remove it as `remove=<id>`, which
+/// the controller carries out before the page is shown
+async fn scripts_page(user_scripts: &dyn UserScriptRepository, colors: &PageColors) -> String {
+    let scripts = match user_scripts.find_all().await {
+        Ok(scripts) => scripts,
+        Err(e) => {
+            let message = escape_html(&format!("Cannot read user scripts: {}", e));
+            return unavailable_page("User scripts", &message, colors);
+        }
+    };
+    let link_style = format!("color: {}", colors.link);
+    let set = |id: i64, option: &str, label: &str| {
+        let value: String = url::form_urlencoded::byte_serialize(format!("{id}:{option}").as_bytes()).collect();
+        format!("<a href=\"?set={value}\" style=\"{link_style}\">[{label}]</a>")
+    };
+    let mut html = format!(
+        "<html><head><title>User scripts</title></head><body style=\"{}\"><h1>User scripts</h1>\
+         <p>Scripts run on the pages their patterns match. Add one from a file with \
+         <code>navigator user-script add &lt;name&gt; &lt;match,...&gt; &lt;file&gt;</code>.</p>",
+        colors.body_style()
+    );
+    if scripts.is_empty() {
+        html.push_str("<p>No user scripts.</p></body></html>");
+        return html;
+    }
+    html.push_str("<ul>");
+    for script in &scripts {
+        let id = script.id;
+        let enabled = if script.enabled {
+            format!("enabled {}", set(id, "enabled=false", "disable"))
+        } else {
+            format!("disabled {}", set(id, "enabled=true", "enable"))
+        };
+        let other = match script.run_at {
+            RunAt::DocumentEnd => RunAt::DocumentIdle,
+            RunAt::DocumentIdle => RunAt::DocumentEnd,
+        };
+        let run_at = format!("{} {}", script.run_at, set(id, &format!("run-at={other}"), &format!("run at {other}")));
+        let allowed = |option: &str, allowed: bool, place: &str| {
+            if allowed {
+                format!("runs in {place} {}", set(id, &format!("{option}=false"), "stop"))
+            } else {
+                format!("not in {place} {}", set(id, &format!("{option}=true"), "allow"))
+            }
+        };
+        let patterns: Vec<&str> = script.matches.iter().map(|pattern| pattern.as_str()).collect();
+        html.push_str(&format!(
+            "<li><strong>{}</strong> {enabled} <a href=\"?remove={id}\" style=\"{link_style}\">[remove]</a>\
+             <br>On <code>{}</code> at {run_at}<br>{}; {}",
+            escape_html(&script.name),
+            escape_html(&patterns.join(", ")),
+            allowed("allow-private", script.allow_private, "private tabs"),
+            allowed("allow-internal", script.allow_internal, "browser pages"),
+        ));
+        if let Some(error) = &script.last_error {
+            html.push_str(&format!("<br>Last run failed: {}", escape_html(error)));
+        }
+        html.push_str("</li>");
+    }
+    html.push_str("</ul></body></html>");
+    html
+}
+
+/// The imported certificate authorities with their fingerprints, each with
+/// a link to remove it as `remove_ca=<id>`
+async fn certificate_authorities_section(custom_cas: &dyn CustomCaRepository, link_style: &str) -> String {
+    let mut html = String::from("<h2>Certificate authorities</h2>");
+    let cas = match custom_cas.find_all().await {
+        Ok(cas) => cas,
+        Err(e) => {
+            return format!("{html}<p>{}</p>", escape_html(&format!("Cannot read certificate authorities: {}", e)));
+        }
+    };
+    if cas.is_empty() {
+        html.push_str(
+            "<p>Only the built-in authorities are trusted. Import one with <code>navigator ca add</code>.</p>",
+        );
+        return html;
+    }
+    html.push_str("<p>Trusted besides the built-in authorities:</p><ul>");
+    for ca in &cas {
+        html.push_str(&format!(
+            "<li>{} <code>{}</code> added {} <a href=\"?remove_ca={}\" style=\"{link_style}\">[remove]</a></li>",
+            escape_html(&ca.name),
+            escape_html(&ca.fingerprint),
+            ca.added_at.format("%Y-%m-%d"),
+            ca.id
+        ));
+    }
+    html.push_str("</ul>");
+    html
+}
+
+/// The rows of a settings view as a list, the focused one marked and
+/// followed by why its last change was refused
+fn settings_list(view: &SettingsView) -> String {
+    settings_rows(view, 0..view.rows.len())
+}
+
+/// The rows of a settings view in `range`, listed as by [`settings_list`]
+fn settings_rows(view: &SettingsView, range: std::ops::Range<usize>) -> String {
+    let mut html = String::from("<ul>");
+    for (index, (label, value)) in view.rows.iter().enumerate().skip(range.start).take(range.len()) {
+        let value = if value.is_empty() { "(not set)".to_string() } else { escape_html(value) };
+        if index != view.focused {
+            html.push_str(&format!("<li>{}: {value}</li>", escape_html(label)));
+            continue;
+        }
+        html.push_str(&format!("<li><strong>&gt; {}: {value}</strong>", escape_html(label)));
+        if let Some(error) = &view.error {
+            html.push_str(&format!("<br><em>{}</em>", escape_html(error)));
+        }
+        html.push_str("</li>");
+    }
+    html.push_str("</ul>");
+    html
+}
+
+/// navigator://site-settings?origin=<origin>: the overrides a site has of
+/// the global settings, changed from the keyboard as on navigator://settings,
+/// then what the page was refused. `blocked_total` counts the requests the
+/// content blocker refused in every tab.
+fn site_settings_page(
+    view: &SiteSettingsView,
+    blocked_total: Option<usize>,
+    query: Option<&str>,
+    colors: &PageColors,
+) -> String {
+    let origin = url::form_urlencoded::parse(query.unwrap_or("").as_bytes())
+        .find(|(name, _)| name == "origin")
+        .and_then(|(_, origin)| SiteOrigin::parse(&origin));
+    let Some(origin) = origin else {
+        return unavailable_page(
+            "Site settings",
+            "Press Ctrl+I on a web page to change its settings.",
+            colors,
+        );
+    };
+    format!(
+        "<html><head><title>Site settings</title></head><body style=\"{}\"><h1>{origin}</h1>\
+         <p>Up and Down choose a setting; Enter changes it, or edits it in the address bar, \
+         and Delete goes back to the browser's own; on Stored data either clears it. \
+         Pages on this site use the changes when next loaded.</p>{}{}{}\
+         <h2>Blocked on the page</h2>{}{}</body></html>",
+        colors.body_style(),
+        settings_list(&view.settings),
+        view.protocol
+            .map(|protocol| format!("<h2>Connection</h2><p>The page came over {}.</p>", protocol))
+            .unwrap_or_default(),
+        view.certificate.as_ref().map(certificate_section).unwrap_or_default(),
+        blocked_list(&view.blocked, blocked_total),
+        csp_violations_section(&view.csp_violations),
+        origin = escape_html(origin.as_str())
+    )
+}
+
+/// Who a certificate is for, and what its stapled OCSP response and
+/// embedded timestamps said
+fn certificate_section(certificate: &Certificate) -> String {
+    let date = |time: &chrono::DateTime<chrono::Utc>| time.format("%Y-%m-%d %H:%M UTC").to_string();
+    let revocation = match &certificate.ocsp {
+        Some(ocsp) => format!(
+            "The issuer's OCSP response says the certificate is {}, as of {}{}.",
+            ocsp.status,
+            date(&ocsp.this_update),
+            ocsp.next_update
+                .map(|next| format!("; the next is due {}", date(&next)))
+                .unwrap_or_default()
+        ),
+        None => "The server did not staple an OCSP response.".to_string(),
+    };
+    let transparency = match certificate.sct_count {
+        0 => "The certificate carries no certificate transparency timestamps.".to_string(),
+        1 => "The certificate carries 1 certificate transparency timestamp.".to_string(),
+        count => format!("The certificate carries {} certificate transparency timestamps.", count),
+    };
+    format!(
+        "<h2>Certificate</h2><p>Issued to {} by {}, valid until {}.</p><p>{}</p><p>{}</p>",
+        escape_html(&certificate.subject),
+        escape_html(&certificate.issuer),
+        date(&certificate.valid_until),
+        revocation,
+        transparency
+    )
+}
+
+/// How many loads broke the page's content security policy, then each
+/// with the directive it broke
+fn csp_violations_section(violations: &[CspViolation]) -> String {
+    if violations.is_empty() {
+        return String::new();
+    }
+    let enforced = violations.iter().filter(|violation| violation.enforced).count();
+    let mut html = format!(
+        "<h2>Content security policy</h2><p>{} loads broke the page's policy; \
+         {} were refused and {} only reported.</p><ul>",
+        violations.len(),
+        enforced,
+        violations.len() - enforced
+    );
+    for violation in violations {
+        html.push_str(&format!(
+            "<li>{} ({})</li>",
+            escape_html(violation.url.as_str()),
+            escape_html(&violation.directive)
+        ));
+    }
+    html.push_str("</ul>");
+    html
+}
+
+/// Counts of what a page was refused, then the URLs by site
+fn blocked_list(blocked: &BlockedSummary, blocked_total: Option<usize>) -> String {
+    let mut html = if blocked.total() == 0 {
+        "<p>Nothing was blocked.</p>".to_string()
+    } else {
+        let counts: Vec<String> = blocked
+            .counts
+            .iter()
+            .map(|(category, count)| format!("{} {}", count, category))
+            .collect();
+        format!("<p>{} requests: {}.</p>", blocked.total(), counts.join(", "))
+    };
+    if let Some(total) = blocked_total {
+        html.push_str(&format!("<p>{} requests blocked in all tabs this session.</p>", total));
+    }
+    if blocked.sites.is_empty() {
+        return html;
+    }
+    html.push_str("<ul>");
+    for (site, urls) in &blocked.sites {
+        html.push_str(&format!("<li>{}<ul>", escape_html(site)));
+        for url in urls {
+            html.push_str(&format!("<li>{}</li>", escape_html(url.as_str())));
+        }
+        html.push_str("</ul></li>");
+    }
+    html.push_str("</ul>");
+    html
+}
+
+/// Whether `engine` was added from, or searches the site of, the description
+/// at `description`
+fn offered_by(engine: &SearchEngine, description: &ValidatedUrl) -> bool {
+    let host = description.normalized().host_str().unwrap_or("").to_string();
+    engine.keyword == host.strip_prefix("www.").unwrap_or(&host) || engine.query_template.host() == Some(host)
+}
+
+fn history_item(entry: &HistoryEntry, time: &str, breakdown: &str, link_style: &str) -> String {
+    let url = escape_html(entry.url.as_str());
+    let title = if entry.title.is_empty() { url.clone() } else { escape_html(&entry.title) };
+    let breakdown = if breakdown.is_empty() { String::new() } else { format!(" ({})", breakdown) };
+    format!(
+        "<li>{time} <a href=\"{url}\" style=\"{link_style}\">{title}</a>{breakdown} \
+         <a href=\"?delete={}\" style=\"{link_style}\">[delete]</a></li>",
+        entry.id
+    )
+}
+
+/// How the visits to the entry `history_id` came about, as in
+/// "typed 12×, links 3×"
+fn visit_breakdown(history_id: i64, visits: &[Visit]) -> String {
+    let visits: Vec<VisitType> = visits
+        .iter()
+        .filter(|visit| visit.history_id == history_id)
+        .map(|visit| visit.visit_type)
+        .collect();
+    VisitType::ALL
+        .iter()
+        .filter_map(|visit_type| {
+            let count = visits.iter().filter(|visited| *visited == visit_type).count();
+            (count > 0).then(|| format!("{} {}×", visit_type.label(), count))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Rendering configuration
+#[derive(Debug, Clone)]
+pub struct RenderingConfig {
+    /// Run pages' scripts; with it off `<noscript>` content is shown
+    pub enable_javascript: bool,
+    pub enable_images: bool,
+    pub enable_plugins: bool,
+    /// Cookies the site's pages may keep, as the network client enforces
+    /// them for each request
+    pub cookie_policy: CookiePolicy,
+    pub user_agent: Option<String>,
+    /// Refuse the ads and trackers the content blocker lists
+    pub block_content: bool,
+    /// The site's choice of dark pages over the browser's; the front end
+    /// does the recoloring
+    pub dark_pages: Option<bool>,
+    pub default_encoding: String,
+    /// Largest decoded payload accepted from a data: URL
+    pub max_data_url_size: usize,
+    /// Largest document body read; anything past it is cut off
+    pub max_document_size: usize,
+    pub parse_limits: ParseLimits,
+    /// Time a page's scripts may run for between them; later ones are skipped
+    pub script_budget: Duration,
+}
+
+impl Default for RenderingConfig {
+    fn default() -> Self {
+        Self {
             enable_javascript: true,
             enable_images: true,
             enable_plugins: false,
+            cookie_policy: CookiePolicy::default(),
             user_agent: Some(format!("Navigator/{}", env!("CARGO_PKG_VERSION"))),
+            block_content: true,
+            dark_pages: None,
             default_encoding: "UTF-8".to_string(),
+            max_data_url_size: DEFAULT_MAX_DATA_URL_SIZE,
+            max_document_size: DEFAULT_MAX_DOCUMENT_SIZE,
+            parse_limits: ParseLimits::default(),
+            script_budget: DEFAULT_SCRIPT_BUDGET,
+        }
+    }
+}
+
+impl RenderingConfig {
+    /// This configuration with a site's overrides on top
+    pub fn for_site(&self, site: Option<&SiteSettings>) -> Self {
+        let mut config = self.clone();
+        if let Some(site) = site {
+            config.enable_javascript = site.javascript.unwrap_or(config.enable_javascript);
+            config.enable_images = site.images.unwrap_or(config.enable_images);
+            config.cookie_policy = site.cookies.unwrap_or(config.cookie_policy);
+            config.user_agent = site.user_agent.clone().or(config.user_agent);
+            config.block_content = site.content_blocking.unwrap_or(config.block_content);
+            config.dark_pages = site.dark_pages.or(config.dark_pages);
+        }
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{OcspStaple, RevocationStatus};
+    use crate::infrastructure::FileAccessPolicy;
+
+    fn temp_site() -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("navigator-site-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(
+            root.join("index.html"),
+            "<html><head><title>Local</title></head><body><a href=\"about.html\">About</a></body></html>",
+        )
+        .unwrap();
+        std::fs::write(root.join("notes.txt"), "<plain & simple>").unwrap();
+        root
+    }
+
+    /// Access to the files under `root` alone
+    fn site_access(root: &Path) -> Arc<FileAccess> {
+        Arc::new(FileAccess::new(FileAccessPolicy {
+            enabled: true,
+            allowed_roots: vec![root.to_path_buf()],
+        }))
+    }
+
+    fn file_url(path: &Path) -> ValidatedUrl {
+        ValidatedUrl::parse(url::Url::from_file_path(path).unwrap().as_str()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_load_local_html() {
+        let root = temp_site();
+        let renderer = ServoRenderer::new().with_file_access(site_access(&root));
+
+        renderer.load_url(&file_url(&root.join("index.html"))).await.unwrap();
+        assert_eq!(renderer.get_title().await.unwrap(), "Local");
+
+        let sibling = renderer.resolve_link("about.html").unwrap();
+        assert_eq!(sibling, file_url(&root.join("about.html")));
+
+        // The renderer checks the policy itself, whatever the URL was
+        // validated with
+        let outside = ServoRenderer::new().with_file_access(site_access(&root.join("docs")));
+        let refused = outside.load_url(&file_url(&root.join("index.html"))).await;
+        assert!(matches!(refused, Err(RenderError::Security(SecurityError::FileAccessDenied(_)))));
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_dom_changes_are_laid_out() {
+        let root = temp_site();
+        let renderer = ServoRenderer::new().with_file_access(site_access(&root));
+        renderer.load_url(&file_url(&root.join("index.html"))).await.unwrap();
+
+        let text = renderer
+            .update_dom(|document| {
+                let link = document.query_selector("a").flatten().unwrap();
+                document.set_text_content(link, "Changed");
+                document.text_content(link)
+            })
+            .await
+            .unwrap();
+        assert_eq!(text.as_deref(), Some("Changed"));
+        assert_eq!(renderer.render_to_text(), "Changed");
+
+        // Reading alone leaves the layout as it is
+        let count = renderer.update_dom(|document| document.get_elements_by_tag_name("a").len()).await.unwrap();
+        assert_eq!(count, 1);
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_crashed_page_is_reloaded_twice_then_shown_as_crashed() {
+        let root = temp_site();
+        let page = file_url(&root.join("index.html"));
+        let (crashing, other) = (ServoRenderer::new().with_file_access(site_access(&root)), ServoRenderer::new().with_file_access(site_access(&root)));
+        crashing.load_url(&page).await.unwrap();
+        other.load_url(&page).await.unwrap();
+
+        for _ in 0..MAX_CRASH_RETRIES {
+            let crashed = crashing.update_dom(|_| -> () { panic!("script bug") }).await;
+            assert!(matches!(crashed, Err(RenderError::Crashed(message)) if message == "script bug"));
+            // Loaded again, scripts and all
+            assert_eq!(crashing.get_title().await.unwrap(), "Local");
+            assert_eq!(crashing.update_dom(|document| document.get_elements_by_tag_name("a").len()).await.unwrap(), 1);
+        }
+        assert!(crashing.update_dom(|_| -> () { panic!("script bug") }).await.is_err());
+        assert_eq!(crashing.get_title().await.unwrap(), "Page crashed");
+        assert_eq!(crashing.current_url(), Some(page.clone()));
+        assert!(crashing.render_to_text().contains("This page crashed"));
+        assert!(crashing.get_source().await.unwrap().contains("script bug"));
+
+        // The other tab's page and scripts never noticed
+        assert_eq!(other.get_title().await.unwrap(), "Local");
+        assert_eq!(other.update_dom(|document| document.get_elements_by_tag_name("a").len()).await.unwrap(), 1);
+
+        // Reloading by hand still tries the page
+        crashing.load_url(&page).await.unwrap();
+        assert_eq!(crashing.get_title().await.unwrap(), "Local");
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_blocked_subresources_are_kept_per_page() {
+        let root = temp_site();
+        std::fs::write(
+            root.join("ads.html"),
+            "<img src=\"https://cdn.ads.example/a.png\"><img src=\"https://cdn.ads.example/a.png\">\
+             <script src=\"https://pixel.example/t.js\"></script>\
+             <link rel=\"stylesheet\" href=\"https://ok.example/s.css\">\
+             <a href=\"https://ads.example/\">Not loaded</a>",
+        )
+        .unwrap();
+        let blocker = Arc::new(ContentBlocker::new().with_hosts(["ads.example".to_string()]));
+        blocker.add_host_as("pixel.example", BlockCategory::Trackers);
+        let renderer = ServoRenderer::new().with_file_access(site_access(&root)).with_content_blocker(blocker.clone());
+
+        renderer.load_url(&file_url(&root.join("ads.html"))).await.unwrap();
+        let blocked = renderer.blocked_requests();
+        let categories: Vec<BlockCategory> = blocked.iter().map(|request| request.category).collect();
+        assert_eq!(categories, [BlockCategory::Ads, BlockCategory::Trackers]);
+        assert_eq!(blocked[0].url.as_str(), "https://cdn.ads.example/a.png");
+        assert_eq!(blocker.get_blocked_count(), 2);
+
+        let page = ValidatedUrl::parse("https://example.com/").unwrap();
+        let subresources = [
+            ("img-src", "http://cdn.ads.example/b.png".to_string()),
+            ("img-src", "http://example.com/c.png".to_string()),
+        ];
+        let exempt = RenderingConfig { block_content: false, ..RenderingConfig::default() };
+        let mixed = renderer.blocked_subresources(&subresources, &page, &exempt, &[]);
+        assert!(mixed.iter().all(|request| request.category == BlockCategory::MixedContent));
+        assert_eq!(mixed.len(), 2);
+        let upgrading = [CspPolicy::parse("upgrade-insecure-requests")];
+        assert!(renderer.blocked_subresources(&subresources, &page, &exempt, &upgrading).is_empty());
+
+        renderer.load_url(&file_url(&root.join("index.html"))).await.unwrap();
+        assert!(renderer.blocked_requests().is_empty());
+
+        renderer.set_site_settings_view(SiteSettingsView {
+            settings: SettingsView::default(),
+            blocked: BlockedSummary::new(&blocked),
+            protocol: Some(HttpVersion::Http2),
+            certificate: Some(Certificate {
+                subject: "CN=example.com".to_string(),
+                issuer: "CN=Example CA".to_string(),
+                valid_from: chrono::DateTime::UNIX_EPOCH,
+                valid_until: chrono::DateTime::from_timestamp(4_102_444_800, 0).unwrap(),
+                is_valid: true,
+                ocsp: Some(OcspStaple {
+                    status: RevocationStatus::Good,
+                    this_update: chrono::DateTime::from_timestamp(1_792_000_000, 0).unwrap(),
+                    next_update: None,
+                }),
+                sct_count: 2,
+            }),
+            csp_violations: Vec::new(),
+        });
+        let panel = ValidatedUrl::parse("navigator://site-settings?origin=https%3A%2F%2Fexample.com").unwrap();
+        renderer.load_url(&panel).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("2 requests: 1 ads, 1 trackers, 0 mixed content, 0 integrity failures."), "{}", text);
+        assert!(text.contains("The page came over HTTP/2."), "{}", text);
+        assert!(text.contains("Issued to CN=example.com by CN=Example CA, valid until 2100-01-01"), "{}", text);
+        assert!(text.contains("OCSP response says the certificate is good, as of 2026-10-14"), "{}", text);
+        assert!(text.contains("carries 2 certificate transparency timestamps"), "{}", text);
+        assert!(text.contains("2 requests blocked in all tabs"), "{}", text);
+        assert!(text.find("ads.example").unwrap() < text.find("pixel.example").unwrap());
+
+        // Scripts' requests go through the same blocker
+        let network = SecureNetworkClient::new().unwrap().with_interceptor(blocker.clone());
+        let scripted = ServoRenderer::new().with_file_access(site_access(&root)).with_network(Arc::new(network)).with_content_blocker(blocker.clone());
+        scripted.load_url(&file_url(&root.join("index.html"))).await.unwrap();
+        let fetched = scripted.script_fetch("https://ads.example/data.json", FetchInit::new()).await;
+        assert!(matches!(fetched, Err(RenderError::Network(NetworkError::Blocked(_)))));
+        assert_eq!(scripted.blocked_requests()[0].url.as_str(), "https://ads.example/data.json");
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_accessibility_mode_and_tree() {
+        let root = temp_site();
+        let mode = AccessibilityMode::new();
+        let renderer = ServoRenderer::new().with_file_access(site_access(&root)).with_accessibility(mode.clone());
+        assert_eq!(renderer.accessibility_tree().await.unwrap(), AccessibleNode::new("document"));
+
+        let url = file_url(&root.join("index.html"));
+        renderer.load_url(&url).await.unwrap();
+        assert_eq!(renderer.render_to_text(), "About");
+        let tree = renderer.accessibility_tree().await.unwrap();
+        assert_eq!(tree.name, "Local");
+        assert_eq!(tree.children[0], {
+            let mut link = AccessibleNode::new("link").with_name("About");
+            link.children.push(AccessibleNode::new("text").with_name("About"));
+            link
+        });
+
+        // Switching the mode shows once the page is laid out again
+        mode.set_enabled(true);
+        renderer.restyle().await.unwrap();
+        assert_eq!(renderer.render_to_text(), "About (link)");
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_oversized_document_is_cut_off_with_notice() {
+        let root = temp_site();
+        let renderer = ServoRenderer::with_config(RenderingConfig {
+            max_document_size: 60,
+            ..RenderingConfig::default()
+        })
+        .with_file_access(site_access(&root));
+
+        renderer.load_url(&file_url(&root.join("index.html"))).await.unwrap();
+        assert_eq!(renderer.get_title().await.unwrap(), "Local");
+        let text = renderer.get_text().await.unwrap();
+        assert!(text.starts_with("This page was cut off after 60 bytes."), "{}", text);
+        assert!(!text.contains("About"));
+
+        // Restyling keeps the notice
+        renderer.restyle().await.unwrap();
+        assert!(renderer.get_text().await.unwrap().starts_with("This page was cut off"));
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_load_local_text_is_escaped() {
+        let root = temp_site();
+        let renderer = ServoRenderer::new().with_file_access(site_access(&root));
+
+        renderer.load_url(&file_url(&root.join("notes.txt"))).await.unwrap();
+        assert!(renderer.render_to_text().contains("<plain & simple>"));
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_load_data_url_is_sanitized() {
+        let renderer = ServoRenderer::new();
+        let url = ValidatedUrl::parse(
+            "data:text/html,<title>Inline</title><script>alert(1)</script>",
+        )
+        .unwrap();
+
+        renderer.load_url(&url).await.unwrap();
+        assert_eq!(renderer.get_title().await.unwrap(), "Inline");
+        assert!(!renderer.current_html.lock().unwrap().contains("<script"));
+    }
+
+    #[tokio::test]
+    async fn test_about_blank_replaces_the_page_with_nothing() {
+        let renderer = ServoRenderer::new();
+        let page = ValidatedUrl::parse("data:text/html,<title>Before</title><p>Stale text</p>").unwrap();
+        renderer.load_url(&page).await.unwrap();
+
+        renderer.load_url(&ValidatedUrl::about_blank()).await.unwrap();
+        assert_ne!(renderer.get_title().await.unwrap(), "Before");
+        assert_eq!(renderer.render_to_text().trim(), "");
+        assert!(renderer.current_html.lock().unwrap().is_empty());
+    }
+
+    /// Hands back the text of the page it was asked to paint
+    struct TextCapture;
+
+    impl PageCapture for TextCapture {
+        fn capture(&self, content: &PageContent) -> Result<Vec<u8>> {
+            Ok(content.text.trim().as_bytes().to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_screenshots_paint_the_laid_out_page() {
+        let page = ValidatedUrl::parse("data:text/html,<p>Painted</p>").unwrap();
+        let renderer = ServoRenderer::new();
+        renderer.load_url(&page).await.unwrap();
+        // Nothing to paint with
+        assert!(renderer.take_screenshot().await.unwrap().is_empty());
+
+        let renderer = ServoRenderer::new().with_capture(Arc::new(TextCapture));
+        renderer.load_url(&page).await.unwrap();
+        assert_eq!(renderer.take_screenshot().await.unwrap(), b"Painted");
+    }
+
+    #[tokio::test]
+    async fn test_directory_listing() {
+        let root = temp_site();
+        let renderer = ServoRenderer::new().with_file_access(site_access(&root));
+
+        let dir_url = ValidatedUrl::parse(url::Url::from_directory_path(&root).unwrap().as_str())
+            .unwrap();
+        renderer.load_url(&dir_url).await.unwrap();
+
+        let html = renderer.current_html.lock().unwrap().clone();
+        assert!(html.contains("href=\"docs/\""));
+        assert!(html.contains("href=\"index.html\""));
+        assert!(html.find("docs/").unwrap() < html.find("index.html").unwrap());
+
+        let child = renderer.resolve_link("docs/").unwrap();
+        assert_eq!(child.as_str(), format!("{}docs/", dir_url.as_str()));
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    /// Roughly `size` bytes of paragraphs, links and tables
+    fn large_document(size: usize) -> String {
+        let chunk = "<p id=\"p{}\">Lorem ipsum <a href=\"#p{}\">dolor</a> sit amet, <b>consectetur</b> \
+                     adipiscing elit.</p><table><tr><th>Key</th><td>Value</td></tr></table>\n";
+        let mut html = String::with_capacity(size + chunk.len());
+        html.push_str("<html><head><title>Large</title></head><body>");
+        let mut i = 0;
+        while html.len() < size {
+            html.push_str(&chunk.replace("{}", &i.to_string()));
+            i += 1;
+        }
+        html.push_str("</body></html>");
+        html
+    }
+
+    #[tokio::test]
+    async fn test_logs_page_filters_and_sets_level() {
+        use crate::infrastructure::{LogBuffer, LogLevelControl};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let buffer = LogBuffer::new(10);
+        let subscriber = tracing_subscriber::registry().with(buffer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("fetched <example>");
+            tracing::warn!("slow <example>");
+            tracing::warn!("unrelated");
+        });
+        let (levels, _layer) = LogLevelControl::new();
+        let diagnostics = Diagnostics {
+            buffer,
+            levels: Arc::new(levels),
+        };
+        let renderer = ServoRenderer::new().with_diagnostics(diagnostics.clone());
+
+        let url = ValidatedUrl::parse("navigator://logs?level=warn&q=EXAMPLE&set_level=debug").unwrap();
+        renderer.load_url(&url).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("slow <example>"), "{}", text);
+        assert!(!text.contains("fetched"));
+        assert!(!text.contains("unrelated"));
+        assert_eq!(diagnostics.levels.overridden(), Some(Level::DEBUG));
+
+        let unknown = ValidatedUrl::parse("navigator://nothing").unwrap();
+        assert!(matches!(renderer.load_url(&unknown).await, Err(RenderError::InvalidContent(_))));
+    }
+
+    #[tokio::test]
+    async fn test_history_page_deletes_entries() {
+        use crate::application::testing::InMemoryHistoryRepository;
+        use crate::domain::NewHistoryEntry;
+
+        let history = Arc::new(InMemoryHistoryRepository::new());
+        let visit = |path: &str, title: &str| {
+            let url = ValidatedUrl::parse(&format!("https://example.com/{path}")).unwrap();
+            NewHistoryEntry::new(url, title.to_string())
+        };
+        let kept = history.add(visit("kept", "Kept page")).await.unwrap();
+        for _ in 0..2 {
+            history.add(visit("kept", "Kept page").with_visit_type(VisitType::Typed)).await.unwrap();
+        }
+        let gone = history.add(visit("gone", "Embarrassing page")).await.unwrap();
+        let renderer = ServoRenderer::new().with_history(history.clone());
+
+        let url = ValidatedUrl::parse(&format!("navigator://history?delete={}", gone.id)).unwrap();
+        renderer.load_url(&url).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("Kept page (typed 2×, links 1×)"), "{}", text);
+        assert!(!text.contains("Embarrassing"));
+        assert!(history.visits(&[gone.id]).await.unwrap().is_empty());
+        assert!(text.contains("Clear this day"));
+
+        let day = kept.visited_at.with_timezone(&chrono::Local).format("%Y-%m-%d");
+        let url = ValidatedUrl::parse(&format!("navigator://history?clear_day={day}")).unwrap();
+        renderer.load_url(&url).await.unwrap();
+        assert!(renderer.render_to_text().contains("No history."));
+        assert!(history.visits(&[kept.id]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_links_to_history_are_marked_visited() {
+        use crate::application::testing::InMemoryHistoryRepository;
+        use crate::domain::NewHistoryEntry;
+
+        let history = Arc::new(InMemoryHistoryRepository::new());
+        let seen = ValidatedUrl::parse("https://example.com/seen").unwrap();
+        history.add(NewHistoryEntry::new(seen, "Seen".to_string())).await.unwrap();
+        let root = temp_site();
+        let page = root.join("links.html");
+        std::fs::write(
+            &page,
+            "<p><a href=\"https://example.com/seen#part\">Seen</a> <a href=\"https://example.com/new\">New</a> \
+             <a href=\"https://example.com/seen\">Again</a></p>",
+        )
+        .unwrap();
+        let visited = |renderer: &ServoRenderer| -> Vec<bool> {
+            renderer.layout().links.iter().map(|link| link.visited).collect()
+        };
+
+        let renderer = ServoRenderer::new().with_file_access(site_access(&root)).with_history(history.clone());
+        renderer.load_url(&file_url(&page)).await.unwrap();
+        assert_eq!(visited(&renderer), vec![true, false, true]);
+
+        history.clear_all().await.unwrap();
+        renderer.restyle().await.unwrap();
+        assert_eq!(visited(&renderer), vec![false, false, false]);
+
+        history
+            .add(NewHistoryEntry::new(ValidatedUrl::parse("https://example.com/new").unwrap(), String::new()))
+            .await
+            .unwrap();
+        let private = ServoRenderer::new().with_file_access(site_access(&root)).with_history(history);
+        private.set_private(true);
+        private.load_url(&file_url(&page)).await.unwrap();
+        assert_eq!(visited(&private), vec![false, false, false]);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_page_lists_and_removes_downloads() {
+        use crate::application::testing::InMemoryDownloadRepository;
+        use crate::domain::NewDownload;
+
+        let downloads = Arc::new(InMemoryDownloadRepository::new());
+        let download = |name: &str| NewDownload {
+            url: ValidatedUrl::parse("https://example.com/guide").unwrap(),
+            path: std::env::temp_dir().join(name),
+            size: 1234,
+        };
+        let kept = downloads.save(download("Kept guide.html")).await.unwrap();
+        let gone = downloads.save(download("Old guide.txt")).await.unwrap();
+        let renderer = ServoRenderer::new().with_downloads(downloads.clone());
+
+        let url = ValidatedUrl::parse("navigator://downloads").unwrap();
+        renderer.load_url(&url).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("Kept guide.html from example.com: 1.2 KB, file missing"), "{}", text);
+        assert!(text.find("Old guide.txt").unwrap() < text.find("Kept guide.html").unwrap());
+        // The newest download has the focus until another row is picked
+        assert!(text.contains("> Old guide.txt"), "{}", text);
+
+        renderer.set_downloads_view(DownloadsView {
+            pending: Vec::new(),
+            unfinished: Vec::new(),
+            focused: 1,
+            error: Some("Cannot open the folder".to_string()),
+        });
+        renderer.load_url(&url).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("> Kept guide.html"), "{}", text);
+        assert!(text.find("Cannot open the folder").unwrap() > text.find("Kept guide.html").unwrap());
+
+        // Downloads waiting for confirmation come first, and take the focus
+        renderer.set_downloads_view(DownloadsView {
+            pending: vec![("setup.exe".to_string(), ".exe files can run programs".to_string())],
+            ..DownloadsView::default()
+        });
+        renderer.load_url(&url).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("> setup.exe: .exe files can run programs"), "{}", text);
+        assert!(text.find("setup.exe").unwrap() < text.find("Old guide.txt").unwrap());
+        assert!(!text.contains("> Old guide.txt"), "{}", text);
+
+        // Failed downloads come between them, and Enter tries them again
+        renderer.set_downloads_view(DownloadsView {
+            pending: vec![("setup.exe".to_string(), ".exe files can run programs".to_string())],
+            unfinished: vec![("report.pdf".to_string(), "failed: Disk full".to_string())],
+            focused: 1,
+            error: None,
+        });
+        renderer.load_url(&url).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("> report.pdf: failed: Disk full"), "{}", text);
+        assert!(text.find("setup.exe").unwrap() < text.find("report.pdf").unwrap());
+        assert!(text.find("report.pdf").unwrap() < text.find("Old guide.txt").unwrap());
+
+        let url = ValidatedUrl::parse(&format!("navigator://downloads?remove={}", gone.id)).unwrap();
+        renderer.load_url(&url).await.unwrap();
+        assert!(!renderer.render_to_text().contains("Old guide"));
+        assert_eq!(downloads.find_all().await.unwrap(), vec![kept]);
+
+        let url = ValidatedUrl::parse("navigator://downloads?clear=all").unwrap();
+        renderer.load_url(&url).await.unwrap();
+        assert!(renderer.render_to_text().contains("No downloads."));
+        assert_eq!(format_size(3 * 1024 * 1024 + 1), "3.0 MB");
+    }
+
+    #[tokio::test]
+    async fn test_bookmarks_page_lists_folders_in_order() {
+        let renderer = ServoRenderer::new();
+        renderer.load_url(&ValidatedUrl::parse("navigator://bookmarks").unwrap()).await.unwrap();
+        assert!(renderer.render_to_text().contains("No bookmarks."));
+
+        let bookmark = |id, title: &str, folder: Option<&str>| Bookmark {
+            id,
+            title: title.to_string(),
+            url: ValidatedUrl::parse(&format!("https://example.com/{}", id)).unwrap(),
+            folder: folder.map(str::to_string),
+            position: id * 1024,
+            created_at: chrono::Utc::now(),
+            tags: Vec::new(),
+        };
+        renderer.set_bookmarks_view(BookmarksView {
+            bookmarks: vec![
+                bookmark(1, "Inbox", None),
+                bookmark(2, "Rust <book>", Some("Work")),
+                bookmark(3, "Tracker", Some("Work")),
+            ],
+            focused: 2,
+            error: Some("Cannot move it".to_string()),
+        });
+        renderer.load_url(&ValidatedUrl::parse("navigator://bookmarks").unwrap()).await.unwrap();
+        let text = renderer.render_to_text();
+        let at = |needle: &str| text.find(needle).unwrap_or_else(|| panic!("{} in {}", needle, text));
+        assert!(at("Unfiled") < at("Inbox"));
+        assert!(at("Inbox") < at("Work"));
+        assert!(at("Work") < at("Rust <book>"));
+        assert!(at("Rust <book>") < at("> Tracker"));
+        assert!(at("> Tracker") < at("Cannot move it"));
+    }
+
+    #[tokio::test]
+    async fn test_shortcuts_page_lists_commands_by_section() {
+        let renderer = ServoRenderer::new();
+        renderer.set_shortcuts_view(ShortcutsView {
+            sections: vec![
+                ("Navigation".to_string(), vec![("Go back".to_string(), "Alt+Left".to_string())]),
+                ("Browser pages".to_string(), vec![("Show the browser's logs".to_string(), String::new())]),
+            ],
+        });
+        renderer.load_url(&ValidatedUrl::parse("navigator://shortcuts").unwrap()).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("Alt+Left: Go back"), "{}", text);
+        assert!(text.contains("(none): Show the browser's logs"), "{}", text);
+        assert!(text.find("Navigation").unwrap() < text.find("Browser pages").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_memory_page_lists_totals_and_tabs() {
+        let renderer = ServoRenderer::new();
+        let (small, large) = (TabId::new(), TabId::new());
+        let report = |name: &str, tab, entries, bytes| MemoryReport {
+            name: name.to_string(),
+            tab,
+            usage: MemoryUsage::new(entries, bytes),
+        };
+        renderer.set_memory_view(MemoryView {
+            reports: vec![
+                report("Thumbnails", None, 2, 2048),
+                report("Page", Some(small), 1, 512),
+                report("Page", Some(large), 1, 3 * 1024 * 1024),
+            ],
+            tab_titles: HashMap::from([(small, "Small <page>".to_string())]),
+            ceiling: 0,
+            trimmed: Some(100),
+        });
+        renderer.load_url(&ValidatedUrl::parse("navigator://memory").unwrap()).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("3.0 MB accounted."), "{}", text);
+        assert!(text.contains("never trimmed"), "{}", text);
+        assert!(text.contains("Trimming freed 100 bytes."), "{}", text);
+        assert!(text.contains("Thumbnails: 2.0 KB in 2 entries"), "{}", text);
+        // The largest tab comes first
+        let large_at = text.find("Untitled tab: 3.0 MB").unwrap();
+        let small_at = text.find("Small <page>: 512 bytes").unwrap();
+        assert!(text.find("Shared").unwrap() < large_at);
+        assert!(large_at < small_at);
+        assert!(renderer.resolve_link("?trim").unwrap().as_str().ends_with("memory?trim"));
+    }
+
+    #[tokio::test]
+    async fn test_update_page_shows_release_notes() {
+        let renderer = ServoRenderer::new();
+        let url = ValidatedUrl::parse("navigator://update").unwrap();
+        renderer.set_update_view(UpdateView {
+            current_version: "0.1.0".to_string(),
+            update: None,
+        });
+        renderer.load_url(&url).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("This is Navigator 0.1.0."), "{}", text);
+        assert!(text.contains("No newer release"), "{}", text);
+
+        renderer.set_update_view(UpdateView {
+            current_version: "0.1.0".to_string(),
+            update: Some(UpdateManifest {
+                version: "0.2.0".to_string(),
+                notes: "Vertical tab groups.\n\n<b>Faster</b> startup.".to_string(),
+                download: ValidatedUrl::parse("https://releases.example/navigator-0.2.0.zip").unwrap(),
+            }),
+        });
+        renderer.load_url(&url).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("Navigator 0.2.0 is available"), "{}", text);
+        assert!(text.contains("<b>Faster</b> startup."), "{}", text);
+        assert_eq!(renderer.resolve_link("?download=1").unwrap().as_str(), "navigator://update?download=1");
+    }
+
+    #[tokio::test]
+    async fn test_settings_page_manages_search_engines() {
+        use super::super::opensearch::OpenSearchDescriptions;
+        use crate::application::testing::InMemorySearchEngineRepository;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0; 4096];
+            let _ = stream.read(&mut buffer).await.unwrap();
+            let body = r#"<OpenSearchDescription><ShortName>Local</ShortName>
+                <Url type="text/html" template="https://local.example/?q={searchTerms}"/></OpenSearchDescription>"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                OPENSEARCH_CONTENT_TYPE,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let root = temp_site();
+        std::fs::write(
+            root.join("search.html"),
+            format!(
+                "<html><head><link rel=\"search\" type=\"{}\" href=\"http://127.0.0.1:{}/os.xml\">\
+                 </head><body>Search me</body></html>",
+                OPENSEARCH_CONTENT_TYPE, port
+            ),
+        )
+        .unwrap();
+        let repository = Arc::new(InMemorySearchEngineRepository::new());
+        let discovered = DiscoveredSearchEngines::new();
+        let descriptions = Arc::new(OpenSearchDescriptions::new(Arc::new(SecureNetworkClient::new().unwrap())));
+        let renderer = ServoRenderer::new().with_file_access(site_access(&root))
+            .with_search_engines(repository.clone(), discovered.clone(), descriptions);
+        let settings = |query: &str| ValidatedUrl::parse(&format!("navigator://settings{}", query)).unwrap();
+
+        renderer.load_url(&file_url(&root.join("search.html"))).await.unwrap();
+        let description = renderer.search_description().unwrap();
+        assert_eq!(description.as_str(), format!("http://127.0.0.1:{}/os.xml", port));
+        renderer.load_url(&settings("")).await.unwrap();
+        assert!(renderer.render_to_text().contains("Offered by sites you visited"));
+
+        let encoded: String = url::form_urlencoded::byte_serialize(description.as_str().as_bytes()).collect();
+        renderer.load_url(&settings(&format!("?add_opensearch={}", encoded))).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("Added Local with the keyword 127.0.0.1"), "{}", text);
+        assert!(!text.contains("Offered by"));
+
+        let add = "?name=Docs&keyword=docs&template=https%3A%2F%2Fdocs.example%2F%3Fq%3D%25s";
+        renderer.load_url(&settings(add)).await.unwrap();
+        renderer.load_url(&settings(add)).await.unwrap();
+        assert!(renderer.render_to_text().contains("already used"));
+        let engines = repository.find_all().await.unwrap();
+        assert_eq!(engines.len(), 2);
+        assert!(engines[0].is_default);
+
+        renderer.load_url(&settings(&format!("?set_default={}", engines[1].id))).await.unwrap();
+        renderer.load_url(&settings(&format!("?remove={}", engines[0].id))).await.unwrap();
+        let engines = repository.find_all().await.unwrap();
+        assert_eq!((engines.len(), engines[0].is_default), (1, true));
+        assert_eq!(engines[0].keyword, "docs");
+    }
+
+    #[tokio::test]
+    async fn test_settings_page_lists_browser_settings() {
+        use super::super::opensearch::OpenSearchDescriptions;
+        use crate::application::testing::InMemorySearchEngineRepository;
+
+        let descriptions = Arc::new(OpenSearchDescriptions::new(Arc::new(SecureNetworkClient::new().unwrap())));
+        let renderer = ServoRenderer::new().with_search_engines(
+            Arc::new(InMemorySearchEngineRepository::new()),
+            DiscoveredSearchEngines::new(),
+            descriptions,
+        );
+        renderer.set_settings_view(SettingsView {
+            rows: vec![
+                ("Homepage".to_string(), "https://example.com/".to_string()),
+                ("User agent".to_string(), String::new()),
+                ("Go back".to_string(), "Alt+Left".to_string()),
+            ],
+            focused: 1,
+            error: Some("Invalid value for User agent: at most 512 characters".to_string()),
+            shortcuts_from: Some(2),
+        });
+
+        renderer.load_url(&ValidatedUrl::parse("navigator://settings").unwrap()).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("Homepage: https://example.com/"), "{}", text);
+        assert!(text.contains("> User agent: (not set)"), "{}", text);
+        assert!(text.contains("at most 512 characters"), "{}", text);
+        assert!(text.find("Browser").unwrap() < text.find("Search engines").unwrap());
+        let shortcuts = text.find("Keyboard shortcuts").unwrap();
+        assert!(text.find("User agent").unwrap() < shortcuts);
+        assert!(shortcuts < text.find("Go back: Alt+Left").unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_settings_page_lists_and_removes_certificate_authorities() {
+        use super::super::opensearch::OpenSearchDescriptions;
+        use crate::application::testing::{InMemoryCustomCaRepository, InMemorySearchEngineRepository};
+        use crate::domain::NewCustomCa;
+
+        let network = Arc::new(SecureNetworkClient::new().unwrap());
+        let custom_cas = Arc::new(InMemoryCustomCaRepository::new());
+        let pem = std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/tls/ca.pem"));
+        let details = network.inspect(&pem.unwrap()).unwrap();
+        let ca = custom_cas
+            .save(NewCustomCa {
+                name: "Office CA".to_string(),
+                pem: details.pem,
+                fingerprint: details.fingerprint.clone(),
+            })
+            .await
+            .unwrap();
+        let renderer = ServoRenderer::new()
+            .with_network(network.clone())
+            .with_search_engines(
+                Arc::new(InMemorySearchEngineRepository::new()),
+                DiscoveredSearchEngines::new(),
+                Arc::new(OpenSearchDescriptions::new(network)),
+            )
+            .with_custom_cas(custom_cas.clone());
+        let settings = |query: &str| ValidatedUrl::parse(&format!("navigator://settings{}", query)).unwrap();
+
+        renderer.load_url(&settings("")).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("Certificate authorities"), "{}", text);
+        assert!(text.contains(&format!("Office CA {}", details.fingerprint)), "{}", text);
+
+        renderer.load_url(&settings(&format!("?remove_ca={}", ca.id))).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("No longer trusting Office CA."), "{}", text);
+        assert!(text.contains("Only the built-in authorities are trusted"), "{}", text);
+        assert!(custom_cas.find_all().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scripts_page_lists_scripts_with_links_to_change_them() {
+        use crate::application::testing::InMemoryUserScriptRepository;
+        use crate::domain::{MatchPattern, NewUserScript, UserScriptRepository};
+
+        let user_scripts = Arc::new(InMemoryUserScriptRepository::new());
+        let renderer = ServoRenderer::new().with_user_scripts(user_scripts.clone());
+        let page = ValidatedUrl::parse("navigator://scripts").unwrap();
+        renderer.load_url(&page).await.unwrap();
+        assert!(renderer.render_to_text().contains("No user scripts."));
+
+        let matches = vec![MatchPattern::parse("*://*.example.com/*").unwrap()];
+        let mut script = user_scripts
+            .save(NewUserScript::new("Dark <mode>".to_string(), matches, "1".to_string()))
+            .await
+            .unwrap();
+        script.last_error = Some("ReferenceError: x".to_string());
+        user_scripts.update(&script).await.unwrap();
+
+        renderer.load_url(&page).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("Dark <mode> enabled [disable] [remove]"), "{}", text);
+        assert!(text.contains("On *://*.example.com/* at document-end [run at document-idle]"), "{}", text);
+        assert!(text.contains("not in private tabs [allow]; not in browser pages [allow]"), "{}", text);
+        assert!(text.contains("Last run failed: ReferenceError: x"), "{}", text);
+        let html = renderer.current_html.lock().unwrap().clone();
+        let id = script.id;
+        for link in [
+            format!("?set={id}%3Aenabled%3Dfalse"),
+            format!("?set={id}%3Arun-at%3Ddocument-idle"),
+            format!("?set={id}%3Aallow-private%3Dtrue"),
+            format!("?remove={id}"),
+        ] {
+            assert!(html.contains(&format!("href=\"{link}\"")), "{} in {}", link, html);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_page_scripts_report_to_the_console() {
+        let root = temp_site();
+        std::fs::write(
+            root.join("app.html"),
+            "<title>App</title><body><div id=\"app\"></div><noscript>Enable JavaScript</noscript>\
+             <script>document.getElementById('app').textContent = 'Built'</script>\
+             <script src=\"missing.js\"></script></body>",
+        )
+        .unwrap();
+        let page = file_url(&root.join("app.html"));
+
+        let renderer = ServoRenderer::new().with_file_access(site_access(&root));
+        renderer.load_url(&page).await.unwrap();
+        assert!(!renderer.render_to_text().contains("Enable JavaScript"));
+        let failed = renderer.console().messages().pop().unwrap();
+        assert_eq!(failed.level, ConsoleLevel::Error);
+        assert!(failed.source.unwrap().ends_with("/missing.js"));
+
+        let budgeted = ServoRenderer::with_config(RenderingConfig {
+            script_budget: Duration::ZERO,
+            ..RenderingConfig::default()
+        })
+        .with_file_access(site_access(&root));
+        budgeted.load_url(&page).await.unwrap();
+        let skipped = budgeted.console().messages().pop().unwrap();
+        assert_eq!(skipped.level, ConsoleLevel::Warn);
+        assert!(skipped.message.starts_with("Skipped 2 scripts"), "{}", skipped.message);
+        budgeted.load_url(&ValidatedUrl::parse("navigator://console").unwrap()).await.unwrap();
+        let text = budgeted.render_to_text();
+        assert!(text.find("Skipped 2 scripts").unwrap() < text.find("Navigated to").unwrap(), "{}", text);
+
+        // The log starts over with each navigation unless preserved
+        let console = |query: &str| ValidatedUrl::parse(&format!("navigator://console{}", query)).unwrap();
+        budgeted.load_url(&page).await.unwrap();
+        assert_eq!(budgeted.console().messages().len(), 2);
+        budgeted.load_url(&console("?preserve=on")).await.unwrap();
+        assert!(budgeted.render_to_text().contains("Preserve log: on"));
+        budgeted.load_url(&page).await.unwrap();
+        assert_eq!(budgeted.console().messages().len(), 4);
+        budgeted.load_url(&console("?clear")).await.unwrap();
+        assert!(budgeted.console().messages().is_empty());
+
+        let disabled = ServoRenderer::with_config(RenderingConfig {
+            enable_javascript: false,
+            ..RenderingConfig::default()
+        })
+        .with_file_access(site_access(&root));
+        disabled.load_url(&page).await.unwrap();
+        assert!(disabled.render_to_text().contains("Enable JavaScript"));
+        assert_eq!(disabled.console().messages().len(), 1);
+
+        // A file page may only open secure WebSockets, and failures are logged
+        let refused = renderer.open_websocket("ws://example.com/chat").await;
+        assert!(matches!(refused, Err(RenderError::Network(NetworkError::Request(_)))));
+        let warning = renderer.console().messages().pop().unwrap();
+        assert_eq!(warning.level, ConsoleLevel::Warn);
+        assert!(warning.message.starts_with("WebSocket connection to ws://example.com/chat failed"));
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn test_offline_mode_serves_kept_pages() {
+        use super::super::network::OfflineMode;
+        use crate::application::testing::InMemoryPageCacheRepository;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            assert!(stream.read(&mut [0; 4096]).await.unwrap() > 0);
+            let body = "<html><head><title>Fresh</title></head><body><p>Fetched today</p></body></html>";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+        let url = |path: &str| ValidatedUrl::parse(&format!("http://127.0.0.1:{}{}", port, path)).unwrap();
+
+        // A page kept long ago is shown all the same once offline
+        let cache = Arc::new(InMemoryPageCacheRepository::new());
+        let fetched_at = chrono::Utc::now() - chrono::Duration::days(30);
+        cache
+            .store(&CachedPage {
+                url: url("/old"),
+                title: "Old".to_string(),
+                content_type: Some("text/html".to_string()),
+                body: b"<html><head><title>Old</title></head><body><p>Kept a month ago</p></body></html>".to_vec(),
+                fetched_at,
+            })
+            .await
+            .unwrap();
+        let offline = OfflineMode::new();
+        let network = SecureNetworkClient::new().unwrap().with_offline_mode(offline.clone());
+        let renderer = ServoRenderer::new()
+            .with_network(Arc::new(network))
+            .with_page_cache(cache.clone());
+
+        renderer.load_url(&url("/fresh")).await.unwrap();
+        server.await.unwrap();
+        assert!(!renderer.render_to_text().contains("Offline copy"));
+        assert_eq!(cache.find(&url("/fresh")).await.unwrap().unwrap().title, "Fresh");
+
+        offline.set_enabled(true);
+        renderer.load_url(&url("/old")).await.unwrap();
+        let text = renderer.render_to_text();
+        let date = fetched_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string();
+        assert!(text.starts_with(&format!("Offline copy from {}", date)), "{}", text);
+        assert!(text.contains("Kept a month ago"), "{}", text);
+        assert_eq!(renderer.get_title().await.unwrap(), "Old");
+        renderer.load_url(&url("/fresh#top")).await.unwrap();
+        assert!(renderer.render_to_text().contains("Fetched today"));
+
+        // Without a copy the site's kept pages are listed, newest first
+        renderer.load_url(&url("/missing")).await.unwrap();
+        assert_eq!(renderer.get_title().await.unwrap(), "Offline");
+        let text = renderer.render_to_text();
+        assert!(text.contains("was not kept for offline use"), "{}", text);
+        assert!(text.find("Fresh").unwrap() < text.find("Old").unwrap(), "{}", text);
+
+        // Private tabs keep nothing
+        offline.set_enabled(false);
+        renderer.set_private(true);
+        let refused = renderer.load_url(&url("/private")).await;
+        assert!(matches!(refused, Err(RenderError::Network(NetworkError::Connection(_)))), "{:?}", refused);
+        assert_eq!(cache.find(&url("/private")).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_content_security_policy_is_reported_then_enforced() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                assert!(stream.read(&mut [0; 4096]).await.unwrap() > 0);
+                let body = "<html><body><img src=\"/logo.png\"><img src=\"https://images.example/a.png\">\
+                            <link rel=\"stylesheet\" href=\"https://cdn.example/site.css\">\
+                            <img src=\"http://127.0.0.1/plain.png\"></body></html>";
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Security-Policy: default-src 'self'; \
+                     style-src https://cdn.example; upgrade-insecure-requests\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let page = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+        let enforcement = CspEnforcement::new();
+        let renderer = ServoRenderer::new().with_csp_enforcement(enforcement.clone());
+
+        // Reported only: the other site's image, and the plain one once
+        // upgraded to https, which is no longer the page's origin
+        renderer.load_url(&page).await.unwrap();
+        let violations = renderer.csp_violations();
+        let refused: Vec<(&str, &str, bool)> = violations
+            .iter()
+            .map(|violation| (violation.url.as_str(), violation.directive.as_str(), violation.enforced))
+            .collect();
+        assert_eq!(
+            refused,
+            [
+                ("https://images.example/a.png", "default-src", false),
+                ("https://127.0.0.1/plain.png", "default-src", false),
+            ]
+        );
+        let messages = renderer.console().messages();
+        assert!(messages.iter().any(|message| message.message.starts_with("[Report only] Loading https://images")));
+
+        enforcement.set_enabled(true);
+        renderer.load_url(&page).await.unwrap();
+        assert!(renderer.csp_violations().iter().all(|violation| violation.enforced));
+        let fetched = renderer.script_fetch("https://images.example/data.json", FetchInit::new()).await;
+        assert!(matches!(fetched, Err(RenderError::Network(NetworkError::Blocked(_)))), "{:?}", fetched);
+        let violations = renderer.csp_violations();
+        assert_eq!(violations.len(), 3);
+        assert_eq!(violations[2].url.as_str(), "https://images.example/data.json");
+
+        renderer.set_site_settings_view(SiteSettingsView {
+            csp_violations: violations,
+            ..SiteSettingsView::default()
+        });
+        let panel = format!("navigator://site-settings?origin=http%3A%2F%2F127.0.0.1%3A{}", port);
+        renderer.load_url(&ValidatedUrl::parse(&panel).unwrap()).await.unwrap();
+        let text = renderer.render_to_text();
+        assert!(text.contains("3 loads broke the page's policy; 3 were refused and 0 only reported."), "{}", text);
+        assert!(text.contains("https://images.example/data.json (default-src)"), "{}", text);
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_scripts_failing_integrity_do_not_run() {
+        use super::super::integrity::IntegrityAlgorithm;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let good = "document.title = 'Good'";
+        let bad = "document.title = 'Bad'";
+        let page = format!(
+            "<html><body>\
+             <script src=\"/good.js\" integrity=\"sha256-c3RhbGU= sha384-c3RhbGU=  sha384-{}\"></script>\
+             <script src=\"/bad.js\" integrity=\"sha256-{}\"></script></body></html>",
+            IntegrityAlgorithm::Sha384.digest(good.as_bytes()),
+            IntegrityAlgorithm::Sha256.digest(good.as_bytes())
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let (content_type, body) = match request.split_whitespace().nth(1) {
+                    Some("/good.js") => ("text/javascript", good.to_string()),
+                    Some("/bad.js") => ("text/javascript", bad.to_string()),
+                    _ => ("text/html", page.clone()),
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    content_type,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let renderer = ServoRenderer::new();
+        let url = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+        renderer.load_url(&url).await.unwrap();
+        server.abort();
+        // One of the strongest algorithm's hashes matching is enough
+        let blocked = renderer.blocked_requests();
+        assert_eq!(blocked.len(), 1);
+        assert_eq!(blocked[0].category, BlockCategory::Integrity);
+        assert!(blocked[0].url.as_str().ends_with("/bad.js"));
+        let failed = renderer.console().messages().pop().unwrap();
+        assert_eq!(failed.level, ConsoleLevel::Error);
+        assert!(failed.message.starts_with("Refused to run script"), "{}", failed.message);
+        assert!(failed.source.unwrap().ends_with("/bad.js"));
+    }
+
+    #[test]
+    fn test_offline_banner_goes_inside_the_body() {
+        let fetched_at = chrono::Utc::now();
+        let colors = PageColors::default();
+        let html = with_offline_banner("<html><BODY class=\"x\"><p>Hi</p></BODY></html>", fetched_at, &colors);
+        let at = html.find("Offline copy from").unwrap();
+        assert!(html.find("<BODY class=\"x\">").unwrap() < at && at < html.find("<p>Hi").unwrap(), "{}", html);
+        let bare = with_offline_banner("<p>Plain</p>", fetched_at, &colors);
+        assert!(bare.starts_with("<div role=\"status\""), "{}", bare);
+        let bodyless = with_offline_banner("<bodyguard></bodyguard>", fetched_at, &colors);
+        assert!(bodyless.starts_with("<div"), "{}", bodyless);
+    }
+
+    #[tokio::test]
+    async fn test_site_without_javascript_shows_noscript() {
+        let html = "<body><noscript><p>Enable JavaScript</p></noscript><p>Body</p><script>run()</script>\
+            <script type=\"text/x-template\">skip</script><script src=\"a.js\"></script></body>";
+        let mut site = SiteSettings::new(SiteOrigin::parse("https://example.com").unwrap());
+        site.javascript = Some(false);
+
+        let scripted = RenderingConfig::default();
+        let parsed = parse_page(html.to_string(), None, UserStylesheet::default(), &scripted, false)
+            .await
+            .unwrap();
+        let sources: Vec<ScriptSource> = parsed.scripts.into_iter().map(|script| script.source).collect();
+        let expected = [ScriptSource::Inline("run()".to_string()), ScriptSource::External("a.js".to_string())];
+        assert_eq!(sources, expected);
+        assert!(!parsed.content.text.contains("Enable JavaScript"), "{}", parsed.content.text);
+
+        let config = scripted.for_site(Some(&site));
+        assert!(!config.enable_javascript);
+        let parsed = parse_page(html.to_string(), None, UserStylesheet::default(), &config, false)
+            .await
+            .unwrap();
+        assert!(parsed.scripts.is_empty());
+        assert!(parsed.content.text.contains("Enable JavaScript"), "{}", parsed.content.text);
+        assert!(parsed.content.text.contains("Body"));
+    }
+
+    #[test]
+    fn test_parsed_page_can_cross_threads() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<ParsedPage>();
+    }
+
+    /// The event loop stand-in keeps receiving "resize" ticks on the single
+    /// runtime thread while a 10MB document is parsed
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_large_page_layout_does_not_block_runtime() {
+        use std::time::{Duration, Instant};
+
+        let html = large_document(10 * 1024 * 1024);
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let ticker = tokio::spawn(async move {
+            loop {
+                if sender.send(Instant::now()).is_err() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+
+        let started = Instant::now();
+        let parsed = parse_page(html, None, UserStylesheet::default(), &RenderingConfig::default(), false)
+            .await
+            .unwrap();
+        let finished = Instant::now();
+        ticker.abort();
+
+        assert_eq!(parsed.title, "Large");
+        assert!(parsed.content.anchor_offset("p1000").is_some());
+
+        let mut ticks = Vec::new();
+        while let Ok(tick) = receiver.try_recv() {
+            ticks.push(tick);
         }
+        let during: Vec<Instant> = ticks
+            .into_iter()
+            .filter(|tick| *tick >= started && *tick <= finished)
+            .collect();
+        assert!(during.len() >= 2, "runtime stalled during layout");
+        let longest_gap = during
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .max()
+            .unwrap_or_default();
+        assert!(longest_gap < Duration::from_millis(500), "stalled for {:?}", longest_gap);
     }
 }
@@ -1,6 +1,5 @@
 // Mock rendering engine for Windows (sans WebKit)
-use crate::domain::{RenderingEngine, ValidatedUrl};
-use anyhow::Result;
+use crate::domain::{RenderError, RenderingEngine, ValidatedUrl};
 use async_trait::async_trait;
 
 /// Mock rendering engine pour tests sur Windows
@@ -26,7 +25,7 @@ impl Default for MockRenderer {
 
 #[async_trait]
 impl RenderingEngine for MockRenderer {
-    async fn load_url(&self, url: &ValidatedUrl) -> Result<()> {
+    async fn load_url(&self, url: &ValidatedUrl) -> Result<(), RenderError> {
         tracing::info!("Mock: Loading URL: {}", url);
 
         if let Ok(mut current) = self.current_url.write() {
@@ -43,7 +42,7 @@ impl RenderingEngine for MockRenderer {
         Ok(())
     }
 
-    async fn get_title(&self) -> Result<String> {
+    async fn get_title(&self) -> Result<String, RenderError> {
         if let Ok(title) = self.current_title.read() {
             Ok(title.clone())
         } else {
@@ -51,12 +50,12 @@ impl RenderingEngine for MockRenderer {
         }
     }
 
-    async fn execute_javascript(&self, script: &str) -> Result<String> {
+    async fn execute_javascript(&self, script: &str) -> Result<String, RenderError> {
         tracing::debug!("Mock: JavaScript execution requested: {}", script);
         Ok(String::new())
     }
 
-    async fn take_screenshot(&self) -> Result<Vec<u8>> {
+    async fn take_screenshot(&self) -> Result<Vec<u8>, RenderError> {
         tracing::debug!("Mock: Screenshot requested");
         Ok(Vec::new())
     }
@@ -0,0 +1,346 @@
+use base64::Engine;
+use ring::digest;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Extension added to a destination's name for the file written in its place
+pub const PART_EXTENSION: &str = "part";
+/// Extension added to a part file's name for the [`WrittenPrefix`] kept
+/// beside it when a write is paused
+pub const RESUME_EXTENSION: &str = "resume";
+
+/// How much of a file was written and synced before a write stopped, to
+/// check the `.part` file against before carrying on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrittenPrefix {
+    pub length: u64,
+    /// Base64 SHA-256 of the first `length` bytes
+    pub checksum: String,
+}
+
+/// Writes a file so it appears whole or not at all. The contents go to
+/// `<name>.part` in the destination's directory, are synced to disk, and
+/// the part file is then renamed into place; a crash mid-write leaves at
+/// most the part file behind, never a truncated destination.
+pub struct SafeFileWriter {
+    destination: PathBuf,
+    part: PathBuf,
+    file: File,
+    hasher: digest::Context,
+    length: u64,
+    /// Leave the part file behind when dropped unfinished, to resume later
+    resumable: bool,
+    finished: bool,
+}
+
+impl SafeFileWriter {
+    /// Start writing `destination`, replacing any part file left by an
+    /// earlier attempt. Dropped unfinished, the part file is removed.
+    pub fn create(destination: impl Into<PathBuf>) -> io::Result<Self> {
+        let destination = destination.into();
+        let part = part_path(&destination);
+        let file = File::create(&part)?;
+        Ok(Self::new(destination, part, file, digest::Context::new(&digest::SHA256), 0, false))
+    }
+
+    /// Like [`create`](Self::create), but failing with `AlreadyExists`
+    /// when another writer is already writing `destination`
+    pub fn create_new(destination: impl Into<PathBuf>) -> io::Result<Self> {
+        let destination = destination.into();
+        let part = part_path(&destination);
+        let file = OpenOptions::new().write(true).create_new(true).open(&part)?;
+        Ok(Self::new(destination, part, file, digest::Context::new(&digest::SHA256), 0, false))
+    }
+
+    /// Start a write that can be picked up again after an interruption:
+    /// dropped unfinished, the part file stays for [`resume`](Self::resume)
+    pub fn create_resumable(destination: impl Into<PathBuf>) -> io::Result<Self> {
+        let mut writer = Self::create(destination)?;
+        writer.resumable = true;
+        Ok(writer)
+    }
+
+    /// Carry on an interrupted resumable write of `destination` after the
+    /// `prefix` it had reached. The part file is cut back to the prefix and
+    /// checked against its checksum first; on a mismatch it fails with
+    /// `InvalidData` and the write has to start over.
+    pub fn resume(destination: impl Into<PathBuf>, prefix: &WrittenPrefix) -> io::Result<Self> {
+        let destination = destination.into();
+        let part = part_path(&destination);
+        let mut file = OpenOptions::new().read(true).write(true).open(&part)?;
+        if file.metadata()?.len() < prefix.length {
+            return Err(io::Error::new(ErrorKind::InvalidData, "the part file is shorter than its prefix"));
+        }
+        // Bytes after the last checkpoint may not have reached the disk whole
+        file.set_len(prefix.length)?;
+
+        let mut hasher = digest::Context::new(&digest::SHA256);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        if encode(hasher.clone().finish()) != prefix.checksum {
+            return Err(io::Error::new(ErrorKind::InvalidData, "the part file does not match its checksum"));
+        }
+        Ok(Self::new(destination, part, file, hasher, prefix.length, true))
+    }
+
+    fn new(
+        destination: PathBuf,
+        part: PathBuf,
+        file: File,
+        hasher: digest::Context,
+        length: u64,
+        resumable: bool,
+    ) -> Self {
+        Self {
+            destination,
+            part,
+            file,
+            hasher,
+            length,
+            resumable,
+            finished: false,
+        }
+    }
+
+    pub fn destination(&self) -> &Path {
+        &self.destination
+    }
+
+    /// Sync what was written so far and describe it, for
+    /// [`resume`](Self::resume) to check
+    pub fn checkpoint(&mut self) -> io::Result<WrittenPrefix> {
+        self.file.sync_data()?;
+        Ok(WrittenPrefix {
+            length: self.length,
+            checksum: encode(self.hasher.clone().finish()),
+        })
+    }
+
+    /// Stop writing and keep the part file, whatever kind of write this
+    /// is: syncs it and saves its prefix beside it at
+    /// [`resume_path`], for [`resume`](Self::resume) to pick up
+    pub fn pause(mut self) -> io::Result<WrittenPrefix> {
+        self.resumable = true;
+        let prefix = self.checkpoint()?;
+        let state = serde_json::to_vec(&prefix).map_err(io::Error::other)?;
+        write_atomically(resume_path(&self.destination), state)?;
+        Ok(prefix)
+    }
+
+    /// Sync the contents and rename them into place, replacing any file
+    /// already there
+    pub fn commit(self) -> io::Result<PathBuf> {
+        self.file.sync_all()?;
+        std::fs::rename(&self.part, &self.destination)?;
+        self.finish()
+    }
+
+    /// Sync the contents and move them into place only if nothing is there
+    /// yet, failing with `AlreadyExists` otherwise
+    pub fn commit_new(self) -> io::Result<PathBuf> {
+        self.file.sync_all()?;
+        match std::fs::hard_link(&self.part, &self.destination) {
+            Ok(()) => std::fs::remove_file(&self.part)?,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => return Err(e),
+            // File systems without hard links; not atomic, but never replaces
+            Err(_) if !self.destination.exists() => std::fs::rename(&self.part, &self.destination)?,
+            Err(_) => return Err(io::Error::new(ErrorKind::AlreadyExists, "the destination exists")),
+        }
+        self.finish()
+    }
+
+    fn finish(mut self) -> io::Result<PathBuf> {
+        self.finished = true;
+        if self.resumable {
+            let _ = std::fs::remove_file(resume_path(&self.destination));
+        }
+        sync_directory(&self.destination);
+        Ok(self.destination.clone())
+    }
+}
+
+impl Write for SafeFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.length += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for SafeFileWriter {
+    fn drop(&mut self) {
+        if !self.finished && !self.resumable {
+            let _ = std::fs::remove_file(&self.part);
+        }
+    }
+}
+
+/// Write `contents` to `path` through a [`SafeFileWriter`], replacing the
+/// file only once the new contents are safely on disk
+pub fn write_atomically(path: impl Into<PathBuf>, contents: impl AsRef<[u8]>) -> io::Result<PathBuf> {
+    let mut writer = SafeFileWriter::create(path)?;
+    writer.write_all(contents.as_ref())?;
+    writer.commit()
+}
+
+/// `<destination>.part`
+pub fn part_path(destination: &Path) -> PathBuf {
+    let mut name = destination.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(PART_EXTENSION);
+    destination.with_file_name(name)
+}
+
+/// `<destination>.part.resume`
+pub fn resume_path(destination: &Path) -> PathBuf {
+    let mut name = part_path(destination).into_os_string();
+    name.push(".");
+    name.push(RESUME_EXTENSION);
+    PathBuf::from(name)
+}
+
+/// The prefix a paused write of `destination` saved, if it was paused
+pub fn load_resume_state(destination: &Path) -> io::Result<Option<WrittenPrefix>> {
+    match std::fs::read(resume_path(destination)) {
+        Ok(state) => serde_json::from_slice(&state).map(Some).map_err(io::Error::other),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn encode(digest: digest::Digest) -> String {
+    base64::engine::general_purpose::STANDARD.encode(digest.as_ref())
+}
+
+/// Make a rename into `path`'s directory survive a crash; only possible,
+/// and only needed, on Unix
+fn sync_directory(path: &Path) {
+    #[cfg(unix)]
+    if let Some(directory) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        let _ = File::open(directory).and_then(|directory| directory.sync_all());
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory() -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("navigator-safe-file-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn test_interrupted_writes_leave_no_destination() {
+        let directory = directory();
+        let path = directory.join("profile.json");
+        std::fs::write(&path, "old").unwrap();
+
+        let mut writer = SafeFileWriter::create(&path).unwrap();
+        writer.write_all(b"{\"half\": ").unwrap();
+        drop(writer);
+        // The earlier file is untouched and no part file lingers
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+        assert!(!part_path(&path).exists());
+
+        write_atomically(&path, "new").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert!(!part_path(&path).exists());
+
+        let fresh = directory.join("report.log");
+        let mut writer = SafeFileWriter::create_new(&fresh).unwrap();
+        writer.write_all(b"partial").unwrap();
+        assert!(matches!(SafeFileWriter::create_new(&fresh), Err(e) if e.kind() == ErrorKind::AlreadyExists));
+        drop(writer);
+        assert!(!fresh.exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_commit_new_never_replaces() {
+        let directory = directory();
+        let path = directory.join("page.html");
+        std::fs::write(&path, "first").unwrap();
+        let mut writer = SafeFileWriter::create_new(&path).unwrap();
+        writer.write_all(b"second").unwrap();
+        assert_eq!(writer.commit_new().unwrap_err().kind(), ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        assert!(!part_path(&path).exists());
+
+        let other = directory.join("other.html");
+        let mut writer = SafeFileWriter::create_new(&other).unwrap();
+        writer.write_all(b"second").unwrap();
+        assert_eq!(writer.commit_new().unwrap(), other);
+        assert_eq!(std::fs::read(&other).unwrap(), b"second");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_resumed_writes_check_their_prefix() {
+        let directory = directory();
+        let path = directory.join("archive.zip");
+        let mut writer = SafeFileWriter::create_resumable(&path).unwrap();
+        writer.write_all(b"first half, ").unwrap();
+        let prefix = writer.checkpoint().unwrap();
+        // Written after the checkpoint, then cut off
+        writer.write_all(b"torn").unwrap();
+        drop(writer);
+        assert!(!path.exists());
+        assert!(part_path(&path).exists());
+
+        let mut resumed = SafeFileWriter::resume(&path, &prefix).unwrap();
+        resumed.write_all(b"second half").unwrap();
+        resumed.commit().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"first half, second half");
+
+        // A part file changed under the prefix is refused
+        let mut writer = SafeFileWriter::create_resumable(&path).unwrap();
+        writer.write_all(b"trusted bytes").unwrap();
+        let prefix = writer.checkpoint().unwrap();
+        drop(writer);
+        std::fs::write(part_path(&path), b"tampered bytes").unwrap();
+        let refused = SafeFileWriter::resume(&path, &prefix).err().unwrap();
+        assert_eq!(refused.kind(), ErrorKind::InvalidData);
+        let longer = WrittenPrefix { length: 100, ..prefix };
+        assert_eq!(SafeFileWriter::resume(&path, &longer).err().unwrap().kind(), ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&path).unwrap(), b"first half, second half");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_paused_writes_keep_their_resume_state() {
+        let directory = directory();
+        let path = directory.join("video.mp4");
+        assert_eq!(load_resume_state(&path).unwrap(), None);
+        let mut writer = SafeFileWriter::create_new(&path).unwrap();
+        writer.write_all(b"frames").unwrap();
+        let prefix = writer.pause().unwrap();
+        assert_eq!(prefix.length, 6);
+        assert_eq!(load_resume_state(&path).unwrap(), Some(prefix.clone()));
+
+        let mut resumed = SafeFileWriter::resume(&path, &prefix).unwrap();
+        resumed.write_all(b" and more").unwrap();
+        resumed.commit_new().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"frames and more");
+        // Finished, nothing is left to resume
+        assert!(!resume_path(&path).exists());
+        assert_eq!(load_resume_state(&path).unwrap(), None);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,365 @@
+use super::network::SecureNetworkClient;
+use crate::domain::{FetchRequest, RenderError, ResourceKind, SiteOrigin, ValidatedUrl};
+
+type Result<T> = std::result::Result<T, RenderError>;
+
+/// Largest response body a script's `fetch` reads
+pub const MAX_FETCH_BODY: usize = 16 * 1024 * 1024;
+/// Methods `fetch` refuses outright
+const FORBIDDEN_METHODS: &[&str] = &["CONNECT", "TRACE", "TRACK"];
+/// Request headers only the browser sets; ones a script passes are dropped
+const FORBIDDEN_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "cookie",
+    "host",
+    "origin",
+    "referer",
+    "transfer-encoding",
+    "user-agent",
+];
+/// Response headers a script sees on any cross-origin response
+const SAFELISTED_RESPONSE_HEADERS: &[&str] = &[
+    "cache-control",
+    "content-language",
+    "content-length",
+    "content-type",
+    "expires",
+    "last-modified",
+    "pragma",
+];
+
+/// The options a script passes to `fetch` with the URL
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchInit {
+    /// `GET` when not given
+    pub method: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+}
+
+impl FetchInit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_method(mut self, method: &str) -> Self {
+        self.method = Some(method.to_string());
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+}
+
+/// What a `fetch` promise resolves to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptResponse {
+    /// Where the body came from, after redirects
+    pub url: ValidatedUrl,
+    pub status: u16,
+    /// Headers the script may read, names lower case
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl ScriptResponse {
+    /// `response.ok`: a 2xx status
+    pub fn ok(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// `response.headers.get`
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers
+            .iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// `response.text()`
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// `response.json()`, which rejects with a SyntaxError on bad JSON
+    pub fn json(&self) -> Result<serde_json::Value> {
+        serde_json::from_slice(&self.body).map_err(|e| RenderError::Script(format!("SyntaxError: {}", e)))
+    }
+}
+
+/// `fetch(url, init)` made by a script on `page`.
+///
+/// Requests to the page's own origin go out as asked, with its cookies,
+/// until a redirect leaves that origin.
+/// Cross-origin requests must be GETs; they carry an `Origin` header and
+/// no cookies, and the response is only handed over if its
+/// `Access-Control-Allow-Origin` names the page's origin or `*`. Refused
+/// requests fail with a `Script` error reading "TypeError: ...", network
+/// failures with a `Network` one; a script binding rejects with a
+/// TypeError for either. A `private` tab's requests carry the cookies of
+/// private tabs only.
+pub async fn script_fetch(
+    network: &SecureNetworkClient,
+    page: &ValidatedUrl,
+    url: &ValidatedUrl,
+    init: FetchInit,
+    user_agent: Option<&str>,
+    private: bool,
+) -> Result<ScriptResponse> {
+    let method = init.method.as_deref().unwrap_or("GET").trim().to_ascii_uppercase();
+    let valid = !method.is_empty() && method.bytes().all(|b| b.is_ascii_alphabetic());
+    if !valid || FORBIDDEN_METHODS.contains(&method.as_str()) {
+        return Err(type_error(format!("'{}' is not a valid method", method)));
+    }
+    if init.body.is_some() && matches!(method.as_str(), "GET" | "HEAD") {
+        return Err(type_error(format!("A {} request cannot have a body", method)));
+    }
+
+    let origin = SiteOrigin::of(page);
+    let same_origin = origin.is_some() && origin == SiteOrigin::of(url);
+    if !same_origin && method != "GET" {
+        return Err(type_error(format!("Cross-origin {} requests to {} are not allowed", method, url)));
+    }
+
+    let mut request = FetchRequest::new(url.clone(), ResourceKind::Fetch)
+        .with_top_level(page.clone())
+        .with_method(&method)
+        .with_private(private);
+    for (name, value) in &init.headers {
+        if !FORBIDDEN_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+            request = request.with_header(name, value);
+        }
+    }
+    if let Some(body) = init.body {
+        request = request.with_body(body);
+    }
+    if !same_origin {
+        let origin = origin.as_ref().map_or("null", SiteOrigin::as_str);
+        request = request.with_header("Origin", origin).without_credentials();
+    }
+
+    // One byte over the cap tells a body at the cap from a larger one
+    let response = network.fetch_request(request, Some(MAX_FETCH_BODY + 1), user_agent).await?;
+    if response.body.len() > MAX_FETCH_BODY {
+        return Err(type_error(format!("The response from {} is too large", url)));
+    }
+
+    // A redirect may have left the page's origin
+    let mut headers = response.headers;
+    if origin.is_none() || SiteOrigin::of(&response.url) != origin {
+        check_cors(&headers, origin.as_ref(), &response.url)?;
+        headers = exposed_headers(headers);
+    }
+    Ok(ScriptResponse {
+        url: response.url,
+        status: response.status,
+        headers,
+        body: response.body,
+    })
+}
+
+fn type_error(message: String) -> RenderError {
+    RenderError::Script(format!("TypeError: {}", message))
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim())
+}
+
+/// Refuse a cross-origin response that does not allow the page's origin
+fn check_cors(headers: &[(String, String)], origin: Option<&SiteOrigin>, url: &ValidatedUrl) -> Result<()> {
+    let allowed = header(headers, "access-control-allow-origin");
+    let origin = origin.map_or("null", SiteOrigin::as_str);
+    match allowed {
+        Some(allowed) if allowed == "*" || allowed == origin => Ok(()),
+        Some(allowed) => Err(type_error(format!("{} allows the origin {}, not {}", url, allowed, origin))),
+        None => Err(type_error(format!("{} does not allow cross-origin requests", url))),
+    }
+}
+
+/// The safelisted headers of a cross-origin response, and those its
+/// `Access-Control-Expose-Headers` lists
+fn exposed_headers(headers: Vec<(String, String)>) -> Vec<(String, String)> {
+    let exposed: Vec<String> = header(&headers, "access-control-expose-headers")
+        .map(|list| list.split(',').map(|name| name.trim().to_ascii_lowercase()).collect())
+        .unwrap_or_default();
+    headers
+        .into_iter()
+        .filter(|(name, _)| {
+            let name = name.to_ascii_lowercase();
+            SAFELISTED_RESPONSE_HEADERS.contains(&name.as_str()) || exposed.contains(&name)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::CookieJar;
+    use std::sync::Arc;
+
+    /// Answer each connection with the next response; returns the port and
+    /// the requests received
+    async fn serve(responses: Vec<String>) -> (u16, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = vec![0; 4096];
+                // Headers and body may arrive in separate reads
+                while !is_complete(&request) {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                requests.push(String::from_utf8_lossy(&request).to_ascii_lowercase());
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        (port, server)
+    }
+
+    fn is_complete(request: &[u8]) -> bool {
+        let text = String::from_utf8_lossy(request).to_ascii_lowercase();
+        let Some((head, body)) = text.split_once("\r\n\r\n") else {
+            return false;
+        };
+        let length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .and_then(|length| length.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        body.len() >= length
+    }
+
+    fn ok(headers: &str, body: &str) -> String {
+        let length = body.len();
+        format!(
+            "HTTP/1.1 200 OK\r\n{headers}Content-Type: application/json\r\nContent-Length: {length}\r\n\
+             Connection: close\r\n\r\n{body}"
+        )
+    }
+
+    fn url(input: &str) -> ValidatedUrl {
+        ValidatedUrl::parse(input).unwrap()
+    }
+
+    fn is_type_error(result: &Result<ScriptResponse>) -> bool {
+        matches!(result, Err(RenderError::Script(message)) if message.starts_with("TypeError"))
+    }
+
+    #[tokio::test]
+    async fn test_same_origin_fetch_sends_method_body_and_cookies() {
+        let (port, server) = serve(vec![ok("X-Secret: 1\r\n", "{\"saved\":true}")]).await;
+        let jar = Arc::new(CookieJar::new());
+        let network = SecureNetworkClient::new().unwrap().with_cookie_jar(jar.clone());
+        let page = url(&format!("http://127.0.0.1:{}/app", port));
+        jar.store(&page, "session=abc");
+
+        let init = FetchInit::new()
+            .with_method("post")
+            .with_header("Content-Type", "application/json")
+            .with_header("Cookie", "forged=1")
+            .with_body("{\"a\":1}");
+        let response = script_fetch(&network, &page, &page.join("/save").unwrap(), init, None, false)
+            .await
+            .unwrap();
+        assert!(response.ok());
+        assert_eq!(response.json().unwrap()["saved"], serde_json::Value::Bool(true));
+        assert_eq!(response.header("x-secret"), Some("1"));
+
+        let request = server.await.unwrap().remove(0);
+        assert!(request.starts_with("post /save "));
+        assert!(request.contains("cookie: session=abc"));
+        assert!(!request.contains("forged"));
+        assert!(!request.to_ascii_lowercase().contains("origin:"));
+        assert!(request.ends_with("{\"a\":1}"));
+    }
+
+    #[tokio::test]
+    async fn test_same_origin_fetch_drops_cookies_once_redirected_away() {
+        let (other_port, other) = serve(vec![ok("Access-Control-Allow-Origin: *\r\n", "[]")]).await;
+        // Another origin of the same site, which the page's cookies would match
+        let back = format!("http://127.0.0.1:{}/back", other_port);
+        let (port, server) = serve(vec![
+            format!("HTTP/1.1 302 Found\r\nLocation: {back}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+        ])
+        .await;
+        let jar = Arc::new(CookieJar::new());
+        let network = SecureNetworkClient::new().unwrap().with_cookie_jar(jar.clone());
+        let page = url(&format!("http://127.0.0.1:{}/app", port));
+        let other_page = url(&back);
+        jar.store(&page, "session=abc");
+
+        let response = script_fetch(&network, &page, &page.join("/api").unwrap(), FetchInit::new(), None, false)
+            .await
+            .unwrap();
+        assert_eq!(response.url, other_page);
+        assert!(server.await.unwrap()[0].contains("cookie: session=abc"));
+        let redirected = other.await.unwrap().remove(0);
+        assert!(redirected.starts_with("get /back "));
+        assert!(!redirected.contains("cookie:"), "{}", redirected);
+    }
+
+    #[tokio::test]
+    async fn test_cross_origin_fetch_needs_cors_headers() {
+        let (port, server) = serve(vec![
+            ok("Access-Control-Allow-Origin: *\r\nX-Secret: 1\r\n", "[1]"),
+            ok(
+                "Access-Control-Allow-Origin: https://example.com\r\n\
+                 Access-Control-Expose-Headers: X-Secret\r\nX-Secret: 2\r\n",
+                "[2]",
+            ),
+            ok("Access-Control-Allow-Origin: https://other.example\r\n", "[3]"),
+            ok("", "[4]"),
+        ])
+        .await;
+        let jar = Arc::new(CookieJar::new());
+        let network = SecureNetworkClient::new().unwrap().with_cookie_jar(jar.clone());
+        let page = url("https://example.com/app");
+        let api = url(&format!("http://127.0.0.1:{}/data", port));
+        jar.store(&api, "tracker=1");
+
+        let any = script_fetch(&network, &page, &api, FetchInit::new(), None, false).await.unwrap();
+        assert_eq!(any.text(), "[1]");
+        assert_eq!(any.header("x-secret"), None);
+        assert_eq!(any.header("content-type"), Some("application/json"));
+        let named = script_fetch(&network, &page, &api, FetchInit::new(), None, false).await.unwrap();
+        assert_eq!(named.header("x-secret"), Some("2"));
+        assert!(is_type_error(&script_fetch(&network, &page, &api, FetchInit::new(), None, false).await));
+        assert!(is_type_error(&script_fetch(&network, &page, &api, FetchInit::new(), None, false).await));
+
+        // Refused before anything is sent
+        let post = FetchInit::new().with_method("POST").with_body("x");
+        assert!(is_type_error(&script_fetch(&network, &page, &api, post, None, false).await));
+        let get_with_body = FetchInit::new().with_body("x");
+        assert!(is_type_error(&script_fetch(&network, &page, &page, get_with_body, None, false).await));
+
+        let requests = server.await.unwrap();
+        assert_eq!(requests.len(), 4);
+        for request in requests {
+            assert!(request.contains("origin: https://example.com"));
+            assert!(!request.contains("tracker=1"));
+        }
+    }
+}
@@ -1,12 +1,226 @@
-use crate::domain::{SecurityService, ValidatedUrl};
-use anyhow::{anyhow, Result};
+use super::data_url::{DataUrl, DEFAULT_MAX_DATA_URL_SIZE};
+use crate::domain::{SecurityError, SecurityService, ValidatedUrl};
 use std::collections::HashSet;
-use std::sync::RwLock;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Settings key for ports exempt from [`RESTRICTED_PORTS`], one per line
+pub const ALLOWED_PORTS_SETTING: &str = "security.allowed_ports";
+
+/// Settings key for schemes opened by other programs on top of
+/// [`DEFAULT_EXTERNAL_SCHEMES`], one per line
+pub const EXTERNAL_SCHEMES_SETTING: &str = "security.external_schemes";
+
+/// Schemes whose URLs are handed to the system's handler, after asking
+pub const DEFAULT_EXTERNAL_SCHEMES: &[&str] = &["mailto", "tel"];
+
+/// Schemes the browser loads or refuses itself, which are never handed to
+/// another program whatever the settings say
+const INTERNAL_SCHEMES: &[&str] = &[
+    "http", "https", "ws", "wss", "about", "data", "file", "navigator", "javascript", "blob", "ftp",
+];
+
+/// Schemes listed in the external schemes setting, lowercased, ignoring
+/// anything that is not a scheme or is one the browser handles itself
+pub fn parse_external_schemes(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|scheme| scheme.trim_end_matches(':').to_ascii_lowercase())
+        .filter(|scheme| is_scheme(scheme) && !INTERNAL_SCHEMES.contains(&scheme.as_str()))
+        .collect()
+}
+
+/// A letter followed by letters, digits, `+`, `-` or `.`
+fn is_scheme(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars.next().is_some_and(|first| first.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// The Fetch standard's bad ports: well-known ports of non-web services,
+/// which a page could otherwise talk to with crafted requests
+pub const RESTRICTED_PORTS: &[u16] = &[
+    0, 1, 7, 9, 11, 13, 15, 17, 19, 20, 21, 22, 23, 25, 37, 42, 43, 53, 69, 77, 79, 87, 95, 101, 102, 103, 104,
+    109, 110, 111, 113, 115, 117, 119, 123, 135, 137, 139, 143, 161, 179, 389, 427, 465, 512, 513, 514, 515, 526,
+    530, 531, 532, 540, 548, 554, 556, 563, 587, 601, 636, 989, 990, 993, 995, 1719, 1720, 1723, 2049, 3659, 4045,
+    4190, 5060, 5061, 6000, 6566, 6665, 6666, 6667, 6668, 6669, 6679, 6697, 10080,
+];
+
+/// Ports listed in the allowed ports setting, ignoring anything unparsable
+pub fn parse_allowed_ports(value: &str) -> Vec<u16> {
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter_map(|port| port.parse().ok())
+        .collect()
+}
+
+/// Which ports web and WebSocket URLs may use; shared by the security
+/// service, which checks typed URLs, and the network client, which checks
+/// every fetch and WebSocket
+#[derive(Debug, Default)]
+pub struct PortPolicy {
+    allowed: RwLock<HashSet<u16>>,
+}
+
+impl PortPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_allowed<I: IntoIterator<Item = u16>>(self, ports: I) -> Self {
+        for port in ports {
+            self.allow(port);
+        }
+        self
+    }
+
+    /// Exempt a restricted port, for developers running services on one
+    pub fn allow(&self, port: u16) {
+        if let Ok(mut allowed) = self.allowed.write() {
+            allowed.insert(port);
+        }
+    }
+
+    /// The URL's port, explicit or implied by its scheme, if it is refused
+    pub fn restricted_port(&self, url: &ValidatedUrl) -> Option<u16> {
+        if !matches!(url.scheme(), "http" | "https" | "ws" | "wss") {
+            return None;
+        }
+        let port = url.port_or_default()?;
+        let allowed = self.allowed.read().is_ok_and(|allowed| allowed.contains(&port));
+        (RESTRICTED_PORTS.contains(&port) && !allowed).then_some(port)
+    }
+}
+
+/// Which local files may be opened through `file://` URLs
+#[derive(Debug, Clone)]
+pub struct FileAccessPolicy {
+    pub enabled: bool,
+    /// Directories the user has approved; anything outside is rejected
+    pub allowed_roots: Vec<PathBuf>,
+}
+
+impl FileAccessPolicy {
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            allowed_roots: Vec::new(),
+        }
+    }
+
+    /// The policy the file access settings describe: `roots` lists the
+    /// approved directories the way `PATH` does, and is the home
+    /// directory while empty
+    pub fn from_settings(enabled: bool, roots: &str) -> Self {
+        let roots = parse_file_roots(roots);
+        if roots.is_empty() {
+            return Self {
+                enabled,
+                ..Self::default()
+            };
+        }
+        Self {
+            enabled,
+            allowed_roots: roots,
+        }
+    }
+
+    /// Check that a path lives under one of the approved roots.
+    ///
+    /// Both sides are canonicalized first so `..` segments and symlinks
+    /// pointing outside a root cannot be used to escape it.
+    pub fn check(&self, path: &Path) -> Result<PathBuf, SecurityError> {
+        if !self.enabled {
+            return Err(SecurityError::FileAccessDenied("file:// URLs are disabled".to_string()));
+        }
+
+        let canonical = std::fs::canonicalize(path).map_err(|e| {
+            SecurityError::FileAccessDenied(format!("Cannot open {}: {}", path.display(), e))
+        })?;
+
+        let allowed = self
+            .allowed_roots
+            .iter()
+            .filter_map(|root| std::fs::canonicalize(root).ok())
+            .any(|root| canonical.starts_with(&root));
+
+        if allowed {
+            Ok(canonical)
+        } else {
+            Err(SecurityError::FileAccessDenied(format!(
+                "Access to {} is outside the allowed directories",
+                canonical.display()
+            )))
+        }
+    }
+}
+
+impl Default for FileAccessPolicy {
+    fn default() -> Self {
+        let home = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(PathBuf::from);
+
+        Self {
+            enabled: true,
+            allowed_roots: home.into_iter().collect(),
+        }
+    }
+}
+
+/// Directories listed in the file access roots setting, separated the way
+/// `PATH` separates them; relative ones are ignored
+pub fn parse_file_roots(value: &str) -> Vec<PathBuf> {
+    std::env::split_paths(value.trim())
+        .filter(|root| root.is_absolute())
+        .collect()
+}
+
+/// The file access policy in effect, shared by the security service, which
+/// checks typed URLs, and the renderers, which check every file they read
+#[derive(Debug, Default)]
+pub struct FileAccess {
+    policy: RwLock<FileAccessPolicy>,
+}
+
+impl FileAccess {
+    pub fn new(policy: FileAccessPolicy) -> Self {
+        Self {
+            policy: RwLock::new(policy),
+        }
+    }
+
+    /// Takes effect from the next file opened
+    pub fn set(&self, policy: FileAccessPolicy) {
+        if let Ok(mut current) = self.policy.write() {
+            *current = policy;
+        }
+    }
+
+    pub fn policy(&self) -> FileAccessPolicy {
+        self.policy
+            .read()
+            .map(|policy| policy.clone())
+            .unwrap_or_else(|_| FileAccessPolicy::disabled())
+    }
+
+    pub fn check(&self, path: &Path) -> Result<PathBuf, SecurityError> {
+        self.policy().check(path)
+    }
+}
 
 /// Default implementation of SecurityService
 pub struct DefaultSecurityService {
     blocked_domains: RwLock<HashSet<String>>,
     allow_mixed_content: bool,
+    file_access: Arc<FileAccess>,
+    ports: Arc<PortPolicy>,
+    /// Schemes classified as external rather than refused
+    external_schemes: RwLock<HashSet<String>>,
+    /// Largest decoded payload accepted from a data: URL, as the renderers
+    /// are configured with
+    max_data_url_size: usize,
 }
 
 impl DefaultSecurityService {
@@ -21,9 +235,78 @@ impl DefaultSecurityService {
         Self {
             blocked_domains: RwLock::new(blocked),
             allow_mixed_content: false,
+            file_access: Arc::new(FileAccess::default()),
+            ports: Arc::new(PortPolicy::new()),
+            external_schemes: RwLock::new(DEFAULT_EXTERNAL_SCHEMES.iter().map(ToString::to_string).collect()),
+            max_data_url_size: DEFAULT_MAX_DATA_URL_SIZE,
+        }
+    }
+
+    /// Refuse data: URLs decoding to more than `max_data_url_size` bytes;
+    /// should match the renderers' `RenderingConfig::max_data_url_size`
+    pub fn with_max_data_url_size(mut self, max_data_url_size: usize) -> Self {
+        self.max_data_url_size = max_data_url_size;
+        self
+    }
+
+    pub fn with_port_policy(mut self, ports: Arc<PortPolicy>) -> Self {
+        self.ports = ports;
+        self
+    }
+
+    /// Check file:// URLs against a policy shared with the renderers
+    pub fn with_shared_file_access(mut self, file_access: Arc<FileAccess>) -> Self {
+        self.file_access = file_access;
+        self
+    }
+
+    pub fn with_file_access(self, policy: FileAccessPolicy) -> Self {
+        self.set_file_access(policy);
+        self
+    }
+
+    pub fn set_file_access(&self, policy: FileAccessPolicy) {
+        self.file_access.set(policy);
+    }
+
+    pub fn file_access(&self) -> FileAccessPolicy {
+        self.file_access.policy()
+    }
+
+    /// Resolve a file:// URL to its canonical form if the policy allows it
+    fn validate_file_url(&self, url: &ValidatedUrl) -> Result<ValidatedUrl, SecurityError> {
+        let path = url
+            .to_file_path()
+            .ok_or_else(|| SecurityError::InvalidUrl(url.to_string()))?;
+
+        let canonical = self.file_access.check(&path)?;
+
+        let resolved = if canonical.is_dir() {
+            url::Url::from_directory_path(&canonical)
+        } else {
+            url::Url::from_file_path(&canonical)
+        }
+        .map_err(|_| SecurityError::InvalidUrl(canonical.display().to_string()))?;
+
+        ValidatedUrl::parse(resolved.as_str()).map_err(|e| SecurityError::InvalidUrl(e.to_string()))
+    }
+
+    /// Hand URLs of `scheme` to another program too; schemes the browser
+    /// handles itself are ignored
+    pub fn add_external_scheme(&self, scheme: &str) {
+        for scheme in parse_external_schemes(scheme) {
+            if let Ok(mut schemes) = self.external_schemes.write() {
+                schemes.insert(scheme);
+            }
         }
     }
 
+    fn is_external_scheme(&self, scheme: &str) -> bool {
+        self.external_schemes
+            .read()
+            .is_ok_and(|schemes| schemes.contains(&scheme.to_ascii_lowercase()))
+    }
+
     pub fn add_blocked_domain(&self, domain: String) {
         if let Ok(mut blocked) = self.blocked_domains.write() {
             blocked.insert(domain);
@@ -44,26 +327,49 @@ impl Default for DefaultSecurityService {
 }
 
 impl SecurityService for DefaultSecurityService {
-    fn validate_url(&self, url: &str) -> Result<ValidatedUrl> {
+    fn validate_url(&self, url: &str) -> Result<ValidatedUrl, SecurityError> {
         // Trim whitespace
         let trimmed = url.trim();
 
         // If no scheme, assume HTTPS (secure by default)
-        let url_with_scheme = if !trimmed.contains("://") {
+        let lowercase = trimmed.to_ascii_lowercase();
+        let has_scheme = trimmed.contains("://")
+            || lowercase.starts_with("about:")
+            || lowercase.starts_with("data:")
+            || lowercase.split_once(':').is_some_and(|(scheme, _)| self.is_external_scheme(scheme));
+        let url_with_scheme = if !has_scheme {
             format!("https://{}", trimmed)
         } else {
             trimmed.to_string()
         };
 
+        // The URL parser silently strips tabs and newlines, so data: URLs
+        // are checked against the raw input before parsing
+        if lowercase.starts_with("data:") {
+            DataUrl::parse(trimmed, self.max_data_url_size)
+                .map_err(|e| SecurityError::InvalidUrl(e.to_string()))?;
+        }
+
         // Parse URL
         let parsed = ValidatedUrl::parse(&url_with_scheme)
-            .map_err(|e| anyhow!("Invalid URL: {}", e))?;
+            .map_err(|e| SecurityError::InvalidUrl(e.to_string()))?;
 
-        // Block non-HTTP(S) schemes for security (except about:, data: for specific cases)
+        // Block non-HTTP(S) schemes for security (except about:blank, data: for specific cases)
         match parsed.scheme() {
-            "http" | "https" => Ok(parsed),
-            "about" | "data" => Ok(parsed),
-            scheme => Err(anyhow!("Unsupported URL scheme: {}", scheme)),
+            "http" | "https" => match self.ports.restricted_port(&parsed) {
+                Some(port) => Err(SecurityError::RestrictedPort(port)),
+                None => Ok(parsed),
+            },
+            // The empty document is the only about: page
+            "about" if parsed.is_about_blank() => Ok(parsed),
+            "about" => Err(SecurityError::InvalidUrl(format!("{} is not a page", parsed))),
+            "data" => Ok(parsed),
+            // Pages generated by the browser itself
+            "navigator" => Ok(parsed),
+            "file" => self.validate_file_url(&parsed),
+            // Handed to another program once the user agrees
+            scheme if self.is_external_scheme(scheme) => Ok(parsed),
+            scheme => Err(SecurityError::UnsupportedScheme(scheme.to_string())),
         }
     }
 
@@ -77,19 +383,27 @@ impl SecurityService for DefaultSecurityService {
     }
 
     fn sanitize_html(&self, html: &str) -> String {
-        // Basic HTML sanitization
-        // In production, use a proper HTML sanitizer library
-        html.replace("<script", "&lt;script")
-            .replace("javascript:", "")
-            .replace("onerror=", "")
-            .replace("onclick=", "")
-            .replace("onload=", "")
+        sanitize_html(html)
     }
 
     fn allow_mixed_content(&self, url: &ValidatedUrl) -> bool {
         // Only allow mixed content if explicitly enabled and URL is secure
         self.allow_mixed_content && url.is_secure()
     }
+
+    fn is_external(&self, url: &ValidatedUrl) -> bool {
+        self.is_external_scheme(url.scheme())
+    }
+}
+
+/// Basic HTML sanitization
+pub fn sanitize_html(html: &str) -> String {
+    // In production, use a proper HTML sanitizer library
+    html.replace("<script", "&lt;script")
+        .replace("javascript:", "")
+        .replace("onerror=", "")
+        .replace("onclick=", "")
+        .replace("onload=", "")
 }
 
 /// Content Security Policy builder
@@ -151,9 +465,32 @@ pub fn default_csp() -> String {
         .build()
 }
 
+/// Whether pages' content security policies refuse what they do not
+/// allow, rather than only reporting it; shared by every tab
+#[derive(Debug, Clone, Default)]
+pub struct CspEnforcement {
+    enabled: Arc<AtomicBool>,
+}
+
+impl CspEnforcement {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Takes effect as pages are next loaded
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::CspPolicy;
 
     #[test]
     fn test_validate_url_adds_https() {
@@ -162,6 +499,13 @@ mod tests {
         assert_eq!(result.as_str(), "https://example.com/");
     }
 
+    #[test]
+    fn test_only_about_blank_is_valid() {
+        let service = DefaultSecurityService::new();
+        assert!(service.validate_url(" about:blank ").unwrap().is_about_blank());
+        assert!(matches!(service.validate_url("about:config"), Err(SecurityError::InvalidUrl(_))));
+    }
+
     #[test]
     fn test_validate_url_preserves_http() {
         let service = DefaultSecurityService::new();
@@ -169,6 +513,49 @@ mod tests {
         assert_eq!(result.as_str(), "http://example.com/");
     }
 
+    #[test]
+    fn test_restricted_ports_are_refused() {
+        let service = DefaultSecurityService::new();
+        for (input, port) in [
+            ("https://example.com:25/", 25),
+            ("http://example.com:6667/chat", 6667),
+            ("example.com:22", 22),
+            ("http://[::1]:10080/", 10080),
+            ("https://example.com:0/", 0),
+        ] {
+            assert_eq!(service.validate_url(input), Err(SecurityError::RestrictedPort(port)), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_ordinary_ports_pass() {
+        let service = DefaultSecurityService::new();
+        for input in [
+            "https://example.com/",
+            "http://example.com/",
+            "https://example.com:443/",
+            "http://example.com:80/",
+            "http://localhost:8080/",
+            "https://example.com:49152/",
+            "https://example.com:65535/",
+        ] {
+            assert!(service.validate_url(input).is_ok(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_allowed_ports_override_restrictions() {
+        let ports = Arc::new(PortPolicy::new().with_allowed(parse_allowed_ports("6667, 25\nbogus")));
+        let service = DefaultSecurityService::new().with_port_policy(ports.clone());
+        assert!(service.validate_url("http://localhost:6667/").is_ok());
+        assert!(service.validate_url("smtp.example:25").is_ok());
+        assert_eq!(service.validate_url("http://localhost:6666/"), Err(SecurityError::RestrictedPort(6666)));
+
+        // Only web schemes have ports to restrict
+        let url = ValidatedUrl::parse("data:text/plain,25").unwrap();
+        assert_eq!(ports.restricted_port(&url), None);
+    }
+
     #[test]
     fn test_blocked_domain() {
         let service = DefaultSecurityService::new();
@@ -186,6 +573,153 @@ mod tests {
         assert!(!sanitized.contains("<script"));
     }
 
+    #[test]
+    fn test_validate_data_url() {
+        let service = DefaultSecurityService::new();
+        let result = service.validate_url("data:text/html,<h1>Hi</h1>").unwrap();
+        assert_eq!(result.scheme(), "data");
+
+        assert!(service.validate_url("data:text/html;base\t64,PGgxPg==").is_err());
+        assert!(service.validate_url("data:application/x-evil,boom").is_err());
+
+        let limited = DefaultSecurityService::new().with_max_data_url_size(4);
+        assert!(limited.validate_url("data:text/plain,four").is_ok());
+        assert!(limited.validate_url("data:text/plain,fives").is_err());
+    }
+
+    fn temp_root() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("navigator-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("site")).unwrap();
+        std::fs::write(root.join("site/index.html"), "<html></html>").unwrap();
+        std::fs::write(root.join("secret.txt"), "secret").unwrap();
+        root
+    }
+
+    fn service_for(root: &Path) -> DefaultSecurityService {
+        DefaultSecurityService::new().with_file_access(FileAccessPolicy {
+            enabled: true,
+            allowed_roots: vec![root.join("site")],
+        })
+    }
+
+    #[test]
+    fn test_file_url_inside_root() {
+        let root = temp_root();
+        let service = service_for(&root);
+
+        let url = url::Url::from_file_path(root.join("site/index.html")).unwrap();
+        let result = service.validate_url(url.as_str()).unwrap();
+        assert_eq!(result.scheme(), "file");
+        assert!(result.as_str().ends_with("/site/index.html"));
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn test_file_url_traversal_rejected() {
+        let root = temp_root();
+        let service = service_for(&root);
+
+        let url = format!("file://{}/site/../secret.txt", root.display());
+        assert!(service.validate_url(&url).is_err());
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_url_symlink_escape_rejected() {
+        let root = temp_root();
+        let service = service_for(&root);
+
+        std::os::unix::fs::symlink(root.join("secret.txt"), root.join("site/link.txt")).unwrap();
+        let url = url::Url::from_file_path(root.join("site/link.txt")).unwrap();
+        assert!(service.validate_url(url.as_str()).is_err());
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn test_file_url_disabled() {
+        let root = temp_root();
+        let service = DefaultSecurityService::new().with_file_access(FileAccessPolicy::disabled());
+
+        let url = url::Url::from_file_path(root.join("site/index.html")).unwrap();
+        assert!(matches!(
+            service.validate_url(url.as_str()),
+            Err(SecurityError::FileAccessDenied(_))
+        ));
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn test_file_access_follows_settings() {
+        let root = temp_root();
+        let roots = std::env::join_paths([root.join("site"), PathBuf::from("relative")]).unwrap();
+        let policy = FileAccessPolicy::from_settings(true, roots.to_str().unwrap());
+        assert_eq!(policy.allowed_roots, [root.join("site")]);
+        assert_eq!(
+            FileAccessPolicy::from_settings(true, " ").allowed_roots,
+            FileAccessPolicy::default().allowed_roots
+        );
+
+        let file_access = Arc::new(FileAccess::new(policy));
+        let service = DefaultSecurityService::new().with_shared_file_access(file_access.clone());
+        let url = url::Url::from_file_path(root.join("site/index.html")).unwrap();
+        assert!(service.validate_url(url.as_str()).is_ok());
+
+        // Turning access off applies to the service sharing the policy
+        file_access.set(FileAccessPolicy::from_settings(false, roots.to_str().unwrap()));
+        assert!(matches!(
+            service.validate_url(url.as_str()),
+            Err(SecurityError::FileAccessDenied(_))
+        ));
+
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[test]
+    fn test_unsupported_scheme() {
+        let service = DefaultSecurityService::new();
+        assert_eq!(
+            service.validate_url("ftp://example.com/file"),
+            Err(SecurityError::UnsupportedScheme("ftp".to_string()))
+        );
+        assert!(matches!(service.validate_url("https://exa mple.com"), Err(SecurityError::InvalidUrl(_))));
+        assert!(service.validate_url("navigator://logs?level=warn").is_ok());
+    }
+
+    #[test]
+    fn test_external_schemes_are_classified_not_refused() {
+        let service = DefaultSecurityService::new();
+        let mail = service.validate_url(" mailto:someone@example.com?subject=Hi ").unwrap();
+        assert_eq!(mail.as_str(), "mailto:someone@example.com?subject=Hi");
+        assert!(service.is_external(&mail));
+        assert!(service.is_external(&service.validate_url("TEL:+1-555-0100").unwrap()));
+        assert!(!service.is_external(&service.validate_url("example.com").unwrap()));
+        assert!(!service.is_external(&service.validate_url("about:blank").unwrap()));
+        // Everything else is still refused
+        assert_eq!(
+            service.validate_url("ssh://example.com"),
+            Err(SecurityError::UnsupportedScheme("ssh".to_string()))
+        );
+        assert!(service.validate_url("javascript:alert(1)").is_err());
+
+        service.add_external_scheme("SSH:");
+        service.add_external_scheme("magnet");
+        service.add_external_scheme("javascript");
+        service.add_external_scheme("file");
+        assert!(service.is_external(&service.validate_url("ssh://example.com").unwrap()));
+        // A scheme without slashes is recognized once it is external
+        assert!(service.is_external(&service.validate_url("magnet:?xt=urn:btih:abc").unwrap()));
+        assert!(service.validate_url("javascript:alert(1)").is_err());
+        assert_eq!(
+            parse_external_schemes("matrix, ssh:\nHTTPS 1bad web+app"),
+            ["matrix", "ssh", "web+app"]
+        );
+    }
+
     #[test]
     fn test_csp_builder() {
         let csp = CspBuilder::new()
@@ -196,4 +730,13 @@ mod tests {
         assert!(csp.contains("default-src 'self'"));
         assert!(csp.contains("script-src 'self' 'unsafe-inline'"));
     }
+
+    #[test]
+    fn test_built_policies_parse_back() {
+        let policy = CspPolicy::parse(&default_csp());
+        assert_eq!(policy.to_string(), default_csp());
+        assert_eq!(policy.sources("img-src").unwrap(), ["'self'", "data:", "https:"]);
+        assert_eq!(policy.sources("upgrade-insecure-requests").unwrap(), [] as [String; 0]);
+        assert_eq!(policy.sources("frame-ancestors"), None);
+    }
 }
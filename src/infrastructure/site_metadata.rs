@@ -0,0 +1,567 @@
+use super::network::SecureNetworkClient;
+use crate::domain::{
+    NetworkError, ResourceKind, RobotsGroup, RobotsRule, RobotsTxt, SiteMetadataError, SiteMetadataFetcher,
+    SitemapEntry, ValidatedUrl,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::read::GzDecoder;
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Crawlers stop reading a robots.txt after about this much
+pub const MAX_ROBOTS_BODY: usize = 512 * 1024;
+/// Largest sitemap the protocol allows, once decompressed
+pub const MAX_SITEMAP_BODY: usize = 50 * 1024 * 1024;
+/// Most sitemaps of an index followed
+pub const MAX_INDEXED_SITEMAPS: usize = 50;
+/// How long fetched rules and sitemaps are reused
+pub const DEFAULT_SITE_METADATA_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// What a sitemap file holds
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sitemap {
+    Pages(Vec<SitemapEntry>),
+    /// A sitemap index, listing other sitemaps
+    Index(Vec<ValidatedUrl>),
+}
+
+/// Read a robots.txt fetched from `robots_url`.
+///
+/// Byte order marks, comments, any line ending and lines this does not
+/// know are skipped, as are rules before the first `User-agent`. Common
+/// misspellings of the keys are accepted, the way crawlers do.
+pub fn parse_robots_txt(body: &[u8], robots_url: &ValidatedUrl) -> RobotsTxt {
+    let text = String::from_utf8_lossy(body);
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text);
+
+    let mut robots = RobotsTxt::default();
+    let mut group: Option<RobotsGroup> = None;
+    // Consecutive User-agent lines share the group after them
+    let mut naming_agents = false;
+    for line in text.split(['\n', '\r']) {
+        let line = line.split('#').next().unwrap_or("");
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "user-agent" | "useragent" | "user agent" => {
+                if !naming_agents {
+                    robots.groups.extend(group.take());
+                }
+                naming_agents = true;
+                let agent = value.split(['/', ' ']).next().unwrap_or("").to_ascii_lowercase();
+                group.get_or_insert_with(RobotsGroup::default).user_agents.push(agent);
+            }
+            key @ ("allow" | "disallow" | "dissallow" | "disalow" | "dissalow") => {
+                naming_agents = false;
+                // An empty Disallow allows everything, as no rule does
+                if let Some(group) = group.as_mut().filter(|_| !value.is_empty()) {
+                    group.rules.push(RobotsRule::new(key == "allow", value));
+                }
+            }
+            "crawl-delay" | "crawldelay" | "crawl delay" => {
+                naming_agents = false;
+                let delay = value.parse::<f64>().ok().filter(|delay| delay.is_finite() && *delay >= 0.0);
+                if let (Some(group), Some(delay)) = (group.as_mut(), delay) {
+                    group.crawl_delay = Some(Duration::from_secs_f64(delay));
+                }
+            }
+            // Sitemaps belong to no group, and may come anywhere
+            "sitemap" | "site-map" => {
+                if let Some(sitemap) = robots_url.join(value).ok().filter(is_web_url) {
+                    if !robots.sitemaps.contains(&sitemap) {
+                        robots.sitemaps.push(sitemap);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    robots.groups.extend(group);
+    robots
+}
+
+/// Read a sitemap fetched from `sitemap_url`: a `urlset`, a
+/// `sitemapindex` or a text file of URLs, one per line, any of them
+/// gzipped.
+///
+/// The XML is read with the HTML parser, which keeps elements this simple
+/// intact. Locations that are not http or https URLs are dropped, as are
+/// dates and priorities that cannot be read.
+pub fn parse_sitemap(body: &[u8], sitemap_url: &ValidatedUrl) -> Result<Sitemap, SiteMetadataError> {
+    let body = gunzip(body)?;
+    let text = String::from_utf8_lossy(&body);
+    let text = text.strip_prefix('\u{feff}').unwrap_or(&text).trim();
+    if !text.starts_with('<') {
+        let pages: Vec<SitemapEntry> = text
+            .lines()
+            // Text sitemaps hold full URLs only, which also keeps stray prose out
+            .filter_map(|line| ValidatedUrl::parse(line.trim()).ok().filter(is_web_url))
+            .map(|url| SitemapEntry {
+                url,
+                last_modified: None,
+                change_frequency: None,
+                priority: None,
+            })
+            .collect();
+        if pages.is_empty() {
+            return Err(SiteMetadataError::InvalidSitemap("no URLs".to_string()));
+        }
+        return Ok(Sitemap::Pages(pages));
+    }
+
+    let dom = parse_document(RcDom::default(), Default::default()).one(unwrap_cdata(text));
+    let mut contents = SitemapContents::default();
+    contents.walk(&dom.document, sitemap_url);
+    match contents {
+        SitemapContents { index: true, sitemaps, .. } => Ok(Sitemap::Index(sitemaps)),
+        SitemapContents { urlset: true, pages, .. } => Ok(Sitemap::Pages(pages)),
+        _ => Err(SiteMetadataError::InvalidSitemap("no urlset or sitemapindex".to_string())),
+    }
+}
+
+fn is_web_url(url: &ValidatedUrl) -> bool {
+    matches!(url.scheme(), "http" | "https")
+}
+
+/// `body`, decompressed if it starts like gzip does
+fn gunzip(body: &[u8]) -> Result<Vec<u8>, SiteMetadataError> {
+    if !body.starts_with(&[0x1f, 0x8b]) {
+        return Ok(body.to_vec());
+    }
+    let mut decompressed = Vec::new();
+    GzDecoder::new(body)
+        .take(MAX_SITEMAP_BODY as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| SiteMetadataError::InvalidSitemap(format!("corrupt gzip: {}", e)))?;
+    if decompressed.len() > MAX_SITEMAP_BODY {
+        return Err(SiteMetadataError::InvalidSitemap("larger than 50 MB once decompressed".to_string()));
+    }
+    Ok(decompressed)
+}
+
+/// Replace CDATA sections, which the HTML parser would drop as comments,
+/// with their text escaped
+fn unwrap_cdata(text: &str) -> String {
+    let mut unwrapped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("<![CDATA[") {
+        unwrapped.push_str(&rest[..start]);
+        let inner = &rest[start + "<![CDATA[".len()..];
+        let end = inner.find("]]>").unwrap_or(inner.len());
+        unwrapped.push_str(&inner[..end].replace('&', "&amp;").replace('<', "&lt;"));
+        rest = inner.get(end + "]]>".len()..).unwrap_or("");
+    }
+    unwrapped.push_str(rest);
+    unwrapped
+}
+
+#[derive(Default)]
+struct SitemapContents {
+    urlset: bool,
+    index: bool,
+    pages: Vec<SitemapEntry>,
+    sitemaps: Vec<ValidatedUrl>,
+}
+
+impl SitemapContents {
+    fn walk(&mut self, handle: &Handle, sitemap_url: &ValidatedUrl) {
+        if let NodeData::Element { name, .. } = &handle.data {
+            let loc = || {
+                find_element(handle, "loc")
+                    .and_then(|loc| sitemap_url.join(&text_of(&loc)).ok())
+                    .filter(is_web_url)
+            };
+            match &*name.local {
+                "urlset" => self.urlset = true,
+                "sitemapindex" => self.index = true,
+                "url" => {
+                    let text = |wanted: &str| find_element(handle, wanted).map(|element| text_of(&element));
+                    self.pages.extend(loc().map(|url| SitemapEntry {
+                        url,
+                        last_modified: text("lastmod").and_then(|date| parse_last_modified(&date)),
+                        change_frequency: text("changefreq")
+                            .map(|frequency| frequency.to_ascii_lowercase())
+                            .filter(|frequency| !frequency.is_empty()),
+                        priority: text("priority")
+                            .and_then(|priority| priority.parse::<f32>().ok())
+                            .filter(|priority| (0.0..=1.0).contains(priority)),
+                    }));
+                    return;
+                }
+                "sitemap" => {
+                    self.sitemaps.extend(loc());
+                    return;
+                }
+                _ => {}
+            }
+        }
+        for child in handle.children.borrow().iter() {
+            self.walk(child, sitemap_url);
+        }
+    }
+}
+
+/// First element named `wanted` inside `handle`, at any depth, since
+/// elements the HTML parser does not know may have been left open
+fn find_element(handle: &Handle, wanted: &str) -> Option<Handle> {
+    handle.children.borrow().iter().find_map(|child| match &child.data {
+        NodeData::Element { name, .. } if &*name.local == wanted => Some(child.clone()),
+        _ => find_element(child, wanted),
+    })
+}
+
+/// Text directly inside an element, trimmed
+fn text_of(handle: &Handle) -> String {
+    let text: String = handle
+        .children
+        .borrow()
+        .iter()
+        .filter_map(|child| match &child.data {
+            NodeData::Text { contents } => Some(contents.borrow().to_string()),
+            _ => None,
+        })
+        .collect();
+    text.trim().to_string()
+}
+
+/// A W3C datetime, such as `2026-09-30`, `2026-09-30T07:03+02:00` or a
+/// full RFC 3339 time
+fn parse_last_modified(date: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(date) {
+        return Some(time.with_timezone(&Utc));
+    }
+    if let Ok(time) = DateTime::parse_from_str(date, "%Y-%m-%dT%H:%M%:z") {
+        return Some(time.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+/// Fetches robots.txt files and sitemaps through the shared client,
+/// reusing each for a while. Failures to fetch are not remembered; a
+/// missing robots.txt is.
+pub struct SiteMetadata {
+    network: Arc<SecureNetworkClient>,
+    ttl: Duration,
+    robots: Mutex<HashMap<String, (Instant, RobotsTxt)>>,
+    sitemaps: Mutex<HashMap<String, (Instant, Vec<SitemapEntry>)>>,
+}
+
+impl SiteMetadata {
+    pub fn new(network: Arc<SecureNetworkClient>) -> Self {
+        Self {
+            network,
+            ttl: DEFAULT_SITE_METADATA_TTL,
+            robots: Mutex::new(HashMap::new()),
+            sitemaps: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Reuse what was fetched for `ttl` instead of the default hour
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// A cached value still fresh enough to reuse
+    fn cached<T: Clone>(&self, cache: &Mutex<HashMap<String, (Instant, T)>>, key: &str) -> Option<T> {
+        let cache = cache.lock().ok()?;
+        let (fetched, value) = cache.get(key)?;
+        (fetched.elapsed() < self.ttl).then(|| value.clone())
+    }
+
+    fn remember<T>(&self, cache: &Mutex<HashMap<String, (Instant, T)>>, key: String, value: T) {
+        if let Ok(mut cache) = cache.lock() {
+            cache.retain(|_, (fetched, _)| fetched.elapsed() < self.ttl);
+            cache.insert(key, (Instant::now(), value));
+        }
+    }
+
+    /// Fetch and read a single sitemap file, without following an index
+    async fn fetch_sitemap_file(&self, url: &ValidatedUrl) -> Result<Sitemap, SiteMetadataError> {
+        let response = self
+            .network
+            .fetch_resource_limited(url, ResourceKind::Subresource, Some(MAX_SITEMAP_BODY + 1))
+            .await?;
+        if response.status >= 400 {
+            return Err(NetworkError::Status(response.status).into());
+        }
+        if response.body.len() > MAX_SITEMAP_BODY {
+            return Err(SiteMetadataError::InvalidSitemap("larger than 50 MB".to_string()));
+        }
+        parse_sitemap(&response.body, &response.url)
+    }
+}
+
+fn check_scheme(url: &ValidatedUrl) -> Result<(), SiteMetadataError> {
+    if !is_web_url(url) {
+        return Err(SiteMetadataError::UnsupportedScheme(url.scheme().to_string()));
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl SiteMetadataFetcher for SiteMetadata {
+    async fn fetch_robots(&self, origin: &ValidatedUrl) -> Result<RobotsTxt, SiteMetadataError> {
+        check_scheme(origin)?;
+        let robots_url = origin
+            .join("/robots.txt")
+            .map_err(|_| SiteMetadataError::UnsupportedScheme(origin.scheme().to_string()))?;
+        let key = robots_url.normalized().as_str().to_string();
+        if let Some(robots) = self.cached(&self.robots, &key) {
+            return Ok(robots);
+        }
+
+        let response = self
+            .network
+            .fetch_resource_limited(&robots_url, ResourceKind::Subresource, Some(MAX_ROBOTS_BODY))
+            .await?;
+        let robots = match response.status {
+            200..=299 => parse_robots_txt(&response.body, &response.url),
+            // Without a robots.txt everything is allowed; a failing server
+            // says nothing either way
+            400..=499 => RobotsTxt::default(),
+            status => return Err(NetworkError::Status(status).into()),
+        };
+        self.remember(&self.robots, key, robots.clone());
+        Ok(robots)
+    }
+
+    async fn fetch_sitemap(&self, url: &ValidatedUrl) -> Result<Vec<SitemapEntry>, SiteMetadataError> {
+        check_scheme(url)?;
+        let key = url.normalized().as_str().to_string();
+        if let Some(pages) = self.cached(&self.sitemaps, &key) {
+            return Ok(pages);
+        }
+
+        let pages = match self.fetch_sitemap_file(url).await? {
+            Sitemap::Pages(pages) => pages,
+            // Indexes are followed one level deep, skipping sitemaps that
+            // fail rather than losing the rest
+            Sitemap::Index(sitemaps) => {
+                let mut pages = Vec::new();
+                for sitemap in sitemaps.iter().take(MAX_INDEXED_SITEMAPS) {
+                    match self.fetch_sitemap_file(sitemap).await {
+                        Ok(Sitemap::Pages(listed)) => pages.extend(listed),
+                        Ok(Sitemap::Index(_)) => tracing::debug!("Skipped nested sitemap index {}", sitemap),
+                        Err(e) => tracing::debug!("Skipped sitemap {}: {}", sitemap, e),
+                    }
+                }
+                pages
+            }
+        };
+        self.remember(&self.sitemaps, key, pages.clone());
+        Ok(pages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::ANY_ROBOT;
+    use std::path::Path;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn sample(name: &str) -> Vec<u8> {
+        std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/site-metadata").join(name)).unwrap()
+    }
+
+    fn url(input: &str) -> ValidatedUrl {
+        ValidatedUrl::parse(input).unwrap()
+    }
+
+    #[test]
+    fn test_parse_published_robots() {
+        let robots_url = url("https://en.encyclopedia.example/robots.txt");
+        let robots = parse_robots_txt(&sample("encyclopedia-robots.txt"), &robots_url);
+        let page = |path: &str| url(&format!("https://en.encyclopedia.example{}", path));
+        assert_eq!(robots.groups.len(), 5);
+        assert_eq!(robots.groups[3].user_agents, ["webreaper", "webcopier"]);
+        assert!(!robots.is_allowed(&page("/wiki/Main_Page"), "Wget/1.21"));
+        assert!(!robots.is_allowed(&page("/"), "WebCopier v4.6"));
+        assert!(robots.is_allowed(&page("/wiki/Main_Page"), "Navigator/1.0"));
+        assert!(!robots.is_allowed(&page("/wiki/Special:Random"), "Navigator/1.0"));
+        // The escaped spelling in the file covers both
+        assert!(!robots.is_allowed(&page("/wiki/Special%3aRandom"), "Navigator/1.0"));
+        assert!(!robots.is_allowed(&page("/wiki/Wikipedia:Articles_for_deletion/Rust"), "Navigator/1.0"));
+        assert!(!robots.is_allowed(&page("/w/index.php?title=Rust"), "Navigator/1.0"));
+        assert!(robots.is_allowed(&page("/w/load.php?modules=site"), "Navigator/1.0"));
+        assert!(robots.sitemaps.is_empty());
+
+        let robots = parse_robots_txt(&sample("forge-robots.txt"), &url("https://forge.example/robots.txt"));
+        let page = |path: &str| url(&format!("https://forge.example{}", path));
+        assert_eq!(robots.crawl_delay("Baiduspider"), None);
+        assert_eq!(robots.crawl_delay("baidu/2.0"), Some(Duration::from_secs(1)));
+        assert!(robots.is_allowed(&page("/rust-lang/rust"), "baidu"));
+        assert!(robots.is_allowed(&page("/rust-lang/rust"), "Navigator/1.0"));
+        assert!(!robots.is_allowed(&page("/rust-lang/rust/pulse"), "Navigator/1.0"));
+        assert!(!robots.is_allowed(&page("/rust-lang/rust.git"), "Navigator/1.0"));
+        assert!(robots.is_allowed(&page("/rust-lang/rust.github.io"), "Navigator/1.0"));
+        assert!(!robots.is_allowed(&page("/ferris?tab=repositories"), "Navigator/1.0"));
+        assert!(robots.is_allowed(&page("/ferris?tab=achievements&achievement=starstruck"), "Navigator/1.0"));
+        // Rules after a blank line still belong to the group
+        assert!(!robots.is_allowed(&page("/account-login"), "Navigator/1.0"));
+    }
+
+    #[test]
+    fn test_robots_with_bom_and_crlf() {
+        let robots = parse_robots_txt(&sample("blog-robots.txt"), &url("https://blog.example/robots.txt"));
+        let page = |path: &str| url(&format!("https://blog.example{}", path));
+        assert_eq!(robots.groups[0].user_agents, [ANY_ROBOT]);
+        assert!(!robots.is_allowed(&page("/wp-admin/options.php"), "Navigator/1.0"));
+        assert!(robots.is_allowed(&page("/wp-admin/admin-ajax.php"), "Navigator/1.0"));
+        assert!(!robots.is_allowed(&page("/?s=kettles"), "Navigator/1.0"));
+        assert!(!robots.is_allowed(&page("/2026/09/hello"), "GPTBot/1.1"));
+        let sitemaps: Vec<&str> = robots.sitemaps.iter().map(ValidatedUrl::as_str).collect();
+        assert_eq!(sitemaps, ["https://blog.example/wp-sitemap.xml", "https://blog.example/news-sitemap.xml"]);
+    }
+
+    #[test]
+    fn test_robots_quirks() {
+        let robots_url = url("https://example.com/robots.txt");
+        let body = "Disallow: /before-any-agent\nUser-Agent: *\nDissallow: /typo # the key is misspelt\n\
+            Disallow:\ncrawl-delay: soon\nUser-agent: other\nDisallow: /";
+        let robots = parse_robots_txt(body.as_bytes(), &robots_url);
+        assert_eq!(robots.groups.len(), 2);
+        assert_eq!(robots.groups[0].rules, [RobotsRule::new(false, "/typo")]);
+        assert_eq!(robots.groups[0].crawl_delay, None);
+        assert!(robots.is_allowed(&url("https://example.com/before-any-agent"), "Navigator"));
+        assert_eq!(parse_robots_txt(b"<html>Not found</html>", &robots_url), RobotsTxt::default());
+    }
+
+    #[test]
+    fn test_parse_sitemaps() {
+        let index_url = url("https://news.example/sitemap.xml");
+        let Sitemap::Index(sitemaps) = parse_sitemap(&sample("news-sitemap-index.xml"), &index_url).unwrap() else {
+            panic!("not read as an index");
+        };
+        assert_eq!(sitemaps.len(), 3);
+        assert_eq!(sitemaps[0].as_str(), "https://news.example/sitemaps/articles-2026-09.xml.gz");
+
+        let articles_url = url("https://news.example/sitemaps/articles-2026-09.xml.gz");
+        let articles = parse_sitemap(&sample("news-articles.xml.gz"), &articles_url).unwrap();
+        assert_eq!(articles, parse_sitemap(&sample("news-articles.xml"), &articles_url).unwrap());
+        let Sitemap::Pages(articles) = articles else {
+            panic!("not read as pages");
+        };
+        assert_eq!(articles.len(), 2, "image locations are not pages");
+        assert_eq!(
+            articles[1].url.as_str(),
+            "https://news.example/2026/09/30/markets-open-higher?ref=sitemap&edition=intl"
+        );
+        assert_eq!(articles[0].last_modified.unwrap().to_rfc3339(), "2026-09-29T18:42:07+00:00");
+        assert_eq!(articles[1].last_modified.unwrap().to_rfc3339(), "2026-09-30T05:03:00+00:00");
+
+        let sections_url = url("https://news.example/sitemaps/sections.xml");
+        let Sitemap::Pages(sections) = parse_sitemap(&sample("news-sections.xml"), &sections_url).unwrap() else {
+            panic!("not read as pages");
+        };
+        assert_eq!(sections[0].change_frequency.as_deref(), Some("hourly"));
+        let priorities: Vec<Option<f32>> = sections.iter().map(|page| page.priority).collect();
+        assert_eq!(priorities, [Some(1.0), Some(0.8), None]);
+    }
+
+    #[test]
+    fn test_sitemaps_with_cdata_bom_and_alternates() {
+        let shop_url = url("https://shop.example/sitemap.xml");
+        let Sitemap::Pages(pages) = parse_sitemap(&sample("shop-sitemap.xml"), &shop_url).unwrap() else {
+            panic!("not read as pages");
+        };
+        let urls: Vec<&str> = pages.iter().map(|page| page.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            ["https://shop.example/en/p/kettle?colour=red&size=1l", "https://shop.example/en/p/toaster"]
+        );
+        assert_eq!(pages[0].last_modified.unwrap().to_rfc3339(), "2026-08-14T00:00:00+00:00");
+        assert_eq!(pages[0].priority, Some(0.6));
+        assert_eq!(pages[1].last_modified, None);
+
+        let docs_url = url("https://docs.example/sitemap.txt");
+        let Sitemap::Pages(pages) = parse_sitemap(&sample("docs-sitemap.txt"), &docs_url).unwrap() else {
+            panic!("not read as pages");
+        };
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[2].url.as_str(), "https://docs.example/guide/configure");
+
+        for invalid in [&b"<html><body>Moved</body></html>"[..], b"", &[0x1f, 0x8b, 0, 0]] {
+            assert!(matches!(
+                parse_sitemap(invalid, &docs_url),
+                Err(SiteMetadataError::InvalidSitemap(_))
+            ));
+        }
+    }
+
+    /// Serve `files` by path until the task is aborted
+    async fn serve(files: Vec<(&'static str, Vec<u8>)>) -> (u16, tokio::task::JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let response = match files.iter().find(|(file, _)| *file == path) {
+                    Some((_, body)) => {
+                        let mut response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                            body.len()
+                        )
+                        .into_bytes();
+                        response.extend_from_slice(body);
+                        response
+                    }
+                    None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec(),
+                };
+                stream.write_all(&response).await.unwrap();
+            }
+        });
+        (port, server)
+    }
+
+    #[tokio::test]
+    async fn test_sitemap_indexes_are_followed_and_cached() {
+        // Relative locations resolve against the index
+        let index = String::from_utf8(sample("news-sitemap-index.xml")).unwrap();
+        let index = index.replace("https://news.example/", "/");
+        let (port, server) = serve(vec![
+            ("/sitemap.xml", index.into_bytes()),
+            ("/sitemaps/articles-2026-09.xml.gz", sample("news-articles.xml.gz")),
+            ("/sitemaps/sections.xml", sample("news-sections.xml")),
+        ])
+        .await;
+        let site = |path: &str| url(&format!("http://127.0.0.1:{}{}", port, path));
+
+        let metadata = SiteMetadata::new(Arc::new(SecureNetworkClient::new().unwrap()));
+        let pages = metadata.fetch_sitemap(&site("/sitemap.xml")).await.unwrap();
+        // The missing sitemap is skipped
+        assert_eq!(pages.len(), 5);
+        assert_eq!(pages[2].url.as_str(), "https://news.example/");
+        assert_eq!(metadata.fetch_robots(&site("/any/page")).await.unwrap(), RobotsTxt::default());
+        assert!(matches!(
+            metadata.fetch_sitemap(&site("/sitemaps/missing.xml")).await,
+            Err(SiteMetadataError::Network(NetworkError::Status(404)))
+        ));
+
+        server.abort();
+        let _ = server.await;
+        assert_eq!(metadata.fetch_sitemap(&site("/sitemap.xml")).await.unwrap(), pages);
+        assert_eq!(metadata.fetch_robots(&site("/")).await.unwrap(), RobotsTxt::default());
+
+        let expired = SiteMetadata::new(Arc::new(SecureNetworkClient::new().unwrap())).with_ttl(Duration::ZERO);
+        assert!(expired.fetch_sitemap(&site("/sitemap.xml")).await.is_err());
+        assert!(matches!(
+            expired.fetch_robots(&url("file:///srv/www/")).await,
+            Err(SiteMetadataError::UnsupportedScheme(scheme)) if scheme == "file"
+        ));
+    }
+}
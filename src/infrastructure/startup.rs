@@ -0,0 +1,135 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+/// One step of starting up, timed from the start of the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StartupPhase {
+    pub name: &'static str,
+    /// When it began, after the start
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+impl StartupPhase {
+    pub fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+}
+
+/// Times the phases of starting up, each in a `startup` tracing span, so
+/// `--startup-profile` can show where the wait before the first page went.
+/// Clones share the phases, as phases run on other threads.
+#[derive(Debug, Clone)]
+pub struct StartupProfile {
+    started: Instant,
+    phases: Arc<Mutex<Vec<StartupPhase>>>,
+}
+
+impl StartupProfile {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            phases: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Run `phase`, recording how long it took
+    pub fn time<T>(&self, name: &'static str, phase: impl FnOnce() -> T) -> T {
+        let began = Instant::now();
+        let result = tracing::info_span!("startup", phase = name).in_scope(phase);
+        self.record(name, began);
+        result
+    }
+
+    /// Await `phase`, recording how long it took to finish
+    pub async fn time_async<T>(&self, name: &'static str, phase: impl Future<Output = T>) -> T {
+        let began = Instant::now();
+        let result = phase.instrument(tracing::info_span!("startup", phase = name)).await;
+        self.record(name, began);
+        result
+    }
+
+    fn record(&self, name: &'static str, began: Instant) {
+        let phase = StartupPhase {
+            name,
+            start: began.saturating_duration_since(self.started),
+            duration: began.elapsed(),
+        };
+        tracing::debug!("Startup phase {} took {:?}", name, phase.duration);
+        if let Ok(mut phases) = self.phases.lock() {
+            phases.push(phase);
+        }
+    }
+
+    /// Phases recorded so far, in the order they began
+    pub fn phases(&self) -> Vec<StartupPhase> {
+        let mut phases = self.phases.lock().map(|phases| phases.clone()).unwrap_or_default();
+        phases.sort_by_key(|phase| phase.start);
+        phases
+    }
+
+    /// The phases as a table of when each began and how long it took, with
+    /// the time until the last one ended; phases that overlap ran at once
+    pub fn report(&self) -> String {
+        let phases = self.phases();
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let mut report = String::from("Startup phases:\n");
+        for phase in &phases {
+            report.push_str(&format!(
+                "  {:<16} at {:>8.1} ms  took {:>8.1} ms\n",
+                phase.name,
+                millis(phase.start),
+                millis(phase.duration)
+            ));
+        }
+        let total = phases.iter().map(StartupPhase::end).max().unwrap_or_default();
+        report.push_str(&format!("  {:<16}    {:>8.1} ms\n", "total", millis(total)));
+        report
+    }
+}
+
+impl Default for StartupProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_phases_are_timed_and_reported_in_order() {
+        let profile = StartupProfile::new();
+        let pause = Duration::from_millis(5);
+        let clone = profile.clone();
+        let database = tokio::spawn(async move {
+            clone
+                .time_async("database", async {
+                    tokio::time::sleep(pause * 2).await;
+                    7
+                })
+                .await
+        });
+        tokio::time::sleep(pause / 5).await;
+        profile.time("window", || std::thread::sleep(pause));
+        assert_eq!(database.await.unwrap(), 7);
+
+        // Clones share the phases, sorted by when they began
+        let phases = profile.phases();
+        let names: Vec<_> = phases.iter().map(|phase| phase.name).collect();
+        assert_eq!(names, ["database", "window"]);
+        assert!(phases.iter().all(|phase| phase.duration >= pause));
+        // The window opened while the database was still opening
+        assert!(phases[1].start < phases[0].end());
+
+        let report = profile.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].trim_start().starts_with("database"));
+        assert!(lines[2].trim_start().starts_with("window"));
+        assert!(lines[3].trim_start().starts_with("total"));
+    }
+}
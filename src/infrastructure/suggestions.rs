@@ -0,0 +1,101 @@
+use super::network::SecureNetworkClient;
+use crate::domain::{NetworkError, ResourceKind, SearchEngine, SuggestionProvider, ValidatedUrl};
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Suggestion responses are a few hundred bytes; anything far larger is not one
+const MAX_SUGGESTIONS_BODY: usize = 64 * 1024;
+
+/// Suggestions in the OpenSearch JSON format, `[query, [suggestion, ...], ...]`;
+/// `None` if the body is not in it
+pub fn parse_opensearch_suggestions(body: &[u8]) -> Option<Vec<String>> {
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let suggestions = value.as_array()?.get(1)?.as_array()?;
+    Some(
+        suggestions
+            .iter()
+            .filter_map(|suggestion| suggestion.as_str())
+            .map(|suggestion| suggestion.trim().to_string())
+            .filter(|suggestion| !suggestion.is_empty())
+            .collect(),
+    )
+}
+
+/// Fetch and parse the suggestions at `url`, an expanded suggest template
+pub async fn fetch_suggestions(
+    network: &SecureNetworkClient,
+    url: &ValidatedUrl,
+) -> Result<Vec<String>, NetworkError> {
+    let response = network
+        .fetch_resource_limited(url, ResourceKind::Subresource, Some(MAX_SUGGESTIONS_BODY))
+        .await?;
+    if response.status >= 400 {
+        return Err(NetworkError::Status(response.status));
+    }
+    parse_opensearch_suggestions(&response.body).ok_or_else(|| {
+        NetworkError::Request(format!("Malformed suggestions from {}", url.host_str().unwrap_or("")))
+    })
+}
+
+/// Asks a search engine's suggest endpoint, through the shared client and
+/// so its interceptors
+pub struct OpenSearchSuggestions {
+    network: Arc<SecureNetworkClient>,
+    engine: SearchEngine,
+}
+
+impl OpenSearchSuggestions {
+    pub fn new(network: Arc<SecureNetworkClient>, engine: SearchEngine) -> Self {
+        Self { network, engine }
+    }
+}
+
+#[async_trait]
+impl SuggestionProvider for OpenSearchSuggestions {
+    async fn suggest(&self, query: &str) -> Result<Vec<String>, NetworkError> {
+        match self.engine.suggest_url(query) {
+            Some(url) => fetch_suggestions(&self.network, &url).await,
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_parse_opensearch_suggestions() {
+        let body = br#"["rus", ["rust", " rust book ", 7, ""], [], []]"#;
+        assert_eq!(parse_opensearch_suggestions(body), Some(vec!["rust".into(), "rust book".into()]));
+        assert_eq!(parse_opensearch_suggestions(br#"{"rust": []}"#), None);
+        assert_eq!(parse_opensearch_suggestions(b"<html>"), None);
+    }
+
+    #[tokio::test]
+    async fn test_suggestions_from_endpoint() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0; 4096];
+            let read = stream.read(&mut buffer).await.unwrap();
+            let body = r#"["ru st",["rust","rust lang"]]"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/x-suggestions+json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buffer[..read]).lines().next().unwrap_or("").to_string()
+        });
+
+        // Templates must be https, so the expanded URL is fetched directly
+        let url = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/suggest?q=ru+st", port)).unwrap();
+        let network = SecureNetworkClient::new().unwrap();
+        assert_eq!(fetch_suggestions(&network, &url).await.unwrap(), vec!["rust", "rust lang"]);
+        assert_eq!(server.await.unwrap(), "GET /suggest?q=ru+st HTTP/1.1");
+    }
+}
@@ -0,0 +1,309 @@
+use crate::domain::RenderError;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Duration;
+
+type Result<T> = std::result::Result<T, RenderError>;
+
+/// Timers a page may have pending at once; a page registering more is
+/// refused rather than left to grow the queue without bound
+pub const MAX_TIMERS_PER_PAGE: usize = 1000;
+/// Shortest delay of a timer set from inside a timer callback, and of any
+/// interval, so pages cannot keep the DOM thread spinning
+pub const MIN_NESTED_DELAY: Duration = Duration::from_millis(4);
+
+/// A timer as `setTimeout` and `setInterval` return it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimerId(u32);
+
+impl TimerId {
+    pub fn get(&self) -> u32 {
+        self.0
+    }
+}
+
+struct PendingTimer<T> {
+    /// Repeat period, for `setInterval`
+    interval: Option<Duration>,
+    /// Taken out while the callback runs
+    callback: Option<T>,
+    /// Registration number of its current queue entry; older entries are stale
+    seq: u64,
+}
+
+/// Pending page timers, ordered by deadline and then by registration.
+///
+/// Time is whatever `advance_to` was last given, so the DOM thread drives it
+/// from a real clock and tests from a fake one. Cleared timers are dropped
+/// from the queue lazily, when they reach its head.
+pub struct TimerScheduler<T> {
+    now: Duration,
+    queue: BinaryHeap<Reverse<(Duration, u64, TimerId)>>,
+    timers: HashMap<TimerId, PendingTimer<T>>,
+    next_id: u32,
+    next_seq: u64,
+    max_timers: usize,
+    /// Shortest delay any timer gets, raised for background tabs
+    floor: Duration,
+    /// A callback is running, so new timers are nested ones
+    firing: bool,
+}
+
+impl<T> TimerScheduler<T> {
+    pub fn new() -> Self {
+        Self {
+            now: Duration::ZERO,
+            queue: BinaryHeap::new(),
+            timers: HashMap::new(),
+            next_id: 1,
+            next_seq: 0,
+            max_timers: MAX_TIMERS_PER_PAGE,
+            floor: Duration::ZERO,
+            firing: false,
+        }
+    }
+
+    /// Refuse timers beyond `max_timers` pending instead of the default cap
+    pub fn with_max_timers(mut self, max_timers: usize) -> Self {
+        self.max_timers = max_timers;
+        self
+    }
+
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    /// Move the clock forward; it never goes back
+    pub fn advance_to(&mut self, now: Duration) {
+        self.now = self.now.max(now);
+    }
+
+    /// Give timers set or repeated from now on at least `floor` of delay, as
+    /// the throttling policy asks of background tabs
+    pub fn set_floor(&mut self, floor: Duration) {
+        self.floor = floor;
+    }
+
+    /// `setTimeout`: run `callback` once, `delay` from now
+    pub fn set_timeout(&mut self, delay: Duration, callback: T) -> Result<TimerId> {
+        self.register(delay, None, callback)
+    }
+
+    /// `setInterval`: run `callback` every `delay` from now
+    pub fn set_interval(&mut self, delay: Duration, callback: T) -> Result<TimerId> {
+        let interval = delay.max(MIN_NESTED_DELAY);
+        self.register(interval, Some(interval), callback)
+    }
+
+    /// `clearTimeout` and `clearInterval`, which share ids; false if the
+    /// timer already ran or was cleared
+    pub fn clear(&mut self, id: TimerId) -> bool {
+        self.timers.remove(&id).is_some()
+    }
+
+    /// Drop every pending timer, as when the page goes away
+    pub fn clear_all(&mut self) {
+        self.timers.clear();
+        self.queue.clear();
+    }
+
+    /// Timers still pending
+    pub fn len(&self) -> usize {
+        self.timers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// When the next pending timer is due
+    pub fn next_deadline(&mut self) -> Option<Duration> {
+        self.drop_stale();
+        self.queue.peek().map(|Reverse((deadline, _, _))| *deadline)
+    }
+
+    /// Run every timer due now with `run`, in deadline order. Callbacks may
+    /// set and clear timers; ones they set run in a later call, even when
+    /// already due. Returns how many ran.
+    pub fn run_due(&mut self, mut run: impl FnMut(&mut Self, &mut T)) -> usize {
+        let horizon = self.horizon();
+        let mut ran = 0;
+        while let Some((id, mut callback)) = self.take_due(horizon) {
+            run(self, &mut callback);
+            self.finish(id, callback);
+            ran += 1;
+        }
+        ran
+    }
+
+    /// Registration number the next timer will get, so timers taken with
+    /// it as the horizon leave out any set after this call
+    pub fn horizon(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Take the next timer due now and registered before `horizon`,
+    /// rescheduling it if it repeats. Its callback goes back with `finish`
+    /// once it has run.
+    pub fn take_due(&mut self, horizon: u64) -> Option<(TimerId, T)> {
+        self.drop_stale();
+        let Reverse((deadline, seq, id)) = *self.queue.peek()?;
+        if deadline > self.now || seq >= horizon {
+            return None;
+        }
+        self.queue.pop();
+        self.firing = true;
+        let interval = self.timers.get(&id)?.interval;
+        let Some(interval) = interval else {
+            return self.timers.remove(&id)?.callback.map(|callback| (id, callback));
+        };
+        // A tab that was paused does not catch up on every missed run
+        let period = interval.max(self.floor);
+        let mut next = deadline + period;
+        if next < self.now {
+            next = self.now + period;
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Reverse((next, seq, id)));
+        let timer = self.timers.get_mut(&id)?;
+        timer.seq = seq;
+        timer.callback.take().map(|callback| (id, callback))
+    }
+
+    /// End the run of a timer taken with `take_due`; an interval keeps its
+    /// callback unless it was cleared meanwhile
+    pub fn finish(&mut self, id: TimerId, callback: T) {
+        self.firing = false;
+        if let Some(timer) = self.timers.get_mut(&id) {
+            timer.callback = Some(callback);
+        }
+    }
+
+    fn register(&mut self, delay: Duration, interval: Option<Duration>, callback: T) -> Result<TimerId> {
+        if self.timers.len() >= self.max_timers {
+            let message = format!("Too many timers, at most {} may be pending", self.max_timers);
+            return Err(RenderError::Script(message));
+        }
+        let mut delay = delay.max(self.floor);
+        if self.firing {
+            delay = delay.max(MIN_NESTED_DELAY);
+        }
+        let id = TimerId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Reverse((self.now + delay, seq, id)));
+        self.timers.insert(
+            id,
+            PendingTimer {
+                interval,
+                callback: Some(callback),
+                seq,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Pop queue entries of cleared timers and of rescheduled ones
+    fn drop_stale(&mut self) {
+        while let Some(Reverse((_, seq, id))) = self.queue.peek() {
+            if self.timers.get(id).is_some_and(|timer| timer.seq == *seq) {
+                break;
+            }
+            self.queue.pop();
+        }
+    }
+}
+
+impl<T> Default for TimerScheduler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    /// Run what is due at `now`, returning the names that ran
+    fn run(timers: &mut TimerScheduler<&'static str>, now: u64) -> Vec<&'static str> {
+        timers.advance_to(ms(now));
+        let mut ran = Vec::new();
+        timers.run_due(|_, name| ran.push(*name));
+        ran
+    }
+
+    #[test]
+    fn test_timers_fire_in_deadline_then_registration_order() {
+        let mut timers = TimerScheduler::new();
+        timers.set_timeout(ms(30), "late").unwrap();
+        timers.set_timeout(ms(10), "first").unwrap();
+        timers.set_timeout(ms(10), "second").unwrap();
+        let cleared = timers.set_timeout(ms(5), "cleared").unwrap();
+        assert!(timers.clear(cleared));
+        assert!(!timers.clear(cleared));
+
+        assert!(run(&mut timers, 9).is_empty());
+        assert_eq!(timers.next_deadline(), Some(ms(10)));
+        assert_eq!(run(&mut timers, 30), vec!["first", "second", "late"]);
+        assert!(timers.is_empty());
+        assert_eq!(timers.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_intervals_repeat_until_cleared() {
+        let mut timers = TimerScheduler::new();
+        let tick = timers.set_interval(ms(10), "tick").unwrap();
+        timers.set_timeout(ms(15), "once").unwrap();
+
+        assert_eq!(run(&mut timers, 10), vec!["tick"]);
+        assert_eq!(run(&mut timers, 20), vec!["once", "tick"]);
+        // A long pause runs a missed interval once, not once per period
+        assert_eq!(run(&mut timers, 100), vec!["tick"]);
+        assert_eq!(timers.next_deadline(), Some(ms(110)));
+        timers.clear(tick);
+        assert!(run(&mut timers, 200).is_empty());
+    }
+
+    #[test]
+    fn test_callbacks_can_set_and_clear_timers() {
+        let mut timers: TimerScheduler<u32> = TimerScheduler::new();
+        timers.set_timeout(ms(0), 0).unwrap();
+        let victim = timers.set_timeout(ms(0), 1).unwrap();
+        timers.set_timeout(ms(0), 2).unwrap();
+
+        let mut ran = Vec::new();
+        timers.run_due(|timers, value| {
+            ran.push(*value);
+            if *value == 0 {
+                timers.clear(victim);
+                // Nested timers are not run in the same pass, even at delay 0
+                timers.set_timeout(ms(0), 3).unwrap();
+            }
+        });
+        assert_eq!(ran, vec![0, 2]);
+        assert_eq!(timers.next_deadline(), Some(MIN_NESTED_DELAY));
+    }
+
+    #[test]
+    fn test_floor_throttles_and_cap_refuses_runaway_pages() {
+        let mut timers = TimerScheduler::new().with_max_timers(3);
+        timers.set_floor(ms(1000));
+        timers.set_timeout(ms(10), "slow").unwrap();
+        assert!(run(&mut timers, 999).is_empty());
+        assert_eq!(run(&mut timers, 1000), vec!["slow"]);
+
+        for _ in 0..3 {
+            timers.set_timeout(ms(1), "many").unwrap();
+        }
+        assert!(matches!(timers.set_timeout(ms(1), "refused"), Err(RenderError::Script(_))));
+        timers.clear_all();
+        assert!(timers.is_empty());
+        assert!(timers.set_interval(ms(1), "again").is_ok());
+    }
+}
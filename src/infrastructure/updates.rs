@@ -0,0 +1,154 @@
+use super::network::SecureNetworkClient;
+use crate::domain::{
+    NetworkError, ReleaseFile, ResourceKind, UpdateError, UpdateManifest, UpdateSource, ValidatedUrl,
+};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Manifests are a few kilobytes of release notes; anything far larger is
+/// not one
+pub const MAX_MANIFEST_BODY: usize = 256 * 1024;
+
+#[derive(Deserialize)]
+struct ManifestDocument {
+    version: String,
+    #[serde(default)]
+    notes: String,
+    download: String,
+}
+
+/// Read an update manifest: a JSON object with the release's `version`,
+/// its `notes` as plain text and the URL to `download` it from, which may
+/// be relative to `manifest_url`
+pub fn parse_update_manifest(body: &[u8], manifest_url: &ValidatedUrl) -> Result<UpdateManifest, UpdateError> {
+    let invalid = |reason: String| UpdateError::InvalidManifest(reason);
+    let document: ManifestDocument = serde_json::from_slice(body).map_err(|e| invalid(e.to_string()))?;
+    if document.version.trim().is_empty() {
+        return Err(invalid("no version".to_string()));
+    }
+    let download = manifest_url
+        .join(document.download.trim())
+        .map_err(|e| invalid(format!("download \"{}\": {}", document.download, e)))?;
+    if !matches!(download.scheme(), "http" | "https") {
+        return Err(invalid(format!("download \"{}\" is not a web address", download)));
+    }
+    Ok(UpdateManifest {
+        version: document.version.trim().to_string(),
+        notes: document.notes,
+        download,
+    })
+}
+
+/// Fetches update manifests and releases through the shared client, so
+/// they get its TLS settings and respect offline mode
+pub struct UpdateManifests {
+    network: Arc<SecureNetworkClient>,
+}
+
+impl UpdateManifests {
+    pub fn new(network: Arc<SecureNetworkClient>) -> Self {
+        Self { network }
+    }
+}
+
+#[async_trait]
+impl UpdateSource for UpdateManifests {
+    async fn manifest(&self, url: &ValidatedUrl) -> Result<UpdateManifest, UpdateError> {
+        let response = self
+            .network
+            .fetch_resource_limited(url, ResourceKind::Subresource, Some(MAX_MANIFEST_BODY))
+            .await?;
+        if response.status >= 400 {
+            return Err(NetworkError::Status(response.status).into());
+        }
+        parse_update_manifest(&response.body, &response.url)
+    }
+
+    async fn download(&self, url: &ValidatedUrl) -> Result<ReleaseFile, UpdateError> {
+        let response = self.network.fetch_resource(url, ResourceKind::Document).await?;
+        if response.status >= 400 {
+            return Err(NetworkError::Status(response.status).into());
+        }
+        Ok(ReleaseFile {
+            url: response.url,
+            content_type: response.content_type,
+            contents: response.body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::newer_release;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Serve `body` as JSON to one request, returning the manifest's URL
+    async fn serve_manifest(body: &'static str) -> (ValidatedUrl, tokio::task::JoinHandle<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0; 4096];
+            let _ = stream.read(&mut buffer).await.unwrap();
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).await.unwrap();
+            stream.write_all(body.as_bytes()).await.unwrap();
+        });
+        let url = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/releases/latest.json", port)).unwrap();
+        (url, server)
+    }
+
+    async fn check(body: &'static str) -> Result<Option<UpdateManifest>, UpdateError> {
+        let (url, server) = serve_manifest(body).await;
+        let source = UpdateManifests::new(Arc::new(SecureNetworkClient::new().unwrap()));
+        let manifest = source.manifest(&url).await;
+        server.await.unwrap();
+        newer_release(manifest?, "1.4.0")
+    }
+
+    #[tokio::test]
+    async fn test_manifests_are_compared_with_the_running_version() {
+        let older = r#"{"version": "1.3.9", "notes": "Fixes", "download": "navigator-1.3.9.zip"}"#;
+        assert_eq!(check(older).await, Ok(None));
+        let equal = r#"{"version": "1.4.0", "notes": "Fixes", "download": "navigator-1.4.0.zip"}"#;
+        assert_eq!(check(equal).await, Ok(None));
+
+        let newer = r#"{"version": "1.10.0", "notes": "Tab groups\nFaster startup", "download": "1.10/nav.zip"}"#;
+        let update = check(newer).await.unwrap().unwrap();
+        assert_eq!(update.version, "1.10.0");
+        assert_eq!(update.notes, "Tab groups\nFaster startup");
+        assert!(update.download.as_str().ends_with("/releases/1.10/nav.zip"));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_manifests_are_rejected() {
+        for body in [
+            "<html>Not found</html>",
+            r#"{"version": "1.5.0"}"#,
+            r#"{"version": "soon", "download": "nav.zip"}"#,
+            r#"{"version": "1.5.0", "download": "file:///etc/passwd"}"#,
+        ] {
+            assert!(
+                matches!(check(body).await, Err(UpdateError::InvalidManifest(_))),
+                "{} was accepted",
+                body
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_manifest_is_a_network_error() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let url = ValidatedUrl::parse(&format!("http://127.0.0.1:{}/latest.json", port)).unwrap();
+        let source = UpdateManifests::new(Arc::new(SecureNetworkClient::new().unwrap()));
+        assert!(matches!(source.manifest(&url).await, Err(UpdateError::Network(_))));
+    }
+}
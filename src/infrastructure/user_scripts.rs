@@ -0,0 +1,208 @@
+use crate::domain::{ScriptOutcome, SecurityLevel, UserScript, UserScriptService, ValidatedUrl};
+use serde::Deserialize;
+use std::sync::RwLock;
+
+/// Runs a script's code with the GM API in scope, catching anything it
+/// throws, and returns what it logged and stored as JSON. The code is
+/// compiled with `new Function` so syntax errors are caught too.
+const GM_WRAPPER: &str = r#"(function () {
+  var values = __VALUES__;
+  var log = [];
+  var changed = {};
+  function GM_log(message) { log.push(String(message)); }
+  function GM_getValue(key, fallback) {
+    return Object.prototype.hasOwnProperty.call(values, key) ? values[key] : fallback;
+  }
+  function GM_setValue(key, value) { values[key] = value; changed[key] = value; }
+  var error = null;
+  try {
+    new Function("GM_log", "GM_getValue", "GM_setValue", __CODE__)(GM_log, GM_getValue, GM_setValue);
+  } catch (e) {
+    error = String(e);
+  }
+  return JSON.stringify({ log: log, values: changed, error: error });
+})()"#;
+
+/// What [`GM_WRAPPER`] returns
+#[derive(Deserialize)]
+struct WrapperResult {
+    #[serde(default)]
+    log: Vec<String>,
+    #[serde(default)]
+    values: serde_json::Map<String, serde_json::Value>,
+    error: Option<String>,
+}
+
+/// Holds the user's scripts in memory and wraps them in the GM API
+pub struct DefaultUserScriptService {
+    scripts: RwLock<Vec<UserScript>>,
+}
+
+impl DefaultUserScriptService {
+    pub fn new() -> Self {
+        Self {
+            scripts: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl Default for DefaultUserScriptService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UserScriptService for DefaultUserScriptService {
+    fn set_scripts(&self, scripts: Vec<UserScript>) {
+        if let Ok(mut current) = self.scripts.write() {
+            *current = scripts;
+        }
+    }
+
+    fn scripts_for(&self, url: &ValidatedUrl, private: bool) -> Vec<UserScript> {
+        let Ok(scripts) = self.scripts.read() else {
+            return Vec::new();
+        };
+        let internal = url.security_level() == SecurityLevel::Internal;
+        let mut matching: Vec<UserScript> = scripts
+            .iter()
+            .filter(|script| script.enabled && script.matches(url))
+            .filter(|script| !internal || script.allow_internal)
+            .filter(|script| !private || script.allow_private)
+            .cloned()
+            .collect();
+        // Stable, so scripts with the same timing run in the order they were added
+        matching.sort_by_key(|script| script.run_at);
+        matching
+    }
+
+    fn prepare(&self, script: &UserScript, values: &[(String, String)]) -> String {
+        let values: serde_json::Map<String, serde_json::Value> = values
+            .iter()
+            .map(|(key, value)| {
+                let value = serde_json::from_str(value).unwrap_or_else(|_| value.clone().into());
+                (key.clone(), value)
+            })
+            .collect();
+        let values = serde_json::Value::Object(values).to_string();
+        let code = serde_json::Value::String(script.code.clone()).to_string();
+        GM_WRAPPER.replace("__VALUES__", &values).replace("__CODE__", &code)
+    }
+
+    fn outcome(&self, output: &str) -> ScriptOutcome {
+        // Engines without JavaScript return nothing
+        if output.trim().is_empty() {
+            return ScriptOutcome::default();
+        }
+        // Some engines return the string value JSON-quoted
+        let output = serde_json::from_str::<String>(output).unwrap_or_else(|_| output.to_string());
+        match serde_json::from_str::<WrapperResult>(&output) {
+            Ok(result) => ScriptOutcome {
+                log: result.log,
+                values: result
+                    .values
+                    .into_iter()
+                    .map(|(key, value)| (key, value.to_string()))
+                    .collect(),
+                error: result.error,
+            },
+            Err(e) => ScriptOutcome {
+                error: Some(format!("Unexpected script result: {}", e)),
+                ..ScriptOutcome::default()
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{MatchPattern, RunAt};
+
+    fn url(input: &str) -> ValidatedUrl {
+        ValidatedUrl::parse(input).unwrap()
+    }
+
+    fn matches(pattern: &str, input: &str) -> bool {
+        MatchPattern::parse(pattern).unwrap().matches(&url(input))
+    }
+
+    fn script(id: i64, pattern: &str) -> UserScript {
+        UserScript {
+            id,
+            name: format!("script {}", id),
+            matches: vec![MatchPattern::parse(pattern).unwrap()],
+            code: "GM_log('hi')".to_string(),
+            enabled: true,
+            run_at: RunAt::DocumentEnd,
+            allow_internal: false,
+            allow_private: false,
+            last_error: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_match_patterns() {
+        assert!(matches("*://*.example.com/*", "https://example.com/"));
+        assert!(matches("*://*.example.com/*", "http://news.Example.com/a/b?q=1"));
+        assert!(!matches("*://*.example.com/*", "https://badexample.com/"));
+        assert!(!matches("*://*.example.com/*", "ftp://example.com/"));
+        assert!(!matches("*://*.example.com/*", "file:///example.com/"));
+
+        assert!(matches("https://example.com/docs/*", "https://example.com/docs/"));
+        assert!(!matches("https://example.com/docs/*", "http://example.com/docs/a"));
+        assert!(!matches("https://example.com/docs/*", "https://example.com/doc"));
+        assert!(matches("*://*/*.html", "https://any.host/a/b.html"));
+        assert!(!matches("*://*/*.html", "https://any.host/a/b.html5"));
+        assert!(matches("*://example.com/*q=*", "https://example.com/search?q=rust"));
+        assert!(matches("file:///home/*", "file:///home/user/page.html"));
+        assert!(matches(MatchPattern::ALL_URLS, "file:///tmp/x"));
+        assert!(!matches(MatchPattern::ALL_URLS, "about:blank"));
+
+        for invalid in ["example.com/*", "*://example.com", "ftp://x/*", "*://ex*ample.com/*", "*://*.*/*", "file://host/*"] {
+            assert_eq!(MatchPattern::parse(invalid), None, "{}", invalid);
+        }
+        assert_eq!(MatchPattern::parse("HTTPS://Example.COM/A").unwrap().as_str(), "https://example.com/A");
+    }
+
+    #[test]
+    fn test_scripts_skip_private_and_internal_pages() {
+        let service = DefaultUserScriptService::new();
+        let mut idle = script(1, "*://*/*");
+        idle.run_at = RunAt::DocumentIdle;
+        let mut disabled = script(3, "*://*/*");
+        disabled.enabled = false;
+        let mut private = script(4, "*://*/*");
+        private.allow_private = true;
+        let mut local = script(5, "file:///*");
+        local.allow_internal = true;
+        service.set_scripts(vec![idle, script(2, "*://*/*"), disabled, private, script(6, "file:///*"), local]);
+
+        let ids = |input: &str, private: bool| -> Vec<i64> {
+            service.scripts_for(&url(input), private).iter().map(|s| s.id).collect()
+        };
+        assert_eq!(ids("https://example.com/", false), vec![2, 4, 1]);
+        assert_eq!(ids("https://example.com/", true), vec![4]);
+        assert_eq!(ids("file:///tmp/page.html", false), vec![5]);
+    }
+
+    #[test]
+    fn test_outcome_reads_wrapper_result() {
+        let service = DefaultUserScriptService::new();
+        let prepared = service.prepare(&script(1, "*://*/*"), &[("count".to_string(), "2".to_string())]);
+        assert!(prepared.contains(r#"var values = {"count":2};"#));
+        assert!(prepared.contains(r#""GM_log('hi')")(GM_log"#));
+
+        let outcome = service.outcome(r#"{"log":["hi"],"values":{"count":3,"name":"x"},"error":null}"#);
+        assert_eq!(outcome.log, vec!["hi"]);
+        assert_eq!(
+            outcome.values,
+            vec![("count".to_string(), "3".to_string()), ("name".to_string(), "\"x\"".to_string())]
+        );
+        assert_eq!(outcome.error, None);
+
+        assert_eq!(service.outcome(""), ScriptOutcome::default());
+        assert!(service.outcome("undefined").error.is_some());
+    }
+}
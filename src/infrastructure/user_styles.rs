@@ -0,0 +1,82 @@
+use super::css::UserStylesheet;
+use crate::domain::{UserStyle, UserStyleError, UserStyleRule, UserStyleService};
+use std::sync::RwLock;
+
+/// Holds the user's style rules in memory for the layout pass
+pub struct DefaultUserStyleService {
+    styles: RwLock<Vec<UserStyle>>,
+}
+
+impl DefaultUserStyleService {
+    pub fn new() -> Self {
+        Self {
+            styles: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl Default for DefaultUserStyleService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UserStyleService for DefaultUserStyleService {
+    fn set_styles(&self, styles: Vec<UserStyle>) {
+        if let Ok(mut current) = self.styles.write() {
+            *current = styles;
+        }
+    }
+
+    fn rules_for(&self, host: Option<&str>) -> Vec<UserStyleRule> {
+        let Ok(styles) = self.styles.read() else {
+            return Vec::new();
+        };
+        let mut matching: Vec<(usize, &UserStyle)> = styles
+            .iter()
+            .filter_map(|style| Some((style.host_pattern.specificity(host)?, style)))
+            .collect();
+        // Stable, so rules of equal specificity keep the order they were added
+        matching.sort_by_key(|(specificity, _)| *specificity);
+        matching
+            .into_iter()
+            .map(|(_, style)| style.rule.clone())
+            .collect()
+    }
+
+    fn validate(&self, rule: &UserStyleRule) -> Result<(), UserStyleError> {
+        UserStylesheet::validate(rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::HostPattern;
+
+    fn style(id: i64, pattern: &str, px: f32) -> UserStyle {
+        UserStyle {
+            id,
+            host_pattern: HostPattern::parse(pattern).unwrap(),
+            rule: UserStyleRule::MinFontSize { px },
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_more_specific_patterns_win() {
+        let service = DefaultUserStyleService::new();
+        service.set_styles(vec![
+            style(1, "news.example.com", 18.0),
+            style(2, "*.example.com", 14.0),
+            style(3, "*", 12.0),
+            style(4, "other.org", 30.0),
+        ]);
+
+        let floor = |host| UserStylesheet::from_rules(&service.rules_for(host)).min_font_size;
+        assert_eq!(floor(Some("news.example.com")), Some(18.0));
+        assert_eq!(floor(Some("www.example.com")), Some(14.0));
+        assert_eq!(floor(None), Some(12.0));
+        assert_eq!(service.rules_for(Some("example.com")).len(), 2);
+    }
+}
@@ -0,0 +1,570 @@
+use super::network::tls_handshake_error;
+use crate::domain::{NetworkError, ValidatedUrl, WebSocketConnection, WebSocketMessage};
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::error::{CapacityError, Error as WsError, ProtocolError};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+type Result<T> = std::result::Result<T, NetworkError>;
+
+/// How often a connection is pinged to keep it open; one that has not
+/// answered the last ping by the next is dropped
+pub const WEBSOCKET_KEEPALIVE: Duration = Duration::from_secs(30);
+/// Largest message accepted, across all its fragments
+pub const MAX_WEBSOCKET_MESSAGE: usize = 16 * 1024 * 1024;
+/// Time the server gets to answer a close before the connection is dropped
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Received messages waiting for `receive`; the server is not read further
+/// while it is full
+const RECEIVE_QUEUE: usize = 256;
+
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_GOING_AWAY: u16 = 1001;
+const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+/// The close frame carried no code
+const CLOSE_NO_STATUS: u16 = 1005;
+const CLOSE_INVALID_DATA: u16 = 1007;
+const CLOSE_TOO_BIG: u16 = 1009;
+
+/// Whether connecting to `ip` would reach the local machine or a private
+/// network rather than the internet
+fn is_private_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified(),
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local and link-local ranges
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                || ip.to_ipv4_mapped().is_some_and(|ip| is_private_address(ip.into()))
+        }
+    }
+}
+
+/// Whether `url` is served from the local machine or a private network,
+/// judged from its host alone
+pub(super) fn is_private_host(url: &ValidatedUrl) -> bool {
+    let Some(host) = url.host_str() else {
+        return true;
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.eq_ignore_ascii_case("localhost") || host.to_ascii_lowercase().ends_with(".localhost") {
+        return true;
+    }
+    host.parse().is_ok_and(is_private_address)
+}
+
+trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+type Socket = WebSocketStream<Box<dyn Transport>>;
+
+/// How a WebSocket is opened, as the network client decided
+pub(super) struct Opening<'a> {
+    /// Origin of the page opening it
+    pub origin: Option<&'a str>,
+    pub user_agent: &'a str,
+    /// Whether the page is on the internet, and so may not reach hosts on
+    /// a private network
+    pub from_public_page: bool,
+    pub keepalive: Duration,
+    /// What `wss:` connections check the server's certificate with
+    pub tls: Arc<rustls::ClientConfig>,
+}
+
+/// Open a WebSocket to `url`, an already checked `ws:` or `wss:` URL
+pub(super) async fn connect(url: &ValidatedUrl, opening: Opening<'_>) -> Result<WebSocketHandle> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| NetworkError::Request(format!("{} has no host", url)))?;
+    let bare_host = host.trim_start_matches('[').trim_end_matches(']');
+    let secure = url.scheme() == "wss";
+    let port = url.port_or_default().unwrap_or(if secure { 443 } else { 80 });
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((bare_host, port))
+        .await
+        .map_err(|_| NetworkError::Dns(host.to_string()))?
+        .collect();
+    if addresses.is_empty() {
+        return Err(NetworkError::Dns(host.to_string()));
+    }
+    if opening.from_public_page && addresses.iter().any(|address| is_private_address(address.ip())) {
+        return Err(NetworkError::PrivateNetwork(host.to_string()));
+    }
+
+    let mut request = url
+        .as_str()
+        .into_client_request()
+        .map_err(|e| NetworkError::Request(e.to_string()))?;
+    let header = |value: &str| HeaderValue::from_str(value).map_err(|e| NetworkError::Request(e.to_string()));
+    request.headers_mut().insert("User-Agent", header(opening.user_agent)?);
+    if let Some(origin) = opening.origin {
+        request.headers_mut().insert("Origin", header(origin)?);
+    }
+    let config = WebSocketConfig {
+        max_message_size: Some(MAX_WEBSOCKET_MESSAGE),
+        max_frame_size: Some(MAX_WEBSOCKET_MESSAGE),
+        ..WebSocketConfig::default()
+    };
+
+    // The addresses were checked above, so the connection goes to one of
+    // them rather than to whatever the host resolves to next
+    let connecting = async {
+        let tcp = tokio::net::TcpStream::connect(&addresses[..]).await.map_err(io_error)?;
+        tcp.set_nodelay(true).ok();
+        let transport: Box<dyn Transport> = if secure {
+            let name = rustls::pki_types::ServerName::try_from(bare_host.to_string())
+                .map_err(|e| NetworkError::Tls(e.to_string()))?;
+            let tls = tokio_rustls::TlsConnector::from(opening.tls.clone())
+                .connect(name, tcp)
+                .await
+                .map_err(|e| tls_handshake_error(&e, bare_host))?;
+            Box::new(tls)
+        } else {
+            Box::new(tcp)
+        };
+        let (socket, _) = tokio_tungstenite::client_async_with_config(request, transport, Some(config))
+            .await
+            .map_err(handshake_error)?;
+        Ok::<_, NetworkError>(socket)
+    };
+    let socket = tokio::time::timeout(HANDSHAKE_TIMEOUT, connecting)
+        .await
+        .map_err(|_| NetworkError::Timeout)??;
+    tracing::info!("WebSocket {} opened", url);
+    Ok(WebSocketHandle::start(url.clone(), socket, opening.keepalive))
+}
+
+fn io_error(error: std::io::Error) -> NetworkError {
+    NetworkError::Connection(error.to_string())
+}
+
+/// Why the server would not open a WebSocket
+fn handshake_error(error: WsError) -> NetworkError {
+    match error {
+        WsError::Http(response) => NetworkError::Status(response.status().as_u16()),
+        WsError::Io(e) => io_error(e),
+        e => NetworkError::WebSocket(e.to_string()),
+    }
+}
+
+/// The close code a connection that broke the protocol with `error` is
+/// closed with, or none if it is beyond saying anything
+fn failure_code(error: &WsError) -> Option<u16> {
+    match error {
+        WsError::Capacity(CapacityError::MessageTooLong { .. }) => Some(CLOSE_TOO_BIG),
+        WsError::Utf8 => Some(CLOSE_INVALID_DATA),
+        WsError::Protocol(ProtocolError::ResetWithoutClosingHandshake) => None,
+        WsError::Protocol(_) | WsError::Capacity(_) => Some(CLOSE_PROTOCOL_ERROR),
+        _ => None,
+    }
+}
+
+fn close_frame(code: u16, reason: &str) -> Option<CloseFrame<'static>> {
+    (code != CLOSE_NO_STATUS).then(|| CloseFrame {
+        code: CloseCode::from(code),
+        reason: reason.to_string().into(),
+    })
+}
+
+enum Command {
+    Send(WebSocketMessage, oneshot::Sender<Result<()>>),
+    /// Start closing; the sender, if any, hears when the connection is gone
+    Close(u16, String, Option<oneshot::Sender<()>>),
+}
+
+#[derive(Debug, Default)]
+struct MessageCounts {
+    sent: AtomicUsize,
+    received: AtomicUsize,
+}
+
+/// An open WebSocket, run by a task of its own that sends keepalive pings
+/// and reads ahead into a queue; the server's pings are answered as they
+/// are read. Dropping it closes the connection as going away.
+pub struct WebSocketHandle {
+    url: ValidatedUrl,
+    commands: mpsc::UnboundedSender<Command>,
+    messages: tokio::sync::Mutex<mpsc::Receiver<WebSocketMessage>>,
+    counts: Arc<MessageCounts>,
+    /// Code and reason the server closed with, once it has
+    server_close: Arc<Mutex<Option<(u16, String)>>>,
+}
+
+impl WebSocketHandle {
+    fn start(url: ValidatedUrl, socket: Socket, keepalive: Duration) -> Self {
+        let (commands, command_queue) = mpsc::unbounded_channel();
+        let (message_sender, messages) = mpsc::channel(RECEIVE_QUEUE);
+        let counts = Arc::new(MessageCounts::default());
+        let server_close = Arc::new(Mutex::new(None));
+        let connection = Connection {
+            url: url.clone(),
+            socket,
+            counts: counts.clone(),
+            server_close: server_close.clone(),
+        };
+        tokio::spawn(connection.run(command_queue, message_sender, keepalive));
+        Self {
+            url,
+            commands,
+            messages: tokio::sync::Mutex::new(messages),
+            counts,
+            server_close,
+        }
+    }
+
+    pub fn url(&self) -> &ValidatedUrl {
+        &self.url
+    }
+
+    /// Text and binary messages sent so far
+    pub fn messages_sent(&self) -> usize {
+        self.counts.sent.load(Ordering::Relaxed)
+    }
+
+    /// Text and binary messages received so far
+    pub fn messages_received(&self) -> usize {
+        self.counts.received.load(Ordering::Relaxed)
+    }
+
+    /// Code and reason the server closed the connection with, if it has
+    pub fn server_close(&self) -> Option<(u16, String)> {
+        self.server_close.lock().ok().and_then(|close| close.clone())
+    }
+
+    /// Start closing with "going away", as when the page that opened the
+    /// connection goes away, without waiting for the server
+    pub fn shut_down(&self) {
+        let _ = self.commands.send(Command::Close(CLOSE_GOING_AWAY, String::new(), None));
+    }
+}
+
+impl Drop for WebSocketHandle {
+    fn drop(&mut self) {
+        self.shut_down();
+    }
+}
+
+fn connection_closed() -> NetworkError {
+    NetworkError::WebSocket("The connection is closed".to_string())
+}
+
+#[async_trait]
+impl WebSocketConnection for WebSocketHandle {
+    async fn send(&self, message: WebSocketMessage) -> Result<()> {
+        let (reply, result) = oneshot::channel();
+        self.commands
+            .send(Command::Send(message, reply))
+            .map_err(|_| connection_closed())?;
+        result.await.map_err(|_| connection_closed())?
+    }
+
+    async fn receive(&self) -> Option<WebSocketMessage> {
+        self.messages.lock().await.recv().await
+    }
+
+    /// Codes are 1000 or in 3000–4999, and reasons at most 123 bytes, as
+    /// for `WebSocket.close` in pages
+    async fn close(&self, code: u16, reason: &str) -> Result<()> {
+        if code != CLOSE_NORMAL && !(3000..=4999).contains(&code) {
+            return Err(NetworkError::WebSocket(format!("Close code {} is not allowed", code)));
+        }
+        if reason.len() > 123 {
+            return Err(NetworkError::WebSocket("The close reason is longer than 123 bytes".to_string()));
+        }
+        let (reply, closed) = oneshot::channel();
+        // A connection that is already gone is closed as asked
+        if self.commands.send(Command::Close(code, reason.to_string(), Some(reply))).is_ok() {
+            let _ = closed.await;
+        }
+        Ok(())
+    }
+}
+
+/// A connection as owned by its task
+struct Connection {
+    url: ValidatedUrl,
+    socket: Socket,
+    counts: Arc<MessageCounts>,
+    server_close: Arc<Mutex<Option<(u16, String)>>>,
+}
+
+impl Connection {
+    async fn write(&mut self, message: Message) -> Result<()> {
+        self.socket
+            .send(message)
+            .await
+            .map_err(|e| NetworkError::WebSocket(e.to_string()))
+    }
+
+    async fn run(
+        mut self,
+        mut commands: mpsc::UnboundedReceiver<Command>,
+        messages: mpsc::Sender<WebSocketMessage>,
+        keepalive: Duration,
+    ) {
+        let mut pings = tokio::time::interval_at(Instant::now() + keepalive, keepalive);
+        let mut awaiting_pong = false;
+        // When closing gives up waiting for the server, and who to tell once closed
+        let mut closing: Option<(Instant, Option<oneshot::Sender<()>>)> = None;
+        let outcome = loop {
+            let deadline = closing.as_ref().map(|(deadline, _)| *deadline);
+            tokio::select! {
+                received = self.socket.next() => match received {
+                    Some(Ok(Message::Text(text))) if closing.is_none() => {
+                        queue(&self.counts, &messages, WebSocketMessage::Text(text)).await;
+                    }
+                    Some(Ok(Message::Binary(data))) if closing.is_none() => {
+                        queue(&self.counts, &messages, WebSocketMessage::Binary(data)).await;
+                    }
+                    Some(Ok(Message::Pong(_))) => awaiting_pong = false,
+                    Some(Ok(Message::Close(frame))) => {
+                        let (code, reason) = frame.map_or((CLOSE_NO_STATUS, String::new()), |frame| {
+                            (u16::from(frame.code), frame.reason.into_owned())
+                        });
+                        if let Ok(mut server_close) = self.server_close.lock() {
+                            *server_close = Some((code, reason));
+                        }
+                        // Sends the close the server's is answered with
+                        let _ = self.socket.flush().await;
+                        break format!("code {}", code);
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        tracing::warn!("WebSocket {} failed: {}", self.url, e);
+                        if let Some(code) = failure_code(&e) {
+                            let _ = self.write(Message::Close(close_frame(code, ""))).await;
+                        }
+                        break e.to_string();
+                    }
+                    None => break "connection lost".to_string(),
+                },
+                command = commands.recv(), if closing.is_none() => match command {
+                    Some(Command::Send(message, reply)) => {
+                        let message = match message {
+                            WebSocketMessage::Text(text) => Message::Text(text),
+                            WebSocketMessage::Binary(data) => Message::Binary(data),
+                        };
+                        let written = self.write(message).await;
+                        if written.is_ok() {
+                            self.counts.sent.fetch_add(1, Ordering::Relaxed);
+                        }
+                        let _ = reply.send(written);
+                    }
+                    Some(Command::Close(code, reason, reply)) => {
+                        let _ = self.write(Message::Close(close_frame(code, &reason))).await;
+                        closing = Some((Instant::now() + CLOSE_TIMEOUT, reply));
+                    }
+                    None => break "handle dropped".to_string(),
+                },
+                _ = pings.tick(), if closing.is_none() => {
+                    if awaiting_pong {
+                        break "no answer to the last ping".to_string();
+                    }
+                    if let Err(e) = self.write(Message::Ping(Vec::new())).await {
+                        break e.to_string();
+                    }
+                    awaiting_pong = true;
+                }
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    break "the server did not answer the close".to_string();
+                }
+            }
+        };
+        let _ = self.socket.get_mut().shutdown().await;
+        tracing::info!(
+            "WebSocket {} closed ({}) after {} messages sent and {} received",
+            self.url,
+            outcome,
+            self.counts.sent.load(Ordering::Relaxed),
+            self.counts.received.load(Ordering::Relaxed)
+        );
+        if let Some((_, Some(reply))) = closing {
+            let _ = reply.send(());
+        }
+    }
+}
+
+/// Queue a message from the server for `receive`
+async fn queue(counts: &MessageCounts, messages: &mpsc::Sender<WebSocketMessage>, message: WebSocketMessage) {
+    counts.received.fetch_add(1, Ordering::Relaxed);
+    if messages.send(message).await.is_err() {
+        tracing::debug!("Dropping a WebSocket message nobody will read");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::{ContentBlocker, SecureNetworkClient};
+    use tokio::task::JoinHandle;
+
+    /// What the echo server saw of a connection
+    #[derive(Debug, Default)]
+    struct Seen {
+        pings: usize,
+        origin: Option<String>,
+        /// Code it was sent on closing
+        close: Option<u16>,
+    }
+
+    /// Accepts one connection and echoes its messages. "ping me" is
+    /// answered with a ping, and the pong to it with "pong <data>".
+    async fn echo_server() -> (u16, JoinHandle<Seen>) {
+        use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut seen = Seen::default();
+            let mut origin = None;
+            // The error type is tungstenite's to choose
+            #[allow(clippy::result_large_err)]
+            let record = |request: &Request, response: Response| {
+                origin = request
+                    .headers()
+                    .get("origin")
+                    .map(|origin| origin.to_str().unwrap().to_string());
+                Ok(response)
+            };
+            let mut socket = tokio_tungstenite::accept_hdr_async(stream, record).await.unwrap();
+            seen.origin = origin;
+            while let Some(Ok(message)) = socket.next().await {
+                let reply = match message {
+                    Message::Text(text) if text == "ping me" => Message::Ping(b"hello".to_vec()),
+                    Message::Pong(data) => Message::Text(format!("pong {}", String::from_utf8_lossy(&data))),
+                    Message::Ping(_) => {
+                        seen.pings += 1;
+                        continue;
+                    }
+                    Message::Close(frame) => {
+                        seen.close = frame.map(|frame| u16::from(frame.code));
+                        continue;
+                    }
+                    message => message,
+                };
+                socket.send(reply).await.unwrap();
+            }
+            seen
+        });
+        (port, server)
+    }
+
+    fn page(port: u16) -> ValidatedUrl {
+        ValidatedUrl::parse(&format!("http://127.0.0.1:{}/", port)).unwrap()
+    }
+
+    fn socket(port: u16) -> ValidatedUrl {
+        ValidatedUrl::parse(&format!("ws://127.0.0.1:{}/echo?room=1", port)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_websocket_echoes_pings_and_closes() {
+        let (port, server) = echo_server().await;
+        let client = SecureNetworkClient::new()
+            .unwrap()
+            .with_websocket_keepalive(Duration::from_millis(50));
+        let socket = client.websocket(&socket(port), Some(&page(port)), None).await.unwrap();
+
+        socket.send(WebSocketMessage::Text("hello".to_string())).await.unwrap();
+        assert_eq!(socket.receive().await, Some(WebSocketMessage::Text("hello".to_string())));
+        socket.send(WebSocketMessage::Binary(vec![0, 1, 2])).await.unwrap();
+        assert_eq!(socket.receive().await, Some(WebSocketMessage::Binary(vec![0, 1, 2])));
+        // The server's ping is answered without the caller's help
+        socket.send(WebSocketMessage::Text("ping me".to_string())).await.unwrap();
+        assert_eq!(socket.receive().await, Some(WebSocketMessage::Text("pong hello".to_string())));
+
+        tokio::time::sleep(Duration::from_millis(120)).await;
+        socket.close(CLOSE_NORMAL, "done").await.unwrap();
+        assert_eq!(socket.receive().await, None);
+        assert_eq!(socket.server_close(), Some((CLOSE_NORMAL, "done".to_string())));
+        assert!(socket.send(WebSocketMessage::Text("late".to_string())).await.is_err());
+
+        let seen = server.await.unwrap();
+        assert_eq!(seen.close, Some(CLOSE_NORMAL));
+        assert_eq!(seen.origin.as_deref(), Some(format!("http://127.0.0.1:{}", port).as_str()));
+        assert!(seen.pings > 0, "no keepalive ping");
+        assert_eq!(socket.messages_sent(), 3);
+        assert_eq!(socket.messages_received(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_shut_down_goes_away() {
+        let (port, server) = echo_server().await;
+        let client = SecureNetworkClient::new().unwrap();
+        let socket = client.websocket(&socket(port), Some(&page(port)), None).await.unwrap();
+        socket.shut_down();
+        assert_eq!(socket.receive().await, None);
+        assert_eq!(server.await.unwrap().close, Some(CLOSE_GOING_AWAY));
+    }
+
+    #[tokio::test]
+    async fn test_refused_upgrade_fails_with_its_status() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0; 4096];
+            let _ = stream.read(&mut buffer).await;
+            let refusal = "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            stream.write_all(refusal.as_bytes()).await.unwrap();
+        });
+        let client = SecureNetworkClient::new().unwrap();
+        let refused = client.websocket(&socket(port), Some(&page(port)), None).await;
+        assert_eq!(refused.err(), Some(NetworkError::Status(403)));
+    }
+
+    #[tokio::test]
+    async fn test_websockets_are_checked_like_fetches() {
+        let client = SecureNetworkClient::new()
+            .unwrap()
+            .with_interceptor(Arc::new(ContentBlocker::new().with_hosts(["ads.example".to_string()])));
+        let url = |input: &str| ValidatedUrl::parse(input).unwrap();
+        let public = url("https://example.com/");
+        let open = |target: ValidatedUrl, page: Option<ValidatedUrl>| {
+            let client = &client;
+            async move { client.websocket(&target, page.as_ref(), None).await.err() }
+        };
+
+        // Plain ws only for pages that are plain http themselves
+        assert!(matches!(open(url("ws://example.com/"), None).await, Some(NetworkError::Request(_))));
+        assert!(matches!(
+            open(url("ws://example.com/"), Some(public.clone())).await,
+            Some(NetworkError::Request(_))
+        ));
+        assert!(matches!(open(url("https://example.com/"), None).await, Some(NetworkError::Request(_))));
+        assert!(matches!(
+            open(url("wss://ads.example/"), Some(public.clone())).await,
+            Some(NetworkError::Blocked(_))
+        ));
+        assert_eq!(
+            open(url("wss://example.com:6667/"), Some(public.clone())).await,
+            Some(NetworkError::RestrictedPort(6667))
+        );
+        assert_eq!(
+            open(url("wss://127.0.0.1:8443/"), Some(public)).await,
+            Some(NetworkError::PrivateNetwork("127.0.0.1".to_string()))
+        );
+        assert!(is_private_host(&url("http://localhost:8080/")));
+        assert!(is_private_host(&url("http://[fd00::1]/")));
+        assert!(!is_private_host(&url("http://93.184.216.34/")));
+    }
+}
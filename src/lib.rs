@@ -5,3 +5,5 @@ pub mod domain;
 pub mod application;
 pub mod infrastructure;
 pub mod ui;
+#[cfg(feature = "remote-debugging")]
+pub mod remote;
@@ -5,207 +5,639 @@ pub mod domain;
 pub mod application;
 pub mod infrastructure;
 pub mod ui;
+#[cfg(feature = "remote-debugging")]
+pub mod remote;
 
-use application::BrowserState;
-use infrastructure::{SqliteDatabase, DefaultSecurityService, SecureNetworkClient, ServoRenderer};
-use domain::{Tab, SecurityService, RenderingEngine};
-use ui::{BrowserWindow, Renderer, AddressBar, AddressBarAction};
-
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use winit::{
-    event::{Event, WindowEvent, KeyEvent, ElementState},
-    event_loop::{EventLoop, ControlFlow},
-    keyboard::{Key, NamedKey, PhysicalKey},
+use application::{
+    format_chords, BrowserController, BrowserImportRequest, BrowserState, ImportItems, ImportMode, ImportOptions,
+    Keymap, Repositories, COMMANDS, FILE_ACCESS_ROOTS_SETTING, FILE_ACCESS_SETTING,
 };
+use domain::{
+    BrowserSource, PageArchiver, PagePrinter, PaperSize, RenderingEngine, SettingsRepository, StorageError,
+    UserStyleRule,
+};
+use infrastructure::{
+    latest_crash_report, CrashReporter, DefaultSecurityService, DefaultUserScriptService, DefaultUserStyleService,
+    Diagnostics, FileAccess, ForeignProfileReader, LogBuffer, LogLevelControl, MhtmlArchiver, PdfPrinter, ProfileDir,
+    SecureNetworkClient, ServoRenderer, SiteMetadata, SqliteDatabase, StartupProfile, SESSION_RUNNING_SETTING,
+    write_atomically,
+};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use ui::{
+    file_access_policy, pdf_fonts, AddressBar, Clipboard, DbReady, FontSettings, Launcher, PageFrame, Renderer, ScrollState,
+    SharedServices, Theme, UserEvent,
+};
+use winit::event_loop::EventLoop;
 
-struct Navigator {
-    browser_state: Arc<RwLock<BrowserState>>,
-    db: Arc<SqliteDatabase>,
-    security: Arc<DefaultSecurityService>,
-    network: Arc<SecureNetworkClient>,
-    html_renderer: Arc<ServoRenderer>,
-    current_html: Arc<RwLock<String>>,
-}
-
-impl Navigator {
-    async fn new() -> anyhow::Result<Self> {
-        tracing::info!("Initializing Navigator Browser...");
-
-        let browser_state = Arc::new(RwLock::new(BrowserState::new()));
-        let db = Arc::new(SqliteDatabase::new("navigator.db").await?);
-        let security = Arc::new(DefaultSecurityService::new());
-        let network = Arc::new(SecureNetworkClient::new()?);
-        let html_renderer = Arc::new(ServoRenderer::new());
-
-        // Create initial tab
-        {
-            let state = browser_state.write().await;
-            let tab = Tab::new(false);
-            state.add_tab(tab);
-        }
-
-        Ok(Self {
-            browser_state,
-            db,
-            security,
-            network,
-            html_renderer,
-            current_html: Arc::new(RwLock::new(String::new())),
-        })
+const DATABASE_PATH: &str = "navigator.db";
+/// Passphrase for an encrypted profile, read instead of prompting
+const PASSPHRASE_ENV: &str = "NAVIGATOR_PROFILE_PASSPHRASE";
+
+fn main() -> anyhow::Result<()> {
+    // Initialize logging, keeping recent events for crash reports
+    let logs = LogBuffer::default();
+    let (log_levels, log_filter) = LogLevelControl::new();
+    tracing_subscriber::registry()
+        .with(log_filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(logs.clone())
+        .init();
+    CrashReporter::new(profile_directory(), logs.clone()).install();
+    let diagnostics = Diagnostics {
+        buffer: logs,
+        levels: Arc::new(log_levels),
+    };
+
+    println!("\n╔═══════════════════════════════════════════════════════╗");
+    println!("║   Navigator - Visual Browser (Phase 2: GPU Rendering)║");
+    println!("╚═══════════════════════════════════════════════════════╝\n");
+
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    // Profile commands run without opening a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("--encrypt-profile") => return runtime.block_on(encrypt_profile(true)),
+        Some("--decrypt-profile") => return runtime.block_on(encrypt_profile(false)),
+        Some("export-profile") => return runtime.block_on(export_profile(&args[1..])),
+        Some("import-profile") => return runtime.block_on(import_profile(&args[1..])),
+        Some("import-browser") => return runtime.block_on(import_browser(&args[1..])),
+        Some("user-style") => return runtime.block_on(user_style(&args[1..])),
+        Some("user-script") => return runtime.block_on(user_script(&args[1..])),
+        Some("ca") => return runtime.block_on(certificate_authority(&args[1..])),
+        Some("--headless") => return runtime.block_on(headless(&args[1..])),
+        Some("--remote-debugging-port" | "--temporary-profile" | "--no-update-check" | "--startup-profile") => {}
+        Some(other) => tracing::warn!("Ignoring unknown argument: {}", other),
+        None => {}
     }
 
-    async fn navigate_to(&self, url_str: &str) -> anyhow::Result<String> {
-        tracing::info!("Navigating to: {}", url_str);
+    tracing::info!("Initializing Navigator Browser...");
+    let startup = StartupProfile::new();
 
-        // Validate URL
-        let validated_url = self.security.validate_url(url_str)?;
+    // The profile opens in the background while the first window comes up
+    let (profile, passphrase) = if args.iter().any(|arg| arg == "--temporary-profile") {
+        let profile = ProfileDir::temporary()?;
+        tracing::info!("Using a temporary profile in {}", profile.directory().display());
+        (Arc::new(profile), None)
+    } else {
+        (Arc::new(ProfileDir::persistent(DATABASE_PATH)), std::env::var(PASSPHRASE_ENV).ok())
+    };
+    let event_loop = EventLoop::<UserEvent>::with_user_event().build()?;
+    let proxy = event_loop.create_proxy();
+    // Kept alive for as long as the window runs
+    let debugger = Arc::new(Mutex::new(None));
+    let opening = open_services(profile, passphrase, diagnostics, args.clone(), debugger.clone(), startup.clone());
+    let db_ready = DbReady::spawn(runtime.handle(), opening, move || {
+        let _ = proxy.send_event(UserEvent::DbReady);
+    });
 
-        // Check if blocked
-        if self.security.is_blocked(&validated_url) {
-            anyhow::bail!("This URL is blocked for security reasons");
+    let mut launcher = Launcher::new(runtime.handle().clone(), event_loop.create_proxy(), db_ready, startup)
+        .with_update_check(!args.iter().any(|arg| arg == "--no-update-check"))
+        .with_startup_report(args.iter().any(|arg| arg == "--startup-profile"));
+    event_loop.run_app(&mut launcher)?;
+    if let Some(e) = launcher.take_failure() {
+        return Err(e);
+    }
+    drop(debugger);
+
+    Ok(())
+}
+
+/// Open the profile, asking for its passphrase if it is encrypted, then
+/// start what only needs the profile: remote debugging and the crash check
+async fn open_services(
+    profile: Arc<ProfileDir>,
+    passphrase: Option<String>,
+    diagnostics: Diagnostics,
+    args: Vec<String>,
+    debugger: Arc<Mutex<Option<RemoteDebugger>>>,
+    startup: StartupProfile,
+) -> anyhow::Result<SharedServices> {
+    let open = |passphrase: Option<String>| {
+        let (profile, diagnostics) = (profile.clone(), diagnostics.clone());
+        startup.time_async("database", async move {
+            SharedServices::new(profile, passphrase.as_deref(), diagnostics).await
+        })
+    };
+    let services = match open(passphrase).await {
+        Err(e) if matches!(e.downcast_ref(), Some(StorageError::PassphraseRequired)) => {
+            let passphrase = tokio::task::spawn_blocking(|| read_passphrase("Profile passphrase: ")).await??;
+            open(Some(passphrase)).await?
         }
+        result => result?,
+    };
+
+    let server = start_remote_debugging(&args, &services).await?;
+    if let Ok(mut debugger) = debugger.lock() {
+        *debugger = server;
+    }
+
+    // Left set if this run ends in a crash
+    let db = &services.db;
+    if db.get(SESSION_RUNNING_SETTING).await?.as_deref() == Some("true") {
+        report_previous_crash();
+    }
+    db.set(SESSION_RUNNING_SETTING, "true").await?;
 
-        // Load URL
-        self.html_renderer.load_url(&validated_url).await?;
+    print_controls(&services.controller.keymap());
+    Ok(services)
+}
+
+/// The keyboard shortcuts and command line options
+fn print_controls(keymap: &Keymap) {
+    println!("Controls:");
+    println!("  Type URL and press Enter to navigate");
+    for info in COMMANDS {
+        let chords = keymap.chords(info.command);
+        if !chords.is_empty() {
+            println!("  {} - {}", format_chords(&chords), info.setting.label);
+        }
+    }
+    println!("  navigator://shortcuts - Every keyboard shortcut; change them on navigator://settings");
+    println!("  navigator://logs?level=warn&q=text - Recent log events; set_level=debug logs more");
+    println!("  navigator://history - Visited pages, with links to delete them");
+    println!("  navigator://downloads - Saved pages: Enter opens a folder, Delete removes, C clears");
+    println!("  navigator://console - What the tab's scripts logged; Enter evaluates an expression");
+    println!("  navigator://settings - Up / Down choose a setting, Enter changes or edits it");
+    println!("  navigator://scripts - User scripts, with links to turn them off and on or remove them");
+    #[cfg(debug_assertions)]
+    println!("  navigator://crash - Panic on purpose to test crash reports");
+    println!("  --encrypt-profile / --decrypt-profile - Change profile encryption");
+    println!("  export-profile <file> [--history] - Write the profile as JSON");
+    println!("  import-profile <file> [--replace] - Merge (or replace) from JSON");
+    println!("  import-browser firefox|chrome [profile-dir] [--dry-run] - Import bookmarks and history");
+    println!("  user-style list | add <host-pattern> <rule> | remove <id> - Edit user styles");
+    println!("  --remote-debugging-port <port> - Serve the HTTP control API on 127.0.0.1");
+    println!("  --temporary-profile - Browse with an empty profile that is deleted on exit");
+    println!("  --no-update-check - Skip this run's update check, when it is turned on in settings");
+    println!("  navigator://update - Release notes of a newer release, with a link to download it");
+    println!("  --headless screenshot <url> [file.png] [--full-page] [--clipboard] - Capture a page");
+    println!("  --headless security-report <url> - Print what is known of a page's security as JSON");
+    println!("  --headless sitemap <origin> - Print the URLs of the pages a site lists in its sitemaps");
+    println!("  user-script list | add <name> <match,...> <file> | set <id> <option>=<value> | remove <id>");
+    println!("  ca list | add <file.pem> [name] | remove <id> - Trust more certificate authorities");
+    println!("  --startup-profile - Print how long each step of starting took\n");
+}
 
-        // Get rendered content
-        let content = self.html_renderer.render_to_text();
+/// Where the profile database and crash reports live
+fn profile_directory() -> &'static Path {
+    match Path::new(DATABASE_PATH).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
 
-        // Update current HTML
-        {
-            let mut current = self.current_html.write().await;
-            *current = content.clone();
+/// Tell the user the last run did not exit cleanly
+fn report_previous_crash() {
+    println!("Navigator closed unexpectedly last time.");
+    match latest_crash_report(profile_directory()) {
+        Some(report) => {
+            println!("  Crash report: {}\n", report.display());
+            tracing::warn!("Previous run crashed; see {}", report.display());
         }
+        None => tracing::warn!("Previous run did not exit cleanly"),
+    }
+}
 
-        // Get title
-        let title = self.html_renderer.get_title().await?;
-        tracing::info!("Page loaded: {} - {}", title, validated_url);
+#[cfg(feature = "remote-debugging")]
+type RemoteDebugger = remote::RemoteDebugServer;
+/// Never constructed when built without the server
+#[cfg(not(feature = "remote-debugging"))]
+type RemoteDebugger = std::convert::Infallible;
 
-        Ok(content)
+/// `--remote-debugging-port <port>`: serve the control API while the window is open
+#[cfg(feature = "remote-debugging")]
+async fn start_remote_debugging(
+    args: &[String],
+    services: &SharedServices,
+) -> anyhow::Result<Option<RemoteDebugger>> {
+    let Some(position) = args.iter().position(|arg| arg == "--remote-debugging-port") else {
+        return Ok(None);
+    };
+    let port: u16 = args
+        .get(position + 1)
+        .ok_or_else(|| anyhow::anyhow!("--remote-debugging-port needs a port"))?
+        .parse()?;
+    let server = remote::RemoteDebugServer::start(services.controller.clone(), port).await?;
+    Ok(Some(server))
+}
+
+#[cfg(not(feature = "remote-debugging"))]
+async fn start_remote_debugging(
+    args: &[String],
+    _services: &SharedServices,
+) -> anyhow::Result<Option<RemoteDebugger>> {
+    if args.iter().any(|arg| arg == "--remote-debugging-port") {
+        tracing::warn!("Built without the remote-debugging feature; ignoring --remote-debugging-port");
     }
+    Ok(None)
+}
 
-    fn get_current_html(&self) -> String {
-        if let Ok(html) = self.current_html.try_read() {
-            html.clone()
-        } else {
-            String::from("Loading...")
+/// Migrate the profile to or from encrypted storage
+async fn encrypt_profile(encrypt: bool) -> anyhow::Result<()> {
+    if encrypt {
+        let mut db = SqliteDatabase::new(DATABASE_PATH).await?;
+        let passphrase = read_passphrase("New profile passphrase: ")?;
+        if read_passphrase("Repeat passphrase: ")? != passphrase {
+            anyhow::bail!("Passphrases do not match");
         }
+        db.encrypt_profile(&passphrase).await?;
+        println!("Profile encrypted.");
+    } else {
+        let passphrase = read_passphrase("Profile passphrase: ")?;
+        let mut db = SqliteDatabase::open(DATABASE_PATH, Some(&passphrase)).await?;
+        db.decrypt_profile().await?;
+        println!("Profile decrypted.");
     }
+    Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    // Initialize logging
-    tracing_subscriber::fmt()
-        .with_env_filter("navigator=info,wgpu=warn")
-        .init();
+/// `export-profile <file> [--history]`
+async fn export_profile(args: &[String]) -> anyhow::Result<()> {
+    let path = profile_file_argument(args)?;
+    let controller = open_profile().await?;
+    let json = controller
+        .export_profile(args.iter().any(|arg| arg == "--history"))
+        .await?;
+    write_atomically(path, json)?;
+    println!("Profile exported to {}.", path);
+    Ok(())
+}
 
-    println!("\n╔═══════════════════════════════════════════════════════╗");
-    println!("║   Navigator - Visual Browser (Phase 2: GPU Rendering)║");
-    println!("╚═══════════════════════════════════════════════════════╝\n");
+/// `import-profile <file> [--replace]`
+async fn import_profile(args: &[String]) -> anyhow::Result<()> {
+    let path = profile_file_argument(args)?;
+    let json = std::fs::read_to_string(path)?;
+    let mode = if args.iter().any(|arg| arg == "--replace") {
+        ImportMode::Replace
+    } else {
+        ImportMode::Merge
+    };
 
-    // Create event loop
-    let event_loop = EventLoop::new()?;
+    let controller = open_profile().await?;
+    let report = controller.import_profile(&json, ImportOptions::all(mode)).await?;
+    controller.shutdown().await;
 
-    // Run async initialization
-    let runtime = tokio::runtime::Runtime::new()?;
-    let navigator = runtime.block_on(async {
-        Navigator::new().await
-    })?;
-    let navigator = Arc::new(navigator);
+    println!(
+        "Imported {} bookmarks, {} settings, {} blocked domains and {} history entries.",
+        report.bookmarks, report.settings, report.blocked_domains, report.history
+    );
+    for failure in &report.failures {
+        println!("  Skipped {} record {}: {}", failure.section, failure.record, failure.reason);
+    }
+    Ok(())
+}
 
-    // Load default page
-    let nav_clone = navigator.clone();
-    runtime.spawn(async move {
-        if let Err(e) = nav_clone.navigate_to("https://example.com").await {
-            tracing::error!("Failed to load default page: {}", e);
-        }
-    });
+/// `import-browser firefox|chrome [profile-dir] [--dry-run] [--bookmarks-only|--history-only]`
+async fn import_browser(args: &[String]) -> anyhow::Result<()> {
+    let mut positional = args.iter().filter(|arg| !arg.starts_with("--"));
+    let source = positional
+        .next()
+        .and_then(|name| BrowserSource::parse(name))
+        .ok_or_else(|| anyhow::anyhow!("Expected firefox or chrome"))?;
+    let flag = |name: &str| args.iter().any(|arg| arg == name);
 
-    // Create window
-    let window = BrowserWindow::new(&event_loop)?;
+    let mut request = BrowserImportRequest::new(source);
+    request.profile_path = positional.next().map(Into::into);
+    request.dry_run = flag("--dry-run");
+    request.items = ImportItems {
+        bookmarks: !flag("--history-only"),
+        history: !flag("--bookmarks-only"),
+    };
 
-    // Create renderer
-    let mut renderer = runtime.block_on(async {
-        Renderer::new(window.window()).await
-    })?;
+    let controller = open_profile().await?;
+    let report = controller
+        .import_from_browser(Arc::new(ForeignProfileReader::new()), request)
+        .await?;
+    controller.shutdown().await;
 
-    // Create address bar
-    let mut address_bar = AddressBar::new();
+    println!(
+        "{} {} bookmarks and {} history entries from {} ({} already present).",
+        if report.dry_run { "Would import" } else { "Imported" },
+        report.bookmarks,
+        report.history,
+        source,
+        report.duplicates
+    );
+    Ok(())
+}
 
-    println!("✓ Window created");
-    println!("✓ GPU renderer initialized");
-    println!("✓ Address bar ready");
-    println!("✓ Loading example.com...\n");
-    println!("Controls:");
-    println!("  Type URL and press Enter to navigate");
-    println!("  F5 - Reload");
-    println!("  ESC - Quit\n");
-
-    // Event loop
-    event_loop.run(move |event, elwt| {
-        elwt.set_control_flow(ControlFlow::Wait);
-
-        match event {
-            Event::WindowEvent { event, .. } => match event {
-                WindowEvent::CloseRequested => {
-                    tracing::info!("Close requested, exiting...");
-                    elwt.exit();
-                }
-                WindowEvent::Resized(physical_size) => {
-                    tracing::debug!("Window resized to: {:?}", physical_size);
-                    renderer.resize(physical_size);
-                    window.request_redraw();
-                }
-                WindowEvent::KeyboardInput { event: key_event, .. } => {
-                    if key_event.state == ElementState::Pressed {
-                        let text = key_event.text.as_ref().map(|s| s.as_str());
-                        if let Some(action) = address_bar.handle_key(&key_event.logical_key, text) {
-                            match action {
-                                AddressBarAction::Navigate(url) => {
-                                    tracing::info!("Navigating to: {}", url);
-                                    let nav_clone = navigator.clone();
-                                    runtime.spawn(async move {
-                                        if let Err(e) = nav_clone.navigate_to(&url).await {
-                                            tracing::error!("Navigation error: {}", e);
-                                        }
-                                    });
-                                }
-                            }
-                        }
-
-                        // Handle special keys
-                        match key_event.logical_key {
-                            Key::Named(NamedKey::F5) => {
-                                tracing::info!("Refresh requested");
-                                let url = address_bar.url().to_string();
-                                let nav_clone = navigator.clone();
-                                runtime.spawn(async move {
-                                    if let Err(e) = nav_clone.navigate_to(&url).await {
-                                        tracing::error!("Navigation error: {}", e);
-                                    }
-                                });
-                            }
-                            _ => {}
-                        }
-
-                        window.request_redraw();
-                    }
+/// `user-style list`, `user-style add <host-pattern> <rule>` or `user-style remove <id>`.
+///
+/// Rules are `min-font-size=<px>`, `text-color=<color>` or `hide=<selectors>`.
+async fn user_style(args: &[String]) -> anyhow::Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let controller = open_profile().await?;
+    match args.as_slice() {
+        ["list"] | [] => {
+            for style in controller.user_styles().await? {
+                println!("{:>4}  {:<24} {}", style.id, style.host_pattern.as_str(), style.rule);
+            }
+        }
+        ["add", pattern, rule] => {
+            let rule = UserStyleRule::parse(rule).ok_or_else(|| anyhow::anyhow!("Unknown rule: {}", rule))?;
+            let style = controller.add_user_style(pattern, rule).await?;
+            println!("Added user style {}.", style.id);
+        }
+        ["remove", id] => {
+            controller.remove_user_style(id.parse()?).await?;
+            println!("Removed user style {}.", id);
+        }
+        _ => anyhow::bail!("Usage: user-style list | add <host-pattern> <rule> | remove <id>"),
+    }
+    controller.shutdown().await;
+    Ok(())
+}
+
+/// `user-script list`, `user-script add <name> <match>[,<match>...] <file>`,
+/// `user-script set <id> <option>=<value>` or `user-script remove <id>`.
+///
+/// Options are `enabled`, `allow-private` and `allow-internal`, set to
+/// `true` or `false`, and `run-at`, set to `document-end` or `document-idle`.
+async fn user_script(args: &[String]) -> anyhow::Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let controller = open_profile().await?;
+    match args.as_slice() {
+        ["list"] | [] => {
+            for script in controller.user_scripts().await? {
+                let patterns: Vec<&str> = script.matches.iter().map(|pattern| pattern.as_str()).collect();
+                println!(
+                    "{:>4}  {:<8} {:<24} {:<14} {}",
+                    script.id,
+                    if script.enabled { "enabled" } else { "disabled" },
+                    script.name,
+                    script.run_at.to_string(),
+                    patterns.join(",")
+                );
+                if script.allow_internal || script.allow_private {
+                    println!(
+                        "      also runs on:{}{}",
+                        if script.allow_internal { " internal pages" } else { "" },
+                        if script.allow_private { " private tabs" } else { "" }
+                    );
                 }
-                WindowEvent::RedrawRequested => {
-                    let html = navigator.get_current_html();
-                    if let Err(e) = renderer.render(&html, &address_bar) {
-                        tracing::error!("Render error: {}", e);
-                    }
+                if let Some(error) = &script.last_error {
+                    println!("      last error: {}", error);
                 }
-                _ => {}
-            },
-            Event::AboutToWait => {
-                window.request_redraw();
             }
-            _ => {}
         }
-    })?;
+        ["add", name, matches, file] => {
+            let code = std::fs::read_to_string(file)?;
+            let matches: Vec<&str> = matches.split(',').collect();
+            let script = controller.add_user_script(name, &matches, &code).await?;
+            println!("Added user script {}.", script.id);
+        }
+        ["set", id, option] => {
+            controller.set_user_script_option(id.parse()?, option).await?;
+            println!("Updated user script {}.", id);
+        }
+        ["remove", id] => {
+            controller.remove_user_script(id.parse()?).await?;
+            println!("Removed user script {}.", id);
+        }
+        _ => anyhow::bail!(
+            "Usage: user-script list | add <name> <match,...> <file> | set <id> <option>=<value> | remove <id>"
+        ),
+    }
+    controller.shutdown().await;
+    Ok(())
+}
 
+/// `ca list`, `ca add <file.pem> [name]` or `ca remove <id>`.
+///
+/// Added authorities must be CA certificates that have not expired; they
+/// are trusted for https connections alongside the built-in roots.
+async fn certificate_authority(args: &[String]) -> anyhow::Result<()> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let controller = open_profile().await?;
+    match args.as_slice() {
+        ["list"] | [] => {
+            for ca in controller.custom_cas().await? {
+                let added = ca.added_at.format("%Y-%m-%d");
+                println!("{:>4}  {:<32} {}  added {}", ca.id, ca.name, ca.fingerprint, added);
+            }
+        }
+        ["add", file] | ["add", file, _] => {
+            let pem = std::fs::read_to_string(file)?;
+            let ca = controller.import_certificate(args.get(2).copied(), &pem).await?;
+            println!("Trusting {} ({}) as certificate authority {}.", ca.name, ca.fingerprint, ca.id);
+        }
+        ["remove", id] => {
+            let ca = controller.remove_certificate(id.parse()?).await?;
+            println!("No longer trusting {}.", ca.name);
+        }
+        _ => anyhow::bail!("Usage: ca list | add <file.pem> [name] | remove <id>"),
+    }
+    controller.shutdown().await;
     Ok(())
 }
+
+/// Size of the page captured by `--headless screenshot`
+const HEADLESS_WIDTH: u32 = 1280;
+const HEADLESS_HEIGHT: u32 = 800;
+const HEADLESS_USAGE: &str = "Usage: --headless screenshot <url> [file.png] [--full-page] [--clipboard] \
+     | archive <url> <file.mhtml> | print <url> <file.pdf> [--letter] | security-report <url> \
+     | sitemap <origin>";
+
+/// `--headless screenshot <url> [file.png] [--full-page] [--clipboard]`,
+/// `--headless archive <url> <file.mhtml>`,
+/// `--headless print <url> <file.pdf> [--letter]`,
+/// `--headless security-report <url>` or `--headless sitemap <origin>`.
+///
+/// The page is loaded in a private tab, so no history is kept. Screenshots
+/// are written to `screenshot.png` unless another file or the clipboard is
+/// given.
+async fn headless(args: &[String]) -> anyhow::Result<()> {
+    let mut positional = args.iter().map(String::as_str).filter(|arg| !arg.starts_with("--"));
+    let flag = |name: &str| args.iter().any(|arg| arg == name);
+    let (command, Some(url)) = (positional.next(), positional.next()) else {
+        anyhow::bail!(HEADLESS_USAGE);
+    };
+    match command {
+        Some("screenshot") => {}
+        Some("archive") => return headless_archive(url, positional.next()).await,
+        Some("security-report") => return headless_security_report(url).await,
+        Some("sitemap") => return headless_sitemap(url).await,
+        Some("print") => {
+            let paper = if flag("--letter") { PaperSize::Letter } else { PaperSize::A4 };
+            return headless_print(url, positional.next(), paper).await;
+        }
+        _ => anyhow::bail!(HEADLESS_USAGE),
+    }
+    let path = positional.next().unwrap_or("screenshot.png");
+
+    let controller = open_profile().await?;
+    controller.state().set_private_mode(true);
+    let tab = controller.open_tab(None).await?;
+    let outcome = controller.navigate(tab, url).await?;
+    let content = controller
+        .get_page(tab)
+        .ok_or_else(|| anyhow::anyhow!("The tab closed while loading"))?
+        .layout();
+    controller.shutdown().await;
+
+    let mut renderer = Renderer::headless(HEADLESS_WIDTH, HEADLESS_HEIGHT, FontSettings::default(), false).await?;
+    let mut address_bar = AddressBar::new();
+    address_bar.set_url(outcome.url.to_string());
+    let frame = PageFrame {
+        content: &content,
+        generation: 1,
+        layout_options: Default::default(),
+        scrollbar_opacity: 0.0,
+        reveal: None,
+        selection: None,
+        hints: None,
+        caret: None,
+        toasts: &[],
+        palette: None,
+        page_theme: None,
+        hit_overlay: None,
+    };
+    let theme = Theme::default();
+    let screenshot = if flag("--full-page") {
+        renderer.capture_full_page(&frame, &address_bar, &theme)?
+    } else {
+        renderer.capture_viewport(&frame, &address_bar, &theme, &ScrollState::new())?
+    };
+
+    if flag("--clipboard") {
+        Clipboard::system().copy_image(&screenshot)?;
+        println!("Copied a {}x{} screenshot of {}.", screenshot.width, screenshot.height, outcome.url);
+    } else {
+        write_atomically(path, screenshot.to_png())?;
+        println!(
+            "Saved a {}x{} screenshot of {} to {}.",
+            screenshot.width, screenshot.height, outcome.url, path
+        );
+    }
+    Ok(())
+}
+
+/// Save `url` with its subresources as an MHTML archive at `path`
+async fn headless_archive(url: &str, path: Option<&str>) -> anyhow::Result<()> {
+    let path = path.ok_or_else(|| anyhow::anyhow!(HEADLESS_USAGE))?;
+    let controller = open_profile().await?;
+    controller.state().set_private_mode(true);
+    let tab = controller.open_tab(None).await?;
+    let outcome = controller.navigate(tab, url).await?;
+    let page = controller
+        .get_page(tab)
+        .ok_or_else(|| anyhow::anyhow!("The tab closed while loading"))?;
+    let (title, html) = (page.get_title().await?, page.get_source().await?);
+    controller.shutdown().await;
+
+    let archiver = MhtmlArchiver::new(Arc::new(SecureNetworkClient::new()?));
+    let archive = archiver.archive(&outcome.url, &title, &html).await;
+    write_atomically(path, &archive)?;
+    println!("Archived {} to {} ({} bytes).", outcome.url, path, archive.len());
+    Ok(())
+}
+
+/// Print `url` to a PDF on `paper` at `path`
+async fn headless_print(url: &str, path: Option<&str>, paper: PaperSize) -> anyhow::Result<()> {
+    let path = path.ok_or_else(|| anyhow::anyhow!(HEADLESS_USAGE))?;
+    let controller = open_profile().await?;
+    controller.state().set_private_mode(true);
+    let tab = controller.open_tab(None).await?;
+    let outcome = controller.navigate(tab, url).await?;
+    let page = controller
+        .get_page(tab)
+        .ok_or_else(|| anyhow::anyhow!("The tab closed while loading"))?;
+    let (title, html) = (page.get_title().await?, page.get_source().await?);
+    controller.shutdown().await;
+
+    let printer = PdfPrinter::new().with_font_loader(|| pdf_fonts(&FontSettings::default()));
+    let pdf = printer.print(&outcome.url, &title, &html, paper).await;
+    write_atomically(path, &pdf)?;
+    println!("Printed {} to {} ({} bytes).", outcome.url, path, pdf.len());
+    Ok(())
+}
+
+/// Print what the browser knows of the security of `url` as JSON
+async fn headless_security_report(url: &str) -> anyhow::Result<()> {
+    let controller = open_profile().await?;
+    controller.state().set_private_mode(true);
+    let tab = controller.open_tab(None).await?;
+    controller.navigate(tab, url).await?;
+    let report = controller.security_report(tab).await;
+    controller.shutdown().await;
+    println!("{}", serde_json::to_string_pretty(&report?)?);
+    Ok(())
+}
+
+/// Print the URL of every page the site at `origin` lists in the
+/// sitemaps its robots.txt names, or else in `/sitemap.xml`
+async fn headless_sitemap(origin: &str) -> anyhow::Result<()> {
+    let controller = open_profile().await?;
+    let pages = controller.site_pages(origin).await;
+    controller.shutdown().await;
+    for page in pages? {
+        println!("{}", page.url);
+    }
+    Ok(())
+}
+
+/// The first argument that is not a flag
+fn profile_file_argument(args: &[String]) -> anyhow::Result<&str> {
+    args.iter()
+        .map(String::as_str)
+        .find(|arg| !arg.starts_with("--"))
+        .ok_or_else(|| anyhow::anyhow!("A profile file is required"))
+}
+
+/// Open the profile for a command, asking for its passphrase if needed
+async fn open_profile() -> anyhow::Result<BrowserController<ServoRenderer>> {
+    let passphrase = std::env::var(PASSPHRASE_ENV).ok();
+    let db = match SqliteDatabase::open(DATABASE_PATH, passphrase.as_deref()).await {
+        Err(StorageError::PassphraseRequired) => {
+            let passphrase = read_passphrase("Profile passphrase: ")?;
+            SqliteDatabase::open(DATABASE_PATH, Some(&passphrase)).await?
+        }
+        result => result?,
+    };
+    let file_access = Arc::new(FileAccess::new(file_access_policy(
+        db.get(FILE_ACCESS_SETTING).await?.as_deref(),
+        db.get(FILE_ACCESS_ROOTS_SETTING).await?.as_deref(),
+    )));
+    let network = Arc::new(SecureNetworkClient::new()?);
+    let engine_network = network.clone();
+    let engine_file_access = file_access.clone();
+    let controller = BrowserController::new(
+        BrowserState::new(),
+        Repositories::shared(Arc::new(db)),
+        Arc::new(DefaultSecurityService::new().with_shared_file_access(file_access)),
+        Arc::new(DefaultUserStyleService::new()),
+        Arc::new(DefaultUserScriptService::new()),
+        move || {
+            ServoRenderer::new()
+                .with_network(engine_network.clone())
+                .with_file_access(engine_file_access.clone())
+        },
+    )
+    .with_trust_store(network.clone())
+    .with_tls_inspector(network.clone())
+    .with_site_metadata(Arc::new(SiteMetadata::new(network)));
+    controller.load_custom_cas().await?;
+    Ok(controller)
+}
+
+/// Read a passphrase from the environment, or else a line from stdin
+fn read_passphrase(prompt: &str) -> anyhow::Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    eprint!("{}", prompt);
+    std::io::stderr().flush()?;
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    let passphrase = line.trim_end_matches(['\r', '\n']).to_string();
+    if passphrase.is_empty() {
+        anyhow::bail!("A passphrase is required");
+    }
+    Ok(passphrase)
+}
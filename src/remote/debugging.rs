@@ -0,0 +1,222 @@
+use crate::application::{BrowserController, NavigatorError};
+use crate::domain::{AccessibleNode, ConsoleMessage, RenderingEngine, Tab, TabId};
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// HTTP control surface for driving the browser from tests.
+///
+/// Listens on 127.0.0.1 only and wants `Authorization: Bearer <token>` on
+/// every request, with a token generated when the server starts. Every
+/// handler goes through the [`BrowserController`], like the UI does.
+pub struct RemoteDebugServer {
+    addr: SocketAddr,
+    token: String,
+    task: JoinHandle<()>,
+}
+
+impl RemoteDebugServer {
+    /// Start serving on `port`, or on any free port if it is 0
+    pub async fn start<E: RenderingEngine + 'static>(
+        controller: Arc<BrowserController<E>>,
+        port: u16,
+    ) -> anyhow::Result<Self> {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let listener = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port)).await?;
+        let addr = listener.local_addr()?;
+
+        let router = Router::new()
+            .route("/tabs", get(list_tabs::<E>).post(open_tab::<E>))
+            .route("/tabs/{id}", axum::routing::delete(close_tab::<E>))
+            .route("/tabs/{id}/navigate", post(navigate::<E>))
+            .route("/tabs/{id}/text", get(page_text::<E>))
+            .route("/tabs/{id}/accessibility", get(accessibility_tree::<E>))
+            .route("/tabs/{id}/screenshot", get(screenshot::<E>))
+            .route("/tabs/{id}/eval", post(evaluate::<E>))
+            .route("/tabs/{id}/console", get(console::<E>))
+            .layer(middleware::from_fn_with_state(Arc::<str>::from(token.as_str()), require_token))
+            .with_state(controller);
+
+        let task = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router).await {
+                tracing::error!("Remote debugging server stopped: {}", e);
+            }
+        });
+        tracing::info!("Remote debugging listening on http://{} with token {}", addr, token);
+        Ok(Self { addr, token, task })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Token clients must send as `Authorization: Bearer <token>`
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+impl Drop for RemoteDebugServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A tab as listed by `GET /tabs`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabInfo {
+    pub id: TabId,
+    pub title: String,
+    pub url: Option<String>,
+    pub loading: bool,
+    pub private: bool,
+}
+
+impl From<Tab> for TabInfo {
+    fn from(tab: Tab) -> Self {
+        Self {
+            id: tab.id,
+            title: tab.title,
+            url: tab.url.map(|url| url.to_string()),
+            loading: tab.is_loading,
+            private: tab.is_private,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NavigateBody {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EvalBody {
+    script: String,
+}
+
+/// An error response, sent as `{"error": message}`
+struct ApiError(StatusCode, String);
+
+impl From<NavigatorError> for ApiError {
+    fn from(error: NavigatorError) -> Self {
+        let status = match &error {
+            NavigatorError::TabNotFound(_) => StatusCode::NOT_FOUND,
+            NavigatorError::Security(_) => StatusCode::BAD_REQUEST,
+            NavigatorError::Network(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+type Controller<E> = State<Arc<BrowserController<E>>>;
+
+async fn require_token(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| given == &*token);
+    if !authorized {
+        return ApiError(StatusCode::UNAUTHORIZED, "Missing or wrong token".to_string()).into_response();
+    }
+    next.run(request).await
+}
+
+fn tab_id(id: &str) -> ApiResult<TabId> {
+    TabId::parse(id).ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("No tab {}", id)))
+}
+
+fn tab_info<E: RenderingEngine + 'static>(controller: &BrowserController<E>, id: TabId) -> ApiResult<TabInfo> {
+    let tab = controller
+        .state()
+        .get_tab(id)
+        .ok_or(NavigatorError::TabNotFound(id))?;
+    Ok(tab.into())
+}
+
+async fn list_tabs<E: RenderingEngine + 'static>(State(controller): Controller<E>) -> Json<Vec<TabInfo>> {
+    Json(controller.state().get_all_tabs().into_iter().map(Into::into).collect())
+}
+
+async fn open_tab<E: RenderingEngine + 'static>(State(controller): Controller<E>) -> ApiResult<impl IntoResponse> {
+    let id = controller.open_tab(None).await?;
+    Ok((StatusCode::CREATED, Json(tab_info(&controller, id)?)))
+}
+
+async fn close_tab<E: RenderingEngine + 'static>(
+    State(controller): Controller<E>,
+    Path(id): Path<String>,
+) -> ApiResult<StatusCode> {
+    controller.close_tab(tab_id(&id)?).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn navigate<E: RenderingEngine + 'static>(
+    State(controller): Controller<E>,
+    Path(id): Path<String>,
+    Json(body): Json<NavigateBody>,
+) -> ApiResult<Json<TabInfo>> {
+    let id = tab_id(&id)?;
+    controller.navigate(id, &body.url).await?;
+    Ok(Json(tab_info(&controller, id)?))
+}
+
+async fn page_text<E: RenderingEngine + 'static>(
+    State(controller): Controller<E>,
+    Path(id): Path<String>,
+) -> ApiResult<String> {
+    Ok(controller.page_text(tab_id(&id)?).await?)
+}
+
+async fn accessibility_tree<E: RenderingEngine + 'static>(
+    State(controller): Controller<E>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<AccessibleNode>> {
+    Ok(Json(controller.accessibility_tree(tab_id(&id)?).await?))
+}
+
+async fn screenshot<E: RenderingEngine + 'static>(
+    State(controller): Controller<E>,
+    Path(id): Path<String>,
+) -> ApiResult<Response> {
+    let png = controller.screenshot(tab_id(&id)?).await?;
+    if png.is_empty() {
+        return Err(ApiError(
+            StatusCode::NOT_IMPLEMENTED,
+            "The rendering engine cannot capture screenshots".to_string(),
+        ));
+    }
+    Ok(([(header::CONTENT_TYPE, "image/png")], png).into_response())
+}
+
+async fn evaluate<E: RenderingEngine + 'static>(
+    State(controller): Controller<E>,
+    Path(id): Path<String>,
+    Json(body): Json<EvalBody>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let result = controller.evaluate(tab_id(&id)?, &body.script).await?;
+    Ok(Json(serde_json::json!({ "result": result })))
+}
+
+async fn console<E: RenderingEngine + 'static>(
+    State(controller): Controller<E>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<Vec<ConsoleMessage>>> {
+    Ok(Json(controller.console(tab_id(&id)?)?))
+}
@@ -0,0 +1,6 @@
+// Remote Layer - Control surfaces for driving the browser from outside
+// Only built with the `remote-debugging` feature
+
+pub mod debugging;
+
+pub use debugging::*;
@@ -1,13 +1,56 @@
+use glyphon::cosmic_text::Align;
 use glyphon::{
-    Attrs, Buffer, Color as GlyphonColor, Family, FontSystem, Metrics, Shaping,
+    Buffer, Color as GlyphonColor, FontSystem, Metrics, Shaping,
 };
+use std::time::Instant;
+use unicode_bidi::BidiClass;
+use unicode_segmentation::UnicodeSegmentation;
 use winit::keyboard::{Key, NamedKey};
 
+use super::chrome::ChromeLayout;
+use super::fonts::FontSettings;
+use super::theme::{Color, Theme};
+use crate::application::wrap_bare_word;
+use crate::domain::{SecurityLevel, ValidatedUrl};
+
 /// Address bar for URL input
 pub struct AddressBar {
     url: String,
     is_focused: bool,
+    /// Byte offset of the cursor in `url`, always between graphemes
     cursor_position: usize,
+    /// Rest of the top suggestion after what was typed, shown selected
+    /// after the cursor until typing goes on
+    completion: String,
+    security_level: Option<SecurityLevel>,
+    /// Question shown in place of the URL until answered
+    prompt: Option<String>,
+    /// Label of the value edited in place of the URL, and the URL to show
+    /// again afterwards
+    editing: Option<(String, String)>,
+    /// Requests the page had blocked, shown as a badge when there are any
+    blocked: usize,
+    /// A download's progress, shown in the badge in place of the blocked
+    /// count until the instant given
+    download: Option<(DownloadIndicator, Instant)>,
+}
+
+/// How a download started from the window is going
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadIndicator {
+    Started,
+    Finished,
+    Failed,
+}
+
+impl DownloadIndicator {
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Started => "↓",
+            Self::Finished => "✓",
+            Self::Failed => "✗",
+        }
+    }
 }
 
 impl AddressBar {
@@ -16,6 +59,12 @@ impl AddressBar {
             url: String::from("https://example.com"),
             is_focused: true,
             cursor_position: 0,
+            completion: String::new(),
+            security_level: None,
+            prompt: None,
+            editing: None,
+            blocked: 0,
+            download: None,
         }
     }
 
@@ -23,9 +72,110 @@ impl AddressBar {
         &self.url
     }
 
+    /// Text completed inline after what was typed, if any
+    pub fn completion(&self) -> &str {
+        &self.completion
+    }
+
+    /// Complete what was typed to `candidate` inline, with the added text
+    /// selected so typing goes on over it. Only done while the cursor is at
+    /// the end of a URL being typed that `candidate` begins with.
+    pub fn set_completion(&mut self, candidate: &str) {
+        self.completion.clear();
+        let typed = self.url.len();
+        if !self.is_focused || self.editing.is_some() || self.cursor_position != typed {
+            return;
+        }
+        let continues = candidate.get(..typed).is_some_and(|start| start.eq_ignore_ascii_case(&self.url));
+        if continues && !self.url.is_empty() {
+            self.completion = candidate[typed..].to_string();
+        }
+    }
+
+    /// Keep the completion as typed text
+    fn accept_completion(&mut self) {
+        self.url.push_str(&std::mem::take(&mut self.completion));
+    }
+
+    /// Start of the grapheme before the cursor
+    fn previous_boundary(&self) -> usize {
+        self.url[..self.cursor_position]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(start, _)| start)
+    }
+
+    /// End of the grapheme after the cursor
+    fn next_boundary(&self) -> usize {
+        self.url[self.cursor_position..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor_position, |grapheme| self.cursor_position + grapheme.len())
+    }
+
+    /// Show `url`; while editing, it is shown once the edit ends
     pub fn set_url(&mut self, url: String) {
+        if let Some((_, saved)) = &mut self.editing {
+            *saved = url;
+            return;
+        }
         self.url = url;
         self.cursor_position = self.url.len();
+        self.completion.clear();
+    }
+
+    /// Show where the page is; a blank tab shows nothing, so typing starts
+    /// from empty
+    pub fn show_url(&mut self, url: &ValidatedUrl) {
+        let text = if url.is_about_blank() { String::new() } else { url.to_string() };
+        self.set_url(text);
+    }
+
+    /// Turn a single bare word typed into `https://www.{word}.com`, for
+    /// Ctrl+Enter; other text, or a value being edited, is left alone
+    pub fn wrap_bare_word(&mut self) {
+        if self.editing.is_some() {
+            return;
+        }
+        if let Some(url) = wrap_bare_word(&self.url) {
+            self.completion.clear();
+            self.url = url;
+            self.cursor_position = self.url.len();
+        }
+    }
+
+    /// Edit `value` in place of the URL, labelled with what it is; Enter
+    /// finishes with [`AddressBarAction::Edited`]
+    pub fn begin_edit(&mut self, label: &str, value: &str) {
+        if self.editing.is_none() {
+            self.editing = Some((label.to_string(), std::mem::take(&mut self.url)));
+        }
+        self.url = value.to_string();
+        self.cursor_position = self.url.len();
+        self.completion.clear();
+        self.is_focused = true;
+    }
+
+    /// Stop editing and show the URL again
+    pub fn end_edit(&mut self) {
+        if let Some((_, url)) = self.editing.take() {
+            self.url = url;
+            self.cursor_position = self.url.len();
+            self.is_focused = false;
+        }
+    }
+
+    /// Label of the value being edited, if any
+    pub fn editing(&self) -> Option<&str> {
+        self.editing.as_ref().map(|(label, _)| label.as_str())
+    }
+
+    /// Insert text at the cursor, e.g. from the clipboard; line breaks are dropped
+    pub fn insert_text(&mut self, text: &str) {
+        let text: String = text.chars().filter(|c| *c != '\n' && *c != '\r').collect();
+        self.completion.clear();
+        self.url.insert_str(self.cursor_position, &text);
+        self.cursor_position += text.len();
     }
 
     pub fn is_focused(&self) -> bool {
@@ -34,36 +184,101 @@ impl AddressBar {
 
     pub fn set_focused(&mut self, focused: bool) {
         self.is_focused = focused;
+        if !focused {
+            self.completion.clear();
+        }
+    }
+
+    pub fn security_level(&self) -> Option<SecurityLevel> {
+        self.security_level
+    }
+
+    /// Set the security level of the page currently shown
+    pub fn set_security_level(&mut self, level: Option<SecurityLevel>) {
+        self.security_level = level;
+    }
+
+    pub fn blocked_count(&self) -> usize {
+        self.blocked
+    }
+
+    /// Set how many requests the page shown had blocked
+    pub fn set_blocked_count(&mut self, blocked: usize) {
+        self.blocked = blocked;
+    }
+
+    pub fn download(&self) -> Option<DownloadIndicator> {
+        self.download.map(|(indicator, _)| indicator)
+    }
+
+    /// Show `indicator` in the badge until `until`
+    pub fn set_download(&mut self, indicator: DownloadIndicator, until: Instant) {
+        self.download = Some((indicator, until));
+    }
+
+    /// When the download indicator shown goes away
+    pub fn download_expiry(&self) -> Option<Instant> {
+        self.download.map(|(_, until)| until)
+    }
+
+    /// Stop showing the download indicator if its time is up; returns
+    /// whether it went away
+    pub fn expire_download(&mut self, now: Instant) -> bool {
+        let expired = self.download.is_some_and(|(_, until)| until <= now);
+        if expired {
+            self.download = None;
+        }
+        expired
     }
 
-    /// Handle keyboard input
+    pub fn prompt(&self) -> Option<&str> {
+        self.prompt.as_deref()
+    }
+
+    /// Ask something in place of the URL, or stop asking with `None`
+    pub fn set_prompt(&mut self, prompt: Option<String>) {
+        self.prompt = prompt;
+    }
+
+    /// Handle keyboard input. Typing over an inline completion keeps what
+    /// still matches; Backspace and Delete remove it, and End and the
+    /// arrow keys keep it, like a selection would.
     pub fn handle_key(&mut self, key: &Key, text: Option<&str>) -> Option<AddressBarAction> {
         match key {
+            Key::Named(NamedKey::Enter) if self.editing.is_some() => {
+                return Some(AddressBarAction::Edited(self.url.clone()));
+            }
             Key::Named(NamedKey::Enter) => {
+                self.accept_completion();
                 return Some(AddressBarAction::Navigate(self.url.clone()));
             }
-            Key::Named(NamedKey::Backspace) => {
-                if !self.url.is_empty() && self.cursor_position > 0 {
-                    self.url.remove(self.cursor_position - 1);
-                    self.cursor_position -= 1;
-                }
+            Key::Named(NamedKey::Backspace | NamedKey::Delete) if !self.completion.is_empty() => {
+                self.completion.clear();
             }
-            Key::Named(NamedKey::Delete) => {
-                if self.cursor_position < self.url.len() {
-                    self.url.remove(self.cursor_position);
-                }
+            Key::Named(NamedKey::Backspace) if self.cursor_position > 0 => {
+                let start = self.previous_boundary();
+                self.url.replace_range(start..self.cursor_position, "");
+                self.cursor_position = start;
             }
-            Key::Named(NamedKey::ArrowLeft) => {
-                if self.cursor_position > 0 {
-                    self.cursor_position -= 1;
-                }
+            Key::Named(NamedKey::Delete) if self.cursor_position < self.url.len() => {
+                let end = self.next_boundary();
+                self.url.replace_range(self.cursor_position..end, "");
             }
-            Key::Named(NamedKey::ArrowRight) => {
-                if self.cursor_position < self.url.len() {
-                    self.cursor_position += 1;
-                }
+            Key::Named(NamedKey::ArrowLeft) if !self.completion.is_empty() => {
+                self.accept_completion();
+            }
+            Key::Named(NamedKey::ArrowLeft) if self.cursor_position > 0 => {
+                self.cursor_position = self.previous_boundary();
+            }
+            Key::Named(NamedKey::ArrowRight | NamedKey::End) if !self.completion.is_empty() => {
+                self.accept_completion();
+                self.cursor_position = self.url.len();
+            }
+            Key::Named(NamedKey::ArrowRight) if self.cursor_position < self.url.len() => {
+                self.cursor_position = self.next_boundary();
             }
             Key::Named(NamedKey::Home) => {
+                self.accept_completion();
                 self.cursor_position = 0;
             }
             Key::Named(NamedKey::End) => {
@@ -71,8 +286,17 @@ impl AddressBar {
             }
             Key::Character(_) => {
                 if let Some(text) = text {
+                    let rest = self
+                        .completion
+                        .get(..text.len())
+                        .filter(|start| start.eq_ignore_ascii_case(text))
+                        .map(|_| self.completion[text.len()..].to_string());
+                    self.completion = rest.unwrap_or_default();
                     self.url.insert_str(self.cursor_position, text);
                     self.cursor_position += text.len();
+                    if self.editing.is_none() {
+                        return Some(AddressBarAction::Typed(self.url.clone()));
+                    }
                 }
             }
             _ => {}
@@ -81,38 +305,82 @@ impl AddressBar {
     }
 
     /// Create a text buffer for rendering the address bar
-    pub fn create_buffer(&self, font_system: &mut FontSystem, width: f32) -> Buffer {
-        let metrics = Metrics::new(18.0, 22.0);
+    pub fn create_buffer(
+        &self,
+        font_system: &mut FontSystem,
+        fonts: &FontSettings,
+        chrome: &ChromeLayout,
+    ) -> Buffer {
+        let size = fonts.clamp_size(chrome.address_font_size);
+        let metrics = Metrics::new(size, size * 1.2);
         let mut buffer = Buffer::new(font_system, metrics);
 
-        buffer.set_size(font_system, Some(width - 40.0), Some(40.0));
+        buffer.set_size(font_system, Some(chrome.address_text_width), Some(chrome.address_text_height));
+
+        let indicator = match self.security_level {
+            Some(SecurityLevel::Secure) => "[secure] ",
+            Some(SecurityLevel::Insecure) => "[not secure] ",
+            Some(SecurityLevel::Internal) => "[internal] ",
+            None => "",
+        };
 
-        let display_text = if self.is_focused {
-            format!("{}|", self.url)
+        let display_text = if let Some(prompt) = &self.prompt {
+            prompt.clone()
+        } else if let Some((label, _)) = &self.editing {
+            format!("{}: {}|", label, self.url)
+        } else if self.is_focused {
+            format!("{}{}|{}", indicator, bidi_safe_url(&self.url), self.completion)
         } else {
-            self.url.clone()
+            format!("{}{}", indicator, bidi_safe_url(&self.url))
         };
 
         buffer.set_text(
             font_system,
             &display_text,
-            Attrs::new().family(Family::Monospace),
+            fonts.mono_attrs(),
             Shaping::Advanced,
         );
 
         buffer
     }
 
-    pub fn background_color(&self) -> [f32; 3] {
+    /// Create a text buffer for the badge, if there is one to show: the
+    /// download indicator, or else the blocked-requests count
+    pub fn create_badge_buffer(
+        &self,
+        font_system: &mut FontSystem,
+        fonts: &FontSettings,
+        chrome: &ChromeLayout,
+    ) -> Option<Buffer> {
+        if self.blocked == 0 && self.download.is_none() {
+            return None;
+        }
+        let size = fonts.clamp_size(chrome.address_font_size * 0.7);
+        let mut buffer = Buffer::new(font_system, Metrics::new(size, size * 1.2));
+        buffer.set_size(font_system, Some(chrome.badge_width), Some(chrome.badge_height));
+        let text = match self.download() {
+            Some(indicator) => indicator.symbol().to_string(),
+            None if self.blocked > 99 => "99+".to_string(),
+            None => self.blocked.to_string(),
+        };
+        buffer.set_text(font_system, &text, fonts.mono_attrs(), Shaping::Advanced);
+        for line in buffer.lines.iter_mut() {
+            line.set_align(Some(Align::Center));
+        }
+        buffer.shape_until_scroll(font_system, false);
+        Some(buffer)
+    }
+
+    pub fn background_color(&self, theme: &Theme) -> Color {
         if self.is_focused {
-            [1.0, 1.0, 1.0] // White when focused
+            theme.chrome_background
         } else {
-            [0.95, 0.95, 0.95] // Light gray when not focused
+            theme.background
         }
     }
 
-    pub fn text_color(&self) -> GlyphonColor {
-        GlyphonColor::rgb(0, 0, 0)
+    pub fn text_color(&self, theme: &Theme) -> GlyphonColor {
+        theme.text.to_glyphon()
     }
 }
 
@@ -122,6 +390,169 @@ impl Default for AddressBar {
     }
 }
 
+/// `url` laid out left to right with its scheme and host isolated, so
+/// right-to-left characters cannot reorder them into another site's name.
+/// URLs without right-to-left characters are left as they are.
+fn bidi_safe_url(url: &str) -> String {
+    let right_to_left = url
+        .chars()
+        .any(|c| matches!(unicode_bidi::bidi_class(c), BidiClass::R | BidiClass::AL));
+    if !right_to_left {
+        return url.to_string();
+    }
+    let host_start = url.find("://").map_or(0, |scheme| scheme + 3);
+    let origin_end = url[host_start..]
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |end| host_start + end);
+    // A left-to-right mark fixes the paragraph direction; the origin sits
+    // in a left-to-right isolate
+    format!("\u{200E}\u{2066}{}\u{2069}{}", &url[..origin_end], &url[origin_end..])
+}
+
 pub enum AddressBarAction {
     Navigate(String),
+    /// Text was typed into the URL, which now reads as given
+    Typed(String),
+    /// The value being edited, as entered
+    Edited(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> AddressBar {
+        let mut bar = AddressBar::new();
+        bar.set_url(String::new());
+        for grapheme in text.graphemes(true) {
+            bar.handle_key(&Key::Character(grapheme.into()), Some(grapheme));
+        }
+        bar
+    }
+
+    fn press(bar: &mut AddressBar, key: NamedKey) -> Option<AddressBarAction> {
+        bar.handle_key(&Key::Named(key), None)
+    }
+
+    #[test]
+    fn test_blank_tabs_show_an_empty_address() {
+        let mut bar = AddressBar::new();
+        bar.show_url(&ValidatedUrl::parse("https://example.com/").unwrap());
+        assert_eq!(bar.url(), "https://example.com/");
+        bar.show_url(&ValidatedUrl::about_blank());
+        assert_eq!(bar.url(), "");
+    }
+
+    #[test]
+    fn test_ctrl_enter_wraps_a_bare_word() {
+        let mut bar = typed("rust");
+        bar.set_completion("rust-lang.org");
+        bar.wrap_bare_word();
+        let action = press(&mut bar, NamedKey::Enter);
+        assert!(matches!(action, Some(AddressBarAction::Navigate(url)) if url == "https://www.rust.com"));
+
+        let mut bar = typed("rust lang");
+        bar.wrap_bare_word();
+        assert_eq!(bar.url(), "rust lang");
+    }
+
+    #[test]
+    fn test_typing_goes_on_over_an_inline_completion() {
+        let mut bar = typed("wik");
+        bar.set_completion("wikipedia.org");
+        assert_eq!((bar.url(), bar.completion()), ("wik", "ipedia.org"));
+
+        let action = bar.handle_key(&Key::Character("I".into()), Some("I"));
+        assert!(matches!(action, Some(AddressBarAction::Typed(input)) if input == "wikI"));
+        assert_eq!((bar.url(), bar.completion()), ("wikI", "pedia.org"));
+        bar.handle_key(&Key::Character("x".into()), Some("x"));
+        assert_eq!((bar.url(), bar.completion()), ("wikIx", ""));
+
+        // Only what was typed is completed, from its end
+        bar.set_completion("wikipedia.org");
+        assert_eq!(bar.completion(), "");
+        press(&mut bar, NamedKey::ArrowLeft);
+        bar.set_completion("wikixyz.org");
+        assert_eq!(bar.completion(), "");
+    }
+
+    #[test]
+    fn test_backspace_and_delete_remove_the_completion_first() {
+        for key in [NamedKey::Backspace, NamedKey::Delete] {
+            let mut bar = typed("wik");
+            bar.set_completion("wikipedia.org");
+            press(&mut bar, key);
+            assert_eq!((bar.url(), bar.completion()), ("wik", ""));
+        }
+        let mut bar = typed("wik");
+        bar.set_completion("wikipedia.org");
+        press(&mut bar, NamedKey::Backspace);
+        press(&mut bar, NamedKey::Backspace);
+        assert_eq!(bar.url(), "wi");
+    }
+
+    #[test]
+    fn test_end_right_and_enter_accept_the_completion() {
+        for key in [NamedKey::End, NamedKey::ArrowRight] {
+            let mut bar = typed("wik");
+            bar.set_completion("wikipedia.org");
+            press(&mut bar, key);
+            assert_eq!((bar.url(), bar.completion()), ("wikipedia.org", ""));
+            bar.handle_key(&Key::Character("/".into()), Some("/"));
+            assert_eq!(bar.url(), "wikipedia.org/");
+        }
+
+        let mut bar = typed("wik");
+        bar.set_completion("wikipedia.org");
+        let action = press(&mut bar, NamedKey::Enter);
+        assert!(matches!(action, Some(AddressBarAction::Navigate(url)) if url == "wikipedia.org"));
+
+        // Leaving the address bar drops it
+        let mut bar = typed("wik");
+        bar.set_completion("wikipedia.org");
+        bar.set_focused(false);
+        assert_eq!((bar.url(), bar.completion()), ("wik", ""));
+    }
+
+    #[test]
+    fn test_cursor_moves_and_deletes_whole_graphemes() {
+        let mut bar = typed("ae\u{301}👍🏽");
+        press(&mut bar, NamedKey::Backspace);
+        assert_eq!(bar.url(), "ae\u{301}");
+        press(&mut bar, NamedKey::ArrowLeft);
+        press(&mut bar, NamedKey::Delete);
+        assert_eq!(bar.url(), "a");
+
+        let mut bar = typed("e\u{301}x");
+        press(&mut bar, NamedKey::Home);
+        press(&mut bar, NamedKey::ArrowRight);
+        bar.insert_text("-");
+        assert_eq!(bar.url(), "e\u{301}-x");
+    }
+
+    #[test]
+    fn test_right_to_left_urls_keep_their_origin_first() {
+        assert_eq!(bidi_safe_url("https://example.com/a?b"), "https://example.com/a?b");
+        assert_eq!(
+            bidi_safe_url("https://xn--4dbrk0ce.example/שלום"),
+            "\u{200E}\u{2066}https://xn--4dbrk0ce.example\u{2069}/שלום"
+        );
+        assert_eq!(bidi_safe_url("https://שלום.example"), "\u{200E}\u{2066}https://שלום.example\u{2069}");
+        assert_eq!(bidi_safe_url("مرحبا"), "\u{200E}\u{2066}مرحبا\u{2069}");
+
+        // The host stays in logical order once shaped
+        let mut font_system = crate::ui::fonts::build_font_system(&FontSettings::default());
+        let text = bidi_safe_url("https://example.com/שלום/123");
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(10.0, 12.0));
+        buffer.set_size(&mut font_system, Some(1000.0), None);
+        buffer.set_text(&mut font_system, &text, glyphon::Attrs::new(), Shaping::Advanced);
+        let run = buffer.layout_runs().next().unwrap();
+        assert!(!run.rtl);
+        let x_of = |needle: &str| {
+            let index = text.find(needle).unwrap();
+            run.glyphs.iter().find(|glyph| glyph.start == index).unwrap().x
+        };
+        assert!(x_of("https") < x_of("example"));
+        assert!(x_of("example") < x_of("שלום"));
+    }
 }
@@ -0,0 +1,3750 @@
+use super::chrome::{parse_ui_scale, step_ui_scale, UI_SCALE_SETTING};
+use super::clipboard::Clipboard;
+use super::dark_pages::{dark_page_theme, declares_dark, parse_dark_pages, DarkPageCache, DARK_PAGES_SETTING};
+use super::caret::{Caret, CaretLayout, CaretMotion};
+use super::fonts::{add_extra_fonts, pdf_fonts, FontSettings};
+use super::hints::{HintAction, LinkHints};
+use super::hit_regions::{parse_hit_regions, HitOverlay, HitRegionKind, HIT_REGIONS_SETTING};
+use super::overview::{overview_key, OverviewAction, OverviewItem, OverviewLayout};
+use super::palette::{CommandPalette, PALETTE_LABEL};
+use super::screenshot::OffscreenCapture;
+use super::scroll::{parse_reduced_motion, ScrollState};
+use super::scrollbar::{Scrollbar, ScrollbarHit};
+use super::selection::{line_range, word_range, ClickCounter, Selection};
+use super::text_renderer::{ContentLayoutOptions, WRAP_PREFORMATTED_SETTING};
+use super::theme::{parse_accessibility, Theme, ThemePreference, ACCESSIBILITY_SETTING};
+use super::thumbnails::{placeholder_letter, ThumbnailCache, THUMBNAIL_WIDTH};
+use super::toasts::ToastManager;
+use super::{AddressBar, AddressBarAction, BrowserWindow, DownloadIndicator, PageFrame, Renderer, StartingRenderer};
+use crate::application::{
+    format_chords, listed_settings, parse_blocked_domains, parse_notification_duration, parse_suspend_after,
+    setting_enabled, BrowserController, BrowserState, Chord, Command, CommandHandler, ExternalLaunch, ImportMode,
+    ImportOptions, Keymap, LaunchAnswer, Modifiers, NavigationOutcome, Notification, NotificationSeverity, PaletteTarget, PendingLaunch,
+    Repositories, SaveFormat, SessionDownloads, StateEvent, TabActivity, ThrottlePolicy, BLOCKED_DOMAINS_SETTING,
+    COMMANDS, CONTENT_BLOCKER_SETTING, COOKIES_SETTING, CURRENT_VERSION, DOWNLOADS_PAGE,
+    EDITABLE_SETTINGS, ENFORCE_CSP_SETTING, FILE_ACCESS_ROOTS_SETTING, FILE_ACCESS_SETTING, HOMEPAGE_SETTING,
+    MEMORY_PAGE, NOTIFICATION_SECONDS_SETTING, OFFLINE_SETTING, PROFILE_EXPORT_HISTORY_SETTING,
+    PROFILE_EXPORT_SETTING, PROFILE_IMPORT_SETTING, SITE_SETTING_LABELS,
+    SITE_STORAGE_ROW, SITE_USER_AGENT_ROW, SHORTCUTS_PAGE, SUSPEND_AFTER_SETTING, THEME_SETTING, UPDATE_PAGE,
+    USER_AGENT_SETTING, next_site_setting, reset_site_setting, site_setting_values, site_storage_value, tab_switch, top_completion,
+};
+use crate::domain::{
+    BlockedSummary, Certificate, CspViolation, CustomCaRepository, DownloadRepository, HistoryRepository,
+    HttpVersion, MemoryReporter, NewSearchEngine, OpenSearchFetcher, PageCacheRepository, PublicSuffixList,
+    RenderingEngine, SavedWindow, SearchEngineRepository, SecurityLevel, SettingsRepository, SiteOrigin,
+    SiteSetting, Tab, TabId, UserScriptRepository, UserStyleService, ValidatedUrl, VisitType,
+    WindowId as BrowserWindowId,
+};
+use crate::infrastructure::{
+    AccessibilityMode, ContentBlocker, CookieJar, CspEnforcement, DefaultSecurityService, DefaultUserScriptService,
+    DefaultUserStyleService, DiscoveredSearchEngines, FileAccess, FileAccessPolicy, OpenSearchDescriptions,
+    PageCapture, PageContent, parse_allowed_ports, open_containing_folder, spawn_supervised, BookmarksView, Diagnostics,
+    DownloadsView, LocalDownloadWriter, MemoryView, MhtmlArchiver, OfflineMode, PdfPrinter, PortPolicy, ProfileDir,
+    RenderingConfig, SecureNetworkClient, ServoRenderer, SettingsView, ShortcutsView, SiteMetadata,
+    SiteSettingsView, SqliteDatabase, SystemLauncher, TrackingParameterCleaner, UpdateManifests, UpdateView,
+    ALLOWED_PORTS_SETTING, EXTERNAL_SCHEMES_SETTING, MAINTENANCE_INTERVAL, SESSION_RUNNING_SETTING,
+    write_atomically,
+};
+
+use anyhow::Result;
+use glyphon::FontSystem;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::error::RecvError;
+use winit::{
+    application::ApplicationHandler,
+    event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoopProxy},
+    keyboard::{Key, ModifiersState, NamedKey},
+    window::WindowId,
+};
+
+/// Browser page listing the settings edited from the keyboard
+const SETTINGS_PAGE: &str = "navigator://settings";
+/// Browser page listing one site's overrides of the settings, given as
+/// `?origin=`
+const SITE_SETTINGS_PAGE: &str = "navigator://site-settings";
+/// Browser page listing what the tab's scripts logged
+const CONSOLE_PAGE: &str = "navigator://console";
+/// Browser page listing the bookmarks, reordered from the keyboard
+const BOOKMARKS_PAGE: &str = "navigator://bookmarks";
+/// Shortest time between two refreshes of navigator://downloads while
+/// downloads change
+const DOWNLOADS_REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the address bar shows that a download started or finished
+const DOWNLOAD_INDICATOR_DURATION: Duration = Duration::from_secs(3);
+/// Label of an expression typed on navigator://console
+const CONSOLE_INPUT_LABEL: &str = "Evaluate";
+/// Window title, following the page title once one is known
+const WINDOW_TITLE: &str = "Navigator";
+/// Lines scrolled per mouse wheel notch
+const WHEEL_SCROLL_LINES: f32 = 3.0;
+/// A full Public Suffix List saved next to the profile database, used
+/// instead of the built-in snapshot
+const PUBLIC_SUFFIX_LIST_FILE: &str = "public_suffix_list.dat";
+/// How often hidden tabs are checked for suspension
+const SUSPEND_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the memory caches hold is checked against the ceiling
+const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// How long shutting down may take before the app exits regardless
+const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(3);
+/// Typed into the address bar, panics to exercise crash reporting
+#[cfg(debug_assertions)]
+const CRASH_PAGE: &str = "navigator://crash";
+
+/// Wake-ups delivered to the event loop from background tasks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserEvent {
+    State(StateEvent),
+    /// A navigation finished and waits for the window's next frame
+    Arrived(WindowId),
+    /// State events were dropped; refresh every window
+    Resync,
+    /// The search engine the window's page offers, once fetched; `None`
+    /// when there is nothing new to add
+    SearchEngineOffered(WindowId, Option<NewSearchEngine>),
+    /// Saving the window's page finished; says whether it worked
+    PageSaved(WindowId, bool),
+    /// A setting changed from the window's settings page was stored, or
+    /// refused for the reason given
+    SettingSaved(WindowId, Option<String>),
+    /// An expression typed on the window's console page was evaluated
+    ConsoleEvaluated(WindowId),
+    /// The window's navigation failed after several fetches in a row could
+    /// not reach the network
+    ConnectivityLost(WindowId),
+    /// A link the window followed went to another program, or waits for
+    /// the user to say it may
+    ExternalLaunch(WindowId, ExternalLaunch),
+    /// Downloading the update for the window finished; says whether it
+    /// worked
+    UpdateDownloaded(WindowId, bool),
+    /// The profile finished opening, or failed to
+    DbReady,
+    /// The system asked the app to quit, e.g. with Ctrl+C or SIGTERM
+    ShutdownRequested,
+}
+
+/// Services shared by every window
+pub struct SharedServices {
+    /// Tabs, navigation and browsing data
+    pub controller: Arc<BrowserController<ServoRenderer>>,
+    /// Profile storage, read directly only for settings
+    pub db: Arc<SqliteDatabase>,
+    pub network: Arc<SecureNetworkClient>,
+    /// Refuses requests to blocked domains while turned on
+    pub blocker: Arc<ContentBlocker>,
+    /// Whether every tab lays its page out for screen readers
+    pub accessibility: AccessibilityMode,
+    /// Whether tabs stay off the network and show pages kept for offline use
+    pub offline: OfflineMode,
+    /// Whether pages' content security policies are enforced or only
+    /// reported
+    pub csp_enforcement: CspEnforcement,
+    /// Which local files file:// URLs may open, for the security service
+    /// and every tab
+    pub file_access: Arc<FileAccess>,
+}
+
+impl SharedServices {
+    /// Wind the controller down, then mark the session as ended cleanly
+    /// and fold the WAL back into the database; call once before exiting
+    pub async fn shutdown(&self) -> Result<()> {
+        self.controller.shutdown().await;
+        self.db.set(SESSION_RUNNING_SETTING, "false").await?;
+        self.db.checkpoint().await?;
+        Ok(())
+    }
+
+    /// Must be called inside a Tokio runtime, which runs the history writer.
+    /// `passphrase` unlocks an encrypted profile.
+    pub async fn new(profile: Arc<ProfileDir>, passphrase: Option<&str>, diagnostics: Diagnostics) -> Result<Self> {
+        let db = Arc::new(SqliteDatabase::open(profile.database_path(), passphrase).await?);
+        let suffixes = profile.directory().join(PUBLIC_SUFFIX_LIST_FILE);
+        if let Ok(text) = std::fs::read_to_string(&suffixes) {
+            tracing::info!("Using the public suffix list in {}", suffixes.display());
+            PublicSuffixList::install(PublicSuffixList::parse(&text));
+        }
+        db.spawn_maintenance(MAINTENANCE_INTERVAL);
+        diagnostics.levels.attach(db.clone()).await?;
+
+        let ports = Arc::new(PortPolicy::new());
+        if let Some(allowed) = db.get(ALLOWED_PORTS_SETTING).await? {
+            for port in parse_allowed_ports(&allowed) {
+                ports.allow(port);
+            }
+        }
+        let file_access = Arc::new(FileAccess::new(file_access_policy(
+            db.get(FILE_ACCESS_SETTING).await?.as_deref(),
+            db.get(FILE_ACCESS_ROOTS_SETTING).await?.as_deref(),
+        )));
+        // The security service checks data: URLs against the limit the
+        // renderers decode them with
+        let rendering = RenderingConfig::default();
+        let security = DefaultSecurityService::new()
+            .with_port_policy(ports.clone())
+            .with_shared_file_access(file_access.clone())
+            .with_max_data_url_size(rendering.max_data_url_size);
+        if let Some(schemes) = db.get(EXTERNAL_SCHEMES_SETTING).await? {
+            security.add_external_scheme(&schemes);
+        }
+        // Blocked domains are also refused for redirects and page resources
+        let blocker = Arc::new(ContentBlocker::new());
+        if let Some(blocked) = db.get(BLOCKED_DOMAINS_SETTING).await? {
+            for domain in parse_blocked_domains(&blocked) {
+                blocker.add_host(&domain);
+                security.add_blocked_domain(domain);
+            }
+        }
+        let offline = OfflineMode::new();
+        let network = Arc::new(
+            SecureNetworkClient::new()?
+                .with_offline_mode(offline.clone())
+                .with_port_policy(ports)
+                .with_cookie_jar(Arc::new(CookieJar::new()))
+                .with_site_settings(db.clone())
+                .with_interceptor(Arc::new(TrackingParameterCleaner::new()))
+                .with_interceptor(blocker.clone()),
+        );
+        let engine_network = network.clone();
+        let engine_blocker = blocker.clone();
+        let engine_file_access = file_access.clone();
+        let engine_history: Arc<dyn HistoryRepository> = db.clone();
+        let engine_page_cache: Arc<dyn PageCacheRepository> = db.clone();
+        let engine_search_engines: Arc<dyn SearchEngineRepository> = db.clone();
+        let engine_custom_cas: Arc<dyn CustomCaRepository> = db.clone();
+        let engine_user_scripts: Arc<dyn UserScriptRepository> = db.clone();
+        let discovered = DiscoveredSearchEngines::new();
+        let opensearch: Arc<dyn OpenSearchFetcher> = Arc::new(OpenSearchDescriptions::new(network.clone()));
+        let engine_opensearch = opensearch.clone();
+        let downloads = Arc::new(SessionDownloads::new(db.clone()));
+        let engine_downloads: Arc<dyn DownloadRepository> = downloads.clone();
+
+        let settings = db.get_all().await?;
+        let fonts = FontSettings::from_settings(&settings);
+        let accessibility = AccessibilityMode::new();
+        accessibility.set_enabled(parse_accessibility(&settings));
+        let engine_accessibility = accessibility.clone();
+        let capture: Arc<dyn PageCapture> = Arc::new(OffscreenCapture::new(fonts.clone(), Theme::default()));
+        let csp_enforcement = CspEnforcement::new();
+        let enforce_csp = db.get(ENFORCE_CSP_SETTING).await?;
+        csp_enforcement.set_enabled(setting_enabled(ENFORCE_CSP_SETTING, enforce_csp.as_deref()));
+        let engine_csp_enforcement = csp_enforcement.clone();
+        let mut throttle = ThrottlePolicy::new();
+        let suspend_after = db.get(SUSPEND_AFTER_SETTING).await?;
+        if let Some(suspend_after) = suspend_after.as_deref().and_then(parse_suspend_after) {
+            throttle = throttle.with_suspend_after(suspend_after);
+        }
+
+        let user_styles: Arc<dyn UserStyleService> = Arc::new(DefaultUserStyleService::new());
+        let engine_styles = user_styles.clone();
+        let controller = BrowserController::new(
+            BrowserState::new(),
+            Repositories::shared(db.clone()),
+            Arc::new(security),
+            user_styles,
+            Arc::new(DefaultUserScriptService::new()),
+            move || {
+                ServoRenderer::with_config(rendering.clone())
+                    .with_user_styles(engine_styles.clone())
+                    .with_accessibility(engine_accessibility.clone())
+                    .with_csp_enforcement(engine_csp_enforcement.clone())
+                    .with_network(engine_network.clone())
+                    .with_file_access(engine_file_access.clone())
+                    .with_content_blocker(engine_blocker.clone())
+                    .with_diagnostics(diagnostics.clone())
+                    .with_history(engine_history.clone())
+                    .with_page_cache(engine_page_cache.clone())
+                    .with_downloads(engine_downloads.clone())
+                    .with_search_engines(
+                        engine_search_engines.clone(),
+                        discovered.clone(),
+                        engine_opensearch.clone(),
+                    )
+                    .with_custom_cas(engine_custom_cas.clone())
+                    .with_user_scripts(engine_user_scripts.clone())
+                    .with_capture(capture.clone())
+            },
+        )
+        .with_throttle_policy(throttle)
+        .with_opensearch_fetcher(opensearch)
+        .with_downloads(downloads, Arc::new(LocalDownloadWriter::new().with_profile(profile)))
+        .with_page_archiver(Arc::new(MhtmlArchiver::new(network.clone())))
+        .with_trust_store(network.clone())
+        .with_tls_inspector(network.clone())
+        .with_external_launcher(Arc::new(SystemLauncher::new()))
+        .with_update_source(Arc::new(UpdateManifests::new(network.clone())))
+        .with_site_metadata(Arc::new(SiteMetadata::new(network.clone())))
+        .with_page_printer(Arc::new(PdfPrinter::new().with_font_loader(move || pdf_fonts(&fonts))));
+        controller.load_user_styles().await?;
+        controller.load_user_scripts().await?;
+        controller.load_custom_cas().await?;
+        controller.load_settings().await?;
+        // Nothing has loaded through these yet, so they can wait for the
+        // settings
+        let settings = controller.settings();
+        blocker.set_enabled(settings.privacy.content_blocker);
+        offline.set_enabled(settings.network.offline);
+        network.set_user_agent(Some(&settings.network.user_agent));
+        network.set_cookie_blocking(settings.network.cookies);
+        Ok(Self {
+            controller: Arc::new(controller),
+            db,
+            network,
+            blocker,
+            accessibility,
+            offline,
+            csp_enforcement,
+            file_access,
+        })
+    }
+}
+
+/// The file access policy given the stored values of its settings
+pub fn file_access_policy(enabled: Option<&str>, roots: Option<&str>) -> FileAccessPolicy {
+    FileAccessPolicy::from_settings(
+        setting_enabled(FILE_ACCESS_SETTING, enabled),
+        roots.unwrap_or_default(),
+    )
+}
+
+impl BrowserController<ServoRenderer> {
+    /// A controller over a throwaway profile, for tests: its database is in
+    /// memory and its downloads go to a temporary directory that is removed
+    /// once the controller is dropped
+    pub async fn ephemeral() -> Result<Self> {
+        Self::ephemeral_with(ServoRenderer::new).await
+    }
+}
+
+impl<E: RenderingEngine + 'static> BrowserController<E> {
+    /// An ephemeral controller whose tabs load pages with `new_engine`'s
+    /// engines
+    pub async fn ephemeral_with(new_engine: impl Fn() -> E + Send + Sync + 'static) -> Result<Self> {
+        let profile = Arc::new(ProfileDir::temporary()?);
+        let db = Arc::new(SqliteDatabase::new(profile.database_path()).await?);
+        let controller = BrowserController::new(
+            BrowserState::new(),
+            Repositories::shared(db.clone()),
+            Arc::new(DefaultSecurityService::new()),
+            Arc::new(DefaultUserStyleService::new()),
+            Arc::new(DefaultUserScriptService::new()),
+            new_engine,
+        )
+        .with_downloads(
+            Arc::new(SessionDownloads::new(db)),
+            Arc::new(LocalDownloadWriter::new().with_profile(profile)),
+        );
+        Ok(controller)
+    }
+}
+
+/// Where to scroll once a page has been laid out
+#[derive(Debug, Clone, PartialEq)]
+enum ScrollTarget {
+    Offset(f32),
+    /// Element named by a URL fragment; the top of the page if it is missing
+    Fragment(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PendingScroll {
+    target: ScrollTarget,
+    animate: bool,
+}
+
+/// A finished navigation waiting for the next frame
+#[derive(Debug, Clone, PartialEq)]
+struct Arrival {
+    url: ValidatedUrl,
+    scroll: PendingScroll,
+}
+
+/// Navigations a window hands to the controller
+enum NavigationRequest {
+    /// Load the input, recording the visit as the given type
+    Go(String, VisitType),
+    Back,
+    Forward,
+    Reload,
+}
+
+/// Page content shown in one window
+struct PageView {
+    /// The tab's engine, owned by the controller
+    html_renderer: Arc<ServoRenderer>,
+    /// Immutable layout result and its generation, swapped whole when a page
+    /// finishes loading
+    content: RwLock<(u64, Arc<PageContent>)>,
+    /// Applied by the first frame laid out after it is set
+    arrival: Mutex<Option<Arrival>>,
+}
+
+impl PageView {
+    fn new(html_renderer: Arc<ServoRenderer>) -> Self {
+        Self {
+            html_renderer,
+            content: RwLock::new((0, Arc::new(PageContent::default()))),
+            arrival: Mutex::new(None),
+        }
+    }
+
+    fn content(&self) -> Arc<PageContent> {
+        self.versioned_content().1
+    }
+
+    /// Current content with a generation that changes whenever it is replaced
+    fn versioned_content(&self) -> (u64, Arc<PageContent>) {
+        self.content
+            .read()
+            .map(|content| content.clone())
+            .unwrap_or_default()
+    }
+
+    fn set_content(&self, content: Arc<PageContent>) {
+        if let Ok(mut current) = self.content.write() {
+            if !Arc::ptr_eq(&current.1, &content) {
+                *current = (current.0 + 1, content);
+            }
+        }
+    }
+
+    /// Show where a navigation ended up: lay out a newly loaded document and
+    /// scroll on the next frame, animating only within the same document
+    fn arrive(&self, outcome: NavigationOutcome) {
+        if !outcome.same_document {
+            self.set_content(self.html_renderer.layout());
+        }
+
+        let target = match (outcome.scroll_offset, outcome.url.fragment()) {
+            (Some(offset), _) => ScrollTarget::Offset(offset),
+            (None, Some(fragment)) => ScrollTarget::Fragment(fragment.to_string()),
+            (None, None) => ScrollTarget::Offset(0.0),
+        };
+        let arrival = Arrival {
+            url: outcome.url,
+            scroll: PendingScroll {
+                target,
+                animate: outcome.same_document,
+            },
+        };
+        if let Ok(mut pending) = self.arrival.lock() {
+            *pending = Some(arrival);
+        }
+    }
+
+    fn take_arrival(&self) -> Option<Arrival> {
+        self.arrival.lock().ok()?.take()
+    }
+
+    /// Security level of the page currently displayed
+    fn security_level(&self) -> Option<SecurityLevel> {
+        self.html_renderer
+            .current_url()
+            .map(|url| url.security_level())
+    }
+}
+
+/// GPU resources and UI state owned by a single window
+struct WindowContext {
+    id: BrowserWindowId,
+    /// The window's only tab
+    tab: TabId,
+    window: BrowserWindow,
+    renderer: Renderer,
+    address_bar: AddressBar,
+    page: Arc<PageView>,
+    scroll: ScrollState,
+    scrollbar: Scrollbar,
+    /// Notifications stacked over the bottom-right corner
+    toasts: ToastManager,
+    /// Last cursor position in physical pixels
+    cursor: Option<(f32, f32)>,
+    /// Jump instead of animating scroll changes
+    reduced_motion: bool,
+    /// Link under the cursor when the left button went down
+    pressed_link: Option<String>,
+    hovered_link: Option<String>,
+    selection: Option<Selection>,
+    /// Left button held down after pressing in page content
+    selecting: bool,
+    /// Column the caret keeps to while moved up and down in caret browsing
+    caret_goal: Option<f32>,
+    clicks: ClickCounter,
+    /// Selected card while the tab overview covers the page
+    overview: Option<usize>,
+    /// Labels over the links in view while picking one by keyboard
+    hints: Option<LinkHints>,
+    /// Open tab that entering the given text in the address bar switches
+    /// to instead of loading it
+    tab_switch: Option<(String, TabId)>,
+    /// What matches the query typed in the address bar while the command
+    /// palette is open
+    palette: Option<CommandPalette>,
+    /// The page recolored dark, while dark pages are on
+    dark_page: DarkPageCache,
+    /// A new document was shown; capture its thumbnail after the next frame
+    thumbnail_due: bool,
+    /// Search engine the address bar asks to add
+    search_offer: Option<NewSearchEngine>,
+    /// Link the address bar asks to open in another program
+    external_launch: Option<PendingLaunch>,
+    /// Setting under the keyboard focus on navigator://settings or
+    /// navigator://site-settings
+    settings_focus: usize,
+    /// Why the last change made on either settings page was refused
+    settings_error: Option<String>,
+    /// What the page navigator://site-settings was opened from had blocked
+    site_blocked: BlockedSummary,
+    /// Protocol that page came over
+    site_protocol: Option<HttpVersion>,
+    /// Certificate that page's server presented
+    site_certificate: Option<Certificate>,
+    /// Loads that page's content security policy did not allow
+    site_csp_violations: Vec<CspViolation>,
+}
+
+impl WindowContext {
+    /// The page as drawn, with the colors it is drawn in if not the
+    /// chrome's: recolored when dark pages are on for its site and it is
+    /// not dark already
+    fn drawn_content(&mut self, dark_pages: bool, theme: &Theme) -> (u64, Arc<PageContent>, Option<Theme>) {
+        let (generation, content) = self.page.versioned_content();
+        let dark_pages = self.page.html_renderer.effective_config().dark_pages.unwrap_or(dark_pages);
+        if !dark_pages || declares_dark(&content) {
+            return (generation, content, None);
+        }
+        let page_theme = dark_page_theme(theme);
+        let darkened = self.dark_page.get_or_darken(generation, &content, &page_theme);
+        (generation, darkened, Some(page_theme))
+    }
+
+    /// Scroll the page, animating unless `smooth` is off or motion is reduced
+    fn scroll_by(&mut self, delta: f32, smooth: bool) {
+        let changed = if smooth && !self.reduced_motion {
+            self.scroll.smooth_scroll_by(delta)
+        } else {
+            self.scroll.scroll_by(delta)
+        };
+        if changed {
+            // Labels are only placed on the links that were in view
+            self.hints = None;
+            self.scrollbar.show(Instant::now());
+            self.window.request_redraw();
+        }
+    }
+
+    /// The caret: the selection's focus, or else the start of the text in view
+    fn caret(&self) -> Caret {
+        match self.selection {
+            Some(selection) => Caret {
+                offset: selection.focus,
+                goal_x: self.caret_goal,
+            },
+            None => {
+                let top = self.renderer.visible_layout_span(&self.scroll).start.max(0.0);
+                let offset = self.renderer.content_layout().and_then(|layout| layout.hit(0.0, top));
+                Caret::new(offset.unwrap_or(0))
+            }
+        }
+    }
+
+    /// Move the caret, extending the selection from its anchor if `extend`
+    /// and otherwise collapsing it onto the caret
+    fn move_caret(&mut self, motion: CaretMotion, extend: bool) {
+        let content = self.page.content();
+        let Some(layout) = self.renderer.content_layout() else {
+            return;
+        };
+        let caret = self.caret().moved(motion, &content.text, layout, self.scroll.page_step());
+        let anchor = match self.selection {
+            Some(selection) if extend => selection.anchor,
+            _ => caret.offset,
+        };
+        self.selection = Some(Selection { anchor, focus: caret.offset });
+        self.caret_goal = caret.goal_x;
+        self.reveal_caret(caret.offset);
+        self.window.request_redraw();
+    }
+
+    /// Scroll just enough to bring the line holding the caret into view
+    fn reveal_caret(&mut self, offset: usize) {
+        let Some((_, top, height)) = self.renderer.content_layout().and_then(|layout| layout.caret_position(offset))
+        else {
+            return;
+        };
+        // Measured against where the page is scrolling to, not where it is now
+        let span = self.renderer.visible_layout_span(&self.scroll);
+        let padding = self.scroll.offset() - span.start;
+        let start = self.scroll.target() - padding;
+        let end = start + (span.end - span.start);
+        if top < start {
+            self.scroll_to(top + padding);
+        } else if top + height > end {
+            self.scroll_to(top + height - (span.end - span.start) + padding);
+        }
+    }
+
+    /// Label the links in view, or leave hints mode if there are none
+    fn place_hints(&mut self) {
+        let (generation, content) = self.page.versioned_content();
+        let ranges: Vec<_> = content.links.iter().map(|link| link.range.clone()).collect();
+        let span = self.renderer.visible_layout_span(&self.scroll);
+        let rects = self.renderer.range_rects(&ranges, span.clone());
+        let hints = LinkHints::new(generation, &rects, span);
+        self.hints = (!hints.is_empty()).then_some(hints);
+        self.window.request_redraw();
+    }
+
+    /// Scroll to an absolute offset (page ends, anchors)
+    fn scroll_to(&mut self, offset: f32) {
+        let delta = offset - self.scroll.target();
+        self.scroll_by(delta, true);
+    }
+
+    /// Keyboard scrolling while the page has focus; returns whether the key was used
+    fn handle_scroll_key(&mut self, key: &Key, shift: bool, line_height: f32) -> bool {
+        let page = self.scroll.page_step();
+        match key {
+            Key::Named(NamedKey::Space) => self.scroll_by(if shift { -page } else { page }, true),
+            Key::Named(NamedKey::PageDown) => self.scroll_by(page, true),
+            Key::Named(NamedKey::PageUp) => self.scroll_by(-page, true),
+            Key::Named(NamedKey::ArrowDown) => self.scroll_by(line_height * WHEEL_SCROLL_LINES, true),
+            Key::Named(NamedKey::ArrowUp) => self.scroll_by(-line_height * WHEEL_SCROLL_LINES, true),
+            Key::Named(NamedKey::Home) => self.scroll_to(0.0),
+            Key::Named(NamedKey::End) => self.scroll_to(self.scroll.max_offset()),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Show in the address bar how a download from this window is going,
+    /// for a few seconds
+    fn show_download(&mut self, indicator: DownloadIndicator) {
+        let until = Instant::now() + DOWNLOAD_INDICATOR_DURATION;
+        self.address_bar.set_download(indicator, until);
+        self.window.request_redraw();
+    }
+
+    /// Whether this window needs frames without further input, and if not,
+    /// when it next will
+    fn next_frame(&self, now: Instant) -> Option<Instant> {
+        if self.scroll.is_animating() {
+            return Some(now);
+        }
+        let changes = [
+            self.scrollbar.next_change(now),
+            self.address_bar.download_expiry(),
+            self.toasts.next_change(now),
+        ];
+        changes.into_iter().flatten().min()
+    }
+
+    fn cursor_moved(&mut self, x: f32, y: f32) {
+        self.cursor = Some((x, y));
+        self.hovered_link = self.link_under_cursor();
+        if self.selecting {
+            self.extend_selection(x, y);
+        }
+        let now = Instant::now();
+        let hit = self.renderer.hit_regions().kind_at(x, y);
+        self.toasts.set_hovered(matches!(hit, Some(HitRegionKind::Toast(_))), now);
+        let opacity = self.scrollbar.opacity(now);
+        let Some(geometry) = self.renderer.scrollbar_geometry(&self.scroll) else {
+            self.scrollbar.set_hovered(false, now);
+            return;
+        };
+
+        if let Some(offset) = self.scrollbar.drag_to(&geometry, y, now) {
+            // Dragging tracks the cursor directly, never animated
+            if self.scroll.set_offset(offset) {
+                self.hints = None;
+                self.window.request_redraw();
+            }
+        }
+        self.scrollbar.set_hovered(matches!(hit, Some(HitRegionKind::Scrollbar(_))), now);
+        if self.scrollbar.opacity(now) != opacity {
+            self.window.request_redraw();
+        }
+    }
+
+    fn cursor_left(&mut self) {
+        self.cursor = None;
+        self.hovered_link = None;
+        let now = Instant::now();
+        self.scrollbar.set_hovered(false, now);
+        self.toasts.set_hovered(false, now);
+    }
+
+    /// Move the selection focus to the text under a point
+    fn extend_selection(&mut self, x: f32, y: f32) {
+        let Some(offset) = self.renderer.hit_test_content(x, y, &self.scroll) else {
+            return;
+        };
+        if let Some(selection) = &mut self.selection {
+            if selection.focus != offset {
+                selection.focus = offset;
+                self.window.request_redraw();
+            }
+        }
+    }
+
+    /// Start a selection at a point: plain clicks place a caret, double
+    /// clicks select a word and triple clicks a whole line
+    fn begin_selection(&mut self, x: f32, y: f32, now: Instant) {
+        let clicks = self.clicks.click(x, y, now);
+        self.caret_goal = None;
+        let Some(offset) = self.renderer.hit_test_content(x, y, &self.scroll) else {
+            self.selection = None;
+            return;
+        };
+
+        let content = self.page.content();
+        self.selection = Some(match clicks {
+            2 => Selection::from_range(word_range(&content.text, offset)),
+            3 => Selection::from_range(line_range(&content.text, offset)),
+            _ => Selection::collapsed(offset),
+        });
+        self.selecting = true;
+    }
+
+    /// Selected page text, if any
+    fn selected_text(&self) -> Option<String> {
+        let content = self.page.content();
+        self.selection?.text(&content.text).map(str::to_string)
+    }
+
+    fn select_all(&mut self) {
+        let content = self.page.content();
+        self.selection = Some(Selection::from_range(0..content.text.len()));
+        self.window.request_redraw();
+    }
+
+    /// Link target under the cursor, if any
+    fn link_under_cursor(&self) -> Option<String> {
+        let (x, y) = self.cursor?;
+        let Some(HitRegionKind::Link(index)) = self.renderer.hit_regions().kind_at(x, y) else {
+            return None;
+        };
+        let content = self.page.content();
+        content.links.get(index).map(|link| link.href.clone())
+    }
+
+    /// Handle a left button press or release; returns a link to follow
+    fn mouse_button(&mut self, state: ElementState) -> Option<String> {
+        let now = Instant::now();
+        if state == ElementState::Released {
+            self.scrollbar.end_drag(now);
+            self.selecting = false;
+            // Follow a link only when released over the one that was pressed,
+            // and not at the end of a selection drag
+            let pressed = self.pressed_link.take()?;
+            let selected = self.selection.is_some_and(|s| !s.is_empty());
+            return (!selected && self.link_under_cursor().as_ref() == Some(&pressed)).then_some(pressed);
+        }
+
+        let (x, y) = self.cursor?;
+        self.hints = None;
+
+        // Clicking the chrome focuses the address bar, clicking the page blurs it
+        let hit = self.renderer.hit_regions().kind_at(x, y);
+        self.address_bar
+            .set_focused(matches!(hit, Some(HitRegionKind::AddressBar | HitRegionKind::Badge)));
+        self.window.request_redraw();
+
+        let page = self.scroll.page_step();
+        match hit {
+            Some(HitRegionKind::Scrollbar(ScrollbarHit::Thumb)) => {
+                if let Some(geometry) = self.renderer.scrollbar_geometry(&self.scroll) {
+                    // Grabbing the thumb stops any animation in progress
+                    self.scroll.set_offset(self.scroll.offset());
+                    self.scrollbar.begin_drag(&geometry, y, now);
+                }
+            }
+            Some(HitRegionKind::Scrollbar(ScrollbarHit::TrackBefore)) => self.scroll_by(-page, true),
+            Some(HitRegionKind::Scrollbar(ScrollbarHit::TrackAfter)) => self.scroll_by(page, true),
+            Some(HitRegionKind::Content | HitRegionKind::Gutter | HitRegionKind::Link(_)) => {
+                self.pressed_link = self.link_under_cursor();
+                self.begin_selection(x, y, now);
+            }
+            // Toasts, link labels and the palette take no clicks of their own
+            _ => {}
+        }
+        None
+    }
+}
+
+/// winit application driving every browser window
+pub struct App {
+    runtime: Handle,
+    proxy: EventLoopProxy<UserEvent>,
+    services: Arc<SharedServices>,
+    windows: HashMap<WindowId, WindowContext>,
+    modifiers: ModifiersState,
+    theme_preference: ThemePreference,
+    system_theme: Option<winit::window::Theme>,
+    theme: Theme,
+    /// Page new windows open on
+    homepage: String,
+    fonts: FontSettings,
+    /// Chrome scale on top of the display scale factor
+    ui_scale: f32,
+    reduced_motion: bool,
+    /// How long notifications stay on screen
+    toast_duration: Duration,
+    /// Arrow keys move a caret through page text instead of scrolling
+    caret_browsing: bool,
+    /// Light pages are recolored dark, except on sites that opt out
+    dark_pages: bool,
+    /// The hit region overlay may be shown: in debug builds, or when
+    /// turned on in settings
+    hit_overlay_allowed: bool,
+    /// Every window outlines what the pointer can hit
+    hit_overlay: bool,
+    /// When windows on navigator://downloads last showed the list again
+    downloads_refreshed: Option<Instant>,
+    /// Downloads changed since, and the list is to be shown again once
+    /// the refresh interval has passed
+    downloads_refresh_due: bool,
+    /// Check for a newer release once the first windows are open
+    update_check: bool,
+    layout_options: ContentLayoutOptions,
+    clipboard: Clipboard,
+    /// Shared with the memory registry, which may trim it
+    thumbnails: Arc<Mutex<ThumbnailCache>>,
+    /// Shutdown began; nothing is drawn any more
+    shutting_down: bool,
+    /// Opened while the profile was, taken by the first window the app opens
+    first_window: Option<(BrowserWindow, Renderer)>,
+}
+
+impl App {
+    pub fn new(runtime: Handle, services: SharedServices, proxy: EventLoopProxy<UserEvent>) -> Self {
+        let settings = runtime
+            .block_on(services.db.get_all())
+            .unwrap_or_else(|e| {
+                tracing::warn!("Failed to load settings: {}", e);
+                Vec::new()
+            });
+
+        let current = services.controller.settings();
+        let theme_preference = ThemePreference::from(current.theme);
+        let homepage = current.homepage.to_string();
+
+        let theme = if services.accessibility.enabled() {
+            Theme::HIGH_CONTRAST
+        } else {
+            theme_preference.resolve(None)
+        };
+
+        forward_state_events(&runtime, services.controller.state(), proxy.clone());
+        spawn_tab_suspender(&runtime, services.controller.clone());
+        spawn_memory_trimmer(&runtime, services.controller.clone());
+        let thumbnails = Arc::new(Mutex::new(ThumbnailCache::default()));
+        let reporter: Arc<dyn MemoryReporter> = thumbnails.clone();
+        services.controller.memory().register(&reporter);
+
+        Self {
+            runtime,
+            proxy,
+            services: Arc::new(services),
+            windows: HashMap::new(),
+            modifiers: ModifiersState::empty(),
+            theme_preference,
+            system_theme: None,
+            theme,
+            homepage,
+            fonts: FontSettings::from_settings(&settings),
+            ui_scale: parse_ui_scale(&settings),
+            reduced_motion: parse_reduced_motion(&settings),
+            toast_duration: parse_notification_duration(
+                settings
+                    .iter()
+                    .find(|(key, _)| key == NOTIFICATION_SECONDS_SETTING)
+                    .map(|(_, value)| value.as_str()),
+            ),
+            caret_browsing: false,
+            dark_pages: parse_dark_pages(&settings),
+            hit_overlay_allowed: parse_hit_regions(&settings),
+            hit_overlay: false,
+            downloads_refreshed: None,
+            downloads_refresh_due: false,
+            update_check: true,
+            layout_options: ContentLayoutOptions::from_settings(&settings),
+            clipboard: Clipboard::system(),
+            thumbnails,
+            shutting_down: false,
+            first_window: None,
+        }
+    }
+
+    /// Open the first window in `window`, put up while the profile opened,
+    /// finishing its renderer with the system fonts scanned meanwhile and
+    /// the extra fonts of the settings
+    pub fn adopt_first_window(
+        &mut self,
+        window: BrowserWindow,
+        renderer: StartingRenderer,
+        mut font_system: FontSystem,
+    ) -> Result<()> {
+        add_extra_fonts(&mut font_system, &self.fonts);
+        let renderer = renderer.finish(font_system, self.fonts.clone())?;
+        window.window().set_title(WINDOW_TITLE);
+        self.first_window = Some((window, renderer));
+        Ok(())
+    }
+
+    pub fn services(&self) -> &Arc<SharedServices> {
+        &self.services
+    }
+
+    /// Whether to check for a newer release on startup, if turned on in
+    /// settings
+    pub fn with_update_check(mut self, enabled: bool) -> Self {
+        self.update_check = enabled;
+        self
+    }
+
+    /// Look for a newer release in the background; the windows hear of one
+    /// as a notification, and of failures not at all
+    fn check_for_update(&mut self) {
+        let controller = self.services.controller.clone();
+        spawn_supervised(&self.runtime, "update check", async move {
+            if let Err(e) = controller.check_for_update(chrono::Utc::now()).await {
+                tracing::debug!("Update check failed: {}", e);
+            }
+        });
+    }
+
+    /// Save the release navigator://update offers through the downloads
+    /// manager, then show it on navigator://downloads
+    fn download_update(&mut self, window_id: WindowId) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        context.show_download(DownloadIndicator::Started);
+        let controller = self.services.controller.clone();
+        let tab = context.tab;
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "download update", async move {
+            let downloaded = controller.download_update(tab).await;
+            if let Err(e) = &downloaded {
+                tracing::warn!("Failed to download update: {}", e);
+                let message = format!("Update not downloaded: {}", e);
+                let failed = Notification::new(message, NotificationSeverity::Error);
+                controller.state().notify(failed.with_tab(tab));
+            }
+            let _ = proxy.send_event(UserEvent::UpdateDownloaded(window_id, downloaded.is_ok()));
+        });
+    }
+
+    /// Re-resolve the active theme and repaint every window
+    fn apply_theme(&mut self) {
+        if self.services.accessibility.enabled() {
+            self.theme = Theme::HIGH_CONTRAST;
+            tracing::info!("Using high-contrast theme for accessibility");
+        } else {
+            self.theme = self.theme_preference.resolve(self.system_theme);
+            tracing::info!(
+                "Using {} theme ({})",
+                if self.theme.is_dark() { "dark" } else { "light" },
+                self.theme_preference.as_str()
+            );
+        }
+
+        for context in self.windows.values() {
+            context.page.html_renderer.set_page_colors(self.theme.page_colors());
+            context.window.request_redraw();
+        }
+    }
+
+    /// Switch between light and dark, persisting the choice
+    fn toggle_theme(&mut self) {
+        self.theme_preference = if self.theme.is_dark() {
+            ThemePreference::Light
+        } else {
+            ThemePreference::Dark
+        };
+        self.apply_theme();
+
+        let controller = self.services.controller.clone();
+        let value = self.theme_preference.as_str();
+        spawn_supervised(&self.runtime, "save theme", async move {
+            if let Err(e) = controller.update_setting(THEME_SETTING, value).await {
+                tracing::warn!("Failed to save theme setting: {}", e);
+            }
+        });
+    }
+
+    /// Switch accessibility mode, laying open pages out again with
+    /// announcements and persisting the choice
+    fn toggle_accessibility(&mut self) {
+        let enabled = !self.services.accessibility.enabled();
+        self.services.accessibility.set_enabled(enabled);
+        self.apply_theme();
+
+        let services = self.services.clone();
+        spawn_supervised(&self.runtime, "apply accessibility mode", async move {
+            services.controller.restyle_tabs().await;
+            let value = if enabled { "true" } else { "false" };
+            if let Err(e) = services.db.set(ACCESSIBILITY_SETTING, value).await {
+                tracing::warn!("Failed to save accessibility setting: {}", e);
+            }
+        });
+    }
+
+    /// Switch offline mode, persisting the choice; pages load from those
+    /// kept for offline use from the next navigation
+    fn toggle_offline(&mut self, window_id: WindowId) {
+        let enabled = !self.services.offline.enabled();
+        self.services.offline.set_enabled(enabled);
+        if let Some(context) = self.windows.get_mut(&window_id) {
+            let message = if enabled {
+                "Offline mode on: pages are shown as kept from earlier visits"
+            } else {
+                "Offline mode off"
+            };
+            context.search_offer = None;
+            context.external_launch = None;
+            context.address_bar.set_prompt(Some(message.to_string()));
+            context.window.request_redraw();
+        }
+
+        let controller = self.services.controller.clone();
+        spawn_supervised(&self.runtime, "save offline mode", async move {
+            let value = if enabled { "true" } else { "false" };
+            if let Err(e) = controller.update_setting(OFFLINE_SETTING, value).await {
+                tracing::warn!("Failed to save offline mode: {}", e);
+            }
+        });
+    }
+
+    /// Switch caret browsing, placing a caret on the text in view of every
+    /// window without a selection
+    fn toggle_caret_browsing(&mut self, window_id: WindowId) {
+        self.caret_browsing = !self.caret_browsing;
+        for context in self.windows.values_mut() {
+            if self.caret_browsing && context.selection.is_none() {
+                context.selection = Some(Selection::collapsed(context.caret().offset));
+            }
+            context.caret_goal = None;
+            context.window.request_redraw();
+        }
+        if let Some(context) = self.windows.get_mut(&window_id) {
+            let message = if self.caret_browsing {
+                "Caret browsing on: arrow keys move through the page, Shift selects"
+            } else {
+                "Caret browsing off"
+            };
+            context.search_offer = None;
+            context.external_launch = None;
+            context.address_bar.set_prompt(Some(message.to_string()));
+        }
+    }
+
+    /// Toggle soft-wrapping of preformatted text, persisting the choice
+    fn toggle_preformatted_wrap(&mut self) {
+        self.layout_options.wrap_preformatted = !self.layout_options.wrap_preformatted;
+        for context in self.windows.values() {
+            context.window.request_redraw();
+        }
+
+        let db = self.services.db.clone();
+        let value = if self.layout_options.wrap_preformatted { "true" } else { "false" };
+        spawn_supervised(&self.runtime, "save preformatted wrap", async move {
+            if let Err(e) = db.set(WRAP_PREFORMATTED_SETTING, value).await {
+                tracing::warn!("Failed to save preformatted wrap setting: {}", e);
+            }
+        });
+    }
+
+    /// Switch dark pages, persisting the choice
+    fn toggle_dark_pages(&mut self, window_id: WindowId) {
+        self.dark_pages = !self.dark_pages;
+        for context in self.windows.values() {
+            context.window.request_redraw();
+        }
+        if let Some(context) = self.windows.get_mut(&window_id) {
+            let message = if self.dark_pages {
+                "Dark pages on: light pages are shown in dark colors"
+            } else {
+                "Dark pages off"
+            };
+            context.search_offer = None;
+            context.external_launch = None;
+            context.address_bar.set_prompt(Some(message.to_string()));
+        }
+
+        let db = self.services.db.clone();
+        let value = if self.dark_pages { "true" } else { "false" };
+        spawn_supervised(&self.runtime, "save dark pages", async move {
+            if let Err(e) = db.set(DARK_PAGES_SETTING, value).await {
+                tracing::warn!("Failed to save dark pages setting: {}", e);
+            }
+        });
+    }
+
+    /// Show or hide the hit region overlay in every window, if allowed
+    fn toggle_hit_overlay(&mut self, window_id: WindowId) {
+        if self.hit_overlay_allowed {
+            self.hit_overlay = !self.hit_overlay;
+            for context in self.windows.values() {
+                context.window.request_redraw();
+            }
+        }
+        if let Some(context) = self.windows.get_mut(&window_id) {
+            let message = if !self.hit_overlay_allowed {
+                format!("Set {} to true to show hit regions", HIT_REGIONS_SETTING)
+            } else if self.hit_overlay {
+                "Hit regions shown: the pointer resolves to the region under it".to_string()
+            } else {
+                "Hit regions hidden".to_string()
+            };
+            context.search_offer = None;
+            context.external_launch = None;
+            context.address_bar.set_prompt(Some(message));
+        }
+    }
+
+    /// Grow or shrink the chrome one step in every window, persisting the scale
+    fn step_ui_scale(&mut self, larger: bool) {
+        let ui_scale = step_ui_scale(self.ui_scale, larger);
+        if ui_scale == self.ui_scale {
+            return;
+        }
+        self.ui_scale = ui_scale;
+        tracing::info!("UI scale set to {}", ui_scale);
+        for context in self.windows.values_mut() {
+            context.renderer.set_ui_scale(ui_scale);
+            context.window.request_redraw();
+        }
+
+        let db = self.services.db.clone();
+        spawn_supervised(&self.runtime, "save ui scale", async move {
+            if let Err(e) = db.set(UI_SCALE_SETTING, &ui_scale.to_string()).await {
+                tracing::warn!("Failed to save UI scale setting: {}", e);
+            }
+        });
+    }
+
+    /// Open a new window on `url` with its own tab set, sharing state and
+    /// storage; unless `active`, it opens behind the focused window
+    fn open_window(&mut self, event_loop: &ActiveEventLoop, url: String, active: bool) -> Result<()> {
+        self.open_window_with(event_loop, url, active, None)
+    }
+
+    /// Open a window as it was saved, with its tabs, where it was on screen
+    fn reopen_window(&mut self, event_loop: &ActiveEventLoop, saved: &SavedWindow) -> Result<()> {
+        let url = saved
+            .shown_tab()
+            .and_then(|tab| tab.url.as_ref())
+            .map_or_else(|| self.homepage.clone(), |url| url.to_string());
+        self.open_window_with(event_loop, url, true, Some(saved))
+    }
+
+    fn open_window_with(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        url: String,
+        active: bool,
+        saved: Option<&SavedWindow>,
+    ) -> Result<()> {
+        let geometry = saved.and_then(|saved| saved.geometry.as_ref());
+        let (window, mut renderer) = match self.first_window.take() {
+            Some((window, renderer)) => {
+                if let Some(geometry) = geometry {
+                    window.restore_geometry(event_loop, geometry);
+                }
+                (window, renderer)
+            }
+            None => {
+                let window = BrowserWindow::new(event_loop, active, geometry)?;
+                let renderer = self.runtime.block_on(Renderer::new(window.window(), self.fonts.clone()))?;
+                (window, renderer)
+            }
+        };
+        renderer.set_ui_scale(self.ui_scale);
+
+        let controller = &self.services.controller;
+        let id = controller.state().open_window();
+        let restored = match saved {
+            Some(saved) => self.runtime.block_on(controller.restore_window(id, saved))?,
+            None => None,
+        };
+        let tab_id = match restored {
+            Some(tab_id) => tab_id,
+            None => self.runtime.block_on(controller.open_tab(Some(id)))?,
+        };
+        let engine = controller
+            .get_page(tab_id)
+            .ok_or_else(|| anyhow::anyhow!("Tab {} has no page", tab_id))?;
+
+        let winit_id = window.id();
+        tracing::info!("Opened window {} with tab {}", id, tab_id);
+
+        let page = Arc::new(PageView::new(engine));
+        page.html_renderer.set_page_colors(self.theme.page_colors());
+
+        let system_theme = window.window().theme();
+        let mut toasts = ToastManager::new();
+        toasts.set_duration(self.toast_duration);
+        toasts.set_reduced_motion(self.reduced_motion);
+        self.windows.insert(
+            winit_id,
+            WindowContext {
+                id,
+                tab: tab_id,
+                window,
+                renderer,
+                address_bar: AddressBar::new(),
+                page,
+                scroll: ScrollState::new(),
+                scrollbar: Scrollbar::new(),
+                toasts,
+                cursor: None,
+                reduced_motion: self.reduced_motion,
+                pressed_link: None,
+                hovered_link: None,
+                selection: None,
+                selecting: false,
+                caret_goal: None,
+                clicks: ClickCounter::new(),
+                overview: None,
+                hints: None,
+                tab_switch: None,
+                palette: None,
+                dark_page: DarkPageCache::new(),
+                thumbnail_due: false,
+                search_offer: None,
+                external_launch: None,
+                settings_focus: 0,
+                settings_error: None,
+                site_blocked: BlockedSummary::default(),
+                site_protocol: None,
+                site_certificate: None,
+                site_csp_violations: Vec::new(),
+            },
+        );
+
+        if self.system_theme.is_none() && system_theme.is_some() {
+            self.system_theme = system_theme;
+            self.apply_theme();
+        }
+
+        // The homepage, or a link opened in a window of its own
+        self.navigate(winit_id, url, VisitType::Link);
+        Ok(())
+    }
+
+    /// Close a window, releasing its GPU resources; the last one exits the
+    /// app, keeping its tabs for the next start if sessions are restored.
+    /// Others are kept to reopen with Ctrl+Shift+N.
+    fn close_window(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId) {
+        self.record_window_geometry();
+        if let Some(context) = self.windows.remove(&window_id) {
+            if !(self.windows.is_empty() && self.save_session()) {
+                let closed = self.runtime.block_on(self.services.controller.close_window(context.id));
+                tracing::info!("Closed window {} ({} tabs)", context.id, closed.len());
+            }
+        }
+
+        if self.windows.is_empty() {
+            tracing::info!("Last window closed, exiting...");
+            self.shut_down(event_loop);
+        }
+    }
+
+    /// Keep the open windows for the next start, wind every service down
+    /// and exit. Windows stop drawing at once; if winding down takes longer
+    /// than [`SHUTDOWN_DEADLINE`], the process exits without waiting.
+    fn shut_down(&mut self, event_loop: &ActiveEventLoop) {
+        if std::mem::replace(&mut self.shutting_down, true) {
+            return;
+        }
+        // Quit with windows still open, e.g. on logging out or a signal
+        if !self.windows.is_empty() {
+            self.record_window_geometry();
+            self.save_session();
+        }
+        for context in self.windows.values() {
+            context.window.window().set_visible(false);
+        }
+        let services = self.services.clone();
+        match self.runtime.block_on(tokio::time::timeout(SHUTDOWN_DEADLINE, services.shutdown())) {
+            Ok(Ok(())) => tracing::info!("Shut down cleanly"),
+            Ok(Err(e)) => tracing::warn!("Failed to shut down cleanly: {:#}", e),
+            Err(_) => {
+                tracing::warn!("Shutting down took longer than {:?}, exiting anyway", SHUTDOWN_DEADLINE);
+                std::process::exit(1);
+            }
+        }
+        event_loop.exit();
+    }
+
+    /// Store every open window for the next start if sessions are restored,
+    /// returning whether they were
+    fn save_session(&self) -> bool {
+        let controller = &self.services.controller;
+        let restore = controller.settings().privacy.restore_session;
+        if restore {
+            if let Err(e) = self.runtime.block_on(controller.save_session()) {
+                tracing::warn!("Failed to save session: {}", e);
+            }
+        }
+        restore
+    }
+
+    /// Note where each window is on screen, so saving the session or
+    /// closing a window keeps it
+    fn record_window_geometry(&self) {
+        let state = self.services.controller.state();
+        for context in self.windows.values() {
+            if let Some(geometry) = context.window.geometry() {
+                state.set_window_geometry(context.id, geometry);
+            }
+        }
+    }
+
+    /// Navigate to user-entered text or a resolved link, adding a history
+    /// entry with a visit of `visit_type`
+    fn navigate(&mut self, window_id: WindowId, url: String, visit_type: VisitType) {
+        if is_update_download(&url) {
+            self.download_update(window_id);
+            return;
+        }
+        self.start_navigation(window_id, NavigationRequest::Go(url, visit_type));
+    }
+
+    /// Follow a link on the window's current page; links on the bookmarks
+    /// page open bookmarks
+    fn follow_link(&mut self, window_id: WindowId, href: &str) {
+        let Some(context) = self.windows.get(&window_id) else {
+            return;
+        };
+        let html_renderer = &context.page.html_renderer;
+        let visit_type = match html_renderer.current_url() {
+            Some(url) if is_bookmarks_page(url.as_str()) => VisitType::Bookmark,
+            _ => VisitType::Link,
+        };
+        match html_renderer.resolve_link(href) {
+            Some(url) => self.navigate(window_id, url.to_string(), visit_type),
+            None => tracing::warn!("Cannot resolve link: {}", href),
+        }
+    }
+
+    /// Open a link of the window's current page in a new window left behind it
+    fn open_link_in_background(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, href: &str) {
+        let Some(context) = self.windows.get(&window_id) else {
+            return;
+        };
+        let Some(url) = context.page.html_renderer.resolve_link(href) else {
+            tracing::warn!("Cannot resolve link: {}", href);
+            return;
+        };
+        if let Err(e) = self.open_window(event_loop, url.to_string(), false) {
+            tracing::error!("Failed to open window: {}", e);
+        }
+    }
+
+    /// Step back or forward through the window's session history
+    fn go_history(&mut self, window_id: WindowId, back: bool) {
+        let request = if back {
+            NavigationRequest::Back
+        } else {
+            NavigationRequest::Forward
+        };
+        self.start_navigation(window_id, request);
+    }
+
+    /// Go up a path level of the window's page, or to its site's root, as
+    /// a new history entry; a page already there stays
+    fn go_up(&mut self, window_id: WindowId, to_root: bool) {
+        let Some(entry) = self
+            .windows
+            .get(&window_id)
+            .and_then(|context| self.services.controller.current_entry(context.tab))
+        else {
+            return;
+        };
+        let target = if to_root { entry.url.root() } else { entry.url.parent() };
+        if let Some(url) = target {
+            self.navigate(window_id, url.to_string(), VisitType::Link);
+        }
+    }
+
+    /// Fetch the current entry again, keeping the scroll position
+    fn reload(&mut self, window_id: WindowId) {
+        self.start_navigation(window_id, NavigationRequest::Reload);
+    }
+
+    /// Save the scroll position, then let the controller load the page and
+    /// show the result on the next frame
+    fn start_navigation(&mut self, window_id: WindowId, request: NavigationRequest) {
+        let Some(context) = self.windows.get(&window_id) else {
+            return;
+        };
+        let controller = self.services.controller.clone();
+        let tab = context.tab;
+        controller.set_scroll_offset(tab, context.scroll.target());
+
+        // Back and forward may land on the settings page too
+        let shows_settings = match &request {
+            NavigationRequest::Go(input, _) => is_settings_page(input),
+            NavigationRequest::Reload => self.shows_settings_page(tab),
+            NavigationRequest::Back | NavigationRequest::Forward => true,
+        };
+        // Back and forward to a site's settings show them as last listed
+        let site = match &request {
+            NavigationRequest::Go(input, _) => site_settings_target(input),
+            NavigationRequest::Reload => self.shown_site_settings(tab),
+            NavigationRequest::Back | NavigationRequest::Forward => None,
+        };
+        let shows_downloads = match &request {
+            NavigationRequest::Go(input, _) => is_downloads_page(input),
+            NavigationRequest::Reload => self.shows_downloads_page(tab),
+            NavigationRequest::Back | NavigationRequest::Forward => true,
+        };
+        let shows_bookmarks = match &request {
+            NavigationRequest::Go(input, _) => is_bookmarks_page(input),
+            NavigationRequest::Reload => self.shows_bookmarks_page(tab),
+            NavigationRequest::Back | NavigationRequest::Forward => true,
+        };
+        let (settings_focus, settings_error) = (context.settings_focus, context.settings_error.clone());
+        if shows_downloads {
+            let pending = controller
+                .pending_downloads()
+                .into_iter()
+                .map(|pending| {
+                    let warnings: Vec<String> = pending.warnings.iter().map(ToString::to_string).collect();
+                    (pending.file_name, warnings.join(", "))
+                })
+                .collect();
+            let unfinished = controller
+                .unfinished_downloads()
+                .into_iter()
+                .map(|unfinished| {
+                    let state = match unfinished.error {
+                        Some(error) => format!("failed: {}", error),
+                        None => "saving".to_string(),
+                    };
+                    (unfinished.file_name, state)
+                })
+                .collect();
+            context.page.html_renderer.set_downloads_view(DownloadsView {
+                pending,
+                unfinished,
+                focused: settings_focus,
+                error: settings_error.clone(),
+            });
+        }
+        if !matches!(&request, NavigationRequest::Go(input, _) if !is_shortcuts_page(input)) {
+            context.page.html_renderer.set_shortcuts_view(shortcuts_view(&controller.keymap()));
+        }
+        if !matches!(&request, NavigationRequest::Go(input, _) if !is_memory_page(input)) {
+            // Trimmed first, so the page lists what is left
+            let trimmed = matches!(&request, NavigationRequest::Go(input, _) if is_memory_trim(input))
+                .then(|| controller.memory().trim_to(0));
+            context.page.html_renderer.set_memory_view(memory_view(&controller, trimmed));
+        }
+        if !matches!(&request, NavigationRequest::Go(input, _) if !is_update_page(input)) {
+            context.page.html_renderer.set_update_view(UpdateView {
+                current_version: CURRENT_VERSION.to_string(),
+                update: controller.available_update(),
+            });
+        }
+        let blocked = context.site_blocked.clone();
+        let protocol = context.site_protocol;
+        let certificate = context.site_certificate.clone();
+        let csp_violations = context.site_csp_violations.clone();
+
+        let page = context.page.clone();
+        let proxy = self.proxy.clone();
+        let offline = self.services.offline.clone();
+        spawn_supervised(&self.runtime, "navigation", async move {
+            if shows_settings {
+                let view = settings_view(&controller, settings_focus, settings_error.clone()).await;
+                page.html_renderer.set_settings_view(view);
+            }
+            if shows_bookmarks {
+                let bookmarks = controller.bookmarks().await.unwrap_or_else(|e| {
+                    tracing::warn!("Failed to read bookmarks: {}", e);
+                    Vec::new()
+                });
+                page.html_renderer.set_bookmarks_view(BookmarksView {
+                    bookmarks,
+                    focused: settings_focus,
+                    error: settings_error.clone(),
+                });
+            }
+            if let Some(site) = site {
+                let settings = site_settings_view(&controller, &site, settings_focus, settings_error).await;
+                page.html_renderer.set_site_settings_view(SiteSettingsView {
+                    settings,
+                    blocked,
+                    protocol,
+                    certificate,
+                    csp_violations,
+                });
+            }
+            let result = match request {
+                // Links for other programs never reach the tab
+                NavigationRequest::Go(input, visit_type) => match controller.open_external(&input).await {
+                    Ok(Some(launch)) => {
+                        let _ = proxy.send_event(UserEvent::ExternalLaunch(window_id, launch));
+                        return;
+                    }
+                    Ok(None) => controller.navigate_as(tab, &input, visit_type).await.map(Some),
+                    Err(e) => Err(e),
+                },
+                NavigationRequest::Back => controller.back(tab).await,
+                NavigationRequest::Forward => controller.forward(tab).await,
+                NavigationRequest::Reload => controller.reload(tab).await,
+            };
+            match result {
+                Ok(Some(outcome)) => {
+                    page.arrive(outcome);
+                    // Fails only once the event loop has exited
+                    let _ = proxy.send_event(UserEvent::Arrived(window_id));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::error!("Navigation error: {}", e);
+                    if offline.connectivity_lost() {
+                        let _ = proxy.send_event(UserEvent::ConnectivityLost(window_id));
+                    }
+                }
+            }
+        });
+    }
+
+    /// Bring a window's tab to the foreground, making it active if asked;
+    /// a suspended tab's page is shown again like a finished navigation
+    fn wake_tab(&mut self, window_id: WindowId, activate: bool) {
+        let Some(context) = self.windows.get(&window_id) else {
+            return;
+        };
+        let controller = self.services.controller.clone();
+        let tab = context.tab;
+        let page = context.page.clone();
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "wake tab", async move {
+            let result = if activate {
+                controller.activate_tab(tab).await
+            } else {
+                controller.show_tab(tab).await
+            };
+            match result {
+                Ok(Some(outcome)) => {
+                    page.arrive(outcome);
+                    let _ = proxy.send_event(UserEvent::Arrived(window_id));
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!("Failed to wake tab {}: {}", tab, e),
+            }
+        });
+    }
+
+    /// Window showing a tab
+    fn window_of_tab(&self, tab: TabId) -> Option<&WindowContext> {
+        self.windows.values().find(|context| context.tab == tab)
+    }
+
+    /// Title the window after its tab's page
+    fn update_window_title(&self, tab: TabId) {
+        let Some(context) = self.window_of_tab(tab) else {
+            return;
+        };
+        let Some(tab) = self.services.controller.state().get_tab(tab) else {
+            return;
+        };
+        let title = if tab.title.is_empty() {
+            WINDOW_TITLE.to_string()
+        } else {
+            format!("{} - {}", tab.title, WINDOW_TITLE)
+        };
+        context.window.window().set_title(&title);
+    }
+
+    /// Tabs shown in the overview, oldest first
+    fn overview_tabs(&self) -> Vec<Tab> {
+        let state = self.services.controller.state();
+        let mut tabs: Vec<Tab> = self
+            .windows
+            .values()
+            .filter_map(|context| state.get_tab(context.tab))
+            .collect();
+        tabs.sort_by_key(|tab| tab.created_at);
+        tabs
+    }
+
+    /// Look for a search engine the window's page offers, asking in the
+    /// address bar whether to add it once fetched
+    fn offer_search_engine(&mut self, window_id: WindowId) {
+        let Some(context) = self.windows.get(&window_id) else {
+            return;
+        };
+        let controller = self.services.controller.clone();
+        let tab = context.tab;
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "search engine offer", async move {
+            let offer = controller.search_engine_offer(tab).await.unwrap_or_else(|e| {
+                tracing::warn!("Cannot add the page's search engine: {}", e);
+                None
+            });
+            let _ = proxy.send_event(UserEvent::SearchEngineOffered(window_id, offer));
+        });
+    }
+
+    /// Ctrl+S saves the page's HTML to the downloads directory, with Alt its
+    /// text, and Ctrl+M an MHTML archive; a notification says where it went
+    fn save_page(&mut self, window_id: WindowId, format: SaveFormat) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        context.show_download(DownloadIndicator::Started);
+        let controller = self.services.controller.clone();
+        let tab = context.tab;
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "save page", async move {
+            let saved = controller.save_page(tab, format).await;
+            if let Err(e) = &saved {
+                tracing::warn!("Failed to save page: {}", e);
+                let failed = Notification::new(format!("Page not saved: {}", e), NotificationSeverity::Error);
+                controller.state().notify(failed.with_tab(tab));
+            }
+            let _ = proxy.send_event(UserEvent::PageSaved(window_id, saved.is_ok()));
+        });
+    }
+
+    /// Ctrl+Z puts back what the latest deletion removed; a toast says what
+    fn undo(&self) {
+        match self.runtime.block_on(self.services.controller.undo()) {
+            Ok(Some(_)) => {}
+            Ok(None) => tracing::info!("Nothing to undo"),
+            Err(e) => {
+                tracing::warn!("Failed to undo: {}", e);
+                let failed = Notification::new(format!("Could not undo: {}", e), NotificationSeverity::Error);
+                self.services.controller.state().notify(failed);
+            }
+        }
+    }
+
+    /// Ctrl+P prints the page to a PDF in the downloads directory
+    fn print_page(&mut self, window_id: WindowId) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        context.show_download(DownloadIndicator::Started);
+        let controller = self.services.controller.clone();
+        let tab = context.tab;
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "print page", async move {
+            let saved = controller.print_page(tab).await;
+            if let Err(e) = &saved {
+                tracing::warn!("Failed to print page: {}", e);
+                let failed = Notification::new(format!("Page not printed: {}", e), NotificationSeverity::Error);
+                controller.state().notify(failed.with_tab(tab));
+            }
+            let _ = proxy.send_event(UserEvent::PageSaved(window_id, saved.is_ok()));
+        });
+    }
+
+    /// Ctrl+Shift+I saves a JSON security report on the page to the
+    /// downloads directory
+    fn export_security_report(&mut self, window_id: WindowId) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        context.show_download(DownloadIndicator::Started);
+        let controller = self.services.controller.clone();
+        let tab = context.tab;
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "export security report", async move {
+            let saved = controller.export_security_report(tab).await;
+            if let Err(e) = &saved {
+                tracing::warn!("Failed to export security report: {}", e);
+                let message = format!("Security report not saved: {}", e);
+                let failed = Notification::new(message, NotificationSeverity::Error);
+                controller.state().notify(failed.with_tab(tab));
+            }
+            let _ = proxy.send_event(UserEvent::PageSaved(window_id, saved.is_ok()));
+        });
+    }
+
+    /// Add the offered search engine if `accept`, and stop asking either way
+    fn answer_search_engine_offer(&mut self, window_id: WindowId, accept: bool) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        context.address_bar.set_prompt(None);
+        context.window.request_redraw();
+        let Some(offer) = context.search_offer.take().filter(|_| accept) else {
+            return;
+        };
+        let controller = self.services.controller.clone();
+        spawn_supervised(&self.runtime, "add search engine", async move {
+            match controller.accept_search_engine_offer(offer).await {
+                Ok(engine) => tracing::info!("Added {} as a search engine", engine.name),
+                Err(e) => tracing::warn!("Failed to add search engine: {}", e),
+            }
+        });
+    }
+
+    /// Open, always open or drop the link the address bar asked about
+    fn answer_external_launch(&mut self, window_id: WindowId, answer: LaunchAnswer) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        context.address_bar.set_prompt(None);
+        context.window.request_redraw();
+        let Some(pending) = context.external_launch.take() else {
+            return;
+        };
+        let controller = self.services.controller.clone();
+        spawn_supervised(&self.runtime, "open external link", async move {
+            if let Err(e) = controller.answer_external_launch(pending.id, answer).await {
+                tracing::warn!("Failed to open {}: {}", pending.url, e);
+            }
+        });
+    }
+
+    /// Up and Down move between the settings on navigator://settings and
+    /// navigator://site-settings; Enter changes the focused one, or edits it
+    /// in the address bar, and Delete removes a site's override. Returns
+    /// whether the key was used.
+    fn handle_settings_key(&mut self, window_id: WindowId, key: &Key) -> bool {
+        let on_settings_page = self
+            .windows
+            .get(&window_id)
+            .is_some_and(|context| self.shows_settings_page(context.tab));
+        let site = self
+            .windows
+            .get(&window_id)
+            .and_then(|context| self.shown_site_settings(context.tab));
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return false;
+        };
+        if context.address_bar.is_focused() || !(on_settings_page || site.is_some()) {
+            return false;
+        }
+        if let Some(site) = site {
+            return self.handle_site_settings_key(window_id, site, key);
+        }
+        let last = listed_settings().count() - 1;
+        match key {
+            Key::Named(NamedKey::ArrowUp | NamedKey::ArrowDown) => {
+                context.settings_focus = if *key == Key::Named(NamedKey::ArrowUp) {
+                    context.settings_focus.saturating_sub(1)
+                } else {
+                    (context.settings_focus + 1).min(last)
+                };
+                context.settings_error = None;
+                self.reload(window_id);
+            }
+            Key::Named(NamedKey::Enter) => {
+                let Some(setting) = listed_settings().nth(context.settings_focus.min(last)) else {
+                    return true;
+                };
+                let current = self
+                    .runtime
+                    .block_on(self.services.controller.setting(setting.key))
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("Failed to read {}: {}", setting.key, e);
+                        setting.default.to_string()
+                    });
+                match setting.next_value(&current) {
+                    Some(value) => self.update_setting(window_id, setting.key, value),
+                    None => {
+                        context.address_bar.begin_edit(setting.label, &current);
+                        context.window.request_redraw();
+                    }
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Keys on navigator://site-settings, listing the overrides of `site`
+    fn handle_site_settings_key(&mut self, window_id: WindowId, site: ValidatedUrl, key: &Key) -> bool {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return false;
+        };
+        let row = context.settings_focus.min(SITE_SETTING_LABELS.len() - 1);
+        match key {
+            Key::Named(NamedKey::ArrowUp | NamedKey::ArrowDown) => {
+                context.settings_focus = if *key == Key::Named(NamedKey::ArrowUp) {
+                    row.saturating_sub(1)
+                } else {
+                    (row + 1).min(SITE_SETTING_LABELS.len() - 1)
+                };
+                context.settings_error = None;
+                self.reload(window_id);
+            }
+            Key::Named(NamedKey::Enter | NamedKey::Delete) if row == SITE_STORAGE_ROW => {
+                self.clear_site_storage(window_id, site);
+            }
+            Key::Named(NamedKey::Enter) => {
+                let current = match self.runtime.block_on(self.services.controller.site_settings(&site)) {
+                    Ok(current) => current,
+                    Err(e) => {
+                        tracing::warn!("Failed to read the settings of {}: {}", site, e);
+                        return true;
+                    }
+                };
+                match next_site_setting(&current, row) {
+                    Some(setting) => self.update_site_setting(window_id, site, setting),
+                    None => {
+                        let agent = current.user_agent.unwrap_or_default();
+                        context.address_bar.begin_edit(SITE_SETTING_LABELS[SITE_USER_AGENT_ROW], &agent);
+                        context.window.request_redraw();
+                    }
+                }
+            }
+            Key::Named(NamedKey::Delete) => {
+                if let Some(setting) = reset_site_setting(row) {
+                    self.update_site_setting(window_id, site, setting);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Open navigator://site-settings for the site of the window's page
+    fn open_site_settings(&mut self, window_id: WindowId) {
+        let Some(context) = self.windows.get(&window_id) else {
+            return;
+        };
+        let tab = self.services.controller.state().get_tab(context.tab);
+        let origin = tab.and_then(|tab| tab.url).as_ref().and_then(SiteOrigin::of);
+        // The panel replaces the page, so what it had blocked is kept first
+        let blocked = self.services.controller.blocked_summary(context.tab).unwrap_or_default();
+        let protocol = self.services.controller.page_protocol(context.tab);
+        let certificate = self.services.controller.page_certificate(context.tab);
+        let csp_violations = self.services.controller.page_csp_violations(context.tab);
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        let Some(origin) = origin else {
+            context.search_offer = None;
+            context.external_launch = None;
+            context.address_bar.set_prompt(Some("Only web pages have site settings".to_string()));
+            context.window.request_redraw();
+            return;
+        };
+        context.settings_focus = 0;
+        context.settings_error = None;
+        context.site_blocked = blocked;
+        context.site_protocol = protocol;
+        context.site_certificate = certificate;
+        context.site_csp_violations = csp_violations;
+        let encoded: String = url::form_urlencoded::byte_serialize(origin.as_str().as_bytes()).collect();
+        self.navigate(window_id, format!("{}?origin={}", SITE_SETTINGS_PAGE, encoded), VisitType::Link);
+    }
+
+    /// Delete what the site keeps in `localStorage`, then show
+    /// navigator://site-settings again
+    fn clear_site_storage(&mut self, window_id: WindowId, site: ValidatedUrl) {
+        let controller = self.services.controller.clone();
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "clear site storage", async move {
+            let error = match controller.clear_site_storage(&site).await {
+                Ok(_) => None,
+                Err(e) => {
+                    tracing::warn!("Failed to clear the storage of {}: {}", site, e);
+                    Some(e.to_string())
+                }
+            };
+            let _ = proxy.send_event(UserEvent::SettingSaved(window_id, error));
+        });
+    }
+
+    /// Store an override changed on navigator://site-settings, then show the
+    /// page again with the stored value or why it was refused
+    fn update_site_setting(&mut self, window_id: WindowId, site: ValidatedUrl, setting: SiteSetting) {
+        let controller = self.services.controller.clone();
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "update site setting", async move {
+            let error = match controller.set_site_setting(&site, setting).await {
+                Ok(_) => None,
+                Err(e) => {
+                    tracing::warn!("Site setting for {} refused: {}", site, e);
+                    Some(e.to_string())
+                }
+            };
+            let _ = proxy.send_event(UserEvent::SettingSaved(window_id, error));
+        });
+    }
+
+    /// Enter on navigator://console starts typing an expression in the
+    /// address bar; returns whether the key was used
+    fn handle_console_key(&mut self, window_id: WindowId, key: &Key) -> bool {
+        let on_console_page = self
+            .windows
+            .get(&window_id)
+            .is_some_and(|context| self.shows_console_page(context.tab));
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return false;
+        };
+        if context.address_bar.is_focused() || !on_console_page || *key != Key::Named(NamedKey::Enter) {
+            return false;
+        }
+        context.address_bar.begin_edit(CONSOLE_INPUT_LABEL, "");
+        context.window.request_redraw();
+        true
+    }
+
+    /// Complete what is typed in the window's address bar inline with the
+    /// top suggestion, and note the open tab Enter would switch to
+    fn complete_address(&mut self, window_id: WindowId, input: &str) {
+        let Some(tab) = self.windows.get(&window_id).map(|context| context.tab) else {
+            return;
+        };
+        let suggestions = match self.runtime.block_on(self.services.controller.suggest(tab, input)) {
+            Ok(suggestions) => suggestions,
+            Err(e) => {
+                tracing::debug!("No address bar suggestions: {}", e);
+                return;
+            }
+        };
+        if let Some(context) = self.windows.get_mut(&window_id) {
+            context.address_bar.set_completion(&top_completion(input, &suggestions).unwrap_or_default());
+            context.tab_switch = tab_switch(input, &suggestions);
+            context.window.request_redraw();
+        }
+    }
+
+    /// Evaluate an expression typed on navigator://console in the window's
+    /// tab, then show the console again with its result
+    fn evaluate_in_console(&mut self, window_id: WindowId, expression: String) {
+        let Some(context) = self.windows.get(&window_id) else {
+            return;
+        };
+        let tab = context.tab;
+        let controller = self.services.controller.clone();
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "evaluate in console", async move {
+            // The console logs the outcome, failures included
+            if let Err(e) = controller.evaluate_in_console(tab, &expression).await {
+                tracing::debug!("Console expression failed: {}", e);
+            }
+            let _ = proxy.send_event(UserEvent::ConsoleEvaluated(window_id));
+        });
+    }
+
+    /// Whether a tab's current page is navigator://console
+    fn shows_console_page(&self, tab: TabId) -> bool {
+        let tab = self.services.controller.state().get_tab(tab);
+        tab.and_then(|tab| tab.url)
+            .is_some_and(|url| is_console_page(url.as_str()))
+    }
+
+    /// The site whose settings a tab's current page lists, if it is
+    /// navigator://site-settings
+    fn shown_site_settings(&self, tab: TabId) -> Option<ValidatedUrl> {
+        let tab = self.services.controller.state().get_tab(tab)?;
+        site_settings_target(tab.url?.as_str())
+    }
+
+    /// Whether a tab's current page is navigator://downloads
+    fn shows_downloads_page(&self, tab: TabId) -> bool {
+        let tab = self.services.controller.state().get_tab(tab);
+        tab.and_then(|tab| tab.url)
+            .is_some_and(|url| is_downloads_page(url.as_str()))
+    }
+
+    /// Whether a tab's current page is navigator://bookmarks
+    fn shows_bookmarks_page(&self, tab: TabId) -> bool {
+        let tab = self.services.controller.state().get_tab(tab);
+        tab.and_then(|tab| tab.url)
+            .is_some_and(|url| is_bookmarks_page(url.as_str()))
+    }
+
+    /// Up and Down move between the bookmarks on navigator://bookmarks and
+    /// Enter opens the focused one; Alt+Up and Alt+Down move it within its
+    /// folder, keeping the focus on it. Returns whether the key was used.
+    fn handle_bookmarks_key(&mut self, window_id: WindowId, key: &Key) -> bool {
+        let on_bookmarks_page = self
+            .windows
+            .get(&window_id)
+            .is_some_and(|context| self.shows_bookmarks_page(context.tab));
+        let Some(context) = self.windows.get(&window_id) else {
+            return false;
+        };
+        if context.address_bar.is_focused() || !on_bookmarks_page || self.modifiers.control_key() {
+            return false;
+        }
+        if !matches!(key, Key::Named(NamedKey::ArrowUp | NamedKey::ArrowDown | NamedKey::Enter)) {
+            return false;
+        }
+
+        let controller = self.services.controller.clone();
+        let bookmarks = match self.runtime.block_on(controller.bookmarks()) {
+            Ok(bookmarks) => bookmarks,
+            Err(e) => {
+                tracing::warn!("Failed to read bookmarks: {}", e);
+                return true;
+            }
+        };
+        let moving = self.modifiers.alt_key();
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return false;
+        };
+        let row = context.settings_focus.min(bookmarks.len().saturating_sub(1));
+        let Some(focused) = bookmarks.get(row) else {
+            return true;
+        };
+        context.settings_error = None;
+        if let Key::Named(NamedKey::Enter) = key {
+            let url = focused.url.to_string();
+            self.navigate(window_id, url, VisitType::Bookmark);
+            return true;
+        }
+        let up = matches!(key, Key::Named(NamedKey::ArrowUp));
+        if moving {
+            // Its place among the others in its folder, which it stays in
+            let folder: Vec<i64> = bookmarks.iter().filter(|b| b.folder == focused.folder).map(|b| b.id).collect();
+            let index = folder.iter().position(|id| *id == focused.id).unwrap_or(0);
+            let target = if up {
+                index.checked_sub(1)
+            } else {
+                Some(index + 1).filter(|next| *next < folder.len())
+            };
+            if let Some(target) = target {
+                let moved = controller.move_bookmark(focused.id, focused.folder.as_deref(), target);
+                match self.runtime.block_on(moved) {
+                    Ok(()) => context.settings_focus = if up { row - 1 } else { row + 1 },
+                    Err(e) => {
+                        tracing::warn!("Failed to move bookmark: {}", e);
+                        context.settings_error = Some(e.to_string());
+                    }
+                }
+            }
+        } else if up {
+            context.settings_focus = row.saturating_sub(1);
+        } else {
+            context.settings_focus = (row + 1).min(bookmarks.len() - 1);
+        }
+        self.reload(window_id);
+        true
+    }
+
+    /// Show navigator://downloads again in every window on it, keeping
+    /// each one's focused row
+    fn refresh_downloads_pages(&mut self) {
+        self.downloads_refreshed = Some(Instant::now());
+        self.downloads_refresh_due = false;
+        let windows: Vec<WindowId> = self
+            .windows
+            .iter()
+            .filter(|(_, context)| self.shows_downloads_page(context.tab))
+            .map(|(window_id, _)| *window_id)
+            .collect();
+        for window_id in windows {
+            self.reload(window_id);
+        }
+    }
+
+    /// Up and Down move between the downloads on navigator://downloads;
+    /// Enter opens the focused one's folder, Delete takes it off the list
+    /// and Shift+Delete deletes its file too, and C clears the list. On a
+    /// download waiting for confirmation, Enter keeps it and Delete discards
+    /// it. Returns whether the key was used.
+    fn handle_downloads_key(&mut self, window_id: WindowId, key: &Key) -> bool {
+        let on_downloads_page = self
+            .windows
+            .get(&window_id)
+            .is_some_and(|context| self.shows_downloads_page(context.tab));
+        let Some(context) = self.windows.get(&window_id) else {
+            return false;
+        };
+        if context.address_bar.is_focused() || !on_downloads_page || self.modifiers.control_key() {
+            return false;
+        }
+        let clear = matches!(key, Key::Character(c) if c.eq_ignore_ascii_case("c"));
+        if !clear
+            && !matches!(
+                key,
+                Key::Named(NamedKey::ArrowUp | NamedKey::ArrowDown | NamedKey::Enter | NamedKey::Delete)
+            )
+        {
+            return false;
+        }
+
+        let controller = self.services.controller.clone();
+        let downloads = match self.runtime.block_on(controller.downloads()) {
+            Ok(downloads) => downloads,
+            Err(e) => {
+                tracing::warn!("Failed to read downloads: {}", e);
+                return true;
+            }
+        };
+        let pending = controller.pending_downloads();
+        let unfinished = controller.unfinished_downloads();
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return false;
+        };
+        let last = (pending.len() + unfinished.len() + downloads.len()).saturating_sub(1);
+        let row = context.settings_focus.min(last);
+        let writing = row.checked_sub(pending.len()).and_then(|row| unfinished.get(row));
+        let focused = row
+            .checked_sub(pending.len() + unfinished.len())
+            .and_then(|row| downloads.get(row));
+        context.settings_error = None;
+        // Writes run in the background, so one can be cancelled while it
+        // runs; a failed one stays listed with why
+        match (key, pending.get(row), writing) {
+            (Key::Named(NamedKey::ArrowUp), ..) => context.settings_focus = row.saturating_sub(1),
+            (Key::Named(NamedKey::ArrowDown), ..) => context.settings_focus = (row + 1).min(last),
+            (Key::Named(NamedKey::Enter), Some(held), _) => {
+                let id = held.id;
+                spawn_supervised(&self.runtime, "keep download", async move {
+                    if let Err(e) = controller.confirm_download(id).await {
+                        tracing::warn!("Failed to keep download: {}", e);
+                    }
+                });
+            }
+            (Key::Named(NamedKey::Delete), Some(held), _) => {
+                if let Err(e) = controller.discard_download(held.id) {
+                    context.settings_error = Some(e.to_string());
+                }
+            }
+            (Key::Named(NamedKey::Enter), None, Some(unfinished)) => {
+                if unfinished.error.is_none() {
+                    return true;
+                }
+                let id = unfinished.id;
+                spawn_supervised(&self.runtime, "retry download", async move {
+                    if let Err(e) = controller.retry_download(id).await {
+                        tracing::warn!("Failed to retry download: {}", e);
+                    }
+                });
+            }
+            (Key::Named(NamedKey::Delete), None, Some(unfinished)) => {
+                if let Err(e) = controller.cancel_download(unfinished.id) {
+                    context.settings_error = Some(e.to_string());
+                }
+            }
+            (Key::Named(NamedKey::Enter), None, None) => {
+                if let Some(Err(e)) = focused.map(|download| open_containing_folder(&download.path)) {
+                    tracing::warn!("Failed to open the folder of a download: {}", e);
+                    context.settings_error = Some(format!("Cannot open its folder: {}", e));
+                }
+            }
+            (Key::Named(NamedKey::Delete), None, None) => {
+                let Some(download) = focused else {
+                    return true;
+                };
+                let delete_file = self.modifiers.shift_key();
+                if let Err(e) = self.runtime.block_on(controller.remove_download(download.id, delete_file)) {
+                    tracing::warn!("Failed to remove download: {}", e);
+                    context.settings_error = Some(e.to_string());
+                }
+            }
+            _ => {
+                if let Err(e) = self.runtime.block_on(controller.clear_downloads()) {
+                    tracing::warn!("Failed to clear downloads: {}", e);
+                    context.settings_error = Some(e.to_string());
+                }
+                context.settings_focus = 0;
+            }
+        }
+        self.reload(window_id);
+        true
+    }
+
+    /// Whether a tab's current page is navigator://settings
+    fn shows_settings_page(&self, tab: TabId) -> bool {
+        let tab = self.services.controller.state().get_tab(tab);
+        tab.and_then(|tab| tab.url)
+            .is_some_and(|url| is_settings_page(url.as_str()))
+    }
+
+    /// Store a setting changed on navigator://settings, then show the page
+    /// again with the stored value or why it was refused
+    fn update_setting(&mut self, window_id: WindowId, key: &'static str, value: String) {
+        let controller = self.services.controller.clone();
+        let proxy = self.proxy.clone();
+        spawn_supervised(&self.runtime, "update setting", async move {
+            let error = match controller.update_setting(key, &value).await {
+                Ok(file) if key == PROFILE_EXPORT_SETTING => export_profile_to(&controller, &file).await.err(),
+                Ok(file) if key == PROFILE_IMPORT_SETTING => import_profile_from(&controller, &file).await.err(),
+                Ok(_) => None,
+                Err(e) => {
+                    tracing::warn!("Setting {} refused: {}", key, e);
+                    Some(e.to_string())
+                }
+            };
+            let _ = proxy.send_event(UserEvent::SettingSaved(window_id, error));
+        });
+    }
+
+    /// Put a setting changed on navigator://settings into effect; the rest
+    /// are read where they are used
+    fn apply_setting(&mut self, key: &str) {
+        let value = match self.runtime.block_on(self.services.controller.setting(key)) {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("Failed to read {}: {}", key, e);
+                return;
+            }
+        };
+        let settings = self.services.controller.settings();
+        match key {
+            HOMEPAGE_SETTING => self.homepage = settings.homepage.to_string(),
+            THEME_SETTING => {
+                self.theme_preference = ThemePreference::from(settings.theme);
+                self.apply_theme();
+            }
+            USER_AGENT_SETTING => self.services.network.set_user_agent(Some(&settings.network.user_agent)),
+            CONTENT_BLOCKER_SETTING => self.services.blocker.set_enabled(settings.privacy.content_blocker),
+            ENFORCE_CSP_SETTING => self
+                .services
+                .csp_enforcement
+                .set_enabled(setting_enabled(key, Some(&value))),
+            COOKIES_SETTING => self.services.network.set_cookie_blocking(settings.network.cookies),
+            NOTIFICATION_SECONDS_SETTING => {
+                self.toast_duration = parse_notification_duration(Some(&value));
+                for context in self.windows.values_mut() {
+                    context.toasts.set_duration(self.toast_duration);
+                }
+            }
+            OFFLINE_SETTING => {
+                let enabled = settings.network.offline;
+                if enabled != self.services.offline.enabled() {
+                    self.services.offline.set_enabled(enabled);
+                }
+            }
+            FILE_ACCESS_SETTING | FILE_ACCESS_ROOTS_SETTING => {
+                let controller = &self.services.controller;
+                let enabled = self.runtime.block_on(controller.setting(FILE_ACCESS_SETTING)).ok();
+                let roots = self.runtime.block_on(controller.setting(FILE_ACCESS_ROOTS_SETTING)).ok();
+                let policy = file_access_policy(enabled.as_deref(), roots.as_deref());
+                self.services.file_access.set(policy);
+            }
+            HIT_REGIONS_SETTING => {
+                self.hit_overlay_allowed = parse_hit_regions(&[(key.to_string(), value)]);
+                if !self.hit_overlay_allowed && self.hit_overlay {
+                    self.hit_overlay = false;
+                    for context in self.windows.values() {
+                        context.window.request_redraw();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Show a posted notification in the window of its tab, or in every
+    /// window if it names none
+    fn show_notification(&mut self, id: u64) {
+        let Some(notification) = self.services.controller.state().notification(id) else {
+            return;
+        };
+        let now = Instant::now();
+        for context in self.windows.values_mut() {
+            if notification.tab.is_none_or(|tab| tab == context.tab) {
+                context.toasts.push(notification.clone(), now);
+                context.window.request_redraw();
+            }
+        }
+    }
+
+    /// A key shown on one of the window's toasts acts on it, unless the
+    /// address bar is taking typing
+    fn handle_toast_key(&mut self, window_id: WindowId, key: &Key) -> bool {
+        if self.modifiers.control_key() || self.modifiers.alt_key() {
+            return false;
+        }
+        let Key::Character(text) = key else {
+            return false;
+        };
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return false;
+        };
+        if context.address_bar.is_focused() {
+            return false;
+        }
+        let mut chars = text.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return false;
+        };
+        let Some(action) = context.toasts.press_key(c, Instant::now()) else {
+            return false;
+        };
+        context.window.request_redraw();
+        self.navigate(window_id, action.url, VisitType::Link);
+        true
+    }
+
+    /// Show or hide the tab overview, starting on the window's own tab
+    fn toggle_overview(&mut self, window_id: WindowId) {
+        let tabs = self.overview_tabs();
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        context.overview = match context.overview {
+            Some(_) => {
+                context.renderer.release_overview();
+                None
+            }
+            None => Some(tabs.iter().position(|tab| tab.id == context.tab).unwrap_or(0)),
+        };
+        context.window.request_redraw();
+    }
+
+    /// Keys while the overview is open: arrows select, Enter switches to the
+    /// selected tab, Delete closes it and Escape leaves
+    fn handle_overview_key(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, key: &Key) {
+        let tabs = self.overview_tabs();
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        let Some(selected) = context.overview else {
+            return;
+        };
+        let width = context.renderer.size().width as f32;
+        let columns = OverviewLayout::compute(tabs.len(), width, context.renderer.scale_factor()).columns;
+        let Some(action) = overview_key(key, selected, tabs.len(), columns) else {
+            return;
+        };
+        context.window.request_redraw();
+
+        match action {
+            OverviewAction::Select(index) => context.overview = Some(index),
+            OverviewAction::Dismiss => self.toggle_overview(window_id),
+            OverviewAction::Open(index) => {
+                self.toggle_overview(window_id);
+                // Each tab has its own window
+                if let Some(target) = self.window_of_tab(tabs[index].id) {
+                    target.window.window().focus_window();
+                }
+            }
+            OverviewAction::Close(index) => {
+                let target = self
+                    .windows
+                    .iter()
+                    .find(|(_, context)| context.tab == tabs[index].id)
+                    .map(|(id, _)| *id);
+                if let Some(target) = target {
+                    self.close_window(event_loop, target);
+                }
+            }
+        }
+    }
+
+    /// Open the command palette in the window's address bar, or close it
+    fn toggle_palette(&mut self, window_id: WindowId) {
+        if self.windows.get(&window_id).is_some_and(|context| context.overview.is_some()) {
+            self.toggle_overview(window_id);
+        }
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        context.address_bar.end_edit();
+        if context.palette.take().is_none() {
+            context.hints = None;
+            context.address_bar.begin_edit(PALETTE_LABEL, "");
+            context.palette = Some(CommandPalette::new());
+        }
+        context.window.request_redraw();
+        self.refresh_palette(window_id);
+    }
+
+    /// List what matches the query typed in the window's palette, if open
+    fn refresh_palette(&mut self, window_id: WindowId) {
+        let Some(query) = self
+            .windows
+            .get(&window_id)
+            .filter(|context| context.palette.is_some())
+            .map(|context| context.address_bar.url().to_string())
+        else {
+            return;
+        };
+        let listed = self.services.controller.palette(&query, chrono::Utc::now());
+        let entries = self.runtime.block_on(listed).unwrap_or_else(|e| {
+            tracing::warn!("Failed to list palette entries: {}", e);
+            Vec::new()
+        });
+        if let Some(palette) = self.windows.get_mut(&window_id).and_then(|context| context.palette.as_mut()) {
+            palette.set_entries(entries);
+        }
+    }
+
+    /// Keys while the palette is open: arrows select, Enter carries out the
+    /// selected entry, Escape closes it and the rest edit the query.
+    /// Returns whether the key was used.
+    fn handle_palette_key(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        key_event: &KeyEvent,
+    ) -> bool {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return false;
+        };
+        let Some(palette) = context.palette.as_mut() else {
+            return false;
+        };
+        context.window.request_redraw();
+        match &key_event.logical_key {
+            Key::Named(NamedKey::ArrowDown) => palette.select_next(),
+            Key::Named(NamedKey::ArrowUp) => palette.select_previous(),
+            Key::Named(NamedKey::Escape) => self.toggle_palette(window_id),
+            Key::Named(NamedKey::Enter) => {
+                let chosen = palette.selected().map(|entry| entry.target.clone());
+                self.toggle_palette(window_id);
+                if let Some(target) = chosen {
+                    self.open_palette_target(event_loop, window_id, target);
+                }
+            }
+            key => {
+                let text = key_event.text.as_ref().map(|s| s.as_str());
+                context.address_bar.handle_key(key, text);
+                self.refresh_palette(window_id);
+            }
+        }
+        true
+    }
+
+    /// Bring an open tab to the front from another window's address bar,
+    /// which shows its own page again
+    fn switch_to_tab(&mut self, window_id: WindowId, tab: TabId) {
+        if let Some(context) = self.windows.get_mut(&window_id) {
+            if let Some(entry) = self.services.controller.current_entry(context.tab) {
+                context.address_bar.show_url(&entry.url);
+            }
+            context.window.request_redraw();
+        }
+        // Each tab has its own window
+        if let Some(target) = self.window_of_tab(tab) {
+            target.window.window().focus_window();
+        }
+    }
+
+    /// Carry out a palette entry chosen in a window: run the command the way
+    /// its shortcut would, with any confirmation it asks for, bring the tab
+    /// to the front or open the page
+    fn open_palette_target(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, target: PaletteTarget) {
+        match target {
+            PaletteTarget::Command(command) => {
+                if let Some(context) = self.windows.get(&window_id) {
+                    let recorded = self.services.controller.record_palette_use(context.tab, command);
+                    if let Err(e) = self.runtime.block_on(recorded) {
+                        tracing::warn!("Failed to record palette use: {}", e);
+                    }
+                }
+                self.run_command(event_loop, window_id, command);
+            }
+            PaletteTarget::Tab(tab) => self.switch_to_tab(window_id, tab),
+            PaletteTarget::Page(url) => self.navigate(window_id, url.to_string(), VisitType::Typed),
+        }
+    }
+
+    /// Keys while links are labelled: typing a label follows its link, with
+    /// Shift in a background window, and Escape takes the labels away.
+    /// Returns whether the key was used.
+    fn handle_hint_key(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, key: &Key) -> bool {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return false;
+        };
+        let Some(action) = context.hints.as_mut().and_then(|hints| hints.key(key)) else {
+            // Other keys leave hints mode and do what they usually do
+            context.hints = None;
+            return false;
+        };
+        context.window.request_redraw();
+        let (link, background) = match action {
+            HintAction::Pending => return true,
+            HintAction::Cancel => {
+                context.hints = None;
+                return true;
+            }
+            HintAction::Follow(link) => (link, false),
+            HintAction::OpenInBackground(link) => (link, true),
+        };
+        context.hints = None;
+        let Some(href) = context.page.content().links.get(link).map(|link| link.href.clone()) else {
+            return true;
+        };
+        if background {
+            self.open_link_in_background(event_loop, window_id, &href);
+        } else {
+            self.follow_link(window_id, &href);
+        }
+        true
+    }
+
+    /// Draw the overview grid in place of the page
+    fn draw_overview(&mut self, window_id: WindowId) {
+        let tabs = self.overview_tabs();
+        let ids: Vec<TabId> = tabs.iter().map(|tab| tab.id).collect();
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        // Tabs may have closed since the selection was made
+        let selected = context.overview.unwrap_or(0).min(tabs.len().saturating_sub(1));
+        context.overview = Some(selected);
+
+        let Ok(mut thumbnails) = self.thumbnails.lock() else {
+            return;
+        };
+        let items: Vec<OverviewItem> = tabs
+            .iter()
+            .zip(thumbnails.get_all(&ids))
+            .map(|(tab, thumbnail)| OverviewItem {
+                title: &tab.title,
+                thumbnail,
+                placeholder: placeholder_letter(tab.page_url()),
+            })
+            .collect();
+        if let Err(e) = context.renderer.render_overview(&items, selected, &self.theme) {
+            tracing::error!("Render error: {}", e);
+        }
+    }
+
+    /// Drop a tab's thumbnail once it no longer shows the page
+    fn forget_thumbnail(&self, tab: TabId) {
+        if let Ok(mut thumbnails) = self.thumbnails.lock() {
+            thumbnails.remove(tab);
+        }
+    }
+
+    /// Repaint windows showing the overview, whose cards follow every tab
+    fn redraw_overviews(&self) {
+        for context in self.windows.values().filter(|context| context.overview.is_some()) {
+            context.window.request_redraw();
+        }
+    }
+
+    /// Ctrl+C / Ctrl+V and the copy-URL variants
+    fn handle_clipboard_key(&mut self, window_id: WindowId, copy: bool) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+
+        let result = if !copy {
+            // Paste only goes to the address bar for now
+            if !context.address_bar.is_focused() {
+                return;
+            }
+            self.clipboard.paste_text().map(|text| {
+                context.address_bar.insert_text(&text);
+                context.window.request_redraw();
+            })
+        } else if self.modifiers.alt_key() {
+            // Copy the target of the link under the cursor
+            let Some(url) = context
+                .hovered_link
+                .as_deref()
+                .and_then(|href| context.page.html_renderer.resolve_link(href))
+            else {
+                return;
+            };
+            self.clipboard.copy_url(&url)
+        } else if self.modifiers.shift_key() {
+            let Some(entry) = self.services.controller.current_entry(context.tab) else {
+                return;
+            };
+            self.clipboard.copy_url(&entry.url)
+        } else if context.address_bar.is_focused() {
+            self.clipboard.copy_text(context.address_bar.url())
+        } else {
+            let Some(text) = context.selected_text() else {
+                return;
+            };
+            self.clipboard.copy_text(&text)
+        };
+
+        if let Err(e) = result {
+            tracing::warn!("Clipboard error: {}", e);
+        }
+    }
+
+    /// Put the text selected in the window's page in the primary selection
+    fn select_primary(&mut self, window_id: WindowId) {
+        let Some(text) = self.windows.get(&window_id).and_then(|context| context.selected_text()) else {
+            return;
+        };
+        if text.is_empty() {
+            return;
+        }
+        if let Err(e) = self.clipboard.select_text(&text) {
+            tracing::warn!("Clipboard error: {}", e);
+        }
+    }
+
+    /// A middle click on the address bar types the primary selection into it
+    fn paste_primary(&mut self, window_id: WindowId) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        let on_address_bar = context.cursor.is_some_and(|(x, y)| {
+            matches!(
+                context.renderer.hit_regions().kind_at(x, y),
+                Some(HitRegionKind::AddressBar | HitRegionKind::Badge)
+            )
+        });
+        if !on_address_bar {
+            return;
+        }
+        match self.clipboard.paste_selection() {
+            Ok(text) => {
+                context.address_bar.set_focused(true);
+                context.address_bar.insert_text(&text);
+                context.window.request_redraw();
+            }
+            Err(e) => tracing::debug!("Nothing to paste from the primary selection: {}", e),
+        }
+    }
+
+    /// Ctrl+Shift+S copies the visible page as an image, with Alt the whole
+    /// page; the address bar says whether it worked
+    fn copy_screenshot(&mut self, window_id: WindowId, full_page: bool) {
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+        let (generation, content, page_theme) = context.drawn_content(self.dark_pages, &self.theme);
+        let frame = PageFrame {
+            content: &content,
+            generation,
+            layout_options: self.layout_options,
+            scrollbar_opacity: 0.0,
+            reveal: None,
+            selection: None,
+            hints: None,
+            caret: None,
+            toasts: &[],
+            palette: None,
+            page_theme,
+            hit_overlay: None,
+        };
+        let captured = if full_page {
+            context.renderer.capture_full_page(&frame, &context.address_bar, &self.theme)
+        } else {
+            context
+                .renderer
+                .capture_viewport(&frame, &context.address_bar, &self.theme, &context.scroll)
+        };
+        let message = match captured.and_then(|screenshot| self.clipboard.copy_image(&screenshot)) {
+            Ok(()) => "Screenshot copied to the clipboard".to_string(),
+            Err(e) => {
+                tracing::warn!("Failed to copy screenshot: {}", e);
+                format!("Screenshot not copied: {}", e)
+            }
+        };
+        // The message replaces any question the address bar was asking
+        context.search_offer = None;
+        context.external_launch = None;
+        context.address_bar.set_prompt(Some(message));
+        context.window.request_redraw();
+    }
+
+    /// Carry out a command typed in a window
+    fn run_command(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, command: Command) {
+        command.dispatch(&mut WindowCommands {
+            app: self,
+            event_loop,
+            window_id,
+        });
+    }
+
+    fn handle_keyboard_input(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        key_event: KeyEvent,
+    ) {
+        // Shortcuts held with Ctrl, Alt or the logo key come first; plain ones
+        // wait until the page's own keys and the address bar have had theirs
+        let shortcut = chord_of(&key_event.logical_key, self.modifiers)
+            .and_then(|chord| Some((chord.clone(), self.services.controller.command_for(&chord)?)));
+        if let Some((_, command)) = shortcut.as_ref().filter(|(chord, _)| !chord.is_plain()) {
+            // Shortcuts close the palette, except pasting into its query
+            let palette_open = self.windows.get(&window_id).is_some_and(|context| context.palette.is_some());
+            if palette_open && *command != Command::Paste {
+                self.toggle_palette(window_id);
+                if *command == Command::CommandPalette {
+                    return;
+                }
+            }
+            self.run_command(event_loop, window_id, *command);
+            self.refresh_palette(window_id);
+            return;
+        }
+
+        if self.windows.get(&window_id).is_some_and(|context| context.overview.is_some()) {
+            self.handle_overview_key(event_loop, window_id, &key_event.logical_key);
+            return;
+        }
+        if self.windows.get(&window_id).is_some_and(|context| context.hints.is_some())
+            && self.handle_hint_key(event_loop, window_id, &key_event.logical_key)
+        {
+            return;
+        }
+
+        // Any key but a modifier answers the address bar's question; only
+        // Enter accepts
+        let modifier = matches!(
+            key_event.logical_key,
+            Key::Named(NamedKey::Shift | NamedKey::Control | NamedKey::Alt | NamedKey::Super)
+        );
+        if !modifier && self.windows.get(&window_id).is_some_and(|context| context.address_bar.prompt().is_some()) {
+            let accept = key_event.logical_key == Key::Named(NamedKey::Enter);
+            if self.windows.get(&window_id).is_some_and(|context| context.external_launch.is_some()) {
+                let answer = match &key_event.logical_key {
+                    _ if accept => LaunchAnswer::Open,
+                    Key::Character(c) if c.eq_ignore_ascii_case("a") => LaunchAnswer::AlwaysOpen,
+                    _ => LaunchAnswer::Cancel,
+                };
+                self.answer_external_launch(window_id, answer);
+            } else {
+                self.answer_search_engine_offer(window_id, accept);
+            }
+            return;
+        }
+        if self.handle_palette_key(event_loop, window_id, &key_event) {
+            return;
+        }
+        if self.handle_toast_key(window_id, &key_event.logical_key) {
+            return;
+        }
+
+        if !self.modifiers.alt_key() && self.handle_settings_key(window_id, &key_event.logical_key) {
+            return;
+        }
+        if self.handle_console_key(window_id, &key_event.logical_key) {
+            return;
+        }
+        if !self.modifiers.alt_key() && self.handle_downloads_key(window_id, &key_event.logical_key) {
+            return;
+        }
+        // Before the shortcuts, so Alt+Up moves a bookmark rather than going up
+        if self.handle_bookmarks_key(window_id, &key_event.logical_key) {
+            return;
+        }
+
+        if let Some((chord, command)) = shortcut {
+            let typing = self.windows.get(&window_id).is_some_and(|context| context.address_bar.is_focused());
+            if !typing || chord.is_function_key() {
+                self.run_command(event_loop, window_id, command);
+                return;
+            }
+        }
+
+        let line_height = self.theme.text_size(self.fonts.default_size) * 1.2;
+        let site = self
+            .windows
+            .get(&window_id)
+            .and_then(|context| self.shown_site_settings(context.tab));
+        let Some(context) = self.windows.get_mut(&window_id) else {
+            return;
+        };
+
+        let mut navigation = None;
+        let mut switched = None;
+        let mut typed = None;
+        let mut followed = None;
+        let mut edited = None;
+        let mut evaluated = None;
+        if context.address_bar.is_focused() {
+            let console_input = context.address_bar.editing() == Some(CONSOLE_INPUT_LABEL);
+            let text = key_event.text.as_ref().map(|s| s.as_str());
+            // Only what was just suggested switches tabs
+            let tab_switch = context.tab_switch.take();
+            // Ctrl+Enter goes to a bare word's .com address
+            if self.modifiers.control_key() && key_event.logical_key == Key::Named(NamedKey::Enter) {
+                context.address_bar.wrap_bare_word();
+            }
+            if let Key::Named(NamedKey::Escape) = key_event.logical_key {
+                context.address_bar.end_edit();
+                context.address_bar.set_focused(false);
+            } else if let Some(action) = context.address_bar.handle_key(&key_event.logical_key, text) {
+                match action {
+                    AddressBarAction::Navigate(url) => {
+                        #[cfg(debug_assertions)]
+                        if url.trim().eq_ignore_ascii_case(CRASH_PAGE) {
+                            panic!("Crash requested from {}", CRASH_PAGE);
+                        }
+                        context.address_bar.set_focused(false);
+                        match tab_switch.filter(|(entered, _)| entered.eq_ignore_ascii_case(url.trim())) {
+                            Some((_, tab)) => switched = Some(tab),
+                            None => {
+                                tracing::info!("Navigating to: {}", url);
+                                navigation = Some(url);
+                            }
+                        }
+                    }
+                    AddressBarAction::Typed(input) => typed = Some(input),
+                    AddressBarAction::Edited(value) if console_input => {
+                        context.address_bar.end_edit();
+                        evaluated = Some(value);
+                    }
+                    AddressBarAction::Edited(value) => {
+                        context.address_bar.end_edit();
+                        edited = Some((context.settings_focus, value));
+                    }
+                }
+            }
+        } else if let Some(motion) = CaretMotion::from_key(&key_event.logical_key).filter(|_| self.caret_browsing) {
+            context.move_caret(motion, self.modifiers.shift_key());
+        } else if self.caret_browsing && key_event.logical_key == Key::Named(NamedKey::Enter) {
+            let content = context.page.content();
+            followed = context
+                .selection
+                .and_then(|selection| content.link_at(selection.focus))
+                .map(|link| link.href.clone());
+        } else {
+            context.handle_scroll_key(&key_event.logical_key, self.modifiers.shift_key(), line_height);
+        }
+
+        context.window.request_redraw();
+
+        if let Some(url) = navigation {
+            self.navigate(window_id, url, VisitType::Typed);
+        }
+        if let Some(tab) = switched {
+            self.switch_to_tab(window_id, tab);
+        }
+        if let Some(input) = typed {
+            self.complete_address(window_id, &input);
+        }
+        if let Some(href) = followed {
+            self.follow_link(window_id, &href);
+        }
+        if let Some(expression) = evaluated {
+            self.evaluate_in_console(window_id, expression);
+        }
+        if let Some((focus, value)) = edited {
+            match site {
+                Some(site) => self.update_site_setting(window_id, site, SiteSetting::UserAgent(Some(value))),
+                None => {
+                    let last = listed_settings().count() - 1;
+                    if let Some(setting) = listed_settings().nth(focus.min(last)) {
+                        self.update_setting(window_id, setting.key, value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Commands typed in one window, carried out by the app
+struct WindowCommands<'a> {
+    app: &'a mut App,
+    event_loop: &'a ActiveEventLoop,
+    window_id: WindowId,
+}
+
+impl WindowCommands<'_> {
+    fn context(&mut self) -> Option<&mut WindowContext> {
+        self.app.windows.get_mut(&self.window_id)
+    }
+}
+
+impl CommandHandler for WindowCommands<'_> {
+    fn back(&mut self) {
+        self.app.go_history(self.window_id, true);
+    }
+
+    fn forward(&mut self) {
+        self.app.go_history(self.window_id, false);
+    }
+
+    fn go_up(&mut self, to_root: bool) {
+        self.app.go_up(self.window_id, to_root);
+    }
+
+    fn reload(&mut self) {
+        tracing::info!("Refresh requested");
+        self.app.reload(self.window_id);
+    }
+
+    fn focus_address_bar(&mut self) {
+        if let Some(context) = self.context() {
+            context.address_bar.set_focused(true);
+            context.window.request_redraw();
+        }
+    }
+
+    fn command_palette(&mut self) {
+        self.app.toggle_palette(self.window_id);
+    }
+
+    fn link_hints(&mut self) {
+        if let Some(context) = self.context() {
+            context.place_hints();
+            context.window.request_redraw();
+        }
+    }
+
+    fn caret_browsing(&mut self) {
+        self.app.toggle_caret_browsing(self.window_id);
+    }
+
+    fn new_window(&mut self) {
+        if let Err(e) = self.app.open_window(self.event_loop, self.app.homepage.clone(), true) {
+            tracing::error!("Failed to open window: {}", e);
+        }
+    }
+
+    fn reopen_closed_window(&mut self) {
+        match self.app.services.controller.reopen_closed_window() {
+            Some(saved) => {
+                if let Err(e) = self.app.reopen_window(self.event_loop, &saved) {
+                    tracing::error!("Failed to reopen window: {}", e);
+                }
+            }
+            None => tracing::info!("No closed window to reopen"),
+        }
+    }
+
+    fn tab_overview(&mut self) {
+        self.app.toggle_overview(self.window_id);
+    }
+
+    fn zoom(&mut self, larger: bool) {
+        self.app.step_ui_scale(larger);
+    }
+
+    fn copy(&mut self) {
+        self.app.handle_clipboard_key(self.window_id, true);
+    }
+
+    fn paste(&mut self) {
+        self.app.handle_clipboard_key(self.window_id, false);
+    }
+
+    fn select_all(&mut self) {
+        if let Some(context) = self.context().filter(|context| !context.address_bar.is_focused()) {
+            context.select_all();
+            self.app.select_primary(self.window_id);
+        }
+    }
+
+    fn undo(&mut self) {
+        self.app.undo();
+    }
+
+    fn save_page(&mut self, format: SaveFormat) {
+        self.app.save_page(self.window_id, format);
+    }
+
+    fn print_page(&mut self) {
+        self.app.print_page(self.window_id);
+    }
+
+    fn copy_screenshot(&mut self, full_page: bool) {
+        self.app.copy_screenshot(self.window_id, full_page);
+    }
+
+    fn wrap_preformatted(&mut self) {
+        self.app.toggle_preformatted_wrap();
+    }
+
+    fn site_settings(&mut self) {
+        self.app.open_site_settings(self.window_id);
+    }
+
+    fn security_report(&mut self) {
+        self.app.export_security_report(self.window_id);
+    }
+
+    fn add_search_engine(&mut self) {
+        self.app.offer_search_engine(self.window_id);
+    }
+
+    fn toggle_theme(&mut self) {
+        self.app.toggle_theme();
+    }
+
+    fn dark_pages(&mut self) {
+        self.app.toggle_dark_pages(self.window_id);
+    }
+
+    fn hit_regions(&mut self) {
+        self.app.toggle_hit_overlay(self.window_id);
+    }
+
+    fn toggle_accessibility(&mut self) {
+        self.app.toggle_accessibility();
+    }
+
+    fn toggle_offline(&mut self) {
+        self.app.toggle_offline(self.window_id);
+    }
+
+    fn show_page(&mut self, url: &str) {
+        self.app.navigate(self.window_id, url.to_string(), VisitType::Link);
+    }
+}
+
+/// The chord `key` makes with the modifiers held, as keymaps write it
+fn chord_of(key: &Key, modifiers: ModifiersState) -> Option<Chord> {
+    let held = Modifiers {
+        ctrl: modifiers.control_key(),
+        shift: modifiers.shift_key(),
+        alt: modifiers.alt_key(),
+        logo: modifiers.super_key(),
+    };
+    let name = match key {
+        // Shift turns '=' into '+' and '-' into '_' on most layouts
+        Key::Character(c) if held.shift && c.as_str() == "+" => "=",
+        Key::Character(c) if held.shift && c.as_str() == "_" => "-",
+        Key::Character(c) => c.as_str(),
+        Key::Named(named) => match named {
+            NamedKey::ArrowLeft => "Left",
+            NamedKey::ArrowRight => "Right",
+            NamedKey::ArrowUp => "Up",
+            NamedKey::ArrowDown => "Down",
+            NamedKey::Home => "Home",
+            NamedKey::End => "End",
+            NamedKey::PageUp => "PageUp",
+            NamedKey::PageDown => "PageDown",
+            NamedKey::Enter => "Enter",
+            NamedKey::Escape => "Escape",
+            NamedKey::Tab => "Tab",
+            NamedKey::Space => "Space",
+            NamedKey::Backspace => "Backspace",
+            NamedKey::Delete => "Delete",
+            NamedKey::Insert => "Insert",
+            NamedKey::F1 => "F1",
+            NamedKey::F2 => "F2",
+            NamedKey::F3 => "F3",
+            NamedKey::F4 => "F4",
+            NamedKey::F5 => "F5",
+            NamedKey::F6 => "F6",
+            NamedKey::F7 => "F7",
+            NamedKey::F8 => "F8",
+            NamedKey::F9 => "F9",
+            NamedKey::F10 => "F10",
+            NamedKey::F11 => "F11",
+            NamedKey::F12 => "F12",
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Chord::new(held, name)
+}
+
+/// What navigator://shortcuts lists for `keymap`, by section
+fn shortcuts_view(keymap: &Keymap) -> ShortcutsView {
+    let mut sections: Vec<(String, Vec<(String, String)>)> = Vec::new();
+    for info in COMMANDS {
+        let row = (info.setting.label.to_string(), format_chords(&keymap.chords(info.command)));
+        match sections.last_mut() {
+            Some((section, rows)) if section == info.section => rows.push(row),
+            _ => sections.push((info.section.to_string(), vec![row])),
+        }
+    }
+    ShortcutsView { sections }
+}
+
+/// The site whose settings `input` lists, if it is the address of
+/// navigator://site-settings
+fn site_settings_target(input: &str) -> Option<ValidatedUrl> {
+    let input = input.trim();
+    let prefix = input.get(..SITE_SETTINGS_PAGE.len())?;
+    if !prefix.eq_ignore_ascii_case(SITE_SETTINGS_PAGE) {
+        return None;
+    }
+    let page = ValidatedUrl::parse(input).ok()?;
+    let origin = url::form_urlencoded::parse(page.query()?.as_bytes()).find(|(name, _)| name == "origin")?.1;
+    ValidatedUrl::parse(&origin).ok().filter(|site| SiteOrigin::of(site).is_some())
+}
+
+/// The overrides of `site` listed on navigator://site-settings
+async fn site_settings_view(
+    controller: &BrowserController<ServoRenderer>,
+    site: &ValidatedUrl,
+    focused: usize,
+    error: Option<String>,
+) -> SettingsView {
+    let mut values = match controller.site_settings(site).await {
+        Ok(settings) => site_setting_values(&settings),
+        Err(e) => {
+            tracing::warn!("Failed to read the settings of {}: {}", site, e);
+            Vec::new()
+        }
+    };
+    match controller.site_storage(site).await {
+        Ok(storage) => values.push(site_storage_value(&storage)),
+        Err(e) => tracing::warn!("Failed to read the storage of {}: {}", site, e),
+    }
+    let rows = SITE_SETTING_LABELS
+        .iter()
+        .zip(values)
+        .map(|(label, value)| (label.to_string(), value))
+        .collect();
+    SettingsView {
+        rows,
+        focused,
+        error,
+        shortcuts_from: None,
+    }
+}
+
+/// Whether `input` is the address of navigator://settings
+fn is_settings_page(input: &str) -> bool {
+    input
+        .trim()
+        .get(..SETTINGS_PAGE.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(SETTINGS_PAGE))
+}
+
+/// Whether `input` is the address of navigator://downloads
+fn is_downloads_page(input: &str) -> bool {
+    input
+        .trim()
+        .get(..DOWNLOADS_PAGE.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(DOWNLOADS_PAGE))
+}
+
+/// Whether `input` is the address of navigator://bookmarks
+fn is_bookmarks_page(input: &str) -> bool {
+    input
+        .trim()
+        .get(..BOOKMARKS_PAGE.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(BOOKMARKS_PAGE))
+}
+
+/// Whether `input` is the address of navigator://update
+fn is_update_page(input: &str) -> bool {
+    input
+        .trim()
+        .get(..UPDATE_PAGE.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(UPDATE_PAGE))
+}
+
+/// Whether `input` is the address of navigator://shortcuts
+fn is_shortcuts_page(input: &str) -> bool {
+    input
+        .trim()
+        .get(..SHORTCUTS_PAGE.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(SHORTCUTS_PAGE))
+}
+
+/// Whether `input` is the address of navigator://memory
+fn is_memory_page(input: &str) -> bool {
+    input
+        .trim()
+        .get(..MEMORY_PAGE.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(MEMORY_PAGE))
+}
+
+/// Whether `input` is the trim link of navigator://memory
+fn is_memory_trim(input: &str) -> bool {
+    is_memory_page(input)
+        && ValidatedUrl::parse(input.trim())
+            .is_ok_and(|url| url.query().is_some_and(|query| query.split('&').any(|pair| pair == "trim")))
+}
+
+/// What navigator://memory lists, with what a trim just freed
+fn memory_view(controller: &BrowserController<ServoRenderer>, trimmed: Option<usize>) -> MemoryView {
+    let tab_titles = controller
+        .state()
+        .get_all_tabs()
+        .into_iter()
+        .map(|tab| (tab.id, tab.title))
+        .collect();
+    MemoryView {
+        reports: controller.memory().reports(),
+        tab_titles,
+        ceiling: controller.memory().ceiling(),
+        trimmed,
+    }
+}
+
+/// Whether `input` is the download link of navigator://update
+fn is_update_download(input: &str) -> bool {
+    is_update_page(input)
+        && ValidatedUrl::parse(input.trim())
+            .is_ok_and(|url| url.query().is_some_and(|query| query.split('&').any(|pair| pair == "download=1")))
+}
+
+/// Whether `input` is the address of navigator://console
+fn is_console_page(input: &str) -> bool {
+    input
+        .trim()
+        .get(..CONSOLE_PAGE.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(CONSOLE_PAGE))
+}
+
+/// The settings edited on navigator://settings, with their stored values
+async fn settings_view(
+    controller: &BrowserController<ServoRenderer>,
+    focused: usize,
+    error: Option<String>,
+) -> SettingsView {
+    let mut rows = Vec::new();
+    for setting in listed_settings() {
+        let value = controller.setting(setting.key).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to read {}: {}", setting.key, e);
+            String::new()
+        });
+        rows.push((setting.label.to_string(), value));
+    }
+    SettingsView {
+        rows,
+        focused,
+        error,
+        shortcuts_from: Some(EDITABLE_SETTINGS.len()),
+    }
+}
+
+/// Write the profile to `file`, as chosen on navigator://settings, with
+/// history if the settings ask for it
+async fn export_profile_to(controller: &BrowserController<ServoRenderer>, file: &str) -> Result<(), String> {
+    let history = controller.setting(PROFILE_EXPORT_HISTORY_SETTING).await.map_err(|e| e.to_string())?;
+    let json = controller
+        .export_profile(setting_enabled(PROFILE_EXPORT_HISTORY_SETTING, Some(&history)))
+        .await
+        .map_err(|e| e.to_string())?;
+    write_atomically(file, json).map_err(|e| format!("Cannot write {}: {}", file, e))?;
+    let exported = Notification::new(format!("Profile exported to {}", file), NotificationSeverity::Success);
+    controller.state().notify(exported);
+    Ok(())
+}
+
+/// Merge the profile in `file`, as chosen on navigator://settings, into
+/// this one; a notification counts what came over and what was skipped
+async fn import_profile_from(controller: &BrowserController<ServoRenderer>, file: &str) -> Result<(), String> {
+    let json = tokio::fs::read_to_string(file)
+        .await
+        .map_err(|e| format!("Cannot read {}: {}", file, e))?;
+    let report = controller
+        .import_profile(&json, ImportOptions::all(ImportMode::Merge))
+        .await
+        .map_err(|e| e.to_string())?;
+    if let Err(e) = controller.load_settings().await {
+        tracing::warn!("Failed to reload imported settings: {}", e);
+    }
+    for failure in &report.failures {
+        tracing::warn!("Skipped {} record {}: {}", failure.section, failure.record, failure.reason);
+    }
+    let mut message = format!(
+        "Imported {} bookmarks, {} settings, {} blocked domains and {} history entries",
+        report.bookmarks, report.settings, report.blocked_domains, report.history
+    );
+    let severity = if report.failures.is_empty() {
+        NotificationSeverity::Success
+    } else {
+        message.push_str(&format!("; skipped {} records", report.failures.len()));
+        NotificationSeverity::Warning
+    };
+    controller.state().notify(Notification::new(message, severity));
+    Ok(())
+}
+
+/// Relay state changes to the event loop so windows redraw only when
+/// something they show has changed
+fn forward_state_events(runtime: &Handle, state: &BrowserState, proxy: EventLoopProxy<UserEvent>) {
+    let mut events = state.subscribe();
+    spawn_supervised(runtime, "state events", async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => UserEvent::State(event),
+                Err(RecvError::Lagged(missed)) => {
+                    tracing::warn!("Missed {} state events", missed);
+                    UserEvent::Resync
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if proxy.send_event(event).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Suspend tabs left hidden too long, checking every `SUSPEND_CHECK_INTERVAL`
+fn spawn_tab_suspender(runtime: &Handle, controller: Arc<BrowserController<ServoRenderer>>) {
+    spawn_supervised(runtime, "tab suspender", async move {
+        let mut ticks = tokio::time::interval(SUSPEND_CHECK_INTERVAL);
+        loop {
+            ticks.tick().await;
+            let suspended = controller.suspend_idle_tabs(Instant::now()).await;
+            if !suspended.is_empty() {
+                tracing::info!("Suspended {} hidden tabs", suspended.len());
+            }
+        }
+    });
+}
+
+/// Trim caches holding more than the memory ceiling, checking every
+/// `MEMORY_CHECK_INTERVAL`
+fn spawn_memory_trimmer(runtime: &Handle, controller: Arc<BrowserController<ServoRenderer>>) {
+    spawn_supervised(runtime, "memory trimmer", async move {
+        let mut ticks = tokio::time::interval(MEMORY_CHECK_INTERVAL);
+        loop {
+            ticks.tick().await;
+            let freed = controller.memory().trim_if_over();
+            if freed > 0 {
+                tracing::info!("Trimmed {} bytes of caches past the memory ceiling", freed);
+            }
+        }
+    });
+}
+
+impl ApplicationHandler<UserEvent> for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        event_loop.set_control_flow(ControlFlow::Wait);
+
+        if self.windows.is_empty() {
+            // Windows left open last time, if restoring them is turned on
+            let saved = self
+                .runtime
+                .block_on(self.services.controller.take_saved_session())
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Failed to restore session: {}", e);
+                    Vec::new()
+                });
+            let opened = if saved.is_empty() {
+                self.open_window(event_loop, self.homepage.clone(), true)
+            } else {
+                saved.iter().try_for_each(|window| self.reopen_window(event_loop, window))
+            };
+            if let Err(e) = opened {
+                tracing::error!("Failed to open window: {}", e);
+                event_loop.exit();
+                return;
+            }
+            // Once, with windows open to show what it finds
+            if std::mem::take(&mut self.update_check) {
+                self.check_for_update();
+            }
+        }
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {
+        // The overview is driven by the keyboard; the page under it ignores the mouse
+        let in_overview = self
+            .windows
+            .get(&window_id)
+            .is_some_and(|context| context.overview.is_some());
+        if in_overview
+            && matches!(
+                event,
+                WindowEvent::MouseWheel { .. } | WindowEvent::MouseInput { .. } | WindowEvent::CursorMoved { .. }
+            )
+        {
+            return;
+        }
+
+        match event {
+            WindowEvent::CloseRequested => {
+                tracing::info!("Close requested for window {:?}", window_id);
+                self.close_window(event_loop, window_id);
+            }
+            WindowEvent::Resized(physical_size) => {
+                tracing::debug!("Window resized to: {:?}", physical_size);
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    context.renderer.resize(physical_size);
+                    context.hints = None;
+                    context.caret_goal = None;
+                    context.window.request_redraw();
+                }
+            }
+            WindowEvent::Focused(focused) => {
+                if let Err(e) = self.clipboard.set_focused(focused) {
+                    tracing::warn!("Clipboard error: {}", e);
+                }
+                if focused {
+                    self.wake_tab(window_id, true);
+                }
+            }
+            WindowEvent::Occluded(false) => self.wake_tab(window_id, false),
+            WindowEvent::Occluded(true) => {
+                if let Some(context) = self.windows.get(&window_id) {
+                    let state = self.services.controller.state();
+                    // A suspended tab stays suspended
+                    if state.tab_activity(context.tab) == TabActivity::Foreground {
+                        state.set_tab_activity(context.tab, TabActivity::Background);
+                        self.services.controller.throttle_timers();
+                    }
+                }
+            }
+            WindowEvent::ThemeChanged(system_theme) => {
+                self.system_theme = Some(system_theme);
+                if self.theme_preference == ThemePreference::Auto {
+                    self.apply_theme();
+                }
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    context.renderer.set_scale_factor(scale_factor);
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let line_height = self.theme.text_size(self.fonts.default_size) * 1.2;
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    // Notched wheels report lines and get smoothed; trackpads
+                    // report pixels and already move smoothly
+                    match delta {
+                        MouseScrollDelta::LineDelta(_, lines) => {
+                            context.scroll_by(-lines * WHEEL_SCROLL_LINES * line_height, true)
+                        }
+                        MouseScrollDelta::PixelDelta(position) => {
+                            context.scroll_by(-position.y as f32, false)
+                        }
+                    }
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    context.cursor_moved(position.x as f32, position.y as f32);
+                    // The overlay reads out where the cursor is
+                    if self.hit_overlay {
+                        context.window.request_redraw();
+                    }
+                }
+            }
+            WindowEvent::CursorLeft { .. } => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    context.cursor_left();
+                    if self.hit_overlay {
+                        context.window.request_redraw();
+                    }
+                }
+            }
+            WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                let link = self
+                    .windows
+                    .get_mut(&window_id)
+                    .and_then(|context| context.mouse_button(state));
+                if state == ElementState::Released {
+                    self.select_primary(window_id);
+                }
+                if let Some(href) = link {
+                    self.follow_link(window_id, &href);
+                }
+            }
+            WindowEvent::MouseInput { state: ElementState::Pressed, button: MouseButton::Middle, .. } => {
+                self.paste_primary(window_id);
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: button @ (MouseButton::Back | MouseButton::Forward),
+                ..
+            } => {
+                self.go_history(window_id, button == MouseButton::Back);
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::KeyboardInput { event: key_event, .. }
+                if key_event.state == ElementState::Pressed =>
+            {
+                self.handle_keyboard_input(event_loop, window_id, key_event);
+            }
+            WindowEvent::RedrawRequested if self.shutting_down => {}
+            WindowEvent::RedrawRequested if in_overview => self.draw_overview(window_id),
+            WindowEvent::RedrawRequested => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    let now = Instant::now();
+                    context.scroll.tick(now);
+                    context.toasts.tick(now);
+                    let (generation, content, page_theme) = context.drawn_content(self.dark_pages, &self.theme);
+                    context.address_bar.set_security_level(context.page.security_level());
+                    context.address_bar.set_blocked_count(context.page.html_renderer.blocked_count());
+                    context.address_bar.expire_download(now);
+                    let opacity = context.scrollbar.opacity(now);
+
+                    let pending = context.page.take_arrival().map(|arrival| {
+                        context.address_bar.show_url(&arrival.url);
+                        context.selection = None;
+                        context.selecting = false;
+                        // Same-document arrivals animate and keep the old thumbnail
+                        context.thumbnail_due |= !arrival.scroll.animate;
+                        arrival.scroll
+                    });
+                    let arrived = pending.is_some();
+                    // The first frame of a page ends its navigation
+                    let _render = arrived.then(|| {
+                        let url = context.address_bar.url();
+                        tracing::info_span!("render", tab = %context.tab, url).entered()
+                    });
+                    let reveal = match &pending {
+                        Some(PendingScroll { target: ScrollTarget::Fragment(fragment), .. }) => {
+                            content.anchor_offset(fragment)
+                        }
+                        _ => None,
+                    };
+
+                    // The palette closes with the edit of its query, however that ended
+                    if context.address_bar.editing() != Some(PALETTE_LABEL) {
+                        context.palette = None;
+                    }
+                    let chrome = context.renderer.chrome_layout();
+                    let toasts = context.toasts.layout(&chrome, now);
+                    let palette = context.palette.as_ref().map(|palette| palette.layout(&chrome));
+                    let frame = PageFrame {
+                        content: &content,
+                        generation,
+                        layout_options: self.layout_options,
+                        scrollbar_opacity: opacity,
+                        reveal,
+                        selection: context.selection.map(|s| s.range()),
+                        hints: context.hints.as_ref(),
+                        caret: context.selection.filter(|_| self.caret_browsing).map(|s| s.focus),
+                        toasts: &toasts,
+                        palette: palette.as_ref(),
+                        page_theme,
+                        hit_overlay: self.hit_overlay.then_some(HitOverlay { cursor: context.cursor }),
+                    };
+                    match context.renderer.render(
+                        &frame,
+                        &context.address_bar,
+                        &self.theme,
+                        &mut context.scroll,
+                    ) {
+                        Ok(anchor_offset) => {
+                            if let Some(pending) = pending {
+                                // Missing anchors silently go to the top
+                                let offset = match pending.target {
+                                    ScrollTarget::Offset(offset) => offset,
+                                    ScrollTarget::Fragment(_) => anchor_offset.unwrap_or(0.0),
+                                };
+                                if pending.animate && !context.reduced_motion {
+                                    context.scroll.smooth_scroll_to(offset);
+                                } else {
+                                    context.scroll.set_offset(offset);
+                                }
+                                context.window.request_redraw();
+                            }
+                        }
+                        Err(e) => tracing::error!("Render error: {}", e),
+                    }
+                    // New content moves the links; label them again once laid out
+                    if context.hints.as_ref().is_some_and(|hints| hints.generation() != generation) {
+                        context.place_hints();
+                    }
+                    // A new page starts caret browsing from the top of the view
+                    if self.caret_browsing && context.selection.is_none() {
+                        context.selection = Some(Selection::collapsed(context.caret().offset));
+                        context.window.request_redraw();
+                    }
+
+                    // Capture once the frame after arrival has scrolled into place
+                    if context.thumbnail_due && !arrived {
+                        context.thumbnail_due = false;
+                        let frame = PageFrame {
+                            content: &content,
+                            generation,
+                            layout_options: self.layout_options,
+                            scrollbar_opacity: 0.0,
+                            reveal: None,
+                            selection: None,
+                            hints: None,
+                            caret: None,
+                            toasts: &[],
+                            palette: None,
+                            page_theme,
+                            hit_overlay: None,
+                        };
+                        match context.renderer.capture_thumbnail(
+                            &frame,
+                            &context.address_bar,
+                            &self.theme,
+                            &context.scroll,
+                            THUMBNAIL_WIDTH,
+                        ) {
+                            Ok(thumbnail) => {
+                                if let Ok(mut thumbnails) = self.thumbnails.lock() {
+                                    thumbnails.insert(context.tab, thumbnail);
+                                }
+                            }
+                            Err(e) => tracing::warn!("Failed to capture thumbnail: {}", e),
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        match event {
+            UserEvent::State(StateEvent::TabUpdated(tab)) => {
+                self.update_window_title(tab);
+                self.redraw_overviews();
+            }
+            UserEvent::State(StateEvent::PageContentChanged(tab)) => {
+                self.forget_thumbnail(tab);
+                if let Some(context) = self.window_of_tab(tab) {
+                    context.window.request_redraw();
+                }
+                self.redraw_overviews();
+            }
+            UserEvent::State(StateEvent::PageRestyled(tab)) => {
+                self.forget_thumbnail(tab);
+                if let Some(context) = self.windows.values_mut().find(|context| context.tab == tab) {
+                    // Keeps the scroll offset; only the layout changed
+                    context.page.set_content(context.page.html_renderer.layout());
+                    context.selection = None;
+                    context.thumbnail_due = true;
+                    context.window.request_redraw();
+                }
+                self.redraw_overviews();
+            }
+            UserEvent::State(StateEvent::TabSuspended(tab)) => {
+                let controller = &self.services.controller;
+                if let Some(context) = self.windows.values_mut().find(|context| context.tab == tab) {
+                    // Let go of the layout too; waking restores it and the scroll offset
+                    controller.set_scroll_offset(tab, context.scroll.target());
+                    context.page.set_content(Arc::new(PageContent::default()));
+                    context.selection = None;
+                }
+            }
+            UserEvent::State(StateEvent::TabRemoved(tab)) => {
+                self.forget_thumbnail(tab);
+                self.redraw_overviews();
+                // Private tabs' cookies go with the last of them
+                let tabs = self.services.controller.state().get_all_tabs();
+                if !tabs.iter().any(|tab| tab.is_private) {
+                    self.services.network.end_private_session();
+                }
+            }
+            UserEvent::State(StateEvent::TabAdded(_)) => self.redraw_overviews(),
+            UserEvent::State(StateEvent::SettingChanged(key)) => self.apply_setting(key),
+            UserEvent::State(StateEvent::Notified(id)) => self.show_notification(id),
+            UserEvent::State(StateEvent::DownloadsChanged) => {
+                let due = self.downloads_refreshed.map(|at| at + DOWNLOADS_REFRESH_INTERVAL);
+                if due.is_some_and(|due| due > Instant::now()) {
+                    self.downloads_refresh_due = true;
+                } else {
+                    self.refresh_downloads_pages();
+                }
+            }
+            // Each window shows a single tab, so the rest change nothing on screen
+            UserEvent::State(_) => {}
+            UserEvent::Arrived(window_id) => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    // An offer from the page left behind no longer applies
+                    context.address_bar.set_prompt(None);
+                    context.search_offer = None;
+                    context.external_launch = None;
+                    context.window.request_redraw();
+                }
+            }
+            UserEvent::SearchEngineOffered(window_id, offer) => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    let prompt = match &offer {
+                        Some(engine) => format!(
+                            "Add {} as a search engine with the keyword {}? Enter adds it, any other key cancels",
+                            engine.name, engine.keyword
+                        ),
+                        None => "This page offers no search engine to add".to_string(),
+                    };
+                    context.address_bar.set_prompt(Some(prompt));
+                    context.search_offer = offer;
+                    context.external_launch = None;
+                    context.window.request_redraw();
+                }
+            }
+            UserEvent::PageSaved(window_id, saved) => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    // Where it went, or why it failed, arrives as a notification
+                    let indicator = if saved { DownloadIndicator::Finished } else { DownloadIndicator::Failed };
+                    context.show_download(indicator);
+                }
+            }
+            UserEvent::UpdateDownloaded(window_id, downloaded) => {
+                let Some(context) = self.windows.get_mut(&window_id) else {
+                    return;
+                };
+                let indicator = if downloaded { DownloadIndicator::Finished } else { DownloadIndicator::Failed };
+                context.show_download(indicator);
+                // Where a saved release went, or that it waits for confirmation
+                if downloaded {
+                    self.navigate(window_id, DOWNLOADS_PAGE.to_string(), VisitType::Link);
+                }
+            }
+            UserEvent::SettingSaved(window_id, error) => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    context.settings_error = error;
+                }
+                self.reload(window_id);
+            }
+            UserEvent::ConsoleEvaluated(window_id) => self.reload(window_id),
+            UserEvent::ConnectivityLost(window_id) => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    let message = "The network cannot be reached. Ctrl+Shift+O shows pages kept for offline use";
+                    context.search_offer = None;
+                    context.external_launch = None;
+                    context.address_bar.set_prompt(Some(message.to_string()));
+                    context.window.request_redraw();
+                }
+            }
+            UserEvent::ExternalLaunch(window_id, launch) => {
+                if let Some(context) = self.windows.get_mut(&window_id) {
+                    let prompt = match launch {
+                        ExternalLaunch::Launched(url) => {
+                            context.external_launch = None;
+                            format!("Opened {} in another program", url)
+                        }
+                        ExternalLaunch::PendingConfirmation(pending) => {
+                            let prompt = format!(
+                                "Open {} in another program? Enter opens it, A always opens {}: links, \
+                                 any other key cancels",
+                                pending.url,
+                                pending.url.scheme()
+                            );
+                            context.external_launch = Some(pending);
+                            prompt
+                        }
+                    };
+                    context.search_offer = None;
+                    context.address_bar.set_prompt(Some(prompt));
+                    context.window.request_redraw();
+                }
+            }
+            UserEvent::Resync => {
+                let tabs: Vec<TabId> = self.windows.values().map(|context| context.tab).collect();
+                for tab in tabs {
+                    self.update_window_title(tab);
+                }
+                for context in self.windows.values() {
+                    context.window.request_redraw();
+                }
+            }
+            // Only the launcher waits for the profile
+            UserEvent::DbReady => {}
+            UserEvent::ShutdownRequested => self.shut_down(event_loop),
+        }
+    }
+
+    fn exiting(&mut self, event_loop: &ActiveEventLoop) {
+        self.shut_down(event_loop);
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        // Only keep the loop awake while something is animating; otherwise
+        // sleep until input or the next scheduled change
+        let now = Instant::now();
+        let mut wake_at: Option<Instant> = None;
+        if self.downloads_refresh_due {
+            let due = self.downloads_refreshed.map_or(now, |at| at + DOWNLOADS_REFRESH_INTERVAL);
+            if due <= now {
+                self.refresh_downloads_pages();
+            } else {
+                wake_at = Some(due);
+            }
+        }
+        for context in self.windows.values() {
+            match context.next_frame(now) {
+                Some(at) if at <= now => context.window.request_redraw(),
+                Some(at) => wake_at = Some(wake_at.map_or(at, |w| w.min(at))),
+                None => {}
+            }
+        }
+
+        event_loop.set_control_flow(match wake_at {
+            Some(at) => ControlFlow::WaitUntil(at),
+            None => ControlFlow::Wait,
+        });
+    }
+}
@@ -0,0 +1,232 @@
+use super::selection::floor_char_boundary;
+use std::ops::Range;
+use winit::keyboard::{Key, NamedKey};
+
+/// Ways the keyboard moves the caret through page text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaretMotion {
+    Left,
+    Right,
+    Up,
+    Down,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+}
+
+impl CaretMotion {
+    pub fn from_key(key: &Key) -> Option<Self> {
+        let motion = match key {
+            Key::Named(NamedKey::ArrowLeft) => Self::Left,
+            Key::Named(NamedKey::ArrowRight) => Self::Right,
+            Key::Named(NamedKey::ArrowUp) => Self::Up,
+            Key::Named(NamedKey::ArrowDown) => Self::Down,
+            Key::Named(NamedKey::Home) => Self::LineStart,
+            Key::Named(NamedKey::End) => Self::LineEnd,
+            Key::Named(NamedKey::PageUp) => Self::PageUp,
+            Key::Named(NamedKey::PageDown) => Self::PageDown,
+            _ => return None,
+        };
+        Some(motion)
+    }
+
+    fn is_vertical(self) -> bool {
+        matches!(self, Self::Up | Self::Down | Self::PageUp | Self::PageDown)
+    }
+}
+
+/// Where laid-out page text is, as moving a caret needs it. All positions
+/// are in layout coordinates.
+pub trait CaretLayout {
+    /// `(x, top, height)` of a caret just before a byte offset of the text
+    fn caret_position(&self, offset: usize) -> Option<(f32, f32, f32)>;
+    /// Byte range of the visual line holding an offset
+    fn visual_line(&self, offset: usize) -> Option<Range<usize>>;
+    /// Byte offset of the text under a point
+    fn hit(&self, x: f32, y: f32) -> Option<usize>;
+}
+
+/// The caret of caret browsing: a byte offset of the page text, so it
+/// stays on the same text when the page is laid out again
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Caret {
+    pub offset: usize,
+    /// Column that moving up and down keeps to, across shorter lines
+    pub goal_x: Option<f32>,
+}
+
+impl Caret {
+    pub fn new(offset: usize) -> Self {
+        Self { offset, goal_x: None }
+    }
+
+    /// Bring the caret back onto `text` after it changed under it, to the
+    /// nearest character boundary
+    pub fn reanchor(self, text: &str) -> Self {
+        let offset = floor_char_boundary(text, self.offset.min(text.len()));
+        Self { offset, ..self }
+    }
+
+    /// The caret after `motion` through `text` laid out by `layout`; paging
+    /// moves by `page` pixels
+    pub fn moved(self, motion: CaretMotion, text: &str, layout: &impl CaretLayout, page: f32) -> Self {
+        let Self { offset, goal_x } = self.reanchor(text);
+        if !motion.is_vertical() {
+            let offset = match motion {
+                CaretMotion::Left => text[..offset].chars().next_back().map_or(0, |c| offset - c.len_utf8()),
+                CaretMotion::Right => text[offset..].chars().next().map_or(offset, |c| offset + c.len_utf8()),
+                CaretMotion::LineStart => layout.visual_line(offset).map_or(offset, |line| line.start),
+                _ => layout.visual_line(offset).map_or(offset, |line| line.end),
+            };
+            return Self::new(offset);
+        }
+
+        let Some((x, top, height)) = layout.caret_position(offset) else {
+            return Self::new(offset);
+        };
+        let x = goal_x.unwrap_or(x);
+        let (y, up) = match motion {
+            CaretMotion::Up => (top - height / 2.0, true),
+            CaretMotion::Down => (top + height * 1.5, false),
+            CaretMotion::PageUp => (top + height / 2.0 - page, true),
+            _ => (top + height / 2.0 + page, false),
+        };
+        let offset = layout
+            .hit(x, y)
+            .map_or(if up { 0 } else { text.len() }, |hit| hit.min(text.len()));
+        Self {
+            offset: floor_char_boundary(text, offset),
+            goal_x: Some(x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Monospace layout: every character 10 wide, lines 20 high, source
+    /// lines wrapped after `columns` characters
+    struct Grid {
+        /// Byte range of every visual line
+        lines: Vec<Range<usize>>,
+    }
+
+    impl Grid {
+        fn new(text: &str, columns: usize) -> Self {
+            let mut lines = Vec::new();
+            let mut start = 0;
+            for source in text.split('\n') {
+                let mut line_start = start;
+                while source.len() - (line_start - start) > columns {
+                    lines.push(line_start..line_start + columns);
+                    line_start += columns;
+                }
+                lines.push(line_start..start + source.len());
+                start += source.len() + 1;
+            }
+            Self { lines }
+        }
+
+        fn line_of(&self, offset: usize) -> usize {
+            // An offset at the end of a wrapped line belongs to the next one
+            self.lines.partition_point(|line| line.start <= offset).saturating_sub(1)
+        }
+    }
+
+    impl CaretLayout for Grid {
+        fn caret_position(&self, offset: usize) -> Option<(f32, f32, f32)> {
+            let index = self.line_of(offset);
+            let line = self.lines.get(index)?;
+            Some(((offset - line.start) as f32 * 10.0, index as f32 * 20.0, 20.0))
+        }
+
+        fn visual_line(&self, offset: usize) -> Option<Range<usize>> {
+            self.lines.get(self.line_of(offset)).cloned()
+        }
+
+        /// Like glyphon, points above the text hit its start and points
+        /// below it the end of the last line
+        fn hit(&self, x: f32, y: f32) -> Option<usize> {
+            if y < 0.0 {
+                return self.lines.first().map(|line| line.start);
+            }
+            let Some(line) = self.lines.get((y / 20.0) as usize) else {
+                return self.lines.last().map(|line| line.end);
+            };
+            let column = (x / 10.0).round() as usize;
+            Some((line.start + column).min(line.end))
+        }
+    }
+
+    fn moved(caret: Caret, motion: CaretMotion, text: &str, grid: &Grid) -> Caret {
+        caret.moved(motion, text, grid, 60.0)
+    }
+
+    #[test]
+    fn test_keys_map_to_motions() {
+        assert_eq!(CaretMotion::from_key(&Key::Named(NamedKey::Home)), Some(CaretMotion::LineStart));
+        assert_eq!(CaretMotion::from_key(&Key::Named(NamedKey::PageDown)), Some(CaretMotion::PageDown));
+        assert_eq!(CaretMotion::from_key(&Key::Named(NamedKey::Space)), None);
+    }
+
+    #[test]
+    fn test_caret_moves_by_character_and_line() {
+        let text = "naïve\nab\nlonger line";
+        let grid = Grid::new(text, 40);
+        let caret = Caret::new(2);
+        // Multi-byte characters are stepped over whole
+        assert_eq!(moved(caret, CaretMotion::Right, text, &grid).offset, 4);
+        assert_eq!(moved(Caret::new(4), CaretMotion::Left, text, &grid).offset, 2);
+        assert_eq!(moved(Caret::new(0), CaretMotion::Left, text, &grid).offset, 0);
+        assert_eq!(moved(Caret::new(text.len()), CaretMotion::Right, text, &grid).offset, text.len());
+
+        assert_eq!(moved(caret, CaretMotion::LineStart, text, &grid).offset, 0);
+        assert_eq!(moved(caret, CaretMotion::LineEnd, text, &grid).offset, 6);
+
+        // Down through a shorter line keeps the column for the next one
+        let start = Caret::new(text.find("ve").unwrap());
+        let short = moved(start, CaretMotion::Down, text, &grid);
+        assert_eq!(short.offset, text.find("\nlonger").unwrap());
+        let long = moved(short, CaretMotion::Down, text, &grid);
+        assert_eq!(long.offset, text.find("er line").unwrap());
+        // Any other motion forgets the column
+        assert_eq!(moved(long, CaretMotion::Left, text, &grid).goal_x, None);
+
+        assert_eq!(moved(start, CaretMotion::Up, text, &grid).offset, 0);
+        assert_eq!(moved(long, CaretMotion::Down, text, &grid).offset, text.len());
+    }
+
+    #[test]
+    fn test_caret_pages_through_text() {
+        let text = (0..30).map(|i| format!("line {:02}", i)).collect::<Vec<_>>().join("\n");
+        let grid = Grid::new(&text, 40);
+        // A page of 60 pixels is three lines
+        let caret = moved(Caret::new(text.find("line 05").unwrap()), CaretMotion::PageDown, &text, &grid);
+        assert_eq!(caret.offset, text.find("line 08").unwrap());
+        let caret = moved(caret, CaretMotion::PageUp, &text, &grid);
+        assert_eq!(caret.offset, text.find("line 05").unwrap());
+        assert_eq!(moved(Caret::new(3), CaretMotion::PageUp, &text, &grid).offset, 0);
+    }
+
+    #[test]
+    fn test_caret_keeps_its_text_across_reflow() {
+        let text = "one two three four five six seven";
+        let offset = text.find("five").unwrap();
+        let wide = Grid::new(text, 40);
+        let narrow = Grid::new(text, 10);
+        // The same offset is found on whichever line the text wrapped to
+        assert_eq!(wide.caret_position(offset), Some((190.0, 0.0, 20.0)));
+        let (x, top, _) = narrow.caret_position(offset).unwrap();
+        assert_eq!(narrow.hit(x, top), Some(offset));
+        let line = narrow.visual_line(offset).unwrap();
+        assert!(line.contains(&offset) && line.len() <= 10);
+        assert_eq!(moved(Caret::new(offset), CaretMotion::Down, text, &narrow).offset, offset + 10);
+
+        // Text shrinking under the caret pulls it back onto a character
+        let shorter = "naïve";
+        assert_eq!(Caret::new(3).reanchor(shorter).offset, 2);
+        assert_eq!(Caret::new(40).reanchor(shorter).offset, shorter.len());
+    }
+}
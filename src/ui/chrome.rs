@@ -0,0 +1,215 @@
+/// Settings key for the chrome scale, e.g. "1.25"
+pub const UI_SCALE_SETTING: &str = "ui.scale";
+pub const MIN_UI_SCALE: f32 = 0.75;
+pub const MAX_UI_SCALE: f32 = 2.0;
+/// How far one Ctrl+Shift+= / Ctrl+Shift+- press moves the scale
+pub const UI_SCALE_STEP: f32 = 0.125;
+
+/// Chrome metrics in logical pixels at a UI scale of 1
+const ADDRESS_BAR_HEIGHT: f32 = 50.0;
+const ADDRESS_BAR_FONT_SIZE: f32 = 18.0;
+const ADDRESS_BAR_INSET: f32 = 10.0;
+/// Size of the blocked-requests badge at the right of the address bar
+const BADGE_WIDTH: f32 = 40.0;
+const BADGE_HEIGHT: f32 = 24.0;
+/// Page margin; part of the page rather than the chrome, so never UI-scaled
+const CONTENT_PADDING: f32 = 20.0;
+
+/// Stored UI scale, clamped to the supported range; 1 when unset or invalid
+pub fn parse_ui_scale(settings: &[(String, String)]) -> f32 {
+    settings
+        .iter()
+        .find(|(key, _)| key == UI_SCALE_SETTING)
+        .and_then(|(_, value)| value.trim().parse::<f32>().ok())
+        .filter(|scale| scale.is_finite())
+        .map_or(1.0, clamp_ui_scale)
+}
+
+pub fn clamp_ui_scale(scale: f32) -> f32 {
+    scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE)
+}
+
+/// The next UI scale step up or down from `scale`
+pub fn step_ui_scale(scale: f32, larger: bool) -> f32 {
+    let steps = (scale / UI_SCALE_STEP).round() + if larger { 1.0 } else { -1.0 };
+    clamp_ui_scale(steps * UI_SCALE_STEP)
+}
+
+/// Part of the window under a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromeRegion {
+    AddressBar,
+    Content,
+    /// Gutter reserved for the page scrollbar
+    Scrollbar,
+}
+
+/// Where the chrome and the page sit in a window, in physical pixels.
+///
+/// Computed per frame from the window size, the display scale factor and
+/// the UI scale; drawing and hit-testing both read it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChromeLayout {
+    pub width: f32,
+    pub height: f32,
+    pub address_bar_height: f32,
+    /// Top-left of the address bar text
+    pub address_text_left: f32,
+    pub address_text_top: f32,
+    pub address_text_width: f32,
+    pub address_text_height: f32,
+    pub address_font_size: f32,
+    /// Top-left and size of the blocked-requests badge
+    pub badge_left: f32,
+    pub badge_top: f32,
+    pub badge_width: f32,
+    pub badge_height: f32,
+    pub content_top: f32,
+    pub content_height: f32,
+    /// Width left of the scrollbar gutter
+    pub content_width: f32,
+    /// Left edge of the content area; past the gutter when it is mirrored
+    pub content_left: f32,
+    pub content_padding: f32,
+    pub scrollbar_width: f32,
+    /// Physical pixels per logical pixel of chrome, after the UI scale
+    pub scale: f32,
+}
+
+impl ChromeLayout {
+    pub fn compute(width: f32, height: f32, scale_factor: f64, ui_scale: f32) -> Self {
+        let hidpi = scale_factor as f32;
+        let chrome = clamp_ui_scale(ui_scale) * hidpi;
+        let address_bar_height = (ADDRESS_BAR_HEIGHT * chrome).min(height.max(0.0));
+        let inset = ADDRESS_BAR_INSET * chrome;
+        let scrollbar_width = super::scrollbar::scrollbar_width(scale_factor);
+        let badge_width = (BADGE_WIDTH * chrome).min(width.max(0.0));
+        let badge_height = (BADGE_HEIGHT * chrome).min(address_bar_height);
+        let badge_left = (width - inset - badge_width).max(0.0);
+
+        Self {
+            width,
+            height,
+            address_bar_height,
+            address_text_left: inset,
+            address_text_top: inset,
+            address_text_width: (badge_left - inset * 2.0).max(0.0),
+            address_text_height: (address_bar_height - inset).max(0.0),
+            address_font_size: ADDRESS_BAR_FONT_SIZE * chrome,
+            badge_left,
+            badge_top: (address_bar_height - badge_height) / 2.0,
+            badge_width,
+            badge_height,
+            content_top: address_bar_height,
+            content_height: (height - address_bar_height).max(0.0),
+            content_width: (width - scrollbar_width).max(0.0),
+            content_left: 0.0,
+            content_padding: CONTENT_PADDING,
+            scrollbar_width,
+            scale: chrome,
+        }
+    }
+
+    /// The layout for a right-to-left page, with the scrollbar gutter on
+    /// the left and the content area after it
+    pub fn mirrored(mut self) -> Self {
+        self.content_left = self.width - self.content_width;
+        self
+    }
+
+    /// Right edge of the scrollbar gutter
+    pub fn scrollbar_right(&self) -> f32 {
+        if self.content_left > 0.0 {
+            self.content_left
+        } else {
+            self.width
+        }
+    }
+
+    pub fn hit(&self, x: f32, y: f32) -> Option<ChromeRegion> {
+        if x < 0.0 || y < 0.0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(if y < self.content_top {
+            ChromeRegion::AddressBar
+        } else if x < self.content_left || x >= self.content_left + self.content_width {
+            ChromeRegion::Scrollbar
+        } else {
+            ChromeRegion::Content
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALES: [(f64, f32); 4] = [(1.0, 1.0), (2.0, 1.0), (1.0, 1.5), (1.5, 2.0)];
+
+    #[test]
+    fn test_hit_regions_follow_combined_scale() {
+        for (scale_factor, ui_scale) in SCALES {
+            let layout = ChromeLayout::compute(1600.0, 1200.0, scale_factor, ui_scale);
+            let bar = 50.0 * ui_scale * scale_factor as f32;
+            let gutter = 12.0 * scale_factor as f32;
+            assert_eq!(layout.content_top, bar);
+            assert_eq!(layout.content_height, 1200.0 - bar);
+
+            assert_eq!(layout.hit(10.0, 0.0), Some(ChromeRegion::AddressBar));
+            assert_eq!(layout.hit(10.0, bar - 0.5), Some(ChromeRegion::AddressBar));
+            assert_eq!(layout.hit(10.0, bar), Some(ChromeRegion::Content));
+            assert_eq!(layout.hit(1600.0 - gutter - 0.5, bar), Some(ChromeRegion::Content));
+            assert_eq!(layout.hit(1600.0 - gutter, bar), Some(ChromeRegion::Scrollbar));
+            assert_eq!(layout.hit(1600.0 - gutter, bar - 1.0), Some(ChromeRegion::AddressBar));
+            assert_eq!(layout.hit(1600.0, bar), None);
+        }
+    }
+
+    #[test]
+    fn test_mirrored_layout_puts_scrollbar_on_the_left() {
+        let layout = ChromeLayout::compute(1600.0, 1200.0, 1.0, 1.0).mirrored();
+        let gutter = 1600.0 - layout.content_width;
+        assert_eq!(layout.content_left, gutter);
+        assert_eq!(layout.scrollbar_right(), gutter);
+        assert_eq!(layout.hit(0.0, 100.0), Some(ChromeRegion::Scrollbar));
+        assert_eq!(layout.hit(gutter - 0.5, 100.0), Some(ChromeRegion::Scrollbar));
+        assert_eq!(layout.hit(gutter, 100.0), Some(ChromeRegion::Content));
+        assert_eq!(layout.hit(1599.0, 100.0), Some(ChromeRegion::Content));
+        assert_eq!(ChromeLayout::compute(1600.0, 1200.0, 1.0, 1.0).scrollbar_right(), 1600.0);
+    }
+
+    #[test]
+    fn test_address_text_scales_with_chrome() {
+        for (scale_factor, ui_scale) in SCALES {
+            let layout = ChromeLayout::compute(1600.0, 1200.0, scale_factor, ui_scale);
+            let chrome = ui_scale * scale_factor as f32;
+            assert_eq!(layout.address_font_size, 18.0 * chrome);
+            assert_eq!(layout.address_text_top, 10.0 * chrome);
+            assert!(layout.address_text_top + layout.address_text_height <= layout.content_top);
+            assert!(layout.address_text_left + layout.address_text_width < layout.badge_left);
+            assert!(layout.badge_left + layout.badge_width <= layout.width);
+            assert!(layout.badge_top >= 0.0 && layout.badge_top + layout.badge_height <= layout.content_top);
+        }
+    }
+
+    #[test]
+    fn test_tiny_window_keeps_layout_inside() {
+        let layout = ChromeLayout::compute(20.0, 40.0, 2.0, 2.0);
+        assert_eq!(layout.content_top, 40.0);
+        assert_eq!(layout.content_height, 0.0);
+        assert_eq!(layout.hit(5.0, 39.0), Some(ChromeRegion::AddressBar));
+    }
+
+    #[test]
+    fn test_ui_scale_setting_and_steps() {
+        let setting = |value: &str| vec![(UI_SCALE_SETTING.to_string(), value.to_string())];
+        assert_eq!(parse_ui_scale(&[]), 1.0);
+        assert_eq!(parse_ui_scale(&setting("1.25")), 1.25);
+        assert_eq!(parse_ui_scale(&setting("5")), MAX_UI_SCALE);
+        assert_eq!(parse_ui_scale(&setting("NaN")), 1.0);
+        assert_eq!(step_ui_scale(1.0, true), 1.125);
+        assert_eq!(step_ui_scale(1.0, false), 0.875);
+        assert_eq!(step_ui_scale(MIN_UI_SCALE, false), MIN_UI_SCALE);
+        assert_eq!(step_ui_scale(MAX_UI_SCALE, true), MAX_UI_SCALE);
+    }
+}
@@ -0,0 +1,461 @@
+use super::screenshot::Screenshot;
+use crate::domain::ValidatedUrl;
+use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
+
+/// Whether there is a primary selection: text selected anywhere, pasted
+/// with a middle click, as X11 and Wayland desktops have
+const PRIMARY_SELECTION: bool = cfg!(target_os = "linux");
+
+/// Something that can hold clipboard text and images
+pub trait ClipboardBackend: Send {
+    fn get_text(&mut self) -> Result<String>;
+    fn set_text(&mut self, text: &str) -> Result<()>;
+    fn set_image(&mut self, image: &Screenshot) -> Result<()>;
+    /// Text in the primary selection
+    fn get_primary(&mut self) -> Result<String>;
+    fn set_primary(&mut self, text: &str) -> Result<()>;
+    /// Whether writes only succeed while one of the browser's windows has
+    /// the keyboard focus, as under Wayland
+    fn needs_focus(&self) -> bool {
+        false
+    }
+}
+
+/// The operating system clipboard
+struct SystemClipboard {
+    clipboard: arboard::Clipboard,
+    wayland: bool,
+}
+
+impl ClipboardBackend for SystemClipboard {
+    fn get_text(&mut self) -> Result<String> {
+        Ok(self.clipboard.get_text()?)
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        Ok(self.clipboard.set_text(text)?)
+    }
+
+    fn set_image(&mut self, image: &Screenshot) -> Result<()> {
+        let image = arboard::ImageData {
+            width: image.width as usize,
+            height: image.height as usize,
+            bytes: Cow::Borrowed(&image.rgba),
+        };
+        Ok(self.clipboard.set_image(image)?)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn get_primary(&mut self) -> Result<String> {
+        use arboard::{GetExtLinux, LinuxClipboardKind};
+        Ok(self.clipboard.get().clipboard(LinuxClipboardKind::Primary).text()?)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn get_primary(&mut self) -> Result<String> {
+        Err(anyhow!("There is no primary selection here"))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_primary(&mut self, text: &str) -> Result<()> {
+        use arboard::{LinuxClipboardKind, SetExtLinux};
+        Ok(self.clipboard.set().clipboard(LinuxClipboardKind::Primary).text(text)?)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_primary(&mut self, _text: &str) -> Result<()> {
+        Err(anyhow!("There is no primary selection here"))
+    }
+
+    fn needs_focus(&self) -> bool {
+        self.wayland
+    }
+}
+
+/// In-process clipboard, used when no system clipboard is available and in tests
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    contents: Arc<Mutex<Option<String>>>,
+    image: Arc<Mutex<Option<Screenshot>>>,
+    primary: Arc<Mutex<Option<String>>>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current contents, for inspecting what was copied
+    pub fn contents(&self) -> Option<String> {
+        self.contents.lock().ok()?.clone()
+    }
+
+    /// Last image copied
+    pub fn image(&self) -> Option<Screenshot> {
+        self.image.lock().ok()?.clone()
+    }
+
+    /// Text last selected
+    pub fn primary(&self) -> Option<String> {
+        self.primary.lock().ok()?.clone()
+    }
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn get_text(&mut self) -> Result<String> {
+        self.contents()
+            .ok_or_else(|| anyhow!("Clipboard is empty"))
+    }
+
+    fn set_text(&mut self, text: &str) -> Result<()> {
+        let mut contents = self
+            .contents
+            .lock()
+            .map_err(|_| anyhow!("Clipboard lock poisoned"))?;
+        *contents = Some(text.to_string());
+        Ok(())
+    }
+
+    fn set_image(&mut self, image: &Screenshot) -> Result<()> {
+        let mut current = self
+            .image
+            .lock()
+            .map_err(|_| anyhow!("Clipboard lock poisoned"))?;
+        *current = Some(image.clone());
+        Ok(())
+    }
+
+    fn get_primary(&mut self) -> Result<String> {
+        self.primary()
+            .ok_or_else(|| anyhow!("Nothing is selected"))
+    }
+
+    fn set_primary(&mut self, text: &str) -> Result<()> {
+        let mut primary = self
+            .primary
+            .lock()
+            .map_err(|_| anyhow!("Clipboard lock poisoned"))?;
+        *primary = Some(text.to_string());
+        Ok(())
+    }
+}
+
+/// A clipboard write held back until a window has the focus again
+#[derive(Debug, Clone, PartialEq)]
+enum QueuedWrite {
+    Text(String),
+    Image(Screenshot),
+}
+
+/// Clipboard shared by the address bar and page content
+pub struct Clipboard {
+    backend: Box<dyn ClipboardBackend>,
+    /// Why the system clipboard could not be opened, when falling back
+    unavailable: Option<String>,
+    /// Whether one of the browser's windows has the keyboard focus
+    focused: bool,
+    /// Last copy made without the focus the backend needs
+    queued: Option<QueuedWrite>,
+    /// Last selection made without the focus the backend needs
+    queued_primary: Option<String>,
+}
+
+impl Clipboard {
+    /// The system clipboard, falling back to an in-process one if it can't be opened
+    pub fn system() -> Self {
+        match arboard::Clipboard::new() {
+            Ok(clipboard) => Self::with_backend(SystemClipboard {
+                clipboard,
+                wayland: cfg!(target_os = "linux") && std::env::var_os("WAYLAND_DISPLAY").is_some(),
+            }),
+            Err(e) => {
+                tracing::warn!("System clipboard unavailable, using in-process clipboard: {}", e);
+                Self {
+                    unavailable: Some(e.to_string()),
+                    ..Self::with_backend(MemoryClipboard::new())
+                }
+            }
+        }
+    }
+
+    pub fn with_backend(backend: impl ClipboardBackend + 'static) -> Self {
+        Self {
+            backend: Box::new(backend),
+            unavailable: None,
+            focused: true,
+            queued: None,
+            queued_primary: None,
+        }
+    }
+
+    /// Copy text; without the focus the backend needs, it is copied once a
+    /// window has it again
+    pub fn copy_text(&mut self, text: &str) -> Result<()> {
+        if self.waits_for_focus() {
+            self.queued = Some(QueuedWrite::Text(text.to_string()));
+            return Ok(());
+        }
+        self.backend.set_text(text)
+    }
+
+    /// Text on the clipboard, counting a copy still waiting for the focus
+    pub fn paste_text(&mut self) -> Result<String> {
+        match &self.queued {
+            Some(QueuedWrite::Text(text)) => Ok(text.clone()),
+            _ => self.backend.get_text(),
+        }
+    }
+
+    /// Put text selected in a page in the primary selection; nothing
+    /// happens where there is none
+    pub fn select_text(&mut self, text: &str) -> Result<()> {
+        if !PRIMARY_SELECTION {
+            return Ok(());
+        }
+        if self.waits_for_focus() {
+            self.queued_primary = Some(text.to_string());
+            return Ok(());
+        }
+        self.backend.set_primary(text)
+    }
+
+    /// Text in the primary selection, for a middle click to paste
+    pub fn paste_selection(&mut self) -> Result<String> {
+        if !PRIMARY_SELECTION {
+            return Err(anyhow!("There is no primary selection here"));
+        }
+        match &self.queued_primary {
+            Some(text) => Ok(text.clone()),
+            None => self.backend.get_primary(),
+        }
+    }
+
+    /// Note whether one of the browser's windows has the keyboard focus,
+    /// making the writes held back for it when it comes back
+    pub fn set_focused(&mut self, focused: bool) -> Result<()> {
+        self.focused = focused;
+        if !focused {
+            return Ok(());
+        }
+        let primary = self.queued_primary.take().map(|text| self.backend.set_primary(&text));
+        let copied = match self.queued.take() {
+            Some(QueuedWrite::Text(text)) => Some(self.backend.set_text(&text)),
+            Some(QueuedWrite::Image(image)) => Some(self.backend.set_image(&image)),
+            None => None,
+        };
+        copied.into_iter().chain(primary).collect()
+    }
+
+    fn waits_for_focus(&self) -> bool {
+        !self.focused && self.backend.needs_focus()
+    }
+
+    /// Copy a URL in its serialized form: punycode hosts and percent-encoded
+    /// paths, so look-alike characters can't be pasted elsewhere
+    pub fn copy_url(&mut self, url: &ValidatedUrl) -> Result<()> {
+        self.copy_text(url.as_str())
+    }
+
+    /// Copy a screenshot. Unlike text, an image is never kept in-process
+    /// only: it is meant for other applications, so a missing system
+    /// clipboard is an error.
+    pub fn copy_image(&mut self, image: &Screenshot) -> Result<()> {
+        if let Some(reason) = &self.unavailable {
+            return Err(anyhow!("The system clipboard is unavailable: {}", reason));
+        }
+        if self.waits_for_focus() {
+            self.queued = Some(QueuedWrite::Image(image.clone()));
+            return Ok(());
+        }
+        self.backend.set_image(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! The system clipboard needs a desktop session, so these run against
+    //! in-process backends. To check it by hand on Linux:
+    //!
+    //! - Under X11 and under Wayland, select text in a page and middle-click
+    //!   another program's text field: the selection is pasted there.
+    //! - Select text in another program and middle-click the address bar:
+    //!   the selection is typed into it.
+    //! - Under Wayland, copy from a page, switch to another program and
+    //!   paste: the copy is there. Logs show no clipboard errors when
+    //!   switching between the browser's windows and other programs.
+    //! - The window shows the browser's icon in the task bar and switcher.
+
+    use super::*;
+
+    /// A clipboard refusing writes while unfocused, as Wayland's does
+    #[derive(Clone, Default)]
+    struct FocusClipboard {
+        memory: MemoryClipboard,
+        focused: Arc<Mutex<bool>>,
+    }
+
+    impl FocusClipboard {
+        fn focus(&self, focused: bool) {
+            *self.focused.lock().unwrap() = focused;
+        }
+
+        fn check(&self) -> Result<()> {
+            if !*self.focused.lock().unwrap() {
+                return Err(anyhow!("Not focused"));
+            }
+            Ok(())
+        }
+    }
+
+    impl ClipboardBackend for FocusClipboard {
+        fn get_text(&mut self) -> Result<String> {
+            self.memory.get_text()
+        }
+
+        fn set_text(&mut self, text: &str) -> Result<()> {
+            self.check()?;
+            self.memory.set_text(text)
+        }
+
+        fn set_image(&mut self, image: &Screenshot) -> Result<()> {
+            self.check()?;
+            self.memory.set_image(image)
+        }
+
+        fn get_primary(&mut self) -> Result<String> {
+            self.memory.get_primary()
+        }
+
+        fn set_primary(&mut self, text: &str) -> Result<()> {
+            self.check()?;
+            self.memory.set_primary(text)
+        }
+
+        fn needs_focus(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_copy_and_paste() {
+        let memory = MemoryClipboard::new();
+        let mut clipboard = Clipboard::with_backend(memory.clone());
+        assert!(clipboard.paste_text().is_err());
+
+        clipboard.copy_text("hello").unwrap();
+        assert_eq!(clipboard.paste_text().unwrap(), "hello");
+        assert_eq!(memory.contents().as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn test_copy_image() {
+        let memory = MemoryClipboard::new();
+        let mut clipboard = Clipboard::with_backend(memory.clone());
+        let image = Screenshot {
+            width: 1,
+            height: 1,
+            rgba: vec![1, 2, 3, 255],
+        };
+        clipboard.copy_image(&image).unwrap();
+        assert_eq!(memory.image(), Some(image.clone()));
+
+        let mut fallback = Clipboard {
+            unavailable: Some("no display".to_string()),
+            ..Clipboard::with_backend(memory.clone())
+        };
+        let error = fallback.copy_image(&image).unwrap_err();
+        assert_eq!(error.to_string(), "The system clipboard is unavailable: no display");
+    }
+
+    #[test]
+    fn test_urls_copied_in_encoded_form() {
+        let memory = MemoryClipboard::new();
+        let mut clipboard = Clipboard::with_backend(memory.clone());
+
+        let url = ValidatedUrl::parse("https://bücher.example/straße?q=ä b").unwrap();
+        clipboard.copy_url(&url).unwrap();
+        assert_eq!(
+            memory.contents().as_deref(),
+            Some("https://xn--bcher-kva.example/stra%C3%9Fe?q=%C3%A4%20b")
+        );
+    }
+
+    #[test]
+    fn test_writes_wait_for_the_focus_where_needed() {
+        let backend = FocusClipboard::default();
+        let mut clipboard = Clipboard::with_backend(backend.clone());
+        backend.focus(true);
+        clipboard.copy_text("focused").unwrap();
+        assert_eq!(backend.memory.contents().as_deref(), Some("focused"));
+
+        // Copies made meanwhile are held back, the last one winning
+        backend.focus(false);
+        clipboard.set_focused(false).unwrap();
+        clipboard.copy_text("first").unwrap();
+        clipboard.copy_text("second").unwrap();
+        assert_eq!(backend.memory.contents().as_deref(), Some("focused"));
+        assert_eq!(clipboard.paste_text().unwrap(), "second");
+
+        backend.focus(true);
+        clipboard.set_focused(true).unwrap();
+        assert_eq!(backend.memory.contents().as_deref(), Some("second"));
+        // Nothing is written twice
+        backend.focus(false);
+        clipboard.set_focused(true).unwrap();
+
+        let image = Screenshot {
+            width: 1,
+            height: 1,
+            rgba: vec![1, 2, 3, 255],
+        };
+        clipboard.set_focused(false).unwrap();
+        clipboard.copy_image(&image).unwrap();
+        backend.focus(true);
+        clipboard.set_focused(true).unwrap();
+        assert_eq!(backend.memory.image(), Some(image));
+    }
+
+    #[test]
+    fn test_writes_without_focus_go_through_where_not_needed() {
+        let memory = MemoryClipboard::new();
+        let mut clipboard = Clipboard::with_backend(memory.clone());
+        clipboard.set_focused(false).unwrap();
+        clipboard.copy_text("unfocused").unwrap();
+        assert_eq!(memory.contents().as_deref(), Some("unfocused"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_selected_text_goes_to_the_primary_selection() {
+        let backend = FocusClipboard::default();
+        backend.focus(true);
+        let mut clipboard = Clipboard::with_backend(backend.clone());
+        assert!(clipboard.paste_selection().is_err());
+
+        clipboard.select_text("selected").unwrap();
+        assert_eq!(backend.memory.primary().as_deref(), Some("selected"));
+        assert_eq!(clipboard.paste_selection().unwrap(), "selected");
+        // The clipboard itself is left alone
+        assert_eq!(backend.memory.contents(), None);
+
+        backend.focus(false);
+        clipboard.set_focused(false).unwrap();
+        clipboard.select_text("later").unwrap();
+        assert_eq!(clipboard.paste_selection().unwrap(), "later");
+        backend.focus(true);
+        clipboard.set_focused(true).unwrap();
+        assert_eq!(backend.memory.primary().as_deref(), Some("later"));
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn test_there_is_no_primary_selection() {
+        let memory = MemoryClipboard::new();
+        let mut clipboard = Clipboard::with_backend(memory.clone());
+        clipboard.select_text("selected").unwrap();
+        assert_eq!(memory.primary(), None);
+        assert!(clipboard.paste_selection().is_err());
+    }
+}
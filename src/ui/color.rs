@@ -0,0 +1,208 @@
+use super::theme::Color;
+
+/// Contrast WCAG asks of body text against its background (level AA)
+pub const MIN_TEXT_CONTRAST: f32 = 4.5;
+/// How much lighter than the page background a dimmed background may get,
+/// in OKLCH lightness
+const DIMMED_BACKGROUND_RANGE: f32 = 0.3;
+/// Steps of the searches for a lightness or chroma; 2^-16 is finer than
+/// 8-bit channels tell apart
+const SEARCH_STEPS: usize = 16;
+
+/// A color in the OKLCH space: lightness from 0 to 1, chroma, and hue in
+/// radians. Changing the lightness keeps the perceived hue, which HSL does
+/// not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Oklch {
+    pub l: f32,
+    pub c: f32,
+    pub h: f32,
+}
+
+impl Oklch {
+    pub fn from_color(color: Color) -> Self {
+        let [r, g, b] = [color.r, color.g, color.b].map(srgb_to_linear);
+        let l = (0.412_221_47 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+        let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+        let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+        let lightness = 0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s;
+        let a = 1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s;
+        let b = 0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s;
+        Self {
+            l: lightness,
+            c: a.hypot(b),
+            h: b.atan2(a),
+        }
+    }
+
+    /// The nearest sRGB color, with as much of the chroma as fits
+    pub fn to_color(self) -> Color {
+        if let Some(color) = self.in_gamut() {
+            return color;
+        }
+        let (mut low, mut high) = (0.0, self.c);
+        for _ in 0..SEARCH_STEPS {
+            let middle = (low + high) / 2.0;
+            match (Self { c: middle, ..self }).in_gamut() {
+                Some(_) => low = middle,
+                None => high = middle,
+            }
+        }
+        let [r, g, b] = Self { c: low, ..self }.linear_rgb().map(linear_to_srgb);
+        Color::rgb(r, g, b)
+    }
+
+    fn linear_rgb(self) -> [f32; 3] {
+        let (a, b) = (self.c * self.h.cos(), self.c * self.h.sin());
+        let l = (self.l + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+        let m = (self.l - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+        let s = (self.l - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+        [
+            4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+            -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+            -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+        ]
+    }
+
+    fn in_gamut(self) -> Option<Color> {
+        let channels = self.linear_rgb();
+        channels.iter().all(|channel| (-1e-4..=1.0 + 1e-4).contains(channel)).then(|| {
+            let [r, g, b] = channels.map(linear_to_srgb);
+            Color::rgb(r, g, b)
+        })
+    }
+}
+
+pub fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let c = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+/// Whether light text reads better on `color` than dark text
+pub fn is_dark(color: Color) -> bool {
+    let (black, white) = (Color::rgb(0, 0, 0), Color::rgb(255, 255, 255));
+    color.contrast_ratio(white) > color.contrast_ratio(black)
+}
+
+/// `color` as it is if it has `ratio` contrast against the dark
+/// `background`, or else lightened just enough, keeping its hue. White if
+/// even that falls short.
+pub fn lighten_to_contrast(color: Color, background: Color, ratio: f32) -> Color {
+    if color.contrast_ratio(background) >= ratio {
+        return color;
+    }
+    let start = Oklch::from_color(color);
+    let at = |l: f32| Oklch { l, ..start }.to_color();
+    let (mut low, mut high) = (start.l, 1.0);
+    if at(high).contrast_ratio(background) < ratio {
+        return Color::rgb(255, 255, 255);
+    }
+    for _ in 0..SEARCH_STEPS {
+        let middle = (low + high) / 2.0;
+        if at(middle).contrast_ratio(background) >= ratio {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    at(high)
+}
+
+/// A page's background `color` turned dark for a page drawn on the dark
+/// `background`, keeping its hue: lightness is inverted and squeezed into
+/// a band just above the background's, so white becomes the background
+/// and dark colors end up a little lighter.
+pub fn dim_background(color: Color, background: Color) -> Color {
+    let (color, floor) = (Oklch::from_color(color), Oklch::from_color(background).l);
+    Oklch {
+        l: floor + (1.0 - color.l).clamp(0.0, 1.0) * DIMMED_BACKGROUND_RANGE,
+        ..color
+    }
+    .to_color()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::Theme;
+
+    const BLACK: Color = Color::rgb(0, 0, 0);
+    const WHITE: Color = Color::rgb(255, 255, 255);
+
+    fn hue_degrees(color: Color) -> f32 {
+        Oklch::from_color(color).h.to_degrees()
+    }
+
+    #[test]
+    fn test_oklch_round_trips_and_matches_known_values() {
+        for color in [BLACK, WHITE, Color::rgb(0x0b, 0x57, 0xd0), Color::rgb(0xff, 0xc0, 0x00)] {
+            assert_eq!(Oklch::from_color(color).to_color(), color);
+        }
+        let red = Oklch::from_color(Color::rgb(255, 0, 0));
+        assert!((red.l - 0.628).abs() < 0.001, "{:?}", red);
+        assert!((red.c - 0.2577).abs() < 0.001, "{:?}", red);
+        assert!((red.h.to_degrees() - 29.23).abs() < 0.1, "{:?}", red);
+        assert!(Oklch::from_color(WHITE).c < 1e-3);
+        // Too much chroma for sRGB gives up chroma, not lightness
+        let vivid = Oklch { l: 0.9, c: 0.4, h: 0.5 }.to_color();
+        assert!((Oklch::from_color(vivid).l - 0.9).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_known_pairs_are_lightened_to_readable_contrast() {
+        let background = Theme::DARK.background;
+        // Already readable: light text, and red on black at 5.25:1
+        assert_eq!(lighten_to_contrast(Theme::DARK.text, background, MIN_TEXT_CONTRAST), Theme::DARK.text);
+        assert_eq!(lighten_to_contrast(Color::rgb(255, 0, 0), BLACK, MIN_TEXT_CONTRAST), Color::rgb(255, 0, 0));
+
+        // Black text becomes the darkest gray that passes
+        let gray = lighten_to_contrast(BLACK, background, MIN_TEXT_CONTRAST);
+        let ratio = gray.contrast_ratio(background);
+        assert!((MIN_TEXT_CONTRAST..MIN_TEXT_CONTRAST + 0.1).contains(&ratio), "{}", ratio);
+        assert!(gray.r.abs_diff(gray.g) <= 1 && gray.g.abs_diff(gray.b) <= 1, "{:?}", gray);
+
+        // The classic link blue stays blue
+        let link = Color::rgb(0x00, 0x00, 0xee);
+        let lightened = lighten_to_contrast(link, background, MIN_TEXT_CONTRAST);
+        assert!(lightened.contrast_ratio(background) >= MIN_TEXT_CONTRAST);
+        assert!((hue_degrees(lightened) - hue_degrees(link)).abs() < 3.0, "{:?}", lightened);
+        assert!(lightened.b > lightened.r && lightened.b > lightened.g, "{:?}", lightened);
+
+        // Nothing reaches 7:1 against mid gray
+        assert_eq!(lighten_to_contrast(BLACK, Color::rgb(0x80, 0x80, 0x80), 7.0), WHITE);
+    }
+
+    #[test]
+    fn test_backgrounds_are_dimmed_keeping_their_hue() {
+        let background = Theme::DARK.background;
+        let dimmed = |color| dim_background(color, background);
+        let lightness = |color| Oklch::from_color(color).l;
+        assert!((lightness(dimmed(WHITE)) - lightness(background)).abs() < 0.01);
+
+        // A yellow highlight stays yellow, with light text readable on it
+        let yellow = Color::rgb(0xff, 0xff, 0x00);
+        let highlight = dimmed(yellow);
+        assert!(is_dark(highlight), "{:?}", highlight);
+        assert!((hue_degrees(highlight) - hue_degrees(yellow)).abs() < 5.0, "{:?}", highlight);
+        assert!(Theme::DARK.text.contrast_ratio(highlight) >= MIN_TEXT_CONTRAST);
+
+        // Dark backgrounds end up lighter than light ones
+        assert!(dimmed(BLACK).relative_luminance() > dimmed(Color::rgb(0xee, 0xee, 0xee)).relative_luminance());
+        assert!(is_dark(dimmed(BLACK)));
+        assert!(is_dark(background) && !is_dark(Theme::LIGHT.background));
+    }
+}
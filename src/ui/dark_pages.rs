@@ -0,0 +1,168 @@
+use super::color::{dim_background, is_dark, lighten_to_contrast, MIN_TEXT_CONTRAST};
+use super::theme::{Color, Theme};
+use crate::infrastructure::{PageContent, Rgb};
+use std::sync::Arc;
+
+/// Settings key: recolor light pages dark, whatever the chrome's theme
+pub const DARK_PAGES_SETTING: &str = "content.dark_pages";
+
+/// Parse the dark pages setting, defaulting to off
+pub fn parse_dark_pages(settings: &[(String, String)]) -> bool {
+    settings
+        .iter()
+        .find(|(key, _)| key == DARK_PAGES_SETTING)
+        .is_some_and(|(_, value)| matches!(value.trim(), "true" | "1" | "yes"))
+}
+
+/// Colors recolored pages are drawn in: the chrome's own if it is dark,
+/// the dark theme's otherwise, at the chrome's text size
+pub fn dark_page_theme(theme: &Theme) -> Theme {
+    if theme.is_dark() {
+        *theme
+    } else {
+        Theme {
+            text_scale: theme.text_scale,
+            ..Theme::DARK
+        }
+    }
+}
+
+/// Whether the page sets a dark background of its own, so it is left as
+/// it is
+pub fn declares_dark(content: &PageContent) -> bool {
+    content.background.is_some_and(|background| is_dark(color(background)))
+}
+
+fn color(rgb: Rgb) -> Color {
+    Color::rgb(rgb.r, rgb.g, rgb.b)
+}
+
+fn rgb(color: Color) -> Rgb {
+    Rgb::new(color.r, color.g, color.b)
+}
+
+/// `content` with its colors made to read on `theme`'s dark background.
+/// The page's own background gives way to the theme's, other backgrounds
+/// are dimmed, and text colors are lightened where they would not stand
+/// out from what is behind them. Text without a color of its own takes
+/// the theme's, as links take its link colors; images are not touched.
+pub fn darken(content: &PageContent, theme: &Theme) -> PageContent {
+    let mut darkened = content.clone();
+    for span in &mut darkened.styles {
+        let style = &mut span.style;
+        style.background = style
+            .background
+            .filter(|background| Some(*background) != content.background)
+            .map(|background| rgb(dim_background(color(background), theme.background)));
+        let behind = style.background.map_or(theme.background, color);
+        style.color = style
+            .color
+            .map(|text| rgb(lighten_to_contrast(color(text), behind, MIN_TEXT_CONTRAST)));
+    }
+    darkened
+}
+
+/// A window's page recolored dark, kept until the page or the colors
+/// change so it is not recolored every frame
+#[derive(Default)]
+pub struct DarkPageCache {
+    entry: Option<(u64, Theme, Arc<PageContent>)>,
+}
+
+impl DarkPageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Content of `generation` darkened for `theme`, recoloring it only if
+    /// either changed
+    pub fn get_or_darken(&mut self, generation: u64, content: &PageContent, theme: &Theme) -> Arc<PageContent> {
+        match &self.entry {
+            Some((cached, cached_theme, darkened)) if *cached == generation && cached_theme == theme => {
+                darkened.clone()
+            }
+            _ => {
+                let darkened = Arc::new(darken(content, theme));
+                self.entry = Some((generation, *theme, darkened.clone()));
+                darkened
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::{StyleSpan, TextStyle};
+
+    fn page(background: Option<Rgb>, styles: Vec<StyleSpan>) -> PageContent {
+        PageContent {
+            background,
+            styles,
+            ..PageContent::from_text("Plain, gray on white and marked")
+        }
+    }
+
+    fn span(range: std::ops::Range<usize>, color: Option<Rgb>, background: Option<Rgb>) -> StyleSpan {
+        StyleSpan {
+            range,
+            style: TextStyle {
+                color,
+                background,
+                ..TextStyle::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_light_pages_are_darkened_and_dark_ones_left_alone() {
+        let white = Rgb::new(255, 255, 255);
+        assert!(!declares_dark(&page(None, Vec::new())));
+        assert!(!declares_dark(&page(Some(white), Vec::new())));
+        assert!(declares_dark(&page(Some(Rgb::new(0x20, 0x20, 0x20)), Vec::new())));
+
+        let theme = dark_page_theme(&Theme::LIGHT);
+        assert_eq!(theme.background, Theme::DARK.background);
+        assert_eq!(dark_page_theme(&Theme::HIGH_CONTRAST), Theme::HIGH_CONTRAST);
+        let gray = Rgb::new(0x55, 0x55, 0x55);
+        let yellow = Rgb::new(0xff, 0xff, 0x00);
+        let content = page(Some(white), vec![
+            span(0..5, None, Some(white)),
+            span(7..21, Some(gray), Some(white)),
+            span(26..32, Some(Rgb::new(0, 0, 0)), Some(yellow)),
+        ]);
+        let darkened = darken(&content, &theme);
+        assert_eq!(darkened.text, content.text);
+
+        // The page's white goes, so the theme's background shows
+        assert_eq!(darkened.styles[0].style, TextStyle::default());
+        let gray_text = color(darkened.styles[1].style.color.unwrap());
+        assert_eq!(darkened.styles[1].style.background, None);
+        assert!(gray_text.contrast_ratio(theme.background) >= MIN_TEXT_CONTRAST);
+
+        // A highlight stays, dimmed, with its text readable on it
+        let marked = darkened.styles[2].style;
+        let highlight = color(marked.background.unwrap());
+        assert!(is_dark(highlight));
+        assert!(color(marked.color.unwrap()).contrast_ratio(highlight) >= MIN_TEXT_CONTRAST);
+    }
+
+    #[test]
+    fn test_cache_recolors_only_when_the_page_or_theme_changes() {
+        let mut cache = DarkPageCache::new();
+        let content = page(None, vec![span(0..5, Some(Rgb::new(0, 0, 0)), None)]);
+        let first = cache.get_or_darken(1, &content, &Theme::DARK);
+        assert!(Arc::ptr_eq(&first, &cache.get_or_darken(1, &content, &Theme::DARK)));
+        assert!(!Arc::ptr_eq(&first, &cache.get_or_darken(2, &content, &Theme::DARK)));
+        let high_contrast = cache.get_or_darken(2, &content, &Theme::HIGH_CONTRAST);
+        assert_ne!(high_contrast.styles, first.styles);
+    }
+
+    #[test]
+    fn test_setting_defaults_to_off() {
+        let setting = |value: &str| vec![(DARK_PAGES_SETTING.to_string(), value.to_string())];
+        assert!(parse_dark_pages(&setting("true")));
+        assert!(!parse_dark_pages(&setting("off")));
+        assert!(!parse_dark_pages(&[]));
+    }
+}
@@ -0,0 +1,315 @@
+use crate::infrastructure::{PdfFont, PdfFonts};
+use glyphon::fontdb::{Database, Query, Style, Weight};
+use glyphon::{Attrs, Buffer, Family, FontSystem, Metrics, Shaping};
+use std::path::PathBuf;
+
+/// Settings keys for font configuration
+pub const SERIF_FAMILY_SETTING: &str = "font.serif_family";
+pub const SANS_FAMILY_SETTING: &str = "font.sans_family";
+pub const MONO_FAMILY_SETTING: &str = "font.mono_family";
+pub const MINIMUM_SIZE_SETTING: &str = "font.minimum_size";
+pub const DEFAULT_SIZE_SETTING: &str = "font.default_size";
+pub const EXTRA_DIRS_SETTING: &str = "font.extra_dirs";
+
+/// User font preferences applied to all text shaping
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontSettings {
+    /// Family names; `None` uses the generic family
+    pub serif_family: Option<String>,
+    pub sans_family: Option<String>,
+    pub mono_family: Option<String>,
+    pub minimum_size: f32,
+    pub default_size: f32,
+    /// Extra directories scanned for fonts, in addition to the system ones
+    pub extra_dirs: Vec<PathBuf>,
+}
+
+impl Default for FontSettings {
+    fn default() -> Self {
+        Self {
+            serif_family: None,
+            sans_family: None,
+            mono_family: None,
+            minimum_size: 9.0,
+            default_size: 14.0,
+            extra_dirs: Vec::new(),
+        }
+    }
+}
+
+impl FontSettings {
+    /// Build from stored settings, ignoring missing or malformed values
+    pub fn from_settings(settings: &[(String, String)]) -> Self {
+        let mut fonts = Self::default();
+
+        for (key, value) in settings {
+            let family = Some(value.trim().to_string()).filter(|v| !v.is_empty());
+            match key.as_str() {
+                SERIF_FAMILY_SETTING => fonts.serif_family = family,
+                SANS_FAMILY_SETTING => fonts.sans_family = family,
+                MONO_FAMILY_SETTING => fonts.mono_family = family,
+                MINIMUM_SIZE_SETTING => {
+                    if let Ok(size) = value.trim().parse::<f32>() {
+                        fonts.minimum_size = size.clamp(4.0, 72.0);
+                    }
+                }
+                DEFAULT_SIZE_SETTING => {
+                    if let Ok(size) = value.trim().parse::<f32>() {
+                        fonts.default_size = size.clamp(4.0, 72.0);
+                    }
+                }
+                EXTRA_DIRS_SETTING => {
+                    fonts.extra_dirs = std::env::split_paths(value).collect();
+                }
+                _ => {}
+            }
+        }
+
+        fonts
+    }
+
+    /// Serialize back into settings key/value pairs
+    pub fn to_settings(&self) -> Vec<(String, String)> {
+        let mut settings = vec![
+            (MINIMUM_SIZE_SETTING.to_string(), self.minimum_size.to_string()),
+            (DEFAULT_SIZE_SETTING.to_string(), self.default_size.to_string()),
+        ];
+        for (key, family) in [
+            (SERIF_FAMILY_SETTING, &self.serif_family),
+            (SANS_FAMILY_SETTING, &self.sans_family),
+            (MONO_FAMILY_SETTING, &self.mono_family),
+        ] {
+            settings.push((key.to_string(), family.clone().unwrap_or_default()));
+        }
+        if let Ok(dirs) = std::env::join_paths(&self.extra_dirs) {
+            settings.push((EXTRA_DIRS_SETTING.to_string(), dirs.to_string_lossy().into_owned()));
+        }
+        settings
+    }
+
+    pub fn serif(&self) -> Family<'_> {
+        self.serif_family.as_deref().map(Family::Name).unwrap_or(Family::Serif)
+    }
+
+    pub fn sans(&self) -> Family<'_> {
+        self.sans_family.as_deref().map(Family::Name).unwrap_or(Family::SansSerif)
+    }
+
+    pub fn mono(&self) -> Family<'_> {
+        self.mono_family.as_deref().map(Family::Name).unwrap_or(Family::Monospace)
+    }
+
+    pub fn sans_attrs(&self) -> Attrs<'_> {
+        Attrs::new().family(self.sans())
+    }
+
+    pub fn serif_attrs(&self) -> Attrs<'_> {
+        Attrs::new().family(self.serif())
+    }
+
+    pub fn mono_attrs(&self) -> Attrs<'_> {
+        Attrs::new().family(self.mono())
+    }
+
+    /// Apply the minimum font size
+    pub fn clamp_size(&self, size: f32) -> f32 {
+        size.max(self.minimum_size)
+    }
+}
+
+/// Directories commonly holding user-installed fonts that fontdb may skip
+fn user_font_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(home) = std::env::var_os("HOME").map(PathBuf::from) {
+        dirs.push(home.join(".fonts"));
+        dirs.push(home.join(".local/share/fonts"));
+        dirs.push(home.join("Library/Fonts"));
+    }
+    if let Some(local) = std::env::var_os("LOCALAPPDATA").map(PathBuf::from) {
+        dirs.push(local.join("Microsoft\\Windows\\Fonts"));
+    }
+    dirs
+}
+
+/// Create the font system: system fonts, extra directories, then the
+/// bundled Noto Sans when discovery found nothing usable
+pub fn build_font_system(settings: &FontSettings) -> FontSystem {
+    let mut font_system = FontSystem::new();
+    load_font_dirs(&mut font_system, user_font_dirs().iter().chain(settings.extra_dirs.iter()));
+
+    if font_system.db().faces().next().is_none() {
+        tracing::warn!("No system fonts found, using bundled Noto Sans");
+        load_bundled_fonts(&mut font_system);
+    }
+
+    tracing::info!("Font system ready with {} faces", font_system.db().len());
+    font_system
+}
+
+/// Add the fonts in `settings`' extra directories to a font system built
+/// before the settings were read
+pub fn add_extra_fonts(font_system: &mut FontSystem, settings: &FontSettings) {
+    load_font_dirs(font_system, settings.extra_dirs.iter());
+}
+
+fn load_font_dirs<'a>(font_system: &mut FontSystem, dirs: impl Iterator<Item = &'a PathBuf>) {
+    for dir in dirs {
+        if dir.is_dir() {
+            font_system.db_mut().load_fonts_dir(dir);
+        }
+    }
+}
+
+fn load_bundled_fonts(font_system: &mut FontSystem) {
+    for data in [
+        notosans::REGULAR_TTF,
+        notosans::BOLD_TTF,
+        notosans::ITALIC_TTF,
+        notosans::BOLD_ITALIC_TTF,
+    ] {
+        font_system.db_mut().load_font_data(data.to_vec());
+    }
+}
+
+/// Fonts for printing: the configured families from the system and extra
+/// directories where they have TrueType outlines, else the bundled Noto Sans
+pub fn pdf_fonts(settings: &FontSettings) -> PdfFonts {
+    let mut db = Database::new();
+    db.load_system_fonts();
+    for dir in user_font_dirs().iter().chain(settings.extra_dirs.iter()) {
+        if dir.is_dir() {
+            db.load_fonts_dir(dir);
+        }
+    }
+    let find = |family: Family, weight: Weight, style: Style| {
+        let families = [family];
+        let query = Query {
+            families: &families,
+            weight,
+            style,
+            ..Query::default()
+        };
+        let id = db.query(&query)?;
+        db.with_face_data(id, |data, index| PdfFont::from_data(data.to_vec(), index).ok())
+            .flatten()
+    };
+
+    let bundled = PdfFonts::bundled();
+    let sans = settings.sans();
+    PdfFonts {
+        regular: find(sans, Weight::NORMAL, Style::Normal).unwrap_or(bundled.regular),
+        bold: find(sans, Weight::BOLD, Style::Normal).unwrap_or(bundled.bold),
+        italic: find(sans, Weight::NORMAL, Style::Italic).unwrap_or(bundled.italic),
+        bold_italic: find(sans, Weight::BOLD, Style::Italic).unwrap_or(bundled.bold_italic),
+        monospace: find(settings.mono(), Weight::NORMAL, Style::Normal),
+    }
+}
+
+/// Whether any loaded face has a glyph for the character
+pub fn has_glyph_for(font_system: &mut FontSystem, ch: char) -> bool {
+    let ids: Vec<_> = font_system.db().faces().map(|face| face.id).collect();
+    ids.into_iter().any(|id| {
+        font_system
+            .get_font(id)
+            .is_some_and(|font| font.rustybuzz().glyph_index(ch).is_some())
+    })
+}
+
+/// Shape text and count the glyphs that fell back to the missing-glyph box
+pub fn count_missing_glyphs(font_system: &mut FontSystem, text: &str, attrs: Attrs) -> usize {
+    let mut buffer = Buffer::new(font_system, Metrics::new(16.0, 20.0));
+    buffer.set_size(font_system, None, None);
+    buffer.set_text(font_system, text, attrs, Shaping::Advanced);
+    buffer.shape_until_scroll(font_system, false);
+
+    buffer
+        .layout_runs()
+        .flat_map(|run| run.glyphs.iter())
+        .filter(|glyph| glyph.glyph_id == 0)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_round_trip() {
+        let fonts = FontSettings {
+            serif_family: Some("Noto Serif".to_string()),
+            sans_family: None,
+            mono_family: Some("Fira Mono".to_string()),
+            minimum_size: 10.0,
+            default_size: 16.0,
+            extra_dirs: vec![PathBuf::from("/opt/fonts")],
+        };
+
+        assert_eq!(FontSettings::from_settings(&fonts.to_settings()), fonts);
+    }
+
+    #[test]
+    fn test_malformed_settings_ignored() {
+        let fonts = FontSettings::from_settings(&[
+            (DEFAULT_SIZE_SETTING.to_string(), "huge".to_string()),
+            (MINIMUM_SIZE_SETTING.to_string(), "1000".to_string()),
+        ]);
+
+        assert_eq!(fonts.default_size, FontSettings::default().default_size);
+        assert_eq!(fonts.minimum_size, 72.0);
+        assert_eq!(fonts.clamp_size(12.0), 72.0);
+    }
+
+    #[test]
+    fn test_mixed_scripts_shape_without_missing_glyphs() {
+        let fonts = FontSettings::default();
+        let mut font_system = build_font_system(&fonts);
+
+        // Each sample is only asserted when some installed font covers it,
+        // so minimal CI images still check the fallback chain for Latin
+        for sample in ["Hello, world", "日本語のテキスト", "مرحبا", "😀🎉"] {
+            let covered = sample
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .all(|c| has_glyph_for(&mut font_system, c));
+            if !covered {
+                eprintln!("skipping {:?}: no installed font covers it", sample);
+                continue;
+            }
+
+            let text = format!("Latin {} mixed", sample);
+            assert_eq!(count_missing_glyphs(&mut font_system, &text, fonts.sans_attrs()), 0);
+        }
+    }
+
+    #[test]
+    fn test_extra_fonts_are_added_after_the_scan() {
+        let dir = std::env::temp_dir().join(format!("navigator-fonts-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("NotoSans-Regular.ttf"), notosans::REGULAR_TTF).unwrap();
+        let mut font_system = FontSystem::new_with_locale_and_db("en-US".to_string(), Database::new());
+        let settings = FontSettings {
+            extra_dirs: vec![dir.clone()],
+            ..FontSettings::default()
+        };
+
+        add_extra_fonts(&mut font_system, &settings);
+        assert_eq!(font_system.db().len(), 1);
+        assert!(has_glyph_for(&mut font_system, 'A'));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bundled_fallback_covers_latin() {
+        let mut font_system = FontSystem::new_with_locale_and_db(
+            "en-US".to_string(),
+            glyphon::fontdb::Database::new(),
+        );
+        load_bundled_fonts(&mut font_system);
+
+        assert!(has_glyph_for(&mut font_system, 'A'));
+        assert_eq!(
+            count_missing_glyphs(&mut font_system, "Fallback text", Attrs::new()),
+            0
+        );
+    }
+}
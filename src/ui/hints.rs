@@ -0,0 +1,236 @@
+use std::ops::Range;
+use winit::keyboard::{Key, NamedKey};
+
+/// Letters labels are made of: the home row, so they are typed without
+/// looking
+pub const HINT_ALPHABET: [char; 9] = ['a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l'];
+/// Labels are never shorter than this, so the first letter never follows
+/// a link on its own
+const MIN_LABEL_LENGTH: usize = 2;
+
+/// Labels for `count` links, all the same length so none is a prefix of
+/// another. The first letter varies fastest, spreading links over as many
+/// first keys as possible.
+pub fn hint_labels(count: usize) -> Vec<String> {
+    let base = HINT_ALPHABET.len();
+    let mut length = MIN_LABEL_LENGTH;
+    while base.checked_pow(length as u32).is_some_and(|capacity| capacity < count) {
+        length += 1;
+    }
+    (0..count)
+        .map(|mut index| {
+            (0..length)
+                .map(|_| {
+                    let letter = HINT_ALPHABET[index % base];
+                    index /= base;
+                    letter
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A label placed on one link
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint {
+    pub label: String,
+    /// Index into the page's links
+    pub link: usize,
+    /// Top left of the link's first visible glyph run, in layout coordinates
+    pub x: f32,
+    pub y: f32,
+}
+
+/// What a key pressed while the labels are shown asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HintAction {
+    /// Keep waiting for letters; the labels shown may have changed
+    Pending,
+    /// Follow the link with this index
+    Follow(usize),
+    /// Open the link with this index without leaving the page
+    OpenInBackground(usize),
+    Cancel,
+}
+
+/// Labels over the links in view, and the letters typed so far
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkHints {
+    /// Content generation the labels were placed on
+    generation: u64,
+    hints: Vec<Hint>,
+    typed: String,
+    /// A letter was typed with Shift
+    background: bool,
+}
+
+impl LinkHints {
+    /// Label every link with a glyph run inside `visible`, a vertical span
+    /// of the layout. `links` holds each link's glyph rectangles
+    /// `(x, y, width, height)` in layout coordinates, in page order.
+    pub fn new(generation: u64, links: &[Vec<(f32, f32, f32, f32)>], visible: Range<f32>) -> Self {
+        let placed: Vec<(usize, f32, f32)> = links
+            .iter()
+            .enumerate()
+            .filter_map(|(link, rects)| {
+                let &(x, y, _, _) = rects
+                    .iter()
+                    .find(|(_, y, _, height)| y + height > visible.start && *y < visible.end)?;
+                Some((link, x, y))
+            })
+            .collect();
+        let hints = hint_labels(placed.len())
+            .into_iter()
+            .zip(placed)
+            .map(|(label, (link, x, y))| Hint { label, link, x, y })
+            .collect();
+        Self {
+            generation,
+            hints,
+            typed: String::new(),
+            background: false,
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether no link was in view
+    pub fn is_empty(&self) -> bool {
+        self.hints.is_empty()
+    }
+
+    /// Letters typed so far
+    pub fn typed(&self) -> &str {
+        &self.typed
+    }
+
+    /// Labels still matching the letters typed
+    pub fn visible(&self) -> impl Iterator<Item = &Hint> {
+        self.hints.iter().filter(|hint| hint.label.starts_with(&self.typed))
+    }
+
+    /// Handle a key; `None` means it has nothing to do with the labels
+    pub fn key(&mut self, key: &Key) -> Option<HintAction> {
+        match key {
+            Key::Named(NamedKey::Escape) => Some(HintAction::Cancel),
+            Key::Named(NamedKey::Backspace) => {
+                self.typed.pop();
+                if self.typed.is_empty() {
+                    self.background = false;
+                }
+                Some(HintAction::Pending)
+            }
+            Key::Named(NamedKey::Shift | NamedKey::Control | NamedKey::Alt | NamedKey::Super) => {
+                Some(HintAction::Pending)
+            }
+            Key::Character(c) => Some(self.letter(c)),
+            _ => None,
+        }
+    }
+
+    /// Add a typed letter if some label goes on with it; letters no label
+    /// continues with are ignored
+    fn letter(&mut self, c: &str) -> HintAction {
+        let lower = c.to_lowercase();
+        let typed = format!("{}{}", self.typed, lower);
+        if !self.hints.iter().any(|hint| hint.label.starts_with(&typed)) {
+            return HintAction::Pending;
+        }
+        self.background |= lower != c;
+        self.typed = typed;
+        match self.hints.iter().find(|hint| hint.label == self.typed) {
+            Some(hint) if self.background => HintAction::OpenInBackground(hint.link),
+            Some(hint) => HintAction::Follow(hint.link),
+            None => HintAction::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn character(c: &str) -> Key {
+        Key::Character(c.into())
+    }
+
+    #[test]
+    fn test_labels_are_unique_and_prefix_free() {
+        for count in [0, 1, 5, 9, 80, 81, 82, 300, 729, 730, 2000] {
+            let labels = hint_labels(count);
+            assert_eq!(labels.len(), count);
+            let unique: HashSet<&String> = labels.iter().collect();
+            assert_eq!(unique.len(), count, "{} labels", count);
+            for label in &labels {
+                assert!(label.chars().all(|c| HINT_ALPHABET.contains(&c)), "{}", label);
+                assert!(label.len() >= MIN_LABEL_LENGTH);
+                // Every label has the same length, so none is a prefix of another
+                assert_eq!(label.len(), labels[0].len());
+            }
+        }
+        assert_eq!(hint_labels(81)[80].len(), 2);
+        assert_eq!(hint_labels(300)[0].len(), 3);
+        assert_eq!(hint_labels(730)[0].len(), 4);
+        // A few links each get their own first letter
+        assert_eq!(hint_labels(3), ["aa", "sa", "da"]);
+    }
+
+    #[test]
+    fn test_links_outside_the_viewport_get_no_label() {
+        // 500 one-line links, 20 pixels apart
+        let links: Vec<_> = (0..500).map(|i| vec![(10.0, i as f32 * 20.0, 50.0, 18.0)]).collect();
+        let hints = LinkHints::new(7, &links, 1000.0..2000.0);
+        assert_eq!(hints.generation(), 7);
+        let labelled: Vec<usize> = hints.visible().map(|hint| hint.link).collect();
+        // Link 49 ends at 998 and link 100 starts at 2000
+        assert_eq!(labelled, (50..100).collect::<Vec<_>>());
+        assert_eq!(hints.visible().next().map(|hint| hint.y), Some(1000.0));
+
+        // A link wrapping into view is labelled at its first visible run
+        let wrapped = [vec![(300.0, 980.0, 40.0, 18.0), (0.0, 1000.0, 40.0, 18.0)], Vec::new()];
+        let hints = LinkHints::new(0, &wrapped, 1000.0..2000.0);
+        let hint = hints.visible().next().unwrap();
+        assert_eq!((hint.link, hint.x, hint.y), (0, 0.0, 1000.0));
+        assert_eq!(hints.visible().count(), 1);
+
+        assert!(LinkHints::new(0, &links, 20000.0..21000.0).is_empty());
+    }
+
+    #[test]
+    fn test_typing_a_label_follows_its_link() {
+        let links: Vec<_> = (0..400).map(|i| vec![(0.0, i as f32, 10.0, 1.0)]).collect();
+        let mut hints = LinkHints::new(0, &links, 0.0..400.0);
+        assert_eq!(hints.visible().count(), 400);
+        let target = hints.visible().nth(123).unwrap().clone();
+        let letters: Vec<String> = target.label.chars().map(String::from).collect();
+
+        assert_eq!(hints.key(&character(&letters[0])), Some(HintAction::Pending));
+        assert!(hints.visible().count() < 400);
+        assert!(hints.visible().all(|hint| hint.label.starts_with(&letters[0])));
+        // Letters no label goes on with change nothing
+        assert_eq!(hints.key(&character("z")), Some(HintAction::Pending));
+        assert_eq!(hints.typed(), letters[0]);
+        assert_eq!(hints.key(&character(&letters[1])), Some(HintAction::Pending));
+        assert_eq!(hints.key(&character(&letters[2])), Some(HintAction::Follow(target.link)));
+
+        // Shift on any letter opens the link in the background instead
+        let mut hints = LinkHints::new(0, &links, 0.0..400.0);
+        hints.key(&character(&letters[0]));
+        assert_eq!(hints.key(&Key::Named(NamedKey::Shift)), Some(HintAction::Pending));
+        hints.key(&character(&letters[1].to_uppercase()));
+        assert_eq!(
+            hints.key(&character(&letters[2])),
+            Some(HintAction::OpenInBackground(target.link))
+        );
+
+        let mut hints = LinkHints::new(0, &links, 0.0..400.0);
+        hints.key(&character(&letters[0]));
+        assert_eq!(hints.key(&Key::Named(NamedKey::Backspace)), Some(HintAction::Pending));
+        assert_eq!(hints.visible().count(), 400);
+        assert_eq!(hints.key(&Key::Named(NamedKey::Escape)), Some(HintAction::Cancel));
+        assert_eq!(hints.key(&Key::Named(NamedKey::PageDown)), None);
+    }
+}
@@ -0,0 +1,301 @@
+use super::chrome::ChromeLayout;
+use super::scrollbar::{ScrollbarGeometry, ScrollbarHit};
+use super::theme::Color;
+
+/// Settings key: allow the hit region overlay in release builds, where
+/// its shortcut does nothing otherwise
+pub const HIT_REGIONS_SETTING: &str = "debug.hit_regions";
+
+/// Whether the hit region overlay may be shown: always in debug builds,
+/// and in release builds when turned on in settings
+pub fn parse_hit_regions(settings: &[(String, String)]) -> bool {
+    cfg!(debug_assertions)
+        || settings
+            .iter()
+            .find(|(key, _)| key == HIT_REGIONS_SETTING)
+            .is_some_and(|(_, value)| matches!(value.trim(), "true" | "1" | "yes"))
+}
+
+/// What a region of the window is, and so what a click on it does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitRegionKind {
+    AddressBar,
+    /// The blocked-requests badge inside the address bar
+    Badge,
+    /// Page text, where clicks select
+    Content,
+    /// Gutter reserved for the scrollbar, clicked like the page while
+    /// there is nothing to scroll
+    Gutter,
+    Scrollbar(ScrollbarHit),
+    /// A glyph run of the link with this index into the page's links
+    Link(usize),
+    /// The label on the link with this index, while picking one by keyboard
+    Hint(usize),
+    /// The toast with this index among those placed
+    Toast(usize),
+    Palette,
+}
+
+impl HitRegionKind {
+    /// Stacking layer: where regions overlap, the one on the highest layer
+    /// is hit
+    pub fn layer(self) -> u8 {
+        match self {
+            HitRegionKind::Content | HitRegionKind::Gutter => 0,
+            HitRegionKind::AddressBar | HitRegionKind::Link(_) => 1,
+            HitRegionKind::Badge | HitRegionKind::Scrollbar(_) | HitRegionKind::Hint(_) => 2,
+            HitRegionKind::Toast(_) => 3,
+            HitRegionKind::Palette => 4,
+        }
+    }
+
+    /// Name shown in the overlay's readout
+    pub fn name(self) -> String {
+        match self {
+            HitRegionKind::AddressBar => "address bar".to_string(),
+            HitRegionKind::Badge => "badge".to_string(),
+            HitRegionKind::Content => "page".to_string(),
+            HitRegionKind::Gutter => "scrollbar gutter".to_string(),
+            HitRegionKind::Scrollbar(ScrollbarHit::Thumb) => "scrollbar thumb".to_string(),
+            HitRegionKind::Scrollbar(_) => "scrollbar track".to_string(),
+            HitRegionKind::Link(index) => format!("link {}", index),
+            HitRegionKind::Hint(index) => format!("hint on link {}", index),
+            HitRegionKind::Toast(index) => format!("toast {}", index),
+            HitRegionKind::Palette => "command palette".to_string(),
+        }
+    }
+
+    /// Color the overlay fills the region with, one per kind
+    pub fn overlay_color(self) -> Color {
+        match self {
+            HitRegionKind::AddressBar => Color::rgb(0x3b, 0x82, 0xf6),
+            HitRegionKind::Badge => Color::rgb(0xa8, 0x55, 0xf7),
+            HitRegionKind::Content => Color::rgb(0x9c, 0xa3, 0xaf),
+            HitRegionKind::Gutter => Color::rgb(0x6b, 0x72, 0x80),
+            HitRegionKind::Scrollbar(_) => Color::rgb(0xf5, 0x9e, 0x0b),
+            HitRegionKind::Link(_) => Color::rgb(0x22, 0xc5, 0x5e),
+            HitRegionKind::Hint(_) => Color::rgb(0xec, 0x48, 0x99),
+            HitRegionKind::Toast(_) => Color::rgb(0xef, 0x44, 0x44),
+            HitRegionKind::Palette => Color::rgb(0x14, 0xb8, 0xa6),
+        }
+    }
+}
+
+/// A rectangle of the window that takes clicks, in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HitRegion {
+    /// Order of registration within its frame
+    pub id: usize,
+    pub kind: HitRegionKind,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub z: u8,
+}
+
+impl HitRegion {
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// Every region of a frame that takes clicks, registered as the frame is
+/// drawn; all pointer hit-testing resolves against it, so what is hit is
+/// what was drawn
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HitRegionMap {
+    regions: Vec<HitRegion>,
+}
+
+impl HitRegionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a region on its kind's layer; returns its id, or `None` for an
+    /// empty rectangle that nothing can hit
+    pub fn register(&mut self, kind: HitRegionKind, x: f32, y: f32, width: f32, height: f32) -> Option<usize> {
+        self.register_at(kind.layer(), kind, x, y, width, height)
+    }
+
+    /// Add a region on an explicit layer
+    pub fn register_at(
+        &mut self,
+        z: u8,
+        kind: HitRegionKind,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> Option<usize> {
+        if width <= 0.0 || height <= 0.0 {
+            return None;
+        }
+        let id = self.regions.len();
+        self.regions.push(HitRegion { id, kind, x, y, width, height, z });
+        Some(id)
+    }
+
+    /// The address bar, its badge when shown, the page and the scrollbar
+    /// gutter
+    pub fn register_chrome(&mut self, chrome: &ChromeLayout, badge: bool) {
+        self.register(HitRegionKind::AddressBar, 0.0, 0.0, chrome.width, chrome.address_bar_height);
+        if badge {
+            self.register(
+                HitRegionKind::Badge,
+                chrome.badge_left,
+                chrome.badge_top,
+                chrome.badge_width,
+                chrome.badge_height,
+            );
+        }
+        let (top, height) = (chrome.content_top, chrome.content_height);
+        self.register(HitRegionKind::Content, chrome.content_left, top, chrome.content_width, height);
+        let gutter_left = chrome.scrollbar_right() - chrome.scrollbar_width;
+        self.register(HitRegionKind::Gutter, gutter_left, top, chrome.scrollbar_width, height);
+    }
+
+    /// The scrollbar track either side of the thumb, and the thumb
+    pub fn register_scrollbar(&mut self, geometry: &ScrollbarGeometry) {
+        let (x, width) = (geometry.track_x, geometry.track_width);
+        let thumb_bottom = geometry.thumb_top + geometry.thumb_height;
+        let track_bottom = geometry.track_top + geometry.track_height;
+        let before = HitRegionKind::Scrollbar(ScrollbarHit::TrackBefore);
+        self.register(before, x, geometry.track_top, width, geometry.thumb_top - geometry.track_top);
+        let thumb = HitRegionKind::Scrollbar(ScrollbarHit::Thumb);
+        self.register(thumb, x, geometry.thumb_top, width, geometry.thumb_height);
+        let after = HitRegionKind::Scrollbar(ScrollbarHit::TrackAfter);
+        self.register(after, x, thumb_bottom, width, track_bottom - thumb_bottom);
+    }
+
+    /// The region a point hits: the one on the highest layer containing
+    /// it, and of those the last registered, as it was drawn over the rest
+    pub fn resolve(&self, x: f32, y: f32) -> Option<&HitRegion> {
+        self.regions
+            .iter()
+            .filter(|region| region.contains(x, y))
+            .max_by_key(|region| region.z)
+    }
+
+    /// Kind of the region a point hits
+    pub fn kind_at(&self, x: f32, y: f32) -> Option<HitRegionKind> {
+        self.resolve(x, y).map(|region| region.kind)
+    }
+
+    /// Every region, in registration order
+    pub fn regions(&self) -> &[HitRegion] {
+        &self.regions
+    }
+}
+
+/// The hit region overlay drawn over a frame, for debugging pointer input
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HitOverlay {
+    /// Window position of the cursor, read out with the region it hits
+    pub cursor: Option<(f32, f32)>,
+}
+
+impl HitOverlay {
+    /// The cursor position and what it resolves to, e.g. "120, 84: #7 link 2"
+    pub fn readout(&self, regions: &HitRegionMap) -> String {
+        let Some((x, y)) = self.cursor else {
+            return "Cursor outside the window".to_string();
+        };
+        match regions.resolve(x, y) {
+            Some(region) => format!("{:.0}, {:.0}: #{} {}", x, y, region.id, region.kind.name()),
+            None => format!("{:.0}, {:.0}: nothing", x, y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::scroll::ScrollState;
+
+    #[test]
+    fn test_higher_layers_win_where_regions_overlap() {
+        let mut map = HitRegionMap::new();
+        map.register(HitRegionKind::Content, 0.0, 50.0, 800.0, 550.0);
+        let link = map.register(HitRegionKind::Link(0), 100.0, 100.0, 80.0, 20.0);
+        let toast = map.register(HitRegionKind::Toast(0), 150.0, 90.0, 300.0, 60.0);
+        let palette = map.register(HitRegionKind::Palette, 0.0, 0.0, 120.0, 120.0);
+
+        assert_eq!(map.kind_at(400.0, 400.0), Some(HitRegionKind::Content));
+        assert_eq!(map.resolve(110.0, 110.0).map(|region| region.id), palette);
+        assert_eq!(map.resolve(120.0, 110.0).map(|region| region.id), link);
+        // The toast covers the end of the link, registered before or not
+        assert_eq!(map.resolve(160.0, 110.0).map(|region| region.id), toast);
+        assert_eq!(map.kind_at(900.0, 10.0), None);
+    }
+
+    #[test]
+    fn test_later_regions_win_on_the_same_layer() {
+        let mut map = HitRegionMap::new();
+        map.register(HitRegionKind::Link(0), 0.0, 0.0, 100.0, 20.0);
+        map.register(HitRegionKind::Link(1), 50.0, 0.0, 100.0, 20.0);
+        assert_eq!(map.kind_at(60.0, 10.0), Some(HitRegionKind::Link(1)));
+        assert_eq!(map.kind_at(10.0, 10.0), Some(HitRegionKind::Link(0)));
+
+        // An explicit layer puts a region under one registered before it
+        map.register_at(0, HitRegionKind::Content, 0.0, 0.0, 200.0, 20.0);
+        assert_eq!(map.kind_at(60.0, 10.0), Some(HitRegionKind::Link(1)));
+        assert_eq!(map.kind_at(180.0, 10.0), Some(HitRegionKind::Content));
+
+        // Edges are half open and empty regions are never registered
+        assert_eq!(map.kind_at(150.0, 10.0), Some(HitRegionKind::Content));
+        assert_eq!(map.register(HitRegionKind::Palette, 0.0, 0.0, 0.0, 20.0), None);
+        assert_eq!(map.regions().len(), 3);
+    }
+
+    #[test]
+    fn test_chrome_and_scrollbar_regions() {
+        let chrome = ChromeLayout::compute(800.0, 600.0, 1.0, 1.0);
+        let mut scroll = ScrollState::new();
+        scroll.set_extent(2000.0, chrome.content_height);
+        scroll.set_offset(500.0);
+        let geometry =
+            ScrollbarGeometry::compute(chrome.scrollbar_right(), chrome.content_top, &scroll, 1.0).unwrap();
+
+        let mut map = HitRegionMap::new();
+        map.register_chrome(&chrome, true);
+        map.register_scrollbar(&geometry);
+
+        let badge = (chrome.badge_left + 1.0, chrome.badge_top + 1.0);
+        assert_eq!(map.kind_at(badge.0, badge.1), Some(HitRegionKind::Badge));
+        assert_eq!(map.kind_at(5.0, 5.0), Some(HitRegionKind::AddressBar));
+        assert_eq!(map.kind_at(5.0, chrome.content_top), Some(HitRegionKind::Content));
+
+        let track = geometry.track_x + 1.0;
+        for y in [chrome.content_top + 1.0, geometry.thumb_top + 1.0, 599.0] {
+            assert_eq!(
+                map.kind_at(track, y).and_then(|kind| match kind {
+                    HitRegionKind::Scrollbar(hit) => Some(hit),
+                    _ => None,
+                }),
+                geometry.hit_test(track, y),
+            );
+        }
+
+        // Without a scrollbar its gutter is hit, and mirrored with the page
+        let mut map = HitRegionMap::new();
+        map.register_chrome(&chrome.mirrored(), false);
+        assert_eq!(map.kind_at(1.0, 300.0), Some(HitRegionKind::Gutter));
+        assert_eq!(map.kind_at(799.0, 300.0), Some(HitRegionKind::Content));
+        assert_eq!(map.kind_at(badge.0, badge.1), Some(HitRegionKind::AddressBar));
+    }
+
+    #[test]
+    fn test_overlay_reads_out_the_region_under_the_cursor() {
+        let mut map = HitRegionMap::new();
+        map.register(HitRegionKind::Content, 0.0, 0.0, 100.0, 100.0);
+        map.register(HitRegionKind::Link(4), 10.0, 10.0, 20.0, 10.0);
+
+        let overlay = |cursor| HitOverlay { cursor };
+        assert_eq!(overlay(Some((15.4, 12.0))).readout(&map), "15, 12: #1 link 4");
+        assert_eq!(overlay(Some((150.0, 12.0))).readout(&map), "150, 12: nothing");
+        assert_eq!(overlay(None).readout(&map), "Cursor outside the window");
+    }
+}
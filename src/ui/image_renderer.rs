@@ -0,0 +1,267 @@
+use super::rect_renderer::Rect;
+use super::thumbnails::Thumbnail;
+use std::collections::HashMap;
+use wgpu::util::DeviceExt;
+use wgpu::{Device, Queue, TextureFormat};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+}
+
+impl Vertex {
+    fn as_bytes(vertices: &[Vertex]) -> &[u8] {
+        // Vertex is repr(C) and made only of f32s, so it has no padding
+        unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(vertices),
+            )
+        }
+    }
+}
+
+const SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@group(0) @binding(0) var image: texture_2d<f32>;
+@group(0) @binding(1) var image_sampler: sampler;
+
+@vertex
+fn vs_main(@location(0) position: vec2<f32>, @location(1) uv: vec2<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(image, image_sampler, in.uv);
+}
+"#;
+
+/// An image to draw this frame, in physical pixels
+pub struct ImageQuad<'a> {
+    pub rect: Rect,
+    pub image: &'a Thumbnail,
+}
+
+/// Draws RGBA images, keeping a texture per image until it stops being drawn
+pub struct ImageRenderer {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Uploaded images by thumbnail ID
+    textures: HashMap<u64, (wgpu::Texture, wgpu::BindGroup)>,
+    vertex_buffer: Option<wgpu::Buffer>,
+    /// Thumbnail ID drawn by each six-vertex quad, in buffer order
+    draws: Vec<u64>,
+}
+
+impl ImageRenderer {
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Image Shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Image Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Image Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Image Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Image Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            textures: HashMap::new(),
+            vertex_buffer: None,
+            draws: Vec::new(),
+        }
+    }
+
+    /// Upload images not yet on the GPU, free the ones no longer drawn, and
+    /// build this frame's quads
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, images: &[ImageQuad], width: u32, height: u32) {
+        self.textures
+            .retain(|id, _| images.iter().any(|quad| quad.image.id == *id));
+        for quad in images {
+            if !self.textures.contains_key(&quad.image.id) {
+                let uploaded = self.upload(device, queue, quad.image);
+                self.textures.insert(quad.image.id, uploaded);
+            }
+        }
+
+        let mut vertices = Vec::with_capacity(images.len() * 6);
+        self.draws.clear();
+        for quad in images.iter().filter(|q| q.rect.width > 0.0 && q.rect.height > 0.0) {
+            vertices.extend(quad_vertices(quad.rect, width as f32, height as f32));
+            self.draws.push(quad.image.id);
+        }
+        self.vertex_buffer = if vertices.is_empty() {
+            None
+        } else {
+            Some(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Image Vertex Buffer"),
+                contents: Vertex::as_bytes(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }))
+        };
+    }
+
+    pub fn render(&self, pass: &mut wgpu::RenderPass<'_>) {
+        let Some(buffer) = &self.vertex_buffer else {
+            return;
+        };
+        pass.set_pipeline(&self.pipeline);
+        pass.set_vertex_buffer(0, buffer.slice(..));
+        for (index, id) in self.draws.iter().enumerate() {
+            if let Some((_, bind_group)) = self.textures.get(id) {
+                let first = index as u32 * 6;
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.draw(first..first + 6, 0..1);
+            }
+        }
+    }
+
+    /// Free every texture, e.g. when the images stop being shown
+    pub fn clear(&mut self) {
+        self.textures.clear();
+        self.vertex_buffer = None;
+        self.draws.clear();
+    }
+
+    fn upload(&self, device: &Device, queue: &Queue, image: &Thumbnail) -> (wgpu::Texture, wgpu::BindGroup) {
+        let size = wgpu::Extent3d {
+            width: image.width,
+            height: image.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Image Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &image.rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(image.width * 4),
+                rows_per_image: Some(image.height),
+            },
+            size,
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Image Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        (texture, bind_group)
+    }
+}
+
+/// Two NDC triangles covering a pixel-space rectangle, textured corner to corner
+fn quad_vertices(rect: Rect, width: f32, height: f32) -> [Vertex; 6] {
+    let left = rect.x / width * 2.0 - 1.0;
+    let right = (rect.x + rect.width) / width * 2.0 - 1.0;
+    let top = 1.0 - rect.y / height * 2.0;
+    let bottom = 1.0 - (rect.y + rect.height) / height * 2.0;
+
+    let vertex = |position, uv| Vertex { position, uv };
+    [
+        vertex([left, top], [0.0, 0.0]),
+        vertex([left, bottom], [0.0, 1.0]),
+        vertex([right, bottom], [1.0, 1.0]),
+        vertex([left, top], [0.0, 0.0]),
+        vertex([right, bottom], [1.0, 1.0]),
+        vertex([right, top], [1.0, 0.0]),
+    ]
+}
@@ -0,0 +1,289 @@
+use super::app::{App, SharedServices, UserEvent};
+use super::fonts::{build_font_system, FontSettings};
+use super::renderer::StartingRenderer;
+use super::theme::{Theme, ThemePreference};
+use super::window::BrowserWindow;
+use crate::infrastructure::StartupProfile;
+
+use anyhow::Result;
+use glyphon::FontSystem;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot::{self, error::TryRecvError};
+use tokio::task::JoinHandle;
+use winit::{
+    application::ApplicationHandler,
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoopProxy},
+    window::WindowId,
+};
+
+/// Window title until the first page starts loading
+const STARTING_TITLE: &str = "Navigator - starting…";
+
+/// The profile database opening in the background: resolves once it is
+/// open and migrated, with what is built on it. Whatever reads the profile
+/// waits for it.
+pub struct DbReady<T> {
+    receiver: oneshot::Receiver<Result<T>>,
+}
+
+impl<T: Send + 'static> DbReady<T> {
+    /// Run `opening` on the runtime, calling `wake` once it is done or has
+    /// failed
+    pub fn spawn<F>(runtime: &Handle, opening: F, wake: impl FnOnce() + Send + 'static) -> Self
+    where
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let opening = runtime.spawn(opening);
+        runtime.spawn(async move {
+            let opened = opening
+                .await
+                .unwrap_or_else(|e| Err(anyhow::anyhow!("Opening the profile failed: {}", e)));
+            let _ = sender.send(opened);
+            wake();
+        });
+        Self { receiver }
+    }
+
+    /// What opening gave, once it is done; only taken once
+    pub fn try_take(&mut self) -> Option<Result<T>> {
+        match self.receiver.try_recv() {
+            Ok(opened) => Some(opened),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(anyhow::anyhow!("The profile was already taken"))),
+        }
+    }
+
+    pub async fn wait(self) -> Result<T> {
+        self.receiver
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("The profile stopped opening")))
+    }
+}
+
+/// The first window while the profile opens
+struct StartingWindow {
+    window: BrowserWindow,
+    renderer: StartingRenderer,
+    theme: Theme,
+}
+
+/// Drives the event loop until the profile is open: puts the first window
+/// up at once with a plain frame, then hands it to the app built on the
+/// profile and passes everything on to it
+pub struct Launcher {
+    runtime: Handle,
+    proxy: EventLoopProxy<UserEvent>,
+    db_ready: DbReady<SharedServices>,
+    /// System fonts, scanned while the profile opens
+    fonts: Option<JoinHandle<FontSystem>>,
+    starting: Option<StartingWindow>,
+    app: Option<App>,
+    update_check: bool,
+    profile: StartupProfile,
+    /// Print the startup phases once the first page starts loading
+    startup_report: bool,
+    /// Why the app could not start
+    failure: Option<anyhow::Error>,
+}
+
+impl Launcher {
+    /// Starts scanning the system fonts and listening for signals to quit
+    /// right away
+    pub fn new(
+        runtime: Handle,
+        proxy: EventLoopProxy<UserEvent>,
+        db_ready: DbReady<SharedServices>,
+        profile: StartupProfile,
+    ) -> Self {
+        let scanning = profile.clone();
+        let fonts = runtime.spawn_blocking(move || {
+            scanning.time("fonts", || build_font_system(&FontSettings::default()))
+        });
+        forward_shutdown_signals(&runtime, proxy.clone());
+        Self {
+            runtime,
+            proxy,
+            db_ready,
+            fonts: Some(fonts),
+            starting: None,
+            app: None,
+            update_check: true,
+            profile,
+            startup_report: false,
+            failure: None,
+        }
+    }
+
+    /// Whether the app checks for a newer release on startup, if turned on
+    /// in settings
+    pub fn with_update_check(mut self, enabled: bool) -> Self {
+        self.update_check = enabled;
+        self
+    }
+
+    /// Print how long each startup phase took
+    pub fn with_startup_report(mut self, enabled: bool) -> Self {
+        self.startup_report = enabled;
+        self
+    }
+
+    /// The services of the app, once it started
+    pub fn services(&self) -> Option<&Arc<SharedServices>> {
+        self.app.as_ref().map(App::services)
+    }
+
+    /// Why the app could not start, if it did not
+    pub fn take_failure(&mut self) -> Option<anyhow::Error> {
+        self.failure.take()
+    }
+
+    /// Put the first window up with a plain frame, before any text can be
+    /// drawn
+    fn open_starting_window(&mut self, event_loop: &ActiveEventLoop) -> Result<()> {
+        let window = self.profile.time("window", || BrowserWindow::new(event_loop, true, None))?;
+        window.window().set_title(STARTING_TITLE);
+        let mut renderer = self
+            .runtime
+            .block_on(self.profile.time_async("gpu", StartingRenderer::new(window.window())))?;
+        let theme = ThemePreference::Auto.resolve(window.window().theme());
+        self.profile.time("first frame", || renderer.render(&theme))?;
+        self.starting = Some(StartingWindow { window, renderer, theme });
+        Ok(())
+    }
+
+    /// Start the app if the profile is open and the window up
+    fn try_start(&mut self, event_loop: &ActiveEventLoop) {
+        if self.app.is_some() || self.starting.is_none() {
+            return;
+        }
+        let Some(opened) = self.db_ready.try_take() else {
+            return;
+        };
+        if let Err(e) = opened.and_then(|services| self.start(event_loop, services)) {
+            tracing::error!("Failed to start: {:#}", e);
+            self.failure = Some(e);
+            event_loop.exit();
+        }
+    }
+
+    /// Hand the first window to the app, which only then loads pages
+    fn start(&mut self, event_loop: &ActiveEventLoop, services: SharedServices) -> Result<()> {
+        let (Some(starting), Some(fonts)) = (self.starting.take(), self.fonts.take()) else {
+            anyhow::bail!("The first window was already handed over");
+        };
+        let font_system = self.runtime.block_on(fonts)?;
+        let mut app =
+            App::new(self.runtime.clone(), services, self.proxy.clone()).with_update_check(self.update_check);
+        self.profile.time("renderer", || {
+            app.adopt_first_window(starting.window, starting.renderer, font_system)
+        })?;
+        self.profile.time("first navigation", || app.resumed(event_loop));
+        if self.startup_report {
+            println!("{}", self.profile.report());
+        }
+        self.app = Some(app);
+        Ok(())
+    }
+}
+
+/// Ask the event loop to shut down on Ctrl+C, or on Unix also SIGTERM
+fn forward_shutdown_signals(runtime: &Handle, proxy: EventLoopProxy<UserEvent>) {
+    runtime.spawn(async move {
+        #[cfg(unix)]
+        let terminate = async {
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+                Ok(mut terminate) => {
+                    terminate.recv().await;
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to listen for SIGTERM: {}", e);
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+        tokio::select! {
+            // A failure to listen disables this branch rather than quitting
+            Ok(()) = tokio::signal::ctrl_c() => {}
+            () = terminate => {}
+        }
+        tracing::info!("Asked to quit, shutting down...");
+        let _ = proxy.send_event(UserEvent::ShutdownRequested);
+    });
+}
+
+impl ApplicationHandler<UserEvent> for Launcher {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(app) = &mut self.app {
+            app.resumed(event_loop);
+            return;
+        }
+        event_loop.set_control_flow(ControlFlow::Wait);
+        if self.starting.is_none() {
+            if let Err(e) = self.open_starting_window(event_loop) {
+                tracing::error!("Failed to open window: {}", e);
+                self.failure = Some(e);
+                event_loop.exit();
+                return;
+            }
+        }
+        // The profile may have opened first
+        self.try_start(event_loop);
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {
+        if let Some(app) = &mut self.app {
+            app.window_event(event_loop, window_id, event);
+            return;
+        }
+        let Some(starting) = self.starting.as_mut().filter(|starting| starting.window.id() == window_id) else {
+            return;
+        };
+        match event {
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::Resized(size) => {
+                starting.renderer.resize(size);
+                starting.window.request_redraw();
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                starting.renderer.set_scale_factor(scale_factor);
+            }
+            WindowEvent::ThemeChanged(system_theme) => {
+                starting.theme = ThemePreference::Auto.resolve(Some(system_theme));
+                starting.window.request_redraw();
+            }
+            WindowEvent::RedrawRequested => {
+                if let Err(e) = starting.renderer.render(&starting.theme) {
+                    tracing::warn!("Failed to draw the starting window: {}", e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        match &mut self.app {
+            Some(app) => app.user_event(event_loop, event),
+            None if event == UserEvent::DbReady => self.try_start(event_loop),
+            None if event == UserEvent::ShutdownRequested => event_loop.exit(),
+            None => {}
+        }
+    }
+
+    fn exiting(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(app) = &mut self.app {
+            app.exiting(event_loop);
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(app) = &mut self.app {
+            app.about_to_wait(event_loop);
+        }
+    }
+}
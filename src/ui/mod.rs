@@ -1,9 +1,49 @@
 // UI Layer - Visual rendering and window management
+pub mod app;
 pub mod window;
 pub mod renderer;
+pub mod rect_renderer;
+pub mod image_renderer;
 pub mod text_renderer;
 pub mod address_bar;
+pub mod chrome;
+pub mod clipboard;
+pub mod fonts;
+pub mod theme;
+pub mod scroll;
+pub mod scrollbar;
+pub mod selection;
+pub mod caret;
+pub mod screenshot;
+pub mod thumbnails;
+pub mod overview;
+pub mod hints;
+pub mod toasts;
+pub mod palette;
+pub mod color;
+pub mod dark_pages;
+pub mod launcher;
+pub mod hit_regions;
 
-pub use window::BrowserWindow;
-pub use renderer::Renderer;
-pub use address_bar::{AddressBar, AddressBarAction};
+pub use app::{file_access_policy, App, SharedServices, UserEvent};
+pub use window::{saved_position, BrowserWindow, MonitorArea};
+pub use renderer::{PageFrame, Renderer, StartingRenderer};
+pub use rect_renderer::{Rect, RectRenderer};
+pub use address_bar::{AddressBar, AddressBarAction, DownloadIndicator};
+pub use chrome::{ChromeLayout, ChromeRegion};
+pub use clipboard::{Clipboard, ClipboardBackend, MemoryClipboard};
+pub use fonts::{pdf_fonts, FontSettings};
+pub use theme::{Color, Theme, ThemePreference};
+pub use scroll::ScrollState;
+pub use scrollbar::{Scrollbar, ScrollbarGeometry, ScrollbarHit};
+pub use selection::Selection;
+pub use caret::{Caret, CaretMotion};
+pub use screenshot::{OffscreenCapture, Screenshot};
+pub use thumbnails::{Thumbnail, ThumbnailCache};
+pub use overview::{OverviewAction, OverviewItem};
+pub use hints::{HintAction, LinkHints};
+pub use toasts::{PlacedToast, ToastManager};
+pub use palette::{CommandPalette, PlacedPalette};
+pub use dark_pages::DarkPageCache;
+pub use launcher::{DbReady, Launcher};
+pub use hit_regions::{HitOverlay, HitRegion, HitRegionKind, HitRegionMap};
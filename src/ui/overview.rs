@@ -0,0 +1,171 @@
+use super::rect_renderer::Rect;
+use super::thumbnails::{Thumbnail, THUMBNAIL_WIDTH};
+use winit::keyboard::{Key, NamedKey};
+
+/// Space around and between cards, in logical pixels
+const GAP: f32 = 24.0;
+/// Height of the title line under each thumbnail, in logical pixels
+const TITLE_HEIGHT: f32 = 28.0;
+/// Thumbnail area shape: width over height
+const IMAGE_ASPECT: f32 = 16.0 / 10.0;
+
+/// One tab in the overview grid
+pub struct OverviewItem<'a> {
+    pub title: &'a str,
+    pub thumbnail: Option<&'a Thumbnail>,
+    /// Shown instead of a thumbnail
+    pub placeholder: char,
+}
+
+/// Where one card of the grid goes, in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverviewCell {
+    pub card: Rect,
+    pub image: Rect,
+    pub title: Rect,
+}
+
+/// Card positions for `count` tabs in a window of the given size
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverviewLayout {
+    pub columns: usize,
+    pub cells: Vec<OverviewCell>,
+}
+
+impl OverviewLayout {
+    pub fn compute(count: usize, width: f32, scale_factor: f64) -> Self {
+        let scale = scale_factor as f32;
+        let gap = GAP * scale;
+        let card_width = (THUMBNAIL_WIDTH as f32).min((width - gap * 2.0).max(1.0));
+        let columns = (((width - gap) / (card_width + gap)).floor() as usize).max(1);
+
+        let image_height = card_width / IMAGE_ASPECT;
+        let card_height = image_height + TITLE_HEIGHT * scale;
+        // Center the grid horizontally
+        let grid_width = columns as f32 * (card_width + gap) - gap;
+        let left = ((width - grid_width) / 2.0).max(gap);
+
+        let cells = (0..count)
+            .map(|index| {
+                let x = left + (index % columns) as f32 * (card_width + gap);
+                let y = gap + (index / columns) as f32 * (card_height + gap);
+                OverviewCell {
+                    card: Rect::new(x, y, card_width, card_height, [0.0; 4]),
+                    image: Rect::new(x, y, card_width, image_height, [0.0; 4]),
+                    title: Rect::new(x, y + image_height, card_width, card_height - image_height, [0.0; 4]),
+                }
+            })
+            .collect();
+
+        Self { columns, cells }
+    }
+
+    /// Vertical scroll that keeps the selected card inside a viewport of `height`
+    pub fn scroll_for(&self, selected: usize, height: f32) -> f32 {
+        self.cells
+            .get(selected)
+            .map(|cell| (cell.card.y + cell.card.height + GAP - height).max(0.0))
+            .unwrap_or(0.0)
+    }
+}
+
+/// Largest rectangle with the image's aspect ratio centered in `area`
+pub fn fit_image(area: Rect, width: u32, height: u32) -> Rect {
+    let scale = (area.width / width as f32).min(area.height / height as f32);
+    let (w, h) = (width as f32 * scale, height as f32 * scale);
+    Rect::new(
+        area.x + (area.width - w) / 2.0,
+        area.y + (area.height - h) / 2.0,
+        w,
+        h,
+        area.color,
+    )
+}
+
+/// What a key does while the overview is open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverviewAction {
+    Select(usize),
+    /// Switch to the tab and leave the overview
+    Open(usize),
+    Close(usize),
+    Dismiss,
+}
+
+/// Map a key to an action on a grid of `count` tabs with `columns` per row
+pub fn overview_key(key: &Key, selected: usize, count: usize, columns: usize) -> Option<OverviewAction> {
+    if count == 0 {
+        return matches!(key, Key::Named(NamedKey::Escape)).then_some(OverviewAction::Dismiss);
+    }
+    let last = count - 1;
+    let selected = selected.min(last);
+    let action = match key {
+        Key::Named(NamedKey::ArrowLeft) => OverviewAction::Select(selected.saturating_sub(1)),
+        Key::Named(NamedKey::ArrowRight) => OverviewAction::Select((selected + 1).min(last)),
+        // Up and down stay put rather than wrap at the first and last rows
+        Key::Named(NamedKey::ArrowUp) => {
+            OverviewAction::Select(selected.checked_sub(columns).unwrap_or(selected))
+        }
+        Key::Named(NamedKey::ArrowDown) => {
+            OverviewAction::Select(Some(selected + columns).filter(|i| *i <= last).unwrap_or(selected))
+        }
+        Key::Named(NamedKey::Home) => OverviewAction::Select(0),
+        Key::Named(NamedKey::End) => OverviewAction::Select(last),
+        Key::Named(NamedKey::Enter) => OverviewAction::Open(selected),
+        Key::Named(NamedKey::Delete) => OverviewAction::Close(selected),
+        Key::Named(NamedKey::Escape) => OverviewAction::Dismiss,
+        _ => return None,
+    };
+    Some(action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_wraps_into_columns() {
+        let layout = OverviewLayout::compute(5, 1200.0, 1.0);
+        assert_eq!(layout.columns, 3);
+        assert_eq!(layout.cells.len(), 5);
+        assert_eq!(layout.cells[3].card.x, layout.cells[0].card.x);
+        assert!(layout.cells[3].card.y > layout.cells[2].card.y);
+
+        // A narrow window still gets one shrunken column
+        let narrow = OverviewLayout::compute(2, 200.0, 1.0);
+        assert_eq!(narrow.columns, 1);
+        assert!(narrow.cells[0].card.width < THUMBNAIL_WIDTH as f32);
+        assert_eq!(narrow.scroll_for(0, 1000.0), 0.0);
+        assert!(narrow.scroll_for(1, 200.0) > 0.0);
+    }
+
+    #[test]
+    fn test_fit_image_keeps_aspect() {
+        let area = Rect::new(0.0, 0.0, 320.0, 200.0, [0.0; 4]);
+        let fitted = fit_image(area, 320, 100);
+        assert_eq!((fitted.width, fitted.height), (320.0, 100.0));
+        assert_eq!(fitted.y, 50.0);
+    }
+
+    #[test]
+    fn test_overview_keys() {
+        let key = |named| Key::Named(named);
+        assert_eq!(
+            overview_key(&key(NamedKey::ArrowDown), 1, 5, 3),
+            Some(OverviewAction::Select(4))
+        );
+        assert_eq!(
+            overview_key(&key(NamedKey::ArrowDown), 2, 5, 3),
+            Some(OverviewAction::Select(2))
+        );
+        assert_eq!(
+            overview_key(&key(NamedKey::ArrowLeft), 0, 5, 3),
+            Some(OverviewAction::Select(0))
+        );
+        assert_eq!(
+            overview_key(&key(NamedKey::Delete), 9, 5, 3),
+            Some(OverviewAction::Close(4))
+        );
+        assert_eq!(overview_key(&key(NamedKey::Enter), 0, 0, 3), None);
+    }
+}
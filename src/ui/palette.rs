@@ -0,0 +1,132 @@
+use super::chrome::ChromeLayout;
+use crate::application::PaletteEntry;
+
+/// Shown in the address bar while it holds a palette query
+pub const PALETTE_LABEL: &str = "Command";
+/// Palette width, row height, margin and corner radius in logical pixels
+pub const PALETTE_WIDTH: f32 = 560.0;
+pub const PALETTE_ROW_HEIGHT: f32 = 30.0;
+pub const PALETTE_MARGIN: f32 = 8.0;
+pub const PALETTE_RADIUS: f32 = 8.0;
+/// Palette text size in logical pixels
+pub const PALETTE_TEXT_SIZE: f32 = 13.0;
+
+/// The command palette of a window: what matches the query typed in the
+/// address bar, one of them selected
+#[derive(Debug, Clone, Default)]
+pub struct CommandPalette {
+    entries: Vec<PaletteEntry>,
+    selected: usize,
+}
+
+/// The palette placed under the address bar, in physical pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedPalette<'a> {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub row_height: f32,
+    pub rows: Vec<&'a PaletteEntry>,
+    pub selected: usize,
+}
+
+impl PlacedPalette<'_> {
+    /// Height of the card, one row tall when nothing matches
+    pub fn height(&self) -> f32 {
+        self.row_height * self.rows.len().max(1) as f32
+    }
+}
+
+impl CommandPalette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List what matches a new query, selecting the best
+    pub fn set_entries(&mut self, entries: Vec<PaletteEntry>) {
+        self.entries = entries;
+        self.selected = 0;
+    }
+
+    pub fn entries(&self) -> &[PaletteEntry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> Option<&PaletteEntry> {
+        self.entries.get(self.selected)
+    }
+
+    /// Select the next entry, wrapping to the first
+    pub fn select_next(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = (self.selected + 1) % self.entries.len();
+        }
+    }
+
+    /// Select the previous entry, wrapping to the last
+    pub fn select_previous(&mut self) {
+        if !self.entries.is_empty() {
+            self.selected = (self.selected + self.entries.len() - 1) % self.entries.len();
+        }
+    }
+
+    /// Where the palette is drawn: centered over the top of the page
+    pub fn layout(&self, chrome: &ChromeLayout) -> PlacedPalette<'_> {
+        let scale = chrome.scale;
+        let margin = PALETTE_MARGIN * scale;
+        let width = (PALETTE_WIDTH * scale).min(chrome.content_width - margin * 2.0).max(0.0);
+        PlacedPalette {
+            x: chrome.content_left + (chrome.content_width - width) / 2.0,
+            y: chrome.content_top + margin,
+            width,
+            row_height: PALETTE_ROW_HEIGHT * scale,
+            rows: self.entries.iter().collect(),
+            selected: self.selected,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::Command;
+
+    fn palette() -> CommandPalette {
+        let mut palette = CommandPalette::new();
+        palette.set_entries(
+            [Command::Reload, Command::Back, Command::Forward]
+                .into_iter()
+                .map(|command| PaletteEntry::command(command, String::new()))
+                .collect(),
+        );
+        palette
+    }
+
+    #[test]
+    fn test_selection_wraps_and_resets_on_new_entries() {
+        let mut palette = palette();
+        let selected = |palette: &CommandPalette| palette.selected().map(|entry| entry.label.clone());
+        assert_eq!(selected(&palette).as_deref(), Some("Reload the page"));
+        palette.select_previous();
+        assert_eq!(selected(&palette).as_deref(), Some("Go forward"));
+        palette.select_next();
+        palette.select_next();
+        assert_eq!(selected(&palette).as_deref(), Some("Go back"));
+
+        palette.set_entries(Vec::new());
+        palette.select_next();
+        assert_eq!(palette.selected(), None);
+    }
+
+    #[test]
+    fn test_palette_is_centered_below_the_address_bar() {
+        let chrome = ChromeLayout::compute(1000.0, 800.0, 1.0, 1.0);
+        let palette = palette();
+        let placed = palette.layout(&chrome);
+        assert_eq!(placed.width, PALETTE_WIDTH);
+        let right = chrome.content_left + chrome.content_width - placed.x - placed.width;
+        assert_eq!(placed.x - chrome.content_left, right);
+        assert!(placed.y > chrome.content_top);
+        assert_eq!(placed.height(), PALETTE_ROW_HEIGHT * 3.0);
+    }
+}
@@ -0,0 +1,252 @@
+use wgpu::util::DeviceExt;
+use wgpu::{Device, TextureFormat};
+
+/// A solid, axis-aligned rectangle in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// Linear RGBA
+    pub color: [f32; 4],
+    /// Corner radius; 0 draws square corners
+    pub radius: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            color,
+            radius: 0.0,
+        }
+    }
+
+    /// The same rectangle with rounded, antialiased corners
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct Vertex {
+    position: [f32; 2],
+    color: [f32; 4],
+    /// Pixel offset from the rectangle's center
+    local: [f32; 2],
+    half_size: [f32; 2],
+    radius: f32,
+}
+
+impl Vertex {
+    fn as_bytes(vertices: &[Vertex]) -> &[u8] {
+        // Vertex is repr(C) and made only of f32s, so it has no padding
+        unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(vertices),
+            )
+        }
+    }
+}
+
+const SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) local: vec2<f32>,
+    @location(2) half_size: vec2<f32>,
+    @location(3) radius: f32,
+};
+
+@vertex
+fn vs_main(
+    @location(0) position: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) local: vec2<f32>,
+    @location(3) half_size: vec2<f32>,
+    @location(4) radius: f32,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = vec4<f32>(position, 0.0, 1.0);
+    out.color = color;
+    out.local = local;
+    out.half_size = half_size;
+    out.radius = radius;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if in.radius <= 0.0 {
+        return in.color;
+    }
+    // Signed distance to the rounded outline, fading over one pixel
+    let q = abs(in.local) - in.half_size + vec2<f32>(in.radius);
+    let distance = length(max(q, vec2<f32>(0.0))) + min(max(q.x, q.y), 0.0) - in.radius;
+    let coverage = clamp(0.5 - distance, 0.0, 1.0);
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
+"#;
+
+/// Draws batches of solid rectangles (backgrounds, scrollbars, highlights),
+/// optionally with rounded corners
+pub struct RectRenderer {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: Option<wgpu::Buffer>,
+    vertex_count: u32,
+}
+
+impl RectRenderer {
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Rect Shader"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Rect Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Rect Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x2,
+                        1 => Float32x4,
+                        2 => Float32x2,
+                        3 => Float32x2,
+                        4 => Float32
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            vertex_buffer: None,
+            vertex_count: 0,
+        }
+    }
+
+    /// Upload the rectangles to draw this frame
+    pub fn prepare(&mut self, device: &Device, rects: &[Rect], width: u32, height: u32) {
+        let vertices = rects_to_vertices(rects, width as f32, height as f32);
+        self.vertex_count = vertices.len() as u32;
+        self.vertex_buffer = if vertices.is_empty() {
+            None
+        } else {
+            Some(device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Rect Vertex Buffer"),
+                contents: Vertex::as_bytes(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }))
+        };
+    }
+
+    pub fn render(&self, pass: &mut wgpu::RenderPass<'_>) {
+        if let Some(buffer) = &self.vertex_buffer {
+            pass.set_pipeline(&self.pipeline);
+            pass.set_vertex_buffer(0, buffer.slice(..));
+            pass.draw(0..self.vertex_count, 0..1);
+        }
+    }
+}
+
+/// Convert pixel-space rectangles into two NDC triangles each
+fn rects_to_vertices(rects: &[Rect], width: f32, height: f32) -> Vec<Vertex> {
+    let mut vertices = Vec::with_capacity(rects.len() * 6);
+    for rect in rects.iter().filter(|r| r.width > 0.0 && r.height > 0.0) {
+        let left = rect.x / width * 2.0 - 1.0;
+        let right = (rect.x + rect.width) / width * 2.0 - 1.0;
+        let top = 1.0 - rect.y / height * 2.0;
+        let bottom = 1.0 - (rect.y + rect.height) / height * 2.0;
+        let half_size = [rect.width / 2.0, rect.height / 2.0];
+        // No corner is rounder than the shorter side allows
+        let radius = rect.radius.clamp(0.0, half_size[0].min(half_size[1]));
+
+        for (position, local) in [
+            ([left, top], [-half_size[0], -half_size[1]]),
+            ([left, bottom], [-half_size[0], half_size[1]]),
+            ([right, bottom], [half_size[0], half_size[1]]),
+            ([left, top], [-half_size[0], -half_size[1]]),
+            ([right, bottom], [half_size[0], half_size[1]]),
+            ([right, top], [half_size[0], -half_size[1]]),
+        ] {
+            vertices.push(Vertex {
+                position,
+                color: rect.color,
+                local,
+                half_size,
+                radius,
+            });
+        }
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rect_to_ndc() {
+        let rect = Rect::new(0.0, 0.0, 50.0, 50.0, [1.0; 4]);
+        let vertices = rects_to_vertices(&[rect], 100.0, 100.0);
+
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(vertices[0].position, [-1.0, 1.0]);
+        assert_eq!(vertices[2].position, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_corner_radius_fits_the_rect() {
+        let rect = Rect::new(0.0, 0.0, 40.0, 10.0, [1.0; 4]).with_radius(8.0);
+        let vertices = rects_to_vertices(&[rect], 100.0, 100.0);
+
+        assert_eq!(vertices[0].local, [-20.0, -5.0]);
+        assert_eq!(vertices[0].half_size, [20.0, 5.0]);
+        assert_eq!(vertices[0].radius, 5.0);
+        assert_eq!(rects_to_vertices(&[Rect::new(0.0, 0.0, 4.0, 4.0, [1.0; 4])], 10.0, 10.0)[0].radius, 0.0);
+    }
+
+    #[test]
+    fn test_empty_rects_skipped() {
+        let rect = Rect::new(10.0, 10.0, 0.0, 5.0, [1.0; 4]);
+        assert!(rects_to_vertices(&[rect], 100.0, 100.0).is_empty());
+    }
+}
@@ -3,26 +3,96 @@ use wgpu::{
 };
 use winit::window::Window;
 use anyhow::Result;
+use std::ops::Range;
 use std::sync::Arc;
-use super::text_renderer::TextRenderer;
+use super::text_renderer::{ContentLayoutOptions, LinkColors, PageArea, PageLayoutKey, TextLayout, TextRenderer};
+use super::caret::CaretLayout;
+use crate::infrastructure::{BlockKind, PageContent};
 use super::address_bar::AddressBar;
-use glyphon::{TextArea, TextBounds, Color as GlyphonColor};
+use super::fonts::{build_font_system, FontSettings};
+use super::theme::{Color, Theme};
+use super::rect_renderer::{Rect, RectRenderer};
+use super::image_renderer::{ImageQuad, ImageRenderer};
+use super::overview::{fit_image, OverviewItem, OverviewLayout};
+use super::hints::LinkHints;
+use super::hit_regions::{HitOverlay, HitRegionKind, HitRegionMap};
+use super::toasts::{severity_color, PlacedToast, TOAST_RADIUS, TOAST_TEXT_SIZE};
+use super::palette::{PlacedPalette, PALETTE_RADIUS, PALETTE_TEXT_SIZE};
+use super::thumbnails::Thumbnail;
+use super::screenshot::{capture_chunks, check_capture_size, Screenshot};
+use super::scroll::ScrollState;
+use super::scrollbar::ScrollbarGeometry;
+use super::chrome::ChromeLayout;
+use glyphon::{FontSystem, TextArea, TextBounds};
 
-const ADDRESS_BAR_HEIGHT: f32 = 50.0;
+/// Size of link labels, in logical pixels before the chrome scale
+const HINT_TEXT_SIZE: f32 = 12.0;
+/// Size of region ids in the hit region overlay
+const HIT_LABEL_TEXT_SIZE: f32 = 9.0;
+
+/// Page state drawn in one frame
+pub struct PageFrame<'a> {
+    pub content: &'a PageContent,
+    /// Changes whenever `content` is replaced, invalidating its cached layout
+    pub generation: u64,
+    pub layout_options: ContentLayoutOptions,
+    pub scrollbar_opacity: f32,
+    /// Byte offset of the page text whose scroll position should be reported
+    pub reveal: Option<usize>,
+    /// Selected byte range of the page text
+    pub selection: Option<Range<usize>>,
+    /// Labels over the links in view, while picking one by keyboard
+    pub hints: Option<&'a LinkHints>,
+    /// Byte offset of the page text the caret is drawn before, in caret browsing
+    pub caret: Option<usize>,
+    /// Notifications over the bottom-right corner of the page
+    pub toasts: &'a [PlacedToast<'a>],
+    /// The command palette over the top of the page, while open
+    pub palette: Option<&'a PlacedPalette<'a>>,
+    /// Colors the page is drawn in when recolored dark, unlike the chrome
+    pub page_theme: Option<Theme>,
+    /// Outline every hit region over the frame, while debugging pointer input
+    pub hit_overlay: Option<HitOverlay>,
+}
 
 /// GPU renderer using wgpu
 pub struct Renderer {
-    surface: Surface<'static>,
+    /// The window drawn to; headless renderers only capture offscreen
+    surface: Option<Surface<'static>>,
     device: Device,
     queue: Queue,
     config: SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     text_renderer: TextRenderer,
+    rect_renderer: RectRenderer,
+    /// Rectangles drawn over the page text, behind overlay text
+    overlay_rect_renderer: RectRenderer,
+    image_renderer: ImageRenderer,
+    scale_factor: f64,
+    ui_scale: f32,
+    /// The page last drawn reads right to left, which mirrors the content
+    /// area and scrollbar
+    rtl: bool,
+    /// What the pointer can hit in the frame last shown
+    hit_regions: HitRegionMap,
 }
 
-impl Renderer {
+/// A window's surface and GPU device, set up before the fonts are ready:
+/// draws plain frames while the browser starts, then becomes the full
+/// renderer
+pub struct StartingRenderer {
+    surface: Surface<'static>,
+    device: Device,
+    queue: Queue,
+    config: SurfaceConfiguration,
+    scale_factor: f64,
+    rect_renderer: RectRenderer,
+}
+
+impl StartingRenderer {
     pub async fn new(window: Arc<Window>) -> Result<Self> {
         let size = window.inner_size();
+        let scale_factor = window.scale_factor();
 
         // Create wgpu instance
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
@@ -43,20 +113,6 @@ impl Renderer {
             .await
             .ok_or_else(|| anyhow::anyhow!("Failed to find an appropriate adapter"))?;
 
-        // Request device and queue
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default(),
-                    memory_hints: Default::default(),
-                },
-                None,
-            )
-            .await?;
-
-        // Configure surface
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = surface_caps
             .formats
@@ -64,7 +120,6 @@ impl Renderer {
             .find(|f| f.is_srgb())
             .copied()
             .unwrap_or(surface_caps.formats[0]);
-
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface_format,
@@ -75,17 +130,162 @@ impl Renderer {
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
         };
-
+        let (device, queue) = request_device(&adapter).await?;
         surface.configure(&device, &config);
+        let rect_renderer = RectRenderer::new(&device, surface_format);
+
+        Ok(Self {
+            surface,
+            device,
+            queue,
+            config,
+            scale_factor,
+            rect_renderer,
+        })
+    }
+
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        if new_size.width > 0 && new_size.height > 0 {
+            self.config.width = new_size.width;
+            self.config.height = new_size.height;
+            self.surface.configure(&self.device, &self.config);
+        }
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    /// Draw the page background under an empty address bar, needing no text
+    pub fn render(&mut self, theme: &Theme) -> Result<()> {
+        let output = self.surface.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let (width, height) = (self.config.width, self.config.height);
+        let chrome = ChromeLayout::compute(width as f32, height as f32, self.scale_factor, 1.0);
+        let bar = Rect::new(
+            0.0,
+            0.0,
+            chrome.width,
+            chrome.address_bar_height,
+            theme.chrome_background.to_linear_rgba(1.0),
+        );
+        self.rect_renderer.prepare(&self.device, &[bar], width, height);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Starting Encoder"),
+            });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Starting Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(theme.background.to_wgpu()),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.rect_renderer.render(&mut render_pass);
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+        Ok(())
+    }
+
+    /// The full renderer on the same surface and device, shaping text with
+    /// `font_system`
+    pub fn finish(self, font_system: FontSystem, fonts: FontSettings) -> Result<Renderer> {
+        Renderer::assemble(
+            self.device,
+            self.queue,
+            Some(self.surface),
+            self.config,
+            self.scale_factor,
+            font_system,
+            fonts,
+        )
+    }
+}
+
+/// Request the device and queue everything is drawn with
+async fn request_device(adapter: &wgpu::Adapter) -> Result<(Device, Queue)> {
+    let device = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: wgpu::Features::empty(),
+                // The adapter's own texture size limit lets full-page captures use fewer chunks
+                required_limits: wgpu::Limits::default().using_resolution(adapter.limits()),
+                memory_hints: Default::default(),
+            },
+            None,
+        )
+        .await?;
+    Ok(device)
+}
+
+impl Renderer {
+    pub async fn new(window: Arc<Window>, fonts: FontSettings) -> Result<Self> {
+        StartingRenderer::new(window).await?.finish(build_font_system(&fonts), fonts)
+    }
+
+    /// A renderer without a window, for capturing pages from the command
+    /// line and in tests. `fallback_adapter` asks for the software adapter.
+    pub async fn headless(width: u32, height: u32, fonts: FontSettings, fallback_adapter: bool) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: fallback_adapter,
+            })
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Failed to find an appropriate adapter"))?;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        let (device, queue) = request_device(&adapter).await?;
+        Self::assemble(device, queue, None, config, 1.0, build_font_system(&fonts), fonts)
+    }
+
+    fn assemble(
+        device: Device,
+        queue: Queue,
+        surface: Option<Surface<'static>>,
+        config: SurfaceConfiguration,
+        scale_factor: f64,
+        font_system: FontSystem,
+        fonts: FontSettings,
+    ) -> Result<Self> {
+        let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+        let surface_format = config.format;
 
         // Create text renderer
-        let text_renderer = TextRenderer::new(
-            &device,
-            &queue,
-            surface_format,
-            size.width,
-            size.height,
-        )?;
+        let mut text_renderer =
+            TextRenderer::with_font_system(&device, &queue, surface_format, font_system, fonts)?;
+        // Headless renderers get no resize event to set the text viewport
+        text_renderer.resize(&device, &queue, size.width, size.height);
+        let rect_renderer = RectRenderer::new(&device, surface_format);
+        let overlay_rect_renderer = RectRenderer::new(&device, surface_format);
+        let image_renderer = ImageRenderer::new(&device, surface_format);
 
         Ok(Self {
             surface,
@@ -94,6 +294,13 @@ impl Renderer {
             config,
             size,
             text_renderer,
+            rect_renderer,
+            overlay_rect_renderer,
+            image_renderer,
+            scale_factor,
+            ui_scale: 1.0,
+            rtl: false,
+            hit_regions: HitRegionMap::new(),
         })
     }
 
@@ -102,37 +309,410 @@ impl Renderer {
             self.size = new_size;
             self.config.width = new_size.width;
             self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            if let Some(surface) = &self.surface {
+                surface.configure(&self.device, &self.config);
+            }
             self.text_renderer.resize(&self.device, &self.queue, new_size.width, new_size.height);
         }
     }
 
-    pub fn render(&mut self, html_content: &str, address_bar: &AddressBar) -> Result<()> {
-        let output = self.surface.get_current_texture()?;
+    fn surface(&self) -> Result<&Surface<'static>> {
+        self.surface
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("A headless renderer has no window to draw to"))
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Set the chrome scale, on top of the display scale factor
+    pub fn set_ui_scale(&mut self, ui_scale: f32) {
+        self.ui_scale = ui_scale;
+    }
+
+    /// Chrome and content regions for the current size and scales
+    pub fn chrome_layout(&self) -> ChromeLayout {
+        let chrome = ChromeLayout::compute(
+            self.size.width as f32,
+            self.size.height as f32,
+            self.scale_factor,
+            self.ui_scale,
+        );
+        if self.rtl {
+            chrome.mirrored()
+        } else {
+            chrome
+        }
+    }
+
+    /// Scrollbar layout for the current size, if the page can scroll
+    pub fn scrollbar_geometry(&self, scroll: &ScrollState) -> Option<ScrollbarGeometry> {
+        let chrome = self.chrome_layout();
+        ScrollbarGeometry::compute(chrome.scrollbar_right(), chrome.content_top, scroll, self.scale_factor)
+    }
+
+    /// Regions of the frame last shown that take clicks, which pointer
+    /// input is resolved against
+    pub fn hit_regions(&self) -> &HitRegionMap {
+        &self.hit_regions
+    }
+
+    /// Byte offset of the page text under a window position, if any
+    pub fn hit_test_content(&self, x: f32, y: f32, scroll: &ScrollState) -> Option<usize> {
+        let chrome = self.chrome_layout();
+        if y < chrome.content_top {
+            return None;
+        }
+        let layout = self.text_renderer.cached_page_layout()?;
+        layout.hit(
+            x - chrome.content_left - chrome.content_padding,
+            y - chrome.content_top - chrome.content_padding + scroll.offset(),
+        )
+    }
+
+    /// Page layout drawn by the last frame
+    pub fn content_layout(&self) -> Option<&TextLayout> {
+        self.text_renderer.cached_page_layout()
+    }
+
+    /// Vertical span of the page layout the content area shows
+    pub fn visible_layout_span(&self, scroll: &ScrollState) -> Range<f32> {
+        let chrome = self.chrome_layout();
+        let top = scroll.offset() - chrome.content_padding;
+        top..top + chrome.content_height
+    }
+
+    /// Glyph rectangles in layout coordinates of each byte range of the page
+    /// text; ranges whose lines all lie outside `span` get none
+    pub fn range_rects(&self, ranges: &[Range<usize>], span: Range<f32>) -> Vec<Vec<(f32, f32, f32, f32)>> {
+        let Some(layout) = self.content_layout() else {
+            return vec![Vec::new(); ranges.len()];
+        };
+        ranges
+            .iter()
+            .map(|range| {
+                let reaches = layout
+                    .range_extent(range.clone())
+                    .is_some_and(|(top, bottom)| bottom > span.start && top < span.end);
+                if reaches {
+                    layout.highlight_rects(range.clone())
+                } else {
+                    Vec::new()
+                }
+            })
+            .collect()
+    }
+
+    /// Draw a frame, updating `scroll` with the laid-out content height.
+    ///
+    /// When the frame asks to reveal a byte offset of the page text, the
+    /// scroll offset that brings it to the top of the viewport is returned.
+    pub fn render(
+        &mut self,
+        page: &PageFrame,
+        address_bar: &AddressBar,
+        theme: &Theme,
+        scroll: &mut ScrollState,
+    ) -> Result<Option<f32>> {
+        let output = self.surface()?.get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        let (revealed, hit_regions) = self.draw(&view, page, address_bar, theme, scroll)?;
+        output.present();
+        self.hit_regions = hit_regions;
+        Ok(revealed)
+    }
+
+    /// Draw the page offscreen and read back its content area, downscaled to
+    /// at most `max_width` pixels wide
+    pub fn capture_thumbnail(
+        &mut self,
+        page: &PageFrame,
+        address_bar: &AddressBar,
+        theme: &Theme,
+        scroll: &ScrollState,
+        max_width: u32,
+    ) -> Result<Thumbnail> {
+        let screenshot = self.capture_viewport(page, address_bar, theme, scroll)?;
+        Ok(Thumbnail::downscale(&screenshot.rgba, screenshot.width, screenshot.height, max_width))
+    }
+
+    /// Draw the page offscreen and read back its content area as scrolled
+    pub fn capture_viewport(
+        &mut self,
+        page: &PageFrame,
+        address_bar: &AddressBar,
+        theme: &Theme,
+        scroll: &ScrollState,
+    ) -> Result<Screenshot> {
+        let chrome = self.chrome_layout();
+        let (width, height) = (self.size.width, chrome.content_height as u32);
+        if width == 0 || height == 0 {
+            anyhow::bail!("Nothing to capture in a {}x{} window", width, height);
+        }
+        // Work on a copy so the window's scroll state is left alone
+        let mut scroll = *scroll;
+        let rgba = self.capture_rows(page, address_bar, theme, &mut scroll, chrome.content_top as u32, height)?;
+        Ok(Screenshot { width, height, rgba })
+    }
+
+    /// Draw the whole page offscreen, at its laid-out height rather than the
+    /// window's. Pages taller than the device's largest texture are drawn a
+    /// texture-full at a time.
+    pub fn capture_full_page(
+        &mut self,
+        page: &PageFrame,
+        address_bar: &AddressBar,
+        theme: &Theme,
+    ) -> Result<Screenshot> {
+        let window = self.size;
+        let chrome = self.chrome_layout();
+        let content_top = chrome.content_top as u32;
+        if window.width == 0 {
+            anyhow::bail!("Nothing to capture in a {}x{} window", window.width, window.height);
+        }
+
+        // A first layout at the window's size finds the page's height
+        let mut scroll = ScrollState::new();
+        self.draw_offscreen(page, address_bar, theme, &mut scroll)?;
+        let total = scroll.content_height().ceil() as u32;
+        check_capture_size(window.width, total)?;
+
+        let max_rows = self
+            .device
+            .limits()
+            .max_texture_dimension_2d
+            .saturating_sub(chrome.content_top.ceil() as u32);
+        let mut rgba = Vec::with_capacity((window.width * total.max(1) * 4) as usize);
+        let result = capture_chunks(total, max_rows).and_then(|chunks| {
+            for (top, rows) in chunks {
+                self.set_capture_size(window.width, chrome.content_top.ceil() as u32 + rows);
+                scroll.set_extent(scroll.content_height(), rows as f32);
+                scroll.set_offset(top as f32);
+                rgba.extend(self.capture_rows(page, address_bar, theme, &mut scroll, content_top, rows)?);
+            }
+            Ok(())
+        });
+        self.set_capture_size(window.width, window.height);
+        result?;
+        let height = (rgba.len() / (window.width as usize * 4)) as u32;
+        Ok(Screenshot {
+            width: window.width,
+            height,
+            rgba,
+        })
+    }
+
+    /// Size drawn to, without touching the window's surface
+    fn set_capture_size(&mut self, width: u32, height: u32) {
+        self.size = winit::dpi::PhysicalSize::new(width, height);
+        self.text_renderer.resize(&self.device, &self.queue, width, height);
+    }
 
+    /// Draw into a throwaway texture, for the layout side effects only
+    fn draw_offscreen(
+        &mut self,
+        page: &PageFrame,
+        address_bar: &AddressBar,
+        theme: &Theme,
+        scroll: &mut ScrollState,
+    ) -> Result<()> {
+        let texture = self.capture_texture();
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.draw(&view, page, address_bar, theme, scroll)?;
+        Ok(())
+    }
+
+    fn capture_texture(&self) -> wgpu::Texture {
+        self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Capture Texture"),
+            size: wgpu::Extent3d {
+                width: self.size.width,
+                height: self.size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    /// Draw a frame offscreen at the current size and read back `height`
+    /// rows from `top` as RGBA
+    fn capture_rows(
+        &mut self,
+        page: &PageFrame,
+        address_bar: &AddressBar,
+        theme: &Theme,
+        scroll: &mut ScrollState,
+        top: u32,
+        height: u32,
+    ) -> Result<Vec<u8>> {
+        let width = self.size.width;
+        let texture = self.capture_texture();
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.draw(&view, page, address_bar, theme, scroll)?;
+
+        // Rows of a texture copy must be padded to the copy alignment
+        let padded_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Capture Buffer"),
+            size: (padded_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
+                label: Some("Capture Encoder"),
             });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: top, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
 
-        // Clear background
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        let bgra = matches!(
+            self.config.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        );
+        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
         {
-            let _render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Background Pass"),
+            let mapped = slice.get_mapped_range();
+            for row in mapped.chunks(padded_row as usize) {
+                rgba.extend_from_slice(&row[..(width * 4) as usize]);
+            }
+        }
+        buffer.unmap();
+        if bgra {
+            for pixel in rgba.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(rgba)
+    }
+
+    /// Draw the tab overview grid with `selected` highlighted
+    pub fn render_overview(&mut self, items: &[OverviewItem], selected: usize, theme: &Theme) -> Result<()> {
+        let output = self.surface()?.get_current_texture()?;
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let (width, height) = (self.size.width as f32, self.size.height as f32);
+        let scale = self.scale_factor as f32;
+
+        let layout = OverviewLayout::compute(items.len(), width, self.scale_factor);
+        let scroll = layout.scroll_for(selected, height);
+        let shift = |rect: Rect| Rect { y: rect.y - scroll, ..rect };
+
+        let mut rects = Vec::new();
+        let mut images = Vec::new();
+        let mut titles = Vec::new();
+        let mut letters = Vec::new();
+        for (index, (item, cell)) in items.iter().zip(&layout.cells).enumerate() {
+            let card = shift(cell.card);
+            let image = shift(cell.image);
+            if card.y + card.height < 0.0 || card.y > height {
+                continue;
+            }
+
+            if index == selected {
+                let border = 3.0 * scale;
+                rects.push(Rect::new(
+                    card.x - border,
+                    card.y - border,
+                    card.width + border * 2.0,
+                    card.height + border * 2.0,
+                    theme.accent.to_linear_rgba(1.0),
+                ));
+            }
+            rects.push(Rect { color: theme.chrome_background.to_linear_rgba(1.0), ..card });
+
+            match item.thumbnail {
+                Some(thumbnail) => images.push(ImageQuad {
+                    rect: fit_image(image, thumbnail.width, thumbnail.height),
+                    image: thumbnail,
+                }),
+                None => {
+                    rects.push(Rect { color: theme.code_background.to_linear_rgba(1.0), ..image });
+                    let size = 48.0 * scale;
+                    let buffer = self.text_renderer.create_buffer(
+                        &item.placeholder.to_string(),
+                        size,
+                        size as u32 * 2,
+                        Some(size as u32 * 2),
+                    );
+                    // Roughly center the glyph in the image area
+                    let left = image.x + image.width / 2.0 - size * 0.3;
+                    let top = image.y + image.height / 2.0 - size * 0.6;
+                    letters.push((buffer, left, top));
+                }
+            }
+
+            let title = shift(cell.title);
+            let padding = 8.0 * scale;
+            let buffer = self.text_renderer.create_buffer(
+                item.title,
+                14.0 * scale,
+                (title.width - padding * 2.0).max(1.0) as u32,
+                Some(title.height as u32),
+            );
+            titles.push((buffer, title.x + padding, title.y + padding / 2.0, title));
+        }
+
+        self.rect_renderer
+            .prepare(&self.device, &rects, self.size.width, self.size.height);
+        self.image_renderer
+            .prepare(&self.device, &self.queue, &images, self.size.width, self.size.height);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Overview Encoder"),
+            });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overview Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: &view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.95,
-                            g: 0.95,
-                            b: 0.95,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(theme.background.to_wgpu()),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -140,78 +720,650 @@ impl Renderer {
                 timestamp_writes: None,
                 occlusion_query_set: None,
             });
+            self.rect_renderer.render(&mut render_pass);
+            self.image_renderer.render(&mut render_pass);
         }
 
+        let bounds = |rect: Rect| TextBounds {
+            left: rect.x as i32,
+            top: rect.y.max(0.0) as i32,
+            right: (rect.x + rect.width) as i32,
+            bottom: (rect.y + rect.height) as i32,
+        };
+        let everywhere = TextBounds {
+            left: 0,
+            top: 0,
+            right: self.size.width as i32,
+            bottom: self.size.height as i32,
+        };
+        let text_areas = titles
+            .iter()
+            .map(|(buffer, left, top, cell)| TextArea {
+                buffer,
+                left: *left,
+                top: *top,
+                scale: 1.0,
+                bounds: bounds(*cell),
+                default_color: theme.text.to_glyphon(),
+                custom_glyphs: &[],
+            })
+            .chain(letters.iter().map(|(buffer, left, top)| TextArea {
+                buffer,
+                left: *left,
+                top: *top,
+                scale: 1.0,
+                bounds: everywhere,
+                default_color: theme.dim_text.to_glyphon(),
+                custom_glyphs: &[],
+            }))
+            .collect();
+        self.text_renderer.render(
+            &self.device,
+            &self.queue,
+            &view,
+            &mut encoder,
+            text_areas,
+            None,
+        )?;
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        output.present();
+        Ok(())
+    }
+
+    /// Free the overview's textures once it closes
+    pub fn release_overview(&mut self) {
+        self.image_renderer.clear();
+    }
+
+    /// Record and submit one frame of the page into `view`
+    fn draw(
+        &mut self,
+        view: &wgpu::TextureView,
+        page: &PageFrame,
+        address_bar: &AddressBar,
+        theme: &Theme,
+        scroll: &mut ScrollState,
+    ) -> Result<(Option<f32>, HitRegionMap)> {
+        let content = page.content;
+        let page_theme = page.page_theme.as_ref().unwrap_or(theme);
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+
+        self.rtl = content.direction.is_rtl();
+        let chrome = self.chrome_layout();
+        let (content_top, viewport_height) = (chrome.content_top, chrome.content_height);
+        // The scrollbar gutter stays reserved so text never reflows when it appears
+        let content_width = chrome.content_width;
+        let padding = chrome.content_padding;
+        let text_left = chrome.content_left + padding;
+        let content_right = chrome.content_left + content_width;
+
         // Create buffers (must live until render call)
-        let address_bar_buffer = address_bar.create_buffer(
-            self.text_renderer.font_system(),
-            self.size.width as f32,
-        );
+        let (font_system, fonts) = self.text_renderer.font_system_and_fonts();
+        let address_bar_buffer = address_bar.create_buffer(font_system, fonts, &chrome);
+        let badge_buffer = address_bar.create_badge_buffer(font_system, fonts, &chrome);
 
-        let content_buffer = if !html_content.is_empty() {
-            Some(self.text_renderer.create_buffer(
-                html_content,
-                14.0,
-                self.size.width,
-                self.size.height - ADDRESS_BAR_HEIGHT as u32
-            ))
+        // Reshape the page only when it or its layout inputs changed
+        if content.text.is_empty() {
+            self.text_renderer.clear_page_layout();
         } else {
-            None
+            let key = PageLayoutKey {
+                generation: page.generation,
+                width: (content_width - padding * 2.0).max(1.0),
+                font_size: page_theme.text_size(self.text_renderer.fonts().default_size),
+                options: page.layout_options,
+                dark_theme: page_theme.is_dark(),
+                link_colors: LinkColors {
+                    link: page_theme.link.to_glyphon(),
+                    visited: page_theme.visited_link.to_glyphon(),
+                },
+                recolored: page.page_theme.is_some(),
+            };
+            self.text_renderer.page_layout(key, content);
+        }
+        // Link labels, measured before the page layout is borrowed for drawing
+        let hint_size = HINT_TEXT_SIZE * self.scale_factor as f32 * self.ui_scale;
+        let hint_buffers: Vec<_> = page
+            .hints
+            .into_iter()
+            .flat_map(|hints| hints.visible())
+            .map(|hint| {
+                let buffer = self.text_renderer.create_buffer(
+                    &hint.label,
+                    hint_size,
+                    (hint_size * hint.label.len() as f32 * 2.0) as u32,
+                    None,
+                );
+                let width = TextRenderer::buffer_width(&buffer);
+                (buffer, width, hint.x, hint.y, hint.link)
+            })
+            .collect();
+        // Hyphens ending the lines in view that broke at soft hyphens
+        let span = self.visible_layout_span(scroll);
+        let soft_hyphens: Vec<_> = self
+            .text_renderer
+            .cached_page_layout()
+            .into_iter()
+            .flat_map(|layout| layout.soft_hyphens())
+            .filter(|hyphen| hyphen.line_top < span.end && hyphen.line_top + hyphen.font_size * 1.2 > span.start)
+            .copied()
+            .collect();
+        let hyphen_buffers: Vec<_> = soft_hyphens
+            .into_iter()
+            .map(|hyphen| {
+                let width = (hyphen.font_size * 2.0) as u32;
+                (self.text_renderer.create_buffer("-", hyphen.font_size, width, None), hyphen)
+            })
+            .collect();
+        let content_buffer = self.text_renderer.cached_page_layout();
+
+        let text_height = content_buffer
+            .as_ref()
+            .map(|layout| layout.height())
+            .unwrap_or(0.0);
+        let revealed = page
+            .reveal
+            .and_then(|offset| content_buffer.as_ref()?.offset_top(offset));
+        scroll.set_extent(text_height + padding * 2.0, viewport_height);
+
+        // Chrome background, selection and scrollbar rectangles
+        let mut rects = vec![Rect::new(
+            0.0,
+            0.0,
+            chrome.width,
+            chrome.address_bar_height,
+            address_bar.background_color(theme).to_linear_rgba(1.0),
+        )];
+
+        if badge_buffer.is_some() {
+            rects.push(Rect::new(
+                chrome.badge_left,
+                chrome.badge_top,
+                chrome.badge_width,
+                chrome.badge_height,
+                theme.accent.to_linear_rgba(0.25),
+            ));
+        }
+
+        let origin_y = content_top + padding - scroll.offset();
+        let bottom = content_top + viewport_height;
+        let is_visible = |(top, end): (f32, f32)| origin_y + end > content_top && origin_y + top < bottom;
+        // A glyph rectangle of the layout in the window, clipped to the content area
+        let clip_to_content = |(x, y, width, height): (f32, f32, f32, f32)| {
+            let top = (origin_y + y).max(content_top);
+            let end = (origin_y + y + height).min(bottom);
+            let left = text_left + x;
+            let right = (left + width).min(content_right);
+            (end > top && right > left).then_some((left, top, right - left, end - top))
         };
 
-        // Build text areas
-        let mut text_areas = Vec::new();
+        // Everything the pointer can hit, from the bottom up
+        let mut hit_regions = HitRegionMap::new();
+        hit_regions.register_chrome(&chrome, badge_buffer.is_some());
+
+        // Preformatted block backgrounds and table row separators, spanning
+        // the text column
+        if let Some(layout) = content_buffer.as_ref() {
+            for block in &content.blocks {
+                let Some((y_top, y_bottom)) = layout
+                    .range_extent(block.range.clone())
+                    .filter(|extent| is_visible(*extent))
+                else {
+                    continue;
+                };
+                let (left, right, y_top, y_bottom, color) = match block.kind {
+                    BlockKind::Preformatted => (
+                        text_left - 6.0,
+                        content_right - padding + 6.0,
+                        y_top - 4.0,
+                        y_bottom + 4.0,
+                        page_theme.code_background.to_linear_rgba(1.0),
+                    ),
+                    BlockKind::TableRow => (
+                        text_left,
+                        content_right - padding,
+                        y_bottom,
+                        y_bottom + (self.scale_factor as f32).max(1.0),
+                        page_theme.dim_text.to_linear_rgba(0.3),
+                    ),
+                    BlockKind::Heading(_) => continue,
+                };
+                let top = (origin_y + y_top).max(content_top);
+                let end = (origin_y + y_bottom).min(bottom);
+                if end > top && right > left {
+                    rects.push(Rect::new(left, top, right - left, end - top, color));
+                }
+            }
+        }
+
+        // Page background colors and selection highlights, clipped to the
+        // content area
+        let mut highlights = Vec::new();
+        if let Some(layout) = content_buffer.as_ref() {
+            for span in &content.styles {
+                if let Some(background) = span.style.background {
+                    let color = Color::rgb(background.r, background.g, background.b);
+                    highlights.push((span.range.clone(), color.to_linear_rgba(1.0)));
+                }
+            }
+            if let Some(range) = page.selection.clone() {
+                highlights.push((range, page_theme.selection.to_linear_rgba(1.0)));
+            }
+            for (range, color) in highlights {
+                if !layout.range_extent(range.clone()).is_some_and(is_visible) {
+                    continue;
+                }
+                let clipped = layout.highlight_rects(range).into_iter().filter_map(clip_to_content);
+                for (left, top, width, height) in clipped {
+                    rects.push(Rect::new(left, top, width, height, color));
+                }
+            }
+            for (index, link) in content.links.iter().enumerate() {
+                if !layout.range_extent(link.range.clone()).is_some_and(is_visible) {
+                    continue;
+                }
+                let clipped = layout.highlight_rects(link.range.clone()).into_iter().filter_map(clip_to_content);
+                for (left, top, width, height) in clipped {
+                    hit_regions.register(HitRegionKind::Link(index), left, top, width, height);
+                }
+            }
+        }
+
+        // The caret, a thin bar in the text color
+        let caret = page
+            .caret
+            .zip(content_buffer)
+            .and_then(|(offset, layout)| layout.caret_position(offset));
+        if let Some((x, y, height)) = caret {
+            let top = origin_y + y;
+            if top >= content_top && top + height <= bottom {
+                let width = (1.5 * self.scale_factor as f32).max(1.0);
+                rects.push(Rect::new(text_left + x, top, width, height, page_theme.text.to_linear_rgba(1.0)));
+            }
+        }
+
+        // Link labels sit on the top left of their links
+        let hint_padding = 3.0 * self.scale_factor as f32;
+        let mut hint_boxes = Vec::new();
+        for (buffer, width, x, y, link) in &hint_buffers {
+            let label = Rect::new(
+                text_left + x,
+                origin_y + y,
+                width + hint_padding * 2.0,
+                buffer.metrics().line_height,
+                theme.accent.to_linear_rgba(1.0),
+            );
+            if label.y >= content_top && label.y + label.height <= bottom {
+                hit_regions.register(HitRegionKind::Hint(*link), label.x, label.y, label.width, label.height);
+                rects.push(label);
+                hint_boxes.push((buffer, label, hint_padding));
+            }
+        }
+
+        // The scrollbar takes clicks while faded out too
+        let scrollbar = self.scrollbar_geometry(scroll);
+        if let Some(geometry) = &scrollbar {
+            hit_regions.register_scrollbar(geometry);
+        }
+        let scrollbar_opacity = page.scrollbar_opacity;
+        if scrollbar_opacity > 0.0 {
+            if let Some(geometry) = scrollbar {
+                rects.push(Rect::new(
+                    geometry.track_x,
+                    geometry.track_top,
+                    geometry.track_width,
+                    geometry.track_height,
+                    page_theme.dim_text.to_linear_rgba(0.15 * scrollbar_opacity),
+                ));
+                rects.push(Rect::new(
+                    geometry.track_x + 2.0,
+                    geometry.thumb_top,
+                    geometry.track_width - 4.0,
+                    geometry.thumb_height,
+                    page_theme.dim_text.to_linear_rgba(0.7 * scrollbar_opacity),
+                ));
+            }
+        }
+        self.rect_renderer
+            .prepare(&self.device, &rects, self.size.width, self.size.height);
+
+        // Clear background and draw rectangles
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Background Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(page_theme.background.to_wgpu()),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.rect_renderer.render(&mut render_pass);
+        }
 
-        // Address bar
-        text_areas.push(TextArea {
+        // Address bar, and the blocked-requests badge inside it
+        let mut text_areas = vec![TextArea {
             buffer: &address_bar_buffer,
-            left: 10.0,
-            top: 10.0,
+            left: chrome.address_text_left,
+            top: chrome.address_text_top,
             scale: 1.0,
             bounds: TextBounds {
                 left: 0,
                 top: 0,
                 right: self.size.width as i32,
-                bottom: ADDRESS_BAR_HEIGHT as i32,
+                bottom: chrome.address_bar_height as i32,
             },
-            default_color: address_bar.text_color(),
+            default_color: address_bar.text_color(theme),
             custom_glyphs: &[],
-        });
-
-        // Page content
-        if let Some(ref buffer) = content_buffer {
+        }];
+        if let Some(buffer) = &badge_buffer {
+            // Centered vertically by the line height, which is the buffer's only line
+            let line_height = buffer.metrics().line_height;
             text_areas.push(TextArea {
                 buffer,
-                left: 20.0,
-                top: ADDRESS_BAR_HEIGHT + 20.0,
+                left: chrome.badge_left,
+                top: chrome.badge_top + (chrome.badge_height - line_height) / 2.0,
                 scale: 1.0,
                 bounds: TextBounds {
-                    left: 0,
-                    top: ADDRESS_BAR_HEIGHT as i32,
-                    right: self.size.width as i32,
-                    bottom: self.size.height as i32,
+                    left: chrome.badge_left as i32,
+                    top: chrome.badge_top as i32,
+                    right: (chrome.badge_left + chrome.badge_width) as i32,
+                    bottom: (chrome.badge_top + chrome.badge_height) as i32,
                 },
-                default_color: GlyphonColor::rgb(0, 0, 0),
+                default_color: theme.text.to_glyphon(),
                 custom_glyphs: &[],
             });
         }
 
+        text_areas.extend(hint_boxes.into_iter().map(|(buffer, label, hint_padding)| TextArea {
+            buffer,
+            left: label.x + hint_padding,
+            top: label.y,
+            scale: 1.0,
+            bounds: TextBounds {
+                left: label.x as i32,
+                top: label.y as i32,
+                right: (label.x + label.width) as i32,
+                bottom: (label.y + label.height) as i32,
+            },
+            default_color: theme.background.to_glyphon(),
+            custom_glyphs: &[],
+        }));
+
+        // Page content, clipped to the area beside the scrollbar
+        let page_bounds = TextBounds {
+            left: chrome.content_left as i32,
+            top: content_top as i32,
+            right: content_right as i32,
+            bottom: self.size.height as i32,
+        };
+        text_areas.extend(hyphen_buffers.iter().map(|(buffer, hyphen)| {
+            let width = if hyphen.rtl { TextRenderer::buffer_width(buffer) } else { 0.0 };
+            TextArea {
+                buffer,
+                left: text_left + hyphen.x - width,
+                top: origin_y + hyphen.line_top,
+                scale: 1.0,
+                bounds: page_bounds,
+                default_color: hyphen.color.unwrap_or(page_theme.text.to_glyphon()),
+                custom_glyphs: &[],
+            }
+        }));
+        let page_area = PageArea {
+            left: text_left,
+            top: content_top + padding - scroll.offset(),
+            bounds: page_bounds,
+            default_color: page_theme.text.to_glyphon(),
+        };
+
         // Render all text
         self.text_renderer.render(
             &self.device,
             &self.queue,
-            &view,
+            view,
             &mut encoder,
             text_areas,
+            Some(page_area),
         )?;
 
+        for (index, toast) in page.toasts.iter().enumerate() {
+            hit_regions.register(HitRegionKind::Toast(index), toast.x, toast.y, toast.width, toast.height);
+        }
+        if let Some(palette) = page.palette {
+            hit_regions.register(HitRegionKind::Palette, palette.x, palette.y, palette.width, palette.height());
+        }
+
+        let hit_overlay = page.hit_overlay.map(|overlay| (&hit_regions, overlay));
+        if !page.toasts.is_empty() || page.palette.is_some() || hit_overlay.is_some() {
+            self.draw_overlays(view, &mut encoder, page.toasts, page.palette, hit_overlay, theme)?;
+        }
+
         self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
 
-        Ok(())
+        Ok((revealed, hit_regions))
+    }
+
+    /// Draw toasts, the command palette and the hit region overlay over the
+    /// page, in one pass as overlay text is prepared once a frame. Toasts
+    /// are rounded cards edged in the color of their severity, each with its
+    /// message; the palette a card of entries with the selected one
+    /// highlighted. Hit regions are tinted by kind and labelled with their
+    /// ids, with what the cursor hits read out in the bottom-left corner.
+    fn draw_overlays(
+        &mut self,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        toasts: &[PlacedToast],
+        palette: Option<&PlacedPalette>,
+        hit_overlay: Option<(&HitRegionMap, HitOverlay)>,
+        theme: &Theme,
+    ) -> Result<()> {
+        let scale = self.scale_factor as f32 * self.ui_scale;
+        let radius = TOAST_RADIUS * scale;
+        let edge = 4.0 * scale;
+        let padding = 10.0 * scale;
+        let font_size = TOAST_TEXT_SIZE * scale;
+
+        let mut rects = Vec::new();
+        // Each buffer with its left edge, the card it is centered on and its color
+        let mut buffers = Vec::new();
+        for toast in toasts {
+            let card = Rect::new(toast.x, toast.y, toast.width, toast.height, [0.0; 4]).with_radius(radius);
+            let color = severity_color(toast.notification.severity, theme);
+            rects.push(Rect { color: color.to_linear_rgba(1.0), ..card });
+            rects.push(Rect {
+                x: card.x + edge,
+                width: card.width - edge,
+                color: theme.chrome_background.to_linear_rgba(1.0),
+                ..card
+            });
+            let text_width = (toast.width - edge - padding * 2.0).max(1.0);
+            let buffer = self.text_renderer.create_buffer(
+                &toast.text(),
+                font_size,
+                text_width as u32,
+                Some((toast.height - padding).max(1.0) as u32),
+            );
+            buffers.push((buffer, card.x + edge + padding, card, theme.text));
+        }
+        if let Some(palette) = palette {
+            let font_size = PALETTE_TEXT_SIZE * scale;
+            let card = Rect::new(palette.x, palette.y, palette.width, palette.height(), [0.0; 4])
+                .with_radius(PALETTE_RADIUS * scale);
+            rects.push(Rect { color: theme.accent.to_linear_rgba(1.0), ..card });
+            rects.push(Rect {
+                x: card.x + 1.0,
+                y: card.y + 1.0,
+                width: card.width - 2.0,
+                height: card.height - 2.0,
+                color: theme.chrome_background.to_linear_rgba(1.0),
+                ..card
+            });
+            // Labels on the left half of each row, details on the right
+            let text_width = ((palette.width - padding * 3.0) / 2.0).max(1.0);
+            let (width, height) = (text_width as u32, Some(palette.row_height as u32));
+            if palette.rows.is_empty() {
+                let row = Rect { height: palette.row_height, ..card };
+                let buffer = self.text_renderer.create_buffer("No matches", font_size, width, height);
+                buffers.push((buffer, row.x + padding, row, theme.dim_text));
+            }
+            for (index, entry) in palette.rows.iter().enumerate() {
+                let row = Rect {
+                    y: card.y + palette.row_height * index as f32,
+                    height: palette.row_height,
+                    ..card
+                };
+                if index == palette.selected {
+                    rects.push(Rect { color: theme.selection.to_linear_rgba(1.0), ..row });
+                }
+                let label = self.text_renderer.create_buffer(&entry.label, font_size, width, height);
+                buffers.push((label, row.x + padding, row, theme.text));
+                let detail = self.text_renderer.create_buffer(&entry.detail, font_size, width, height);
+                buffers.push((detail, row.x + padding * 2.0 + text_width, row, theme.dim_text));
+            }
+        }
+        if let Some((regions, overlay)) = hit_overlay {
+            let font_size = HIT_LABEL_TEXT_SIZE * scale;
+            let hit = overlay.cursor.and_then(|(x, y)| regions.resolve(x, y)).map(|region| region.id);
+            for region in regions.regions() {
+                let alpha = if Some(region.id) == hit { 0.5 } else { 0.2 };
+                let color = region.kind.overlay_color().to_linear_rgba(alpha);
+                rects.push(Rect::new(region.x, region.y, region.width, region.height, color));
+                // Cards hug their labels, which may spill out of small regions
+                let label = self.text_renderer.create_buffer(
+                    &region.id.to_string(),
+                    font_size,
+                    (font_size * 8.0) as u32,
+                    None,
+                );
+                let (width, height) = (TextRenderer::buffer_width(&label), label.metrics().line_height);
+                let card = Rect::new(region.x, region.y, width + 2.0 + padding, height, [0.0; 4]);
+                buffers.push((label, region.x + 2.0, card, theme.text));
+            }
+
+            let readout = self.text_renderer.create_buffer(
+                &overlay.readout(regions),
+                TOAST_TEXT_SIZE * scale,
+                self.size.width,
+                None,
+            );
+            let (width, height) = (TextRenderer::buffer_width(&readout), readout.metrics().line_height);
+            let chrome = self.chrome_layout();
+            let card = Rect::new(
+                chrome.content_left + padding,
+                chrome.height - padding - height - edge * 2.0,
+                width + padding * 2.0,
+                height + edge * 2.0,
+                theme.chrome_background.to_linear_rgba(0.9),
+            )
+            .with_radius(radius);
+            rects.push(card);
+            buffers.push((readout, card.x + padding, card, theme.text));
+        }
+        self.overlay_rect_renderer
+            .prepare(&self.device, &rects, self.size.width, self.size.height);
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.overlay_rect_renderer.render(&mut render_pass);
+        }
+
+        let text_areas = buffers
+            .iter()
+            .map(|(buffer, left, card, color)| {
+                // Centered vertically on the lines the text takes
+                let height = TextRenderer::buffer_height(buffer);
+                TextArea {
+                    buffer,
+                    left: *left,
+                    top: card.y + (card.height - height) / 2.0,
+                    scale: 1.0,
+                    bounds: TextBounds {
+                        left: card.x as i32,
+                        top: card.y as i32,
+                        right: (card.x + card.width - padding) as i32,
+                        bottom: (card.y + card.height) as i32,
+                    },
+                    default_color: color.to_glyphon(),
+                    custom_glyphs: &[],
+                }
+            })
+            .collect();
+        self.text_renderer
+            .render_overlay(&self.device, &self.queue, view, encoder, text_areas)
     }
 
     pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
         self.size
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::screenshot::tests::decode_png;
+
+    #[test]
+    fn test_capture_round_trips_through_png() {
+        let mut renderer = pollster::block_on(Renderer::headless(320, 240, FontSettings::default(), true))
+            .expect("the fallback adapter is needed to test captures");
+        let theme = Theme::light();
+        let lines: Vec<String> = (1..=60).map(|line| format!("Line {}", line)).collect();
+        let content = PageContent::from_text(lines.join("\n"));
+        let frame = PageFrame {
+            content: &content,
+            generation: 1,
+            layout_options: ContentLayoutOptions::default(),
+            scrollbar_opacity: 0.0,
+            reveal: None,
+            selection: None,
+            hints: None,
+            caret: None,
+            toasts: &[],
+            palette: None,
+            page_theme: None,
+            hit_overlay: None,
+        };
+        let address_bar = AddressBar::new();
+
+        let viewport = renderer.capture_viewport(&frame, &address_bar, &theme, &ScrollState::new()).unwrap();
+        let decoded = decode_png(&viewport.to_png());
+        assert_eq!(decoded, viewport);
+        assert_eq!(decoded.width, 320);
+        assert_eq!(decoded.height, renderer.chrome_layout().content_height as u32);
+        // The right edge is past the text, showing the page background
+        let background = theme.background;
+        let pixel = decoded.pixel(decoded.width - 1, decoded.height - 1).unwrap();
+        assert_eq!(pixel, [background.r, background.g, background.b, 255]);
+        // Some of the first lines' text is darker than the background
+        assert!(decoded.rgba.chunks_exact(4).any(|pixel| pixel[0] < background.r / 2));
+
+        let full = renderer.capture_full_page(&frame, &address_bar, &theme).unwrap();
+        assert!(full.height > decoded.height * 2, "{} rows", full.height);
+        assert_eq!(decode_png(&full.to_png()), full);
+        assert_eq!(full.rgba[..320 * 4 * 20], decoded.rgba[..320 * 4 * 20]);
+        // The window's size is restored
+        assert_eq!(renderer.size(), winit::dpi::PhysicalSize::new(320, 240));
+    }
+}
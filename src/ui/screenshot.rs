@@ -0,0 +1,372 @@
+use super::fonts::FontSettings;
+use super::renderer::{PageFrame, Renderer};
+use super::scroll::ScrollState;
+use super::theme::Theme;
+use super::AddressBar;
+use crate::domain::RenderError;
+use crate::infrastructure::{PageCapture, PageContent};
+use anyhow::Result;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+
+/// Most pixels a capture may hold, about 256MB of RGBA; taller pages are refused
+pub const MAX_CAPTURE_PIXELS: u64 = 64 * 1024 * 1024;
+/// Size of the viewport [`OffscreenCapture`] draws pages in
+pub const CAPTURE_VIEWPORT: (u32, u32) = (1280, 800);
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// An RGBA image of a page read back from the renderer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed sRGB RGBA rows
+    pub rgba: Vec<u8>,
+}
+
+impl Screenshot {
+    /// RGBA of the pixel at `x`, `y`
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = ((y * self.width + x) * 4) as usize;
+        self.rgba.get(i..i + 4)?.try_into().ok()
+    }
+
+    /// Encode as a PNG, with unfiltered rows
+    pub fn to_png(&self) -> Vec<u8> {
+        let row = self.width as usize * 4;
+        let mut raw = Vec::with_capacity((row + 1) * self.height as usize);
+        for line in self.rgba.chunks_exact(row.max(1)).take(self.height as usize) {
+            // Filter type None
+            raw.push(0);
+            raw.extend_from_slice(line);
+        }
+        let mut encoder = ZlibEncoder::new(Vec::with_capacity(raw.len() / 4), Compression::fast());
+        // Writing to memory cannot fail
+        let zlib = encoder.write_all(&raw).and_then(|_| encoder.finish()).unwrap_or_default();
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        // 8 bits per channel, RGBA, default compression, filtering and no interlace
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let mut png = Vec::with_capacity(zlib.len() + 64);
+        png.extend_from_slice(&PNG_SIGNATURE);
+        write_chunk(&mut png, b"IHDR", &header);
+        write_chunk(&mut png, b"IDAT", &zlib);
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    /// Decode a PNG with 8-bit RGB or RGBA pixels and no interlacing, as
+    /// the window icon is stored
+    pub fn from_png(png: &[u8]) -> Result<Screenshot> {
+        if png.get(..8) != Some(&PNG_SIGNATURE[..]) {
+            anyhow::bail!("Not a PNG image");
+        }
+        let (mut rest, mut header, mut zlib) = (&png[8..], None, Vec::new());
+        while rest.len() >= 12 {
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let Some(data) = rest.get(8..8 + length).filter(|_| rest.len() >= 12 + length) else {
+                anyhow::bail!("Truncated PNG chunk");
+            };
+            match &rest[4..8] {
+                b"IHDR" => header = Some(data),
+                b"IDAT" => zlib.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {}
+            }
+            rest = &rest[12 + length..];
+        }
+        let Some(&[w0, w1, w2, w3, h0, h1, h2, h3, depth, color, _, _, interlace]) = header else {
+            anyhow::bail!("PNG has no image header");
+        };
+        let (width, height) = (u32::from_be_bytes([w0, w1, w2, w3]), u32::from_be_bytes([h0, h1, h2, h3]));
+        let channels = match (depth, color, interlace) {
+            (8, 2, 0) => 3,
+            (8, 6, 0) => 4,
+            _ => anyhow::bail!("Unsupported PNG format (depth {}, color type {})", depth, color),
+        };
+        check_capture_size(width, height)?;
+
+        let row = width as usize * channels;
+        let mut raw = Vec::with_capacity((row + 1) * height as usize);
+        // No more than the image holds, however far the data would inflate
+        flate2::read::ZlibDecoder::new(&zlib[..])
+            .take((row + 1) as u64 * height as u64)
+            .read_to_end(&mut raw)?;
+        if raw.len() < (row + 1) * height as usize {
+            anyhow::bail!("PNG image data is truncated");
+        }
+        let mut pixels = vec![0u8; row * height as usize];
+        for (y, line) in raw.chunks_exact(row + 1).take(height as usize).enumerate() {
+            let (done, current) = pixels.split_at_mut(y * row);
+            let previous = done.get(done.len().saturating_sub(row)..).filter(|_| y > 0);
+            unfilter(line[0], &line[1..], previous, &mut current[..row], channels)?;
+        }
+        let rgba = match channels {
+            4 => pixels,
+            _ => pixels.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+        };
+        Ok(Screenshot { width, height, rgba })
+    }
+}
+
+/// Paints tabs' pages for `GET /tabs/{id}/screenshot`, which has no window
+/// to read back, on a windowless renderer made at the first capture
+pub struct OffscreenCapture {
+    fonts: FontSettings,
+    theme: Theme,
+    fallback_adapter: bool,
+    /// The renderer, and the generation of the last page it drew
+    renderer: Mutex<Option<(Renderer, u64)>>,
+}
+
+impl OffscreenCapture {
+    pub fn new(fonts: FontSettings, theme: Theme) -> Self {
+        Self {
+            fonts,
+            theme,
+            fallback_adapter: false,
+            renderer: Mutex::new(None),
+        }
+    }
+
+    /// Draw with the software adapter, for machines without a GPU
+    pub fn with_fallback_adapter(mut self) -> Self {
+        self.fallback_adapter = true;
+        self
+    }
+
+    fn draw(&self, content: &PageContent) -> Result<Screenshot> {
+        let mut kept = self
+            .renderer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("An earlier capture panicked"))?;
+        let (mut renderer, generation) = match kept.take() {
+            Some(kept) => kept,
+            None => {
+                let (width, height) = CAPTURE_VIEWPORT;
+                let headless = Renderer::headless(width, height, self.fonts.clone(), self.fallback_adapter);
+                (pollster::block_on(headless)?, 0)
+            }
+        };
+        // Every capture is of a page the renderer has not laid out
+        let generation = generation + 1;
+        let frame = PageFrame {
+            content,
+            generation,
+            layout_options: Default::default(),
+            scrollbar_opacity: 0.0,
+            reveal: None,
+            selection: None,
+            hints: None,
+            caret: None,
+            toasts: &[],
+            palette: None,
+            page_theme: None,
+            hit_overlay: None,
+        };
+        let captured = renderer.capture_viewport(&frame, &AddressBar::new(), &self.theme, &ScrollState::new());
+        *kept = Some((renderer, generation));
+        captured
+    }
+}
+
+impl PageCapture for OffscreenCapture {
+    fn capture(&self, content: &PageContent) -> std::result::Result<Vec<u8>, RenderError> {
+        self.draw(content)
+            .map(|screenshot| screenshot.to_png())
+            .map_err(|e| RenderError::Capture(format!("{:#}", e)))
+    }
+}
+
+/// Undo one of the five PNG row filters, predicting each byte from the one
+/// a pixel to the left, the one above and the one above that
+fn unfilter(filter: u8, line: &[u8], previous: Option<&[u8]>, out: &mut [u8], channels: usize) -> Result<()> {
+    for i in 0..line.len() {
+        let left = if i >= channels { out[i - channels] } else { 0 };
+        let up = previous.map_or(0, |previous| previous[i]);
+        let up_left = if i >= channels { previous.map_or(0, |previous| previous[i - channels]) } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => anyhow::bail!("Unknown PNG filter {}", filter),
+        };
+        out[i] = line[i].wrapping_add(predicted);
+    }
+    Ok(())
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = (
+        (estimate - left as i16).abs(),
+        (estimate - up as i16).abs(),
+        (estimate - up_left as i16).abs(),
+    );
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Split `content_height` rows into captures of at most `max_rows` each,
+/// as `(first row, rows)`
+pub fn capture_chunks(content_height: u32, max_rows: u32) -> Result<Vec<(u32, u32)>> {
+    if max_rows == 0 {
+        anyhow::bail!("The window leaves no room to capture the page");
+    }
+    let mut chunks = Vec::new();
+    let mut top = 0;
+    while top < content_height.max(1) {
+        let rows = max_rows.min(content_height.max(1) - top);
+        chunks.push((top, rows));
+        top += rows;
+    }
+    Ok(chunks)
+}
+
+/// Refuse captures too large to hold in memory
+pub fn check_capture_size(width: u32, height: u32) -> Result<()> {
+    if width as u64 * height as u64 > MAX_CAPTURE_PIXELS {
+        anyhow::bail!("The page is too large to capture ({}x{} pixels)", width, height);
+    }
+    Ok(())
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Read back a PNG written by `to_png`, checking every chunk's CRC:
+    /// unfiltered RGBA rows only
+    pub(crate) fn decode_png(png: &[u8]) -> Screenshot {
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        let (mut rest, mut header, mut zlib) = (&png[8..], Vec::new(), Vec::new());
+        while rest.len() >= 12 {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind, data) = (&rest[4..8], &rest[8..8 + length]);
+            let crc = u32::from_be_bytes(rest[8 + length..12 + length].try_into().unwrap());
+            assert_eq!(crc, crc32(&rest[4..8 + length]));
+            match kind {
+                b"IHDR" => header = data.to_vec(),
+                b"IDAT" => zlib.extend_from_slice(data),
+                _ => {}
+            }
+            rest = &rest[12 + length..];
+        }
+        let width = u32::from_be_bytes(header[..4].try_into().unwrap());
+        let height = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        assert_eq!(header[8..], [8, 6, 0, 0, 0]);
+
+        let mut raw = Vec::new();
+        flate2::read::ZlibDecoder::new(&zlib[..]).read_to_end(&mut raw).unwrap();
+        let rgba = raw
+            .chunks_exact(width as usize * 4 + 1)
+            .flat_map(|row| {
+                assert_eq!(row[0], 0);
+                row[1..].to_vec()
+            })
+            .collect();
+        Screenshot { width, height, rgba }
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let (width, height) = (97, 400);
+        let rgba: Vec<u8> = (0..width * height * 4).map(|i| (i * 7 % 251) as u8).collect();
+        let screenshot = Screenshot { width, height, rgba };
+        let png = screenshot.to_png();
+        assert_eq!(decode_png(&png), screenshot);
+        assert_eq!(screenshot.pixel(1, 0), Some([28, 35, 42, 49]));
+        assert_eq!(screenshot.pixel(width, 0), None);
+
+        assert_eq!(Screenshot::from_png(&png).unwrap(), screenshot);
+        // Compressed, well below the raw pixels' size
+        assert!(png.len() < screenshot.rgba.len() / 2, "{} bytes", png.len());
+
+        // Known checksum
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn test_filtered_pngs_are_decoded() {
+        let data = |name: &str| {
+            std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/png").join(name))
+                .unwrap()
+        };
+        // A row for each filter, then RGB rows filtered the other way round
+        let rgba = Screenshot::from_png(&data("filters.png")).unwrap();
+        assert_eq!((rgba.width, rgba.height), (7, 5));
+        assert_eq!(rgba.rgba, data("filters.rgba"));
+        let rgb = Screenshot::from_png(&data("rgb.png")).unwrap();
+        assert_eq!(rgb.rgba, data("rgb.rgba"));
+
+        let png = data("filters.png");
+        assert!(Screenshot::from_png(&png[..png.len() / 2]).is_err());
+
+        // Image data inflating past the header's size is cut off there
+        let image = Screenshot {
+            width: 2,
+            height: 2,
+            rgba: vec![7; 16],
+        };
+        let mut bomb = ZlibEncoder::new(Vec::new(), Compression::best());
+        bomb.write_all(&vec![0; 64 * 1024 * 1024]).unwrap();
+        let bomb = bomb.finish().unwrap();
+        let mut png = image.to_png();
+        let idat = png.windows(4).position(|kind| kind == b"IDAT").unwrap() - 4;
+        let mut chunk = Vec::new();
+        write_chunk(&mut chunk, b"IDAT", &bomb);
+        let length = u32::from_be_bytes(png[idat..idat + 4].try_into().unwrap()) as usize;
+        png.splice(idat..idat + 12 + length, chunk);
+        let decoded = Screenshot::from_png(&png).unwrap();
+        assert_eq!((decoded.width, decoded.height), (2, 2));
+        assert_eq!(decoded.rgba, [0; 16]);
+        assert!(Screenshot::from_png(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn test_tall_pages_are_captured_in_chunks() {
+        assert_eq!(capture_chunks(500, 8192).unwrap(), vec![(0, 500)]);
+        assert_eq!(capture_chunks(20000, 8152).unwrap(), vec![(0, 8152), (8152, 8152), (16304, 3696)]);
+        // An empty page still captures its blank viewport row
+        assert_eq!(capture_chunks(0, 100).unwrap(), vec![(0, 1)]);
+        assert!(capture_chunks(100, 0).is_err());
+        assert!(check_capture_size(1280, 50_000).is_ok());
+        assert!(check_capture_size(4000, 20_000).is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use std::time::{Duration, Instant};
+
+/// Settings key that disables scroll animations
+pub const REDUCED_MOTION_SETTING: &str = "ui.reduced_motion";
+
+/// Time constant of the exponential ease toward the target offset
+const EASE_TIME_CONSTANT: Duration = Duration::from_millis(70);
+/// Remaining distance (in pixels) at which the animation snaps to the target
+const SNAP_DISTANCE: f32 = 0.5;
+/// Frame time assumed for the first step of an animation
+const FIRST_FRAME: Duration = Duration::from_millis(16);
+/// Longest step applied at once, so a stalled frame doesn't jump
+const MAX_FRAME: Duration = Duration::from_millis(100);
+
+/// Parse the reduced-motion setting, defaulting to animations on
+pub fn parse_reduced_motion(settings: &[(String, String)]) -> bool {
+    settings
+        .iter()
+        .find(|(key, _)| key == REDUCED_MOTION_SETTING)
+        .is_some_and(|(_, value)| matches!(value.trim(), "true" | "1" | "yes"))
+}
+
+/// Vertical scroll position of a page, in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ScrollState {
+    offset: f32,
+    /// Where an in-progress animation is heading; equals `offset` when idle
+    target: f32,
+    content_height: f32,
+    viewport_height: f32,
+    last_tick: Option<Instant>,
+}
+
+impl ScrollState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn offset(&self) -> f32 {
+        self.offset
+    }
+
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    pub fn content_height(&self) -> f32 {
+        self.content_height
+    }
+
+    pub fn viewport_height(&self) -> f32 {
+        self.viewport_height
+    }
+
+    pub fn max_offset(&self) -> f32 {
+        (self.content_height - self.viewport_height).max(0.0)
+    }
+
+    /// Whether the content is taller than the viewport
+    pub fn is_scrollable(&self) -> bool {
+        self.max_offset() > 0.0
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.offset != self.target
+    }
+
+    /// Update the laid-out content and viewport heights, keeping the offset in range
+    pub fn set_extent(&mut self, content_height: f32, viewport_height: f32) {
+        self.content_height = content_height.max(0.0);
+        self.viewport_height = viewport_height.max(0.0);
+        self.offset = self.offset.clamp(0.0, self.max_offset());
+        self.target = self.target.clamp(0.0, self.max_offset());
+    }
+
+    /// Jump to an offset, cancelling any animation; returns whether it changed
+    pub fn set_offset(&mut self, offset: f32) -> bool {
+        let clamped = offset.clamp(0.0, self.max_offset());
+        let changed = clamped != self.offset;
+        self.offset = clamped;
+        self.target = clamped;
+        self.last_tick = None;
+        changed
+    }
+
+    pub fn scroll_by(&mut self, delta: f32) -> bool {
+        self.set_offset(self.target + delta)
+    }
+
+    /// Start animating toward an offset; returns whether the target changed
+    pub fn smooth_scroll_to(&mut self, offset: f32) -> bool {
+        let clamped = offset.clamp(0.0, self.max_offset());
+        let changed = clamped != self.target;
+        self.target = clamped;
+        changed
+    }
+
+    /// Animate by a delta, accumulating onto any animation in progress
+    pub fn smooth_scroll_by(&mut self, delta: f32) -> bool {
+        self.smooth_scroll_to(self.target + delta)
+    }
+
+    /// Distance scrolled by one page, keeping a little overlap for context
+    pub fn page_step(&self) -> f32 {
+        (self.viewport_height * 0.9).max(1.0)
+    }
+
+    /// Back to the top, e.g. after navigating
+    pub fn reset(&mut self) {
+        self.offset = 0.0;
+        self.target = 0.0;
+        self.last_tick = None;
+    }
+
+    /// Advance the animation to `now`; returns whether it is still running
+    pub fn tick(&mut self, now: Instant) -> bool {
+        if !self.is_animating() {
+            self.last_tick = None;
+            return false;
+        }
+
+        let dt = self
+            .last_tick
+            .map(|last| now.saturating_duration_since(last))
+            .unwrap_or(FIRST_FRAME)
+            .min(MAX_FRAME);
+        self.last_tick = Some(now);
+
+        let remaining = self.target - self.offset;
+        let progress = 1.0 - (-dt.as_secs_f32() / EASE_TIME_CONSTANT.as_secs_f32()).exp();
+        self.offset += remaining * progress;
+
+        if (self.target - self.offset).abs() < SNAP_DISTANCE {
+            self.offset = self.target;
+            self.last_tick = None;
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_clamped_to_content() {
+        let mut scroll = ScrollState::new();
+        scroll.set_extent(1000.0, 400.0);
+
+        assert!(scroll.scroll_by(250.0));
+        assert_eq!(scroll.offset(), 250.0);
+        scroll.scroll_by(10_000.0);
+        assert_eq!(scroll.offset(), 600.0);
+        assert!(!scroll.scroll_by(1.0));
+        scroll.scroll_by(-10_000.0);
+        assert_eq!(scroll.offset(), 0.0);
+    }
+
+    #[test]
+    fn test_shrinking_content_reclamps() {
+        let mut scroll = ScrollState::new();
+        scroll.set_extent(1000.0, 400.0);
+        scroll.set_offset(600.0);
+
+        scroll.set_extent(300.0, 400.0);
+        assert_eq!(scroll.offset(), 0.0);
+        assert!(!scroll.is_scrollable());
+    }
+
+    #[test]
+    fn test_smooth_scroll_converges_to_target() {
+        let mut scroll = ScrollState::new();
+        scroll.set_extent(2000.0, 400.0);
+        assert!(scroll.smooth_scroll_by(300.0));
+        assert_eq!(scroll.offset(), 0.0);
+
+        let mut now = Instant::now();
+        let mut previous = scroll.offset();
+        let mut frames = 0;
+        while scroll.tick(now) {
+            assert!(scroll.offset() > previous && scroll.offset() < 300.0);
+            previous = scroll.offset();
+            now += Duration::from_millis(16);
+            frames += 1;
+            assert!(frames < 120, "animation never settled");
+        }
+        assert_eq!(scroll.offset(), 300.0);
+        assert!(!scroll.is_animating());
+    }
+
+    #[test]
+    fn test_smooth_deltas_accumulate_and_clamp() {
+        let mut scroll = ScrollState::new();
+        scroll.set_extent(1000.0, 400.0);
+        scroll.smooth_scroll_by(400.0);
+        scroll.smooth_scroll_by(400.0);
+        assert_eq!(scroll.target(), 600.0);
+
+        // An immediate scroll cancels the animation
+        scroll.scroll_by(-100.0);
+        assert_eq!(scroll.offset(), 500.0);
+        assert!(!scroll.is_animating());
+    }
+
+    #[test]
+    fn test_reduced_motion_setting() {
+        assert!(!parse_reduced_motion(&[]));
+        assert!(parse_reduced_motion(&[(REDUCED_MOTION_SETTING.to_string(), "true".to_string())]));
+        assert!(!parse_reduced_motion(&[(REDUCED_MOTION_SETTING.to_string(), "no".to_string())]));
+    }
+}
@@ -0,0 +1,309 @@
+use super::scroll::ScrollState;
+use std::time::{Duration, Instant};
+
+/// Scrollbar width in logical pixels
+pub const SCROLLBAR_WIDTH: f32 = 12.0;
+/// Shortest thumb in logical pixels, so it stays grabbable on long pages
+pub const MIN_THUMB_LENGTH: f32 = 24.0;
+/// How long the scrollbar stays visible after the last scroll or hover
+pub const AUTO_HIDE_DELAY: Duration = Duration::from_millis(1500);
+const FADE_DURATION: Duration = Duration::from_millis(250);
+
+/// Part of the scrollbar under a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollbarHit {
+    Thumb,
+    /// Track above the thumb (pages up)
+    TrackBefore,
+    /// Track below the thumb (pages down)
+    TrackAfter,
+}
+
+/// Scrollbar width in physical pixels for a scale factor
+pub fn scrollbar_width(scale_factor: f64) -> f32 {
+    SCROLLBAR_WIDTH * scale_factor as f32
+}
+
+/// Track and thumb positions in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrollbarGeometry {
+    pub track_x: f32,
+    pub track_top: f32,
+    pub track_width: f32,
+    pub track_height: f32,
+    pub thumb_top: f32,
+    pub thumb_height: f32,
+    max_offset: f32,
+}
+
+impl ScrollbarGeometry {
+    /// Lay out a scrollbar along the right edge of the viewport; `None` when
+    /// the content fits and there is nothing to scroll
+    pub fn compute(
+        right_edge: f32,
+        viewport_top: f32,
+        scroll: &ScrollState,
+        scale_factor: f64,
+    ) -> Option<Self> {
+        if !scroll.is_scrollable() || scroll.viewport_height() <= 0.0 {
+            return None;
+        }
+
+        let track_width = scrollbar_width(scale_factor);
+        let track_height = scroll.viewport_height();
+        let min_thumb = (MIN_THUMB_LENGTH * scale_factor as f32).min(track_height);
+
+        let proportion = scroll.viewport_height() / scroll.content_height();
+        let thumb_height = (track_height * proportion).clamp(min_thumb, track_height);
+        let travel = track_height - thumb_height;
+        let max_offset = scroll.max_offset();
+
+        Some(Self {
+            track_x: right_edge - track_width,
+            track_top: viewport_top,
+            track_width,
+            track_height,
+            thumb_top: viewport_top + travel * (scroll.offset() / max_offset),
+            thumb_height,
+            max_offset,
+        })
+    }
+
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<ScrollbarHit> {
+        let in_track = x >= self.track_x
+            && x < self.track_x + self.track_width
+            && y >= self.track_top
+            && y < self.track_top + self.track_height;
+        if !in_track {
+            return None;
+        }
+
+        Some(if y < self.thumb_top {
+            ScrollbarHit::TrackBefore
+        } else if y < self.thumb_top + self.thumb_height {
+            ScrollbarHit::Thumb
+        } else {
+            ScrollbarHit::TrackAfter
+        })
+    }
+
+    /// Content offset that puts the thumb's top edge at `thumb_top`
+    pub fn offset_for_thumb_top(&self, thumb_top: f32) -> f32 {
+        let travel = self.track_height - self.thumb_height;
+        if travel <= 0.0 {
+            return 0.0;
+        }
+        ((thumb_top - self.track_top) / travel).clamp(0.0, 1.0) * self.max_offset
+    }
+}
+
+/// Interaction and auto-hide state of a window's scrollbar
+#[derive(Debug, Default)]
+pub struct Scrollbar {
+    last_activity: Option<Instant>,
+    hovered: bool,
+    /// Distance from the thumb top to where it was grabbed
+    drag_grab: Option<f32>,
+}
+
+impl Scrollbar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reveal the scrollbar and restart the auto-hide timer
+    pub fn show(&mut self, now: Instant) {
+        self.last_activity = Some(now);
+    }
+
+    pub fn set_hovered(&mut self, hovered: bool, now: Instant) {
+        if hovered != self.hovered {
+            self.hovered = hovered;
+            self.show(now);
+        }
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag_grab.is_some()
+    }
+
+    pub fn begin_drag(&mut self, geometry: &ScrollbarGeometry, y: f32, now: Instant) {
+        self.drag_grab = Some(y - geometry.thumb_top);
+        self.show(now);
+    }
+
+    /// Content offset for the cursor at `y` while dragging
+    pub fn drag_to(&mut self, geometry: &ScrollbarGeometry, y: f32, now: Instant) -> Option<f32> {
+        let grab = self.drag_grab?;
+        self.show(now);
+        Some(geometry.offset_for_thumb_top(y - grab))
+    }
+
+    pub fn end_drag(&mut self, now: Instant) {
+        if self.drag_grab.take().is_some() {
+            self.show(now);
+        }
+    }
+
+    /// When the scrollbar will next change appearance without input: `now`
+    /// while fading out, the end of the idle delay before that, and `None`
+    /// while held visible or once hidden
+    pub fn next_change(&self, now: Instant) -> Option<Instant> {
+        if self.hovered || self.is_dragging() {
+            return None;
+        }
+        let hide_at = self.last_activity? + AUTO_HIDE_DELAY;
+        if now < hide_at {
+            Some(hide_at)
+        } else if now < hide_at + FADE_DURATION {
+            Some(now)
+        } else {
+            None
+        }
+    }
+
+    /// 1.0 while active, fading to 0.0 once the auto-hide delay has passed
+    pub fn opacity(&self, now: Instant) -> f32 {
+        if self.hovered || self.is_dragging() {
+            return 1.0;
+        }
+        let Some(last) = self.last_activity else {
+            return 0.0;
+        };
+
+        let idle = now.saturating_duration_since(last);
+        if idle <= AUTO_HIDE_DELAY {
+            1.0
+        } else {
+            let fading = (idle - AUTO_HIDE_DELAY).as_secs_f32() / FADE_DURATION.as_secs_f32();
+            (1.0 - fading).max(0.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALES: [f64; 4] = [1.0, 1.25, 2.0, 3.0];
+
+    fn scroll(content: f32, viewport: f32, offset: f32) -> ScrollState {
+        let mut scroll = ScrollState::new();
+        scroll.set_extent(content, viewport);
+        scroll.set_offset(offset);
+        scroll
+    }
+
+    #[test]
+    fn test_no_scrollbar_when_content_fits() {
+        for scale in SCALES {
+            let state = scroll(300.0, 400.0, 0.0);
+            assert!(ScrollbarGeometry::compute(800.0, 50.0, &state, scale).is_none());
+        }
+    }
+
+    #[test]
+    fn test_thumb_proportional_to_viewport() {
+        for scale in SCALES {
+            let viewport = 400.0 * scale as f32;
+            let state = scroll(viewport * 4.0, viewport, 0.0);
+            let geometry = ScrollbarGeometry::compute(800.0, 50.0, &state, scale).unwrap();
+
+            assert_eq!(geometry.track_width, SCROLLBAR_WIDTH * scale as f32);
+            assert_eq!(geometry.track_x, 800.0 - geometry.track_width);
+            assert!((geometry.thumb_height - viewport / 4.0).abs() < 0.01);
+            assert_eq!(geometry.thumb_top, 50.0);
+        }
+    }
+
+    #[test]
+    fn test_min_thumb_length_scales() {
+        for scale in SCALES {
+            let viewport = 400.0 * scale as f32;
+            let state = scroll(viewport * 1000.0, viewport, 0.0);
+            let geometry = ScrollbarGeometry::compute(800.0, 0.0, &state, scale).unwrap();
+            assert_eq!(geometry.thumb_height, MIN_THUMB_LENGTH * scale as f32);
+        }
+    }
+
+    #[test]
+    fn test_hit_testing() {
+        for scale in SCALES {
+            let viewport = 400.0 * scale as f32;
+            let state = scroll(viewport * 4.0, viewport, viewport);
+            let geometry = ScrollbarGeometry::compute(800.0, 50.0, &state, scale).unwrap();
+            let x = geometry.track_x + geometry.track_width / 2.0;
+
+            assert_eq!(
+                geometry.hit_test(x, geometry.thumb_top + geometry.thumb_height / 2.0),
+                Some(ScrollbarHit::Thumb)
+            );
+            assert_eq!(geometry.hit_test(x, geometry.thumb_top - 1.0), Some(ScrollbarHit::TrackBefore));
+            assert_eq!(
+                geometry.hit_test(x, geometry.thumb_top + geometry.thumb_height + 1.0),
+                Some(ScrollbarHit::TrackAfter)
+            );
+            // Just left of the track and above the viewport miss
+            assert_eq!(geometry.hit_test(geometry.track_x - 1.0, geometry.thumb_top + 1.0), None);
+            assert_eq!(geometry.hit_test(x, 49.0), None);
+        }
+    }
+
+    #[test]
+    fn test_thumb_position_round_trips() {
+        for scale in SCALES {
+            let viewport = 400.0 * scale as f32;
+            for offset in [0.0, 123.0 * scale as f32, viewport * 3.0] {
+                let state = scroll(viewport * 4.0, viewport, offset);
+                let geometry = ScrollbarGeometry::compute(800.0, 50.0, &state, scale).unwrap();
+                let mapped = geometry.offset_for_thumb_top(geometry.thumb_top);
+                assert!((mapped - offset).abs() < 0.01, "scale {} offset {}", scale, offset);
+            }
+        }
+    }
+
+    #[test]
+    fn test_drag_maps_cursor_to_offset() {
+        for scale in SCALES {
+            let viewport = 400.0 * scale as f32;
+            let state = scroll(viewport * 2.0, viewport, 0.0);
+            let geometry = ScrollbarGeometry::compute(800.0, 50.0, &state, scale).unwrap();
+            let now = Instant::now();
+            let mut scrollbar = Scrollbar::new();
+
+            // Grab the middle of the thumb and drag past the bottom of the track
+            let grab_y = geometry.thumb_top + geometry.thumb_height / 2.0;
+            scrollbar.begin_drag(&geometry, grab_y, now);
+            let offset = scrollbar.drag_to(&geometry, grab_y + viewport * 2.0, now).unwrap();
+            assert_eq!(offset, state.max_offset());
+
+            // Moving by half the travel scrolls half the content
+            let travel = geometry.track_height - geometry.thumb_height;
+            let offset = scrollbar.drag_to(&geometry, grab_y + travel / 2.0, now).unwrap();
+            assert!((offset - state.max_offset() / 2.0).abs() < 0.01);
+
+            scrollbar.end_drag(now);
+            assert!(scrollbar.drag_to(&geometry, grab_y, now).is_none());
+        }
+    }
+
+    #[test]
+    fn test_auto_hide() {
+        let start = Instant::now();
+        let mut scrollbar = Scrollbar::new();
+        assert_eq!(scrollbar.opacity(start), 0.0);
+
+        scrollbar.show(start);
+        assert_eq!(scrollbar.opacity(start + Duration::from_millis(1000)), 1.0);
+        assert_eq!(scrollbar.opacity(start + Duration::from_secs(3)), 0.0);
+
+        assert_eq!(scrollbar.next_change(start), Some(start + AUTO_HIDE_DELAY));
+        let fading = start + AUTO_HIDE_DELAY + Duration::from_millis(100);
+        assert_eq!(scrollbar.next_change(fading), Some(fading));
+        assert_eq!(scrollbar.next_change(start + Duration::from_secs(3)), None);
+
+        scrollbar.set_hovered(true, start);
+        assert_eq!(scrollbar.next_change(start), None);
+        assert_eq!(scrollbar.opacity(start + Duration::from_secs(3)), 1.0);
+    }
+}
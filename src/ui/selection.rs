@@ -0,0 +1,162 @@
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Longest gap between clicks that still counts as a double/triple click
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(500);
+/// How far (in physical pixels) the cursor may move between multi-clicks
+const MULTI_CLICK_DISTANCE: f32 = 4.0;
+
+/// A selection in the page text, anchored to byte offsets so it survives
+/// scrolling and relayout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    /// Where the selection started
+    pub anchor: usize,
+    /// Where it was extended to; may be before the anchor
+    pub focus: usize,
+}
+
+impl Selection {
+    pub fn collapsed(offset: usize) -> Self {
+        Self {
+            anchor: offset,
+            focus: offset,
+        }
+    }
+
+    pub fn from_range(range: Range<usize>) -> Self {
+        Self {
+            anchor: range.start,
+            focus: range.end,
+        }
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.anchor.min(self.focus)..self.anchor.max(self.focus)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.focus
+    }
+
+    /// Selected text, or `None` if the selection is empty or out of range
+    pub fn text<'a>(&self, text: &'a str) -> Option<&'a str> {
+        if self.is_empty() {
+            return None;
+        }
+        text.get(self.range())
+    }
+}
+
+/// Byte range of the word around an offset; whitespace and punctuation
+/// select just themselves
+pub fn word_range(text: &str, offset: usize) -> Range<usize> {
+    let offset = floor_char_boundary(text, offset.min(text.len()));
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    let Some(current) = text[offset..].chars().next() else {
+        return offset..offset;
+    };
+    if !is_word(current) {
+        return offset..offset + current.len_utf8();
+    }
+
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let end = text[offset..]
+        .char_indices()
+        .find(|(_, c)| !is_word(*c))
+        .map_or(text.len(), |(i, _)| offset + i);
+    start..end
+}
+
+/// Byte range of the source line (paragraph) around an offset, without its newline
+pub fn line_range(text: &str, offset: usize) -> Range<usize> {
+    let offset = offset.min(text.len());
+    let start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+    start..end
+}
+
+/// Largest character boundary of `text` at or before `offset`
+pub fn floor_char_boundary(text: &str, mut offset: usize) -> usize {
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// Counts rapid clicks at the same spot: 1 single, 2 double, 3 triple
+#[derive(Debug, Default)]
+pub struct ClickCounter {
+    last: Option<(Instant, f32, f32)>,
+    count: u32,
+}
+
+impl ClickCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn click(&mut self, x: f32, y: f32, now: Instant) -> u32 {
+        let repeated = self.last.is_some_and(|(at, last_x, last_y)| {
+            now.saturating_duration_since(at) <= MULTI_CLICK_INTERVAL
+                && (x - last_x).abs() <= MULTI_CLICK_DISTANCE
+                && (y - last_y).abs() <= MULTI_CLICK_DISTANCE
+        });
+
+        // A fourth click starts over as a single click
+        self.count = if repeated && self.count < 3 { self.count + 1 } else { 1 };
+        self.last = Some((now, x, y));
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_range_and_text() {
+        let text = "Hello world\nSecond line";
+        let selection = Selection { anchor: 14, focus: 6 };
+        assert_eq!(selection.range(), 6..14);
+        assert_eq!(selection.text(text), Some("world\nSe"));
+        assert_eq!(Selection::collapsed(3).text(text), None);
+    }
+
+    #[test]
+    fn test_word_range() {
+        let text = "copy the_word, then naïve";
+        assert_eq!(&text[word_range(text, 7)], "the_word");
+        assert_eq!(&text[word_range(text, 13)], ",");
+        assert_eq!(&text[word_range(text, text.len() - 2)], "naïve");
+        assert_eq!(word_range(text, text.len()), text.len()..text.len());
+    }
+
+    #[test]
+    fn test_line_range() {
+        let text = "first line\nsecond line\nthird";
+        assert_eq!(&text[line_range(text, 14)], "second line");
+        assert_eq!(&text[line_range(text, 0)], "first line");
+        assert_eq!(&text[line_range(text, text.len())], "third");
+    }
+
+    #[test]
+    fn test_click_counting() {
+        let start = Instant::now();
+        let mut clicks = ClickCounter::new();
+        assert_eq!(clicks.click(10.0, 10.0, start), 1);
+        assert_eq!(clicks.click(11.0, 10.0, start + Duration::from_millis(200)), 2);
+        assert_eq!(clicks.click(11.0, 11.0, start + Duration::from_millis(400)), 3);
+        assert_eq!(clicks.click(11.0, 11.0, start + Duration::from_millis(600)), 1);
+
+        // Too slow or too far apart resets
+        assert_eq!(clicks.click(11.0, 11.0, start + Duration::from_secs(2)), 1);
+        assert_eq!(clicks.click(40.0, 11.0, start + Duration::from_millis(2100)), 1);
+    }
+}
@@ -1,17 +1,589 @@
 use glyphon::{
-    Attrs, Buffer, Family, FontSystem, Metrics, Resolution, Shaping,
-    SwashCache, TextArea, TextAtlas, TextRenderer as GlyphonTextRenderer, Viewport,
+    Affinity, Attrs, Buffer, Color as GlyphColor, Cursor, Style, Wrap, FontSystem, Metrics, Resolution, Shaping,
+    cosmic_text::Scroll, SwashCache, TextArea, TextAtlas, TextBounds, TextRenderer as GlyphonTextRenderer, Viewport, Weight,
 };
 use wgpu::{Device, Queue, MultisampleState, TextureFormat};
 use anyhow::Result;
+use glyphon::LayoutRun;
+use std::ops::Range;
+use super::caret::CaretLayout;
+use super::fonts::{build_font_system, FontSettings};
+use crate::infrastructure::{BlockKind, PageContent, TextStyle};
+
+/// Settings key: soft-wrap preformatted blocks instead of letting them overflow
+pub const WRAP_PREFORMATTED_SETTING: &str = "content.wrap_preformatted";
+/// Settings key: columns per tab stop in preformatted text
+pub const TAB_WIDTH_SETTING: &str = "content.tab_width";
+pub const DEFAULT_TAB_WIDTH: u16 = 8;
+
+/// How page content is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLayoutOptions {
+    pub wrap_preformatted: bool,
+    pub tab_width: u16,
+}
+
+impl Default for ContentLayoutOptions {
+    fn default() -> Self {
+        Self {
+            wrap_preformatted: false,
+            tab_width: DEFAULT_TAB_WIDTH,
+        }
+    }
+}
+
+impl ContentLayoutOptions {
+    /// Build from stored settings, ignoring missing or malformed values
+    pub fn from_settings(settings: &[(String, String)]) -> Self {
+        let mut options = Self::default();
+        for (key, value) in settings {
+            match key.as_str() {
+                WRAP_PREFORMATTED_SETTING => {
+                    options.wrap_preformatted = matches!(value.trim(), "true" | "1" | "yes");
+                }
+                TAB_WIDTH_SETTING => {
+                    if let Ok(width) = value.trim().parse::<u16>() {
+                        options.tab_width = width.clamp(1, 16);
+                    }
+                }
+                _ => {}
+            }
+        }
+        options
+    }
+}
+
+/// Extra height above the viewport whose lines are still prepared, so
+/// glyphs partly scrolled out aren't dropped
+const VISIBLE_MARGIN: f32 = 256.0;
+
+/// Shaped as nothing; a line may break after it, and then shows a hyphen
+const SOFT_HYPHEN: char = '\u{ad}';
+/// Zero-width non-joiner, shaped between adjacent punctuation of monospace
+/// text so fonts with programming ligatures draw `=>` and `!=` as typed
+const LIGATURE_BREAK: &str = "\u{200c}";
+
+/// Whether a ligature break goes between two adjacent characters of
+/// monospace text
+fn breaks_ligature(previous: char, next: char) -> bool {
+    previous.is_ascii_graphic()
+        && next.is_ascii_graphic()
+        && (previous.is_ascii_punctuation() || next.is_ascii_punctuation())
+}
+
+/// A line broken after a soft hyphen, which shows a hyphen at its end
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftHyphen {
+    /// Where the line ends, in layout coordinates: its right edge, or its
+    /// left edge if right to left
+    pub x: f32,
+    pub line_top: f32,
+    pub font_size: f32,
+    pub rtl: bool,
+    /// Color of the word broken, if not the page's
+    pub color: Option<GlyphColor>,
+}
+
+/// Colors of links to pages not yet visited and to those in history
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkColors {
+    pub link: GlyphColor,
+    pub visited: GlyphColor,
+}
+
+/// Inputs a shaped page layout depends on; any change forces reshaping
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageLayoutKey {
+    /// Bumped whenever the page content is replaced
+    pub generation: u64,
+    pub width: f32,
+    pub font_size: f32,
+    pub options: ContentLayoutOptions,
+    pub dark_theme: bool,
+    pub link_colors: LinkColors,
+    /// The page's colors were changed to show it dark
+    pub recolored: bool,
+}
+
+/// Where the cached page layout is drawn
+#[derive(Debug, Clone, Copy)]
+pub struct PageArea {
+    pub left: f32,
+    /// Window position of the top of the layout
+    pub top: f32,
+    pub bounds: TextBounds,
+    pub default_color: GlyphColor,
+}
+
+/// Shaped page layout kept across frames, so scrolling only moves it
+#[derive(Default)]
+pub struct PageLayoutCache {
+    entry: Option<(PageLayoutKey, TextLayout)>,
+}
+
+impl PageLayoutCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The layout for `key`, shaping `content` only if the key changed
+    pub fn get_or_build(
+        &mut self,
+        key: PageLayoutKey,
+        content: &PageContent,
+        font_system: &mut FontSystem,
+        fonts: &FontSettings,
+    ) -> &TextLayout {
+        if self.entry.as_ref().is_none_or(|(cached, _)| *cached != key) {
+            let layout = build_page_layout(font_system, fonts, content, &key);
+            self.entry = Some((key, layout));
+        }
+        &self.entry.as_ref().expect("layout was just built").1
+    }
+
+    pub fn get(&self) -> Option<&TextLayout> {
+        self.entry.as_ref().map(|(_, layout)| layout)
+    }
+
+    pub fn clear(&mut self) {
+        self.entry = None;
+    }
+
+    /// Text area for the part of the layout inside `area`'s bounds. The
+    /// buffer is scrolled to the first visible line so earlier lines are
+    /// never visited; call `reset_scroll` once the area is prepared.
+    pub fn visible_area(&mut self, area: &PageArea) -> Option<TextArea<'_>> {
+        let (_, layout) = self.entry.as_mut()?;
+        let first = layout.first_line_below(area.bounds.top as f32 - area.top - VISIBLE_MARGIN);
+        let line_top = layout.line_extents.get(first).map_or(0.0, |(top, _)| *top);
+        layout.buffer.set_scroll(Scroll::new(first, 0.0, 0.0));
+        Some(TextArea {
+            buffer: &layout.buffer,
+            left: area.left,
+            top: area.top + line_top,
+            scale: 1.0,
+            bounds: area.bounds,
+            default_color: area.default_color,
+            custom_glyphs: &[],
+        })
+    }
+
+    /// Undo `visible_area`, so layout queries see every line again
+    pub fn reset_scroll(&mut self) {
+        if let Some((_, layout)) = self.entry.as_mut() {
+            layout.buffer.set_scroll(Scroll::default());
+        }
+    }
+}
+
+/// Font attributes for a styled span of page text
+fn style_attrs<'a>(fonts: &'a FontSettings, style: &TextStyle, font_size: f32) -> Attrs<'a> {
+    let mut attrs = if style.monospace {
+        fonts.mono_attrs()
+    } else {
+        fonts.sans_attrs()
+    };
+    if style.bold {
+        attrs = attrs.weight(Weight::BOLD);
+    }
+    if style.italic {
+        attrs = attrs.style(Style::Italic);
+    }
+    if let Some(color) = style.color {
+        attrs = attrs.color(GlyphColor::rgb(color.r, color.g, color.b));
+    }
+    if let Some(scale) = style.font_scale {
+        let size = fonts.clamp_size(font_size * f32::from(scale) / 100.0);
+        attrs = attrs.metrics(Metrics::new(size, size * 1.2));
+    }
+    attrs
+}
+
+/// Runs of text in one style: the page's style spans, split where links
+/// start and end, with whether each run is a visited link, if a link
+fn style_runs(content: &PageContent) -> Vec<(Range<usize>, TextStyle, Option<bool>)> {
+    let len = content.text.len();
+    let mut links: Vec<_> = content.links.iter().collect();
+    links.sort_by_key(|link| link.range.start);
+    let mut bounds: Vec<usize> = content
+        .styles
+        .iter()
+        .map(|span| &span.range)
+        .chain(links.iter().map(|link| &link.range))
+        .flat_map(|range| [range.start, range.end])
+        .chain([0, len])
+        .filter(|bound| *bound <= len && content.text.is_char_boundary(*bound))
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let (mut styles, mut links) = (content.styles.iter().peekable(), links.into_iter().peekable());
+    let mut runs = Vec::with_capacity(bounds.len());
+    for pair in bounds.windows(2) {
+        let range = pair[0]..pair[1];
+        while styles.next_if(|span| span.range.end <= range.start).is_some() {}
+        while links.next_if(|link| link.range.end <= range.start).is_some() {}
+        let style = styles
+            .peek()
+            .filter(|span| span.range.start <= range.start)
+            .map_or_else(TextStyle::default, |span| span.style);
+        let visited = links
+            .peek()
+            .filter(|link| link.range.start <= range.start)
+            .map(|link| link.visited);
+        runs.push((range, style, visited));
+    }
+    runs
+}
+
+/// Shape page content with per-span fonts and link colors; table rows and
+/// preformatted lines are laid out without wrapping unless the options ask
+/// for it.
+///
+/// Other lines wrap at Unicode (UAX #14) break opportunities, so CJK text
+/// breaks between characters, no-break spaces hold and words break after
+/// soft hyphens; a word wider than the line is broken between glyphs,
+/// without a hyphen. Monospace text is shaped with ligature breaks between
+/// punctuation, which `TextLayout` maps out of every offset. Hit-testing,
+/// selection and scrolling all measure the buffer this lays out.
+pub fn build_page_layout(
+    font_system: &mut FontSystem,
+    fonts: &FontSettings,
+    content: &PageContent,
+    key: &PageLayoutKey,
+) -> TextLayout {
+    let (width, options) = (key.width, key.options);
+    let font_size = fonts.clamp_size(key.font_size);
+    let metrics = Metrics::new(font_size, font_size * 1.2);
+
+    let mut buffer = Buffer::new(font_system, metrics);
+    buffer.set_size(font_system, Some(width), None);
+    buffer.set_wrap(font_system, Wrap::WordOrGlyph);
+    buffer.set_tab_width(font_system, options.tab_width);
+
+    // Split the text into runs at style and link boundaries, and monospace
+    // runs again wherever a ligature break goes
+    let text = content.text.as_str();
+    let mut spans = Vec::new();
+    let mut ligature_breaks = Vec::new();
+    for (range, style, visited) in style_runs(content) {
+        let attrs = style_attrs(fonts, &style, font_size);
+        let attrs = match visited {
+            Some(true) => attrs.color(key.link_colors.visited),
+            Some(false) => attrs.color(key.link_colors.link),
+            None => attrs,
+        };
+        let mut start = range.start;
+        if style.monospace {
+            let run = &text[range.clone()];
+            for ((_, previous), (i, next)) in run.char_indices().zip(run.char_indices().skip(1)) {
+                if breaks_ligature(previous, next) {
+                    let at = range.start + i;
+                    spans.push((&text[start..at], attrs));
+                    spans.push((LIGATURE_BREAK, attrs));
+                    ligature_breaks.push(at);
+                    start = at;
+                }
+            }
+        }
+        spans.push((&text[start..range.end], attrs));
+    }
+    if spans.is_empty() {
+        spans.push(("", fonts.sans_attrs()));
+    }
+    buffer.set_rich_text(font_system, spans, fonts.sans_attrs(), Shaping::Advanced);
+
+    let mut layout = TextLayout::new(buffer, text).with_ligature_breaks(ligature_breaks);
+    // Table rows are already wrapped into their columns
+    let lines: Vec<usize> = content
+        .blocks
+        .iter()
+        .filter(|block| match block.kind {
+            BlockKind::Preformatted => !options.wrap_preformatted,
+            BlockKind::TableRow => true,
+            BlockKind::Heading(_) => false,
+        })
+        .flat_map(|block| layout.lines_covering(&block.range))
+        .collect();
+    let relaid = !lines.is_empty();
+    for line_i in lines {
+        if let Some(line) = layout.buffer.lines.get_mut(line_i) {
+            line.reset_layout();
+            line.layout(font_system, font_size, Some(width), Wrap::None, None, options.tab_width);
+        }
+    }
+    if relaid {
+        layout.measure();
+    }
+
+    layout
+}
+
+/// A shaped page buffer plus the byte offsets where each source line starts,
+/// mapping between text positions and layout positions.
+///
+/// The buffer's text is the source text with ligature breaks inserted;
+/// every offset taken or given is one of the source text, and the breaks
+/// are mapped out in between.
+pub struct TextLayout {
+    pub buffer: Buffer,
+    line_starts: Vec<usize>,
+    /// `(top, bottom)` of every buffer line, so lookups by position don't
+    /// walk the whole layout
+    line_extents: Vec<(f32, f32)>,
+    /// Source offsets a ligature break was shaped before, in order
+    ligature_breaks: Vec<usize>,
+    soft_hyphens: Vec<SoftHyphen>,
+}
+
+impl TextLayout {
+    pub fn new(buffer: Buffer, text: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let mut layout = Self {
+            buffer,
+            line_starts,
+            line_extents: Vec::new(),
+            ligature_breaks: Vec::new(),
+            soft_hyphens: Vec::new(),
+        };
+        layout.measure();
+        layout
+    }
+
+    /// The layout of a buffer shaped with ligature breaks before these
+    /// source offsets
+    pub fn with_ligature_breaks(mut self, ligature_breaks: Vec<usize>) -> Self {
+        self.ligature_breaks = ligature_breaks;
+        self
+    }
+
+    /// Record line positions and the soft hyphens lines broke at; must be
+    /// called again after relaying out lines
+    fn measure(&mut self) {
+        let mut extents = vec![(0.0, 0.0); self.buffer.lines.len()];
+        let mut seen = vec![false; extents.len()];
+        let mut soft_hyphens = Vec::new();
+        let mut runs = self.buffer.layout_runs().peekable();
+        while let Some(run) = runs.next() {
+            let bottom = run.line_top + run.line_height;
+            if let Some(extent) = extents.get_mut(run.line_i) {
+                if !seen[run.line_i] {
+                    *extent = (run.line_top, bottom);
+                    seen[run.line_i] = true;
+                } else {
+                    extent.1 = bottom;
+                }
+            }
+
+            // Broken within its source line, after a soft hyphen
+            let wrapped = runs.peek().is_some_and(|next| next.line_i == run.line_i);
+            let last = run.glyphs.iter().max_by_key(|glyph| glyph.end);
+            if let Some(glyph) = last.filter(|glyph| wrapped && run.text[glyph.start..].starts_with(SOFT_HYPHEN)) {
+                let rtl = glyph.level.is_rtl();
+                soft_hyphens.push(SoftHyphen {
+                    x: if rtl { glyph.x } else { glyph.x + glyph.w },
+                    line_top: run.line_top,
+                    font_size: glyph.font_size,
+                    rtl,
+                    color: glyph.color_opt,
+                });
+            }
+        }
+        self.line_extents = extents;
+        self.soft_hyphens = soft_hyphens;
+    }
+
+    /// Ends of lines broken at a soft hyphen, top to bottom
+    pub fn soft_hyphens(&self) -> &[SoftHyphen] {
+        &self.soft_hyphens
+    }
+
+    /// Ligature breaks shaped into source line `line`, as source columns
+    fn line_breaks(&self, line: usize) -> &[usize] {
+        let (Some(&start), end) = (self.line_starts.get(line), self.line_starts.get(line + 1)) else {
+            return &[];
+        };
+        let first = self.ligature_breaks.partition_point(|&offset| offset < start);
+        let last = end.map_or(self.ligature_breaks.len(), |&end| {
+            self.ligature_breaks.partition_point(|&offset| offset < end)
+        });
+        &self.ligature_breaks[first..last]
+    }
+
+    /// Index into buffer line `line` of a source column, past the ligature
+    /// breaks shaped before it
+    fn buffer_index(&self, line: usize, column: usize) -> usize {
+        let start = self.line_starts.get(line).copied().unwrap_or(0);
+        let before = self.line_breaks(line).partition_point(|&offset| offset - start <= column);
+        column + before * LIGATURE_BREAK.len()
+    }
+
+    /// Source column of an index into buffer line `line`; an index within a
+    /// ligature break maps to the character after it
+    fn source_column(&self, line: usize, index: usize) -> usize {
+        let start = self.line_starts.get(line).copied().unwrap_or(0);
+        let width = LIGATURE_BREAK.len();
+        let mut column = index;
+        for (shaped, offset) in self.line_breaks(line).iter().enumerate() {
+            let position = offset - start + shaped * width;
+            if index >= position + width {
+                column -= width;
+            } else {
+                return if index > position { offset - start } else { column };
+            }
+        }
+        column
+    }
+
+    /// Index of the first line not entirely above `y`
+    pub fn first_line_below(&self, y: f32) -> usize {
+        self.line_extents
+            .partition_point(|&(_, bottom)| bottom <= y)
+            .min(self.line_extents.len().saturating_sub(1))
+    }
+
+    /// Indices of the source lines touched by a byte range
+    fn lines_covering(&self, range: &Range<usize>) -> Range<usize> {
+        let first = self
+            .line_starts
+            .partition_point(|&start| start <= range.start)
+            .saturating_sub(1);
+        let last = self
+            .line_starts
+            .partition_point(|&start| start < range.end)
+            .max(first + 1);
+        first..last
+    }
+
+    /// Vertical extent `(top, bottom)` of the lines covering a byte range
+    pub fn range_extent(&self, range: Range<usize>) -> Option<(f32, f32)> {
+        let lines = self.lines_covering(&range);
+        let (top, _) = self.line_extents.get(lines.start)?;
+        let (_, bottom) = self.line_extents.get(lines.end.min(self.line_extents.len()) - 1)?;
+        Some((*top, *bottom))
+    }
+
+    /// Height of all laid-out lines
+    pub fn height(&self) -> f32 {
+        self.line_extents.last().map_or(0.0, |(_, bottom)| *bottom)
+    }
+
+    /// Top of the visual line holding a byte offset of the source text
+    pub fn offset_top(&self, offset: usize) -> Option<f32> {
+        let line = self.line_starts.partition_point(|&start| start <= offset).checked_sub(1)?;
+        let column = self.buffer_index(line, offset - self.line_starts[line]);
+
+        // A wrapped line spans several runs; take the last one starting at or before the column
+        let mut top = None;
+        for run in self.buffer.layout_runs() {
+            if run.line_i > line {
+                return top.or(Some(run.line_top));
+            }
+            if run.line_i == line {
+                let starts_before = run.glyphs.first().is_none_or(|g| g.start <= column);
+                if starts_before || top.is_none() {
+                    top = Some(run.line_top);
+                }
+            }
+        }
+        top
+    }
+
+    /// Highlight rectangles `(x, y, width, height)` in layout coordinates
+    /// covering a byte range of the source text
+    pub fn highlight_rects(&self, range: Range<usize>) -> Vec<(f32, f32, f32, f32)> {
+        let mut rects = Vec::new();
+        if range.is_empty() {
+            return rects;
+        }
+
+        for run in self.buffer.layout_runs() {
+            let Some(&line_start) = self.line_starts.get(run.line_i) else {
+                continue;
+            };
+            let line_end = line_start + self.source_column(run.line_i, run.text.len());
+            if range.end <= line_start || range.start > line_end {
+                continue;
+            }
+
+            let start = self.buffer_index(run.line_i, range.start.max(line_start) - line_start);
+            let end = self.buffer_index(run.line_i, range.end.min(line_end) - line_start);
+            let cursor_start = Cursor::new_with_affinity(run.line_i, start, Affinity::Before);
+            let cursor_end = Cursor::new_with_affinity(run.line_i, end, Affinity::After);
+            if let Some((x, width)) = run.highlight(cursor_start, cursor_end) {
+                if width > 0.0 {
+                    rects.push((x, run.line_top, width, run.line_height));
+                }
+            }
+        }
+        rects
+    }
+
+    /// Byte offset of the source text under a point in layout coordinates
+    pub fn hit(&self, x: f32, y: f32) -> Option<usize> {
+        let cursor = self.buffer.hit(x, y)?;
+        Some(self.line_starts.get(cursor.line)? + self.source_column(cursor.line, cursor.index))
+    }
+
+    /// The visual line holding a byte offset of the source text, with the
+    /// offset its line starts at and the offset's index into the buffer line
+    fn run_at(&self, offset: usize) -> Option<(LayoutRun<'_>, usize, usize)> {
+        let line = self.line_starts.partition_point(|&start| start <= offset).checked_sub(1)?;
+        let line_start = self.line_starts[line];
+        let column = self.buffer_index(line, offset - line_start);
+        // A wrapped line spans several runs; take the last one starting at or before the column
+        let mut found = None;
+        for run in self.buffer.layout_runs() {
+            if run.line_i > line {
+                break;
+            }
+            if run.line_i == line && (found.is_none() || run.glyphs.first().is_none_or(|g| g.start <= column)) {
+                found = Some(run);
+            }
+        }
+        Some((found?, line_start, column))
+    }
+}
+
+impl CaretLayout for TextLayout {
+    fn caret_position(&self, offset: usize) -> Option<(f32, f32, f32)> {
+        let (run, _, column) = self.run_at(offset)?;
+        let x = run
+            .glyphs
+            .iter()
+            .find(|glyph| glyph.end > column)
+            .map(|glyph| glyph.x)
+            .or_else(|| run.glyphs.last().map(|glyph| glyph.x + glyph.w))
+            .unwrap_or(0.0);
+        Some((x, run.line_top, run.line_height))
+    }
+
+    fn visual_line(&self, offset: usize) -> Option<Range<usize>> {
+        let (run, line_start, column) = self.run_at(offset)?;
+        let start = run.glyphs.first().map_or(column, |glyph| glyph.start);
+        let end = run.glyphs.last().map_or(column, |glyph| glyph.end);
+        let source = |index| line_start + self.source_column(run.line_i, index);
+        Some(source(start)..source(end))
+    }
+
+    fn hit(&self, x: f32, y: f32) -> Option<usize> {
+        TextLayout::hit(self, x, y)
+    }
+}
 
 /// Text rendering system using glyphon
 pub struct TextRenderer {
     font_system: FontSystem,
+    fonts: FontSettings,
     swash_cache: SwashCache,
     atlas: TextAtlas,
     text_renderer: GlyphonTextRenderer,
+    /// Draws text over everything else, e.g. toasts above the page
+    overlay_renderer: GlyphonTextRenderer,
     viewport: Viewport,
+    page_cache: PageLayoutCache,
 }
 
 impl TextRenderer {
@@ -21,8 +593,19 @@ impl TextRenderer {
         format: TextureFormat,
         _width: u32,
         _height: u32,
+        fonts: FontSettings,
+    ) -> Result<Self> {
+        Self::with_font_system(device, queue, format, build_font_system(&fonts), fonts)
+    }
+
+    /// A text renderer shaping with `font_system`, built ahead of the device
+    pub fn with_font_system(
+        device: &Device,
+        queue: &Queue,
+        format: TextureFormat,
+        font_system: FontSystem,
+        fonts: FontSettings,
     ) -> Result<Self> {
-        let font_system = FontSystem::new();
         let swash_cache = SwashCache::new();
         let cache = glyphon::Cache::new(device);
         let mut atlas = TextAtlas::new(device, queue, &cache, format);
@@ -32,15 +615,24 @@ impl TextRenderer {
             MultisampleState::default(),
             None,
         );
+        let overlay_renderer = GlyphonTextRenderer::new(
+            &mut atlas,
+            device,
+            MultisampleState::default(),
+            None,
+        );
 
         let viewport = Viewport::new(device, &cache);
 
         Ok(Self {
             font_system,
+            fonts,
             swash_cache,
             atlas,
             text_renderer,
+            overlay_renderer,
             viewport,
+            page_cache: PageLayoutCache::new(),
         })
     }
 
@@ -54,27 +646,57 @@ impl TextRenderer {
         );
     }
 
-    /// Create a text buffer for rendering
-    pub fn create_buffer(&mut self, text: &str, font_size: f32, width: u32, height: u32) -> Buffer {
+    /// Create a text buffer for rendering; `None` height lays out every line
+    pub fn create_buffer(&mut self, text: &str, font_size: f32, width: u32, height: Option<u32>) -> Buffer {
+        let font_size = self.fonts.clamp_size(font_size);
         let metrics = Metrics::new(font_size, font_size * 1.2);
         let mut buffer = Buffer::new(&mut self.font_system, metrics);
 
         buffer.set_size(
             &mut self.font_system,
             Some(width as f32),
-            Some(height as f32),
+            height.map(|h| h as f32),
         );
 
         buffer.set_text(
             &mut self.font_system,
             text,
-            Attrs::new().family(Family::SansSerif),
+            self.fonts.sans_attrs(),
             Shaping::Advanced,
         );
 
         buffer
     }
 
+    /// Shaped layout of the page, reused until `key` changes
+    pub fn page_layout(&mut self, key: PageLayoutKey, content: &PageContent) -> &TextLayout {
+        self.page_cache
+            .get_or_build(key, content, &mut self.font_system, &self.fonts)
+    }
+
+    /// Layout drawn by the last frame, for hit testing
+    pub fn cached_page_layout(&self) -> Option<&TextLayout> {
+        self.page_cache.get()
+    }
+
+    pub fn clear_page_layout(&mut self) {
+        self.page_cache.clear();
+    }
+
+    /// Height of all laid-out lines in a buffer
+    pub fn buffer_height(buffer: &Buffer) -> f32 {
+        buffer
+            .layout_runs()
+            .last()
+            .map(|run| run.line_top + run.line_height)
+            .unwrap_or(0.0)
+    }
+
+    /// Width of the longest laid-out line in a buffer
+    pub fn buffer_width(buffer: &Buffer) -> f32 {
+        buffer.layout_runs().map(|run| run.line_w).fold(0.0, f32::max)
+    }
+
     /// Render text buffers to screen
     pub fn render(
         &mut self,
@@ -83,9 +705,18 @@ impl TextRenderer {
         view: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
         text_areas: Vec<TextArea>,
+        page: Option<PageArea>,
     ) -> Result<()> {
+        // Only the visible part of the cached page is prepared
+        let page_area = match page {
+            Some(area) => self.page_cache.visible_area(&area),
+            None => None,
+        };
+        let text_areas: Vec<TextArea> = text_areas.into_iter().chain(page_area).collect();
+
         // Prepare text atlas
-        self.text_renderer
+        let prepared = self
+            .text_renderer
             .prepare(
                 device,
                 queue,
@@ -95,7 +726,9 @@ impl TextRenderer {
                 text_areas,
                 &mut self.swash_cache,
             )
-            .map_err(|e| anyhow::anyhow!("Failed to prepare text: {:?}", e))?;
+            .map_err(|e| anyhow::anyhow!("Failed to prepare text: {:?}", e));
+        self.page_cache.reset_scroll();
+        prepared?;
 
         // Render text
         {
@@ -122,7 +755,419 @@ impl TextRenderer {
         Ok(())
     }
 
+    /// Render text buffers on top of what [`render`](Self::render) drew
+    /// this frame
+    pub fn render_overlay(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+        text_areas: Vec<TextArea>,
+    ) -> Result<()> {
+        self.overlay_renderer
+            .prepare(
+                device,
+                queue,
+                &mut self.font_system,
+                &mut self.atlas,
+                &self.viewport,
+                text_areas,
+                &mut self.swash_cache,
+            )
+            .map_err(|e| anyhow::anyhow!("Failed to prepare overlay text: {:?}", e))?;
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Overlay Text Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        self.overlay_renderer
+            .render(&self.atlas, &self.viewport, &mut pass)
+            .map_err(|e| anyhow::anyhow!("Failed to render overlay text: {:?}", e))
+    }
+
     pub fn font_system(&mut self) -> &mut FontSystem {
         &mut self.font_system
     }
+
+    pub fn fonts(&self) -> &FontSettings {
+        &self.fonts
+    }
+
+    /// Borrow the font system together with the font settings
+    pub fn font_system_and_fonts(&mut self) -> (&mut FontSystem, &FontSettings) {
+        (&mut self.font_system, &self.fonts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(text: &str, width: f32) -> TextLayout {
+        let mut font_system = build_font_system(&FontSettings::default());
+        let mut buffer = Buffer::new(&mut font_system, Metrics::new(10.0, 12.0));
+        buffer.set_size(&mut font_system, Some(width), None);
+        buffer.set_text(&mut font_system, text, Attrs::new(), Shaping::Advanced);
+        TextLayout::new(buffer, text)
+    }
+
+    #[test]
+    fn test_offset_top_per_line() {
+        let text = "first\nsecond\n\nfourth";
+        let layout = layout(text, 500.0);
+
+        assert_eq!(layout.offset_top(0), Some(0.0));
+        assert_eq!(layout.offset_top(text.find("second").unwrap()), Some(12.0));
+        assert_eq!(layout.offset_top(text.find("fourth").unwrap()), Some(36.0));
+        assert_eq!(layout.height(), 48.0);
+    }
+
+    #[test]
+    fn test_offset_top_in_wrapped_line() {
+        let text = "word ".repeat(40);
+        let layout = layout(&text, 100.0);
+
+        let last = layout.offset_top(text.len() - 2).unwrap();
+        assert!(last > 0.0);
+        assert_eq!(layout.offset_top(0), Some(0.0));
+    }
+
+    #[test]
+    fn test_highlight_rects_span_lines() {
+        let text = "first\nsecond";
+        let layout = layout(text, 500.0);
+
+        let rects = layout.highlight_rects(2..9);
+        assert_eq!(rects.len(), 2);
+        assert_eq!(rects[0].1, 0.0);
+        assert_eq!(rects[1].1, 12.0);
+        assert!(rects[0].0 > 0.0 && rects[1].0 == 0.0);
+        assert!(layout.highlight_rects(3..3).is_empty());
+    }
+
+    #[test]
+    fn test_hit_maps_back_to_text() {
+        let text = "first\nsecond";
+        let layout = layout(text, 500.0);
+
+        let offset = layout.hit(1.0, 18.0).unwrap();
+        assert!(offset >= text.find("second").unwrap());
+    }
+
+    #[test]
+    fn test_caret_stays_on_its_text_across_widths() {
+        let text = "word ".repeat(40);
+        let offset = text.len() / 2;
+        for width in [100.0, 300.0, 1000.0] {
+            let layout = layout(&text, width);
+            let (x, top, height) = layout.caret_position(offset).unwrap();
+            assert_eq!(CaretLayout::hit(&layout, x, top + height / 2.0), Some(offset), "width {}", width);
+            let line = layout.visual_line(offset).unwrap();
+            assert!(line.contains(&offset), "{:?} at width {}", line, width);
+        }
+        let narrow = layout(&text, 100.0);
+        assert!(narrow.caret_position(offset).unwrap().1 > layout(&text, 1000.0).caret_position(offset).unwrap().1);
+        assert_eq!(narrow.caret_position(0), Some((0.0, 0.0, 12.0)));
+    }
+
+    #[test]
+    fn test_range_extent_covers_block_lines() {
+        let text = "intro\ncode one\ncode two\noutro";
+        let layout = layout(text, 500.0);
+
+        let start = text.find("code one").unwrap();
+        let end = text.find("outro").unwrap() - 1;
+        assert_eq!(layout.range_extent(start..end), Some((12.0, 36.0)));
+    }
+
+    #[test]
+    fn test_layout_options_from_settings() {
+        let settings = vec![
+            (WRAP_PREFORMATTED_SETTING.to_string(), "true".to_string()),
+            (TAB_WIDTH_SETTING.to_string(), "40".to_string()),
+        ];
+        let options = ContentLayoutOptions::from_settings(&settings);
+        assert!(options.wrap_preformatted);
+        assert_eq!(options.tab_width, 16);
+
+        let defaults = ContentLayoutOptions::from_settings(&[]);
+        assert!(!defaults.wrap_preformatted);
+        assert_eq!(defaults.tab_width, DEFAULT_TAB_WIDTH);
+    }
+
+    fn page_key(generation: u64, width: f32) -> PageLayoutKey {
+        PageLayoutKey {
+            generation,
+            width,
+            font_size: 10.0,
+            options: ContentLayoutOptions::default(),
+            dark_theme: false,
+            link_colors: LinkColors {
+                link: GlyphColor::rgb(0, 0, 255),
+                visited: GlyphColor::rgb(128, 0, 128),
+            },
+            recolored: false,
+        }
+    }
+
+    #[test]
+    fn test_mixed_direction_runs_keep_visual_order() {
+        use html5ever::parse_document;
+        use html5ever::tendril::TendrilSink;
+        use markup5ever_rcdom::RcDom;
+
+        let html = include_str!("../../tests/fixtures/layout/rtl.html");
+        let dom = parse_document(RcDom::default(), Default::default())
+            .from_utf8()
+            .read_from(&mut html.as_bytes())
+            .unwrap();
+        let content = crate::infrastructure::LayoutBuilder::new().build(&dom);
+        let mut font_system = build_font_system(&FontSettings::default());
+        let layout = build_page_layout(&mut font_system, &FontSettings::default(), &content, &page_key(1, 800.0));
+
+        // x of the first glyph of `needle` on the run whose line holds it
+        let position = |needle: &str| {
+            layout
+                .buffer
+                .layout_runs()
+                .find_map(|run| {
+                    let index = run.text.find(needle)?;
+                    let glyph = run.glyphs.iter().find(|glyph| glyph.start == index)?;
+                    Some((run.rtl, glyph.x, run.line_w))
+                })
+                .unwrap()
+        };
+        let (rtl, english, line_width) = position("English");
+        let (_, hebrew, _) = position("שלום");
+        assert!(rtl);
+        assert!(english < hebrew);
+        // Right-to-left lines end at the right edge
+        let (_, _, heading_width) = position("مرحبا");
+        let run = layout.buffer.layout_runs().next().unwrap();
+        assert!(run.glyphs.iter().all(|glyph| glyph.x >= 800.0 - heading_width - 1.0));
+        assert!(line_width < 800.0);
+
+        let (rtl, mixed, _) = position("Mixed");
+        let (_, isolated, _) = position("אבג");
+        assert!(!rtl);
+        assert!(mixed < isolated);
+    }
+
+    /// `text` laid out as a page, and the text of each visual line
+    fn wrapped_lines(text: &str, width: f32) -> (TextLayout, Vec<String>) {
+        let mut font_system = build_font_system(&FontSettings::default());
+        let content = PageContent::from_text(text);
+        let layout = build_page_layout(&mut font_system, &FontSettings::default(), &content, &page_key(1, width));
+        let lines = layout
+            .buffer
+            .layout_runs()
+            .map(|run| match (run.glyphs.first(), run.glyphs.last()) {
+                (Some(first), Some(last)) => run.text[first.start..last.end].to_string(),
+                _ => String::new(),
+            })
+            .collect();
+        for run in layout.buffer.layout_runs() {
+            assert!(run.line_w <= width + 0.5, "{} wide in {}", run.line_w, width);
+        }
+        (layout, lines)
+    }
+
+    #[test]
+    fn test_long_url_breaks_between_glyphs() {
+        let url = format!("https://example.com/{}", "abcdefghij".repeat(48));
+        let text = format!("See {} for details.", url);
+        let (layout, lines) = wrapped_lines(&text, 300.0);
+
+        assert!(lines.len() > 5);
+        // UAX #14 allows a break after the slash, and the rest is too long
+        // for any line
+        assert_eq!(lines[0], "See https://example.com/");
+        assert!(lines.iter().all(|line| !line.ends_with('-')));
+        assert_eq!(lines.concat().replace(' ', ""), text.replace(' ', ""));
+        // The caret, hit-testing and page height use the same lines
+        assert_eq!(layout.height(), lines.len() as f32 * 12.0);
+        let end = text.len() - 1;
+        let (x, top, height) = layout.caret_position(end).unwrap();
+        assert_eq!(top, layout.height() - 12.0);
+        assert_eq!(layout.hit(x, top + height / 2.0), Some(end));
+    }
+
+    #[test]
+    fn test_japanese_wraps_between_characters() {
+        let text = "吾輩は猫である。名前はまだ無い。どこで生れたかとんと見当がつかぬ。".repeat(4);
+        let (_, lines) = wrapped_lines(&text, 120.0);
+
+        assert!(lines.len() > 3);
+        assert_eq!(lines.concat(), text);
+        // Closing punctuation never starts a line
+        assert!(lines.iter().all(|line| !line.starts_with('。')));
+    }
+
+    #[test]
+    fn test_no_break_spaces_hold_numbers_together() {
+        let text = "Totals of 1\u{a0}000\u{a0}000 and 25\u{a0}km or 3\u{a0}500 items ".repeat(6);
+        let (_, lines) = wrapped_lines(&text, 90.0);
+
+        assert!(lines.len() > 6);
+        for line in &lines {
+            assert!(!line.starts_with(['\u{a0}', '0', 'k']), "{:?}", line);
+            assert!(!line.ends_with('\u{a0}'), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn test_soft_hyphens_break_words_and_show_hyphen() {
+        let word = "Donau\u{ad}dampf\u{ad}schiff\u{ad}fahrts\u{ad}gesell\u{ad}schaft";
+        let text = format!("Die {} und {} fahren", word, word);
+        let (layout, lines) = wrapped_lines(&text, 90.0);
+
+        assert!(lines.len() > 3);
+        // Lines break only at spaces and soft hyphens, and those ending in
+        // one show a hyphen there
+        let hyphenated = lines.iter().filter(|line| line.ends_with(SOFT_HYPHEN)).count();
+        assert!(hyphenated > 0);
+        assert_eq!(layout.soft_hyphens().len(), hyphenated);
+        for run in layout.buffer.layout_runs() {
+            let end = run.glyphs.last().unwrap().end;
+            let around = [run.text[..end].chars().next_back(), run.text[end..].chars().next()];
+            assert!(around.iter().any(|c| matches!(c, Some(' ' | SOFT_HYPHEN) | None)), "{:?}", around);
+        }
+        let broken = layout
+            .buffer
+            .layout_runs()
+            .filter(|run| run.glyphs.last().is_some_and(|glyph| run.text[..glyph.end].ends_with(SOFT_HYPHEN)));
+        for (hyphen, run) in layout.soft_hyphens().iter().zip(broken) {
+            assert_eq!(hyphen.line_top, run.line_top);
+            assert!(!hyphen.rtl);
+            assert_eq!(hyphen.font_size, 10.0);
+        }
+
+        // Unbroken, a soft hyphen takes no width
+        let (plain, _) = wrapped_lines(&text.replace(SOFT_HYPHEN, ""), 2000.0);
+        let (soft, _) = wrapped_lines(&text, 2000.0);
+        let width = |layout: &TextLayout| layout.buffer.layout_runs().next().unwrap().line_w;
+        assert!((width(&plain) - width(&soft)).abs() < 0.5);
+        assert!(soft.soft_hyphens().is_empty());
+
+        // and offsets still map to the source text
+        let offset = text.find("schaft").unwrap();
+        let (x, top, height) = layout.caret_position(offset).unwrap();
+        assert_eq!(layout.hit(x + 1.0, top + height / 2.0), Some(offset));
+    }
+
+    #[test]
+    fn test_monospace_punctuation_is_not_ligated() {
+        use crate::infrastructure::StyleSpan;
+
+        let code = TextStyle { monospace: true, ..TextStyle::default() };
+        let text = "if a != b => c";
+        let content = PageContent {
+            text: text.to_string(),
+            styles: vec![StyleSpan { range: 0..text.len(), style: code }],
+            ..PageContent::default()
+        };
+        let mut font_system = build_font_system(&FontSettings::default());
+        let layout = build_page_layout(&mut font_system, &FontSettings::default(), &content, &page_key(1, 800.0));
+
+        let run = layout.buffer.layout_runs().next().unwrap();
+        assert_eq!(run.text, "if a !\u{200c}= b =\u{200c}> c");
+        // Every character keeps its own glyph; the breaks take no width
+        let visible = run.glyphs.iter().filter(|glyph| glyph.w > 0.0).count();
+        assert_eq!(visible, text.len());
+
+        // Offsets in and out are those of the source text
+        let arrow = text.find('>').unwrap();
+        let (x, top, height) = layout.caret_position(arrow).unwrap();
+        assert_eq!(layout.hit(x + 1.0, top + height / 2.0), Some(arrow));
+        let (next_x, _, _) = layout.caret_position(arrow + 1).unwrap();
+        assert!(next_x > x);
+        let rects = layout.highlight_rects(text.find("=>").unwrap()..arrow + 1);
+        assert_eq!(rects.len(), 1);
+        assert!((rects[0].2 - 2.0 * (next_x - x)).abs() < 0.5);
+        assert_eq!(CaretLayout::visual_line(&layout, arrow), Some(0..text.len()));
+    }
+
+    #[test]
+    fn test_links_split_style_runs() {
+        use crate::infrastructure::{LinkSpan, StyleSpan};
+
+        let bold = TextStyle { bold: true, ..TextStyle::default() };
+        let content = PageContent {
+            text: "plain bold link tail".to_string(),
+            styles: vec![StyleSpan { range: 6..15, style: bold }],
+            links: vec![LinkSpan { range: 11..15, href: "/seen".to_string(), visited: true }],
+            ..PageContent::default()
+        };
+        assert_eq!(
+            style_runs(&content),
+            vec![
+                (0..6, TextStyle::default(), None),
+                (6..11, bold, None),
+                (11..15, bold, Some(true)),
+                (15..20, TextStyle::default(), None),
+            ]
+        );
+        assert!(style_runs(&PageContent::default()).is_empty());
+    }
+
+    #[test]
+    fn test_page_layout_cache_reuses_until_key_changes() {
+        let mut font_system = build_font_system(&FontSettings::default());
+        let fonts = FontSettings::default();
+        let content = PageContent::from_text("some text\nmore text");
+        let mut cache = PageLayoutCache::new();
+
+        let first = cache.get_or_build(page_key(1, 200.0), &content, &mut font_system, &fonts)
+            as *const TextLayout;
+        let again = cache.get_or_build(page_key(1, 200.0), &content, &mut font_system, &fonts)
+            as *const TextLayout;
+        assert_eq!(first, again);
+
+        let resized = cache.get_or_build(page_key(1, 300.0), &content, &mut font_system, &fonts);
+        assert_eq!(resized.buffer.size().0, Some(300.0));
+        let reloaded = cache.get_or_build(page_key(2, 300.0), &content, &mut font_system, &fonts);
+        assert!(reloaded.height() > 0.0);
+
+        cache.clear();
+        assert!(cache.get().is_none());
+    }
+
+    #[test]
+    fn test_visible_area_skips_lines_above_viewport() {
+        let mut font_system = build_font_system(&FontSettings::default());
+        let fonts = FontSettings::default();
+        let text = (0..100).map(|i| format!("line {}", i)).collect::<Vec<_>>().join("\n");
+        let content = PageContent::from_text(text.clone());
+        let mut cache = PageLayoutCache::new();
+        let layout = cache.get_or_build(page_key(1, 500.0), &content, &mut font_system, &fonts);
+        let line_height = layout.height() / 100.0;
+
+        // Scrolled so the viewport starts 60 lines into the page
+        let area = PageArea {
+            left: 0.0,
+            top: -60.0 * line_height,
+            bounds: TextBounds { left: 0, top: 0, right: 500, bottom: 400 },
+            default_color: GlyphColor::rgb(0, 0, 0),
+        };
+        let visible = cache.visible_area(&area).unwrap();
+        let first_run = visible.buffer.layout_runs().next().unwrap();
+        assert!(first_run.line_i > 0 && first_run.line_i < 60);
+        assert!((visible.top + first_run.line_top - (area.top + first_run.line_i as f32 * line_height)).abs() < 0.5);
+
+        cache.reset_scroll();
+        let layout = cache.get().unwrap();
+        assert_eq!(layout.offset_top(0), Some(0.0));
+        assert_eq!(layout.first_line_below(0.0), 0);
+    }
 }
@@ -0,0 +1,289 @@
+use super::color::srgb_to_linear;
+use crate::application::Theme as ThemeSetting;
+use crate::infrastructure::PageColors;
+use glyphon::Color as GlyphonColor;
+
+/// Settings key that turns on accessible output and the high-contrast theme
+pub const ACCESSIBILITY_SETTING: &str = "accessibility.enabled";
+
+/// Parse the accessibility setting, defaulting to off
+pub fn parse_accessibility(settings: &[(String, String)]) -> bool {
+    settings
+        .iter()
+        .find(|(key, _)| key == ACCESSIBILITY_SETTING)
+        .is_some_and(|(_, value)| matches!(value.trim(), "true" | "1" | "yes"))
+}
+
+/// An sRGB color with 8-bit channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    pub fn to_wgpu(self) -> wgpu::Color {
+        // Surface formats are sRGB, so convert to linear for clear colors
+        wgpu::Color {
+            r: srgb_to_linear(self.r) as f64,
+            g: srgb_to_linear(self.g) as f64,
+            b: srgb_to_linear(self.b) as f64,
+            a: 1.0,
+        }
+    }
+
+    pub fn to_glyphon(self) -> GlyphonColor {
+        GlyphonColor::rgb(self.r, self.g, self.b)
+    }
+
+    pub fn to_f32(self) -> [f32; 3] {
+        [
+            self.r as f32 / 255.0,
+            self.g as f32 / 255.0,
+            self.b as f32 / 255.0,
+        ]
+    }
+
+    /// Linear RGBA for vertex colors written to an sRGB surface
+    pub fn to_linear_rgba(self, alpha: f32) -> [f32; 4] {
+        [
+            srgb_to_linear(self.r),
+            srgb_to_linear(self.g),
+            srgb_to_linear(self.b),
+            alpha,
+        ]
+    }
+
+    /// CSS hex notation, e.g. `#1a2b3c`
+    pub fn to_css(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+
+    /// WCAG relative luminance
+    pub fn relative_luminance(self) -> f32 {
+        0.2126 * srgb_to_linear(self.r)
+            + 0.7152 * srgb_to_linear(self.g)
+            + 0.0722 * srgb_to_linear(self.b)
+    }
+
+    /// WCAG contrast ratio between two colors (1.0 to 21.0)
+    pub fn contrast_ratio(self, other: Color) -> f32 {
+        let a = self.relative_luminance();
+        let b = other.relative_luminance();
+        let (lighter, darker) = if a > b { (a, b) } else { (b, a) };
+        (lighter + 0.05) / (darker + 0.05)
+    }
+}
+
+/// Colors used to paint the browser chrome and page content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub background: Color,
+    pub chrome_background: Color,
+    pub text: Color,
+    pub dim_text: Color,
+    pub accent: Color,
+    pub link: Color,
+    pub visited_link: Color,
+    pub selection: Color,
+    /// Background of preformatted and code blocks
+    pub code_background: Color,
+    /// Page text size as a percentage of the default font size
+    pub text_scale: u16,
+}
+
+impl Theme {
+    pub const LIGHT: Theme = Theme {
+        background: Color::rgb(0xf2, 0xf2, 0xf2),
+        chrome_background: Color::rgb(0xff, 0xff, 0xff),
+        text: Color::rgb(0x1a, 0x1a, 0x1a),
+        dim_text: Color::rgb(0x5c, 0x5c, 0x5c),
+        accent: Color::rgb(0x1a, 0x5f, 0xb4),
+        link: Color::rgb(0x0b, 0x57, 0xd0),
+        visited_link: Color::rgb(0x6b, 0x2f, 0xa0),
+        selection: Color::rgb(0xb4, 0xd5, 0xfe),
+        code_background: Color::rgb(0xe6, 0xe6, 0xe9),
+        text_scale: 100,
+    };
+
+    pub const DARK: Theme = Theme {
+        background: Color::rgb(0x1e, 0x1e, 0x22),
+        chrome_background: Color::rgb(0x2b, 0x2b, 0x30),
+        text: Color::rgb(0xe8, 0xe8, 0xea),
+        dim_text: Color::rgb(0xa8, 0xa8, 0xb0),
+        accent: Color::rgb(0x8a, 0xb4, 0xf8),
+        link: Color::rgb(0x8a, 0xb4, 0xf8),
+        visited_link: Color::rgb(0xc5, 0x8a, 0xf9),
+        selection: Color::rgb(0x26, 0x4f, 0x78),
+        code_background: Color::rgb(0x2a, 0x2a, 0x30),
+        text_scale: 100,
+    };
+
+    /// Used in accessibility mode: white and yellow on black, with larger text
+    pub const HIGH_CONTRAST: Theme = Theme {
+        background: Color::rgb(0x00, 0x00, 0x00),
+        chrome_background: Color::rgb(0x14, 0x14, 0x14),
+        text: Color::rgb(0xff, 0xff, 0xff),
+        dim_text: Color::rgb(0xd8, 0xd8, 0xd8),
+        accent: Color::rgb(0xff, 0xff, 0x00),
+        link: Color::rgb(0xff, 0xff, 0x00),
+        visited_link: Color::rgb(0x7f, 0xff, 0xff),
+        selection: Color::rgb(0x00, 0x3c, 0x96),
+        code_background: Color::rgb(0x1c, 0x1c, 0x1c),
+        text_scale: 150,
+    };
+
+    pub fn light() -> Self {
+        Self::LIGHT
+    }
+
+    pub fn dark() -> Self {
+        Self::DARK
+    }
+
+    pub fn is_dark(&self) -> bool {
+        self.background.relative_luminance() < 0.5
+    }
+
+    /// Page font size for a default of `size`
+    pub fn text_size(&self, size: f32) -> f32 {
+        size * f32::from(self.text_scale) / 100.0
+    }
+
+    /// Colors for browser-generated pages (directory listings, placeholders)
+    pub fn page_colors(&self) -> PageColors {
+        PageColors {
+            background: self.background.to_css(),
+            text: self.text.to_css(),
+            link: self.link.to_css(),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::LIGHT
+    }
+}
+
+/// Which theme the user asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThemePreference {
+    Light,
+    Dark,
+    /// Follow the operating system where winit reports it
+    #[default]
+    Auto,
+}
+
+impl ThemePreference {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "light" => Some(Self::Light),
+            "dark" => Some(Self::Dark),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Dark => "dark",
+            Self::Auto => "auto",
+        }
+    }
+
+    /// Resolve to a concrete theme given the OS preference, if known
+    pub fn resolve(&self, system: Option<winit::window::Theme>) -> Theme {
+        match self {
+            Self::Light => Theme::LIGHT,
+            Self::Dark => Theme::DARK,
+            Self::Auto => match system {
+                Some(winit::window::Theme::Dark) => Theme::DARK,
+                _ => Theme::LIGHT,
+            },
+        }
+    }
+}
+
+impl From<ThemeSetting> for ThemePreference {
+    fn from(theme: ThemeSetting) -> Self {
+        match theme {
+            ThemeSetting::Light => Self::Light,
+            ThemeSetting::Dark => Self::Dark,
+            ThemeSetting::Auto => Self::Auto,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_readable(theme: &Theme) {
+        for surface in [theme.background, theme.chrome_background, theme.code_background] {
+            assert!(theme.text.contrast_ratio(surface) >= 7.0);
+            assert!(theme.dim_text.contrast_ratio(surface) >= 4.5);
+            assert!(theme.link.contrast_ratio(surface) >= 4.5);
+            assert!(theme.visited_link.contrast_ratio(surface) >= 4.5);
+        }
+        assert!(theme.text.contrast_ratio(theme.selection) >= 4.5);
+    }
+
+    #[test]
+    fn test_contrast_ratio_extremes() {
+        let black = Color::rgb(0, 0, 0);
+        let white = Color::rgb(255, 255, 255);
+        assert!((black.contrast_ratio(white) - 21.0).abs() < 0.01);
+        assert!((white.contrast_ratio(white) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_light_theme_contrast() {
+        assert_readable(&Theme::LIGHT);
+        assert!(!Theme::LIGHT.is_dark());
+    }
+
+    #[test]
+    fn test_dark_theme_contrast() {
+        assert_readable(&Theme::DARK);
+        assert!(Theme::DARK.is_dark());
+    }
+
+    #[test]
+    fn test_high_contrast_theme() {
+        let theme = Theme::HIGH_CONTRAST;
+        assert_readable(&theme);
+        for surface in [theme.background, theme.chrome_background, theme.code_background] {
+            assert!(theme.text.contrast_ratio(surface) >= 15.0);
+            assert!(theme.link.contrast_ratio(surface) >= 15.0);
+        }
+        assert_eq!(theme.text_size(14.0), 21.0);
+        assert_eq!(Theme::LIGHT.text_size(14.0), 14.0);
+
+        assert!(parse_accessibility(&[(ACCESSIBILITY_SETTING.to_string(), "true".to_string())]));
+        assert!(!parse_accessibility(&[(ACCESSIBILITY_SETTING.to_string(), "off".to_string())]));
+        assert!(!parse_accessibility(&[]));
+    }
+
+    #[test]
+    fn test_preference_resolution() {
+        assert_eq!(ThemePreference::parse("Dark"), Some(ThemePreference::Dark));
+        assert_eq!(ThemePreference::parse("sepia"), None);
+        assert_eq!(
+            ThemePreference::Auto.resolve(Some(winit::window::Theme::Dark)),
+            Theme::DARK
+        );
+        assert_eq!(ThemePreference::Auto.resolve(None), Theme::LIGHT);
+        assert_eq!(
+            ThemePreference::Light.resolve(Some(winit::window::Theme::Dark)),
+            Theme::LIGHT
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use crate::domain::{MemoryReporter, MemoryUsage, TabId, ValidatedUrl};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Width thumbnails are downscaled to, in pixels
+pub const THUMBNAIL_WIDTH: u32 = 320;
+/// Decoded thumbnails kept in memory before the least recently used is dropped
+pub const MAX_THUMBNAILS: usize = 24;
+
+static NEXT_THUMBNAIL_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A small RGBA image of a tab's content
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thumbnail {
+    /// Unique per capture, so GPU copies can be keyed by it
+    pub id: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed sRGB RGBA rows
+    pub rgba: Vec<u8>,
+}
+
+impl Thumbnail {
+    /// Box-filter an RGBA image down to at most `max_width` pixels wide,
+    /// keeping its aspect ratio
+    pub fn downscale(rgba: &[u8], width: u32, height: u32, max_width: u32) -> Self {
+        let scale = (width as f32 / max_width as f32).max(1.0);
+        let out_width = ((width as f32 / scale).round() as u32).max(1);
+        let out_height = ((height as f32 / scale).round() as u32).max(1);
+
+        let mut out = Vec::with_capacity((out_width * out_height * 4) as usize);
+        for y in 0..out_height {
+            let y0 = y * height / out_height;
+            let y1 = ((y + 1) * height / out_height).max(y0 + 1).min(height);
+            for x in 0..out_width {
+                let x0 = x * width / out_width;
+                let x1 = ((x + 1) * width / out_width).max(x0 + 1).min(width);
+
+                let mut sum = [0u32; 4];
+                for sy in y0..y1 {
+                    for sx in x0..x1 {
+                        let i = ((sy * width + sx) * 4) as usize;
+                        for (channel, total) in sum.iter_mut().enumerate() {
+                            *total += rgba[i + channel] as u32;
+                        }
+                    }
+                }
+                let count = (y1 - y0) * (x1 - x0);
+                out.extend(sum.iter().map(|total| (total / count) as u8));
+            }
+        }
+
+        Self {
+            id: NEXT_THUMBNAIL_ID.fetch_add(1, Ordering::Relaxed),
+            width: out_width,
+            height: out_height,
+            rgba: out,
+        }
+    }
+
+    /// Bytes of its RGBA pixels
+    pub fn byte_size(&self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+}
+
+/// Letter drawn in place of a thumbnail for tabs that never loaded
+pub fn placeholder_letter(url: Option<&ValidatedUrl>) -> char {
+    url.and_then(|url| url.host_str())
+        .map(|host| host.strip_prefix("www.").unwrap_or(host))
+        .and_then(|host| host.chars().next())
+        .map(|c| c.to_ascii_uppercase())
+        .unwrap_or('?')
+}
+
+/// Per-tab thumbnails, evicting the least recently used past a capacity
+pub struct ThumbnailCache {
+    capacity: usize,
+    thumbnails: HashMap<TabId, Thumbnail>,
+    /// Least recently used first
+    order: VecDeque<TabId>,
+    /// When a thumbnail was last read or added
+    last_used: Option<Instant>,
+}
+
+impl ThumbnailCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            thumbnails: HashMap::new(),
+            order: VecDeque::new(),
+            last_used: None,
+        }
+    }
+
+    /// Thumbnail of a tab, marking it recently used
+    pub fn get(&mut self, tab: TabId) -> Option<&Thumbnail> {
+        if self.thumbnails.contains_key(&tab) {
+            self.touch(tab);
+        }
+        self.thumbnails.get(&tab)
+    }
+
+    /// Thumbnails of several tabs at once, marking each recently used
+    pub fn get_all(&mut self, tabs: &[TabId]) -> Vec<Option<&Thumbnail>> {
+        for tab in tabs {
+            if self.thumbnails.contains_key(tab) {
+                self.touch(*tab);
+            }
+        }
+        tabs.iter().map(|tab| self.thumbnails.get(tab)).collect()
+    }
+
+    pub fn contains(&self, tab: TabId) -> bool {
+        self.thumbnails.contains_key(&tab)
+    }
+
+    pub fn insert(&mut self, tab: TabId, thumbnail: Thumbnail) {
+        self.thumbnails.insert(tab, thumbnail);
+        self.touch(tab);
+        while self.thumbnails.len() > self.capacity {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.thumbnails.remove(&oldest);
+        }
+    }
+
+    /// Drop a tab's thumbnail, e.g. once it navigates or closes
+    pub fn remove(&mut self, tab: TabId) {
+        self.thumbnails.remove(&tab);
+        self.order.retain(|id| *id != tab);
+    }
+
+    pub fn len(&self) -> usize {
+        self.thumbnails.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.thumbnails.is_empty()
+    }
+
+    /// Thumbnails kept and the bytes of their pixels
+    pub fn usage(&self) -> MemoryUsage {
+        let bytes = self.thumbnails.values().map(Thumbnail::byte_size).sum();
+        MemoryUsage::new(self.thumbnails.len(), bytes)
+    }
+
+    /// Drop the least recently used thumbnails until at most `target` bytes
+    /// are kept; returns the bytes freed
+    pub fn trim(&mut self, target: usize) -> usize {
+        let mut kept = self.usage().bytes;
+        let mut freed = 0;
+        while kept > target {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            let size = self.thumbnails.remove(&oldest).map_or(0, |thumbnail| thumbnail.byte_size());
+            kept -= size;
+            freed += size;
+        }
+        freed
+    }
+
+    fn touch(&mut self, tab: TabId) {
+        self.order.retain(|id| *id != tab);
+        self.order.push_back(tab);
+        self.last_used = Some(Instant::now());
+    }
+}
+
+impl Default for ThumbnailCache {
+    fn default() -> Self {
+        Self::new(MAX_THUMBNAILS)
+    }
+}
+
+/// Shared with the memory registry, which trims it with the other caches
+impl MemoryReporter for Mutex<ThumbnailCache> {
+    fn name(&self) -> &str {
+        "Thumbnails"
+    }
+
+    fn usage(&self) -> MemoryUsage {
+        self.lock().map(|cache| cache.usage()).unwrap_or_default()
+    }
+
+    fn last_used(&self) -> Option<Instant> {
+        self.lock().ok()?.last_used
+    }
+
+    fn trim(&self, target: usize) -> usize {
+        self.lock().map(|mut cache| cache.trim(target)).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, pixel: [u8; 4]) -> Vec<u8> {
+        pixel.repeat((width * height) as usize)
+    }
+
+    #[test]
+    fn test_downscale_averages_blocks() {
+        // Left half black, right half white
+        let mut rgba = Vec::new();
+        for _ in 0..4 {
+            rgba.extend(solid(2, 1, [0, 0, 0, 255]));
+            rgba.extend(solid(2, 1, [255, 255, 255, 255]));
+        }
+
+        let thumbnail = Thumbnail::downscale(&rgba, 4, 4, 2);
+        assert_eq!((thumbnail.width, thumbnail.height), (2, 2));
+        assert_eq!(&thumbnail.rgba[..8], &[0, 0, 0, 255, 255, 255, 255, 255]);
+
+        let small = Thumbnail::downscale(&solid(3, 2, [9; 4]), 3, 2, THUMBNAIL_WIDTH);
+        assert_eq!((small.width, small.height), (3, 2));
+        assert_ne!(small.id, thumbnail.id);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let mut cache = ThumbnailCache::new(2);
+        let (a, b, c) = (TabId::new(), TabId::new(), TabId::new());
+        let image = || Thumbnail::downscale(&solid(1, 1, [0; 4]), 1, 1, 1);
+
+        cache.insert(a, image());
+        cache.insert(b, image());
+        assert!(cache.get(a).is_some());
+        cache.insert(c, image());
+
+        assert!(cache.contains(a));
+        assert!(!cache.contains(b));
+        assert!(cache.contains(c));
+
+        cache.remove(a);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_trim_drops_least_recently_used_to_the_target() {
+        let mut cache = ThumbnailCache::new(4);
+        let (a, b, c) = (TabId::new(), TabId::new(), TabId::new());
+        let image = || Thumbnail::downscale(&solid(2, 2, [0; 4]), 2, 2, 2);
+
+        cache.insert(a, image());
+        cache.insert(b, image());
+        cache.insert(c, image());
+        assert!(cache.get(a).is_some());
+        assert_eq!(cache.usage(), MemoryUsage::new(3, 48));
+
+        // b then c are the least recently used
+        assert_eq!(cache.trim(20), 32);
+        assert!(cache.contains(a));
+        assert!(!cache.contains(b) && !cache.contains(c));
+        assert_eq!(cache.trim(16), 0);
+    }
+
+    #[test]
+    fn test_placeholder_letter() {
+        let url = ValidatedUrl::parse("https://www.example.com/").unwrap();
+        assert_eq!(placeholder_letter(Some(&url)), 'E');
+        assert_eq!(placeholder_letter(None), '?');
+    }
+}
@@ -0,0 +1,381 @@
+use super::chrome::ChromeLayout;
+use super::theme::{Color, Theme};
+use crate::application::{Notification, NotificationAction, NotificationSeverity, DEFAULT_NOTIFICATION_DURATION};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Toasts on screen at once; later ones wait for a place
+pub const MAX_VISIBLE_TOASTS: usize = 3;
+/// Toast size, gap and corner radius in logical pixels
+pub const TOAST_WIDTH: f32 = 320.0;
+pub const TOAST_HEIGHT: f32 = 52.0;
+pub const TOAST_MARGIN: f32 = 12.0;
+pub const TOAST_RADIUS: f32 = 8.0;
+/// Toast text size in logical pixels
+pub const TOAST_TEXT_SIZE: f32 = 13.0;
+/// Time a toast takes to slide in or out
+const SLIDE_DURATION: Duration = Duration::from_millis(200);
+
+/// A notification on screen, counting down to its dismissal
+#[derive(Debug, Clone)]
+struct ShownToast {
+    notification: Notification,
+    shown_at: Instant,
+    /// Time left on screen as of `counting_since`
+    remaining: Duration,
+    /// When the countdown last resumed; `None` while paused
+    counting_since: Option<Instant>,
+    /// When it began sliding out
+    leaving_at: Option<Instant>,
+}
+
+impl ShownToast {
+    fn expiry(&self) -> Option<Instant> {
+        self.counting_since.map(|since| since + self.remaining)
+    }
+
+    /// How far the toast has slid in, from 0 off screen to 1 in place
+    fn slide(&self, now: Instant, animate: bool) -> f32 {
+        if !animate {
+            return 1.0;
+        }
+        let elapsed = |since: Instant| {
+            (now.saturating_duration_since(since).as_secs_f32() / SLIDE_DURATION.as_secs_f32()).min(1.0)
+        };
+        let progress = match self.leaving_at {
+            Some(at) => 1.0 - elapsed(at),
+            None => elapsed(self.shown_at),
+        };
+        // Ease out: fast at first, settling into place
+        1.0 - (1.0 - progress).powi(3)
+    }
+}
+
+/// A toast placed in the window, in physical pixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedToast<'a> {
+    pub notification: &'a Notification,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl PlacedToast<'_> {
+    /// The message, and below it the key for its action
+    pub fn text(&self) -> String {
+        match &self.notification.action {
+            Some(action) => format!(
+                "{}\nPress {} to {}",
+                self.notification.message,
+                action.key.to_ascii_uppercase(),
+                action.label
+            ),
+            None => self.notification.message.clone(),
+        }
+    }
+}
+
+/// Color of the edge of a toast, by how much it matters
+pub fn severity_color(severity: NotificationSeverity, theme: &Theme) -> Color {
+    match severity {
+        NotificationSeverity::Info => theme.accent,
+        NotificationSeverity::Success => Color::rgb(0x2e, 0x9e, 0x5b),
+        NotificationSeverity::Warning => Color::rgb(0xe0, 0x9b, 0x1a),
+        NotificationSeverity::Error => Color::rgb(0xd9, 0x3a, 0x3a),
+    }
+}
+
+/// A window's notifications, stacked in the bottom-right corner. Each
+/// dismisses itself after a while, unless the pointer rests on the stack.
+#[derive(Debug)]
+pub struct ToastManager {
+    /// Oldest first, drawn from the bottom up
+    shown: Vec<ShownToast>,
+    queued: VecDeque<Notification>,
+    duration: Duration,
+    hovered: bool,
+    animate: bool,
+}
+
+impl Default for ToastManager {
+    fn default() -> Self {
+        Self {
+            shown: Vec::new(),
+            queued: VecDeque::new(),
+            duration: DEFAULT_NOTIFICATION_DURATION,
+            hovered: false,
+            animate: true,
+        }
+    }
+}
+
+impl ToastManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long toasts shown from now on stay
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+    }
+
+    /// Show and hide toasts at once instead of sliding them
+    pub fn set_reduced_motion(&mut self, reduced: bool) {
+        self.animate = !reduced;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shown.is_empty() && self.queued.is_empty()
+    }
+
+    /// Toasts on screen, including those sliding out
+    pub fn shown(&self) -> impl Iterator<Item = &Notification> {
+        self.shown.iter().map(|toast| &toast.notification)
+    }
+
+    /// Notifications waiting for a place on screen
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// Show `notification`, or queue it while the stack is full
+    pub fn push(&mut self, notification: Notification, now: Instant) {
+        self.queued.push_back(notification);
+        self.fill(now);
+    }
+
+    /// Pause every countdown while the pointer is on the stack, and resume
+    /// them when it leaves
+    pub fn set_hovered(&mut self, hovered: bool, now: Instant) {
+        if hovered == self.hovered {
+            return;
+        }
+        self.hovered = hovered;
+        for toast in self.shown.iter_mut().filter(|toast| toast.leaving_at.is_none()) {
+            if hovered {
+                if let Some(since) = toast.counting_since.take() {
+                    toast.remaining = toast.remaining.saturating_sub(now.saturating_duration_since(since));
+                }
+            } else {
+                toast.counting_since = Some(now);
+            }
+        }
+    }
+
+    /// Dismiss the toasts whose time is up, drop those done sliding out and
+    /// show queued ones in their place; returns whether anything changed
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let mut changed = false;
+        for toast in &mut self.shown {
+            if let Some(expiry) = toast.expiry().filter(|expiry| *expiry <= now && toast.leaving_at.is_none()) {
+                toast.leaving_at = Some(expiry);
+                changed = true;
+            }
+        }
+        let animate = self.animate;
+        let before = self.shown.len();
+        self.shown.retain(|toast| {
+            toast
+                .leaving_at
+                .is_none_or(|at| animate && now.saturating_duration_since(at) < SLIDE_DURATION)
+        });
+        changed |= self.shown.len() != before;
+        self.fill(now) || changed
+    }
+
+    /// Act on the toast showing `key`, dismissing it; keys match regardless
+    /// of case
+    pub fn press_key(&mut self, key: char, now: Instant) -> Option<NotificationAction> {
+        let toast = self.shown.iter_mut().rev().find(|toast| {
+            toast.leaving_at.is_none()
+                && toast
+                    .notification
+                    .action
+                    .as_ref()
+                    .is_some_and(|action| action.key.eq_ignore_ascii_case(&key))
+        })?;
+        toast.leaving_at = Some(now);
+        toast.notification.action.clone()
+    }
+
+    /// When toasts next change on their own: now while one slides, else
+    /// the next dismissal
+    pub fn next_change(&self, now: Instant) -> Option<Instant> {
+        let sliding = self.animate
+            && self.shown.iter().any(|toast| {
+                toast.leaving_at.is_some() || now.saturating_duration_since(toast.shown_at) < SLIDE_DURATION
+            });
+        if sliding {
+            return Some(now);
+        }
+        self.shown
+            .iter()
+            .filter(|toast| toast.leaving_at.is_none())
+            .filter_map(ShownToast::expiry)
+            .min()
+    }
+
+    /// Where each shown toast is drawn at `now`, oldest at the bottom
+    pub fn layout(&self, chrome: &ChromeLayout, now: Instant) -> Vec<PlacedToast<'_>> {
+        let scale = chrome.scale;
+        let margin = TOAST_MARGIN * scale;
+        let width = (TOAST_WIDTH * scale).min(chrome.content_width - margin * 2.0).max(0.0);
+        let height = TOAST_HEIGHT * scale;
+        let right = chrome.content_left + chrome.content_width - margin;
+        self.shown
+            .iter()
+            .enumerate()
+            .map(|(index, toast)| {
+                let offscreen = (1.0 - toast.slide(now, self.animate)) * (width + margin * 2.0);
+                PlacedToast {
+                    notification: &toast.notification,
+                    x: right - width + offscreen,
+                    y: chrome.height - (height + margin) * (index + 1) as f32,
+                    width,
+                    height,
+                }
+            })
+            .collect()
+    }
+
+    /// Whether a point is on one of the toasts
+    pub fn contains(&self, chrome: &ChromeLayout, x: f32, y: f32, now: Instant) -> bool {
+        self.layout(chrome, now).iter().any(|toast| {
+            x >= toast.x && x < toast.x + toast.width && y >= toast.y && y < toast.y + toast.height
+        })
+    }
+
+    /// Move queued notifications into free places on screen
+    fn fill(&mut self, now: Instant) -> bool {
+        let mut filled = false;
+        while self.shown.len() < MAX_VISIBLE_TOASTS {
+            let Some(notification) = self.queued.pop_front() else {
+                break;
+            };
+            self.shown.push(ShownToast {
+                notification,
+                shown_at: now,
+                remaining: self.duration,
+                counting_since: (!self.hovered).then_some(now),
+                leaving_at: None,
+            });
+            filled = true;
+        }
+        filled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(message: &str) -> Notification {
+        Notification::new(message, NotificationSeverity::Info)
+    }
+
+    fn messages(toasts: &ToastManager) -> Vec<&str> {
+        toasts.shown().map(|notification| notification.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_at_most_three_shown_with_the_rest_queued() {
+        let start = Instant::now();
+        let mut toasts = ToastManager::new();
+        toasts.set_duration(Duration::from_secs(5));
+        for message in ["one", "two", "three", "four"] {
+            toasts.push(info(message), start);
+        }
+        assert_eq!(messages(&toasts), ["one", "two", "three"]);
+        assert_eq!(toasts.queued(), 1);
+        assert_eq!(toasts.next_change(start), Some(start), "sliding in");
+
+        let settled = start + SLIDE_DURATION;
+        assert_eq!(toasts.next_change(settled), Some(start + Duration::from_secs(5)));
+
+        // Time up: all three slide out, then the fourth takes a place
+        let expired = start + Duration::from_secs(5);
+        assert!(toasts.tick(expired));
+        assert_eq!(messages(&toasts).len(), 3);
+        assert_eq!(toasts.next_change(expired), Some(expired));
+        assert!(toasts.tick(expired + SLIDE_DURATION));
+        assert_eq!(messages(&toasts), ["four"]);
+        assert_eq!(toasts.queued(), 0);
+
+        assert!(toasts.tick(expired + SLIDE_DURATION * 2 + Duration::from_secs(5)));
+        assert!(!toasts.tick(expired + SLIDE_DURATION * 3 + Duration::from_secs(5)));
+        assert!(toasts.is_empty());
+    }
+
+    #[test]
+    fn test_hover_pauses_dismissal() {
+        let start = Instant::now();
+        let mut toasts = ToastManager::new();
+        toasts.set_duration(Duration::from_secs(4));
+        toasts.push(info("saved"), start);
+
+        toasts.set_hovered(true, start + Duration::from_secs(1));
+        assert_eq!(toasts.next_change(start + Duration::from_secs(1)), None);
+        assert!(!toasts.tick(start + Duration::from_secs(60)));
+        assert_eq!(messages(&toasts), ["saved"]);
+
+        // Three seconds were left when the pointer arrived
+        let left = start + Duration::from_secs(61);
+        toasts.set_hovered(false, left);
+        assert_eq!(toasts.next_change(left), Some(left + Duration::from_secs(3)));
+        assert!(!toasts.tick(left + Duration::from_secs(2)));
+        assert!(toasts.tick(left + Duration::from_secs(3)));
+
+        let gone = left + Duration::from_secs(3) + SLIDE_DURATION;
+        assert!(toasts.tick(gone));
+        assert!(toasts.is_empty());
+
+        // Shown while hovered, a toast waits for the pointer to leave
+        toasts.set_hovered(true, gone);
+        toasts.push(info("printed"), gone);
+        assert_eq!(toasts.next_change(gone + SLIDE_DURATION), None);
+    }
+
+    #[test]
+    fn test_action_key_dismisses_its_toast() {
+        let start = Instant::now();
+        let mut toasts = ToastManager::new();
+        toasts.set_reduced_motion(true);
+        let saved = Notification::new("Saved", NotificationSeverity::Success).with_action(
+            'o',
+            "open downloads",
+            "navigator://downloads",
+        );
+        toasts.push(saved.clone(), start);
+        toasts.push(info("plain"), start);
+
+        assert_eq!(toasts.press_key('x', start), None);
+        assert_eq!(toasts.press_key('O', start), saved.action);
+        assert_eq!(toasts.press_key('o', start), None, "acted on once");
+        assert!(toasts.tick(start));
+        assert_eq!(messages(&toasts), ["plain"]);
+    }
+
+    #[test]
+    fn test_stack_in_the_bottom_right_corner() {
+        let start = Instant::now();
+        let chrome = ChromeLayout::compute(800.0, 600.0, 1.0, 1.0);
+        let mut toasts = ToastManager::new();
+        toasts.push(info("first"), start);
+        toasts.push(info("second").with_action('o', "open", "navigator://downloads"), start);
+
+        // Sliding in from the right edge
+        let entering = toasts.layout(&chrome, start);
+        assert!(entering[0].x >= chrome.content_width);
+
+        let placed = toasts.layout(&chrome, start + SLIDE_DURATION);
+        let right = chrome.content_left + chrome.content_width - TOAST_MARGIN;
+        assert_eq!(placed[0].x + placed[0].width, right);
+        assert_eq!(placed[0].y + placed[0].height, 600.0 - TOAST_MARGIN);
+        assert!(placed[1].y + placed[1].height < placed[0].y, "newer toasts stack above");
+        assert_eq!(placed[1].text(), "second\nPress O to open");
+
+        assert!(toasts.contains(&chrome, right - 1.0, 600.0 - TOAST_MARGIN - 1.0, start + SLIDE_DURATION));
+        assert!(!toasts.contains(&chrome, 10.0, 10.0, start + SLIDE_DURATION));
+    }
+}
@@ -1,33 +1,190 @@
 use winit::{
-    event_loop::EventLoop,
-    window::Window,
-    dpi::LogicalSize,
+    event_loop::ActiveEventLoop,
+    window::{Icon, Window, WindowId},
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
+    monitor::MonitorHandle,
 };
+use super::screenshot::Screenshot;
+use crate::domain::WindowGeometry;
 use anyhow::Result;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+
+/// Icon the window manager shows for browser windows
+const WINDOW_ICON_PNG: &[u8] = include_bytes!("../../icons/icon.png");
 
 /// Browser window manager
 pub struct BrowserWindow {
     window: Arc<Window>,
 }
 
+/// A monitor's name and the area it covers, in physical pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorArea {
+    pub name: Option<String>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MonitorArea {
+    fn of(monitor: &MonitorHandle) -> Self {
+        let position = monitor.position();
+        let size = monitor.size();
+        Self {
+            name: monitor.name(),
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+        }
+    }
+}
+
+/// Where to put a window saved at `geometry`: where it was if its monitor
+/// is still connected, else centered on the primary monitor. `None` leaves
+/// it to the system.
+pub fn saved_position(
+    geometry: &WindowGeometry,
+    monitors: &[MonitorArea],
+    primary: Option<&MonitorArea>,
+) -> Option<(i32, i32)> {
+    let connected = match &geometry.monitor {
+        Some(name) => monitors.iter().any(|monitor| monitor.name.as_ref() == Some(name)),
+        None => monitors.iter().any(|monitor| {
+            (monitor.x..monitor.x.saturating_add_unsigned(monitor.width)).contains(&geometry.x)
+                && (monitor.y..monitor.y.saturating_add_unsigned(monitor.height)).contains(&geometry.y)
+        }),
+    };
+    if connected {
+        return Some((geometry.x, geometry.y));
+    }
+    let primary = primary?;
+    let centered =
+        |origin: i32, space: u32, length: u32| origin.saturating_add_unsigned(space.saturating_sub(length) / 2);
+    Some((
+        centered(primary.x, primary.width, geometry.width),
+        centered(primary.y, primary.height, geometry.height),
+    ))
+}
+
+/// `saved_position` on the monitors connected now
+fn position_on_monitors(event_loop: &ActiveEventLoop, geometry: &WindowGeometry) -> Option<(i32, i32)> {
+    let monitors: Vec<MonitorArea> =
+        event_loop.available_monitors().map(|monitor| MonitorArea::of(&monitor)).collect();
+    let primary = event_loop.primary_monitor().map(|monitor| MonitorArea::of(&monitor));
+    saved_position(geometry, &monitors, primary.as_ref())
+}
+
+/// The embedded icon, decoded once for every window
+fn window_icon() -> Option<Icon> {
+    static ICON: OnceLock<Option<Icon>> = OnceLock::new();
+    ICON.get_or_init(|| {
+        let decoded = Screenshot::from_png(WINDOW_ICON_PNG)
+            .and_then(|image| Ok(Icon::from_rgba(image.rgba, image.width, image.height)?));
+        decoded.inspect_err(|e| tracing::warn!("Failed to load the window icon: {}", e)).ok()
+    })
+    .clone()
+}
+
 impl BrowserWindow {
-    pub fn new(event_loop: &EventLoop<()>) -> Result<Self> {
-        let window_attributes = Window::default_attributes()
+    /// Create a window, taking the focus unless `active` is off, where
+    /// `geometry` puts it if given
+    pub fn new(event_loop: &ActiveEventLoop, active: bool, geometry: Option<&WindowGeometry>) -> Result<Self> {
+        let mut window_attributes = Window::default_attributes()
             .with_title("Navigator - Custom Browser")
+            .with_active(active)
             .with_inner_size(LogicalSize::new(1400.0, 900.0))
             .with_min_inner_size(LogicalSize::new(800.0, 600.0));
+        if let Some(geometry) = geometry {
+            window_attributes =
+                window_attributes.with_inner_size(PhysicalSize::new(geometry.width, geometry.height));
+            if let Some((x, y)) = position_on_monitors(event_loop, geometry) {
+                window_attributes = window_attributes.with_position(PhysicalPosition::new(x, y));
+            }
+        }
 
         let window = Arc::new(event_loop.create_window(window_attributes)?);
+        window.set_window_icon(window_icon());
 
         Ok(Self { window })
     }
 
+    /// Move and size a window opened before it was known where it goes
+    pub fn restore_geometry(&self, event_loop: &ActiveEventLoop, geometry: &WindowGeometry) {
+        let _ = self.window.request_inner_size(PhysicalSize::new(geometry.width, geometry.height));
+        if let Some((x, y)) = position_on_monitors(event_loop, geometry) {
+            self.window.set_outer_position(PhysicalPosition::new(x, y));
+        }
+    }
+
     pub fn window(&self) -> Arc<Window> {
         self.window.clone()
     }
 
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
     pub fn request_redraw(&self) {
         self.window.request_redraw();
     }
+
+    /// Where the window is on screen; `None` where the system does not say
+    pub fn geometry(&self) -> Option<WindowGeometry> {
+        let position = self.window.outer_position().ok()?;
+        let size = self.window.inner_size();
+        Some(WindowGeometry {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+            monitor: self.window.current_monitor().and_then(|monitor| monitor.name()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, x: i32) -> MonitorArea {
+        MonitorArea {
+            name: Some(name.to_string()),
+            x,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        }
+    }
+
+    #[test]
+    fn test_missing_monitor_falls_back_to_the_primary() {
+        let geometry = WindowGeometry {
+            x: 2000,
+            y: 100,
+            width: 1200,
+            height: 800,
+            monitor: Some("HDMI-1".to_string()),
+        };
+        let laptop = monitor("eDP-1", 0);
+        let both = [laptop.clone(), monitor("HDMI-1", 1920)];
+        assert_eq!(saved_position(&geometry, &both, Some(&laptop)), Some((2000, 100)));
+
+        let alone = [laptop.clone()];
+        assert_eq!(saved_position(&geometry, &alone, Some(&laptop)), Some((360, 140)));
+        assert_eq!(saved_position(&geometry, &alone, None), None);
+
+        // Unnamed monitors are matched by the saved position
+        let unnamed = WindowGeometry { monitor: None, ..geometry };
+        assert_eq!(saved_position(&unnamed, &both, Some(&laptop)), Some((2000, 100)));
+        assert_eq!(saved_position(&unnamed, &alone, Some(&laptop)), Some((360, 140)));
+    }
+
+    #[test]
+    fn test_window_icon_is_embedded() {
+        let icon = Screenshot::from_png(WINDOW_ICON_PNG).unwrap();
+        assert_eq!((icon.width, icon.height), (128, 123));
+        assert!(window_icon().is_some());
+    }
 }
@@ -0,0 +1,42 @@
+//! A controller over a temporary profile leaves nothing on disk
+
+use navigator::application::{BrowserController, DownloadOutcome, FileDownload};
+use navigator::domain::ValidatedUrl;
+use navigator::infrastructure::TEMPORARY_PROFILE_PREFIX;
+use std::path::PathBuf;
+
+/// Temporary profiles in the system's temporary directory
+fn temporary_profiles() -> Vec<PathBuf> {
+    std::fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(TEMPORARY_PROFILE_PREFIX))
+        .map(|entry| entry.path())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_ephemeral_controller_leaves_nothing_behind() {
+    let before = temporary_profiles();
+    let controller = BrowserController::ephemeral().await.unwrap();
+    let tab = controller.open_tab(None).await.unwrap();
+    let download = FileDownload {
+        url: ValidatedUrl::parse("https://example.com/notes.txt").unwrap(),
+        file_name: "notes.txt".to_string(),
+        content_type: Some("text/plain".to_string()),
+        contents: b"kept for now".to_vec(),
+    };
+    let DownloadOutcome::Saved(saved) = controller.download_file(tab, download).await.unwrap() else {
+        panic!("a plain text download needs no confirmation");
+    };
+
+    let profile = temporary_profiles()
+        .into_iter()
+        .find(|profile| !before.contains(profile) && saved.path.starts_with(profile))
+        .expect("downloads go into the temporary profile");
+    assert_eq!(std::fs::read(&saved.path).unwrap(), b"kept for now");
+
+    drop(controller);
+    assert!(!temporary_profiles().contains(&profile));
+    assert!(!saved.path.exists());
+}
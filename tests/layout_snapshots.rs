@@ -0,0 +1,85 @@
+//! Lays out the HTML fixtures in `tests/fixtures/layout` and compares the
+//! result with the `.snap` file next to each one. Runs without a GPU: it
+//! stops at the laid-out text, before any glyphs are shaped.
+//!
+//! Set `UPDATE_SNAPSHOTS=1` to write the snapshots afresh after an
+//! intended change, then review the diff.
+
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use markup5ever_rcdom::RcDom;
+use navigator::infrastructure::{layout_snapshot, LayoutBuilder};
+use std::path::{Path, PathBuf};
+
+const UPDATE_ENV: &str = "UPDATE_SNAPSHOTS";
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/layout")
+}
+
+fn snapshot(html: &str) -> String {
+    let dom = parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut html.as_bytes())
+        .unwrap();
+    layout_snapshot(&LayoutBuilder::new().build(&dom))
+}
+
+#[test]
+fn test_layout_matches_snapshots() {
+    let update = std::env::var_os(UPDATE_ENV).is_some_and(|value| value != "0");
+    let mut fixtures: Vec<PathBuf> = std::fs::read_dir(fixtures())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "html"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty(), "no fixtures in {}", self::fixtures().display());
+
+    let mut mismatched = Vec::new();
+    for fixture in &fixtures {
+        let actual = snapshot(&std::fs::read_to_string(fixture).unwrap());
+        let path = fixture.with_extension("snap");
+        if update {
+            std::fs::write(&path, &actual).unwrap();
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                eprintln!("{} differs:\n{}", name, line_diff(&expected, &actual));
+                mismatched.push(name);
+            }
+            Err(_) => mismatched.push(format!("{} (missing)", path.display())),
+        }
+    }
+    assert!(
+        mismatched.is_empty(),
+        "layout changed for {}; rerun with {}=1 if that was intended",
+        mismatched.join(", "),
+        UPDATE_ENV
+    );
+}
+
+/// Lines only in `expected` marked `-`, lines only in `actual` marked `+`,
+/// compared position by position
+fn line_diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut diff = String::new();
+    for index in 0..expected.len().max(actual.len()) {
+        match (expected.get(index), actual.get(index)) {
+            (Some(old), Some(new)) if old == new => {}
+            (old, new) => {
+                if let Some(old) = old {
+                    diff.push_str(&format!("-{}\n", old));
+                }
+                if let Some(new) = new {
+                    diff.push_str(&format!("+{}\n", new));
+                }
+            }
+        }
+    }
+    diff
+}
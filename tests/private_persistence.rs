@@ -0,0 +1,118 @@
+//! Nothing a private tab does is stored: browsing in one leaves every
+//! table and every file of the profile as it was
+
+use navigator::application::{
+    BrowserController, BrowserState, Repositories, SessionDownloads, RESTORE_SESSION_SETTING,
+};
+use navigator::domain::{StorageChange, StorageKind};
+use navigator::infrastructure::{
+    DefaultSecurityService, DefaultUserScriptService, DefaultUserStyleService, LocalDownloadWriter, ProfileDir,
+    ServoRenderer, SqliteDatabase,
+};
+use sqlx::Row;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Serve the same small page at every path, until the test ends
+async fn serve_pages() -> u16 {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let _ = stream.read(&mut [0; 4096]).await;
+            let body = "<html><head><title>Private</title></head><body><p>Not for keeping</p></body></html>";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+    port
+}
+
+/// Rows in every table of the database
+async fn row_counts(db: &SqliteDatabase) -> BTreeMap<String, i64> {
+    let tables = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")
+        .fetch_all(db.get_pool())
+        .await
+        .unwrap();
+    let mut counts = BTreeMap::new();
+    for table in tables {
+        let name: String = table.get("name");
+        let count: i64 = sqlx::query(&format!("SELECT COUNT(*) AS count FROM \"{}\"", name))
+            .fetch_one(db.get_pool())
+            .await
+            .unwrap()
+            .get("count");
+        counts.insert(name, count);
+    }
+    counts
+}
+
+/// Every file under `directory`; the database of a temporary profile is in memory
+fn files(directory: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(directory).unwrap().filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            found.extend(files(&path));
+        } else {
+            found.push(path);
+        }
+    }
+    found.sort();
+    found
+}
+
+#[tokio::test]
+async fn test_private_tab_leaves_the_profile_unchanged() {
+    let profile = Arc::new(ProfileDir::temporary().unwrap());
+    let db = Arc::new(SqliteDatabase::new(profile.database_path()).await.unwrap());
+    let (page_cache, history) = (db.clone(), db.clone());
+    let controller = BrowserController::new(
+        BrowserState::new(),
+        Repositories::shared(db.clone()),
+        Arc::new(DefaultSecurityService::new()),
+        Arc::new(DefaultUserStyleService::new()),
+        Arc::new(DefaultUserScriptService::new()),
+        move || ServoRenderer::new().with_page_cache(page_cache.clone()).with_history(history.clone()),
+    )
+    .with_downloads(
+        Arc::new(SessionDownloads::new(db.clone())),
+        Arc::new(LocalDownloadWriter::new().with_profile(profile.clone())),
+    );
+
+    // Features that store as a page is browsed are all on
+    controller.update_setting(RESTORE_SESSION_SETTING, "true").await.unwrap();
+    let mut script = controller
+        .add_user_script("Counter", &["*://*/*"], "GM_setValue('visits', (GM_getValue('visits') || 0) + 1);")
+        .await
+        .unwrap();
+    script.allow_private = true;
+    controller.update_user_script(&script).await.unwrap();
+
+    let port = serve_pages().await;
+    let rows = row_counts(&db).await;
+    let stored = files(profile.directory());
+
+    controller.state().set_private_mode(true);
+    let tab = controller.open_tab(None).await.unwrap();
+    controller.navigate(tab, &format!("http://127.0.0.1:{}/first", port)).await.unwrap();
+    controller.navigate(tab, &format!("http://127.0.0.1:{}/second", port)).await.unwrap();
+    controller.back(tab).await.unwrap();
+    controller.reload(tab).await.unwrap();
+    for kind in [StorageKind::Local, StorageKind::Session] {
+        let change = StorageChange::Set { key: "seen".to_string(), value: "yes".to_string() };
+        controller.update_storage(tab, kind, change).await.unwrap();
+    }
+    controller.save_session().await.unwrap();
+    controller.close_tab(tab).await.unwrap();
+    controller.shutdown().await;
+
+    assert_eq!(row_counts(&db).await, rows);
+    assert_eq!(files(profile.directory()), stored);
+}
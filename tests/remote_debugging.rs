@@ -0,0 +1,155 @@
+//! Drives a headless browser end to end through the remote debugging API
+
+use navigator::application::{BrowserController, BrowserState, Repositories};
+use navigator::infrastructure::{
+    DefaultSecurityService, DefaultUserScriptService, DefaultUserStyleService, ServoRenderer, SqliteDatabase,
+};
+use navigator::remote::{RemoteDebugServer, TabInfo};
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+struct Client {
+    http: reqwest::Client,
+    base: String,
+    token: String,
+}
+
+impl Client {
+    async fn send(&self, method: Method, path: &str, body: Option<Value>) -> (StatusCode, Vec<u8>) {
+        let mut request = self
+            .http
+            .request(method, format!("{}{}", self.base, path))
+            .bearer_auth(&self.token);
+        if let Some(body) = body {
+            request = request
+                .header("content-type", "application/json")
+                .body(body.to_string());
+        }
+        let response = request.send().await.unwrap();
+        (response.status(), response.bytes().await.unwrap().to_vec())
+    }
+
+    async fn json<T: serde::de::DeserializeOwned>(&self, method: Method, path: &str, body: Option<Value>) -> T {
+        let (status, bytes) = self.send(method, path, body).await;
+        assert!(status.is_success(), "{}: {}", status, String::from_utf8_lossy(&bytes));
+        serde_json::from_slice(&bytes).unwrap()
+    }
+}
+
+/// A browser whose tabs run `engine`, served on a free port
+async fn serve(
+    engine: impl Fn() -> ServoRenderer + Send + Sync + 'static,
+) -> (Arc<BrowserController<ServoRenderer>>, RemoteDebugServer, Client) {
+    let db = Arc::new(SqliteDatabase::new(":memory:").await.unwrap());
+    let controller = Arc::new(BrowserController::new(
+        BrowserState::new(),
+        Repositories::shared(db),
+        Arc::new(DefaultSecurityService::new()),
+        Arc::new(DefaultUserStyleService::new()),
+        Arc::new(DefaultUserScriptService::new()),
+        engine,
+    ));
+    let server = RemoteDebugServer::start(controller.clone(), 0).await.unwrap();
+    let client = Client {
+        http: reqwest::Client::new(),
+        base: format!("http://{}", server.addr()),
+        token: server.token().to_string(),
+    };
+    (controller, server, client)
+}
+
+#[tokio::test]
+async fn test_drive_headless_browser_through_api() {
+    let (controller, server, client) = serve(ServoRenderer::new).await;
+    assert!(server.addr().ip().is_loopback());
+
+    let anonymous = reqwest::get(format!("{}/tabs", client.base)).await.unwrap();
+    assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+
+    let tab: TabInfo = client.json(Method::POST, "/tabs", None).await;
+    let tabs: Vec<TabInfo> = client.json(Method::GET, "/tabs", None).await;
+    assert_eq!(tabs, vec![tab.clone()]);
+
+    let page = "data:text/html,<title>Remote</title><p>Hello%20from%20the%20API</p>";
+    let navigated: TabInfo = client
+        .json(Method::POST, &format!("/tabs/{}/navigate", tab.id), Some(json!({ "url": page })))
+        .await;
+    assert_eq!(navigated.title, "Remote");
+
+    let (status, text) = client.send(Method::GET, &format!("/tabs/{}/text", tab.id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(String::from_utf8(text).unwrap().contains("Hello from the API"));
+
+    let tree: Value = client
+        .json(Method::GET, &format!("/tabs/{}/accessibility", tab.id), None)
+        .await;
+    assert_eq!(tree["role"], "document");
+    assert_eq!(
+        tree["children"][0],
+        json!({ "role": "paragraph", "children": [{ "role": "text", "name": "Hello from the API" }] })
+    );
+
+    let eval: Value = client
+        .json(Method::POST, &format!("/tabs/{}/eval", tab.id), Some(json!({ "script": "1 + 1" })))
+        .await;
+    assert!(eval["result"].is_string());
+
+    let console: Value = client
+        .json(Method::GET, &format!("/tabs/{}/console", tab.id), None)
+        .await;
+    assert_eq!(console[0]["level"], "log");
+    assert!(console[0]["message"].as_str().unwrap().starts_with("Navigated to data:"));
+
+    // Nothing paints this browser's pages
+    let (status, _) = client.send(Method::GET, &format!("/tabs/{}/screenshot", tab.id), None).await;
+    assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+
+    let blocked = json!({ "url": "https://malware-example.com/" });
+    let (status, _) = client
+        .send(Method::POST, &format!("/tabs/{}/navigate", tab.id), Some(blocked))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = client.send(Method::DELETE, &format!("/tabs/{}", tab.id), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = client.send(Method::GET, &format!("/tabs/{}/text", tab.id), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(controller.state().get_all_tabs().is_empty());
+}
+
+/// Draws on the software adapter, so only with `--features gpu-tests`
+#[cfg(feature = "gpu-tests")]
+#[tokio::test]
+async fn test_screenshots_are_painted_offscreen() {
+    use navigator::ui::{FontSettings, OffscreenCapture, Screenshot, Theme};
+
+    let theme = Theme::default();
+    let capture = Arc::new(OffscreenCapture::new(FontSettings::default(), theme).with_fallback_adapter());
+    let (_controller, _server, client) = serve(move || ServoRenderer::new().with_capture(capture.clone())).await;
+
+    let tab: TabInfo = client.json(Method::POST, "/tabs", None).await;
+    let page = "data:text/html,<h1>Screenshot</h1><p>Painted%20offscreen</p>";
+    let _: TabInfo = client
+        .json(Method::POST, &format!("/tabs/{}/navigate", tab.id), Some(json!({ "url": page })))
+        .await;
+
+    let response = client
+        .http
+        .get(format!("{}/tabs/{}/screenshot", client.base, tab.id))
+        .bearer_auth(&client.token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    let png = response.bytes().await.unwrap();
+    let screenshot = Screenshot::from_png(&png).unwrap();
+    assert_eq!(screenshot.width, 1280);
+    assert!(screenshot.height > 0);
+
+    // The text is drawn over the page background
+    let background = [theme.background.r, theme.background.g, theme.background.b, 255];
+    let drawn = screenshot.rgba.chunks_exact(4).filter(|pixel| *pixel != background).count();
+    assert!(drawn > 100, "{} pixels differ from the background", drawn);
+}
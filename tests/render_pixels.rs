@@ -0,0 +1,174 @@
+//! Draws the layout fixtures offscreen and checks the pixels that come
+//! back. Needs a GPU or a software adapter, so it only builds with
+//! `--features gpu-tests`.
+
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use markup5ever_rcdom::RcDom;
+use navigator::infrastructure::LayoutBuilder;
+use navigator::application::{Command, Notification, NotificationSeverity, PaletteEntry};
+use navigator::ui::dark_pages::{dark_page_theme, darken};
+use navigator::ui::toasts::severity_color;
+use navigator::ui::{
+    AddressBar, ChromeLayout, CommandPalette, FontSettings, PageFrame, Renderer, Screenshot, ScrollState, Theme,
+    ToastManager,
+};
+use std::path::Path;
+use std::time::Instant;
+
+const WIDTH: u32 = 480;
+const HEIGHT: u32 = 360;
+
+fn capture(fixture: &str, theme: &Theme) -> Screenshot {
+    capture_with_overlays(fixture, theme, &ToastManager::new(), None, None)
+}
+
+fn capture_with_overlays(
+    fixture: &str,
+    theme: &Theme,
+    toasts: &ToastManager,
+    palette: Option<&CommandPalette>,
+    page_theme: Option<Theme>,
+) -> Screenshot {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/layout").join(fixture);
+    let html = std::fs::read_to_string(path).unwrap();
+    let dom = parse_document(RcDom::default(), Default::default())
+        .from_utf8()
+        .read_from(&mut html.as_bytes())
+        .unwrap();
+    let mut content = LayoutBuilder::new().build(&dom);
+    if let Some(page_theme) = &page_theme {
+        content = darken(&content, page_theme);
+    }
+
+    let mut renderer = pollster::block_on(Renderer::headless(WIDTH, HEIGHT, FontSettings::default(), true))
+        .expect("the fallback adapter is needed to render offscreen");
+    let placed = toasts.layout(&renderer.chrome_layout(), Instant::now());
+    let palette = palette.map(|palette| palette.layout(&renderer.chrome_layout()));
+    let frame = PageFrame {
+        content: &content,
+        generation: 1,
+        layout_options: Default::default(),
+        scrollbar_opacity: 0.0,
+        reveal: None,
+        selection: None,
+        hints: None,
+        caret: None,
+        toasts: &placed,
+        palette: palette.as_ref(),
+        page_theme,
+        hit_overlay: None,
+    };
+    renderer
+        .capture_viewport(&frame, &AddressBar::new(), theme, &ScrollState::new())
+        .unwrap()
+}
+
+fn count(screenshot: &Screenshot, matches: impl Fn([u8; 4]) -> bool) -> usize {
+    screenshot
+        .rgba
+        .chunks_exact(4)
+        .filter(|pixel| matches([pixel[0], pixel[1], pixel[2], pixel[3]]))
+        .count()
+}
+
+#[test]
+fn test_preformatted_blocks_paint_their_background() {
+    let theme = Theme::light();
+    let screenshot = capture("preformatted.html", &theme);
+    let code = theme.code_background;
+    let background = theme.background;
+    assert!(count(&screenshot, |pixel| pixel == [code.r, code.g, code.b, 255]) > 1000);
+    // Below the last line only the page background shows
+    let corner = screenshot.pixel(screenshot.width - 1, screenshot.height - 1).unwrap();
+    assert_eq!(corner, [background.r, background.g, background.b, 255]);
+}
+
+#[test]
+fn test_links_are_drawn_in_the_link_color() {
+    let theme = Theme::light();
+    let headings = capture("headings.html", &theme);
+    // Link blue is the only color with far more blue than red on the page
+    let bluish = |[r, g, b, _]: [u8; 4]| b as u16 > r as u16 + 80 && b > g;
+    assert!(count(&headings, bluish) > 20);
+
+    let lists = capture("nested_lists.html", &theme);
+    assert_eq!(count(&lists, bluish), 0);
+    // The list text itself is drawn, darker than the background
+    assert!(count(&lists, |[r, _, _, _]| r < theme.background.r / 2) > 20);
+}
+
+#[test]
+fn test_toasts_are_rounded_cards_in_the_corner() {
+    let theme = Theme::light();
+    let mut toasts = ToastManager::new();
+    toasts.set_reduced_motion(true);
+    let saved = Notification::new("Saved to /tmp/page.html", NotificationSeverity::Success)
+        .with_action('o', "open downloads", "navigator://downloads");
+    toasts.push(saved, Instant::now());
+    let screenshot = capture_with_overlays("nested_lists.html", &theme, &toasts, None, None);
+
+    let chrome = ChromeLayout::compute(WIDTH as f32, HEIGHT as f32, 1.0, 1.0);
+    let placed = toasts.layout(&chrome, Instant::now());
+    let (left, top) = (placed[0].x as u32, (placed[0].y - chrome.content_top) as u32);
+    let bottom = top + placed[0].height as u32 - 1;
+
+    // The card is drawn in the chrome color over the page, edged in green
+    let card = theme.chrome_background;
+    assert!(count(&screenshot, |pixel| pixel == [card.r, card.g, card.b, 255]) > 5000);
+    let edge = severity_color(NotificationSeverity::Success, &theme);
+    let middle = screenshot.pixel(left + 1, (top + bottom) / 2).unwrap();
+    assert_eq!(middle, [edge.r, edge.g, edge.b, 255]);
+    // Its message is drawn darker than the card
+    let text = (left..left + placed[0].width as u32)
+        .flat_map(|x| (top..bottom).map(move |y| (x, y)))
+        .filter(|(x, y)| screenshot.pixel(*x, *y).is_some_and(|[r, _, _, _]| r < card.r / 2))
+        .count();
+    assert!(text > 50, "{} text pixels", text);
+    // Rounded corners leave the page background showing
+    let background = theme.background;
+    for (x, y) in [(left, top), (left, bottom)] {
+        assert_eq!(screenshot.pixel(x, y).unwrap(), [background.r, background.g, background.b, 255]);
+    }
+}
+
+#[test]
+fn test_palette_highlights_the_selected_entry() {
+    let theme = Theme::light();
+    let mut palette = CommandPalette::new();
+    palette.set_entries(
+        [Command::Reload, Command::ShowHistory]
+            .into_iter()
+            .map(|command| PaletteEntry::command(command, String::new()))
+            .collect(),
+    );
+    palette.select_next();
+    let screenshot =
+        capture_with_overlays("nested_lists.html", &theme, &ToastManager::new(), Some(&palette), None);
+
+    let chrome = ChromeLayout::compute(WIDTH as f32, HEIGHT as f32, 1.0, 1.0);
+    let placed = palette.layout(&chrome);
+    let left = placed.x as u32 + 4;
+    let row = |index: f32| (placed.y - chrome.content_top + placed.row_height * (index + 0.5)) as u32;
+    // The first row is on the card, the second highlighted
+    let card = theme.chrome_background;
+    let selection = theme.selection;
+    assert_eq!(screenshot.pixel(left, row(0.0)).unwrap(), [card.r, card.g, card.b, 255]);
+    assert_eq!(screenshot.pixel(left, row(1.0)).unwrap(), [selection.r, selection.g, selection.b, 255]);
+}
+
+#[test]
+fn test_dark_pages_draw_light_pages_on_the_dark_background() {
+    let theme = Theme::light();
+    let page_theme = dark_page_theme(&theme);
+    let screenshot =
+        capture_with_overlays("preformatted.html", &theme, &ToastManager::new(), None, Some(page_theme));
+    let background = page_theme.background;
+    let corner = screenshot.pixel(screenshot.width - 1, screenshot.height - 1).unwrap();
+    assert_eq!(corner, [background.r, background.g, background.b, 255]);
+    // Code blocks take the dark theme's shade, and no light pixels are left over
+    let code = page_theme.code_background;
+    assert!(count(&screenshot, |pixel| pixel == [code.r, code.g, code.b, 255]) > 1000);
+    let light = theme.code_background;
+    assert_eq!(count(&screenshot, |pixel| pixel == [light.r, light.g, light.b, 255]), 0);
+}
@@ -0,0 +1,73 @@
+//! Shutting down in the middle of a download keeps what was written, to
+//! resume on the next start
+
+use navigator::application::{
+    BrowserController, BrowserState, FileDownload, NavigatorError, Repositories, SessionDownloads,
+};
+use navigator::domain::{DownloadError, ValidatedUrl};
+use navigator::infrastructure::{
+    load_resume_state, part_path, DefaultSecurityService, DefaultUserScriptService, DefaultUserStyleService,
+    LocalDownloadWriter, ProfileDir, SafeFileWriter, ServoRenderer, SqliteDatabase,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_shutdown_pauses_downloads_being_written() {
+    let profile = Arc::new(ProfileDir::temporary().unwrap());
+    let db = Arc::new(SqliteDatabase::new(profile.database_path()).await.unwrap());
+    let controller = Arc::new(
+        BrowserController::new(
+            BrowserState::new(),
+            Repositories::shared(db.clone()),
+            Arc::new(DefaultSecurityService::new()),
+            Arc::new(DefaultUserStyleService::new()),
+            Arc::new(DefaultUserScriptService::new()),
+            ServoRenderer::new,
+        )
+        .with_downloads(
+            Arc::new(SessionDownloads::new(db.clone())),
+            // A byte at a time, so the write is still under way when shutdown starts
+            Arc::new(LocalDownloadWriter::new().with_profile(profile.clone()).with_chunk_size(1)),
+        ),
+    );
+    let tab = controller.open_tab(None).await.unwrap();
+    let destination = profile.downloads_directory().unwrap().join("large.txt");
+
+    let download = tokio::spawn({
+        let controller = controller.clone();
+        async move {
+            let download = FileDownload {
+                url: ValidatedUrl::parse("https://example.com/large.txt").unwrap(),
+                file_name: "large.txt".to_string(),
+                content_type: Some("text/plain".to_string()),
+                contents: vec![b'x'; 8 * 1024 * 1024],
+            };
+            controller.download_file(tab, download).await
+        }
+    });
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while std::fs::metadata(part_path(&destination)).map_or(true, |part| part.len() == 0) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    })
+    .await
+    .expect("the download starts writing");
+
+    controller.shutdown().await;
+    let paused = download.await.unwrap().unwrap_err();
+    assert!(matches!(paused, NavigatorError::Download(DownloadError::Paused(_))), "{:?}", paused);
+    assert!(!destination.exists());
+
+    // The part file and what it holds so far are kept for the next start
+    let prefix = load_resume_state(&destination).unwrap().expect("the resume state was saved");
+    assert!(prefix.length > 0);
+    assert_eq!(std::fs::metadata(part_path(&destination)).unwrap().len(), prefix.length);
+    let mut resumed = SafeFileWriter::resume(&destination, &prefix).unwrap();
+    assert_eq!(resumed.checkpoint().unwrap(), prefix);
+    drop(resumed);
+
+    // Nothing starts loading any more
+    let refused = controller.navigate(tab, "https://example.com/").await.unwrap_err();
+    assert_eq!(refused, NavigatorError::ShuttingDown);
+}
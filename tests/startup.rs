@@ -0,0 +1,80 @@
+//! The browser is ready to use before its homepage has loaded
+
+use async_trait::async_trait;
+use navigator::application::BrowserController;
+use navigator::domain::{RenderError, RenderingEngine, ValidatedUrl};
+use navigator::ui::DbReady;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+
+/// An engine whose pages never finish loading, counting those it started
+struct StalledEngine {
+    fetches: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl RenderingEngine for StalledEngine {
+    async fn load_url(&self, _url: &ValidatedUrl) -> Result<(), RenderError> {
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        std::future::pending().await
+    }
+
+    async fn get_title(&self) -> Result<String, RenderError> {
+        Ok(String::new())
+    }
+
+    async fn execute_javascript(&self, _script: &str) -> Result<String, RenderError> {
+        Ok(String::new())
+    }
+
+    async fn take_screenshot(&self) -> Result<Vec<u8>, RenderError> {
+        Ok(Vec::new())
+    }
+}
+
+#[tokio::test]
+async fn test_controller_is_ready_before_the_homepage_loads() {
+    let fetches = Arc::new(AtomicUsize::new(0));
+    let engine_fetches = fetches.clone();
+    let (woken, wake) = oneshot::channel();
+    let opening = async move {
+        BrowserController::ephemeral_with(move || StalledEngine {
+            fetches: engine_fetches.clone(),
+        })
+        .await
+    };
+    let db_ready = DbReady::spawn(&Handle::current(), opening, move || {
+        let _ = woken.send(());
+    });
+    let controller = Arc::new(db_ready.wait().await.unwrap());
+    wake.await.unwrap();
+    // Opening the profile fetched nothing
+    assert_eq!(fetches.load(Ordering::SeqCst), 0);
+
+    let tab = controller.open_tab(None).await.unwrap();
+    let homepage = tokio::spawn({
+        let controller = controller.clone();
+        async move { controller.navigate(tab, "https://example.com/").await }
+    });
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while fetches.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .expect("the homepage fetch starts");
+
+    // The controller answers while the homepage is still loading
+    let answered = tokio::time::timeout(Duration::from_secs(5), async {
+        let second = controller.open_tab(None).await.unwrap();
+        controller.close_tab(second).await.unwrap();
+        controller.suggest(tab, "exa").await.unwrap()
+    })
+    .await;
+    assert!(answered.is_ok());
+    assert!(!homepage.is_finished());
+    homepage.abort();
+}